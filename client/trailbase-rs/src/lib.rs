@@ -111,6 +111,7 @@ pub enum DbEvent {
 pub struct ListResponse<T> {
   pub cursor: Option<String>,
   pub total_count: Option<usize>,
+  pub has_more: Option<bool>,
   pub records: Vec<T>,
 }
 
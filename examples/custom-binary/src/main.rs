@@ -36,6 +36,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     main_router,
     admin_router,
     tls,
+    ..
   } = Server::init_with_custom_initializer(
     ServerOptions {
       data_dir: DataDir::default(),
@@ -103,6 +103,7 @@ const columns: ColumnDef<LogJson>[] = [
     },
   },
   { accessorKey: "user_id" },
+  { accessorKey: "request_id" },
 ];
 
 // Value is the previous value in case this isn't the first fetch.
@@ -23,6 +23,7 @@ import {
   notEmptyValidator,
   unsetOrValidUrl,
   buildOptionalNumberFormField,
+  buildOptionalBoolFormField,
   buildTextFormField,
   buildOptionalTextFormField,
   gapStyle,
@@ -132,6 +133,22 @@ function ServerSettings(props: CommonProps) {
                 })}
               </form.Field>
             </div>
+
+            <div>
+              <form.Field name="verboseErrors">
+                {buildOptionalBoolFormField({
+                  label: () => <div class={labelWidth}>Verbose Errors</div>,
+                  info: (
+                    <p>
+                      Include internal details, e.g. database errors, in API
+                      error responses. Helpful for diagnosing staging
+                      deployments but may leak implementation details in
+                      production. Enabled by default only for debug builds.
+                    </p>
+                  ),
+                })}
+              </form.Field>
+            </div>
           </CardContent>
         </Card>
 
@@ -204,6 +221,39 @@ function ServerSettings(props: CommonProps) {
 
                   <TextFieldLabel class={width}>Commit Date:</TextFieldLabel>
                   <span>{info()?.commit_date}</span>
+
+                  <TextFieldLabel class={width}>Read Connections:</TextFieldLabel>
+                  <span>{info()?.read_connections}</span>
+
+                  <TextFieldLabel class={width}>Statement Cache:</TextFieldLabel>
+                  <span>
+                    {`${info()?.statement_cache_hits} hits / ${info()?.statement_cache_misses} misses`}
+                  </span>
+
+                  <TextFieldLabel class={width}>Query Cache:</TextFieldLabel>
+                  <span>
+                    {`${info()?.query_cache_hits} hits / ${info()?.query_cache_misses} misses`}
+                  </span>
+
+                  <TextFieldLabel class={width}>List Query Cache:</TextFieldLabel>
+                  <span>
+                    {`${info()?.list_query_cache_hits} hits / ${info()?.list_query_cache_misses} misses`}
+                  </span>
+
+                  <TextFieldLabel class={width}>WAL Size:</TextFieldLabel>
+                  <span>
+                    {info()?.wal_size_bytes != null
+                      ? `${info()?.wal_size_bytes} bytes`
+                      : "n/a"}
+                  </span>
+
+                  <TextFieldLabel class={width}>Panics:</TextFieldLabel>
+                  <span>{info()?.panics}</span>
+
+                  <TextFieldLabel class={width}>Rate Limits:</TextFieldLabel>
+                  <span>
+                    {`${info()?.rate_limit_allowed} allowed / ${info()?.rate_limit_rejected} rejected`}
+                  </span>
                 </div>
               </TextField>
             </Match>
@@ -93,7 +93,19 @@ export async function executeSql(sql: string): Promise<ExecutionResult> {
     timestamp: Date.now(),
     error: {
       code: response.status,
-      message: await response.text(),
+      message: await errorMessage(response),
     } as ExecutionError,
   } as ExecutionResult;
 }
+
+/// Extracts a human-readable message from an error response, unpacking RFC 9457 problem details.
+async function errorMessage(response: Response): Promise<string> {
+  const body = await response.text();
+  if (response.headers.get("content-type")?.startsWith("application/problem+json")) {
+    try {
+      const problem = JSON.parse(body);
+      return problem.detail ?? problem.title;
+    } catch {}
+  }
+  return body;
+}
@@ -1,6 +1,7 @@
 import { jwtDecode } from "jwt-decode";
 
 import type { ChangeEmailRequest } from "@bindings/ChangeEmailRequest";
+import type { ErrorCode } from "@bindings/ErrorCode";
 import type { LoginRequest } from "@bindings/LoginRequest";
 import type { LoginResponse } from "@bindings/LoginResponse";
 import type { LoginStatusResponse } from "@bindings/LoginStatusResponse";
@@ -23,6 +24,7 @@ export type ListResponse<T> = {
   cursor?: string;
   records: T[];
   total_count?: number;
+  has_more?: boolean;
 };
 
 export type Tokens = {
@@ -97,12 +99,36 @@ type FetchOptions = RequestInit & {
   throwOnError?: boolean;
 };
 
+export type { ErrorCode };
+
+/// RFC 9457 problem details as returned by failing TrailBase APIs.
+export type Problem = {
+  type: string;
+  title: string;
+  status: number;
+  /// Stable, machine-readable error code, e.g. "record/not_found".
+  code: ErrorCode;
+  detail?: string | null;
+  errors?: { field: string; code: string; message: string }[] | null;
+  /// Id of the failed request to quote when reporting failures.
+  request_id?: string | null;
+  /// Id under which unexpected server failures are logged.
+  incident_id?: string | null;
+};
+
 export class FetchError extends Error {
   public status: number;
+  public problem?: Problem;
 
-  constructor(status: number, msg: string) {
+  constructor(status: number, msg: string, problem?: Problem) {
     super(msg);
     this.status = status;
+    this.problem = problem;
+  }
+
+  /// Machine-readable error code, if the server responded with problem details.
+  public get code(): ErrorCode | undefined {
+    return this.problem?.code;
   }
 
   static async from(response: Response): Promise<FetchError> {
@@ -113,6 +139,20 @@ export class FetchError extends Error {
 
     console.debug(response);
 
+    const contentType = response.headers.get("content-type");
+    if (body && contentType?.startsWith("application/problem+json")) {
+      try {
+        const problem = JSON.parse(body) as Problem;
+        return new FetchError(
+          response.status,
+          problem.detail
+            ? `${problem.title}: ${problem.detail}`
+            : problem.title,
+          problem,
+        );
+      } catch {}
+    }
+
     return new FetchError(
       response.status,
       body ? `${response.statusText}: ${body}` : response.statusText,
@@ -156,7 +196,7 @@ export class RecordApi {
     pagination?: Pagination;
     order?: string[];
     filters?: string[];
-    count?: boolean;
+    count?: boolean | "estimate";
     expand?: string[];
   }): Promise<ListResponse<T>> {
     const params = new URLSearchParams();
@@ -174,7 +214,8 @@ export class RecordApi {
     const order = opts?.order;
     if (order) params.append("order", order.join(","));
 
-    if (opts?.count) params.append("count", "true");
+    const count = opts?.count;
+    if (count) params.append("count", count === "estimate" ? "estimate" : "true");
 
     const expand = opts?.expand;
     if (expand) params.append("expand", expand.join(","));
@@ -301,7 +342,7 @@ class ThinClient {
   }
 }
 
-type ClientOptions = {
+export type ClientOptions = {
   tokens?: Tokens;
   onAuthChange?: (client: Client, user?: User) => void;
 };
@@ -1,7 +1,7 @@
 use clap::{Args, Parser, Subcommand, ValueEnum};
 
 use trailbase::DataDir;
-use trailbase::api::JsonSchemaMode;
+use trailbase::api::{CodegenTarget, JsonSchemaMode};
 
 #[derive(ValueEnum, Clone, Copy, Debug)]
 pub enum JsonSchemaModeArg {
@@ -23,6 +23,35 @@ impl From<JsonSchemaModeArg> for JsonSchemaMode {
   }
 }
 
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum CodegenTargetArg {
+  /// TypeScript client based on the "trailbase" npm package.
+  #[value(alias = "ts")]
+  Typescript,
+  /// Dart client based on the "trailbase" pub package.
+  Dart,
+  /// Kotlin models using kotlinx.serialization.
+  #[value(alias = "kt")]
+  Kotlin,
+  /// Swift models using Codable.
+  Swift,
+  /// Protocol buffer message definitions.
+  #[value(alias = "protobuf")]
+  Proto,
+}
+
+impl From<CodegenTargetArg> for CodegenTarget {
+  fn from(value: CodegenTargetArg) -> Self {
+    match value {
+      CodegenTargetArg::Typescript => Self::TypeScript,
+      CodegenTargetArg::Dart => Self::Dart,
+      CodegenTargetArg::Kotlin => Self::Kotlin,
+      CodegenTargetArg::Swift => Self::Swift,
+      CodegenTargetArg::Proto => Self::Proto,
+    }
+  }
+}
+
 /// Command line arguments for TrailBase's CLI.
 ///
 /// NOTE: a good rule of thumb for thinking of proto config vs CLI options: if it requires a
@@ -45,6 +74,9 @@ pub enum SubCommands {
   Run(ServerArgs),
   /// Export JSON Schema definitions.
   Schema(JsonSchemaArgs),
+  /// Generate a typed client for the configured record APIs.
+  #[command(alias = "gen")]
+  Codegen(CodegenArgs),
   #[cfg(feature = "openapi")]
   /// Export OpenAPI definitions.
   OpenApi {
@@ -68,6 +100,22 @@ pub enum SubCommands {
   },
   /// Programmatically send emails.
   Email(EmailArgs),
+  /// Insert records from JSON/CSV fixtures, validated like record API requests.
+  Seed(SeedArgs),
+  /// Migrate to the tables, views and indexes declared in a SQL file, generating the migration.
+  Apply(ApplyArgs),
+  /// Take a backup of the main database according to the configured backup options.
+  Backup,
+  /// Restore a backup into a fresh data directory, i.e. one without a main database.
+  Restore(RestoreArgs),
+  /// Re-encrypt the databases with a new key, e.g. to rotate keys. The current key is taken from
+  /// TRAIL_DATABASE_KEY or TRAIL_DATABASE_KEY_FILE. Requires the server to be stopped.
+  Rekey(RekeyArgs),
+  /// Load-test record APIs of a running instance and report latencies.
+  Bench {
+    #[command(subcommand)]
+    cmd: BenchSubCommands,
+  },
 }
 
 #[derive(Args, Clone, Debug)]
@@ -100,6 +148,18 @@ pub struct ServerArgs {
   #[arg(long, default_value_t = false)]
   pub disable_auth_ui: bool,
 
+  /// Serve record APIs over gRPC on the same address. Requires a build with the "grpc" feature.
+  #[arg(long, default_value_t = false)]
+  pub enable_grpc: bool,
+
+  /// Serve a GraphQL endpoint over record APIs. Requires a build with the "graphql" feature.
+  #[arg(long, default_value_t = false)]
+  pub enable_graphql: bool,
+
+  /// Disable gzip, brotli and zstd response compression, e.g. behind a compressing reverse proxy.
+  #[arg(long, default_value_t = false)]
+  pub disable_compression: bool,
+
   /// Limit the set of allowed origins the HTTP server will answer to.
   #[arg(long, default_value = "*")]
   pub cors_allowed_origins: Vec<String>,
@@ -107,6 +167,15 @@ pub struct ServerArgs {
   /// Number of JavaScript isolates/workers to start (Default: #cpus).
   #[arg(long, env)]
   pub js_runtime_threads: Option<usize>,
+
+  /// Keep all databases in memory, e.g. for tests or ephemeral previews. Data is lost on exit.
+  #[arg(long, default_value_t = false)]
+  pub in_memory: bool,
+
+  /// Authenticate requests with an `X-Test-User: <id>[;<role>,...]` header as the given user,
+  /// e.g. for integration tests or frontend development. Never use in production.
+  #[arg(long, default_value_t = false)]
+  pub mock_auth: bool,
 }
 
 #[derive(Args, Clone, Debug)]
@@ -120,6 +189,16 @@ pub struct JsonSchemaArgs {
   pub mode: Option<JsonSchemaModeArg>,
 }
 
+#[derive(Args, Clone, Debug)]
+pub struct CodegenArgs {
+  /// Target language of the generated client.
+  pub target: CodegenTargetArg,
+
+  /// Output file. Prints to stdout if omitted.
+  #[arg(long, short)]
+  pub out: Option<std::path::PathBuf>,
+}
+
 #[derive(Args, Clone, Debug)]
 pub struct EmailArgs {
   /// Receiver address, e.g. foo@bar.baz.
@@ -135,6 +214,136 @@ pub struct EmailArgs {
   pub body: String,
 }
 
+#[derive(Args, Clone, Debug)]
+pub struct SeedArgs {
+  /// Fixture files or directories thereof. Fixtures are JSON or CSV files named after the table
+  /// they're inserted into, e.g. "author.json". Reads JSON from stdin if omitted.
+  pub paths: Vec<std::path::PathBuf>,
+
+  /// Table to insert into, overriding the name derived from a single fixture file. Required when
+  /// reading from stdin.
+  #[arg(long)]
+  pub table: Option<String>,
+
+  /// Only seed tables that are empty, e.g. to seed dev environments on every start.
+  #[arg(long, default_value_t = false)]
+  pub if_empty: bool,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct ApplyArgs {
+  /// SQL file containing the desired CREATE TABLE/VIEW/INDEX statements. Reads from stdin if
+  /// omitted.
+  pub file: Option<std::path::PathBuf>,
+
+  /// Only print the generated migration w/o applying it.
+  #[arg(long, default_value_t = false)]
+  pub dry_run: bool,
+
+  /// Allow dropping tables and columns missing from the desired schema.
+  #[arg(long, default_value_t = false)]
+  pub allow_data_loss: bool,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct RestoreArgs {
+  /// Backup file to restore. Defaults to the latest backup in `--from`.
+  pub file: Option<std::path::PathBuf>,
+
+  /// Data directory whose backups to pick from if no file is given.
+  #[arg(long)]
+  pub from: Option<std::path::PathBuf>,
+
+  /// Restore the latest backup taken at or before the given time, e.g. 2025-01-01T00:00:00Z.
+  #[arg(long)]
+  pub at: Option<chrono::DateTime<chrono::Utc>>,
+
+  /// Rebuild the database from the latest replication generation in the object store configured
+  /// in `--data-dir` rather than from a backup.
+  #[arg(long, default_value_t = false)]
+  pub replica: bool,
+
+  /// Do nothing if `--data-dir` already has a main database, e.g. when restoring on startup.
+  #[arg(long, default_value_t = false)]
+  pub if_missing: bool,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct RekeyArgs {
+  /// New key, either a passphrase or a raw key given as "x'<64 hex digits>'".
+  #[arg(long, env = "TRAIL_NEW_DATABASE_KEY", hide_env_values = true)]
+  pub new_key: Option<String>,
+
+  /// File containing the new key.
+  #[arg(long)]
+  pub new_key_file: Option<std::path::PathBuf>,
+
+  /// Decrypt the databases instead.
+  #[arg(long, default_value_t = false)]
+  pub decrypt: bool,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum BenchSubCommands {
+  /// Replay list requests, optionally interleaved with creates, against a record API.
+  RecordList(RecordListBenchArgs),
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct RecordListBenchArgs {
+  /// Name of the record API.
+  pub api: String,
+
+  /// Address of the running instance.
+  #[arg(
+    long,
+    env = "TRAIL_BENCH_SITE",
+    default_value = "http://localhost:4000"
+  )]
+  pub site: String,
+
+  /// Log in as the given user, e.g. if the API requires authentication.
+  #[arg(long, env = "TRAIL_BENCH_EMAIL")]
+  pub email: Option<String>,
+
+  #[arg(long, env = "TRAIL_BENCH_PASSWORD")]
+  pub password: Option<String>,
+
+  /// Total number of requests.
+  #[arg(short = 'n', long, default_value_t = 1000)]
+  pub requests: usize,
+
+  /// Number of concurrent in-flight requests.
+  #[arg(short, long, default_value_t = 10)]
+  pub concurrency: usize,
+
+  /// Synthetic rows to create from the template before replaying requests.
+  #[arg(long, default_value_t = 0)]
+  pub rows: usize,
+
+  /// JSON record template for synthetic rows and creates. String values "{i}" and "{rand}" are
+  /// replaced with the row's index and a random integer, respectively, and "{i}" is substituted
+  /// within other strings, e.g. '{"name": "user-{i}", "age": "{rand}"}'.
+  #[arg(long)]
+  pub template: Option<String>,
+
+  /// Percentage of requests that are creates rather than lists.
+  #[arg(long, default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=100))]
+  pub writes: u8,
+
+  /// List filters, e.g. "age[gt]=18". Can be repeated.
+  #[arg(long)]
+  pub filter: Vec<String>,
+
+  /// Comma-separated list order, e.g. "-age,id".
+  #[arg(long)]
+  pub order: Option<String>,
+
+  /// Page size of list requests.
+  #[arg(long)]
+  pub limit: Option<usize>,
+}
+
 #[cfg(feature = "openapi")]
 #[derive(Subcommand, Debug, Clone)]
 pub enum OpenApiSubCommands {
@@ -0,0 +1,240 @@
+use rand::Rng;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use trailbase_client::{Client, ListArguments, Pagination};
+
+use crate::args::RecordListBenchArgs;
+
+/// Number of synthetic rows created per request while seeding.
+const SEED_BATCH_SIZE: usize = 100;
+
+#[derive(Debug, Error)]
+pub enum BenchError {
+  #[error("Client error: {0}")]
+  Client(#[from] trailbase_client::Error),
+  #[error("Template error: {0}")]
+  Template(String),
+  #[error("Join error: {0}")]
+  Join(#[from] tokio::task::JoinError),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Op {
+  List,
+  Create,
+}
+
+#[derive(Debug)]
+struct Sample {
+  op: Op,
+  latency: Duration,
+  ok: bool,
+}
+
+/// Latency statistics for a single kind of request.
+#[derive(Debug)]
+pub struct OpReport {
+  pub name: &'static str,
+  pub requests: usize,
+  pub errors: usize,
+  pub mean: Duration,
+  pub p50: Duration,
+  pub p90: Duration,
+  pub p99: Duration,
+  pub max: Duration,
+}
+
+#[derive(Debug)]
+pub struct BenchReport {
+  pub elapsed: Duration,
+  pub ops: Vec<OpReport>,
+}
+
+impl std::fmt::Display for BenchReport {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let requests: usize = self.ops.iter().map(|op| op.requests).sum();
+    writeln!(
+      f,
+      "{requests} requests in {:.2?} ({:.1} req/s)",
+      self.elapsed,
+      requests as f64 / self.elapsed.as_secs_f64()
+    )?;
+    writeln!(
+      f,
+      "{: <8}{: >10}{: >8}{: >12}{: >12}{: >12}{: >12}{: >12}",
+      "op", "requests", "errors", "mean", "p50", "p90", "p99", "max"
+    )?;
+    for op in &self.ops {
+      writeln!(
+        f,
+        "{: <8}{: >10}{: >8}{: >12}{: >12}{: >12}{: >12}{: >12}",
+        op.name,
+        op.requests,
+        op.errors,
+        format!("{:.2?}", op.mean),
+        format!("{:.2?}", op.p50),
+        format!("{:.2?}", op.p90),
+        format!("{:.2?}", op.p99),
+        format!("{:.2?}", op.max),
+      )?;
+    }
+    return Ok(());
+  }
+}
+
+/// Replays list and create requests against a running instance's record API, see
+/// [`RecordListBenchArgs`].
+pub async fn bench_record_list(args: RecordListBenchArgs) -> Result<BenchReport, BenchError> {
+  let template: Option<serde_json::Value> = args
+    .template
+    .as_deref()
+    .map(serde_json::from_str)
+    .transpose()
+    .map_err(|err| BenchError::Template(err.to_string()))?;
+  if template.is_none() && (args.rows > 0 || args.writes > 0) {
+    return Err(BenchError::Template(
+      "--template is required for synthetic rows and writes".to_string(),
+    ));
+  }
+
+  let client = Client::new(&args.site, None)?;
+  if let (Some(email), Some(password)) = (&args.email, &args.password) {
+    client.login(email, password).await?;
+  }
+  let api = client.records(&args.api);
+
+  // Seed synthetic rows.
+  if let Some(ref template) = template {
+    let mut index = 0;
+    while index < args.rows {
+      let batch: Vec<serde_json::Value> = (index..args.rows.min(index + SEED_BATCH_SIZE))
+        .map(|i| render_template(template, i))
+        .collect();
+      index += batch.len();
+      api.create_bulk(&batch).await?;
+    }
+  }
+
+  let args = Arc::new(args);
+  let template = Arc::new(template);
+  let counter = Arc::new(AtomicUsize::new(0));
+
+  let start = Instant::now();
+  let workers: Vec<_> = (0..args.concurrency.max(1))
+    .map(|_| {
+      let args = args.clone();
+      let template = template.clone();
+      let counter = counter.clone();
+      let api = api.clone();
+
+      tokio::spawn(async move {
+        let filters: Vec<&str> = args.filter.iter().map(|f| f.as_str()).collect();
+        let order: Vec<&str> = args
+          .order
+          .as_deref()
+          .map_or_else(Vec::new, |order| order.split(',').collect());
+
+        let mut samples: Vec<Sample> = vec![];
+        loop {
+          let i = counter.fetch_add(1, Ordering::Relaxed);
+          if i >= args.requests {
+            break;
+          }
+
+          let is_write = rand::rng().random_range(0..100) < args.writes;
+          let request_start = Instant::now();
+          let (op, ok) = match (&*template, is_write) {
+            (Some(template), true) => {
+              let record = render_template(template, args.rows + i);
+              (Op::Create, api.create(record).await.is_ok())
+            }
+            _ => {
+              let list_args = ListArguments::new()
+                .with_pagination(Pagination::new().with_limit(args.limit))
+                .with_filters(&filters)
+                .with_order(&order);
+              (
+                Op::List,
+                api.list::<serde_json::Value>(list_args).await.is_ok(),
+              )
+            }
+          };
+
+          samples.push(Sample {
+            op,
+            latency: request_start.elapsed(),
+            ok,
+          });
+        }
+
+        return samples;
+      })
+    })
+    .collect();
+
+  let mut samples: Vec<Sample> = vec![];
+  for worker in workers {
+    samples.extend(worker.await?);
+  }
+  let elapsed = start.elapsed();
+
+  return Ok(BenchReport {
+    elapsed,
+    ops: [(Op::List, "list"), (Op::Create, "create")]
+      .into_iter()
+      .filter_map(|(op, name)| {
+        op_report(
+          name,
+          samples.iter().filter(|s| s.op == op).collect::<Vec<_>>(),
+        )
+      })
+      .collect(),
+  });
+}
+
+fn op_report(name: &'static str, samples: Vec<&Sample>) -> Option<OpReport> {
+  if samples.is_empty() {
+    return None;
+  }
+
+  let mut latencies: Vec<Duration> = samples.iter().map(|s| s.latency).collect();
+  latencies.sort();
+
+  let percentile = |p: f64| -> Duration {
+    let rank = (p * latencies.len() as f64).ceil() as usize;
+    return latencies[rank.clamp(1, latencies.len()) - 1];
+  };
+
+  return Some(OpReport {
+    name,
+    requests: samples.len(),
+    errors: samples.iter().filter(|s| !s.ok).count(),
+    mean: latencies.iter().sum::<Duration>() / latencies.len() as u32,
+    p50: percentile(0.5),
+    p90: percentile(0.9),
+    p99: percentile(0.99),
+    max: latencies[latencies.len() - 1],
+  });
+}
+
+fn render_template(template: &serde_json::Value, index: usize) -> serde_json::Value {
+  use serde_json::Value;
+
+  return match template {
+    Value::String(s) if s == "{i}" => Value::from(index),
+    Value::String(s) if s == "{rand}" => Value::from(rand::rng().random::<u32>()),
+    Value::String(s) => Value::String(s.replace("{i}", &index.to_string())),
+    Value::Array(values) => {
+      Value::Array(values.iter().map(|v| render_template(v, index)).collect())
+    }
+    Value::Object(map) => Value::Object(
+      map
+        .iter()
+        .map(|(k, v)| (k.clone(), render_template(v, index)))
+        .collect(),
+    ),
+    v => v.clone(),
+  };
+}
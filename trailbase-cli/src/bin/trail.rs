@@ -8,14 +8,20 @@ use clap::{CommandFactory, Parser};
 use log::*;
 use serde::Deserialize;
 use std::rc::Rc;
-use tokio::{fs, io::AsyncWriteExt};
+use tokio::{
+  fs,
+  io::{AsyncReadExt, AsyncWriteExt},
+};
 use trailbase::{
-  DataDir, Server, ServerOptions,
+  Clock, DataDir, Server, ServerOptions,
   api::{self, Email, InitArgs, JsonSchemaMode, TokenClaims, init_app_state},
   constants::USER_TABLE,
 };
 
-use trailbase_cli::{AdminSubCommands, DefaultCommandLineArgs, SubCommands, UserSubCommands};
+use trailbase_cli::{
+  AdminSubCommands, BenchSubCommands, DefaultCommandLineArgs, SubCommands, UserSubCommands,
+  bench_record_list,
+};
 
 type BoxError = Box<dyn std::error::Error + Send + Sync>;
 
@@ -80,8 +86,15 @@ async fn async_main() -> Result<(), BoxError> {
         dev: cmd.dev,
         demo: cmd.demo,
         disable_auth_ui: cmd.disable_auth_ui,
+        enable_grpc: cmd.enable_grpc,
+        enable_graphql: cmd.enable_graphql,
+        disable_compression: cmd.disable_compression,
         cors_allowed_origins: cmd.cors_allowed_origins,
         js_runtime_threads: cmd.js_runtime_threads,
+        in_memory: cmd.in_memory,
+        skip_tracing_init: false,
+        mock_auth: cmd.mock_auth,
+        clock: Clock::system(),
         tls_key: None,
         tls_cert: None,
       })
@@ -111,8 +124,11 @@ async fn async_main() -> Result<(), BoxError> {
 
       match cmd {
         Some(OpenApiSubCommands::Print) => {
-          let json = trailbase::openapi::Doc::openapi().to_pretty_json()?;
-          println!("{json}");
+          // Include the configured record APIs, so the output can be fed into client generators.
+          let (_new_db, state) =
+            init_app_state(DataDir(args.data_dir), None, InitArgs::default()).await?;
+          let spec = trailbase::openapi::build_openapi_spec(&state)?;
+          println!("{}", serde_json::to_string_pretty(&spec)?);
         }
         Some(OpenApiSubCommands::Run { port }) => {
           run_server(port).await;
@@ -138,6 +154,21 @@ async fn async_main() -> Result<(), BoxError> {
 
       println!("{}", serde_json::to_string_pretty(&json_schema)?);
     }
+    Some(SubCommands::Codegen(cmd)) => {
+      init_logger(false);
+
+      let (_new_db, state) =
+        init_app_state(DataDir(args.data_dir), None, InitArgs::default()).await?;
+
+      let client = api::generate_client(&state, cmd.target.into())?;
+      match cmd.out {
+        Some(path) => {
+          fs::write(&path, client).await?;
+          println!("Generated client: {path:?}");
+        }
+        None => println!("{client}"),
+      };
+    }
     Some(SubCommands::Migration { suffix }) => {
       init_logger(false);
 
@@ -260,6 +291,140 @@ async fn async_main() -> Result<(), BoxError> {
         }
       };
     }
+    Some(SubCommands::Seed(cmd)) => {
+      init_logger(false);
+
+      let fixtures = if cmd.paths.is_empty() {
+        let Some(table) = cmd.table else {
+          return Err("Missing --table for reading from stdin".into());
+        };
+
+        let mut buffer = String::new();
+        tokio::io::stdin().read_to_string(&mut buffer).await?;
+        vec![api::Fixture::from_json(
+          table,
+          serde_json::from_str(&buffer)?,
+          std::env::current_dir()?,
+        )?]
+      } else {
+        let mut fixtures = api::load_fixtures(&cmd.paths)?;
+        if let Some(table) = cmd.table {
+          if fixtures.len() != 1 {
+            return Err("--table requires a single fixture".into());
+          }
+          fixtures[0].table = table;
+        }
+        fixtures
+      };
+
+      let (_new_db, state) =
+        init_app_state(DataDir(args.data_dir), None, InitArgs::default()).await?;
+
+      let report = api::seed_fixtures(&state, fixtures, cmd.if_empty).await?;
+      for (table, count) in &report.seeded {
+        println!("Seeded {count} record(s) into '{table}'");
+      }
+      for table in &report.skipped {
+        println!("Skipped non-empty table '{table}'");
+      }
+    }
+    Some(SubCommands::Apply(cmd)) => {
+      init_logger(false);
+
+      let sql = match cmd.file {
+        Some(path) => fs::read_to_string(&path).await?,
+        None => {
+          let mut buffer = String::new();
+          tokio::io::stdin().read_to_string(&mut buffer).await?;
+          buffer
+        }
+      };
+
+      let (_new_db, state) =
+        init_app_state(DataDir(args.data_dir), None, InitArgs::default()).await?;
+
+      let statements = api::apply_schema(&state, &sql, cmd.allow_data_loss, cmd.dry_run).await?;
+      if statements.is_empty() {
+        println!("Schema is up to date");
+      } else {
+        for stmt in &statements {
+          println!("{stmt};");
+        }
+        if !cmd.dry_run {
+          println!("Applied {} statement(s)", statements.len());
+        }
+      }
+    }
+    Some(SubCommands::Backup) => {
+      init_logger(false);
+
+      let (_new_db, state) =
+        init_app_state(DataDir(args.data_dir), None, InitArgs::default()).await?;
+
+      let backup = api::create_backup(&state).await?;
+      println!("Created backup '{}'", backup.name);
+    }
+    Some(SubCommands::Restore(cmd)) => {
+      init_logger(false);
+
+      let data_dir = DataDir(args.data_dir.clone());
+      if cmd.if_missing && fs::try_exists(data_dir.main_db_path()).await? {
+        println!(
+          "Skipping restore, {:?} already exists",
+          data_dir.main_db_path()
+        );
+        return Ok(());
+      }
+
+      if cmd.replica {
+        let generation = api::restore_replica(&data_dir).await?;
+        println!(
+          "Restored generation '{generation}' into {:?}",
+          args.data_dir
+        );
+        return Ok(());
+      }
+
+      let file = match cmd.file {
+        Some(file) => file,
+        None => {
+          let Some(from) = cmd.from else {
+            return Err("Either a backup file or --from is required".into());
+          };
+          api::find_backup(&DataDir(from), cmd.at).await?
+        }
+      };
+
+      api::restore_backup(&file, &data_dir).await?;
+      println!("Restored {file:?} into {:?}", args.data_dir);
+    }
+    Some(SubCommands::Rekey(cmd)) => {
+      init_logger(false);
+
+      let new_key = match (cmd.new_key, cmd.new_key_file, cmd.decrypt) {
+        (Some(key), None, false) => Some(api::DatabaseKey::passphrase(key)?),
+        (None, Some(path), false) => Some(api::DatabaseKey::passphrase(
+          fs::read_to_string(&path).await?.trim(),
+        )?),
+        (None, None, true) => None,
+        _ => {
+          return Err("Expected exactly one of --new-key, --new-key-file or --decrypt".into());
+        }
+      };
+
+      api::rekey_data_dir(&DataDir(args.data_dir.clone()), new_key.as_ref())?;
+      println!("Re-encrypted databases in {:?}", args.data_dir);
+    }
+    Some(SubCommands::Bench { cmd }) => {
+      init_logger(false);
+
+      match cmd {
+        BenchSubCommands::RecordList(cmd) => {
+          let report = bench_record_list(cmd).await?;
+          print!("{report}");
+        }
+      };
+    }
     None => {
       let _ = DefaultCommandLineArgs::command().print_help();
     }
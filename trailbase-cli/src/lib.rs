@@ -1,11 +1,15 @@
 #![allow(clippy::needless_return)]
 
 mod args;
+mod bench;
 
 pub use args::{
-  AdminSubCommands, DefaultCommandLineArgs, EmailArgs, JsonSchemaModeArg, SubCommands,
-  UserSubCommands,
+  AdminSubCommands, ApplyArgs, BenchSubCommands, CodegenArgs, CodegenTargetArg,
+  DefaultCommandLineArgs, EmailArgs, JsonSchemaModeArg, RecordListBenchArgs, RekeyArgs,
+  RestoreArgs, SeedArgs, SubCommands, UserSubCommands,
 };
 
+pub use bench::{BenchError, BenchReport, OpReport, bench_record_list};
+
 #[cfg(feature = "openapi")]
 pub use args::OpenApiSubCommands;
@@ -0,0 +1,10 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Stable, machine-readable error codes included in all API error responses and exported
+ * through client codegen.
+ *
+ * Clients should branch on these rather than HTTP status codes or messages. Codes may be
+ * added but existing ones are never repurposed.
+ */
+export type ErrorCode = "record/api_not_found" | "record/api_requires_table" | "record/not_found" | "record/forbidden" | "record/bad_request" | "record/validation_failed" | "record/constraint_check" | "record/constraint_foreign_key" | "record/constraint_not_null" | "record/constraint_primary_key" | "record/constraint_unique" | "record/constraint" | "record/precondition_failed" | "record/limit_exceeded" | "record/unavailable" | "record/internal" | "auth/unauthorized" | "auth/invalid_credentials" | "auth/forbidden" | "auth/conflict" | "auth/not_found" | "auth/oauth_provider_not_found" | "auth/bad_request" | "auth/too_many_requests" | "auth/failed_dependency" | "auth/internal" | "admin/bad_request" | "admin/precondition_failed" | "admin/already_exists" | "admin/internal" | "sql/not_found" | "sql/forbidden" | "sql/bad_request" | "sql/timeout" | "sql/internal" | "server/panic" | "server/rate_limited";
@@ -0,0 +1,17 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Validation failure for a specific field of a request.
+ */
+export type FieldError = { 
+/**
+ * Name of the offending field. Violations within JSON columns are suffixed with a JSON pointer,
+ * e.g. "address/zip".
+ */
+field: string, 
+/**
+ * Machine-readable failure category: "invalid" for custom validators, "schema" for JSON schema
+ * violations, "type" for mismatching types, "format" for unparsable values and "unique" or
+ * "not_null" for violated database constraints.
+ */
+code: string, message: string, };
@@ -0,0 +1,16 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ImportReport } from "./ImportReport";
+
+export type ImportJobResponse = { 
+/**
+ * Url-safe Base64 encoded id of the import job.
+ */
+id: string, 
+/**
+ * One of: "pending", "running", "completed", "failed".
+ */
+status: string, 
+/**
+ * Present once the job has completed.
+ */
+report: ImportReport | null, };
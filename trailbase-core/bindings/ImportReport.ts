@@ -0,0 +1,8 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ImportRowError } from "./ImportRowError";
+
+export type ImportReport = { total_rows: number, imported_rows: number, failed_rows: number, 
+/**
+ * Row errors, capped at the first 1000.
+ */
+errors: Array<ImportRowError>, };
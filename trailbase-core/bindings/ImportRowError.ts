@@ -0,0 +1,10 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Failure to import a specific row.
+ */
+export type ImportRowError = { 
+/**
+ * 1-based index of the record in the input, excluding the CSV header.
+ */
+row: number, message: string, };
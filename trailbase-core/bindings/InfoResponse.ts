@@ -1,3 +1,31 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 
-export type InfoResponse = { version: string, compiler: string | null, commit_hash: string | null, commit_date: string | null, threads: number, };
+export type InfoResponse = { version: string, compiler: string | null, commit_hash: string | null, commit_date: string | null, threads: number, 
+/**
+ * Dedicated read connections of the main database, see `read_connections`.
+ */
+read_connections: number, 
+/**
+ * Prepared statement cache hits and misses of the main database connection.
+ */
+statement_cache_hits: bigint, statement_cache_misses: bigint, 
+/**
+ * Record API response cache hits and misses, see `cache_ttl_sec`.
+ */
+query_cache_hits: bigint, query_cache_misses: bigint, 
+/**
+ * Rendered listing query cache hits and misses.
+ */
+list_query_cache_hits: bigint, list_query_cache_misses: bigint, 
+/**
+ * Current size of the main database's write-ahead log in bytes.
+ */
+wal_size_bytes: bigint | null, 
+/**
+ * Number of handler panics caught since start.
+ */
+panics: bigint, 
+/**
+ * Requests admitted and rejected by rate limits since start, see `server.rate_limits`.
+ */
+rate_limit_allowed: bigint, rate_limit_rejected: bigint, };
@@ -0,0 +1,11 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type JobRun = { id: bigint, name: string, 
+/**
+ * Start timestamp in seconds since epoch.
+ */
+started: bigint, duration_ms: bigint, 
+/**
+ * Error output, if the run failed.
+ */
+error: string | null, };
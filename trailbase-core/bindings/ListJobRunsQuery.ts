@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type ListJobRunsQuery = { 
+/**
+ * Only list runs of the job with the given name.
+ */
+name: string | null, limit: bigint | null, };
@@ -4,4 +4,4 @@ export type LogJson = { id: bigint, created: number, status: number, method: str
 /**
  * Optional two-letter country code.
  */
-client_cc: string | null, referer: string, user_agent: string, user_id: string | null, };
+client_cc: string | null, referer: string, user_agent: string, user_id: string | null, request_id: string | null, };
@@ -0,0 +1,44 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ErrorCode } from "./ErrorCode";
+import type { FieldError } from "./FieldError";
+
+/**
+ * Error response body following RFC 9457, i.e. "Problem Details for HTTP APIs".
+ */
+export type Problem = { 
+/**
+ * URI identifying the problem type.
+ */
+type: string, 
+/**
+ * Short, human-readable summary of the problem type.
+ */
+title: string, 
+/**
+ * The HTTP status code.
+ */
+status: number, 
+/**
+ * Stable, machine-readable error code.
+ */
+code: ErrorCode, 
+/**
+ * Human-readable explanation specific to this occurrence.
+ */
+detail: string | null, 
+/**
+ * Per-field validation failures.
+ */
+errors: Array<FieldError> | null, 
+/**
+ * Id of the failed request, also found in the logs and the `X-Request-Id` header.
+ */
+request_id: string | null, 
+/**
+ * Id under which unexpected failures, e.g. panics, are logged.
+ */
+incident_id: string | null, 
+/**
+ * Largest permissible page size, if the requested one exceeded it.
+ */
+max_limit: number | null, };
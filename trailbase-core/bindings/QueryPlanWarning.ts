@@ -0,0 +1,15 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Step of a Record API query's plan, which is likely to be slow on large tables, as reported by
+ * `EXPLAIN QUERY PLAN`.
+ */
+export type QueryPlanWarning = { api_name: string, 
+/**
+ * Query that was explained: "list", "read", "read_access" or "filter:<column>".
+ */
+query: string, 
+/**
+ * Offending plan step, e.g. "SCAN room_members".
+ */
+detail: string, };
@@ -0,0 +1,14 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Columns of a "CREATE VIRTUAL TABLE ... USING rtree(...)" spatial index.
+ */
+export type RtreeTable = { 
+/**
+ * Integer primary key column.
+ */
+id: string, 
+/**
+ * Lower and upper bound columns per dimension, e.g. [("min_x", "max_x"), ("min_y", "max_y")].
+ */
+dimensions: Array<[string, string]>, };
@@ -0,0 +1,5 @@
+-- Correlates log entries with the `X-Request-Id` returned to clients, e.g. in
+-- error responses.
+ALTER TABLE _logs ADD COLUMN request_id TEXT;
+
+CREATE INDEX IF NOT EXISTS __logs__request_id_index ON _logs (request_id);
@@ -0,0 +1,20 @@
+-- Job state and run history
+--
+-- Persisted last and next runs of scheduled jobs as well as a bounded history
+-- of recent runs per job, see the admin jobs API.
+CREATE TABLE _job_state (
+  name                         TEXT PRIMARY KEY NOT NULL,
+  last_run                     INTEGER,
+  next_run                     INTEGER
+) STRICT;
+
+CREATE TABLE _job_run (
+  id                           INTEGER PRIMARY KEY NOT NULL,
+  name                         TEXT NOT NULL,
+  started                      INTEGER NOT NULL,
+  duration_ms                  INTEGER NOT NULL,
+  -- NULL on success.
+  error                        TEXT
+) STRICT;
+
+CREATE INDEX __job_run__name_index ON _job_run (name);
@@ -0,0 +1,18 @@
+-- Offline sync change tracking
+--
+-- Latest version of every row of record APIs with `enable_sync`, maintained by
+-- TrailBase-managed "_sync_*" triggers. Versions are drawn from a single,
+-- increasing sequence across tables, i.e. clients pull all changes since the
+-- last version they've seen. Deleted rows are kept as tombstones.
+CREATE TABLE _sync_row (
+  table_name                   TEXT NOT NULL,
+  -- Primary key value of the row.
+  record_id                    ANY NOT NULL,
+  version                      INTEGER NOT NULL,
+  deleted                      INTEGER NOT NULL DEFAULT FALSE,
+  updated                      INTEGER NOT NULL DEFAULT (UNIXEPOCH()),
+
+  PRIMARY KEY (table_name, record_id)
+) STRICT;
+
+CREATE UNIQUE INDEX __sync_row__version_index ON _sync_row (version);
@@ -0,0 +1,16 @@
+-- Data retention audit log
+--
+-- Every time the retention job evaluates a policy, it records how many rows
+-- were (or, for dry runs, would have been) affected.
+CREATE TABLE _retention_audit (
+  id                           INTEGER PRIMARY KEY NOT NULL,
+  created                      INTEGER NOT NULL DEFAULT (UNIXEPOCH()),
+
+  table_name                   TEXT NOT NULL,
+  -- One of: 'delete', 'anonymize'.
+  action                       TEXT NOT NULL,
+  -- Rows older than this UNIX timestamp were subject to the policy.
+  cutoff                       INTEGER NOT NULL,
+  affected_rows                INTEGER NOT NULL,
+  dry_run                      INTEGER NOT NULL DEFAULT FALSE
+) STRICT;
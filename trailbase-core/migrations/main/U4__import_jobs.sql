@@ -0,0 +1,16 @@
+-- Background record imports
+--
+-- Tracks imports started with `?async=true` on a record API's import endpoint.
+CREATE TABLE _import_job (
+  id                           BLOB PRIMARY KEY NOT NULL CHECK(is_uuid_v7(id)),
+  created                      INTEGER NOT NULL DEFAULT (UNIXEPOCH()),
+  updated                      INTEGER NOT NULL DEFAULT (UNIXEPOCH()),
+
+  api_name                     TEXT NOT NULL,
+  -- User who started the import, if any. Only they can read the job's status.
+  user                         BLOB REFERENCES _user(id) ON DELETE CASCADE,
+  -- One of: 'pending', 'running', 'completed', 'failed'.
+  status                       TEXT NOT NULL DEFAULT 'pending',
+  -- JSON-serialized import report once completed.
+  report                       TEXT
+) STRICT;
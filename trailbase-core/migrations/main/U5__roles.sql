@@ -0,0 +1,18 @@
+-- Built-in roles
+--
+-- Roles are granted to users via `_user_role`, included in auth token claims and
+-- can be checked in record API access rules using `has_role('<name>')`.
+CREATE TABLE _role (
+  name                         TEXT PRIMARY KEY NOT NULL CHECK(length(name) > 0),
+  created                      INTEGER NOT NULL DEFAULT (UNIXEPOCH())
+) STRICT;
+
+CREATE TABLE _user_role (
+  user                         BLOB NOT NULL REFERENCES _user(id) ON DELETE CASCADE,
+  role                         TEXT NOT NULL REFERENCES _role(name) ON DELETE CASCADE ON UPDATE CASCADE,
+
+  PRIMARY KEY (user, role)
+) STRICT;
+
+-- An index on the role for efficient lookups of a role's users and deletions.
+CREATE INDEX __user_role__role_index ON _user_role (role);
@@ -0,0 +1,18 @@
+-- API keys
+--
+-- Long-lived tokens for machine-to-machine access to record APIs. Keys act on
+-- behalf of their user restricted to their scopes. Only a hash of the key is
+-- stored, the key itself is shown once upon creation.
+CREATE TABLE _api_key (
+  id                           BLOB PRIMARY KEY NOT NULL CHECK(is_uuid_v7(id)) DEFAULT (uuid_v7()),
+  name                         TEXT NOT NULL,
+  user                         BLOB NOT NULL REFERENCES _user(id) ON DELETE CASCADE,
+  key_hash                     BLOB NOT NULL UNIQUE,
+  -- JSON array of scopes, e.g. [{"api": "*", "access": "read_only"}].
+  scopes                       TEXT NOT NULL DEFAULT '[]' CHECK(json_valid(scopes)),
+  created                      INTEGER NOT NULL DEFAULT (UNIXEPOCH()),
+  -- Optional UNIX timestamp in seconds after which the key is rejected.
+  expires                      INTEGER
+) STRICT;
+
+CREATE INDEX __api_key__user_index ON _api_key (user);
@@ -0,0 +1,21 @@
+-- Webhook deliveries
+--
+-- Persistent queue of outgoing webhook requests for record mutations, see
+-- `RecordApiConfig.webhooks`. Failed deliveries are retried with exponential
+-- backoff until they succeed or exhaust their attempts.
+CREATE TABLE _webhook_delivery (
+  id                           INTEGER PRIMARY KEY NOT NULL,
+  api                          TEXT NOT NULL,
+  url                          TEXT NOT NULL,
+  event                        TEXT NOT NULL,
+  payload                      TEXT NOT NULL CHECK(json_valid(payload)),
+  status                       TEXT NOT NULL DEFAULT 'pending' CHECK(status IN ('pending', 'delivered', 'failed')),
+  attempts                     INTEGER NOT NULL DEFAULT 0,
+  -- UNIX timestamp in seconds of the next delivery attempt.
+  next_attempt                 INTEGER NOT NULL DEFAULT (UNIXEPOCH()),
+  last_error                   TEXT,
+  created                      INTEGER NOT NULL DEFAULT (UNIXEPOCH()),
+  updated                      INTEGER NOT NULL DEFAULT (UNIXEPOCH())
+) STRICT;
+
+CREATE INDEX __webhook_delivery__status_next_attempt_index ON _webhook_delivery (status, next_attempt);
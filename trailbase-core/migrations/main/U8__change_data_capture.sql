@@ -0,0 +1,21 @@
+-- Change data capture
+--
+-- Changelog of tables configured in `server.change_data_capture`, populated by
+-- TrailBase-managed "_cdc_*" triggers and published in `seq` order.
+CREATE TABLE _cdc_change (
+  seq                          INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  table_name                   TEXT NOT NULL,
+  op                           TEXT NOT NULL CHECK(op IN ('insert', 'update', 'delete')),
+  row_id                       INTEGER NOT NULL,
+  -- Row after inserts and updates or before deletions.
+  record                       TEXT NOT NULL CHECK(json_valid(record)),
+  created                      INTEGER NOT NULL DEFAULT (UNIXEPOCH())
+) STRICT;
+
+-- High-water mark of published changes per sink, i.e. publishing resumes after
+-- `seq` on restart.
+CREATE TABLE _cdc_offset (
+  sink                         TEXT PRIMARY KEY NOT NULL,
+  seq                          INTEGER NOT NULL,
+  updated                      INTEGER NOT NULL DEFAULT (UNIXEPOCH())
+) STRICT;
@@ -0,0 +1,26 @@
+-- Audit log
+--
+-- Append-only log of authenticated record mutations and admin actions, see
+-- `server.audit_log`. Entries are only deleted once they exceed the retention.
+CREATE TABLE _audit_log (
+  id                           INTEGER PRIMARY KEY NOT NULL,
+  created                      INTEGER NOT NULL DEFAULT (UNIXEPOCH()),
+  -- Acting user. Not a foreign key, entries outlive their users.
+  user                         BLOB CHECK(is_uuid(user)),
+  action                       TEXT NOT NULL CHECK(action IN ('create', 'update', 'delete', 'admin')),
+  table_name                   TEXT,
+  record_id                    TEXT,
+  -- Changed columns before and after record mutations.
+  before                       TEXT CHECK(before IS NULL OR json_valid(before)),
+  after                        TEXT CHECK(after IS NULL OR json_valid(after)),
+  -- E.g. the method and path of admin requests.
+  details                      TEXT
+) STRICT;
+
+CREATE INDEX __audit_log__created_index ON _audit_log (created);
+CREATE INDEX __audit_log__table_name_record_id_index ON _audit_log (table_name, record_id);
+
+CREATE TRIGGER __audit_log__append_only BEFORE UPDATE ON _audit_log
+  BEGIN
+    SELECT RAISE(ABORT, 'audit log is append-only');
+  END;
@@ -0,0 +1,28 @@
+use axum::{Json, extract::State};
+use serde::Serialize;
+use ts_rs::TS;
+
+use crate::admin::AdminError as Error;
+use crate::app_state::AppState;
+use crate::backup::{BackupFile, create_backup, list_backups};
+
+#[derive(Debug, Serialize, TS)]
+#[ts(export)]
+pub struct ListBackupsResponse {
+  backups: Vec<BackupFile>,
+}
+
+pub async fn list_backups_handler(
+  State(state): State<AppState>,
+) -> Result<Json<ListBackupsResponse>, Error> {
+  return Ok(Json(ListBackupsResponse {
+    backups: list_backups(state.data_dir()).await?,
+  }));
+}
+
+/// Takes a backup right away, independent of the "Backup" job's schedule.
+pub async fn create_backup_handler(
+  State(state): State<AppState>,
+) -> Result<Json<BackupFile>, Error> {
+  return Ok(Json(create_backup(&state).await?));
+}
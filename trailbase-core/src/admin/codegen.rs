@@ -0,0 +1,29 @@
+use axum::extract::{Path, State};
+use axum::http::header;
+use axum::response::{IntoResponse, Response};
+
+use crate::admin::AdminError as Error;
+use crate::app_state::AppState;
+use crate::codegen::{CodegenTarget, generate_client};
+
+/// Generates a typed client for the configured record APIs in the requested target language.
+pub async fn codegen_handler(
+  State(state): State<AppState>,
+  Path(target): Path<CodegenTarget>,
+) -> Result<Response, Error> {
+  let filename = match target {
+    CodegenTarget::TypeScript => "trailbase_client.ts",
+    CodegenTarget::Dart => "trailbase_client.dart",
+    CodegenTarget::Kotlin => "TrailBaseModels.kt",
+    CodegenTarget::Swift => "TrailBaseModels.swift",
+    CodegenTarget::Proto => "trailbase_records.proto",
+  };
+
+  let mut response = generate_client(&state, target)?.into_response();
+  response.headers_mut().insert(
+    header::CONTENT_DISPOSITION,
+    header::HeaderValue::from_str(&format!("attachment; filename=\"{filename}\""))
+      .map_err(|err| Error::Internal(err.into()))?,
+  );
+  return Ok(response);
+}
@@ -7,6 +7,7 @@ use crate::admin::AdminError as Error;
 use crate::app_state::AppState;
 use crate::config::proto::{UpdateConfigRequest, Vault};
 use crate::config::{merge_vault_and_env, redact_secrets};
+use crate::records::query_plan::spawn_query_plan_check;
 
 pub async fn update_config_handler(
   State(state): State<AppState>,
@@ -25,6 +26,7 @@ pub async fn update_config_handler(
   let merged = merge_vault_and_env(config, Vault { secrets })?;
 
   state.validate_and_update_config(merged, Some(hash)).await?;
+  spawn_query_plan_check(&state);
 
   return Ok((StatusCode::OK, "Config updated"));
 }
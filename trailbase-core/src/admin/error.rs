@@ -1,9 +1,9 @@
-use axum::body::Body;
-use axum::http::{StatusCode, header::CONTENT_TYPE};
 use axum::response::{IntoResponse, Response};
 use log::*;
 use thiserror::Error;
 
+use crate::problem::{ErrorCode, Problem};
+
 // FIXME: Admin APIs also deserve more explicit error handling eventually.
 #[derive(Debug, Error)]
 pub enum AdminError {
@@ -55,28 +55,38 @@ pub enum AdminError {
   Query(#[from] crate::records::query_builder::QueryError),
   #[error("File error: {0}")]
   File(#[from] crate::records::files::FileError),
+  #[error("Codegen error: {0}")]
+  Codegen(#[from] crate::codegen::CodegenError),
+  #[error("Export error: {0}")]
+  Export(#[from] crate::export::ExportError),
+  #[error("Backup error: {0}")]
+  Backup(#[from] crate::backup::BackupError),
 }
 
 impl IntoResponse for AdminError {
   fn into_response(self) -> Response {
-    let (status, msg) = match self {
+    let problem = match self {
       // FIXME: For error types that already implement "into_response" we should just unpack them.
       // We should be able to use a generic for that.
       Self::Auth(err) => return err.into_response(),
-      Self::Deserialization(err) => (StatusCode::BAD_REQUEST, err.to_string()),
-      Self::Precondition(_) => (StatusCode::PRECONDITION_FAILED, self.to_string()),
-      Self::BadRequest(err) => (StatusCode::BAD_REQUEST, err.to_string()),
-      Self::Internal(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()),
-      Self::AlreadyExists(_) => (StatusCode::CONFLICT, self.to_string()),
+      Self::Deserialization(err) => {
+        Problem::new(ErrorCode::AdminBadRequest).with_detail(err.to_string())
+      }
+      Self::Precondition(_) => {
+        Problem::new(ErrorCode::AdminPreconditionFailed).with_detail(self.to_string())
+      }
+      Self::BadRequest(err) => {
+        Problem::new(ErrorCode::AdminBadRequest).with_detail(err.to_string())
+      }
+      Self::Internal(err) => Problem::new(ErrorCode::AdminInternal).with_detail(err.to_string()),
+      Self::AlreadyExists(_) => {
+        Problem::new(ErrorCode::AdminAlreadyExists).with_detail(self.to_string())
+      }
       // NOTE: We can almost always leak the internal error (except for permission errors) since
       // these are errors for the admin apis.
-      ref _err => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
+      ref _err => Problem::new(ErrorCode::AdminInternal).with_detail(self.to_string()),
     };
 
-    return Response::builder()
-      .status(status)
-      .header(CONTENT_TYPE, "text/plain")
-      .body(Body::new(msg))
-      .unwrap_or_default();
+    return problem.into_response();
   }
 }
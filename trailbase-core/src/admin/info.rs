@@ -13,6 +13,24 @@ pub struct InfoResponse {
   commit_hash: Option<String>,
   commit_date: Option<String>,
   threads: usize,
+  /// Dedicated read connections of the main database, see `read_connections`.
+  read_connections: usize,
+  /// Prepared statement cache hits and misses of the main database connection.
+  statement_cache_hits: u64,
+  statement_cache_misses: u64,
+  /// Record API response cache hits and misses, see `cache_ttl_sec`.
+  query_cache_hits: u64,
+  query_cache_misses: u64,
+  /// Rendered listing query cache hits and misses.
+  list_query_cache_hits: u64,
+  list_query_cache_misses: u64,
+  /// Current size of the main database's write-ahead log in bytes.
+  wal_size_bytes: Option<u64>,
+  /// Number of handler panics caught since start.
+  panics: u64,
+  /// Requests admitted and rejected by rate limits since start, see `server.rate_limits`.
+  rate_limit_allowed: u64,
+  rate_limit_rejected: u64,
 }
 
 pub async fn info_handler(State(state): State<AppState>) -> Result<Json<InfoResponse>, Error> {
@@ -24,11 +42,28 @@ pub async fn info_handler(State(state): State<AppState>) -> Result<Json<InfoResp
     patch = version_info.patch
   );
 
+  let statement_cache = state.conn().statement_cache_stats();
+  let query_cache = state.query_cache().stats();
+  let list_query_cache = state.list_query_cache().stats();
+  let rate_limits = state.rate_limiter().stats();
+  let wal_size_bytes = state.conn().wal_size().await?;
+
   return Ok(Json(InfoResponse {
     version,
     compiler: version_info.host_compiler,
     commit_hash: version_info.commit_hash,
     commit_date: version_info.commit_date,
     threads: std::thread::available_parallelism().map_or(0, |v| v.into()),
+    read_connections: state.conn().read_connections(),
+    statement_cache_hits: statement_cache.hits,
+    statement_cache_misses: statement_cache.misses,
+    query_cache_hits: query_cache.hits,
+    query_cache_misses: query_cache.misses,
+    list_query_cache_hits: list_query_cache.hits,
+    list_query_cache_misses: list_query_cache.misses,
+    wal_size_bytes,
+    panics: crate::server::panic_count(),
+    rate_limit_allowed: rate_limits.allowed,
+    rate_limit_rejected: rate_limits.rejected,
   }));
 }
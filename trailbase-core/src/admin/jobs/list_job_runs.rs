@@ -0,0 +1,59 @@
+use axum::{
+  Json,
+  extract::{Query, State},
+};
+use serde::{Deserialize, Serialize};
+use trailbase_sqlite::params;
+use ts_rs::TS;
+
+use crate::AppState;
+use crate::admin::AdminError as Error;
+use crate::constants::JOB_RUN_TABLE;
+
+const LIMIT: i64 = 500;
+
+#[derive(Debug, Deserialize, Serialize, TS)]
+#[ts(export)]
+pub struct JobRun {
+  pub id: i64,
+  pub name: String,
+  /// Start timestamp in seconds since epoch.
+  pub started: i64,
+  pub duration_ms: i64,
+  /// Error output, if the run failed.
+  pub error: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Default, TS)]
+#[ts(export)]
+pub struct ListJobRunsQuery {
+  /// Only list runs of the job with the given name.
+  name: Option<String>,
+  limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize, TS)]
+#[ts(export)]
+pub struct ListJobRunsResponse {
+  runs: Vec<JobRun>,
+}
+
+/// Lists the most recent runs of scheduled jobs, most recent first.
+pub async fn list_job_runs_handler(
+  State(state): State<AppState>,
+  Query(query): Query<ListJobRunsQuery>,
+) -> Result<Json<ListJobRunsResponse>, Error> {
+  let limit = query.limit.unwrap_or(LIMIT).clamp(1, LIMIT);
+
+  let runs = state
+    .conn()
+    .read_query_values::<JobRun>(
+      format!(
+        "SELECT id, name, started, duration_ms, error FROM {JOB_RUN_TABLE} WHERE $1 IS NULL OR name = $1 ORDER BY id DESC LIMIT $2"
+      ),
+      params!(query.name, limit),
+    )
+    .await?;
+
+  return Ok(Json(ListJobRunsResponse { runs }));
+}
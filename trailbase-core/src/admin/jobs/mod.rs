@@ -1,5 +1,7 @@
+mod list_job_runs;
 mod list_jobs;
 mod run_job;
 
+pub use list_job_runs::list_job_runs_handler;
 pub use list_jobs::list_jobs_handler;
 pub use run_job::run_job_handler;
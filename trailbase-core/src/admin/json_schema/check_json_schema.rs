@@ -0,0 +1,240 @@
+use axum::extract::{Json, State};
+use jsonschema::Validator;
+use serde::{Deserialize, Serialize};
+use trailbase_schema::registry::{compile_user_schema, get_compiled_schema};
+use ts_rs::TS;
+
+use crate::admin::AdminError as Error;
+use crate::app_state::AppState;
+use crate::schema_metadata::JsonColumnMetadata;
+
+/// Maximum number of offending rows reported, the total is always counted.
+const MAX_REPORTED_VIOLATIONS: usize = 100;
+const PAGE_SIZE: usize = 1024;
+
+#[derive(Debug, Serialize, TS)]
+#[ts(export)]
+pub struct JsonSchemaRowViolation {
+  pub table_name: String,
+  pub column_name: String,
+  pub rowid: i64,
+  pub errors: Vec<String>,
+}
+
+#[derive(Debug, Default, Serialize, TS)]
+#[ts(export)]
+pub struct CheckJsonSchemaResponse {
+  /// Number of non-NULL values checked across all columns referencing the schema.
+  pub checked: usize,
+  /// Total number of values failing validation.
+  pub violation_count: usize,
+  /// The first offending rows.
+  pub violations: Vec<JsonSchemaRowViolation>,
+}
+
+/// Re-validates the data of all table columns constrained by the schema `name`, i.e.
+/// `CHECK(jsonschema('<name>', col))`, against `validator`.
+pub(super) async fn check_schema_columns(
+  state: &AppState,
+  name: &str,
+  validator: &Validator,
+) -> Result<CheckJsonSchemaResponse, Error> {
+  let mut response = CheckJsonSchemaResponse::default();
+
+  for table in state.schema_metadata().tables() {
+    for (index, metadata) in table.json_metadata.columns.iter().enumerate() {
+      if !matches!(metadata, Some(JsonColumnMetadata::SchemaName(n, _)) if n == name) {
+        continue;
+      }
+
+      let table_name = &table.schema.name;
+      let column_name = &table.schema.columns[index].name;
+
+      #[derive(Deserialize)]
+      struct Row {
+        rowid: i64,
+        value: String,
+      }
+
+      let mut cursor: i64 = i64::MIN;
+      loop {
+        let rows = state
+          .conn()
+          .read_query_values::<Row>(
+            format!(
+              r#"SELECT _rowid_ AS rowid, "{column_name}" AS value FROM "{table_name}" WHERE "{column_name}" IS NOT NULL AND _rowid_ > ?1 ORDER BY _rowid_ LIMIT {PAGE_SIZE}"#
+            ),
+            trailbase_sqlite::params!(cursor),
+          )
+          .await?;
+
+        for row in &rows {
+          response.checked += 1;
+
+          let errors: Vec<String> = match serde_json::from_str::<serde_json::Value>(&row.value) {
+            Ok(value) => validator
+              .iter_errors(&value)
+              .map(|e| e.to_string())
+              .collect(),
+            Err(err) => vec![err.to_string()],
+          };
+          if errors.is_empty() {
+            continue;
+          }
+
+          response.violation_count += 1;
+          if response.violations.len() < MAX_REPORTED_VIOLATIONS {
+            response.violations.push(JsonSchemaRowViolation {
+              table_name: table_name.clone(),
+              column_name: column_name.clone(),
+              rowid: row.rowid,
+              errors,
+            });
+          }
+        }
+
+        match rows.last() {
+          Some(last) if rows.len() == PAGE_SIZE => cursor = last.rowid,
+          _ => break,
+        };
+      }
+    }
+  }
+
+  return Ok(response);
+}
+
+#[derive(Debug, Deserialize, TS)]
+#[ts(export)]
+pub struct CheckJsonSchemaRequest {
+  /// Schema referenced by the columns to check, e.g. "my.Schema@v1".
+  name: String,
+  /// Registered schema to check the existing data against, e.g. "my.Schema@v2".
+  target: Option<String>,
+  /// Unregistered schema to check against instead, compiled as if registered as `name`.
+  #[ts(type = "Object | undefined")]
+  schema: Option<serde_json::Value>,
+}
+
+/// Migration helper reporting the rows that would violate a new schema (version) before switching
+/// the columns over, e.g. from `jsonschema('my.Schema@v1', col)` to `jsonschema('my.Schema@v2',
+/// col)`, or before updating a schema in place.
+pub async fn check_schema_handler(
+  State(state): State<AppState>,
+  Json(request): Json<CheckJsonSchemaRequest>,
+) -> Result<Json<CheckJsonSchemaResponse>, Error> {
+  let validator = match (request.target, request.schema) {
+    (Some(_), Some(_)) => {
+      return Err(Error::BadRequest("Expected either target or schema".into()));
+    }
+    (_, Some(schema)) => std::sync::Arc::new(compile_user_schema(&request.name, &schema)?),
+    (target, None) => {
+      let target = target.unwrap_or_else(|| request.name.clone());
+      get_compiled_schema(&target)
+        .ok_or_else(|| Error::Precondition(format!("Schema {target} not found")))?
+    }
+  };
+
+  return Ok(Json(
+    check_schema_columns(&state, &request.name, &validator).await?,
+  ));
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::json;
+  use trailbase_schema::registry::set_user_schema;
+
+  use super::*;
+  use crate::app_state::test_state;
+
+  #[tokio::test]
+  async fn test_check_schema_columns() {
+    let state = test_state(None).await.unwrap();
+
+    set_user_schema(
+      "test_check.Person",
+      Some(json!({
+        "type": "object",
+        "properties": { "name": { "type": "string" } },
+      })),
+    )
+    .unwrap();
+    set_user_schema(
+      "test_check.Person@v2",
+      Some(json!({
+        "type": "object",
+        "properties": { "name": { "type": "string" } },
+        "required": ["name"],
+      })),
+    )
+    .unwrap();
+
+    state
+      .conn()
+      .execute_batch(
+        r#"
+          CREATE TABLE test_check (
+            id      INTEGER PRIMARY KEY,
+            person  TEXT CHECK(jsonschema('test_check.Person', person))
+          ) STRICT;
+          INSERT INTO test_check (person) VALUES ('{"name": "alice"}'), ('{}'), (NULL), ('{}');
+        "#,
+      )
+      .await
+      .unwrap();
+    state.refresh_table_cache().await.unwrap();
+
+    let response = check_schema_handler(
+      State(state.clone()),
+      Json(CheckJsonSchemaRequest {
+        name: "test_check.Person".to_string(),
+        target: Some("test_check.Person@v2".to_string()),
+        schema: None,
+      }),
+    )
+    .await
+    .unwrap()
+    .0;
+
+    assert_eq!(3, response.checked);
+    assert_eq!(2, response.violation_count);
+    assert_eq!(
+      vec![2, 4],
+      response
+        .violations
+        .iter()
+        .map(|v| v.rowid)
+        .collect::<Vec<_>>()
+    );
+    assert_eq!("person", response.violations[0].column_name);
+
+    // Existing data is valid against the current version.
+    let response = check_schema_handler(
+      State(state.clone()),
+      Json(CheckJsonSchemaRequest {
+        name: "test_check.Person".to_string(),
+        target: None,
+        schema: None,
+      }),
+    )
+    .await
+    .unwrap()
+    .0;
+    assert_eq!(0, response.violation_count);
+
+    // Unregistered candidates.
+    let response = check_schema_handler(
+      State(state.clone()),
+      Json(CheckJsonSchemaRequest {
+        name: "test_check.Person".to_string(),
+        target: None,
+        schema: Some(json!({"type": "string"})),
+      }),
+    )
+    .await
+    .unwrap()
+    .0;
+    assert_eq!(3, response.violation_count);
+  }
+}
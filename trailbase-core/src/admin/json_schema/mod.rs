@@ -1,12 +1,18 @@
+mod check_json_schema;
 mod get_api_json_schema;
 
+pub(super) use check_json_schema::check_schema_handler;
 pub(super) use get_api_json_schema::get_api_json_schema_handler;
 
-use axum::extract::{Json, State};
+use axum::extract::{Json, Query, State};
 use serde::{Deserialize, Serialize};
 use ts_rs::TS;
 
-use trailbase_schema::registry::{get_schemas, set_user_schema};
+use trailbase_schema::registry::{
+  SchemaChange, compile_user_schema, diff_schemas, get_schema, get_schemas, set_user_schema,
+};
+
+use crate::schema_metadata::JsonColumnMetadata;
 
 use crate::admin::AdminError as Error;
 use crate::app_state::AppState;
@@ -58,8 +64,37 @@ pub async fn update_schema_handler(
   State(state): State<AppState>,
   Json(request): Json<UpdateJsonSchemaRequest>,
 ) -> Result<Json<serde_json::Value>, Error> {
-  // Update the schema in memory.
   let (name, schema) = (request.name, request.schema);
+
+  // Make sure existing rows remain valid, otherwise they could no longer be updated.
+  match schema {
+    Some(ref schema) => {
+      let validator = compile_user_schema(&name, schema)?;
+      let check = check_json_schema::check_schema_columns(&state, &name, &validator).await?;
+      if check.violation_count > 0 {
+        return Err(Error::Precondition(format!(
+          "{} existing value(s) violate the updated schema {name}. Consider registering a new version instead, e.g. '{name}@v2'.",
+          check.violation_count
+        )));
+      }
+    }
+    None => {
+      let referenced = state.schema_metadata().tables().iter().any(|table| {
+        table
+          .json_metadata
+          .columns
+          .iter()
+          .any(|c| matches!(c, Some(JsonColumnMetadata::SchemaName(n, _)) if *n == name))
+      });
+      if referenced {
+        return Err(Error::Precondition(format!(
+          "Schema {name} is still referenced by table columns"
+        )));
+      }
+    }
+  };
+
+  // Update the schema in memory.
   set_user_schema(&name, schema.clone())?;
 
   // And if that succeeds update config.
@@ -70,6 +105,7 @@ pub async fn update_schema_handler(
     for s in &mut config.schemas {
       if s.name.as_ref() == Some(&name) {
         s.schema = Some(schema.to_string());
+        s.url = None;
         found = true;
       }
     }
@@ -78,6 +114,7 @@ pub async fn update_schema_handler(
       config.schemas.push(crate::config::proto::JsonSchemaConfig {
         name: Some(name.clone()),
         schema: Some(schema.to_string()),
+        ..Default::default()
       })
     }
   } else {
@@ -99,3 +136,31 @@ pub async fn update_schema_handler(
 
   return Ok(Json(serde_json::json!({})));
 }
+
+#[derive(Debug, Deserialize, TS)]
+#[ts(export)]
+pub struct DiffJsonSchemasQuery {
+  from: String,
+  to: String,
+}
+
+#[derive(Debug, Serialize, TS)]
+#[ts(export)]
+pub struct DiffJsonSchemasResponse {
+  changes: Vec<SchemaChange>,
+}
+
+/// Diffs two registered schemas, e.g. two versions "my.Schema@v1" and "my.Schema@v2".
+pub async fn diff_schemas_handler(
+  State(_state): State<AppState>,
+  Query(query): Query<DiffJsonSchemasQuery>,
+) -> Result<Json<DiffJsonSchemasResponse>, Error> {
+  let lookup = |name: &str| {
+    return get_schema(name).ok_or_else(|| Error::Precondition(format!("Schema {name} not found")));
+  };
+  let (from, to) = (lookup(&query.from)?, lookup(&query.to)?);
+
+  return Ok(Json(DiffJsonSchemasResponse {
+    changes: diff_schemas(&from.schema, &to.schema),
+  }));
+}
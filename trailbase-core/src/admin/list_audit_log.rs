@@ -0,0 +1,126 @@
+use axum::{
+  Json,
+  extract::{RawQuery, State},
+};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use ts_rs::TS;
+use uuid::Uuid;
+
+use crate::admin::AdminError as Error;
+use crate::app_state::AppState;
+use crate::constants::AUDIT_LOG_TABLE;
+use crate::listing::{
+  Cursor, QueryParseResult, build_filter_where_clause, limit_or_default, parse_and_sanitize_query,
+};
+
+#[derive(Debug, Serialize, TS)]
+pub struct AuditLogEntryJson {
+  pub id: i64,
+  pub created: i64,
+  pub user_id: Option<String>,
+  /// One of "create", "update", "delete" or "admin".
+  pub action: String,
+  pub table_name: Option<String>,
+  pub record_id: Option<String>,
+  /// Changed columns before and after record mutations.
+  pub before: Option<serde_json::Value>,
+  pub after: Option<serde_json::Value>,
+  pub details: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AuditLogEntry {
+  id: i64,
+  created: i64,
+  user: Option<[u8; 16]>,
+  action: String,
+  table_name: Option<String>,
+  record_id: Option<String>,
+  before: Option<String>,
+  after: Option<String>,
+  details: Option<String>,
+}
+
+impl TryFrom<AuditLogEntry> for AuditLogEntryJson {
+  type Error = serde_json::Error;
+
+  fn try_from(value: AuditLogEntry) -> Result<Self, Self::Error> {
+    let parse = |json: Option<String>| json.as_deref().map(serde_json::from_str).transpose();
+
+    return Ok(AuditLogEntryJson {
+      id: value.id,
+      created: value.created,
+      user_id: value.user.map(|blob| Uuid::from_bytes(blob).to_string()),
+      action: value.action,
+      table_name: value.table_name,
+      record_id: value.record_id,
+      before: parse(value.before)?,
+      after: parse(value.after)?,
+      details: value.details,
+    });
+  }
+}
+
+#[derive(Debug, Serialize, TS)]
+#[ts(export)]
+pub struct ListAuditLogResponse {
+  cursor: Option<String>,
+  entries: Vec<AuditLogEntryJson>,
+}
+
+/// Lists audit log entries, newest first. Supports the same filters as record listings, e.g.
+/// `?filter[table_name]=orders&filter[action]=delete&filter[created][$gte]=1735689600`.
+pub async fn list_audit_log_handler(
+  State(state): State<AppState>,
+  RawQuery(raw_url_query): RawQuery,
+) -> Result<Json<ListAuditLogResponse>, Error> {
+  let QueryParseResult {
+    params: filter_params,
+    filter,
+    cursor,
+    limit,
+    ..
+  } = parse_and_sanitize_query(raw_url_query.as_deref())
+    .map_err(|err| Error::Precondition(format!("Invalid query '{err}': {raw_url_query:?}")))?;
+  let cursor = cursor.as_deref().and_then(Cursor::parse);
+
+  let Some(table) = state.schema_metadata().get_table(AUDIT_LOG_TABLE) else {
+    return Err(Error::Precondition(format!(
+      "Missing table: {AUDIT_LOG_TABLE}"
+    )));
+  };
+  let filter_where_clause =
+    build_filter_where_clause("audit", &table.schema.columns, filter_params, filter, None)?;
+
+  let mut params = filter_where_clause.params;
+  let mut where_clause = filter_where_clause.clause;
+  params.push((
+    Cow::Borrowed(":limit"),
+    trailbase_sqlite::Value::Integer(
+      limit_or_default(limit).map_err(|err| Error::BadRequest(err.into()))? as i64,
+    ),
+  ));
+  if let Some(cursor) = cursor {
+    params.push((Cow::Borrowed(":cursor"), cursor.into()));
+    where_clause = format!("{where_clause} AND audit.id < :cursor");
+  }
+
+  let entries = state
+    .conn()
+    .read_query_values::<AuditLogEntry>(
+      format!(
+        "SELECT audit.* FROM {AUDIT_LOG_TABLE} AS audit WHERE {where_clause} ORDER BY audit.id DESC LIMIT :limit"
+      ),
+      params,
+    )
+    .await?;
+
+  return Ok(Json(ListAuditLogResponse {
+    cursor: entries.last().map(|entry| entry.id.to_string()),
+    entries: entries
+      .into_iter()
+      .map(AuditLogEntryJson::try_from)
+      .collect::<Result<Vec<_>, _>>()?,
+  }));
+}
@@ -36,6 +36,7 @@ pub struct LogJson {
   pub referer: String,
   pub user_agent: String,
   pub user_id: Option<String>,
+  pub request_id: Option<String>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -56,6 +57,7 @@ struct LogEntry {
   referer: String,
   user_agent: String,
   user_id: Option<[u8; 16]>,
+  request_id: Option<String>,
   // data: Option<Vec<u8>>,
 }
 
@@ -87,6 +89,7 @@ impl From<LogEntry> for LogJson {
       referer: value.referer,
       user_agent: value.user_agent,
       user_id: value.user_id.map(|blob| Uuid::from_bytes(blob).to_string()),
+      request_id: value.request_id,
     };
   }
 }
@@ -111,19 +114,27 @@ pub async fn list_logs_handler(
   // falling back to defaults.
   let QueryParseResult {
     params: filter_params,
+    filter,
     cursor,
     limit,
     order,
     ..
   } = parse_and_sanitize_query(raw_url_query.as_deref())
     .map_err(|err| Error::Precondition(format!("Invalid query '{err}': {raw_url_query:?}")))?;
+  let cursor = cursor.as_deref().and_then(Cursor::parse);
 
   // NOTE: We cannot use state.schema_metadata() here, since we're working on the logs database.
   // We could cache, however this is just the admin logs handler.
   let table = lookup_and_parse_table_schema(conn, LOGS_TABLE_NAME).await?;
   let schema_metadata = TableMetadata::new(table.clone(), &[table], crate::constants::USER_TABLE);
   let filter_where_clause =
-    build_filter_where_clause("log", &schema_metadata.schema.columns, filter_params)?;
+    build_filter_where_clause(
+    "log",
+    &schema_metadata.schema.columns,
+    filter_params,
+    filter,
+    None,
+  )?;
 
   let total_row_count: i64 = conn
     .read_query_row_f(
@@ -353,16 +364,21 @@ async fn fetch_aggregate_stats(
   "#
     );
 
-    let rows = conn.read_query_rows(cc_query, ()).await?;
+    #[derive(Deserialize)]
+    struct CountryRow {
+      country_code: Option<String>,
+      count: i64,
+    }
 
-    let mut country_codes = HashMap::<String, usize>::new();
-    for row in rows.iter() {
-      let cc: Option<String> = row.get(0)?;
-      let count: i64 = row.get(1)?;
+    let rows = conn.read_query_as::<CountryRow>(cc_query, ()).await?;
 
+    let mut country_codes = HashMap::<String, usize>::new();
+    for row in rows {
       country_codes.insert(
-        cc.unwrap_or_else(|| "unattributed".to_string()),
-        count as usize,
+        row
+          .country_code
+          .unwrap_or_else(|| "unattributed".to_string()),
+        row.count as usize,
       );
     }
 
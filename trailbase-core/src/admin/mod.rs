@@ -1,17 +1,22 @@
+mod backup;
+mod codegen;
 mod config;
 mod error;
 mod info;
 mod jobs;
 mod json_schema;
 mod jwt;
+mod list_audit_log;
 mod list_logs;
 mod oauth_providers;
 mod parse;
 mod query;
+mod query_plans;
 pub(crate) mod rows;
-mod table;
+pub(crate) mod table;
 pub(crate) mod user;
 mod util;
+mod webhooks;
 
 pub use error::AdminError;
 
@@ -26,6 +31,7 @@ pub fn router() -> Router<AppState> {
     // Row actions.
     .route("/table/{table_name}/rows", get(rows::list_rows_handler))
     .route("/table/{table_name}/files", get(rows::read_files_handler))
+    .route("/table/{table_name}/export", get(rows::export_rows_handler))
     .route(
       "/table/{table_name}/rows",
       delete(rows::delete_rows_handler),
@@ -41,8 +47,13 @@ pub fn router() -> Router<AppState> {
     .route("/table", post(table::create_table_handler))
     .route("/table", delete(table::drop_table_handler))
     .route("/table", patch(table::alter_table_handler))
+    .route(
+      "/table/{table_name}/schema",
+      post(table::mutate_table_handler),
+    )
     // Table & Index actions.
     .route("/tables", get(table::list_tables_handler))
+    .route("/tables/apply", post(table::apply_schema_handler))
     // Config actions
     .route("/config", get(config::get_config_handler))
     .route("/config", post(config::update_config_handler))
@@ -51,15 +62,28 @@ pub fn router() -> Router<AppState> {
     .route("/user", post(user::create_user_handler))
     .route("/user", patch(user::update_user_handler))
     .route("/user", delete(user::delete_user_handler))
+    // Role actions
+    .route("/role", get(user::list_roles_handler))
+    .route("/role", post(user::create_role_handler))
+    .route("/role", delete(user::delete_role_handler))
+    // API key actions
+    .route("/api_key", get(user::list_api_keys_handler))
+    .route("/api_key", post(user::create_api_key_handler))
+    .route("/api_key", delete(user::delete_api_key_handler))
     // Schema actions
     .route("/schema", get(json_schema::list_schemas_handler))
     .route("/schema", post(json_schema::update_schema_handler))
+    .route("/schema/diff", get(json_schema::diff_schemas_handler))
+    .route("/schema/check", post(json_schema::check_schema_handler))
     .route(
       "/schema/{record_api_name}/schema.json",
       get(json_schema::get_api_json_schema_handler),
     )
+    // Client code generation
+    .route("/codegen/{target}", get(codegen::codegen_handler))
     // Logs
     .route("/logs", get(list_logs::list_logs_handler))
+    .route("/audit_log", get(list_audit_log::list_audit_log_handler))
     // Query execution handler for the UI editor
     .route("/query", post(query::query_handler))
     // Parse handler for UI validation.
@@ -71,6 +95,18 @@ pub fn router() -> Router<AppState> {
     )
     .route("/public_key", get(jwt::get_public_key))
     .route("/info", get(info::info_handler))
+    .route("/query_plans", get(query_plans::query_plans_handler))
     .route("/jobs", get(jobs::list_jobs_handler))
+    .route("/backup", get(backup::list_backups_handler))
+    .route("/backup", post(backup::create_backup_handler))
     .route("/job/run", post(jobs::run_job_handler))
+    .route("/job/runs", get(jobs::list_job_runs_handler))
+    .route(
+      "/webhook/delivery",
+      get(webhooks::list_webhook_deliveries_handler),
+    )
+    .route(
+      "/webhook/retry",
+      post(webhooks::retry_webhook_delivery_handler),
+    )
 }
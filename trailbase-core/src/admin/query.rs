@@ -6,6 +6,7 @@ use ts_rs::TS;
 
 use crate::admin::AdminError as Error;
 use crate::app_state::AppState;
+use crate::records::query_plan::spawn_query_plan_check;
 
 #[derive(Debug, Default, Serialize, TS)]
 #[ts(export)]
@@ -74,6 +75,7 @@ pub async fn query_handler(
   // In the fallback case we always need to invalidate the cache.
   if must_invalidate_table_cache {
     state.schema_metadata().invalidate_all().await?;
+    spawn_query_plan_check(&state);
   }
 
   let batched_rows = batched_rows_result.map_err(|err| Error::BadRequest(err.into()))?;
@@ -0,0 +1,23 @@
+use axum::{Json, extract::State};
+use serde::Serialize;
+use ts_rs::TS;
+
+use crate::admin::AdminError as Error;
+use crate::app_state::AppState;
+use crate::records::query_plan::{QueryPlanWarning, explain_record_apis};
+
+#[derive(Debug, Serialize, TS)]
+#[ts(export)]
+pub struct QueryPlansResponse {
+  warnings: Vec<QueryPlanWarning>,
+}
+
+pub async fn query_plans_handler(
+  State(state): State<AppState>,
+) -> Result<Json<QueryPlansResponse>, Error> {
+  let warnings = explain_record_apis(&state)
+    .await
+    .map_err(|err| Error::Internal(err.into()))?;
+
+  return Ok(Json(QueryPlansResponse { warnings }));
+}
@@ -62,6 +62,8 @@ pub(crate) async fn delete_row(
     pk_col,
     simple_json_value_to_param(column.data_type, value)?,
     schema_metadata.json_metadata.has_file_columns(),
+    None,
+    None,
   )
   .await?;
 
@@ -160,8 +162,12 @@ mod tests {
           foreign_keys: vec![],
           unique: vec![],
           checks: vec![],
+          primary_key: None,
           virtual_table: false,
           temporary: false,
+          fts5: None,
+          vec0: None,
+          rtree: None,
         },
         dry_run: Some(false),
       }),
@@ -0,0 +1,231 @@
+use axum::Json;
+use axum::body::Body;
+use axum::extract::{Path, RawQuery, State};
+use axum::http::header;
+use axum::response::{IntoResponse, Response};
+use futures_util::{StreamExt, TryStreamExt};
+use serde::Serialize;
+use ts_rs::TS;
+
+use crate::admin::AdminError as Error;
+use crate::app_state::AppState;
+use crate::export::{ExportColumns, ExportFormat, encode_batches, query_batches};
+use crate::listing::{
+  Order, QueryParseResult, WhereClause, build_filter_where_clause, parse_and_sanitize_query,
+};
+use crate::schema_metadata::TableOrViewMetadata;
+
+#[derive(Debug, Serialize, TS)]
+#[ts(export)]
+pub struct ExportRowsResponse {
+  /// Object store path of the written export.
+  pub path: String,
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct ExportRowsOptions {
+  format: ExportFormat,
+  /// Write the export to the object store rather than streaming it back.
+  to_objectstore: bool,
+}
+
+/// Splits out export-specific query parameters, returning the remainder as list query.
+fn split_export_query(query: Option<&str>) -> Result<(ExportRowsOptions, String), Error> {
+  let mut options = ExportRowsOptions::default();
+  let mut remainder = form_urlencoded::Serializer::new(String::new());
+
+  for (key, value) in form_urlencoded::parse(query.unwrap_or_default().as_bytes()) {
+    match key.as_ref() {
+      "format" => {
+        options.format = ExportFormat::parse(&value)
+          .ok_or_else(|| Error::BadRequest(format!("Unsupported format: {value}").into()))?;
+      }
+      "to" => {
+        options.to_objectstore = match value.as_ref() {
+          "objectstore" => true,
+          "response" => false,
+          _ => return Err(Error::BadRequest(format!("Invalid target: {value}").into())),
+        };
+      }
+      _ => {
+        remainder.append_pair(&key, &value);
+      }
+    }
+  }
+
+  return Ok((options, remainder.finish()));
+}
+
+/// Exports all rows of a table or view matching the given filters as CSV or Parquet.
+///
+/// Either streams the export back or, with `to=objectstore`, writes it to the configured object
+/// store under `exports/`, which is useful for feeding analytics pipelines.
+pub async fn export_rows_handler(
+  State(state): State<AppState>,
+  Path(table_name): Path<String>,
+  RawQuery(raw_url_query): RawQuery,
+) -> Result<Response, Error> {
+  let (options, list_query) = split_export_query(raw_url_query.as_deref())?;
+
+  let QueryParseResult {
+    params: filter_params,
+    filter,
+    limit,
+    order,
+    offset,
+    ..
+  } = parse_and_sanitize_query(Some(&list_query))
+    .map_err(|err| Error::Precondition(format!("Invalid query '{err}': {list_query:?}")))?;
+
+  let (metadata, is_table): (std::sync::Arc<dyn TableOrViewMetadata + Send + Sync>, bool) =
+    if let Some(metadata) = state.schema_metadata().get_table(&table_name) {
+      (metadata, true)
+    } else if let Some(metadata) = state.schema_metadata().get_view(&table_name) {
+      (metadata, false)
+    } else {
+      return Err(Error::Precondition(format!(
+        "Table or view '{table_name}' not found"
+      )));
+    };
+
+  let (Some(columns), Some(json_metadata)) = (metadata.columns(), metadata.json_metadata()) else {
+    return Err(Error::Precondition(format!(
+      "Cannot export '{table_name}' with unknown columns"
+    )));
+  };
+
+  let WhereClause { clause, params } =
+    build_filter_where_clause("_ROW_", columns, filter_params, filter, None)?;
+
+  fn fmt_order(col: &str, order: Order) -> String {
+    return format!(
+      r#"_ROW_."{col}" {}"#,
+      match order {
+        Order::Descending => "DESC",
+        Order::Ascending => "ASC",
+      }
+    );
+  }
+
+  // Batches are fetched using offsets, thus break ties to get a stable order.
+  let mut order_clause: Vec<String> = order
+    .unwrap_or_default()
+    .into_iter()
+    .map(|(col, ord)| fmt_order(&col, ord))
+    .collect();
+  if let Some((_idx, pk_column)) = metadata.record_pk_column() {
+    order_clause.push(fmt_order(&pk_column.name, Order::Descending));
+  } else if is_table {
+    order_clause.push("_ROW_._rowid_ DESC".to_string());
+  } else if order_clause.is_empty() {
+    order_clause.push("NULL".to_string());
+  }
+
+  let column_names = columns
+    .iter()
+    .map(|c| format!(r#"_ROW_."{}""#, c.name))
+    .collect::<Vec<_>>()
+    .join(", ");
+  let query = format!(
+    r#"
+      SELECT {column_names}
+      FROM '{table_name}' AS _ROW_
+      WHERE {clause}
+      ORDER BY {order_clause}
+      LIMIT :__limit
+      OFFSET :__offset
+    "#,
+    order_clause = order_clause.join(", "),
+  );
+
+  let format = options.format;
+  let batches = query_batches(
+    state.conn().clone(),
+    query,
+    params,
+    offset.unwrap_or(0),
+    limit,
+  )
+  .map_err(Error::from);
+
+  let chunks = encode_batches(
+    format,
+    ExportColumns {
+      columns: columns.to_vec(),
+      json_metadata: json_metadata.columns.clone(),
+      selected: (0..columns.len()).collect(),
+    },
+    false,
+    batches,
+  )?;
+
+  if options.to_objectstore {
+    let path = format!(
+      "exports/{table_name}_{}.{}",
+      chrono::Utc::now().format("%Y%m%dT%H%M%S"),
+      format.extension()
+    );
+
+    let upload = state
+      .objectstore()
+      .put_multipart(&object_store::path::Path::from(path.as_str()))
+      .await
+      .map_err(|err| Error::Internal(err.into()))?;
+    let mut writer = object_store::WriteMultipart::new(upload);
+
+    let mut chunks = Box::pin(chunks);
+    while let Some(chunk) = chunks.next().await {
+      match chunk {
+        Ok(chunk) => writer.write(&chunk),
+        Err(err) => {
+          let _ = writer.abort().await;
+          return Err(err);
+        }
+      };
+    }
+    writer
+      .finish()
+      .await
+      .map_err(|err| Error::Internal(err.into()))?;
+
+    return Ok(Json(ExportRowsResponse { path }).into_response());
+  }
+
+  return Ok(
+    (
+      [
+        (header::CONTENT_TYPE, format.content_type().to_string()),
+        (
+          header::CONTENT_DISPOSITION,
+          format!(
+            "attachment; filename=\"{table_name}.{}\"",
+            format.extension()
+          ),
+        ),
+      ],
+      Body::from_stream(chunks),
+    )
+      .into_response(),
+  );
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_split_export_query() {
+    let (options, remainder) =
+      split_export_query(Some("format=parquet&to=objectstore&limit=5")).unwrap();
+    assert_eq!(
+      ExportRowsOptions {
+        format: ExportFormat::Parquet,
+        to_objectstore: true,
+      },
+      options
+    );
+    assert_eq!("limit=5", remainder);
+
+    assert!(split_export_query(Some("to=s3")).is_err());
+  }
+}
@@ -37,6 +37,7 @@ pub(crate) async fn insert_row(
     state,
     schema_metadata.name(),
     None,
+    None,
     "_rowid_",
     schema_metadata.json_metadata.has_file_columns(),
     Params::from(&*schema_metadata, json_row, None)?,
@@ -50,3 +51,139 @@ pub(crate) async fn insert_row(
     )),
   };
 }
+
+/// Seeds the given table with a record or array of records, e.g. test fixtures, returning their
+/// rowids.
+///
+/// Unlike raw SQL, values are converted and validated like record API requests, i.e. JSON schemas
+/// are enforced and files written to the object store. Records are inserted atomically.
+pub async fn seed_records(
+  state: &AppState,
+  table_name: &str,
+  records: serde_json::Value,
+) -> Result<Vec<i64>, Error> {
+  let Some(schema_metadata) = state.schema_metadata().get_table(table_name) else {
+    return Err(Error::Precondition(format!("Table {table_name} not found")));
+  };
+
+  let rows: Vec<JsonRow> = match records {
+    serde_json::Value::Object(row) => vec![row],
+    serde_json::Value::Array(rows) => rows
+      .into_iter()
+      .map(|row| match row {
+        serde_json::Value::Object(row) => Ok(row),
+        _ => Err(Error::BadRequest(
+          "Expected record or array of records".into(),
+        )),
+      })
+      .collect::<Result<_, _>>()?,
+    _ => {
+      return Err(Error::BadRequest(
+        "Expected record or array of records".into(),
+      ));
+    }
+  };
+  if rows.is_empty() {
+    return Ok(vec![]);
+  }
+
+  let params_list = rows
+    .into_iter()
+    .map(|row| Params::from(&*schema_metadata, row, None))
+    .collect::<Result<Vec<_>, _>>()?;
+
+  let rowids = InsertQueryBuilder::run_bulk(
+    state,
+    schema_metadata.name(),
+    None,
+    None,
+    "_rowid_",
+    schema_metadata.json_metadata.has_file_columns(),
+    params_list,
+  )
+  .await?;
+
+  return rowids
+    .into_iter()
+    .map(|(rowid, _inserted)| match rowid {
+      rusqlite::types::Value::Integer(rowid) => Ok(rowid),
+      _ => Err(Error::Internal(
+        format!("unexpected return type: {rowid:?}").into(),
+      )),
+    })
+    .collect();
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::app_state::test_state;
+
+  #[tokio::test]
+  async fn test_seed_records() {
+    let state = test_state(None).await.unwrap();
+    state
+      .conn()
+      .execute_batch(
+        r#"
+          CREATE TABLE seeded (
+            id      INTEGER PRIMARY KEY,
+            name    TEXT NOT NULL,
+            data    TEXT CHECK(jsonschema('std.FileUpload', data))
+          ) STRICT;
+        "#,
+      )
+      .await
+      .unwrap();
+    state.refresh_table_cache().await.unwrap();
+
+    let rowids = seed_records(
+      &state,
+      "seeded",
+      serde_json::json!([
+        { "name": "first" },
+        { "name": "second", "data": { "data": [104, 101, 108, 108, 111], "filename": "hello.txt" } },
+      ]),
+    )
+    .await
+    .unwrap();
+    assert_eq!(rowids.len(), 2);
+
+    // The file got written to the object store rather than inlined.
+    let data: String = state
+      .conn()
+      .read_query_row_f("SELECT data FROM seeded WHERE name = 'second'", (), |row| {
+        row.get(0)
+      })
+      .await
+      .unwrap()
+      .unwrap();
+    let upload: serde_json::Value = serde_json::from_str(&data).unwrap();
+    assert_eq!(upload["filename"], "hello.txt");
+    assert!(upload.get("data").is_none());
+
+    // Invalid records fail the entire batch.
+    assert!(
+      seed_records(
+        &state,
+        "seeded",
+        serde_json::json!([{ "name": "third" }, { "data": { "invalid": 1 } }]),
+      )
+      .await
+      .is_err()
+    );
+    let count: i64 = state
+      .conn()
+      .read_query_row_f("SELECT COUNT(*) FROM seeded", (), |row| row.get(0))
+      .await
+      .unwrap()
+      .unwrap();
+    assert_eq!(count, 2);
+
+    assert!(
+      seed_records(&state, "missing", serde_json::json!({ "name": "x" }))
+        .await
+        .is_err()
+    );
+  }
+}
@@ -36,6 +36,7 @@ pub async fn list_rows_handler(
 ) -> Result<Json<ListRowsResponse>, Error> {
   let QueryParseResult {
     params: filter_params,
+    filter,
     cursor,
     limit,
     order,
@@ -43,6 +44,7 @@ pub async fn list_rows_handler(
     ..
   } = parse_and_sanitize_query(raw_url_query.as_deref())
     .map_err(|err| Error::Precondition(format!("Invalid query '{err}': {raw_url_query:?}")))?;
+  let cursor = cursor.as_deref().and_then(Cursor::parse);
 
   let (schema_metadata, table_or_view_metadata): (
     Option<Arc<TableMetadata>>,
@@ -62,7 +64,7 @@ pub async fn list_rows_handler(
   // Where clause contains column filters and cursor depending on what's present in the url query
   // string.
   let filter_where_clause = if let Some(columns) = table_or_view_metadata.columns() {
-    build_filter_where_clause("_ROW_", columns, filter_params)?
+    build_filter_where_clause("_ROW_", columns, filter_params, filter, None)?
   } else {
     debug!("Filter clauses currently not supported for complex views");
 
@@ -84,7 +86,10 @@ pub async fn list_rows_handler(
       .unwrap_or(-1)
   };
 
-  let cursor_column = table_or_view_metadata.record_pk_column();
+  // Random primary keys, e.g. UUIDv4, fall back to offset pagination.
+  let cursor_column = table_or_view_metadata
+    .record_pk_column()
+    .filter(|_| table_or_view_metadata.record_pk_sortable());
   let (rows, columns) = fetch_rows(
     state.conn(),
     &table_name,
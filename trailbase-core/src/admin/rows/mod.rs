@@ -1,11 +1,14 @@
 mod delete_rows;
+mod export_rows;
 mod insert_row;
 mod list_rows;
 mod read_files;
 mod update_row;
 
 pub(super) use delete_rows::{delete_row, delete_row_handler, delete_rows_handler};
+pub(super) use export_rows::export_rows_handler;
 pub(super) use insert_row::insert_row_handler;
+pub(crate) use insert_row::seed_records;
 pub(super) use list_rows::list_rows_handler;
 pub(super) use read_files::read_files_handler;
 pub(super) use update_row::update_row_handler;
@@ -58,6 +58,7 @@ pub async fn read_files_handler(
   };
 
   let pk_value = simple_json_value_to_param(col.data_type, request.pk_value)?;
+  let record_id_clause = format!(r#""{pk_col}" = $1"#);
 
   return if let Some(file_index) = request.file_index {
     let mut file_uploads = GetFilesQueryBuilder::run(
@@ -65,7 +66,7 @@ pub async fn read_files_handler(
       &table_name,
       file_col_metadata,
       file_col_json_metadata,
-      &request.pk_column,
+      &record_id_clause,
       pk_value,
     )
     .await?;
@@ -81,7 +82,7 @@ pub async fn read_files_handler(
       &table_name,
       file_col_metadata,
       file_col_json_metadata,
-      &request.pk_column,
+      &record_id_clause,
       pk_value,
     )
     .await?;
@@ -66,6 +66,7 @@ pub async fn update_row_handler(
     schema_metadata.name(),
     &column.name,
     schema_metadata.json_metadata.has_file_columns(),
+    None,
     Params::from(&*schema_metadata, row, None)?,
   )
   .await?;
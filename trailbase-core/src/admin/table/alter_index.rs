@@ -11,6 +11,7 @@ use ts_rs::TS;
 
 use crate::admin::AdminError as Error;
 use crate::app_state::AppState;
+use crate::records::query_plan::spawn_query_plan_check;
 use crate::transaction::TransactionRecorder;
 
 #[derive(Clone, Debug, Deserialize, TS)]
@@ -65,5 +66,7 @@ pub async fn alter_index_handler(
     debug!("Migration report: {report:?}");
   }
 
+  spawn_query_plan_check(&state);
+
   return Ok((StatusCode::OK, "altered index").into_response());
 }
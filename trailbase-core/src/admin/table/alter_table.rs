@@ -13,6 +13,7 @@ use ts_rs::TS;
 
 use crate::admin::AdminError as Error;
 use crate::app_state::AppState;
+use crate::records::query_plan::spawn_query_plan_check;
 use crate::transaction::{TransactionLog, TransactionRecorder};
 
 #[derive(Clone, Debug, Deserialize, TS)]
@@ -133,6 +134,7 @@ pub async fn alter_table_handler(
   }
 
   state.schema_metadata().invalidate_all().await?;
+  spawn_query_plan_check(&state);
 
   return Ok((StatusCode::OK, "altered table").into_response());
 }
@@ -166,8 +168,12 @@ mod tests {
         foreign_keys: vec![],
         unique: vec![],
         checks: vec![],
+        primary_key: None,
         virtual_table: false,
         temporary: false,
+        fts5: None,
+        vec0: None,
+        rtree: None,
       },
       dry_run: Some(false),
     };
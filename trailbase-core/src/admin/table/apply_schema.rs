@@ -0,0 +1,205 @@
+use axum::{Json, extract::State};
+use log::*;
+use serde::{Deserialize, Serialize};
+use trailbase_schema::migration::{DeclaredSchema, plan_migration};
+use trailbase_schema::sqlite::{TableIndex, sqlite3_parse_into_statement};
+use ts_rs::TS;
+
+use crate::admin::AdminError as Error;
+use crate::app_state::AppState;
+use crate::constants::SQLITE_SCHEMA_TABLE;
+use crate::records::query_plan::spawn_query_plan_check;
+use crate::schema_metadata::schema_snapshot;
+use crate::transaction::TransactionRecorder;
+
+#[derive(Clone, Debug, Deserialize, TS)]
+#[ts(export)]
+pub struct ApplySchemaRequest {
+  /// Desired schema as `CREATE TABLE`, `CREATE VIEW` and `CREATE INDEX` statements.
+  pub sql: String,
+  /// Allow dropping tables and columns missing from the desired schema.
+  pub allow_data_loss: Option<bool>,
+  pub dry_run: Option<bool>,
+}
+
+#[derive(Clone, Debug, Serialize, TS)]
+#[ts(export)]
+pub struct ApplySchemaResponse {
+  /// The generated migration, empty if the schema is already up to date.
+  pub sql: String,
+}
+
+/// Current app-owned schema, i.e. excluding TrailBase's own "_"-prefixed tables.
+pub(super) async fn current_schema(state: &AppState) -> Result<DeclaredSchema, Error> {
+  let snapshot = schema_snapshot(state);
+
+  let rows = state
+    .conn()
+    .read_query_as::<String>(
+      format!("SELECT sql FROM {SQLITE_SCHEMA_TABLE} WHERE type = 'index' AND sql IS NOT NULL"),
+      (),
+    )
+    .await?;
+
+  let mut indexes: Vec<TableIndex> = vec![];
+  for sql in rows {
+    let Some(stmt) =
+      sqlite3_parse_into_statement(&sql).map_err(|err| Error::Internal(err.into()))?
+    else {
+      continue;
+    };
+    let index = TableIndex {
+      if_not_exists: false,
+      ..stmt.try_into()?
+    };
+
+    if snapshot.tables.iter().any(|t| t.name == index.table_name) {
+      indexes.push(index);
+    }
+  }
+
+  return Ok(DeclaredSchema {
+    tables: snapshot.tables,
+    views: snapshot.views,
+    indexes,
+    triggers: snapshot.triggers,
+  });
+}
+
+/// Migrates the database to the desired schema, writing the generated migration to the migrations
+/// directory. Returns the generated statements.
+pub async fn apply_schema(
+  state: &AppState,
+  sql: &str,
+  allow_data_loss: bool,
+  dry_run: bool,
+) -> Result<Vec<String>, Error> {
+  let desired = DeclaredSchema::parse(sql)?;
+  let statements = plan_migration(&current_schema(state).await?, &desired, allow_data_loss)?;
+
+  if dry_run || statements.is_empty() {
+    return Ok(statements);
+  }
+
+  debug!("Apply schema: {statements:?}");
+
+  let migration = statements.clone();
+  let log = state
+    .conn()
+    .call(move |conn| {
+      let mut tx = TransactionRecorder::new(conn)?;
+
+      for stmt in &migration {
+        tx.execute(stmt, ())?;
+      }
+
+      return tx
+        .rollback()
+        .map_err(|err| trailbase_sqlite::Error::Other(err.into()));
+    })
+    .await?;
+
+  // Write to migration file.
+  if let Some(log) = log {
+    let migration_path = state.data_dir().migrations_path();
+    let report = log
+      .apply_as_migration(state.conn(), migration_path, "apply_schema")
+      .await?;
+    debug!("Migration report: {report:?}");
+  }
+
+  state.schema_metadata().invalidate_all().await?;
+  spawn_query_plan_check(state);
+
+  return Ok(statements);
+}
+
+pub async fn apply_schema_handler(
+  State(state): State<AppState>,
+  Json(request): Json<ApplySchemaRequest>,
+) -> Result<Json<ApplySchemaResponse>, Error> {
+  let statements = apply_schema(
+    &state,
+    &request.sql,
+    request.allow_data_loss.unwrap_or(false),
+    request.dry_run.unwrap_or(false),
+  )
+  .await?;
+
+  return Ok(Json(ApplySchemaResponse {
+    sql: statements
+      .iter()
+      .map(|stmt| format!("{stmt};"))
+      .collect::<Vec<_>>()
+      .join("\n"),
+  }));
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::app_state::*;
+
+  #[tokio::test]
+  async fn test_apply_schema() {
+    let state = test_state(None).await.unwrap();
+
+    let v1 = r#"
+      CREATE TABLE apply_test (id INTEGER PRIMARY KEY, name TEXT NOT NULL, note TEXT) STRICT;
+      CREATE INDEX apply_test_name_index ON apply_test (name);
+    "#;
+
+    // Dry-runs don't change anything.
+    let statements = apply_schema(&state, v1, false, true).await.unwrap();
+    assert_eq!(2, statements.len());
+    assert!(state.schema_metadata().get_table("apply_test").is_none());
+
+    apply_schema(&state, v1, false, false).await.unwrap();
+    assert!(state.schema_metadata().get_table("apply_test").is_some());
+    assert!(
+      apply_schema(&state, v1, false, true)
+        .await
+        .unwrap()
+        .is_empty()
+    );
+
+    state
+      .conn()
+      .execute(
+        "INSERT INTO apply_test (name, note) VALUES ('alice', 'x')",
+        (),
+      )
+      .await
+      .unwrap();
+
+    // Dropping "note" requires a table rebuild.
+    let v2 = r#"
+      CREATE TABLE apply_test (id INTEGER PRIMARY KEY, name TEXT NOT NULL, age INTEGER NOT NULL DEFAULT 0) STRICT;
+      CREATE INDEX apply_test_name_index ON apply_test (name);
+    "#;
+    assert!(apply_schema(&state, v2, false, false).await.is_err());
+    apply_schema(&state, v2, true, false).await.unwrap();
+
+    let table = state.schema_metadata().get_table("apply_test").unwrap();
+    assert_eq!(
+      vec!["id", "name", "age"],
+      table
+        .schema
+        .columns
+        .iter()
+        .map(|c| c.name.as_str())
+        .collect::<Vec<_>>()
+    );
+    let name: Option<String> = state
+      .conn()
+      .read_query_row_f("SELECT name FROM apply_test WHERE age = 0", (), |row| {
+        row.get(0)
+      })
+      .await
+      .unwrap();
+    assert_eq!(Some("alice".to_string()), name);
+
+    // TrailBase's own tables remain untouched.
+    assert!(state.schema_metadata().get_table("_user").is_some());
+  }
+}
@@ -5,6 +5,7 @@ use ts_rs::TS;
 
 use crate::admin::AdminError as Error;
 use crate::app_state::AppState;
+use crate::records::query_plan::spawn_query_plan_check;
 use crate::transaction::TransactionRecorder;
 
 #[derive(Clone, Debug, Deserialize, TS)]
@@ -52,6 +53,8 @@ pub async fn create_index_handler(
         .apply_as_migration(conn, migration_path, &filename)
         .await?;
     }
+
+    spawn_query_plan_check(&state);
   }
 
   return Ok(Json(CreateIndexResponse {
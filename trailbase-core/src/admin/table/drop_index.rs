@@ -10,6 +10,7 @@ use ts_rs::TS;
 
 use crate::admin::AdminError as Error;
 use crate::app_state::AppState;
+use crate::records::query_plan::spawn_query_plan_check;
 use crate::transaction::TransactionRecorder;
 
 #[derive(Clone, Debug, Deserialize, TS)]
@@ -51,5 +52,7 @@ pub async fn drop_index_handler(
       .await?;
   }
 
+  spawn_query_plan_check(&state);
+
   return Ok((StatusCode::OK, "").into_response());
 }
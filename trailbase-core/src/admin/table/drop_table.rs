@@ -11,6 +11,7 @@ use ts_rs::TS;
 use crate::admin::AdminError as Error;
 use crate::app_state::AppState;
 use crate::config::proto::hash_config;
+use crate::records::query_plan::spawn_query_plan_check;
 use crate::transaction::TransactionRecorder;
 
 #[derive(Clone, Debug, Deserialize, TS)]
@@ -79,6 +80,7 @@ pub async fn drop_table_handler(
   }
 
   state.schema_metadata().invalidate_all().await?;
+  spawn_query_plan_check(&state);
 
   return Ok((StatusCode::OK, "").into_response());
 }
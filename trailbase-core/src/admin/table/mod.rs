@@ -9,13 +9,17 @@ pub(super) use drop_index::drop_index_handler;
 
 // Tables
 mod alter_table;
+mod apply_schema;
 mod create_table;
 mod drop_table;
+mod mutate_table;
 
 pub(crate) use alter_table::alter_table_handler;
+pub(crate) use apply_schema::{apply_schema, apply_schema_handler};
 #[allow(unused)]
 pub(crate) use create_table::{CreateTableRequest, create_table_handler};
 pub(crate) use drop_table::drop_table_handler;
+pub(crate) use mutate_table::mutate_table_handler;
 
 // Lists both Tables and Indexes
 mod list_tables;
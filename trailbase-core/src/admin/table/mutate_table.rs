@@ -0,0 +1,499 @@
+use axum::{
+  Json,
+  extract::{Path, State},
+};
+use log::*;
+use serde::{Deserialize, Serialize};
+use trailbase_schema::metadata::{JsonColumnMetadata, TableMetadata};
+use trailbase_schema::migration::{DeclaredSchema, plan_migration};
+use trailbase_schema::sqlite::{Column, Table, TableIndex};
+use ts_rs::TS;
+
+use crate::admin::AdminError as Error;
+use crate::admin::table::apply_schema::current_schema;
+use crate::app_state::AppState;
+use crate::config::proto::hash_config;
+use crate::constants::USER_TABLE;
+use crate::records::RecordApi;
+use crate::records::query_plan::spawn_query_plan_check;
+use crate::transaction::TransactionRecorder;
+use crate::util::quote_identifier;
+
+/// Serializes schema mutations, s.t. each one is planned against the schema it's applied to.
+static MUTATION_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// A single change to an existing table.
+#[derive(Clone, Debug, Deserialize, TS)]
+#[serde(tag = "op", rename_all = "snake_case")]
+#[ts(export)]
+pub enum TableMutation {
+  AddColumn {
+    column: Column,
+  },
+  DropColumn {
+    column_name: String,
+  },
+  RenameColumn {
+    column_name: String,
+    new_name: String,
+  },
+  AddIndex {
+    index: TableIndex,
+  },
+  DropIndex {
+    index_name: String,
+  },
+  RenameIndex {
+    index_name: String,
+    new_name: String,
+  },
+}
+
+#[derive(Clone, Debug, Deserialize, TS)]
+#[ts(export)]
+pub struct MutateTableRequest {
+  pub mutation: TableMutation,
+  pub dry_run: Option<bool>,
+}
+
+#[derive(Clone, Debug, Serialize, TS)]
+#[ts(export)]
+pub struct MutateTableResponse {
+  /// The statements applied or, for dry-runs, to be applied.
+  pub sql: String,
+}
+
+/// Applies a guarded change to an existing table.
+///
+/// Unlike `alter_table_handler`, changes are validated before being applied: they must not break
+/// the primary key requirements of record APIs using the table, drop indexed columns or reference
+/// unknown JSON schemas. Columns are renamed in place, anything else SQLite cannot `ALTER` is
+/// applied by rebuilding the table, preserving its indexes and triggers.
+pub async fn mutate_table_handler(
+  State(state): State<AppState>,
+  Path(table_name): Path<String>,
+  Json(request): Json<MutateTableRequest>,
+) -> Result<Json<MutateTableResponse>, Error> {
+  let _lock = MUTATION_LOCK.lock().await;
+
+  let current = current_schema(&state).await?;
+  let statements = plan_mutation(&state, &current, &table_name, request.mutation)?;
+
+  let response = MutateTableResponse {
+    sql: statements
+      .iter()
+      .map(|stmt| format!("{stmt};"))
+      .collect::<Vec<_>>()
+      .join("\n"),
+  };
+  if request.dry_run.unwrap_or(false) || statements.is_empty() {
+    return Ok(Json(response));
+  }
+
+  debug!("Mutate table '{table_name}': {statements:?}");
+
+  let log = state
+    .conn()
+    .call(move |conn| {
+      let mut tx = TransactionRecorder::new(conn)?;
+
+      for stmt in &statements {
+        tx.execute(stmt, ())?;
+      }
+
+      return tx
+        .rollback()
+        .map_err(|err| trailbase_sqlite::Error::Other(err.into()));
+    })
+    .await?;
+
+  // Write to migration file.
+  if let Some(log) = log {
+    let migration_path = state.data_dir().migrations_path();
+    let report = log
+      .apply_as_migration(
+        state.conn(),
+        migration_path,
+        &format!("mutate_table_{table_name}"),
+      )
+      .await?;
+    debug!("Migration report: {report:?}");
+  }
+
+  // Swap in the new table metadata and rebuild the record APIs on top of it.
+  state.refresh_table_cache().await?;
+  let config = state.get_config();
+  let hash = hash_config(&config);
+  state.validate_and_update_config(config, Some(hash)).await?;
+
+  spawn_query_plan_check(&state);
+
+  return Ok(Json(response));
+}
+
+/// Validates the mutation against the current schema and derives the statements to apply it.
+fn plan_mutation(
+  state: &AppState,
+  current: &DeclaredSchema,
+  table_name: &str,
+  mutation: TableMutation,
+) -> Result<Vec<String>, Error> {
+  let Some(table) = current.tables.iter().find(|t| t.name == table_name) else {
+    return Err(Error::Precondition(format!(
+      "Table '{table_name}' not found. Only app-owned tables can be mutated."
+    )));
+  };
+  if table.virtual_table {
+    return Err(Error::Precondition(format!(
+      "Virtual table '{table_name}' cannot be mutated"
+    )));
+  }
+
+  let has_column = |name: &str| table.columns.iter().any(|c| c.name == name);
+  let find_index = |name: &str| {
+    return current
+      .indexes
+      .iter()
+      .position(|i| i.name == name && i.table_name == table_name)
+      .ok_or_else(|| Error::Precondition(format!("Index '{name}' not found on '{table_name}'")));
+  };
+  // New names end up in generated DDL, e.g. via the migration planner, thus quotes are rejected.
+  let check_column_name = |name: &str| {
+    if name.is_empty() || name.contains('"') || has_column(name) {
+      return Err(Error::Precondition(format!(
+        "Invalid or duplicate column '{name}'"
+      )));
+    }
+    return Ok(());
+  };
+  let check_index_name = |name: &str| {
+    if name.contains('"') {
+      return Err(Error::Precondition(format!("Invalid index name '{name}'")));
+    }
+    if name.starts_with('_') || name.starts_with("sqlite_") {
+      return Err(Error::Precondition(format!(
+        "'{name}' uses a reserved prefix"
+      )));
+    }
+    if current.indexes.iter().any(|i| i.name == name) {
+      return Err(Error::Precondition(format!(
+        "Index '{name}' already exists"
+      )));
+    }
+    return Ok(());
+  };
+
+  let mut desired = current.clone();
+  let desired_table = desired
+    .tables
+    .iter_mut()
+    .find(|t| t.name == table_name)
+    .expect("checked above");
+
+  // Columns are renamed in place, which also updates indexes, triggers and views referencing
+  // them. Everything else is planned declaratively.
+  let mut rename: Option<String> = None;
+
+  match mutation {
+    TableMutation::AddColumn { column } => {
+      check_column_name(&column.name)?;
+      desired_table.columns.push(column);
+    }
+    TableMutation::DropColumn { column_name } => {
+      if !has_column(&column_name) {
+        return Err(Error::Precondition(format!(
+          "Column '{column_name}' not found"
+        )));
+      }
+      if let Some(index) = current.indexes.iter().find(|i| {
+        return i.table_name == table_name
+          && i.columns.iter().any(|c| c.column_name == column_name);
+      }) {
+        return Err(Error::Precondition(format!(
+          "Column '{column_name}' is used by index '{}', drop it first",
+          index.name
+        )));
+      }
+      if table
+        .checks
+        .iter()
+        .any(|check| references_column(&check.expr, &column_name))
+      {
+        return Err(Error::Precondition(format!(
+          "Column '{column_name}' is used by a CHECK constraint"
+        )));
+      }
+      desired_table.columns.retain(|c| c.name != column_name);
+    }
+    TableMutation::RenameColumn {
+      column_name,
+      new_name,
+    } => {
+      if !has_column(&column_name) {
+        return Err(Error::Precondition(format!(
+          "Column '{column_name}' not found"
+        )));
+      }
+      check_column_name(&new_name)?;
+      for column in &mut desired_table.columns {
+        if column.name == column_name {
+          column.name = new_name.clone();
+        }
+      }
+      rename = Some(format!(
+        "ALTER TABLE {} RENAME COLUMN {} TO {}",
+        quote_identifier(table_name),
+        quote_identifier(&column_name),
+        quote_identifier(&new_name),
+      ));
+    }
+    TableMutation::AddIndex { index } => {
+      if index.table_name != table_name {
+        return Err(Error::Precondition(format!(
+          "Index '{}' is not on '{table_name}'",
+          index.name
+        )));
+      }
+      check_index_name(&index.name)?;
+      if let Some(column) = index.columns.iter().find(|c| !has_column(&c.column_name)) {
+        return Err(Error::Precondition(format!(
+          "Column '{}' not found",
+          column.column_name
+        )));
+      }
+      desired.indexes.push(TableIndex {
+        if_not_exists: false,
+        ..index
+      });
+    }
+    TableMutation::DropIndex { index_name } => {
+      desired.indexes.remove(find_index(&index_name)?);
+    }
+    TableMutation::RenameIndex {
+      index_name,
+      new_name,
+    } => {
+      check_index_name(&new_name)?;
+      // SQLite cannot rename indexes, thus they're re-created.
+      let index = find_index(&index_name)?;
+      desired.indexes[index].name = new_name;
+    }
+  };
+
+  let desired_table = desired
+    .tables
+    .iter()
+    .find(|t| t.name == table_name)
+    .expect("checked above");
+  validate_table(state, desired_table)?;
+
+  if let Some(rename) = rename {
+    return Ok(vec![rename]);
+  }
+  return Ok(plan_migration(current, &desired, true)?);
+}
+
+/// Checks that the table still satisfies the requirements of record APIs using it and that JSON
+/// schema CHECKs reference registered schemas.
+fn validate_table(state: &AppState, table: &Table) -> Result<(), Error> {
+  let tables: Vec<Table> = state
+    .schema_metadata()
+    .tables()
+    .into_iter()
+    .map(|t| t.schema)
+    .filter(|t| t.name != table.name)
+    .chain(std::iter::once(table.clone()))
+    .collect();
+  let metadata = TableMetadata::new(table.clone(), &tables, USER_TABLE);
+
+  let schema_names = metadata
+    .json_metadata
+    .columns
+    .iter()
+    .flatten()
+    .filter_map(|column| {
+      return match column {
+        JsonColumnMetadata::SchemaName(name, _) => Some(name),
+        JsonColumnMetadata::Pattern(_) => None,
+      };
+    });
+  for name in schema_names {
+    if trailbase_schema::registry::get_schema(name).is_none() {
+      return Err(Error::Precondition(format!("Unknown JSON schema '{name}'")));
+    }
+  }
+
+  for api_config in state.get_config().record_apis {
+    if api_config.table_name.as_deref() != Some(table.name.as_str()) {
+      continue;
+    }
+
+    let api_name = api_config.name.clone().unwrap_or_default();
+    if let Err(err) = RecordApi::from_table(
+      state.conn().clone(),
+      &metadata,
+      api_config,
+      state.script_validators(),
+    ) {
+      return Err(Error::Precondition(format!(
+        "Change breaks record API '{api_name}': {err}"
+      )));
+    }
+  }
+
+  return Ok(());
+}
+
+/// Whether the SQL expression references the given column, i.e. contains it as an identifier.
+fn references_column(expr: &str, column_name: &str) -> bool {
+  return expr
+    .split(|c: char| !c.is_alphanumeric() && c != '_')
+    .any(|token| token == column_name);
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::app_state::test_state;
+  use crate::config::proto::{PermissionFlag, RecordApiConfig};
+  use crate::records::test_utils::add_record_api_config;
+
+  async fn mutate(state: &AppState, mutation: serde_json::Value) -> Result<String, Error> {
+    let Json(response) = mutate_table_handler(
+      State(state.clone()),
+      Path("mutate_test".to_string()),
+      Json(MutateTableRequest {
+        mutation: serde_json::from_value(mutation).unwrap(),
+        dry_run: None,
+      }),
+    )
+    .await?;
+    return Ok(response.sql);
+  }
+
+  #[tokio::test]
+  async fn test_mutate_table() {
+    let state = test_state(None).await.unwrap();
+    state
+      .conn()
+      .execute_batch(
+        r#"
+          CREATE TABLE mutate_test (id INTEGER PRIMARY KEY, name TEXT NOT NULL) STRICT;
+          CREATE INDEX mutate_test_name_index ON mutate_test (name);
+          INSERT INTO mutate_test (id, name) VALUES (1, 'first');
+        "#,
+      )
+      .await
+      .unwrap();
+    state.refresh_table_cache().await.unwrap();
+
+    add_record_api_config(
+      &state,
+      RecordApiConfig {
+        name: Some("mutate_api".to_string()),
+        table_name: Some("mutate_test".to_string()),
+        acl_world: vec![PermissionFlag::Read as i32],
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    mutate(
+      &state,
+      serde_json::json!({
+        "op": "add_column",
+        "column": {"name": "note", "data_type": "Text", "options": []},
+      }),
+    )
+    .await
+    .unwrap();
+    mutate(
+      &state,
+      serde_json::json!({"op": "rename_column", "column_name": "name", "new_name": "title"}),
+    )
+    .await
+    .unwrap();
+
+    // Names containing quotes are rejected.
+    assert!(
+      mutate(
+        &state,
+        serde_json::json!({"op": "rename_column", "column_name": "note", "new_name": "x\" TEXT"}),
+      )
+      .await
+      .is_err()
+    );
+    assert!(
+      mutate(
+        &state,
+        serde_json::json!({
+          "op": "add_column",
+          "column": {"name": "a\"b", "data_type": "Text", "options": []},
+        }),
+      )
+      .await
+      .is_err()
+    );
+
+    // Renamed in place, including the index.
+    let title: Option<String> = state
+      .conn()
+      .read_query_row_f(
+        "SELECT title FROM mutate_test INDEXED BY mutate_test_name_index WHERE title = 'first'",
+        (),
+        |row| row.get(0),
+      )
+      .await
+      .unwrap();
+    assert_eq!(title.as_deref(), Some("first"));
+    let api = state.lookup_record_api("mutate_api").unwrap();
+    assert!(api.column_index_by_name("title").is_some());
+
+    // Indexed columns and the record API's primary key cannot be dropped.
+    assert!(
+      mutate(
+        &state,
+        serde_json::json!({"op": "drop_column", "column_name": "title"})
+      )
+      .await
+      .is_err()
+    );
+    assert!(
+      mutate(
+        &state,
+        serde_json::json!({"op": "drop_column", "column_name": "id"})
+      )
+      .await
+      .is_err()
+    );
+
+    mutate(
+      &state,
+      serde_json::json!({"op": "drop_index", "index_name": "mutate_test_name_index"}),
+    )
+    .await
+    .unwrap();
+    mutate(
+      &state,
+      serde_json::json!({"op": "drop_column", "column_name": "title"}),
+    )
+    .await
+    .unwrap();
+
+    let count: Option<i64> = state
+      .conn()
+      .read_query_row_f("SELECT COUNT(*) FROM mutate_test", (), |row| row.get(0))
+      .await
+      .unwrap();
+    assert_eq!(count, Some(1));
+    assert!(
+      state
+        .schema_metadata()
+        .get_table("mutate_test")
+        .unwrap()
+        .column_index_by_name("title")
+        .is_none()
+    );
+  }
+}
@@ -0,0 +1,282 @@
+use axum::{
+  Json,
+  extract::State,
+  http::StatusCode,
+  response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use trailbase_sqlite::params;
+use ts_rs::TS;
+use uuid::Uuid;
+
+use crate::admin::AdminError as Error;
+use crate::app_state::AppState;
+use crate::auth::ApiKeyScope;
+use crate::auth::api_key::generate_api_key;
+use crate::constants::API_KEY_TABLE;
+
+#[derive(Debug, Deserialize)]
+struct DbApiKey {
+  id: [u8; 16],
+  name: String,
+  user: [u8; 16],
+  scopes: String,
+  created: i64,
+  expires: Option<i64>,
+}
+
+#[derive(Debug, Serialize, TS)]
+pub struct ApiKeyJson {
+  pub id: String,
+  pub name: String,
+  /// Id of the user the key acts on behalf of.
+  pub user: String,
+  pub scopes: Vec<ApiKeyScope>,
+  pub created: i64,
+  pub expires: Option<i64>,
+}
+
+#[derive(Debug, Serialize, TS)]
+#[ts(export)]
+pub struct ListApiKeysResponse {
+  api_keys: Vec<ApiKeyJson>,
+}
+
+pub async fn list_api_keys_handler(
+  State(state): State<AppState>,
+) -> Result<Json<ListApiKeysResponse>, Error> {
+  let keys = state
+    .user_conn()
+    .read_query_values::<DbApiKey>(
+      format!(
+        "SELECT id, name, user, scopes, created, expires FROM {API_KEY_TABLE} ORDER BY created DESC"
+      ),
+      (),
+    )
+    .await?;
+
+  return Ok(Json(ListApiKeysResponse {
+    api_keys: keys
+      .into_iter()
+      .map(|key| {
+        return Ok(ApiKeyJson {
+          id: Uuid::from_bytes(key.id).to_string(),
+          name: key.name,
+          user: Uuid::from_bytes(key.user).to_string(),
+          scopes: serde_json::from_str(&key.scopes)?,
+          created: key.created,
+          expires: key.expires,
+        });
+      })
+      .collect::<Result<Vec<_>, Error>>()?,
+  }));
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, TS)]
+#[ts(export)]
+pub struct CreateApiKeyRequest {
+  name: String,
+  /// Id of the user the key acts on behalf of, e.g. a dedicated service account.
+  user: Uuid,
+  scopes: Vec<ApiKeyScope>,
+  /// Optional UNIX timestamp in seconds after which the key is rejected.
+  expires: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct CreateApiKeyResponse {
+  id: Uuid,
+  /// The API key. It is only stored hashed and thus cannot be retrieved again.
+  key: String,
+}
+
+pub async fn create_api_key_handler(
+  State(state): State<AppState>,
+  Json(request): Json<CreateApiKeyRequest>,
+) -> Result<Json<CreateApiKeyResponse>, Error> {
+  if request.scopes.is_empty() {
+    return Err(Error::BadRequest("API key without scopes".into()));
+  }
+  for scope in &request.scopes {
+    if scope.api != "*" && state.lookup_record_api(&scope.api).is_none() {
+      return Err(Error::BadRequest(
+        format!("Unknown record API: {}", scope.api).into(),
+      ));
+    }
+  }
+
+  let (key, key_hash) = generate_api_key();
+  let Some(id) = state
+    .user_conn()
+    .write_query_value::<Uuid>(
+      format!(
+        "INSERT INTO {API_KEY_TABLE} (name, user, key_hash, scopes, expires) VALUES ($1, $2, $3, $4, $5) RETURNING id"
+      ),
+      params!(
+        request.name,
+        request.user.into_bytes(),
+        key_hash,
+        serde_json::to_string(&request.scopes)?,
+        request.expires,
+      ),
+    )
+    .await?
+  else {
+    return Err(Error::Precondition("Failed to create API key".into()));
+  };
+
+  return Ok(Json(CreateApiKeyResponse { id, key }));
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, TS)]
+#[ts(export)]
+pub struct DeleteApiKeyRequest {
+  id: Uuid,
+}
+
+/// Revokes an API key. Takes effect immediately, since keys are looked up on every request.
+pub async fn delete_api_key_handler(
+  State(state): State<AppState>,
+  Json(request): Json<DeleteApiKeyRequest>,
+) -> Result<Response, Error> {
+  state
+    .user_conn()
+    .execute(
+      format!("DELETE FROM {API_KEY_TABLE} WHERE id = $1"),
+      params!(request.id.into_bytes()),
+    )
+    .await?;
+
+  return Ok((StatusCode::OK, "deleted").into_response());
+}
+
+#[cfg(test)]
+mod tests {
+  use axum::body::Body;
+  use axum::http::Request;
+  use axum::http::header::{AUTHORIZATION, CONTENT_TYPE};
+  use tower::ServiceExt;
+
+  use super::*;
+  use crate::admin::user::create_user_for_test;
+  use crate::app_state::test_state;
+  use crate::auth::ApiKeyAccess;
+  use crate::config::proto::{PermissionFlag, RecordApiConfig};
+  use crate::constants::RECORD_API_PATH;
+  use crate::records::test_utils::add_record_api_config;
+
+  #[tokio::test]
+  async fn test_api_key_record_access() {
+    let state = test_state(None).await.unwrap();
+    state
+      .conn()
+      .execute_batch(
+        r#"
+          CREATE TABLE item (id INTEGER PRIMARY KEY, name TEXT) STRICT;
+          INSERT INTO item (id, name) VALUES (1, 'alice');
+        "#,
+      )
+      .await
+      .unwrap();
+    state.schema_metadata().invalidate_all().await.unwrap();
+
+    add_record_api_config(
+      &state,
+      RecordApiConfig {
+        name: Some("items".to_string()),
+        table_name: Some("item".to_string()),
+        acl_authenticated: [PermissionFlag::Create as i32, PermissionFlag::Read as i32].into(),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    let user = create_user_for_test(&state, "service@bar.org", "Secret!1!!")
+      .await
+      .unwrap();
+
+    // Unknown APIs and empty scopes are rejected.
+    for scopes in [
+      vec![],
+      vec![ApiKeyScope {
+        api: "unknown".to_string(),
+        access: ApiKeyAccess::ReadOnly,
+      }],
+    ] {
+      assert!(
+        create_api_key_handler(
+          State(state.clone()),
+          Json(CreateApiKeyRequest {
+            name: "ci".to_string(),
+            user,
+            scopes,
+            expires: None,
+          }),
+        )
+        .await
+        .is_err()
+      );
+    }
+
+    let Json(CreateApiKeyResponse { id, key }) = create_api_key_handler(
+      State(state.clone()),
+      Json(CreateApiKeyRequest {
+        name: "ci".to_string(),
+        user,
+        scopes: vec![ApiKeyScope {
+          api: "items".to_string(),
+          access: ApiKeyAccess::ReadOnly,
+        }],
+        expires: None,
+      }),
+    )
+    .await
+    .unwrap();
+
+    let Json(response) = list_api_keys_handler(State(state.clone())).await.unwrap();
+    assert_eq!(response.api_keys.len(), 1);
+    assert_eq!(response.api_keys[0].user, user.to_string());
+
+    let router = crate::records::router().with_state(state.clone());
+    let request = |method: &str, key: &str| {
+      let builder = Request::builder()
+        .method(method)
+        .header(AUTHORIZATION, format!("Bearer {key}"));
+
+      return match method {
+        "POST" => builder
+          .uri(format!("/{RECORD_API_PATH}/items"))
+          .header(CONTENT_TYPE, "application/json")
+          .body(Body::from(r#"{"id": 2, "name": "bob"}"#))
+          .unwrap(),
+        _ => builder
+          .uri(format!("/{RECORD_API_PATH}/items/1"))
+          .body(Body::empty())
+          .unwrap(),
+      };
+    };
+
+    let response = router.clone().oneshot(request("GET", &key)).await.unwrap();
+    assert_eq!(StatusCode::OK, response.status());
+
+    // Read-only keys cannot create records even if the user could.
+    let response = router.clone().oneshot(request("POST", &key)).await.unwrap();
+    assert_eq!(StatusCode::FORBIDDEN, response.status());
+
+    let response = router
+      .clone()
+      .oneshot(request("GET", "tbk_invalid"))
+      .await
+      .unwrap();
+    assert_eq!(StatusCode::UNAUTHORIZED, response.status());
+
+    delete_api_key_handler(State(state.clone()), Json(DeleteApiKeyRequest { id }))
+      .await
+      .unwrap();
+
+    let response = router.oneshot(request("GET", &key)).await.unwrap();
+    assert_eq!(StatusCode::UNAUTHORIZED, response.status());
+  }
+}
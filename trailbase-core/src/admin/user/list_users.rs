@@ -63,20 +63,27 @@ pub async fn list_users_handler(
 
   let QueryParseResult {
     params: filter_params,
+    filter,
     cursor,
     limit,
     order,
     ..
   } = parse_and_sanitize_query(raw_url_query.as_deref())
     .map_err(|err| Error::Precondition(format!("Invalid query '{err}': {raw_url_query:?}")))?;
+  let cursor = cursor.as_deref().and_then(Cursor::parse);
 
   let Some(schema_metadata) = state.schema_metadata().get_table(USER_TABLE) else {
     return Err(Error::Precondition(format!("Table {USER_TABLE} not found")));
   };
   // Where clause contains column filters and cursor depending on what's present in the url query
   // string.
-  let filter_where_clause =
-    build_filter_where_clause("_ROW_", &schema_metadata.schema.columns, filter_params)?;
+  let filter_where_clause = build_filter_where_clause(
+    "_ROW_",
+    &schema_metadata.schema.columns,
+    filter_params,
+    filter,
+    None,
+  )?;
 
   let total_row_count: i64 = conn
     .read_query_row_f(
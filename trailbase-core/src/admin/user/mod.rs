@@ -4,14 +4,18 @@ use uuid::Uuid;
 use crate::AppState;
 use crate::constants::USER_TABLE;
 
+mod api_keys;
 mod create_user;
 mod delete_user;
 mod list_users;
+mod roles;
 mod update_user;
 
+pub(super) use api_keys::{create_api_key_handler, delete_api_key_handler, list_api_keys_handler};
 pub use create_user::{CreateUserRequest, create_user_handler};
 pub(super) use delete_user::delete_user_handler;
 pub(super) use list_users::list_users_handler;
+pub(super) use roles::{create_role_handler, delete_role_handler, list_roles_handler};
 pub(super) use update_user::update_user_handler;
 
 pub async fn is_demo_admin(state: &AppState, id: &Uuid) -> bool {
@@ -0,0 +1,159 @@
+use axum::{
+  Json,
+  extract::State,
+  http::StatusCode,
+  response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use trailbase_sqlite::params;
+use ts_rs::TS;
+
+use crate::admin::AdminError as Error;
+use crate::app_state::AppState;
+use crate::constants::{ROLE_TABLE, USER_ROLE_TABLE};
+
+#[derive(Debug, Serialize, Deserialize, TS)]
+pub struct RoleJson {
+  pub name: String,
+  pub created: i64,
+  /// Number of users granted the role.
+  pub users: i64,
+}
+
+#[derive(Debug, Serialize, TS)]
+#[ts(export)]
+pub struct ListRolesResponse {
+  roles: Vec<RoleJson>,
+}
+
+pub async fn list_roles_handler(
+  State(state): State<AppState>,
+) -> Result<Json<ListRolesResponse>, Error> {
+  let roles = state
+    .user_conn()
+    .read_query_values::<RoleJson>(
+      format!(
+        "SELECT r.name, r.created, (SELECT COUNT(*) FROM {USER_ROLE_TABLE} WHERE role = r.name) AS users FROM {ROLE_TABLE} AS r ORDER BY r.name"
+      ),
+      (),
+    )
+    .await?;
+
+  return Ok(Json(ListRolesResponse { roles }));
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, TS)]
+#[ts(export)]
+pub struct CreateRoleRequest {
+  name: String,
+}
+
+pub async fn create_role_handler(
+  State(state): State<AppState>,
+  Json(request): Json<CreateRoleRequest>,
+) -> Result<Response, Error> {
+  if request.name.is_empty() {
+    return Err(Error::BadRequest("Empty role name".into()));
+  }
+
+  let rows_affected = state
+    .user_conn()
+    .execute(
+      format!("INSERT OR IGNORE INTO {ROLE_TABLE} (name) VALUES ($1)"),
+      params!(request.name),
+    )
+    .await?;
+  if rows_affected == 0 {
+    return Err(Error::AlreadyExists("role"));
+  }
+
+  return Ok((StatusCode::OK, "created").into_response());
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, TS)]
+#[ts(export)]
+pub struct DeleteRoleRequest {
+  name: String,
+}
+
+/// Deletes a role and thus revokes it from all users.
+///
+/// NOTE: Already minted auth tokens keep the role claim until they're refreshed.
+pub async fn delete_role_handler(
+  State(state): State<AppState>,
+  Json(request): Json<DeleteRoleRequest>,
+) -> Result<Response, Error> {
+  state
+    .user_conn()
+    .execute(
+      format!("DELETE FROM {ROLE_TABLE} WHERE name = $1"),
+      params!(request.name),
+    )
+    .await?;
+
+  return Ok((StatusCode::OK, "deleted").into_response());
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::admin::user::create_user_for_test;
+  use crate::app_state::test_state;
+  use crate::auth::util::user_roles;
+
+  #[tokio::test]
+  async fn test_role_creation_and_deletion() {
+    let state = test_state(None).await.unwrap();
+
+    create_role_handler(
+      State(state.clone()),
+      Json(CreateRoleRequest {
+        name: "editor".to_string(),
+      }),
+    )
+    .await
+    .unwrap();
+
+    assert!(
+      create_role_handler(
+        State(state.clone()),
+        Json(CreateRoleRequest {
+          name: "editor".to_string(),
+        }),
+      )
+      .await
+      .is_err()
+    );
+
+    let user_id = create_user_for_test(&state, "foo@bar.org", "Secret!1!!")
+      .await
+      .unwrap();
+    state
+      .user_conn()
+      .execute(
+        format!("INSERT INTO {USER_ROLE_TABLE} (user, role) VALUES ($1, 'editor')"),
+        params!(user_id.into_bytes()),
+      )
+      .await
+      .unwrap();
+    assert_eq!(user_roles(&state, &user_id).await.unwrap(), vec!["editor"]);
+
+    let Json(response) = list_roles_handler(State(state.clone())).await.unwrap();
+    assert_eq!(response.roles.len(), 1);
+    assert_eq!(response.roles[0].name, "editor");
+    assert_eq!(response.roles[0].users, 1);
+
+    delete_role_handler(
+      State(state.clone()),
+      Json(DeleteRoleRequest {
+        name: "editor".to_string(),
+      }),
+    )
+    .await
+    .unwrap();
+
+    assert!(user_roles(&state, &user_id).await.unwrap().is_empty());
+    let Json(response) = list_roles_handler(State(state.clone())).await.unwrap();
+    assert!(response.roles.is_empty());
+  }
+}
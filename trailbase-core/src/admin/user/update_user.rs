@@ -13,7 +13,7 @@ use crate::admin::AdminError as Error;
 use crate::admin::user::is_demo_admin;
 use crate::app_state::AppState;
 use crate::auth::password::hash_password;
-use crate::constants::USER_TABLE;
+use crate::constants::{USER_ROLE_TABLE, USER_TABLE};
 
 #[derive(Debug, Serialize, Deserialize, Default, TS)]
 #[ts(export)]
@@ -23,6 +23,8 @@ pub struct UpdateUserRequest {
   email: Option<String>,
   password: Option<String>,
   verified: Option<bool>,
+  /// Replaces the user's roles, if present.
+  roles: Option<Vec<String>>,
 }
 
 pub async fn update_user_handler(
@@ -48,10 +50,15 @@ pub async fn update_user_handler(
     static ref UPDATE_EMAIL_QUERY: String = update_query("email");
     static ref UPDATE_PW_HASH_QUERY: String = update_query("password_hash");
     static ref UPDATE_VERIFIED_QUERY: String = update_query("verified");
+    static ref DELETE_ROLES_QUERY: String =
+      format!("DELETE FROM '{USER_ROLE_TABLE}' WHERE user = $1");
+    static ref INSERT_ROLE_QUERY: String =
+      format!("INSERT INTO '{USER_ROLE_TABLE}' (user, role) VALUES ($1, $2)");
   }
 
   let email = request.email.clone();
   let verified = request.verified;
+  let roles = request.roles.clone();
   state
     .user_conn()
     .call(move |conn| {
@@ -67,6 +74,12 @@ pub async fn update_user_handler(
       if let Some(verified) = verified {
         tx.execute(&UPDATE_VERIFIED_QUERY, params!(verified, user_id_bytes))?;
       }
+      if let Some(roles) = roles {
+        tx.execute(&DELETE_ROLES_QUERY, params!(user_id_bytes))?;
+        for role in roles {
+          tx.execute(&INSERT_ROLE_QUERY, params!(user_id_bytes, role))?;
+        }
+      }
 
       tx.commit()?;
 
@@ -0,0 +1,94 @@
+use axum::{
+  Json,
+  extract::{Query, State},
+  http::StatusCode,
+  response::{IntoResponse, Response},
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use trailbase_sqlite::params;
+use ts_rs::TS;
+
+use crate::admin::AdminError as Error;
+use crate::app_state::AppState;
+use crate::constants::WEBHOOK_DELIVERY_TABLE;
+
+const LIMIT: i64 = 500;
+
+#[derive(Debug, Deserialize, Serialize, TS)]
+#[ts(export)]
+pub struct WebhookDelivery {
+  pub id: i64,
+  pub api: String,
+  pub url: String,
+  pub event: String,
+  pub payload: String,
+  /// One of "pending", "delivered" or "failed".
+  pub status: String,
+  pub attempts: i64,
+  pub next_attempt: i64,
+  pub last_error: Option<String>,
+  pub created: i64,
+  pub updated: i64,
+}
+
+#[derive(Debug, Deserialize, Default, TS)]
+#[ts(export)]
+pub struct ListWebhookDeliveriesQuery {
+  status: Option<String>,
+}
+
+#[derive(Debug, Serialize, TS)]
+#[ts(export)]
+pub struct ListWebhookDeliveriesResponse {
+  deliveries: Vec<WebhookDelivery>,
+}
+
+pub async fn list_webhook_deliveries_handler(
+  State(state): State<AppState>,
+  Query(query): Query<ListWebhookDeliveriesQuery>,
+) -> Result<Json<ListWebhookDeliveriesResponse>, Error> {
+  let deliveries = state
+    .conn()
+    .read_query_values::<WebhookDelivery>(
+      format!(
+        "SELECT * FROM {WEBHOOK_DELIVERY_TABLE} WHERE $1 IS NULL OR status = $1 ORDER BY id DESC LIMIT $2"
+      ),
+      params!(query.status, LIMIT),
+    )
+    .await?;
+
+  return Ok(Json(ListWebhookDeliveriesResponse { deliveries }));
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, TS)]
+#[ts(export)]
+pub struct RetryWebhookDeliveryRequest {
+  id: i64,
+}
+
+/// Re-schedules a delivery for immediate delivery, e.g. after it failed permanently.
+pub async fn retry_webhook_delivery_handler(
+  State(state): State<AppState>,
+  Json(request): Json<RetryWebhookDeliveryRequest>,
+) -> Result<Response, Error> {
+  let now = Utc::now().timestamp();
+  let updated = state
+    .conn()
+    .execute(
+      format!(
+        "UPDATE {WEBHOOK_DELIVERY_TABLE} SET status = 'pending', attempts = 0, next_attempt = $2, updated = $2 WHERE id = $1"
+      ),
+      params!(request.id, now),
+    )
+    .await?;
+
+  if updated == 0 {
+    return Err(Error::Precondition(format!(
+      "Webhook delivery not found: {}",
+      request.id
+    )));
+  }
+
+  return Ok((StatusCode::OK, "scheduled").into_response());
+}
@@ -1,20 +1,29 @@
 use log::*;
 use object_store::ObjectStore;
+use parking_lot::Mutex;
+use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::Arc;
 
 use crate::auth::jwt::JwtHelper;
 use crate::auth::options::AuthOptions;
-use crate::config::proto::{Config, RecordApiConfig, S3StorageConfig, hash_config};
+use crate::clock::Clock;
+use crate::config::proto::{Config, RecordApiConfig, S3StorageConfig, ServerConfig, hash_config};
 use crate::config::{validate_config, write_config_and_vault_textproto};
 use crate::data_dir::DataDir;
 use crate::email::Mailer;
 use crate::js::{RuntimeHandle, register_database_functions};
 use crate::queue::Queue;
+use crate::rate_limit::RateLimiter;
 use crate::records::RecordApi;
+use crate::records::cache::QueryCache;
+use crate::records::hooks::RecordHooks;
+use crate::records::list_query_cache::ListQueryCache;
 use crate::records::subscribe::SubscriptionManager;
+use crate::records::validators::ScriptValidators;
 use crate::scheduler::{JobRegistry, build_job_registry_from_config};
 use crate::schema_metadata::SchemaMetadataCache;
+use crate::tenant::{TenantError, TenantOptions, open_tenant_connection};
 use crate::value_notifier::{Computed, Guard, ValueNotifier};
 
 /// The app's internal state. AppState needs to be clonable which puts unnecessary constraints on
@@ -25,6 +34,8 @@ struct InternalState {
   site_url: Computed<url::Url>,
   dev: bool,
   demo: bool,
+  mock_auth: bool,
+  clock: Clock,
 
   auth: Computed<AuthOptions>,
   jobs: Computed<JobRegistry>,
@@ -33,6 +44,7 @@ struct InternalState {
   config: ValueNotifier<Config>,
 
   conn: trailbase_sqlite::Connection,
+  read_only_conn: trailbase_sqlite::Connection,
   logs_conn: trailbase_sqlite::Connection,
   queue: Queue,
 
@@ -40,52 +52,82 @@ struct InternalState {
 
   schema_metadata: SchemaMetadataCache,
   subscription_manager: SubscriptionManager,
+  query_cache: QueryCache,
+  list_query_cache: ListQueryCache,
+  rate_limiter: RateLimiter,
+  record_hooks: RecordHooks,
+  script_validators: ScriptValidators,
   object_store: Arc<dyn ObjectStore + Send + Sync>,
 
   runtime: RuntimeHandle,
 
+  /// None if tenants are unsupported, e.g. for in-memory databases.
+  tenant_options: Option<TenantOptions>,
+  tenants: Mutex<HashMap<String, Arc<TenantState>>>,
+
   #[cfg(test)]
   #[allow(unused)]
   cleanup: Vec<Box<dyn std::any::Any + Send + Sync>>,
 }
 
+/// A tenant's connection and what's derived from it, see `Config::tenants`.
+struct TenantState {
+  conn: trailbase_sqlite::Connection,
+  record_apis: Computed<Vec<(String, RecordApi)>>,
+  query_cache: QueryCache,
+}
+
 pub(crate) struct AppStateArgs {
   pub data_dir: DataDir,
   pub public_dir: Option<PathBuf>,
   pub address: String,
   pub dev: bool,
   pub demo: bool,
+  pub mock_auth: bool,
+  pub clock: Clock,
   pub schema_metadata: SchemaMetadataCache,
   pub config: Config,
   pub conn: trailbase_sqlite::Connection,
+  pub read_only_conn: trailbase_sqlite::Connection,
   pub logs_conn: trailbase_sqlite::Connection,
   pub queue: Queue,
   pub jwt: JwtHelper,
   pub object_store: Box<dyn ObjectStore + Send + Sync>,
   pub js_runtime_threads: Option<usize>,
+  pub tenant_options: Option<TenantOptions>,
 }
 
 #[derive(Clone)]
 pub struct AppState {
   state: Arc<InternalState>,
+  /// Set for requests resolved to a tenant, see `Config::tenants`.
+  tenant: Option<Arc<TenantState>>,
 }
 
 impl AppState {
   pub(crate) fn new(args: AppStateArgs) -> Self {
+    crate::problem::set_verbose_errors(&args.config);
     let config = ValueNotifier::new(args.config);
 
     let site_url = Computed::new(&config, move |c| build_site_url(c, &args.address));
 
+    let script_validators = ScriptValidators::default();
     let record_apis = {
       let schema_metadata_clone = args.schema_metadata.clone();
       let conn_clone = args.conn.clone();
+      let script_validators = script_validators.clone();
 
       Computed::new(&config, move |c| {
         return c
           .record_apis
           .iter()
           .filter_map(|config| {
-            match build_record_api(conn_clone.clone(), &schema_metadata_clone, config.clone()) {
+            match build_record_api(
+              conn_clone.clone(),
+              &schema_metadata_clone,
+              config.clone(),
+              &script_validators,
+            ) {
               Ok(api) => Some((api.api_name().to_string(), api)),
               Err(err) => {
                 error!("{err}");
@@ -114,6 +156,8 @@ impl AppState {
         site_url,
         dev: args.dev,
         demo: args.demo,
+        mock_auth: args.mock_auth,
+        clock: args.clock,
         auth: Computed::new(&config, |c| AuthOptions::from_config(c.auth.clone())),
         jobs: Computed::new(&config, move |c| {
           debug!("building jobs from config");
@@ -136,6 +180,7 @@ impl AppState {
         record_apis: record_apis.clone(),
         config,
         conn: args.conn.clone(),
+        read_only_conn: args.read_only_conn,
         logs_conn: args.logs_conn,
         queue: args.queue,
         jwt: args.jwt,
@@ -145,11 +190,19 @@ impl AppState {
           args.schema_metadata,
           record_apis,
         ),
+        query_cache: QueryCache::new(),
+        list_query_cache: ListQueryCache::new(),
+        rate_limiter: RateLimiter::new(),
+        record_hooks: RecordHooks::default(),
+        script_validators,
         object_store,
         runtime,
+        tenant_options: args.tenant_options,
+        tenants: Mutex::new(HashMap::new()),
         #[cfg(test)]
         cleanup: vec![],
       }),
+      tenant: None,
     }
   }
 
@@ -171,8 +224,28 @@ impl AppState {
     return self.state.demo;
   }
 
+  /// Whether `X-Test-User` headers are trusted, see [crate::ServerOptions::mock_auth].
+  pub(crate) fn mock_auth(&self) -> bool {
+    return self.state.mock_auth;
+  }
+
+  /// Source of time and UUIDv7s, see [`Clock`].
+  pub fn clock(&self) -> &Clock {
+    return &self.state.clock;
+  }
+
+  /// Connection to the main database or, for requests resolved to a tenant, to the main database
+  /// with the tenant's databases attached.
   pub fn conn(&self) -> &trailbase_sqlite::Connection {
-    return &self.state.conn;
+    return match self.tenant {
+      Some(ref tenant) => &tenant.conn,
+      None => &self.state.conn,
+    };
+  }
+
+  /// Connection opened with `SQLITE_OPEN_READONLY`, used to serve the SQL API.
+  pub(crate) fn read_only_conn(&self) -> &trailbase_sqlite::Connection {
+    return &self.state.read_only_conn;
   }
 
   pub fn user_conn(&self) -> &trailbase_sqlite::Connection {
@@ -199,7 +272,112 @@ impl AppState {
     return &self.state.subscription_manager;
   }
 
+  pub(crate) fn query_cache(&self) -> &QueryCache {
+    return match self.tenant {
+      Some(ref tenant) => &tenant.query_cache,
+      None => &self.state.query_cache,
+    };
+  }
+
+  /// Rendered listing queries, shared across tenants.
+  pub(crate) fn list_query_cache(&self) -> &ListQueryCache {
+    return &self.state.list_query_cache;
+  }
+
+  /// Clears the query caches of all tenants.
+  fn clear_query_caches(&self) {
+    self.state.query_cache.clear();
+    self.state.list_query_cache.clear();
+    for tenant in self.state.tenants.lock().values() {
+      tenant.query_cache.clear();
+    }
+  }
+
+  /// State scoped to the given tenant, see `Config::tenants`, opening the tenant's databases if
+  /// needed.
+  pub(crate) async fn tenant(&self, name: &str) -> Result<AppState, TenantError> {
+    let scoped = |tenant: Arc<TenantState>| AppState {
+      state: self.state.clone(),
+      tenant: Some(tenant),
+    };
+
+    if let Some(tenant) = self.state.tenants.lock().get(name) {
+      return Ok(scoped(tenant.clone()));
+    }
+
+    let Some(ref options) = self.state.tenant_options else {
+      return Err(TenantError::Unsupported("in-memory databases"));
+    };
+    let config = self.get_config();
+    let known = config
+      .tenants
+      .as_ref()
+      .is_some_and(|tenants| tenants.names.iter().any(|n| n == name));
+    if !known {
+      return Err(TenantError::Unknown(name.to_string()));
+    }
+
+    let conn = open_tenant_connection(options, &config.databases, name).await?;
+    let record_apis = {
+      let conn = conn.clone();
+      let schema_metadata = self.state.schema_metadata.clone();
+      let script_validators = self.state.script_validators.clone();
+      Computed::new(&self.state.config, move |c| {
+        return c
+          .record_apis
+          .iter()
+          .filter_map(|config| {
+            match build_record_api(
+              conn.clone(),
+              &schema_metadata,
+              config.clone(),
+              &script_validators,
+            ) {
+              Ok(api) => Some((api.api_name().to_string(), api)),
+              Err(err) => {
+                error!("{err}");
+                None
+              }
+            }
+          })
+          .collect::<Vec<_>>();
+      })
+    };
+
+    // Keep the first, if the tenant got opened concurrently.
+    let tenant = self
+      .state
+      .tenants
+      .lock()
+      .entry(name.to_string())
+      .or_insert_with(|| {
+        return Arc::new(TenantState {
+          conn,
+          record_apis,
+          query_cache: QueryCache::new(),
+        });
+      })
+      .clone();
+
+    return Ok(scoped(tenant));
+  }
+
+  pub(crate) fn rate_limiter(&self) -> &RateLimiter {
+    return &self.state.rate_limiter;
+  }
+
+  /// Record lifecycle hooks, see [`RecordHooks::register`].
+  pub fn record_hooks(&self) -> &RecordHooks {
+    return &self.state.record_hooks;
+  }
+
+  /// Column validators implemented by scripts, see [`ScriptValidators::register`].
+  pub fn script_validators(&self) -> &ScriptValidators {
+    return &self.state.script_validators;
+  }
+
   pub async fn refresh_table_cache(&self) -> Result<(), crate::schema_metadata::SchemaLookupError> {
+    self.clear_query_caches();
     self.schema_metadata().invalidate_all().await
   }
 
@@ -227,8 +405,15 @@ impl AppState {
     return &self.state.jwt;
   }
 
+  pub(crate) fn record_apis(&self) -> Guard<Arc<Vec<(String, RecordApi)>>> {
+    return match self.tenant {
+      Some(ref tenant) => tenant.record_apis.load(),
+      None => self.state.record_apis.load(),
+    };
+  }
+
   pub fn lookup_record_api(&self, name: &str) -> Option<RecordApi> {
-    for (record_api_name, record_api) in self.state.record_apis.load().iter() {
+    for (record_api_name, record_api) in self.record_apis().iter() {
       if record_api_name == name {
         return Some(record_api.clone());
       }
@@ -276,6 +461,9 @@ impl AppState {
       }
       None => self.state.config.store(config.clone()),
     };
+    crate::problem::set_verbose_errors(&self.state.config.load());
+    // Record APIs got rebuilt, e.g. with different access rules.
+    self.clear_query_caches();
 
     // Write new config to the file system.
     return write_config_and_vault_textproto(
@@ -297,6 +485,8 @@ impl AppState {
 pub struct TestStateOptions {
   pub config: Option<Config>,
   pub(crate) mailer: Option<Mailer>,
+  pub(crate) mock_auth: bool,
+  pub(crate) clock: Option<Clock>,
 }
 
 #[cfg(test)]
@@ -313,9 +503,14 @@ pub async fn test_state(options: Option<TestStateOptions>) -> anyhow::Result<App
   let temp_dir = temp_dir::TempDir::new()?;
   tokio::fs::create_dir_all(temp_dir.child("uploads")).await?;
 
-  let (conn, new) = crate::connection::init_main_db(None, None)?;
+  let clock = options
+    .as_ref()
+    .and_then(|o| o.clock.clone())
+    .unwrap_or_default();
+  let (conn, new) =
+    crate::connection::init_main_db_at(None, None, None, None, None, clock.clone())?;
   assert!(new);
-  let logs_conn = crate::connection::init_logs_db(None)?;
+  let logs_conn = crate::connection::init_logs_db(None, None)?;
 
   let schema_metadata = SchemaMetadataCache::new(conn.clone()).await?;
 
@@ -364,6 +559,8 @@ pub async fn test_state(options: Option<TestStateOptions>) -> anyhow::Result<App
 
   let main_conn_clone = conn.clone();
   let schema_metadata_clone = schema_metadata.clone();
+  let script_validators = ScriptValidators::default();
+  let script_validators_clone = script_validators.clone();
 
   let data_dir = DataDir(temp_dir.path().to_path_buf());
 
@@ -372,18 +569,24 @@ pub async fn test_state(options: Option<TestStateOptions>) -> anyhow::Result<App
 
     build_objectstore(
       &data_dir,
-      Some(&S3StorageConfig {
-        endpoint: Some("http://127.0.0.1:9000".to_string()),
-        region: None,
-        bucket_name: Some("test".to_string()),
-        access_key: Some("minioadmin".to_string()),
-        secret_access_key: Some("minioadmin".to_string()),
-      }),
+      &ServerConfig {
+        s3_storage_config: Some(S3StorageConfig {
+          endpoint: Some("http://127.0.0.1:9000".to_string()),
+          region: None,
+          bucket_name: Some("test".to_string()),
+          access_key: Some("minioadmin".to_string()),
+          secret_access_key: Some("minioadmin".to_string()),
+          prefix: None,
+        }),
+        ..Default::default()
+      },
     )
     .unwrap()
     .into()
   } else {
-    build_objectstore(&data_dir, None).unwrap().into()
+    build_objectstore(&data_dir, &ServerConfig::default())
+      .unwrap()
+      .into()
   };
 
   let record_apis = Computed::new(&config, move |c| {
@@ -395,6 +598,7 @@ pub async fn test_state(options: Option<TestStateOptions>) -> anyhow::Result<App
           main_conn_clone.clone(),
           &schema_metadata_clone,
           config.clone(),
+          &script_validators_clone,
         )
         .unwrap();
 
@@ -417,21 +621,33 @@ pub async fn test_state(options: Option<TestStateOptions>) -> anyhow::Result<App
       site_url: Computed::new(&config, move |c| build_site_url(c, address)),
       dev: true,
       demo: false,
+      mock_auth: options.as_ref().is_some_and(|o| o.mock_auth),
+      clock: clock.clone(),
       auth: Computed::new(&config, |c| AuthOptions::from_config(c.auth.clone())),
       jobs: Computed::new(&config, |_c| JobRegistry::new()),
       mailer: build_mailer(&config, options.and_then(|o| o.mailer)),
       record_apis: record_apis.clone(),
       config,
       conn: conn.clone(),
+      // NOTE: In-memory DBs cannot be shared across connections.
+      read_only_conn: conn.clone(),
       logs_conn,
       queue: Queue::new(None).await.unwrap(),
-      jwt: jwt::test_jwt_helper(),
+      jwt: jwt::test_jwt_helper().with_clock(clock),
       schema_metadata: schema_metadata.clone(),
       subscription_manager: SubscriptionManager::new(conn.clone(), schema_metadata, record_apis),
+      query_cache: QueryCache::new(),
+      list_query_cache: ListQueryCache::new(),
+      rate_limiter: RateLimiter::new(),
+      record_hooks: RecordHooks::default(),
+      script_validators,
       object_store,
       runtime: build_js_runtime(conn, None),
+      tenant_options: None,
+      tenants: Mutex::new(HashMap::new()),
       cleanup: vec![Box::new(temp_dir)],
     }),
+    tenant: None,
   });
 }
 
@@ -460,6 +676,7 @@ fn build_record_api(
   conn: trailbase_sqlite::Connection,
   schema_metadata_cache: &SchemaMetadataCache,
   config: RecordApiConfig,
+  script_validators: &ScriptValidators,
 ) -> Result<RecordApi, String> {
   let Some(ref table_name) = config.table_name else {
     return Err(format!(
@@ -468,19 +685,34 @@ fn build_record_api(
   };
 
   if let Some(schema_metadata) = schema_metadata_cache.get_table(table_name) {
-    return RecordApi::from_table(conn, &schema_metadata, config);
+    return RecordApi::from_table(conn, &schema_metadata, config, script_validators);
   } else if let Some(view) = schema_metadata_cache.get_view(table_name) {
-    return RecordApi::from_view(conn, &view, config);
+    return RecordApi::from_view(conn, &view, config, script_validators);
   }
 
   return Err(format!("RecordApi references missing table: {config:?}"));
 }
 
+/// Builds the object store for file columns: S3(-compatible), GCS or, by default, the local
+/// file-system.
 pub(crate) fn build_objectstore(
   data_dir: &DataDir,
-  config: Option<&S3StorageConfig>,
+  config: &ServerConfig,
 ) -> Result<Box<dyn ObjectStore + Send + Sync>, object_store::Error> {
-  if let Some(config) = config {
+  fn with_prefix(
+    store: impl ObjectStore,
+    prefix: Option<&String>,
+  ) -> Box<dyn ObjectStore + Send + Sync> {
+    return match prefix {
+      Some(prefix) => Box::new(object_store::prefix::PrefixStore::new(
+        store,
+        prefix.as_str(),
+      )),
+      None => Box::new(store),
+    };
+  }
+
+  if let Some(ref config) = config.s3_storage_config {
     let mut builder = object_store::aws::AmazonS3Builder::from_env();
 
     if let Some(ref endpoint) = config.endpoint {
@@ -509,7 +741,22 @@ pub(crate) fn build_objectstore(
       builder = builder.with_secret_access_key(secret_access_key);
     }
 
-    return Ok(Box::new(builder.build()?));
+    return Ok(with_prefix(builder.build()?, config.prefix.as_ref()));
+  }
+
+  if let Some(ref config) = config.gcs_storage_config {
+    let mut builder = object_store::gcp::GoogleCloudStorageBuilder::from_env();
+
+    let Some(ref bucket_name) = config.bucket_name else {
+      panic!("GcsStorageConfig missing 'bucket_name'.");
+    };
+    builder = builder.with_bucket_name(bucket_name);
+
+    if let Some(ref service_account_key) = config.service_account_key {
+      builder = builder.with_service_account_key(service_account_key);
+    }
+
+    return Ok(with_prefix(builder.build()?, config.prefix.as_ref()));
   }
 
   return Ok(Box::new(
@@ -0,0 +1,353 @@
+use log::*;
+use serde_json::{Map, Value};
+use trailbase_sqlite::{Connection, params};
+
+use crate::app_state::AppState;
+use crate::auth::user::User;
+use crate::constants::{AUDIT_LOG_RETENTION_DEFAULT, AUDIT_LOG_TABLE};
+use crate::records::RecordApi;
+use crate::records::query_builder::SelectQueryBuilder;
+use crate::records::sql_to_json::row_to_json;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum AuditAction {
+  Create,
+  Update,
+  Delete,
+  Admin,
+}
+
+impl AuditAction {
+  fn as_str(&self) -> &'static str {
+    return match self {
+      Self::Create => "create",
+      Self::Update => "update",
+      Self::Delete => "delete",
+      Self::Admin => "admin",
+    };
+  }
+}
+
+pub(crate) fn audit_log_enabled(state: &AppState) -> bool {
+  return state.access_config(|c| {
+    c.server
+      .audit_log
+      .as_ref()
+      .is_some_and(|a| a.enabled == Some(true))
+  });
+}
+
+/// Reads a record's current column values for audit entries if the audit log is enabled.
+///
+/// NOTE: Unlike the writes themselves, snapshots aren't transactional, i.e. concurrent writes in
+/// between may be attributed to the audited mutation.
+pub(crate) async fn snapshot_record(
+  state: &AppState,
+  api: &RecordApi,
+  record_id: &str,
+) -> Option<Value> {
+  if !audit_log_enabled(state) {
+    return None;
+  }
+  return read_record_json(state, api, record_id).await;
+}
+
+/// Reads a record's current column values, e.g. for audit entries and record hooks.
+pub(crate) async fn read_record_json(
+  state: &AppState,
+  api: &RecordApi,
+  record_id: &str,
+) -> Option<Value> {
+  let pk = api.id_to_sql(record_id).ok()?;
+  let column_names: Vec<_> = api.columns().iter().map(|c| c.name.as_str()).collect();
+  let row = match SelectQueryBuilder::run(
+    state.conn(),
+    api.table_name(),
+    &column_names,
+    &api.record_id_clause(Some("MAIN"), "?1"),
+    pk,
+  )
+  .await
+  {
+    Ok(row) => row?,
+    Err(err) => {
+      warn!("Failed to read record '{record_id}': {err}");
+      return None;
+    }
+  };
+
+  // NOTE: Encrypted columns are returned as ciphertexts.
+  return row_to_json(api.columns(), api.json_column_metadata(), &row, |_| true).ok();
+}
+
+/// Appends an entry for a mutation by an authenticated user. Only changed columns are logged.
+pub(crate) async fn audit_record_mutation(
+  state: &AppState,
+  user: Option<&User>,
+  api: &RecordApi,
+  action: AuditAction,
+  record_id: &str,
+  before: Option<Value>,
+  after: Option<Value>,
+) {
+  let Some(user) = user else {
+    return;
+  };
+  if !audit_log_enabled(state) {
+    return;
+  }
+
+  let (before, after) = diff(before, after);
+  append(
+    state.conn(),
+    user,
+    action,
+    Some(api.table_name().to_string()),
+    Some(record_id.to_string()),
+    before,
+    after,
+    None,
+  )
+  .await;
+}
+
+/// Appends an entry for a successful admin request, e.g. "POST /table".
+pub(crate) async fn audit_admin_action(state: &AppState, user: &User, method: &str, path: &str) {
+  if !audit_log_enabled(state) {
+    return;
+  }
+
+  append(
+    state.conn(),
+    user,
+    AuditAction::Admin,
+    None,
+    None,
+    None,
+    None,
+    Some(format!("{method} {path}")),
+  )
+  .await;
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn append(
+  conn: &Connection,
+  user: &User,
+  action: AuditAction,
+  table_name: Option<String>,
+  record_id: Option<String>,
+  before: Option<Value>,
+  after: Option<Value>,
+  details: Option<String>,
+) {
+  let result = conn
+    .execute(
+      format!(
+        "INSERT INTO {AUDIT_LOG_TABLE} (user, action, table_name, record_id, before, after, details) VALUES ($1, $2, $3, $4, $5, $6, $7)"
+      ),
+      params!(
+        user.uuid.into_bytes(),
+        action.as_str().to_string(),
+        table_name,
+        record_id,
+        before.map(|v| v.to_string()),
+        after.map(|v| v.to_string()),
+        details,
+      ),
+    )
+    .await;
+
+  if let Err(err) = result {
+    warn!("Failed to append to audit log: {err}");
+  }
+}
+
+/// Reduces before and after snapshots of a record to the changed columns.
+fn diff(before: Option<Value>, after: Option<Value>) -> (Option<Value>, Option<Value>) {
+  let (Some(Value::Object(before)), Some(Value::Object(after))) = (&before, &after) else {
+    return (before, after);
+  };
+
+  let mut changed_before = Map::new();
+  let mut changed_after = Map::new();
+  for (key, value) in after {
+    let previous = before.get(key).unwrap_or(&Value::Null);
+    if previous != value {
+      changed_before.insert(key.clone(), previous.clone());
+      changed_after.insert(key.clone(), value.clone());
+    }
+  }
+
+  return (
+    Some(Value::Object(changed_before)),
+    Some(Value::Object(changed_after)),
+  );
+}
+
+/// Deletes entries exceeding the configured retention.
+pub(crate) async fn delete_expired_audit_log_entries(
+  conn: &Connection,
+  retention_sec: Option<i64>,
+) -> Result<(), trailbase_sqlite::Error> {
+  let retention = retention_sec.unwrap_or_else(|| AUDIT_LOG_RETENTION_DEFAULT.num_seconds());
+  conn
+    .execute(
+      format!("DELETE FROM {AUDIT_LOG_TABLE} WHERE created < UNIXEPOCH() - $1"),
+      params!(retention),
+    )
+    .await?;
+  return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::json;
+
+  use super::*;
+  use crate::admin::user::create_user_for_test;
+  use crate::app_state::test_state;
+  use crate::config::proto::{AuditLogConfig, PermissionFlag, RecordApiConfig};
+  use crate::records::test_utils::add_record_api_config;
+
+  #[test]
+  fn test_diff() {
+    assert_eq!(
+      diff(
+        Some(json!({"id": 1, "name": "a", "age": 3})),
+        Some(json!({"id": 1, "name": "b", "age": 3})),
+      ),
+      (Some(json!({"name": "a"})), Some(json!({"name": "b"})))
+    );
+    assert_eq!(
+      diff(None, Some(json!({"id": 1}))),
+      (None, Some(json!({"id": 1})))
+    );
+  }
+
+  #[tokio::test]
+  async fn test_audit_record_mutations() {
+    let state = test_state(None).await.unwrap();
+    state
+      .conn()
+      .execute_batch("CREATE TABLE item (id INTEGER PRIMARY KEY, name TEXT) STRICT;")
+      .await
+      .unwrap();
+    state.schema_metadata().invalidate_all().await.unwrap();
+
+    add_record_api_config(
+      &state,
+      RecordApiConfig {
+        name: Some("items".to_string()),
+        table_name: Some("item".to_string()),
+        acl_authenticated: [
+          PermissionFlag::Create as i32,
+          PermissionFlag::Update as i32,
+          PermissionFlag::Delete as i32,
+        ]
+        .into(),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    let mut config = state.get_config();
+    config.server.audit_log = Some(AuditLogConfig {
+      enabled: Some(true),
+      retention_sec: None,
+    });
+    state
+      .validate_and_update_config(config, None)
+      .await
+      .unwrap();
+
+    let user_id = create_user_for_test(&state, "user@bar.org", "Secret!1!!")
+      .await
+      .unwrap();
+    let user = User::from_unverified(user_id, "user@bar.org");
+    let api = state.lookup_record_api("items").unwrap();
+
+    crate::records::create_record::create_records(
+      &state,
+      &api,
+      vec![(
+        json!({"id": 1, "name": "first"})
+          .as_object()
+          .unwrap()
+          .clone(),
+        None,
+      )],
+      None,
+      Some(&user),
+    )
+    .await
+    .unwrap();
+    crate::records::update_record::update_record(
+      &state,
+      &api,
+      "1".to_string(),
+      json!({"name": "second"}).as_object().unwrap().clone(),
+      None,
+      None,
+      Some(&user),
+    )
+    .await
+    .unwrap();
+    crate::records::delete_record::delete_record(&state, &api, "1", None, Some(&user))
+      .await
+      .unwrap();
+
+    #[derive(Debug, serde::Deserialize)]
+    struct Entry {
+      user: [u8; 16],
+      action: String,
+      record_id: String,
+      before: Option<String>,
+      after: Option<String>,
+    }
+
+    let entries = state
+      .conn()
+      .read_query_as::<Entry>(
+        format!("SELECT user, action, record_id, before, after FROM {AUDIT_LOG_TABLE} ORDER BY id"),
+        (),
+      )
+      .await
+      .unwrap();
+
+    assert_eq!(
+      entries
+        .iter()
+        .map(|e| e.action.as_str())
+        .collect::<Vec<_>>(),
+      ["create", "update", "delete"]
+    );
+    assert!(
+      entries
+        .iter()
+        .all(|e| e.user == user_id.into_bytes() && e.record_id == "1")
+    );
+
+    let parse = |v: &Option<String>| {
+      v.as_deref()
+        .map(|v| serde_json::from_str::<Value>(v).unwrap())
+    };
+    assert_eq!(parse(&entries[1].before), Some(json!({"name": "first"})));
+    assert_eq!(parse(&entries[1].after), Some(json!({"name": "second"})));
+    assert_eq!(
+      parse(&entries[2].before),
+      Some(json!({"id": 1, "name": "second"}))
+    );
+    assert_eq!(entries[2].after, None);
+
+    // The log is append-only.
+    assert!(
+      state
+        .conn()
+        .execute(format!("UPDATE {AUDIT_LOG_TABLE} SET action = 'admin'"), ())
+        .await
+        .is_err()
+    );
+  }
+}
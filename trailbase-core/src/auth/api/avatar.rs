@@ -122,6 +122,7 @@ mod tests {
   use crate::records::create_record::{
     CreateRecordQuery, CreateRecordResponse, create_record_handler,
   };
+  use crate::records::image_transform::FileQuery;
   use crate::records::read_record::get_uploaded_file_from_record_handler;
   use crate::test::unpack_json_response;
   use crate::util::{b64_to_uuid, id_to_b64, uuid_to_b64};
@@ -187,6 +188,7 @@ mod tests {
         id_to_b64(record_id),
         COL_NAME.to_string(),
       )),
+      Query(FileQuery::default()),
       None,
     )
     .await
@@ -48,6 +48,7 @@ pub async fn change_email_request_handler(
     Either::Json(req) => (req, true),
     Either::Multipart(req, _) => (req, false),
     Either::Form(req) => (req, false),
+    Either::MsgPack(req) | Either::Cbor(req) => (req, true),
   };
 
   if request.csrf_token != user.csrf_token {
@@ -72,8 +73,11 @@ pub async fn change_email_request_handler(
     };
 
     let age: chrono::Duration = chrono::Utc::now() - timestamp;
-    if age < chrono::Duration::seconds(RATE_LIMIT_SEC) {
-      return Err(AuthError::BadRequest("verification sent already"));
+    let retry_after = chrono::Duration::seconds(RATE_LIMIT_SEC) - age;
+    if retry_after > chrono::Duration::zero() {
+      return Err(AuthError::TooManyRequests(
+        retry_after.to_std().unwrap_or_default(),
+      ));
     }
   }
 
@@ -50,6 +50,7 @@ pub async fn change_password_handler(
     Either::Json(req) => req,
     Either::Multipart(req, _) => req,
     Either::Form(req) => req,
+    Either::MsgPack(req) | Either::Cbor(req) => req,
   };
 
   let auth_options = state.auth_options();
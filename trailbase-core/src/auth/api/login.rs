@@ -70,6 +70,7 @@ pub(crate) async fn login_handler(
     Either::Json(req) => (req, true),
     Either::Form(req) => (req, false),
     Either::Multipart(req, _) => (req, false),
+    Either::MsgPack(req) | Either::Cbor(req) => (req, true),
   };
 
   let normalized_email = validate_and_normalize_email_address(&request.email)?;
@@ -253,7 +254,13 @@ pub async fn login_with_password(
   normalized_email: &str,
   password: &str,
 ) -> Result<NewTokens, AuthError> {
-  let db_user: DbUser = user_by_email(state, normalized_email).await?;
+  let db_user: DbUser = user_by_email(state, normalized_email)
+    .await
+    .map_err(|err| match err {
+      // Don't reveal whether the user exists.
+      AuthError::UnauthorizedExt(_) => AuthError::InvalidCredentials,
+      err => err,
+    })?;
 
   // Validate password.
   check_user_password(&db_user, password, state.demo_mode())?;
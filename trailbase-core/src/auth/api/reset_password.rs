@@ -45,6 +45,7 @@ pub async fn reset_password_request_handler(
     Either::Json(req) => req,
     Either::Multipart(req, _) => req,
     Either::Form(req) => req,
+    Either::MsgPack(req) | Either::Cbor(req) => req,
   };
 
   let normalized_email = validate_and_normalize_email_address(&request.email)?;
@@ -57,8 +58,11 @@ pub async fn reset_password_request_handler(
     };
 
     let age: chrono::Duration = chrono::Utc::now() - timestamp;
-    if age < chrono::Duration::seconds(RATE_LIMIT_SEC) {
-      return Err(AuthError::BadRequest("Password reset sent already"));
+    let retry_after = chrono::Duration::seconds(RATE_LIMIT_SEC) - age;
+    if retry_after > chrono::Duration::zero() {
+      return Err(AuthError::TooManyRequests(
+        retry_after.to_std().unwrap_or_default(),
+      ));
     }
   }
 
@@ -128,6 +132,7 @@ pub async fn reset_password_update_handler(
     Either::Json(req) => req,
     Either::Multipart(req, _) => req,
     Either::Form(req) => req,
+    Either::MsgPack(req) | Either::Cbor(req) => req,
   };
 
   let auth_options = state.auth_options();
@@ -44,8 +44,11 @@ pub async fn request_email_verification_handler(
     };
 
     let age: chrono::Duration = chrono::Utc::now() - timestamp;
-    if age < chrono::Duration::seconds(RATE_LIMIT_SEC) {
-      return Err(AuthError::BadRequest("verification sent already"));
+    let retry_after = chrono::Duration::seconds(RATE_LIMIT_SEC) - age;
+    if retry_after > chrono::Duration::zero() {
+      return Err(AuthError::TooManyRequests(
+        retry_after.to_std().unwrap_or_default(),
+      ));
     }
   }
 
@@ -0,0 +1,187 @@
+use axum::extract::Request;
+use axum::http::{header, request::Parts};
+use axum::middleware::Next;
+use axum::response::Response;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use trailbase_sqlite::params;
+use ts_rs::TS;
+use uuid::Uuid;
+
+use crate::app_state::AppState;
+use crate::auth::AuthError;
+use crate::auth::user::User;
+use crate::auth::util::user_roles;
+use crate::constants::{API_KEY_TABLE, USER_TABLE};
+use crate::rand::generate_random_string;
+use crate::records::Permission;
+use crate::util::{get_header, uuid_to_b64};
+
+/// Prefix distinguishing API keys from JWT auth tokens in `Authorization: Bearer` headers.
+pub(crate) const API_KEY_PREFIX: &str = "tbk_";
+const API_KEY_LENGTH: usize = 40;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Deserialize, Serialize, TS)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiKeyAccess {
+  #[default]
+  ReadOnly,
+  ReadWrite,
+}
+
+/// Scope of an API key, granting access to a record API on behalf of the key's user.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Deserialize, Serialize, TS)]
+#[ts(export)]
+pub struct ApiKeyScope {
+  /// Name of the record API or "*" for all APIs.
+  pub api: String,
+  #[serde(default)]
+  pub access: ApiKeyAccess,
+}
+
+impl ApiKeyScope {
+  /// Whether the scope grants the permission on the given record API. Note that the key's user
+  /// still needs the permission, i.e. scopes can only restrict access.
+  pub fn grants(&self, api_name: &str, p: Permission) -> bool {
+    if self.api != "*" && self.api != api_name {
+      return false;
+    }
+
+    return match self.access {
+      ApiKeyAccess::ReadOnly => matches!(p, Permission::Read | Permission::Schema),
+      ApiKeyAccess::ReadWrite => true,
+    };
+  }
+}
+
+/// Marker inserted by [accept_api_keys_middleware] for routes accepting API keys.
+#[derive(Clone, Copy, Debug)]
+struct AcceptApiKeys;
+
+/// Allows requests to authenticate using API keys, which are otherwise rejected.
+pub(crate) async fn accept_api_keys_middleware(mut request: Request, next: Next) -> Response {
+  request.extensions_mut().insert(AcceptApiKeys);
+  return next.run(request).await;
+}
+
+/// Generates a new API key returning the key and its hash. Only the hash is meant to be stored.
+pub(crate) fn generate_api_key() -> (String, Vec<u8>) {
+  let key = format!("{API_KEY_PREFIX}{}", generate_random_string(API_KEY_LENGTH));
+  let hash = hash_api_key(&key);
+  return (key, hash);
+}
+
+/// Unlike passwords, API keys are high-entropy random strings, thus a fast hash is sufficient.
+pub(crate) fn hash_api_key(key: &str) -> Vec<u8> {
+  return Sha256::digest(key.as_bytes()).to_vec();
+}
+
+/// Returns the key's [User] if the request carries an API key and the route accepts API keys.
+pub(crate) async fn user_from_api_key(
+  state: &AppState,
+  parts: &Parts,
+) -> Result<Option<User>, AuthError> {
+  let Some(key) = get_header(&parts.headers, header::AUTHORIZATION)
+    .and_then(|v| v.strip_prefix("Bearer "))
+    .filter(|v| v.starts_with(API_KEY_PREFIX))
+  else {
+    return Ok(None);
+  };
+
+  if parts.extensions.get::<AcceptApiKeys>().is_none() {
+    return Err(AuthError::Unauthorized);
+  }
+
+  lazy_static! {
+    static ref QUERY: String = format!(
+      r#"
+        SELECT u.id, u.email, k.scopes
+        FROM "{API_KEY_TABLE}" AS k JOIN "{USER_TABLE}" AS u ON k.user = u.id
+        WHERE k.key_hash = $1 AND u.verified AND (k.expires IS NULL OR k.expires > $2)
+      "#
+    );
+  }
+
+  let Some((id, email, scopes)) = state
+    .user_conn()
+    .read_query_row_f(
+      &*QUERY,
+      params!(hash_api_key(key), state.clock().now().timestamp()),
+      |row| -> Result<(Vec<u8>, String, String), rusqlite::Error> {
+        return Ok((row.get(0)?, row.get(1)?, row.get(2)?));
+      },
+    )
+    .await?
+  else {
+    return Err(AuthError::Unauthorized);
+  };
+
+  let uuid = Uuid::from_slice(&id).map_err(|err| AuthError::Internal(err.into()))?;
+  let scopes: Vec<ApiKeyScope> =
+    serde_json::from_str(&scopes).map_err(|err| AuthError::Internal(err.into()))?;
+
+  return Ok(Some(User {
+    id: uuid_to_b64(&uuid),
+    email,
+    uuid,
+    csrf_token: String::new(),
+    roles: user_roles(state, &uuid).await?,
+    api_key_scopes: Some(scopes),
+  }));
+}
+
+/// Whether the key exists, i.e. belongs to a verified user and hasn't expired. Unlike
+/// [user_from_api_key], this doesn't require the route to accept API keys.
+pub(crate) async fn is_valid_api_key(state: &AppState, key: &str) -> Result<bool, AuthError> {
+  lazy_static! {
+    static ref QUERY: String = format!(
+      r#"
+        SELECT EXISTS(
+          SELECT 1 FROM "{API_KEY_TABLE}" AS k JOIN "{USER_TABLE}" AS u ON k.user = u.id
+          WHERE k.key_hash = $1 AND u.verified AND (k.expires IS NULL OR k.expires > $2)
+        )
+      "#
+    );
+  }
+
+  let exists = state
+    .user_conn()
+    .read_query_row_f(
+      &*QUERY,
+      params!(hash_api_key(key), state.clock().now().timestamp()),
+      |row| row.get::<_, bool>(0),
+    )
+    .await?;
+  return Ok(exists.unwrap_or(false));
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_api_key_scope() {
+    let read_only = ApiKeyScope {
+      api: "posts".to_string(),
+      access: ApiKeyAccess::ReadOnly,
+    };
+    assert!(read_only.grants("posts", Permission::Read));
+    assert!(read_only.grants("posts", Permission::Schema));
+    assert!(!read_only.grants("posts", Permission::Create));
+    assert!(!read_only.grants("posts", Permission::Delete));
+    assert!(!read_only.grants("comments", Permission::Read));
+
+    let read_write = ApiKeyScope {
+      api: "*".to_string(),
+      access: ApiKeyAccess::ReadWrite,
+    };
+    assert!(read_write.grants("posts", Permission::Update));
+    assert!(read_write.grants("comments", Permission::Create));
+
+    let (key, hash) = generate_api_key();
+    assert!(key.starts_with(API_KEY_PREFIX));
+    assert_eq!(hash, hash_api_key(&key));
+    assert_ne!(hash, hash_api_key(&generate_api_key().0));
+  }
+}
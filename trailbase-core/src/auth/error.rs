@@ -1,17 +1,24 @@
-use axum::body::Body;
-use axum::http::{StatusCode, header::CONTENT_TYPE};
 use axum::response::{IntoResponse, Response};
 use log::*;
+use std::time::Duration;
 use thiserror::Error;
 
+use crate::problem::{ErrorCode, Problem, verbose_errors};
+
 #[derive(Debug, Error)]
 pub enum AuthError {
   #[error("Unauthorized")]
   Unauthorized,
   #[error("Unauthorized")]
   UnauthorizedExt(Box<dyn std::error::Error + Send + Sync>),
+  /// Wrong email or password. Deliberately doesn't distinguish between the two.
+  #[error("Invalid credentials")]
+  InvalidCredentials,
   #[error("Forbidden")]
   Forbidden,
+  /// Rate limited, clients should retry after the given delay.
+  #[error("Too many requests, retry after {0:?}")]
+  TooManyRequests(Duration),
   #[error("Conflict")]
   Conflict,
   #[error("NotFound")]
@@ -64,39 +71,32 @@ impl From<trailbase_sqlite::Error> for AuthError {
 
 impl IntoResponse for AuthError {
   fn into_response(self) -> Response {
-    let (status, body) = match self {
-      Self::Unauthorized => (StatusCode::UNAUTHORIZED, None),
-      Self::UnauthorizedExt(msg) if cfg!(debug_assertions) => {
-        (StatusCode::UNAUTHORIZED, Some(msg.to_string()))
+    let problem = match self {
+      Self::Unauthorized => Problem::new(ErrorCode::AuthUnauthorized),
+      Self::UnauthorizedExt(msg) if verbose_errors() => {
+        Problem::new(ErrorCode::AuthUnauthorized).with_detail(msg.to_string())
+      }
+      Self::UnauthorizedExt(_msg) => Problem::new(ErrorCode::AuthUnauthorized),
+      Self::InvalidCredentials => Problem::new(ErrorCode::AuthInvalidCredentials),
+      Self::Forbidden => Problem::new(ErrorCode::AuthForbidden),
+      Self::Conflict => Problem::new(ErrorCode::AuthConflict),
+      Self::NotFound => Problem::new(ErrorCode::AuthNotFound),
+      Self::OAuthProviderNotFound => Problem::new(ErrorCode::AuthOAuthProviderNotFound),
+      Self::BadRequest(msg) => Problem::new(ErrorCode::AuthBadRequest).with_detail(msg),
+      Self::TooManyRequests(retry_after) => {
+        Problem::new(ErrorCode::AuthTooManyRequests).with_retry_after(retry_after)
       }
-      Self::UnauthorizedExt(_msg) => (StatusCode::UNAUTHORIZED, None),
-      Self::Forbidden => (StatusCode::FORBIDDEN, None),
-      Self::Conflict => (StatusCode::CONFLICT, None),
-      Self::NotFound => (StatusCode::NOT_FOUND, None),
-      Self::OAuthProviderNotFound => (StatusCode::METHOD_NOT_ALLOWED, None),
-      Self::BadRequest(msg) => (StatusCode::BAD_REQUEST, Some(msg.to_string())),
-      Self::FailedDependency(err) if cfg!(debug_assertions) => {
-        (StatusCode::FAILED_DEPENDENCY, Some(err.to_string()))
+      Self::FailedDependency(err) if verbose_errors() => {
+        Problem::new(ErrorCode::AuthFailedDependency).with_detail(err.to_string())
       }
-      Self::FailedDependency(_err) => (StatusCode::FAILED_DEPENDENCY, None),
-      Self::Internal(err) if cfg!(debug_assertions) => {
-        (StatusCode::INTERNAL_SERVER_ERROR, Some(err.to_string()))
+      Self::FailedDependency(_err) => Problem::new(ErrorCode::AuthFailedDependency),
+      Self::Internal(err) if verbose_errors() => {
+        Problem::new(ErrorCode::AuthInternal).with_detail(err.to_string())
       }
-      Self::Internal(_err) => (StatusCode::INTERNAL_SERVER_ERROR, None),
+      Self::Internal(_err) => Problem::new(ErrorCode::AuthInternal),
     };
 
-    if let Some(body) = body {
-      return Response::builder()
-        .status(status)
-        .header(CONTENT_TYPE, "text/plain")
-        .body(Body::new(body))
-        .unwrap_or_default();
-    }
-
-    return Response::builder()
-      .status(status)
-      .body(Body::empty())
-      .unwrap_or_default();
+    return problem.into_response();
   }
 }
 
@@ -6,6 +6,7 @@ use ed25519_dalek::pkcs8::{EncodePrivateKey, EncodePublicKey};
 use ed25519_dalek::{SigningKey, VerifyingKey};
 use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation, errors::Error as JwtError};
 use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use sha2::{Digest, Sha256};
 use std::path::{Path, PathBuf};
 use thiserror::Error;
 use tokio::{
@@ -13,6 +14,7 @@ use tokio::{
   io::{AsyncReadExt, AsyncWriteExt},
 };
 
+use crate::clock::Clock;
 use crate::data_dir::DataDir;
 
 #[derive(Debug, Error)]
@@ -42,6 +44,10 @@ pub struct TokenClaims {
   /// CSRF random token. Requiring that the client echos this random token back on a non-cookie,
   /// non-auto-attach channel can be used to protect from CSRF.
   pub csrf_token: String,
+
+  /// Built-in roles granted to the [sub] at the time the token was minted.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub roles: Vec<String>,
 }
 
 impl TokenClaims {
@@ -50,16 +56,27 @@ impl TokenClaims {
     user_id: uuid::Uuid,
     email: String,
     expires_in: chrono::Duration,
+  ) -> Self {
+    return Self::new_at(chrono::Utc::now(), verified, user_id, email, expires_in);
+  }
+
+  /// Like [`TokenClaims::new`] but issued at the given time, e.g. from the server's [`Clock`].
+  pub(crate) fn new_at(
+    now: chrono::DateTime<chrono::Utc>,
+    verified: bool,
+    user_id: uuid::Uuid,
+    email: String,
+    expires_in: chrono::Duration,
   ) -> Self {
     assert!(verified);
 
-    let now = chrono::Utc::now();
     return TokenClaims {
       sub: uuid_to_b64(&user_id),
       exp: (now + expires_in).timestamp(),
       iat: now.timestamp(),
       email,
       csrf_token: generate_random_string(20),
+      roles: vec![],
     };
   }
 }
@@ -74,6 +91,11 @@ pub struct JwtHelper {
   // The public key used for validating provided JWTs.
   decoding_key: DecodingKey,
   public_key: String,
+
+  // Symmetric secret derived from the private key, e.g. for signing pagination cursors.
+  secret: [u8; 32],
+
+  clock: Clock,
 }
 
 impl JwtHelper {
@@ -84,9 +106,17 @@ impl JwtHelper {
       encoding_key: EncodingKey::from_ed_pem(&private_key)?,
       decoding_key: DecodingKey::from_ed_pem(&public_key)?,
       public_key: String::from_utf8_lossy(&public_key).to_string(),
+      secret: Sha256::digest(&private_key).into(),
+      clock: Clock::system(),
     });
   }
 
+  /// Validates token expiry against the given clock rather than the system time.
+  pub(crate) fn with_clock(mut self, clock: Clock) -> Self {
+    self.clock = clock;
+    return self;
+  }
+
   pub async fn init_from_path(data_dir: &DataDir) -> Result<Self, JwtHelperError> {
     let key_path = data_dir.key_path();
 
@@ -117,10 +147,39 @@ impl JwtHelper {
     return self.public_key.clone();
   }
 
+  /// Symmetric key for the given purpose, e.g. to sign opaque tokens that are only ever verified
+  /// by this server. Stable across restarts as long as the key pair is.
+  pub(crate) fn derive_secret(&self, purpose: &str) -> [u8; 32] {
+    return Sha256::new()
+      .chain_update(purpose.as_bytes())
+      .chain_update(self.secret)
+      .finalize()
+      .into();
+  }
+
   pub fn decode<T: DeserializeOwned>(&self, token: &str) -> Result<T, JwtError> {
-    // Note: we don't need to expose the token headers.
-    return jsonwebtoken::decode::<T>(token, &self.decoding_key, &self.validation)
-      .map(|data| data.claims);
+    if !self.clock.is_frozen() {
+      // Note: we don't need to expose the token headers.
+      return jsonwebtoken::decode::<T>(token, &self.decoding_key, &self.validation)
+        .map(|data| data.claims);
+    }
+
+    // jsonwebtoken validates expiry against the system time, thus check ourselves.
+    let mut validation = self.validation.clone();
+    validation.validate_exp = false;
+    let claims =
+      jsonwebtoken::decode::<serde_json::Value>(token, &self.decoding_key, &validation)?.claims;
+
+    let cutoff = self.clock.now().timestamp() - validation.leeway as i64;
+    if claims
+      .get("exp")
+      .and_then(|exp| exp.as_i64())
+      .is_some_and(|exp| exp < cutoff)
+    {
+      return Err(jsonwebtoken::errors::ErrorKind::ExpiredSignature.into());
+    }
+
+    return Ok(serde_json::from_value(claims)?);
   }
 
   pub fn encode<T: Serialize>(&self, claims: &T) -> Result<String, JwtError> {
@@ -187,7 +246,7 @@ mod tests {
   fn test_decode_encode() {
     let jwt = test_jwt_helper();
 
-    let claims = TokenClaims::new(
+    let mut claims = TokenClaims::new(
       true,
       uuid::Uuid::now_v7(),
       "foo@bar.com".to_string(),
@@ -196,6 +255,11 @@ mod tests {
     let token = jwt.encode(&claims).unwrap();
 
     assert_eq!(claims, jwt.decode(&token).unwrap());
+
+    claims.roles = vec!["editor".to_string()];
+    let token = jwt.encode(&claims).unwrap();
+
+    assert_eq!(claims, jwt.decode(&token).unwrap());
   }
 }
 
@@ -8,6 +8,7 @@ pub mod jwt;
 pub mod user;
 
 pub(crate) mod api;
+pub(crate) mod api_key;
 pub(crate) mod oauth;
 pub(crate) mod options;
 pub(crate) mod password;
@@ -18,10 +19,11 @@ mod error;
 mod ui;
 
 pub use api::reset_password::force_password_reset;
+pub use api_key::{ApiKeyAccess, ApiKeyScope};
 pub use error::AuthError;
 pub use jwt::{JwtHelper, TokenClaims};
 pub(crate) use ui::auth_ui_router;
-pub use user::User;
+pub use user::{AdminRole, OptionalUser, RequireRole, Role, User};
 
 use crate::constants::AUTH_API_PATH;
 
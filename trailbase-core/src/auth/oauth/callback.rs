@@ -63,7 +63,7 @@ pub(crate) async fn callback_from_external_auth_provider(
     .build()
     .map_err(|err| AuthError::Internal(err.into()))?;
 
-  let client = provider.oauth_client(&state)?;
+  let client = provider.oauth_client(&state).await?;
 
   // Exchange code for token.
   let token_response: StandardTokenResponse<_, oauth2::basic::BasicTokenType> = client
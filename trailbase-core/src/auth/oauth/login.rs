@@ -34,7 +34,7 @@ pub(crate) async fn login_with_external_auth_provider(
   let redirect = validate_redirects(&state, &query.redirect_to, &None)?;
   let code_response = query.response_type.is_some_and(|r| r == "code");
 
-  let client = provider.oauth_client(&state)?;
+  let client = provider.oauth_client(&state).await?;
 
   let (pkce_code_challenge, pkce_code_verifier) = PkceCodeChallenge::new_random_sha256();
 
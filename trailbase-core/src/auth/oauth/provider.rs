@@ -62,9 +62,9 @@ pub trait OAuthProvider {
 
   fn display_name(&self) -> &str;
 
-  fn settings(&self) -> Result<OAuthClientSettings, AuthError>;
+  async fn settings(&self) -> Result<OAuthClientSettings, AuthError>;
 
-  fn oauth_client(&self, state: &AppState) -> Result<OAuthClient, AuthError> {
+  async fn oauth_client(&self, state: &AppState) -> Result<OAuthClient, AuthError> {
     let redirect_url: Url = state
       .site_url()
       .join(&format!(
@@ -73,7 +73,7 @@ pub trait OAuthProvider {
       ))
       .map_err(|err| AuthError::FailedDependency(err.into()))?;
 
-    let settings = self.settings()?;
+    let settings = self.settings().await?;
     if settings.client_id.is_empty() {
       return Err(AuthError::Internal(
         format!("Missing client id for {}", self.name()).into(),
@@ -16,6 +16,8 @@ use thiserror::Error;
 use crate::auth::oauth::OAuthProvider;
 use crate::config::proto::{AuthConfig, OAuthProviderConfig, OAuthProviderId};
 
+pub(crate) use oidc::OIDC_CLAIM_PROPERTIES;
+
 #[derive(Debug, Error)]
 pub enum OAuthProviderError {
   #[error("Missing error: {0}")]
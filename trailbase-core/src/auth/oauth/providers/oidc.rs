@@ -1,5 +1,7 @@
 use async_trait::async_trait;
-use serde::{Deserialize, Serialize};
+use serde::Deserialize;
+use std::collections::HashMap;
+use tokio::sync::OnceCell;
 use url::Url;
 
 use crate::auth::AuthError;
@@ -7,16 +9,28 @@ use crate::auth::oauth::providers::OAuthProviderFactory;
 use crate::auth::oauth::{OAuthClientSettings, OAuthProvider, OAuthUser};
 use crate::config::proto::{OAuthProviderConfig, OAuthProviderId};
 
-// TODO: Add name/display name and this would already be a generic CustomOAuthProvider.
+/// User properties, which can be mapped to custom claims via `claim_mapping`.
+pub(crate) const OIDC_CLAIM_PROPERTIES: [&str; 4] =
+  ["provider_user_id", "email", "email_verified", "avatar"];
+
+const DISCOVERY_PATH: &str = ".well-known/openid-configuration";
+
+/// Generic OpenID Connect provider, e.g. for Keycloak, Entra ID or Authentik.
 pub struct OidcProvider {
   name: String,
   display_name: String,
   client_id: String,
   client_secret: String,
 
-  auth_url: String,
-  token_url: String,
-  user_api_url: String,
+  // Explicitly configured endpoints take precedence over discovered ones.
+  auth_url: Option<String>,
+  token_url: Option<String>,
+  user_api_url: Option<String>,
+
+  discovery_url: Option<String>,
+  discovered: OnceCell<DiscoveryDocument>,
+
+  claim_mapping: HashMap<String, String>,
 }
 
 impl OidcProvider {
@@ -41,38 +55,122 @@ impl OidcProvider {
           client_id: config.client_id.clone().expect("startup"),
           client_secret: config.client_secret.clone().expect("startup"),
 
-          // NOTE: the following unwraps/expects are checked for by config validation.
-          auth_url: config
-            .auth_url
-            .as_deref()
-            .expect("Auth url missing")
-            .to_string(),
-          token_url: config
-            .token_url
-            .as_deref()
-            .expect("Token url missing")
-            .to_string(),
-          user_api_url: config
-            .user_api_url
-            .as_deref()
-            .expect("User api url missing")
-            .to_string(),
+          // NOTE: Config validation ensures that either the discovery url or all endpoints are set.
+          auth_url: config.auth_url.clone(),
+          token_url: config.token_url.clone(),
+          user_api_url: config.user_api_url.clone(),
+
+          discovery_url: config.discovery_url.clone(),
+          discovered: OnceCell::new(),
+
+          claim_mapping: config.claim_mapping.clone(),
         }))
       }),
     }
   }
+
+  /// Returns the discovery document, which is fetched once and cached for the provider's lifetime,
+  /// i.e. until the config changes.
+  async fn discover(&self) -> Result<Option<&DiscoveryDocument>, AuthError> {
+    let Some(ref discovery_url) = self.discovery_url else {
+      return Ok(None);
+    };
+
+    return Ok(Some(
+      self
+        .discovered
+        .get_or_try_init(|| fetch_discovery_document(discovery_url))
+        .await?,
+    ));
+  }
+
+  async fn user_api_url(&self) -> Result<String, AuthError> {
+    if let Some(ref url) = self.user_api_url {
+      return Ok(url.clone());
+    }
+
+    return self
+      .discover()
+      .await?
+      .and_then(|doc| doc.userinfo_endpoint.clone())
+      .ok_or_else(|| AuthError::Internal("Missing OIDC user info endpoint".into()));
+  }
+
+  fn claim<'a>(
+    &self,
+    claims: &'a serde_json::Value,
+    property: &str,
+    default: &str,
+  ) -> Option<&'a serde_json::Value> {
+    let name = self
+      .claim_mapping
+      .get(property)
+      .map_or(default, |s| s.as_str());
+    return claims.get(name).filter(|v| !v.is_null());
+  }
+
+  fn user_from_claims(&self, claims: &serde_json::Value) -> Result<OAuthUser, AuthError> {
+    let string_claim = |property: &str, default: &str| -> Option<String> {
+      return match self.claim(claims, property, default)? {
+        serde_json::Value::String(s) => Some(s.clone()),
+        // Some providers use numeric subject ids.
+        serde_json::Value::Number(n) => Some(n.to_string()),
+        _ => None,
+      };
+    };
+
+    let Some(provider_user_id) = string_claim("provider_user_id", "sub") else {
+      return Err(AuthError::FailedDependency("Missing OIDC subject".into()));
+    };
+    let Some(email) = string_claim("email", "email") else {
+      return Err(AuthError::FailedDependency("Missing OIDC email".into()));
+    };
+
+    // NOTE: Providers like Entra ID don't include "email_verified", in which case we trust the
+    // provider.
+    let verified = match self.claim(claims, "email_verified", "email_verified") {
+      Some(serde_json::Value::Bool(b)) => *b,
+      Some(serde_json::Value::String(s)) => s == "true",
+      _ => true,
+    };
+
+    return Ok(OAuthUser {
+      provider_user_id,
+      provider_id: OAuthProviderId::Oidc0,
+      email,
+      verified,
+      avatar: string_claim("avatar", "picture"),
+    });
+  }
+}
+
+// Reference: https://openid.net/specs/openid-connect-discovery-1_0.html#ProviderMetadata
+#[derive(Debug, Deserialize)]
+struct DiscoveryDocument {
+  authorization_endpoint: String,
+  token_endpoint: String,
+  userinfo_endpoint: Option<String>,
 }
 
-// Reference: https://openid.net/specs/openid-connect-core-1_0.html#StandardClaims
-#[derive(Default, Debug, Deserialize, Serialize)]
-pub struct OidcUser {
-  pub sub: String,
-  pub email: String,
-  pub email_verified: Option<bool>,
+async fn fetch_discovery_document(discovery_url: &str) -> Result<DiscoveryDocument, AuthError> {
+  let url = if discovery_url.ends_with(DISCOVERY_PATH) {
+    discovery_url.to_string()
+  } else {
+    // Issuer URL.
+    format!("{}/{DISCOVERY_PATH}", discovery_url.trim_end_matches('/'))
+  };
+
+  let response = reqwest::Client::new()
+    .get(&url)
+    .send()
+    .await
+    .and_then(|response| response.error_for_status())
+    .map_err(|err| AuthError::FailedDependency(err.into()))?;
 
-  // pub name: Option<String>,
-  // pub preferred_username : Option<String>,
-  pub picture: Option<String>,
+  return response
+    .json::<DiscoveryDocument>()
+    .await
+    .map_err(|err| AuthError::FailedDependency(err.into()));
 }
 
 #[async_trait]
@@ -87,10 +185,21 @@ impl OAuthProvider for OidcProvider {
     return &self.display_name;
   }
 
-  fn settings(&self) -> Result<OAuthClientSettings, AuthError> {
+  async fn settings(&self) -> Result<OAuthClientSettings, AuthError> {
+    let discovered = self.discover().await?;
+    let endpoint = |explicit: &Option<String>, discovered: Option<&String>| {
+      let Some(url) = explicit.as_ref().or(discovered) else {
+        return Err(AuthError::Internal("Missing OIDC endpoint".into()));
+      };
+      return Url::parse(url).map_err(|err| AuthError::Internal(err.into()));
+    };
+
     return Ok(OAuthClientSettings {
-      auth_url: Url::parse(&self.auth_url).map_err(|err| AuthError::Internal(err.into()))?,
-      token_url: Url::parse(&self.token_url).map_err(|err| AuthError::Internal(err.into()))?,
+      auth_url: endpoint(
+        &self.auth_url,
+        discovered.map(|doc| &doc.authorization_endpoint),
+      )?,
+      token_url: endpoint(&self.token_url, discovered.map(|doc| &doc.token_endpoint))?,
       client_id: self.client_id.clone(),
       client_secret: self.client_secret.clone(),
     });
@@ -102,23 +211,119 @@ impl OAuthProvider for OidcProvider {
 
   async fn get_user(&self, access_token: String) -> Result<OAuthUser, AuthError> {
     let response = reqwest::Client::new()
-      .get(&self.user_api_url)
+      .get(self.user_api_url().await?)
       .bearer_auth(access_token)
       .send()
       .await
       .map_err(|err| AuthError::FailedDependency(err.into()))?;
 
-    let user = response
-      .json::<OidcUser>()
+    // Reference: https://openid.net/specs/openid-connect-core-1_0.html#StandardClaims
+    let claims = response
+      .json::<serde_json::Value>()
       .await
       .map_err(|err| AuthError::FailedDependency(err.into()))?;
 
-    return Ok(OAuthUser {
-      provider_user_id: user.sub,
-      provider_id: OAuthProviderId::Oidc0,
-      email: user.email,
-      verified: user.email_verified.unwrap_or(true),
-      avatar: user.picture,
+    return self.user_from_claims(&claims);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use axum::routing::{Router, get};
+  use axum::{Json, extract::State};
+  use axum_test::{TestServer, TestServerConfig};
+
+  use super::*;
+
+  fn provider(config: OAuthProviderConfig) -> OidcProvider {
+    return OidcProvider {
+      name: "oidc0".to_string(),
+      display_name: "OIDC".to_string(),
+      client_id: "id".to_string(),
+      client_secret: "secret".to_string(),
+      auth_url: config.auth_url,
+      token_url: config.token_url,
+      user_api_url: config.user_api_url,
+      discovery_url: config.discovery_url,
+      discovered: OnceCell::new(),
+      claim_mapping: config.claim_mapping,
+    };
+  }
+
+  #[tokio::test]
+  async fn test_oidc_discovery() {
+    let app = Router::new()
+      .route(
+        &format!("/realms/acme/{DISCOVERY_PATH}"),
+        get(|State(base): State<String>| async move {
+          Json(serde_json::json!({
+            "issuer": format!("{base}/realms/acme"),
+            "authorization_endpoint": format!("{base}/auth"),
+            "token_endpoint": format!("{base}/token"),
+            "userinfo_endpoint": format!("{base}/userinfo"),
+          }))
+        }),
+      )
+      .with_state("http://idp".to_string());
+
+    let server = TestServer::new_with_config(
+      app,
+      TestServerConfig {
+        transport: Some(axum_test::Transport::HttpRandomPort),
+        ..Default::default()
+      },
+    )
+    .unwrap();
+
+    let issuer = server.server_url("/realms/acme").unwrap().to_string();
+    let provider = provider(OAuthProviderConfig {
+      discovery_url: Some(issuer),
+      token_url: Some("http://override/token".to_string()),
+      ..Default::default()
     });
+
+    let settings = provider.settings().await.unwrap();
+    assert_eq!(settings.auth_url.as_str(), "http://idp/auth");
+    assert_eq!(settings.token_url.as_str(), "http://override/token");
+    assert_eq!(
+      provider.user_api_url().await.unwrap(),
+      "http://idp/userinfo"
+    );
+  }
+
+  #[test]
+  fn test_oidc_claim_mapping() {
+    let claims = serde_json::json!({
+      "sub": "subject",
+      "email": "personal@bar.org",
+      "upn": "work@bar.org",
+      "email_verified": false,
+    });
+
+    let user = provider(OAuthProviderConfig::default())
+      .user_from_claims(&claims)
+      .unwrap();
+    assert_eq!(user.provider_user_id, "subject");
+    assert_eq!(user.email, "personal@bar.org");
+    assert!(!user.verified);
+    assert_eq!(user.avatar, None);
+
+    let user = provider(OAuthProviderConfig {
+      claim_mapping: HashMap::from([
+        ("email".to_string(), "upn".to_string()),
+        ("email_verified".to_string(), "missing".to_string()),
+      ]),
+      ..Default::default()
+    })
+    .user_from_claims(&claims)
+    .unwrap();
+    assert_eq!(user.email, "work@bar.org");
+    assert!(user.verified);
+
+    assert!(
+      provider(OAuthProviderConfig::default())
+        .user_from_claims(&serde_json::json!({"sub": "subject"}))
+        .is_err()
+    );
   }
 }
@@ -57,7 +57,7 @@ impl OAuthProvider for TestOAuthProvider {
     Self::DISPLAY_NAME
   }
 
-  fn settings(&self) -> Result<OAuthClientSettings, AuthError> {
+  async fn settings(&self) -> Result<OAuthClientSettings, AuthError> {
     return Ok(OAuthClientSettings {
       auth_url: Url::parse(&self.auth_url).unwrap(),
       token_url: Url::parse(&self.token_url).unwrap(),
@@ -68,18 +68,25 @@ pub fn validate_password_policy(
 #[derive(Clone)]
 struct FailedAttempt {
   tries: usize,
+  /// Attempts expire relative to the last failure.
+  last: std::time::Instant,
 }
 
 impl Default for FailedAttempt {
   fn default() -> Self {
-    return Self { tries: 1 };
+    return Self {
+      tries: 1,
+      last: std::time::Instant::now(),
+    };
   }
 }
 
+const ATTEMPTS_TTL: std::time::Duration = std::time::Duration::from_secs(5 * 60);
+
 lazy_static! {
   static ref ARGON2: Argon2<'static> = Argon2::default();
   static ref ATTEMPTS: Cache<String, FailedAttempt> = Cache::builder()
-    .time_to_live(std::time::Duration::from_secs(5 * 60))
+    .time_to_live(ATTEMPTS_TTL)
     .max_capacity(1024)
     .build();
 }
@@ -102,8 +109,12 @@ pub fn check_user_password(
     return Err(AuthError::Unauthorized);
   }
   let attempts = ATTEMPTS.get(&db_user.email);
-  if !is_demo && attempts.as_ref().map(|a| a.tries).unwrap_or(0) >= 3 {
-    return Err(AuthError::Unauthorized);
+  if let Some(ref attempts) = attempts {
+    if !is_demo && attempts.tries >= 3 {
+      return Err(AuthError::TooManyRequests(
+        ATTEMPTS_TTL.saturating_sub(attempts.last.elapsed()),
+      ));
+    }
   }
 
   let parsed_hash = PasswordHash::new(&db_user.password_hash)
@@ -114,12 +125,15 @@ pub fn check_user_password(
       ATTEMPTS.insert(
         db_user.email.to_string(),
         attempts
-          .map(|a| FailedAttempt { tries: a.tries + 1 })
+          .map(|a| FailedAttempt {
+            tries: a.tries + 1,
+            last: std::time::Instant::now(),
+          })
           .unwrap_or_default(),
       );
 
       return match err {
-        argon2::password_hash::Error::Password => AuthError::Unauthorized,
+        argon2::password_hash::Error::Password => AuthError::InvalidCredentials,
         err => AuthError::Internal(err.to_string().into()),
       };
     },
@@ -11,7 +11,7 @@ use crate::app_state::AppState;
 use crate::auth::AuthError;
 use crate::auth::jwt::TokenClaims;
 use crate::auth::user::DbUser;
-use crate::auth::util::new_cookie;
+use crate::auth::util::{new_cookie, user_roles};
 use crate::constants::{
   COOKIE_AUTH_TOKEN, COOKIE_REFRESH_TOKEN, HEADER_REFRESH_TOKEN, REFRESH_TOKEN_LENGTH,
   SESSION_TABLE, USER_TABLE,
@@ -172,7 +172,14 @@ pub(crate) async fn mint_new_tokens(
     ));
   }
 
-  let claims = TokenClaims::new(verified, user_id, user_email, expires_in);
+  let mut claims = TokenClaims::new_at(
+    state.clock().now(),
+    verified,
+    user_id,
+    user_email,
+    expires_in,
+  );
+  claims.roles = user_roles(state, &user_id).await?;
 
   // Unlike JWT auth tokens, refresh tokens are opaque.
   let refresh_token = generate_random_string(REFRESH_TOKEN_LENGTH);
@@ -238,10 +245,15 @@ pub(crate) async fn reauth_with_refresh_token(
     "unverified user, should have been caught by above query"
   );
 
-  return Ok(TokenClaims::new(
+  let user_id = db_user.uuid();
+  let mut claims = TokenClaims::new_at(
+    state.clock().now(),
     db_user.verified,
-    db_user.uuid(),
+    user_id,
     db_user.email,
     auth_token_ttl,
-  ));
+  );
+  claims.roles = user_roles(state, &user_id).await?;
+
+  return Ok(claims);
 }
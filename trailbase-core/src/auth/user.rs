@@ -1,14 +1,19 @@
 use axum::{
   extract::{FromRef, FromRequestParts, OptionalFromRequestParts},
-  http::request::Parts,
+  http::{HeaderMap, request::Parts},
 };
 use serde::{Deserialize, Serialize};
+use std::marker::PhantomData;
 use uuid::Uuid;
 
+use crate::app_state::AppState;
 use crate::auth::AuthError;
+use crate::auth::api_key::{ApiKeyScope, user_from_api_key};
 use crate::auth::jwt::TokenClaims;
 use crate::auth::tokens::extract_tokens_from_request_parts;
-use crate::{app_state::AppState, util::b64_to_uuid};
+use crate::auth::util::{is_admin, user_roles};
+use crate::constants::HEADER_TEST_USER;
+use crate::util::{b64_to_uuid, uuid_to_b64};
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub(crate) struct DbUser {
@@ -94,6 +99,12 @@ pub struct User {
 
   /// The "expected" CSRF token as included in the auth token claims [User] was constructed from.
   pub csrf_token: String,
+
+  /// Built-in roles as included in the auth token claims [User] was constructed from.
+  pub roles: Vec<String>,
+
+  /// Scopes restricting the user's access when authenticated using an API key.
+  pub api_key_scopes: Option<Vec<ApiKeyScope>>,
 }
 
 impl PartialEq for User {
@@ -115,9 +126,17 @@ impl User {
       email: claims.email,
       uuid,
       csrf_token: claims.csrf_token,
+      roles: claims.roles,
+      api_key_scopes: None,
     });
   }
 
+  /// Whether the user had the given built-in role when their auth token was minted. Roles may have
+  /// changed since, use [Role] for up-to-date checks.
+  pub fn has_role(&self, role: &str) -> bool {
+    return self.roles.iter().any(|r| r == role);
+  }
+
   #[cfg(test)]
   pub(crate) fn from_auth_token(state: &AppState, auth_token: &str) -> Option<Self> {
     Some(Self::from_token_claims(state.jwt().decode(auth_token).unwrap()).unwrap())
@@ -130,10 +149,55 @@ impl User {
       email: email.to_string(),
       uuid: user_id,
       csrf_token: crate::rand::generate_random_string(20),
+      roles: vec![],
+      api_key_scopes: None,
     };
   }
 }
 
+/// Returns the user and roles of an `X-Test-User: <id>[;<role>,...]` header if present and mock
+/// auth is enabled. The id may either be a UUID or url-safe base64 encoded.
+fn mock_user_from_headers(
+  state: &AppState,
+  headers: &HeaderMap,
+) -> Option<Result<(User, Vec<String>), AuthError>> {
+  if !state.mock_auth() {
+    return None;
+  }
+  let value = headers.get(HEADER_TEST_USER)?;
+
+  let parse = || -> Result<(User, Vec<String>), AuthError> {
+    let value = value
+      .to_str()
+      .map_err(|_err| AuthError::BadRequest("invalid test user header"))?;
+    let (id, roles) = value.split_once(';').unwrap_or((value, ""));
+
+    let id = id.trim();
+    let uuid = Uuid::parse_str(id)
+      .or_else(|_err| b64_to_uuid(id))
+      .map_err(|_err| AuthError::BadRequest("invalid test user id"))?;
+
+    let roles: Vec<String> = roles
+      .split(',')
+      .map(str::trim)
+      .filter(|role| !role.is_empty())
+      .map(str::to_string)
+      .collect();
+    let user = User {
+      id: uuid_to_b64(&uuid),
+      email: String::new(),
+      uuid,
+      csrf_token: String::new(),
+      roles: roles.clone(),
+      api_key_scopes: None,
+    };
+
+    return Ok((user, roles));
+  };
+
+  return Some(parse());
+}
+
 impl<S> FromRequestParts<S> for User
 where
   AppState: FromRef<S>,
@@ -143,6 +207,16 @@ where
 
   async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
     let state = AppState::from_ref(state);
+    if let Some(mock) = mock_user_from_headers(&state, &parts.headers) {
+      return Ok(mock?.0);
+    }
+
+    if let Some(user) = user_from_api_key(&state, parts).await? {
+      tracing::Span::current().record("user_id", user.uuid.to_u128_le());
+
+      return Ok(user);
+    }
+
     let tokens = extract_tokens_from_request_parts(&state, parts).await?;
 
     let user = User::from_token_claims(tokens.auth_token_claims)?;
@@ -165,6 +239,15 @@ where
     state: &S,
   ) -> Result<Option<Self>, Self::Rejection> {
     let state = AppState::from_ref(state);
+    if let Some(mock) = mock_user_from_headers(&state, &parts.headers) {
+      return Ok(Some(mock?.0));
+    }
+
+    if let Some(user) = user_from_api_key(&state, parts).await? {
+      tracing::Span::current().record("user_id", user.uuid.to_u128_le());
+
+      return Ok(Some(user));
+    }
 
     if let Ok(tokens) = extract_tokens_from_request_parts(&state, parts).await {
       let user = User::from_token_claims(tokens.auth_token_claims)?;
@@ -177,6 +260,96 @@ where
   }
 }
 
+/// Extracts the current [User] if authenticated without rejecting anonymous requests, i.e. the
+/// same as `Option<User>`.
+#[derive(Debug, Clone)]
+pub struct OptionalUser(pub Option<User>);
+
+impl<S> FromRequestParts<S> for OptionalUser
+where
+  AppState: FromRef<S>,
+  S: Send + Sync,
+{
+  type Rejection = AuthError;
+
+  async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+    return Ok(Self(
+      <User as OptionalFromRequestParts<S>>::from_request_parts(parts, state).await?,
+    ));
+  }
+}
+
+/// A role users can be required to have using [RequireRole]. By default, roles are backed by the
+/// built-in `_role` and `_user_role` tables. Apps can also define their own roles, e.g. backed by
+/// a custom table, by overriding [Role::has_role].
+pub trait Role {
+  /// Name of the built-in role. Also grants the role in mock auth mode, e.g. "admin" for
+  /// `X-Test-User: <id>;admin`.
+  const NAME: &'static str;
+
+  fn has_role(state: &AppState, user: &User) -> impl Future<Output = bool> + Send {
+    let state = state.clone();
+    let user_id = user.uuid;
+    return async move {
+      return match user_roles(&state, &user_id).await {
+        Ok(roles) => roles.iter().any(|role| role == Self::NAME),
+        Err(err) => {
+          log::warn!("Failed to look up roles: {err}");
+          false
+        }
+      };
+    };
+  }
+}
+
+/// Users with admin privileges.
+pub struct AdminRole;
+
+impl Role for AdminRole {
+  const NAME: &'static str = "admin";
+
+  async fn has_role(state: &AppState, user: &User) -> bool {
+    return is_admin(state, user).await;
+  }
+}
+
+/// Extracts the current [User] like the plain [User] extractor but additionally rejects users
+/// lacking role `R` with a 403, e.g. `RequireRole<AdminRole>`.
+pub struct RequireRole<R: Role>(pub User, PhantomData<fn() -> R>);
+
+impl<R: Role> RequireRole<R> {
+  pub fn into_inner(self) -> User {
+    return self.0;
+  }
+}
+
+impl<S, R> FromRequestParts<S> for RequireRole<R>
+where
+  AppState: FromRef<S>,
+  S: Send + Sync,
+  R: Role,
+{
+  type Rejection = AuthError;
+
+  async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+    if let Some(mock) = mock_user_from_headers(&AppState::from_ref(state), &parts.headers) {
+      let (user, roles) = mock?;
+      if !roles.iter().any(|role| role == R::NAME) {
+        return Err(AuthError::Forbidden);
+      }
+      return Ok(Self(user, PhantomData));
+    }
+
+    let user = <User as FromRequestParts<S>>::from_request_parts(parts, state).await?;
+
+    if !R::has_role(&AppState::from_ref(state), &user).await {
+      return Err(AuthError::Forbidden);
+    }
+
+    return Ok(Self(user, PhantomData));
+  }
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
@@ -184,7 +357,7 @@ mod tests {
   use axum::http::{Request, header};
 
   use crate::admin::user::create_user_for_test;
-  use crate::app_state::test_state;
+  use crate::app_state::{TestStateOptions, test_state};
   use crate::auth::api::login::login_with_password;
   use crate::constants::COOKIE_REFRESH_TOKEN;
 
@@ -240,4 +413,120 @@ mod tests {
       .await
       .unwrap();
   }
+
+  #[tokio::test]
+  async fn test_extractors() {
+    let state = test_state(None).await.unwrap();
+
+    let email = "name@bar.com".to_string();
+    let password = "secret123".to_string();
+
+    let user_id = create_user_for_test(&state, &email, &password)
+      .await
+      .unwrap();
+    let tokens = login_with_password(&state, &email, &password)
+      .await
+      .unwrap();
+
+    let parts = |auth_token: Option<&str>| {
+      let mut builder = Request::builder();
+      if let Some(auth_token) = auth_token {
+        builder = builder.header(header::AUTHORIZATION, format!("Bearer {auth_token}"));
+      }
+      let (mut parts, _body) = builder.body(Body::empty()).unwrap().into_parts();
+      parts.extensions.insert(tower_cookies::Cookies::default());
+      return parts;
+    };
+
+    // Anonymous.
+    let OptionalUser(user) = OptionalUser::from_request_parts(&mut parts(None), &state)
+      .await
+      .unwrap();
+    assert!(user.is_none());
+    assert!(matches!(
+      RequireRole::<AdminRole>::from_request_parts(&mut parts(None), &state).await,
+      Err(AuthError::Unauthorized)
+    ));
+
+    // Authenticated but not an admin.
+    let OptionalUser(user) =
+      OptionalUser::from_request_parts(&mut parts(Some(&tokens.auth_token)), &state)
+        .await
+        .unwrap();
+    assert_eq!(user.unwrap().uuid, user_id);
+    assert!(matches!(
+      RequireRole::<AdminRole>::from_request_parts(&mut parts(Some(&tokens.auth_token)), &state)
+        .await,
+      Err(AuthError::Forbidden)
+    ));
+
+    state
+      .user_conn()
+      .execute(
+        "UPDATE _user SET admin = TRUE WHERE id = $1",
+        trailbase_sqlite::params!(user_id.into_bytes().to_vec()),
+      )
+      .await
+      .unwrap();
+
+    let admin =
+      RequireRole::<AdminRole>::from_request_parts(&mut parts(Some(&tokens.auth_token)), &state)
+        .await
+        .unwrap();
+    assert_eq!(admin.into_inner().uuid, user_id);
+  }
+
+  #[tokio::test]
+  async fn test_mock_auth() {
+    let user_id = Uuid::now_v7();
+    let parts = |test_user: &str| {
+      let (parts, _body) = Request::builder()
+        .header(HEADER_TEST_USER, test_user)
+        .body(Body::empty())
+        .unwrap()
+        .into_parts();
+      return parts;
+    };
+
+    let state = test_state(Some(TestStateOptions {
+      mock_auth: true,
+      ..Default::default()
+    }))
+    .await
+    .unwrap();
+
+    for id in [user_id.to_string(), uuid_to_b64(&user_id)] {
+      let user = User::from_request_parts(&mut parts(&id), &state)
+        .await
+        .unwrap();
+      assert_eq!(user.uuid, user_id);
+      assert_eq!(user.id, uuid_to_b64(&user_id));
+    }
+
+    assert!(matches!(
+      RequireRole::<AdminRole>::from_request_parts(&mut parts(&user_id.to_string()), &state).await,
+      Err(AuthError::Forbidden)
+    ));
+    let admin = RequireRole::<AdminRole>::from_request_parts(
+      &mut parts(&format!("{user_id}; editor, admin")),
+      &state,
+    )
+    .await
+    .unwrap();
+    assert_eq!(admin.into_inner().uuid, user_id);
+
+    assert!(matches!(
+      User::from_request_parts(&mut parts("invalid"), &state).await,
+      Err(AuthError::BadRequest(_))
+    ));
+
+    // Ignored unless explicitly enabled.
+    let state = test_state(None).await.unwrap();
+    let mut parts = parts(&format!("{user_id};admin"));
+    parts.extensions.insert(tower_cookies::Cookies::default());
+    let OptionalUser(user) = OptionalUser::from_request_parts(&mut parts, &state)
+      .await
+      .unwrap();
+    assert!(user.is_none());
+  }
 }
@@ -13,7 +13,8 @@ use crate::AppState;
 use crate::auth::AuthError;
 use crate::auth::user::{DbUser, User};
 use crate::constants::{
-  COOKIE_AUTH_TOKEN, COOKIE_OAUTH_STATE, COOKIE_REFRESH_TOKEN, SESSION_TABLE, USER_TABLE,
+  COOKIE_AUTH_TOKEN, COOKIE_OAUTH_STATE, COOKIE_REFRESH_TOKEN, SESSION_TABLE, USER_ROLE_TABLE,
+  USER_TABLE,
 };
 
 /// Strips plus-addressing, e.g. foo+spam@test.org becomes foo@test.org.
@@ -211,6 +212,24 @@ pub(crate) async fn is_admin(state: &AppState, user: &User) -> bool {
   return row;
 }
 
+/// Names of the built-in roles granted to the given user, see `_user_role`.
+pub(crate) async fn user_roles(
+  state: &AppState,
+  user_id: &uuid::Uuid,
+) -> Result<Vec<String>, trailbase_sqlite::Error> {
+  lazy_static! {
+    static ref QUERY: String =
+      format!(r#"SELECT role FROM "{USER_ROLE_TABLE}" WHERE user = $1 ORDER BY role"#);
+  };
+
+  let roles: Vec<(String,)> = state
+    .user_conn()
+    .read_query_values(&*QUERY, params!(user_id.as_bytes().to_vec()))
+    .await?;
+
+  return Ok(roles.into_iter().map(|(role,)| role).collect());
+}
+
 pub(crate) async fn delete_all_sessions_for_user(
   state: &AppState,
   user_id: uuid::Uuid,
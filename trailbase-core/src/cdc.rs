@@ -0,0 +1,482 @@
+use log::*;
+use rskafka::client::partition::{Compression, UnknownTopicHandling};
+use rskafka::record::Record;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+use thiserror::Error;
+use trailbase_schema::sqlite::ColumnDataType;
+use trailbase_sqlite::{Connection, params};
+
+use crate::app_state::AppState;
+use crate::config::proto::{ChangeDataCaptureConfig, ChangeDataCaptureSink};
+use crate::constants::{CDC_CHANGE_TABLE, CDC_OFFSET_TABLE};
+use crate::schema_metadata::SchemaMetadataCache;
+use crate::util::{quote_identifier, quote_literal};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+const BATCH_SIZE: i64 = 256;
+const TRIGGER_PREFIX: &str = "_cdc_";
+const DEFAULT_TOPIC_PREFIX: &str = "trailbase";
+
+#[derive(Debug, Error)]
+pub enum CdcError {
+  #[error("SQLite error: {0}")]
+  Sqlite(#[from] trailbase_sqlite::Error),
+  #[error("Json error: {0}")]
+  Json(#[from] serde_json::Error),
+  #[error("Sink error: {0}")]
+  Sink(Box<dyn std::error::Error + Send + Sync>),
+}
+
+/// A captured row change, see the `_cdc_change` table.
+#[derive(Clone, Debug, Deserialize)]
+struct Change {
+  seq: i64,
+  table_name: String,
+  op: String,
+  row_id: i64,
+  record: String,
+  created: i64,
+}
+
+impl Change {
+  fn payload(&self) -> Result<Vec<u8>, CdcError> {
+    return Ok(serde_json::to_vec(&serde_json::json!({
+      "seq": self.seq,
+      "table": self.table_name,
+      "op": self.op,
+      "row_id": self.row_id,
+      "record": serde_json::from_str::<serde_json::Value>(&self.record)?,
+      "timestamp": self.created,
+    }))?);
+  }
+}
+
+/// Publishes captured changes until the process exits.
+///
+/// Changes are captured by triggers writing to the `_cdc_change` table within the writing
+/// transaction, i.e. they survive restarts and broker outages, unlike SQLite's update hooks.
+/// Changes are only deleted after the broker acknowledged them and the high-water mark was
+/// advanced, thus delivery is at-least-once. Consumers can de-duplicate using `seq`.
+pub(crate) async fn run_change_data_capture(state: AppState) {
+  let mut sink: Option<(ChangeDataCaptureConfig, Sink)> = None;
+  let mut interval = tokio::time::interval(POLL_INTERVAL);
+
+  loop {
+    interval.tick().await;
+
+    let config = state
+      .get_config()
+      .server
+      .change_data_capture
+      .unwrap_or_default();
+
+    // NOTE: Triggers are re-synced continuously to pick up config as well as schema changes, e.g.
+    // added columns or re-created tables.
+    if let Err(err) = sync_triggers(state.conn(), state.schema_metadata(), &config.tables).await {
+      warn!("Failed to sync change data capture triggers: {err}");
+      continue;
+    }
+    if config.tables.is_empty() {
+      sink = None;
+      continue;
+    }
+
+    let current = match sink.take() {
+      Some((c, s)) if c == config => s,
+      _ => match Sink::connect(&config).await {
+        Ok(s) => s,
+        Err(err) => {
+          warn!("Failed to connect change data capture sink: {err}");
+          continue;
+        }
+      },
+    };
+
+    let key = config.sink().as_str_name();
+    loop {
+      match publish_pending(state.conn(), &current, key).await {
+        Ok(n) if n as i64 == BATCH_SIZE => continue,
+        Ok(_) => {
+          sink = Some((config, current));
+        }
+        Err(err) => {
+          // Reconnect on the next tick.
+          warn!("Failed to publish changes: {err}");
+        }
+      }
+      break;
+    }
+  }
+}
+
+async fn publish_pending(conn: &Connection, sink: &Sink, key: &str) -> Result<usize, CdcError> {
+  let changes = read_pending_changes(conn, key).await?;
+  let Some(last) = changes.last().map(|c| c.seq) else {
+    return Ok(0);
+  };
+
+  sink.publish(&changes).await?;
+  commit_offset(conn, key, last).await?;
+
+  return Ok(changes.len());
+}
+
+async fn read_pending_changes(conn: &Connection, key: &str) -> Result<Vec<Change>, CdcError> {
+  return Ok(
+    conn
+      .read_query_as::<Change>(
+        format!(
+          r#"
+            SELECT seq, table_name, op, row_id, record, created FROM {CDC_CHANGE_TABLE}
+            WHERE seq > COALESCE((SELECT seq FROM {CDC_OFFSET_TABLE} WHERE sink = $1), 0)
+            ORDER BY seq LIMIT $2
+          "#
+        ),
+        params!(key.to_string(), BATCH_SIZE),
+      )
+      .await?,
+  );
+}
+
+/// Advances the high-water mark and drops published changes.
+async fn commit_offset(conn: &Connection, key: &str, seq: i64) -> Result<(), CdcError> {
+  let key = key.to_string();
+  conn
+    .call(move |conn| {
+      let tx = conn.transaction()?;
+      tx.execute(
+        &format!(
+          r#"
+            INSERT INTO {CDC_OFFSET_TABLE} (sink, seq) VALUES ($1, $2)
+            ON CONFLICT (sink) DO UPDATE SET seq = excluded.seq, updated = UNIXEPOCH()
+          "#
+        ),
+        rusqlite::params![key, seq],
+      )?;
+      tx.execute(
+        &format!("DELETE FROM {CDC_CHANGE_TABLE} WHERE seq <= $1"),
+        rusqlite::params![seq],
+      )?;
+      tx.commit()?;
+
+      return Ok(());
+    })
+    .await?;
+
+  return Ok(());
+}
+
+/// Builds the "_cdc_*" capture triggers for the given tables keyed by name.
+fn build_triggers(
+  schema_metadata: &SchemaMetadataCache,
+  tables: &[String],
+) -> BTreeMap<String, String> {
+  let mut triggers = BTreeMap::<String, String>::new();
+  for table_name in tables {
+    let Some(table) = schema_metadata.get_table(table_name) else {
+      debug!("Missing table for change data capture: {table_name}");
+      continue;
+    };
+
+    let record = |row: &str| -> String {
+      let fields: Vec<String> = table
+        .schema
+        .columns
+        .iter()
+        .map(|column| {
+          let key = quote_literal(&column.name);
+          let name = quote_identifier(&column.name);
+          // JSON cannot hold blobs.
+          return match column.data_type {
+            ColumnDataType::Blob => format!("{key}, hex({row}.{name})"),
+            _ => format!("{key}, {row}.{name}"),
+          };
+        })
+        .collect();
+      return format!("json_object({})", fields.join(", "));
+    };
+
+    for (op, event, row) in [
+      ("insert", "INSERT", "NEW"),
+      ("update", "UPDATE", "NEW"),
+      ("delete", "DELETE", "OLD"),
+    ] {
+      let name = format!("{TRIGGER_PREFIX}{table_name}_{op}");
+      let sql = format!(
+        "CREATE TRIGGER {} AFTER {event} ON {} BEGIN INSERT INTO {CDC_CHANGE_TABLE} (table_name, op, row_id, record) VALUES ({}, '{op}', {row}._rowid_, {}); END",
+        quote_identifier(&name),
+        quote_identifier(table_name),
+        quote_literal(table_name),
+        record(row)
+      );
+      triggers.insert(name, sql);
+    }
+  }
+  return triggers;
+}
+
+/// Installs, updates and drops capture triggers, such that exactly the given tables are captured.
+async fn sync_triggers(
+  conn: &Connection,
+  schema_metadata: &SchemaMetadataCache,
+  tables: &[String],
+) -> Result<(), CdcError> {
+  #[derive(Deserialize)]
+  struct Trigger {
+    name: String,
+    sql: String,
+  }
+
+  let desired = build_triggers(schema_metadata, tables);
+  let existing: HashMap<String, String> = conn
+    .read_query_as::<Trigger>(
+      format!(
+        r#"SELECT name, sql FROM sqlite_master WHERE type = 'trigger' AND name GLOB '{TRIGGER_PREFIX}*'"#
+      ),
+      (),
+    )
+    .await?
+    .into_iter()
+    .map(|t| (t.name, t.sql))
+    .collect();
+
+  let stale: Vec<String> = existing
+    .iter()
+    .filter(|(name, sql)| desired.get(*name) != Some(*sql))
+    .map(|(name, _)| name.clone())
+    .collect();
+  let missing: Vec<String> = desired
+    .into_iter()
+    .filter(|(name, sql)| existing.get(name) != Some(sql))
+    .map(|(_, sql)| sql)
+    .collect();
+  if stale.is_empty() && missing.is_empty() {
+    return Ok(());
+  }
+
+  debug!("Syncing change data capture triggers");
+  conn
+    .call(move |conn| {
+      let tx = conn.transaction()?;
+      for name in stale {
+        tx.execute(
+          &format!("DROP TRIGGER IF EXISTS {}", quote_identifier(&name)),
+          (),
+        )?;
+      }
+      for sql in missing {
+        tx.execute(&sql, ())?;
+      }
+      tx.commit()?;
+
+      return Ok(());
+    })
+    .await?;
+
+  return Ok(());
+}
+
+enum Sink {
+  Nats {
+    jetstream: async_nats::jetstream::Context,
+    prefix: String,
+  },
+  Kafka {
+    client: rskafka::client::Client,
+    prefix: String,
+  },
+}
+
+impl Sink {
+  async fn connect(config: &ChangeDataCaptureConfig) -> Result<Self, CdcError> {
+    let url = config.url.clone().unwrap_or_default();
+    let prefix = config
+      .topic_prefix
+      .clone()
+      .unwrap_or_else(|| DEFAULT_TOPIC_PREFIX.to_string());
+
+    return match config.sink() {
+      ChangeDataCaptureSink::Nats => {
+        let client = async_nats::connect(url)
+          .await
+          .map_err(|err| CdcError::Sink(err.into()))?;
+        Ok(Sink::Nats {
+          jetstream: async_nats::jetstream::new(client),
+          prefix,
+        })
+      }
+      ChangeDataCaptureSink::Kafka => {
+        let client = rskafka::client::ClientBuilder::new(vec![url])
+          .build()
+          .await
+          .map_err(|err| CdcError::Sink(err.into()))?;
+        Ok(Sink::Kafka { client, prefix })
+      }
+      ChangeDataCaptureSink::Undefined => Err(CdcError::Sink("Undefined sink".into())),
+    };
+  }
+
+  /// Publishes changes to "<prefix>.<table>" subjects or topics preserving their order per table
+  /// and returns once all of them have been acknowledged.
+  async fn publish(&self, changes: &[Change]) -> Result<(), CdcError> {
+    match self {
+      Sink::Nats { jetstream, prefix } => {
+        let mut acks = Vec::with_capacity(changes.len());
+        for change in changes {
+          // Lets JetStream de-duplicate re-deliveries within its duplicate window.
+          let mut headers = async_nats::HeaderMap::new();
+          headers.insert(
+            async_nats::header::NATS_MESSAGE_ID,
+            change.seq.to_string().as_str(),
+          );
+
+          acks.push(
+            jetstream
+              .publish_with_headers(
+                format!("{prefix}.{}", change.table_name),
+                headers,
+                change.payload()?.into(),
+              )
+              .await
+              .map_err(|err| CdcError::Sink(err.into()))?,
+          );
+        }
+
+        for ack in acks {
+          ack.await.map_err(|err| CdcError::Sink(err.into()))?;
+        }
+      }
+      Sink::Kafka { client, prefix } => {
+        let mut topics = BTreeMap::<String, Vec<Record>>::new();
+        for change in changes {
+          topics
+            .entry(format!("{prefix}.{}", change.table_name))
+            .or_default()
+            .push(Record {
+              key: Some(change.row_id.to_string().into_bytes()),
+              value: Some(change.payload()?),
+              headers: BTreeMap::new(),
+              timestamp: chrono::DateTime::from_timestamp(change.created, 0).unwrap_or_default(),
+            });
+        }
+
+        // NOTE: Changes are published to a single partition per topic to preserve their order.
+        for (topic, records) in topics {
+          let partition = client
+            .partition_client(topic, 0, UnknownTopicHandling::Retry)
+            .await
+            .map_err(|err| CdcError::Sink(err.into()))?;
+          partition
+            .produce(records, Compression::NoCompression)
+            .await
+            .map_err(|err| CdcError::Sink(err.into()))?;
+        }
+      }
+    }
+
+    return Ok(());
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::app_state::test_state;
+
+  #[tokio::test]
+  async fn test_change_capture() {
+    let state = test_state(None).await.unwrap();
+    let conn = state.conn();
+    conn
+      .execute_batch("CREATE TABLE item (id INTEGER PRIMARY KEY, name TEXT, data BLOB) STRICT;")
+      .await
+      .unwrap();
+    state.schema_metadata().invalidate_all().await.unwrap();
+
+    let tables = vec!["item".to_string()];
+    sync_triggers(conn, state.schema_metadata(), &tables)
+      .await
+      .unwrap();
+    // Idempotent.
+    sync_triggers(conn, state.schema_metadata(), &tables)
+      .await
+      .unwrap();
+
+    conn
+      .execute_batch(
+        r#"
+          INSERT INTO item (id, name, data) VALUES (1, 'first', X'0A0B');
+          UPDATE item SET name = 'second' WHERE id = 1;
+          DELETE FROM item WHERE id = 1;
+        "#,
+      )
+      .await
+      .unwrap();
+
+    let changes = read_pending_changes(conn, "test").await.unwrap();
+    assert_eq!(
+      changes.iter().map(|c| c.op.as_str()).collect::<Vec<_>>(),
+      ["insert", "update", "delete"]
+    );
+    assert!(
+      changes
+        .iter()
+        .all(|c| c.table_name == "item" && c.row_id == 1)
+    );
+    assert_eq!(
+      serde_json::from_str::<serde_json::Value>(&changes[2].record).unwrap(),
+      serde_json::json!({"id": 1, "name": "second", "data": "0A0B"})
+    );
+
+    // Publishing resumes after the high-water mark.
+    commit_offset(conn, "test", changes[1].seq).await.unwrap();
+    let pending = read_pending_changes(conn, "test").await.unwrap();
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].seq, changes[2].seq);
+
+    // Removing the table from the config drops its triggers.
+    sync_triggers(conn, state.schema_metadata(), &[])
+      .await
+      .unwrap();
+    conn
+      .execute("INSERT INTO item (id, name) VALUES (2, 'third')", ())
+      .await
+      .unwrap();
+    assert_eq!(read_pending_changes(conn, "test").await.unwrap().len(), 1);
+  }
+
+  #[tokio::test]
+  async fn test_change_capture_quoted_names() {
+    let state = test_state(None).await.unwrap();
+    let conn = state.conn();
+    conn
+      .execute_batch(r#"CREATE TABLE "it's" (id INTEGER PRIMARY KEY, "a'b" TEXT) STRICT;"#)
+      .await
+      .unwrap();
+    state.schema_metadata().invalidate_all().await.unwrap();
+
+    let tables = vec!["it's".to_string()];
+    sync_triggers(conn, state.schema_metadata(), &tables)
+      .await
+      .unwrap();
+
+    conn
+      .execute(r#"INSERT INTO "it's" (id, "a'b") VALUES (1, 'first')"#, ())
+      .await
+      .unwrap();
+
+    let changes = read_pending_changes(conn, "test").await.unwrap();
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].table_name, "it's");
+    assert_eq!(
+      serde_json::from_str::<serde_json::Value>(&changes[0].record).unwrap(),
+      serde_json::json!({"id": 1, "a'b": "first"})
+    );
+
+    sync_triggers(conn, state.schema_metadata(), &[])
+      .await
+      .unwrap();
+  }
+}
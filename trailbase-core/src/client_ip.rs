@@ -0,0 +1,83 @@
+use axum::extract::{Request, State};
+use axum::http::{Extensions, HeaderMap};
+use axum::middleware::Next;
+use axum::response::Response;
+use axum_client_ip::{SecureClientIp, SecureClientIpSource};
+use std::net::IpAddr;
+
+use crate::app_state::AppState;
+use crate::config::ConfigError;
+
+/// Makes the configured `server.client_ip_source` available to [secure_client_ip].
+pub(crate) async fn client_ip_source_middleware(
+  State(state): State<AppState>,
+  mut request: Request,
+  next: Next,
+) -> Response {
+  let source = state
+    .access_config(|c| c.server.client_ip_source.clone())
+    .and_then(|source| source.parse::<SecureClientIpSource>().ok());
+  if let Some(source) = source {
+    request.extensions_mut().insert(source);
+  }
+
+  return next.run(request).await;
+}
+
+/// Client IP for security-relevant uses, i.e. access rules and rate limiting.
+///
+/// Unlike `InsecureClientIp`, which trusts any forwarding header and is thus only good for
+/// logging, this is the connection's peer address unless `server.client_ip_source` names a header
+/// set by a trusted reverse proxy.
+pub(crate) fn secure_client_ip(headers: &HeaderMap, extensions: &Extensions) -> Option<IpAddr> {
+  let source = extensions
+    .get::<SecureClientIpSource>()
+    .cloned()
+    .unwrap_or(SecureClientIpSource::ConnectInfo);
+
+  return SecureClientIp::from(&source, headers, extensions)
+    .ok()
+    .map(|ip| ip.0);
+}
+
+pub(crate) fn validate_client_ip_source(source: &str) -> Result<(), ConfigError> {
+  if source.parse::<SecureClientIpSource>().is_err() {
+    return Err(ConfigError::Invalid(format!(
+      "Invalid client IP source: {source}"
+    )));
+  }
+  return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+  use axum::extract::ConnectInfo;
+  use std::net::SocketAddr;
+
+  use super::*;
+
+  #[test]
+  fn test_secure_client_ip() {
+    let mut headers = HeaderMap::new();
+    headers.insert("x-forwarded-for", "1.2.3.4".parse().unwrap());
+    headers.insert("x-real-ip", "5.6.7.8".parse().unwrap());
+
+    let mut extensions = Extensions::new();
+    assert_eq!(secure_client_ip(&headers, &extensions), None);
+
+    // Forwarding headers are ignored by default.
+    let peer: SocketAddr = "10.0.0.1:1234".parse().unwrap();
+    extensions.insert(ConnectInfo(peer));
+    assert_eq!(secure_client_ip(&headers, &extensions), Some(peer.ip()));
+
+    // Unless configured explicitly.
+    extensions.insert(SecureClientIpSource::XRealIp);
+    assert_eq!(
+      secure_client_ip(&headers, &extensions),
+      Some("5.6.7.8".parse().unwrap())
+    );
+
+    assert!(validate_client_ip_source("XRealIp").is_ok());
+    assert!(validate_client_ip_source("X-Forwarded-For").is_err());
+  }
+}
@@ -0,0 +1,195 @@
+use chrono::{DateTime, Duration, Utc};
+use parking_lot::Mutex;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Source of the current time and new UUIDv7s, e.g. record ids and token timestamps.
+///
+/// Defaults to the system clock. Tests can instead use a [`Clock::frozen`] clock, which only
+/// moves when advanced explicitly and produces deterministic, monotonically increasing UUIDv7s,
+/// e.g. to make cursor pagination stable. Clones share the same underlying clock.
+#[derive(Clone, Debug, Default)]
+pub struct Clock {
+  frozen: Option<Arc<Mutex<FrozenState>>>,
+}
+
+#[derive(Debug)]
+struct FrozenState {
+  now: DateTime<Utc>,
+  counter: u64,
+}
+
+impl Clock {
+  pub fn system() -> Self {
+    return Self::default();
+  }
+
+  /// A clock stopped at the given time.
+  pub fn frozen(at: DateTime<Utc>) -> Self {
+    return Self {
+      frozen: Some(Arc::new(Mutex::new(FrozenState {
+        now: at,
+        counter: 0,
+      }))),
+    };
+  }
+
+  pub fn is_frozen(&self) -> bool {
+    return self.frozen.is_some();
+  }
+
+  pub fn now(&self) -> DateTime<Utc> {
+    return match self.frozen {
+      Some(ref state) => state.lock().now,
+      None => Utc::now(),
+    };
+  }
+
+  /// Moves a frozen clock forward. No-op for the system clock.
+  pub fn advance(&self, by: Duration) {
+    if let Some(ref state) = self.frozen {
+      state.lock().now += by;
+    }
+  }
+
+  /// Sets a frozen clock to the given time. No-op for the system clock.
+  pub fn set(&self, at: DateTime<Utc>) {
+    if let Some(ref state) = self.frozen {
+      state.lock().now = at;
+    }
+  }
+
+  /// New UUIDv7. For frozen clocks, the random bits are replaced by a counter, i.e. ids are
+  /// deterministic and strictly increasing unless the clock is set back.
+  pub fn uuid_v7(&self) -> Uuid {
+    let Some(ref state) = self.frozen else {
+      return Uuid::now_v7();
+    };
+
+    let mut state = state.lock();
+    state.counter += 1;
+
+    let mut counter_bytes = [0u8; 10];
+    counter_bytes[2..].copy_from_slice(&state.counter.to_be_bytes());
+
+    return uuid::Builder::from_unix_timestamp_millis(
+      state.now.timestamp_millis().max(0) as u64,
+      &counter_bytes,
+    )
+    .into_uuid();
+  }
+
+  /// Overrides SQLite's `uuid_v7()` on the given connection for frozen clocks, since e.g. record
+  /// ids are typically generated by column defaults.
+  pub(crate) fn register_sqlite_functions(
+    &self,
+    conn: &rusqlite::Connection,
+  ) -> Result<(), rusqlite::Error> {
+    if !self.is_frozen() {
+      return Ok(());
+    }
+
+    let clock = self.clone();
+    return conn.create_scalar_function(
+      "uuid_v7",
+      0,
+      rusqlite::functions::FunctionFlags::SQLITE_INNOCUOUS,
+      move |_context| Ok(clock.uuid_v7().as_bytes().to_vec()),
+    );
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_frozen_clock() {
+    let start = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+    let clock = Clock::frozen(start);
+    assert_eq!(clock.now(), start);
+
+    let first = clock.uuid_v7();
+    let second = clock.uuid_v7();
+    assert_eq!(first.get_version_num(), 7);
+    assert!(first < second);
+
+    // Deterministic across clocks.
+    assert_eq!(first, Clock::frozen(start).uuid_v7());
+
+    clock.advance(Duration::seconds(10));
+    assert_eq!(clock.now(), start + Duration::seconds(10));
+    assert!(second < clock.uuid_v7());
+
+    // Clones share state.
+    clock.clone().set(start);
+    assert_eq!(clock.now(), start);
+
+    let conn = trailbase_extension::connect_sqlite(None, None).unwrap();
+    clock.register_sqlite_functions(&conn).unwrap();
+    let id: [u8; 16] = conn
+      .query_row("SELECT uuid_v7()", (), |row| row.get(0))
+      .unwrap();
+    let (seconds, _nanos) = Uuid::from_bytes(id).get_timestamp().unwrap().to_unix();
+    assert_eq!(seconds, 1_700_000_000);
+  }
+
+  #[tokio::test]
+  async fn test_frozen_clock_state() {
+    use crate::admin::user::create_user_for_test;
+    use crate::app_state::{TestStateOptions, test_state};
+    use crate::auth::jwt::TokenClaims;
+    use crate::auth::tokens::mint_new_tokens;
+
+    let start = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+    let clock = Clock::frozen(start);
+    let state = test_state(Some(TestStateOptions {
+      clock: Some(clock.clone()),
+      ..Default::default()
+    }))
+    .await
+    .unwrap();
+
+    // Record ids generated by column defaults are deterministic.
+    state
+      .conn()
+      .execute_batch(
+        "CREATE TABLE item (id BLOB PRIMARY KEY NOT NULL DEFAULT (uuid_v7()), name TEXT) STRICT;",
+      )
+      .await
+      .unwrap();
+    let id: [u8; 16] = state
+      .conn()
+      .query_row_f(
+        "INSERT INTO item (name) VALUES ('a') RETURNING id",
+        (),
+        |row| row.get(0),
+      )
+      .await
+      .unwrap()
+      .unwrap();
+    let (seconds, _nanos) = Uuid::from_bytes(id).get_timestamp().unwrap().to_unix();
+    assert_eq!(seconds, 1_700_000_000);
+
+    // Tokens are issued and validated against the frozen clock, even though it's in the past.
+    let user_id = create_user_for_test(&state, "user@test.org", "secret123")
+      .await
+      .unwrap();
+    let tokens = mint_new_tokens(
+      &state,
+      true,
+      user_id,
+      "user@test.org".to_string(),
+      Duration::minutes(5),
+    )
+    .await
+    .unwrap();
+    assert_eq!(tokens.auth_token_claims.iat, start.timestamp());
+
+    let auth_token = state.jwt().encode(&tokens.auth_token_claims).unwrap();
+    assert!(state.jwt().decode::<TokenClaims>(&auth_token).is_ok());
+
+    clock.advance(Duration::hours(1));
+    assert!(state.jwt().decode::<TokenClaims>(&auth_token).is_err());
+  }
+}
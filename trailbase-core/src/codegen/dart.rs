@@ -0,0 +1,378 @@
+use std::fmt::Write;
+
+use crate::codegen::{
+  ClientSchema, FieldType, Model, RecordApiModel, camel_case, error_codes, pascal_case,
+};
+
+const HEADER: &str = r#"// This file was generated by TrailBase. Do not edit this file manually.
+
+// ignore_for_file: constant_identifier_names
+
+import 'package:trailbase/trailbase.dart';
+
+/// Filter qualifiers supported by list queries. Omitting the qualifier means equality.
+enum FilterOp {
+  gt('gt'),
+  gte('gte'),
+  lt('lt'),
+  lte('lte'),
+  not('not'),
+  ne('ne'),
+  like('like'),
+  ilike('ilike'),
+  glob('glob'),
+  re('re'),
+  in_('in'),
+  nin('nin');
+
+  const FilterOp(this.op);
+  final String op;
+}
+
+/// Builds a list filter, e.g. `filter('price', 100, FilterOp.lte)` yields "price[lte]=100".
+/// Lists are joined for [FilterOp.in_] and [FilterOp.nin], e.g. `filter('id', [1, 2], FilterOp.in_)`.
+String filter(String column, Object value, [FilterOp? op]) {
+  final v = value is Iterable ? value.join(',') : value;
+  return op == null ? '${column}=${v}' : '${column}[${op.op}]=${v}';
+}
+
+/// Typed counterpart of [ListResponse].
+class RecordList<T> {
+  final String? cursor;
+  final List<T> records;
+  final int? totalCount;
+
+  const RecordList({this.cursor, required this.records, this.totalCount});
+}
+"#;
+
+const KEYWORDS: &[&str] = &[
+  "abstract", "as", "assert", "async", "await", "break", "case", "catch", "class", "const",
+  "continue", "default", "do", "dynamic", "else", "enum", "export", "extends", "external",
+  "factory", "false", "final", "finally", "for", "get", "if", "implements", "import", "in", "is",
+  "late", "library", "new", "null", "operator", "required", "rethrow", "return", "set", "static",
+  "super", "switch", "this", "throw", "true", "try", "var", "void", "while", "with", "yield",
+];
+
+/// Renders a single-quoted Dart string literal.
+fn quote(s: &str) -> String {
+  let escaped = s
+    .replace('\\', "\\\\")
+    .replace('\'', "\\'")
+    .replace('$', "\\$")
+    .replace('\n', "\\n");
+  return format!("'{escaped}'");
+}
+
+fn identifier(name: &str) -> String {
+  let ident = camel_case(name);
+  if KEYWORDS.contains(&ident.as_str()) {
+    return format!("{ident}_");
+  }
+  return ident;
+}
+
+fn dart_type(ty: &FieldType) -> String {
+  return match ty {
+    FieldType::String | FieldType::Enum(_) => "String".to_string(),
+    FieldType::Integer => "int".to_string(),
+    FieldType::Number => "num".to_string(),
+    FieldType::Boolean => "bool".to_string(),
+    FieldType::Any => "dynamic".to_string(),
+    FieldType::Array(item) => format!("List<{}>", dart_type(item)),
+    FieldType::Model(name) => name.clone(),
+  };
+}
+
+/// Expression converting the decoded JSON `value` into the given type.
+fn decode(ty: &FieldType, value: &str) -> String {
+  return match ty {
+    FieldType::Model(name) => format!("{name}.fromJson({value} as Map<String, dynamic>)"),
+    FieldType::Array(item) => format!(
+      "({value} as List).map((e) => {}).toList()",
+      decode(item, "e")
+    ),
+    FieldType::Number => format!("({value} as num)"),
+    FieldType::Any => value.to_string(),
+    ty => format!("{value} as {}", dart_type(ty)),
+  };
+}
+
+/// Expression converting `value` of the given type into encodable JSON.
+fn encode(ty: &FieldType, value: &str) -> String {
+  return match ty {
+    FieldType::Model(_) => format!("{value}.toJson()"),
+    FieldType::Array(item) => match **item {
+      FieldType::Model(_) | FieldType::Array(_) => {
+        format!("{value}.map((e) => {}).toList()", encode(item, "e"))
+      }
+      _ => value.to_string(),
+    },
+    _ => value.to_string(),
+  };
+}
+
+fn render_model(out: &mut String, model: &Model) {
+  let name = &model.name;
+  let _ = writeln!(out, "class {name} {{");
+
+  for field in &model.fields {
+    if let FieldType::Enum(values) = &field.ty {
+      let _ = writeln!(out, "  /// One of: {}.", values.join(", "));
+    }
+    let _ = writeln!(
+      out,
+      "  final {}{} {};",
+      dart_type(&field.ty),
+      if field.required { "" } else { "?" },
+      identifier(&field.name)
+    );
+  }
+
+  // Constructor.
+  if model.fields.is_empty() {
+    let _ = writeln!(out, "\n  const {name}();");
+  } else {
+    let _ = writeln!(out, "\n  const {name}({{");
+    for field in &model.fields {
+      let _ = writeln!(
+        out,
+        "    {}this.{},",
+        if field.required { "required " } else { "" },
+        identifier(&field.name)
+      );
+    }
+    let _ = writeln!(out, "  }});");
+  }
+
+  // Deserialization.
+  let _ = writeln!(
+    out,
+    "\n  factory {name}.fromJson(Map<String, dynamic> json) => {name}("
+  );
+  for field in &model.fields {
+    let key = format!("json[{}]", quote(&field.name));
+    let value = if field.required {
+      decode(&field.ty, &key)
+    } else {
+      format!("{key} == null ? null : {}", decode(&field.ty, &key))
+    };
+    let _ = writeln!(out, "        {}: {value},", identifier(&field.name));
+  }
+  let _ = writeln!(out, "      );");
+
+  // Serialization. Unset optional fields are omitted, which matters for partial updates.
+  let _ = writeln!(out, "\n  Map<String, dynamic> toJson() => {{");
+  for field in &model.fields {
+    let ident = identifier(&field.name);
+    let key = quote(&field.name);
+    if field.required {
+      let _ = writeln!(out, "        {key}: {},", encode(&field.ty, &ident));
+    } else {
+      let _ = writeln!(
+        out,
+        "        if ({ident} != null) {key}: {},",
+        encode(&field.ty, &format!("{ident}!"))
+      );
+    }
+  }
+  let _ = writeln!(out, "      }};");
+
+  let _ = writeln!(out, "}}\n");
+}
+
+fn render_api(out: &mut String, api: &RecordApiModel) {
+  let base = pascal_case(&api.api_name);
+  let select = &api.select;
+
+  // Column names for filters and ordering.
+  let _ = writeln!(out, "abstract final class {base}Columns {{");
+  for column in &api.filter_columns {
+    let _ = writeln!(
+      out,
+      "  static const {} = {};",
+      identifier(column),
+      quote(column)
+    );
+  }
+  let _ = writeln!(out, "}}\n");
+
+  let _ = write!(
+    out,
+    r#"class {base}Api {{
+  static const String apiName = {api_name};
+
+  final RecordApi _api;
+
+  {base}Api(Client client) : _api = client.records(apiName);
+
+  Future<RecordList<{select}>> list({{
+    Pagination? pagination,
+    List<String>? order,
+    List<String>? filters,
+    bool? count,
+    List<String>? expand,
+  }}) async {{
+    final response = await _api.list(
+      pagination: pagination,
+      order: order,
+      filters: filters,
+      count: count,
+      expand: expand,
+    );
+    return RecordList(
+      cursor: response.cursor,
+      records: response.records.map({select}.fromJson).toList(),
+      totalCount: response.totalCount,
+    );
+  }}
+
+  Future<{select}> read(RecordId id, {{List<String>? expand}}) async =>
+      {select}.fromJson(await _api.read(id, expand: expand));
+"#,
+    api_name = quote(&api.api_name),
+  );
+
+  if let (Some(insert), Some(update)) = (&api.insert, &api.update) {
+    let _ = write!(
+      out,
+      r#"
+  Future<RecordId> create({insert} record) => _api.create(record.toJson());
+
+  Future<List<RecordId>> createBulk(List<{insert}> records) =>
+      _api.createBulk(records.map((r) => r.toJson()).toList());
+
+  Future<void> update(RecordId id, {update} record) =>
+      _api.update(id, record.toJson());
+
+  Future<void> delete(RecordId id) => _api.delete(id);
+"#
+    );
+  }
+
+  let _ = write!(
+    out,
+    r#"
+  Future<Stream<Event>> subscribe(RecordId id) => _api.subscribe(id);
+
+  Future<Stream<Event>> subscribeAll() => _api.subscribeAll();
+"#
+  );
+
+  for column in &api.file_columns {
+    let _ = write!(
+      out,
+      r#"
+  Uri {ident}Uri(RecordId id, {{int? index}}) =>
+      _api.imageUri(id, {column}, index: index);
+"#,
+      ident = identifier(column),
+      column = quote(column),
+    );
+  }
+
+  let _ = writeln!(out, "}}\n");
+}
+
+fn render_client(out: &mut String, schema: &ClientSchema) {
+  let _ = write!(
+    out,
+    r#"/// Typed entry point bundling auth helpers and accessors for all record APIs.
+class TypedClient {{
+  final Client client;
+
+  TypedClient(String site, {{void Function(Client, Tokens?)? onAuthChange}})
+      : client = Client(site, onAuthChange: onAuthChange);
+
+  User? user() => client.user();
+
+  Future<Tokens> login(String email, String password) =>
+      client.login(email, password);
+
+  Future<bool> logout() => client.logout();
+"#
+  );
+
+  for api in &schema.apis {
+    let base = pascal_case(&api.api_name);
+    let _ = write!(
+      out,
+      "\n  {base}Api get {}Api => {base}Api(client);\n",
+      camel_case(&api.api_name),
+    );
+  }
+
+  let _ = writeln!(out, "}}");
+}
+
+fn render_error_codes(out: &mut String) {
+  let _ = writeln!(out, "/// Stable codes of API error responses.");
+  let _ = writeln!(out, "abstract final class ErrorCode {{");
+  for code in error_codes() {
+    let _ = writeln!(
+      out,
+      "  static const {} = {};",
+      identifier(&code),
+      quote(&code)
+    );
+  }
+  let _ = writeln!(out, "}}\n");
+}
+
+pub(super) fn render(schema: &ClientSchema) -> String {
+  let mut out = HEADER.to_string();
+  out.push('\n');
+
+  render_error_codes(&mut out);
+
+  for model in &schema.models {
+    render_model(&mut out, model);
+  }
+
+  for api in &schema.apis {
+    render_api(&mut out, api);
+  }
+
+  render_client(&mut out, schema);
+
+  return out;
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::codegen::Field;
+
+  #[test]
+  fn test_render_model() {
+    let mut out = String::new();
+    render_model(
+      &mut out,
+      &Model {
+        name: "Article".to_string(),
+        fields: vec![
+          Field {
+            name: "id".to_string(),
+            ty: FieldType::Integer,
+            required: true,
+          },
+          Field {
+            name: "class".to_string(),
+            ty: FieldType::Array(Box::new(FieldType::Model("Tag".to_string()))),
+            required: false,
+          },
+        ],
+      },
+    );
+
+    assert!(out.contains("  final int id;\n"), "{out}");
+    assert!(out.contains("  final List<Tag>? class_;\n"), "{out}");
+    assert!(out.contains(
+      "class_: json['class'] == null ? null : (json['class'] as List).map((e) => Tag.fromJson(e as Map<String, dynamic>)).toList(),"
+    ), "{out}");
+    assert!(
+      out.contains("if (class_ != null) 'class': class_!.map((e) => e.toJson()).toList(),"),
+      "{out}"
+    );
+  }
+}
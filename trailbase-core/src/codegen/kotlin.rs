@@ -0,0 +1,199 @@
+use std::fmt::Write;
+
+use crate::codegen::{
+  ClientSchema, FieldType, Model, camel_case, error_codes, pascal_case, upper_snake_case,
+};
+
+const HEADER: &str = r#"// This file was generated by TrailBase. Do not edit this file manually.
+
+@file:Suppress("ktlint", "EnumEntryName")
+
+import kotlinx.serialization.SerialName
+import kotlinx.serialization.Serializable
+import kotlinx.serialization.json.JsonElement
+"#;
+
+const KEYWORDS: &[&str] = &[
+  "as", "break", "class", "continue", "do", "else", "false", "for", "fun", "if", "in",
+  "interface", "is", "null", "object", "package", "return", "super", "this", "throw", "true",
+  "try", "typealias", "typeof", "val", "var", "when", "while",
+];
+
+/// Renders a Kotlin string literal.
+fn quote(s: &str) -> String {
+  let escaped = s
+    .replace('\\', "\\\\")
+    .replace('"', "\\\"")
+    .replace('$', "\\$")
+    .replace('\n', "\\n");
+  return format!("\"{escaped}\"");
+}
+
+fn escape_keyword(ident: String) -> String {
+  if KEYWORDS.contains(&ident.as_str()) {
+    return format!("`{ident}`");
+  }
+  return ident;
+}
+
+/// Derives an UPPER_SNAKE_CASE enum entry name from a value, falling back to a positional name.
+fn enum_entry(value: &str, index: usize) -> String {
+  let entry = upper_snake_case(value);
+  if entry.is_empty() {
+    return format!("VALUE_{index}");
+  }
+  if entry.starts_with(|c: char| c.is_ascii_digit()) {
+    return format!("_{entry}");
+  }
+  return entry;
+}
+
+struct Renderer<'a> {
+  enums: &'a [(String, Vec<String>)],
+}
+
+impl Renderer<'_> {
+  fn kotlin_type(&self, ty: &FieldType) -> String {
+    return match ty {
+      FieldType::String => "String".to_string(),
+      FieldType::Integer => "Long".to_string(),
+      FieldType::Number => "Double".to_string(),
+      FieldType::Boolean => "Boolean".to_string(),
+      FieldType::Any => "JsonElement".to_string(),
+      FieldType::Array(item) => format!("List<{}>", self.kotlin_type(item)),
+      FieldType::Model(name) => name.clone(),
+      FieldType::Enum(values) => self
+        .enums
+        .iter()
+        .find(|(_, v)| v == values)
+        .map_or_else(|| "String".to_string(), |(name, _)| name.clone()),
+    };
+  }
+
+  fn render_enum(&self, out: &mut String, name: &str, values: &[String]) {
+    let _ = writeln!(out, "@Serializable\nenum class {name} {{");
+    let mut entries: Vec<String> = vec![];
+    for (index, value) in values.iter().enumerate() {
+      let mut entry = enum_entry(value, index);
+      if entries.contains(&entry) {
+        entry = format!("{entry}_{index}");
+      }
+      let _ = writeln!(out, "  @SerialName({}) {entry},", quote(value));
+      entries.push(entry);
+    }
+    let _ = writeln!(out, "}}\n");
+  }
+
+  fn render_model(&self, out: &mut String, model: &Model) {
+    if model.fields.is_empty() {
+      // Data classes require at least one property.
+      let _ = writeln!(out, "@Serializable\nclass {}\n", model.name);
+      return;
+    }
+
+    let _ = writeln!(out, "@Serializable\ndata class {}(", model.name);
+    for field in &model.fields {
+      let ident = escape_keyword(camel_case(&field.name));
+      let serial_name = if ident == field.name {
+        "".to_string()
+      } else {
+        format!("@SerialName({}) ", quote(&field.name))
+      };
+
+      // Optional properties default to null, which kotlinx.serialization omits when encoding by
+      // default. This matters for partial updates.
+      let _ = writeln!(
+        out,
+        "  {serial_name}val {ident}: {}{},",
+        self.kotlin_type(&field.ty),
+        if field.required { "" } else { "? = null" },
+      );
+    }
+    let _ = writeln!(out, ")\n");
+  }
+}
+
+pub(super) fn render(schema: &ClientSchema) -> String {
+  let enums = schema.named_enums();
+  let renderer = Renderer { enums: &enums };
+
+  let mut out = HEADER.to_string();
+  out.push('\n');
+
+  for (name, values) in &enums {
+    renderer.render_enum(&mut out, name, values);
+  }
+  renderer.render_enum(&mut out, "ErrorCode", &error_codes());
+
+  for model in &schema.models {
+    renderer.render_model(&mut out, model);
+  }
+
+  // API names to be used with the client's `records()` accessor.
+  let _ = writeln!(out, "object RecordApis {{");
+  for api in &schema.apis {
+    let _ = writeln!(
+      out,
+      "  const val {}: String = {}",
+      pascal_case(&api.api_name),
+      quote(&api.api_name)
+    );
+  }
+  let _ = writeln!(out, "}}");
+
+  return out;
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::codegen::Field;
+
+  #[test]
+  fn test_enum_entry() {
+    assert_eq!("DRAFT", enum_entry("draft", 0));
+    assert_eq!("IN_REVIEW", enum_entry("in-review", 0));
+    assert_eq!("IN_REVIEW", enum_entry("inReview", 0));
+    assert_eq!("_1ST", enum_entry("1st", 0));
+    assert_eq!("VALUE_2", enum_entry("--", 2));
+  }
+
+  #[test]
+  fn test_render_model() {
+    let enums = vec![(
+      "ArticleState".to_string(),
+      vec!["a".to_string(), "b".to_string()],
+    )];
+    let renderer = Renderer { enums: &enums };
+
+    let mut out = String::new();
+    renderer.render_model(
+      &mut out,
+      &Model {
+        name: "Article".to_string(),
+        fields: vec![
+          Field {
+            name: "id".to_string(),
+            ty: FieldType::Integer,
+            required: true,
+          },
+          Field {
+            name: "tags".to_string(),
+            ty: FieldType::Array(Box::new(FieldType::String)),
+            required: false,
+          },
+          Field {
+            name: "my_state".to_string(),
+            ty: FieldType::Enum(vec!["a".to_string(), "b".to_string()]),
+            required: true,
+          },
+        ],
+      },
+    );
+
+    assert_eq!(
+      out,
+      "@Serializable\ndata class Article(\n  val id: Long,\n  val tags: List<String>? = null,\n  @SerialName(\"my_state\") val myState: ArticleState,\n)\n\n"
+    );
+  }
+}
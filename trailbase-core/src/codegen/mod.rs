@@ -0,0 +1,599 @@
+//! Client code generation from the live schema.
+//!
+//! Record API JSON schemas are first lowered into a small, language-agnostic model (`ClientSchema`)
+//! which is then rendered by the individual targets.
+
+mod dart;
+mod kotlin;
+pub(crate) mod proto;
+mod swift;
+mod typescript;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::Value;
+use thiserror::Error;
+use trailbase_schema::json_schema::JsonSchemaMode;
+use trailbase_schema::metadata::JsonColumnMetadata;
+use trailbase_schema::sqlite::{Column, ColumnOption};
+use ts_rs::TS;
+
+use crate::app_state::AppState;
+use crate::problem::ErrorCode;
+use crate::records::json_schema::build_api_json_schema;
+use crate::records::{RecordApi, RecordError};
+
+#[derive(Debug, Error)]
+pub enum CodegenError {
+  #[error("Record API error: {0}")]
+  RecordApi(#[from] RecordError),
+  #[error("Unsupported schema: {0}")]
+  Unsupported(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, TS)]
+#[ts(export)]
+pub enum CodegenTarget {
+  #[serde(rename = "typescript", alias = "ts")]
+  TypeScript,
+  #[serde(rename = "dart")]
+  Dart,
+  #[serde(rename = "kotlin", alias = "kt")]
+  Kotlin,
+  #[serde(rename = "swift")]
+  Swift,
+  #[serde(rename = "proto", alias = "protobuf")]
+  Proto,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum FieldType {
+  String,
+  Integer,
+  Number,
+  Boolean,
+  /// Arbitrary JSON, e.g. JSON columns without schema or union types.
+  Any,
+  Array(Box<FieldType>),
+  /// Reference to another `Model` by name.
+  Model(String),
+  /// Closed set of string literals.
+  Enum(Vec<String>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Field {
+  pub name: String,
+  pub ty: FieldType,
+  pub required: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Model {
+  pub name: String,
+  pub fields: Vec<Field>,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct RecordApiModel {
+  pub api_name: String,
+  /// Name of the model returned by reads and listings.
+  pub select: String,
+  /// Name of the insert and update models. Only present for table APIs.
+  pub insert: Option<String>,
+  pub update: Option<String>,
+  /// Columns that can be used in list filters and ordering.
+  pub filter_columns: Vec<String>,
+  pub expand: Vec<String>,
+  /// Columns holding `std.FileUpload` or `std.FileUploads`.
+  pub file_columns: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ClientSchema {
+  pub models: Vec<Model>,
+  pub apis: Vec<RecordApiModel>,
+}
+
+impl ClientSchema {
+  fn model_name(&self, name: String) -> String {
+    // Disambiguate colliding names, e.g. APIs named "foo_bar" and "foo-bar".
+    let mut candidate = name.clone();
+    let mut i = 1;
+    while self.models.iter().any(|m| m.name == candidate) {
+      i += 1;
+      candidate = format!("{name}{i}");
+    }
+    return candidate;
+  }
+
+  /// Adds a model returning its name. Nested models are deduplicated structurally, e.g. the same
+  /// JSON schema referenced by the insert and select schemas only yields a single model.
+  fn add_model(&mut self, name: String, fields: Vec<Field>, nested: bool) -> String {
+    if nested {
+      if let Some(existing) = self.models.iter().find(|m| m.fields == fields) {
+        return existing.name.clone();
+      }
+    }
+
+    let name = self.model_name(name);
+    self.models.push(Model {
+      name: name.clone(),
+      fields,
+    });
+    return name;
+  }
+
+  fn add_object_schema(
+    &mut self,
+    name: String,
+    schema: &Value,
+    defs: &[&Value],
+    nested: bool,
+  ) -> Result<String, CodegenError> {
+    let mut defs = defs.to_vec();
+    if let Some(local_defs) = schema.get("$defs") {
+      defs.push(local_defs);
+    }
+
+    let required: Vec<&str> = schema
+      .get("required")
+      .and_then(|r| r.as_array())
+      .map(|r| r.iter().filter_map(|v| v.as_str()).collect())
+      .unwrap_or_default();
+
+    let mut fields: Vec<Field> = vec![];
+    if let Some(properties) = schema.get("properties").and_then(|p| p.as_object()) {
+      for (field_name, property) in properties {
+        fields.push(Field {
+          name: field_name.clone(),
+          ty: self.field_type(
+            &format!("{name}{}", pascal_case(field_name)),
+            property,
+            &defs,
+          )?,
+          required: required.contains(&field_name.as_str()),
+        });
+      }
+    }
+
+    return Ok(self.add_model(name, fields, nested));
+  }
+
+  /// Narrows top-level string fields of the given model to the enum values derived from CHECK
+  /// constraints.
+  fn apply_enums(&mut self, model_name: &str, enums: &[(String, Vec<String>)]) {
+    let Some(model) = self.models.iter_mut().find(|m| m.name == model_name) else {
+      return;
+    };
+
+    for field in &mut model.fields {
+      if field.ty != FieldType::String {
+        continue;
+      }
+      if let Some((_, values)) = enums.iter().find(|(column, _)| *column == field.name) {
+        field.ty = FieldType::Enum(values.clone());
+      }
+    }
+  }
+
+  /// Distinct enum types in declaration order, named after the first model field using them.
+  ///
+  /// Used by targets with nominal enum types, e.g. Kotlin and Swift, to share a single type
+  /// between the select, insert and update models.
+  pub(crate) fn named_enums(&self) -> Vec<(String, Vec<String>)> {
+    fn collect(name: String, ty: &FieldType, enums: &mut Vec<(String, Vec<String>)>) {
+      match ty {
+        FieldType::Enum(values) => {
+          if !enums.iter().any(|(_, v)| v == values) {
+            let mut candidate = name.clone();
+            let mut i = 1;
+            while enums.iter().any(|(n, _)| *n == candidate) {
+              i += 1;
+              candidate = format!("{name}{i}");
+            }
+            enums.push((candidate, values.clone()));
+          }
+        }
+        FieldType::Array(item) => collect(name, item, enums),
+        _ => {}
+      }
+    }
+
+    let mut enums: Vec<(String, Vec<String>)> = vec![];
+    for model in &self.models {
+      for field in &model.fields {
+        collect(
+          format!("{}{}", model.name, pascal_case(&field.name)),
+          &field.ty,
+          &mut enums,
+        );
+      }
+    }
+    return enums;
+  }
+
+  fn field_type(
+    &mut self,
+    name: &str,
+    schema: &Value,
+    defs: &[&Value],
+  ) -> Result<FieldType, CodegenError> {
+    if let Some(reference) = schema.get("$ref").and_then(|r| r.as_str()) {
+      let Some(def_name) = reference.strip_prefix("#/$defs/") else {
+        return Err(CodegenError::Unsupported(format!("$ref: {reference}")));
+      };
+
+      let Some(def) = defs.iter().rev().find_map(|d| d.get(def_name)) else {
+        return Err(CodegenError::Unsupported(format!(
+          "missing def: {def_name}"
+        )));
+      };
+
+      return self.field_type(name, def, defs);
+    }
+
+    if let Some(values) = schema.get("enum").and_then(|e| e.as_array()) {
+      let literals: Option<Vec<String>> = values
+        .iter()
+        .map(|v| v.as_str().map(|s| s.to_string()))
+        .collect();
+      return Ok(literals.map_or(FieldType::Any, FieldType::Enum));
+    }
+
+    return Ok(match schema.get("type") {
+      Some(Value::String(t)) => match t.as_str() {
+        "string" => FieldType::String,
+        "integer" => FieldType::Integer,
+        "number" => FieldType::Number,
+        "boolean" => FieldType::Boolean,
+        "array" => FieldType::Array(Box::new(match schema.get("items") {
+          Some(items) => self.field_type(&format!("{name}Item"), items, defs)?,
+          None => FieldType::Any,
+        })),
+        "object" if schema.get("properties").is_some() => {
+          FieldType::Model(self.add_object_schema(name.to_string(), schema, defs, true)?)
+        }
+        _ => FieldType::Any,
+      },
+      _ => FieldType::Any,
+    });
+  }
+}
+
+fn lower_api(
+  state: &AppState,
+  schema: &mut ClientSchema,
+  api: &RecordApi,
+) -> Result<(), CodegenError> {
+  let api_name = api.api_name();
+  let base = pascal_case(api_name);
+
+  let mut model = |mode: JsonSchemaMode, name: String| -> Result<String, CodegenError> {
+    let json = build_api_json_schema(state, api, Some(mode))?;
+    return schema.add_object_schema(name, &json, &[], false);
+  };
+
+  let select = model(JsonSchemaMode::Select, base.clone())?;
+  let (insert, update) = if api.is_table() {
+    (
+      Some(model(JsonSchemaMode::Insert, format!("{base}Insert"))?),
+      Some(model(JsonSchemaMode::Update, format!("{base}Update"))?),
+    )
+  } else {
+    (None, None)
+  };
+
+  let enums: Vec<(String, Vec<String>)> = api
+    .columns()
+    .iter()
+    .filter_map(|c| Some((c.name.clone(), check_enum_values(c)?)))
+    .collect();
+  for name in [Some(&select), insert.as_ref(), update.as_ref()]
+    .into_iter()
+    .flatten()
+  {
+    schema.apply_enums(name, &enums);
+  }
+
+  let mut expand: Vec<String> = api
+    .expand()
+    .map(|e| e.keys().cloned().collect())
+    .unwrap_or_default();
+  expand.sort();
+
+  schema.apis.push(RecordApiModel {
+    api_name: api_name.to_string(),
+    select,
+    insert,
+    update,
+    filter_columns: api
+      .columns()
+      .iter()
+      .zip(api.json_column_metadata())
+      .filter(|(c, json)| !c.name.starts_with('_') && json.is_none())
+      .map(|(c, _)| c.name.clone())
+      .collect(),
+    expand,
+    file_columns: api
+      .columns()
+      .iter()
+      .zip(api.json_column_metadata())
+      .filter(|(_, json)| match json {
+        Some(JsonColumnMetadata::SchemaName(name, _)) => {
+          name == "std.FileUpload" || name == "std.FileUploads"
+        }
+        _ => false,
+      })
+      .map(|(c, _)| c.name.clone())
+      .collect(),
+  });
+
+  return Ok(());
+}
+
+pub(crate) fn build_client_schema(state: &AppState) -> Result<ClientSchema, CodegenError> {
+  let mut apis: Vec<RecordApi> = state
+    .record_apis()
+    .iter()
+    .map(|(_name, api)| api.clone())
+    .collect();
+  apis.sort_by(|a, b| a.api_name().cmp(b.api_name()));
+
+  let mut schema = ClientSchema::default();
+  for api in &apis {
+    lower_api(state, &mut schema, api)?;
+  }
+  return Ok(schema);
+}
+
+/// Stable API error codes, rendered as an enum by all targets so that clients can match on them.
+pub(crate) fn error_codes() -> Vec<String> {
+  return ErrorCode::ALL
+    .iter()
+    .map(|code| code.as_str().to_string())
+    .collect();
+}
+
+/// Generates a typed client for all configured record APIs in the given target language.
+pub fn generate_client(state: &AppState, target: CodegenTarget) -> Result<String, CodegenError> {
+  let schema = build_client_schema(state)?;
+  return Ok(match target {
+    CodegenTarget::TypeScript => typescript::render(&schema),
+    CodegenTarget::Dart => dart::render(&schema),
+    CodegenTarget::Kotlin => kotlin::render(&schema),
+    CodegenTarget::Swift => swift::render(&schema),
+    CodegenTarget::Proto => proto::render(&schema),
+  });
+}
+
+/// Extracts the allowed values from a column constraint of the form `CHECK(col IN ('a', 'b'))`.
+fn check_enum_values(column: &Column) -> Option<Vec<String>> {
+  lazy_static! {
+    static ref CHECK_IN_RE: Regex =
+      Regex::new(r#"(?is)^\s*["`\[]?(?<column>\w+)["`\]]?\s+IN\s*\((?<values>.*)\)\s*$"#)
+        .expect("valid");
+    static ref LITERAL_RE: Regex =
+      Regex::new(r#"^\s*'(?<value>(?:[^']|'')*)'\s*(?:,|$)"#).expect("valid");
+  }
+
+  for opt in &column.options {
+    let ColumnOption::Check(check) = opt else {
+      continue;
+    };
+
+    let Some(captures) = CHECK_IN_RE.captures(check) else {
+      continue;
+    };
+    if !captures["column"].eq_ignore_ascii_case(&column.name) {
+      continue;
+    }
+
+    // Only accept lists consisting entirely of string literals.
+    let mut rest = &captures["values"];
+    let mut values: Vec<String> = vec![];
+    while !rest.trim().is_empty() {
+      let literal = LITERAL_RE.captures(rest)?;
+      values.push(literal["value"].replace("''", "'"));
+      rest = &rest[literal.get(0)?.end()..];
+    }
+
+    if !values.is_empty() {
+      return Some(values);
+    }
+  }
+
+  return None;
+}
+
+/// Converts snake_case and kebab-case names into PascalCase identifiers.
+pub(crate) fn pascal_case(name: &str) -> String {
+  let mut result = String::with_capacity(name.len());
+  let mut upper = true;
+  for c in name.chars() {
+    if !c.is_ascii_alphanumeric() {
+      upper = true;
+      continue;
+    }
+
+    if upper {
+      result.push(c.to_ascii_uppercase());
+      upper = false;
+    } else {
+      result.push(c);
+    }
+  }
+
+  if result.starts_with(|c: char| c.is_ascii_digit()) {
+    result.insert(0, '_');
+  }
+  return result;
+}
+
+/// Converts arbitrary names, e.g. camelCase or kebab-case, into UPPER_SNAKE_CASE. May be empty.
+pub(crate) fn upper_snake_case(name: &str) -> String {
+  let mut result = String::with_capacity(name.len());
+  let mut last_underscore = true;
+  let mut last_lowercase = false;
+  for c in name.chars() {
+    if c.is_ascii_alphanumeric() {
+      // Split camelCase words.
+      if c.is_ascii_uppercase() && last_lowercase {
+        result.push('_');
+      }
+      result.push(c.to_ascii_uppercase());
+      last_underscore = false;
+      last_lowercase = c.is_ascii_lowercase();
+    } else {
+      if !last_underscore {
+        result.push('_');
+      }
+      last_underscore = true;
+      last_lowercase = false;
+    }
+  }
+  return result.trim_end_matches('_').to_string();
+}
+
+/// Converts names into camelCase identifiers.
+pub(crate) fn camel_case(name: &str) -> String {
+  let pascal = pascal_case(name);
+  let mut chars = pascal.chars();
+  return match chars.next() {
+    Some(first) => first.to_ascii_lowercase().to_string() + chars.as_str(),
+    None => pascal,
+  };
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::app_state::test_state;
+  use crate::config::proto::PermissionFlag;
+  use crate::records::test_utils::add_record_api;
+  use crate::records::{AccessRules, Acls};
+
+  #[test]
+  fn test_case_conversion() {
+    assert_eq!("FooBar", pascal_case("foo_bar"));
+    assert_eq!("FooBar", pascal_case("foo-bar"));
+    assert_eq!("_1Foo", pascal_case("1foo"));
+    assert_eq!("fooBar", camel_case("foo_bar"));
+    assert_eq!("FOO_BAR", upper_snake_case("fooBar"));
+    assert_eq!("ARTICLES_STATUS", upper_snake_case("ArticlesStatus"));
+  }
+
+  #[tokio::test]
+  async fn test_build_client_schema() {
+    let state = test_state(None).await.unwrap();
+
+    state
+      .conn()
+      .execute_batch(
+        r#"
+          CREATE TABLE article (
+            id        INTEGER PRIMARY KEY,
+            title     TEXT NOT NULL,
+            score     REAL,
+            status    TEXT NOT NULL DEFAULT 'draft' CHECK(status IN ('draft', 'published')),
+            meta      TEXT CHECK(jsonschema('std.FileUpload', meta))
+          ) STRICT;
+        "#,
+      )
+      .await
+      .unwrap();
+    state.schema_metadata().invalidate_all().await.unwrap();
+
+    add_record_api(
+      &state,
+      "articles",
+      "article",
+      Acls {
+        world: vec![PermissionFlag::Read],
+        ..Default::default()
+      },
+      AccessRules::default(),
+    )
+    .await
+    .unwrap();
+
+    let schema = build_client_schema(&state).unwrap();
+    assert_eq!(1, schema.apis.len());
+
+    let api = &schema.apis[0];
+    assert_eq!("Articles", api.select);
+    assert_eq!(Some("ArticlesInsert".to_string()), api.insert);
+    assert_eq!(vec!["id", "title", "score", "status"], api.filter_columns);
+    assert_eq!(vec!["meta"], api.file_columns);
+
+    let select = schema.models.iter().find(|m| m.name == "Articles").unwrap();
+    let title = select.fields.iter().find(|f| f.name == "title").unwrap();
+    assert_eq!(FieldType::String, title.ty);
+    assert!(title.required);
+
+    let status = select.fields.iter().find(|f| f.name == "status").unwrap();
+    assert_eq!(
+      FieldType::Enum(vec!["draft".to_string(), "published".to_string()]),
+      status.ty
+    );
+    assert_eq!(
+      vec![(
+        "ArticlesStatus".to_string(),
+        vec!["draft".to_string(), "published".to_string()]
+      )],
+      schema.named_enums()
+    );
+
+    let meta = select.fields.iter().find(|f| f.name == "meta").unwrap();
+    let FieldType::Model(ref meta_model) = meta.ty else {
+      panic!("expected nested model: {meta:?}");
+    };
+    assert!(schema.models.iter().any(|m| m.name == *meta_model));
+
+    let ts = generate_client(&state, CodegenTarget::TypeScript).unwrap();
+    assert!(ts.contains("export interface Articles {"), "{ts}");
+    assert!(
+      ts.contains(r#"export type ErrorCode = "record/api_not_found" | "#),
+      "{ts}"
+    );
+
+    let dart = generate_client(&state, CodegenTarget::Dart).unwrap();
+    assert!(dart.contains("class Articles {"), "{dart}");
+    assert!(dart.contains("Uri metaUri(RecordId id"), "{dart}");
+    assert!(
+      dart.contains("static const recordNotFound = 'record/not_found';"),
+      "{dart}"
+    );
+
+    let kotlin = generate_client(&state, CodegenTarget::Kotlin).unwrap();
+    assert!(kotlin.contains("enum class ArticlesStatus {"), "{kotlin}");
+    assert!(kotlin.contains("val status: ArticlesStatus,"), "{kotlin}");
+    assert!(
+      kotlin.contains(r#"@SerialName("record/not_found") RECORD_NOT_FOUND,"#),
+      "{kotlin}"
+    );
+
+    let swift = generate_client(&state, CodegenTarget::Swift).unwrap();
+    assert!(
+      swift.contains("public enum ArticlesStatus: String, Codable"),
+      "{swift}"
+    );
+    assert!(
+      swift.contains("public var status: ArticlesStatus?"),
+      "{swift}"
+    );
+    assert!(
+      swift.contains(r#"case recordNotFound = "record/not_found""#),
+      "{swift}"
+    );
+
+    let proto = generate_client(&state, CodegenTarget::Proto).unwrap();
+    assert!(proto.contains("enum ArticlesStatus {"), "{proto}");
+    assert!(proto.contains("ArticlesStatus status = 4;"), "{proto}");
+    assert!(
+      proto.contains("ERROR_CODE_RECORD_NOT_FOUND = 3;"),
+      "{proto}"
+    );
+  }
+}
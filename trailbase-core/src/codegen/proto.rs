@@ -0,0 +1,346 @@
+use std::fmt::Write;
+
+use crate::codegen::{
+  ClientSchema, FieldType, Model, RecordApiModel, error_codes, upper_snake_case,
+};
+
+const HEADER: &str = r#"// This file was generated by TrailBase. Do not edit this file manually.
+
+syntax = "proto3";
+
+package trailbase.records;
+
+import "google/protobuf/empty.proto";
+import "google/protobuf/struct.proto";
+"#;
+
+/// Derives a valid proto field name. Returns whether the name had to be changed, in which case
+/// the original name is preserved via `json_name`.
+fn field_name(name: &str) -> (String, bool) {
+  let mut ident: String = name
+    .chars()
+    .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+    .collect();
+  if !ident.starts_with(|c: char| c.is_ascii_alphabetic()) {
+    ident.insert_str(0, "field_");
+  }
+  let changed = ident != name;
+  return (ident, changed);
+}
+
+/// Renders a proto string literal.
+fn quote(s: &str) -> String {
+  let escaped = s
+    .replace('\\', "\\\\")
+    .replace('"', "\\\"")
+    .replace('\n', "\\n");
+  return format!("\"{escaped}\"");
+}
+
+/// Enum value names share the enclosing scope in proto, thus prefix them with the enum's name.
+fn enum_value_name(enum_name: &str, value: &str, index: usize) -> String {
+  let value = upper_snake_case(value);
+  if value.is_empty() {
+    return format!("{}_VALUE_{index}", upper_snake_case(enum_name));
+  }
+  return format!("{}_{value}", upper_snake_case(enum_name));
+}
+
+struct Renderer<'a> {
+  enums: &'a [(String, Vec<String>)],
+}
+
+impl Renderer<'_> {
+  fn proto_type(&self, ty: &FieldType) -> String {
+    return match ty {
+      FieldType::String => "string".to_string(),
+      FieldType::Integer => "int64".to_string(),
+      FieldType::Number => "double".to_string(),
+      FieldType::Boolean => "bool".to_string(),
+      FieldType::Any => "google.protobuf.Value".to_string(),
+      // Nested repeated fields aren't supported, fall back to a generic list.
+      FieldType::Array(_) => "google.protobuf.ListValue".to_string(),
+      FieldType::Model(name) => name.clone(),
+      FieldType::Enum(values) => self
+        .enums
+        .iter()
+        .find(|(_, v)| v == values)
+        .map_or_else(|| "string".to_string(), |(name, _)| name.clone()),
+    };
+  }
+
+  fn render_enum(&self, out: &mut String, name: &str, values: &[String]) {
+    let _ = writeln!(out, "enum {name} {{");
+    // The first value of proto3 enums is the default and must be zero.
+    let _ = writeln!(out, "  {}_UNSPECIFIED = 0;", upper_snake_case(name));
+
+    let mut entries: Vec<String> = vec![];
+    for (index, value) in values.iter().enumerate() {
+      let mut entry = enum_value_name(name, value, index);
+      if entries.contains(&entry) {
+        entry = format!("{entry}_{index}");
+      }
+      let _ = writeln!(out, "  // {}", quote(value));
+      let _ = writeln!(out, "  {entry} = {};", index + 1);
+      entries.push(entry);
+    }
+    let _ = writeln!(out, "}}\n");
+  }
+
+  fn render_model(&self, out: &mut String, model: &Model) {
+    let _ = writeln!(out, "message {} {{", model.name);
+    for (index, field) in model.fields.iter().enumerate() {
+      let (ident, changed) = field_name(&field.name);
+      let json_name = if changed {
+        format!(" [json_name = {}]", quote(&field.name))
+      } else {
+        "".to_string()
+      };
+
+      let label = match &field.ty {
+        FieldType::Array(item) if !matches!(**item, FieldType::Array(_)) => "repeated ",
+        // Use explicit presence for optional fields, which matters for partial updates.
+        _ if !field.required => "optional ",
+        _ => "",
+      };
+      let ty = match &field.ty {
+        FieldType::Array(item) if !matches!(**item, FieldType::Array(_)) => self.proto_type(item),
+        ty => self.proto_type(ty),
+      };
+
+      let _ = writeln!(out, "  {label}{ty} {ident} = {}{json_name};", index + 1);
+    }
+    let _ = writeln!(out, "}}\n");
+  }
+
+  /// Renders request/response messages and the service exposed by the optional gRPC server.
+  fn render_service(&self, out: &mut String, api: &RecordApiModel) {
+    let name = &api.select;
+
+    let _ = writeln!(
+      out,
+      r#"message {name}ListRequest {{
+  // Filters in URL query syntax as accepted by the HTTP API, e.g. "price[lte]=100".
+  string filter = 1;
+  optional int64 limit = 2;
+  optional string cursor = 3;
+  optional int64 offset = 4;
+  // Columns to order by, prefixed with "-" for descending order.
+  repeated string order = 5;
+  bool count = 6;
+}}
+
+message {name}ListResponse {{
+  repeated {name} records = 1;
+  optional string cursor = 2;
+  optional int64 total_count = 3;
+  optional bool has_more = 4;
+}}
+
+message {name}GetRequest {{
+  string id = 1;
+}}
+
+message {name}SubscribeRequest {{
+  // Subscribes to all records of the API if unset.
+  optional string id = 1;
+}}
+
+message {name}Event {{
+  oneof event {{
+    {name} insert = 1;
+    {name} update = 2;
+    {name} delete = 3;
+    string error = 4;
+  }}
+}}
+"#
+    );
+
+    // Only table APIs support mutations.
+    let mutations = api.insert.as_ref().zip(api.update.as_ref());
+    if let Some((_insert, update)) = mutations {
+      let _ = writeln!(
+        out,
+        r#"message {name}CreateResponse {{
+  string id = 1;
+}}
+
+message {name}UpdateRequest {{
+  string id = 1;
+  {update} record = 2;
+}}
+
+message {name}DeleteRequest {{
+  string id = 1;
+}}
+"#
+      );
+    }
+
+    let _ = writeln!(out, "service {name}Service {{");
+    let _ = writeln!(
+      out,
+      "  rpc List({name}ListRequest) returns ({name}ListResponse);"
+    );
+    let _ = writeln!(out, "  rpc Get({name}GetRequest) returns ({name});");
+    if let Some((insert, _update)) = mutations {
+      let _ = writeln!(
+        out,
+        "  rpc Create({insert}) returns ({name}CreateResponse);"
+      );
+      let _ = writeln!(
+        out,
+        "  rpc Update({name}UpdateRequest) returns (google.protobuf.Empty);"
+      );
+      let _ = writeln!(
+        out,
+        "  rpc Delete({name}DeleteRequest) returns (google.protobuf.Empty);"
+      );
+    }
+    let _ = writeln!(
+      out,
+      "  rpc Subscribe({name}SubscribeRequest) returns (stream {name}Event);"
+    );
+    let _ = writeln!(out, "}}\n");
+  }
+}
+
+pub(crate) fn render(schema: &ClientSchema) -> String {
+  let enums = schema.named_enums();
+  let renderer = Renderer { enums: &enums };
+
+  let mut out = HEADER.to_string();
+  out.push('\n');
+
+  for api in &schema.apis {
+    let _ = writeln!(
+      out,
+      "// Record API {}: {}{}{}",
+      quote(&api.api_name),
+      api.select,
+      api
+        .insert
+        .as_ref()
+        .map_or_else(String::new, |name| format!(", {name}")),
+      api
+        .update
+        .as_ref()
+        .map_or_else(String::new, |name| format!(", {name}")),
+    );
+  }
+  out.push('\n');
+
+  for (name, values) in &enums {
+    renderer.render_enum(&mut out, name, values);
+  }
+  renderer.render_enum(&mut out, "ErrorCode", &error_codes());
+
+  for model in &schema.models {
+    renderer.render_model(&mut out, model);
+  }
+
+  for api in &schema.apis {
+    renderer.render_service(&mut out, api);
+  }
+
+  return out;
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::codegen::Field;
+
+  #[test]
+  fn test_render() {
+    let enums = vec![(
+      "ArticleState".to_string(),
+      vec!["draft".to_string(), "in-review".to_string()],
+    )];
+    let renderer = Renderer { enums: &enums };
+
+    let mut out = String::new();
+    renderer.render_enum(&mut out, &enums[0].0, &enums[0].1);
+    renderer.render_model(
+      &mut out,
+      &Model {
+        name: "Article".to_string(),
+        fields: vec![
+          Field {
+            name: "id".to_string(),
+            ty: FieldType::Integer,
+            required: true,
+          },
+          Field {
+            name: "tags".to_string(),
+            ty: FieldType::Array(Box::new(FieldType::String)),
+            required: false,
+          },
+          Field {
+            name: "1st-state".to_string(),
+            ty: FieldType::Enum(vec!["draft".to_string(), "in-review".to_string()]),
+            required: false,
+          },
+        ],
+      },
+    );
+
+    assert_eq!(
+      out,
+      r#"enum ArticleState {
+  ARTICLE_STATE_UNSPECIFIED = 0;
+  // "draft"
+  ARTICLE_STATE_DRAFT = 1;
+  // "in-review"
+  ARTICLE_STATE_IN_REVIEW = 2;
+}
+
+message Article {
+  int64 id = 1;
+  repeated string tags = 2;
+  optional ArticleState field_1st_state = 3 [json_name = "1st-state"];
+}
+
+"#
+    );
+  }
+
+  #[test]
+  fn test_render_service() {
+    let renderer = Renderer { enums: &[] };
+    let mut api = RecordApiModel {
+      api_name: "articles".to_string(),
+      select: "Articles".to_string(),
+      insert: None,
+      update: None,
+      filter_columns: vec![],
+      expand: vec![],
+      file_columns: vec![],
+    };
+
+    let mut view = String::new();
+    renderer.render_service(&mut view, &api);
+    assert!(view.contains("service ArticlesService {"), "{view}");
+    assert!(
+      view.contains("rpc Get(ArticlesGetRequest) returns (Articles);"),
+      "{view}"
+    );
+    assert!(!view.contains("rpc Create("), "{view}");
+
+    api.insert = Some("ArticlesInsert".to_string());
+    api.update = Some("ArticlesUpdate".to_string());
+
+    let mut table = String::new();
+    renderer.render_service(&mut table, &api);
+    assert!(
+      table.contains("rpc Create(ArticlesInsert) returns (ArticlesCreateResponse);"),
+      "{table}"
+    );
+    assert!(table.contains("  ArticlesUpdate record = 2;"), "{table}");
+    assert!(
+      table.contains("rpc Subscribe(ArticlesSubscribeRequest) returns (stream ArticlesEvent);"),
+      "{table}"
+    );
+  }
+}
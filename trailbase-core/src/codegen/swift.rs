@@ -0,0 +1,255 @@
+use std::fmt::Write;
+
+use crate::codegen::{ClientSchema, FieldType, Model, camel_case, error_codes, pascal_case};
+
+const HEADER: &str = r#"// This file was generated by TrailBase. Do not edit this file manually.
+
+import Foundation
+
+/// Arbitrary JSON value, e.g. for JSON columns without schema.
+public enum JSONValue: Codable, Hashable {
+  case null
+  case bool(Bool)
+  case number(Double)
+  case string(String)
+  case array([JSONValue])
+  case object([String: JSONValue])
+
+  public init(from decoder: Decoder) throws {
+    let container = try decoder.singleValueContainer()
+    if container.decodeNil() {
+      self = .null
+    } else if let value = try? container.decode(Bool.self) {
+      self = .bool(value)
+    } else if let value = try? container.decode(Double.self) {
+      self = .number(value)
+    } else if let value = try? container.decode(String.self) {
+      self = .string(value)
+    } else if let value = try? container.decode([JSONValue].self) {
+      self = .array(value)
+    } else {
+      self = .object(try container.decode([String: JSONValue].self))
+    }
+  }
+
+  public func encode(to encoder: Encoder) throws {
+    var container = encoder.singleValueContainer()
+    switch self {
+    case .null: try container.encodeNil()
+    case .bool(let value): try container.encode(value)
+    case .number(let value): try container.encode(value)
+    case .string(let value): try container.encode(value)
+    case .array(let value): try container.encode(value)
+    case .object(let value): try container.encode(value)
+    }
+  }
+}
+"#;
+
+const KEYWORDS: &[&str] = &[
+  "associatedtype", "class", "deinit", "enum", "extension", "fileprivate", "func", "import",
+  "init", "inout", "internal", "let", "open", "operator", "private", "protocol", "public",
+  "rethrows", "static", "struct", "subscript", "typealias", "var", "break", "case", "continue",
+  "default", "defer", "do", "else", "fallthrough", "for", "guard", "if", "in", "repeat", "return",
+  "switch", "where", "while", "as", "catch", "false", "is", "nil", "self", "super", "throw",
+  "throws", "true", "try", "Type", "Self",
+];
+
+/// Renders a Swift string literal.
+fn quote(s: &str) -> String {
+  let escaped = s
+    .replace('\\', "\\\\")
+    .replace('"', "\\\"")
+    .replace('\n', "\\n");
+  return format!("\"{escaped}\"");
+}
+
+fn identifier(name: &str) -> String {
+  let ident = camel_case(name);
+  if ident.is_empty() {
+    return "_".to_string();
+  }
+  if KEYWORDS.contains(&ident.as_str()) {
+    return format!("`{ident}`");
+  }
+  return ident;
+}
+
+struct Renderer<'a> {
+  enums: &'a [(String, Vec<String>)],
+}
+
+impl Renderer<'_> {
+  fn swift_type(&self, ty: &FieldType) -> String {
+    return match ty {
+      FieldType::String => "String".to_string(),
+      FieldType::Integer => "Int64".to_string(),
+      FieldType::Number => "Double".to_string(),
+      FieldType::Boolean => "Bool".to_string(),
+      FieldType::Any => "JSONValue".to_string(),
+      FieldType::Array(item) => format!("[{}]", self.swift_type(item)),
+      FieldType::Model(name) => name.clone(),
+      FieldType::Enum(values) => self
+        .enums
+        .iter()
+        .find(|(_, v)| v == values)
+        .map_or_else(|| "String".to_string(), |(name, _)| name.clone()),
+    };
+  }
+
+  fn render_enum(&self, out: &mut String, name: &str, values: &[String]) {
+    let _ = writeln!(
+      out,
+      "public enum {name}: String, Codable, Hashable, CaseIterable {{"
+    );
+    let mut cases: Vec<String> = vec![];
+    for (index, value) in values.iter().enumerate() {
+      let mut case = identifier(value);
+      if case == "_" || cases.contains(&case) {
+        case = format!("value{index}");
+      }
+      let _ = writeln!(out, "  case {case} = {}", quote(value));
+      cases.push(case);
+    }
+    let _ = writeln!(out, "}}\n");
+  }
+
+  fn render_model(&self, out: &mut String, model: &Model) {
+    let _ = writeln!(out, "public struct {}: Codable, Hashable {{", model.name);
+
+    let fields: Vec<(String, String)> = model
+      .fields
+      .iter()
+      .map(|field| {
+        (
+          identifier(&field.name),
+          format!(
+            "{}{}",
+            self.swift_type(&field.ty),
+            if field.required { "" } else { "?" }
+          ),
+        )
+      })
+      .collect();
+
+    for (ident, ty) in &fields {
+      let _ = writeln!(out, "  public var {ident}: {ty}");
+    }
+
+    // Memberwise initializers are internal, provide a public one. Optional members default to nil
+    // and are omitted when encoding, which matters for partial updates.
+    let params: Vec<String> = model
+      .fields
+      .iter()
+      .zip(&fields)
+      .map(|(field, (ident, ty))| {
+        if field.required {
+          format!("{ident}: {ty}")
+        } else {
+          format!("{ident}: {ty} = nil")
+        }
+      })
+      .collect();
+    let _ = writeln!(out, "\n  public init({}) {{", params.join(", "));
+    for (ident, _) in &fields {
+      let _ = writeln!(out, "    self.{ident} = {ident}");
+    }
+    let _ = writeln!(out, "  }}");
+
+    if !model.fields.is_empty() {
+      let _ = writeln!(out, "\n  enum CodingKeys: String, CodingKey {{");
+      for (field, (ident, _)) in model.fields.iter().zip(&fields) {
+        if ident.trim_matches('`') == field.name {
+          let _ = writeln!(out, "    case {ident}");
+        } else {
+          let _ = writeln!(out, "    case {ident} = {}", quote(&field.name));
+        }
+      }
+      let _ = writeln!(out, "  }}");
+    }
+
+    let _ = writeln!(out, "}}\n");
+  }
+}
+
+pub(super) fn render(schema: &ClientSchema) -> String {
+  let enums = schema.named_enums();
+  let renderer = Renderer { enums: &enums };
+
+  let mut out = HEADER.to_string();
+  out.push('\n');
+
+  for (name, values) in &enums {
+    renderer.render_enum(&mut out, name, values);
+  }
+  renderer.render_enum(&mut out, "ErrorCode", &error_codes());
+
+  for model in &schema.models {
+    renderer.render_model(&mut out, model);
+  }
+
+  // API names to be used with the client's `records()` accessor.
+  let _ = writeln!(out, "public enum RecordApis {{");
+  for api in &schema.apis {
+    let _ = writeln!(
+      out,
+      "  public static let {} = {}",
+      identifier(&pascal_case(&api.api_name)),
+      quote(&api.api_name)
+    );
+  }
+  let _ = writeln!(out, "}}");
+
+  return out;
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::codegen::Field;
+
+  #[test]
+  fn test_render_model() {
+    let renderer = Renderer { enums: &[] };
+
+    let mut out = String::new();
+    renderer.render_model(
+      &mut out,
+      &Model {
+        name: "Article".to_string(),
+        fields: vec![
+          Field {
+            name: "id".to_string(),
+            ty: FieldType::Integer,
+            required: true,
+          },
+          Field {
+            name: "created_at".to_string(),
+            ty: FieldType::Array(Box::new(FieldType::Number)),
+            required: false,
+          },
+        ],
+      },
+    );
+
+    assert_eq!(
+      out,
+      r#"public struct Article: Codable, Hashable {
+  public var id: Int64
+  public var createdAt: [Double]?
+
+  public init(id: Int64, createdAt: [Double]? = nil) {
+    self.id = id
+    self.createdAt = createdAt
+  }
+
+  enum CodingKeys: String, CodingKey {
+    case id
+    case createdAt = "created_at"
+  }
+}
+
+"#
+    );
+  }
+}
@@ -0,0 +1,286 @@
+use std::fmt::Write;
+
+use crate::codegen::{
+  ClientSchema, FieldType, Model, RecordApiModel, camel_case, error_codes, pascal_case,
+};
+
+const HEADER: &str = r#"// This file was generated by TrailBase. Do not edit this file manually.
+
+import { Client } from "trailbase";
+import type { ClientOptions, ListResponse, Pagination, User } from "trailbase";
+
+/// Filter qualifiers supported by list queries. Omitting the qualifier means equality.
+export type FilterOp =
+  | "gt"
+  | "gte"
+  | "lt"
+  | "lte"
+  | "not"
+  | "ne"
+  | "like"
+  | "ilike"
+  | "glob"
+  | "re"
+  | "in"
+  | "nin";
+
+/// Builds a list filter, e.g. `filter("price", 100, "lte")` yields "price[lte]=100". Arrays are
+/// joined for "in" and "nin", e.g. `filter("id", [1, 2], "in")` yields "id[in]=1,2".
+export function filter<C extends string>(
+  column: C,
+  value: string | number | boolean | (string | number)[],
+  op?: FilterOp,
+): string {
+  const v = Array.isArray(value) ? value.join(",") : value;
+  return op ? `${column}[${op}]=${v}` : `${column}=${v}`;
+}
+"#;
+
+/// Renders a JS string literal.
+fn quote(s: &str) -> String {
+  return serde_json::Value::String(s.to_string()).to_string();
+}
+
+fn ts_type(ty: &FieldType) -> String {
+  return match ty {
+    FieldType::String => "string".to_string(),
+    FieldType::Integer | FieldType::Number => "number".to_string(),
+    FieldType::Boolean => "boolean".to_string(),
+    FieldType::Any => "unknown".to_string(),
+    FieldType::Array(item) => match **item {
+      FieldType::Enum(_) => format!("({})[]", ts_type(item)),
+      _ => format!("{}[]", ts_type(item)),
+    },
+    FieldType::Model(name) => name.clone(),
+    FieldType::Enum(values) if values.is_empty() => "never".to_string(),
+    FieldType::Enum(values) => values
+      .iter()
+      .map(|v| quote(v))
+      .collect::<Vec<_>>()
+      .join(" | "),
+  };
+}
+
+/// Quotes property names that aren't valid identifiers.
+fn ts_property(name: &str) -> String {
+  let is_ident = name
+    .chars()
+    .next()
+    .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == '$')
+    && name
+      .chars()
+      .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$');
+  return if is_ident {
+    name.to_string()
+  } else {
+    quote(name)
+  };
+}
+
+fn render_model(out: &mut String, model: &Model) {
+  let _ = writeln!(out, "export interface {} {{", model.name);
+  for field in &model.fields {
+    let _ = writeln!(
+      out,
+      "  {}{}: {};",
+      ts_property(&field.name),
+      if field.required { "" } else { "?" },
+      ts_type(&field.ty)
+    );
+  }
+  let _ = writeln!(out, "}}\n");
+}
+
+fn render_api(out: &mut String, api: &RecordApiModel) {
+  let class_name = format!("{}Api", pascal_case(&api.api_name));
+  let select = &api.select;
+
+  let columns_type = format!("{}Column", pascal_case(&api.api_name));
+  if api.filter_columns.is_empty() {
+    let _ = writeln!(out, "export type {columns_type} = never;\n");
+  } else {
+    let _ = writeln!(
+      out,
+      "export type {columns_type} = {};\n",
+      api
+        .filter_columns
+        .iter()
+        .map(|c| quote(c))
+        .collect::<Vec<_>>()
+        .join(" | ")
+    );
+  }
+
+  let expand_type = if api.expand.is_empty() {
+    "never".to_string()
+  } else {
+    api
+      .expand
+      .iter()
+      .map(|c| quote(c))
+      .collect::<Vec<_>>()
+      .join(" | ")
+  };
+
+  let _ = write!(
+    out,
+    r#"export class {class_name} {{
+  public static readonly apiName = {api_name};
+
+  constructor(private readonly client: Client) {{}}
+
+  private get api() {{
+    return this.client.records({class_name}.apiName);
+  }}
+
+  public list(opts?: {{
+    pagination?: Pagination;
+    /// Column names, optionally prefixed with "-" for descending order.
+    order?: ({columns_type} | `-${{{columns_type}}}` | `+${{{columns_type}}}`)[];
+    /// Filters built with `filter()`.
+    filters?: string[];
+    count?: boolean;
+    expand?: ({expand_type})[];
+  }}): Promise<ListResponse<{select}>> {{
+    return this.api.list<{select}>(opts);
+  }}
+
+  public read(
+    id: string | number,
+    opts?: {{ expand?: ({expand_type})[] }},
+  ): Promise<{select}> {{
+    return this.api.read<{select}>(id, opts);
+  }}
+"#,
+    api_name = quote(&api.api_name),
+  );
+
+  if let (Some(insert), Some(update)) = (&api.insert, &api.update) {
+    let _ = write!(
+      out,
+      r#"
+  public create(record: {insert}): Promise<string | number> {{
+    return this.api.create<{insert}>(record);
+  }}
+
+  public createBulk(records: {insert}[]): Promise<(string | number)[]> {{
+    return this.api.createBulk<{insert}>(records);
+  }}
+
+  public update(id: string | number, record: {update}): Promise<void> {{
+    return this.api.update<{update}>(id, record);
+  }}
+
+  public delete(id: string | number): Promise<void> {{
+    return this.api.delete(id);
+  }}
+"#
+    );
+  }
+
+  let _ = writeln!(out, "}}\n");
+}
+
+fn render_client(out: &mut String, schema: &ClientSchema) {
+  let _ = write!(
+    out,
+    r#"/// Typed entry point bundling auth helpers and accessors for all record APIs.
+export class TypedClient {{
+  public readonly client: Client;
+
+  constructor(site?: URL | string, opts?: ClientOptions) {{
+    this.client = Client.init(site, opts);
+  }}
+
+  public user(): User | undefined {{
+    return this.client.user();
+  }}
+
+  public login(email: string, password: string): Promise<void> {{
+    return this.client.login(email, password);
+  }}
+
+  public logout(): Promise<boolean> {{
+    return this.client.logout();
+  }}
+"#
+  );
+
+  for api in &schema.apis {
+    let _ = write!(
+      out,
+      r#"
+  public get {accessor}(): {class_name}Api {{
+    return new {class_name}Api(this.client);
+  }}
+"#,
+      accessor = camel_case(&api.api_name),
+      class_name = pascal_case(&api.api_name),
+    );
+  }
+
+  let _ = writeln!(out, "}}");
+}
+
+pub(super) fn render(schema: &ClientSchema) -> String {
+  let mut out = HEADER.to_string();
+  out.push('\n');
+
+  // Stable codes of API error responses, see `FetchError.code`.
+  let _ = writeln!(
+    out,
+    "export type ErrorCode = {};\n",
+    ts_type(&FieldType::Enum(error_codes()))
+  );
+
+  for model in &schema.models {
+    render_model(&mut out, model);
+  }
+
+  for api in &schema.apis {
+    render_api(&mut out, api);
+  }
+
+  render_client(&mut out, schema);
+
+  return out;
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::codegen::Field;
+
+  #[test]
+  fn test_render_model() {
+    let mut out = String::new();
+    render_model(
+      &mut out,
+      &Model {
+        name: "Article".to_string(),
+        fields: vec![
+          Field {
+            name: "id".to_string(),
+            ty: FieldType::Integer,
+            required: true,
+          },
+          Field {
+            name: "tags".to_string(),
+            ty: FieldType::Array(Box::new(FieldType::String)),
+            required: false,
+          },
+          Field {
+            name: "my-state".to_string(),
+            ty: FieldType::Enum(vec!["a".to_string(), "b".to_string()]),
+            required: true,
+          },
+        ],
+      },
+    );
+
+    assert_eq!(
+      out,
+      "export interface Article {\n  id: number;\n  tags?: string[];\n  \"my-state\": \"a\" | \"b\";\n}\n\n"
+    );
+  }
+}
@@ -3,20 +3,26 @@ use log::*;
 use prost_reflect::{
   DynamicMessage, ExtensionDescriptor, FieldDescriptor, Kind, MapKey, ReflectMessage, Value,
 };
-use proto::{EmailTemplate, OAuthProviderId};
+use proto::{
+  ChangeDataCaptureSink, EmailTemplate, EmbeddingProviderType, OAuthProviderId, SynchronousMode,
+};
 use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
-use std::str::FromStr;
 use thiserror::Error;
 use tokio::fs;
 use validator::{ValidateEmail, ValidateUrl};
 
 use crate::DESCRIPTOR_POOL;
-use crate::auth::oauth::providers::oauth_provider_registry;
+use crate::auth::oauth::providers::{OIDC_CLAIM_PROPERTIES, oauth_provider_registry};
 use crate::data_dir::DataDir;
+use crate::records::encryption::{ENCRYPTION_KEYS_ENV_VAR, encryption_keys};
 use crate::records::validate_record_api_config;
+use crate::retention::validate_retention_policy;
+use crate::scheduler::parse_schedule;
 use crate::schema_metadata::SchemaMetadataCache;
 
+pub mod builder;
+
 #[derive(Debug, Error)]
 pub enum ConfigError {
   #[error("Decode error: {0}")]
@@ -153,6 +159,19 @@ pub mod proto {
         delete_access_rule: Some("_ROW_.user = _USER_.id".to_string()),
         schema_access_rule: None,
         expand: vec![],
+        column_validators: vec![],
+        response_format: None,
+        embedding: None,
+        cache_ttl_sec: None,
+        cache_max_bytes: None,
+        list_limits: None,
+        encrypted_columns: vec![],
+        soft_delete_column: None,
+        max_expand_depth: None,
+        require_sortable_primary_key: None,
+        webhooks: vec![],
+        rate_limit: None,
+        enable_sync: None,
       }];
 
       return config;
@@ -333,6 +352,9 @@ pub(crate) fn merge_vault_and_env(
 
 const PLACEHOLDER: &str = "<REDACTED>";
 
+/// Upper bound for `server.read_connections`, each of which is backed by its own thread.
+const MAX_READ_CONNECTIONS: u32 = 64;
+
 fn recursively_redact_secrets(
   msg: &mut DynamicMessage,
   secrets: &mut HashMap<String, String>,
@@ -451,6 +473,30 @@ pub async fn load_or_init_config_textproto(
   return Ok(merged_config);
 }
 
+/// Reads the config without merging secrets or validating it, e.g. to access settings needed
+/// before the main database can be opened. Returns None if there's no config yet.
+pub(crate) async fn read_config_textproto_unvalidated(
+  data_dir: &DataDir,
+) -> Result<Option<proto::Config>, ConfigError> {
+  return match fs::read_to_string(data_dir.config_path().join(CONFIG_FILENAME)).await {
+    Ok(contents) => Ok(Some(proto::Config::from_text(&contents)?)),
+    Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+    Err(err) => Err(err.into()),
+  };
+}
+
+/// Like `read_config_textproto_unvalidated` but with secrets from the vault and the environment
+/// merged in, e.g. to access the object store before the main database exists.
+pub(crate) async fn read_config_with_secrets_unvalidated(
+  data_dir: &DataDir,
+) -> Result<Option<proto::Config>, ConfigError> {
+  let Some(config) = read_config_textproto_unvalidated(data_dir).await? else {
+    return Ok(None);
+  };
+  let vault = load_vault_textproto_or_default(data_dir).await?;
+  return Ok(Some(merge_vault_and_env(config, vault)?));
+}
+
 fn split_config(config: &proto::Config) -> Result<(proto::Config, proto::Vault), ConfigError> {
   let mut new_vault = proto::Vault::default();
   let (stripped_config, secrets) = redact_secrets(config)?;
@@ -503,6 +549,55 @@ fn validate_application_name(name: &str) -> Result<(), ConfigError> {
   Ok(())
 }
 
+pub(crate) fn collation_spec_from_config(
+  config: &proto::CollationConfig,
+) -> Option<trailbase_extension::collation::CollationSpec> {
+  let (Some(name), Some(locale)) = (&config.name, &config.locale) else {
+    warn!("Collation config entry missing name or locale: {config:?}");
+    return None;
+  };
+
+  return Some(trailbase_extension::collation::CollationSpec {
+    name: name.clone(),
+    locale: locale.clone(),
+    case_insensitive: config.case_insensitive.unwrap_or(false),
+  });
+}
+
+/// Checks that page size limits are positive and consistent, i.e. default limits don't exceed the
+/// corresponding max limits.
+pub(crate) fn validate_list_limits(config: &proto::ListLimitsConfig) -> Result<(), ConfigError> {
+  fn check(default_limit: Option<u32>, max_limit: Option<u32>) -> Result<(), ConfigError> {
+    if default_limit == Some(0) || max_limit == Some(0) {
+      return Err(ConfigError::Invalid(
+        "List limits must be positive".to_string(),
+      ));
+    }
+    if let (Some(default_limit), Some(max_limit)) = (default_limit, max_limit) {
+      if default_limit > max_limit {
+        return Err(ConfigError::Invalid(format!(
+          "Default list limit {default_limit} exceeds max limit {max_limit}"
+        )));
+      }
+    }
+    return Ok(());
+  }
+
+  check(config.default_limit, config.max_limit)?;
+  for role in &config.roles {
+    if role.role.as_ref().is_none_or(|r| r.is_empty()) {
+      return Err(ConfigError::Invalid(
+        "Role list limits missing role".to_string(),
+      ));
+    }
+    check(
+      role.default_limit.or(config.default_limit),
+      role.max_limit.or(config.max_limit),
+    )?;
+  }
+  return Ok(());
+}
+
 pub(crate) fn validate_config(
   tables: &SchemaMetadataCache,
   config: &proto::Config,
@@ -523,6 +618,67 @@ pub(crate) fn validate_config(
     }
   }
 
+  // Check file storage.
+  match (
+    &config.server.s3_storage_config,
+    &config.server.gcs_storage_config,
+  ) {
+    (Some(_), Some(_)) => {
+      return ierr("Only one of S3 and GCS storage can be configured");
+    }
+    (Some(s3), None) if s3.bucket_name.is_none() => {
+      return ierr("S3 storage config missing bucket name");
+    }
+    (None, Some(gcs)) if gcs.bucket_name.is_none() => {
+      return ierr("GCS storage config missing bucket name");
+    }
+    _ => {}
+  };
+
+  if let Some(ref signed_file_urls) = config.server.signed_file_urls {
+    if signed_file_urls.ttl_sec.is_some_and(|ttl| ttl <= 0) {
+      return ierr("Signed file URL TTL must be positive");
+    }
+    if signed_file_urls
+      .signing_key
+      .as_ref()
+      .is_some_and(|key| key.is_empty())
+    {
+      return ierr("Signed file URL signing key must not be empty");
+    }
+  }
+
+  if let Some(ref list_limits) = config.server.list_limits {
+    validate_list_limits(list_limits)?;
+  }
+  if let Some(ref source) = config.server.client_ip_source {
+    crate::client_ip::validate_client_ip_source(source)?;
+  }
+
+  // Check attached databases and tenants.
+  let mut database_names = HashSet::<String>::new();
+  for database in &config.databases {
+    let Some(ref name) = database.name else {
+      return ierr("Database config missing name");
+    };
+    if let Err(err) = crate::connection::validate_database_name(name) {
+      return ierr(err.to_string());
+    }
+    if !database_names.insert(name.to_ascii_lowercase()) {
+      return ierr(format!("Duplicate database: '{name}'"));
+    }
+    if database.per_tenant.unwrap_or(false) && config.tenants.is_none() {
+      return ierr(format!("Per-tenant database '{name}' requires tenants"));
+    }
+  }
+  // SQLite's default limit of attached databases.
+  if database_names.len() > 10 {
+    return ierr("At most 10 databases can be attached");
+  }
+  if let Some(ref tenants) = config.tenants {
+    crate::tenant::validate_tenants_config(tenants)?;
+  }
+
   // Check RecordApis.
   //
   // Note: it is valid to declare multiple api (e.g. with different acls) over the same
@@ -538,6 +694,138 @@ pub(crate) fn validate_config(
     }
   }
 
+  // Check embedding providers.
+  for (name, provider) in &config.server.embedding_providers {
+    let provider_type: EmbeddingProviderType = provider
+      .provider_type
+      .unwrap_or(0)
+      .try_into()
+      .map_err(|_| ConfigError::Invalid("Invalid embedding provider type".into()))?;
+    if provider_type == EmbeddingProviderType::Undefined {
+      return ierr(format!("Missing type for embedding provider: {name}"));
+    }
+
+    if provider.model.is_none() {
+      return ierr(format!("Missing model for embedding provider: {name}"));
+    }
+
+    if let Some(ref url) = provider.url {
+      if !url.validate_url() {
+        return ierr(format!("Invalid url for embedding provider: {name}"));
+      }
+    }
+  }
+
+  for api in &config.record_apis {
+    if let Some(provider) = api.embedding.as_ref().and_then(|e| e.provider.as_ref()) {
+      if !config.server.embedding_providers.contains_key(provider) {
+        return ierr(format!("Missing embedding provider: {provider}"));
+      }
+    }
+  }
+
+  // Check webhooks.
+  for api in &config.record_apis {
+    for webhook in &api.webhooks {
+      if !webhook.url.validate_url() || webhook.url.is_none() {
+        return ierr(format!("Invalid webhook url for: {:?}", api.name));
+      }
+    }
+  }
+  if config
+    .record_apis
+    .iter()
+    .any(|api| !api.webhooks.is_empty())
+    && config
+      .server
+      .webhook_signing_key
+      .as_ref()
+      .is_none_or(|key| key.is_empty())
+  {
+    return ierr("Webhooks require a 'server.webhook_signing_key'");
+  }
+
+  // Check change data capture.
+  if let Some(ref cdc) = config.server.change_data_capture {
+    let sink: ChangeDataCaptureSink = cdc
+      .sink
+      .unwrap_or(0)
+      .try_into()
+      .map_err(|_| ConfigError::Invalid("Invalid change data capture sink".into()))?;
+    if sink == ChangeDataCaptureSink::Undefined {
+      return ierr("Missing change data capture sink");
+    }
+    if cdc.url.as_ref().is_none_or(|url| url.is_empty()) {
+      return ierr("Missing change data capture url");
+    }
+
+    for table_name in &cdc.tables {
+      let Some(table) = tables.get_table(table_name) else {
+        return ierr(format!(
+          "Missing table for change data capture: {table_name}"
+        ));
+      };
+      if table.schema.virtual_table || table_name.starts_with('_') {
+        return ierr(format!(
+          "Change data capture not supported for table: {table_name}"
+        ));
+      }
+    }
+  }
+
+  // Check rate limits.
+  let rate_limits = config.server.rate_limits.iter().flat_map(|limits| {
+    [
+      ("records", &limits.records),
+      ("auth", &limits.auth),
+      ("query", &limits.query),
+    ]
+  });
+  let api_rate_limits = config
+    .record_apis
+    .iter()
+    .map(|api| (api.name.as_deref().unwrap_or_default(), &api.rate_limit));
+  for (name, limit) in rate_limits.chain(api_rate_limits) {
+    if limit
+      .as_ref()
+      .is_some_and(|l| l.requests_per_minute.unwrap_or(0) == 0 || l.burst == Some(0))
+    {
+      return ierr(format!("Invalid rate limit for: {name}"));
+    }
+  }
+
+  if config
+    .server
+    .read_connections
+    .is_some_and(|n| n > MAX_READ_CONNECTIONS)
+  {
+    return ierr(format!(
+      "Read connections exceed maximum of {MAX_READ_CONNECTIONS}"
+    ));
+  }
+
+  if let Some(ref backup) = config.server.backup {
+    if backup.retention_count == Some(0) {
+      return ierr("Backup retention count must be positive");
+    }
+    if backup.encrypt == Some(true) && encryption_keys().is_none() {
+      return ierr(format!(
+        "Encrypted backups require {ENCRYPTION_KEYS_ENV_VAR}"
+      ));
+    }
+  }
+
+  if let Some(ref replication) = config.server.replication {
+    if replication.sync_interval_ms == Some(0) || replication.retention_generations == Some(0) {
+      return ierr("Replication sync interval and retention must be positive");
+    }
+  }
+
+  // Check data retention policies.
+  for policy in &config.jobs.retention_policies {
+    validate_retention_policy(tables, policy)?;
+  }
+
   // Check OAuth.
   for (name, provider) in &config.auth.oauth_providers {
     let provider_id: OAuthProviderId = provider
@@ -580,6 +868,25 @@ pub(crate) fn validate_config(
       if !provider.user_api_url.validate_url() {
         return ierr(format!("Invalid user api url for: {name}"));
       }
+
+      if !provider.discovery_url.validate_url() {
+        return ierr(format!("Invalid discovery url for: {name}"));
+      }
+
+      // Without discovery, all endpoints need to be configured explicitly.
+      if provider.discovery_url.is_none()
+        && (provider.auth_url.is_none()
+          || provider.token_url.is_none()
+          || provider.user_api_url.is_none())
+      {
+        return ierr(format!("Missing discovery or endpoint urls for: {name}"));
+      }
+
+      for property in provider.claim_mapping.keys() {
+        if !OIDC_CLAIM_PROPERTIES.contains(&property.as_str()) {
+          return ierr(format!("Invalid claim mapping '{property}' for: {name}"));
+        }
+      }
     }
   }
 
@@ -590,7 +897,11 @@ pub(crate) fn validate_config(
     }
 
     let Some(schema_text) = &schema.schema else {
-      return ierr("Missing schema");
+      match &schema.url {
+        Some(url) if url::Url::parse(url).is_ok() => continue,
+        Some(_) => return ierr("Invalid schema url"),
+        None => return ierr("Missing schema"),
+      }
     };
 
     let schema_json: serde_json::Value = serde_json::from_str(schema_text)
@@ -602,6 +913,106 @@ pub(crate) fn validate_config(
     }
   }
 
+  // Check collations.
+  let mut collation_names = HashSet::<String>::new();
+  for collation in &config.collations {
+    let Some(spec) = collation_spec_from_config(collation) else {
+      return ierr("Collation misses name or locale");
+    };
+
+    if let Err(err) = trailbase_extension::collation::validate_collation(&spec) {
+      return ierr(format!("Invalid collation '{}': {err}", spec.name));
+    }
+
+    if !collation_names.insert(spec.name.clone()) {
+      return ierr(format!("Duplicate collation: {}", spec.name));
+    }
+  }
+
+  // Check pragma profiles.
+  let mut profile_names = HashSet::<String>::new();
+  for profile in &config.pragma_profiles {
+    let Some(name) = profile.name.as_ref().filter(|n| !n.is_empty()) else {
+      return ierr("Pragma profile misses name");
+    };
+
+    if profile.mmap_size.is_some_and(|size| size < 0) {
+      return ierr(format!("Negative mmap_size for pragma profile: {name}"));
+    }
+
+    if let Some(synchronous) = profile.synchronous {
+      if SynchronousMode::try_from(synchronous).is_err() {
+        return ierr(format!(
+          "Invalid synchronous mode for pragma profile: {name}"
+        ));
+      }
+    }
+
+    if !profile_names.insert(name.clone()) {
+      return ierr(format!("Duplicate pragma profile: {name}"));
+    }
+  }
+
+  for profile in [
+    &config.server.writer_pragma_profile,
+    &config.server.reader_pragma_profile,
+    &config
+      .server
+      .sql_api
+      .as_ref()
+      .and_then(|c| c.pragma_profile.clone()),
+  ]
+  .into_iter()
+  .flatten()
+  {
+    if !profile_names.contains(profile) {
+      return ierr(format!("Missing pragma profile: {profile}"));
+    }
+  }
+
+  // Check SQLite extensions.
+  let mut extension_paths = HashSet::<&str>::new();
+  for extension in &config.sqlite_extensions {
+    let Some(path) = extension.path.as_deref().filter(|p| !p.is_empty()) else {
+      return ierr("SQLite extension misses path");
+    };
+
+    if !extension_paths.insert(path) {
+      return ierr(format!("Duplicate SQLite extension: {path}"));
+    }
+
+    if extension.entry_point.as_deref() == Some("") {
+      return ierr(format!("Empty entry point for SQLite extension: {path}"));
+    }
+  }
+
+  // Check error messages.
+  let mut error_messages = HashSet::<(String, &str)>::new();
+  for error_message in &config.error_messages {
+    let Some(language) = error_message.language.as_deref().filter(|l| !l.is_empty()) else {
+      return ierr("Error message misses language");
+    };
+    let Some(code) = error_message.code.as_deref() else {
+      return ierr(format!("Error message for '{language}' misses code"));
+    };
+    if crate::problem::ErrorCode::from_code(code).is_none() {
+      return ierr(format!("Unknown error code: {code}"));
+    }
+    if error_message
+      .message
+      .as_deref()
+      .is_none_or(|m| m.is_empty())
+    {
+      return ierr(format!("Empty error message for '{code}' in '{language}'"));
+    }
+
+    if !error_messages.insert((language.to_ascii_lowercase(), code)) {
+      return ierr(format!(
+        "Duplicate error message for '{code}' in '{language}'"
+      ));
+    }
+  }
+
   // Check email config.
   {
     let email = &config.email;
@@ -673,11 +1084,37 @@ pub(crate) fn validate_config(
       return ierr(format!("Job '{id}' is missing schedule."));
     };
 
-    if let Err(err) = cron::Schedule::from_str(schedule) {
+    if let Err(err) = parse_schedule(schedule) {
       return ierr(format!("Schedule of job '{id}' not valid cron: {err}"));
     }
   }
 
+  let mut cron_job_names = HashSet::<String>::new();
+  for job in &config.jobs.cron_jobs {
+    let Some(ref name) = job.name else {
+      return ierr("Cron job is missing name.");
+    };
+    if name.is_empty() {
+      return ierr("Cron job name must not be empty.");
+    }
+    if !cron_job_names.insert(name.clone()) {
+      return ierr(format!("Duplicate cron job name: '{name}'."));
+    }
+
+    let Some(ref schedule) = job.schedule else {
+      return ierr(format!("Cron job '{name}' is missing schedule."));
+    };
+    if let Err(err) = parse_schedule(schedule) {
+      return ierr(format!(
+        "Schedule of cron job '{name}' not valid cron: {err}"
+      ));
+    }
+
+    if job.query.as_ref().is_none_or(|q| q.trim().is_empty()) {
+      return ierr(format!("Cron job '{name}' is missing query."));
+    }
+  }
+
   return Ok(());
 }
 
@@ -0,0 +1,329 @@
+//! Typed builders for constructing configs in code, e.g. when embedding TrailBase or in tests,
+//! rather than writing textproto.
+//!
+//! ```no_run
+//! # use trailbase::config::builder::{AuthBuilder, ConfigBuilder, RecordApiBuilder};
+//! # use trailbase::config::proto::PermissionFlag;
+//! # async fn configure(state: &trailbase::AppState) -> Result<(), trailbase::config::ConfigError> {
+//! ConfigBuilder::from(state.get_config())
+//!   .record_api(
+//!     RecordApiBuilder::new("messages", "message")
+//!       .acl_authenticated([PermissionFlag::Create, PermissionFlag::Read])
+//!       .create_access_rule("_REQ_.owner = _USER_.id")
+//!       .build(),
+//!   )
+//!   .auth(AuthBuilder::new().password_minimal_length(12).build())
+//!   .apply(state)
+//!   .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use chrono::Duration;
+
+use crate::app_state::AppState;
+use crate::config::proto::{
+  AuthConfig, ColumnValidatorConfig, Config, ConflictResolutionStrategy, OAuthProviderConfig,
+  PermissionFlag, RecordApiConfig, ResponseFormat,
+};
+use crate::config::{ConfigError, validate_config};
+
+/// Builds a [`Config`] on top of an existing one or the defaults.
+#[derive(Clone, Debug)]
+pub struct ConfigBuilder {
+  config: Config,
+}
+
+impl Default for ConfigBuilder {
+  fn default() -> Self {
+    return Self::from(Config::new_with_custom_defaults());
+  }
+}
+
+impl From<Config> for ConfigBuilder {
+  fn from(config: Config) -> Self {
+    return Self { config };
+  }
+}
+
+impl ConfigBuilder {
+  /// Starts from the same defaults a new data directory is initialized with.
+  pub fn new() -> Self {
+    return Self::default();
+  }
+
+  pub fn application_name(mut self, name: impl Into<String>) -> Self {
+    self.config.server.application_name = Some(name.into());
+    return self;
+  }
+
+  pub fn site_url(mut self, url: impl Into<String>) -> Self {
+    self.config.server.site_url = Some(url.into());
+    return self;
+  }
+
+  /// Adds a record API or replaces an existing one with the same name.
+  pub fn record_api(mut self, api: RecordApiConfig) -> Self {
+    match self
+      .config
+      .record_apis
+      .iter_mut()
+      .find(|existing| existing.name == api.name)
+    {
+      Some(existing) => *existing = api,
+      None => self.config.record_apis.push(api),
+    };
+    return self;
+  }
+
+  /// Removes the record API with the given name if present.
+  pub fn remove_record_api(mut self, name: &str) -> Self {
+    self
+      .config
+      .record_apis
+      .retain(|api| api.name.as_deref() != Some(name));
+    return self;
+  }
+
+  pub fn auth(mut self, auth: AuthConfig) -> Self {
+    self.config.auth = auth;
+    return self;
+  }
+
+  /// Returns the config without validation.
+  pub fn build(self) -> Config {
+    return self.config;
+  }
+
+  /// Validates the config against the live schema, e.g. that record APIs reference existing
+  /// tables and access rules are valid SQL.
+  pub fn build_validated(self, state: &AppState) -> Result<Config, ConfigError> {
+    validate_config(state.schema_metadata(), &self.config)?;
+    return Ok(self.config);
+  }
+
+  /// Validates the config against the live schema and makes it the server's active config. Like
+  /// admin UI changes, it's not written to the data directory.
+  pub async fn apply(self, state: &AppState) -> Result<(), ConfigError> {
+    return state.validate_and_update_config(self.config, None).await;
+  }
+}
+
+/// Builds a [`RecordApiConfig`]. APIs grant no access until permissions are added.
+#[derive(Clone, Debug)]
+pub struct RecordApiBuilder {
+  config: RecordApiConfig,
+}
+
+impl RecordApiBuilder {
+  pub fn new(name: impl Into<String>, table_name: impl Into<String>) -> Self {
+    return Self {
+      config: RecordApiConfig {
+        name: Some(name.into()),
+        table_name: Some(table_name.into()),
+        ..Default::default()
+      },
+    };
+  }
+
+  /// Permissions granted to everyone including anonymous users.
+  pub fn acl_world(mut self, flags: impl IntoIterator<Item = PermissionFlag>) -> Self {
+    self.config.acl_world = flags.into_iter().map(|flag| flag as i32).collect();
+    return self;
+  }
+
+  /// Permissions granted to authenticated users.
+  pub fn acl_authenticated(mut self, flags: impl IntoIterator<Item = PermissionFlag>) -> Self {
+    self.config.acl_authenticated = flags.into_iter().map(|flag| flag as i32).collect();
+    return self;
+  }
+
+  /// SQL expression further restricting inserts, see `RecordApiConfig::create_access_rule`.
+  pub fn create_access_rule(mut self, rule: impl Into<String>) -> Self {
+    self.config.create_access_rule = Some(rule.into());
+    return self;
+  }
+
+  pub fn read_access_rule(mut self, rule: impl Into<String>) -> Self {
+    self.config.read_access_rule = Some(rule.into());
+    return self;
+  }
+
+  pub fn update_access_rule(mut self, rule: impl Into<String>) -> Self {
+    self.config.update_access_rule = Some(rule.into());
+    return self;
+  }
+
+  pub fn delete_access_rule(mut self, rule: impl Into<String>) -> Self {
+    self.config.delete_access_rule = Some(rule.into());
+    return self;
+  }
+
+  pub fn schema_access_rule(mut self, rule: impl Into<String>) -> Self {
+    self.config.schema_access_rule = Some(rule.into());
+    return self;
+  }
+
+  pub fn conflict_resolution(mut self, strategy: ConflictResolutionStrategy) -> Self {
+    self.config.conflict_resolution = Some(strategy as i32);
+    return self;
+  }
+
+  pub fn autofill_missing_user_id_columns(mut self, autofill: bool) -> Self {
+    self.config.autofill_missing_user_id_columns = Some(autofill);
+    return self;
+  }
+
+  pub fn enable_subscriptions(mut self, enable: bool) -> Self {
+    self.config.enable_subscriptions = Some(enable);
+    return self;
+  }
+
+  pub fn excluded_columns(mut self, columns: impl IntoIterator<Item = impl Into<String>>) -> Self {
+    self.config.excluded_columns = columns.into_iter().map(Into::into).collect();
+    return self;
+  }
+
+  /// Foreign key columns that can be expanded on read and list.
+  pub fn expand(mut self, columns: impl IntoIterator<Item = impl Into<String>>) -> Self {
+    self.config.expand = columns.into_iter().map(Into::into).collect();
+    return self;
+  }
+
+  /// Maximum number of levels of nested expansions, e.g. 2 for `expand=author.team`.
+  pub fn max_expand_depth(mut self, depth: u32) -> Self {
+    self.config.max_expand_depth = Some(depth);
+    return self;
+  }
+
+  pub fn column_validator(mut self, validator: ColumnValidatorConfig) -> Self {
+    self.config.column_validators.push(validator);
+    return self;
+  }
+
+  pub fn response_format(mut self, format: ResponseFormat) -> Self {
+    self.config.response_format = Some(format as i32);
+    return self;
+  }
+
+  pub fn build(self) -> RecordApiConfig {
+    return self.config;
+  }
+}
+
+/// Builds an [`AuthConfig`] starting from the defaults.
+#[derive(Clone, Debug, Default)]
+pub struct AuthBuilder {
+  config: AuthConfig,
+}
+
+impl AuthBuilder {
+  pub fn new() -> Self {
+    return Self::default();
+  }
+
+  pub fn auth_token_ttl(mut self, ttl: Duration) -> Self {
+    self.config.auth_token_ttl_sec = Some(ttl.num_seconds());
+    return self;
+  }
+
+  pub fn refresh_token_ttl(mut self, ttl: Duration) -> Self {
+    self.config.refresh_token_ttl_sec = Some(ttl.num_seconds());
+    return self;
+  }
+
+  /// Disables password-based sign-up. Does not affect already registered users.
+  pub fn disable_password_auth(mut self, disable: bool) -> Self {
+    self.config.disable_password_auth = Some(disable);
+    return self;
+  }
+
+  pub fn password_minimal_length(mut self, length: u32) -> Self {
+    self.config.password_minimal_length = Some(length);
+    return self;
+  }
+
+  pub fn password_must_contain_upper_and_lower_case(mut self, required: bool) -> Self {
+    self.config.password_must_contain_upper_and_lower_case = Some(required);
+    return self;
+  }
+
+  pub fn password_must_contain_digits(mut self, required: bool) -> Self {
+    self.config.password_must_contain_digits = Some(required);
+    return self;
+  }
+
+  pub fn password_must_contain_special_characters(mut self, required: bool) -> Self {
+    self.config.password_must_contain_special_characters = Some(required);
+    return self;
+  }
+
+  /// Adds an OAuth provider, e.g. "google", or replaces an existing one with the same name.
+  pub fn oauth_provider(mut self, name: impl Into<String>, provider: OAuthProviderConfig) -> Self {
+    self.config.oauth_providers.insert(name.into(), provider);
+    return self;
+  }
+
+  pub fn build(self) -> AuthConfig {
+    return self.config;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::app_state::test_state;
+
+  #[tokio::test]
+  async fn test_config_builder() {
+    let state = test_state(None).await.unwrap();
+    state
+      .conn()
+      .execute_batch("CREATE TABLE message (id INTEGER PRIMARY KEY, owner BLOB, text TEXT) STRICT;")
+      .await
+      .unwrap();
+    state.refresh_table_cache().await.unwrap();
+
+    let api = RecordApiBuilder::new("messages", "message")
+      .acl_world([PermissionFlag::Read])
+      .acl_authenticated([PermissionFlag::Create, PermissionFlag::Read])
+      .create_access_rule("_REQ_.owner = _USER_.id")
+      .build();
+    assert_eq!(api.acl_world, vec![PermissionFlag::Read as i32]);
+
+    let config = ConfigBuilder::from(state.get_config())
+      .record_api(api.clone())
+      .auth(AuthBuilder::new().password_minimal_length(12).build())
+      .build_validated(&state)
+      .unwrap();
+    assert_eq!(config.auth.password_minimal_length, Some(12));
+
+    // Replaces rather than duplicates APIs with the same name.
+    let config = ConfigBuilder::from(config)
+      .record_api(
+        RecordApiBuilder::new("messages", "message")
+          .acl_world([PermissionFlag::Read])
+          .build(),
+      )
+      .build();
+    assert_eq!(
+      config
+        .record_apis
+        .iter()
+        .filter(|api| api.name.as_deref() == Some("messages"))
+        .count(),
+      1
+    );
+
+    // Validated against the schema.
+    assert!(
+      ConfigBuilder::from(config.clone())
+        .record_api(RecordApiBuilder::new("missing", "missing_table").build())
+        .build_validated(&state)
+        .is_err()
+    );
+
+    ConfigBuilder::from(config).apply(&state).await.unwrap();
+    assert!(state.lookup_record_api("messages").is_some());
+  }
+}
@@ -1,10 +1,14 @@
-use parking_lot::Mutex;
-use std::path::PathBuf;
+use parking_lot::{Mutex, RwLock};
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
 use thiserror::Error;
 
+use crate::clock::Clock;
+use crate::config::proto::{Config, PragmaProfileConfig, SqliteExtensionConfig, SynchronousMode};
 use crate::data_dir::DataDir;
-use crate::migrations::{apply_logs_migrations, apply_main_migrations};
+use crate::migrations::{apply_database_migrations, apply_logs_migrations, apply_main_migrations};
 
+pub use trailbase_extension::LoadableExtension;
 pub use trailbase_sqlite::Connection;
 
 #[derive(Debug, Error)]
@@ -15,6 +19,112 @@ pub enum ConnectionError {
   Rusqlite(#[from] rusqlite::Error),
   #[error("Migration error: {0}")]
   Migration(#[from] trailbase_refinery_core::Error),
+  #[error("SQLite error: {0}")]
+  Sqlite(#[from] trailbase_sqlite::Error),
+  #[error("Extension error: {0}")]
+  Extension(String),
+  #[error("Database key error: {0}")]
+  Key(String),
+  #[error("Database error: {0}")]
+  Database(String),
+}
+
+pub const DATABASE_KEY_ENV_VAR: &str = "TRAIL_DATABASE_KEY";
+pub const DATABASE_KEY_FILE_ENV_VAR: &str = "TRAIL_DATABASE_KEY_FILE";
+
+/// SQLCipher key for at-rest encryption of the main and logs databases. Requires the `sqlcipher`
+/// feature.
+#[derive(Clone)]
+pub struct DatabaseKey(String);
+
+impl std::fmt::Debug for DatabaseKey {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    return f.write_str("DatabaseKey(<redacted>)");
+  }
+}
+
+impl DatabaseKey {
+  /// Passphrase, from which SQLCipher derives the actual key. Also accepts raw keys given as
+  /// "x'<64 hex digits>'".
+  pub fn passphrase(passphrase: impl Into<String>) -> Result<Self, ConnectionError> {
+    let passphrase: String = passphrase.into();
+    if passphrase.is_empty() {
+      return Err(ConnectionError::Key("empty key".to_string()));
+    }
+    return Ok(Self(passphrase));
+  }
+
+  /// Raw 256-bit key, e.g. fetched from a KMS, skipping SQLCipher's key derivation.
+  pub fn raw(key: &[u8; 32]) -> Self {
+    let hex: String = key.iter().map(|b| format!("{b:02x}")).collect();
+    return Self(format!("x'{hex}'"));
+  }
+
+  pub(crate) fn as_str(&self) -> &str {
+    return &self.0;
+  }
+}
+
+static DATABASE_KEY: LazyLock<RwLock<Result<Option<DatabaseKey>, String>>> = LazyLock::new(|| {
+  let key = if let Ok(key) = std::env::var(DATABASE_KEY_ENV_VAR) {
+    DatabaseKey::passphrase(key)
+      .map(Some)
+      .map_err(|err| format!("{DATABASE_KEY_ENV_VAR}: {err}"))
+  } else if let Ok(path) = std::env::var(DATABASE_KEY_FILE_ENV_VAR) {
+    std::fs::read_to_string(&path)
+      .map_err(|err| err.to_string())
+      .and_then(|contents| DatabaseKey::passphrase(contents.trim()).map_err(|err| err.to_string()))
+      .map(Some)
+      .map_err(|err| format!("{DATABASE_KEY_FILE_ENV_VAR} ({path}): {err}"))
+  } else {
+    Ok(None)
+  };
+  return RwLock::new(key);
+});
+
+/// Installs the database key, e.g. fetched from a KMS, taking precedence over
+/// `TRAIL_DATABASE_KEY` and `TRAIL_DATABASE_KEY_FILE`. Needs to be called before the server is
+/// initialized.
+pub fn set_database_key(key: Option<DatabaseKey>) {
+  *DATABASE_KEY.write() = Ok(key);
+}
+
+pub(crate) fn database_key() -> Result<Option<DatabaseKey>, ConnectionError> {
+  return DATABASE_KEY.read().clone().map_err(ConnectionError::Key);
+}
+
+/// Backup options for the main database, i.e. backups are encrypted with the database key.
+pub(crate) fn backup_options() -> trailbase_sqlite::BackupOptions {
+  return trailbase_sqlite::BackupOptions {
+    key: database_key()
+      .ok()
+      .flatten()
+      .map(|key| key.as_str().to_string()),
+    ..Default::default()
+  };
+}
+
+/// Opens a database file, e.g. a restored backup, with the database key applied.
+pub(crate) fn open_with_database_key(path: &Path) -> Result<rusqlite::Connection, ConnectionError> {
+  let conn = rusqlite::Connection::open(path)?;
+  if let Some(key) = database_key()? {
+    trailbase_extension::apply_key(&conn, key.as_str())?;
+  }
+  return Ok(conn);
+}
+
+/// Turns SQLite's generic "file is not a database" into an actionable error.
+fn map_key_error(err: trailbase_extension::Error, key: Option<&DatabaseKey>) -> ConnectionError {
+  if key.is_none() {
+    if let trailbase_extension::Error::Rusqlite(rusqlite::Error::SqliteFailure(ref e, _)) = err {
+      if e.code == rusqlite::ErrorCode::NotADatabase {
+        return ConnectionError::Key(format!(
+          "database is encrypted or corrupt, missing {DATABASE_KEY_ENV_VAR}? ({err})"
+        ));
+      }
+    }
+  }
+  return err.into();
 }
 
 /// Initializes a new SQLite Connection with all the default extensions, migrations and settings
@@ -23,29 +133,61 @@ pub enum ConnectionError {
 /// Returns a Connection and whether the DB was newly created..
 pub fn init_main_db(
   data_dir: Option<&DataDir>,
-  extensions: Option<Vec<PathBuf>>,
+  extensions: Option<Vec<LoadableExtension>>,
 ) -> Result<(Connection, bool), ConnectionError> {
-  let new_db = Mutex::new(false);
+  let key = match data_dir {
+    Some(_) => database_key()?,
+    None => None,
+  };
 
-  let main_path = data_dir.map(|d| d.main_db_path());
-  let migrations_path = data_dir.map(|d| d.migrations_path());
+  return init_main_db_at(
+    data_dir.map(|d| d.main_db_path()),
+    data_dir.map(|d| d.migrations_path()),
+    extensions,
+    None,
+    key,
+    Clock::system(),
+  );
+}
+
+/// Like [init_main_db] but with explicit DB and migrations paths. `main_path` may also be a URI,
+/// e.g. of a shared in-memory database, see [trailbase_sqlite::connection::shared_memory_uri].
+///
+/// `read_connections` overrides the default number of dedicated readers, see
+/// `server.read_connections`, and `key` encrypts the database at rest.
+pub(crate) fn init_main_db_at(
+  main_path: Option<PathBuf>,
+  migrations_path: Option<PathBuf>,
+  extensions: Option<Vec<LoadableExtension>>,
+  read_connections: Option<usize>,
+  key: Option<DatabaseKey>,
+  clock: Clock,
+) -> Result<(Connection, bool), ConnectionError> {
+  let new_db = Mutex::new(false);
+  let n_read_threads = match (&main_path, read_connections) {
+    (None, _) => 0,
+    (Some(_), Some(n)) => n,
+    (Some(_), None) => std::thread::available_parallelism().map_or(4, |n| n.get().clamp(2, 4)),
+  };
 
   let conn = trailbase_sqlite::Connection::new(
     || -> Result<_, ConnectionError> {
       trailbase_schema::registry::try_init_schemas();
 
-      let mut conn = trailbase_extension::connect_sqlite(main_path.clone(), extensions.clone())?;
+      let mut conn = trailbase_extension::connect_sqlite_with_key(
+        main_path.clone(),
+        extensions.clone(),
+        key.as_ref().map(DatabaseKey::as_str),
+      )
+      .map_err(|err| map_key_error(err, key.as_ref()))?;
+      clock.register_sqlite_functions(&conn)?;
 
       *(new_db.lock()) |= apply_main_migrations(&mut conn, migrations_path.clone())?;
 
       return Ok(conn);
     },
     Some(trailbase_sqlite::connection::Options {
-      n_read_threads: match (data_dir, std::thread::available_parallelism()) {
-        (None, _) => 0,
-        (Some(_), Ok(n)) => n.get().clamp(2, 4),
-        (Some(_), Err(_)) => 4,
-      },
+      n_read_threads,
       ..Default::default()
     }),
   )?;
@@ -53,15 +195,256 @@ pub fn init_main_db(
   return Ok((conn, *new_db.lock()));
 }
 
-pub(crate) fn init_logs_db(data_dir: Option<&DataDir>) -> Result<Connection, ConnectionError> {
+/// Opens a dedicated read-only connection to the existing main DB, e.g. for serving untrusted
+/// queries where the authorizer shouldn't be the only line of defense.
+pub(crate) fn init_read_only_main_db(
+  main_path: PathBuf,
+  extensions: Option<Vec<LoadableExtension>>,
+  key: Option<DatabaseKey>,
+) -> Result<Connection, ConnectionError> {
+  return trailbase_sqlite::Connection::new(
+    || -> Result<_, ConnectionError> {
+      return Ok(trailbase_extension::connect_sqlite_read_only(
+        main_path.clone(),
+        extensions.clone(),
+        key.as_ref().map(DatabaseKey::as_str),
+      )?);
+    },
+    None,
+  );
+}
+
+/// An additional database attached to the main database's connections, see `Config::databases`.
+#[derive(Clone, Debug)]
+pub(crate) struct AttachedDatabase {
+  pub name: String,
+  pub path: PathBuf,
+  /// Attach read-only, e.g. templates of per-tenant databases, which must not hold any records.
+  pub read_only: bool,
+}
+
+/// Valid database names are plain identifiers, since they're used as schema names.
+pub(crate) fn validate_database_name(name: &str) -> Result<(), ConnectionError> {
+  let valid = name.starts_with(|c: char| c.is_ascii_alphabetic())
+    && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    && !matches!(name.to_ascii_lowercase().as_str(), "main" | "temp");
+  if !valid {
+    return Err(ConnectionError::Database(format!(
+      "Invalid database name: '{name}'"
+    )));
+  }
+  return Ok(());
+}
+
+/// Creates the database file, if needed, and applies its migrations from
+/// `<data_dir>/database_migrations/<name>/`.
+pub(crate) fn init_database(
+  data_dir: &DataDir,
+  name: &str,
+  path: &Path,
+  extensions: Option<Vec<LoadableExtension>>,
+  key: Option<&DatabaseKey>,
+) -> Result<(), ConnectionError> {
+  validate_database_name(name)?;
+  if let Some(parent) = path.parent() {
+    std::fs::create_dir_all(parent).map_err(|err| ConnectionError::Database(err.to_string()))?;
+  }
+
+  let mut conn = trailbase_extension::connect_sqlite_with_key(
+    Some(path.to_path_buf()),
+    extensions,
+    key.map(DatabaseKey::as_str),
+  )
+  .map_err(|err| map_key_error(err, key))?;
+  apply_database_migrations(&mut conn, data_dir.database_migrations_path(name))?;
+
+  return Ok(());
+}
+
+/// Attaches the databases to all connections, i.e. the writer and all readers.
+pub(crate) async fn attach_databases(
+  conn: &Connection,
+  databases: &[AttachedDatabase],
+  key: Option<&DatabaseKey>,
+) -> Result<(), ConnectionError> {
+  if databases.is_empty() {
+    return Ok(());
+  }
+
+  let statements: Vec<(String, String)> = databases
+    .iter()
+    .map(|db| -> Result<_, ConnectionError> {
+      validate_database_name(&db.name)?;
+
+      let path = db.path.to_string_lossy().to_string();
+      return Ok((
+        format!(r#"ATTACH DATABASE ?1 AS "{}" KEY ?2"#, db.name),
+        if db.read_only {
+          format!("file:{path}?mode=ro")
+        } else {
+          path
+        },
+      ));
+    })
+    .collect::<Result<_, _>>()?;
+  let key = key.map_or("", DatabaseKey::as_str).to_string();
+
+  let attach = move |conn: &rusqlite::Connection| -> Result<(), trailbase_sqlite::Error> {
+    for (stmt, path) in &statements {
+      conn.execute(stmt, (path, &key))?;
+    }
+    return Ok(());
+  };
+
+  let writer_attach = attach.clone();
+  conn.call(move |conn| writer_attach(conn)).await?;
+  conn.call_readers(attach).await?;
+
+  return Ok(());
+}
+
+/// Resolves the configured SQLite extensions, only permitting libraries within the data
+/// directory's allow-listed extensions directory.
+pub(crate) fn resolve_sqlite_extensions(
+  data_dir: &DataDir,
+  configs: &[SqliteExtensionConfig],
+) -> Result<Vec<LoadableExtension>, ConnectionError> {
+  let allowed_dir = data_dir.extensions_path();
+
+  let mut extensions = vec![];
+  for config in configs {
+    let Some(ref path) = config.path else {
+      return Err(ConnectionError::Extension(
+        "SQLite extension misses path".to_string(),
+      ));
+    };
+
+    let path = allowed_dir.join(path).canonicalize().map_err(|err| {
+      ConnectionError::Extension(format!(
+        "SQLite extension '{path}' not found in {allowed_dir:?}: {err}"
+      ))
+    })?;
+
+    // Guard against escaping the directory, e.g. via '..' or symlinks.
+    let allowed_dir = allowed_dir
+      .canonicalize()
+      .map_err(|err| ConnectionError::Extension(format!("{allowed_dir:?}: {err}")))?;
+    if !path.starts_with(&allowed_dir) {
+      return Err(ConnectionError::Extension(format!(
+        "SQLite extension {path:?} is outside of allow-listed {allowed_dir:?}"
+      )));
+    }
+
+    extensions.push(LoadableExtension {
+      path,
+      entry_point: config.entry_point.clone(),
+    });
+  }
+
+  return Ok(extensions);
+}
+
+/// Applies the configured pragma profiles to the main DB's writer and reader connections as well
+/// as the SQL API's read-only connection.
+pub(crate) async fn apply_pragma_profiles(
+  config: &Config,
+  conn: &Connection,
+  read_only_conn: &Connection,
+) -> Result<(), ConnectionError> {
+  let find = |name: &Option<String>| -> Option<PragmaProfileConfig> {
+    let name = name.as_ref()?;
+    return config
+      .pragma_profiles
+      .iter()
+      .find(|p| p.name.as_ref() == Some(name))
+      .cloned();
+  };
+
+  if let Some(profile) = find(&config.server.writer_pragma_profile) {
+    conn
+      .call(move |conn| {
+        return Ok(apply_pragma_profile(conn, &profile)?);
+      })
+      .await?;
+  }
+
+  if let Some(profile) = find(&config.server.reader_pragma_profile) {
+    conn
+      .call_readers(move |conn| {
+        return Ok(apply_pragma_profile(conn, &profile)?);
+      })
+      .await?;
+  }
+
+  let sql_api_profile = config
+    .server
+    .sql_api
+    .as_ref()
+    .and_then(|c| find(&c.pragma_profile));
+  if let Some(profile) = sql_api_profile {
+    read_only_conn
+      .call(move |conn| {
+        return Ok(apply_pragma_profile(conn, &profile)?);
+      })
+      .await?;
+  }
+
+  return Ok(());
+}
+
+pub(crate) fn apply_pragma_profile(
+  conn: &rusqlite::Connection,
+  profile: &PragmaProfileConfig,
+) -> Result<(), rusqlite::Error> {
+  let mut pragmas: Vec<String> = vec![];
+  if let Some(mmap_size) = profile.mmap_size {
+    pragmas.push(format!("PRAGMA mmap_size = {mmap_size}"));
+  }
+  if let Some(cache_size) = profile.cache_size {
+    pragmas.push(format!("PRAGMA cache_size = {cache_size}"));
+  }
+  if let Some(synchronous) = profile
+    .synchronous
+    .and_then(|s| SynchronousMode::try_from(s).ok())
+  {
+    let mode = match synchronous {
+      SynchronousMode::Undefined => None,
+      SynchronousMode::Off => Some("OFF"),
+      SynchronousMode::Normal => Some("NORMAL"),
+      SynchronousMode::Full => Some("FULL"),
+      SynchronousMode::Extra => Some("EXTRA"),
+    };
+    if let Some(mode) = mode {
+      pragmas.push(format!("PRAGMA synchronous = {mode}"));
+    }
+  }
+
+  // NOTE: we're querying here since some pragmas return data.
+  for pragma in pragmas {
+    let mut stmt = conn.prepare(&pragma)?;
+    let mut rows = stmt.query([])?;
+    let _maybe_row = rows.next()?;
+  }
+
+  return Ok(());
+}
+
+pub(crate) fn init_logs_db(
+  data_dir: Option<&DataDir>,
+  key: Option<DatabaseKey>,
+) -> Result<Connection, ConnectionError> {
   let path = data_dir.map(|d| d.logs_db_path());
 
   return trailbase_sqlite::Connection::new(
     || -> Result<_, ConnectionError> {
+      let conn = open_rusqlite(path.clone())?;
+      if let Some(ref key) = key {
+        trailbase_extension::apply_key(&conn, key.as_str())?;
+      }
+      apply_default_pragmas_and_optimize(&conn)?;
+
       // NOTE: The logs db needs the trailbase extensions for the maxminddb geoip lookup.
-      let mut conn = trailbase_extension::sqlite3_extension_init(
-        connect_rusqlite_without_default_extensions_and_schemas(path.clone())?,
-      )?;
+      let mut conn = trailbase_extension::sqlite3_extension_init(conn)?;
 
       // Turn off secure_deletions, i.e. don't wipe the memory with zeros.
       conn.query_row("PRAGMA secure_delete = FALSE", (), |_row| Ok(()))?;
@@ -79,21 +462,325 @@ pub(crate) fn init_logs_db(data_dir: Option<&DataDir>) -> Result<Connection, Con
 pub(crate) fn connect_rusqlite_without_default_extensions_and_schemas(
   path: Option<PathBuf>,
 ) -> Result<rusqlite::Connection, rusqlite::Error> {
-  let conn = if let Some(p) = path {
-    use rusqlite::OpenFlags;
-    let flags = OpenFlags::SQLITE_OPEN_READ_WRITE
-      | OpenFlags::SQLITE_OPEN_CREATE
-      | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+  let conn = open_rusqlite(path)?;
+  apply_default_pragmas_and_optimize(&conn)?;
+  return Ok(conn);
+}
 
-    rusqlite::Connection::open_with_flags(p, flags)?
-  } else {
-    rusqlite::Connection::open_in_memory()?
+fn open_rusqlite(path: Option<PathBuf>) -> Result<rusqlite::Connection, rusqlite::Error> {
+  return match path {
+    Some(p) => {
+      use rusqlite::OpenFlags;
+      let flags = OpenFlags::SQLITE_OPEN_READ_WRITE
+        | OpenFlags::SQLITE_OPEN_CREATE
+        | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+
+      rusqlite::Connection::open_with_flags(p, flags)
+    }
+    None => rusqlite::Connection::open_in_memory(),
   };
+}
 
-  trailbase_extension::apply_default_pragmas(&conn)?;
+fn apply_default_pragmas_and_optimize(conn: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+  trailbase_extension::apply_default_pragmas(conn)?;
 
   // Initial optimize.
   conn.execute("PRAGMA optimize = 0x10002", ())?;
 
-  return Ok(conn);
+  return Ok(());
+}
+
+/// Re-encrypts the main and logs databases from the configured to the `new` key, e.g. to rotate
+/// keys or to encrypt existing plaintext databases. `None` decrypts the databases. Must not run
+/// concurrently with a server using the data directory.
+pub fn rekey_data_dir(
+  data_dir: &DataDir,
+  new: Option<&DatabaseKey>,
+) -> Result<(), ConnectionError> {
+  let old = database_key()?;
+  for path in [data_dir.main_db_path(), data_dir.logs_db_path()] {
+    if path.exists() {
+      rekey_database(&path, old.as_ref(), new)?;
+    }
+  }
+  return Ok(());
+}
+
+/// Re-encrypts the database at `path` from `old` to `new` keys. The database is exported into a
+/// new file, which then replaces the original.
+fn rekey_database(
+  path: &Path,
+  old: Option<&DatabaseKey>,
+  new: Option<&DatabaseKey>,
+) -> Result<(), ConnectionError> {
+  let conn = trailbase_extension::connect_sqlite_with_key(
+    Some(path.to_path_buf()),
+    None,
+    old.map(DatabaseKey::as_str),
+  )
+  .map_err(|err| map_key_error(err, old))?;
+
+  // Fold the WAL into the database file first.
+  conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", (), |_row| Ok(()))?;
+  let user_version: i64 = conn.query_row("PRAGMA user_version", (), |row| row.get(0))?;
+
+  let mut tmp = path.as_os_str().to_owned();
+  tmp.push(".rekey");
+  let tmp = PathBuf::from(tmp);
+  if tmp.exists() {
+    std::fs::remove_file(&tmp).map_err(|err| ConnectionError::Key(err.to_string()))?;
+  }
+
+  conn.execute(
+    "ATTACH DATABASE ?1 AS rekeyed KEY ?2",
+    (
+      tmp.to_string_lossy().to_string(),
+      new.map_or("", DatabaseKey::as_str).to_string(),
+    ),
+  )?;
+  conn.query_row("SELECT sqlcipher_export('rekeyed')", (), |_row| Ok(()))?;
+  conn.execute_batch(&format!(
+    "PRAGMA rekeyed.user_version = {user_version}; DETACH DATABASE rekeyed;"
+  ))?;
+  drop(conn);
+
+  let io_err = |err: std::io::Error| ConnectionError::Key(err.to_string());
+  std::fs::rename(&tmp, path).map_err(io_err)?;
+  for suffix in ["-wal", "-shm"] {
+    let mut file = path.as_os_str().to_owned();
+    file.push(suffix);
+    match std::fs::remove_file(PathBuf::from(file)) {
+      Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(io_err(err)),
+      _ => {}
+    };
+  }
+
+  return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[tokio::test]
+  async fn test_resolve_sqlite_extensions() {
+    let temp_dir = temp_dir::TempDir::new().unwrap();
+    let data_dir = DataDir(temp_dir.path().to_path_buf());
+    data_dir.ensure_directory_structure().await.unwrap();
+
+    std::fs::write(
+      data_dir.extensions_path().join("bogus.so"),
+      b"not a library",
+    )
+    .unwrap();
+
+    let ext = |path: &str| SqliteExtensionConfig {
+      path: Some(path.to_string()),
+      entry_point: None,
+    };
+
+    let extensions = resolve_sqlite_extensions(&data_dir, &[ext("bogus.so")]).unwrap();
+    assert_eq!(extensions.len(), 1);
+
+    // Missing and out-of-directory libraries are rejected.
+    assert!(resolve_sqlite_extensions(&data_dir, &[ext("missing.so")]).is_err());
+    std::fs::write(data_dir.root().join("outside.so"), b"").unwrap();
+    assert!(resolve_sqlite_extensions(&data_dir, &[ext("../outside.so")]).is_err());
+
+    // Loading failures surface the offending library.
+    match init_main_db(Some(&data_dir), Some(extensions)) {
+      Err(ConnectionError::SqliteExtension(trailbase_extension::Error::Extension {
+        path, ..
+      })) => {
+        assert!(path.ends_with("bogus.so"));
+      }
+      _ => panic!("expected extension error"),
+    };
+  }
+
+  #[tokio::test]
+  async fn test_shared_memory_main_db() {
+    let main_path = PathBuf::from(trailbase_sqlite::connection::shared_memory_uri(
+      "test_shared_memory_main_db",
+    ));
+
+    let (conn, new) = init_main_db_at(
+      Some(main_path.clone()),
+      None,
+      None,
+      None,
+      None,
+      Clock::system(),
+    )
+    .unwrap();
+    assert!(new);
+    let read_only_conn = init_read_only_main_db(main_path, None, None).unwrap();
+
+    conn
+      .execute("CREATE TABLE test (id INTEGER PRIMARY KEY) STRICT", ())
+      .await
+      .unwrap();
+    conn
+      .execute("INSERT INTO test (id) VALUES (1)", ())
+      .await
+      .unwrap();
+
+    // Both dedicated readers and the separate read-only connection see the same database.
+    let count: i64 = conn
+      .read_query_row_f("SELECT COUNT(*) FROM test", (), |row| row.get(0))
+      .await
+      .unwrap()
+      .unwrap();
+    assert_eq!(1, count);
+
+    let count: i64 = read_only_conn
+      .query_row_f("SELECT COUNT(*) FROM _user", (), |row| row.get(0))
+      .await
+      .unwrap()
+      .unwrap();
+    assert_eq!(0, count);
+    assert!(
+      read_only_conn
+        .execute("INSERT INTO test (id) VALUES (2)", ())
+        .await
+        .is_err()
+    );
+  }
+
+  #[tokio::test]
+  async fn test_read_only_db_and_pragma_profiles() {
+    let temp_dir = temp_dir::TempDir::new().unwrap();
+    let data_dir = DataDir(temp_dir.path().to_path_buf());
+    data_dir.ensure_directory_structure().await.unwrap();
+
+    let (conn, new) = init_main_db(Some(&data_dir), None).unwrap();
+    assert!(new);
+    let read_only_conn = init_read_only_main_db(data_dir.main_db_path(), None, None).unwrap();
+
+    let mut config = Config::new_with_custom_defaults();
+    config.pragma_profiles = vec![
+      PragmaProfileConfig {
+        name: Some("writer".to_string()),
+        synchronous: Some(SynchronousMode::Full as i32),
+        ..Default::default()
+      },
+      PragmaProfileConfig {
+        name: Some("console".to_string()),
+        cache_size: Some(-2000),
+        ..Default::default()
+      },
+    ];
+    config.server.writer_pragma_profile = Some("writer".to_string());
+    config.server.sql_api = Some(crate::config::proto::SqlApiConfig {
+      pragma_profile: Some("console".to_string()),
+      ..Default::default()
+    });
+
+    apply_pragma_profiles(&config, &conn, &read_only_conn)
+      .await
+      .unwrap();
+
+    // FULL == 2.
+    let synchronous: i64 = conn
+      .query_row_f("PRAGMA synchronous", (), |row| row.get(0))
+      .await
+      .unwrap()
+      .unwrap();
+    assert_eq!(2, synchronous);
+
+    let cache_size: i64 = read_only_conn
+      .query_row_f("PRAGMA cache_size", (), |row| row.get(0))
+      .await
+      .unwrap()
+      .unwrap();
+    assert_eq!(-2000, cache_size);
+
+    let count: i64 = read_only_conn
+      .query_row_f("SELECT COUNT(*) FROM _user", (), |row| row.get(0))
+      .await
+      .unwrap()
+      .unwrap();
+    assert_eq!(0, count);
+
+    assert!(
+      read_only_conn
+        .execute("CREATE TABLE foo (id INTEGER PRIMARY KEY)", ())
+        .await
+        .is_err()
+    );
+  }
+
+  #[test]
+  fn test_database_key() {
+    assert!(DatabaseKey::passphrase("").is_err());
+    assert_eq!(
+      DatabaseKey::raw(&[0xab; 32]).as_str(),
+      format!("x'{}'", "ab".repeat(32))
+    );
+    assert_eq!(
+      format!("{:?}", DatabaseKey::passphrase("secret").unwrap()),
+      "DatabaseKey(<redacted>)"
+    );
+  }
+
+  #[cfg(not(feature = "sqlcipher"))]
+  #[test]
+  fn test_database_key_requires_sqlcipher() {
+    let temp_dir = temp_dir::TempDir::new().unwrap();
+    let key = DatabaseKey::passphrase("secret").unwrap();
+
+    // Rather than silently storing plaintext.
+    assert!(matches!(
+      init_main_db_at(
+        Some(temp_dir.path().join("main.db")),
+        None,
+        None,
+        None,
+        Some(key),
+        Clock::system()
+      ),
+      Err(ConnectionError::SqliteExtension(
+        trailbase_extension::Error::Key(_)
+      ))
+    ));
+  }
+
+  #[cfg(feature = "sqlcipher")]
+  #[test]
+  fn test_rekey_database() {
+    let temp_dir = temp_dir::TempDir::new().unwrap();
+    let path = temp_dir.path().join("main.db");
+    let key0 = DatabaseKey::passphrase("secret0").unwrap();
+    let key1 = DatabaseKey::raw(&[1; 32]);
+
+    let open = |key: Option<&DatabaseKey>| {
+      return trailbase_extension::connect_sqlite_with_key(
+        Some(path.clone()),
+        None,
+        key.map(DatabaseKey::as_str),
+      );
+    };
+
+    // Plaintext -> key0.
+    open(None)
+      .unwrap()
+      .execute_batch("CREATE TABLE t (id INTEGER PRIMARY KEY); INSERT INTO t VALUES (1);")
+      .unwrap();
+    rekey_database(&path, None, Some(&key0)).unwrap();
+    assert!(open(None).is_err());
+
+    // key0 -> key1.
+    assert!(rekey_database(&path, Some(&key1), Some(&key0)).is_err());
+    rekey_database(&path, Some(&key0), Some(&key1)).unwrap();
+    assert!(matches!(
+      open(Some(&key0)),
+      Err(trailbase_extension::Error::Key(_))
+    ));
+
+    let count: i64 = open(Some(&key1))
+      .unwrap()
+      .query_row("SELECT COUNT(*) FROM t", (), |row| row.get(0))
+      .unwrap();
+    assert_eq!(count, 1);
+  }
 }
@@ -6,9 +6,25 @@ pub(crate) const USER_TABLE_ID_COLUMN: &str = "id";
 
 pub(crate) const SESSION_TABLE: &str = "_session";
 pub(crate) const AVATAR_TABLE: &str = "_user_avatar";
+pub(crate) const ROLE_TABLE: &str = "_role";
+pub(crate) const USER_ROLE_TABLE: &str = "_user_role";
+pub(crate) const API_KEY_TABLE: &str = "_api_key";
+pub(crate) const WEBHOOK_DELIVERY_TABLE: &str = "_webhook_delivery";
+pub(crate) const CDC_CHANGE_TABLE: &str = "_cdc_change";
+pub(crate) const CDC_OFFSET_TABLE: &str = "_cdc_offset";
+pub(crate) const AUDIT_LOG_TABLE: &str = "_audit_log";
+pub(crate) const JOB_RUN_TABLE: &str = "_job_run";
+pub(crate) const JOB_STATE_TABLE: &str = "_job_state";
+pub(crate) const SYNC_ROW_TABLE: &str = "_sync_row";
 
 pub(crate) const LOGS_TABLE_ID_COLUMN: &str = "id";
 pub const LOGS_RETENTION_DEFAULT: Duration = Duration::days(7);
+pub const AUDIT_LOG_RETENTION_DEFAULT: Duration = Duration::days(90);
+
+pub const WAL_TRUNCATE_THRESHOLD_DEFAULT: u64 = 64 * 1024 * 1024;
+
+/// Upper bound for request bodies, which includes multipart file uploads held in memory.
+pub(crate) const MAX_REQUEST_BODY_SIZE: usize = 10 * 1024 * 1024;
 
 pub const COOKIE_AUTH_TOKEN: &str = "auth_token";
 pub const COOKIE_REFRESH_TOKEN: &str = "refresh_token";
@@ -18,6 +34,16 @@ pub const COOKIE_OAUTH_STATE: &str = "oauth_state";
 // naming: https://datatracker.ietf.org/doc/html/draft-saintandre-xdash-00
 pub const HEADER_REFRESH_TOKEN: &str = "Refresh-Token";
 pub const HEADER_CSRF_TOKEN: &str = "CSRF-Token";
+pub const HEADER_API_KEY: &str = "Api-Key";
+/// Pagination cursor and total count for list responses, which don't have a JSON envelope, e.g.
+/// Arrow IPC streams.
+pub const HEADER_CURSOR: &str = "Cursor";
+pub const HEADER_TOTAL_COUNT: &str = "Total-Count";
+pub const HEADER_HAS_MORE: &str = "Has-More";
+/// Generated unless provided by the client, correlates responses with logs.
+pub const HEADER_REQUEST_ID: &str = "x-request-id";
+/// Trusted as the authenticated user in mock auth mode.
+pub const HEADER_TEST_USER: &str = "x-test-user";
 
 #[cfg(debug_assertions)]
 pub const DEFAULT_AUTH_TOKEN_TTL: Duration = Duration::minutes(2);
@@ -31,6 +57,10 @@ pub(crate) const REFRESH_TOKEN_LENGTH: usize = 32;
 
 // Public APIs
 pub const RECORD_API_PATH: &str = "api/records/v1";
+pub const TRANSACTION_API_PATH: &str = "api/transaction/v1";
+pub const SYNC_API_PATH: &str = "api/sync/v1";
 pub const QUERY_API_PATH: &str = "api/query/v1";
+pub const SQL_API_PATH: &str = "api/sql/v1";
+pub const GRAPHQL_API_PATH: &str = "api/graphql/v1";
 pub const AUTH_API_PATH: &str = "api/auth/v1";
 pub const ADMIN_API_PATH: &str = "api/_admin";
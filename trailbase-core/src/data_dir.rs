@@ -31,6 +31,21 @@ impl DataDir {
     return self.data_path().join("queue.db");
   }
 
+  /// File of an additional database, see `Config::databases`. For per-tenant databases, this is
+  /// the template only holding the schema.
+  pub fn database_path(&self, name: &str) -> PathBuf {
+    return self.data_path().join(format!("{name}.db"));
+  }
+
+  /// File of a per-tenant database for the given tenant.
+  pub fn tenant_database_path(&self, tenant: &str, name: &str) -> PathBuf {
+    return self
+      .data_path()
+      .join("tenants")
+      .join(tenant)
+      .join(format!("{name}.db"));
+  }
+
   pub fn data_path(&self) -> PathBuf {
     return self.0.join("data/");
   }
@@ -51,10 +66,40 @@ impl DataDir {
     return self.0.join("migrations/");
   }
 
+  /// Migrations of an additional database, see `Config::databases`.
+  pub fn database_migrations_path(&self, name: &str) -> PathBuf {
+    return self.0.join("database_migrations").join(name);
+  }
+
   pub fn uploads_path(&self) -> PathBuf {
     return self.0.join("uploads/");
   }
 
+  /// Allow-listed location of shared-library SQLite extensions.
+  pub fn extensions_path(&self) -> PathBuf {
+    return self.0.join("extensions/");
+  }
+
+  /// Location of WASM plugins loaded on startup.
+  pub fn plugins_path(&self) -> PathBuf {
+    return self.0.join("plugins/");
+  }
+
+  /// Default location of JSON schema files, see `Config::schemas_path`.
+  pub fn schemas_path(&self) -> PathBuf {
+    return self.0.join("schemas/");
+  }
+
+  /// Disk cache of derived image variants, e.g. thumbnails. Safe to delete.
+  pub fn image_cache_path(&self) -> PathBuf {
+    return self.0.join("cache/images/");
+  }
+
+  /// Staging area for incomplete resumable uploads.
+  pub fn upload_staging_path(&self) -> PathBuf {
+    return self.0.join("staging/uploads/");
+  }
+
   pub fn key_path(&self) -> PathBuf {
     return self.secrets_path().join("keys/");
   }
@@ -67,6 +112,7 @@ impl DataDir {
       self.migrations_path(),
       self.uploads_path(),
       self.key_path(),
+      self.extensions_path(),
     ];
   }
 
@@ -98,7 +144,9 @@ impl DataDir {
 
 const GIT_IGNORE: &str = r#"# Deployment-specific directories:
 backups/
+cache/
 data/
 secrets/
+staging/
 uploads/
 "#;
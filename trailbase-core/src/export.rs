@@ -0,0 +1,561 @@
+//! Encoders for bulk exports of table rows, e.g. record API exports and admin table dumps.
+
+use arrow_array::builder::{BinaryBuilder, Float64Builder, Int64Builder, StringBuilder};
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_ipc::writer::StreamWriter;
+use arrow_schema::{ArrowError, DataType, Field, Schema, SchemaRef};
+use futures_util::{Stream, StreamExt, stream};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::errors::ParquetError;
+use parquet::file::properties::WriterProperties;
+use rusqlite::types::Value as SqliteValue;
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::sync::Arc;
+use thiserror::Error;
+use trailbase_schema::sqlite::{Column, ColumnDataType, ColumnOption};
+use trailbase_sqlite::{Connection, Rows, Value};
+
+use crate::records::sql_to_json::row_to_json;
+use crate::schema_metadata::JsonColumnMetadata;
+
+/// Number of rows fetched from the database per batch, i.e. per streamed chunk or Parquet row
+/// group.
+pub(crate) const BATCH_SIZE: usize = 1024;
+
+#[derive(Debug, Error)]
+pub enum ExportError {
+  #[error("CSV error: {0}")]
+  Csv(#[from] csv::Error),
+  #[error("Arrow error: {0}")]
+  Arrow(#[from] ArrowError),
+  #[error("Parquet error: {0}")]
+  Parquet(#[from] ParquetError),
+  #[error("Json error: {0}")]
+  Json(#[from] crate::records::sql_to_json::JsonError),
+  #[error("SerdeJson error: {0}")]
+  SerdeJson(#[from] serde_json::Error),
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+  #[default]
+  Csv,
+  Parquet,
+  /// Arrow IPC streaming format.
+  Arrow,
+  /// Newline-delimited JSON, i.e. one JSON object per row.
+  NdJson,
+}
+
+impl ExportFormat {
+  pub(crate) fn parse(s: &str) -> Option<Self> {
+    return match s {
+      "csv" => Some(Self::Csv),
+      "parquet" => Some(Self::Parquet),
+      "arrow" => Some(Self::Arrow),
+      "ndjson" => Some(Self::NdJson),
+      _ => None,
+    };
+  }
+
+  pub(crate) fn content_type(&self) -> &'static str {
+    return match self {
+      Self::Csv => "text/csv; charset=utf-8",
+      Self::Parquet => "application/vnd.apache.parquet",
+      Self::Arrow => crate::extract::ARROW_STREAM_MIME_TYPE,
+      Self::NdJson => crate::extract::NDJSON_MIME_TYPE,
+    };
+  }
+
+  pub(crate) fn extension(&self) -> &'static str {
+    return match self {
+      Self::Csv => "csv",
+      Self::Parquet => "parquet",
+      Self::Arrow => "arrows",
+      Self::NdJson => "ndjson",
+    };
+  }
+}
+
+/// Describes the rows being exported.
+#[derive(Clone, Debug)]
+pub(crate) struct ExportColumns {
+  /// All columns of the queried rows in order.
+  pub columns: Vec<Column>,
+  pub json_metadata: Vec<Option<JsonColumnMetadata>>,
+  /// Indexes into `columns` of the exported columns in output order.
+  pub selected: Vec<usize>,
+}
+
+impl ExportColumns {
+  fn names(&self) -> impl Iterator<Item = &str> {
+    return self.selected.iter().map(|i| self.columns[*i].name.as_str());
+  }
+}
+
+pub(crate) fn arrow_data_type(column: &Column) -> DataType {
+  return match column.data_type {
+    ColumnDataType::Integer
+    | ColumnDataType::Int
+    | ColumnDataType::TinyInt
+    | ColumnDataType::SmallInt
+    | ColumnDataType::MediumInt
+    | ColumnDataType::BigInt
+    | ColumnDataType::UnignedBigInt
+    | ColumnDataType::Int2
+    | ColumnDataType::Int4
+    | ColumnDataType::Int8
+    | ColumnDataType::Numeric
+    | ColumnDataType::Boolean
+    | ColumnDataType::Decimal
+    | ColumnDataType::Date
+    | ColumnDataType::DateTime => DataType::Int64,
+    ColumnDataType::Real
+    | ColumnDataType::Double
+    | ColumnDataType::DoublePrecision
+    | ColumnDataType::Float => DataType::Float64,
+    ColumnDataType::Blob | ColumnDataType::JSONB => DataType::Binary,
+    // Text, JSON, and columns without type affinity.
+    _ => DataType::Utf8,
+  };
+}
+
+fn is_not_null(column: &Column) -> bool {
+  return column.options.iter().any(|o| match o {
+    ColumnOption::NotNull => true,
+    // INTEGER PRIMARY KEY is an alias for the rowid and thus never NULL.
+    ColumnOption::Unique { is_primary, .. } => {
+      *is_primary && column.data_type == ColumnDataType::Integer
+    }
+    _ => false,
+  });
+}
+
+pub(crate) fn arrow_schema(columns: &ExportColumns) -> Schema {
+  return Schema::new(
+    columns
+      .selected
+      .iter()
+      .map(|i| {
+        let column = &columns.columns[*i];
+        return Field::new(&column.name, arrow_data_type(column), !is_not_null(column));
+      })
+      .collect::<Vec<_>>(),
+  );
+}
+
+/// Converts SQLite rows into an Arrow record batch.
+///
+/// Values are coerced into the column's declared type, which matters for non-STRICT tables.
+/// Values that cannot be represented are mapped to NULL.
+pub(crate) fn rows_to_record_batch(
+  schema: SchemaRef,
+  columns: &ExportColumns,
+  rows: &Rows,
+) -> Result<RecordBatch, ArrowError> {
+  let mut arrays: Vec<ArrayRef> = Vec::with_capacity(columns.selected.len());
+
+  for (field, index) in schema.fields().iter().zip(&columns.selected) {
+    let values = rows.iter().map(|row| &row[*index]);
+
+    let array: ArrayRef = match field.data_type() {
+      DataType::Int64 => {
+        let mut builder = Int64Builder::with_capacity(rows.len());
+        for value in values {
+          builder.append_option(match value {
+            SqliteValue::Integer(i) => Some(*i),
+            SqliteValue::Real(f) if f.fract() == 0.0 => Some(*f as i64),
+            SqliteValue::Text(s) => s.parse().ok(),
+            _ => None,
+          });
+        }
+        Arc::new(builder.finish())
+      }
+      DataType::Float64 => {
+        let mut builder = Float64Builder::with_capacity(rows.len());
+        for value in values {
+          builder.append_option(match value {
+            SqliteValue::Real(f) => Some(*f),
+            SqliteValue::Integer(i) => Some(*i as f64),
+            SqliteValue::Text(s) => s.parse().ok(),
+            _ => None,
+          });
+        }
+        Arc::new(builder.finish())
+      }
+      DataType::Binary => {
+        let mut builder = BinaryBuilder::with_capacity(rows.len(), 0);
+        for value in values {
+          match value {
+            SqliteValue::Blob(b) => builder.append_value(b),
+            SqliteValue::Text(s) => builder.append_value(s.as_bytes()),
+            _ => builder.append_null(),
+          };
+        }
+        Arc::new(builder.finish())
+      }
+      _ => {
+        let mut builder = StringBuilder::with_capacity(rows.len(), 0);
+        for value in values {
+          match value {
+            SqliteValue::Text(s) => builder.append_value(s),
+            SqliteValue::Integer(i) => builder.append_value(i.to_string()),
+            SqliteValue::Real(f) => builder.append_value(f.to_string()),
+            SqliteValue::Blob(b) => builder.append_value(String::from_utf8_lossy(b)),
+            SqliteValue::Null => builder.append_null(),
+          };
+        }
+        Arc::new(builder.finish())
+      }
+    };
+
+    arrays.push(array);
+  }
+
+  return RecordBatch::try_new(schema, arrays);
+}
+
+fn to_csv_field(value: Option<&serde_json::Value>) -> String {
+  return match value {
+    None | Some(serde_json::Value::Null) => String::new(),
+    Some(serde_json::Value::String(s)) => s.clone(),
+    Some(v) => v.to_string(),
+  };
+}
+
+fn no_filter(_col_name: &str) -> bool {
+  return true;
+}
+
+enum Encoder {
+  Csv {
+    header_written: bool,
+    bom: bool,
+  },
+  Parquet {
+    schema: SchemaRef,
+    writer: ArrowWriter<Vec<u8>>,
+  },
+  Arrow {
+    schema: SchemaRef,
+    writer: StreamWriter<Vec<u8>>,
+  },
+  NdJson,
+}
+
+impl Encoder {
+  fn new(format: ExportFormat, columns: &ExportColumns, bom: bool) -> Result<Self, ExportError> {
+    return Ok(match format {
+      ExportFormat::Csv => Self::Csv {
+        header_written: false,
+        bom,
+      },
+      ExportFormat::Parquet => {
+        let schema = Arc::new(arrow_schema(columns));
+        let props = WriterProperties::builder()
+          .set_compression(Compression::SNAPPY)
+          .build();
+        Self::Parquet {
+          writer: ArrowWriter::try_new(vec![], schema.clone(), Some(props))?,
+          schema,
+        }
+      }
+      ExportFormat::Arrow => {
+        let schema = Arc::new(arrow_schema(columns));
+        Self::Arrow {
+          writer: StreamWriter::try_new(vec![], &schema)?,
+          schema,
+        }
+      }
+      ExportFormat::NdJson => Self::NdJson,
+    });
+  }
+
+  fn csv_header(columns: &ExportColumns, bom: bool) -> Result<Vec<u8>, ExportError> {
+    let mut buffer = if bom {
+      "\u{FEFF}".as_bytes().to_vec()
+    } else {
+      vec![]
+    };
+
+    let mut writer = csv::WriterBuilder::new()
+      .terminator(csv::Terminator::CRLF)
+      .from_writer(&mut buffer);
+    writer.write_record(columns.names())?;
+    writer.flush().map_err(csv::Error::from)?;
+    drop(writer);
+
+    return Ok(buffer);
+  }
+
+  fn encode(&mut self, columns: &ExportColumns, rows: &Rows) -> Result<Vec<u8>, ExportError> {
+    return match self {
+      Self::Csv {
+        header_written,
+        bom,
+      } => {
+        let mut buffer = if *header_written {
+          vec![]
+        } else {
+          *header_written = true;
+          Self::csv_header(columns, *bom)?
+        };
+
+        let mut writer = csv::WriterBuilder::new()
+          .terminator(csv::Terminator::CRLF)
+          .from_writer(&mut buffer);
+
+        for row in rows.iter() {
+          let json = row_to_json(&columns.columns, &columns.json_metadata, row, no_filter)?;
+          writer.write_record(columns.names().map(|name| to_csv_field(json.get(name))))?;
+        }
+        writer.flush().map_err(csv::Error::from)?;
+        drop(writer);
+
+        Ok(buffer)
+      }
+      Self::Parquet { schema, writer } => {
+        writer.write(&rows_to_record_batch(schema.clone(), columns, rows)?)?;
+        // Close the row group to be able to stream out what's been written so far.
+        writer.flush()?;
+        Ok(std::mem::take(writer.inner_mut()))
+      }
+      Self::Arrow { schema, writer } => {
+        writer.write(&rows_to_record_batch(schema.clone(), columns, rows)?)?;
+        Ok(std::mem::take(writer.get_mut()))
+      }
+      Self::NdJson => {
+        let mut buffer = vec![];
+        for row in rows.iter() {
+          let mut json = row_to_json(&columns.columns, &columns.json_metadata, row, no_filter)?;
+          let record: serde_json::Map<String, serde_json::Value> = columns
+            .names()
+            .map(|name| {
+              let value = json
+                .get_mut(name)
+                .map_or(serde_json::Value::Null, serde_json::Value::take);
+              (name.to_string(), value)
+            })
+            .collect();
+
+          serde_json::to_writer(&mut buffer, &record)?;
+          buffer.push(b'\n');
+        }
+
+        Ok(buffer)
+      }
+    };
+  }
+
+  fn finish(self, columns: &ExportColumns) -> Result<Vec<u8>, ExportError> {
+    return match self {
+      Self::Csv {
+        header_written,
+        bom,
+      } => {
+        if header_written {
+          Ok(vec![])
+        } else {
+          Self::csv_header(columns, bom)
+        }
+      }
+      Self::Parquet { writer, .. } => Ok(writer.into_inner()?),
+      Self::Arrow { mut writer, .. } => {
+        // Writes the end-of-stream marker.
+        writer.finish()?;
+        Ok(writer.into_inner()?)
+      }
+      Self::NdJson => Ok(vec![]),
+    };
+  }
+}
+
+/// Encodes a single batch of rows as complete Arrow IPC stream.
+pub(crate) fn encode_arrow_stream(
+  columns: &ExportColumns,
+  rows: &Rows,
+) -> Result<Vec<u8>, ExportError> {
+  let mut encoder = Encoder::new(ExportFormat::Arrow, columns, false)?;
+  let mut buffer = encoder.encode(columns, rows)?;
+  buffer.extend(encoder.finish(columns)?);
+  return Ok(buffer);
+}
+
+/// Streams the results of the given query in batches of `BATCH_SIZE` using the `:__limit` and
+/// `:__offset` params.
+///
+/// NOTE: The query runs once as opposed to being re-run per batch, which yields a consistent
+/// snapshot and avoids the cost of skipping ever larger offsets.
+pub(crate) fn query_batches(
+  conn: Connection,
+  query: String,
+  mut params: Vec<(Cow<'static, str>, Value)>,
+  offset: usize,
+  limit: Option<usize>,
+) -> impl Stream<Item = Result<Rows, trailbase_sqlite::Error>> + Send + 'static {
+  params.extend([
+    (
+      Cow::Borrowed(":__limit"),
+      // Negative limits mean no limit.
+      Value::Integer(limit.map_or(-1, |l| l as i64)),
+    ),
+    (Cow::Borrowed(":__offset"), Value::Integer(offset as i64)),
+  ]);
+
+  return conn.query_stream(query, params, BATCH_SIZE);
+}
+
+/// Encodes a stream of row batches into chunks of the given format.
+pub(crate) fn encode_batches<S, E>(
+  format: ExportFormat,
+  columns: ExportColumns,
+  bom: bool,
+  batches: S,
+) -> Result<impl Stream<Item = Result<Vec<u8>, E>> + Send + 'static, ExportError>
+where
+  S: Stream<Item = Result<Rows, E>> + Send + 'static,
+  E: From<ExportError> + Send + 'static,
+{
+  let encoder = Encoder::new(format, &columns, bom)?;
+
+  return Ok(stream::try_unfold(
+    (Box::pin(batches), Some(encoder)),
+    move |(mut batches, encoder)| {
+      let columns = columns.clone();
+
+      async move {
+        let Some(mut encoder) = encoder else {
+          return Ok(None);
+        };
+
+        return match batches.next().await {
+          Some(rows) => {
+            let chunk = encoder.encode(&columns, &rows?)?;
+            Ok(Some((chunk, (batches, Some(encoder)))))
+          }
+          None => {
+            let chunk = encoder.finish(&columns)?;
+            Ok(Some((chunk, (batches, None))))
+          }
+        };
+      }
+    },
+  ));
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use arrow_array::{Array, Float64Array, Int64Array, StringArray};
+  use bytes::Bytes;
+  use futures_util::TryStreamExt;
+  use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+  use crate::app_state::test_state;
+
+  #[tokio::test]
+  async fn test_parquet_export() {
+    let state = test_state(None).await.unwrap();
+    let conn = state.conn();
+
+    conn
+      .execute_batch(
+        r#"
+          CREATE TABLE test (
+            id      INTEGER PRIMARY KEY,
+            name    TEXT,
+            score   REAL NOT NULL
+          ) STRICT;
+          INSERT INTO test (id, name, score) VALUES (1, 'a', 0.5), (2, NULL, 1.5), (3, 'c', 2.5);
+        "#,
+      )
+      .await
+      .unwrap();
+
+    state.schema_metadata().invalidate_all().await.unwrap();
+    let metadata = state.schema_metadata().get_table("test").unwrap();
+
+    let columns = ExportColumns {
+      columns: metadata.schema.columns.clone(),
+      json_metadata: metadata.json_metadata.columns.clone(),
+      selected: vec![0, 1, 2],
+    };
+
+    let schema = arrow_schema(&columns);
+    assert!(!schema.field(0).is_nullable());
+    assert!(schema.field(1).is_nullable());
+    assert!(!schema.field(2).is_nullable());
+
+    let batches = query_batches(
+      conn.clone(),
+      "SELECT * FROM test ORDER BY id LIMIT :__limit OFFSET :__offset".to_string(),
+      vec![],
+      0,
+      None,
+    );
+
+    let chunks: Vec<Vec<u8>> = encode_batches::<_, ExportTestError>(
+      ExportFormat::Parquet,
+      columns,
+      false,
+      batches.map_err(ExportTestError::from),
+    )
+    .unwrap()
+    .try_collect()
+    .await
+    .unwrap();
+
+    let reader = ParquetRecordBatchReaderBuilder::try_new(Bytes::from(chunks.concat()))
+      .unwrap()
+      .build()
+      .unwrap();
+    let batches: Vec<RecordBatch> = reader.collect::<Result<_, _>>().unwrap();
+    assert_eq!(1, batches.len());
+
+    let batch = &batches[0];
+    let ids = batch
+      .column(0)
+      .as_any()
+      .downcast_ref::<Int64Array>()
+      .unwrap();
+    assert_eq!(&[1, 2, 3], ids.values().as_ref());
+
+    let names = batch
+      .column(1)
+      .as_any()
+      .downcast_ref::<StringArray>()
+      .unwrap();
+    assert_eq!("a", names.value(0));
+    assert!(names.is_null(1));
+
+    let scores = batch
+      .column(2)
+      .as_any()
+      .downcast_ref::<Float64Array>()
+      .unwrap();
+    assert_eq!(2.5, scores.value(2));
+  }
+
+  #[derive(Debug)]
+  enum ExportTestError {
+    #[allow(unused)]
+    Export(ExportError),
+    #[allow(unused)]
+    Sqlite(trailbase_sqlite::Error),
+  }
+
+  impl From<ExportError> for ExportTestError {
+    fn from(err: ExportError) -> Self {
+      return Self::Export(err);
+    }
+  }
+
+  impl From<trailbase_sqlite::Error> for ExportTestError {
+    fn from(err: trailbase_sqlite::Error) -> Self {
+      return Self::Sqlite(err);
+    }
+  }
+}
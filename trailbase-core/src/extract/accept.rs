@@ -0,0 +1,138 @@
+use axum::extract::FromRequestParts;
+use axum::http::header::ACCEPT;
+use axum::http::request::Parts;
+use std::convert::Infallible;
+
+pub const ARROW_STREAM_MIME_TYPE: &str = "application/vnd.apache.arrow.stream";
+pub const MSGPACK_MIME_TYPE: &str = "application/msgpack";
+
+/// MessagePack doesn't have a registered media type, thus also accept common alternatives.
+const MSGPACK_MIME_TYPES: &[&str] = &[
+  MSGPACK_MIME_TYPE,
+  "application/x-msgpack",
+  "application/vnd.msgpack",
+];
+
+pub const CBOR_MIME_TYPE: &str = "application/cbor";
+pub const CSV_MIME_TYPE: &str = "text/csv";
+pub const NDJSON_MIME_TYPE: &str = "application/x-ndjson";
+
+fn media_type(value: &[u8]) -> &[u8] {
+  return value
+    .split(|b| *b == b';')
+    .next()
+    .unwrap_or_default()
+    .trim_ascii();
+}
+
+pub(crate) fn is_msgpack_content_type(value: &[u8]) -> bool {
+  let media_type = media_type(value);
+  return MSGPACK_MIME_TYPES
+    .iter()
+    .any(|t| media_type.eq_ignore_ascii_case(t.as_bytes()));
+}
+
+pub(crate) fn is_cbor_content_type(value: &[u8]) -> bool {
+  return media_type(value).eq_ignore_ascii_case(CBOR_MIME_TYPE.as_bytes());
+}
+
+/// Response encoding requested by the client via the `Accept` header.
+///
+/// NOTE: Media types are considered in order of appearance, quality values are ignored. Unknown
+/// or missing media types fall back to JSON.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum AcceptFormat {
+  #[default]
+  Json,
+  Arrow,
+  MsgPack,
+  Cbor,
+  Csv,
+  /// Newline-delimited JSON.
+  NdJson,
+}
+
+impl AcceptFormat {
+  fn from_header(value: &str) -> Self {
+    for media_type in value.split(',') {
+      let media_type = media_type.split(';').next().unwrap_or_default().trim();
+      match media_type {
+        ARROW_STREAM_MIME_TYPE => return Self::Arrow,
+        t if MSGPACK_MIME_TYPES.contains(&t) => return Self::MsgPack,
+        CBOR_MIME_TYPE => return Self::Cbor,
+        CSV_MIME_TYPE => return Self::Csv,
+        NDJSON_MIME_TYPE => return Self::NdJson,
+        "application/json" | "application/*" | "*/*" => return Self::Json,
+        _ => {}
+      }
+    }
+    return Self::Json;
+  }
+}
+
+impl<S> FromRequestParts<S> for AcceptFormat
+where
+  S: Send + Sync,
+{
+  type Rejection = Infallible;
+
+  async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+    return Ok(
+      parts
+        .headers
+        .get(ACCEPT)
+        .and_then(|value| value.to_str().ok())
+        .map_or(Self::Json, Self::from_header),
+    );
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_accept_format_from_header() {
+    assert_eq!(AcceptFormat::Json, AcceptFormat::from_header(""));
+    assert_eq!(
+      AcceptFormat::Json,
+      AcceptFormat::from_header("text/html, application/json")
+    );
+    assert_eq!(
+      AcceptFormat::Arrow,
+      AcceptFormat::from_header("application/vnd.apache.arrow.stream; q=1.0, */*")
+    );
+    assert_eq!(
+      AcceptFormat::Json,
+      AcceptFormat::from_header("*/*, application/vnd.apache.arrow.stream")
+    );
+    assert_eq!(
+      AcceptFormat::MsgPack,
+      AcceptFormat::from_header("application/x-msgpack")
+    );
+    assert_eq!(
+      AcceptFormat::Cbor,
+      AcceptFormat::from_header("text/plain, application/cbor")
+    );
+    assert_eq!(
+      AcceptFormat::Csv,
+      AcceptFormat::from_header("text/csv; charset=utf-8")
+    );
+    assert_eq!(
+      AcceptFormat::NdJson,
+      AcceptFormat::from_header("application/x-ndjson")
+    );
+  }
+
+  #[test]
+  fn test_is_msgpack_content_type() {
+    assert!(is_msgpack_content_type(b"application/msgpack"));
+    assert!(is_msgpack_content_type(
+      b"application/vnd.msgpack; charset=binary"
+    ));
+    assert!(!is_msgpack_content_type(b"application/json"));
+
+    assert!(is_cbor_content_type(b"application/cbor"));
+    assert!(!is_cbor_content_type(b"application/msgpack"));
+  }
+}
@@ -1,4 +1,5 @@
 use axum::Json;
+use axum::body::Bytes;
 use axum::extract::{Form, FromRequest, Request, rejection::*};
 use axum::http::StatusCode;
 use axum::http::header::CONTENT_TYPE;
@@ -9,6 +10,9 @@ use serde::de::DeserializeOwned;
 use thiserror::Error;
 use trailbase_schema::FileUploadInput;
 
+use crate::extract::accept::{
+  CBOR_MIME_TYPE, MSGPACK_MIME_TYPE, is_cbor_content_type, is_msgpack_content_type,
+};
 use crate::extract::multipart::{Rejection as MultipartRejection, parse_multipart};
 
 #[derive(Debug, Error)]
@@ -23,6 +27,12 @@ pub enum EitherRejection {
   Json(#[from] JsonRejection),
   #[error("Multipart error: {0}")]
   Multipart(#[from] MultipartRejection),
+  #[error("Body error: {0}")]
+  Bytes(#[from] BytesRejection),
+  #[error("MessagePack error: {0}")]
+  MsgPack(#[from] rmp_serde::decode::Error),
+  #[error("CBOR error: {0}")]
+  Cbor(#[from] ciborium::de::Error<std::io::Error>),
 }
 
 impl IntoResponse for EitherRejection {
@@ -39,6 +49,8 @@ pub enum Either<T> {
   Json(T),
   Multipart(T, Vec<FileUploadInput>),
   Form(T),
+  MsgPack(T),
+  Cbor(T),
   // Proto(DynamicMessage),
 }
 
@@ -63,6 +75,14 @@ where
         let (value, files) = parse_multipart(req).await?;
         Ok(Either::Multipart(value, files))
       }
+      Some(x) if is_msgpack_content_type(x.as_ref()) => {
+        let body = Bytes::from_request(req, state).await?;
+        Ok(Either::MsgPack(rmp_serde::from_slice(&body)?))
+      }
+      Some(x) if is_cbor_content_type(x.as_ref()) => {
+        let body = Bytes::from_request(req, state).await?;
+        Ok(Either::Cbor(ciborium::from_reader(body.as_ref())?))
+      }
       // Some(x) if x == "application/x-protobuf" => {
       //   return Ok(Either::Proto(DynamicMessage::decode::from_request(req,
       // state).await.unwrap())); }
@@ -91,6 +111,17 @@ where
         axum::Json(form).into_response()
       }
       Either::Form(form) => axum::Form(form).into_response(),
+      Either::MsgPack(value) => match rmp_serde::to_vec_named(&value) {
+        Ok(buffer) => ([(CONTENT_TYPE, MSGPACK_MIME_TYPE)], buffer).into_response(),
+        Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+      },
+      Either::Cbor(value) => {
+        let mut buffer = vec![];
+        match ciborium::into_writer(&value, &mut buffer) {
+          Ok(_) => ([(CONTENT_TYPE, CBOR_MIME_TYPE)], buffer).into_response(),
+          Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+        }
+      }
     }
   }
 }
@@ -180,6 +211,55 @@ mod test {
     return Ok(());
   }
 
+  #[tokio::test]
+  async fn test_from_request_for_msgpack() -> Result<(), anyhow::Error> {
+    let input = serde_json::json!({
+      "foo": 42,
+      "bar": ["a", "b"],
+    });
+    let body = rmp_serde::to_vec_named(&input)?;
+
+    let request = axum::http::Request::builder()
+      .header("content-type", "application/msgpack")
+      .header("content-length", body.len())
+      .body(axum::body::Body::from(body))
+      .unwrap();
+
+    let e = Either::<serde_json::Value>::from_request(request, &()).await?;
+
+    let Either::MsgPack(value) = e else {
+      panic!("Expected MsgPack, got: {e:?}");
+    };
+    assert_eq!(input, value);
+
+    return Ok(());
+  }
+
+  #[tokio::test]
+  async fn test_from_request_for_cbor() -> Result<(), anyhow::Error> {
+    let input = serde_json::json!({
+      "foo": 42,
+      "bar": ["a", "b"],
+    });
+    let mut body = vec![];
+    ciborium::into_writer(&input, &mut body)?;
+
+    let request = axum::http::Request::builder()
+      .header("content-type", "application/cbor")
+      .header("content-length", body.len())
+      .body(axum::body::Body::from(body))
+      .unwrap();
+
+    let e = Either::<serde_json::Value>::from_request(request, &()).await?;
+
+    let Either::Cbor(value) = e else {
+      panic!("Expected CBOR, got: {e:?}");
+    };
+    assert_eq!(input, value);
+
+    return Ok(());
+  }
+
   #[tokio::test]
   async fn test_from_request_for_urlencoding() -> Result<(), anyhow::Error> {
     let input = serde_json::json!({
@@ -1,4 +1,9 @@
+mod accept;
 mod either;
 mod multipart;
 
+pub use accept::{
+  ARROW_STREAM_MIME_TYPE, AcceptFormat, CBOR_MIME_TYPE, CSV_MIME_TYPE, MSGPACK_MIME_TYPE,
+  NDJSON_MIME_TYPE,
+};
 pub use either::Either;
@@ -0,0 +1,946 @@
+//! Optional GraphQL endpoint for record APIs.
+//!
+//! The schema is derived from the same `ClientSchema` as the client codegen targets, i.e. each
+//! record API contributes an object type as well as list and lookup queries and, for table APIs,
+//! create, update and delete mutations. Foreign keys referencing tables exposed by another record
+//! API additionally yield relation fields. Resolvers dispatch to the regular record API handlers,
+//! thus sharing access control, filtering and validation with the HTTP API.
+
+use async_graphql::dynamic::{
+  Field, FieldFuture, FieldValue, InputObject, InputValue, Object, ResolverContext, Scalar, Schema,
+  TypeRef,
+};
+use async_graphql::{ErrorExtensions, ServerError, Value};
+use axum::Router;
+use axum::extract::{Json, Path, Query, State};
+use axum::http::HeaderMap;
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use parking_lot::Mutex;
+use std::collections::HashSet;
+use std::sync::Arc;
+use trailbase_schema::sqlite::ColumnOption;
+
+use crate::app_state::AppState;
+use crate::auth::User;
+use crate::codegen::{ClientSchema, CodegenError, FieldType, Model, build_client_schema};
+use crate::constants::GRAPHQL_API_PATH;
+use crate::extract::{AcceptFormat, Either};
+use crate::problem::{ErrorCode, verbose_errors};
+use crate::records::create_record::{
+  CreateRecordQuery, CreateRecordResponse, create_record_handler,
+};
+use crate::records::delete_record::delete_record_handler;
+use crate::records::list_records::{ListResponse, Listing, list_records};
+use crate::records::read_record::read_record;
+use crate::records::update_record::update_record_handler;
+use crate::records::{RecordApi, RecordError};
+
+const JSON_SCALAR: &str = "JSON";
+
+/// Bounds on incoming queries, since relation fields allow arbitrarily nested and thus expensive
+/// queries, e.g. following self-referencing foreign keys.
+const MAX_QUERY_DEPTH: usize = 12;
+const MAX_QUERY_COMPLEXITY: usize = 1000;
+const MAX_RECURSIVE_DEPTH: usize = 32;
+
+/// Per-request data available to all resolvers.
+struct Context {
+  state: AppState,
+  user: Option<User>,
+}
+
+pub(crate) fn router() -> Router<AppState> {
+  return Router::new().route(&format!("/{GRAPHQL_API_PATH}"), post(graphql_handler));
+}
+
+/// Executes a GraphQL request against the record APIs.
+pub(crate) async fn graphql_handler(
+  State(state): State<AppState>,
+  user: Option<User>,
+  Json(request): Json<async_graphql::Request>,
+) -> Response {
+  let schema = match load_schema(&state) {
+    Ok(schema) => schema,
+    Err(err) => {
+      return Json(async_graphql::Response::from_errors(vec![
+        ServerError::new(err.to_string(), None),
+      ]))
+      .into_response();
+    }
+  };
+
+  let response = schema.execute(request.data(Context { state, user })).await;
+  return Json(response).into_response();
+}
+
+/// Returns the cached schema, rebuilding it whenever the record APIs have changed, e.g. due to
+/// config or schema changes.
+fn load_schema(state: &AppState) -> Result<Arc<Schema>, CodegenError> {
+  type Cache = Option<(Arc<Vec<(String, RecordApi)>>, Arc<Schema>)>;
+  static CACHE: Mutex<Cache> = Mutex::new(None);
+
+  let record_apis = state.record_apis();
+  let mut cache = CACHE.lock();
+  if let Some((key, schema)) = cache.as_ref() {
+    if Arc::ptr_eq(key, &record_apis) {
+      return Ok(schema.clone());
+    }
+  }
+
+  let schema = Arc::new(build_schema(state, &build_client_schema(state)?)?);
+  *cache = Some((arc_swap::Guard::into_inner(record_apis), schema.clone()));
+  return Ok(schema);
+}
+
+fn is_valid_name(name: &str) -> bool {
+  let mut chars = name.chars();
+  return chars
+    .next()
+    .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+    && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+    && !name.starts_with("__");
+}
+
+/// Narrows the codegen types to what's representable in the schema, i.e. enums are exposed as
+/// strings and models that only exist as inputs as JSON.
+fn output_type(ty: &FieldType, input_models: &HashSet<String>) -> FieldType {
+  return match ty {
+    FieldType::Enum(_) => FieldType::String,
+    FieldType::Model(name) if input_models.contains(name) => FieldType::Any,
+    FieldType::Array(item) => FieldType::Array(Box::new(output_type(item, input_models))),
+    ty => ty.clone(),
+  };
+}
+
+fn type_ref(ty: &FieldType, required: bool) -> TypeRef {
+  let ty = match ty {
+    FieldType::String | FieldType::Enum(_) => TypeRef::named(TypeRef::STRING),
+    FieldType::Integer => TypeRef::named(TypeRef::INT),
+    FieldType::Number => TypeRef::named(TypeRef::FLOAT),
+    FieldType::Boolean => TypeRef::named(TypeRef::BOOLEAN),
+    FieldType::Any => TypeRef::named(JSON_SCALAR),
+    FieldType::Array(item) => TypeRef::List(Box::new(type_ref(item, false))),
+    FieldType::Model(name) => TypeRef::named(name),
+  };
+  if required {
+    return TypeRef::NonNull(Box::new(ty));
+  }
+  return ty;
+}
+
+/// Input types are flat, i.e. nested objects and arrays are passed as JSON.
+fn input_type_ref(ty: &FieldType, required: bool) -> TypeRef {
+  return match ty {
+    FieldType::Array(_) | FieldType::Model(_) => type_ref(&FieldType::Any, required),
+    ty => type_ref(ty, required),
+  };
+}
+
+fn to_field_value(
+  value: serde_json::Value,
+  ty: &FieldType,
+) -> Result<Option<FieldValue<'static>>, async_graphql::Error> {
+  return Ok(match (value, ty) {
+    (serde_json::Value::Null, _) => None,
+    (value @ serde_json::Value::Object(_), FieldType::Model(_)) => {
+      Some(FieldValue::owned_any(value))
+    }
+    (serde_json::Value::Array(items), FieldType::Array(item_ty)) => Some(FieldValue::list(
+      items
+        .into_iter()
+        .map(|item| Ok(to_field_value(item, item_ty)?.unwrap_or(FieldValue::NULL)))
+        .collect::<Result<Vec<_>, async_graphql::Error>>()?,
+    )),
+    (value, _) => Some(FieldValue::value(Value::from_json(value)?)),
+  });
+}
+
+fn graphql_error(err: RecordError) -> async_graphql::Error {
+  let code = match &err {
+    RecordError::ApiNotFound => ErrorCode::RecordApiNotFound,
+    RecordError::ApiRequiresTable => ErrorCode::RecordApiRequiresTable,
+    RecordError::RecordNotFound => ErrorCode::RecordNotFound,
+    RecordError::Forbidden => ErrorCode::RecordForbidden,
+    RecordError::BadRequest(_) | RecordError::Rejected(_) => ErrorCode::RecordBadRequest,
+    RecordError::Constraint(code, _) | RecordError::Conflict(code, _) => *code,
+    RecordError::Validation(_) => ErrorCode::RecordValidationFailed,
+    RecordError::PreconditionFailed => ErrorCode::RecordPreconditionFailed,
+    RecordError::LimitExceeded(_) => ErrorCode::RecordLimitExceeded,
+    RecordError::Unavailable(_) => ErrorCode::RecordUnavailable,
+    RecordError::Internal(_) => ErrorCode::RecordInternal,
+  };
+
+  let message = match &err {
+    RecordError::Internal(_) if !verbose_errors() => code.title().to_string(),
+    err => err.to_string(),
+  };
+
+  return async_graphql::Error::new(message).extend_with(|_err, extensions| {
+    extensions.set("code", code.as_str());
+    if let RecordError::Validation(errors) = &err {
+      if let Ok(errors) = serde_json::to_value(errors).and_then(Value::from_json) {
+        extensions.set("errors", errors);
+      }
+    }
+  });
+}
+
+fn context<'a>(ctx: &ResolverContext<'a>) -> Result<&'a Context, async_graphql::Error> {
+  return ctx.data::<Context>();
+}
+
+fn lookup_api(state: &AppState, api_name: &str) -> Result<RecordApi, async_graphql::Error> {
+  return state
+    .lookup_record_api(api_name)
+    .ok_or_else(|| graphql_error(RecordError::ApiNotFound));
+}
+
+/// Reads a record returning `None` for missing records.
+async fn read_optional(
+  context: &Context,
+  api_name: &str,
+  id: &str,
+) -> Result<Option<FieldValue<'static>>, async_graphql::Error> {
+  let api = lookup_api(&context.state, api_name)?;
+  return match read_record(&context.state, &api, id, None, context.user.as_ref()).await {
+    Ok(record) => Ok(Some(FieldValue::owned_any(record))),
+    Err(RecordError::RecordNotFound) => Ok(None),
+    Err(err) => Err(graphql_error(err)),
+  };
+}
+
+/// Converts a scalar filter value into its URL query representation.
+fn filter_value(value: &Value) -> Result<String, async_graphql::Error> {
+  return match value {
+    Value::String(s) => Ok(s.clone()),
+    Value::Number(n) => Ok(n.to_string()),
+    Value::Boolean(b) => Ok(if *b { "1" } else { "0" }.to_string()),
+    Value::Enum(name) => Ok(name.to_string()),
+    Value::List(items) => Ok(
+      items
+        .iter()
+        .map(filter_value)
+        .collect::<Result<Vec<_>, _>>()?
+        .join(","),
+    ),
+    _ => Err(async_graphql::Error::new("Invalid filter value")),
+  };
+}
+
+/// Translates a filter input into the grouped filter syntax understood by the list handler, i.e.
+/// `filter[$and][0][price][lt]=10`, where `prefix` names the "$and" group the filter's conditions
+/// are added to.
+fn append_filter(
+  prefix: &str,
+  filter: &Value,
+  params: &mut Vec<(String, String)>,
+) -> Result<(), async_graphql::Error> {
+  let Value::Object(fields) = filter else {
+    return Err(async_graphql::Error::new("Invalid filter"));
+  };
+
+  let mut index = 0;
+  for (name, value) in fields {
+    match (name.as_str(), value) {
+      (_, Value::Null) => {}
+      ("and", Value::List(filters)) => {
+        for filter in filters {
+          append_filter(&format!("{prefix}[{index}][$and]"), filter, params)?;
+          index += 1;
+        }
+      }
+      ("or", Value::List(filters)) => {
+        for (i, filter) in filters.iter().enumerate() {
+          append_filter(
+            &format!("{prefix}[{index}][$or][{i}][$and]"),
+            filter,
+            params,
+          )?;
+        }
+        index += 1;
+      }
+      (column, Value::Object(ops)) => {
+        for (op, value) in ops {
+          if matches!(value, Value::Null) {
+            continue;
+          }
+          let key = match op.as_str() {
+            "eq" => format!("{prefix}[{index}][{column}]"),
+            op => format!("{prefix}[{index}][{column}][{op}]"),
+          };
+          params.push((key, filter_value(value)?));
+          index += 1;
+        }
+      }
+      _ => {
+        return Err(async_graphql::Error::new("Invalid filter"));
+      }
+    }
+  }
+
+  return Ok(());
+}
+
+/// Translates list arguments into the URL query understood by the HTTP list handler.
+fn list_query(ctx: &ResolverContext<'_>) -> Result<String, async_graphql::Error> {
+  let mut query = form_urlencoded::Serializer::new(String::new());
+  for name in ["limit", "offset"] {
+    if let Some(value) = ctx.args.get(name) {
+      query.append_pair(name, &value.i64()?.to_string());
+    }
+  }
+  if let Some(cursor) = ctx.args.get("cursor") {
+    query.append_pair("cursor", cursor.string()?);
+  }
+  if let Some(order) = ctx.args.get("order") {
+    let order = order
+      .list()?
+      .iter()
+      .map(|v| v.string())
+      .collect::<Result<Vec<_>, _>>()?;
+    if !order.is_empty() {
+      query.append_pair("order", &order.join(","));
+    }
+  }
+  if let Some(count) = ctx.args.get("count") {
+    if count.boolean()? {
+      query.append_pair("count", "true");
+    }
+  }
+  if let Some(filter) = ctx.args.get("filter") {
+    let mut params = vec![];
+    append_filter("filter[$and]", filter.as_value(), &mut params)?;
+    for (key, value) in params {
+      query.append_pair(&key, &value);
+    }
+  }
+  return Ok(query.finish());
+}
+
+/// Operators of the filter input for the given scalar type, mirroring list qualifiers.
+fn scalar_filter(name: &str, scalar: &str) -> InputObject {
+  let mut input = InputObject::new(name);
+  for op in ["eq", "ne", "gt", "gte", "lt", "lte"] {
+    input = input.field(InputValue::new(op, TypeRef::named(scalar)));
+  }
+  for op in ["in", "nin"] {
+    input = input.field(InputValue::new(op, TypeRef::named_nn_list(scalar)));
+  }
+  if scalar == TypeRef::STRING {
+    for op in ["like", "ilike", "glob", "re"] {
+      input = input.field(InputValue::new(op, TypeRef::named(scalar)));
+    }
+  }
+  return input;
+}
+
+fn scalar_filter_name(ty: &FieldType) -> Option<&'static str> {
+  return match ty {
+    FieldType::String | FieldType::Enum(_) => Some("StringFilter"),
+    FieldType::Integer => Some("IntFilter"),
+    FieldType::Number => Some("FloatFilter"),
+    FieldType::Boolean => Some("BooleanFilter"),
+    _ => None,
+  };
+}
+
+fn model_object(model: &Model, input_models: &HashSet<String>) -> Object {
+  let mut object = Object::new(&model.name);
+  for field in &model.fields {
+    if !is_valid_name(&field.name) {
+      continue;
+    }
+
+    let name = field.name.clone();
+    let ty = output_type(&field.ty, input_models);
+    object = object.field(Field::new(
+      &field.name,
+      type_ref(&ty, field.required),
+      move |ctx| {
+        let (name, ty) = (name.clone(), ty.clone());
+        return FieldFuture::new(async move {
+          let record = ctx.parent_value.try_downcast_ref::<serde_json::Value>()?;
+          return to_field_value(record.get(&name).cloned().unwrap_or_default(), &ty);
+        });
+      },
+    ));
+  }
+  return object;
+}
+
+fn input_object(model: &Model) -> InputObject {
+  let mut input = InputObject::new(&model.name);
+  for field in &model.fields {
+    if is_valid_name(&field.name) {
+      input = input.field(InputValue::new(
+        &field.name,
+        input_type_ref(&field.ty, field.required),
+      ));
+    }
+  }
+  return input;
+}
+
+fn build_schema(state: &AppState, client: &ClientSchema) -> Result<Schema, CodegenError> {
+  let apis: Vec<_> = client
+    .apis
+    .iter()
+    .filter(|api| is_valid_name(&api.api_name))
+    .collect();
+  if apis.is_empty() {
+    return Err(CodegenError::Unsupported("no record APIs".to_string()));
+  }
+
+  let input_models: HashSet<String> = apis
+    .iter()
+    .flat_map(|api| [api.insert.clone(), api.update.clone()])
+    .flatten()
+    .collect();
+
+  let mut objects: Vec<Object> = vec![];
+  let mut inputs: Vec<InputObject> = vec![
+    scalar_filter("StringFilter", TypeRef::STRING),
+    scalar_filter("IntFilter", TypeRef::INT),
+    scalar_filter("FloatFilter", TypeRef::FLOAT),
+    scalar_filter("BooleanFilter", TypeRef::BOOLEAN),
+  ];
+  for model in &client.models {
+    if input_models.contains(&model.name) {
+      inputs.push(input_object(model));
+    } else {
+      objects.push(model_object(model, &input_models));
+    }
+  }
+
+  let mut query = Object::new("Query");
+  let mut mutation = Object::new("Mutation");
+  let mut has_mutations = false;
+
+  for api in &apis {
+    let Some(model) = client.models.iter().find(|m| m.name == api.select) else {
+      continue;
+    };
+    let select = api.select.clone();
+
+    // Relations, i.e. foreign keys referencing the table of another record API.
+    if let (Some(record_api), Some(object)) = (
+      state.lookup_record_api(&api.api_name),
+      objects.iter_mut().find(|o| o.type_name() == select),
+    ) {
+      for column in record_api.columns() {
+        let Some(foreign_table) = column.options.iter().find_map(|o| match o {
+          ColumnOption::ForeignKey { foreign_table, .. } => Some(foreign_table),
+          _ => None,
+        }) else {
+          continue;
+        };
+        let Some(foreign_api) = apis.iter().find(|foreign_api| {
+          state
+            .lookup_record_api(&foreign_api.api_name)
+            .is_some_and(|a| a.table_name() == foreign_table)
+        }) else {
+          continue;
+        };
+
+        let field_name = format!("{}_record", column.name);
+        if !is_valid_name(&field_name) || model.fields.iter().any(|f| f.name == field_name) {
+          continue;
+        }
+
+        let column_name = column.name.clone();
+        let foreign_api_name = foreign_api.api_name.clone();
+        *object = std::mem::replace(object, Object::new(&select)).field(Field::new(
+          field_name,
+          TypeRef::named(&foreign_api.select),
+          move |ctx| {
+            let (column_name, foreign_api_name) = (column_name.clone(), foreign_api_name.clone());
+            return FieldFuture::new(async move {
+              let record = ctx.parent_value.try_downcast_ref::<serde_json::Value>()?;
+              // Expandable foreign keys are rendered as `{"id": <id>}`.
+              let id = match record.get(&column_name) {
+                Some(serde_json::Value::Object(o)) => o.get("id").cloned(),
+                value => value.cloned(),
+              };
+              let id = match id {
+                Some(serde_json::Value::String(id)) => id,
+                Some(serde_json::Value::Number(id)) => id.to_string(),
+                _ => return Ok(None),
+              };
+              return read_optional(context(&ctx)?, &foreign_api_name, &id).await;
+            });
+          },
+        ));
+      }
+    }
+
+    // Filter input.
+    let filter_name = format!("{select}Filter");
+    let mut filter = InputObject::new(&filter_name)
+      .field(InputValue::new("and", TypeRef::named_nn_list(&filter_name)))
+      .field(InputValue::new("or", TypeRef::named_nn_list(&filter_name)));
+    for field in &model.fields {
+      if !api.filter_columns.contains(&field.name) || !is_valid_name(&field.name) {
+        continue;
+      }
+      if let Some(scalar_filter) = scalar_filter_name(&field.ty) {
+        filter = filter.field(InputValue::new(&field.name, TypeRef::named(scalar_filter)));
+      }
+    }
+    inputs.push(filter);
+
+    // List response.
+    let list_name = format!("{select}List");
+    objects.push(
+      Object::new(&list_name)
+        .field(Field::new(
+          "records",
+          TypeRef::named_nn_list_nn(&select),
+          |ctx| {
+            return FieldFuture::new(async move {
+              let list = ctx.parent_value.try_downcast_ref::<ListResponse>()?;
+              return Ok(Some(FieldValue::list(
+                list.records.iter().cloned().map(FieldValue::owned_any),
+              )));
+            });
+          },
+        ))
+        .field(Field::new(
+          "cursor",
+          TypeRef::named(TypeRef::STRING),
+          |ctx| {
+            return FieldFuture::new(async move {
+              let list = ctx.parent_value.try_downcast_ref::<ListResponse>()?;
+              return Ok(
+                list
+                  .cursor
+                  .clone()
+                  .map(|c| FieldValue::value(Value::String(c))),
+              );
+            });
+          },
+        ))
+        .field(Field::new(
+          "total_count",
+          TypeRef::named(TypeRef::INT),
+          |ctx| {
+            return FieldFuture::new(async move {
+              let list = ctx.parent_value.try_downcast_ref::<ListResponse>()?;
+              return Ok(
+                list
+                  .total_count
+                  .map(|c| FieldValue::value(Value::from(c as i64))),
+              );
+            });
+          },
+        ))
+        .field(Field::new(
+          "has_more",
+          TypeRef::named(TypeRef::BOOLEAN),
+          |ctx| {
+            return FieldFuture::new(async move {
+              let list = ctx.parent_value.try_downcast_ref::<ListResponse>()?;
+              return Ok(list.has_more.map(|h| FieldValue::value(Value::Boolean(h))));
+            });
+          },
+        )),
+    );
+
+    // Queries.
+    let api_name = api.api_name.clone();
+    query = query.field(
+      Field::new(&api.api_name, TypeRef::named_nn(&list_name), move |ctx| {
+        let api_name = api_name.clone();
+        return FieldFuture::new(async move {
+          let context = context(&ctx)?;
+          let api = lookup_api(&context.state, &api_name)?;
+          let query = list_query(&ctx)?;
+
+          let listing = list_records(
+            &context.state,
+            &api,
+            Some(&query),
+            context.user.clone(),
+            AcceptFormat::Json,
+          )
+          .await
+          .map_err(graphql_error)?;
+          let Listing::Records(list) = listing else {
+            return Err(async_graphql::Error::new("Unexpected listing"));
+          };
+          return Ok(Some(FieldValue::owned_any(list)));
+        });
+      })
+      .argument(InputValue::new("filter", TypeRef::named(&filter_name)))
+      .argument(InputValue::new(
+        "order",
+        TypeRef::named_nn_list(TypeRef::STRING),
+      ))
+      .argument(InputValue::new("limit", TypeRef::named(TypeRef::INT)))
+      .argument(InputValue::new("offset", TypeRef::named(TypeRef::INT)))
+      .argument(InputValue::new("cursor", TypeRef::named(TypeRef::STRING)))
+      .argument(InputValue::new("count", TypeRef::named(TypeRef::BOOLEAN))),
+    );
+
+    let api_name = api.api_name.clone();
+    query = query.field(
+      Field::new(
+        format!("{}_by_id", api.api_name),
+        TypeRef::named(&select),
+        move |ctx| {
+          let api_name = api_name.clone();
+          return FieldFuture::new(async move {
+            let id = ctx.args.try_get("id")?.string()?;
+            return read_optional(context(&ctx)?, &api_name, id).await;
+          });
+        },
+      )
+      .argument(InputValue::new("id", TypeRef::named_nn(TypeRef::ID))),
+    );
+
+    // Mutations.
+    let (Some(insert), Some(update)) = (&api.insert, &api.update) else {
+      continue;
+    };
+    has_mutations = true;
+
+    let api_name = api.api_name.clone();
+    mutation = mutation.field(
+      Field::new(
+        format!("create_{}", api.api_name),
+        TypeRef::named(TypeRef::ID),
+        move |ctx| {
+          let api_name = api_name.clone();
+          return FieldFuture::new(async move {
+            let context = context(&ctx)?;
+            let record = ctx.args.try_get("record")?.as_value().clone().into_json()?;
+
+            let response = create_record_handler(
+              State(context.state.clone()),
+              Path(api_name),
+              Query(CreateRecordQuery::default()),
+              context.user.clone(),
+              Either::Json(record),
+            )
+            .await
+            .map_err(graphql_error)?;
+
+            let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
+            let created: CreateRecordResponse = serde_json::from_slice(&bytes)?;
+            return Ok(
+              created
+                .ids
+                .into_iter()
+                .next()
+                .map(|id| FieldValue::value(Value::String(id))),
+            );
+          });
+        },
+      )
+      .argument(InputValue::new("record", TypeRef::named_nn(insert))),
+    );
+
+    let api_name = api.api_name.clone();
+    mutation = mutation.field(
+      Field::new(
+        format!("update_{}", api.api_name),
+        TypeRef::named(&select),
+        move |ctx| {
+          let api_name = api_name.clone();
+          return FieldFuture::new(async move {
+            let context = context(&ctx)?;
+            let id = ctx.args.try_get("id")?.string()?.to_string();
+            let serde_json::Value::Object(record) =
+              ctx.args.try_get("record")?.as_value().clone().into_json()?
+            else {
+              return Err(async_graphql::Error::new("Invalid record"));
+            };
+
+            update_record_handler(
+              State(context.state.clone()),
+              Path((api_name.clone(), id.clone())),
+              context.user.clone(),
+              HeaderMap::new(),
+              Either::Json(record),
+            )
+            .await
+            .map_err(graphql_error)?;
+
+            // Return the updated record, if the user may read it.
+            return match read_optional(context, &api_name, &id).await {
+              Ok(record) => Ok(record),
+              Err(_err) => Ok(None),
+            };
+          });
+        },
+      )
+      .argument(InputValue::new("id", TypeRef::named_nn(TypeRef::ID)))
+      .argument(InputValue::new("record", TypeRef::named_nn(update))),
+    );
+
+    let api_name = api.api_name.clone();
+    mutation = mutation.field(
+      Field::new(
+        format!("delete_{}", api.api_name),
+        TypeRef::named_nn(TypeRef::BOOLEAN),
+        move |ctx| {
+          let api_name = api_name.clone();
+          return FieldFuture::new(async move {
+            let context = context(&ctx)?;
+            let id = ctx.args.try_get("id")?.string()?.to_string();
+
+            delete_record_handler(
+              State(context.state.clone()),
+              Path((api_name, id)),
+              context.user.clone(),
+              HeaderMap::new(),
+            )
+            .await
+            .map_err(graphql_error)?;
+
+            return Ok(Some(FieldValue::value(Value::Boolean(true))));
+          });
+        },
+      )
+      .argument(InputValue::new("id", TypeRef::named_nn(TypeRef::ID))),
+    );
+  }
+
+  let mut builder = Schema::build(
+    query.type_name(),
+    has_mutations.then(|| mutation.type_name()),
+    None,
+  )
+  .register(Scalar::new(JSON_SCALAR))
+  .limit_depth(MAX_QUERY_DEPTH)
+  .limit_complexity(MAX_QUERY_COMPLEXITY)
+  .limit_recursive_depth(MAX_RECURSIVE_DEPTH);
+  builder = builder.register(query);
+  if has_mutations {
+    builder = builder.register(mutation);
+  }
+  for object in objects {
+    builder = builder.register(object);
+  }
+  for input in inputs {
+    builder = builder.register(input);
+  }
+
+  return builder
+    .finish()
+    .map_err(|err| CodegenError::Unsupported(err.to_string()));
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::app_state::test_state;
+  use crate::config::proto::PermissionFlag;
+  use crate::records::test_utils::add_record_api;
+  use crate::records::{AccessRules, Acls};
+
+  async fn execute(state: &AppState, query: &str) -> serde_json::Value {
+    let response = graphql_handler(
+      State(state.clone()),
+      None,
+      Json(async_graphql::Request::new(query)),
+    )
+    .await;
+
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+      .await
+      .unwrap();
+    return serde_json::from_slice(&bytes).unwrap();
+  }
+
+  #[test]
+  fn test_append_filter() {
+    let filter = Value::from_json(serde_json::json!({
+      "price": {"gte": 5, "lt": 10},
+      "or": [{"name": {"eq": "a"}}, {"name": {"like": "b%"}}],
+    }))
+    .unwrap();
+
+    let mut params = vec![];
+    append_filter("filter[$and]", &filter, &mut params).unwrap();
+    assert_eq!(
+      params,
+      [
+        ("filter[$and][0][price][gte]", "5"),
+        ("filter[$and][1][price][lt]", "10"),
+        ("filter[$and][2][$or][0][$and][0][name]", "a"),
+        ("filter[$and][2][$or][1][$and][0][name][like]", "b%"),
+      ]
+      .map(|(k, v)| (k.to_string(), v.to_string()))
+    );
+  }
+
+  #[tokio::test]
+  async fn test_graphql_queries_and_mutations() {
+    let state = test_state(None).await.unwrap();
+    state
+      .conn()
+      .execute_batch(
+        r#"
+          CREATE TABLE author (
+            id        INTEGER PRIMARY KEY,
+            name      TEXT NOT NULL
+          ) STRICT;
+          CREATE TABLE article (
+            id        INTEGER PRIMARY KEY,
+            title     TEXT NOT NULL,
+            views     INTEGER NOT NULL DEFAULT 0,
+            author    INTEGER REFERENCES author(id)
+          ) STRICT;
+
+          INSERT INTO author (id, name) VALUES (1, 'alice');
+        "#,
+      )
+      .await
+      .unwrap();
+    state.schema_metadata().invalidate_all().await.unwrap();
+
+    for (api_name, table_name) in [("authors", "author"), ("articles", "article")] {
+      add_record_api(
+        &state,
+        api_name,
+        table_name,
+        Acls {
+          world: vec![
+            PermissionFlag::Create,
+            PermissionFlag::Read,
+            PermissionFlag::Update,
+            PermissionFlag::Delete,
+          ],
+          ..Default::default()
+        },
+        AccessRules::default(),
+      )
+      .await
+      .unwrap();
+    }
+
+    for (title, views) in [("first", 5), ("second", 50)] {
+      let response = execute(
+        &state,
+        &format!(
+          r#"mutation {{ create_articles(record: {{title: "{title}", views: {views}, author: 1}}) }}"#
+        ),
+      )
+      .await;
+      assert!(response.get("errors").is_none(), "{response}");
+    }
+
+    let response = execute(
+      &state,
+      r#"{
+        articles(filter: {views: {gt: 10}}, count: true) {
+          records { title author_record { name } }
+          total_count
+        }
+      }"#,
+    )
+    .await;
+    assert_eq!(
+      serde_json::json!({
+        "data": {
+          "articles": {
+            "records": [{"title": "second", "author_record": {"name": "alice"}}],
+            "total_count": 1,
+          },
+        },
+      }),
+      response
+    );
+
+    let response = execute(
+      &state,
+      r#"mutation { update_articles(id: "1", record: {title: "updated"}) { title views } }"#,
+    )
+    .await;
+    assert_eq!(
+      serde_json::json!({"data": {"update_articles": {"title": "updated", "views": 5}}}),
+      response
+    );
+
+    let response = execute(&state, r#"mutation { delete_articles(id: "1") }"#).await;
+    assert_eq!(
+      serde_json::json!({"data": {"delete_articles": true}}),
+      response
+    );
+    let response = execute(&state, r#"{ articles_by_id(id: "1") { title } }"#).await;
+    assert_eq!(
+      serde_json::json!({"data": {"articles_by_id": null}}),
+      response
+    );
+
+    // Errors carry the record API's error codes.
+    let response = execute(
+      &state,
+      r#"mutation { create_articles(record: {title: "x", author: 5}) }"#,
+    )
+    .await;
+    assert_eq!(
+      Some("record/constraint_foreign_key"),
+      response["errors"][0]["extensions"]["code"].as_str(),
+      "{response}"
+    );
+  }
+
+  #[tokio::test]
+  async fn test_graphql_query_limits() {
+    let state = test_state(None).await.unwrap();
+    state
+      .conn()
+      .execute_batch(
+        r#"
+          CREATE TABLE node (
+            id        INTEGER PRIMARY KEY,
+            parent    INTEGER REFERENCES node(id)
+          ) STRICT;
+
+          INSERT INTO node (id, parent) VALUES (1, NULL), (2, 1);
+        "#,
+      )
+      .await
+      .unwrap();
+    state.schema_metadata().invalidate_all().await.unwrap();
+
+    add_record_api(
+      &state,
+      "nodes",
+      "node",
+      Acls {
+        world: vec![PermissionFlag::Read],
+        ..Default::default()
+      },
+      AccessRules::default(),
+    )
+    .await
+    .unwrap();
+
+    let nested_query = |depth: usize| {
+      return format!(
+        r#"{{ nodes_by_id(id: "2") {{ {}id{} }} }}"#,
+        "parent_record { ".repeat(depth),
+        " }".repeat(depth)
+      );
+    };
+
+    let response = execute(&state, &nested_query(1)).await;
+    assert_eq!(
+      serde_json::json!({"data": {"nodes_by_id": {"parent_record": {"id": 1}}}}),
+      response
+    );
+
+    // Self-referencing relations must not allow unbounded nesting.
+    let response = execute(&state, &nested_query(MAX_QUERY_DEPTH)).await;
+    assert!(response["data"].is_null(), "{response}");
+    assert!(
+      response["errors"][0]["message"]
+        .as_str()
+        .is_some_and(|message| message.contains("nested too deep")),
+      "{response}"
+    );
+  }
+}
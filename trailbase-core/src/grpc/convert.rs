@@ -0,0 +1,186 @@
+use prost_reflect::{DynamicMessage, FieldDescriptor, Kind, MessageDescriptor, Value};
+use thiserror::Error;
+
+use crate::codegen::{ClientSchema, FieldType, Model};
+
+#[derive(Debug, Error)]
+pub enum ConvertError {
+  #[error("Missing model: {0}")]
+  MissingModel(String),
+  #[error("Missing field: {0}")]
+  MissingField(String),
+  #[error("Invalid value for: {0}")]
+  InvalidValue(String),
+  #[error("Json: {0}")]
+  Json(#[from] serde_json::Error),
+}
+
+/// Converts between JSON records and protobuf messages rendered from the same `ClientSchema`.
+///
+/// Field numbers correspond to a field's position in its model and enum numbers to a value's
+/// position in its enum, both offset by one. This mapping is independent of proto's JSON
+/// mapping, which renames fields and enum values.
+pub(crate) struct Converter<'a> {
+  pub schema: &'a ClientSchema,
+}
+
+impl Converter<'_> {
+  fn model(&self, name: &str) -> Result<&Model, ConvertError> {
+    return self
+      .schema
+      .models
+      .iter()
+      .find(|m| m.name == name)
+      .ok_or_else(|| ConvertError::MissingModel(name.to_string()));
+  }
+
+  pub fn record_to_message(
+    &self,
+    model_name: &str,
+    desc: &MessageDescriptor,
+    record: serde_json::Value,
+  ) -> Result<DynamicMessage, ConvertError> {
+    let model = self.model(model_name)?;
+    let serde_json::Value::Object(mut record) = record else {
+      return Err(ConvertError::InvalidValue(model_name.to_string()));
+    };
+
+    let mut message = DynamicMessage::new(desc.clone());
+    for (index, field) in model.fields.iter().enumerate() {
+      let value = match record.remove(&field.name) {
+        Some(serde_json::Value::Null) | None => continue,
+        Some(value) => value,
+      };
+
+      let field_desc = field_descriptor(desc, index, &field.name)?;
+      let value = self.json_to_value(&field.name, &field.ty, &field_desc.kind(), value)?;
+      message.set_field(&field_desc, value);
+    }
+
+    return Ok(message);
+  }
+
+  fn json_to_value(
+    &self,
+    name: &str,
+    ty: &FieldType,
+    kind: &Kind,
+    value: serde_json::Value,
+  ) -> Result<Value, ConvertError> {
+    let invalid = || ConvertError::InvalidValue(name.to_string());
+
+    return Ok(match (ty, value) {
+      (FieldType::Array(item), serde_json::Value::Array(items))
+        if !matches!(**item, FieldType::Array(_)) =>
+      {
+        Value::List(
+          items
+            .into_iter()
+            .map(|v| self.json_to_value(name, item, kind, v))
+            .collect::<Result<Vec<_>, _>>()?,
+        )
+      }
+      (FieldType::String, serde_json::Value::String(s)) => Value::String(s),
+      (FieldType::Integer, serde_json::Value::Number(n)) => {
+        Value::I64(n.as_i64().ok_or_else(invalid)?)
+      }
+      (FieldType::Number, serde_json::Value::Number(n)) => {
+        Value::F64(n.as_f64().ok_or_else(invalid)?)
+      }
+      (FieldType::Boolean, serde_json::Value::Bool(b)) => Value::Bool(b),
+      (FieldType::Enum(values), serde_json::Value::String(s)) => {
+        let index = values.iter().position(|v| *v == s).ok_or_else(invalid)?;
+        match kind {
+          Kind::Enum(_) => Value::EnumNumber(index as i32 + 1),
+          _ => Value::String(s),
+        }
+      }
+      (FieldType::Model(model_name), value) => {
+        let Kind::Message(desc) = kind else {
+          return Err(invalid());
+        };
+        Value::Message(self.record_to_message(model_name, desc, value)?)
+      }
+      // Arbitrary JSON and nested arrays map to `google.protobuf.Value` and `ListValue`.
+      (FieldType::Any | FieldType::Array(_), value) => {
+        let Kind::Message(desc) = kind else {
+          return Err(invalid());
+        };
+        Value::Message(DynamicMessage::deserialize(desc.clone(), value)?)
+      }
+      _ => {
+        return Err(invalid());
+      }
+    });
+  }
+
+  pub fn message_to_record(
+    &self,
+    model_name: &str,
+    message: &DynamicMessage,
+  ) -> Result<serde_json::Value, ConvertError> {
+    let model = self.model(model_name)?;
+    let desc = message.descriptor();
+
+    let mut record = serde_json::Map::new();
+    for (index, field) in model.fields.iter().enumerate() {
+      let field_desc = field_descriptor(&desc, index, &field.name)?;
+      // Unset optional fields are omitted, which matters for partial updates.
+      if field_desc.supports_presence() && !message.has_field(&field_desc) {
+        continue;
+      }
+
+      record.insert(
+        field.name.clone(),
+        self.value_to_json(&field.name, &field.ty, &message.get_field(&field_desc))?,
+      );
+    }
+
+    return Ok(serde_json::Value::Object(record));
+  }
+
+  fn value_to_json(
+    &self,
+    name: &str,
+    ty: &FieldType,
+    value: &Value,
+  ) -> Result<serde_json::Value, ConvertError> {
+    return Ok(match (ty, value) {
+      (FieldType::Array(item), Value::List(items)) => serde_json::Value::Array(
+        items
+          .iter()
+          .map(|v| self.value_to_json(name, item, v))
+          .collect::<Result<Vec<_>, _>>()?,
+      ),
+      (_, Value::String(s)) => serde_json::Value::String(s.clone()),
+      (_, Value::I64(i)) => serde_json::Value::from(*i),
+      (_, Value::F64(f)) => serde_json::Value::from(*f),
+      (_, Value::Bool(b)) => serde_json::Value::Bool(*b),
+      (FieldType::Enum(values), Value::EnumNumber(number)) => {
+        // Zero is the implicit `UNSPECIFIED` value.
+        let value = usize::try_from(*number - 1)
+          .ok()
+          .and_then(|index| values.get(index))
+          .ok_or_else(|| ConvertError::InvalidValue(name.to_string()))?;
+        serde_json::Value::String(value.clone())
+      }
+      (FieldType::Model(model_name), Value::Message(message)) => {
+        self.message_to_record(model_name, message)?
+      }
+      (_, Value::Message(message)) => serde_json::to_value(message)?,
+      _ => {
+        return Err(ConvertError::InvalidValue(name.to_string()));
+      }
+    });
+  }
+}
+
+fn field_descriptor(
+  desc: &MessageDescriptor,
+  index: usize,
+  name: &str,
+) -> Result<FieldDescriptor, ConvertError> {
+  return desc
+    .get_field(index as u32 + 1)
+    .ok_or_else(|| ConvertError::MissingField(name.to_string()));
+}
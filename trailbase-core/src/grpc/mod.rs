@@ -0,0 +1,628 @@
+//! Optional gRPC server for record APIs.
+//!
+//! Services and messages are rendered from the same `ClientSchema` as the `.proto` client
+//! codegen target, i.e. each record API is exposed as `trailbase.records.<Model>Service`. Calls
+//! are dispatched to the regular record API handlers, thus sharing access control and validation
+//! with the HTTP API.
+
+mod convert;
+
+use axum::extract::{OptionalFromRequestParts, Path, Query, Request, State};
+use axum::http::HeaderMap;
+use axum::http::header::CONTENT_TYPE;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use futures_util::future::BoxFuture;
+use futures_util::stream::{BoxStream, StreamExt};
+use parking_lot::Mutex;
+use prost::Message;
+use prost_reflect::{DescriptorPool, DynamicMessage, MessageDescriptor, MethodDescriptor, Value};
+use protox::file::{ChainFileResolver, File, FileResolver, GoogleFileResolver};
+use std::sync::Arc;
+use tonic::Status;
+use tonic::codec::{Codec, DecodeBuf, Decoder, EncodeBuf, Encoder};
+use tonic::server::{Grpc, ServerStreamingService, UnaryService};
+
+use crate::app_state::AppState;
+use crate::auth::User;
+use crate::codegen::{ClientSchema, CodegenError, RecordApiModel, build_client_schema, proto};
+use crate::extract::{AcceptFormat, Either};
+use crate::records::create_record::{
+  CreateRecordQuery, CreateRecordResponse, create_record_handler,
+};
+use crate::records::delete_record::delete_record_handler;
+use crate::records::list_records::{Listing, list_records};
+use crate::records::read_record::read_record;
+use crate::records::subscribe::{self, DbEvent};
+use crate::records::update_record::update_record_handler;
+use crate::records::{RecordApi, RecordError};
+
+use convert::{ConvertError, Converter};
+
+const PROTO_FILE: &str = "trailbase_records.proto";
+
+/// Compiled descriptors of the rendered `.proto` file alongside the schema they were rendered
+/// from.
+struct GrpcSchema {
+  client: ClientSchema,
+  pool: DescriptorPool,
+}
+
+impl GrpcSchema {
+  fn build(state: &AppState) -> Result<Self, Status> {
+    let client = build_client_schema(state)?;
+    let pool = compile(proto::render(&client)).map_err(|err| Status::internal(err.to_string()))?;
+    return Ok(Self { client, pool });
+  }
+
+  /// Returns the cached schema, rebuilding it whenever the record APIs have changed, e.g. due to
+  /// config or schema changes.
+  fn load(state: &AppState) -> Result<Arc<Self>, Status> {
+    type Cache = Option<(Arc<Vec<(String, RecordApi)>>, Arc<GrpcSchema>)>;
+    static CACHE: Mutex<Cache> = Mutex::new(None);
+
+    let record_apis = state.record_apis();
+    let mut cache = CACHE.lock();
+    if let Some((key, schema)) = cache.as_ref() {
+      if Arc::ptr_eq(key, &record_apis) {
+        return Ok(schema.clone());
+      }
+    }
+
+    let schema = Arc::new(Self::build(state)?);
+    *cache = Some((arc_swap::Guard::into_inner(record_apis), schema.clone()));
+    return Ok(schema);
+  }
+}
+
+/// Compiles the rendered `.proto` file, resolving well-known imports.
+fn compile(source: String) -> Result<DescriptorPool, protox::Error> {
+  struct SourceResolver(String);
+
+  impl FileResolver for SourceResolver {
+    fn open_file(&self, name: &str) -> Result<File, protox::Error> {
+      if name == PROTO_FILE {
+        return File::from_source(name, &self.0);
+      }
+      return Err(protox::Error::file_not_found(name));
+    }
+  }
+
+  let mut resolver = ChainFileResolver::new();
+  resolver.add(SourceResolver(source));
+  resolver.add(GoogleFileResolver::new());
+
+  let mut compiler = protox::Compiler::with_file_resolver(resolver);
+  compiler.include_imports(true);
+  compiler.open_file(PROTO_FILE)?;
+  return Ok(compiler.descriptor_pool());
+}
+
+impl From<CodegenError> for Status {
+  fn from(err: CodegenError) -> Self {
+    return Status::internal(err.to_string());
+  }
+}
+
+impl From<ConvertError> for Status {
+  fn from(err: ConvertError) -> Self {
+    return Status::invalid_argument(err.to_string());
+  }
+}
+
+fn is_grpc_request(headers: &HeaderMap) -> bool {
+  let Some(content_type) = headers.get(CONTENT_TYPE) else {
+    return false;
+  };
+  let content_type = content_type.as_bytes();
+  // gRPC-Web uses a different framing, which isn't supported.
+  return content_type.starts_with(b"application/grpc")
+    && !content_type.starts_with(b"application/grpc-web");
+}
+
+/// Middleware routing gRPC requests, i.e. requests with an `application/grpc` content type, to
+/// the record API services and everything else to the regular HTTP routes.
+pub(crate) async fn grpc_middleware(
+  State(state): State<AppState>,
+  request: Request,
+  next: Next,
+) -> Response {
+  if !is_grpc_request(request.headers()) {
+    return next.run(request).await;
+  }
+  return serve(state, request).await;
+}
+
+async fn serve(state: AppState, request: Request) -> Response {
+  let (mut parts, body) = request.into_parts();
+
+  let user = match <User as OptionalFromRequestParts<AppState>>::from_request_parts(
+    &mut parts, &state,
+  )
+  .await
+  {
+    Ok(user) => user,
+    Err(_err) => {
+      return Status::unauthenticated("Invalid auth token").into_http();
+    }
+  };
+
+  let schema = match GrpcSchema::load(&state) {
+    Ok(schema) => schema,
+    Err(status) => {
+      return status.into_http();
+    }
+  };
+
+  let Some((service_name, method_name)) = parts
+    .uri
+    .path()
+    .strip_prefix('/')
+    .and_then(|path| path.split_once('/'))
+  else {
+    return Status::unimplemented("Invalid path").into_http();
+  };
+
+  let Some(service) = schema.pool.get_service_by_name(service_name) else {
+    return Status::unimplemented(format!("Unknown service: {service_name}")).into_http();
+  };
+  let Some(method) = service.methods().find(|m| m.name() == method_name) else {
+    return Status::unimplemented(format!("Unknown method: {method_name}")).into_http();
+  };
+  let Some(api) = schema
+    .client
+    .apis
+    .iter()
+    .find(|api| format!("{}Service", api.select) == service.name())
+    .cloned()
+  else {
+    return Status::unimplemented(format!("Unknown service: {service_name}")).into_http();
+  };
+
+  let call = Arc::new(Call {
+    state,
+    schema,
+    api,
+    method: method.clone(),
+    user,
+  });
+
+  let request = Request::from_parts(parts, body);
+  let mut grpc = Grpc::new(DynamicCodec(method.input()));
+  if method.is_server_streaming() {
+    return grpc
+      .server_streaming(StreamingMethod(call), request)
+      .await
+      .into_response();
+  }
+  return grpc.unary(UnaryMethod(call), request).await.into_response();
+}
+
+/// A single RPC invocation of a record API's service.
+struct Call {
+  state: AppState,
+  schema: Arc<GrpcSchema>,
+  api: RecordApiModel,
+  method: MethodDescriptor,
+  user: Option<User>,
+}
+
+impl Call {
+  fn converter(&self) -> Converter<'_> {
+    return Converter {
+      schema: &self.schema.client,
+    };
+  }
+
+  fn message_descriptor(&self, name: &str) -> Result<MessageDescriptor, Status> {
+    return self
+      .schema
+      .pool
+      .get_message_by_name(&format!("trailbase.records.{name}"))
+      .ok_or_else(|| Status::internal(format!("Missing message: {name}")));
+  }
+
+  fn record_to_message(&self, record: serde_json::Value) -> Result<DynamicMessage, Status> {
+    let desc = self.message_descriptor(&self.api.select)?;
+    return self
+      .converter()
+      .record_to_message(&self.api.select, &desc, record)
+      .map_err(|err| Status::internal(err.to_string()));
+  }
+
+  async fn unary(&self, request: DynamicMessage) -> Result<DynamicMessage, Status> {
+    let state = State(self.state.clone());
+    let api_name = self.api.api_name.clone();
+    let user = self.user.clone();
+
+    let mut response = DynamicMessage::new(self.method.output());
+    match self.method.name() {
+      "List" => {
+        let Some(api) = self.state.lookup_record_api(&api_name) else {
+          return Err(RecordError::ApiNotFound.into());
+        };
+        let query = list_query(&request);
+        let Listing::Records(list) =
+          list_records(&self.state, &api, Some(&query), user, AcceptFormat::Json).await?
+        else {
+          return Err(Status::internal("Unexpected listing"));
+        };
+
+        let records = list
+          .records
+          .into_iter()
+          .map(|record| Ok(Value::Message(self.record_to_message(record)?)))
+          .collect::<Result<Vec<_>, Status>>()?;
+        response.set_field_by_name("records", Value::List(records));
+        if let Some(cursor) = list.cursor {
+          response.set_field_by_name("cursor", Value::String(cursor));
+        }
+        if let Some(total_count) = list.total_count {
+          response.set_field_by_name("total_count", Value::I64(total_count as i64));
+        }
+        if let Some(has_more) = list.has_more {
+          response.set_field_by_name("has_more", Value::Bool(has_more));
+        }
+      }
+      "Get" => {
+        let Some(api) = self.state.lookup_record_api(&api_name) else {
+          return Err(RecordError::ApiNotFound.into());
+        };
+        let record = read_record(
+          &self.state,
+          &api,
+          &required_id(&request)?,
+          None,
+          user.as_ref(),
+        )
+        .await?;
+
+        response = self.record_to_message(record)?;
+      }
+      "Create" => {
+        let Some(insert) = &self.api.insert else {
+          return Err(Status::unimplemented("Record API requires table"));
+        };
+        let record = self.converter().message_to_record(insert, &request)?;
+
+        let created: CreateRecordResponse = json_body(
+          create_record_handler(
+            state,
+            Path(api_name),
+            Query(CreateRecordQuery::default()),
+            user,
+            Either::Json(record),
+          )
+          .await?,
+        )
+        .await?;
+
+        if let Some(id) = created.ids.into_iter().next() {
+          response.set_field_by_name("id", Value::String(id));
+        }
+      }
+      "Update" => {
+        let Some(update) = &self.api.update else {
+          return Err(Status::unimplemented("Record API requires table"));
+        };
+        let id = required_id(&request)?;
+        let record = match request.get_field_by_name("record").as_deref() {
+          Some(Value::Message(record)) => self.converter().message_to_record(update, record)?,
+          _ => serde_json::Value::Object(Default::default()),
+        };
+        let serde_json::Value::Object(record) = record else {
+          return Err(Status::invalid_argument("Invalid record"));
+        };
+
+        update_record_handler(
+          state,
+          Path((api_name, id)),
+          user,
+          HeaderMap::new(),
+          Either::Json(record),
+        )
+        .await?;
+      }
+      "Delete" => {
+        delete_record_handler(
+          state,
+          Path((api_name, required_id(&request)?)),
+          user,
+          HeaderMap::new(),
+        )
+        .await?;
+      }
+      name => {
+        return Err(Status::unimplemented(format!("Unknown method: {name}")));
+      }
+    }
+
+    return Ok(response);
+  }
+
+  async fn subscribe(
+    self: Arc<Self>,
+    request: DynamicMessage,
+  ) -> Result<BoxStream<'static, Result<DynamicMessage, Status>>, Status> {
+    let record = optional_string(&request, "id").unwrap_or_else(|| "*".to_string());
+    let events = subscribe::subscribe(
+      &self.state,
+      &self.api.api_name,
+      &record,
+      None,
+      self.user.clone(),
+    )
+    .await?
+    .into_db_events();
+
+    return Ok(
+      events
+        .map(move |event| {
+          let mut message = DynamicMessage::new(self.method.output());
+          let (name, record) = match event.as_ref() {
+            DbEvent::Insert(record) => ("insert", record),
+            DbEvent::Update(record) => ("update", record),
+            DbEvent::Delete(record) => ("delete", record),
+            DbEvent::Error(err) => {
+              message.set_field_by_name("error", Value::String(err.clone()));
+              return Ok(message);
+            }
+          };
+
+          if let Some(record) = record {
+            let record = self.record_to_message(record.clone())?;
+            message.set_field_by_name(name, Value::Message(record));
+          }
+          return Ok(message);
+        })
+        .boxed(),
+    );
+  }
+}
+
+fn optional_string(message: &DynamicMessage, name: &str) -> Option<String> {
+  if !message.has_field_by_name(name) {
+    return None;
+  }
+  return message
+    .get_field_by_name(name)
+    .and_then(|v| v.as_str().map(|s| s.to_string()));
+}
+
+fn required_id(message: &DynamicMessage) -> Result<String, Status> {
+  return optional_string(message, "id").ok_or_else(|| Status::invalid_argument("Missing id"));
+}
+
+/// Translates a list request into the URL query understood by the HTTP list handler.
+fn list_query(message: &DynamicMessage) -> String {
+  let mut query = form_urlencoded::Serializer::new(String::new());
+  for name in ["limit", "offset"] {
+    if message.has_field_by_name(name) {
+      if let Some(value) = message.get_field_by_name(name).and_then(|v| v.as_i64()) {
+        query.append_pair(name, &value.to_string());
+      }
+    }
+  }
+  if let Some(cursor) = optional_string(message, "cursor") {
+    query.append_pair("cursor", &cursor);
+  }
+  if let Some(Value::List(order)) = message.get_field_by_name("order").as_deref() {
+    let order: Vec<&str> = order.iter().filter_map(|v| v.as_str()).collect();
+    if !order.is_empty() {
+      query.append_pair("order", &order.join(","));
+    }
+  }
+  if message
+    .get_field_by_name("count")
+    .and_then(|v| v.as_bool())
+    .unwrap_or(false)
+  {
+    query.append_pair("count", "true");
+  }
+
+  let mut query = query.finish();
+  // Filters are already URL-encoded.
+  if let Some(filter) = optional_string(message, "filter") {
+    if !query.is_empty() {
+      query.push('&');
+    }
+    query.push_str(&filter);
+  }
+  return query;
+}
+
+async fn json_body<T: serde::de::DeserializeOwned>(response: Response) -> Result<T, Status> {
+  let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+    .await
+    .map_err(|err| Status::internal(err.to_string()))?;
+  return serde_json::from_slice(&bytes).map_err(|err| Status::internal(err.to_string()));
+}
+
+struct UnaryMethod(Arc<Call>);
+
+impl UnaryService<DynamicMessage> for UnaryMethod {
+  type Response = DynamicMessage;
+  type Future = BoxFuture<'static, Result<tonic::Response<DynamicMessage>, Status>>;
+
+  fn call(&mut self, request: tonic::Request<DynamicMessage>) -> Self::Future {
+    let call = self.0.clone();
+    return Box::pin(async move {
+      let response = call.unary(request.into_inner()).await?;
+      return Ok(tonic::Response::new(response));
+    });
+  }
+}
+
+struct StreamingMethod(Arc<Call>);
+
+impl ServerStreamingService<DynamicMessage> for StreamingMethod {
+  type Response = DynamicMessage;
+  type ResponseStream = BoxStream<'static, Result<DynamicMessage, Status>>;
+  type Future = BoxFuture<'static, Result<tonic::Response<Self::ResponseStream>, Status>>;
+
+  fn call(&mut self, request: tonic::Request<DynamicMessage>) -> Self::Future {
+    let call = self.0.clone();
+    return Box::pin(async move {
+      let stream = call.subscribe(request.into_inner()).await?;
+      return Ok(tonic::Response::new(stream));
+    });
+  }
+}
+
+/// Codec for messages only known at runtime.
+struct DynamicCodec(MessageDescriptor);
+
+impl Codec for DynamicCodec {
+  type Encode = DynamicMessage;
+  type Decode = DynamicMessage;
+  type Encoder = DynamicEncoder;
+  type Decoder = DynamicDecoder;
+
+  fn encoder(&mut self) -> Self::Encoder {
+    return DynamicEncoder;
+  }
+
+  fn decoder(&mut self) -> Self::Decoder {
+    return DynamicDecoder(self.0.clone());
+  }
+}
+
+struct DynamicEncoder;
+
+impl Encoder for DynamicEncoder {
+  type Item = DynamicMessage;
+  type Error = Status;
+
+  fn encode(&mut self, item: Self::Item, dst: &mut EncodeBuf<'_>) -> Result<(), Self::Error> {
+    return item
+      .encode(dst)
+      .map_err(|err| Status::internal(err.to_string()));
+  }
+}
+
+struct DynamicDecoder(MessageDescriptor);
+
+impl Decoder for DynamicDecoder {
+  type Item = DynamicMessage;
+  type Error = Status;
+
+  fn decode(&mut self, src: &mut DecodeBuf<'_>) -> Result<Option<Self::Item>, Self::Error> {
+    return DynamicMessage::decode(self.0.clone(), src)
+      .map(Some)
+      .map_err(|err| Status::invalid_argument(err.to_string()));
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use axum::body::Body;
+
+  use super::*;
+  use crate::app_state::test_state;
+  use crate::config::proto::PermissionFlag;
+  use crate::records::test_utils::add_record_api;
+  use crate::records::{AccessRules, Acls};
+
+  async fn call(
+    state: &AppState,
+    method: &str,
+    request: &DynamicMessage,
+    response: MessageDescriptor,
+  ) -> DynamicMessage {
+    let bytes = request.encode_to_vec();
+    // Length-prefixed message framing: compression flag, big-endian length, payload.
+    let mut body = vec![0_u8];
+    body.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+    body.extend(bytes);
+
+    let http_request = Request::builder()
+      .method("POST")
+      .uri(format!("/trailbase.records.ArticlesService/{method}"))
+      .header(CONTENT_TYPE, "application/grpc")
+      .body(Body::from(body))
+      .unwrap();
+
+    let http_response = serve(state.clone(), http_request).await;
+    assert_eq!(None, http_response.headers().get("grpc-status"));
+
+    let bytes = axum::body::to_bytes(http_response.into_body(), usize::MAX)
+      .await
+      .unwrap();
+    assert!(bytes.len() >= 5, "{bytes:?}");
+    return DynamicMessage::decode(response, &bytes[5..]).unwrap();
+  }
+
+  #[tokio::test]
+  async fn test_create_and_get_record() {
+    let state = test_state(None).await.unwrap();
+    state
+      .conn()
+      .execute_batch(
+        r#"
+          CREATE TABLE article (
+            id        INTEGER PRIMARY KEY,
+            title     TEXT NOT NULL,
+            status    TEXT NOT NULL DEFAULT 'draft' CHECK(status IN ('draft', 'published'))
+          ) STRICT;
+        "#,
+      )
+      .await
+      .unwrap();
+    state.schema_metadata().invalidate_all().await.unwrap();
+
+    add_record_api(
+      &state,
+      "articles",
+      "article",
+      Acls {
+        world: vec![PermissionFlag::Create, PermissionFlag::Read],
+        ..Default::default()
+      },
+      AccessRules::default(),
+    )
+    .await
+    .unwrap();
+
+    let schema = GrpcSchema::load(&state).unwrap();
+    let message = |name: &str| {
+      return schema
+        .pool
+        .get_message_by_name(&format!("trailbase.records.{name}"))
+        .unwrap();
+    };
+
+    let mut insert = DynamicMessage::new(message("ArticlesInsert"));
+    insert.set_field_by_name("title", Value::String("first".to_string()));
+    // "published", i.e. the second value of the enum.
+    insert.set_field_by_name("status", Value::EnumNumber(2));
+
+    let created = call(&state, "Create", &insert, message("ArticlesCreateResponse")).await;
+    let id = created.get_field_by_name("id").unwrap();
+    assert_eq!(Some("1"), id.as_str());
+
+    let mut get = DynamicMessage::new(message("ArticlesGetRequest"));
+    get.set_field_by_name("id", id.into_owned());
+
+    let record = call(&state, "Get", &get, message("Articles")).await;
+    assert_eq!(
+      Some("first"),
+      record.get_field_by_name("title").unwrap().as_str()
+    );
+    assert_eq!(
+      Some(2),
+      record.get_field_by_name("status").unwrap().as_enum_number()
+    );
+
+    let converter = Converter {
+      schema: &schema.client,
+    };
+    assert_eq!(
+      serde_json::json!({
+        "id": 1,
+        "title": "first",
+        "status": "published",
+      }),
+      converter.message_to_record("Articles", &record).unwrap()
+    );
+  }
+}
@@ -8,6 +8,9 @@ use futures_util::FutureExt;
 use log::*;
 use serde::Deserialize;
 use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::time::Duration;
 use thiserror::Error;
 use tokio::sync::oneshot;
 
@@ -18,9 +21,19 @@ use trailbase_js::runtime::{
 
 use crate::AppState;
 use crate::auth::user::User;
+use crate::records::RecordError;
+use crate::records::hooks::{HookContext, HookEvent, RecordHookFn};
+use crate::records::validators::ColumnValidatorFn;
+use crate::scheduler::parse_schedule;
 
 type AnyError = Box<dyn std::error::Error + Send + Sync>;
 
+static RECORD_HOOK_ID_COUNTER: AtomicI64 = AtomicI64::new(0);
+static COLUMN_VALIDATOR_ID_COUNTER: AtomicI64 = AtomicI64::new(0);
+
+/// Upper bound for JS column validators, which block the request while running.
+const COLUMN_VALIDATOR_TIMEOUT: Duration = Duration::from_secs(1);
+
 pub struct DispatchArgs {
   pub method: String,
   pub route_path: String,
@@ -40,6 +53,13 @@ pub struct JsHttpResponse {
   pub body: Option<bytes::Bytes>,
 }
 
+/// Result of a JS record hook: either a modified record or the error thrown to veto the operation.
+#[derive(Deserialize, Default, Debug)]
+struct JsRecordHookResult {
+  record: Option<serde_json::Map<String, serde_json::Value>>,
+  error: Option<String>,
+}
+
 #[derive(Debug, Error)]
 pub enum JsHttpResponseError {
   #[error("Precondition: {0}")]
@@ -73,6 +93,96 @@ impl IntoResponse for JsHttpResponseError {
   }
 }
 
+/// Get's called from JS during `addRecordHook` and builds a hook calling back into the registered
+/// callback in JS.
+fn build_record_hook(runtime_handle: RuntimeHandle, id: i64) -> Arc<RecordHookFn> {
+  return Arc::new(
+    move |context: HookContext, record: Option<serde_json::Map<String, serde_json::Value>>| {
+      let runtime_handle = runtime_handle.clone();
+
+      return async move {
+        let Some(first_isolate) = runtime_handle.state().first() else {
+          return Err(RecordError::Internal("Missing isolate".into()));
+        };
+
+        let (sender, receiver) = oneshot::channel::<Result<JsRecordHookResult, RSError>>();
+        first_isolate
+          .send_privately(build_call_async_js_function_message::<JsRecordHookResult>(
+            None,
+            "__dispatchRecordHook",
+            serde_json::json!([id, context, record]),
+            sender,
+          ))
+          .await
+          .map_err(|err| RecordError::Internal(err.to_string().into()))?;
+
+        let result = receiver
+          .await
+          .map_err(|err| RecordError::Internal(err.into()))?
+          .map_err(|err| RecordError::Internal(err.to_string().into()))?;
+
+        if let Some(error) = result.error {
+          return Err(RecordError::Rejected(error));
+        }
+        return Ok(result.record);
+      }
+      .boxed();
+    },
+  );
+}
+
+/// Get's called from JS during `addColumnValidator` and builds a validator calling back into the
+/// registered callback in JS.
+///
+/// NOTE: Column validators are synchronous, since they're applied while converting request params.
+/// The validator thus blocks the calling thread until the first isolate has run the callback,
+/// which consequently must not be async either. On multi-threaded tokio runtimes, the wait is
+/// moved off the async worker via `block_in_place` to not stall other tasks.
+fn build_column_validator(runtime_handle: RuntimeHandle, id: i64) -> Arc<ColumnValidatorFn> {
+  return Arc::new(move |value: &serde_json::Value| {
+    let Some(first_isolate) = runtime_handle.state().first() else {
+      return Err("missing isolate".to_string());
+    };
+
+    let (sender, receiver) = std::sync::mpsc::sync_channel::<Result<Option<String>, RSError>>(1);
+    let args = serde_json::json!([id, value]);
+    let message = Message::Run(
+      None,
+      Box::new(move |module_handle, runtime: &mut Runtime| {
+        let _ = sender.send(runtime.call_function_immediate::<Option<String>>(
+          module_handle,
+          "__dispatchColumnValidator",
+          &args,
+        ));
+        return None;
+      }),
+    );
+
+    // The isolate's channel is unbounded, i.e. sending completes immediately.
+    let Some(Ok(())) = first_isolate.send_privately(message).now_or_never() else {
+      return Err("failed to dispatch validator".to_string());
+    };
+
+    let receive = || receiver.recv_timeout(COLUMN_VALIDATOR_TIMEOUT);
+    let result = match tokio::runtime::Handle::try_current() {
+      Ok(handle) if handle.runtime_flavor() == tokio::runtime::RuntimeFlavor::MultiThread => {
+        tokio::task::block_in_place(receive)
+      }
+      _ => receive(),
+    };
+
+    return match result {
+      Ok(Ok(None)) => Ok(()),
+      Ok(Ok(Some(message))) => Err(message),
+      Ok(Err(err)) => {
+        debug!("Column validator failed: {err}");
+        Err("validator failed".to_string())
+      }
+      Err(_) => Err("validator timed out".to_string()),
+    };
+  });
+}
+
 /// Get's called from JS during `addRoute` and installs an axum HTTP handler.
 ///
 /// The axum HTTP handler will then call back into the registered callback in JS.
@@ -180,6 +290,8 @@ async fn install_routes_and_jobs(
 ) -> Result<Option<Router<AppState>>, AnyError> {
   let runtime_handle = state.script_runtime();
   let jobs = state.jobs();
+  let record_hooks = state.record_hooks().clone();
+  let script_validators = state.script_validators().clone();
 
   // For all the isolates/worker-threads.
   let receivers: Vec<_> = runtime_handle
@@ -190,6 +302,8 @@ async fn install_routes_and_jobs(
       let module = module.clone();
       let runtime_handle = runtime_handle.clone();
       let jobs = jobs.clone();
+      let record_hooks = record_hooks.clone();
+      let script_validators = script_validators.clone();
 
       let (router_sender, router_receiver) = kanal::unbounded::<Router<AppState>>();
 
@@ -215,6 +329,50 @@ async fn install_routes_and_jobs(
               })
               .expect("Failed to register 'install_route' function");
 
+            // Register native callback for registering record lifecycle hooks.
+            let runtime_handle_clone = runtime_handle.clone();
+            runtime
+              .register_function(
+                "install_record_hook",
+                move |args: &[serde_json::Value]| -> Result<serde_json::Value, _> {
+                  let event: HookEvent = get_arg(args, 0)?;
+                  let api_name: String = get_arg(args, 1)?;
+
+                  let id = RECORD_HOOK_ID_COUNTER.fetch_add(1, Ordering::SeqCst);
+                  record_hooks.register(
+                    api_name,
+                    event,
+                    build_record_hook(runtime_handle_clone.clone(), id),
+                  );
+
+                  return Ok(id.into());
+                },
+              )
+              .expect("Failed to register 'install_record_hook' function");
+
+            // Register native callback for registering column validators.
+            let runtime_handle_clone = runtime_handle.clone();
+            runtime
+              .register_function(
+                "install_column_validator",
+                move |args: &[serde_json::Value]| -> Result<serde_json::Value, _> {
+                  let name: String = get_arg(args, 0)?;
+
+                  let id = COLUMN_VALIDATOR_ID_COUNTER.fetch_add(1, Ordering::SeqCst);
+                  if !script_validators.register(
+                    name.clone(),
+                    build_column_validator(runtime_handle_clone.clone(), id),
+                  ) {
+                    return Err(RSError::Runtime(format!(
+                      "Column validator already exists: {name}"
+                    )));
+                  }
+
+                  return Ok(id.into());
+                },
+              )
+              .expect("Failed to register 'install_column_validator' function");
+
             // Register native callback for registering cron jobs.
             runtime
               .register_function(
@@ -222,7 +380,7 @@ async fn install_routes_and_jobs(
                 move |args: &[serde_json::Value]| -> Result<serde_json::Value, _> {
                   let name: String = get_arg(args, 0)?;
                   let default_spec: String = get_arg(args, 1)?;
-                  let schedule = cron::Schedule::from_str(&default_spec).map_err(|err| {
+                  let schedule = parse_schedule(&default_spec).map_err(|err| {
                     return RSError::Runtime(err.to_string());
                   })?;
 
@@ -0,0 +1,148 @@
+use log::*;
+use std::path::PathBuf;
+
+use crate::config::proto::Config;
+use crate::data_dir::DataDir;
+
+/// Directory JSON schema files are loaded from, see `Config::schemas_path`.
+fn schemas_path(data_dir: &DataDir, config: &Config) -> PathBuf {
+  return match config.schemas_path {
+    Some(ref path) => data_dir.root().join(path),
+    None => data_dir.schemas_path(),
+  };
+}
+
+async fn fetch_json_schema(url: &str) -> Result<serde_json::Value, reqwest::Error> {
+  return reqwest::get(url).await?.error_for_status()?.json().await;
+}
+
+/// Collects user JSON schemas from the schema directory and the config, where config entries take
+/// precedence. Invalid config entries and unreachable URLs are skipped.
+async fn collect_json_schemas(
+  data_dir: &DataDir,
+  config: &Config,
+) -> Result<Vec<(String, serde_json::Value)>, trailbase_schema::Error> {
+  let mut schemas =
+    trailbase_schema::registry::load_schemas_from_dir(&schemas_path(data_dir, config))?;
+
+  for s in &config.schemas {
+    let Some(ref name) = s.name else {
+      warn!("Schema config entry missing name: {s:?}");
+      continue;
+    };
+
+    let json = match (&s.schema, &s.url) {
+      (Some(schema), _) => match serde_json::from_str(schema) {
+        Ok(json) => json,
+        Err(err) => {
+          error!("Invalid schema config entry for '{name}': {err}");
+          continue;
+        }
+      },
+      (None, Some(url)) => match fetch_json_schema(url).await {
+        Ok(json) => json,
+        Err(err) => {
+          error!("Failed to fetch schema '{name}' from {url}: {err}");
+          continue;
+        }
+      },
+      (None, None) => {
+        warn!("Schema config entry missing schema: {s:?}");
+        continue;
+      }
+    };
+
+    schemas.retain(|(n, _)| n != name);
+    schemas.push((name.clone(), json));
+  }
+
+  return Ok(schemas);
+}
+
+/// (Re-)loads user JSON schemas from the schema directory, the config and remote URLs, replacing
+/// all previously registered user schemas and recompiling their validators.
+///
+/// NOTE: Table metadata should be rebuilt afterwards, since it references schemas by name.
+pub(crate) async fn load_json_schemas(
+  data_dir: &DataDir,
+  config: &Config,
+) -> Result<(), trailbase_schema::Error> {
+  let schemas = collect_json_schemas(data_dir, config).await?;
+  debug!(
+    "Loaded JSON schemas: {:?}",
+    schemas.iter().map(|(name, _)| name).collect::<Vec<_>>()
+  );
+
+  return trailbase_schema::registry::set_user_schemas(schemas);
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::config::proto::JsonSchemaConfig;
+
+  #[tokio::test]
+  async fn test_collect_json_schemas() {
+    let temp_dir = temp_dir::TempDir::new().unwrap();
+    let data_dir = DataDir(temp_dir.path().to_path_buf());
+
+    std::fs::create_dir_all(data_dir.schemas_path()).unwrap();
+    std::fs::write(
+      data_dir.schemas_path().join("test.Name.json"),
+      r#"{"type": "string"}"#,
+    )
+    .unwrap();
+    std::fs::write(
+      data_dir.schemas_path().join("test.Overridden.json"),
+      r#"{"type": "string"}"#,
+    )
+    .unwrap();
+
+    let config = Config {
+      schemas: vec![
+        JsonSchemaConfig {
+          name: Some("test.Overridden".to_string()),
+          schema: Some(r#"{"type": "integer"}"#.to_string()),
+          ..Default::default()
+        },
+        JsonSchemaConfig {
+          name: Some("test.Person".to_string()),
+          schema: Some(r#"{"properties": {"name": {"$ref": "test.Name"}}}"#.to_string()),
+          ..Default::default()
+        },
+      ],
+      ..Default::default()
+    };
+
+    let schemas = collect_json_schemas(&data_dir, &config).await.unwrap();
+    assert_eq!(
+      vec![
+        (
+          "test.Name".to_string(),
+          serde_json::json!({"type": "string"})
+        ),
+        (
+          "test.Overridden".to_string(),
+          serde_json::json!({"type": "integer"})
+        ),
+        (
+          "test.Person".to_string(),
+          serde_json::json!({"properties": {"name": {"$ref": "test.Name"}}})
+        ),
+      ],
+      schemas
+    );
+
+    // A custom directory w/o schemas.
+    let config = Config {
+      schemas_path: Some("missing".to_string()),
+      ..Default::default()
+    };
+    assert!(
+      collect_json_schemas(&data_dir, &config)
+        .await
+        .unwrap()
+        .is_empty()
+    );
+  }
+}
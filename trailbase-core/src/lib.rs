@@ -6,32 +6,56 @@ pub mod app_state;
 pub mod config;
 pub mod constants;
 pub mod logging;
+pub mod openapi;
+pub mod problem;
 pub mod records;
 pub mod util;
 
 mod admin;
+mod audit;
 mod auth;
+mod backup;
+#[cfg(feature = "cdc")]
+mod cdc;
+mod client_ip;
+mod clock;
+mod codegen;
 mod connection;
 mod data_dir;
 mod email;
+mod export;
 mod extract;
+#[cfg(feature = "graphql")]
+mod graphql;
+#[cfg(feature = "grpc")]
+mod grpc;
 mod js;
+mod json_schemas;
 mod listing;
 mod migrations;
 mod queue;
+mod rate_limit;
+mod replication;
+mod retention;
 mod scheduler;
 mod schema_metadata;
+mod seed;
 mod server;
+mod sql_api;
+mod tenant;
 mod transaction;
 mod value_notifier;
+#[cfg(feature = "wasm")]
+mod wasm;
 
 #[cfg(test)]
 mod test;
 
 pub use app_state::AppState;
-pub use auth::User;
+pub use auth::{AdminRole, OptionalUser, RequireRole, Role, User};
+pub use clock::Clock;
 pub use data_dir::DataDir;
-pub use server::{InitError, Server, ServerOptions};
+pub use server::{InitError, Server, ServerBuilder, ServerOptions, ShutdownHandle};
 
 use prost_reflect::DescriptorPool;
 use std::sync::LazyLock;
@@ -43,31 +67,29 @@ static DESCRIPTOR_POOL: LazyLock<DescriptorPool> = LazyLock::new(|| {
   DescriptorPool::decode(FILE_DESCRIPTOR_SET).expect("Failed to load file descriptor set")
 });
 
-pub mod openapi {
-  use utoipa::OpenApi;
-
-  #[derive(OpenApi)]
-  #[openapi(
-        modifiers(),
-        nest(
-            (path = "/api/auth/v1", api = crate::auth::AuthAPI),
-            (path = "/api/records/v1", api = crate::records::RecordOpenApi),
-        ),
-        tags()
-    )]
-  pub struct Doc;
-}
-
 pub mod api {
+  pub use crate::admin::rows::seed_records;
+  pub use crate::admin::table::apply_schema;
   pub use crate::admin::user::{CreateUserRequest, create_user_handler};
   pub use crate::auth::api::login::login_with_password;
   pub use crate::auth::{JwtHelper, TokenClaims, force_password_reset};
-  pub use crate::connection::{Connection, init_main_db};
+  pub use crate::backup::{BackupError, BackupFile, create_backup, find_backup, restore_backup};
+  pub use crate::codegen::{CodegenError, CodegenTarget, generate_client};
+  pub use crate::connection::{
+    Connection, DatabaseKey, init_main_db, rekey_data_dir, set_database_key,
+  };
   pub use crate::email::{Email, EmailError};
   pub use crate::migrations::new_unique_migration_filename;
+  pub use crate::records::encryption::{EncryptionError, EncryptionKeys, set_encryption_keys};
+  pub use crate::records::hooks::{HookContext, HookEvent, RecordHookFn, RecordHooks};
   pub use crate::records::json_schema::build_api_json_schema;
-  pub use crate::schema_metadata::SchemaMetadataCache;
-  pub use crate::server::{InitArgs, init_app_state, serve};
+  pub use crate::records::validators::{
+    ColumnValidatorFactory, ColumnValidatorFn, ScriptValidators, register_column_validator,
+  };
+  pub use crate::replication::{ReplicationError, restore_replica};
+  pub use crate::schema_metadata::{SchemaMetadataCache, SchemaSnapshot, schema_snapshot};
+  pub use crate::seed::{Fixture, SeedError, SeedReport, load_fixtures, seed_fixtures};
+  pub use crate::server::{InitArgs, init_app_state, serve, serve_with_shutdown};
 
   pub use trailbase_schema::json_schema::JsonSchemaMode;
 }
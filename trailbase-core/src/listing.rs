@@ -1,13 +1,18 @@
+use base64::prelude::*;
 use lazy_static::lazy_static;
 use log::*;
 use std::borrow::Cow;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use thiserror::Error;
-use trailbase_schema::sqlite::Column;
+use trailbase_extension::geometry::EARTH_RADIUS_METERS;
+use trailbase_schema::metadata::{GeoPoint, extract_json_metadata};
+use trailbase_schema::sqlite::{Column, ColumnDataType};
 
-use crate::records::params::{json_string_to_value, prefix_colon};
+use crate::records::params::{ParamsError, json_string_to_value, prefix_colon};
 use crate::util::b64_to_id;
 
+mod odata;
+
 #[derive(Debug, Error)]
 pub enum WhereClauseError {
   #[error("Parse error: {0}")]
@@ -28,6 +33,17 @@ pub struct QueryParam {
   pub qualifier: Option<Qualifier>,
 }
 
+/// Grouped filter expression.
+///
+/// Syntax: ?filter[$or][0][price][lt]=10&filter[$or][1][category]=sale, where groups can be
+/// nested, e.g. filter[$or][0][$and][0][price][gt]=5.
+#[derive(Debug, PartialEq)]
+pub enum Filter {
+  Column(String, QueryParam),
+  And(Vec<Filter>),
+  Or(Vec<Filter>),
+}
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Qualifier {
   Not,
@@ -38,7 +54,19 @@ pub enum Qualifier {
   LessThanEqual,
   LessThan,
   Like,
+  /// Case-insensitive LIKE, i.e. `lower(lhs) LIKE lower(rhs)`, regardless of how LIKE is
+  /// configured.
+  ILike,
+  /// Case-sensitive pattern matching using Unix glob syntax, i.e. "*", "?" and "[...]".
+  Glob,
   Regexp,
+  /// Membership in a comma-separated list of values, e.g. "id[in]=1,2,3".
+  In,
+  NotIn,
+  /// Geo point within a bounding box, e.g. "location[within]=minLat,minLng,maxLat,maxLng".
+  Within,
+  /// Geo point within a radius in meters, e.g. "location[near]=lat,lng,radius".
+  Near,
 }
 
 impl Qualifier {
@@ -51,7 +79,13 @@ impl Qualifier {
       Some("not") => Some(Self::Not),
       Some("ne") => Some(Self::NotEqual),
       Some("like") => Some(Self::Like),
+      Some("ilike") => Some(Self::ILike),
+      Some("glob") => Some(Self::Glob),
       Some("re") => Some(Self::Regexp),
+      Some("in") => Some(Self::In),
+      Some("nin") => Some(Self::NotIn),
+      Some("within") => Some(Self::Within),
+      Some("near") => Some(Self::Near),
       None => Some(Self::Equal),
       _ => None,
     };
@@ -65,11 +99,25 @@ impl Qualifier {
       Self::LessThan => "<",
       Self::Not => "<>",
       Self::NotEqual => "<>",
-      Self::Like => "LIKE",
+      Self::Like | Self::ILike => "LIKE",
+      Self::Glob => "GLOB",
       Self::Regexp => "REGEXP",
       Self::Equal => "=",
+      Self::In => "IN",
+      Self::NotIn => "NOT IN",
+      Self::Within | Self::Near => unreachable!("geo filters are built by geo_filter_expression"),
     };
   }
+
+  /// Whether the qualifier's value is a comma-separated list.
+  pub fn is_list(self) -> bool {
+    return matches!(self, Self::In | Self::NotIn);
+  }
+
+  /// Whether the qualifier applies to geo points rather than columns.
+  pub fn is_geo(self) -> bool {
+    return matches!(self, Self::Within | Self::Near);
+  }
 }
 
 #[derive(Clone, Debug, PartialEq, PartialOrd)]
@@ -85,7 +133,9 @@ pub enum Cursor {
 }
 
 impl Cursor {
-  fn parse(value: &str) -> Option<Cursor> {
+  /// Parses a raw primary key cursor as used by the admin APIs, i.e. a base64 encoded UUID or an
+  /// integer. Record APIs use signed cursors instead.
+  pub fn parse(value: &str) -> Option<Cursor> {
     if let Ok(id) = b64_to_id(value) {
       return Some(Cursor::Blob(id.into()));
     }
@@ -107,14 +157,261 @@ impl From<Cursor> for rusqlite::types::Value {
   }
 }
 
+/// Distance metric for nearest neighbor search, i.e. "metric=<metric>".
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VectorMetric {
+  L2,
+  Cosine,
+  L1,
+}
+
+impl VectorMetric {
+  pub fn parse(value: &str) -> Option<Self> {
+    return match value {
+      "l2" => Some(Self::L2),
+      "cosine" => Some(Self::Cosine),
+      "l1" => Some(Self::L1),
+      _ => None,
+    };
+  }
+
+  /// Name of the respective sqlite-vec distance function.
+  pub fn distance_function(&self) -> &'static str {
+    return match self {
+      Self::L2 => "vec_distance_l2",
+      Self::Cosine => "vec_distance_cosine",
+      Self::L1 => "vec_distance_l1",
+    };
+  }
+}
+
+/// Nearest neighbor search, i.e. "nearest=<column>:<base64 vector>" or "vector=<base64 vector>"
+/// for APIs with a single vector column.
+#[derive(Debug, PartialEq)]
+pub struct Nearest {
+  /// Vector column or None if left to the API to pick.
+  pub column: Option<String>,
+  /// Little-endian float32 vector as stored by sqlite-vec.
+  pub vector: Vec<u8>,
+  /// Explicit metric or None for the column's declared metric falling back to L2.
+  pub metric: Option<VectorMetric>,
+}
+
+impl Nearest {
+  fn parse(value: &str) -> Option<Nearest> {
+    let (column, vector) = value.split_once(':')?;
+    if !sanitize_column_name(column) {
+      return None;
+    }
+
+    return Some(Nearest {
+      column: Some(column.to_string()),
+      ..Self::parse_vector(vector)?
+    });
+  }
+
+  fn parse_vector(value: &str) -> Option<Nearest> {
+    let vector = BASE64_URL_SAFE.decode(value).ok()?;
+    if vector.is_empty() || vector.len() % 4 != 0 {
+      return None;
+    }
+
+    return Some(Nearest {
+      column: None,
+      vector,
+      metric: None,
+    });
+  }
+
+  pub fn dimensions(&self) -> usize {
+    return self.vector.len() / 4;
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BoundingBox {
+  pub min_lat: f64,
+  pub min_lng: f64,
+  pub max_lat: f64,
+  pub max_lng: f64,
+}
+
+/// Geographic filter on a geo point, see [`Qualifier::Within`] and [`Qualifier::Near`].
+///
+/// NOTE: Bounding boxes crossing the antimeridian aren't supported.
+#[derive(Clone, Debug, PartialEq)]
+pub enum GeoFilter {
+  Within(BoundingBox),
+  Near { lat: f64, lng: f64, radius: f64 },
+}
+
+impl GeoFilter {
+  pub fn parse(query_param: &QueryParam) -> Option<Self> {
+    let values = query_param
+      .value
+      .split(',')
+      .map(|v| v.trim().parse::<f64>().ok().filter(|v| v.is_finite()))
+      .collect::<Option<Vec<_>>>()?;
+    let valid_lat = |lat: f64| (-90.0..=90.0).contains(&lat);
+    let valid_lng = |lng: f64| (-180.0..=180.0).contains(&lng);
+
+    return match (query_param.qualifier?, values.as_slice()) {
+      (Qualifier::Within, &[min_lat, min_lng, max_lat, max_lng]) => {
+        if !valid_lat(min_lat)
+          || !valid_lat(max_lat)
+          || !valid_lng(min_lng)
+          || !valid_lng(max_lng)
+          || min_lat > max_lat
+          || min_lng > max_lng
+        {
+          return None;
+        }
+        Some(Self::Within(BoundingBox {
+          min_lat,
+          min_lng,
+          max_lat,
+          max_lng,
+        }))
+      }
+      (Qualifier::Near, &[lat, lng, radius]) => {
+        if !valid_lat(lat) || !valid_lng(lng) || radius < 0.0 {
+          return None;
+        }
+        Some(Self::Near { lat, lng, radius })
+      }
+      _ => None,
+    };
+  }
+
+  /// Bounding box containing all matches, which lets indexes narrow down candidates before exact
+  /// distances are computed.
+  fn bounding_box(&self) -> BoundingBox {
+    let (lat, lng, radius) = match *self {
+      Self::Within(bbox) => return bbox,
+      Self::Near { lat, lng, radius } => (lat, lng, radius),
+    };
+
+    let d_lat = (radius / EARTH_RADIUS_METERS).to_degrees();
+    let (min_lat, max_lat) = (lat - d_lat, lat + d_lat);
+    if min_lat <= -90.0 || max_lat >= 90.0 {
+      // Includes a pole, i.e. all longitudes.
+      return BoundingBox {
+        min_lat: min_lat.max(-90.0),
+        min_lng: -180.0,
+        max_lat: max_lat.min(90.0),
+        max_lng: 180.0,
+      };
+    }
+
+    // Degrees of longitude shrink towards the poles, thus use the latitude furthest from the
+    // equator.
+    let d_lng = d_lat / min_lat.abs().max(max_lat.abs()).to_radians().cos();
+    let (min_lng, max_lng) = (lng - d_lng, lng + d_lng);
+    if min_lng < -180.0 || max_lng > 180.0 {
+      return BoundingBox {
+        min_lat,
+        min_lng: -180.0,
+        max_lat,
+        max_lng: 180.0,
+      };
+    }
+
+    return BoundingBox {
+      min_lat,
+      min_lng,
+      max_lat,
+      max_lng,
+    };
+  }
+}
+
+/// Distance in meters between `point` and the center of a [`GeoFilter::Near`] bound by
+/// [`geo_filter_expression`] with the same `prefix`.
+pub fn geo_distance_expression(table_name: &str, point: &GeoPoint, prefix: &str) -> String {
+  return format!(
+    r#"geo_distance({table_name}."{lat}", {table_name}."{lng}", {prefix}_lat, {prefix}_lng)"#,
+    lat = point.lat_column,
+    lng = point.lng_column,
+  );
+}
+
+/// Builds the where clause for a geo filter, binding its values as `{prefix}_<name>`. Bounding
+/// boxes are looked up in the point's R*Tree index, if any, before checking the exact coordinates.
+pub fn geo_filter_expression(
+  table_name: &str,
+  point: &GeoPoint,
+  filter: &GeoFilter,
+  prefix: &str,
+  params: &mut Vec<(Cow<'static, str>, trailbase_sqlite::Value)>,
+) -> String {
+  let mut bind = |name: &str, value: f64| -> String {
+    let name = format!("{prefix}_{name}");
+    params.push((name.clone().into(), trailbase_sqlite::Value::Real(value)));
+    return name;
+  };
+
+  let bbox = filter.bounding_box();
+  let min_lat = bind("min_lat", bbox.min_lat);
+  let min_lng = bind("min_lng", bbox.min_lng);
+  let max_lat = bind("max_lat", bbox.max_lat);
+  let max_lng = bind("max_lng", bbox.max_lng);
+
+  let mut clauses: Vec<String> = vec![];
+  if let Some(ref rtree) = point.rtree {
+    // NOTE: R*Trees store rounded 32-bit floats, i.e. results may include false positives.
+    clauses.push(format!(
+      r#"{table_name}.rowid IN (SELECT "{id}" FROM "{rtree_table}" WHERE "{lat1}" >= {min_lat} AND "{lat0}" <= {max_lat} AND "{lng1}" >= {min_lng} AND "{lng0}" <= {max_lng})"#,
+      id = rtree.id_column,
+      rtree_table = rtree.table_name,
+      lat0 = rtree.lat_columns.0,
+      lat1 = rtree.lat_columns.1,
+      lng0 = rtree.lng_columns.0,
+      lng1 = rtree.lng_columns.1,
+    ));
+  }
+  clauses.push(format!(
+    r#"{table_name}."{lat}" BETWEEN {min_lat} AND {max_lat} AND {table_name}."{lng}" BETWEEN {min_lng} AND {max_lng}"#,
+    lat = point.lat_column,
+    lng = point.lng_column,
+  ));
+
+  if let GeoFilter::Near { lat, lng, radius } = *filter {
+    bind("lat", lat);
+    bind("lng", lng);
+    let radius = bind("radius", radius);
+    clauses.push(format!(
+      "{} <= {radius}",
+      geo_distance_expression(table_name, point, prefix)
+    ));
+  }
+
+  return clauses.join(" AND ");
+}
+
+/// Alternative listing formats, i.e. "format=<format>".
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ListFormat {
+  /// GeoJSON FeatureCollection.
+  GeoJson,
+}
+
+/// How to count the records matching a listing, i.e. "count=true" or "count=estimate".
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CountMode {
+  Exact,
+  /// Cheap estimate, e.g. from the table's max rowid, if available. Falls back to exact counting.
+  Estimate,
+}
+
 #[derive(Default, Debug)]
 pub struct QueryParseResult {
   // Pagination parameters.
   pub limit: Option<usize>,
-  pub cursor: Option<Cursor>,
+  /// Raw cursor, whose interpretation is up to the respective API, see [`Cursor::parse`].
+  pub cursor: Option<String>,
   pub offset: Option<usize>,
-  pub count: Option<bool>,
-  pub expand: Option<Vec<String>>,
+  pub count: Option<CountMode>,
+  pub expand: Option<Vec<Expansion>>,
 
   // Ordering. It's a vector for &order=-col0,+col1,col2
   pub order: Option<Vec<(String, Order)>>,
@@ -122,25 +419,121 @@ pub struct QueryParseResult {
   // Map from filter params to filter value. It's a vector in cases like
   // "col0[gte]=2&col0[lte]=10".
   pub params: Option<HashMap<String, Vec<QueryParam>>>,
+
+  // Grouped filter expression, i.e. "filter[$or][0][col]=value", AND-ed with the above params.
+  pub filter: Option<Filter>,
+
+  // Subset of columns to return, i.e. "select=col0,col1" or OData's $select.
+  pub select: Option<Vec<String>>,
+
+  // Vector similarity search returning the k nearest records.
+  pub nearest: Option<Nearest>,
+  pub k: Option<usize>,
+
+  // Full-text search query, i.e. "search=<terms>", for tables with an FTS5 index.
+  pub search: Option<String>,
+
+  // Named collation applied to ordering and text comparisons, e.g. for case-insensitive matching
+  // of non-ASCII text.
+  pub collate: Option<String>,
+
+  pub format: Option<ListFormat>,
+
+  // Include soft-deleted records, i.e. "include_deleted=true", for APIs with soft-deletion.
+  pub include_deleted: Option<bool>,
 }
 
-pub fn limit_or_default(limit: Option<usize>) -> Result<usize, &'static str> {
-  const DEFAULT_LIMIT: usize = 50;
-  const MAX_LIMIT: usize = 256;
+impl QueryParseResult {
+  /// Canonical representation of the parsed query, e.g. for keying cached responses. Equivalent
+  /// queries yield the same representation regardless of parameter order or encoding.
+  pub(crate) fn canonical(&self) -> String {
+    let Self {
+      limit,
+      cursor,
+      offset,
+      count,
+      expand,
+      order,
+      params,
+      filter,
+      select,
+      nearest,
+      k,
+      search,
+      collate,
+      format,
+      include_deleted,
+    } = self;
 
-  if let Some(limit) = limit {
-    if limit > MAX_LIMIT {
-      return Err("limit exceeds max limit of 256");
-    }
-    return Ok(limit);
+    let params: Option<BTreeMap<&String, Vec<String>>> = params.as_ref().map(|params| {
+      return params
+        .iter()
+        .map(|(column, params)| {
+          let mut params: Vec<String> = params.iter().map(|p| format!("{p:?}")).collect();
+          params.sort();
+          return (column, params);
+        })
+        .collect();
+    });
+
+    return format!(
+      "{limit:?}|{cursor:?}|{offset:?}|{count:?}|{expand:?}|{order:?}|{params:?}|{filter:?}|\
+       {select:?}|{nearest:?}|{k:?}|{search:?}|{collate:?}|{format:?}|{include_deleted:?}"
+    );
+  }
+}
+
+/// The requested page size exceeds the applicable maximum.
+#[derive(Clone, Copy, Debug, Error, PartialEq, Eq)]
+#[error("limit exceeds max limit of {max_limit}")]
+pub struct LimitExceeded {
+  pub max_limit: usize,
+}
+
+/// Page size bounds of listings.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ListLimits {
+  pub default_limit: usize,
+  pub max_limit: usize,
+}
+
+impl Default for ListLimits {
+  fn default() -> Self {
+    return Self {
+      default_limit: 50,
+      max_limit: 256,
+    };
   }
-  return Ok(limit.unwrap_or(DEFAULT_LIMIT));
+}
+
+impl ListLimits {
+  /// Returns the requested limit or the default, if none was requested.
+  pub fn limit(&self, limit: Option<usize>) -> Result<usize, LimitExceeded> {
+    return match limit {
+      Some(limit) if limit > self.max_limit => Err(LimitExceeded {
+        max_limit: self.max_limit,
+      }),
+      Some(limit) => Ok(limit),
+      None => Ok(self.default_limit.min(self.max_limit)),
+    };
+  }
+}
+
+pub fn limit_or_default(limit: Option<usize>) -> Result<usize, LimitExceeded> {
+  return ListLimits::default().limit(limit);
+}
+
+fn parse_count(s: &str) -> Option<CountMode> {
+  if s == "estimate" {
+    return Some(CountMode::Estimate);
+  }
+  return parse_bool(s).unwrap_or(false).then_some(CountMode::Exact);
 }
 
 fn parse_bool(s: &str) -> Option<bool> {
   return match s {
     "TRUE" | "true" | "1" => Some(true),
-    "FALSE" | "false" | "0" => Some(true),
+    "FALSE" | "false" | "0" => Some(false),
     _ => None,
   };
 }
@@ -154,12 +547,278 @@ fn sanitize_column_name(name: &str) -> bool {
     .all(|c| c.is_alphanumeric() || c == '.' || c == '-' || c == '_');
 }
 
+/// Parses a comma separated list of column names, e.g. "id,name", as used for projections.
+pub fn parse_select(select: &str) -> Result<Vec<String>, String> {
+  return select
+    .split(',')
+    .map(|column| {
+      let column = column.trim();
+      if column.is_empty() || !sanitize_column_name(column) {
+        return Err(column.to_string());
+      }
+      return Ok(column.to_string());
+    })
+    .collect();
+}
+
+/// Foreign key expansion, e.g. "author", "author.team" or "author(name,avatar)", or expansion of
+/// referencing records, e.g. "comments_via_post(limit=5,order=-created)".
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Expansion {
+  /// Foreign key column or reverse relation to be expanded.
+  pub column: String,
+  /// Subset of the foreign record's columns to return. Defaults to all columns.
+  pub select: Option<Vec<String>>,
+  /// Max number and order of referencing records for reverse relations.
+  pub limit: Option<usize>,
+  pub order: Option<Vec<(String, Order)>>,
+  /// Expansions of the foreign record's own foreign keys.
+  pub nested: Vec<Expansion>,
+}
+
+impl Expansion {
+  /// Number of levels, i.e. 1 for "author" and 2 for "author.team".
+  pub fn depth(&self) -> usize {
+    return 1 + self.nested.iter().map(Expansion::depth).max().unwrap_or(0);
+  }
+
+  fn merge(expansions: &mut Vec<Expansion>, mut path: Vec<Expansion>) -> Result<(), String> {
+    if path.is_empty() {
+      return Ok(());
+    }
+    let mut head = path.remove(0);
+
+    let Some(existing) = expansions.iter_mut().find(|e| e.column == head.column) else {
+      Self::merge(&mut head.nested, path)?;
+      expansions.push(head);
+      return Ok(());
+    };
+
+    fn merge_option<T: PartialEq>(existing: &mut Option<T>, other: Option<T>) -> bool {
+      match (&existing, other) {
+        (Some(a), Some(b)) if *a != b => return false,
+        (None, Some(b)) => *existing = Some(b),
+        _ => {}
+      };
+      return true;
+    }
+
+    if !merge_option(&mut existing.select, head.select)
+      || !merge_option(&mut existing.limit, head.limit)
+      || !merge_option(&mut existing.order, head.order)
+    {
+      return Err(head.column);
+    }
+    return Self::merge(&mut existing.nested, path);
+  }
+}
+
+/// Splits `s` at `sep` unless nested within parentheses.
+fn split_unnested(s: &str, sep: char) -> Result<Vec<&str>, String> {
+  let mut parts: Vec<&str> = vec![];
+  let mut depth: usize = 0;
+  let mut start = 0;
+  for (i, c) in s.char_indices() {
+    match c {
+      '(' => depth += 1,
+      ')' => depth = depth.checked_sub(1).ok_or_else(|| s.to_string())?,
+      c if c == sep && depth == 0 => {
+        parts.push(&s[start..i]);
+        start = i + c.len_utf8();
+      }
+      _ => {}
+    }
+  }
+  if depth != 0 {
+    return Err(s.to_string());
+  }
+  parts.push(&s[start..]);
+  return Ok(parts);
+}
+
+/// Parses a comma separated list of expansions, where nested expansions are separated by "." and
+/// the foreign record's columns can be projected in parentheses, e.g.
+/// "author(name,avatar).team,parent". Paths sharing a prefix are merged, e.g. "author,author.team".
+pub fn parse_expand(expand: &str) -> Result<Vec<Expansion>, String> {
+  let mut expansions: Vec<Expansion> = vec![];
+  for item in split_unnested(expand, ',')? {
+    let item = item.trim();
+    if item.is_empty() {
+      continue;
+    }
+
+    let path = split_unnested(item, '.')?
+      .into_iter()
+      .map(|segment| {
+        let (column, args) = match segment.split_once('(') {
+          Some((column, rest)) => {
+            let Some(args) = rest.strip_suffix(')') else {
+              return Err(segment.to_string());
+            };
+            (column, Some(args))
+          }
+          None => (segment, None),
+        };
+
+        if column.is_empty() || !sanitize_column_name(column) {
+          return Err(segment.to_string());
+        }
+
+        let mut expansion = Expansion {
+          column: column.to_string(),
+          ..Default::default()
+        };
+
+        // Arguments are either options, i.e. "limit=<n>" and "order=[+-]<col>", or the columns to
+        // be returned.
+        for arg in args.map(|args| args.split(',')).into_iter().flatten() {
+          let arg = arg.trim();
+          match arg.split_once('=') {
+            Some(("limit", limit)) => {
+              expansion.limit = Some(limit.parse::<usize>().map_err(|_| arg.to_string())?);
+            }
+            Some(("order", order)) => {
+              let col_order = match order {
+                x if x.starts_with("-") => (x[1..].to_string(), Order::Descending),
+                x if x.starts_with("+") => (x[1..].to_string(), Order::Ascending),
+                x => (x.to_string(), Order::Ascending),
+              };
+              if col_order.0.is_empty() || !sanitize_column_name(&col_order.0) {
+                return Err(arg.to_string());
+              }
+              expansion.order.get_or_insert_default().push(col_order);
+            }
+            Some(_) => return Err(arg.to_string()),
+            None => {
+              if arg.is_empty() || arg.contains('.') || !sanitize_column_name(arg) {
+                return Err(arg.to_string());
+              }
+              expansion
+                .select
+                .get_or_insert_default()
+                .push(arg.to_string());
+            }
+          }
+        }
+
+        return Ok(expansion);
+      })
+      .collect::<Result<Vec<_>, _>>()?;
+
+    Expansion::merge(&mut expansions, path)?;
+  }
+
+  return Ok(expansions);
+}
+
+/// Max number of elements of "in" and "nin" filters.
+const MAX_LIST_LEN: usize = 100;
+
+#[inline]
+fn list_len_exceeded(qualifier: Option<Qualifier>, value: &str) -> bool {
+  return qualifier.is_some_and(|q| q.is_list()) && value.split(',').count() > MAX_LIST_LEN;
+}
+
+/// Max nesting depth of grouped filter expressions.
+const MAX_FILTER_DEPTH: usize = 8;
+
+/// Intermediate representation of grouped filters, which are parsed one query parameter, i.e.
+/// path to a leaf, at a time.
+#[derive(Default)]
+struct FilterBuilder {
+  columns: Vec<(String, QueryParam)>,
+  and: BTreeMap<usize, FilterBuilder>,
+  or: BTreeMap<usize, FilterBuilder>,
+}
+
+impl FilterBuilder {
+  fn insert(&mut self, path: &[&str], value: &str, depth: usize) -> Result<(), ()> {
+    if depth > MAX_FILTER_DEPTH {
+      return Err(());
+    }
+
+    return match path {
+      [group @ ("$and" | "$or"), index, rest @ ..] => {
+        let index = index.parse::<usize>().map_err(|_| ())?;
+        let groups = if *group == "$and" {
+          &mut self.and
+        } else {
+          &mut self.or
+        };
+        groups
+          .entry(index)
+          .or_default()
+          .insert(rest, value, depth + 1)
+      }
+      [column, ops @ ..] if ops.len() <= 1 => {
+        if !sanitize_column_name(column) || value.is_empty() {
+          return Err(());
+        }
+        let qualifier = match ops.first() {
+          Some(op) => Some(Qualifier::from(Some(*op)).ok_or(())?),
+          None => Some(Qualifier::Equal),
+        };
+        if list_len_exceeded(qualifier, value) {
+          return Err(());
+        }
+
+        self.columns.push((
+          column.to_string(),
+          QueryParam {
+            value: value.to_string(),
+            qualifier,
+          },
+        ));
+        Ok(())
+      }
+      _ => Err(()),
+    };
+  }
+
+  fn build(self) -> Filter {
+    let mut operands: Vec<Filter> = self
+      .columns
+      .into_iter()
+      .map(|(column, param)| Filter::Column(column, param))
+      .collect();
+
+    // Members of an "$and" group are simply AND-ed with their siblings.
+    for (_index, group) in self.and {
+      operands.push(group.build());
+    }
+    if !self.or.is_empty() {
+      operands.push(Filter::Or(
+        self.or.into_values().map(FilterBuilder::build).collect(),
+      ));
+    }
+
+    if operands.len() == 1 {
+      return operands.pop().expect("len == 1");
+    }
+    return Filter::And(operands);
+  }
+}
+
+/// Splits "filter[a][b][c]" into ["a", "b", "c"] if key is a grouped filter.
+fn split_filter_key(key: &str) -> Option<Vec<&str>> {
+  let segments = key.strip_prefix("filter[")?.strip_suffix(']')?;
+  return Some(segments.split("][").collect());
+}
+
 /// Parses out list-related query params including pagination (limit, cursort), order, and filters.
 ///
 /// An example query may look like:
 ///  ?cursor=[0:16]&limit=50&order=price,-date&price[lte]=100&date[gte]=<timestamp>.
+///
+/// Disjunctions and nested groups can be expressed via "filter", e.g.:
+///  ?filter[$or][0][price][lt]=10&filter[$or][1][category]=sale.
+///
+/// Additionally, a subset of OData's query options is accepted, e.g.:
+///  ?$top=50&$orderby=price desc&$filter=price le 100 and contains(name,'foo').
 pub fn parse_and_sanitize_query(query: Option<&str>) -> Result<QueryParseResult, String> {
   let mut result: QueryParseResult = Default::default();
+  let mut filter: Option<FilterBuilder> = None;
+  let mut metric: Option<VectorMetric> = None;
   let Some(query) = query else {
     return Ok(result);
   };
@@ -171,23 +830,53 @@ pub fn parse_and_sanitize_query(query: Option<&str>) -> Result<QueryParseResult,
   for (key, value) in form_urlencoded::parse(query.as_bytes()) {
     match key.as_ref() {
       "limit" => result.limit = value.parse::<usize>().ok(),
-      "cursor" => result.cursor = Cursor::parse(value.as_ref()),
+      "cursor" => result.cursor = Some(value.to_string()),
       "offset" => result.offset = value.parse::<usize>().ok(),
-      "count" => result.count = parse_bool(&value),
+      "count" => result.count = parse_count(&value),
+      "include_deleted" => result.include_deleted = parse_bool(&value),
+      "nearest" | "vector" => {
+        if result.nearest.is_some() {
+          return Err(key.to_string());
+        }
+        let nearest = match key.as_ref() {
+          "nearest" => Nearest::parse(&value),
+          _ => Nearest::parse_vector(&value),
+        };
+        result.nearest = Some(nearest.ok_or_else(|| key.to_string())?);
+      }
+      "metric" => metric = Some(VectorMetric::parse(&value).ok_or_else(|| key.to_string())?),
+      "k" => result.k = value.parse::<usize>().ok(),
+      "search" => {
+        if value.trim().is_empty() {
+          return Err(key.to_string());
+        }
+        result.search = Some(value.to_string());
+      }
+      "format" => match value.as_ref() {
+        "geojson" => result.format = Some(ListFormat::GeoJson),
+        _ => return Err(key.to_string()),
+      },
+      "collate" => {
+        if value.is_empty() || !value.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+          return Err(key.to_string());
+        }
+        result.collate = Some(value.to_string());
+      }
       "expand" => {
-        let column_names = value
-          .split(",")
-          .map(|s| {
-            if !sanitize_column_name(s) {
-              return Err(s);
-            }
-
-            return Ok(s.to_string());
-          })
-          .collect::<Result<Vec<_>, _>>()?;
-
-        if !column_names.is_empty() {
-          result.expand = Some(column_names);
+        let expansions = parse_expand(&value)?;
+        if !expansions.is_empty() {
+          result.expand = Some(expansions);
+        }
+      }
+      "$top" => result.limit = Some(value.parse::<usize>().map_err(|_| key.to_string())?),
+      "$skip" => result.offset = Some(value.parse::<usize>().map_err(|_| key.to_string())?),
+      "$count" => result.count = parse_count(&value),
+      "$orderby" => result.order = Some(odata::parse_orderby(&value)?),
+      "select" | "$select" => result.select = Some(parse_select(&value)?),
+      "$filter" => {
+        let params = result.params.get_or_insert_default();
+        for (column_name, query_param) in odata::parse_filter(&value)? {
+          params.entry(column_name).or_default().push(query_param);
         }
       }
       "order" => {
@@ -212,6 +901,18 @@ pub fn parse_and_sanitize_query(query: Option<&str>) -> Result<QueryParseResult,
           result.order = Some(col_order);
         }
       }
+      key
+        if split_filter_key(key).is_some_and(|path| {
+          // A single known op, e.g. "filter[gt]", is a plain filter on a column named "filter".
+          !(path.len() == 1 && Qualifier::from(Some(path[0])).is_some())
+        }) =>
+      {
+        let path = split_filter_key(key).expect("checked above");
+        filter
+          .get_or_insert_default()
+          .insert(&path, &value, 0)
+          .map_err(|_| key.to_string())?;
+      }
       key => {
         // Key didn't match any of the predefined list operations (limit, cursor, order, ...), we
         // thus assume it's a column filter. We try to split any qualifier/operation, e.g.
@@ -232,6 +933,9 @@ pub fn parse_and_sanitize_query(query: Option<&str>) -> Result<QueryParseResult,
           value: value.to_string(),
           qualifier: Qualifier::from(maybe_op),
         };
+        if list_len_exceeded(query_param.qualifier, &query_param.value) {
+          return Err(key.to_string());
+        }
 
         let params = result.params.get_or_insert_default();
         if let Some(v) = params.get_mut(k) {
@@ -243,6 +947,15 @@ pub fn parse_and_sanitize_query(query: Option<&str>) -> Result<QueryParseResult,
     }
   }
 
+  result.filter = filter.map(FilterBuilder::build);
+
+  if let Some(metric) = metric {
+    let Some(ref mut nearest) = result.nearest else {
+      return Err("metric".to_string());
+    };
+    nearest.metric = Some(metric);
+  }
+
   return Ok(result);
 }
 
@@ -252,42 +965,293 @@ pub struct WhereClause {
   pub params: Vec<(Cow<'static, str>, trailbase_sqlite::Value)>,
 }
 
+/// Column or, for JSON columns, path into a column's value that a filter or ordering refers to.
+pub struct ColumnExpression<'a> {
+  pub column: &'a Column,
+  /// SQL expression, e.g. `_ROW_."price"` or `json_extract(_ROW_."profile", '$."age"')`.
+  pub sql: String,
+  /// Whether `sql` extracts a JSON path, i.e. the column's type doesn't apply.
+  pub json_path: bool,
+}
+
+/// Resolves a filter or order target, i.e. a column name or, for columns with JSON metadata, a
+/// dotted path such as "profile.address.city", which translates to
+/// `json_extract(<table_name>."profile", '$."address"."city"')`. Numeric segments index arrays.
+///
+/// IMPORTANT: We only include parameters with known columns to avoid building an invalid query
+/// early and forbid injections.
+pub fn column_expression<'a>(
+  table_name: &str,
+  columns: &'a [Column],
+  name: &str,
+) -> Result<ColumnExpression<'a>, WhereClauseError> {
+  if name.starts_with("_") {
+    return Err(WhereClauseError::UnrecognizedParam(format!(
+      "Invalid parameter: {name}"
+    )));
+  }
+
+  if let Some(column) = columns.iter().find(|c| c.name == name) {
+    return Ok(ColumnExpression {
+      column,
+      sql: format!(r#"{table_name}."{name}""#),
+      json_path: false,
+    });
+  }
+
+  let unrecognized =
+    || WhereClauseError::UnrecognizedParam(format!("Unrecognized parameter: {name}"));
+
+  let Some((column_name, path)) = name.split_once('.') else {
+    return Err(unrecognized());
+  };
+  let Some(column) = columns.iter().find(|c| c.name == column_name) else {
+    return Err(unrecognized());
+  };
+  if !column
+    .options
+    .iter()
+    .any(|opt| matches!(extract_json_metadata(opt), Ok(Some(_))))
+  {
+    return Err(unrecognized());
+  }
+
+  let mut json_path = "$".to_string();
+  for segment in path.split('.') {
+    // NOTE: Restricting segments keeps the path literal below free of quotes.
+    if segment.is_empty()
+      || !segment
+        .chars()
+        .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+    {
+      return Err(unrecognized());
+    }
+
+    if segment.chars().all(|c| c.is_ascii_digit()) {
+      json_path.push_str(&format!("[{segment}]"));
+    } else {
+      json_path.push_str(&format!(r#"."{segment}""#));
+    }
+  }
+
+  return Ok(ColumnExpression {
+    column,
+    sql: format!(r#"json_extract({table_name}."{column_name}", '{json_path}')"#),
+    json_path: true,
+  });
+}
+
+/// Converts values compared against JSON paths, whose type is only known at runtime. Numeric
+/// values are compared as numbers, everything else as text.
+fn json_path_value(value: String) -> trailbase_sqlite::Value {
+  if let Ok(i) = value.parse::<i64>() {
+    return trailbase_sqlite::Value::Integer(i);
+  }
+  if let Ok(f) = value.parse::<f64>() {
+    return trailbase_sqlite::Value::Real(f);
+  }
+  return trailbase_sqlite::Value::Text(value);
+}
+
+/// Converts a filter's value, which for list qualifiers comprises one value per element.
+fn filter_values(
+  target: &ColumnExpression,
+  qualifier: Qualifier,
+  value: String,
+) -> Result<Vec<trailbase_sqlite::Value>, ParamsError> {
+  let convert = |value: String| -> Result<trailbase_sqlite::Value, ParamsError> {
+    if target.json_path {
+      return Ok(json_path_value(value));
+    }
+    return json_string_to_value(target.column.data_type, value);
+  };
+
+  if !qualifier.is_list() {
+    return Ok(vec![convert(value)?]);
+  }
+
+  return value.split(',').map(|v| convert(v.to_string())).collect();
+}
+
+/// Comparison of `target` with placeholders `param_names`, e.g. `_ROW_."price" < :price` or
+/// `_ROW_."id" IN (:id_0, :id_1)` for list qualifiers.
+fn filter_expression(
+  target: &ColumnExpression,
+  qualifier: Qualifier,
+  values: &[trailbase_sqlite::Value],
+  param_names: &[String],
+  collation: Option<&str>,
+) -> String {
+  let op = qualifier.to_sql();
+  let is_text = if target.json_path {
+    values
+      .iter()
+      .all(|v| matches!(v, trailbase_sqlite::Value::Text(_)))
+  } else {
+    target.column.data_type == ColumnDataType::Text
+  };
+
+  // NOTE: LIKE ignores collations, thus ILIKE lower-cases both sides instead.
+  if qualifier == Qualifier::ILike {
+    return format!(
+      "lower({lhs}) {op} lower({})",
+      param_names.join(", "),
+      lhs = target.sql
+    );
+  }
+
+  // NOTE: Collations only affect comparisons, pattern matching ignores them.
+  let collate = match collation {
+    Some(collation)
+      if is_text
+        && !matches!(
+          qualifier,
+          Qualifier::Like | Qualifier::Glob | Qualifier::Regexp
+        ) =>
+    {
+      format!(" COLLATE {collation}")
+    }
+    _ => String::new(),
+  };
+
+  if qualifier.is_list() {
+    // The collation needs to go on the left-hand side, since the right-hand side is a list.
+    return format!(
+      "{lhs}{collate} {op} ({})",
+      param_names.join(", "),
+      lhs = target.sql
+    );
+  }
+
+  return format!(
+    "{lhs} {op} {}{collate}",
+    param_names.join(", "),
+    lhs = target.sql
+  );
+}
+
+/// Binds `values` as `{prefix}` or, for list qualifiers, `{prefix}_{i}` and returns the names.
+fn bind_filter_values(
+  prefix: &str,
+  qualifier: Qualifier,
+  values: &[trailbase_sqlite::Value],
+  params: &mut Vec<(Cow<'static, str>, trailbase_sqlite::Value)>,
+) -> Vec<String> {
+  if !qualifier.is_list() {
+    params.extend(
+      values
+        .iter()
+        .map(|v| (prefix.to_string().into(), v.clone())),
+    );
+    return vec![prefix.to_string()];
+  }
+
+  return values
+    .iter()
+    .enumerate()
+    .map(|(i, value)| {
+      let name = format!("{prefix}_{i}");
+      params.push((name.clone().into(), value.clone()));
+      return name;
+    })
+    .collect();
+}
+
+fn filter_to_sql(
+  table_name: &str,
+  columns: &[Column],
+  filter: Filter,
+  collation: Option<&str>,
+  params: &mut Vec<(Cow<'static, str>, trailbase_sqlite::Value)>,
+) -> Result<String, WhereClauseError> {
+  let (operands, separator) = match filter {
+    Filter::Column(column_name, query_param) => {
+      let target = column_expression(table_name, columns, &column_name)?;
+      let Some(qualifier) = query_param.qualifier.filter(|q| !q.is_geo()) else {
+        return Err(WhereClauseError::Parse(format!(
+          "Invalid operation for: {column_name}"
+        )));
+      };
+
+      // Unlike top-level filters, invalid values cannot be dropped, since this would change the
+      // meaning of the enclosing group.
+      let values = filter_values(&target, qualifier, query_param.value)
+        .map_err(|err| WhereClauseError::Parse(format!("{column_name}: {err}")))?;
+
+      // Placeholders are numbered, since columns may appear multiple times across groups.
+      let prefix = format!(":__filter{}", params.len());
+      let param_names = bind_filter_values(&prefix, qualifier, &values, params);
+      return Ok(filter_expression(
+        &target,
+        qualifier,
+        &values,
+        &param_names,
+        collation,
+      ));
+    }
+    Filter::And(operands) => (operands, " AND "),
+    Filter::Or(operands) => (operands, " OR "),
+  };
+
+  if operands.is_empty() {
+    return Err(WhereClauseError::Parse("Empty filter group".to_string()));
+  }
+
+  let operands = operands
+    .into_iter()
+    .map(|operand| filter_to_sql(table_name, columns, operand, collation, params))
+    .collect::<Result<Vec<_>, _>>()?;
+
+  return Ok(format!("({})", operands.join(separator)));
+}
+
+/// Builds the SQL where clause from top-level column filters, which are implicitly AND-ed, and
+/// a grouped filter expression.
 pub fn build_filter_where_clause(
   table_name: &str,
   columns: &[Column],
   filter_params: Option<HashMap<String, Vec<QueryParam>>>,
+  filter: Option<Filter>,
+  collation: Option<&str>,
 ) -> Result<WhereClause, WhereClauseError> {
   let mut where_clauses = Vec::<String>::with_capacity(16);
   let mut params = Vec::<(Cow<'static, str>, trailbase_sqlite::Value)>::with_capacity(16);
 
   if let Some(filter_params) = filter_params {
     for (column_name, query_params) in filter_params {
-      if column_name.starts_with("_") {
-        return Err(WhereClauseError::UnrecognizedParam(format!(
-          "Invalid parameter: {column_name}"
-        )));
-      }
-
-      // IMPORTANT: We only include parameters with known columns to avoid building an invalid
-      // query early and forbid injections.
-      let Some(col) = columns.iter().find(|c| c.name == column_name) else {
-        return Err(WhereClauseError::UnrecognizedParam(format!(
-          "Unrecognized parameter: {column_name}"
-        )));
-      };
+      let target = column_expression(table_name, columns, &column_name)?;
 
       for query_param in query_params {
-        let Some(op) = query_param.qualifier.map(|q| q.to_sql()) else {
+        let Some(qualifier) = query_param.qualifier else {
           info!("No op for: {column_name}={query_param:?}");
           continue;
         };
+        if qualifier.is_geo() {
+          return Err(WhereClauseError::Parse(format!(
+            "Not a geo point: {column_name}"
+          )));
+        }
 
-        match json_string_to_value(col.data_type, query_param.value) {
-          Ok(value) => {
-            where_clauses.push(format!(
-              r#"{table_name}."{column_name}" {op} :{column_name}"#
+        match filter_values(&target, qualifier, query_param.value) {
+          Ok(values) => {
+            // NOTE: Column names cannot start with "_", thus these placeholders don't collide.
+            // JSON paths contain dots and lists may be given multiple times per column, e.g.
+            // "[in]" and "[nin]", thus both are numbered.
+            let prefix = if target.json_path {
+              format!(":__path{}", params.len())
+            } else if qualifier.is_list() {
+              format!(":__in{}", params.len())
+            } else {
+              prefix_colon(&column_name)
+            };
+            let param_names = bind_filter_values(&prefix, qualifier, &values, &mut params);
+            where_clauses.push(filter_expression(
+              &target,
+              qualifier,
+              &values,
+              &param_names,
+              collation,
             ));
-            params.push((prefix_colon(&column_name).into(), value));
           }
           Err(err) => debug!("Parameter conversion for {column_name} failed: {err}"),
         };
@@ -295,6 +1259,16 @@ pub fn build_filter_where_clause(
     }
   }
 
+  if let Some(filter) = filter {
+    where_clauses.push(filter_to_sql(
+      table_name,
+      columns,
+      filter,
+      collation,
+      &mut params,
+    )?);
+  }
+
   let clause = match where_clauses.len() {
     0 => "TRUE".to_string(),
     _ => where_clauses.join(" AND "),
@@ -320,12 +1294,14 @@ fn split_key_into_col_and_op(key: &str) -> Option<(&str, Option<&str>)> {
 lazy_static! {
   /// Regex that splits the key part of "column[op]=value", i.e. column & op.
   static ref QUALIFIER_REGEX: regex::Regex =
-    regex::Regex::new(r"^(?<key>\w*)(?:\[(?<qualifier>\w+)\])?$").expect("infallible");
+    regex::Regex::new(r"^(?<key>[\w.]*)(?:\[(?<qualifier>\w+)\])?$").expect("infallible");
 }
 
 #[cfg(test)]
 mod tests {
   use super::*;
+  use trailbase_schema::sqlite::ColumnOption;
+
   use crate::util::id_to_b64;
   use crate::util::urlencode;
 
@@ -344,6 +1320,12 @@ mod tests {
       Some(("_foo", Some("gte")))
     );
     assert_eq!(split_key_into_col_and_op("_foo[$!]"), None);
+
+    // Check JSON paths
+    assert_eq!(
+      split_key_into_col_and_op("foo.bar[gte]"),
+      Some(("foo.bar", Some("gte")))
+    );
   }
 
   #[test]
@@ -363,7 +1345,10 @@ mod tests {
       let result = parse_and_sanitize_query(Some(&query)).unwrap();
 
       assert_eq!(result.limit, Some(10));
-      assert_eq!(result.cursor, Some(Cursor::Blob(cursor.to_vec())));
+      assert_eq!(
+        result.cursor.as_deref().and_then(Cursor::parse),
+        Some(Cursor::Blob(cursor.to_vec()))
+      );
       assert_eq!(
         result.order.unwrap(),
         vec![
@@ -374,6 +1359,16 @@ mod tests {
       );
     }
 
+    {
+      let result = parse_and_sanitize_query(Some("select=id,name")).unwrap();
+      assert_eq!(
+        result.select,
+        Some(vec!["id".to_string(), "name".to_string()])
+      );
+      assert!(parse_and_sanitize_query(Some("select=id,")).is_err());
+      assert!(parse_and_sanitize_query(Some("select=id,\"name\"")).is_err());
+    }
+
     {
       let query = Some("baz=23&bar[like]=foo");
       let result = parse_and_sanitize_query(query).unwrap();
@@ -480,5 +1475,490 @@ mod tests {
       );
       assert!(parse_and_sanitize_query(Some(&urlencode("col'; inject"))).is_err());
     }
+
+    {
+      // Expansions
+      let expansion = |column: &str, select: Option<&[&str]>, nested: Vec<Expansion>| Expansion {
+        column: column.to_string(),
+        select: select.map(|s| s.iter().map(|c| c.to_string()).collect()),
+        nested,
+        ..Default::default()
+      };
+
+      assert_eq!(
+        parse_expand("author(name,avatar).team,author.editor,parent").unwrap(),
+        vec![
+          expansion(
+            "author",
+            Some(&["name", "avatar"][..]),
+            vec![
+              expansion("team", None, vec![]),
+              expansion("editor", None, vec![])
+            ]
+          ),
+          expansion("parent", None, vec![]),
+        ]
+      );
+      assert_eq!(parse_expand("a.b.c").unwrap()[0].depth(), 3);
+
+      assert_eq!(
+        parse_expand("comments_via_post(limit=5,order=-created,order=id,body)").unwrap(),
+        vec![Expansion {
+          column: "comments_via_post".to_string(),
+          select: Some(vec!["body".to_string()]),
+          limit: Some(5),
+          order: Some(vec![
+            ("created".to_string(), Order::Descending),
+            ("id".to_string(), Order::Ascending),
+          ]),
+          nested: vec![],
+        }]
+      );
+      assert!(parse_expand("").unwrap().is_empty());
+
+      for invalid in [
+        "author(name",
+        "author)",
+        "author()",
+        "a(b).c(d)x",
+        "a(b),a(c)",
+        "a'",
+        "a(limit=x)",
+        "a(order=)",
+        "a(other=1)",
+      ] {
+        assert!(parse_expand(invalid).is_err(), "{invalid}");
+      }
+    }
+
+    {
+      // Booleans
+      let result = parse_and_sanitize_query(Some("count=true&include_deleted=false")).unwrap();
+      assert_eq!(result.count, Some(CountMode::Exact));
+      assert_eq!(result.include_deleted, Some(false));
+
+      let result = parse_and_sanitize_query(Some("count=estimate")).unwrap();
+      assert_eq!(result.count, Some(CountMode::Estimate));
+      let result = parse_and_sanitize_query(Some("count=false")).unwrap();
+      assert_eq!(result.count, None);
+    }
+
+    {
+      // Collation and format
+      let result = parse_and_sanitize_query(Some("collate=de_ci&order=name")).unwrap();
+      assert_eq!(result.collate.as_deref(), Some("de_ci"));
+
+      let result = parse_and_sanitize_query(Some("format=geojson")).unwrap();
+      assert_eq!(result.format, Some(ListFormat::GeoJson));
+      assert!(parse_and_sanitize_query(Some("format=kml")).is_err());
+
+      assert!(
+        parse_and_sanitize_query(Some(&format!("collate={}", urlencode("de_ci; inject")))).is_err()
+      );
+    }
+
+    {
+      // OData
+      let query = format!(
+        "$top=10&$skip=20&$orderby={}&$select=id,name&$filter={}",
+        urlencode("price desc"),
+        urlencode("price le 100 and contains(name, 'foo')")
+      );
+      let result = parse_and_sanitize_query(Some(&query)).unwrap();
+
+      assert_eq!(result.limit, Some(10));
+      assert_eq!(result.offset, Some(20));
+      assert_eq!(
+        result.order.unwrap(),
+        vec![("price".to_string(), Order::Descending)]
+      );
+      assert_eq!(
+        result.select.unwrap(),
+        vec!["id".to_string(), "name".to_string()]
+      );
+      assert_eq!(
+        result.params.as_ref().unwrap().get("name").unwrap(),
+        &vec![QueryParam {
+          value: "%foo%".to_string(),
+          qualifier: Some(Qualifier::Like),
+        }]
+      );
+
+      assert!(
+        parse_and_sanitize_query(Some(&format!(
+          "$filter={}",
+          urlencode("name eq 'x'; inject")
+        )))
+        .is_err()
+      );
+    }
+  }
+
+  #[test]
+  fn test_canonical_query() {
+    let canonical = |query: &str| parse_and_sanitize_query(Some(query)).unwrap().canonical();
+
+    assert_eq!(
+      canonical("limit=5&order=-id&a[gt]=1&a[lt]=9&b=x"),
+      canonical("b=x&a[lt]=9&order=-id&a[gt]=1&limit=5")
+    );
+    assert_eq!(canonical("b=%78"), canonical("b=x"));
+    assert_eq!(canonical("limit=05"), canonical("limit=5"));
+    assert_ne!(canonical("limit=5"), canonical("limit=6"));
+    assert_ne!(canonical("a[gt]=1"), canonical("a[lt]=1"));
+  }
+
+  #[test]
+  fn test_nearest_parsing() {
+    let vector: Vec<u8> = [1.0f32, 0.5].iter().flat_map(|f| f.to_le_bytes()).collect();
+    let encoded = BASE64_URL_SAFE.encode(&vector);
+
+    let result =
+      parse_and_sanitize_query(Some(&format!("nearest=embedding:{encoded}&k=5"))).unwrap();
+    assert_eq!(
+      result.nearest,
+      Some(Nearest {
+        column: Some("embedding".to_string()),
+        vector: vector.clone(),
+        metric: None,
+      })
+    );
+    assert_eq!(result.k, Some(5));
+
+    // Metric may precede the vector.
+    let result =
+      parse_and_sanitize_query(Some(&format!("metric=cosine&vector={encoded}&k=20"))).unwrap();
+    assert_eq!(
+      result.nearest,
+      Some(Nearest {
+        column: None,
+        vector: vector.clone(),
+        metric: Some(VectorMetric::Cosine),
+      })
+    );
+
+    assert!(parse_and_sanitize_query(Some("metric=cosine")).is_err());
+    assert!(parse_and_sanitize_query(Some(&format!("vector={encoded}&metric=dot"))).is_err());
+    assert!(
+      parse_and_sanitize_query(Some(&format!(
+        "vector={encoded}&nearest=embedding:{encoded}"
+      )))
+      .is_err()
+    );
+    // 3 bytes, i.e. not a float32 vector.
+    assert!(parse_and_sanitize_query(Some("vector=AAAA")).is_err());
+  }
+
+  #[test]
+  fn test_grouped_filter_parsing() {
+    let column = |name: &str, value: &str, qualifier: Qualifier| {
+      return Filter::Column(
+        name.to_string(),
+        QueryParam {
+          value: value.to_string(),
+          qualifier: Some(qualifier),
+        },
+      );
+    };
+
+    let result = parse_and_sanitize_query(Some(
+      "filter[$or][0][price][lt]=10&filter[$or][1][category]=sale",
+    ))
+    .unwrap();
+    assert_eq!(
+      result.filter.unwrap(),
+      Filter::Or(vec![
+        column("price", "10", Qualifier::LessThan),
+        column("category", "sale", Qualifier::Equal),
+      ])
+    );
+
+    // Nested groups, AND-ed with top-level grouped filters.
+    let result = parse_and_sanitize_query(Some(
+      "filter[stock][gt]=0&filter[$or][0][$and][0][price][lt]=10&filter[$or][0][$and][1][price][gt]=5&filter[$or][1][category]=sale",
+    ))
+    .unwrap();
+    assert_eq!(
+      result.filter.unwrap(),
+      Filter::And(vec![
+        column("stock", "0", Qualifier::GreaterThan),
+        Filter::Or(vec![
+          Filter::And(vec![
+            column("price", "10", Qualifier::LessThan),
+            column("price", "5", Qualifier::GreaterThan),
+          ]),
+          column("category", "sale", Qualifier::Equal),
+        ]),
+      ])
+    );
+
+    // Legacy filters on a column named "filter" still work.
+    let result = parse_and_sanitize_query(Some("filter[gt]=5")).unwrap();
+    assert!(result.filter.is_none());
+    assert_eq!(
+      result.params.unwrap().get("filter").unwrap(),
+      &vec![QueryParam {
+        value: "5".to_string(),
+        qualifier: Some(Qualifier::GreaterThan),
+      }]
+    );
+
+    assert!(parse_and_sanitize_query(Some("filter[$or][x][price]=10")).is_err());
+    assert!(parse_and_sanitize_query(Some("filter[$or][0][price][unknown]=10")).is_err());
+    assert!(parse_and_sanitize_query(Some("filter[$or][0][price][lt][gt]=10")).is_err());
+    assert!(parse_and_sanitize_query(Some("filter[$or][0][price]=")).is_err());
+    assert!(parse_and_sanitize_query(Some("filter[$xor][0][price]=10")).is_err());
+    assert!(
+      parse_and_sanitize_query(Some(&format!(
+        "filter[$or][0][{}]=10",
+        urlencode("col'; inject")
+      )))
+      .is_err()
+    );
+
+    let too_deep = format!(
+      "filter{}[price]=10",
+      "[$or][0]".repeat(MAX_FILTER_DEPTH + 1)
+    );
+    assert!(parse_and_sanitize_query(Some(&too_deep)).is_err());
+  }
+
+  #[test]
+  fn test_grouped_filter_where_clause() {
+    let columns = vec![
+      Column {
+        name: "price".to_string(),
+        data_type: ColumnDataType::Integer,
+        options: vec![],
+      },
+      Column {
+        name: "category".to_string(),
+        data_type: ColumnDataType::Text,
+        options: vec![],
+      },
+      Column {
+        name: "_secret".to_string(),
+        data_type: ColumnDataType::Text,
+        options: vec![],
+      },
+    ];
+
+    let build = |query: &str| {
+      let result = parse_and_sanitize_query(Some(query)).unwrap();
+      return build_filter_where_clause(
+        "_ROW_",
+        &columns,
+        result.params,
+        result.filter,
+        Some("NOCASE"),
+      );
+    };
+
+    let where_clause = build(
+      "price[gt]=1&filter[$or][0][price][lt]=10&filter[$or][1][$and][0][category]=sale&filter[$or][1][$and][1][price][lt]=20",
+    )
+    .unwrap();
+    assert_eq!(
+      where_clause.clause,
+      r#"_ROW_."price" > :price AND (_ROW_."price" < :__filter1 OR (_ROW_."category" = :__filter2 COLLATE NOCASE AND _ROW_."price" < :__filter3))"#
+    );
+    let names: Vec<_> = where_clause
+      .params
+      .iter()
+      .map(|(name, _)| name.as_ref())
+      .collect();
+    assert_eq!(names, [":price", ":__filter1", ":__filter2", ":__filter3"]);
+
+    // Same column sanitization as for top-level filters.
+    assert!(matches!(
+      build("filter[$or][0][_secret]=x&filter[$or][1][price]=1"),
+      Err(WhereClauseError::UnrecognizedParam(_))
+    ));
+    assert!(matches!(
+      build("filter[$or][0][unknown]=x&filter[$or][1][price]=1"),
+      Err(WhereClauseError::UnrecognizedParam(_))
+    ));
+    // Invalid values within groups are errors rather than being dropped.
+    assert!(matches!(
+      build("filter[$or][0][price]=abc&filter[$or][1][price]=1"),
+      Err(WhereClauseError::Parse(_))
+    ));
+  }
+
+  #[test]
+  fn test_list_filter_where_clause() {
+    let columns = vec![
+      Column {
+        name: "id".to_string(),
+        data_type: ColumnDataType::Integer,
+        options: vec![],
+      },
+      Column {
+        name: "name".to_string(),
+        data_type: ColumnDataType::Text,
+        options: vec![],
+      },
+    ];
+
+    let result = parse_and_sanitize_query(Some("id[in]=1,2,3")).unwrap();
+    assert_eq!(
+      result.params.as_ref().unwrap().get("id").unwrap(),
+      &vec![QueryParam {
+        value: "1,2,3".to_string(),
+        qualifier: Some(Qualifier::In),
+      }]
+    );
+
+    let where_clause =
+      build_filter_where_clause("_ROW_", &columns, result.params, None, None).unwrap();
+    assert_eq!(
+      where_clause.clause,
+      r#"_ROW_."id" IN (:__in0_0, :__in0_1, :__in0_2)"#
+    );
+    assert_eq!(
+      where_clause
+        .params
+        .iter()
+        .map(|(name, value)| (name.as_ref(), value.clone()))
+        .collect::<Vec<_>>(),
+      vec![
+        (":__in0_0", trailbase_sqlite::Value::Integer(1)),
+        (":__in0_1", trailbase_sqlite::Value::Integer(2)),
+        (":__in0_2", trailbase_sqlite::Value::Integer(3)),
+      ]
+    );
+
+    // Multiple list filters on the same column get distinct placeholders.
+    let result = parse_and_sanitize_query(Some("id[in]=1,2&id[nin]=2")).unwrap();
+    let where_clause =
+      build_filter_where_clause("_ROW_", &columns, result.params, None, None).unwrap();
+    assert_eq!(
+      where_clause.clause,
+      r#"_ROW_."id" IN (:__in0_0, :__in0_1) AND _ROW_."id" NOT IN (:__in2_0)"#
+    );
+    assert_eq!(
+      where_clause
+        .params
+        .iter()
+        .map(|(name, value)| (name.as_ref(), value.clone()))
+        .collect::<Vec<_>>(),
+      vec![
+        (":__in0_0", trailbase_sqlite::Value::Integer(1)),
+        (":__in0_1", trailbase_sqlite::Value::Integer(2)),
+        (":__in2_0", trailbase_sqlite::Value::Integer(2)),
+      ]
+    );
+
+    // Collations go on the column and grouped filters are numbered.
+    let result =
+      parse_and_sanitize_query(Some("filter[$or][0][name][nin]=a,b&filter[$or][1][id]=1")).unwrap();
+    let where_clause =
+      build_filter_where_clause("_ROW_", &columns, None, result.filter, Some("NOCASE")).unwrap();
+    assert_eq!(
+      where_clause.clause,
+      r#"(_ROW_."name" COLLATE NOCASE NOT IN (:__filter0_0, :__filter0_1) OR _ROW_."id" = :__filter2)"#
+    );
+
+    // Invalid elements drop top-level filters.
+    let result = parse_and_sanitize_query(Some("id[in]=1,x")).unwrap();
+    let where_clause =
+      build_filter_where_clause("_ROW_", &columns, result.params, None, None).unwrap();
+    assert_eq!(where_clause.clause, "TRUE");
+    assert!(where_clause.params.is_empty());
+
+    let too_long = (0..=MAX_LIST_LEN)
+      .map(|i| i.to_string())
+      .collect::<Vec<_>>()
+      .join(",");
+    assert!(parse_and_sanitize_query(Some(&format!("id[in]={too_long}"))).is_err());
+  }
+
+  #[test]
+  fn test_pattern_filter_where_clause() {
+    let columns = vec![Column {
+      name: "name".to_string(),
+      data_type: ColumnDataType::Text,
+      options: vec![],
+    }];
+
+    let build = |query: &str, collation: Option<&str>| {
+      let result = parse_and_sanitize_query(Some(query)).unwrap();
+      return build_filter_where_clause("_ROW_", &columns, result.params, result.filter, collation)
+        .unwrap()
+        .clause;
+    };
+
+    assert_eq!(build("name[glob]=foo*", None), r#"_ROW_."name" GLOB :name"#);
+    assert_eq!(
+      build("name[glob]=foo*", Some("de_ci")),
+      r#"_ROW_."name" GLOB :name"#
+    );
+  }
+
+  #[test]
+  fn test_json_path_where_clause() {
+    let columns = vec![
+      Column {
+        name: "profile".to_string(),
+        data_type: ColumnDataType::Text,
+        options: vec![ColumnOption::Check(
+          r#"jsonschema_matches('{"type": "object"}', profile)"#.to_string(),
+        )],
+      },
+      Column {
+        name: "name".to_string(),
+        data_type: ColumnDataType::Text,
+        options: vec![],
+      },
+    ];
+
+    let build = |query: &str| {
+      let result = parse_and_sanitize_query(Some(query)).unwrap();
+      return build_filter_where_clause("_ROW_", &columns, result.params, result.filter, None);
+    };
+
+    let where_clause = build("profile.address.city=Berlin").unwrap();
+    assert_eq!(
+      where_clause.clause,
+      r#"json_extract(_ROW_."profile", '$."address"."city"') = :__path0"#
+    );
+    assert_eq!(
+      where_clause.params,
+      vec![(
+        Cow::Borrowed(":__path0"),
+        trailbase_sqlite::Value::Text("Berlin".to_string())
+      )]
+    );
+
+    // Numbers are compared as such and numeric segments index arrays.
+    let where_clause = build("profile.pets.0.age[gt]=3").unwrap();
+    assert_eq!(
+      where_clause.clause,
+      r#"json_extract(_ROW_."profile", '$."pets"[0]."age"') > :__path0"#
+    );
+    assert_eq!(
+      where_clause.params[0].1,
+      trailbase_sqlite::Value::Integer(3)
+    );
+
+    let where_clause = build("filter[$or][0][profile.age][lt]=18&filter[$or][1][name]=x").unwrap();
+    assert_eq!(
+      where_clause.clause,
+      r#"(json_extract(_ROW_."profile", '$."age"') < :__filter0 OR _ROW_."name" = :__filter1)"#
+    );
+
+    // Only JSON columns have paths.
+    assert!(matches!(
+      build("name.first=x"),
+      Err(WhereClauseError::UnrecognizedParam(_))
+    ));
+    assert!(matches!(
+      build("profile..age=1"),
+      Err(WhereClauseError::UnrecognizedParam(_))
+    ));
+    assert!(matches!(
+      build("unknown.age=1"),
+      Err(WhereClauseError::UnrecognizedParam(_))
+    ));
   }
 }
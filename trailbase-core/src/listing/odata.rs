@@ -0,0 +1,294 @@
+//! Translates a subset of OData's system query options into list queries, allowing BI tools such
+//! as Excel or Power BI to talk to list endpoints natively.
+//!
+//! Supported options are `$filter`, `$orderby`, `$top`, `$skip`, `$select` and `$count`. Since
+//! list filters are a conjunction of column predicates, `$filter` is limited to comparisons (`eq`,
+//! `ne`, `gt`, `ge`, `lt`, `le`) and the string functions `contains`, `startswith` and `endswith`
+//! joined by `and`.
+//!
+//! See https://docs.oasis-open.org/odata/odata/v4.01/odata-v4.01-part2-url-conventions.html.
+
+use crate::listing::{Order, Qualifier, QueryParam, parse_select, sanitize_column_name};
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+  Ident(String),
+  String(String),
+  Number(String),
+  OpenParen,
+  CloseParen,
+  Comma,
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+  let mut tokens: Vec<Token> = vec![];
+  let mut chars = input.chars().peekable();
+
+  while let Some(c) = chars.next() {
+    match c {
+      c if c.is_whitespace() => {}
+      '(' => tokens.push(Token::OpenParen),
+      ')' => tokens.push(Token::CloseParen),
+      ',' => tokens.push(Token::Comma),
+      '\'' => {
+        // Single quotes within string literals are escaped by doubling them.
+        let mut value = String::new();
+        loop {
+          match chars.next() {
+            Some('\'') if chars.peek() == Some(&'\'') => {
+              chars.next();
+              value.push('\'');
+            }
+            Some('\'') => break,
+            Some(c) => value.push(c),
+            None => return Err(format!("Unterminated string: {input}")),
+          }
+        }
+        tokens.push(Token::String(value));
+      }
+      c if c.is_ascii_digit() || c == '-' => {
+        let mut value = c.to_string();
+        while let Some(c) = chars.next_if(|c| c.is_ascii_digit() || *c == '.') {
+          value.push(c);
+        }
+        tokens.push(Token::Number(value));
+      }
+      c if c.is_alphanumeric() || c == '_' => {
+        let mut value = c.to_string();
+        while let Some(c) = chars.next_if(|c| c.is_alphanumeric() || *c == '_') {
+          value.push(c);
+        }
+        tokens.push(Token::Ident(value));
+      }
+      c => return Err(format!("Unexpected character '{c}': {input}")),
+    }
+  }
+
+  return Ok(tokens);
+}
+
+struct FilterParser {
+  tokens: std::iter::Peekable<std::vec::IntoIter<Token>>,
+  params: Vec<(String, QueryParam)>,
+}
+
+impl FilterParser {
+  fn next(&mut self) -> Result<Token, String> {
+    return self
+      .tokens
+      .next()
+      .ok_or_else(|| "Unexpected end of filter".to_string());
+  }
+
+  fn expect(&mut self, expected: Token) -> Result<(), String> {
+    let token = self.next()?;
+    if token != expected {
+      return Err(format!("Expected {expected:?}, got {token:?}"));
+    }
+    return Ok(());
+  }
+
+  fn column(&mut self) -> Result<String, String> {
+    return match self.next()? {
+      Token::Ident(name) if sanitize_column_name(&name) => Ok(name),
+      token => Err(format!("Expected column, got {token:?}")),
+    };
+  }
+
+  /// Parses `predicate ("and" predicate)*`.
+  fn conjunction(&mut self) -> Result<(), String> {
+    self.predicate()?;
+    while let Some(Token::Ident(ident)) = self.tokens.peek() {
+      match ident.as_str() {
+        "and" => {
+          self.tokens.next();
+          self.predicate()?;
+        }
+        "or" | "not" => return Err(format!("Unsupported operator: {ident}")),
+        _ => return Err(format!("Unexpected token: {ident}")),
+      }
+    }
+    return Ok(());
+  }
+
+  fn predicate(&mut self) -> Result<(), String> {
+    let ident = match self.next()? {
+      Token::OpenParen => {
+        self.conjunction()?;
+        return self.expect(Token::CloseParen);
+      }
+      Token::Ident(ident) => ident,
+      token => return Err(format!("Unexpected token: {token:?}")),
+    };
+
+    // String functions are mapped onto LIKE patterns. NOTE: "%" and "_" within the argument
+    // still act as wildcards.
+    let pattern = match ident.as_str() {
+      "contains" => Some(("%", "%")),
+      "startswith" => Some(("", "%")),
+      "endswith" => Some(("%", "")),
+      _ => None,
+    };
+    if let Some((prefix, suffix)) = pattern {
+      self.expect(Token::OpenParen)?;
+      let column = self.column()?;
+      self.expect(Token::Comma)?;
+      let Token::String(value) = self.next()? else {
+        return Err(format!("Expected string argument for {ident}"));
+      };
+      self.expect(Token::CloseParen)?;
+
+      self.params.push((
+        column,
+        QueryParam {
+          value: format!("{prefix}{value}{suffix}"),
+          qualifier: Some(Qualifier::Like),
+        },
+      ));
+      return Ok(());
+    }
+
+    if !sanitize_column_name(&ident) {
+      return Err(format!("Invalid column: {ident}"));
+    }
+
+    let qualifier = match self.next()? {
+      Token::Ident(op) => match op.as_str() {
+        "eq" => Qualifier::Equal,
+        "ne" => Qualifier::NotEqual,
+        "gt" => Qualifier::GreaterThan,
+        "ge" => Qualifier::GreaterThanEqual,
+        "lt" => Qualifier::LessThan,
+        "le" => Qualifier::LessThanEqual,
+        _ => return Err(format!("Unsupported operator: {op}")),
+      },
+      token => return Err(format!("Expected operator, got {token:?}")),
+    };
+
+    let value = match self.next()? {
+      Token::String(value) | Token::Number(value) => value,
+      // Booleans are stored as integers.
+      Token::Ident(ident) if ident == "true" => "1".to_string(),
+      Token::Ident(ident) if ident == "false" => "0".to_string(),
+      token => return Err(format!("Unsupported literal: {token:?}")),
+    };
+
+    self.params.push((
+      ident,
+      QueryParam {
+        value,
+        qualifier: Some(qualifier),
+      },
+    ));
+    return Ok(());
+  }
+}
+
+/// Parses a `$filter` expression into column filters.
+pub(super) fn parse_filter(filter: &str) -> Result<Vec<(String, QueryParam)>, String> {
+  let mut parser = FilterParser {
+    tokens: tokenize(filter)?.into_iter().peekable(),
+    params: vec![],
+  };
+
+  parser.conjunction()?;
+  if let Some(token) = parser.tokens.next() {
+    return Err(format!("Unexpected token: {token:?}"));
+  }
+
+  return Ok(parser.params);
+}
+
+/// Parses `$orderby`, e.g. "price desc,name".
+pub(super) fn parse_orderby(orderby: &str) -> Result<Vec<(String, Order)>, String> {
+  return orderby
+    .split(',')
+    .map(|item| {
+      let mut parts = item.split_whitespace();
+      let column = parts.next().unwrap_or_default();
+      if column.is_empty() || !sanitize_column_name(column) {
+        return Err(column.to_string());
+      }
+
+      let order = match parts.next() {
+        None | Some("asc") => Order::Ascending,
+        Some("desc") => Order::Descending,
+        Some(order) => return Err(order.to_string()),
+      };
+      if parts.next().is_some() {
+        return Err(item.to_string());
+      }
+
+      return Ok((column.to_string(), order));
+    })
+    .collect();
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_parse_filter() {
+    assert_eq!(
+      parse_filter("price le 100 and (name eq 'O''Brien' and active eq true)").unwrap(),
+      vec![
+        (
+          "price".to_string(),
+          QueryParam {
+            value: "100".to_string(),
+            qualifier: Some(Qualifier::LessThanEqual),
+          }
+        ),
+        (
+          "name".to_string(),
+          QueryParam {
+            value: "O'Brien".to_string(),
+            qualifier: Some(Qualifier::Equal),
+          }
+        ),
+        (
+          "active".to_string(),
+          QueryParam {
+            value: "1".to_string(),
+            qualifier: Some(Qualifier::Equal),
+          }
+        ),
+      ]
+    );
+
+    assert_eq!(
+      parse_filter("startswith(name, 'Jo')").unwrap(),
+      vec![(
+        "name".to_string(),
+        QueryParam {
+          value: "Jo%".to_string(),
+          qualifier: Some(Qualifier::Like),
+        }
+      )]
+    );
+
+    assert!(parse_filter("price le 100 or price gt 200").is_err());
+    assert!(parse_filter("name eq 'unterminated").is_err());
+    assert!(parse_filter("name eq null").is_err());
+    assert!(parse_filter("(price le 100").is_err());
+  }
+
+  #[test]
+  fn test_parse_orderby_and_select() {
+    assert_eq!(
+      parse_orderby("price desc, name").unwrap(),
+      vec![
+        ("price".to_string(), Order::Descending),
+        ("name".to_string(), Order::Ascending),
+      ]
+    );
+    assert!(parse_orderby("price sideways").is_err());
+
+    assert_eq!(
+      parse_select("id, name").unwrap(),
+      vec!["id".to_string(), "name".to_string()]
+    );
+    assert!(parse_select("id,").is_err());
+  }
+}
@@ -13,6 +13,7 @@ use tracing_subscriber::layer::{Context, Layer};
 use uuid::Uuid;
 
 use crate::AppState;
+use crate::constants::HEADER_REQUEST_ID;
 use crate::util::get_header;
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize)]
@@ -183,6 +184,8 @@ pub(super) fn sqlite_logger_make_span(request: &Request<Body>) -> Span {
       client_ip,
       user_agent = get_header(headers, "user-agent"),
       referer = get_header(headers, "referer"),
+      // Set by `SetRequestIdLayer` and thus attached to all events of the request.
+      request_id = get_header(headers, HEADER_REQUEST_ID),
       // Reserve placeholders that may be recorded later.
       user_id = tracing::field::Empty,
       latency_ms = tracing::field::Empty,
@@ -319,9 +322,9 @@ impl SqliteLogLayer {
     lazy_static::lazy_static! {
       static ref QUERY: String = indoc::formatdoc! {"
         INSERT INTO
-          _logs (created, status, method, url, latency, client_ip, referer, user_agent, user_id)
+          _logs (created, status, method, url, latency, client_ip, referer, user_agent, user_id, request_id)
         VALUES
-          ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+          ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
       "};
     }
 
@@ -352,6 +355,7 @@ impl SqliteLogLayer {
         } else {
           rusqlite::types::Value::Null
         },
+        log.request_id,
         // TODO: we're not (yet) writing extra JSON data to the data field.
       ))?;
     }
@@ -429,6 +433,7 @@ struct LogFieldStorage {
   referer: String,
   user_agent: String,
   user_id: u128,
+  request_id: Option<String>,
   version: HttpVersion,
 
   // Response fields/properties
@@ -461,6 +466,8 @@ struct JsonLog {
   user: u128,
   /// Client ip address.
   client_ip: Option<String>,
+  /// Request id, see `X-Request-Id` header.
+  request_id: Option<String>,
 
   // HTTP response status code.
   status: u64,
@@ -480,6 +487,7 @@ impl From<&LogFieldStorage> for JsonLog {
       user_agent: storage.user_agent.clone(),
       user: storage.user_id,
       client_ip: storage.client_ip.clone(),
+      request_id: storage.request_id.clone(),
       status: storage.status,
       latency_ms: storage.latency_ms,
     };
@@ -535,6 +543,7 @@ impl tracing::field::Visit for LogVisitor<'_> {
       "host" => self.0.host = s.to_string(),
       "referer" => self.0.referer = s.to_string(),
       "user_agent" => self.0.user_agent = s.to_string(),
+      "request_id" => self.0.request_id = Some(s.to_string()),
       name => {
         self.0.fields.insert(name.into(), s.into());
       }
@@ -109,3 +109,27 @@ pub(crate) fn apply_logs_migrations(
 
   return Ok(());
 }
+
+/// Applies the user migrations of an additional database, see `Config::databases`, if any.
+pub(crate) fn apply_database_migrations(
+  conn: &mut rusqlite::Connection,
+  migrations_path: PathBuf,
+) -> Result<(), trailbase_refinery_core::Error> {
+  if !migrations_path.exists() {
+    return Ok(());
+  }
+
+  let mut migrations = trailbase_refinery_core::load_sql_migrations(&migrations_path)?;
+  migrations.sort();
+
+  let report = new_migration_runner(&migrations).run(conn).map_err(|err| {
+    error!("Database migrations {migrations_path:?}: {err}");
+    return err;
+  })?;
+
+  for applied_migration in report.applied_migrations() {
+    info!("applied migration: {applied_migration:?}");
+  }
+
+  return Ok(());
+}
@@ -0,0 +1,485 @@
+use axum::Json;
+use axum::extract::State;
+use serde_json::{Map, Value, json};
+use trailbase_schema::json_schema::JsonSchemaMode;
+use utoipa::OpenApi;
+
+use crate::app_state::AppState;
+use crate::constants::RECORD_API_PATH;
+use crate::records::json_schema::build_api_json_schema;
+use crate::records::{Permission, RecordApi, RecordError};
+
+/// Static OpenAPI document of the built-in APIs.
+#[derive(OpenApi)]
+#[openapi(
+      modifiers(),
+      nest(
+          (path = "/api/auth/v1", api = crate::auth::AuthAPI),
+          (path = "/api/records/v1", api = crate::records::RecordOpenApi),
+      ),
+      tags()
+  )]
+pub struct Doc;
+
+const BEARER_AUTH: &str = "bearerAuth";
+
+/// Serves the OpenAPI document for the currently configured record APIs.
+pub(crate) async fn openapi_handler(
+  State(state): State<AppState>,
+) -> Result<Json<Value>, RecordError> {
+  return Ok(Json(build_openapi_spec(&state)?));
+}
+
+/// Builds an OpenAPI 3.1 document describing the built-in auth APIs as well as all configured
+/// record APIs, including their record schemas and list parameters.
+///
+/// Since OpenAPI 3.1 is a superset of JSON schema, the record schemas are embedded as is.
+pub fn build_openapi_spec(state: &AppState) -> Result<Value, RecordError> {
+  let mut spec =
+    serde_json::to_value(Doc::openapi()).map_err(|err| RecordError::Internal(err.into()))?;
+
+  // The generic record API paths are superseded by the concrete ones below.
+  let paths = spec
+    .as_object_mut()
+    .and_then(|spec| {
+      spec
+        .entry("paths")
+        .or_insert_with(|| json!({}))
+        .as_object_mut()
+    })
+    .ok_or_else(|| RecordError::Internal("malformed spec".into()))?;
+  paths.retain(|path, _| !path.starts_with(&format!("/{RECORD_API_PATH}")));
+
+  let mut record_paths = Map::<String, Value>::new();
+  let mut schemas = Map::<String, Value>::new();
+
+  let mut apis: Vec<RecordApi> = state
+    .record_apis()
+    .iter()
+    .map(|(_name, api)| api.clone())
+    .collect();
+  apis.sort_by(|a, b| a.api_name().cmp(b.api_name()));
+
+  for api in &apis {
+    let name = api.api_name();
+
+    schemas.insert(
+      schema_name(name, JsonSchemaMode::Select),
+      build_api_json_schema(state, api, Some(JsonSchemaMode::Select))?,
+    );
+    if api.is_table() {
+      schemas.insert(
+        schema_name(name, JsonSchemaMode::Insert),
+        build_api_json_schema(state, api, Some(JsonSchemaMode::Insert))?,
+      );
+      schemas.insert(
+        schema_name(name, JsonSchemaMode::Update),
+        build_api_json_schema(state, api, Some(JsonSchemaMode::Update))?,
+      );
+    }
+
+    let collection = record_collection_path(api);
+    if !collection.is_empty() {
+      record_paths.insert(format!("/{RECORD_API_PATH}/{name}"), Value::Object(collection));
+    }
+
+    let record = record_path(api);
+    if !record.is_empty() {
+      record_paths.insert(
+        format!("/{RECORD_API_PATH}/{name}/{{record}}"),
+        Value::Object(record),
+      );
+    }
+
+    if let Some(schema_op) = operation(
+      api,
+      Permission::Schema,
+      json!({
+        "summary": format!("JSON schema of '{name}' records"),
+        "operationId": format!("{name}_schema"),
+        "tags": [name],
+        "responses": {
+          "200": { "description": "JSON schema." },
+        },
+      }),
+    ) {
+      record_paths.insert(
+        format!("/{RECORD_API_PATH}/{name}/schema"),
+        json!({ "get": schema_op }),
+      );
+    }
+  }
+
+  paths.extend(record_paths);
+
+  let spec_object = spec
+    .as_object_mut()
+    .ok_or_else(|| RecordError::Internal("malformed spec".into()))?;
+  let components = spec_object
+    .entry("components")
+    .or_insert_with(|| json!({}))
+    .as_object_mut()
+    .ok_or_else(|| RecordError::Internal("malformed spec".into()))?;
+
+  components
+    .entry("schemas")
+    .or_insert_with(|| json!({}))
+    .as_object_mut()
+    .ok_or_else(|| RecordError::Internal("malformed spec".into()))?
+    .extend(schemas);
+  components.insert(
+    "securitySchemes".to_string(),
+    json!({
+      BEARER_AUTH: {
+        "type": "http",
+        "scheme": "bearer",
+        "bearerFormat": "JWT",
+      },
+    }),
+  );
+
+  let version_info = state.version();
+  spec_object.insert(
+    "info".to_string(),
+    json!({
+      "title": state
+        .access_config(|c| c.server.application_name.clone())
+        .unwrap_or_else(|| "TrailBase".to_string()),
+      "version": format!(
+        "{major}.{minor}.{patch}",
+        major = version_info.major,
+        minor = version_info.minor,
+        patch = version_info.patch
+      ),
+    }),
+  );
+
+  return Ok(spec);
+}
+
+fn schema_name(api_name: &str, mode: JsonSchemaMode) -> String {
+  return match mode {
+    JsonSchemaMode::Insert => format!("{api_name}_insert"),
+    JsonSchemaMode::Select => api_name.to_string(),
+    JsonSchemaMode::Update => format!("{api_name}_update"),
+  };
+}
+
+fn schema_ref(api_name: &str, mode: JsonSchemaMode) -> Value {
+  return json!({
+    "$ref": format!("#/components/schemas/{}", schema_name(api_name, mode)),
+  });
+}
+
+/// Returns the operation with security requirements attached, or None if the operation isn't
+/// accessible to anyone according to the API's ACLs.
+fn operation(api: &RecordApi, p: Permission, mut op: Value) -> Option<Value> {
+  let (world, authenticated) = api.table_level_acl(p);
+  if !world && !authenticated {
+    return None;
+  }
+
+  if let Some(op) = op.as_object_mut() {
+    op.insert(
+      "security".to_string(),
+      if world {
+        // Anonymous access with optional authentication.
+        json!([{}, { BEARER_AUTH: [] }])
+      } else {
+        json!([{ BEARER_AUTH: [] }])
+      },
+    );
+  }
+  return Some(op);
+}
+
+fn record_id_parameter() -> Value {
+  return json!({
+    "name": "record",
+    "in": "path",
+    "required": true,
+    "description": "Record id: integer or url-safe base64 encoded UUID.",
+    "schema": { "type": "string" },
+  });
+}
+
+fn list_parameters(api: &RecordApi) -> Vec<Value> {
+  let mut parameters = vec![
+    json!({
+      "name": "limit",
+      "in": "query",
+      "description": "Maximum number of records to return.",
+      "schema": { "type": "integer", "minimum": 0 },
+    }),
+    json!({
+      "name": "cursor",
+      "in": "query",
+      "description": "Pagination cursor returned by a previous list request.",
+      "schema": { "type": "string" },
+    }),
+    json!({
+      "name": "offset",
+      "in": "query",
+      "schema": { "type": "integer", "minimum": 0 },
+    }),
+    json!({
+      "name": "count",
+      "in": "query",
+      "description": "Include the total number of matching records.",
+      "schema": { "type": "boolean" },
+    }),
+    json!({
+      "name": "order",
+      "in": "query",
+      "description": "Comma-separated list of columns, prefixed with '-' for descending order.",
+      "schema": { "type": "string" },
+    }),
+  ];
+
+  if let Some(expand) = api.expand() {
+    let mut columns: Vec<&String> = expand.keys().collect();
+    columns.sort();
+    parameters.push(json!({
+      "name": "expand",
+      "in": "query",
+      "description": format!("Comma-separated list of foreign keys to expand: {columns:?}."),
+      "schema": { "type": "string" },
+    }));
+  }
+
+  // Filters are expressed as `column=value` or `column[op]=value`.
+  for (column, json_metadata) in api.columns().iter().zip(api.json_column_metadata()) {
+    if column.name.starts_with('_') || json_metadata.is_some() {
+      continue;
+    }
+
+    parameters.push(json!({
+      "name": column.name,
+      "in": "query",
+      "description": format!(
+        "Filter by '{}'. Supports qualifiers, e.g. {}[gte]=<value>.",
+        column.name, column.name
+      ),
+      "schema": { "type": "string" },
+    }));
+  }
+
+  return parameters;
+}
+
+fn record_collection_path(api: &RecordApi) -> Map<String, Value> {
+  let name = api.api_name();
+  let mut path = Map::<String, Value>::new();
+
+  if let Some(op) = operation(
+    api,
+    Permission::Read,
+    json!({
+      "summary": format!("List '{name}' records"),
+      "operationId": format!("{name}_list"),
+      "tags": [name],
+      "parameters": list_parameters(api),
+      "responses": {
+        "200": {
+          "description": "Matching records.",
+          "content": {
+            "application/json": {
+              "schema": {
+                "type": "object",
+                "properties": {
+                  "cursor": { "type": "string" },
+                  "total_count": { "type": "integer" },
+                  "records": {
+                    "type": "array",
+                    "items": schema_ref(name, JsonSchemaMode::Select),
+                  },
+                },
+                "required": ["records"],
+              },
+            },
+          },
+        },
+      },
+    }),
+  ) {
+    path.insert("get".to_string(), op);
+  }
+
+  if api.is_table() {
+    if let Some(op) = operation(
+      api,
+      Permission::Create,
+      json!({
+        "summary": format!("Create '{name}' records"),
+        "operationId": format!("{name}_create"),
+        "tags": [name],
+        "requestBody": {
+          "required": true,
+          "content": {
+            "application/json": {
+              "schema": {
+                "oneOf": [
+                  schema_ref(name, JsonSchemaMode::Insert),
+                  {
+                    "type": "array",
+                    "items": schema_ref(name, JsonSchemaMode::Insert),
+                  },
+                ],
+              },
+            },
+          },
+        },
+        "responses": {
+          "200": {
+            "description": "Ids of the created records.",
+            "content": {
+              "application/json": {
+                "schema": { "$ref": "#/components/schemas/CreateRecordResponse" },
+              },
+            },
+          },
+        },
+      }),
+    ) {
+      path.insert("post".to_string(), op);
+    }
+  }
+
+  return path;
+}
+
+fn record_path(api: &RecordApi) -> Map<String, Value> {
+  let name = api.api_name();
+  let mut path = Map::<String, Value>::new();
+
+  if let Some(op) = operation(
+    api,
+    Permission::Read,
+    json!({
+      "summary": format!("Read '{name}' record"),
+      "operationId": format!("{name}_read"),
+      "tags": [name],
+      "parameters": [record_id_parameter()],
+      "responses": {
+        "200": {
+          "description": "Record.",
+          "content": {
+            "application/json": {
+              "schema": schema_ref(name, JsonSchemaMode::Select),
+            },
+          },
+        },
+        "404": { "description": "Record not found." },
+      },
+    }),
+  ) {
+    path.insert("get".to_string(), op);
+  }
+
+  if !api.is_table() {
+    return path;
+  }
+
+  if let Some(op) = operation(
+    api,
+    Permission::Update,
+    json!({
+      "summary": format!("Update '{name}' record"),
+      "operationId": format!("{name}_update"),
+      "tags": [name],
+      "parameters": [record_id_parameter()],
+      "requestBody": {
+        "required": true,
+        "content": {
+          "application/json": {
+            "schema": schema_ref(name, JsonSchemaMode::Update),
+          },
+        },
+      },
+      "responses": {
+        "200": { "description": "Record updated." },
+      },
+    }),
+  ) {
+    path.insert("patch".to_string(), op);
+  }
+
+  if let Some(op) = operation(
+    api,
+    Permission::Delete,
+    json!({
+      "summary": format!("Delete '{name}' record"),
+      "operationId": format!("{name}_delete"),
+      "tags": [name],
+      "parameters": [record_id_parameter()],
+      "responses": {
+        "200": { "description": "Record deleted." },
+      },
+    }),
+  ) {
+    path.insert("delete".to_string(), op);
+  }
+
+  return path;
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::app_state::test_state;
+  use crate::config::proto::PermissionFlag;
+  use crate::records::test_utils::add_record_api;
+  use crate::records::{AccessRules, Acls};
+
+  #[tokio::test]
+  async fn test_build_openapi_spec() {
+    let state = test_state(None).await.unwrap();
+
+    state
+      .conn()
+      .execute_batch(
+        r#"
+          CREATE TABLE article (
+            id        INTEGER PRIMARY KEY,
+            title     TEXT NOT NULL,
+            body      TEXT
+          ) STRICT;
+        "#,
+      )
+      .await
+      .unwrap();
+    state.schema_metadata().invalidate_all().await.unwrap();
+
+    add_record_api(
+      &state,
+      "articles",
+      "article",
+      Acls {
+        world: vec![PermissionFlag::Read],
+        authenticated: vec![PermissionFlag::Create],
+      },
+      AccessRules::default(),
+    )
+    .await
+    .unwrap();
+
+    let spec = build_openapi_spec(&state).unwrap();
+    assert!(spec["openapi"].as_str().unwrap().starts_with("3.1"));
+
+    let paths = &spec["paths"];
+    let collection = &paths[format!("/{RECORD_API_PATH}/articles")];
+    assert!(collection["get"].is_object());
+    assert_eq!(collection["post"]["security"], json!([{ BEARER_AUTH: [] }]));
+
+    let record = &paths[format!("/{RECORD_API_PATH}/articles/{{record}}")];
+    assert!(record["get"].is_object());
+    // Not granted by ACLs.
+    assert!(record["patch"].is_null());
+    assert!(record["delete"].is_null());
+
+    let schemas = &spec["components"]["schemas"];
+    assert!(schemas["articles"].is_object());
+    assert!(schemas["articles_insert"].is_object());
+    assert!(spec["components"]["securitySchemes"][BEARER_AUTH].is_object());
+  }
+}
@@ -0,0 +1,547 @@
+use axum::body::Body;
+use axum::extract::{Request, State};
+use axum::http::header::{
+  ACCEPT_LANGUAGE, CONTENT_LANGUAGE, CONTENT_LENGTH, CONTENT_TYPE, RETRY_AFTER,
+};
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use log::*;
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use ts_rs::TS;
+
+use crate::app_state::AppState;
+use crate::config::proto::Config;
+use crate::constants::HEADER_REQUEST_ID;
+use crate::records::validators::FieldError;
+use crate::util::get_header_owned;
+
+pub const PROBLEM_JSON_MIME_TYPE: &str = "application/problem+json";
+
+/// Whether to include internal error details in responses, see `ServerConfig.verbose_errors`.
+///
+/// NOTE: Process-wide rather than per `AppState`, since errors are rendered without access to
+/// state.
+static VERBOSE_ERRORS: AtomicBool = AtomicBool::new(cfg!(debug_assertions));
+
+#[inline]
+pub(crate) fn verbose_errors() -> bool {
+  return VERBOSE_ERRORS.load(Ordering::Relaxed);
+}
+
+pub(crate) fn set_verbose_errors(config: &Config) {
+  VERBOSE_ERRORS.store(
+    config
+      .server
+      .verbose_errors
+      .unwrap_or(cfg!(debug_assertions)),
+    Ordering::Relaxed,
+  );
+}
+
+/// Base URI of problem types, each documented under its `code`.
+const PROBLEM_TYPE_BASE: &str = "https://trailbase.io/reference/errors";
+
+macro_rules! error_codes {
+  ($($variant:ident => ($code:literal, $status:ident, $title:literal),)+) => {
+    /// Stable, machine-readable error codes included in all API error responses and exported
+    /// through client codegen.
+    ///
+    /// Clients should branch on these rather than HTTP status codes or messages. Codes may be
+    /// added but existing ones are never repurposed.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, TS)]
+    #[ts(export)]
+    pub enum ErrorCode {
+      $(
+        #[serde(rename = $code)]
+        $variant,
+      )+
+    }
+
+    impl ErrorCode {
+      pub const ALL: &[ErrorCode] = &[$(Self::$variant),+];
+
+      pub fn from_code(code: &str) -> Option<Self> {
+        return match code {
+          $($code => Some(Self::$variant),)+
+          _ => None,
+        };
+      }
+
+      pub fn as_str(&self) -> &'static str {
+        return match self {
+          $(Self::$variant => $code,)+
+        };
+      }
+
+      pub fn status(&self) -> StatusCode {
+        return match self {
+          $(Self::$variant => StatusCode::$status,)+
+        };
+      }
+
+      /// Short, human-readable summary.
+      pub fn title(&self) -> &'static str {
+        return match self {
+          $(Self::$variant => $title,)+
+        };
+      }
+    }
+  };
+}
+
+error_codes! {
+  RecordApiNotFound => ("record/api_not_found", METHOD_NOT_ALLOWED, "Api Not Found"),
+  RecordApiRequiresTable => ("record/api_requires_table", METHOD_NOT_ALLOWED, "Api Requires Table"),
+  RecordNotFound => ("record/not_found", NOT_FOUND, "Record Not Found"),
+  RecordForbidden => ("record/forbidden", FORBIDDEN, "Forbidden"),
+  RecordBadRequest => ("record/bad_request", BAD_REQUEST, "Bad Request"),
+  RecordValidationFailed => ("record/validation_failed", BAD_REQUEST, "Validation Failed"),
+  RecordConstraintCheck => ("record/constraint_check", BAD_REQUEST, "Check Constraint Violated"),
+  RecordConstraintForeignKey => ("record/constraint_foreign_key", CONFLICT, "Foreign Key Constraint Violated"),
+  RecordConstraintNotNull => ("record/constraint_not_null", BAD_REQUEST, "Not Null Constraint Violated"),
+  RecordConstraintPrimaryKey => ("record/constraint_primary_key", CONFLICT, "Primary Key Constraint Violated"),
+  RecordConstraintUnique => ("record/constraint_unique", CONFLICT, "Unique Constraint Violated"),
+  RecordConstraint => ("record/constraint", BAD_REQUEST, "Constraint Violated"),
+  RecordPreconditionFailed => ("record/precondition_failed", PRECONDITION_FAILED, "Precondition Failed"),
+  RecordLimitExceeded => ("record/limit_exceeded", BAD_REQUEST, "Limit Exceeded"),
+  RecordUnavailable => ("record/unavailable", SERVICE_UNAVAILABLE, "Unavailable"),
+  RecordInternal => ("record/internal", INTERNAL_SERVER_ERROR, "Internal"),
+  AuthUnauthorized => ("auth/unauthorized", UNAUTHORIZED, "Unauthorized"),
+  AuthInvalidCredentials => ("auth/invalid_credentials", UNAUTHORIZED, "Invalid Credentials"),
+  AuthForbidden => ("auth/forbidden", FORBIDDEN, "Forbidden"),
+  AuthConflict => ("auth/conflict", CONFLICT, "Conflict"),
+  AuthNotFound => ("auth/not_found", NOT_FOUND, "Not Found"),
+  AuthOAuthProviderNotFound => ("auth/oauth_provider_not_found", METHOD_NOT_ALLOWED, "OAuth Provider Not Found"),
+  AuthBadRequest => ("auth/bad_request", BAD_REQUEST, "Bad Request"),
+  AuthTooManyRequests => ("auth/too_many_requests", TOO_MANY_REQUESTS, "Too Many Requests"),
+  AuthFailedDependency => ("auth/failed_dependency", FAILED_DEPENDENCY, "Failed Dependency"),
+  AuthInternal => ("auth/internal", INTERNAL_SERVER_ERROR, "Internal"),
+  AdminBadRequest => ("admin/bad_request", BAD_REQUEST, "Bad Request"),
+  AdminPreconditionFailed => ("admin/precondition_failed", PRECONDITION_FAILED, "Precondition Failed"),
+  AdminAlreadyExists => ("admin/already_exists", CONFLICT, "Already Exists"),
+  AdminInternal => ("admin/internal", INTERNAL_SERVER_ERROR, "Internal"),
+  SqlNotFound => ("sql/not_found", NOT_FOUND, "Not Found"),
+  SqlForbidden => ("sql/forbidden", FORBIDDEN, "Forbidden"),
+  SqlBadRequest => ("sql/bad_request", BAD_REQUEST, "Bad Request"),
+  SqlTimeout => ("sql/timeout", REQUEST_TIMEOUT, "Timeout"),
+  SqlInternal => ("sql/internal", INTERNAL_SERVER_ERROR, "Internal"),
+  ServerPanic => ("server/panic", INTERNAL_SERVER_ERROR, "Internal Server Error"),
+  ServerRateLimited => ("server/rate_limited", TOO_MANY_REQUESTS, "Too Many Requests"),
+}
+
+/// Error response body following RFC 9457, i.e. "Problem Details for HTTP APIs".
+#[derive(Debug, Clone, PartialEq, Serialize, TS)]
+#[ts(export)]
+pub struct Problem {
+  /// URI identifying the problem type.
+  #[serde(rename = "type")]
+  pub kind: String,
+  /// Short, human-readable summary of the problem type.
+  pub title: String,
+  /// The HTTP status code.
+  pub status: u16,
+  /// Stable, machine-readable error code.
+  pub code: ErrorCode,
+  /// Human-readable explanation specific to this occurrence.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub detail: Option<String>,
+  /// Per-field validation failures.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub errors: Option<Vec<FieldError>>,
+  /// Id of the failed request, also found in the logs and the `X-Request-Id` header.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub request_id: Option<String>,
+  /// Id under which unexpected failures, e.g. panics, are logged.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub incident_id: Option<String>,
+  /// Largest permissible page size, if the requested one exceeded it.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub max_limit: Option<usize>,
+  /// Sent as `Retry-After` header rather than in the body.
+  #[serde(skip)]
+  pub retry_after: Option<Duration>,
+}
+
+impl Problem {
+  pub fn new(code: ErrorCode) -> Self {
+    return Self {
+      kind: format!("{PROBLEM_TYPE_BASE}#{}", code.as_str()),
+      title: code.title().to_string(),
+      status: code.status().as_u16(),
+      code,
+      detail: None,
+      errors: None,
+      request_id: None,
+      incident_id: None,
+      max_limit: None,
+      retry_after: None,
+    };
+  }
+
+  pub fn with_detail(mut self, detail: impl Into<String>) -> Self {
+    self.detail = Some(detail.into());
+    return self;
+  }
+
+  pub fn with_errors(mut self, errors: Vec<FieldError>) -> Self {
+    self.errors = Some(errors);
+    return self;
+  }
+
+  pub fn with_incident_id(mut self, incident_id: impl Into<String>) -> Self {
+    self.incident_id = Some(incident_id.into());
+    return self;
+  }
+
+  pub fn with_max_limit(mut self, max_limit: usize) -> Self {
+    self.max_limit = Some(max_limit);
+    return self;
+  }
+
+  pub fn with_retry_after(mut self, retry_after: Duration) -> Self {
+    self.retry_after = Some(retry_after);
+    return self;
+  }
+}
+
+impl IntoResponse for Problem {
+  fn into_response(self) -> Response {
+    let mut builder = Response::builder()
+      .status(self.code.status())
+      .header(CONTENT_TYPE, PROBLEM_JSON_MIME_TYPE);
+    if let Some(retry_after) = self.retry_after {
+      // Delay in whole seconds, rounded up so clients don't retry prematurely.
+      let secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+      builder = builder.header(RETRY_AFTER, secs.max(1));
+    }
+
+    return builder
+      .body(Body::new(serde_json::to_string(&self).unwrap_or_default()))
+      .unwrap_or_default();
+  }
+}
+
+/// Languages accepted by the client in order of preference, see RFC 9110, section 12.5.4.
+fn accepted_languages(headers: &HeaderMap) -> Vec<String> {
+  let mut languages: Vec<(String, f32)> = headers
+    .get_all(ACCEPT_LANGUAGE)
+    .iter()
+    .filter_map(|value| value.to_str().ok())
+    .flat_map(|value| value.split(','))
+    .filter_map(|entry| {
+      let mut parts = entry.split(';');
+      let tag = parts.next()?.trim();
+      if tag.is_empty() || tag == "*" {
+        return None;
+      }
+      let q = parts
+        .find_map(|param| param.trim().strip_prefix("q="))
+        .map_or(Some(1.0), |q| q.trim().parse::<f32>().ok())?;
+      return (q > 0.0).then(|| (tag.to_ascii_lowercase(), q));
+    })
+    .collect();
+
+  // Stable sort, i.e. equally weighted languages retain their order.
+  languages.sort_by(|a, b| b.1.total_cmp(&a.1));
+  return languages.into_iter().map(|(tag, _)| tag).collect();
+}
+
+/// Picks the configured language best matching the client's preferences. Falls back from regional
+/// variants to the primary language, e.g. "de-CH" to "de", and otherwise to the default language.
+fn negotiate_language(config: &Config, headers: &HeaderMap) -> Option<String> {
+  let available = |tag: &str| {
+    return config.error_messages.iter().any(|m| {
+      m.language
+        .as_deref()
+        .is_some_and(|l| l.eq_ignore_ascii_case(tag))
+    });
+  };
+
+  for tag in accepted_languages(headers) {
+    if available(&tag) {
+      return Some(tag);
+    }
+    if let Some((primary, _)) = tag.split_once('-') {
+      if available(primary) {
+        return Some(primary.to_string());
+      }
+    }
+  }
+
+  return config.server.default_language.clone();
+}
+
+fn localized_message<'a>(config: &'a Config, language: &str, code: &str) -> Option<&'a str> {
+  return config
+    .error_messages
+    .iter()
+    .find(|m| {
+      m.code.as_deref() == Some(code)
+        && m
+          .language
+          .as_deref()
+          .is_some_and(|l| l.eq_ignore_ascii_case(language))
+    })
+    .and_then(|m| m.message.as_deref());
+}
+
+/// Post-processes problem responses, which don't have access to the request when rendered:
+///
+///  * attaches the request's `X-Request-Id` so users can quote it when reporting failures,
+///  * replaces the `title` with the configured message for the error code in the negotiated
+///    language, see `Config.error_messages`.
+pub(crate) async fn problem_middleware(
+  State(state): State<AppState>,
+  req: Request,
+  next: Next,
+) -> Response {
+  let request_id = get_header_owned(req.headers(), HEADER_REQUEST_ID);
+  let language = state.access_config(|config| {
+    if config.error_messages.is_empty() {
+      return None;
+    }
+    return negotiate_language(config, req.headers());
+  });
+
+  let response = next.run(req).await;
+  if request_id.is_none() && language.is_none() {
+    return response;
+  }
+
+  let is_problem = response
+    .headers()
+    .get(CONTENT_TYPE)
+    .is_some_and(|v| v.as_bytes().starts_with(PROBLEM_JSON_MIME_TYPE.as_bytes()));
+  if !is_problem {
+    return response;
+  }
+
+  let (mut parts, body) = response.into_parts();
+  let bytes = match axum::body::to_bytes(body, usize::MAX).await {
+    Ok(bytes) => bytes,
+    Err(err) => {
+      warn!("Failed to read problem response: {err}");
+      return (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response();
+    }
+  };
+
+  let Ok(serde_json::Value::Object(mut problem)) = serde_json::from_slice(&bytes) else {
+    return Response::from_parts(parts, Body::from(bytes));
+  };
+
+  if let Some(request_id) = request_id {
+    problem.insert("request_id".to_string(), request_id.into());
+  }
+
+  if let Some(language) = language {
+    let message = problem
+      .get("code")
+      .and_then(|code| code.as_str())
+      .and_then(|code| {
+        state
+          .access_config(|config| localized_message(config, &language, code).map(|m| m.to_string()))
+      });
+
+    if let Some(message) = message {
+      problem.insert("title".to_string(), message.into());
+      if let Ok(language) = HeaderValue::from_str(&language) {
+        parts.headers.insert(CONTENT_LANGUAGE, language);
+      }
+    }
+  }
+  parts.headers.remove(CONTENT_LENGTH);
+
+  return Response::from_parts(
+    parts,
+    Body::new(serde_json::to_string(&problem).unwrap_or_default()),
+  );
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::records::RecordError;
+
+  #[tokio::test]
+  async fn test_problem_response() {
+    let response = RecordError::Validation(vec![FieldError {
+      field: "name".to_string(),
+      code: "invalid".to_string(),
+      message: "too short".to_string(),
+    }])
+    .into_response();
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+      response.headers().get(CONTENT_TYPE).unwrap(),
+      PROBLEM_JSON_MIME_TYPE
+    );
+
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+      .await
+      .unwrap();
+    let value: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(
+      value,
+      serde_json::json!({
+        "type": "https://trailbase.io/reference/errors#record/validation_failed",
+        "title": "Validation Failed",
+        "status": 400,
+        "code": "record/validation_failed",
+        "errors": [{"field": "name", "code": "invalid", "message": "too short"}],
+      })
+    );
+  }
+
+  #[tokio::test]
+  async fn test_verbose_errors() {
+    let detail = async |err: RecordError| -> Option<String> {
+      let body = axum::body::to_bytes(err.into_response().into_body(), usize::MAX)
+        .await
+        .unwrap();
+      let value: serde_json::Value = serde_json::from_slice(&body).unwrap();
+      return value
+        .get("detail")
+        .and_then(|d| d.as_str())
+        .map(|d| d.to_string());
+    };
+
+    let mut config = Config::new_with_custom_defaults();
+    config.server.verbose_errors = Some(false);
+    set_verbose_errors(&config);
+    assert_eq!(detail(RecordError::Internal("secret".into())).await, None);
+
+    config.server.verbose_errors = Some(true);
+    set_verbose_errors(&config);
+    assert_eq!(
+      detail(RecordError::Internal("secret".into())).await,
+      Some("secret".to_string())
+    );
+
+    config.server.verbose_errors = None;
+    set_verbose_errors(&config);
+    assert_eq!(verbose_errors(), cfg!(debug_assertions));
+  }
+
+  #[test]
+  fn test_retry_after() {
+    let response = Problem::new(ErrorCode::RecordUnavailable)
+      .with_retry_after(Duration::from_millis(1500))
+      .into_response();
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(response.headers().get(RETRY_AFTER).unwrap(), "2");
+
+    let response = Problem::new(ErrorCode::AuthTooManyRequests)
+      .with_retry_after(Duration::ZERO)
+      .into_response();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(response.headers().get(RETRY_AFTER).unwrap(), "1");
+  }
+
+  #[tokio::test]
+  async fn test_problem_middleware() {
+    use axum::Router;
+    use axum::routing::get;
+    use tower::ServiceExt;
+
+    use crate::app_state::test_state;
+    use crate::config::proto::ErrorMessageConfig;
+
+    let state = test_state(None).await.unwrap();
+    let mut config = state.get_config();
+    config.error_messages.push(ErrorMessageConfig {
+      language: Some("de".to_string()),
+      code: Some("record/not_found".to_string()),
+      message: Some("Eintrag nicht gefunden".to_string()),
+    });
+    state
+      .validate_and_update_config(config, None)
+      .await
+      .unwrap();
+
+    let router = Router::new()
+      .route("/", get(|| async { RecordError::RecordNotFound }))
+      .layer(axum::middleware::from_fn_with_state(
+        state.clone(),
+        problem_middleware,
+      ))
+      .with_state(state);
+
+    let response = router
+      .oneshot(
+        Request::builder()
+          .uri("/")
+          .header(HEADER_REQUEST_ID, "req-1")
+          .header(ACCEPT_LANGUAGE, "de-DE, en;q=0.5")
+          .body(Body::empty())
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(response.headers().get(CONTENT_LANGUAGE).unwrap(), "de");
+
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+      .await
+      .unwrap();
+    let value: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(value["request_id"], "req-1");
+    assert_eq!(value["title"], "Eintrag nicht gefunden");
+    assert_eq!(value["code"], "record/not_found");
+  }
+
+  #[test]
+  fn test_negotiate_language() {
+    use crate::config::proto::ErrorMessageConfig;
+
+    let mut config = Config::new_with_custom_defaults();
+    for language in ["de", "pt-BR"] {
+      config.error_messages.push(ErrorMessageConfig {
+        language: Some(language.to_string()),
+        code: Some("record/not_found".to_string()),
+        message: Some(format!("{language}: not found")),
+      });
+    }
+
+    let negotiate = |accept: &str| {
+      let mut headers = HeaderMap::new();
+      headers.insert(ACCEPT_LANGUAGE, HeaderValue::from_str(accept).unwrap());
+      return negotiate_language(&config, &headers);
+    };
+
+    assert_eq!(negotiate("de"), Some("de".to_string()));
+    assert_eq!(negotiate("de-CH, en;q=0.8"), Some("de".to_string()));
+    assert_eq!(negotiate("en, pt-BR;q=0.9"), Some("pt-br".to_string()));
+    assert_eq!(negotiate("de;q=0.5, pt-BR"), Some("pt-br".to_string()));
+    assert_eq!(negotiate("fr, de;q=0"), None);
+
+    assert_eq!(
+      localized_message(&config, "pt-br", "record/not_found"),
+      Some("pt-BR: not found")
+    );
+    assert_eq!(localized_message(&config, "de", "record/forbidden"), None);
+
+    config.server.default_language = Some("de".to_string());
+    assert_eq!(negotiate("fr"), Some("de".to_string()));
+  }
+
+  #[test]
+  fn test_error_codes() {
+    let mut codes: Vec<&str> = ErrorCode::ALL.iter().map(|c| c.as_str()).collect();
+    codes.sort();
+    codes.dedup();
+    assert_eq!(codes.len(), ErrorCode::ALL.len());
+
+    for code in ErrorCode::ALL {
+      assert_eq!(ErrorCode::from_code(code.as_str()), Some(*code));
+      assert_eq!(
+        serde_json::to_value(code).unwrap(),
+        serde_json::Value::String(code.as_str().to_string())
+      );
+    }
+  }
+}
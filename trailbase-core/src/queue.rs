@@ -1,6 +1,10 @@
+use log::*;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+use crate::app_state::AppState;
 use crate::data_dir::DataDir;
+use crate::records::embeddings::{EmbeddingError, update_embedding};
 
 #[derive(Debug, Error)]
 pub enum QueueError {
@@ -10,31 +14,40 @@ pub enum QueueError {
   Sqlite(#[from] trailbase_sqlite::Error),
   #[error("IO error: {0}")]
   IO(#[from] std::io::Error),
+  #[error("Embedding error: {0}")]
+  Embedding(#[from] EmbeddingError),
+  #[error("Storage error: {0}")]
+  Storage(String),
 }
 
-#[cfg(feature = "queue")]
-pub(crate) mod queue_impl {
-  use log::*;
-  use serde::{Deserialize, Serialize};
-
-  use super::QueueError;
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Job {
+  #[allow(unused)]
+  Something(),
+  /// (Re-)computes the embedding of a record, see `RecordApiConfig.embedding`.
+  Embed { api_name: String, record_id: String },
+}
 
-  #[derive(Debug, Serialize, Deserialize)]
-  pub enum Job {
-    Something(),
+pub(crate) async fn handle_job(state: &AppState, job: Job) -> Result<(), QueueError> {
+  match job {
+    Job::Something() => {
+      info!("Queue got something");
+    }
+    Job::Embed {
+      api_name,
+      record_id,
+    } => {
+      update_embedding(state, &api_name, &record_id).await?;
+    }
   }
 
-  pub async fn handle_job(job: Job) -> Result<(), QueueError> {
-    match job {
-      Job::Something() => {
-        info!("Queue got something");
-      }
-    }
+  return Ok(());
+}
 
-    return Ok(());
-  }
+#[cfg(feature = "queue")]
+pub(crate) mod queue_impl {
+  use super::Job;
 
-  #[cfg(feature = "queue")]
   pub(crate) type QueueStorage = trailbase_apalis::sqlite::SqliteStorage<Job>;
 }
 
@@ -53,16 +66,49 @@ impl Queue {
     });
   }
 
+  /// Enqueues the given job. Without the "queue" feature, jobs are processed by a background task
+  /// right away and are thus lost on shutdown.
+  #[cfg_attr(feature = "queue", allow(unused_variables))]
+  pub(crate) async fn push(&self, state: &AppState, job: Job) -> Result<(), QueueError> {
+    #[cfg(feature = "queue")]
+    {
+      use apalis::prelude::Storage;
+
+      self
+        .storage
+        .clone()
+        .push(job)
+        .await
+        .map_err(|err| QueueError::Storage(err.to_string()))?;
+    }
+
+    #[cfg(not(feature = "queue"))]
+    {
+      let state = state.clone();
+      tokio::spawn(async move {
+        if let Err(err) = handle_job(&state, job).await {
+          warn!("Job failed: {err}");
+        }
+      });
+    }
+
+    return Ok(());
+  }
+
   #[cfg(feature = "queue")]
-  #[allow(unused)]
-  pub(crate) async fn run(&self) -> Result<(), QueueError> {
+  pub(crate) async fn run(&self, state: AppState) -> Result<(), QueueError> {
     use apalis::prelude::*;
 
     let monitor = Monitor::new().register({
       WorkerBuilder::new("default-worker")
         // .enable_tracing()
+        .data(state)
         .backend(self.storage.clone())
-        .build_fn(queue_impl::handle_job)
+        .build_fn(
+          async |job: Job, state: Data<AppState>| -> Result<(), QueueError> {
+            return handle_job(&state, job).await;
+          },
+        )
     });
 
     return Ok(monitor.run().await?);
@@ -94,7 +140,6 @@ pub(crate) async fn init_queue_storage(
 #[cfg(test)]
 #[cfg(feature = "queue")]
 mod tests {
-  use super::queue_impl::*;
   use super::*;
 
   use apalis::prelude::*;
@@ -113,8 +158,8 @@ mod tests {
           .backend(storage)
           .build_fn(
             async |job: Job, sender: Data<async_channel::Sender<()>>| -> Result<(), QueueError> {
-              match job {
-                Job::Something() => sender.send(()).await.unwrap(),
+              if let Job::Something() = job {
+                sender.send(()).await.unwrap();
               }
 
               return Ok(());
@@ -0,0 +1,311 @@
+use axum::RequestExt;
+use axum::extract::{Request, State};
+use axum::http::header;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use base64::prelude::*;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::app_state::AppState;
+use crate::auth::OptionalUser;
+use crate::auth::api_key::{API_KEY_PREFIX, hash_api_key, is_valid_api_key};
+use crate::client_ip::secure_client_ip;
+use crate::config::proto::RateLimitConfig;
+use crate::constants::RECORD_API_PATH;
+use crate::problem::{ErrorCode, Problem};
+use crate::util::get_header;
+
+/// Upper bound of tracked buckets, beyond which replenished buckets are evicted.
+const MAX_BUCKETS: usize = 100_000;
+
+/// Route groups with separately configurable limits, see `server.rate_limits`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum RouteGroup {
+  Records,
+  Auth,
+  Query,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RateLimiterStats {
+  pub allowed: u64,
+  pub rejected: u64,
+  pub clients: u64,
+}
+
+#[derive(Debug)]
+struct Bucket {
+  tokens: f64,
+  updated_ms: i64,
+  /// Point in time at which the bucket would be full again, i.e. indistinguishable from a new one.
+  replenished_ms: i64,
+}
+
+/// In-memory token buckets keyed by scope, e.g. "records/<api>", and client.
+///
+/// NOTE: Limits aren't shared across multiple TrailBase instances.
+pub(crate) struct RateLimiter {
+  buckets: Mutex<HashMap<(String, String), Bucket>>,
+
+  allowed: AtomicU64,
+  rejected: AtomicU64,
+}
+
+impl RateLimiter {
+  pub(crate) fn new() -> Self {
+    return Self {
+      buckets: Mutex::new(HashMap::new()),
+      allowed: AtomicU64::new(0),
+      rejected: AtomicU64::new(0),
+    };
+  }
+
+  /// Takes a token from the client's bucket. Returns the time until the next token becomes
+  /// available if the bucket is empty.
+  pub(crate) fn check(
+    &self,
+    scope: &str,
+    client: &str,
+    limit: &RateLimitConfig,
+    now_ms: i64,
+  ) -> Result<(), Duration> {
+    let per_minute = limit.requests_per_minute.unwrap_or(0);
+    if per_minute == 0 {
+      return Ok(());
+    }
+    // Milliseconds per token.
+    let interval = 60_000.0 / per_minute as f64;
+    let burst = limit.burst.unwrap_or(per_minute) as f64;
+
+    let mut buckets = self.buckets.lock();
+    if buckets.len() >= MAX_BUCKETS {
+      buckets.retain(|_, bucket| bucket.replenished_ms > now_ms);
+    }
+
+    let bucket = buckets
+      .entry((scope.to_string(), client.to_string()))
+      .or_insert(Bucket {
+        tokens: burst,
+        updated_ms: now_ms,
+        replenished_ms: now_ms,
+      });
+
+    let elapsed = (now_ms - bucket.updated_ms).max(0) as f64;
+    bucket.tokens = (bucket.tokens + elapsed / interval).min(burst);
+    bucket.updated_ms = now_ms;
+
+    let result = if bucket.tokens >= 1.0 {
+      bucket.tokens -= 1.0;
+      self.allowed.fetch_add(1, Ordering::Relaxed);
+      Ok(())
+    } else {
+      self.rejected.fetch_add(1, Ordering::Relaxed);
+      Err(Duration::from_millis(
+        ((1.0 - bucket.tokens) * interval).ceil() as u64,
+      ))
+    };
+
+    bucket.replenished_ms = now_ms + ((burst - bucket.tokens) * interval).ceil() as i64;
+
+    return result;
+  }
+
+  pub(crate) fn stats(&self) -> RateLimiterStats {
+    return RateLimiterStats {
+      allowed: self.allowed.load(Ordering::Relaxed),
+      rejected: self.rejected.load(Ordering::Relaxed),
+      clients: self.buckets.lock().len() as u64,
+    };
+  }
+}
+
+/// Rejects requests exceeding the configured limits with 429 and a `Retry-After` header.
+pub(crate) async fn rate_limit_middleware(
+  State((state, group)): State<(AppState, RouteGroup)>,
+  mut req: Request,
+  next: Next,
+) -> Response {
+  let Some((scope, limit)) = lookup_limit(&state, group, req.uri().path()) else {
+    return next.run(req).await;
+  };
+
+  let client = client_key(&state, &mut req).await;
+  let now_ms = state.clock().now().timestamp_millis();
+  if let Err(retry_after) = state.rate_limiter().check(&scope, &client, &limit, now_ms) {
+    return Problem::new(ErrorCode::ServerRateLimited)
+      .with_retry_after(retry_after)
+      .into_response();
+  }
+
+  return next.run(req).await;
+}
+
+/// Returns the bucket scope and the limit applicable to the given request, if any.
+fn lookup_limit(
+  state: &AppState,
+  group: RouteGroup,
+  path: &str,
+) -> Option<(String, RateLimitConfig)> {
+  if group == RouteGroup::Records {
+    let api_name = path
+      .strip_prefix(&format!("/{RECORD_API_PATH}/"))
+      .and_then(|rest| rest.split('/').next());
+    let api_limit = api_name
+      .and_then(|name| state.lookup_record_api(name))
+      .and_then(|api| Some((format!("records/{}", api.api_name()), *api.rate_limit()?)));
+    if api_limit.is_some() {
+      return api_limit;
+    }
+  }
+
+  return state.access_config(|c| {
+    let limits = c.server.rate_limits.as_ref()?;
+    return match group {
+      RouteGroup::Records => limits.records.map(|l| ("records".to_string(), l)),
+      RouteGroup::Auth => limits.auth.map(|l| ("auth".to_string(), l)),
+      RouteGroup::Query => limits.query.map(|l| ("query".to_string(), l)),
+    };
+  });
+}
+
+/// Identifies the client by API key, authenticated user or, as a fallback, client IP.
+///
+/// NOTE: Only valid API keys get their own bucket, otherwise clients could evade limits by
+/// sending random keys. Likewise, the client IP must not be taken from spoofable headers.
+async fn client_key(state: &AppState, req: &mut Request) -> String {
+  if let Some(key) = get_header(req.headers(), header::AUTHORIZATION)
+    .and_then(|v| v.strip_prefix("Bearer "))
+    .filter(|v| v.starts_with(API_KEY_PREFIX))
+  {
+    if is_valid_api_key(state, key).await.unwrap_or(false) {
+      return format!("key:{}", BASE64_URL_SAFE.encode(hash_api_key(key)));
+    }
+  }
+
+  if let Ok(OptionalUser(Some(user))) = req.extract_parts_with_state::<OptionalUser, _>(state).await
+  {
+    return format!("user:{}", user.uuid);
+  }
+
+  return match secure_client_ip(req.headers(), req.extensions()) {
+    Some(ip) => format!("ip:{ip}"),
+    None => "ip:unknown".to_string(),
+  };
+}
+
+#[cfg(test)]
+mod tests {
+  use axum::Router;
+  use axum::extract::connect_info::MockConnectInfo;
+  use axum::middleware;
+  use axum::routing::post;
+  use axum_test::TestServer;
+
+  use std::net::SocketAddr;
+
+  use super::*;
+  use crate::auth::api_key::generate_api_key;
+  use crate::config::proto::RateLimitsConfig;
+  use crate::constants::AUTH_API_PATH;
+
+  #[test]
+  fn test_token_bucket() {
+    let limiter = RateLimiter::new();
+    let limit = RateLimitConfig {
+      requests_per_minute: Some(60),
+      burst: Some(2),
+    };
+
+    assert!(limiter.check("s", "a", &limit, 0).is_ok());
+    assert!(limiter.check("s", "a", &limit, 0).is_ok());
+    assert_eq!(
+      limiter.check("s", "a", &limit, 0),
+      Err(Duration::from_secs(1))
+    );
+    // Other clients and scopes have their own buckets.
+    assert!(limiter.check("s", "b", &limit, 0).is_ok());
+    assert!(limiter.check("t", "a", &limit, 0).is_ok());
+
+    // Replenished at one token per second.
+    assert_eq!(
+      limiter.check("s", "a", &limit, 500),
+      Err(Duration::from_millis(500))
+    );
+    assert!(limiter.check("s", "a", &limit, 1000).is_ok());
+    assert!(limiter.check("s", "a", &limit, 1000).is_err());
+
+    assert_eq!(
+      limiter.stats(),
+      RateLimiterStats {
+        allowed: 5,
+        rejected: 3,
+        clients: 3,
+      }
+    );
+  }
+
+  #[tokio::test]
+  async fn test_rate_limit_middleware() {
+    let state = crate::app_state::test_state(None).await.unwrap();
+
+    let mut config = state.get_config();
+    config.server.rate_limits = Some(RateLimitsConfig {
+      auth: Some(RateLimitConfig {
+        requests_per_minute: Some(1),
+        burst: None,
+      }),
+      ..Default::default()
+    });
+    state
+      .validate_and_update_config(config, None)
+      .await
+      .unwrap();
+
+    let path = format!("/{AUTH_API_PATH}/login");
+    let server = |peer: &str| {
+      let app = Router::new()
+        .route(&path, post(|| async { "Ok" }))
+        .layer(middleware::from_fn_with_state(
+          (state.clone(), RouteGroup::Auth),
+          rate_limit_middleware,
+        ))
+        .layer(MockConnectInfo(peer.parse::<SocketAddr>().unwrap()))
+        .with_state(state.clone());
+      return TestServer::new(app).unwrap();
+    };
+
+    let server1 = server("1.2.3.4:1000");
+    server1
+      .post(&path)
+      .add_header("X-Forwarded-For", "10.0.0.1")
+      .await
+      .assert_status_ok();
+
+    let response = server1.post(&path).await;
+    response.assert_status(axum::http::StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(response.header(header::RETRY_AFTER), "60");
+
+    // Spoofed forwarding headers don't yield new buckets.
+    server1
+      .post(&path)
+      .add_header("X-Forwarded-For", "10.0.0.2")
+      .await
+      .assert_status(axum::http::StatusCode::TOO_MANY_REQUESTS);
+
+    // Neither do made-up API keys.
+    server1
+      .post(&path)
+      .add_header(
+        header::AUTHORIZATION,
+        format!("Bearer {}", generate_api_key().0),
+      )
+      .await
+      .assert_status(axum::http::StatusCode::TOO_MANY_REQUESTS);
+
+    // Other peers have their own buckets.
+    server("5.6.7.8:1000").post(&path).await.assert_status_ok();
+  }
+}
@@ -0,0 +1,487 @@
+use askama::Template;
+use axum::Json;
+use axum::extract::{Path, RawQuery, State};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use trailbase_sqlite::Value;
+
+use crate::app_state::AppState;
+use crate::auth::user::User;
+use crate::listing::{
+  Order, QueryParseResult, WhereClause, build_filter_where_clause, parse_and_sanitize_query,
+};
+use crate::records::list_records::{column_filter, encrypt_filter_params, list_limits};
+use crate::records::request_context::push_request_context_params;
+use crate::records::{Permission, RecordApi, RecordError};
+
+/// Max number of aggregates per query.
+const MAX_AGGREGATES: usize = 16;
+
+#[derive(Template)]
+#[template(escape = "none", path = "aggregate_record_query.sql")]
+struct AggregateRecordQueryTemplate<'a> {
+  table_name: &'a str,
+  select_exprs: &'a [String],
+  read_access_clause: &'a str,
+  filter_clause: &'a str,
+  group_by: &'a [String],
+  order_clause: &'a str,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum AggregateFunction {
+  Count,
+  Sum,
+  Avg,
+  Min,
+  Max,
+}
+
+impl AggregateFunction {
+  fn parse(name: &str) -> Option<Self> {
+    return match name.to_ascii_lowercase().as_str() {
+      "count" => Some(Self::Count),
+      "sum" => Some(Self::Sum),
+      "avg" => Some(Self::Avg),
+      "min" => Some(Self::Min),
+      "max" => Some(Self::Max),
+      _ => None,
+    };
+  }
+
+  fn name(self) -> &'static str {
+    return match self {
+      Self::Count => "count",
+      Self::Sum => "sum",
+      Self::Avg => "avg",
+      Self::Min => "min",
+      Self::Max => "max",
+    };
+  }
+}
+
+/// Aggregate function applied to a column, e.g. "sum(price)", or all rows, i.e. "count(*)".
+#[derive(Clone, Debug, PartialEq)]
+struct Aggregate {
+  function: AggregateFunction,
+  column: Option<String>,
+}
+
+impl Aggregate {
+  fn parse(value: &str) -> Option<Self> {
+    let (name, rest) = value.trim().split_once('(')?;
+    let column = rest.strip_suffix(')')?.trim();
+    let function = AggregateFunction::parse(name.trim())?;
+
+    if column == "*" {
+      // Only counting supports "*".
+      return (function == AggregateFunction::Count).then_some(Self {
+        function,
+        column: None,
+      });
+    }
+
+    if column.is_empty() || !column.chars().all(|c| c.is_alphanumeric() || c == '_') {
+      return None;
+    }
+    return Some(Self {
+      function,
+      column: Some(column.to_string()),
+    });
+  }
+
+  /// Key of the aggregate in the response, e.g. "sum(price)".
+  fn key(&self) -> String {
+    return format!(
+      "{}({})",
+      self.function.name(),
+      self.column.as_deref().unwrap_or("*")
+    );
+  }
+
+  fn to_sql(&self) -> String {
+    return match self.column {
+      Some(ref column) => format!(r#"{}(_ROW_."{column}")"#, self.function.name()),
+      None => format!("{}(*)", self.function.name()),
+    };
+  }
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct AggregateOptions {
+  /// Columns to group by. Without grouping, a single group comprising all matching records is
+  /// returned.
+  group_by: Vec<String>,
+  /// Aggregates to compute per group. Defaults to "count(*)".
+  aggregates: Vec<Aggregate>,
+}
+
+/// Splits out aggregation-specific query parameters, returning the remainder as list query.
+fn split_aggregate_query(query: Option<&str>) -> Result<(AggregateOptions, String), RecordError> {
+  let mut options = AggregateOptions::default();
+  let mut remainder = form_urlencoded::Serializer::new(String::new());
+
+  for (key, value) in form_urlencoded::parse(query.unwrap_or_default().as_bytes()) {
+    match key.as_ref() {
+      "group_by" => {
+        options.group_by.extend(
+          value
+            .split(',')
+            .map(|c| c.trim())
+            .filter(|c| !c.is_empty())
+            .map(|c| c.to_string()),
+        );
+      }
+      "agg" => {
+        // NOTE: Splitting on "," is fine since aggregates take a single argument.
+        for aggregate in value.split(',').filter(|a| !a.trim().is_empty()) {
+          options
+            .aggregates
+            .push(Aggregate::parse(aggregate).ok_or(RecordError::BadRequest("Invalid aggregate"))?);
+        }
+      }
+      _ => {
+        remainder.append_pair(&key, &value);
+      }
+    }
+  }
+
+  if options.aggregates.is_empty() {
+    options.aggregates.push(Aggregate {
+      function: AggregateFunction::Count,
+      column: None,
+    });
+  }
+  if options.aggregates.len() > MAX_AGGREGATES {
+    return Err(RecordError::BadRequest("Too many aggregates"));
+  }
+
+  return Ok((options, remainder.finish()));
+}
+
+/// Only visible, unencrypted columns can be grouped by or aggregated, since ciphertexts don't
+/// compare meaningfully.
+fn check_column(api: &RecordApi, column: &str) -> Result<(), RecordError> {
+  if !column_filter(column) {
+    return Err(RecordError::BadRequest("Invalid column"));
+  }
+  let Some(index) = api.column_index_by_name(column) else {
+    return Err(RecordError::BadRequest("Invalid column"));
+  };
+  if api.column_encryption(index).is_some() {
+    return Err(RecordError::BadRequest("Cannot aggregate encrypted column"));
+  }
+  return Ok(());
+}
+
+/// JSON response containing the aggregated groups.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AggregateResponse {
+  /// One entry per group, containing the group's column values and aggregates keyed by their
+  /// normalized expression, e.g. "sum(price)".
+  pub groups: Vec<serde_json::Map<String, serde_json::Value>>,
+}
+
+/// Aggregates records matching the given filters, optionally grouped by columns.
+///
+/// Accepts the same filters as listing, as well as `group_by` (comma separated columns), `agg`
+/// (comma separated `count`, `sum`, `avg`, `min` or `max` aggregates, e.g. `sum(price),count(*)`),
+/// `order` to order groups by grouped columns, `limit` and `offset`.
+#[utoipa::path(
+  get,
+  path = "/:name/aggregate",
+  responses(
+    (status = 200, description = "Aggregated groups.")
+  )
+)]
+pub async fn aggregate_records_handler(
+  State(state): State<AppState>,
+  Path(api_name): Path<String>,
+  RawQuery(raw_url_query): RawQuery,
+  user: Option<User>,
+) -> Result<Json<AggregateResponse>, RecordError> {
+  let Some(api) = state.lookup_record_api(&api_name) else {
+    return Err(RecordError::ApiNotFound);
+  };
+
+  // NOTE: Like listing, the read access rule is applied as a filter.
+  api.check_table_level_access(Permission::Read, user.as_ref())?;
+
+  let (options, list_query) = split_aggregate_query(raw_url_query.as_deref())?;
+
+  let QueryParseResult {
+    limit,
+    cursor,
+    count,
+    expand,
+    order,
+    params: filter_params,
+    filter,
+    offset,
+    select,
+    nearest,
+    k: _,
+    search,
+    collate,
+    format,
+    include_deleted,
+  } = parse_and_sanitize_query(Some(&list_query)).map_err(|_err| {
+    return RecordError::BadRequest("Invalid query");
+  })?;
+
+  if cursor.is_some()
+    || count.is_some()
+    || expand.is_some()
+    || select.is_some()
+    || nearest.is_some()
+    || search.is_some()
+    || format.is_some()
+  {
+    return Err(RecordError::BadRequest(
+      "Unsupported parameter for aggregation",
+    ));
+  }
+
+  if let Some(ref collate) = collate {
+    if !trailbase_extension::collation::has_collation(collate) {
+      return Err(RecordError::BadRequest("Invalid collation"));
+    }
+  }
+
+  // NOTE: Validating all columns against the schema also avoids SQL injections.
+  for column in &options.group_by {
+    check_column(&api, column)?;
+  }
+  for column in options.aggregates.iter().filter_map(|a| a.column.as_ref()) {
+    check_column(&api, column)?;
+  }
+
+  fn fmt_order(col: &str, order: Order) -> String {
+    return format!(
+      r#"_ROW_."{col}" {}"#,
+      match order {
+        Order::Descending => "DESC",
+        Order::Ascending => "ASC",
+      }
+    );
+  }
+
+  // Groups are ordered by the grouped columns, which only leaves the direction up to the caller.
+  let order_clause = match order {
+    Some(order) => {
+      if order.iter().any(|(col, _)| !options.group_by.contains(col)) {
+        return Err(RecordError::BadRequest("Invalid order"));
+      }
+      order
+        .into_iter()
+        .map(|(col, ord)| fmt_order(&col, ord))
+        .join(",")
+    }
+    None => options
+      .group_by
+      .iter()
+      .map(|col| fmt_order(col, Order::Ascending))
+      .join(","),
+  };
+
+  let read_access_clause =
+    api.list_access_clause(include_deleted.unwrap_or(false), user.as_ref())?;
+
+  // NOTE: This will also drop any filters for unknown columns, thus avoiding SQL injections.
+  let (filter_params, filter) = encrypt_filter_params(&api, filter_params, filter)?;
+  let WhereClause {
+    clause: filter_clause,
+    mut params,
+  } = build_filter_where_clause(
+    "_ROW_",
+    api.columns(),
+    filter_params,
+    filter,
+    collate.as_deref(),
+  )
+  .map_err(|_err| RecordError::BadRequest("Invalid filter params"))?;
+
+  params.extend_from_slice(&[
+    (
+      Cow::Borrowed(":__limit"),
+      Value::Integer(list_limits(&state, &api, user.as_ref()).limit(limit)? as i64),
+    ),
+    (
+      Cow::Borrowed(":__offset"),
+      Value::Integer(
+        offset
+          .unwrap_or(0)
+          .try_into()
+          .map_err(|_| RecordError::BadRequest("Invalid offset"))?,
+      ),
+    ),
+    (
+      Cow::Borrowed(":__user_id"),
+      user.map_or(Value::Null, |u| Value::Blob(u.uuid.into())),
+    ),
+  ]);
+  push_request_context_params(&mut params);
+
+  let keys: Vec<String> = options
+    .group_by
+    .iter()
+    .cloned()
+    .chain(options.aggregates.iter().map(|a| a.key()))
+    .collect();
+  let select_exprs: Vec<String> = options
+    .group_by
+    .iter()
+    .map(|col| format!(r#"_ROW_."{col}""#))
+    .chain(options.aggregates.iter().map(|a| a.to_sql()))
+    .collect();
+
+  let query = AggregateRecordQueryTemplate {
+    table_name: api.table_name(),
+    select_exprs: &select_exprs,
+    read_access_clause: &read_access_clause,
+    filter_clause: &filter_clause,
+    group_by: &options.group_by,
+    order_clause: &order_clause,
+  }
+  .render()
+  .map_err(|err| RecordError::Internal(err.into()))?;
+
+  let rows = state.conn().read_query_rows(query, params).await?;
+
+  let groups = rows
+    .iter()
+    .map(|row| {
+      return keys
+        .iter()
+        .enumerate()
+        .map(|(i, key)| {
+          let value = row
+            .get_value(i)
+            .ok_or_else(|| RecordError::Internal("missing value".into()))?;
+          let value = trailbase_sqlite::rows::value_to_json(value)
+            .map_err(|err| RecordError::Internal(err.into()))?;
+          return Ok((key.clone(), value));
+        })
+        .collect::<Result<serde_json::Map<_, _>, RecordError>>();
+    })
+    .collect::<Result<Vec<_>, RecordError>>()?;
+
+  return Ok(Json(AggregateResponse { groups }));
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::app_state::test_state;
+  use crate::config::proto::{PermissionFlag, RecordApiConfig};
+  use crate::records::test_utils::add_record_api_config;
+
+  #[test]
+  fn test_split_aggregate_query() {
+    let (options, remainder) = split_aggregate_query(Some(
+      "group_by=category&agg=SUM(price),count(*)&price[gt]=5",
+    ))
+    .unwrap();
+    assert_eq!(
+      AggregateOptions {
+        group_by: vec!["category".to_string()],
+        aggregates: vec![
+          Aggregate {
+            function: AggregateFunction::Sum,
+            column: Some("price".to_string()),
+          },
+          Aggregate {
+            function: AggregateFunction::Count,
+            column: None,
+          },
+        ],
+      },
+      options
+    );
+    assert_eq!("price%5Bgt%5D=5", remainder);
+    assert_eq!(options.aggregates[0].key(), "sum(price)");
+
+    let (options, _) = split_aggregate_query(None).unwrap();
+    assert_eq!(options.aggregates[0].key(), "count(*)");
+
+    assert!(split_aggregate_query(Some("agg=sum(*)")).is_err());
+    assert!(split_aggregate_query(Some("agg=median(price)")).is_err());
+    assert!(split_aggregate_query(Some(r#"agg=sum(price")"#)).is_err());
+    assert!(split_aggregate_query(Some("agg=sum(price")).is_err());
+  }
+
+  #[tokio::test]
+  async fn test_record_api_aggregate() {
+    let state = test_state(None).await.unwrap();
+    state
+      .conn()
+      .execute_batch(
+        r#"
+          CREATE TABLE product (
+            id       INTEGER PRIMARY KEY,
+            category TEXT,
+            price    INTEGER,
+            owner    INTEGER,
+            _hidden  TEXT
+          ) STRICT;
+          INSERT INTO product (category, price, owner) VALUES
+            ('a', 10, 1), ('a', 20, 1), ('b', 5, 1), ('b', 7, 2), ('c', 100, 2);
+        "#,
+      )
+      .await
+      .unwrap();
+    state.refresh_table_cache().await.unwrap();
+
+    add_record_api_config(
+      &state,
+      RecordApiConfig {
+        name: Some("products".to_string()),
+        table_name: Some("product".to_string()),
+        acl_world: [PermissionFlag::Read as i32].into(),
+        read_access_rule: Some("_ROW_.owner = 1".to_string()),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    let aggregate = async |query: &str| -> Result<serde_json::Value, RecordError> {
+      let Json(response) = aggregate_records_handler(
+        State(state.clone()),
+        Path("products".to_string()),
+        RawQuery(Some(query.to_string())),
+        None,
+      )
+      .await?;
+      return Ok(serde_json::to_value(response.groups).unwrap());
+    };
+
+    // Records not passing the read access rule aren't aggregated.
+    assert_eq!(
+      aggregate("group_by=category&agg=sum(price),count(*)")
+        .await
+        .unwrap(),
+      serde_json::json!([
+        {"category": "a", "sum(price)": 30, "count(*)": 2},
+        {"category": "b", "sum(price)": 5, "count(*)": 1},
+      ])
+    );
+    assert_eq!(
+      aggregate("group_by=category&order=-category&price[gt]=5&agg=max(price)")
+        .await
+        .unwrap(),
+      serde_json::json!([{"category": "a", "max(price)": 20}])
+    );
+    assert_eq!(
+      aggregate("").await.unwrap(),
+      serde_json::json!([{"count(*)": 3}])
+    );
+
+    assert!(aggregate("group_by=_hidden").await.is_err());
+    assert!(aggregate("group_by=missing").await.is_err());
+    assert!(aggregate("agg=sum(missing)").await.is_err());
+    assert!(aggregate("group_by=category&order=price").await.is_err());
+    assert!(aggregate("cursor=abc").await.is_err());
+  }
+}
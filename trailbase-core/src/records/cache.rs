@@ -0,0 +1,464 @@
+use itertools::Itertools;
+use mini_moka::sync::Cache;
+use parking_lot::{Mutex, RwLock};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+use uuid::Uuid;
+
+use crate::auth::api_key::ApiKeyScope;
+use crate::auth::user::User;
+use crate::records::list_records::ListResponse;
+use crate::records::{RecordApi, RecordError};
+
+/// Default upper bound for the approximate size of an API's cached responses, see
+/// `cache_max_bytes`.
+const DEFAULT_MAX_CACHE_BYTES: u64 = 64 * 1024 * 1024;
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub(crate) enum CacheOp {
+  List,
+  Read,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub(crate) struct CacheKey {
+  api_name: String,
+  op: CacheOp,
+  /// Canonical query, i.e. the parsed listing query or the record id.
+  query: String,
+  /// Principal the response was computed for, since access rules depend on the user.
+  principal: Option<Principal>,
+}
+
+/// Everything about the requesting user, which may affect a response.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+struct Principal {
+  user: Uuid,
+  /// Roles, e.g. affecting page size limits.
+  roles: Vec<String>,
+  /// Scopes of the API key used, if any, which restrict the user's access.
+  api_key_scopes: Option<Vec<ApiKeyScope>>,
+}
+
+impl CacheKey {
+  pub(crate) fn new(api: &RecordApi, op: CacheOp, query: String, user: Option<&User>) -> Self {
+    return Self {
+      api_name: api.api_name().to_string(),
+      op,
+      query,
+      principal: user.map(|u| Principal {
+        user: u.uuid,
+        roles: u.roles.iter().cloned().sorted().collect(),
+        api_key_scopes: u.api_key_scopes.clone(),
+      }),
+    };
+  }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) enum CachedResponse {
+  List(ListResponse),
+  Json(serde_json::Value),
+  /// Single record and its ETag.
+  Record(serde_json::Value, String),
+}
+
+#[derive(Clone)]
+struct CacheEntry {
+  response: Arc<CachedResponse>,
+  generation: (u64, u64),
+  expires_at: Instant,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct QueryCacheStats {
+  pub hits: u64,
+  pub misses: u64,
+  pub entries: u64,
+}
+
+#[derive(Default)]
+struct Generations {
+  /// Bumped whenever the cache is cleared, e.g. on config changes.
+  epoch: AtomicU64,
+  /// Bumped on any write to the main database.
+  writes: AtomicU64,
+  /// Bumped on writes to the respective table.
+  tables: Mutex<HashMap<String, u64>>,
+}
+
+impl Generations {
+  fn bump(&self, table_name: &str) {
+    self.writes.fetch_add(1, Ordering::SeqCst);
+
+    let mut tables = self.tables.lock();
+    if let Some(generation) = tables.get_mut(table_name) {
+      *generation += 1;
+    } else {
+      tables.insert(table_name.to_string(), 1);
+    }
+  }
+
+  fn current(&self, dependency: Option<&str>) -> (u64, u64) {
+    let epoch = self.epoch.load(Ordering::SeqCst);
+    return match dependency {
+      Some(table_name) => (
+        epoch,
+        self.tables.lock().get(table_name).copied().unwrap_or(0),
+      ),
+      None => (epoch, self.writes.load(Ordering::SeqCst)),
+    };
+  }
+}
+
+/// Read-through cache for list and read responses of record APIs with a `cache_ttl_sec`.
+///
+/// Only payloads are cached. Callers need to check access for every request before consulting
+/// the cache, since hits skip the computation entirely.
+///
+/// Entries are keyed by API, canonical query and principal, and invalidated on writes observed via
+/// update and commit listeners on the main connection, which share SQLite's hooks with other
+/// consumers, e.g. [trailbase_sqlite::Connection::change_stream]. Writes bypassing the connection,
+/// e.g. from other processes, are only picked up once entries expire. Each API has its own
+/// size-bounded cache, such that large responses of one API don't evict the hot entries of
+/// another.
+pub(crate) struct QueryCache {
+  entries: RwLock<HashMap<String, Cache<CacheKey, CacheEntry>>>,
+  generations: Arc<Generations>,
+  hooks: tokio::sync::OnceCell<()>,
+
+  hits: AtomicU64,
+  misses: AtomicU64,
+}
+
+impl QueryCache {
+  pub(crate) fn new() -> Self {
+    return Self {
+      entries: RwLock::new(HashMap::new()),
+      generations: Arc::new(Generations::default()),
+      hooks: tokio::sync::OnceCell::new(),
+      hits: AtomicU64::new(0),
+      misses: AtomicU64::new(0),
+    };
+  }
+
+  pub(crate) fn stats(&self) -> QueryCacheStats {
+    return QueryCacheStats {
+      hits: self.hits.load(Ordering::Relaxed),
+      misses: self.misses.load(Ordering::Relaxed),
+      entries: self.entries.read().values().map(|e| e.entry_count()).sum(),
+    };
+  }
+
+  /// Drops all entries, e.g. when record APIs get rebuilt. Caches are re-created on demand to pick
+  /// up changed size limits.
+  pub(crate) fn clear(&self) {
+    self.generations.epoch.fetch_add(1, Ordering::SeqCst);
+    self.entries.write().clear();
+  }
+
+  fn api_entries(&self, api: &RecordApi) -> Cache<CacheKey, CacheEntry> {
+    if let Some(entries) = self.entries.read().get(api.api_name()) {
+      return entries.clone();
+    }
+
+    return self
+      .entries
+      .write()
+      .entry(api.api_name().to_string())
+      .or_insert_with(|| {
+        return Cache::builder()
+          .max_capacity(api.cache_max_bytes().unwrap_or(DEFAULT_MAX_CACHE_BYTES))
+          .weigher(|_key, entry: &CacheEntry| entry.weight())
+          .build();
+      })
+      .clone();
+  }
+
+  /// Returns the cached response or computes and caches a new one, if caching is enabled for the
+  /// given API. Errors aren't cached.
+  pub(crate) async fn get_or_compute<F>(
+    &self,
+    conn: &trailbase_sqlite::Connection,
+    api: &RecordApi,
+    key: CacheKey,
+    compute: F,
+  ) -> Result<Arc<CachedResponse>, RecordError>
+  where
+    F: Future<Output = Result<CachedResponse, RecordError>>,
+  {
+    let Some(ttl) = api.cache_ttl() else {
+      return Ok(Arc::new(compute.await?));
+    };
+    let dependency = dependency(api);
+    let entries = self.api_entries(api);
+
+    if let Some(entry) = entries.get(&key) {
+      if entry.expires_at > Instant::now()
+        && entry.generation == self.generations.current(dependency)
+      {
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        return Ok(entry.response);
+      }
+      entries.invalidate(&key);
+    }
+    self.misses.fetch_add(1, Ordering::Relaxed);
+
+    // Hooks need to be in place before the query runs to not miss concurrent writes.
+    self.install_hooks(conn).await?;
+
+    let generation = self.generations.current(dependency);
+    let response = Arc::new(compute.await?);
+
+    // Only cache if nothing changed while computing, otherwise the response may be stale already.
+    if generation == self.generations.current(dependency) {
+      entries.insert(
+        key,
+        CacheEntry {
+          response: response.clone(),
+          generation,
+          expires_at: Instant::now() + ttl,
+        },
+      );
+    }
+
+    return Ok(response);
+  }
+
+  async fn install_hooks(&self, conn: &trailbase_sqlite::Connection) -> Result<(), RecordError> {
+    let generations = self.generations.clone();
+    self
+      .hooks
+      .get_or_try_init(|| async move {
+        // Bump on update and again on commit, since readers may pick up the previous state in
+        // between.
+        let pending: Arc<Mutex<Vec<String>>> = Arc::default();
+        {
+          let pending = pending.clone();
+          let generations = generations.clone();
+          conn
+            .add_update_listener(
+              move |_action, _db: &str, table_name: &str, _rowid| -> bool {
+                generations.bump(table_name);
+
+                let mut pending = pending.lock();
+                if !pending.iter().any(|t| t == table_name) {
+                  pending.push(table_name.to_string());
+                }
+                return true;
+              },
+            )
+            .await?;
+        }
+        return conn
+          .add_commit_listener(move || -> bool {
+            for table_name in std::mem::take(&mut *pending.lock()) {
+              generations.bump(&table_name);
+            }
+            return true;
+          })
+          .await;
+      })
+      .await
+      .map_err(|err| RecordError::Internal(err.into()))?;
+
+    return Ok(());
+  }
+}
+
+impl CacheEntry {
+  fn weight(&self) -> u32 {
+    let bytes = match *self.response {
+      CachedResponse::List(ref list) => serde_json::to_vec(list).map_or(0, |v| v.len()),
+      CachedResponse::Json(ref value) | CachedResponse::Record(ref value, _) => {
+        serde_json::to_vec(value).map_or(0, |v| v.len())
+      }
+    };
+    return bytes.try_into().unwrap_or(u32::MAX);
+  }
+}
+
+/// Table an API's responses depend on or None if they may depend on any table, e.g. for views,
+/// expansions or access rules with sub-queries.
+fn dependency(api: &RecordApi) -> Option<&str> {
+  if !api.is_table() || api.expand().is_some() {
+    return None;
+  }
+  // Listings of soft-deleted records are also subject to the delete access rule.
+  let rules = [api.read_access_rule(), api.soft_delete_access_rule()];
+  for rule in rules.into_iter().flatten() {
+    if rule.to_ascii_uppercase().contains("SELECT") {
+      return None;
+    }
+  }
+  return Some(api.table_name());
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::app_state::test_state;
+  use crate::auth::api_key::ApiKeyAccess;
+  use crate::config::proto::{PermissionFlag, RecordApiConfig};
+  use crate::records::RecordsClient;
+  use crate::records::test_utils::add_record_api_config;
+
+  #[tokio::test]
+  async fn test_query_cache() {
+    let state = test_state(None).await.unwrap();
+    state
+      .conn()
+      .execute_batch(
+        "CREATE TABLE article (id INTEGER PRIMARY KEY, title TEXT) STRICT;
+         INSERT INTO article (title) VALUES ('first');",
+      )
+      .await
+      .unwrap();
+    state.refresh_table_cache().await.unwrap();
+
+    add_record_api_config(
+      &state,
+      RecordApiConfig {
+        name: Some("articles".to_string()),
+        table_name: Some("article".to_string()),
+        acl_world: [PermissionFlag::Read as i32].into(),
+        cache_ttl_sec: Some(60),
+        cache_max_bytes: Some(1024 * 1024),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    let client = RecordsClient::new(&state);
+    let stats = || state.query_cache().stats();
+
+    assert_eq!(
+      client.list("articles", None).await.unwrap().records.len(),
+      1
+    );
+    assert_eq!((stats().hits, stats().misses), (0, 1));
+    assert_eq!(
+      client.list("articles", None).await.unwrap().records.len(),
+      1
+    );
+    assert_eq!((stats().hits, stats().misses), (1, 1));
+    assert_eq!(state.query_cache().entries.read().len(), 1);
+
+    // Writes invalidate, including ones bypassing the record API.
+    state
+      .conn()
+      .execute("INSERT INTO article (title) VALUES ('second')", ())
+      .await
+      .unwrap();
+    assert_eq!(
+      client.list("articles", None).await.unwrap().records.len(),
+      2
+    );
+    assert_eq!((stats().hits, stats().misses), (1, 2));
+
+    let record = client.read("articles", "1", None).await.unwrap();
+    assert_eq!(record["title"], "first");
+    assert_eq!(client.read("articles", "1", None).await.unwrap(), record);
+    assert_eq!((stats().hits, stats().misses), (2, 3));
+
+    state
+      .conn()
+      .execute("UPDATE article SET title = 'updated' WHERE id = 1", ())
+      .await
+      .unwrap();
+    let record = client.read("articles", "1", None).await.unwrap();
+    assert_eq!(record["title"], "updated");
+  }
+
+  #[tokio::test]
+  async fn test_query_cache_api_key_scopes() {
+    let state = test_state(None).await.unwrap();
+    state
+      .conn()
+      .execute_batch(
+        "CREATE TABLE post (id INTEGER PRIMARY KEY, title TEXT) STRICT;
+         INSERT INTO post (title) VALUES ('first');",
+      )
+      .await
+      .unwrap();
+    state.refresh_table_cache().await.unwrap();
+
+    add_record_api_config(
+      &state,
+      RecordApiConfig {
+        name: Some("posts".to_string()),
+        table_name: Some("post".to_string()),
+        acl_authenticated: [PermissionFlag::Read as i32].into(),
+        cache_ttl_sec: Some(60),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    let user = User::from_unverified(Uuid::now_v7(), "user@test.org");
+    let session = RecordsClient::new(&state).with_user(Some(user.clone()));
+    assert_eq!(session.list("posts", None).await.unwrap().records.len(), 1);
+    assert!(session.read("posts", "1", None).await.is_ok());
+
+    // The same user's API key scoped to another API must not be served the cached responses.
+    let key = RecordsClient::new(&state).with_user(Some(User {
+      api_key_scopes: Some(vec![ApiKeyScope {
+        api: "comments".to_string(),
+        access: ApiKeyAccess::ReadOnly,
+      }]),
+      ..user.clone()
+    }));
+    assert!(matches!(
+      key.list("posts", None).await,
+      Err(RecordError::Forbidden)
+    ));
+    assert!(matches!(
+      key.read("posts", "1", None).await,
+      Err(RecordError::Forbidden)
+    ));
+
+    let key = RecordsClient::new(&state).with_user(Some(User {
+      api_key_scopes: Some(vec![ApiKeyScope {
+        api: "posts".to_string(),
+        access: ApiKeyAccess::ReadOnly,
+      }]),
+      ..user.clone()
+    }));
+    assert_eq!(key.list("posts", None).await.unwrap().records.len(), 1);
+    assert_eq!(state.query_cache().stats().hits, 0);
+
+    // Roles are part of the principal as well, e.g. affecting page size limits.
+    let editor = RecordsClient::new(&state).with_user(Some(User {
+      roles: vec!["editor".to_string()],
+      ..user.clone()
+    }));
+    assert_eq!(editor.list("posts", None).await.unwrap().records.len(), 1);
+    assert_eq!(state.query_cache().stats().hits, 0);
+
+    // Equivalent queries hit.
+    assert_eq!(
+      session
+        .list("posts", Some("limit=01"))
+        .await
+        .unwrap()
+        .records
+        .len(),
+      1
+    );
+    assert_eq!(
+      session
+        .list("posts", Some("limit=1"))
+        .await
+        .unwrap()
+        .records
+        .len(),
+      1
+    );
+    assert_eq!(state.query_cache().stats().hits, 1);
+  }
+}
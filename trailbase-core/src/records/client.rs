@@ -0,0 +1,247 @@
+use crate::app_state::AppState;
+use crate::auth::user::User;
+use crate::extract::AcceptFormat;
+use crate::records::create_record::create_records;
+use crate::records::delete_record::{delete_record, undelete_record};
+use crate::records::list_records::{ListResponse, Listing, list_records};
+use crate::records::params::JsonRow;
+use crate::records::read_record::read_record;
+use crate::records::update_record::update_record;
+use crate::records::{RecordApi, RecordError};
+
+/// In-process client for record APIs, e.g. for embedders and custom handlers.
+///
+/// Calls go through the same pipeline as HTTP requests, i.e. access rules, validation and
+/// side-effects such as subscriptions apply, just without the overhead of loopback HTTP.
+/// Requests are anonymous unless a user is set via [`RecordsClient::with_user`].
+#[derive(Clone)]
+pub struct RecordsClient {
+  state: AppState,
+  user: Option<User>,
+}
+
+impl RecordsClient {
+  pub fn new(state: &AppState) -> Self {
+    return Self {
+      state: state.clone(),
+      user: None,
+    };
+  }
+
+  /// Acts on behalf of the given user, e.g. the one extracted from the incoming request.
+  pub fn with_user(mut self, user: Option<User>) -> Self {
+    self.user = user;
+    return self;
+  }
+
+  /// Lists records. The query uses the same syntax as the HTTP API's URL query, e.g.
+  /// "limit=10&order=-created&filter[age][$gt]=18".
+  pub async fn list(
+    &self,
+    api_name: &str,
+    query: Option<&str>,
+  ) -> Result<ListResponse, RecordError> {
+    let api = self.api(api_name)?;
+    return match list_records(
+      &self.state,
+      &api,
+      query,
+      self.user.clone(),
+      AcceptFormat::Json,
+    )
+    .await?
+    {
+      Listing::Records(list) => Ok(list),
+      Listing::Arrow(_) | Listing::GeoJson(_) => {
+        Err(RecordError::BadRequest("Unsupported list format"))
+      }
+    };
+  }
+
+  /// Reads a single record. `expand` is a comma-separated list of foreign key columns.
+  pub async fn read(
+    &self,
+    api_name: &str,
+    record_id: &str,
+    expand: Option<&str>,
+  ) -> Result<serde_json::Value, RecordError> {
+    let api = self.api(api_name)?;
+    return read_record(&self.state, &api, record_id, expand, self.user.as_ref()).await;
+  }
+
+  /// Creates a record and returns its id.
+  pub async fn create(&self, api_name: &str, record: JsonRow) -> Result<String, RecordError> {
+    let mut ids = self.create_bulk(api_name, vec![record]).await?;
+    return ids
+      .pop()
+      .ok_or_else(|| RecordError::Internal("missing record id".into()));
+  }
+
+  /// Creates multiple records within a single transaction and returns their ids.
+  pub async fn create_bulk(
+    &self,
+    api_name: &str,
+    records: Vec<JsonRow>,
+  ) -> Result<Vec<String>, RecordError> {
+    let api = self.api(api_name)?;
+    return create_records(
+      &self.state,
+      &api,
+      records.into_iter().map(|record| (record, None)).collect(),
+      None,
+      self.user.as_ref(),
+    )
+    .await;
+  }
+
+  /// Partially updates a record, i.e. columns missing from `record` remain unchanged.
+  pub async fn update(
+    &self,
+    api_name: &str,
+    record_id: &str,
+    record: JsonRow,
+  ) -> Result<(), RecordError> {
+    let api = self.api(api_name)?;
+    return update_record(
+      &self.state,
+      &api,
+      record_id.to_string(),
+      record,
+      None,
+      None,
+      self.user.as_ref(),
+    )
+    .await;
+  }
+
+  pub async fn delete(&self, api_name: &str, record_id: &str) -> Result<(), RecordError> {
+    let api = self.api(api_name)?;
+    return delete_record(&self.state, &api, record_id, None, self.user.as_ref()).await;
+  }
+
+  /// Restores a soft-deleted record, see `soft_delete_column`.
+  pub async fn undelete(&self, api_name: &str, record_id: &str) -> Result<(), RecordError> {
+    let api = self.api(api_name)?;
+    return undelete_record(&self.state, &api, record_id, self.user.as_ref()).await;
+  }
+
+  fn api(&self, api_name: &str) -> Result<RecordApi, RecordError> {
+    return self
+      .state
+      .lookup_record_api(api_name)
+      .ok_or(RecordError::ApiNotFound);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::json;
+
+  use super::*;
+  use crate::admin::user::create_user_for_test;
+  use crate::app_state::test_state;
+  use crate::auth::api::login::login_with_password;
+  use crate::config::proto::{PermissionFlag, RecordApiConfig};
+  use crate::records::test_utils::add_record_api_config;
+
+  fn row(value: serde_json::Value) -> JsonRow {
+    let serde_json::Value::Object(row) = value else {
+      panic!("expected object");
+    };
+    return row;
+  }
+
+  #[tokio::test]
+  async fn test_records_client() {
+    let state = test_state(None).await.unwrap();
+    state
+      .conn()
+      .execute_batch("CREATE TABLE note (id INTEGER PRIMARY KEY, text TEXT NOT NULL) STRICT;")
+      .await
+      .unwrap();
+    state.refresh_table_cache().await.unwrap();
+
+    add_record_api_config(
+      &state,
+      RecordApiConfig {
+        name: Some("notes".to_string()),
+        table_name: Some("note".to_string()),
+        acl_world: [PermissionFlag::Read as i32].into(),
+        acl_authenticated: [
+          PermissionFlag::Create as i32,
+          PermissionFlag::Read as i32,
+          PermissionFlag::Update as i32,
+          PermissionFlag::Delete as i32,
+        ]
+        .into(),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    let email = "user@test.org";
+    let password = "Secret!1!!";
+    create_user_for_test(&state, email, password).await.unwrap();
+    let tokens = login_with_password(&state, email, password).await.unwrap();
+    let user = User::from_auth_token(&state, &tokens.auth_token);
+
+    // ACLs apply: anonymous users may only read.
+    let anonymous = RecordsClient::new(&state);
+    assert!(matches!(
+      anonymous
+        .create("notes", row(json!({"text": "first"})))
+        .await,
+      Err(RecordError::Forbidden)
+    ));
+
+    let client = RecordsClient::new(&state).with_user(user);
+    let id = client
+      .create("notes", row(json!({"text": "first"})))
+      .await
+      .unwrap();
+    let ids = client
+      .create_bulk(
+        "notes",
+        vec![
+          row(json!({"text": "second"})),
+          row(json!({"text": "third"})),
+        ],
+      )
+      .await
+      .unwrap();
+    assert_eq!(ids.len(), 2);
+
+    // Validation applies.
+    assert!(
+      client
+        .create("notes", row(json!({"text": null})))
+        .await
+        .is_err()
+    );
+
+    client
+      .update("notes", &id, row(json!({"text": "updated"})))
+      .await
+      .unwrap();
+    let record = anonymous.read("notes", &id, None).await.unwrap();
+    assert_eq!(record["text"], "updated");
+
+    let list = anonymous
+      .list("notes", Some("order=id&limit=2"))
+      .await
+      .unwrap();
+    assert_eq!(list.records.len(), 2);
+
+    client.delete("notes", &id).await.unwrap();
+    assert!(matches!(
+      anonymous.read("notes", &id, None).await,
+      Err(RecordError::RecordNotFound)
+    ));
+
+    assert!(matches!(
+      anonymous.list("missing", None).await,
+      Err(RecordError::ApiNotFound)
+    ));
+  }
+}
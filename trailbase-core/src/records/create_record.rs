@@ -1,16 +1,22 @@
 use axum::extract::{Json, Path, Query, State};
 use axum::response::{IntoResponse, Redirect, Response};
 use base64::prelude::*;
+use log::*;
 use serde::{Deserialize, Serialize};
 use trailbase_schema::FileUploadInput;
 use utoipa::{IntoParams, ToSchema};
 
 use crate::app_state::AppState;
+use crate::audit::{AuditAction, audit_record_mutation, read_record_json, snapshot_record};
 use crate::auth::user::User;
+use crate::config::proto::WebhookEvent;
 use crate::extract::Either;
-use crate::records::params::{JsonRow, LazyParams, Params};
-use crate::records::query_builder::InsertQueryBuilder;
-use crate::records::{Permission, RecordError};
+use crate::queue::Job;
+use crate::records::hooks::{HookContext, HookEvent};
+use crate::records::params::{JsonRow, LazyParams, Params, ParamsError};
+use crate::records::query_builder::{InsertQueryBuilder, Upsert};
+use crate::records::webhooks::enqueue_webhooks;
+use crate::records::{Permission, RecordApi, RecordError};
 use crate::util::uuid_to_b64;
 
 #[derive(Clone, Debug, Default, Deserialize, IntoParams)]
@@ -20,6 +26,23 @@ pub struct CreateRecordQuery {
   ///
   /// We may want to have a different on-error redirect to better support the static HTML use-case.
   pub redirect_to: Option<String>,
+
+  /// Upsert records conflicting with existing ones on `conflict_target` rather than failing.
+  pub on_conflict: Option<OnConflict>,
+
+  /// Comma-separated columns of a uniqueness constraint to detect conflicts on. Defaults to the
+  /// primary key.
+  pub conflict_target: Option<String>,
+}
+
+/// How to handle records conflicting with existing ones, i.e. `ON CONFLICT DO UPDATE/NOTHING`.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OnConflict {
+  /// Update the existing record with the provided values.
+  Update,
+  /// Keep the existing record and skip the provided one.
+  Ignore,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
@@ -38,7 +61,7 @@ fn extract_record(value: serde_json::Value) -> Result<JsonRow, RecordError> {
   });
 }
 
-type RecordAndFiles = (JsonRow, Option<Vec<FileUploadInput>>);
+pub(crate) type RecordAndFiles = (JsonRow, Option<Vec<FileUploadInput>>);
 
 #[inline]
 fn extract_records(value: serde_json::Value) -> Result<Vec<RecordAndFiles>, RecordError> {
@@ -69,7 +92,9 @@ fn extract_records(value: serde_json::Value) -> Result<Vec<RecordAndFiles>, Reco
 }
 
 #[inline]
-fn extract_record_id(value: rusqlite::types::Value) -> Result<String, trailbase_sqlite::Error> {
+pub(crate) fn extract_record_id(
+  value: rusqlite::types::Value,
+) -> Result<String, trailbase_sqlite::Error> {
   return match value {
     rusqlite::types::Value::Blob(blob) => Ok(BASE64_URL_SAFE.encode(blob)),
     rusqlite::types::Value::Text(text) => Ok(text),
@@ -100,60 +125,164 @@ pub async fn create_record_handler(
   let Some(api) = state.lookup_record_api(&api_name) else {
     return Err(RecordError::ApiNotFound);
   };
-  if !api.is_table() {
-    return Err(RecordError::ApiRequiresTable);
-  }
 
   let records_and_files: Vec<RecordAndFiles> = match either_request {
     Either::Json(value) => extract_records(value)?,
     Either::Multipart(value, files) => vec![(extract_record(value)?, Some(files))],
     Either::Form(value) => vec![(extract_record(value)?, None)],
+    Either::MsgPack(value) | Either::Cbor(value) => extract_records(value)?,
+  };
+
+  let upsert = build_upsert(
+    &api,
+    create_record_query.on_conflict,
+    create_record_query.conflict_target.as_deref(),
+  )?;
+
+  let record_ids = create_records(
+    &state,
+    &api,
+    records_and_files,
+    upsert.as_ref(),
+    user.as_ref(),
+  )
+  .await?;
+
+  if let Some(redirect_to) = create_record_query.redirect_to {
+    return Ok(Redirect::to(&redirect_to).into_response());
+  }
+
+  return Ok(Json(CreateRecordResponse { ids: record_ids }).into_response());
+}
+
+fn build_upsert(
+  api: &RecordApi,
+  on_conflict: Option<OnConflict>,
+  conflict_target: Option<&str>,
+) -> Result<Option<Upsert>, RecordError> {
+  let Some(on_conflict) = on_conflict else {
+    if conflict_target.is_some() {
+      return Err(RecordError::BadRequest(
+        "conflict_target requires on_conflict",
+      ));
+    }
+    return Ok(None);
+  };
+
+  let target: Vec<String> = match conflict_target {
+    Some(target) => target.split(',').map(|c| c.trim().to_string()).collect(),
+    None => vec![api.record_pk_column().1.name.clone()],
   };
 
+  // The target must match a uniqueness constraint exactly, albeit in any order.
+  let is_unique_key = api.unique_keys().iter().any(|key| {
+    return key.len() == target.len() && key.iter().all(|col| target.contains(col));
+  });
+  if !is_unique_key
+    || target
+      .iter()
+      .any(|col| api.column_index_by_name(col).is_none())
+  {
+    return Err(RecordError::BadRequest("Invalid conflict_target"));
+  }
+
+  return Ok(Some(Upsert {
+    target,
+    update: on_conflict == OnConflict::Update,
+  }));
+}
+
+/// Fills in missing user id columns with the current user's id, if configured for the API.
+pub(crate) fn autofill_user_id_columns(api: &RecordApi, record: &mut JsonRow, user: Option<&User>) {
+  if !api.insert_autofill_missing_user_id_columns() {
+    return;
+  }
+  let Some(user) = user else {
+    return;
+  };
+
+  for column_index in api.user_id_columns() {
+    let col_name = &api.columns()[*column_index].name;
+    if !record.contains_key(col_name) {
+      record.insert(
+        col_name.to_owned(),
+        serde_json::Value::String(uuid_to_b64(&user.uuid)),
+      );
+    }
+  }
+}
+
+/// Creates records going through the same access checks, validation and side-effects as record
+/// API requests. Returns the new records' ids.
+///
+/// With an `upsert`, returns the ids of updated records in place and omits skipped ones.
+pub(crate) async fn create_records(
+  state: &AppState,
+  api: &RecordApi,
+  records_and_files: Vec<RecordAndFiles>,
+  upsert: Option<&Upsert>,
+  user: Option<&User>,
+) -> Result<Vec<String>, RecordError> {
+  if !api.is_table() {
+    return Err(RecordError::ApiRequiresTable);
+  }
+
+  if upsert.is_some_and(|u| u.update) {
+    // NOTE: Update access rules are evaluated against the existing record, which isn't known
+    // until the conflict occurs within the insert.
+    api.check_table_level_access(Permission::Update, user)?;
+    if api.has_update_access_rule() {
+      return Err(RecordError::BadRequest(
+        "on_conflict=update not supported with update access rule",
+      ));
+    }
+  }
+
+  let hooks = state.record_hooks();
+  let has_before_hooks = hooks.has(api.api_name(), HookEvent::BeforeCreate);
+
   let mut params_list: Vec<Params> = Vec::with_capacity(records_and_files.len());
   for (mut record, files) in records_and_files {
-    if api.insert_autofill_missing_user_id_columns() {
-      if let Some(ref user) = user {
-        for column_index in api.user_id_columns() {
-          let col_name = &api.columns()[*column_index].name;
-          if !record.contains_key(col_name) {
-            record.insert(
-              col_name.to_owned(),
-              serde_json::Value::String(uuid_to_b64(&user.uuid)),
-            );
-          }
-        }
-      }
-    }
+    autofill_user_id_columns(api, &mut record, user);
 
-    let mut lazy_params = LazyParams::new(&api, record, files);
+    let hook_record = has_before_hooks.then(|| record.clone());
+    let mut lazy_params = LazyParams::new(api, record, files);
 
     // NOTE: We're currently serializing the async checks, we could parallelize them however it's
     // unclear if this would be much faster.
     api
-      .check_record_level_access(
-        Permission::Create,
-        None,
-        Some(&mut lazy_params),
-        user.as_ref(),
-      )
+      .check_record_level_access(Permission::Create, None, Some(&mut lazy_params), user)
       .await?;
 
-    params_list.push(
-      lazy_params
-        .consume()
-        .map_err(|_| RecordError::BadRequest("Parameter conversion"))?,
-    );
+    let mut params = lazy_params.consume().map_err(|err| match err {
+      ParamsError::FieldValidation(errors) => RecordError::Validation(errors),
+      ParamsError::Geometry(_) => RecordError::BadRequest("Invalid geometry"),
+      ParamsError::GeneratedColumn(_) => RecordError::BadRequest("Cannot write generated column"),
+      ParamsError::FileConstraint(msg) => RecordError::BadRequest(msg),
+      _ => RecordError::BadRequest("Parameter conversion"),
+    })?;
+
+    if let Some(hook_record) = hook_record {
+      let context = HookContext::new(HookEvent::BeforeCreate, api.api_name(), None, user);
+      if let Some(changed) = hooks.run_before(context, Some(hook_record)).await? {
+        params.override_with(api, changed)?;
+      }
+    }
+    if upsert.is_some() && params.column_names.is_empty() {
+      return Err(RecordError::BadRequest("Upsert requires values"));
+    }
+    params_list.push(params);
   }
 
   let (_index, pk_column) = api.record_pk_column();
-  let record_ids: Vec<String> = match params_list.len() {
+  // Record ids paired with whether they were inserted rather than updated by the upsert.
+  let records: Vec<(String, bool)> = match params_list.len() {
     0 => {
       return Err(RecordError::BadRequest("no values provided"));
     }
-    1 => {
+    1 if upsert.is_none() => {
       let record_id = InsertQueryBuilder::run(
-        &state,
+        state,
         api.table_name(),
         api.insert_conflict_resolution_strategy(),
         &pk_column.name,
@@ -163,13 +292,14 @@ pub async fn create_record_handler(
       .await
       .map_err(|err| RecordError::Internal(err.into()))?;
 
-      vec![extract_record_id(record_id)?]
+      vec![(extract_record_id(record_id)?, true)]
     }
     _ => {
       let record_ids = InsertQueryBuilder::run_bulk(
-        &state,
+        state,
         api.table_name(),
         api.insert_conflict_resolution_strategy(),
+        upsert,
         &pk_column.name,
         api.has_file_columns(),
         params_list,
@@ -179,16 +309,64 @@ pub async fn create_record_handler(
 
       record_ids
         .into_iter()
-        .map(extract_record_id)
-        .collect::<Result<Vec<_>, _>>()?
+        .map(|(record_id, inserted)| Ok((extract_record_id(record_id)?, inserted)))
+        .collect::<Result<Vec<_>, RecordError>>()?
     }
   };
+  let record_ids: Vec<String> = records.iter().map(|(id, _)| id.clone()).collect();
 
-  if let Some(redirect_to) = create_record_query.redirect_to {
-    return Ok(Redirect::to(&redirect_to).into_response());
+  if api.embedding().is_some() {
+    let api_name = api.api_name();
+    for record_id in &record_ids {
+      let job = Job::Embed {
+        api_name: api_name.to_string(),
+        record_id: record_id.clone(),
+      };
+      if let Err(err) = state.queue().push(state, job).await {
+        warn!("Failed to enqueue embedding for '{api_name}': {err}");
+      }
+    }
   }
 
-  return Ok(Json(CreateRecordResponse { ids: record_ids }).into_response());
+  // Existing records updated by an upsert are reported as updates.
+  let (created, updated): (Vec<_>, Vec<_>) =
+    records.into_iter().partition(|(_, inserted)| *inserted);
+  for (ids, webhook_event, hook_event, action) in [
+    (
+      created,
+      WebhookEvent::Create,
+      HookEvent::AfterCreate,
+      AuditAction::Create,
+    ),
+    (
+      updated,
+      WebhookEvent::Update,
+      HookEvent::AfterUpdate,
+      AuditAction::Update,
+    ),
+  ] {
+    let ids: Vec<String> = ids.into_iter().map(|(id, _)| id).collect();
+    enqueue_webhooks(state, api, webhook_event, &ids).await;
+
+    if hooks.has(api.api_name(), hook_event) {
+      for record_id in &ids {
+        let record = read_record_json(state, api, record_id).await;
+        let context = HookContext::new(hook_event, api.api_name(), Some(record_id), user);
+        hooks
+          .run_after(context, record.and_then(|r| r.as_object().cloned()))
+          .await;
+      }
+    }
+
+    if user.is_some() {
+      for record_id in &ids {
+        let after = snapshot_record(state, api, record_id).await;
+        audit_record_mutation(state, user, api, action, record_id, None, after).await;
+      }
+    }
+  }
+
+  return Ok(record_ids);
 }
 
 #[cfg(test)]
@@ -197,7 +375,9 @@ mod test {
   use crate::admin::user::*;
   use crate::app_state::*;
   use crate::auth::api::login::login_with_password;
-  use crate::config::proto::{ConflictResolutionStrategy, PermissionFlag, RecordApiConfig};
+  use crate::config::proto::{
+    ColumnValidatorConfig, ConflictResolutionStrategy, PermissionFlag, RecordApiConfig,
+  };
   use crate::records::test_utils::*;
   use crate::records::*;
   use crate::test::unpack_json_response;
@@ -528,4 +708,680 @@ mod test {
       assert!(response.is_ok(), "{response:?}");
     }
   }
+
+  #[tokio::test]
+  async fn test_record_api_bulk_create() {
+    let state = test_state(None).await.unwrap();
+    state
+      .conn()
+      .execute_batch(
+        r#"
+          CREATE TABLE item (
+            id    INTEGER PRIMARY KEY,
+            name  TEXT NOT NULL UNIQUE,
+            meta  TEXT CHECK(jsonschema_matches('{"type": "object"}', meta))
+          ) STRICT;
+        "#,
+      )
+      .await
+      .unwrap();
+    state.refresh_table_cache().await.unwrap();
+
+    add_record_api_config(
+      &state,
+      RecordApiConfig {
+        name: Some("items".to_string()),
+        table_name: Some("item".to_string()),
+        acl_world: [PermissionFlag::Create as i32].into(),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    let create = async |records: serde_json::Value| -> Result<Vec<String>, RecordError> {
+      let response = create_record_handler(
+        State(state.clone()),
+        Path("items".to_string()),
+        Query(CreateRecordQuery::default()),
+        None,
+        Either::Json(records),
+      )
+      .await?;
+      let response: CreateRecordResponse = unpack_json_response(response).await.unwrap();
+      return Ok(response.ids);
+    };
+    let count = async || -> i64 {
+      return state
+        .conn()
+        .read_query_row_f("SELECT COUNT(*) FROM item", (), |row| row.get(0))
+        .await
+        .unwrap()
+        .unwrap();
+    };
+
+    // Ids are returned in order.
+    assert_eq!(
+      create(json!([
+        {"name": "a", "meta": {"x": 1}},
+        {"name": "b"},
+      ]))
+      .await
+      .unwrap(),
+      vec!["1", "2"]
+    );
+
+    // All records are inserted in a single transaction, i.e. a conflict rolls back the batch.
+    assert!(create(json!([{"name": "c"}, {"name": "a"}])).await.is_err());
+    assert_eq!(count().await, 2);
+
+    // Every record is validated before anything gets inserted.
+    assert!(
+      create(json!([{"name": "d"}, {"name": "e", "meta": 5}]))
+        .await
+        .is_err()
+    );
+    assert_eq!(count().await, 2);
+
+    assert!(create(json!([])).await.is_err());
+    assert!(create(json!([{"name": "f"}, 5])).await.is_err());
+  }
+
+  #[tokio::test]
+  async fn test_record_api_upsert() {
+    use futures_util::FutureExt;
+    use parking_lot::Mutex;
+    use std::sync::Arc;
+
+    use crate::records::hooks::{HookContext, HookEvent};
+
+    let state = test_state(None).await.unwrap();
+    state
+      .conn()
+      .execute_batch(
+        r#"
+          CREATE TABLE item (
+            id     INTEGER PRIMARY KEY,
+            name   TEXT NOT NULL UNIQUE,
+            shop   TEXT NOT NULL DEFAULT '',
+            sku    TEXT NOT NULL DEFAULT '',
+            price  INTEGER,
+            UNIQUE (shop, sku)
+          ) STRICT;
+        "#,
+      )
+      .await
+      .unwrap();
+    state.refresh_table_cache().await.unwrap();
+
+    add_record_api_config(
+      &state,
+      RecordApiConfig {
+        name: Some("items".to_string()),
+        table_name: Some("item".to_string()),
+        acl_world: [PermissionFlag::Create as i32, PermissionFlag::Update as i32].into(),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    add_record_api_config(
+      &state,
+      RecordApiConfig {
+        name: Some("create_only".to_string()),
+        table_name: Some("item".to_string()),
+        acl_world: [PermissionFlag::Create as i32].into(),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    let events: Arc<Mutex<Vec<(HookEvent, Option<String>)>>> = Arc::default();
+    for event in [HookEvent::AfterCreate, HookEvent::AfterUpdate] {
+      let events = events.clone();
+      state.record_hooks().register(
+        "items",
+        event,
+        Arc::new(move |context: HookContext, _record: Option<JsonRow>| {
+          events.lock().push((context.event, context.record_id));
+          return async { Ok::<Option<JsonRow>, RecordError>(None) }.boxed();
+        }),
+      );
+    }
+
+    let create = async |api_name: &str,
+                        on_conflict: OnConflict,
+                        conflict_target: Option<&str>,
+                        records: serde_json::Value|
+           -> Result<Vec<String>, RecordError> {
+      let response = create_record_handler(
+        State(state.clone()),
+        Path(api_name.to_string()),
+        Query(CreateRecordQuery {
+          on_conflict: Some(on_conflict),
+          conflict_target: conflict_target.map(|t| t.to_string()),
+          ..Default::default()
+        }),
+        None,
+        Either::Json(records),
+      )
+      .await?;
+      let response: CreateRecordResponse = unpack_json_response(response).await.unwrap();
+      return Ok(response.ids);
+    };
+    let price = async |name: &str| -> Option<i64> {
+      return state
+        .conn()
+        .read_query_row_f(
+          "SELECT price FROM item WHERE name = $1",
+          trailbase_sqlite::params!(name.to_string()),
+          |row| row.get(0),
+        )
+        .await
+        .unwrap()
+        .flatten();
+    };
+
+    // Upserting on the primary key, i.e. the default target.
+    assert_eq!(
+      create(
+        "items",
+        OnConflict::Update,
+        None,
+        json!([{"id": 1, "name": "a", "price": 1}])
+      )
+      .await
+      .unwrap(),
+      vec!["1"]
+    );
+    assert_eq!(
+      create(
+        "items",
+        OnConflict::Update,
+        None,
+        json!([{"id": 1, "name": "a", "price": 2}, {"id": 2, "name": "b", "price": 3}])
+      )
+      .await
+      .unwrap(),
+      vec!["1", "2"]
+    );
+    assert_eq!(price("a").await, Some(2));
+
+    // Existing records updated by the upsert are reported as updates.
+    assert_eq!(
+      std::mem::take(&mut *events.lock()),
+      vec![
+        (HookEvent::AfterCreate, Some("1".to_string())),
+        (HookEvent::AfterCreate, Some("2".to_string())),
+        (HookEvent::AfterUpdate, Some("1".to_string())),
+      ]
+    );
+
+    // Upserting on a UNIQUE column updates the existing record.
+    assert_eq!(
+      create(
+        "items",
+        OnConflict::Update,
+        Some("name"),
+        json!({"name": "b", "price": 4})
+      )
+      .await
+      .unwrap(),
+      vec!["2"]
+    );
+    assert_eq!(price("b").await, Some(4));
+
+    // Upserting on a table-level UNIQUE constraint with columns in any order.
+    create(
+      "items",
+      OnConflict::Update,
+      Some("sku, shop"),
+      json!({"name": "c", "shop": "x", "sku": "y", "price": 5}),
+    )
+    .await
+    .unwrap();
+    create(
+      "items",
+      OnConflict::Update,
+      Some("shop,sku"),
+      json!({"name": "d", "shop": "x", "sku": "y", "price": 6}),
+    )
+    .await
+    .unwrap();
+    assert_eq!(price("c").await, None);
+    assert_eq!(price("d").await, Some(6));
+
+    // Ignored records are skipped and omitted from the response.
+    assert_eq!(
+      create(
+        "items",
+        OnConflict::Ignore,
+        Some("name"),
+        json!([{"name": "a", "price": 7}, {"name": "e", "price": 8}])
+      )
+      .await
+      .unwrap(),
+      vec!["4"]
+    );
+    assert_eq!(price("a").await, Some(2));
+    assert_eq!(price("e").await, Some(8));
+
+    // Conflicts on other constraints still fail.
+    assert!(
+      create(
+        "items",
+        OnConflict::Update,
+        None,
+        json!({"id": 1, "name": "e"})
+      )
+      .await
+      .is_err()
+    );
+
+    // Targets must be uniqueness constraints.
+    for target in ["price", "shop", "name,price", "missing"] {
+      assert!(matches!(
+        create(
+          "items",
+          OnConflict::Ignore,
+          Some(target),
+          json!({"name": "f"})
+        )
+        .await,
+        Err(RecordError::BadRequest(_))
+      ));
+    }
+
+    // Updating on conflict requires update permissions.
+    assert!(matches!(
+      create(
+        "create_only",
+        OnConflict::Update,
+        Some("name"),
+        json!({"name": "a", "price": 9})
+      )
+      .await,
+      Err(RecordError::Forbidden)
+    ));
+    assert_eq!(
+      create(
+        "create_only",
+        OnConflict::Ignore,
+        Some("name"),
+        json!({"name": "a", "price": 9})
+      )
+      .await
+      .unwrap(),
+      Vec::<String>::new()
+    );
+    assert_eq!(price("a").await, Some(2));
+  }
+
+  #[tokio::test]
+  async fn test_record_api_create_column_validators() {
+    use std::sync::Arc;
+
+    let state = test_state(None).await.unwrap();
+
+    state
+      .conn()
+      .execute(
+        r#"
+      CREATE TABLE cards (
+        id      INTEGER PRIMARY KEY,
+        number  TEXT,
+        code    TEXT
+      ) STRICT;
+      "#,
+        (),
+      )
+      .await
+      .unwrap();
+
+    state.schema_metadata().invalidate_all().await.unwrap();
+
+    add_record_api_config(
+      &state,
+      RecordApiConfig {
+        name: Some("cards_api".to_string()),
+        table_name: Some("cards".to_string()),
+        acl_world: [PermissionFlag::Create as i32].into(),
+        column_validators: vec![
+          ColumnValidatorConfig {
+            column_name: Some("number".to_string()),
+            validator: Some("luhn".to_string()),
+            argument: None,
+          },
+          ColumnValidatorConfig {
+            column_name: Some("code".to_string()),
+            validator: Some("regex".to_string()),
+            argument: Some("^[A-Z]{3}$".to_string()),
+          },
+        ],
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    let create = |value: serde_json::Value| {
+      create_record_handler(
+        State(state.clone()),
+        Path("cards_api".to_string()),
+        Query(CreateRecordQuery::default()),
+        None,
+        Either::Json(json_row_from_value(value).unwrap().into()),
+      )
+    };
+
+    assert!(
+      create(json!({"number": "4111 1111 1111 1111", "code": "ABC"}))
+        .await
+        .is_ok()
+    );
+
+    let Err(RecordError::Validation(mut errors)) =
+      create(json!({"number": "4111 1111 1111 1112", "code": "abc"})).await
+    else {
+      panic!("expected validation error");
+    };
+    errors.sort_by(|a, b| a.field.cmp(&b.field));
+    assert_eq!(
+      vec!["code", "number"],
+      errors.iter().map(|e| e.field.as_str()).collect::<Vec<_>>()
+    );
+
+    // Invalid validator configurations are rejected.
+    assert!(
+      add_record_api_config(
+        &state,
+        RecordApiConfig {
+          name: Some("cards_api2".to_string()),
+          table_name: Some("cards".to_string()),
+          column_validators: vec![ColumnValidatorConfig {
+            column_name: Some("code".to_string()),
+            validator: Some("regex".to_string()),
+            argument: None,
+          }],
+          ..Default::default()
+        },
+      )
+      .await
+      .is_err()
+    );
+
+    // Script validators are scoped to the state they're registered with.
+    add_record_api_config(
+      &state,
+      RecordApiConfig {
+        name: Some("cards_script_api".to_string()),
+        table_name: Some("cards".to_string()),
+        acl_world: [PermissionFlag::Create as i32].into(),
+        column_validators: vec![ColumnValidatorConfig {
+          column_name: Some("code".to_string()),
+          validator: Some("script".to_string()),
+          argument: Some("not_xyz".to_string()),
+        }],
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    let create_script = |value: serde_json::Value| {
+      create_record_handler(
+        State(state.clone()),
+        Path("cards_script_api".to_string()),
+        Query(CreateRecordQuery::default()),
+        None,
+        Either::Json(json_row_from_value(value).unwrap().into()),
+      )
+    };
+
+    assert!(create_script(json!({"code": "ABC"})).await.is_err());
+
+    assert!(state.script_validators().register(
+      "not_xyz",
+      Arc::new(|value: &serde_json::Value| {
+        return match value.as_str() {
+          Some("XYZ") => Err("must not be XYZ".to_string()),
+          _ => Ok(()),
+        };
+      }),
+    ));
+    let other_state = test_state(None).await.unwrap();
+    assert!(
+      other_state
+        .script_validators()
+        .register("not_xyz", Arc::new(|_value: &serde_json::Value| Ok(())))
+    );
+
+    assert!(create_script(json!({"code": "ABC"})).await.is_ok());
+    assert!(matches!(
+      create_script(json!({"code": "XYZ"})).await,
+      Err(RecordError::Validation(_))
+    ));
+  }
+
+  #[tokio::test]
+  async fn test_record_api_create_generated_columns() {
+    let state = test_state(None).await.unwrap();
+
+    state
+      .conn()
+      .execute(
+        r#"
+      CREATE TABLE items (
+        id      INTEGER PRIMARY KEY,
+        price   INTEGER NOT NULL,
+        total   INTEGER GENERATED ALWAYS AS (price * 2) STORED
+      ) STRICT;
+      "#,
+        (),
+      )
+      .await
+      .unwrap();
+
+    state.schema_metadata().invalidate_all().await.unwrap();
+
+    let metadata = state.schema_metadata().get_table("items").unwrap();
+    assert_eq!(metadata.generated_columns, vec![2]);
+
+    add_record_api_config(
+      &state,
+      RecordApiConfig {
+        name: Some("items_api".to_string()),
+        table_name: Some("items".to_string()),
+        acl_world: [PermissionFlag::Create as i32, PermissionFlag::Update as i32].into(),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    let create = |value: serde_json::Value| {
+      create_record_handler(
+        State(state.clone()),
+        Path("items_api".to_string()),
+        Query(CreateRecordQuery::default()),
+        None,
+        Either::Json(json_row_from_value(value).unwrap().into()),
+      )
+    };
+
+    assert!(create(json!({"id": 1, "price": 5})).await.is_ok());
+    let total: Option<i64> = state
+      .conn()
+      .read_query_row_f("SELECT total FROM items WHERE id = 1", (), |row| row.get(0))
+      .await
+      .unwrap();
+    assert_eq!(total, Some(10));
+
+    assert!(matches!(
+      create(json!({"id": 2, "price": 5, "total": 3})).await,
+      Err(RecordError::BadRequest(_))
+    ));
+
+    let update = crate::records::update_record::update_record_handler(
+      State(state.clone()),
+      Path(("items_api".to_string(), "1".to_string())),
+      None,
+      axum::http::HeaderMap::new(),
+      Either::Json(json_row_from_value(json!({"total": 3})).unwrap()),
+    )
+    .await;
+    assert!(matches!(update, Err(RecordError::BadRequest(_))));
+  }
+
+  #[tokio::test]
+  async fn test_record_api_hooks() {
+    use futures_util::FutureExt;
+    use parking_lot::Mutex;
+    use std::sync::Arc;
+
+    use crate::records::delete_record::delete_record;
+    use crate::records::hooks::{HookContext, HookEvent};
+    use crate::records::update_record::update_record;
+
+    let state = test_state(None).await.unwrap();
+
+    state
+      .conn()
+      .execute(
+        r#"
+      CREATE TABLE posts (
+        id      INTEGER PRIMARY KEY,
+        title   TEXT NOT NULL,
+        slug    TEXT
+      ) STRICT;
+      "#,
+        (),
+      )
+      .await
+      .unwrap();
+
+    state.schema_metadata().invalidate_all().await.unwrap();
+
+    add_record_api_config(
+      &state,
+      RecordApiConfig {
+        name: Some("posts_api".to_string()),
+        table_name: Some("posts".to_string()),
+        acl_world: [
+          PermissionFlag::Create as i32,
+          PermissionFlag::Update as i32,
+          PermissionFlag::Delete as i32,
+        ]
+        .into(),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    // Derive the slug from the title and reject some titles.
+    let derive_slug = Arc::new(|_context: HookContext, record: Option<JsonRow>| {
+      return async move {
+        let Some(mut record) = record else {
+          return Ok(None);
+        };
+        let Some(title) = record
+          .get("title")
+          .and_then(|t| t.as_str())
+          .map(str::to_string)
+        else {
+          return Ok(None);
+        };
+        if title == "forbidden" {
+          return Err(RecordError::Rejected("forbidden title".to_string()));
+        }
+        record.insert("slug".to_string(), json!(title.to_lowercase()));
+        return Ok(Some(record));
+      }
+      .boxed();
+    });
+    let hooks = state.record_hooks();
+    hooks.register("posts_api", HookEvent::BeforeCreate, derive_slug.clone());
+    hooks.register("posts_api", HookEvent::BeforeUpdate, derive_slug);
+
+    let events: Arc<Mutex<Vec<(HookEvent, Option<String>, Option<JsonRow>)>>> = Arc::default();
+    for event in [HookEvent::AfterCreate, HookEvent::AfterDelete] {
+      let events = events.clone();
+      hooks.register(
+        "posts_api",
+        event,
+        Arc::new(move |context: HookContext, record: Option<JsonRow>| {
+          events
+            .lock()
+            .push((context.event, context.record_id, record));
+          return async { Ok::<Option<JsonRow>, RecordError>(None) }.boxed();
+        }),
+      );
+    }
+
+    let create = |value: serde_json::Value| {
+      create_record_handler(
+        State(state.clone()),
+        Path("posts_api".to_string()),
+        Query(CreateRecordQuery::default()),
+        None,
+        Either::Json(json_row_from_value(value).unwrap().into()),
+      )
+    };
+
+    let response: CreateRecordResponse =
+      unpack_json_response(create(json!({"title": "Hello"})).await.unwrap())
+        .await
+        .unwrap();
+    let record_id = response.ids[0].clone();
+
+    let slug = || async {
+      return state
+        .conn()
+        .read_query_row_f("SELECT slug FROM posts", (), |row| row.get::<_, String>(0))
+        .await
+        .unwrap()
+        .unwrap();
+    };
+    assert_eq!(slug().await, "hello");
+
+    let Err(RecordError::Rejected(msg)) = create(json!({"title": "forbidden"})).await else {
+      panic!("expected rejection");
+    };
+    assert_eq!(msg, "forbidden title");
+
+    let api = state.lookup_record_api("posts_api").unwrap();
+    update_record(
+      &state,
+      &api,
+      record_id.clone(),
+      json_row_from_value(json!({"title": "World"})).unwrap(),
+      None,
+      None,
+      None,
+    )
+    .await
+    .unwrap();
+    assert_eq!(slug().await, "world");
+
+    delete_record(&state, &api, &record_id, None, None)
+      .await
+      .unwrap();
+
+    let events = events.lock();
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0].0, HookEvent::AfterCreate);
+    assert_eq!(events[0].1.as_deref(), Some(record_id.as_str()));
+    assert_eq!(
+      events[0].2.as_ref().and_then(|r| r.get("slug")),
+      Some(&json!("hello"))
+    );
+    assert_eq!(events[1].0, HookEvent::AfterDelete);
+    assert_eq!(
+      events[1].2.as_ref().and_then(|r| r.get("title")),
+      Some(&json!("World"))
+    );
+  }
 }
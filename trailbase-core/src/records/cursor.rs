@@ -0,0 +1,261 @@
+use base64::prelude::*;
+use hmac::{Hmac, Mac};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use trailbase_sqlite::Value;
+
+use crate::app_state::AppState;
+use crate::listing::Order;
+use crate::records::{RecordApi, RecordError};
+
+/// Version of the cursor encoding. Bumping it invalidates all outstanding cursors.
+const CURSOR_VERSION: u8 = 1;
+
+const SIGNATURE_LEN: usize = 16;
+const SECRET_PURPOSE: &str = "trailbase record api cursor";
+
+/// Keyset value a page continues from, i.e. the value of a sort key of the last record returned.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) enum KeysetValue {
+  #[serde(rename = "i")]
+  Integer(i64),
+  #[serde(rename = "b")]
+  Blob(Vec<u8>),
+  #[serde(rename = "r")]
+  Real(f64),
+  #[serde(rename = "t")]
+  Text(String),
+  #[serde(rename = "n")]
+  Null,
+}
+
+impl From<&Value> for KeysetValue {
+  fn from(value: &Value) -> Self {
+    return match value {
+      Value::Integer(i) => Self::Integer(*i),
+      Value::Blob(b) => Self::Blob(b.clone()),
+      Value::Real(r) => Self::Real(*r),
+      Value::Text(t) => Self::Text(t.clone()),
+      Value::Null => Self::Null,
+    };
+  }
+}
+
+impl From<KeysetValue> for Value {
+  fn from(value: KeysetValue) -> Self {
+    return match value {
+      KeysetValue::Integer(i) => Value::Integer(i),
+      KeysetValue::Blob(b) => Value::Blob(b),
+      KeysetValue::Real(r) => Value::Real(r),
+      KeysetValue::Text(t) => Value::Text(t),
+      KeysetValue::Null => Value::Null,
+    };
+  }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CursorPayload {
+  #[serde(rename = "v")]
+  version: u8,
+  /// Normalized sort spec, e.g. "-id".
+  #[serde(rename = "o")]
+  order: String,
+  /// Fingerprint of the API and its schema.
+  #[serde(rename = "s")]
+  schema: String,
+  /// Values of all sort keys, i.e. the order columns followed by the primary key.
+  #[serde(rename = "k")]
+  keyset: Vec<KeysetValue>,
+}
+
+/// Normalized sort spec of a list query, e.g. "+price,-id". Defaults to the primary key, i.e. all
+/// of its columns, in descending order.
+pub(crate) fn sort_spec(api: &RecordApi, order: Option<&[(String, Order)]>) -> String {
+  return match order {
+    Some(order) if !order.is_empty() => order
+      .iter()
+      .map(|(col, order)| match order {
+        Order::Ascending => format!("+{col}"),
+        Order::Descending => format!("-{col}"),
+      })
+      .join(","),
+    _ => api
+      .record_pk_columns()
+      .iter()
+      .map(|(_index, pk_column)| format!("-{}", pk_column.name))
+      .join(","),
+  };
+}
+
+/// Encodes an opaque, signed cursor for the given query's next page.
+///
+/// Cursors are only valid for the API, schema and sort order they were issued for, which keeps
+/// clients from forging them or misapplying them across queries.
+pub(crate) fn encode_cursor(
+  state: &AppState,
+  api: &RecordApi,
+  order: &str,
+  keyset: Vec<KeysetValue>,
+) -> Result<String, RecordError> {
+  let payload = serde_json::to_vec(&CursorPayload {
+    version: CURSOR_VERSION,
+    order: order.to_string(),
+    schema: schema_fingerprint(api),
+    keyset,
+  })
+  .map_err(|err| RecordError::Internal(err.into()))?;
+
+  let mut token = sign(state, &payload).to_vec();
+  token.extend_from_slice(&payload);
+  return Ok(BASE64_URL_SAFE_NO_PAD.encode(token));
+}
+
+/// Decodes and verifies a cursor previously issued by [`encode_cursor`] for the given query.
+pub(crate) fn decode_cursor(
+  state: &AppState,
+  api: &RecordApi,
+  order: &str,
+  cursor: &str,
+) -> Result<Vec<KeysetValue>, RecordError> {
+  const INVALID: RecordError = RecordError::BadRequest("Invalid cursor");
+
+  let token = BASE64_URL_SAFE_NO_PAD.decode(cursor).map_err(|_| INVALID)?;
+  if token.len() <= SIGNATURE_LEN {
+    return Err(INVALID);
+  }
+  let (signature, payload) = token.split_at(SIGNATURE_LEN);
+
+  let mut mac = hmac(state);
+  mac.update(payload);
+  mac.verify_truncated_left(signature).map_err(|_| INVALID)?;
+
+  // Check the version first, since the payload format may change between versions.
+  let value: serde_json::Value = serde_json::from_slice(payload).map_err(|_| INVALID)?;
+  if value.get("v").and_then(|v| v.as_u64()) != Some(CURSOR_VERSION as u64) {
+    return Err(RecordError::BadRequest("Unsupported cursor version"));
+  }
+  let payload: CursorPayload = serde_json::from_value(value).map_err(|_| INVALID)?;
+
+  if payload.schema != schema_fingerprint(api) {
+    return Err(RecordError::BadRequest(
+      "Cursor is stale: the API or its schema changed",
+    ));
+  }
+  if payload.order != order {
+    return Err(RecordError::BadRequest(
+      "Cursor was issued for a different sort order",
+    ));
+  }
+
+  return Ok(payload.keyset);
+}
+
+fn hmac(state: &AppState) -> Hmac<Sha256> {
+  return Hmac::<Sha256>::new_from_slice(&state.jwt().derive_secret(SECRET_PURPOSE))
+    .expect("HMAC accepts keys of any size");
+}
+
+fn sign(state: &AppState, payload: &[u8]) -> [u8; SIGNATURE_LEN] {
+  let mut mac = hmac(state);
+  mac.update(payload);
+  let mut signature = [0u8; SIGNATURE_LEN];
+  signature.copy_from_slice(&mac.finalize().into_bytes()[..SIGNATURE_LEN]);
+  return signature;
+}
+
+fn schema_fingerprint(api: &RecordApi) -> String {
+  let mut hasher = Sha256::new()
+    .chain_update(api.api_name().as_bytes())
+    .chain_update([0u8])
+    .chain_update(api.table_name().as_bytes());
+  for column in api.columns() {
+    hasher.update([0u8]);
+    hasher.update(column.name.as_bytes());
+    hasher.update([0u8]);
+    hasher.update(format!("{:?}", column.data_type).as_bytes());
+  }
+  return BASE64_URL_SAFE_NO_PAD.encode(&hasher.finalize()[..8]);
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::app_state::test_state;
+  use crate::config::proto::{PermissionFlag, RecordApiConfig};
+  use crate::records::test_utils::add_record_api_config;
+
+  #[tokio::test]
+  async fn test_cursor_roundtrip() {
+    let state = test_state(None).await.unwrap();
+    state
+      .conn()
+      .execute_batch("CREATE TABLE item (id INTEGER PRIMARY KEY, name TEXT) STRICT;")
+      .await
+      .unwrap();
+    state.refresh_table_cache().await.unwrap();
+
+    add_record_api_config(
+      &state,
+      RecordApiConfig {
+        name: Some("items".to_string()),
+        table_name: Some("item".to_string()),
+        acl_world: [PermissionFlag::Read as i32].into(),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+    let api = state.lookup_record_api("items").unwrap();
+
+    let order = sort_spec(&api, None);
+    assert_eq!(order, "-id");
+
+    let keyset = vec![KeysetValue::Integer(5)];
+    let cursor = encode_cursor(&state, &api, &order, keyset.clone()).unwrap();
+    assert_eq!(
+      decode_cursor(&state, &api, &order, &cursor).unwrap(),
+      keyset
+    );
+
+    // Raw primary keys aren't accepted.
+    assert!(decode_cursor(&state, &api, &order, "5").is_err());
+
+    // Tampering invalidates the signature.
+    let mut token = BASE64_URL_SAFE_NO_PAD.decode(&cursor).unwrap();
+    *token.last_mut().unwrap() ^= 1;
+    assert!(matches!(
+      decode_cursor(&state, &api, &order, &BASE64_URL_SAFE_NO_PAD.encode(token)),
+      Err(RecordError::BadRequest("Invalid cursor"))
+    ));
+
+    // Cursors are bound to the sort order.
+    let asc = sort_spec(&api, Some(&[("id".to_string(), Order::Ascending)]));
+    assert_eq!(asc, "+id");
+    assert!(matches!(
+      decode_cursor(&state, &api, &asc, &cursor),
+      Err(RecordError::BadRequest(
+        "Cursor was issued for a different sort order"
+      ))
+    ));
+
+    // ...and to the schema.
+    state
+      .conn()
+      .execute_batch("ALTER TABLE item ADD COLUMN price INTEGER;")
+      .await
+      .unwrap();
+    state.refresh_table_cache().await.unwrap();
+    state
+      .validate_and_update_config(state.get_config(), None)
+      .await
+      .unwrap();
+    let api = state.lookup_record_api("items").unwrap();
+    assert!(matches!(
+      decode_cursor(&state, &api, &order, &cursor),
+      Err(RecordError::BadRequest(
+        "Cursor is stale: the API or its schema changed"
+      ))
+    ));
+  }
+}
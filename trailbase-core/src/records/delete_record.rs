@@ -1,53 +1,184 @@
 use axum::{
   extract::{Path, State},
-  http::StatusCode,
+  http::{HeaderMap, StatusCode, header::IF_MATCH},
   response::{IntoResponse, Response},
 };
+use trailbase_sqlite::named_params;
 
 use crate::app_state::AppState;
+use crate::audit::{AuditAction, audit_record_mutation, read_record_json, snapshot_record};
 use crate::auth::user::User;
-use crate::records::query_builder::DeleteQueryBuilder;
-use crate::records::{Permission, RecordError};
+use crate::config::proto::WebhookEvent;
+use crate::records::etag::IfMatch;
+use crate::records::hooks::{HookContext, HookEvent};
+use crate::records::query_builder::{DeleteQueryBuilder, QueryError};
+use crate::records::webhooks::enqueue_webhooks;
+use crate::records::{Permission, RecordApi, RecordError};
 
 /// Delete record.
+///
+/// Honors `If-Match`, i.e. only deletes the record if it still matches the given ETag.
 #[utoipa::path(
   delete,
   path = "/:name/:record",
   responses(
-    (status = 200, description = "Successful deletion.")
+    (status = 200, description = "Successful deletion."),
+    (status = 412, description = "Record changed, i.e. If-Match did not match.")
   )
 )]
 pub async fn delete_record_handler(
   State(state): State<AppState>,
   Path((api_name, record)): Path<(String, String)>,
   user: Option<User>,
+  headers: HeaderMap,
 ) -> Result<Response, RecordError> {
   let Some(api) = state.lookup_record_api(&api_name) else {
     return Err(RecordError::ApiNotFound);
   };
+
+  let if_match = match headers.get(IF_MATCH) {
+    Some(value) => Some(
+      value
+        .to_str()
+        .map_err(|_err| RecordError::BadRequest("Invalid If-Match"))?
+        .to_string(),
+    ),
+    None => None,
+  };
+
+  delete_record(&state, &api, &record, if_match, user.as_ref()).await?;
+
+  return Ok((StatusCode::OK, "deleted").into_response());
+}
+
+/// Deletes a record going through the same access checks as record API requests.
+///
+/// For APIs with soft-deletion, the record is only marked as deleted.
+pub(crate) async fn delete_record(
+  state: &AppState,
+  api: &RecordApi,
+  record: &str,
+  if_match: Option<String>,
+  user: Option<&User>,
+) -> Result<(), RecordError> {
   if !api.is_table() {
     return Err(RecordError::ApiRequiresTable);
   }
 
-  let record_id = api.id_to_sql(&record)?;
+  let record_id = api.id_to_sql(record)?;
 
   api
-    .check_record_level_access(Permission::Delete, Some(&record_id), None, user.as_ref())
+    .check_record_level_access(Permission::Delete, Some(&record_id), None, user)
     .await?;
 
+  let hooks = state.record_hooks();
+  if hooks.has(api.api_name(), HookEvent::BeforeDelete) {
+    let context = HookContext::new(HookEvent::BeforeDelete, api.api_name(), Some(record), user);
+    hooks.run_before(context, None).await?;
+  }
+
+  let before = match user {
+    Some(_) => snapshot_record(state, api, record).await,
+    None => None,
+  };
+  let deleted = if hooks.has(api.api_name(), HookEvent::AfterDelete) {
+    read_record_json(state, api, record).await
+  } else {
+    None
+  };
+
   let (_index, pk_column) = api.record_pk_column();
 
   DeleteQueryBuilder::run(
-    &state,
+    state,
     api.table_name(),
     &pk_column.name,
-    record_id,
+    record_id.clone(),
     api.has_file_columns(),
+    api
+      .soft_delete_column()
+      .map(|index| api.columns()[index].name.as_str()),
+    if_match.map(|header| IfMatch::new(api, record_id, header)),
   )
   .await
-  .map_err(|err| RecordError::Internal(err.into()))?;
+  .map_err(|err| match err {
+    QueryError::NotFound => RecordError::RecordNotFound,
+    QueryError::PreconditionFailed => RecordError::PreconditionFailed,
+    err => RecordError::Internal(err.into()),
+  })?;
 
-  return Ok((StatusCode::OK, "deleted").into_response());
+  enqueue_webhooks(state, api, WebhookEvent::Delete, &[record.to_string()]).await;
+
+  if hooks.has(api.api_name(), HookEvent::AfterDelete) {
+    let context = HookContext::new(HookEvent::AfterDelete, api.api_name(), Some(record), user);
+    hooks
+      .run_after(context, deleted.and_then(|r| r.as_object().cloned()))
+      .await;
+  }
+
+  audit_record_mutation(state, user, api, AuditAction::Delete, record, before, None).await;
+
+  return Ok(());
+}
+
+/// Restore soft-deleted record.
+#[utoipa::path(
+  post,
+  path = "/:name/:record/undelete",
+  responses(
+    (status = 200, description = "Successful restoration.")
+  )
+)]
+pub async fn undelete_record_handler(
+  State(state): State<AppState>,
+  Path((api_name, record)): Path<(String, String)>,
+  user: Option<User>,
+) -> Result<Response, RecordError> {
+  let Some(api) = state.lookup_record_api(&api_name) else {
+    return Err(RecordError::ApiNotFound);
+  };
+
+  undelete_record(&state, &api, &record, user.as_ref()).await?;
+
+  return Ok((StatusCode::OK, "restored").into_response());
+}
+
+/// Restores a soft-deleted record, which requires the same access as deleting it.
+pub(crate) async fn undelete_record(
+  state: &AppState,
+  api: &RecordApi,
+  record: &str,
+  user: Option<&User>,
+) -> Result<(), RecordError> {
+  let Some(index) = api.soft_delete_column() else {
+    return Err(RecordError::BadRequest("Soft-deletion not enabled"));
+  };
+
+  let record_id = api.id_to_sql(record)?;
+
+  api
+    .check_record_level_access(Permission::Delete, Some(&record_id), None, user)
+    .await?;
+
+  let (_index, pk_column) = api.record_pk_column();
+  let rowid: Option<i64> = state
+    .conn()
+    .query_row_f(
+      DeleteQueryBuilder::undelete_sql(
+        api.table_name(),
+        &pk_column.name,
+        &api.columns()[index].name,
+      ),
+      named_params! {":__record_id": record_id},
+      |row| row.get(0),
+    )
+    .await?;
+
+  if rowid.is_none() {
+    return Err(RecordError::RecordNotFound);
+  }
+
+  return Ok(());
 }
 
 #[cfg(test)]
@@ -60,11 +191,12 @@ mod test {
   use crate::app_state::*;
   use crate::auth::api::login::login_with_password;
   use crate::auth::user::User;
-  use crate::config::proto::PermissionFlag;
+  use crate::config::proto::{PermissionFlag, RecordApiConfig};
   use crate::extract::Either;
   use crate::records::create_record::{
     CreateRecordQuery, CreateRecordResponse, create_record_handler,
   };
+  use crate::records::read_record::{ReadRecordQuery, read_record_handler};
   use crate::records::test_utils::*;
   use crate::records::*;
   use crate::test::unpack_json_response;
@@ -202,8 +334,137 @@ mod test {
       State(state.clone()),
       Path(("messages_api".to_string(), id_to_b64(&id))),
       User::from_auth_token(state, auth_token),
+      HeaderMap::new(),
     )
     .await?;
     return Ok(());
   }
+
+  #[tokio::test]
+  async fn test_record_api_soft_delete() {
+    let state = test_state(None).await.unwrap();
+    state
+      .conn()
+      .execute_batch(
+        "CREATE TABLE article (id INTEGER PRIMARY KEY, title TEXT NOT NULL, deleted_at INTEGER) STRICT;
+         INSERT INTO article (title) VALUES ('first'), ('second');",
+      )
+      .await
+      .unwrap();
+    state.refresh_table_cache().await.unwrap();
+
+    let soft_delete_config = |name: &str, acl: Vec<PermissionFlag>, column: &str| {
+      return RecordApiConfig {
+        name: Some(name.to_string()),
+        table_name: Some("article".to_string()),
+        acl_world: acl.into_iter().map(|f| f as i32).collect(),
+        soft_delete_column: Some(column.to_string()),
+        ..Default::default()
+      };
+    };
+
+    // Soft-delete columns must exist and be nullable.
+    for column in ["missing", "title"] {
+      assert!(
+        add_record_api_config(
+          &state,
+          soft_delete_config("invalid", vec![PermissionFlag::Read], column)
+        )
+        .await
+        .is_err()
+      );
+    }
+
+    add_record_api_config(
+      &state,
+      soft_delete_config(
+        "articles",
+        vec![PermissionFlag::Read, PermissionFlag::Delete],
+        "deleted_at",
+      ),
+    )
+    .await
+    .unwrap();
+    add_record_api_config(
+      &state,
+      soft_delete_config("articles_ro", vec![PermissionFlag::Read], "deleted_at"),
+    )
+    .await
+    .unwrap();
+
+    let client = RecordsClient::new(&state);
+    let count = async |api_name: &str, query: Option<&str>| {
+      return client
+        .list(api_name, query)
+        .await
+        .map(|list| list.records.len());
+    };
+
+    client.delete("articles", "1").await.unwrap();
+
+    // The record is retained but hidden.
+    let deleted_at: Option<i64> = state
+      .conn()
+      .read_query_value("SELECT deleted_at FROM article WHERE id = 1", ())
+      .await
+      .unwrap()
+      .unwrap();
+    assert!(deleted_at.is_some());
+
+    assert!(matches!(
+      client.read("articles", "1", None).await,
+      Err(RecordError::RecordNotFound)
+    ));
+    assert!(matches!(
+      client.delete("articles", "1").await,
+      Err(RecordError::RecordNotFound)
+    ));
+    assert_eq!(count("articles", None).await.unwrap(), 1);
+    assert_eq!(
+      count("articles", Some("include_deleted=false"))
+        .await
+        .unwrap(),
+      1
+    );
+
+    // Only users with delete access can see soft-deleted records.
+    assert_eq!(
+      count("articles", Some("include_deleted=true"))
+        .await
+        .unwrap(),
+      2
+    );
+    assert!(matches!(
+      count("articles_ro", Some("include_deleted=true")).await,
+      Err(RecordError::Forbidden)
+    ));
+
+    let response = read_record_handler(
+      State(state.clone()),
+      Path(("articles".to_string(), "1".to_string())),
+      Query(ReadRecordQuery {
+        include_deleted: Some(true),
+        ..Default::default()
+      }),
+      None,
+    )
+    .await;
+    assert!(response.is_ok(), "{response:?}");
+
+    // Restoring requires delete access as well.
+    assert!(matches!(
+      client.undelete("articles_ro", "1").await,
+      Err(RecordError::Forbidden)
+    ));
+    client.undelete("articles", "1").await.unwrap();
+    assert_eq!(
+      client.read("articles", "1", None).await.unwrap()["title"],
+      "first"
+    );
+    assert!(matches!(
+      client.undelete("articles", "1").await,
+      Err(RecordError::RecordNotFound)
+    ));
+    assert_eq!(count("articles", None).await.unwrap(), 2);
+  }
 }
@@ -0,0 +1,296 @@
+use log::*;
+use serde::Deserialize;
+use thiserror::Error;
+use trailbase_schema::metadata::vector_column_dimensions;
+
+use crate::app_state::AppState;
+use crate::config::proto::{EmbeddingProviderConfig, EmbeddingProviderType};
+
+#[derive(Debug, Error)]
+pub enum EmbeddingError {
+  #[error("Not found: {0}")]
+  NotFound(String),
+  #[error("Config error: {0}")]
+  Config(String),
+  #[error("HTTP error: {0}")]
+  Http(#[from] reqwest::Error),
+  #[error("Invalid response: {0}")]
+  InvalidResponse(String),
+  #[error("SQLite error: {0}")]
+  Sqlite(#[from] trailbase_sqlite::Error),
+}
+
+const OPENAI_DEFAULT_URL: &str = "https://api.openai.com/v1/embeddings";
+const OLLAMA_DEFAULT_URL: &str = "http://localhost:11434/api/embed";
+
+#[derive(Deserialize)]
+struct OpenAiEmbedding {
+  embedding: Vec<f32>,
+}
+
+#[derive(Deserialize)]
+struct OpenAiResponse {
+  data: Vec<OpenAiEmbedding>,
+}
+
+#[derive(Deserialize)]
+struct OllamaResponse {
+  embeddings: Vec<Vec<f32>>,
+}
+
+/// Calls the given provider to embed `input`.
+async fn embed(
+  provider: &EmbeddingProviderConfig,
+  input: &str,
+) -> Result<Vec<f32>, EmbeddingError> {
+  let provider_type = provider
+    .provider_type
+    .and_then(|t| EmbeddingProviderType::try_from(t).ok())
+    .unwrap_or(EmbeddingProviderType::Undefined);
+
+  let url = match (provider_type, &provider.url) {
+    (EmbeddingProviderType::Undefined, _) => {
+      return Err(EmbeddingError::Config("Missing provider type".to_string()));
+    }
+    (_, Some(url)) => url.as_str(),
+    (EmbeddingProviderType::Openai, None) => OPENAI_DEFAULT_URL,
+    (EmbeddingProviderType::Ollama, None) => OLLAMA_DEFAULT_URL,
+  };
+
+  // Both, OpenAI and Ollama, accept the same request shape.
+  let mut request = reqwest::Client::new().post(url).json(&serde_json::json!({
+    "model": provider.model,
+    "input": input,
+  }));
+  if let Some(ref api_key) = provider.api_key {
+    request = request.bearer_auth(api_key);
+  }
+
+  let response = request.send().await?.error_for_status()?;
+
+  let embedding = match provider_type {
+    EmbeddingProviderType::Openai => response
+      .json::<OpenAiResponse>()
+      .await?
+      .data
+      .into_iter()
+      .next()
+      .map(|e| e.embedding),
+    _ => response
+      .json::<OllamaResponse>()
+      .await?
+      .embeddings
+      .into_iter()
+      .next(),
+  };
+
+  return embedding.ok_or_else(|| EmbeddingError::InvalidResponse("No embedding".to_string()));
+}
+
+/// (Re-)computes the embedding of the given record from its configured source columns and writes
+/// it into the embedding column as little-endian float32s, i.e. sqlite-vec's vector format.
+///
+/// Records whose source columns are all NULL or empty get a NULL embedding.
+pub(crate) async fn update_embedding(
+  state: &AppState,
+  api_name: &str,
+  record_id: &str,
+) -> Result<(), EmbeddingError> {
+  let Some(api) = state.lookup_record_api(api_name) else {
+    return Err(EmbeddingError::NotFound(format!("API: {api_name}")));
+  };
+  let Some(config) = api.embedding() else {
+    return Err(EmbeddingError::Config(format!(
+      "Embeddings not configured for: {api_name}"
+    )));
+  };
+  let (Some(provider_name), Some(embedding_column)) = (&config.provider, &config.embedding_column)
+  else {
+    return Err(EmbeddingError::Config(format!(
+      "Incomplete embedding config: {config:?}"
+    )));
+  };
+  let Some(provider) =
+    state.access_config(|c| c.server.embedding_providers.get(provider_name).cloned())
+  else {
+    return Err(EmbeddingError::NotFound(format!(
+      "Embedding provider: {provider_name}"
+    )));
+  };
+
+  let id = api
+    .id_to_sql(record_id)
+    .map_err(|_err| EmbeddingError::NotFound(format!("Record: {record_id}")))?;
+
+  let table_name = api.table_name();
+  let source_columns = config.source_columns.clone();
+  let num_columns = source_columns.len();
+
+  let Some(sources) = state
+    .conn()
+    .read_query_row_f(
+      format!(
+        "SELECT {columns} FROM \"{table_name}\" WHERE {record_id_clause}",
+        columns = source_columns
+          .iter()
+          .map(|c| format!("\"{c}\""))
+          .collect::<Vec<_>>()
+          .join(", "),
+        record_id_clause = api.record_id_clause(None, "$1"),
+      ),
+      [id.clone()],
+      move |row| -> Result<Vec<Option<String>>, rusqlite::Error> {
+        return (0..num_columns).map(|i| row.get(i)).collect();
+      },
+    )
+    .await?
+  else {
+    return Err(EmbeddingError::NotFound(format!("Record: {record_id}")));
+  };
+
+  let input = sources
+    .into_iter()
+    .flatten()
+    .filter(|s| !s.is_empty())
+    .collect::<Vec<_>>()
+    .join("\n\n");
+
+  let blob: Option<Vec<u8>> = if input.is_empty() {
+    None
+  } else {
+    let embedding = embed(&provider, &input).await?;
+
+    if let Some(column) = api
+      .column_index_by_name(embedding_column)
+      .map(|index| &api.columns()[index])
+    {
+      if let Some(dimensions) = vector_column_dimensions(column) {
+        if embedding.len() != dimensions {
+          return Err(EmbeddingError::InvalidResponse(format!(
+            "Expected {dimensions} dimensions, got {}",
+            embedding.len()
+          )));
+        }
+      }
+    }
+
+    Some(embedding.iter().flat_map(|f| f.to_le_bytes()).collect())
+  };
+
+  let rows_affected = state
+    .conn()
+    .execute(
+      format!(
+        "UPDATE \"{table_name}\" SET \"{embedding_column}\" = $1 WHERE {record_id_clause}",
+        record_id_clause = api.record_id_clause(None, "$2"),
+      ),
+      trailbase_sqlite::params!(blob, id),
+    )
+    .await?;
+
+  if rows_affected == 0 {
+    debug!("Record '{record_id}' of '{api_name}' deleted before embedding");
+  }
+
+  return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+  use axum::{Json, Router, routing::post};
+
+  use super::*;
+  use crate::app_state::{TestStateOptions, test_state};
+  use crate::config::proto::{Config, EmbeddingConfig, PermissionFlag, RecordApiConfig};
+  use crate::records::test_utils::add_record_api_config;
+
+  #[tokio::test]
+  async fn test_update_embedding() {
+    // Mock an Ollama server returning a constant embedding.
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+      let router = Router::new().route(
+        "/api/embed",
+        post(async || Json(serde_json::json!({ "embeddings": [[0.5, 1.0]] }))),
+      );
+      axum::serve(listener, router).await.unwrap();
+    });
+
+    let mut config = Config::new_with_custom_defaults();
+    config.server.embedding_providers.insert(
+      "local".to_string(),
+      EmbeddingProviderConfig {
+        provider_type: Some(EmbeddingProviderType::Ollama as i32),
+        url: Some(format!("http://{address}/api/embed")),
+        model: Some("test".to_string()),
+        api_key: None,
+      },
+    );
+
+    let state = test_state(Some(TestStateOptions {
+      config: Some(config),
+      ..Default::default()
+    }))
+    .await
+    .unwrap();
+
+    state
+      .conn()
+      .execute_batch(
+        r#"
+        CREATE TABLE notes (
+          id         INTEGER PRIMARY KEY,
+          title      TEXT,
+          body       TEXT,
+          embedding  BLOB CHECK(vec_length(embedding) = 2)
+        ) STRICT;
+        INSERT INTO notes (id, title, body) VALUES (1, 'title', 'body'), (2, NULL, NULL);
+      "#,
+      )
+      .await
+      .unwrap();
+
+    state.schema_metadata().invalidate_all().await.unwrap();
+
+    add_record_api_config(
+      &state,
+      RecordApiConfig {
+        name: Some("notes".to_string()),
+        table_name: Some("notes".to_string()),
+        acl_world: [PermissionFlag::Read as i32].into(),
+        embedding: Some(EmbeddingConfig {
+          provider: Some("local".to_string()),
+          source_columns: vec!["title".to_string(), "body".to_string()],
+          embedding_column: Some("embedding".to_string()),
+        }),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    update_embedding(&state, "notes", "1").await.unwrap();
+    update_embedding(&state, "notes", "2").await.unwrap();
+    assert!(update_embedding(&state, "notes", "3").await.is_err());
+
+    let embedding = |id: i64| {
+      let state = state.clone();
+      async move {
+        return state
+          .conn()
+          .read_query_row_f(
+            "SELECT vec_to_json(embedding) FROM notes WHERE id = $1",
+            trailbase_sqlite::params!(id),
+            |row| row.get::<_, Option<String>>(0),
+          )
+          .await
+          .unwrap()
+          .unwrap();
+      }
+    };
+
+    assert_eq!(Some("[0.500000,1.000000]".to_string()), embedding(1).await);
+    assert_eq!(None, embedding(2).await);
+  }
+}
@@ -0,0 +1,214 @@
+use axum::body::Body;
+use axum::extract::Request;
+use axum::http::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use axum::http::{HeaderValue, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use log::*;
+
+use crate::extract::{AcceptFormat, CBOR_MIME_TYPE, MSGPACK_MIME_TYPE};
+
+#[derive(Clone, Copy)]
+enum Encoding {
+  MsgPack,
+  Cbor,
+}
+
+/// Re-encodes JSON responses of record APIs as MessagePack or CBOR if requested via the `Accept`
+/// header.
+///
+/// NOTE: Transcoding happens on the `serde_json::Value` level, thus the payload has the exact same
+/// structure as its JSON counterpart, e.g. blobs remain base64 encoded strings.
+pub(super) async fn encode_response_middleware(
+  accept: AcceptFormat,
+  req: Request,
+  next: Next,
+) -> Response {
+  let response = next.run(req).await;
+  let encoding = match accept {
+    AcceptFormat::MsgPack => Encoding::MsgPack,
+    AcceptFormat::Cbor => Encoding::Cbor,
+    AcceptFormat::Json | AcceptFormat::Arrow | AcceptFormat::Csv | AcceptFormat::NdJson => {
+      return response;
+    }
+  };
+
+  let is_json = response
+    .headers()
+    .get(CONTENT_TYPE)
+    .is_some_and(|v| v.as_bytes().starts_with(b"application/json"));
+  if !is_json {
+    return response;
+  }
+
+  let (mut parts, body) = response.into_parts();
+  return match transcode(body, encoding).await {
+    Ok(buffer) => {
+      let content_type = match encoding {
+        Encoding::MsgPack => MSGPACK_MIME_TYPE,
+        Encoding::Cbor => CBOR_MIME_TYPE,
+      };
+      parts
+        .headers
+        .insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+      parts.headers.remove(CONTENT_LENGTH);
+      Response::from_parts(parts, Body::from(buffer))
+    }
+    Err(err) => {
+      warn!("Failed to encode response: {err}");
+      (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response()
+    }
+  };
+}
+
+async fn transcode(
+  body: Body,
+  encoding: Encoding,
+) -> Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>> {
+  let bytes = axum::body::to_bytes(body, usize::MAX).await?;
+  let value: serde_json::Value = serde_json::from_slice(&bytes)?;
+
+  return match encoding {
+    Encoding::MsgPack => Ok(rmp_serde::to_vec_named(&value)?),
+    Encoding::Cbor => {
+      let mut buffer = vec![];
+      ciborium::into_writer(&value, &mut buffer)?;
+      Ok(buffer)
+    }
+  };
+}
+
+#[cfg(test)]
+mod tests {
+  use axum::Router;
+  use axum::routing::get;
+  use tower::ServiceExt;
+
+  use super::*;
+
+  #[tokio::test]
+  async fn test_encode_response_middleware() {
+    let router = Router::new()
+      .route(
+        "/json",
+        get(|| async { axum::Json(serde_json::json!({"id": 5, "name": "alice"})) }),
+      )
+      .route("/text", get(|| async { "text" }))
+      .layer(axum::middleware::from_fn(encode_response_middleware));
+
+    let request = |uri: &str, accept: &str| {
+      return Request::builder()
+        .uri(uri)
+        .header("accept", accept)
+        .body(Body::empty())
+        .unwrap();
+    };
+
+    let response = router
+      .clone()
+      .oneshot(request("/json", MSGPACK_MIME_TYPE))
+      .await
+      .unwrap();
+    assert_eq!(MSGPACK_MIME_TYPE, response.headers()[CONTENT_TYPE]);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+      .await
+      .unwrap();
+    let value: serde_json::Value = rmp_serde::from_slice(&body).unwrap();
+    assert_eq!(serde_json::json!({"id": 5, "name": "alice"}), value);
+
+    let response = router
+      .clone()
+      .oneshot(request("/json", CBOR_MIME_TYPE))
+      .await
+      .unwrap();
+    assert_eq!(CBOR_MIME_TYPE, response.headers()[CONTENT_TYPE]);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+      .await
+      .unwrap();
+    let value: serde_json::Value = ciborium::from_reader(body.as_ref()).unwrap();
+    assert_eq!(serde_json::json!({"id": 5, "name": "alice"}), value);
+
+    let response = router
+      .clone()
+      .oneshot(request("/json", "application/json"))
+      .await
+      .unwrap();
+    assert_eq!("application/json", response.headers()[CONTENT_TYPE]);
+
+    // Non-JSON responses are passed through.
+    let response = router
+      .oneshot(request("/text", MSGPACK_MIME_TYPE))
+      .await
+      .unwrap();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+      .await
+      .unwrap();
+    assert_eq!(b"text", body.as_ref());
+  }
+
+  #[tokio::test]
+  async fn test_record_api_binary_encodings() {
+    use crate::app_state::test_state;
+    use crate::config::proto::{PermissionFlag, RecordApiConfig};
+    use crate::constants::RECORD_API_PATH;
+    use crate::records::test_utils::add_record_api_config;
+
+    let state = test_state(None).await.unwrap();
+    state
+      .conn()
+      .execute_batch("CREATE TABLE item (id INTEGER PRIMARY KEY, name TEXT, data BLOB) STRICT;")
+      .await
+      .unwrap();
+    state.schema_metadata().invalidate_all().await.unwrap();
+
+    add_record_api_config(
+      &state,
+      RecordApiConfig {
+        name: Some("items".to_string()),
+        table_name: Some("item".to_string()),
+        acl_world: [PermissionFlag::Create as i32, PermissionFlag::Read as i32].into(),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    let router = crate::records::router().with_state(state.clone());
+    let record = serde_json::json!({"id": 1, "name": "alice", "data": "AQID"});
+
+    // Create with MessagePack and read back with CBOR through the actual record endpoints.
+    let response = router
+      .clone()
+      .oneshot(
+        Request::builder()
+          .method("POST")
+          .uri(format!("/{RECORD_API_PATH}/items"))
+          .header(CONTENT_TYPE, MSGPACK_MIME_TYPE)
+          .header("accept", MSGPACK_MIME_TYPE)
+          .body(Body::from(rmp_serde::to_vec_named(&record).unwrap()))
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(StatusCode::OK, response.status());
+    assert_eq!(MSGPACK_MIME_TYPE, response.headers()[CONTENT_TYPE]);
+
+    let response = router
+      .oneshot(
+        Request::builder()
+          .uri(format!("/{RECORD_API_PATH}/items/1"))
+          .header("accept", CBOR_MIME_TYPE)
+          .body(Body::empty())
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(StatusCode::OK, response.status());
+    assert_eq!(CBOR_MIME_TYPE, response.headers()[CONTENT_TYPE]);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+      .await
+      .unwrap();
+    let value: serde_json::Value = ciborium::from_reader(body.as_ref()).unwrap();
+    assert_eq!(record, value);
+  }
+}
@@ -0,0 +1,507 @@
+use aes_gcm_siv::aead::{Aead, KeyInit, Payload};
+use aes_gcm_siv::{Aes256GcmSiv, Nonce};
+use base64::prelude::*;
+use hmac::{Hmac, Mac};
+use log::*;
+use parking_lot::RwLock;
+use rand::RngCore;
+use sha2::Sha256;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock};
+
+/// Environment variable providing keys as "<id>:<base64 key>[,<id>:<base64 key>...]", where the
+/// first key is the active one.
+pub const ENCRYPTION_KEYS_ENV_VAR: &str = "TRAIL_ENCRYPTION_KEYS";
+
+/// Ciphertexts are stored as "enc1:<key id>:<base64(nonce || ciphertext)>".
+const CIPHERTEXT_PREFIX: &str = "enc1";
+const NONCE_LEN: usize = 12;
+const KEY_LEN: usize = 32;
+
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum EncryptionError {
+  #[error("Invalid encryption keys: {0}")]
+  InvalidKeys(String),
+  #[error("Unknown encryption key: {0}")]
+  UnknownKey(String),
+  #[error("Malformed ciphertext")]
+  Malformed,
+  #[error("Encryption failed")]
+  Encryption,
+  #[error("Decryption failed")]
+  Decryption,
+}
+
+struct Key {
+  cipher_key: [u8; KEY_LEN],
+  /// Separate key for deriving nonces in deterministic mode.
+  nonce_key: [u8; KEY_LEN],
+  /// Separate key for encrypting files, e.g. backups.
+  file_key: [u8; KEY_LEN],
+}
+
+impl Key {
+  fn derive(master: &[u8; KEY_LEN]) -> Self {
+    let derive = |purpose: &[u8]| -> [u8; KEY_LEN] {
+      let mut mac = hmac(master);
+      mac.update(purpose);
+      return mac.finalize().into_bytes().into();
+    };
+
+    return Self {
+      cipher_key: derive(b"trailbase column encryption"),
+      nonce_key: derive(b"trailbase column encryption nonce"),
+      file_key: derive(b"trailbase file encryption"),
+    };
+  }
+}
+
+/// Keys for encrypted columns tagged with ids, which allows for key rotation: values are always
+/// encrypted with the active key but can be decrypted with any known key.
+pub struct EncryptionKeys {
+  active: String,
+  keys: HashMap<String, Key>,
+}
+
+impl std::fmt::Debug for EncryptionKeys {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    return f
+      .debug_struct("EncryptionKeys")
+      .field("active", &self.active)
+      .field("keys", &self.keys.keys().collect::<Vec<_>>())
+      .finish();
+  }
+}
+
+impl EncryptionKeys {
+  /// Builds keys from raw 32-byte key material, e.g. fetched from a KMS.
+  pub fn new(
+    active_key_id: &str,
+    keys: impl IntoIterator<Item = (String, [u8; KEY_LEN])>,
+  ) -> Result<Self, EncryptionError> {
+    let keys: HashMap<String, Key> = keys
+      .into_iter()
+      .map(|(id, key)| {
+        if id.is_empty() || id.contains([':', ',']) {
+          return Err(EncryptionError::InvalidKeys(format!(
+            "invalid key id: {id:?}"
+          )));
+        }
+        return Ok((id, Key::derive(&key)));
+      })
+      .collect::<Result<_, _>>()?;
+
+    if !keys.contains_key(active_key_id) {
+      return Err(EncryptionError::UnknownKey(active_key_id.to_string()));
+    }
+
+    return Ok(Self {
+      active: active_key_id.to_string(),
+      keys,
+    });
+  }
+
+  /// Parses "<id>:<base64 key>[,<id>:<base64 key>...]", where the first key is the active one.
+  pub fn parse(spec: &str) -> Result<Self, EncryptionError> {
+    let keys = spec
+      .split(',')
+      .map(|entry| {
+        let Some((id, key)) = entry.trim().split_once(':') else {
+          return Err(EncryptionError::InvalidKeys(
+            "expected <id>:<key>".to_string(),
+          ));
+        };
+        let key = BASE64_STANDARD
+          .decode(key)
+          .map_err(|err| EncryptionError::InvalidKeys(err.to_string()))?;
+        let key: [u8; KEY_LEN] = key.try_into().map_err(|_| {
+          return EncryptionError::InvalidKeys(format!("key '{id}' must be {KEY_LEN} bytes"));
+        })?;
+        return Ok((id.to_string(), key));
+      })
+      .collect::<Result<Vec<_>, _>>()?;
+
+    let active = keys[0].0.clone();
+    return Self::new(&active, keys);
+  }
+
+  fn active(&self) -> (&str, &Key) {
+    return (&self.active, &self.keys[&self.active]);
+  }
+}
+
+static KEYS: LazyLock<RwLock<Option<Arc<EncryptionKeys>>>> = LazyLock::new(|| {
+  let keys = match std::env::var(ENCRYPTION_KEYS_ENV_VAR) {
+    Ok(spec) => EncryptionKeys::parse(&spec)
+      .map_err(|err| error!("Failed to parse {ENCRYPTION_KEYS_ENV_VAR}: {err}"))
+      .ok(),
+    Err(_) => None,
+  };
+  return RwLock::new(keys.map(Arc::new));
+});
+
+/// Installs the keys for encrypted columns, e.g. fetched from a KMS, taking precedence over
+/// `TRAIL_ENCRYPTION_KEYS`. Needs to be called before record APIs are built, i.e. before the
+/// server is initialized.
+pub fn set_encryption_keys(keys: EncryptionKeys) {
+  *KEYS.write() = Some(Arc::new(keys));
+}
+
+pub(crate) fn encryption_keys() -> Option<Arc<EncryptionKeys>> {
+  return KEYS.read().clone();
+}
+
+/// Encryption of a single record API column.
+#[derive(Clone)]
+pub(crate) struct ColumnEncryption {
+  keys: Arc<EncryptionKeys>,
+  /// Binds ciphertexts to their column, i.e. they cannot be copied over to other columns.
+  associated_data: String,
+  deterministic: bool,
+}
+
+impl ColumnEncryption {
+  pub(crate) fn new(
+    keys: Arc<EncryptionKeys>,
+    table_name: &str,
+    column_name: &str,
+    deterministic: bool,
+  ) -> Self {
+    return Self {
+      keys,
+      associated_data: format!("{table_name}.{column_name}"),
+      deterministic,
+    };
+  }
+
+  #[inline]
+  pub(crate) fn deterministic(&self) -> bool {
+    return self.deterministic;
+  }
+
+  pub(crate) fn encrypt(&self, plaintext: &str) -> Result<String, EncryptionError> {
+    let (key_id, key) = self.keys.active();
+
+    let mut nonce = [0u8; NONCE_LEN];
+    if self.deterministic {
+      // Synthetic nonce: AES-GCM-SIV stays secure if nonces repeat for equal inputs.
+      let mut mac = hmac(&key.nonce_key);
+      mac.update(self.associated_data.as_bytes());
+      mac.update(&[0]);
+      mac.update(plaintext.as_bytes());
+      nonce.copy_from_slice(&mac.finalize().into_bytes()[..NONCE_LEN]);
+    } else {
+      rand::rng().fill_bytes(&mut nonce);
+    }
+
+    let ciphertext = cipher(key)
+      .encrypt(
+        Nonce::from_slice(&nonce),
+        Payload {
+          msg: plaintext.as_bytes(),
+          aad: self.associated_data.as_bytes(),
+        },
+      )
+      .map_err(|_| EncryptionError::Encryption)?;
+
+    let mut token = nonce.to_vec();
+    token.extend_from_slice(&ciphertext);
+    return Ok(format!(
+      "{CIPHERTEXT_PREFIX}:{key_id}:{}",
+      BASE64_URL_SAFE_NO_PAD.encode(token)
+    ));
+  }
+
+  /// Decrypts values produced by [Self::encrypt]. Other values, e.g. written before encryption
+  /// was enabled, are returned as is.
+  pub(crate) fn decrypt<'a>(&self, value: &'a str) -> Result<Cow<'a, str>, EncryptionError> {
+    let Some(rest) = value
+      .strip_prefix(CIPHERTEXT_PREFIX)
+      .and_then(|rest| rest.strip_prefix(':'))
+    else {
+      return Ok(Cow::Borrowed(value));
+    };
+    let Some((key_id, token)) = rest.split_once(':') else {
+      return Err(EncryptionError::Malformed);
+    };
+    let Some(key) = self.keys.keys.get(key_id) else {
+      return Err(EncryptionError::UnknownKey(key_id.to_string()));
+    };
+
+    let token = BASE64_URL_SAFE_NO_PAD
+      .decode(token)
+      .map_err(|_| EncryptionError::Malformed)?;
+    if token.len() < NONCE_LEN {
+      return Err(EncryptionError::Malformed);
+    }
+    let (nonce, ciphertext) = token.split_at(NONCE_LEN);
+
+    let plaintext = cipher(key)
+      .decrypt(
+        Nonce::from_slice(nonce),
+        Payload {
+          msg: ciphertext,
+          aad: self.associated_data.as_bytes(),
+        },
+      )
+      .map_err(|_| EncryptionError::Decryption)?;
+
+    return String::from_utf8(plaintext)
+      .map(Cow::Owned)
+      .map_err(|_| EncryptionError::Decryption);
+  }
+}
+
+/// Chunked encryption of large files, e.g. backups. Chunks are bound to their position and to
+/// whether they're the last one, i.e. they cannot be reordered, dropped or truncated unnoticed.
+pub(crate) struct FileEncryption {
+  key_id: String,
+  cipher: Aes256GcmSiv,
+}
+
+impl FileEncryption {
+  /// Encryption using the active key.
+  pub(crate) fn active(keys: &EncryptionKeys) -> Self {
+    let (key_id, key) = keys.active();
+    return Self {
+      key_id: key_id.to_string(),
+      cipher: Aes256GcmSiv::new((&key.file_key).into()),
+    };
+  }
+
+  pub(crate) fn with_key(keys: &EncryptionKeys, key_id: &str) -> Result<Self, EncryptionError> {
+    let Some(key) = keys.keys.get(key_id) else {
+      return Err(EncryptionError::UnknownKey(key_id.to_string()));
+    };
+    return Ok(Self {
+      key_id: key_id.to_string(),
+      cipher: Aes256GcmSiv::new((&key.file_key).into()),
+    });
+  }
+
+  #[inline]
+  pub(crate) fn key_id(&self) -> &str {
+    return &self.key_id;
+  }
+
+  /// Returns nonce || ciphertext.
+  pub(crate) fn encrypt_chunk(
+    &self,
+    index: u64,
+    last: bool,
+    plaintext: &[u8],
+  ) -> Result<Vec<u8>, EncryptionError> {
+    let mut nonce = [0u8; NONCE_LEN];
+    rand::rng().fill_bytes(&mut nonce);
+
+    let ciphertext = self
+      .cipher
+      .encrypt(
+        Nonce::from_slice(&nonce),
+        Payload {
+          msg: plaintext,
+          aad: &chunk_associated_data(index, last),
+        },
+      )
+      .map_err(|_| EncryptionError::Encryption)?;
+
+    let mut chunk = nonce.to_vec();
+    chunk.extend_from_slice(&ciphertext);
+    return Ok(chunk);
+  }
+
+  pub(crate) fn decrypt_chunk(
+    &self,
+    index: u64,
+    last: bool,
+    chunk: &[u8],
+  ) -> Result<Vec<u8>, EncryptionError> {
+    if chunk.len() < NONCE_LEN {
+      return Err(EncryptionError::Malformed);
+    }
+    let (nonce, ciphertext) = chunk.split_at(NONCE_LEN);
+
+    return self
+      .cipher
+      .decrypt(
+        Nonce::from_slice(nonce),
+        Payload {
+          msg: ciphertext,
+          aad: &chunk_associated_data(index, last),
+        },
+      )
+      .map_err(|_| EncryptionError::Decryption);
+  }
+}
+
+fn chunk_associated_data(index: u64, last: bool) -> [u8; 9] {
+  let mut aad = [0u8; 9];
+  aad[..8].copy_from_slice(&index.to_be_bytes());
+  aad[8] = last as u8;
+  return aad;
+}
+
+fn hmac(key: &[u8; KEY_LEN]) -> Hmac<Sha256> {
+  return Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any size");
+}
+
+fn cipher(key: &Key) -> Aes256GcmSiv {
+  return Aes256GcmSiv::new((&key.cipher_key).into());
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn keys(spec: &str) -> Arc<EncryptionKeys> {
+    return Arc::new(EncryptionKeys::parse(spec).unwrap());
+  }
+
+  const KEY0: &str = "k0:AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=";
+  const KEY1: &str = "k1:AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE=";
+
+  #[test]
+  fn test_column_encryption() {
+    let randomized = ColumnEncryption::new(keys(KEY0), "person", "ssn", false);
+    let a = randomized.encrypt("123-45-6789").unwrap();
+    let b = randomized.encrypt("123-45-6789").unwrap();
+    assert!(a.starts_with("enc1:k0:"), "{a}");
+    assert_ne!(a, b);
+    assert_eq!(randomized.decrypt(&a).unwrap(), "123-45-6789");
+    assert_eq!(randomized.decrypt(&b).unwrap(), "123-45-6789");
+
+    // Plaintext, e.g. from before encryption was enabled, is passed through.
+    assert_eq!(randomized.decrypt("plain").unwrap(), "plain");
+
+    let deterministic = ColumnEncryption::new(keys(KEY0), "person", "email", true);
+    let a = deterministic.encrypt("alice@test.org").unwrap();
+    assert_eq!(a, deterministic.encrypt("alice@test.org").unwrap());
+    assert_ne!(a, deterministic.encrypt("bob@test.org").unwrap());
+    assert_eq!(deterministic.decrypt(&a).unwrap(), "alice@test.org");
+
+    // Ciphertexts are bound to their column.
+    let other = ColumnEncryption::new(keys(KEY0), "person", "ssn", true);
+    assert!(matches!(
+      other.decrypt(&a),
+      Err(EncryptionError::Decryption)
+    ));
+
+    // Rotated keys can still decrypt values encrypted with previous keys.
+    let rotated = ColumnEncryption::new(keys(&format!("{KEY1},{KEY0}")), "person", "email", true);
+    assert_eq!(rotated.decrypt(&a).unwrap(), "alice@test.org");
+    assert!(rotated.encrypt("x").unwrap().starts_with("enc1:k1:"));
+
+    let unknown = ColumnEncryption::new(keys(KEY1), "person", "email", true);
+    assert!(matches!(
+      unknown.decrypt(&a),
+      Err(EncryptionError::UnknownKey(_))
+    ));
+  }
+
+  #[tokio::test]
+  async fn test_encrypted_record_api() {
+    use serde_json::json;
+
+    use crate::app_state::test_state;
+    use crate::config::proto::{EncryptedColumnConfig, PermissionFlag, RecordApiConfig};
+    use crate::records::test_utils::add_record_api_config;
+    use crate::records::{RecordError, RecordsClient};
+
+    set_encryption_keys(EncryptionKeys::parse(KEY0).unwrap());
+
+    let state = test_state(None).await.unwrap();
+    state
+      .conn()
+      .execute_batch(
+        "CREATE TABLE patient (id INTEGER PRIMARY KEY, name TEXT, ssn TEXT, email TEXT) STRICT;",
+      )
+      .await
+      .unwrap();
+    state.refresh_table_cache().await.unwrap();
+
+    add_record_api_config(
+      &state,
+      RecordApiConfig {
+        name: Some("patients".to_string()),
+        table_name: Some("patient".to_string()),
+        acl_world: [PermissionFlag::Create as i32, PermissionFlag::Read as i32].into(),
+        encrypted_columns: vec![
+          EncryptedColumnConfig {
+            column_name: Some("ssn".to_string()),
+            deterministic: None,
+          },
+          EncryptedColumnConfig {
+            column_name: Some("email".to_string()),
+            deterministic: Some(true),
+          },
+        ],
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    let client = RecordsClient::new(&state);
+    for (name, ssn, email) in [
+      ("alice", "111-11-1111", "alice@test.org"),
+      ("bob", "222-22-2222", "bob@test.org"),
+    ] {
+      let serde_json::Value::Object(record) = json!({"name": name, "ssn": ssn, "email": email})
+      else {
+        unreachable!();
+      };
+      client.create("patients", record).await.unwrap();
+    }
+
+    // Values don't hit the database in plaintext.
+    let (ssn, email): (String, String) = state
+      .conn()
+      .read_query_row_f(
+        "SELECT ssn, email FROM patient WHERE name = 'alice'",
+        (),
+        |row| -> rusqlite::Result<_> { Ok((row.get(0)?, row.get(1)?)) },
+      )
+      .await
+      .unwrap()
+      .unwrap();
+    assert!(ssn.starts_with("enc1:k0:"), "{ssn}");
+    assert!(email.starts_with("enc1:k0:"), "{email}");
+
+    let record = client.read("patients", "1", None).await.unwrap();
+    assert_eq!(record["ssn"], "111-11-1111");
+    assert_eq!(record["email"], "alice@test.org");
+
+    // Deterministic columns support equality filters.
+    let list = client
+      .list("patients", Some("email=bob@test.org"))
+      .await
+      .unwrap();
+    assert_eq!(list.records.len(), 1);
+    assert_eq!(list.records[0]["ssn"], "222-22-2222");
+
+    assert!(matches!(
+      client.list("patients", Some("ssn=111-11-1111")).await,
+      Err(RecordError::BadRequest(_))
+    ));
+    assert!(matches!(
+      client
+        .list("patients", Some("email[like]=%25test.org"))
+        .await,
+      Err(RecordError::BadRequest(_))
+    ));
+  }
+
+  #[test]
+  fn test_parse_keys() {
+    assert!(EncryptionKeys::parse(KEY0).is_ok());
+    assert!(EncryptionKeys::parse("k0:AAAA").is_err());
+    assert!(EncryptionKeys::parse("nokey").is_err());
+    assert_eq!(
+      EncryptionKeys::parse(&format!("{KEY1},{KEY0}"))
+        .unwrap()
+        .active,
+      "k1"
+    );
+  }
+}
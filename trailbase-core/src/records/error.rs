@@ -1,9 +1,17 @@
-use axum::body::Body;
-use axum::http::{StatusCode, header::CONTENT_TYPE};
 use axum::response::{IntoResponse, Response};
+use lazy_static::lazy_static;
 use log::*;
+use parking_lot::Mutex;
+use std::time::{Duration, Instant};
 use thiserror::Error;
 
+use crate::export::ExportError;
+use crate::listing::LimitExceeded;
+use crate::problem::{ErrorCode, Problem, verbose_errors};
+use crate::records::image_transform::ImageTransformError;
+use crate::records::params::ParamsError;
+use crate::records::validators::FieldError;
+
 /// Publicly visible errors of record APIs.
 ///
 /// This error is deliberately opaque and kept very close to HTTP error codes to avoid the leaking
@@ -21,6 +29,26 @@ pub enum RecordError {
   Forbidden,
   #[error("Bad request: {0}")]
   BadRequest(&'static str),
+  /// The requested page size exceeds the applicable maximum, see `list_limits`.
+  #[error("Limit exceeded, max: {0}")]
+  LimitExceeded(usize),
+  #[error("Constraint violation: {1}")]
+  Constraint(ErrorCode, String),
+  /// Uniqueness or foreign-key violations, which may be resolved by the client, e.g. by retrying
+  /// or updating the existing record instead.
+  #[error("Conflict: {1}")]
+  Conflict(ErrorCode, String),
+  #[error("Validation failed")]
+  Validation(Vec<FieldError>),
+  /// The record has changed since it was read, i.e. an `If-Match` precondition failed.
+  #[error("Precondition failed")]
+  PreconditionFailed,
+  /// The operation was vetoed, e.g. by a record hook, with the given reason.
+  #[error("Rejected: {0}")]
+  Rejected(String),
+  /// The database is busy or locked, clients should retry after the given delay.
+  #[error("Unavailable, retry after {0:?}")]
+  Unavailable(Duration),
   #[error("Internal: {0}")]
   Internal(Box<dyn std::error::Error + Send + Sync>),
 }
@@ -36,21 +64,70 @@ impl From<trailbase_sqlite::Error> for RecordError {
           Self::RecordNotFound
         }
 
-        rusqlite::Error::SqliteFailure(err, _msg) => {
+        rusqlite::Error::SqliteFailure(err, _msg)
+          if matches!(
+            err.code,
+            rusqlite::ErrorCode::DatabaseBusy | rusqlite::ErrorCode::DatabaseLocked
+          ) =>
+        {
+          Self::Unavailable(busy_retry_after())
+        }
+
+        rusqlite::Error::SqliteFailure(err, msg) => {
+          // SQLite's message names the violated constraint, e.g. "UNIQUE constraint failed:
+          // table.column".
+          let message = |fallback: &str| msg.unwrap_or_else(|| fallback.to_string());
+
           match err.extended_code {
             // List of error codes: https://www.sqlite.org/rescode.html
-            275 => Self::BadRequest("sqlite constraint: check"),
-            531 => Self::BadRequest("sqlite constraint: commit hook"),
-            3091 => Self::BadRequest("sqlite constraint: data type"),
-            787 => Self::BadRequest("sqlite constraint: fk"),
-            1043 => Self::BadRequest("sqlite constraint: function"),
-            1299 => Self::BadRequest("sqlite constraint: not null"),
-            2835 => Self::BadRequest("sqlite constraint: pinned"),
-            1555 => Self::BadRequest("sqlite constraint: pk"),
-            2579 => Self::BadRequest("sqlite constraint: row id"),
-            1811 => Self::BadRequest("sqlite constraint: trigger"),
-            2067 => Self::BadRequest("sqlite constraint: unique"),
-            2323 => Self::BadRequest("sqlite constraint: vtab"),
+            275 => Self::Constraint(
+              ErrorCode::RecordConstraintCheck,
+              message("sqlite constraint: check"),
+            ),
+            531 => Self::Constraint(
+              ErrorCode::RecordConstraint,
+              message("sqlite constraint: commit hook"),
+            ),
+            3091 => Self::Constraint(
+              ErrorCode::RecordConstraint,
+              message("sqlite constraint: data type"),
+            ),
+            787 => Self::Conflict(
+              ErrorCode::RecordConstraintForeignKey,
+              message("sqlite constraint: fk"),
+            ),
+            1043 => Self::Constraint(
+              ErrorCode::RecordConstraint,
+              message("sqlite constraint: function"),
+            ),
+            1299 => Self::Constraint(
+              ErrorCode::RecordConstraintNotNull,
+              message("sqlite constraint: not null"),
+            ),
+            2835 => Self::Constraint(
+              ErrorCode::RecordConstraint,
+              message("sqlite constraint: pinned"),
+            ),
+            1555 => Self::Conflict(
+              ErrorCode::RecordConstraintPrimaryKey,
+              message("sqlite constraint: pk"),
+            ),
+            2579 => Self::Constraint(
+              ErrorCode::RecordConstraint,
+              message("sqlite constraint: row id"),
+            ),
+            1811 => Self::Constraint(
+              ErrorCode::RecordConstraint,
+              message("sqlite constraint: trigger"),
+            ),
+            2067 => Self::Conflict(
+              ErrorCode::RecordConstraintUnique,
+              message("sqlite constraint: unique"),
+            ),
+            2323 => Self::Constraint(
+              ErrorCode::RecordConstraint,
+              message("sqlite constraint: vtab"),
+            ),
             _ => Self::Internal(err.into()),
           }
         }
@@ -61,31 +138,247 @@ impl From<trailbase_sqlite::Error> for RecordError {
   }
 }
 
+impl From<ParamsError> for RecordError {
+  fn from(err: ParamsError) -> Self {
+    return match err {
+      ParamsError::FieldValidation(errors) => Self::Validation(errors),
+      ParamsError::Geometry(_) => Self::BadRequest("Invalid geometry"),
+      ParamsError::GeneratedColumn(_) => Self::BadRequest("Cannot write generated column"),
+      ParamsError::FileConstraint(msg) => Self::BadRequest(msg),
+      err => Self::Internal(err.into()),
+    };
+  }
+}
+
+impl From<ImageTransformError> for RecordError {
+  fn from(err: ImageTransformError) -> Self {
+    return match err {
+      ImageTransformError::InvalidParams(msg) => Self::BadRequest(msg),
+      ImageTransformError::UnsupportedFormat => Self::BadRequest("Not a supported image"),
+      ImageTransformError::Storage(object_store::Error::NotFound { .. }) => Self::RecordNotFound,
+      err => Self::Internal(err.into()),
+    };
+  }
+}
+
+impl From<LimitExceeded> for RecordError {
+  fn from(err: LimitExceeded) -> Self {
+    return Self::LimitExceeded(err.max_limit);
+  }
+}
+
+impl From<ExportError> for RecordError {
+  fn from(err: ExportError) -> Self {
+    return Self::Internal(err.into());
+  }
+}
+
+#[cfg(feature = "grpc")]
+impl From<RecordError> for tonic::Status {
+  fn from(err: RecordError) -> Self {
+    return match err {
+      RecordError::ApiNotFound => Self::not_found("Api Not Found"),
+      RecordError::ApiRequiresTable => Self::failed_precondition("Api Requires Table"),
+      RecordError::RecordNotFound => Self::not_found("Record Not Found"),
+      RecordError::Forbidden => Self::permission_denied("Forbidden"),
+      RecordError::BadRequest(msg) => Self::invalid_argument(msg),
+      RecordError::LimitExceeded(max_limit) => {
+        Self::invalid_argument(LimitExceeded { max_limit }.to_string())
+      }
+      RecordError::Constraint(_code, msg) => Self::invalid_argument(msg),
+      RecordError::Conflict(_code, msg) => Self::already_exists(msg),
+      RecordError::Validation(errors) => {
+        Self::invalid_argument(serde_json::to_string(&errors).unwrap_or_default())
+      }
+      RecordError::PreconditionFailed => Self::failed_precondition("Precondition Failed"),
+      RecordError::Rejected(msg) => Self::invalid_argument(msg),
+      RecordError::Unavailable(_retry_after) => Self::unavailable("Unavailable"),
+      RecordError::Internal(err) if verbose_errors() => Self::internal(err.to_string()),
+      RecordError::Internal(_err) => Self::internal("Internal"),
+    };
+  }
+}
+
 impl IntoResponse for RecordError {
   fn into_response(self) -> Response {
-    let (status, body) = match self {
-      Self::ApiNotFound => (StatusCode::METHOD_NOT_ALLOWED, None),
-      Self::ApiRequiresTable => (StatusCode::METHOD_NOT_ALLOWED, None),
-      Self::RecordNotFound => (StatusCode::NOT_FOUND, None),
-      Self::Forbidden => (StatusCode::FORBIDDEN, None),
-      Self::BadRequest(msg) => (StatusCode::BAD_REQUEST, Some(msg.to_string())),
-      Self::Internal(err) if cfg!(debug_assertions) => {
-        (StatusCode::INTERNAL_SERVER_ERROR, Some(err.to_string()))
+    let problem = match self {
+      Self::ApiNotFound => Problem::new(ErrorCode::RecordApiNotFound),
+      Self::ApiRequiresTable => Problem::new(ErrorCode::RecordApiRequiresTable),
+      Self::RecordNotFound => Problem::new(ErrorCode::RecordNotFound),
+      Self::Forbidden => Problem::new(ErrorCode::RecordForbidden),
+      Self::BadRequest(msg) => Problem::new(ErrorCode::RecordBadRequest).with_detail(msg),
+      Self::LimitExceeded(max_limit) => Problem::new(ErrorCode::RecordLimitExceeded)
+        .with_detail(LimitExceeded { max_limit }.to_string())
+        .with_max_limit(max_limit),
+      Self::Constraint(code, msg) | Self::Conflict(code, msg) => {
+        let errors = constraint_field_errors(&msg);
+        let problem = Problem::new(code).with_detail(msg);
+        if errors.is_empty() {
+          problem
+        } else {
+          problem.with_errors(errors)
+        }
+      }
+      Self::Validation(errors) => {
+        Problem::new(ErrorCode::RecordValidationFailed).with_errors(errors)
       }
-      Self::Internal(_err) => (StatusCode::INTERNAL_SERVER_ERROR, None),
+      Self::PreconditionFailed => Problem::new(ErrorCode::RecordPreconditionFailed),
+      Self::Rejected(msg) => Problem::new(ErrorCode::RecordBadRequest).with_detail(msg),
+      Self::Unavailable(retry_after) => {
+        Problem::new(ErrorCode::RecordUnavailable).with_retry_after(retry_after)
+      }
+      Self::Internal(err) if verbose_errors() => {
+        Problem::new(ErrorCode::RecordInternal).with_detail(err.to_string())
+      }
+      Self::Internal(_err) => Problem::new(ErrorCode::RecordInternal),
     };
 
-    if let Some(body) = body {
-      return Response::builder()
-        .status(status)
-        .header(CONTENT_TYPE, "text/plain")
-        .body(Body::new(body))
-        .unwrap_or_default();
+    return problem.into_response();
+  }
+}
+
+/// Suggested delay before retrying after the database was busy. Backs off exponentially, from 1s
+/// up to 32s, while busy errors keep occurring within short succession and resets otherwise.
+fn busy_retry_after() -> Duration {
+  const RESET_AFTER: Duration = Duration::from_secs(10);
+
+  lazy_static! {
+    static ref BACKOFF: Mutex<Option<(Instant, u32)>> = Mutex::new(None);
+  }
+
+  let mut backoff = BACKOFF.lock();
+  let attempts = match *backoff {
+    Some((last, attempts)) if last.elapsed() <= RESET_AFTER => attempts.saturating_add(1),
+    _ => 0,
+  };
+  *backoff = Some((Instant::now(), attempts));
+
+  return Duration::from_secs(1 << attempts.min(5));
+}
+
+/// Extracts the offending columns from SQLite's constraint error messages, e.g. "UNIQUE
+/// constraint failed: user.email" or "NOT NULL constraint failed: user.name".
+///
+/// Returns no errors for constraints that don't name columns, e.g. CHECK constraints or unique
+/// indexes on expressions.
+fn constraint_field_errors(msg: &str) -> Vec<FieldError> {
+  let Some((kind, columns)) = msg.split_once(" constraint failed: ") else {
+    return vec![];
+  };
+  let (code, message) = match kind {
+    "UNIQUE" => ("unique", "already taken"),
+    "NOT NULL" => ("not_null", "required"),
+    _ => return vec![],
+  };
+  if columns.starts_with("index ") {
+    return vec![];
+  }
+
+  return columns
+    .split(", ")
+    .map(|column| FieldError {
+      field: column
+        .split_once('.')
+        .map_or(column, |(_table, column)| column)
+        .to_string(),
+      code: code.to_string(),
+      message: message.to_string(),
+    })
+    .collect();
+}
+
+#[cfg(test)]
+mod tests {
+  use axum::http::StatusCode;
+
+  use super::*;
+
+  #[test]
+  fn test_conflict_errors() {
+    let conn = rusqlite::Connection::open_in_memory().unwrap();
+    conn
+      .execute_batch(
+        r#"
+          PRAGMA foreign_keys = ON;
+          CREATE TABLE parent (id INTEGER PRIMARY KEY, name TEXT UNIQUE) STRICT;
+          CREATE TABLE child (id INTEGER PRIMARY KEY, parent INTEGER REFERENCES parent(id)) STRICT;
+          INSERT INTO parent (id, name) VALUES (1, 'a');
+        "#,
+      )
+      .unwrap();
+
+    let err = |sql: &str| -> RecordError {
+      return trailbase_sqlite::Error::Rusqlite(conn.execute(sql, ()).unwrap_err()).into();
+    };
+
+    let RecordError::Conflict(code, msg) = err("INSERT INTO parent (id, name) VALUES (2, 'a')")
+    else {
+      panic!("expected conflict");
+    };
+    assert_eq!(code, ErrorCode::RecordConstraintUnique);
+    assert!(msg.contains("parent.name"), "{msg}");
+
+    let RecordError::Conflict(code, _) = err("INSERT INTO parent (id, name) VALUES (1, 'b')")
+    else {
+      panic!("expected conflict");
+    };
+    assert_eq!(code, ErrorCode::RecordConstraintPrimaryKey);
+
+    let conflict = err("INSERT INTO child (id, parent) VALUES (1, 5)");
+    assert!(
+      matches!(
+        conflict,
+        RecordError::Conflict(ErrorCode::RecordConstraintForeignKey, _)
+      ),
+      "{conflict:?}"
+    );
+    assert_eq!(conflict.into_response().status(), StatusCode::CONFLICT);
+  }
+
+  #[test]
+  fn test_busy_errors() {
+    let busy = |code: std::os::raw::c_int| -> RecordError {
+      return trailbase_sqlite::Error::Rusqlite(rusqlite::Error::SqliteFailure(
+        rusqlite::ffi::Error::new(code),
+        None,
+      ))
+      .into();
+    };
+
+    // SQLITE_BUSY_TIMEOUT and SQLITE_LOCKED.
+    for code in [773, 6] {
+      let err = busy(code);
+      assert!(matches!(err, RecordError::Unavailable(_)), "{err:?}");
+
+      let response = err.into_response();
+      assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+      assert!(response.headers().contains_key("retry-after"));
     }
+  }
 
-    return Response::builder()
-      .status(status)
-      .body(Body::empty())
-      .unwrap_or_default();
+  #[test]
+  fn test_constraint_field_errors() {
+    assert_eq!(
+      constraint_field_errors("UNIQUE constraint failed: user.email"),
+      vec![FieldError {
+        field: "email".to_string(),
+        code: "unique".to_string(),
+        message: "already taken".to_string(),
+      }]
+    );
+    assert_eq!(
+      constraint_field_errors("UNIQUE constraint failed: t.a, t.b")
+        .into_iter()
+        .map(|e| e.field)
+        .collect::<Vec<_>>(),
+      vec!["a", "b"]
+    );
+    assert_eq!(
+      constraint_field_errors("NOT NULL constraint failed: t.name")[0].code,
+      "not_null"
+    );
+    assert!(constraint_field_errors("UNIQUE constraint failed: index 'idx'").is_empty());
+    assert!(constraint_field_errors("CHECK constraint failed: length(name) > 2").is_empty());
+    assert!(constraint_field_errors("FOREIGN KEY constraint failed").is_empty());
   }
 }
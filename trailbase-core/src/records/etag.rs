@@ -0,0 +1,127 @@
+use base64::prelude::*;
+use itertools::Itertools;
+use rusqlite::types::ValueRef;
+use sha2::{Digest, Sha256};
+use trailbase_sqlite::{Params as _, Value, named_params};
+
+use crate::records::RecordApi;
+
+/// Computes a strong ETag from a record's column values, i.e. any change to the API's columns of
+/// a record yields a different ETag.
+pub(crate) fn record_etag<'a>(values: impl Iterator<Item = ValueRef<'a>>) -> String {
+  let mut hasher = Sha256::new();
+  for value in values {
+    // Type tags and length prefixes keep the encoding unambiguous.
+    match value {
+      ValueRef::Null => hasher.update([0]),
+      ValueRef::Integer(i) => {
+        hasher.update([1]);
+        hasher.update(i.to_le_bytes());
+      }
+      ValueRef::Real(r) => {
+        hasher.update([2]);
+        hasher.update(r.to_bits().to_le_bytes());
+      }
+      ValueRef::Text(text) => {
+        hasher.update([3]);
+        hasher.update((text.len() as u64).to_le_bytes());
+        hasher.update(text);
+      }
+      ValueRef::Blob(blob) => {
+        hasher.update([4]);
+        hasher.update((blob.len() as u64).to_le_bytes());
+        hasher.update(blob);
+      }
+    }
+  }
+
+  let digest = hasher.finalize();
+  return format!("\"{}\"", BASE64_URL_SAFE_NO_PAD.encode(&digest[..16]));
+}
+
+/// Whether an `If-Match` header value, i.e. "*" or a list of ETags, matches the given ETag using
+/// strong comparison, see RFC 9110.
+pub(crate) fn if_match(header: &str, etag: &str) -> bool {
+  return header
+    .split(',')
+    .map(str::trim)
+    .any(|tag| tag == "*" || tag == etag);
+}
+
+/// `If-Match` precondition of an update or deletion. It is evaluated as part of the write's
+/// transaction to avoid races with concurrent writers.
+#[derive(Debug)]
+pub(crate) struct IfMatch {
+  query: String,
+  record_id: Value,
+  header: String,
+}
+
+impl IfMatch {
+  pub(crate) fn new(api: &RecordApi, record_id: Value, header: String) -> Self {
+    let (_index, pk_column) = api.record_pk_column();
+    let column_names = api
+      .columns()
+      .iter()
+      .map(|c| format!(r#""{}""#, c.name))
+      .join(",");
+
+    return Self {
+      query: format!(
+        r#"SELECT {column_names} FROM "{table_name}" WHERE "{pk}" = :__record_id"#,
+        table_name = api.table_name(),
+        pk = pk_column.name,
+      ),
+      record_id,
+      header,
+    };
+  }
+
+  /// Returns whether the record exists and matches the precondition.
+  pub(crate) fn check(self, conn: &rusqlite::Connection) -> rusqlite::Result<bool> {
+    let mut stmt = conn.prepare_cached(&self.query)?;
+    named_params! {":__record_id": self.record_id}.bind(&mut stmt)?;
+
+    let mut rows = stmt.raw_query();
+    let Some(row) = rows.next()? else {
+      return Ok(false);
+    };
+
+    let values = (0..row.as_ref().column_count())
+      .map(|idx| row.get_ref(idx))
+      .collect::<Result<Vec<_>, _>>()?;
+
+    return Ok(if_match(&self.header, &record_etag(values.into_iter())));
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_record_etag() {
+    let etag = record_etag([ValueRef::Integer(1), ValueRef::Text(b"a")].into_iter());
+    assert!(etag.starts_with('"') && etag.ends_with('"'), "{etag}");
+    assert_eq!(
+      etag,
+      record_etag([ValueRef::Integer(1), ValueRef::Text(b"a")].into_iter())
+    );
+
+    // Unambiguous with respect to types and value boundaries.
+    assert_ne!(
+      etag,
+      record_etag([ValueRef::Integer(1), ValueRef::Blob(b"a")].into_iter())
+    );
+    assert_ne!(
+      record_etag([ValueRef::Text(b"ab"), ValueRef::Text(b"c")].into_iter()),
+      record_etag([ValueRef::Text(b"a"), ValueRef::Text(b"bc")].into_iter())
+    );
+
+    assert!(if_match(&etag, &etag));
+    assert!(if_match("*", &etag));
+    assert!(if_match(&format!(r#""other", {etag}"#), &etag));
+    assert!(!if_match(r#""other""#, &etag));
+    assert!(!if_match(&format!("W/{etag}"), &etag));
+  }
+}
@@ -0,0 +1,394 @@
+use askama::Template;
+use axum::body::Body;
+use axum::extract::{Path, RawQuery, State};
+use axum::http::header;
+use axum::response::{IntoResponse, Response};
+use futures_util::TryStreamExt;
+use itertools::Itertools;
+use std::borrow::Cow;
+use trailbase_sqlite::Value;
+
+use crate::app_state::AppState;
+use crate::auth::user::User;
+use crate::export::{ExportColumns, ExportFormat, encode_batches, query_batches};
+use crate::extract::AcceptFormat;
+use crate::listing::{
+  Order, QueryParseResult, WhereClause, build_filter_where_clause, parse_and_sanitize_query,
+};
+use crate::records::list_records::{ListRecordQueryTemplate, column_filter};
+use crate::records::request_context::push_request_context_params;
+use crate::records::{Permission, RecordApi, RecordError};
+
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct ExportOptions {
+  /// Explicit output format. Otherwise derived from the `Accept` header, defaulting to CSV.
+  pub(crate) format: Option<ExportFormat>,
+  /// Explicit column selection and order. Defaults to all visible columns.
+  pub(crate) columns: Option<Vec<String>>,
+  /// Prefix CSV output with a UTF-8 byte order mark, which helps spreadsheet software detect the
+  /// encoding.
+  pub(crate) bom: bool,
+}
+
+/// Splits out export-specific query parameters, returning the remainder as list query.
+pub(crate) fn split_export_query(
+  query: Option<&str>,
+) -> Result<(ExportOptions, String), RecordError> {
+  let mut options = ExportOptions::default();
+  let mut remainder = form_urlencoded::Serializer::new(String::new());
+
+  for (key, value) in form_urlencoded::parse(query.unwrap_or_default().as_bytes()) {
+    match key.as_ref() {
+      "format" => {
+        options.format = Some(
+          ExportFormat::parse(&value)
+            .ok_or(RecordError::BadRequest("Unsupported export format"))?,
+        );
+      }
+      "columns" => {
+        let columns: Vec<String> = value
+          .split(',')
+          .map(|c| c.trim())
+          .filter(|c| !c.is_empty())
+          .map(|c| c.to_string())
+          .collect();
+        if !columns.is_empty() {
+          options.columns = Some(columns);
+        }
+      }
+      "bom" => {
+        options.bom = match value.as_ref() {
+          "TRUE" | "true" | "1" => true,
+          "FALSE" | "false" | "0" => false,
+          _ => return Err(RecordError::BadRequest("Invalid bom")),
+        };
+      }
+      _ => {
+        remainder.append_pair(&key, &value);
+      }
+    }
+  }
+
+  return Ok((options, remainder.finish()));
+}
+
+/// Returns the streaming format, if the client requested listing records as CSV or NDJSON either
+/// via `format` query parameter or `Accept` header.
+pub(crate) fn streaming_list_format(
+  query: Option<&str>,
+  accept: AcceptFormat,
+) -> Option<ExportFormat> {
+  let format = form_urlencoded::parse(query.unwrap_or_default().as_bytes())
+    .find(|(key, _)| key == "format")
+    .and_then(|(_, value)| ExportFormat::parse(&value));
+
+  return match (format, accept) {
+    (Some(format @ (ExportFormat::Csv | ExportFormat::NdJson)), _) => Some(format),
+    (Some(_), _) => None,
+    (None, AcceptFormat::Csv) => Some(ExportFormat::Csv),
+    (None, AcceptFormat::NdJson) => Some(ExportFormat::NdJson),
+    (None, _) => None,
+  };
+}
+
+/// Exports records matching the given filters as CSV, NDJSON, Parquet or Arrow IPC stream.
+///
+/// Accepts the same filters and ordering as listing, as well as `format` (`csv`, `ndjson`,
+/// `parquet` or `arrow`, alternatively negotiated via the `Accept` header),
+/// `columns` to select and order columns, `bom` to prepend a byte order mark to CSV and `limit` to
+/// cap the number of exported rows. Unlike listing, exports aren't paginated and stream all
+/// matching records.
+#[utoipa::path(
+  get,
+  path = "/:name/export",
+  responses(
+    (status = 200, description = "Matching records as CSV, NDJSON, Parquet or Arrow IPC stream.")
+  )
+)]
+pub async fn export_records_handler(
+  State(state): State<AppState>,
+  Path(api_name): Path<String>,
+  RawQuery(raw_url_query): RawQuery,
+  user: Option<User>,
+  accept: AcceptFormat,
+) -> Result<Response, RecordError> {
+  let Some(api) = state.lookup_record_api(&api_name) else {
+    return Err(RecordError::ApiNotFound);
+  };
+
+  let (options, list_query) = split_export_query(raw_url_query.as_deref())?;
+  let format = options.format.unwrap_or(match accept {
+    AcceptFormat::Arrow => ExportFormat::Arrow,
+    AcceptFormat::NdJson => ExportFormat::NdJson,
+    _ => ExportFormat::Csv,
+  });
+
+  let body = export_records(
+    &state,
+    &api,
+    &list_query,
+    options.columns,
+    options.bom,
+    user,
+    format,
+  )
+  .await?;
+
+  return Ok(
+    (
+      [
+        (header::CONTENT_TYPE, format.content_type().to_string()),
+        (
+          header::CONTENT_DISPOSITION,
+          format!("attachment; filename=\"{api_name}.{}\"", format.extension()),
+        ),
+      ],
+      body,
+    )
+      .into_response(),
+  );
+}
+
+/// Streams all records matching the list query in the given format, i.e. w/o pagination.
+pub(crate) async fn export_records(
+  state: &AppState,
+  api: &RecordApi,
+  list_query: &str,
+  columns: Option<Vec<String>>,
+  bom: bool,
+  user: Option<User>,
+  format: ExportFormat,
+) -> Result<Body, RecordError> {
+  // NOTE: Like listing, the read access rule is applied as a filter.
+  api.check_table_level_access(Permission::Read, user.as_ref())?;
+
+  let QueryParseResult {
+    limit,
+    cursor,
+    count: _,
+    expand,
+    order,
+    params: filter_params,
+    filter,
+    offset,
+    select,
+    nearest: _,
+    k: _,
+    search,
+    collate: _,
+    format: _,
+    include_deleted,
+  } = parse_and_sanitize_query(Some(list_query)).map_err(|_err| {
+    return RecordError::BadRequest("Invalid query");
+  })?;
+
+  if cursor.is_some() {
+    return Err(RecordError::BadRequest("Cursors not supported for export"));
+  }
+  if search.is_some() {
+    return Err(RecordError::BadRequest("Search not supported for export"));
+  }
+  if expand.is_some() {
+    return Err(RecordError::BadRequest(
+      "Expansion not supported for export",
+    ));
+  }
+
+  // Explicit export columns take precedence over a list query's selection.
+  let selected: Vec<usize> = match columns.or(select) {
+    Some(columns) => columns
+      .iter()
+      .map(|column| {
+        if !column_filter(column) {
+          return None;
+        }
+        return api.column_index_by_name(column);
+      })
+      .collect::<Option<Vec<_>>>()
+      .ok_or(RecordError::BadRequest("Invalid column"))?,
+    None => api
+      .columns()
+      .iter()
+      .enumerate()
+      .filter(|(_, c)| column_filter(&c.name))
+      .map(|(i, _)| i)
+      .collect(),
+  };
+
+  let read_access_clause =
+    api.list_access_clause(include_deleted.unwrap_or(false), user.as_ref())?;
+
+  // NOTE: This will also drop any filters for unknown columns, thus avoiding SQL injections.
+  let WhereClause {
+    clause: filter_clause,
+    mut params,
+  } = build_filter_where_clause("_ROW_", api.columns(), filter_params, filter, None)
+    .map_err(|_err| RecordError::BadRequest("Invalid filter params"))?;
+
+  params.push((
+    Cow::Borrowed(":__user_id"),
+    user.map_or(Value::Null, |u| Value::Blob(u.uuid.into())),
+  ));
+  push_request_context_params(&mut params);
+
+  fn fmt_order(col: &str, order: Order) -> String {
+    return format!(
+      r#"_ROW_."{col}" {}"#,
+      match order {
+        Order::Descending => "DESC",
+        Order::Ascending => "ASC",
+      }
+    );
+  }
+
+  // Batches are fetched using offsets, always order by the primary key last to get a total and
+  // thus stable order.
+  let order_clause = order
+    .unwrap_or_default()
+    .into_iter()
+    .map(|(col, ord)| fmt_order(&col, ord))
+    .chain(
+      api
+        .record_pk_columns()
+        .iter()
+        .map(|(_index, pk_column)| fmt_order(&pk_column.name, Order::Descending)),
+    )
+    .join(",");
+
+  let column_names: Vec<_> = api.columns().iter().map(|c| c.name.as_str()).collect();
+  let query = ListRecordQueryTemplate {
+    table_name: api.table_name(),
+    column_names: &column_names,
+    read_access_clause: &read_access_clause,
+    filter_clause: &filter_clause,
+    cursor_clause: None,
+    order_clause: &order_clause,
+    expanded_tables: &[],
+    fts_table: None,
+    vec_table: None,
+    count: false,
+    offset: true,
+  }
+  .render()
+  .map_err(|err| RecordError::Internal(err.into()))?;
+
+  let batches = query_batches(
+    state.conn().clone(),
+    query,
+    params,
+    offset.unwrap_or(0),
+    limit,
+  )
+  .map_err(RecordError::from);
+
+  let chunks = encode_batches(
+    format,
+    ExportColumns {
+      columns: api.columns().to_vec(),
+      json_metadata: api.json_column_metadata().to_vec(),
+      selected,
+    },
+    bom,
+    batches,
+  )?;
+
+  return Ok(Body::from_stream(chunks));
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::app_state::test_state;
+  use crate::config::proto::{PermissionFlag, RecordApiConfig};
+  use crate::records::test_utils::add_record_api_config;
+
+  #[test]
+  fn test_split_export_query() {
+    let (options, remainder) =
+      split_export_query(Some("format=csv&columns=b,a&bom=1&order=-a&a[gt]=5")).unwrap();
+    assert_eq!(
+      ExportOptions {
+        format: Some(ExportFormat::Csv),
+        columns: Some(vec!["b".to_string(), "a".to_string()]),
+        bom: true,
+      },
+      options
+    );
+    assert_eq!("order=-a&a%5Bgt%5D=5", remainder);
+
+    let (options, _) = split_export_query(Some("format=parquet")).unwrap();
+    assert_eq!(Some(ExportFormat::Parquet), options.format);
+
+    assert!(split_export_query(Some("format=xlsx")).is_err());
+  }
+
+  async fn export(state: &AppState, query: &str) -> Result<String, RecordError> {
+    let response = export_records_handler(
+      State(state.clone()),
+      Path("api".to_string()),
+      RawQuery(Some(query.to_string())),
+      None,
+      AcceptFormat::Json,
+    )
+    .await?;
+
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+      .await
+      .unwrap();
+    return Ok(String::from_utf8(body.to_vec()).unwrap());
+  }
+
+  #[tokio::test]
+  async fn test_record_api_export() {
+    let state = test_state(None).await.unwrap();
+
+    state
+      .conn()
+      .execute_batch(
+        r#"
+        CREATE TABLE 'table' (
+          id      INTEGER PRIMARY KEY,
+          name    TEXT,
+          _hidden TEXT
+        ) STRICT;
+        INSERT INTO 'table' (id, name) VALUES (1, 'a'), (2, 'b,"c"'), (3, NULL);
+      "#,
+      )
+      .await
+      .unwrap();
+
+    state.schema_metadata().invalidate_all().await.unwrap();
+
+    add_record_api_config(
+      &state,
+      RecordApiConfig {
+        name: Some("api".to_string()),
+        table_name: Some("table".to_string()),
+        acl_world: [PermissionFlag::Read as i32].into(),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+      "id,name\r\n3,\r\n2,\"b,\"\"c\"\"\"\r\n1,a\r\n",
+      export(&state, "").await.unwrap()
+    );
+
+    assert_eq!(
+      "\u{FEFF}name,id\r\na,1\r\n\"b,\"\"c\"\"\",2\r\n",
+      export(&state, "bom=true&columns=name,id&order=id&id[lt]=3")
+        .await
+        .unwrap()
+    );
+
+    assert_eq!(
+      "id\r\n3\r\n",
+      export(&state, "columns=id&limit=1").await.unwrap()
+    );
+
+    assert!(export(&state, "columns=_hidden").await.is_err());
+    assert!(export(&state, "columns=missing").await.is_err());
+  }
+}
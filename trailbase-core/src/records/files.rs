@@ -9,6 +9,8 @@ use trailbase_schema::{FileUpload, FileUploads};
 use trailbase_sqlite::params;
 
 use crate::app_state::AppState;
+use crate::data_dir::DataDir;
+use crate::records::image_transform::remove_cached_variants;
 use crate::records::params::FileMetadataContents;
 
 #[derive(Debug, Error)]
@@ -80,7 +82,7 @@ pub(crate) async fn delete_pending_files(
     )
     .await?;
 
-  delete_pending_files_impl(state.conn(), state.objectstore(), rows).await?;
+  delete_pending_files_impl(state.conn(), state.objectstore(), state.data_dir(), rows).await?;
 
   return Ok(());
 }
@@ -88,6 +90,7 @@ pub(crate) async fn delete_pending_files(
 pub(crate) async fn delete_pending_files_impl(
   conn: &trailbase_sqlite::Connection,
   store: &dyn ObjectStore,
+  data_dir: &DataDir,
   pending_deletions: Vec<FileDeletionsDb>,
 ) -> Result<(), FileError> {
   const ATTEMPTS_LIMIT: i64 = 10;
@@ -100,6 +103,7 @@ pub(crate) async fn delete_pending_files_impl(
     async |row: &FileDeletionsDb, file: FileUpload| match delete_file(store, &file).await {
       Err(object_store::Error::NotFound { .. }) | Err(object_store::Error::InvalidPath { .. }) => {
         info!("Dropping further deletion attempts for invalid file: {file:?}");
+        remove_cached_variants(data_dir, &file).await;
       }
       Err(err) => {
         if row.attempts < ATTEMPTS_LIMIT {
@@ -111,7 +115,7 @@ pub(crate) async fn delete_pending_files_impl(
           info!("Abandoning deletion of {file:?} after {ATTEMPTS_LIMIT} failed attemps: {err}");
         }
       }
-      Ok(_) => {}
+      Ok(_) => remove_cached_variants(data_dir, &file).await,
     };
 
   for pending_deletion in pending_deletions {
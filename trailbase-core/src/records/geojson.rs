@@ -0,0 +1,207 @@
+//! GeoJSON FeatureCollections for listings requested with `format=geojson`.
+//!
+//! See https://datatracker.ietf.org/doc/html/rfc7946.
+
+use axum::Json;
+use axum::http::header::CONTENT_TYPE;
+use axum::response::{IntoResponse, Response};
+use base64::prelude::*;
+use serde_json::{Map, Value, json};
+use trailbase_extension::geometry::wkb_to_geojson;
+use trailbase_schema::metadata::GeometryColumns;
+
+use crate::records::list_records::ListResponse;
+use crate::records::{RecordApi, RecordError};
+
+pub const GEOJSON_MIME_TYPE: &str = "application/geo+json";
+
+/// Removes the location columns from the record and returns the corresponding GeoJSON geometry.
+fn take_geometry(
+  api: &RecordApi,
+  geometry_columns: &GeometryColumns,
+  record: &mut Map<String, Value>,
+) -> Result<Value, RecordError> {
+  return match geometry_columns {
+    GeometryColumns::Wkb(index) => match record.remove(&api.columns()[*index].name) {
+      // NOTE: Blobs are base64 encoded in JSON records.
+      Some(Value::String(encoded)) => {
+        let wkb = BASE64_URL_SAFE
+          .decode(encoded)
+          .map_err(|err| RecordError::Internal(err.into()))?;
+        wkb_to_geojson(&wkb).map_err(|err| RecordError::Internal(err.into()))
+      }
+      Some(Value::Null) | None => Ok(Value::Null),
+      Some(value) => Err(RecordError::Internal(
+        format!("Unexpected geometry: {value}").into(),
+      )),
+    },
+    GeometryColumns::LatLng { lat, lng } => {
+      let lat = record.remove(&api.columns()[*lat].name);
+      let lng = record.remove(&api.columns()[*lng].name);
+
+      match (
+        lat.as_ref().and_then(|v| v.as_f64()),
+        lng.as_ref().and_then(|v| v.as_f64()),
+      ) {
+        // GeoJSON positions are [longitude, latitude].
+        (Some(lat), Some(lng)) => Ok(json!({
+          "type": "Point",
+          "coordinates": [lng, lat],
+        })),
+        _ => Ok(Value::Null),
+      }
+    }
+  };
+}
+
+/// Builds a FeatureCollection, where each record becomes a Feature with its remaining columns as
+/// properties. Pagination state is added as foreign members.
+pub(crate) fn feature_collection(
+  api: &RecordApi,
+  list: ListResponse,
+) -> Result<Value, RecordError> {
+  let Some(geometry_columns) = api.geometry_columns() else {
+    return Err(RecordError::BadRequest("No geometry columns"));
+  };
+  let features = list
+    .records
+    .into_iter()
+    .map(|record| {
+      let Value::Object(mut properties) = record else {
+        return Err(RecordError::Internal("Expected record object".into()));
+      };
+
+      let geometry = take_geometry(api, geometry_columns, &mut properties)?;
+      let id = api.record_id_of(&properties);
+
+      return Ok(json!({
+        "type": "Feature",
+        "id": id,
+        "geometry": geometry,
+        "properties": properties,
+      }));
+    })
+    .collect::<Result<Vec<_>, RecordError>>()?;
+
+  let mut collection = json!({
+    "type": "FeatureCollection",
+    "features": features,
+  });
+  if let Some(cursor) = list.cursor {
+    collection["cursor"] = Value::String(cursor);
+  }
+  if let Some(total_count) = list.total_count {
+    collection["total_count"] = total_count.into();
+  }
+  if let Some(has_more) = list.has_more {
+    collection["has_more"] = has_more.into();
+  }
+
+  return Ok(collection);
+}
+
+pub(crate) fn geojson_response(collection: Value) -> Response {
+  return ([(CONTENT_TYPE, GEOJSON_MIME_TYPE)], Json(collection)).into_response();
+}
+
+#[cfg(test)]
+mod tests {
+  use axum::extract::{Path, RawQuery, State};
+  use trailbase_extension::geometry::geojson_to_wkb;
+
+  use super::*;
+  use crate::app_state::test_state;
+  use crate::config::proto::{PermissionFlag, RecordApiConfig};
+  use crate::extract::AcceptFormat;
+  use crate::records::list_records::list_records_handler;
+  use crate::records::test_utils::{add_record_api_config, json_body};
+
+  #[tokio::test]
+  async fn test_feature_collection() {
+    let state = test_state(None).await.unwrap();
+
+    state
+      .conn()
+      .execute_batch(
+        r#"
+          CREATE TABLE place (
+            id           INTEGER PRIMARY KEY NOT NULL,
+            name         TEXT NOT NULL,
+            area         BLOB CHECK(is_geometry(area))
+          ) STRICT;
+
+          CREATE TABLE station (
+            id           INTEGER PRIMARY KEY NOT NULL,
+            lat          REAL NOT NULL,
+            lng          REAL NOT NULL
+          ) STRICT;
+          INSERT INTO station (id, lat, lng) VALUES (1, 52.5, 13.4);
+
+          CREATE TABLE plain (
+            id           INTEGER PRIMARY KEY NOT NULL
+          ) STRICT;
+        "#,
+      )
+      .await
+      .unwrap();
+
+    let point = json!({"type": "Point", "coordinates": [13.4, 52.5]});
+    state
+      .conn()
+      .execute(
+        "INSERT INTO place (id, name, area) VALUES (1, 'Berlin', $1)",
+        trailbase_sqlite::params!(geojson_to_wkb(&point).unwrap()),
+      )
+      .await
+      .unwrap();
+
+    state.schema_metadata().invalidate_all().await.unwrap();
+
+    for table in ["place", "station", "plain"] {
+      add_record_api_config(
+        &state,
+        RecordApiConfig {
+          name: Some(table.to_string()),
+          table_name: Some(table.to_string()),
+          acl_world: [PermissionFlag::Read as i32].into(),
+          ..Default::default()
+        },
+      )
+      .await
+      .unwrap();
+    }
+
+    let list = async |api: &str| {
+      return list_records_handler(
+        State(state.clone()),
+        Path(api.to_string()),
+        RawQuery(Some("format=geojson".to_string())),
+        None,
+        AcceptFormat::Json,
+      )
+      .await;
+    };
+
+    let response = list("place").await.unwrap();
+    assert_eq!(
+      response.headers().get(CONTENT_TYPE).unwrap(),
+      GEOJSON_MIME_TYPE
+    );
+    let collection: Value = json_body(response).await;
+    assert_eq!(
+      collection["features"][0],
+      json!({
+        "type": "Feature",
+        "id": 1,
+        "geometry": point,
+        "properties": { "id": 1, "name": "Berlin" },
+      })
+    );
+
+    let collection: Value = json_body(list("station").await.unwrap()).await;
+    assert_eq!(collection["features"][0]["geometry"], point);
+    assert_eq!(collection["features"][0]["properties"], json!({ "id": 1 }));
+
+    assert!(list("plain").await.is_err());
+  }
+}
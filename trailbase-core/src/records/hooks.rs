@@ -0,0 +1,174 @@
+use futures_util::future::BoxFuture;
+use log::*;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::auth::user::User;
+use crate::records::RecordError;
+use crate::records::params::JsonRow;
+use crate::util::uuid_to_b64;
+
+/// Point in a record API's write path at which hooks are invoked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HookEvent {
+  BeforeCreate,
+  AfterCreate,
+  BeforeUpdate,
+  AfterUpdate,
+  BeforeDelete,
+  AfterDelete,
+  /// A client pushed a change to a record that was modified since the client's last pull, see the
+  /// sync API.
+  SyncConflict,
+}
+
+/// Context of the operation a hook is invoked for.
+#[derive(Clone, Debug, Serialize)]
+pub struct HookContext {
+  pub event: HookEvent,
+  pub api_name: String,
+  /// Id of the affected record. Unknown before creation.
+  pub record_id: Option<String>,
+  /// Url-safe base64 encoded id of the user performing the operation, if any.
+  pub user_id: Option<String>,
+}
+
+impl HookContext {
+  pub(crate) fn new(
+    event: HookEvent,
+    api_name: &str,
+    record_id: Option<&str>,
+    user: Option<&User>,
+  ) -> Self {
+    return HookContext {
+      event,
+      api_name: api_name.to_string(),
+      record_id: record_id.map(|id| id.to_string()),
+      user_id: user.map(|u| uuid_to_b64(&u.uuid)),
+    };
+  }
+}
+
+/// A record lifecycle hook receiving the operation's context and record.
+///
+/// Before hooks run after access checks and validation, receiving the request's fields for
+/// creates and updates and no record for deletes. They can veto the operation by returning an
+/// error, e.g. `RecordError::Rejected`, or, for creates and updates, return a modified record.
+/// Changed fields are validated again, removed fields are ignored.
+///
+/// After hooks run once the operation was committed, receiving the record as stored, for deletes
+/// the record prior to deletion. Their results are ignored and errors only logged.
+pub type RecordHookFn = dyn Fn(
+    HookContext,
+    Option<serde_json::Map<String, serde_json::Value>>,
+  )
+    -> BoxFuture<'static, Result<Option<serde_json::Map<String, serde_json::Value>>, RecordError>>
+  + Send
+  + Sync;
+
+/// Hooks registered by record API name and event. Cheap to clone.
+#[derive(Clone, Default)]
+pub struct RecordHooks {
+  hooks: Arc<RwLock<HashMap<String, HashMap<HookEvent, Vec<Arc<RecordHookFn>>>>>>,
+}
+
+impl RecordHooks {
+  /// Registers a hook for the given record API. Multiple hooks for the same event run in
+  /// registration order, each receiving the previous one's record.
+  pub fn register(&self, api_name: impl Into<String>, event: HookEvent, hook: Arc<RecordHookFn>) {
+    self
+      .hooks
+      .write()
+      .entry(api_name.into())
+      .or_default()
+      .entry(event)
+      .or_default()
+      .push(hook);
+  }
+
+  pub(crate) fn has(&self, api_name: &str, event: HookEvent) -> bool {
+    return self
+      .hooks
+      .read()
+      .get(api_name)
+      .is_some_and(|hooks| hooks.contains_key(&event));
+  }
+
+  fn get(&self, api_name: &str, event: HookEvent) -> Vec<Arc<RecordHookFn>> {
+    return self
+      .hooks
+      .read()
+      .get(api_name)
+      .and_then(|hooks| hooks.get(&event).cloned())
+      .unwrap_or_default();
+  }
+
+  /// Runs the before hooks, returning only the fields changed by the hooks, if any.
+  pub(crate) async fn run_before(
+    &self,
+    context: HookContext,
+    record: Option<JsonRow>,
+  ) -> Result<Option<JsonRow>, RecordError> {
+    let original = record.clone();
+    let mut current = record;
+    for hook in self.get(&context.api_name, context.event) {
+      if let Some(modified) = hook(context.clone(), current.clone()).await? {
+        current = Some(modified);
+      }
+    }
+
+    let (Some(original), Some(current)) = (original, current) else {
+      return Ok(None);
+    };
+
+    let changed: JsonRow = current
+      .into_iter()
+      .filter(|(key, value)| original.get(key) != Some(value))
+      .collect();
+    if changed.is_empty() {
+      return Ok(None);
+    }
+    return Ok(Some(changed));
+  }
+
+  /// Runs the conflict hooks, which receive `{"client": ..., "server": ...}` records, i.e. null for
+  /// deletions. Returns the first record returned by a hook, if any. Otherwise, the client's change
+  /// wins.
+  pub(crate) async fn resolve_conflict(
+    &self,
+    context: HookContext,
+    client: Option<JsonRow>,
+    server: Option<JsonRow>,
+  ) -> Result<Option<JsonRow>, RecordError> {
+    let mut conflict = JsonRow::new();
+    conflict.insert(
+      "client".to_string(),
+      client.map_or(serde_json::Value::Null, serde_json::Value::Object),
+    );
+    conflict.insert(
+      "server".to_string(),
+      server.map_or(serde_json::Value::Null, serde_json::Value::Object),
+    );
+
+    for hook in self.get(&context.api_name, context.event) {
+      if let Some(resolved) = hook(context.clone(), Some(conflict.clone())).await? {
+        return Ok(Some(resolved));
+      }
+    }
+    return Ok(None);
+  }
+
+  pub(crate) async fn run_after(&self, context: HookContext, record: Option<JsonRow>) {
+    for hook in self.get(&context.api_name, context.event) {
+      if let Err(err) = hook(context.clone(), record.clone()).await {
+        warn!(
+          "{:?} hook for '{}' failed: {err}",
+          context.event, context.api_name
+        );
+      }
+    }
+  }
+}
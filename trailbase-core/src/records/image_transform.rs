@@ -0,0 +1,384 @@
+use axum::body::Body;
+use axum::http::header;
+use axum::response::{IntoResponse, Response};
+use image::{DynamicImage, ImageFormat, ImageReader, imageops::FilterType};
+use log::*;
+use serde::Deserialize;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use trailbase_schema::FileUpload;
+
+use crate::app_state::AppState;
+use crate::data_dir::DataDir;
+
+/// Upper bound for the requested thumbnail width and height.
+const MAX_DIMENSION: u32 = 4096;
+const FILTER: FilterType = FilterType::CatmullRom;
+
+#[derive(Debug, Error)]
+pub enum ImageTransformError {
+  #[error("Invalid parameters: {0}")]
+  InvalidParams(&'static str),
+  #[error("Unsupported image format")]
+  UnsupportedFormat,
+  #[error("Image error: {0}")]
+  Image(#[from] image::ImageError),
+  #[error("Storage error: {0}")]
+  Storage(#[from] object_store::Error),
+  #[error("IO error: {0}")]
+  IO(#[from] std::io::Error),
+  #[error("Join error: {0}")]
+  Join(#[from] tokio::task::JoinError),
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageFit {
+  /// Scale down to fit within the bounds, preserving the aspect ratio. Never upscales.
+  #[default]
+  Contain,
+  /// Scale and crop to fill the bounds, preserving the aspect ratio.
+  Cover,
+  /// Stretch to exactly the bounds.
+  Fill,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageOutputFormat {
+  Jpeg,
+  Png,
+  Webp,
+}
+
+impl ImageOutputFormat {
+  fn from_image_format(format: ImageFormat) -> Option<Self> {
+    return match format {
+      ImageFormat::Jpeg => Some(Self::Jpeg),
+      ImageFormat::Png => Some(Self::Png),
+      ImageFormat::WebP => Some(Self::Webp),
+      _ => None,
+    };
+  }
+
+  fn image_format(self) -> ImageFormat {
+    return match self {
+      Self::Jpeg => ImageFormat::Jpeg,
+      Self::Png => ImageFormat::Png,
+      Self::Webp => ImageFormat::WebP,
+    };
+  }
+
+  fn name(self) -> &'static str {
+    return match self {
+      Self::Jpeg => "jpeg",
+      Self::Png => "png",
+      Self::Webp => "webp",
+    };
+  }
+}
+
+/// Query parameters of the file-serving routes, e.g. `?thumb=200x200&fit=cover&format=webp`.
+#[derive(Debug, Default, Deserialize)]
+pub struct FileQuery {
+  /// Thumbnail bounds as "<width>x<height>", e.g. "200x200".
+  pub thumb: Option<String>,
+  /// How to fit the image into the thumbnail bounds. Defaults to "contain".
+  pub fit: Option<ImageFit>,
+  /// Output format. Defaults to the original's format, or PNG for formats we cannot encode.
+  pub format: Option<ImageOutputFormat>,
+  /// Expiry of a signed URL, see [`crate::records::signed_url`].
+  pub expires: Option<i64>,
+  /// Signature of a signed URL, granting access without further authentication.
+  pub signature: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct ImageTransform {
+  pub size: Option<(u32, u32)>,
+  pub fit: ImageFit,
+  pub format: Option<ImageOutputFormat>,
+}
+
+impl FileQuery {
+  /// Returns the requested transformation, if any.
+  pub(crate) fn image_transform(&self) -> Result<Option<ImageTransform>, ImageTransformError> {
+    if self.thumb.is_none() && self.format.is_none() {
+      if self.fit.is_some() {
+        return Err(ImageTransformError::InvalidParams("'fit' requires 'thumb'"));
+      }
+      return Ok(None);
+    }
+
+    return Ok(Some(ImageTransform {
+      size: self.thumb.as_deref().map(parse_dimensions).transpose()?,
+      fit: self.fit.unwrap_or_default(),
+      format: self.format,
+    }));
+  }
+}
+
+fn parse_dimensions(thumb: &str) -> Result<(u32, u32), ImageTransformError> {
+  let invalid = || ImageTransformError::InvalidParams("Expected thumb=<width>x<height>");
+
+  let (width, height) = thumb.split_once('x').ok_or_else(invalid)?;
+  let width: u32 = width.parse().map_err(|_| invalid())?;
+  let height: u32 = height.parse().map_err(|_| invalid())?;
+
+  if width == 0 || height == 0 || width > MAX_DIMENSION || height > MAX_DIMENSION {
+    return Err(ImageTransformError::InvalidParams(
+      "Thumbnail dimensions out of range",
+    ));
+  }
+
+  return Ok((width, height));
+}
+
+/// Applies `transform` to the encoded image `data`. The input format is sniffed from the contents
+/// rather than trusting the content type provided on upload.
+pub(crate) fn transform_image(
+  data: &[u8],
+  transform: &ImageTransform,
+) -> Result<(Vec<u8>, ImageOutputFormat), ImageTransformError> {
+  let input_format =
+    image::guess_format(data).map_err(|_| ImageTransformError::UnsupportedFormat)?;
+  let output_format = transform
+    .format
+    .or_else(|| ImageOutputFormat::from_image_format(input_format))
+    .unwrap_or(ImageOutputFormat::Png);
+
+  // NOTE: The reader applies default allocation limits protecting us from decompression bombs.
+  let mut image = ImageReader::with_format(Cursor::new(data), input_format)
+    .decode()
+    .map_err(|err| match err {
+      image::ImageError::Unsupported(_) => ImageTransformError::UnsupportedFormat,
+      err => err.into(),
+    })?;
+
+  if let Some((width, height)) = transform.size {
+    image = match transform.fit {
+      ImageFit::Contain if image.width() <= width && image.height() <= height => image,
+      ImageFit::Contain => image.resize(width, height, FILTER),
+      ImageFit::Cover => image.resize_to_fill(width, height, FILTER),
+      ImageFit::Fill => image.resize_exact(width, height, FILTER),
+    };
+  }
+
+  // The JPEG encoder doesn't support alpha channels and neither encoder supports 16-bit colors.
+  image = match output_format {
+    ImageOutputFormat::Jpeg => DynamicImage::ImageRgb8(image.to_rgb8()),
+    ImageOutputFormat::Webp => DynamicImage::ImageRgba8(image.to_rgba8()),
+    ImageOutputFormat::Png => image,
+  };
+
+  let mut buffer = Cursor::new(Vec::<u8>::new());
+  image.write_to(&mut buffer, output_format.image_format())?;
+
+  return Ok((buffer.into_inner(), output_format));
+}
+
+/// Directory holding all cached variants of `file`. File ids are immutable, i.e. changing a file
+/// column's contents results in a new id, thus variants never go stale.
+fn variants_dir(data_dir: &DataDir, file: &FileUpload) -> Option<PathBuf> {
+  let id = file.path();
+  if id.is_empty()
+    || !id
+      .chars()
+      .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+  {
+    return None;
+  }
+  return Some(data_dir.image_cache_path().join(id));
+}
+
+fn variant_path(
+  data_dir: &DataDir,
+  file: &FileUpload,
+  transform: &ImageTransform,
+) -> Option<PathBuf> {
+  let size = transform
+    .size
+    .map_or_else(|| "original".to_string(), |(w, h)| format!("{w}x{h}"));
+  let fit = match transform.fit {
+    ImageFit::Contain => "contain",
+    ImageFit::Cover => "cover",
+    ImageFit::Fill => "fill",
+  };
+  let format = transform.format.map_or("auto", |f| f.name());
+
+  return variants_dir(data_dir, file).map(|dir| dir.join(format!("{size}_{fit}_{format}")));
+}
+
+async fn write_variant(path: &Path, contents: &[u8]) -> Result<(), std::io::Error> {
+  if let Some(parent) = path.parent() {
+    tokio::fs::create_dir_all(parent).await?;
+  }
+
+  // Write to a temporary file first to not serve partially written variants to concurrent
+  // requests.
+  let tmp = path.with_extension(format!("{}.tmp", uuid::Uuid::now_v7()));
+  tokio::fs::write(&tmp, contents).await?;
+  if let Err(err) = tokio::fs::rename(&tmp, path).await {
+    let _ = tokio::fs::remove_file(&tmp).await;
+    return Err(err);
+  }
+
+  return Ok(());
+}
+
+/// Removes all cached variants of `file`, e.g. after the file itself has been deleted.
+pub(crate) async fn remove_cached_variants(data_dir: &DataDir, file: &FileUpload) {
+  let Some(dir) = variants_dir(data_dir, file) else {
+    return;
+  };
+
+  match tokio::fs::remove_dir_all(&dir).await {
+    Ok(_) => {}
+    Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+    Err(err) => warn!("Failed to remove cached image variants {dir:?}: {err}"),
+  };
+}
+
+fn image_response(contents: Vec<u8>) -> Response {
+  let content_type =
+    image::guess_format(&contents).map_or("application/octet-stream", |f| f.to_mime_type());
+
+  return (
+    [
+      (header::CONTENT_TYPE, content_type.to_string()),
+      (header::CONTENT_DISPOSITION, "inline".to_string()),
+    ],
+    Body::from(contents),
+  )
+    .into_response();
+}
+
+/// Serves a derived variant of the image `file_upload`, transforming the original and populating
+/// the on-disk cache on first access.
+pub(crate) async fn read_transformed_image_into_response(
+  state: &AppState,
+  file_upload: FileUpload,
+  transform: ImageTransform,
+) -> Result<Response, ImageTransformError> {
+  let cache_path = variant_path(state.data_dir(), &file_upload, &transform);
+  if let Some(ref path) = cache_path {
+    if let Ok(contents) = tokio::fs::read(path).await {
+      return Ok(image_response(contents));
+    }
+  }
+
+  let path = object_store::path::Path::from(file_upload.path());
+  let data = state.objectstore().get(&path).await?.bytes().await?;
+
+  let (contents, _format) =
+    tokio::task::spawn_blocking(move || transform_image(&data, &transform)).await??;
+
+  if let Some(ref path) = cache_path {
+    if let Err(err) = write_variant(path, &contents).await {
+      warn!("Failed to cache image variant {path:?}: {err}");
+    }
+  }
+
+  return Ok(image_response(contents));
+}
+
+#[cfg(test)]
+mod tests {
+  use image::{Rgba, RgbaImage};
+
+  use super::*;
+
+  fn encode_png(width: u32, height: u32) -> Vec<u8> {
+    let image = RgbaImage::from_pixel(width, height, Rgba([255, 0, 0, 128]));
+    let mut buffer = Cursor::new(Vec::<u8>::new());
+    image.write_to(&mut buffer, ImageFormat::Png).unwrap();
+    return buffer.into_inner();
+  }
+
+  fn dimensions(data: &[u8]) -> (u32, u32) {
+    let image = image::load_from_memory(data).unwrap();
+    return (image.width(), image.height());
+  }
+
+  #[test]
+  fn test_file_query() {
+    assert_eq!(None, FileQuery::default().image_transform().unwrap());
+
+    let query = FileQuery {
+      thumb: Some("200x100".to_string()),
+      fit: Some(ImageFit::Cover),
+      format: Some(ImageOutputFormat::Webp),
+      ..Default::default()
+    };
+    assert_eq!(
+      Some(ImageTransform {
+        size: Some((200, 100)),
+        fit: ImageFit::Cover,
+        format: Some(ImageOutputFormat::Webp),
+      }),
+      query.image_transform().unwrap()
+    );
+
+    for thumb in ["200", "0x100", "x100", "axb", "100000x10", "-1x10"] {
+      let query = FileQuery {
+        thumb: Some(thumb.to_string()),
+        ..Default::default()
+      };
+      assert!(query.image_transform().is_err(), "{thumb}");
+    }
+
+    let query = FileQuery {
+      fit: Some(ImageFit::Fill),
+      ..Default::default()
+    };
+    assert!(query.image_transform().is_err());
+  }
+
+  #[test]
+  fn test_transform_image() {
+    let png = encode_png(400, 200);
+
+    let transform = |size: (u32, u32), fit: ImageFit, format: Option<ImageOutputFormat>| {
+      return transform_image(
+        &png,
+        &ImageTransform {
+          size: Some(size),
+          fit,
+          format,
+        },
+      )
+      .unwrap();
+    };
+
+    let (data, format) = transform((100, 100), ImageFit::Contain, None);
+    assert_eq!(ImageOutputFormat::Png, format);
+    assert_eq!((100, 50), dimensions(&data));
+
+    // Contain doesn't upscale.
+    let (data, _) = transform((1000, 1000), ImageFit::Contain, None);
+    assert_eq!((400, 200), dimensions(&data));
+
+    let (data, format) = transform((100, 100), ImageFit::Cover, Some(ImageOutputFormat::Webp));
+    assert_eq!(ImageOutputFormat::Webp, format);
+    assert_eq!(ImageFormat::WebP, image::guess_format(&data).unwrap());
+    assert_eq!((100, 100), dimensions(&data));
+
+    let (data, _) = transform((50, 80), ImageFit::Fill, Some(ImageOutputFormat::Jpeg));
+    assert_eq!(ImageFormat::Jpeg, image::guess_format(&data).unwrap());
+    assert_eq!((50, 80), dimensions(&data));
+
+    // Content is sniffed and non-images are rejected.
+    assert!(matches!(
+      transform_image(
+        b"not an image",
+        &ImageTransform {
+          size: Some((10, 10)),
+          fit: ImageFit::Contain,
+          format: None,
+        }
+      ),
+      Err(ImageTransformError::UnsupportedFormat)
+    ));
+  }
+}
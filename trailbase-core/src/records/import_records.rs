@@ -0,0 +1,848 @@
+use axum::body::Bytes;
+use axum::extract::{Json, Path, Query, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use log::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use trailbase_schema::sqlite::{Column, ColumnDataType};
+use trailbase_sqlite::params;
+use ts_rs::TS;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::app_state::AppState;
+use crate::audit::{AuditAction, audit_record_mutation, read_record_json, snapshot_record};
+use crate::auth::user::User;
+use crate::config::proto::WebhookEvent;
+use crate::records::create_record::extract_record_id;
+use crate::records::hooks::{HookContext, HookEvent};
+use crate::records::params::{JsonRow, LazyParams, Params, ParamsError};
+use crate::records::query_builder::{InsertQueryBuilder, QueryError};
+use crate::records::webhooks::enqueue_webhooks;
+use crate::records::{Permission, RecordApi, RecordError};
+use crate::schema_metadata::JsonColumnMetadata;
+use crate::util::{b64_to_uuid, uuid_to_b64};
+
+/// Number of rows inserted per transaction.
+const BATCH_SIZE: usize = 256;
+
+/// Upper bound on individually reported row errors to keep reports reasonably sized.
+const MAX_REPORTED_ERRORS: usize = 1000;
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportFormat {
+  Csv,
+  Ndjson,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, IntoParams)]
+pub struct ImportRecordsQuery {
+  /// Input format. Inferred from the content type if absent and defaults to CSV.
+  pub format: Option<ImportFormat>,
+  /// JSON object mapping CSV headers or NDJSON keys onto column names, e.g.
+  /// `{"First Name": "first_name"}`. Mapping onto "" ignores the input field.
+  pub mapping: Option<String>,
+  /// Process the import in the background and return a job id to poll instead.
+  #[serde(rename = "async")]
+  pub run_async: Option<bool>,
+}
+
+/// Failure to import a specific row.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize, ToSchema, TS)]
+#[ts(export)]
+pub struct ImportRowError {
+  /// 1-based index of the record in the input, excluding the CSV header.
+  pub row: usize,
+  pub message: String,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize, ToSchema, TS)]
+#[ts(export)]
+pub struct ImportReport {
+  pub total_rows: usize,
+  pub imported_rows: usize,
+  pub failed_rows: usize,
+  /// Row errors, capped at the first 1000.
+  pub errors: Vec<ImportRowError>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema, TS)]
+#[ts(export)]
+pub struct ImportJobResponse {
+  /// Url-safe Base64 encoded id of the import job.
+  pub id: String,
+  /// One of: "pending", "running", "completed", "failed".
+  pub status: String,
+  /// Present once the job has completed.
+  pub report: Option<ImportReport>,
+}
+
+type InputRow = (usize, Result<JsonRow, String>);
+
+pub(crate) fn coerce_csv_value(
+  column: &Column,
+  json_metadata: Option<&JsonColumnMetadata>,
+  value: &str,
+) -> serde_json::Value {
+  // Nested JSON, e.g. for JSON schema columns, is expected to be serialized into the cell.
+  if json_metadata.is_some() {
+    if let Ok(json @ (serde_json::Value::Object(_) | serde_json::Value::Array(_))) =
+      serde_json::from_str(value)
+    {
+      return json;
+    }
+  }
+
+  let is_integer = matches!(
+    column.data_type,
+    ColumnDataType::Integer
+      | ColumnDataType::Int
+      | ColumnDataType::Numeric
+      | ColumnDataType::Boolean
+      | ColumnDataType::TinyInt
+  );
+  if is_integer {
+    if value.eq_ignore_ascii_case("true") {
+      return serde_json::Value::Bool(true);
+    } else if value.eq_ignore_ascii_case("false") {
+      return serde_json::Value::Bool(false);
+    }
+  }
+
+  // Everything else is parsed according to the column's type during param conversion.
+  return serde_json::Value::String(value.to_string());
+}
+
+fn map_field<'a>(mapping: &'a HashMap<String, String>, field: &'a str) -> Option<&'a str> {
+  return match mapping.get(field) {
+    Some(column) if column.is_empty() => None,
+    Some(column) => Some(column),
+    None => Some(field),
+  };
+}
+
+fn parse_csv(
+  api: &RecordApi,
+  mapping: &HashMap<String, String>,
+  data: &[u8],
+) -> Result<Vec<InputRow>, RecordError> {
+  let data = data.strip_prefix("\u{FEFF}".as_bytes()).unwrap_or(data);
+  let mut reader = csv::ReaderBuilder::new()
+    .has_headers(true)
+    .trim(csv::Trim::Headers)
+    .from_reader(data);
+
+  let headers = reader
+    .headers()
+    .map_err(|_err| RecordError::BadRequest("Invalid CSV header"))?
+    .clone();
+
+  let mut column_indexes: Vec<Option<usize>> = Vec::with_capacity(headers.len());
+  for header in &headers {
+    column_indexes.push(match map_field(mapping, header) {
+      Some(column) => Some(
+        api
+          .column_index_by_name(column)
+          .ok_or(RecordError::BadRequest("CSV header without matching column"))?,
+      ),
+      None => None,
+    });
+  }
+
+  let mut rows: Vec<InputRow> = vec![];
+  for (index, record) in reader.records().enumerate() {
+    let row = index + 1;
+    let record = match record {
+      Ok(record) => record,
+      Err(err) => {
+        rows.push((row, Err(err.to_string())));
+        continue;
+      }
+    };
+
+    let mut json_row = JsonRow::new();
+    for (value, column_index) in record.iter().zip(&column_indexes) {
+      // Empty cells are omitted to let column defaults apply.
+      let Some(column_index) = column_index else {
+        continue;
+      };
+      if value.is_empty() {
+        continue;
+      }
+
+      let column = &api.columns()[*column_index];
+      json_row.insert(
+        column.name.clone(),
+        coerce_csv_value(
+          column,
+          api.json_column_metadata()[*column_index].as_ref(),
+          value,
+        ),
+      );
+    }
+    rows.push((row, Ok(json_row)));
+  }
+
+  return Ok(rows);
+}
+
+fn parse_ndjson(mapping: &HashMap<String, String>, data: &[u8]) -> Vec<InputRow> {
+  return data
+    .split(|b| *b == b'\n')
+    .filter(|line| !line.trim_ascii().is_empty())
+    .enumerate()
+    .map(|(index, line)| {
+      let row = index + 1;
+      return match serde_json::from_slice::<serde_json::Value>(line) {
+        Ok(serde_json::Value::Object(object)) => (
+          row,
+          Ok(
+            object
+              .into_iter()
+              .filter_map(|(key, value)| Some((map_field(mapping, &key)?.to_string(), value)))
+              .collect(),
+          ),
+        ),
+        Ok(_) => (row, Err("Expected JSON object".to_string())),
+        Err(err) => (row, Err(err.to_string())),
+      };
+    })
+    .collect();
+}
+
+fn params_error_message(err: ParamsError) -> String {
+  return match err {
+    ParamsError::FieldValidation(errors) => errors
+      .into_iter()
+      .map(|e| format!("{}: {}", e.field, e.message))
+      .collect::<Vec<_>>()
+      .join(", "),
+    err => err.to_string(),
+  };
+}
+
+fn insert_error_message(err: QueryError) -> String {
+  return match err {
+    QueryError::TokioRusqlite(err) => match RecordError::from(err) {
+      RecordError::BadRequest(msg) => msg.to_string(),
+      RecordError::Constraint(_, msg) | RecordError::Conflict(_, msg) => msg,
+      _ => "Internal error".to_string(),
+    },
+    _ => "Internal error".to_string(),
+  };
+}
+
+/// Builds the params for inserting `record` running before-create hooks unless `run_hooks` is
+/// false, e.g. when retrying a record they already ran for. Returns the record including the
+/// hooks' changes alongside.
+async fn build_params(
+  state: &AppState,
+  api: &RecordApi,
+  user: Option<&User>,
+  mut record: JsonRow,
+  run_hooks: bool,
+) -> Result<(Params, JsonRow), String> {
+  if api.insert_autofill_missing_user_id_columns() {
+    if let Some(user) = user {
+      for column_index in api.user_id_columns() {
+        let col_name = &api.columns()[*column_index].name;
+        if !record.contains_key(col_name) {
+          record.insert(
+            col_name.to_owned(),
+            serde_json::Value::String(uuid_to_b64(&user.uuid)),
+          );
+        }
+      }
+    }
+  }
+
+  let hooks = state.record_hooks();
+  let hook_record =
+    (run_hooks && hooks.has(api.api_name(), HookEvent::BeforeCreate)).then(|| record.clone());
+
+  let mut lazy_params = LazyParams::new(api, record.clone(), None);
+  api
+    .check_record_level_access(Permission::Create, None, Some(&mut lazy_params), user)
+    .await
+    .map_err(|err| err.to_string())?;
+
+  let mut params = lazy_params.consume().map_err(params_error_message)?;
+  if let Some(hook_record) = hook_record {
+    let context = HookContext::new(HookEvent::BeforeCreate, api.api_name(), None, user);
+    let changed = hooks
+      .run_before(context, Some(hook_record))
+      .await
+      .map_err(|err| match err {
+        RecordError::Rejected(msg) => msg,
+        err => err.to_string(),
+      })?;
+    if let Some(changed) = changed {
+      params
+        .override_with(api, changed.clone())
+        .map_err(params_error_message)?;
+      record.extend(changed);
+    }
+  }
+
+  return Ok((params, record));
+}
+
+/// Runs the side-effects of record creation for imported records, i.e. webhooks, after-create
+/// hooks and audit entries.
+async fn after_import(state: &AppState, api: &RecordApi, user: Option<&User>, ids: &[String]) {
+  enqueue_webhooks(state, api, WebhookEvent::Create, ids).await;
+
+  let hooks = state.record_hooks();
+  if hooks.has(api.api_name(), HookEvent::AfterCreate) {
+    for record_id in ids {
+      let record = read_record_json(state, api, record_id).await;
+      let context = HookContext::new(
+        HookEvent::AfterCreate,
+        api.api_name(),
+        Some(record_id),
+        user,
+      );
+      hooks
+        .run_after(context, record.and_then(|r| r.as_object().cloned()))
+        .await;
+    }
+  }
+
+  if user.is_some() {
+    for record_id in ids {
+      let after = snapshot_record(state, api, record_id).await;
+      audit_record_mutation(
+        state,
+        user,
+        api,
+        AuditAction::Create,
+        record_id,
+        None,
+        after,
+      )
+      .await;
+    }
+  }
+}
+
+fn add_error(report: &mut ImportReport, row: usize, message: String) {
+  report.failed_rows += 1;
+  if report.errors.len() < MAX_REPORTED_ERRORS {
+    report.errors.push(ImportRowError { row, message });
+  }
+}
+
+/// Inserts the given rows in batched transactions.
+///
+/// If a batch fails, e.g. due to a constraint violation, its rows are retried individually to
+/// pinpoint the failing rows.
+async fn import_rows(
+  state: &AppState,
+  api: &RecordApi,
+  user: Option<&User>,
+  rows: Vec<InputRow>,
+) -> ImportReport {
+  let mut report = ImportReport {
+    total_rows: rows.len(),
+    ..Default::default()
+  };
+
+  let (_index, pk_column) = api.record_pk_column();
+  for batch in rows.chunks(BATCH_SIZE) {
+    let mut valid: Vec<(usize, JsonRow)> = Vec::with_capacity(batch.len());
+    let mut params_list: Vec<Params> = Vec::with_capacity(batch.len());
+
+    for (row, record) in batch {
+      let record = match record {
+        Ok(record) => record,
+        Err(err) => {
+          add_error(&mut report, *row, err.clone());
+          continue;
+        }
+      };
+
+      match build_params(state, api, user, record.clone(), true).await {
+        Ok((params, record)) => {
+          valid.push((*row, record));
+          params_list.push(params);
+        }
+        Err(err) => add_error(&mut report, *row, err),
+      }
+    }
+
+    if params_list.is_empty() {
+      continue;
+    }
+
+    let result = InsertQueryBuilder::run_bulk(
+      state,
+      api.table_name(),
+      api.insert_conflict_resolution_strategy(),
+      None,
+      &pk_column.name,
+      api.has_file_columns(),
+      params_list,
+    )
+    .await;
+
+    match result {
+      Ok(ids) => {
+        report.imported_rows += ids.len();
+
+        let ids: Vec<String> = ids
+          .into_iter()
+          .filter_map(|(id, _inserted)| extract_record_id(id).ok())
+          .collect();
+        after_import(state, api, user, &ids).await;
+      }
+      Err(err) => {
+        debug!("Import batch failed, retrying rows individually: {err}");
+
+        for (row, record) in valid {
+          let params = match build_params(state, api, user, record, false).await {
+            Ok((params, _record)) => params,
+            Err(err) => {
+              add_error(&mut report, row, err);
+              continue;
+            }
+          };
+
+          match InsertQueryBuilder::run(
+            state,
+            api.table_name(),
+            api.insert_conflict_resolution_strategy(),
+            &pk_column.name,
+            api.has_file_columns(),
+            params,
+          )
+          .await
+          {
+            Ok(id) => {
+              report.imported_rows += 1;
+
+              if let Ok(id) = extract_record_id(id) {
+                after_import(state, api, user, &[id]).await;
+              }
+            }
+            Err(err) => add_error(&mut report, row, insert_error_message(err)),
+          }
+        }
+      }
+    }
+  }
+
+  return report;
+}
+
+async fn run_import_job(
+  state: AppState,
+  api: RecordApi,
+  user: Option<User>,
+  id: uuid::Uuid,
+  rows: Vec<InputRow>,
+) {
+  let conn = state.conn();
+  let id_blob: Vec<u8> = id.into_bytes().to_vec();
+
+  if let Err(err) = conn
+    .execute(
+      "UPDATE _import_job SET status = 'running', updated = UNIXEPOCH() WHERE id = $1",
+      params!(id_blob.clone()),
+    )
+    .await
+  {
+    warn!("Failed to update import job: {err}");
+  }
+
+  let report = import_rows(&state, &api, user.as_ref(), rows).await;
+  let (status, report) = match serde_json::to_string(&report) {
+    Ok(report) => ("completed", Some(report)),
+    Err(err) => {
+      warn!("Failed to serialize import report: {err}");
+      ("failed", None)
+    }
+  };
+
+  if let Err(err) = conn
+    .execute(
+      "UPDATE _import_job SET status = $2, report = $3, updated = UNIXEPOCH() WHERE id = $1",
+      params!(id_blob, status, report),
+    )
+    .await
+  {
+    warn!("Failed to update import job: {err}");
+  }
+}
+
+/// Imports records from CSV or NDJSON.
+///
+/// Rows are inserted in batched transactions subject to the API's create access rules. Invalid
+/// rows are skipped and reported individually.
+#[utoipa::path(
+  post,
+  path = "/:name/import",
+  params(ImportRecordsQuery),
+  request_body = String,
+  responses(
+    (status = 200, description = "Import report.", body = ImportReport),
+    (status = 202, description = "Background import job.", body = ImportJobResponse),
+  )
+)]
+pub async fn import_records_handler(
+  State(state): State<AppState>,
+  Path(api_name): Path<String>,
+  Query(query): Query<ImportRecordsQuery>,
+  user: Option<User>,
+  headers: HeaderMap,
+  body: Bytes,
+) -> Result<Response, RecordError> {
+  let Some(api) = state.lookup_record_api(&api_name) else {
+    return Err(RecordError::ApiNotFound);
+  };
+  if !api.is_table() {
+    return Err(RecordError::ApiRequiresTable);
+  }
+
+  // Fail early, row-level access is checked for every record.
+  api.check_table_level_access(Permission::Create, user.as_ref())?;
+
+  let mapping: HashMap<String, String> = match query.mapping {
+    Some(ref mapping) => serde_json::from_str(mapping)
+      .map_err(|_err| RecordError::BadRequest("Invalid mapping"))?,
+    None => HashMap::new(),
+  };
+
+  let format = query.format.unwrap_or_else(|| {
+    let content_type = headers
+      .get(header::CONTENT_TYPE)
+      .and_then(|v| v.to_str().ok())
+      .unwrap_or_default();
+    if content_type.starts_with("application/x-ndjson")
+      || content_type.starts_with("application/jsonl")
+    {
+      ImportFormat::Ndjson
+    } else {
+      ImportFormat::Csv
+    }
+  });
+
+  let rows = match format {
+    ImportFormat::Csv => parse_csv(&api, &mapping, &body)?,
+    ImportFormat::Ndjson => parse_ndjson(&mapping, &body),
+  };
+
+  if query.run_async != Some(true) {
+    return Ok(Json(import_rows(&state, &api, user.as_ref(), rows).await).into_response());
+  }
+
+  let id = uuid::Uuid::now_v7();
+  state
+    .conn()
+    .execute(
+      "INSERT INTO _import_job (id, api_name, user) VALUES ($1, $2, $3)",
+      params!(
+        id.into_bytes().to_vec(),
+        api_name,
+        user.as_ref().map(|u| u.uuid.into_bytes().to_vec())
+      ),
+    )
+    .await?;
+
+  tokio::spawn(run_import_job(state.clone(), api, user, id, rows));
+
+  return Ok(
+    (
+      StatusCode::ACCEPTED,
+      Json(ImportJobResponse {
+        id: uuid_to_b64(&id),
+        status: "pending".to_string(),
+        report: None,
+      }),
+    )
+      .into_response(),
+  );
+}
+
+/// Reads the status of a background import job.
+#[utoipa::path(
+  get,
+  path = "/:name/import/:job",
+  responses(
+    (status = 200, description = "Import job status.", body = ImportJobResponse),
+  )
+)]
+pub async fn import_job_handler(
+  State(state): State<AppState>,
+  Path((api_name, job_id)): Path<(String, String)>,
+  user: Option<User>,
+) -> Result<Json<ImportJobResponse>, RecordError> {
+  let id = b64_to_uuid(&job_id).map_err(|_err| RecordError::BadRequest("Invalid job id"))?;
+
+  let Some(row) = state
+    .conn()
+    .read_query_row(
+      "SELECT user, status, report FROM _import_job WHERE id = $1 AND api_name = $2",
+      params!(id.into_bytes().to_vec(), api_name),
+    )
+    .await?
+  else {
+    return Err(RecordError::RecordNotFound);
+  };
+
+  // Jobs started by authenticated users are only visible to the same user.
+  let job_user: Option<Vec<u8>> = row
+    .get(0)
+    .map_err(|err| RecordError::Internal(err.into()))?;
+  if let Some(job_user) = job_user {
+    if user.is_none_or(|u| u.uuid.as_bytes()[..] != job_user[..]) {
+      return Err(RecordError::Forbidden);
+    }
+  }
+
+  let status: String = row
+    .get(1)
+    .map_err(|err| RecordError::Internal(err.into()))?;
+  let report: Option<String> = row
+    .get(2)
+    .map_err(|err| RecordError::Internal(err.into()))?;
+
+  return Ok(Json(ImportJobResponse {
+    id: job_id,
+    status,
+    report: report
+      .map(|r| serde_json::from_str(&r))
+      .transpose()
+      .map_err(|err| RecordError::Internal(err.into()))?,
+  }));
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::admin::user::create_user_for_test;
+  use crate::app_state::test_state;
+  use crate::config::proto::{AuditLogConfig, PermissionFlag, RecordApiConfig, WebhookConfig};
+  use crate::constants::{AUDIT_LOG_TABLE, WEBHOOK_DELIVERY_TABLE};
+  use crate::records::test_utils::add_record_api_config;
+
+  async fn import(state: &AppState, query: ImportRecordsQuery, body: &str) -> Response {
+    return import_as(state, query, None, body).await;
+  }
+
+  async fn import_as(
+    state: &AppState,
+    query: ImportRecordsQuery,
+    user: Option<User>,
+    body: &str,
+  ) -> Response {
+    return import_records_handler(
+      State(state.clone()),
+      Path("api".to_string()),
+      Query(query),
+      user,
+      HeaderMap::new(),
+      Bytes::from(body.to_string()),
+    )
+    .await
+    .unwrap();
+  }
+
+  async fn report(response: Response) -> ImportReport {
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+      .await
+      .unwrap();
+    return serde_json::from_slice(&body).unwrap();
+  }
+
+  #[tokio::test]
+  async fn test_record_api_import() {
+    let state = test_state(None).await.unwrap();
+
+    state
+      .conn()
+      .execute_batch(
+        r#"
+        CREATE TABLE 'table' (
+          id      INTEGER PRIMARY KEY,
+          name    TEXT NOT NULL UNIQUE,
+          active  INTEGER NOT NULL DEFAULT FALSE,
+          score   REAL
+        ) STRICT;
+      "#,
+      )
+      .await
+      .unwrap();
+
+    state.schema_metadata().invalidate_all().await.unwrap();
+
+    add_record_api_config(
+      &state,
+      RecordApiConfig {
+        name: Some("api".to_string()),
+        table_name: Some("table".to_string()),
+        acl_world: [PermissionFlag::Create as i32, PermissionFlag::Read as i32].into(),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    let response = import(
+      &state,
+      ImportRecordsQuery {
+        mapping: Some(r#"{"Name": "name", "Ignored": ""}"#.to_string()),
+        ..Default::default()
+      },
+      "Name,active,score,Ignored\r\na,true,1.5,x\r\nb,,,\r\nc,maybe,,\r\na,false,,\r\n",
+    )
+    .await;
+
+    assert_eq!(
+      ImportReport {
+        total_rows: 4,
+        imported_rows: 2,
+        failed_rows: 2,
+        errors: vec![
+          ImportRowError {
+            row: 3,
+            message: "Parse int error: invalid digit found in string".to_string(),
+          },
+          ImportRowError {
+            row: 4,
+            message: "sqlite constraint: unique".to_string(),
+          },
+        ],
+      },
+      report(response).await
+    );
+
+    let response = import(
+      &state,
+      ImportRecordsQuery {
+        format: Some(ImportFormat::Ndjson),
+        ..Default::default()
+      },
+      "{\"name\": \"d\", \"active\": true}\n\n[]\n",
+    )
+    .await;
+
+    let report = report(response).await;
+    assert_eq!(2, report.total_rows);
+    assert_eq!(1, report.imported_rows);
+    assert_eq!(2, report.errors[0].row);
+
+    let count: i64 = state
+      .conn()
+      .read_query_row_f("SELECT COUNT(*) FROM 'table'", (), |row| row.get(0))
+      .await
+      .unwrap()
+      .unwrap();
+    assert_eq!(3, count);
+
+    // Unknown columns are rejected.
+    assert!(
+      import_records_handler(
+        State(state.clone()),
+        Path("api".to_string()),
+        Query(ImportRecordsQuery::default()),
+        None,
+        HeaderMap::new(),
+        Bytes::from("unknown\r\nx\r\n"),
+      )
+      .await
+      .is_err()
+    );
+  }
+
+  #[tokio::test]
+  async fn test_record_api_import_side_effects() {
+    let state = test_state(None).await.unwrap();
+
+    state
+      .conn()
+      .execute_batch(
+        "CREATE TABLE item (id INTEGER PRIMARY KEY, name TEXT NOT NULL UNIQUE) STRICT;",
+      )
+      .await
+      .unwrap();
+    state.schema_metadata().invalidate_all().await.unwrap();
+
+    let mut config = state.get_config();
+    config.server.audit_log = Some(AuditLogConfig {
+      enabled: Some(true),
+      retention_sec: None,
+    });
+    config.server.webhook_signing_key = Some("secret".to_string());
+    state
+      .validate_and_update_config(config, None)
+      .await
+      .unwrap();
+
+    add_record_api_config(
+      &state,
+      RecordApiConfig {
+        name: Some("api".to_string()),
+        table_name: Some("item".to_string()),
+        acl_authenticated: [PermissionFlag::Create as i32].into(),
+        webhooks: vec![WebhookConfig {
+          url: Some("http://127.0.0.1:1/hook".to_string()),
+          events: vec![WebhookEvent::Create as i32],
+        }],
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    let user_id = create_user_for_test(&state, "user@bar.org", "Secret!1!!")
+      .await
+      .unwrap();
+    let user = User::from_unverified(user_id, "user@bar.org");
+
+    // The failing batch is retried row by row, which must emit side-effects for the remaining rows
+    // too.
+    let response = import_as(
+      &state,
+      ImportRecordsQuery::default(),
+      Some(user),
+      "id,name\r\n1,a\r\n2,b\r\n3,a\r\n",
+    )
+    .await;
+    assert_eq!(2, report(response).await.imported_rows);
+
+    #[derive(Debug, serde::Deserialize)]
+    struct Entry {
+      action: String,
+      record_id: String,
+    }
+
+    let entries = state
+      .conn()
+      .read_query_as::<Entry>(
+        format!("SELECT action, record_id FROM {AUDIT_LOG_TABLE} ORDER BY id"),
+        (),
+      )
+      .await
+      .unwrap();
+    assert_eq!(
+      entries
+        .iter()
+        .map(|e| (e.action.as_str(), e.record_id.as_str()))
+        .collect::<Vec<_>>(),
+      [("create", "1"), ("create", "2")]
+    );
+
+    let webhook_count: i64 = state
+      .conn()
+      .read_query_row_f(
+        format!("SELECT COUNT(*) FROM {WEBHOOK_DELIVERY_TABLE} WHERE event = 'create'"),
+        (),
+        |row| row.get(0),
+      )
+      .await
+      .unwrap()
+      .unwrap();
+    assert_eq!(2, webhook_count);
+  }
+}
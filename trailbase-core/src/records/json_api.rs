@@ -0,0 +1,283 @@
+//! JSON:API documents for record APIs configured with `RESPONSE_FORMAT_JSON_API`.
+//!
+//! See https://jsonapi.org/format/.
+
+use axum::Json;
+use axum::http::header::CONTENT_TYPE;
+use axum::response::{IntoResponse, Response};
+use serde_json::{Map, Value, json};
+use trailbase_schema::sqlite::{Column, ColumnOption};
+
+use crate::records::RecordApi;
+use crate::records::list_records::ListResponse;
+
+pub const JSON_API_MIME_TYPE: &str = "application/vnd.api+json";
+
+/// JSON:API requires resource identifiers to be strings.
+fn resource_id(id: Option<Value>) -> Value {
+  return match id {
+    Some(Value::String(id)) => Value::String(id),
+    Some(Value::Null) | None => Value::Null,
+    Some(id) => Value::String(id.to_string()),
+  };
+}
+
+/// Returns the foreign table and referred column of foreign key columns.
+fn foreign_key(column: &Column) -> Option<(&str, Option<&str>)> {
+  return column.options.iter().find_map(|option| match option {
+    ColumnOption::ForeignKey {
+      foreign_table,
+      referred_columns,
+      ..
+    } => Some((
+      foreign_table.as_str(),
+      referred_columns.first().map(|c| c.as_str()),
+    )),
+    _ => None,
+  });
+}
+
+struct DocumentBuilder<'a> {
+  api: &'a RecordApi,
+  included: Vec<Value>,
+}
+
+impl<'a> DocumentBuilder<'a> {
+  fn new(api: &'a RecordApi) -> Self {
+    return Self {
+      api,
+      included: vec![],
+    };
+  }
+
+  /// Turns a plain JSON record into a resource object. Expandable foreign key columns become
+  /// relationships and expanded foreign records are collected as included resources.
+  fn resource(&mut self, record: Value) -> Value {
+    let Value::Object(mut attributes) = record else {
+      return record;
+    };
+
+    // Composite primary key columns remain attributes, since the id merely joins them.
+    let id = match self.api.record_pk_columns() {
+      [(_index, pk_column)] => resource_id(attributes.remove(&pk_column.name)),
+      _ => resource_id(self.api.record_id_of(&attributes)),
+    };
+
+    let mut relationships = Map::new();
+    if let Some(expand) = self.api.expand() {
+      for column in self.api.columns() {
+        if !expand.contains_key(&column.name) {
+          continue;
+        }
+        let Some((foreign_table, referred_column)) = foreign_key(column) else {
+          continue;
+        };
+        let Some(value) = attributes.remove(&column.name) else {
+          continue;
+        };
+
+        // Expandable columns are serialized as `{ id, data? }`, where data is only present if
+        // the column was expanded.
+        let data = match value {
+          Value::Object(mut value) => {
+            let id = resource_id(value.remove("id"));
+            if let Some(Value::Object(mut foreign)) = value.remove("data") {
+              // The referred column defaults to the foreign table's primary key.
+              foreign.remove(referred_column.unwrap_or("id"));
+              self.include(json!({
+                "type": foreign_table,
+                "id": id,
+                "attributes": foreign,
+              }));
+            }
+            json!({
+              "type": foreign_table,
+              "id": id,
+            })
+          }
+          _ => Value::Null,
+        };
+
+        relationships.insert(column.name.clone(), json!({ "data": data }));
+      }
+    }
+
+    let mut resource = json!({
+      "type": self.api.api_name(),
+      "id": id,
+      "attributes": attributes,
+    });
+    if !relationships.is_empty() {
+      resource["relationships"] = Value::Object(relationships);
+    }
+    return resource;
+  }
+
+  fn include(&mut self, resource: Value) {
+    let duplicate = self
+      .included
+      .iter()
+      .any(|r| r["type"] == resource["type"] && r["id"] == resource["id"]);
+    if !duplicate {
+      self.included.push(resource);
+    }
+  }
+
+  fn build(self, data: Value, meta: Map<String, Value>) -> Value {
+    let mut document = json!({
+      "data": data,
+    });
+    if !self.included.is_empty() {
+      document["included"] = Value::Array(self.included);
+    }
+    if !meta.is_empty() {
+      document["meta"] = Value::Object(meta);
+    }
+    return document;
+  }
+}
+
+/// Builds a JSON:API document for a single record.
+pub(crate) fn record_document(api: &RecordApi, record: Value) -> Value {
+  let mut builder = DocumentBuilder::new(api);
+  let data = builder.resource(record);
+  return builder.build(data, Map::new());
+}
+
+/// Builds a JSON:API document for a list of records. Pagination cursor and total count are
+/// provided as meta information.
+pub(crate) fn list_document(api: &RecordApi, list: ListResponse) -> Value {
+  let mut builder = DocumentBuilder::new(api);
+  let data: Vec<Value> = list
+    .records
+    .into_iter()
+    .map(|record| builder.resource(record))
+    .collect();
+
+  let mut meta = Map::new();
+  if let Some(cursor) = list.cursor {
+    meta.insert("cursor".to_string(), Value::String(cursor));
+  }
+  if let Some(total_count) = list.total_count {
+    meta.insert("total_count".to_string(), Value::from(total_count));
+  }
+  if let Some(has_more) = list.has_more {
+    meta.insert("has_more".to_string(), Value::Bool(has_more));
+  }
+
+  return builder.build(Value::Array(data), meta);
+}
+
+pub(crate) fn json_api_response(document: Value) -> Response {
+  return ([(CONTENT_TYPE, JSON_API_MIME_TYPE)], Json(document)).into_response();
+}
+
+#[cfg(test)]
+mod tests {
+  use axum::extract::{Path, Query, RawQuery, State};
+
+  use super::*;
+  use crate::app_state::test_state;
+  use crate::config::proto::{PermissionFlag, RecordApiConfig, ResponseFormat};
+  use crate::extract::AcceptFormat;
+  use crate::records::list_records::list_records_handler;
+  use crate::records::read_record::{ReadRecordQuery, read_record_handler};
+  use crate::records::test_utils::{add_record_api_config, json_body};
+
+  #[tokio::test]
+  async fn test_json_api_documents() {
+    let state = test_state(None).await.unwrap();
+
+    state
+      .conn()
+      .execute_batch(
+        r#"
+          CREATE TABLE author (
+            id           INTEGER PRIMARY KEY NOT NULL,
+            name         TEXT NOT NULL
+          ) STRICT;
+          INSERT INTO author (id, name) VALUES (1, 'Alice');
+
+          CREATE TABLE post (
+            id           INTEGER PRIMARY KEY NOT NULL,
+            title        TEXT NOT NULL,
+            author       INTEGER REFERENCES author NOT NULL
+          ) STRICT;
+          INSERT INTO post (id, title, author) VALUES (1, 'first', 1), (2, 'second', 1);
+       "#,
+      )
+      .await
+      .unwrap();
+
+    state.schema_metadata().invalidate_all().await.unwrap();
+
+    add_record_api_config(
+      &state,
+      RecordApiConfig {
+        name: Some("posts".to_string()),
+        table_name: Some("post".to_string()),
+        acl_world: [PermissionFlag::Read as i32].into(),
+        expand: vec!["author".to_string()],
+        response_format: Some(ResponseFormat::JsonApi as i32),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    let response = read_record_handler(
+      State(state.clone()),
+      Path(("posts".to_string(), "1".to_string())),
+      Query(ReadRecordQuery {
+        expand: Some("author".to_string()),
+        ..Default::default()
+      }),
+      None,
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+      response.headers().get(CONTENT_TYPE).unwrap(),
+      JSON_API_MIME_TYPE
+    );
+
+    let document: Value = json_body(response).await;
+    assert_eq!(
+      document,
+      json!({
+        "data": {
+          "type": "posts",
+          "id": "1",
+          "attributes": { "title": "first" },
+          "relationships": {
+            "author": { "data": { "type": "author", "id": "1" } },
+          },
+        },
+        "included": [
+          { "type": "author", "id": "1", "attributes": { "name": "Alice" } },
+        ],
+      })
+    );
+
+    let document: Value = json_body(
+      list_records_handler(
+        State(state.clone()),
+        Path("posts".to_string()),
+        RawQuery(Some("expand=author&count=true".to_string())),
+        None,
+        AcceptFormat::Json,
+      )
+      .await
+      .unwrap(),
+    )
+    .await;
+
+    let data = document["data"].as_array().unwrap();
+    assert_eq!(data.len(), 2);
+    assert_eq!(data[0]["id"], "2");
+    assert_eq!(data[1]["relationships"]["author"]["data"]["id"], "1");
+    // Shared foreign records are only included once.
+    assert_eq!(document["included"].as_array().unwrap().len(), 1);
+    assert_eq!(document["meta"]["total_count"], 2);
+  }
+}
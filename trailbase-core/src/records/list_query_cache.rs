@@ -0,0 +1,165 @@
+use mini_moka::sync::Cache;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::records::query_builder::ExpandedTable;
+
+/// Upper bound for the number of distinct listing queries cached.
+const MAX_LIST_QUERIES: u64 = 1024;
+
+/// Everything determining a listing query's SQL, i.e. all but the bound parameters.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub(crate) struct ListQueryShape {
+  pub api_name: String,
+  pub read_access_clause: String,
+  pub filter_clause: String,
+  pub cursor_clause: Option<String>,
+  pub order_clause: String,
+  pub expanded_tables: Vec<ExpandedTableShape>,
+  pub fts_table: Option<String>,
+  pub vec_table: Option<String>,
+  pub count: bool,
+  pub offset: bool,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub(crate) struct ExpandedTableShape {
+  local_column_name: String,
+  foreign_table_name: String,
+  foreign_column_name: String,
+  parent: Option<usize>,
+  select: Option<Vec<String>>,
+}
+
+impl From<&ExpandedTable> for ExpandedTableShape {
+  fn from(table: &ExpandedTable) -> Self {
+    return Self {
+      local_column_name: table.local_column_name.clone(),
+      foreign_table_name: table.foreign_table_name.clone(),
+      foreign_column_name: table.foreign_column_name.clone(),
+      parent: table.parent,
+      select: table.select.clone(),
+    };
+  }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ListQueryCacheStats {
+  pub hits: u64,
+  pub misses: u64,
+}
+
+/// Cache of rendered listing queries keyed by their shape, e.g. API, filter structure and order.
+///
+/// Filter values, cursors and limits are bound as parameters, thus requests of the same shape
+/// yield the very same SQL, which in turn hits the connections' prepared statement caches. Only
+/// parameter binding happens per request.
+///
+/// Needs to be cleared on schema changes, since expansions depend on the foreign tables' columns.
+pub(crate) struct ListQueryCache {
+  queries: Cache<ListQueryShape, Arc<str>>,
+
+  hits: AtomicU64,
+  misses: AtomicU64,
+}
+
+impl ListQueryCache {
+  pub(crate) fn new() -> Self {
+    return Self {
+      queries: Cache::new(MAX_LIST_QUERIES),
+      hits: AtomicU64::new(0),
+      misses: AtomicU64::new(0),
+    };
+  }
+
+  pub(crate) fn stats(&self) -> ListQueryCacheStats {
+    return ListQueryCacheStats {
+      hits: self.hits.load(Ordering::Relaxed),
+      misses: self.misses.load(Ordering::Relaxed),
+    };
+  }
+
+  pub(crate) fn clear(&self) {
+    self.queries.invalidate_all();
+  }
+
+  /// Returns the cached query for the given shape or renders and caches a new one.
+  pub(crate) fn get_or_render<E>(
+    &self,
+    shape: ListQueryShape,
+    render: impl FnOnce() -> Result<String, E>,
+  ) -> Result<Arc<str>, E> {
+    if let Some(query) = self.queries.get(&shape) {
+      self.hits.fetch_add(1, Ordering::Relaxed);
+      return Ok(query);
+    }
+    self.misses.fetch_add(1, Ordering::Relaxed);
+
+    let query: Arc<str> = render()?.into();
+    self.queries.insert(shape, query.clone());
+    return Ok(query);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::app_state::test_state;
+  use crate::config::proto::{PermissionFlag, RecordApiConfig};
+  use crate::records::RecordsClient;
+  use crate::records::test_utils::add_record_api_config;
+
+  #[tokio::test]
+  async fn test_list_query_cache() {
+    let state = test_state(None).await.unwrap();
+    state
+      .conn()
+      .execute_batch(
+        "CREATE TABLE article (id INTEGER PRIMARY KEY, title TEXT, views INTEGER) STRICT;
+         INSERT INTO article (title, views) VALUES ('first', 1), ('second', 2);",
+      )
+      .await
+      .unwrap();
+    state.refresh_table_cache().await.unwrap();
+
+    add_record_api_config(
+      &state,
+      RecordApiConfig {
+        name: Some("articles".to_string()),
+        table_name: Some("article".to_string()),
+        acl_world: [PermissionFlag::Read as i32].into(),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    let client = RecordsClient::new(&state);
+    let stats = || state.list_query_cache().stats();
+    let list = async |query: &str| {
+      return client
+        .list("articles", Some(query))
+        .await
+        .unwrap()
+        .records
+        .len();
+    };
+
+    assert_eq!(list("filter[views]=1").await, 1);
+    assert_eq!(stats(), ListQueryCacheStats { hits: 0, misses: 1 });
+
+    // Same shape, different values and limit.
+    assert_eq!(list("filter[views]=2&limit=5").await, 1);
+    assert_eq!(stats(), ListQueryCacheStats { hits: 1, misses: 1 });
+
+    // Different shapes.
+    assert_eq!(list("filter[title]=first").await, 1);
+    assert_eq!(list("filter[views]=1&order=title").await, 1);
+    assert_eq!(stats(), ListQueryCacheStats { hits: 1, misses: 3 });
+
+    // Schema changes invalidate.
+    state.refresh_table_cache().await.unwrap();
+    assert_eq!(list("filter[views]=1").await, 1);
+    assert_eq!(stats(), ListQueryCacheStats { hits: 1, misses: 4 });
+  }
+}
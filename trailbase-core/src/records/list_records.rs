@@ -1,52 +1,84 @@
 use askama::Template;
 use axum::{
-  Json,
+  body::Body,
   extract::{Path, RawQuery, State},
+  http::{HeaderValue, header},
+  response::{IntoResponse, Response},
 };
+use bytes::Bytes;
 use itertools::Itertools;
-use serde::Serialize;
+use log::*;
+use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
+use std::collections::HashMap;
+use trailbase_schema::metadata::{GeoPoint, vector_column_dimensions};
 use trailbase_sqlite::Value;
 
 use crate::app_state::AppState;
 use crate::auth::user::User;
+use crate::config::proto::{ListLimitsConfig, ResponseFormat};
+use crate::constants::{HEADER_CURSOR, HEADER_HAS_MORE, HEADER_TOTAL_COUNT};
+use crate::export::{ExportColumns, encode_arrow_stream};
+use crate::extract::{ARROW_STREAM_MIME_TYPE, AcceptFormat};
+use crate::listing::ListFormat;
 use crate::listing::{
-  Order, QueryParseResult, WhereClause, build_filter_where_clause, limit_or_default,
-  parse_and_sanitize_query,
+  CountMode, Filter, GeoFilter, ListLimits, Nearest, Order, Qualifier, QueryParam,
+  QueryParseResult, VectorMetric, WhereClause, build_filter_where_clause, column_expression,
+  geo_distance_expression, geo_filter_expression, parse_and_sanitize_query,
 };
-use crate::records::query_builder::{ExpandedTable, expand_tables};
-use crate::records::sql_to_json::{row_to_json, row_to_json_expand, rows_to_json_expand};
-use crate::records::{Permission, RecordError};
-use crate::util::uuid_to_b64;
+use crate::records::cache::{CacheKey, CacheOp, CachedResponse};
+use crate::records::cursor::{KeysetValue, decode_cursor, encode_cursor, sort_spec};
+use crate::records::export_records::{export_records, split_export_query, streaming_list_format};
+use crate::records::geojson::{feature_collection, geojson_response};
+use crate::records::json_api::{json_api_response, list_document};
+use crate::records::list_query_cache::ListQueryShape;
+use crate::records::query_builder::{
+  ExpandedTable, expand_tables, expanded_rows_to_json, reverse_expand_tables, split_expanded_row,
+};
+use crate::records::request_context::push_request_context_params;
+use crate::records::sql_to_json::{row_to_json_expand, rows_to_json_expand};
+use crate::records::{Permission, RecordApi, RecordError};
 
 /// JSON response containing the listed records.
-#[derive(Debug, Serialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ListResponse {
   /// Pagination cursor. Round-trip to get the next batch.
   #[serde(skip_serializing_if = "Option::is_none")]
   pub cursor: Option<String>,
-  /// The total number of records matching the query.
+  /// The total number of records matching the query. Approximate for `count=estimate`.
   #[serde(skip_serializing_if = "Option::is_none")]
   pub total_count: Option<usize>,
+  /// Whether there are more records after this page.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub has_more: Option<bool>,
   /// Actual record data for records matching the query.
   pub records: Vec<serde_json::Value>,
 }
 
 #[derive(Template)]
 #[template(escape = "none", path = "list_record_query.sql")]
-struct ListRecordQueryTemplate<'a> {
-  table_name: &'a str,
-  column_names: &'a [&'a str],
-  read_access_clause: &'a str,
-  filter_clause: &'a str,
-  cursor_clause: Option<&'a str>,
-  order_clause: &'a str,
-  expanded_tables: &'a [ExpandedTable],
-  count: bool,
-  offset: bool,
+pub(super) struct ListRecordQueryTemplate<'a> {
+  pub(super) table_name: &'a str,
+  pub(super) column_names: &'a [&'a str],
+  pub(super) read_access_clause: &'a str,
+  pub(super) filter_clause: &'a str,
+  pub(super) cursor_clause: Option<&'a str>,
+  pub(super) order_clause: &'a str,
+  pub(super) expanded_tables: &'a [ExpandedTable],
+  /// FTS5 table joined as `_FTS_` for full-text searches.
+  pub(super) fts_table: Option<&'a str>,
+  /// vec0 table joined as `_VEC_` for nearest neighbor searches.
+  pub(super) vec_table: Option<&'a str>,
+  pub(super) count: bool,
+  pub(super) offset: bool,
 }
 
-/// Lists records matching the given filters
+/// Lists records matching the given filters.
+///
+/// Responds with an Arrow IPC stream instead of JSON if requested via the `Accept` header, in which
+/// case the cursor and total count are returned as headers. With `format=geojson`, records are
+/// returned as a GeoJSON FeatureCollection. CSV and NDJSON, requested via `Accept` header or
+/// `format=csv|ndjson`, stream all matching records w/o pagination like exports do.
 #[utoipa::path(
   get,
   path = "/:name",
@@ -59,17 +91,234 @@ pub async fn list_records_handler(
   Path(api_name): Path<String>,
   RawQuery(raw_url_query): RawQuery,
   user: Option<User>,
-) -> Result<Json<ListResponse>, RecordError> {
+  accept: AcceptFormat,
+) -> Result<Response, RecordError> {
   let Some(api) = state.lookup_record_api(&api_name) else {
     return Err(RecordError::ApiNotFound);
   };
 
+  if let Some(format) = streaming_list_format(raw_url_query.as_deref(), accept) {
+    let (options, list_query) = split_export_query(raw_url_query.as_deref())?;
+    let body = export_records(
+      &state,
+      &api,
+      &list_query,
+      options.columns,
+      options.bom,
+      user,
+      format,
+    )
+    .await?;
+
+    return Ok(([(header::CONTENT_TYPE, format.content_type())], body).into_response());
+  }
+
+  return match list_records(&state, &api, raw_url_query.as_deref(), user, accept).await? {
+    Listing::Arrow(response) => Ok(response),
+    Listing::GeoJson(collection) => Ok(geojson_response(collection)),
+    Listing::Records(list) => match api.response_format() {
+      ResponseFormat::JsonApi => Ok(json_api_response(list_document(&api, list))),
+      _ => Ok(json_list_response(list)),
+    },
+  };
+}
+
+/// Size of the chunks JSON list responses are streamed in.
+const LIST_CHUNK_SIZE: usize = 32 * 1024;
+
+/// Serializes the listed records chunk by chunk rather than into a single buffer, which lets
+/// compression and transmission start early and keeps large pages from being held in memory twice.
+/// Responses fitting into a single chunk are sent in one piece, i.e. with a content length.
+pub(crate) fn json_list_response(list: ListResponse) -> Response {
+  let mut chunks = ListChunks::new(list);
+  let first = match chunks.next() {
+    Some(Ok(chunk)) => chunk,
+    Some(Err(err)) => return RecordError::Internal(err.into()).into_response(),
+    None => Bytes::new(),
+  };
+
+  let headers = [(header::CONTENT_TYPE, "application/json")];
+  if chunks.done {
+    return (headers, first).into_response();
+  }
+  return (
+    headers,
+    Body::from_stream(futures_util::stream::iter(
+      std::iter::once(Ok(first)).chain(chunks),
+    )),
+  )
+    .into_response();
+}
+
+/// Lazily serializes a [ListResponse] into chunks of roughly [LIST_CHUNK_SIZE] bytes.
+struct ListChunks {
+  head: Option<Vec<u8>>,
+  records: std::vec::IntoIter<serde_json::Value>,
+  first_record: bool,
+  done: bool,
+}
+
+impl ListChunks {
+  fn new(list: ListResponse) -> Self {
+    let ListResponse {
+      cursor,
+      total_count,
+      has_more,
+      records,
+    } = list;
+
+    // Mirrors the field order and skipping of ListResponse's derived Serialize implementation.
+    let mut head = b"{".to_vec();
+    if let Some(cursor) = cursor {
+      head.extend_from_slice(
+        format!(r#""cursor":{},"#, serde_json::Value::String(cursor)).as_bytes(),
+      );
+    }
+    if let Some(total_count) = total_count {
+      head.extend_from_slice(format!(r#""total_count":{total_count},"#).as_bytes());
+    }
+    if let Some(has_more) = has_more {
+      head.extend_from_slice(format!(r#""has_more":{has_more},"#).as_bytes());
+    }
+    head.extend_from_slice(br#""records":["#);
+
+    return Self {
+      head: Some(head),
+      records: records.into_iter(),
+      first_record: true,
+      done: false,
+    };
+  }
+}
+
+impl Iterator for ListChunks {
+  type Item = Result<Bytes, serde_json::Error>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    if self.done {
+      return None;
+    }
+
+    let mut buffer = self
+      .head
+      .take()
+      .unwrap_or_else(|| Vec::with_capacity(LIST_CHUNK_SIZE));
+    while buffer.len() < LIST_CHUNK_SIZE {
+      let Some(record) = self.records.next() else {
+        buffer.extend_from_slice(b"]}");
+        self.done = true;
+        break;
+      };
+
+      if !self.first_record {
+        buffer.push(b',');
+      }
+      self.first_record = false;
+
+      if let Err(err) = serde_json::to_writer(&mut buffer, &record) {
+        self.done = true;
+        return Some(Err(err));
+      }
+    }
+
+    return Some(Ok(Bytes::from(buffer)));
+  }
+}
+
+/// List query with default ordering and pagination, i.e. what's run for plain listings and
+/// optionally filtered by `filter_clause`.
+pub(crate) fn default_list_query(
+  api: &RecordApi,
+  filter_clause: &str,
+) -> Result<String, RecordError> {
+  let column_names: Vec<_> = api.columns().iter().map(|c| c.name.as_str()).collect();
+
+  return ListRecordQueryTemplate {
+    table_name: api.table_name(),
+    column_names: &column_names,
+    read_access_clause: &api.list_access_clause(false, None)?,
+    filter_clause,
+    cursor_clause: None,
+    order_clause: &api
+      .record_pk_columns()
+      .iter()
+      .map(|(_index, pk_column)| format!(r#"_ROW_."{}" DESC"#, pk_column.name))
+      .join(","),
+    expanded_tables: &[],
+    fts_table: None,
+    vec_table: None,
+    count: false,
+    offset: false,
+  }
+  .render()
+  .map_err(|err| RecordError::Internal(err.into()));
+}
+
+pub(crate) enum Listing {
+  Arrow(Response),
+  Records(ListResponse),
+  GeoJson(serde_json::Value),
+}
+
+fn to_listing(
+  api: &RecordApi,
+  format: Option<ListFormat>,
+  list: ListResponse,
+) -> Result<Listing, RecordError> {
+  return match format {
+    Some(ListFormat::GeoJson) => feature_collection(api, list).map(Listing::GeoJson),
+    None => Ok(Listing::Records(list)),
+  };
+}
+
+pub(crate) async fn list_records(
+  state: &AppState,
+  api: &RecordApi,
+  raw_url_query: Option<&str>,
+  user: Option<User>,
+  accept: AcceptFormat,
+) -> Result<Listing, RecordError> {
   // WARN: We do different access checking here because the access rule is used as a filter query
-  // on the table, i.e. no access -> empty results.
+  // on the table, i.e. no access -> empty results. Table-level access is checked for every
+  // request, i.e. also on cache hits.
   api.check_table_level_access(Permission::Read, user.as_ref())?;
 
+  // Arrow responses are encoded right away and thus not cached.
+  if api.cache_ttl().is_none() || accept == AcceptFormat::Arrow {
+    return list_records_uncached(state, api, raw_url_query, user, accept).await;
+  }
+
+  let query = parse_and_sanitize_query(raw_url_query)
+    .map_err(|_err| RecordError::BadRequest("Invalid query"))?;
+  let key = CacheKey::new(api, CacheOp::List, query.canonical(), user.as_ref());
+  let response = state
+    .query_cache()
+    .get_or_compute(state.conn(), api, key, async move {
+      return match list_records_uncached(state, api, raw_url_query, user, accept).await? {
+        Listing::Records(list) => Ok(CachedResponse::List(list)),
+        Listing::GeoJson(collection) => Ok(CachedResponse::Json(collection)),
+        Listing::Arrow(_) => Err(RecordError::Internal("unexpected Arrow listing".into())),
+      };
+    })
+    .await?;
+
+  return match &*response {
+    CachedResponse::List(list) => Ok(Listing::Records(list.clone())),
+    CachedResponse::Json(collection) => Ok(Listing::GeoJson(collection.clone())),
+    CachedResponse::Record(..) => Err(RecordError::Internal("unexpected record response".into())),
+  };
+}
+
+/// Lists records w/o checking table-level access, which is up to the caller.
+async fn list_records_uncached(
+  state: &AppState,
+  api: &RecordApi,
+  raw_url_query: Option<&str>,
+  user: Option<User>,
+  accept: AcceptFormat,
+) -> Result<Listing, RecordError> {
   let table_name = api.table_name();
-  let (pk_index, pk_column) = api.record_pk_column();
+  let (pk_index, _pk_column) = api.record_pk_column();
 
   let QueryParseResult {
     limit,
@@ -78,37 +327,178 @@ pub async fn list_records_handler(
     expand: query_expand,
     order,
     params: filter_params,
+    filter,
     offset,
-  } = parse_and_sanitize_query(raw_url_query.as_deref()).map_err(|_err| {
+    select,
+    nearest,
+    k,
+    search,
+    collate,
+    format,
+    include_deleted,
+  } = parse_and_sanitize_query(raw_url_query).map_err(|_err| {
     return RecordError::BadRequest("Invalid query");
   })?;
 
+  if let Some(ref select) = select {
+    for col_name in select {
+      if !column_filter(col_name) || api.column_index_by_name(col_name).is_none() {
+        return Err(RecordError::BadRequest("Invalid select"));
+      }
+    }
+  }
+
+  if let Some(ref collate) = collate {
+    if !trailbase_extension::collation::has_collation(collate) {
+      return Err(RecordError::BadRequest("Invalid collation"));
+    }
+  }
+
+  if api.has_encrypted_columns() {
+    if accept == AcceptFormat::Arrow {
+      return Err(RecordError::BadRequest(
+        "Arrow responses not supported for encrypted columns",
+      ));
+    }
+    if let Some(ref order) = order {
+      for (col_name, _) in order {
+        // Also covers JSON paths, e.g. "profile.age".
+        let col_name = col_name.split('.').next().unwrap_or(col_name);
+        if api
+          .column_index_by_name(col_name)
+          .is_some_and(|index| api.column_encryption(index).is_some())
+        {
+          return Err(RecordError::BadRequest("Cannot order by encrypted column"));
+        }
+      }
+    }
+  }
+
+  if let Some(ListFormat::GeoJson) = format {
+    if accept == AcceptFormat::Arrow {
+      return Err(RecordError::BadRequest("GeoJSON and Arrow are exclusive"));
+    }
+    if api.geometry_columns().is_none() {
+      return Err(RecordError::BadRequest("No geometry columns"));
+    }
+  }
+
+  // Nearest neighbor search orders by distance, which is incompatible with explicit ordering and
+  // cursors.
+  let nearest = match nearest {
+    Some(nearest) => {
+      if cursor.is_some() || order.is_some() {
+        return Err(RecordError::BadRequest(
+          "Nearest cannot be combined with cursor or order",
+        ));
+      }
+      Some((resolve_vector_column(api, &nearest)?, nearest))
+    }
+    None => None,
+  };
+
+  // Full-text search orders by rank unless ordered explicitly, in which case cursors cannot be
+  // supported.
+  let fts_index = match search {
+    Some(_) => {
+      let Some(fts_index) = api.fts_index() else {
+        return Err(RecordError::BadRequest("No full-text search index"));
+      };
+      if nearest.is_some() {
+        return Err(RecordError::BadRequest(
+          "Search cannot be combined with nearest",
+        ));
+      }
+      if cursor.is_some() && order.is_none() {
+        return Err(RecordError::BadRequest(
+          "Cursors require explicit ordering when searching",
+        ));
+      }
+      Some(fts_index)
+    }
+    None => None,
+  };
+  let rank_ordered = fts_index.is_some() && order.is_none();
+
+  // Geo filters on latitude/longitude column pairs. Unless ordered otherwise, records are ordered
+  // by distance to the first "near" filter's center, which is incompatible with cursors.
+  let mut filter_params = filter_params;
+  let geo_filters = extract_geo_filters(api, &mut filter_params)?;
+  let distance_ordered = order.is_none()
+    && !rank_ordered
+    && nearest.is_none()
+    && geo_filters
+      .iter()
+      .any(|(_, filter)| matches!(filter, GeoFilter::Near { .. }));
+  if distance_ordered && cursor.is_some() {
+    return Err(RecordError::BadRequest(
+      "Cursors require explicit ordering for near filters",
+    ));
+  }
+
   // NOTE: We're using the read access rule to filter the rows as opposed to yes/no early access
   // blocking as for read-record.
   //
   // TODO: Should this be a separate access rule? Maybe one wants users to access a specific
   // record but not list all the records.
-  let read_access_clause: &str = api.read_access_rule().unwrap_or("TRUE");
+  let read_access_clause =
+    api.list_access_clause(include_deleted.unwrap_or(false), user.as_ref())?;
 
   // Where clause contains column filters and cursor depending on what's present.
   // NOTE: This will also drop any filters for unknown columns, thus avoiding SQL injections.
+  let (filter_params, filter) = encrypt_filter_params(api, filter_params, filter)?;
   let WhereClause {
-    clause: filter_clause,
+    clause: mut filter_clause,
     mut params,
-  } = build_filter_where_clause("_ROW_", api.columns(), filter_params)
-    .map_err(|_err| RecordError::BadRequest("Invalid filter params"))?;
+  } = build_filter_where_clause(
+    "_ROW_",
+    api.columns(),
+    filter_params,
+    filter,
+    collate.as_deref(),
+  )
+  .map_err(|_err| RecordError::BadRequest("Invalid filter params"))?;
+
+  if let (Some(fts_index), Some(search)) = (fts_index, search) {
+    let rowid = fts_index
+      .rowid_column
+      .as_ref()
+      .map_or_else(|| "rowid".to_string(), |c| format!(r#""{c}""#));
+    filter_clause = format!(
+      r#"({filter_clause}) AND _FTS_.rowid = _ROW_.{rowid} AND _FTS_."{fts_table}" MATCH :__search"#,
+      fts_table = fts_index.table_name
+    );
+    params.push((Cow::Borrowed(":__search"), Value::Text(fts_query(&search))));
+  }
+
+  let mut distance_order: Option<String> = None;
+  for (i, (point, geo_filter)) in geo_filters.iter().enumerate() {
+    let prefix = format!(":__geo{i}");
+    let clause = geo_filter_expression("_ROW_", point, geo_filter, &prefix, &mut params);
+    filter_clause = format!("({filter_clause}) AND ({clause})");
+
+    if distance_ordered && distance_order.is_none() {
+      if let GeoFilter::Near { .. } = geo_filter {
+        distance_order = Some(geo_distance_expression("_ROW_", point, &prefix));
+      }
+    }
+  }
 
   // User properties
+  let limit = list_limits(state, api, user.as_ref()).limit(if nearest.is_some() {
+    k.or(limit)
+  } else {
+    limit
+  })?;
   params.extend_from_slice(&[
-    (
-      Cow::Borrowed(":__limit"),
-      Value::Integer(limit_or_default(limit).map_err(RecordError::BadRequest)? as i64),
-    ),
+    // Fetch one extra record to determine whether there are more.
+    (Cow::Borrowed(":__limit"), Value::Integer(limit as i64 + 1)),
     (
       Cow::Borrowed(":__user_id"),
       user.map_or(Value::Null, |u| Value::Blob(u.uuid.into())),
     ),
   ]);
+  push_request_context_params(&mut params);
 
   if let Some(offset) = offset {
     params.push((
@@ -121,32 +511,44 @@ pub async fn list_records_handler(
     ));
   }
 
+  // Cursors are only valid for the sort order they were issued for. Random primary keys, e.g.
+  // UUIDv4, aren't supported, since records inserted later can sort anywhere. Use offsets instead.
+  let sort_order = sort_spec(api, order.as_deref());
+  let keyset = keyset_columns(api, order.as_deref()).filter(|_| api.record_pk_sortable());
+
+  if cursor.is_some() && !api.record_pk_sortable() {
+    return Err(RecordError::BadRequest(
+      "Cursors require time-sortable primary key, use offset",
+    ));
+  }
+
   let cursor_clause = if let Some(cursor) = cursor {
-    let mut pk_order = Order::Descending;
-    if let Some(ref order) = order {
-      if let Some((col, ord)) = order.first() {
-        if *col != pk_column.name {
-          return Err(RecordError::BadRequest(
-            "Cannot cursor on queries where the primary order criterion is not the primary key",
-          ));
-        }
+    let Some(ref keyset) = keyset else {
+      return Err(RecordError::BadRequest(
+        "Cursors not supported for ordering by JSON paths",
+      ));
+    };
 
-        pk_order = ord.clone();
-      }
+    let values = decode_cursor(state, api, &sort_order, &cursor)?;
+    if values.len() != keyset.len() {
+      return Err(RecordError::BadRequest("Invalid cursor"));
     }
 
-    params.push((Cow::Borrowed(":cursor"), cursor.into()));
-    match pk_order {
-      Order::Descending => Some(format!(r#"_ROW_."{}" < :cursor"#, pk_column.name)),
-      Order::Ascending => Some(format!(r#"_ROW_."{}" > :cursor"#, pk_column.name)),
-    }
+    Some(keyset_clause(
+      api,
+      keyset,
+      values,
+      collate.as_deref(),
+      &mut params,
+    ))
   } else {
     None
   };
 
-  fn fmt_order(col: &str, order: Order) -> String {
+  fn fmt_order(expr: &str, order: Order, collate: Option<&str>) -> String {
     return format!(
-      r#"_ROW_."{col}" {}"#,
+      "{expr}{} {}",
+      collate.map_or_else(String::new, |c| format!(" COLLATE {c}")),
       match order {
         Order::Descending => "DESC",
         Order::Ascending => "ASC",
@@ -154,80 +556,190 @@ pub async fn list_records_handler(
     );
   }
 
-  let order_clause = order.map_or_else(
-    || fmt_order(&pk_column.name, Order::Descending),
-    |order| {
-      order
+  let pk_tiebreaker = tiebreaker(api, order.as_deref());
+  let order_clause = if let Some((ref vector_column, ref nearest)) = nearest {
+    params.push((
+      Cow::Borrowed(":__nearest"),
+      Value::Blob(nearest.vector.clone()),
+    ));
+    // NOTE: NULLs would otherwise come first.
+    format!(
+      r#"{expr} IS NULL, {distance}({expr}, :__nearest)"#,
+      expr = vector_column.expr,
+      distance = vector_column.metric.distance_function(),
+    )
+  } else if rank_ordered {
+    "_FTS_.rank".to_string()
+  } else if let Some(distance_order) = distance_order {
+    distance_order
+  } else {
+    match order {
+      Some(order) => order
         .into_iter()
-        .map(|(col, ord)| fmt_order(&col, ord))
-        .join(",")
-    },
-  );
+        .chain(pk_tiebreaker)
+        .map(|(col, ord)| -> Result<String, RecordError> {
+          // Plain columns or JSON paths, e.g. "profile.age".
+          let expr = match api.column_index_by_name(&col) {
+            Some(_) => format!(r#"_ROW_."{col}""#),
+            None => {
+              column_expression("_ROW_", api.columns(), &col)
+                .map_err(|_| RecordError::BadRequest("Invalid order"))?
+                .sql
+            }
+          };
+          return Ok(fmt_order(&expr, ord, collate.as_deref()));
+        })
+        .collect::<Result<Vec<_>, _>>()?
+        .join(","),
+      None => api
+        .record_pk_columns()
+        .iter()
+        .map(|(_index, pk_column)| {
+          fmt_order(
+            &format!(r#"_ROW_."{}""#, pk_column.name),
+            Order::Descending,
+            None,
+          )
+        })
+        .join(","),
+    }
+  };
 
-  let expanded_tables = match query_expand {
+  let (expanded_tables, reverse_expansions) = match query_expand {
+    Some(_) if accept == AcceptFormat::Arrow => {
+      return Err(RecordError::BadRequest(
+        "Expansion not supported for Arrow responses",
+      ));
+    }
     Some(ref expand) => {
       let Some(config_expand) = api.expand() else {
         return Err(RecordError::BadRequest("Invalid expansion"));
       };
 
-      // NOTE: This will drop any unknown expand column, thus avoiding SQL injections.
-      for col_name in expand {
-        if !config_expand.contains_key(col_name) {
+      // NOTE: This will drop any unknown expand column, thus avoiding SQL injections. Nested
+      // expansions are validated against the schema.
+      for expansion in expand {
+        if !config_expand.contains_key(&expansion.column) {
           return Err(RecordError::BadRequest("Invalid expansion"));
         }
       }
 
-      expand_tables(
-        state.schema_metadata(),
-        |column_name| {
-          api
-            .column_index_by_name(column_name)
-            .map(|idx| &api.columns()[idx])
-        },
-        expand,
-      )?
+      // Expansions not naming one of the API's columns expand referencing records.
+      let (expand, reverse_expand): (Vec<_>, Vec<_>) = expand
+        .iter()
+        .cloned()
+        .partition(|expansion| api.column_index_by_name(&expansion.column).is_some());
+
+      (
+        expand_tables(
+          state.schema_metadata(),
+          api.table_name(),
+          |column_name| {
+            api
+              .column_index_by_name(column_name)
+              .map(|idx| &api.columns()[idx])
+          },
+          &expand,
+          api.max_expand_depth(),
+        )?,
+        reverse_expand_tables(state.schema_metadata(), api.table_name(), &reverse_expand)?,
+      )
     }
-    None => vec![],
+    None => (vec![], vec![]),
   };
 
   // NOTE: the `total_count._value_` underscore is load-bearing to strip it from result based on
   // "_" prefix.
-  let column_names: Vec<_> = api.columns().iter().map(|c| c.name.as_str()).collect();
-  let query = ListRecordQueryTemplate {
-    table_name,
-    column_names: &column_names,
-    read_access_clause,
-    filter_clause: &filter_clause,
-    cursor_clause: cursor_clause.as_deref(),
-    order_clause: &order_clause,
-    expanded_tables: &expanded_tables,
-    count: count.unwrap_or(false),
+  // Estimates are only meaningful for unfiltered listings of tables visible in full, otherwise
+  // fall back to exact counting.
+  let estimated_count = match count {
+    Some(CountMode::Estimate)
+      if api.is_table() && read_access_clause == "TRUE" && filter_clause == "TRUE" =>
+    {
+      estimate_count(state, table_name).await
+    }
+    _ => None,
+  };
+  let exact_count = count.is_some() && estimated_count.is_none();
+
+  let fts_table = fts_index.map(|fts_index| fts_index.table_name.as_str());
+  let vec_table = nearest
+    .as_ref()
+    .and_then(|(vector_column, _)| vector_column.vec_table);
+  let shape = ListQueryShape {
+    api_name: api.api_name().to_string(),
+    read_access_clause: read_access_clause.to_string(),
+    filter_clause: filter_clause.clone(),
+    cursor_clause: cursor_clause.clone(),
+    order_clause: order_clause.clone(),
+    expanded_tables: expanded_tables.iter().map(Into::into).collect(),
+    fts_table: fts_table.map(str::to_string),
+    vec_table: vec_table.map(str::to_string),
+    count: exact_count,
     offset: offset.is_some(),
-  }
-  .render()
-  .map_err(|err| RecordError::Internal(err.into()))?;
+  };
+  let query = state
+    .list_query_cache()
+    .get_or_render(shape, || {
+      let column_names: Vec<_> = api.columns().iter().map(|c| c.name.as_str()).collect();
+      return ListRecordQueryTemplate {
+        table_name,
+        column_names: &column_names,
+        read_access_clause: &read_access_clause,
+        filter_clause: &filter_clause,
+        cursor_clause: cursor_clause.as_deref(),
+        order_clause: &order_clause,
+        expanded_tables: &expanded_tables,
+        fts_table,
+        vec_table,
+        count: exact_count,
+        offset: offset.is_some(),
+      }
+      .render();
+    })
+    .map_err(|err| RecordError::Internal(err.into()))?;
 
   // Execute the query.
-  let rows = state.conn().read_query_rows(query, params).await?;
+  let mut rows = state.conn().read_query_rows(query, params).await?;
+  let has_more = rows.len() > limit;
+  rows.truncate(limit);
+
   let Some(last_row) = rows.last() else {
     // Rows are empty:
-    return Ok(Json(ListResponse {
-      cursor: None,
-      total_count: Some(0),
-      records: vec![],
-    }));
+    if accept == AcceptFormat::Arrow {
+      return list_records_arrow(api, &rows, select.as_deref(), None, Some(0), false)
+        .map(Listing::Arrow);
+    }
+
+    return to_listing(
+      api,
+      format,
+      ListResponse {
+        cursor: None,
+        total_count: Some(0),
+        has_more: Some(false),
+        records: vec![],
+      },
+    );
   };
 
   assert!(*pk_index < last_row.len());
-  let cursor = match &last_row[*pk_index] {
-    rusqlite::types::Value::Blob(blob) => {
-      uuid::Uuid::from_slice(blob).as_ref().map(uuid_to_b64).ok()
-    }
-    rusqlite::types::Value::Integer(i) => Some(i.to_string()),
-    _ => None,
+  // Cursors aren't meaningful for results ordered by distance or rank.
+  let cursor = match keyset {
+    _ if nearest.is_some() || rank_ordered || distance_ordered => None,
+    Some(ref keyset) => Some(encode_cursor(
+      state,
+      api,
+      &sort_order,
+      keyset
+        .iter()
+        .map(|(index, _order)| KeysetValue::from(&last_row[*index]))
+        .collect(),
+    )?),
+    None => None,
   };
 
-  let total_count = if count == Some(true) {
+  let total_count = if exact_count {
     let Some(rusqlite::types::Value::Integer(count)) = rows[0].last() else {
       return Err(RecordError::Internal(
         format!("expected count, got {:?}", rows[0].last()).into(),
@@ -235,10 +747,22 @@ pub async fn list_records_handler(
     };
     Some(*count as usize)
   } else {
-    None
+    estimated_count
   };
 
-  let records = if expanded_tables.is_empty() {
+  if accept == AcceptFormat::Arrow {
+    return list_records_arrow(api, &rows, select.as_deref(), cursor, total_count, has_more)
+      .map(Listing::Arrow);
+  }
+
+  // Collect the record ids before consuming the rows to look up referencing records.
+  let record_ids: Vec<_> = if reverse_expansions.is_empty() {
+    vec![]
+  } else {
+    rows.iter().map(|row| row[*pk_index].clone()).collect()
+  };
+
+  let mut records = if expanded_tables.is_empty() {
     rows_to_json_expand(
       api.columns(),
       api.json_column_metadata(),
@@ -258,23 +782,13 @@ pub async fn list_records_handler(
           ));
         };
 
-        let mut curr = row.split_off(api.columns().len());
-
-        for expanded in &expanded_tables {
-          let next = curr.split_off(expanded.num_columns);
-
-          let foreign_value = row_to_json(
-            &expanded.metadata.schema.columns,
-            &expanded.metadata.json_metadata.columns,
-            &curr,
-            column_filter,
-          )
-          .map_err(|err| RecordError::Internal(err.into()))?;
-
-          let result = expand.insert(expanded.local_column_name.clone(), foreign_value);
+        let foreign_rows = split_expanded_row(&mut row, api.columns().len(), &expanded_tables);
+        for (col_name, foreign_value) in
+          expanded_rows_to_json(&expanded_tables, &foreign_rows, column_filter)
+            .map_err(|err| RecordError::Internal(err.into()))?
+        {
+          let result = expand.insert(col_name, foreign_value);
           assert!(result.is_some());
-
-          curr = next;
         }
 
         return row_to_json_expand(
@@ -289,84 +803,506 @@ pub async fn list_records_handler(
       .collect::<Result<Vec<_>, RecordError>>()?
   };
 
-  return Ok(Json(ListResponse {
-    cursor,
-    total_count,
-    records,
-  }));
-}
+  // Project before decrypting to not decrypt columns that aren't returned anyway.
+  if let Some(select) = select {
+    for record in &mut records {
+      if let Some(record) = record.as_object_mut() {
+        record.retain(|col_name, _| select.contains(col_name));
+      }
+    }
+  }
 
-#[inline]
-fn column_filter(col_name: &str) -> bool {
-  return !col_name.starts_with("_");
+  for reverse_expansion in &reverse_expansions {
+    let children = reverse_expansion
+      .run(state.conn(), &record_ids, column_filter)
+      .await?;
+    for (record, children) in records.iter_mut().zip(children) {
+      if let Some(record) = record.as_object_mut() {
+        record.insert(reverse_expansion.name.clone(), children);
+      }
+    }
+  }
+
+  if api.has_encrypted_columns() {
+    for record in &mut records {
+      api.decrypt_record(record)?;
+    }
+  }
+
+  return to_listing(
+    api,
+    format,
+    ListResponse {
+      cursor,
+      total_count,
+      has_more: Some(has_more),
+      records,
+    },
+  );
 }
 
-#[cfg(test)]
-mod tests {
-  use serde::Deserialize;
-  use std::borrow::Cow;
-  use trailbase_schema::sqlite::sqlite3_parse_into_statement;
-  use trailbase_sqlite::Value;
+/// Page size bounds for listing the API's records as the given user: built-in defaults overridden
+/// by `server.list_limits`, which are in turn overridden by the API's own `list_limits`.
+pub(crate) fn list_limits(state: &AppState, api: &RecordApi, user: Option<&User>) -> ListLimits {
+  fn apply(limits: ListLimits, config: &ListLimitsConfig, user: Option<&User>) -> ListLimits {
+    let base = ListLimits {
+      default_limit: config
+        .default_limit
+        .map_or(limits.default_limit, |l| l as usize),
+      max_limit: config.max_limit.map_or(limits.max_limit, |l| l as usize),
+    };
+    let Some(user) = user else {
+      return base;
+    };
 
-  use super::*;
-  use crate::admin::user::*;
-  use crate::app_state::*;
-  use crate::auth::api::login::login_with_password;
-  use crate::auth::user::User;
-  use crate::config::proto::PermissionFlag;
-  use crate::records::RecordError;
-  use crate::records::query_builder::expand_tables;
-  use crate::records::test_utils::*;
-  use crate::schema_metadata::SchemaMetadataCache;
-  use crate::util::id_to_b64;
-  use crate::util::urlencode;
+    return config
+      .roles
+      .iter()
+      .filter(|o| o.role.as_ref().is_some_and(|role| user.has_role(role)))
+      .map(|o| ListLimits {
+        default_limit: o.default_limit.map_or(base.default_limit, |l| l as usize),
+        max_limit: o.max_limit.map_or(base.max_limit, |l| l as usize),
+      })
+      .max_by_key(|limits| limits.max_limit)
+      .unwrap_or(base);
+  }
 
-  fn sanitize_template(template: &str) {
-    assert!(sqlite3_parse_into_statement(template).is_ok(), "{template}");
-    assert!(!template.contains("\n\n"), "{template}");
+  let mut limits = ListLimits::default();
+  if let Some(config) = state.access_config(|c| c.server.list_limits.clone()) {
+    limits = apply(limits, &config, user);
   }
+  if let Some(config) = api.list_limits() {
+    limits = apply(limits, config, user);
+  }
+  return limits;
+}
 
-  #[test]
-  fn test_list_records_template() {
-    sanitize_template(
-      &ListRecordQueryTemplate {
-        table_name: "table",
-        column_names: &["a", "index"],
-        read_access_clause: "TRUE",
-        filter_clause: "TRUE",
-        cursor_clause: Some("TRUE"),
-        order_clause: "NULL",
-        expanded_tables: &[],
-        count: false,
-        offset: false,
-      }
-      .render()
-      .unwrap(),
-    );
+/// Estimates the number of records from the table's max rowid, which is exact for append-only
+/// tables and an upper bound otherwise. None for tables w/o rowid, e.g. `WITHOUT ROWID` tables.
+async fn estimate_count(state: &AppState, table_name: &str) -> Option<usize> {
+  let max_rowid = state
+    .conn()
+    .read_query_row_f(
+      format!(r#"SELECT MAX(_rowid_) FROM "{table_name}""#),
+      (),
+      |row| row.get::<_, Option<i64>>(0),
+    )
+    .await;
 
-    sanitize_template(
-      &ListRecordQueryTemplate {
-        table_name: "table",
-        column_names: &["a", "index"],
-        read_access_clause: "_USER_.id IS NOT NULL",
-        filter_clause: "a = 'value'",
-        cursor_clause: None,
-        order_clause: "'index' ASC",
-        expanded_tables: &[],
-        count: true,
-        offset: true,
-      }
-      .render()
-      .unwrap(),
+  return match max_rowid {
+    Ok(max_rowid) => Some(max_rowid.flatten().unwrap_or(0).max(0) as usize),
+    Err(err) => {
+      debug!("Count estimate unavailable for '{table_name}': {err}");
+      None
+    }
+  };
+}
+
+/// Primary key ordering appended to explicit orders, which makes the order total and thus keyset
+/// pagination stable. Ties are broken in the direction of the last order column. For composite
+/// primary keys, only the parts not already ordered by are appended.
+fn tiebreaker(api: &RecordApi, order: Option<&[(String, Order)]>) -> Vec<(String, Order)> {
+  let Some((_, last)) = order.and_then(|order| order.last()) else {
+    return vec![];
+  };
+  let order = order.unwrap_or_default();
+
+  return api
+    .record_pk_columns()
+    .iter()
+    .filter(|(_index, pk_column)| !order.iter().any(|(col, _)| *col == pk_column.name))
+    .map(|(_index, pk_column)| (pk_column.name.clone(), last.clone()))
+    .collect();
+}
+
+/// Column indexes and directions of the sort keys a cursor comprises, i.e. the order columns
+/// followed by the primary key tiebreaker. None if the order can't be paginated by keyset, i.e.
+/// includes JSON paths.
+fn keyset_columns(
+  api: &RecordApi,
+  order: Option<&[(String, Order)]>,
+) -> Option<Vec<(usize, Order)>> {
+  let Some(order) = order.filter(|o| !o.is_empty()) else {
+    return Some(
+      api
+        .record_pk_columns()
+        .iter()
+        .map(|(index, _)| (*index, Order::Descending))
+        .collect(),
     );
+  };
+
+  return order
+    .iter()
+    .cloned()
+    .chain(tiebreaker(api, Some(order)))
+    .map(|(col, ord)| Some((api.column_index_by_name(&col)?, ord)))
+    .collect();
+}
+
+/// Builds the WHERE clause selecting records after the cursor, i.e. for keys `(k_1, ..., k_n)` and
+/// values `(v_1, ..., v_n)`:
+///
+///   (k_1 > v_1) OR (k_1 IS v_1 AND k_2 > v_2) OR ...
+///
+/// where ">" means "sorts after" given the key's direction and SQLite's NULLs-first ordering.
+fn keyset_clause(
+  api: &RecordApi,
+  keyset: &[(usize, Order)],
+  values: Vec<KeysetValue>,
+  collate: Option<&str>,
+  params: &mut Vec<(Cow<'static, str>, Value)>,
+) -> String {
+  let collate = collate.map_or_else(String::new, |c| format!(" COLLATE {c}"));
+
+  let mut disjuncts: Vec<String> = vec![];
+  let mut equal: Vec<String> = vec![];
+  for (i, ((index, order), value)) in keyset.iter().zip(values).enumerate() {
+    let col = format!(r#"_ROW_."{}""#, api.columns()[*index].name);
+    let param = format!(":__cursor{i}");
+
+    let after = match (order, &value) {
+      (Order::Ascending, KeysetValue::Null) => format!("{col} IS NOT NULL"),
+      (Order::Ascending, _) => format!("{col} > {param}{collate}"),
+      (Order::Descending, KeysetValue::Null) => "FALSE".to_string(),
+      (Order::Descending, _) => format!("({col} < {param}{collate} OR {col} IS NULL)"),
+    };
+    disjuncts.push(format!(
+      "({})",
+      equal.iter().chain(std::iter::once(&after)).join(" AND ")
+    ));
+
+    equal.push(format!("{col} IS {param}{collate}"));
+    params.push((Cow::Owned(param), value.into()));
   }
 
-  #[tokio::test]
-  async fn test_list_records_template_with_expansions() {
-    let state = test_state(None).await.unwrap();
-    let conn = state.conn();
+  return disjuncts.join(" OR ");
+}
 
-    conn
+/// Removes filters with geo qualifiers, e.g. "location[near]=...", from `filter_params` and resolves
+/// their geo points.
+fn extract_geo_filters<'a>(
+  api: &'a RecordApi,
+  filter_params: &mut Option<HashMap<String, Vec<QueryParam>>>,
+) -> Result<Vec<(&'a GeoPoint, GeoFilter)>, RecordError> {
+  let mut geo_filters = vec![];
+  let Some(filter_params) = filter_params else {
+    return Ok(geo_filters);
+  };
+
+  for (name, query_params) in filter_params.iter_mut() {
+    if !query_params
+      .iter()
+      .any(|p| p.qualifier.is_some_and(|q| q.is_geo()))
+    {
+      continue;
+    }
+
+    let Some(point) = api.geo_point(name) else {
+      return Err(RecordError::BadRequest("Invalid geo point"));
+    };
+
+    let (geo, other): (Vec<_>, Vec<_>) = std::mem::take(query_params)
+      .into_iter()
+      .partition(|p| p.qualifier.is_some_and(|q| q.is_geo()));
+    *query_params = other;
+
+    for query_param in geo {
+      let Some(geo_filter) = GeoFilter::parse(&query_param) else {
+        return Err(RecordError::BadRequest("Invalid geo filter"));
+      };
+      geo_filters.push((point, geo_filter));
+    }
+  }
+  filter_params.retain(|_, query_params| !query_params.is_empty());
+
+  // Deterministic order independent of the map's iteration order.
+  geo_filters.sort_by(|a, b| a.0.name.cmp(&b.0.name));
+
+  return Ok(geo_filters);
+}
+
+/// Vector column a nearest neighbor search ranks by.
+struct VectorColumn<'a> {
+  /// SQL expression, e.g. `_ROW_."embedding"` or `_VEC_."embedding"` for vec0 index columns.
+  expr: String,
+  metric: VectorMetric,
+  /// vec0 table to join as `_VEC_`, if the column belongs to the table's vector index.
+  vec_table: Option<&'a str>,
+}
+
+/// Resolves the column to rank by among the API's vector columns, i.e. BLOB columns with a
+/// `CHECK(vec_length(col) = N)` constraint, and the columns of its table's vec0 index. Without an
+/// explicit column, there must be exactly one candidate.
+fn resolve_vector_column<'a>(
+  api: &'a RecordApi,
+  nearest: &Nearest,
+) -> Result<VectorColumn<'a>, RecordError> {
+  let table_columns = api.columns().iter().filter_map(|col| {
+    let dimensions = vector_column_dimensions(col)?;
+    return Some((
+      col.name.as_str(),
+      dimensions,
+      VectorColumn {
+        expr: format!(r#"_ROW_."{}""#, col.name),
+        metric: VectorMetric::L2,
+        vec_table: None,
+      },
+    ));
+  });
+  let index_columns = api.vector_index().into_iter().flat_map(|index| {
+    return index.columns.iter().map(|col| {
+      return (
+        col.name.as_str(),
+        col.dimensions,
+        VectorColumn {
+          expr: format!(r#"_VEC_."{}""#, col.name),
+          metric: col
+            .distance_metric
+            .as_deref()
+            .and_then(VectorMetric::parse)
+            .unwrap_or(VectorMetric::L2),
+          vec_table: Some(index.table_name.as_str()),
+        },
+      );
+    });
+  });
+  let mut candidates = table_columns.chain(index_columns);
+
+  let candidate = match nearest.column {
+    Some(ref name) => candidates.find(|(candidate, _, _)| *candidate == name.as_str()),
+    None => match (candidates.next(), candidates.next()) {
+      (Some(candidate), None) => Some(candidate),
+      _ => None,
+    },
+  };
+  let Some((_name, dimensions, mut column)) = candidate else {
+    return Err(RecordError::BadRequest("Invalid nearest column"));
+  };
+  if dimensions != nearest.dimensions() {
+    return Err(RecordError::BadRequest("Invalid nearest vector"));
+  }
+  if let Some(metric) = nearest.metric {
+    column.metric = metric;
+  }
+
+  return Ok(column);
+}
+
+/// Turns free-form search input into an FTS5 query matching all terms, i.e. a sequence of quoted
+/// strings, which avoids syntax errors and column filters. Trailing "*" is kept for prefix queries,
+/// e.g. "data*" matches "database".
+fn fts_query(search: &str) -> String {
+  return search
+    .split_whitespace()
+    .map(|term| {
+      let (term, prefix) = match term.strip_suffix('*') {
+        Some(term) if !term.is_empty() => (term, "*"),
+        _ => (term, ""),
+      };
+      return format!(r#""{}"{prefix}"#, term.replace('"', r#""""#));
+    })
+    .join(" ");
+}
+
+/// Replaces filter values on encrypted columns with their ciphertexts. Only equality filters on
+/// deterministically encrypted columns can be supported, since other ciphertexts don't compare.
+pub(super) fn encrypt_filter_params(
+  api: &RecordApi,
+  filter_params: Option<HashMap<String, Vec<QueryParam>>>,
+  filter: Option<Filter>,
+) -> Result<(Option<HashMap<String, Vec<QueryParam>>>, Option<Filter>), RecordError> {
+  if !api.has_encrypted_columns() {
+    return Ok((filter_params, filter));
+  }
+
+  let mut filter_params = filter_params;
+  for (column_name, query_params) in filter_params.iter_mut().flatten() {
+    for query_param in query_params {
+      encrypt_query_param(api, column_name, query_param)?;
+    }
+  }
+
+  fn encrypt_filter(api: &RecordApi, filter: &mut Filter) -> Result<(), RecordError> {
+    return match filter {
+      Filter::Column(column_name, query_param) => {
+        encrypt_query_param(api, column_name, query_param)
+      }
+      Filter::And(operands) | Filter::Or(operands) => operands
+        .iter_mut()
+        .try_for_each(|operand| encrypt_filter(api, operand)),
+    };
+  }
+
+  let mut filter = filter;
+  if let Some(ref mut filter) = filter {
+    encrypt_filter(api, filter)?;
+  }
+
+  return Ok((filter_params, filter));
+}
+
+fn encrypt_query_param(
+  api: &RecordApi,
+  column_name: &str,
+  query_param: &mut QueryParam,
+) -> Result<(), RecordError> {
+  let Some(encryption) = api
+    .column_index_by_name(column_name)
+    .and_then(|index| api.column_encryption(index))
+  else {
+    return Ok(());
+  };
+
+  if !encryption.deterministic() {
+    return Err(RecordError::BadRequest(
+      "Filtering requires deterministic encryption",
+    ));
+  }
+
+  let encrypt = |value: &str| -> Result<String, RecordError> {
+    return encryption
+      .encrypt(value)
+      .map_err(|err| RecordError::Internal(err.into()));
+  };
+
+  query_param.value = match query_param.qualifier {
+    Some(Qualifier::Equal | Qualifier::NotEqual | Qualifier::Not) => encrypt(&query_param.value)?,
+    // Ciphertexts, i.e. key ids and base64 encoded values, never contain commas.
+    Some(Qualifier::In | Qualifier::NotIn) => query_param
+      .value
+      .split(',')
+      .map(encrypt)
+      .collect::<Result<Vec<_>, _>>()?
+      .join(","),
+    _ => {
+      return Err(RecordError::BadRequest(
+        "Encrypted columns only support equality filters",
+      ));
+    }
+  };
+
+  return Ok(());
+}
+
+fn list_records_arrow(
+  api: &RecordApi,
+  rows: &trailbase_sqlite::Rows,
+  select: Option<&[String]>,
+  cursor: Option<String>,
+  total_count: Option<usize>,
+  has_more: bool,
+) -> Result<Response, RecordError> {
+  let columns = ExportColumns {
+    columns: api.columns().to_vec(),
+    json_metadata: api.json_column_metadata().to_vec(),
+    selected: api
+      .columns()
+      .iter()
+      .enumerate()
+      .filter(|(_, c)| column_filter(&c.name))
+      .filter(|(_, c)| select.is_none_or(|select| select.contains(&c.name)))
+      .map(|(i, _)| i)
+      .collect(),
+  };
+
+  let mut response = (
+    [(header::CONTENT_TYPE, ARROW_STREAM_MIME_TYPE)],
+    encode_arrow_stream(&columns, rows)?,
+  )
+    .into_response();
+
+  let headers = response.headers_mut();
+  if let Some(cursor) = cursor.and_then(|c| c.parse().ok()) {
+    headers.insert(HEADER_CURSOR, cursor);
+  }
+  if let Some(total_count) = total_count {
+    headers.insert(HEADER_TOTAL_COUNT, total_count.into());
+  }
+  headers.insert(
+    HEADER_HAS_MORE,
+    HeaderValue::from_static(if has_more { "true" } else { "false" }),
+  );
+
+  return Ok(response);
+}
+
+#[inline]
+pub(super) fn column_filter(col_name: &str) -> bool {
+  return !col_name.starts_with("_");
+}
+
+#[cfg(test)]
+mod tests {
+  use serde::Deserialize;
+  use std::borrow::Cow;
+  use trailbase_schema::sqlite::sqlite3_parse_into_statement;
+  use trailbase_sqlite::Value;
+
+  use super::*;
+  use crate::admin::user::*;
+  use crate::app_state::*;
+  use crate::auth::api::login::login_with_password;
+  use crate::auth::user::User;
+  use crate::config::proto::{PermissionFlag, RoleListLimitsConfig};
+  use crate::listing::parse_expand;
+  use crate::records::RecordError;
+  use crate::records::query_builder::expand_tables;
+  use crate::records::test_utils::*;
+  use crate::schema_metadata::SchemaMetadataCache;
+  use crate::util::id_to_b64;
+  use crate::util::urlencode;
+
+  fn sanitize_template(template: &str) {
+    assert!(sqlite3_parse_into_statement(template).is_ok(), "{template}");
+    assert!(!template.contains("\n\n"), "{template}");
+  }
+
+  #[test]
+  fn test_list_records_template() {
+    sanitize_template(
+      &ListRecordQueryTemplate {
+        table_name: "table",
+        column_names: &["a", "index"],
+        read_access_clause: "TRUE",
+        filter_clause: "TRUE",
+        cursor_clause: Some("TRUE"),
+        order_clause: "NULL",
+        expanded_tables: &[],
+        fts_table: None,
+        vec_table: None,
+        count: false,
+        offset: false,
+      }
+      .render()
+      .unwrap(),
+    );
+
+    sanitize_template(
+      &ListRecordQueryTemplate {
+        table_name: "table",
+        column_names: &["a", "index"],
+        read_access_clause: "_USER_.id IS NOT NULL",
+        filter_clause: "a = 'value'",
+        cursor_clause: None,
+        order_clause: "'index' ASC",
+        expanded_tables: &[],
+        fts_table: Some("table_fts"),
+        vec_table: Some("table_vec"),
+        count: true,
+        offset: true,
+      }
+      .render()
+      .unwrap(),
+    );
+  }
+
+  #[tokio::test]
+  async fn test_list_records_template_with_expansions() {
+    let state = test_state(None).await.unwrap();
+    let conn = state.conn();
+
+    conn
       .execute(
         r#"CREATE TABLE "other" (
               "index" INTEGER PRIMARY KEY
@@ -392,8 +1328,10 @@ mod tests {
     let table_metadata = schema_metadata.get_table("table").unwrap();
     let expanded_tables = expand_tables(
       &schema_metadata,
+      "table",
       |column_name| table_metadata.column_by_name(column_name).map(|(_, c)| c),
-      &["index"],
+      &parse_expand("index").unwrap(),
+      1,
     )
     .unwrap();
 
@@ -410,6 +1348,8 @@ mod tests {
       cursor_clause: None,
       order_clause: "tid",
       expanded_tables: &expanded_tables,
+      fts_table: None,
+      vec_table: None,
       count: true,
       offset: false,
     }
@@ -482,12 +1422,33 @@ mod tests {
       Path("api".to_string()),
       RawQuery(None),
       None,
+      AcceptFormat::Json,
     )
     .await
-    .unwrap()
-    .0;
+    .unwrap();
+    let response: ListResponse = json_body(response).await;
 
     assert_eq!(3, response.records.len());
+    assert_eq!(Some(false), response.has_more);
+
+    for (query, total_count, has_more) in [
+      ("count=estimate&limit=2", 3, true),
+      // Filtered listings fall back to exact counts.
+      ("count=estimate&id[gt]=1", 2, false),
+    ] {
+      let response = list_records_handler(
+        State(state.clone()),
+        Path("api".to_string()),
+        RawQuery(Some(query.to_string())),
+        None,
+        AcceptFormat::Json,
+      )
+      .await
+      .unwrap();
+      let response: ListResponse = json_body(response).await;
+      assert_eq!(Some(total_count), response.total_count, "{query}");
+      assert_eq!(Some(has_more), response.has_more, "{query}");
+    }
 
     let first: Entry = serde_json::from_value(response.records[0].clone()).unwrap();
 
@@ -496,91 +1457,467 @@ mod tests {
       Path("api".to_string()),
       RawQuery(Some(format!("id={}", first.id))),
       None,
+      AcceptFormat::Json,
     )
     .await
-    .unwrap()
-    .0;
+    .unwrap();
+    let response: ListResponse = json_body(response).await;
 
     assert_eq!(1, response.records.len());
     assert_eq!(
       first,
       serde_json::from_value(response.records[0].clone()).unwrap()
     );
+
+    for (query, expected) in [("id[in]=1,3", vec![3, 1]), ("id[nin]=1,3", vec![2])] {
+      let response = list_records_handler(
+        State(state.clone()),
+        Path("api".to_string()),
+        RawQuery(Some(query.to_string())),
+        None,
+        AcceptFormat::Json,
+      )
+      .await
+      .unwrap();
+      let response: ListResponse = json_body(response).await;
+
+      let ids: Vec<i64> = response
+        .records
+        .into_iter()
+        .map(|r| serde_json::from_value::<Entry>(r).unwrap().id)
+        .collect();
+      assert_eq!(expected, ids, "{query}");
+    }
+
+    let response = list_records_handler(
+      State(state.clone()),
+      Path("api".to_string()),
+      RawQuery(Some("count=true&limit=2".to_string())),
+      None,
+      AcceptFormat::Arrow,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+      ARROW_STREAM_MIME_TYPE,
+      response.headers()[header::CONTENT_TYPE]
+    );
+    assert_eq!("3", response.headers()[HEADER_TOTAL_COUNT]);
+    assert_eq!("2", response.headers()[HEADER_CURSOR]);
+    assert_eq!("true", response.headers()[HEADER_HAS_MORE]);
+
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+      .await
+      .unwrap();
+    let batches: Vec<arrow_array::RecordBatch> =
+      arrow_ipc::reader::StreamReader::try_new(std::io::Cursor::new(body), None)
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(1, batches.len());
+
+    let ids = batches[0]
+      .column_by_name("id")
+      .unwrap()
+      .as_any()
+      .downcast_ref::<arrow_array::Int64Array>()
+      .unwrap();
+    assert_eq!(&[3, 2], ids.values().as_ref());
   }
 
   #[tokio::test]
-  async fn test_record_api_list_messages_api() {
+  async fn test_record_api_list_nearest() {
+    use base64::prelude::*;
+
     let state = test_state(None).await.unwrap();
-    let conn = state.conn();
 
-    create_chat_message_app_tables(&state).await.unwrap();
-    let room0 = add_room(conn, "room0").await.unwrap();
-    let room1 = add_room(conn, "room1").await.unwrap();
-    let password = "Secret!1!!";
+    state
+      .conn()
+      .execute_batch(
+        r#"
+        CREATE TABLE docs (
+          id         INTEGER PRIMARY KEY,
+          public     INTEGER NOT NULL,
+          embedding  BLOB CHECK(vec_length(embedding) = 3)
+        ) STRICT;
+        INSERT INTO docs (id, public, embedding) VALUES
+          (1, 1, vec_f32('[1.0, 0.0, 0.0]')),
+          (2, 1, vec_f32('[0.0, 1.0, 0.0]')),
+          (3, 1, vec_f32('[0.9, 0.1, 0.0]')),
+          (4, 0, vec_f32('[1.0, 0.0, 0.0]')),
+          (5, 1, NULL);
+      "#,
+      )
+      .await
+      .unwrap();
+
+    state.schema_metadata().invalidate_all().await.unwrap();
 
     add_record_api_config(
       &state,
       RecordApiConfig {
-        name: Some("messages_api".to_string()),
-        table_name: Some("message".to_string()),
-        acl_authenticated: [PermissionFlag::Create as i32, PermissionFlag::Read as i32].into(),
-        read_access_rule: Some("(_ROW_._owner = _USER_.id OR EXISTS(SELECT 1 FROM room_members WHERE room = _ROW_.room AND user = _USER_.id))".to_string()),
+        name: Some("docs".to_string()),
+        table_name: Some("docs".to_string()),
+        acl_world: [PermissionFlag::Read as i32].into(),
+        read_access_rule: Some("_ROW_.public = 1".to_string()),
         ..Default::default()
       },
     )
     .await
     .unwrap();
 
-    {
-      // Unauthenticated users cannot list
-      let response = list_records(&state, None, None).await;
-      assert!(
-        is_auth_err(response.as_ref().err().unwrap()),
-        "{response:?}"
+    let vector: Vec<u8> = [1.0f32, 0.0, 0.0]
+      .iter()
+      .flat_map(|f| f.to_le_bytes())
+      .collect();
+    let nearest = format!("embedding:{}", BASE64_URL_SAFE.encode(&vector));
+
+    let list = async |query: String| -> Result<Vec<i64>, RecordError> {
+      let response = list_records_handler(
+        State(state.clone()),
+        Path("docs".to_string()),
+        RawQuery(Some(query)),
+        None,
+        AcceptFormat::Json,
+      )
+      .await?;
+      let response: ListResponse = json_body(response).await;
+      assert!(response.cursor.is_none());
+      return Ok(
+        response
+          .records
+          .iter()
+          .map(|r| r["id"].as_i64().unwrap())
+          .collect(),
       );
-    }
+    };
 
-    let user_x_email = "user_x@test.com";
-    let user_x = create_user_for_test(&state, user_x_email, password)
-      .await
-      .unwrap()
-      .into_bytes();
-    let user_x_token = login_with_password(&state, user_x_email, password)
-      .await
-      .unwrap();
+    // Record 4 is filtered by the access rule.
+    assert_eq!(
+      vec![1, 3, 2],
+      list(format!("nearest={nearest}&k=3")).await.unwrap()
+    );
+    assert_eq!(
+      vec![3, 2, 5],
+      list(format!("nearest={nearest}&id[gt]=1")).await.unwrap()
+    );
 
-    add_user_to_room(conn, user_x, room0).await.unwrap();
-    send_message(conn, user_x, room0, "user_x to room0")
+    assert!(list(format!("nearest={nearest}&order=id")).await.is_err());
+    assert!(
+      list(format!(
+        "nearest=public:{}",
+        BASE64_URL_SAFE.encode(&vector)
+      ))
       .await
-      .unwrap();
+      .is_err()
+    );
+    assert!(
+      list(format!(
+        "nearest=embedding:{}",
+        BASE64_URL_SAFE.encode(&vector[..8])
+      ))
+      .await
+      .is_err()
+    );
 
-    let user_y_email = "user_y@foo.baz";
-    let user_y = create_user_for_test(&state, user_y_email, password)
+    // The single vector column may be omitted.
+    assert_eq!(
+      vec![1, 3],
+      list(format!(
+        "vector={}&metric=cosine&k=2",
+        BASE64_URL_SAFE.encode(&vector)
+      ))
       .await
       .unwrap()
-      .into_bytes();
+    );
+  }
 
-    add_user_to_room(conn, user_y, room0).await.unwrap();
-    send_message(conn, user_y, room0, "user_y to room0")
-      .await
-      .unwrap();
+  #[tokio::test]
+  async fn test_record_api_list_geo_filters() {
+    let state = test_state(None).await.unwrap();
 
-    add_user_to_room(conn, user_y, room1).await.unwrap();
-    send_message(conn, user_y, room1, "user_y to room1")
+    state
+      .conn()
+      .execute_batch(
+        r#"
+        CREATE TABLE places (
+          id         INTEGER PRIMARY KEY,
+          lat        REAL,
+          lng        REAL
+        ) STRICT;
+        CREATE VIRTUAL TABLE places_rtree USING rtree(id, min_lat, max_lat, min_lng, max_lng);
+
+        INSERT INTO places (id, lat, lng) VALUES
+          (1, 52.52, 13.405),
+          (2, 52.39, 13.065),
+          (3, 48.8566, 2.3522),
+          (4, 53.55, 9.99),
+          (5, NULL, NULL);
+        INSERT INTO places_rtree SELECT id, lat, lat, lng, lng FROM places WHERE lat IS NOT NULL;
+
+        CREATE TABLE shops (
+          id         INTEGER PRIMARY KEY,
+          pos_lat    REAL NOT NULL,
+          pos_lng    REAL NOT NULL
+        ) STRICT;
+        INSERT INTO shops (id, pos_lat, pos_lng) VALUES (1, 52.52, 13.405), (2, 48.8566, 2.3522);
+      "#,
+      )
       .await
       .unwrap();
 
-    let user_y_token = login_with_password(&state, user_y_email, password)
+    state.schema_metadata().invalidate_all().await.unwrap();
+
+    for name in ["places", "shops"] {
+      add_record_api_config(
+        &state,
+        RecordApiConfig {
+          name: Some(name.to_string()),
+          table_name: Some(name.to_string()),
+          acl_world: [PermissionFlag::Read as i32].into(),
+          ..Default::default()
+        },
+      )
       .await
       .unwrap();
+    }
 
-    {
-      // User X can list the messages they have access to, i.e. room0.
-      let resp = list_records(&state, Some(&user_x_token.auth_token), None)
-        .await
-        .unwrap();
+    let list = async |api: &str, query: &str| -> Result<Vec<i64>, RecordError> {
+      let response = list_records_handler(
+        State(state.clone()),
+        Path(api.to_string()),
+        RawQuery(Some(query.to_string())),
+        None,
+        AcceptFormat::Json,
+      )
+      .await?;
+      let response: ListResponse = json_body(response).await;
+      return Ok(
+        response
+          .records
+          .iter()
+          .map(|r| r["id"].as_i64().unwrap())
+          .collect(),
+      );
+    };
 
-      assert_eq!(resp.records.len(), 2);
+    // Ordered by distance from Berlin.
+    assert_eq!(
+      vec![1, 2],
+      list("places", "location[near]=52.52,13.405,50000")
+        .await
+        .unwrap()
+    );
+    assert_eq!(
+      vec![1, 2, 4],
+      list("places", "location[near]=52.52,13.405,300000")
+        .await
+        .unwrap()
+    );
+    assert_eq!(
+      vec![2, 1],
+      list("places", "location[near]=52.0,13.0,100000&order=-id")
+        .await
+        .unwrap()
+    );
+    assert_eq!(
+      vec![2],
+      list("places", "location[near]=52.52,13.405,50000&id[gt]=1")
+        .await
+        .unwrap()
+    );
+
+    let mut within = list("places", "location[within]=52.0,13.0,53.0,14.0")
+      .await
+      .unwrap();
+    within.sort();
+    assert_eq!(vec![1, 2], within);
+
+    // Without R*Tree index.
+    assert_eq!(
+      vec![2],
+      list("shops", "pos[within]=45,0,50,5").await.unwrap()
+    );
+    assert_eq!(
+      vec![1, 2],
+      list("shops", "pos[near]=52.0,13.0,1000000").await.unwrap()
+    );
+
+    assert!(list("places", "id[near]=52.0,13.0,1000").await.is_err());
+    assert!(
+      list("places", "location[within]=53.0,13.0,52.0,14.0")
+        .await
+        .is_err()
+    );
+    assert!(
+      list("places", "location[near]=95.0,13.0,1000")
+        .await
+        .is_err()
+    );
+    assert!(
+      list("places", "location[near]=52.0,13.0,1000&cursor=abc")
+        .await
+        .is_err()
+    );
+  }
+
+  #[tokio::test]
+  async fn test_record_api_list_nearest_vector_index() {
+    use base64::prelude::*;
+
+    let state = test_state(None).await.unwrap();
+
+    state
+      .conn()
+      .execute_batch(
+        r#"
+        CREATE TABLE notes (
+          id         INTEGER PRIMARY KEY,
+          public     INTEGER NOT NULL
+        ) STRICT;
+        CREATE VIRTUAL TABLE notes_vec USING vec0(embedding float[2] distance_metric=cosine);
+        INSERT INTO notes (id, public) VALUES (1, 1), (2, 1), (3, 1), (4, 1), (5, 0);
+        INSERT INTO notes_vec (rowid, embedding) VALUES
+          (1, vec_f32('[1.0, 0.0]')),
+          (2, vec_f32('[3.0, 0.1]')),
+          (3, vec_f32('[0.0, 1.0]')),
+          (5, vec_f32('[1.0, 0.0]'));
+      "#,
+      )
+      .await
+      .unwrap();
+
+    state.schema_metadata().invalidate_all().await.unwrap();
+
+    add_record_api_config(
+      &state,
+      RecordApiConfig {
+        name: Some("notes".to_string()),
+        table_name: Some("notes".to_string()),
+        acl_world: [PermissionFlag::Read as i32].into(),
+        read_access_rule: Some("_ROW_.public = 1".to_string()),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    let vector: Vec<u8> = [1.0f32, 0.0].iter().flat_map(|f| f.to_le_bytes()).collect();
+    let vector = BASE64_URL_SAFE.encode(&vector);
+
+    let list = async |query: String| -> Result<Vec<i64>, RecordError> {
+      let response = list_records_handler(
+        State(state.clone()),
+        Path("notes".to_string()),
+        RawQuery(Some(query)),
+        None,
+        AcceptFormat::Json,
+      )
+      .await?;
+      let response: ListResponse = json_body(response).await;
+      return Ok(
+        response
+          .records
+          .iter()
+          .map(|r| r["id"].as_i64().unwrap())
+          .collect(),
+      );
+    };
+
+    // Ranked by the index's declared cosine distance. Records without embedding come last and
+    // record 5 is filtered by the access rule.
+    assert_eq!(
+      vec![1, 2, 3, 4],
+      list(format!("vector={vector}")).await.unwrap()
+    );
+    assert_eq!(
+      vec![1, 3, 2, 4],
+      list(format!("nearest=embedding:{vector}&metric=l2"))
+        .await
+        .unwrap()
+    );
+    assert_eq!(
+      vec![2, 3],
+      list(format!("vector={vector}&id[gt]=1&k=2")).await.unwrap()
+    );
+
+    assert!(list(format!("nearest=other:{vector}")).await.is_err());
+  }
+
+  #[tokio::test]
+  async fn test_record_api_list_messages_api() {
+    let state = test_state(None).await.unwrap();
+    let conn = state.conn();
+
+    create_chat_message_app_tables(&state).await.unwrap();
+    let room0 = add_room(conn, "room0").await.unwrap();
+    let room1 = add_room(conn, "room1").await.unwrap();
+    let password = "Secret!1!!";
+
+    add_record_api_config(
+      &state,
+      RecordApiConfig {
+        name: Some("messages_api".to_string()),
+        table_name: Some("message".to_string()),
+        acl_authenticated: [PermissionFlag::Create as i32, PermissionFlag::Read as i32].into(),
+        read_access_rule: Some("(_ROW_._owner = _USER_.id OR EXISTS(SELECT 1 FROM room_members WHERE room = _ROW_.room AND user = _USER_.id))".to_string()),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    {
+      // Unauthenticated users cannot list
+      let response = list_records(&state, None, None).await;
+      assert!(
+        is_auth_err(response.as_ref().err().unwrap()),
+        "{response:?}"
+      );
+    }
+
+    let user_x_email = "user_x@test.com";
+    let user_x = create_user_for_test(&state, user_x_email, password)
+      .await
+      .unwrap()
+      .into_bytes();
+    let user_x_token = login_with_password(&state, user_x_email, password)
+      .await
+      .unwrap();
+
+    add_user_to_room(conn, user_x, room0).await.unwrap();
+    send_message(conn, user_x, room0, "user_x to room0")
+      .await
+      .unwrap();
+
+    let user_y_email = "user_y@foo.baz";
+    let user_y = create_user_for_test(&state, user_y_email, password)
+      .await
+      .unwrap()
+      .into_bytes();
+
+    add_user_to_room(conn, user_y, room0).await.unwrap();
+    send_message(conn, user_y, room0, "user_y to room0")
+      .await
+      .unwrap();
+
+    add_user_to_room(conn, user_y, room1).await.unwrap();
+    send_message(conn, user_y, room1, "user_y to room1")
+      .await
+      .unwrap();
+
+    let user_y_token = login_with_password(&state, user_y_email, password)
+      .await
+      .unwrap();
+
+    {
+      // User X can list the messages they have access to, i.e. room0.
+      let resp = list_records(&state, Some(&user_x_token.auth_token), None)
+        .await
+        .unwrap();
+
+      assert_eq!(resp.records.len(), 2);
 
       let messages: Vec<Message> = resp.records.into_iter().map(to_message).collect();
 
@@ -644,6 +1981,7 @@ mod tests {
       assert_eq!(resp0.records.len(), 1);
       assert_eq!("user_y to room0", to_message(resp0.records[0].clone()).data);
       assert_eq!(resp0.total_count, Some(2));
+      assert_eq!(resp0.has_more, Some(true));
 
       let cursor = resp0.cursor.unwrap();
       let resp1 = list_records(
@@ -657,6 +1995,7 @@ mod tests {
       assert_eq!(resp1.records.len(), 1);
       assert_eq!("user_x to room0", to_message(resp1.records[0].clone()).data);
       assert_eq!(resp1.total_count, Some(2));
+      assert_eq!(resp1.has_more, Some(false));
       let cursor = resp1.cursor.unwrap();
 
       let resp2 = list_records(
@@ -766,52 +2105,86 @@ mod tests {
       assert_eq!(arr_asc, arr_desc.into_iter().rev().collect::<Vec<_>>());
 
       // Ordering and cursor work well together.
-      let cursor_middle = to_message(arr_asc[1].clone()).mid;
+      let cursor_asc = list_records(
+        &state,
+        Some(&user_y_token.auth_token),
+        Some(format!("limit=2&order={}", urlencode("+mid"))),
+      )
+      .await
+      .unwrap()
+      .cursor
+      .unwrap();
 
-      let mut cursored_desc = list_records(
+      let mut cursored_asc = list_records(
         &state,
         Some(&user_y_token.auth_token),
-        Some(format!(
-          "order={}&cursor={cursor_middle}",
-          urlencode("-mid")
-        )),
+        Some(format!("order={}&cursor={cursor_asc}", urlencode("+mid"))),
       )
       .await
       .unwrap()
       .records;
 
-      assert_eq!(cursored_desc.len(), 1);
+      assert_eq!(cursored_asc.len(), 1);
       assert_eq!(
-        to_message(cursored_desc.swap_remove(0)),
-        to_message(arr_asc[0].clone())
+        to_message(cursored_asc.swap_remove(0)),
+        to_message(arr_asc[2].clone())
       );
 
-      let mut cursored_asc = list_records(
+      let cursor_desc = list_records(
+        &state,
+        Some(&user_y_token.auth_token),
+        Some(format!("limit=2&order={}", urlencode("-mid"))),
+      )
+      .await
+      .unwrap()
+      .cursor
+      .unwrap();
+
+      let mut cursored_desc = list_records(
         &state,
         Some(&user_y_token.auth_token),
-        Some(format!(
-          "order={}&cursor={cursor_middle}",
-          urlencode("+mid")
-        )),
+        Some(format!("order={}&cursor={cursor_desc}", urlencode("-mid"))),
       )
       .await
       .unwrap()
       .records;
 
-      assert_eq!(cursored_asc.len(), 1);
+      assert_eq!(cursored_desc.len(), 1);
       assert_eq!(
-        to_message(cursored_asc.swap_remove(0)),
-        to_message(arr_asc[2].clone())
+        to_message(cursored_desc.swap_remove(0)),
+        to_message(arr_asc[0].clone())
+      );
+
+      // Cursors are bound to the sort order they were issued for.
+      assert!(
+        list_records(
+          &state,
+          Some(&user_y_token.auth_token),
+          Some(format!("order={}&cursor={cursor_asc}", urlencode("-mid"))),
+        )
+        .await
+        .is_err()
+      );
+
+      // Raw primary keys aren't accepted as cursors.
+      let mid = to_message(arr_asc[1].clone()).mid;
+      assert!(
+        list_records(
+          &state,
+          Some(&user_y_token.auth_token),
+          Some(format!("order={}&cursor={mid}", urlencode("+mid"))),
+        )
+        .await
+        .is_err()
       );
 
-      // Ordering and cursor return an error when PK is not primary order cirteria.
-      let cursor_first = to_message(arr_asc[0].clone()).mid;
+      // Cursors are bound to the order they were issued for.
       assert!(
         list_records(
           &state,
           Some(&user_y_token.auth_token),
           Some(format!(
-            "order={}&cursor={cursor_first}",
+            "order={}&cursor={cursor_asc}",
             urlencode("+room,+mid")
           )),
         )
@@ -850,15 +2223,793 @@ mod tests {
     auth_token: Option<&str>,
     query: Option<String>,
   ) -> Result<ListResponse, RecordError> {
-    let json_response = list_records_handler(
+    let response = list_records_handler(
       State(state.clone()),
       Path("messages_api".to_string()),
       RawQuery(query),
       auth_token.and_then(|token| User::from_auth_token(&state, token)),
+      AcceptFormat::Json,
     )
     .await?;
 
-    let response: ListResponse = json_response.0;
-    return Ok(response);
+    return Ok(json_body(response).await);
+  }
+
+  #[tokio::test]
+  async fn test_record_api_list_search() {
+    let state = test_state(None).await.unwrap();
+    state
+      .conn()
+      .execute_batch(
+        r#"
+          CREATE TABLE post (id INTEGER PRIMARY KEY, title TEXT, body TEXT) STRICT;
+          CREATE VIRTUAL TABLE post_fts USING fts5(title, body, content='post', content_rowid='id');
+          INSERT INTO post (id, title, body) VALUES
+            (1, 'sqlite', 'an embedded database'),
+            (2, 'databases', 'database database database'),
+            (3, 'unrelated', 'nothing to see');
+          INSERT INTO post_fts(post_fts) VALUES('rebuild');
+        "#,
+      )
+      .await
+      .unwrap();
+    state.refresh_table_cache().await.unwrap();
+
+    add_record_api_config(
+      &state,
+      RecordApiConfig {
+        name: Some("posts".to_string()),
+        table_name: Some("post".to_string()),
+        acl_world: [PermissionFlag::Read as i32].into(),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    let list = async |query: &str| -> Result<Vec<i64>, RecordError> {
+      let response = list_records_handler(
+        State(state.clone()),
+        Path("posts".to_string()),
+        RawQuery(Some(query.to_string())),
+        None,
+        AcceptFormat::Json,
+      )
+      .await?;
+      let response: ListResponse = json_body(response).await;
+      return Ok(
+        response
+          .records
+          .iter()
+          .map(|r| r["id"].as_i64().unwrap())
+          .collect(),
+      );
+    };
+
+    // Ordered by rank.
+    assert_eq!(list("search=database").await.unwrap(), vec![2, 1]);
+    assert_eq!(list("search=data*").await.unwrap(), vec![2, 1]);
+    assert_eq!(list("search=embedded%20database").await.unwrap(), vec![1]);
+    // ...unless ordered explicitly.
+    assert_eq!(list("search=database&order=id").await.unwrap(), vec![1, 2]);
+    // FTS syntax is treated as plain text.
+    assert_eq!(
+      list("search=title:%22nothing%22%20OR").await.unwrap(),
+      Vec::<i64>::new()
+    );
+    // Combined with filters.
+    assert_eq!(list("search=database&id[gt]=1").await.unwrap(), vec![2]);
+
+    let response = list_records_handler(
+      State(state.clone()),
+      Path("posts".to_string()),
+      RawQuery(Some("search=database&count=true".to_string())),
+      None,
+      AcceptFormat::Json,
+    )
+    .await
+    .unwrap();
+    let response: ListResponse = json_body(response).await;
+    assert_eq!(response.total_count, Some(2));
+    assert_eq!(response.cursor, None);
+
+    assert!(list("search=database&cursor=abc").await.is_err());
+  }
+
+  #[tokio::test]
+  async fn test_record_api_list_composite_cursor() {
+    let state = test_state(None).await.unwrap();
+    state
+      .conn()
+      .execute_batch(
+        r#"
+          CREATE TABLE item (id INTEGER PRIMARY KEY, price INTEGER, name TEXT) STRICT;
+          INSERT INTO item (id, price, name) VALUES
+            (1, 10, 'b'), (2, 10, 'a'), (3, NULL, 'c'), (4, 5, NULL), (5, 10, 'a'),
+            (6, NULL, NULL), (7, 20, 'z'), (8, 5, 'y');
+        "#,
+      )
+      .await
+      .unwrap();
+    state.refresh_table_cache().await.unwrap();
+
+    add_record_api_config(
+      &state,
+      RecordApiConfig {
+        name: Some("items".to_string()),
+        table_name: Some("item".to_string()),
+        acl_world: [PermissionFlag::Read as i32].into(),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    let list = async |query: String| -> (Vec<i64>, Option<String>) {
+      let response = list_records_handler(
+        State(state.clone()),
+        Path("items".to_string()),
+        RawQuery(Some(query)),
+        None,
+        AcceptFormat::Json,
+      )
+      .await
+      .unwrap();
+      let response: ListResponse = json_body(response).await;
+      let ids = response
+        .records
+        .iter()
+        .map(|r| r["id"].as_i64().unwrap())
+        .collect();
+      return (ids, response.cursor);
+    };
+
+    for order in ["price,-name", "-price,name", "name", "-name,-price"] {
+      let order = urlencode(order);
+      let (expected, _) = list(format!("order={order}")).await;
+      assert_eq!(expected.len(), 8);
+
+      // Paginating yields the same records in the same order, including ties and NULLs.
+      let mut paginated: Vec<i64> = vec![];
+      let mut cursor: Option<String> = None;
+      loop {
+        let query = match cursor {
+          Some(ref cursor) => format!("order={order}&limit=3&cursor={cursor}"),
+          None => format!("order={order}&limit=3"),
+        };
+        let (ids, next) = list(query).await;
+        if ids.is_empty() {
+          break;
+        }
+        paginated.extend(ids);
+        if next.is_none() {
+          break;
+        }
+        cursor = next;
+      }
+      assert_eq!(expected, paginated, "{order}");
+    }
+  }
+
+  #[tokio::test]
+  async fn test_record_api_composite_primary_key() {
+    let state = test_state(None).await.unwrap();
+    state
+      .conn()
+      .execute_batch(
+        r#"
+          CREATE TABLE membership (
+            org     INTEGER NOT NULL,
+            member  INTEGER NOT NULL,
+            role    TEXT,
+            PRIMARY KEY (org, member)
+          ) STRICT;
+          INSERT INTO membership (org, member, role) VALUES
+            (1, 1, 'owner'), (1, 2, 'admin'), (2, 1, 'member'), (2, 3, NULL), (3, 2, 'owner');
+        "#,
+      )
+      .await
+      .unwrap();
+    state.refresh_table_cache().await.unwrap();
+
+    // Composite primary keys are read-only.
+    assert!(
+      add_record_api_config(
+        &state,
+        RecordApiConfig {
+          name: Some("memberships".to_string()),
+          table_name: Some("membership".to_string()),
+          acl_world: [PermissionFlag::Create as i32, PermissionFlag::Read as i32].into(),
+          ..Default::default()
+        },
+      )
+      .await
+      .is_err()
+    );
+
+    add_record_api_config(
+      &state,
+      RecordApiConfig {
+        name: Some("memberships".to_string()),
+        table_name: Some("membership".to_string()),
+        acl_world: [PermissionFlag::Read as i32].into(),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    let read = async |id: &str| -> Result<serde_json::Value, RecordError> {
+      let response = crate::records::read_record::read_record_handler(
+        State(state.clone()),
+        Path(("memberships".to_string(), id.to_string())),
+        axum::extract::Query(Default::default()),
+        None,
+      )
+      .await?;
+      return Ok(json_body(response).await);
+    };
+
+    assert_eq!(
+      serde_json::json!({"org": 2, "member": 1, "role": "member"}),
+      read("2,1").await.unwrap()
+    );
+    assert!(matches!(
+      read("1,3").await,
+      Err(RecordError::RecordNotFound)
+    ));
+    assert!(read("1").await.is_err());
+    assert!(read("1,2,3").await.is_err());
+
+    let list = async |query: String| -> (Vec<(i64, i64)>, Option<String>) {
+      let response = list_records_handler(
+        State(state.clone()),
+        Path("memberships".to_string()),
+        RawQuery(Some(query)),
+        None,
+        AcceptFormat::Json,
+      )
+      .await
+      .unwrap();
+      let response: ListResponse = json_body(response).await;
+      let ids = response
+        .records
+        .iter()
+        .map(|r| (r["org"].as_i64().unwrap(), r["member"].as_i64().unwrap()))
+        .collect();
+      return (ids, response.cursor);
+    };
+
+    // Defaults to ordering by all primary key columns.
+    let (ids, _) = list(String::new()).await;
+    assert_eq!(vec![(3, 2), (2, 3), (2, 1), (1, 2), (1, 1)], ids);
+
+    for query in ["", "order=org", "order=-role"] {
+      let (expected, _) = list(query.to_string()).await;
+
+      let mut paginated: Vec<(i64, i64)> = vec![];
+      let mut cursor: Option<String> = None;
+      loop {
+        let (ids, next) = match cursor {
+          Some(ref cursor) => list(format!("{query}&limit=2&cursor={cursor}")).await,
+          None => list(format!("{query}&limit=2")).await,
+        };
+        paginated.extend(ids);
+        let Some(next) = next else {
+          break;
+        };
+        cursor = Some(next);
+      }
+      assert_eq!(expected, paginated, "{query}");
+    }
+  }
+
+  #[tokio::test]
+  async fn test_record_api_random_uuid_primary_key() {
+    let state = test_state(None).await.unwrap();
+    state
+      .conn()
+      .execute_batch(
+        r#"
+          CREATE TABLE token (
+            id      BLOB PRIMARY KEY NOT NULL CHECK(is_uuid(id)),
+            value   TEXT
+          ) STRICT;
+          INSERT INTO token (id, value) VALUES
+            (uuid_parse('0c2fa0a3-0bd6-4f40-9a0a-6f6c1a3f0e51'), 'a'),
+            (uuid_parse('f1e2d3c4-b5a6-4978-8a9b-0c1d2e3f4a5b'), 'b'),
+            (uuid_parse('7a8b9c0d-1e2f-4a3b-8c4d-5e6f7a8b9c0d'), 'c');
+        "#,
+      )
+      .await
+      .unwrap();
+    state.refresh_table_cache().await.unwrap();
+
+    let config = RecordApiConfig {
+      name: Some("tokens".to_string()),
+      table_name: Some("token".to_string()),
+      acl_world: [PermissionFlag::Read as i32].into(),
+      ..Default::default()
+    };
+
+    // Random primary keys have to be allowed explicitly.
+    assert!(add_record_api_config(&state, config.clone()).await.is_err());
+
+    add_record_api_config(
+      &state,
+      RecordApiConfig {
+        require_sortable_primary_key: Some(false),
+        ..config
+      },
+    )
+    .await
+    .unwrap();
+
+    let list = async |query: &str| -> Result<ListResponse, RecordError> {
+      let response = list_records_handler(
+        State(state.clone()),
+        Path("tokens".to_string()),
+        RawQuery(Some(query.to_string())),
+        None,
+        AcceptFormat::Json,
+      )
+      .await?;
+      return Ok(json_body(response).await);
+    };
+
+    let values = |response: &ListResponse| -> Vec<String> {
+      return response
+        .records
+        .iter()
+        .map(|r| r["value"].as_str().unwrap().to_string())
+        .collect();
+    };
+
+    // No cursors, offset pagination instead.
+    let first = list("limit=2").await.unwrap();
+    assert_eq!(2, first.records.len());
+    assert_eq!(None, first.cursor);
+
+    let second = list("limit=2&offset=2").await.unwrap();
+    let mut all = [values(&first), values(&second)].concat();
+    all.sort();
+    assert_eq!(vec!["a", "b", "c"], all);
+
+    assert!(list("cursor=abc").await.is_err());
+
+    let response = crate::records::read_record::read_record_handler(
+      State(state.clone()),
+      Path((
+        "tokens".to_string(),
+        "f1e2d3c4-b5a6-4978-8a9b-0c1d2e3f4a5b".to_string(),
+      )),
+      axum::extract::Query(Default::default()),
+      None,
+    )
+    .await
+    .unwrap();
+    let record: serde_json::Value = json_body(response).await;
+    assert_eq!("b", record["value"]);
+  }
+
+  #[tokio::test]
+  async fn test_record_api_list_pattern_filters() {
+    let state = test_state(None).await.unwrap();
+    state
+      .conn()
+      .execute_batch(
+        r#"
+          CREATE TABLE item (id INTEGER PRIMARY KEY, name TEXT) STRICT;
+          INSERT INTO item (id, name) VALUES
+            (1, 'Foo'), (2, 'foo'), (3, 'FOOBAR'), (4, 'bar'), (5, 'Ärger'), (6, 'ärger');
+        "#,
+      )
+      .await
+      .unwrap();
+    state.refresh_table_cache().await.unwrap();
+
+    add_record_api_config(
+      &state,
+      RecordApiConfig {
+        name: Some("items".to_string()),
+        table_name: Some("item".to_string()),
+        acl_world: [PermissionFlag::Read as i32].into(),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    let ids = async |query: &str| -> Vec<i64> {
+      let response = list_records_handler(
+        State(state.clone()),
+        Path("items".to_string()),
+        RawQuery(Some(format!("{query}&order=id"))),
+        None,
+        AcceptFormat::Json,
+      )
+      .await
+      .unwrap();
+      let response: ListResponse = json_body(response).await;
+      return response
+        .records
+        .iter()
+        .map(|r| r["id"].as_i64().unwrap())
+        .collect();
+    };
+
+    assert_eq!(ids("name[ilike]=foo").await, vec![1, 2]);
+    assert_eq!(ids("name[ilike]=FOO%25").await, vec![1, 2, 3]);
+    assert_eq!(ids("name[ilike]=%25Bar").await, vec![3, 4]);
+    assert_eq!(ids("name[like]=foo%25").await, vec![1, 2, 3]);
+    assert_eq!(ids("name[glob]=F*").await, vec![1, 3]);
+    assert_eq!(
+      ids("filter[$or][0][name][glob]=b*&filter[$or][1][name][ilike]=fOo").await,
+      vec![1, 2, 4]
+    );
+
+    // Pattern matching ignores case also for non-ASCII characters.
+    assert_eq!(ids("name[like]=ä%25").await, vec![5, 6]);
+  }
+
+  #[tokio::test]
+  async fn test_record_api_list_select() {
+    let state = test_state(None).await.unwrap();
+    state
+      .conn()
+      .execute_batch(
+        r#"
+          CREATE TABLE item (id INTEGER PRIMARY KEY, name TEXT, price INTEGER, _hidden TEXT) STRICT;
+          INSERT INTO item (id, name, price, _hidden) VALUES (1, 'a', 10, 'x'), (2, 'b', 20, 'y');
+        "#,
+      )
+      .await
+      .unwrap();
+    state.refresh_table_cache().await.unwrap();
+
+    add_record_api_config(
+      &state,
+      RecordApiConfig {
+        name: Some("items".to_string()),
+        table_name: Some("item".to_string()),
+        acl_world: [PermissionFlag::Read as i32].into(),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    let list = async |query: &str| -> Result<Vec<serde_json::Value>, RecordError> {
+      let response = list_records_handler(
+        State(state.clone()),
+        Path("items".to_string()),
+        RawQuery(Some(query.to_string())),
+        None,
+        AcceptFormat::Json,
+      )
+      .await?;
+      let response: ListResponse = json_body(response).await;
+      return Ok(response.records);
+    };
+
+    assert_eq!(
+      list("select=id,price&order=id").await.unwrap(),
+      vec![
+        serde_json::json!({"id": 1, "price": 10}),
+        serde_json::json!({"id": 2, "price": 20}),
+      ]
+    );
+    // Projections don't affect filtering or ordering on other columns.
+    assert_eq!(
+      list("select=price&name=b").await.unwrap(),
+      vec![serde_json::json!({"price": 20})]
+    );
+    assert!(list("select=missing").await.is_err());
+    assert!(list("select=_hidden").await.is_err());
+  }
+
+  #[tokio::test]
+  async fn test_record_api_list_limits() {
+    let state = test_state(None).await.unwrap();
+    state
+      .conn()
+      .execute_batch(
+        r#"
+          CREATE TABLE item (id INTEGER PRIMARY KEY, name TEXT) STRICT;
+          INSERT INTO item (name) VALUES ('a'), ('b'), ('c'), ('d'), ('e');
+        "#,
+      )
+      .await
+      .unwrap();
+    state.refresh_table_cache().await.unwrap();
+
+    let mut config = state.get_config();
+    config.server.list_limits = Some(ListLimitsConfig {
+      default_limit: Some(3),
+      max_limit: Some(4),
+      roles: vec![],
+    });
+    state
+      .validate_and_update_config(config, None)
+      .await
+      .unwrap();
+
+    for (name, list_limits) in [
+      ("items", None),
+      (
+        "limited_items",
+        Some(ListLimitsConfig {
+          default_limit: Some(1),
+          max_limit: Some(2),
+          roles: vec![RoleListLimitsConfig {
+            role: Some("editor".to_string()),
+            default_limit: None,
+            max_limit: Some(5),
+          }],
+        }),
+      ),
+    ] {
+      add_record_api_config(
+        &state,
+        RecordApiConfig {
+          name: Some(name.to_string()),
+          table_name: Some("item".to_string()),
+          acl_world: [PermissionFlag::Read as i32].into(),
+          list_limits,
+          ..Default::default()
+        },
+      )
+      .await
+      .unwrap();
+    }
+
+    let list = async |api: &str, query: &str, user: Option<User>| -> Result<usize, RecordError> {
+      let response = list_records_handler(
+        State(state.clone()),
+        Path(api.to_string()),
+        RawQuery(Some(query.to_string())),
+        user,
+        AcceptFormat::Json,
+      )
+      .await?;
+      let response: ListResponse = json_body(response).await;
+      return Ok(response.records.len());
+    };
+
+    // Server-wide limits.
+    assert_eq!(list("items", "", None).await.unwrap(), 3);
+    assert_eq!(list("items", "limit=4", None).await.unwrap(), 4);
+    assert!(matches!(
+      list("items", "limit=5", None).await,
+      Err(RecordError::LimitExceeded(4))
+    ));
+
+    // Per-API limits.
+    assert_eq!(list("limited_items", "", None).await.unwrap(), 1);
+    assert!(matches!(
+      list("limited_items", "limit=3", None).await,
+      Err(RecordError::LimitExceeded(2))
+    ));
+
+    // Per-role limits.
+    let mut editor = User::from_unverified(uuid::Uuid::now_v7(), "editor@test.org");
+    editor.roles = vec!["editor".to_string()];
+    assert_eq!(
+      list("limited_items", "", Some(editor.clone()))
+        .await
+        .unwrap(),
+      1
+    );
+    assert_eq!(
+      list("limited_items", "limit=5", Some(editor.clone()))
+        .await
+        .unwrap(),
+      5
+    );
+
+    let api = state.lookup_record_api("limited_items").unwrap();
+    assert_eq!(
+      list_limits(&state, &api, Some(&editor)),
+      ListLimits {
+        default_limit: 1,
+        max_limit: 5
+      }
+    );
+  }
+
+  #[tokio::test]
+  async fn test_record_api_list_streaming() {
+    let state = test_state(None).await.unwrap();
+    state
+      .conn()
+      .execute_batch(
+        r#"
+          CREATE TABLE item (id INTEGER PRIMARY KEY, name TEXT, price INTEGER) STRICT;
+          INSERT INTO item (id, name, price) VALUES (1, 'a,b', 10), (2, 'c', NULL), (3, 'd', 30);
+        "#,
+      )
+      .await
+      .unwrap();
+    state.refresh_table_cache().await.unwrap();
+
+    add_record_api_config(
+      &state,
+      RecordApiConfig {
+        name: Some("items".to_string()),
+        table_name: Some("item".to_string()),
+        acl_world: [PermissionFlag::Read as i32].into(),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    let list = async |query: &str, accept: AcceptFormat| -> Result<(String, String), RecordError> {
+      let response = list_records_handler(
+        State(state.clone()),
+        Path("items".to_string()),
+        RawQuery(Some(query.to_string())),
+        None,
+        accept,
+      )
+      .await?;
+
+      let content_type = response.headers()[header::CONTENT_TYPE]
+        .to_str()
+        .unwrap()
+        .to_string();
+      let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+      return Ok((content_type, String::from_utf8(body.to_vec()).unwrap()));
+    };
+
+    let (content_type, body) = list("order=id&id[lt]=3", AcceptFormat::Csv).await.unwrap();
+    assert!(content_type.starts_with("text/csv"), "{content_type}");
+    assert_eq!("id,name,price\r\n1,\"a,b\",10\r\n2,c,\r\n", body);
+
+    let (content_type, body) = list(
+      "format=ndjson&select=id,price&order=-id",
+      AcceptFormat::Json,
+    )
+    .await
+    .unwrap();
+    assert_eq!(content_type, "application/x-ndjson");
+    assert_eq!(
+      "{\"id\":3,\"price\":30}\n{\"id\":2,\"price\":null}\n{\"id\":1,\"price\":10}\n",
+      body
+    );
+
+    // Streams aren't subject to the max page size.
+    let (_, body) = list("format=csv&limit=1000", AcceptFormat::Json)
+      .await
+      .unwrap();
+    assert_eq!(body.lines().count(), 4);
+
+    assert!(
+      list("format=csv&select=_hidden", AcceptFormat::Json)
+        .await
+        .is_err()
+    );
+  }
+
+  #[tokio::test]
+  async fn test_record_api_list_json_paths() {
+    let state = test_state(None).await.unwrap();
+    state
+      .conn()
+      .execute_batch(
+        r#"
+          CREATE TABLE person (
+            id       INTEGER PRIMARY KEY,
+            profile  TEXT CHECK(jsonschema_matches('{"type": "object"}', profile))
+          ) STRICT;
+          INSERT INTO person (id, profile) VALUES
+            (1, '{"age": 42, "address": {"city": "Berlin"}}'),
+            (2, '{"age": 7, "address": {"city": "Paris"}}'),
+            (3, '{"age": 23, "address": {"city": "Berlin"}}');
+        "#,
+      )
+      .await
+      .unwrap();
+    state.refresh_table_cache().await.unwrap();
+
+    add_record_api_config(
+      &state,
+      RecordApiConfig {
+        name: Some("people".to_string()),
+        table_name: Some("person".to_string()),
+        acl_world: [PermissionFlag::Read as i32].into(),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    let list = async |query: &str| -> Result<Vec<i64>, RecordError> {
+      let response = list_records_handler(
+        State(state.clone()),
+        Path("people".to_string()),
+        RawQuery(Some(query.to_string())),
+        None,
+        AcceptFormat::Json,
+      )
+      .await?;
+      let response: ListResponse = json_body(response).await;
+      return Ok(
+        response
+          .records
+          .iter()
+          .map(|r| r["id"].as_i64().unwrap())
+          .collect(),
+      );
+    };
+
+    assert_eq!(
+      list("profile.address.city=Berlin").await.unwrap(),
+      vec![3, 1]
+    );
+    assert_eq!(list("profile.age[lt]=30").await.unwrap(), vec![3, 2]);
+    assert_eq!(list("order=profile.age").await.unwrap(), vec![2, 3, 1]);
+    assert_eq!(
+      list(&format!("order={}", urlencode("-profile.age")))
+        .await
+        .unwrap(),
+      vec![1, 3, 2]
+    );
+    assert!(list("order=id.age").await.is_err());
+  }
+
+  #[test]
+  fn test_fts_query() {
+    assert_eq!(fts_query("foo bar"), r#""foo" "bar""#);
+    assert_eq!(fts_query("  data*  "), r#""data"*"#);
+    assert_eq!(fts_query(r#"a"b OR c:d"#), r#""a""b" "OR" "c:d""#);
+    assert_eq!(fts_query("*"), r#""*""#);
+  }
+
+  #[tokio::test]
+  async fn test_json_list_response() {
+    async fn roundtrip(list: ListResponse) -> (bool, serde_json::Value) {
+      let response = json_list_response(list);
+      let has_length = response.headers().contains_key(header::CONTENT_LENGTH)
+        || axum::body::HttpBody::size_hint(response.body())
+          .exact()
+          .is_some();
+      let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+      return (has_length, serde_json::from_slice(&body).unwrap());
+    }
+
+    let small = ListResponse {
+      cursor: Some("abc\"".to_string()),
+      total_count: Some(2),
+      has_more: Some(true),
+      records: vec![serde_json::json!({"id": 1}), serde_json::json!({"id": 2})],
+    };
+    let (has_length, value) = roundtrip(small.clone()).await;
+    assert!(has_length);
+    assert_eq!(value, serde_json::to_value(&small).unwrap());
+
+    let large = ListResponse {
+      cursor: None,
+      total_count: None,
+      has_more: None,
+      records: (0..2000)
+        .map(|i| serde_json::json!({"id": i, "text": "x".repeat(64)}))
+        .collect(),
+    };
+    let (has_length, value) = roundtrip(large.clone()).await;
+    assert!(!has_length);
+    assert_eq!(value, serde_json::to_value(&large).unwrap());
+
+    let empty = ListResponse {
+      cursor: None,
+      total_count: None,
+      has_more: None,
+      records: vec![],
+    };
+    assert_eq!(roundtrip(empty).await.1, serde_json::json!({"records": []}));
   }
 }
@@ -1,32 +1,57 @@
 use axum::{
-  Router,
-  routing::{delete, get, patch, post},
+  Router, middleware,
+  routing::{delete, get, head, options, patch, post},
 };
 use utoipa::OpenApi;
 
+pub(crate) mod aggregate_records;
+pub(crate) mod cache;
+mod client;
 pub(crate) mod create_record;
+mod cursor;
 pub(crate) mod delete_record;
+pub(crate) mod embeddings;
+mod encoding;
+pub(crate) mod encryption;
 mod error;
+pub(crate) mod etag;
+pub(crate) mod export_records;
 pub(crate) mod files;
+pub(crate) mod geojson;
+pub mod hooks;
+pub(crate) mod image_transform;
+pub(crate) mod import_records;
+pub(crate) mod json_api;
 pub(crate) mod json_schema;
+pub(crate) mod list_query_cache;
 pub(crate) mod list_records;
 pub(crate) mod params;
 pub mod query_builder;
+pub(crate) mod query_plan;
 pub(crate) mod read_record;
 mod record_api;
+pub(crate) mod request_context;
+pub(crate) mod signed_url;
 pub mod sql_to_json;
 pub(crate) mod subscribe;
+pub(crate) mod sync;
 pub mod test_utils;
-mod update_record;
+pub(crate) mod transaction;
+pub(crate) mod tus_upload;
+pub(crate) mod update_record;
 mod validate;
+pub mod validators;
+pub(crate) mod webhooks;
 
-pub(crate) use error::RecordError;
+pub use client::RecordsClient;
+pub use error::RecordError;
+pub use list_records::ListResponse;
 pub use record_api::RecordApi;
 pub(crate) use validate::validate_record_api_config;
 
 use crate::AppState;
 use crate::config::proto::PermissionFlag;
-use crate::constants::RECORD_API_PATH;
+use crate::constants::{RECORD_API_PATH, SYNC_API_PATH, TRANSACTION_API_PATH};
 
 #[derive(OpenApi)]
 #[openapi(
@@ -34,13 +59,25 @@ use crate::constants::RECORD_API_PATH;
     read_record::read_record_handler,
     read_record::get_uploaded_file_from_record_handler,
     read_record::get_uploaded_files_from_record_handler,
+    signed_url::signed_file_url_handler,
     list_records::list_records_handler,
+    export_records::export_records_handler,
+    aggregate_records::aggregate_records_handler,
+    import_records::import_records_handler,
+    import_records::import_job_handler,
     create_record::create_record_handler,
     update_record::update_record_handler,
     delete_record::delete_record_handler,
+    delete_record::undelete_record_handler,
     json_schema::json_schema_handler,
   ),
-  components(schemas(create_record::CreateRecordResponse))
+  components(schemas(
+    create_record::CreateRecordResponse,
+    create_record::OnConflict,
+    import_records::ImportReport,
+    import_records::ImportJobResponse,
+    signed_url::SignedFileUrlResponse
+  ))
 )]
 pub(super) struct RecordOpenApi;
 
@@ -62,6 +99,10 @@ pub(crate) fn router() -> Router<AppState> {
       &format!("/{RECORD_API_PATH}/{{name}}/{{record}}"),
       delete(delete_record::delete_record_handler),
     )
+    .route(
+      &format!("/{RECORD_API_PATH}/{{name}}/{{record}}/undelete"),
+      post(delete_record::undelete_record_handler),
+    )
     .route(
       &format!("/{RECORD_API_PATH}/{{name}}"),
       get(list_records::list_records_handler),
@@ -74,14 +115,65 @@ pub(crate) fn router() -> Router<AppState> {
       &format!("/{RECORD_API_PATH}/{{name}}/{{record}}/files/{{column_name}}/{{file_index}}"),
       get(read_record::get_uploaded_files_from_record_handler),
     )
+    .route(
+      &format!("/{RECORD_API_PATH}/{{name}}/{{record}}/signed_url/{{column_name}}"),
+      get(signed_url::signed_file_url_handler),
+    )
+    .route(
+      &format!("/{RECORD_API_PATH}/{{name}}/{{record}}/upload/{{column_name}}"),
+      post(tus_upload::create_upload_handler).options(tus_upload::upload_options_handler),
+    )
+    .route(
+      &format!("/{RECORD_API_PATH}/{{name}}/{{record}}/upload/{{column_name}}/{{upload_id}}"),
+      head(tus_upload::upload_offset_handler)
+        .patch(tus_upload::append_upload_handler)
+        .delete(tus_upload::terminate_upload_handler),
+    )
+    .route(
+      &format!("/{RECORD_API_PATH}/{{name}}/export"),
+      get(export_records::export_records_handler),
+    )
+    .route(
+      &format!("/{RECORD_API_PATH}/{{name}}/aggregate"),
+      get(aggregate_records::aggregate_records_handler),
+    )
+    .route(
+      &format!("/{RECORD_API_PATH}/{{name}}/import"),
+      post(import_records::import_records_handler),
+    )
+    .route(
+      &format!("/{RECORD_API_PATH}/{{name}}/import/{{job}}"),
+      get(import_records::import_job_handler),
+    )
     .route(
       &format!("/{RECORD_API_PATH}/{{name}}/schema"),
       get(json_schema::json_schema_handler),
     )
+    .route(
+      &format!("/{RECORD_API_PATH}/{{name}}/subscribe"),
+      get(subscribe::add_table_subscription_sse_handler),
+    )
+    .route(
+      &format!("/{RECORD_API_PATH}/{{name}}/subscribe/ws"),
+      get(subscribe::add_subscription_ws_handler),
+    )
     .route(
       &format!("/{RECORD_API_PATH}/{{name}}/subscribe/{{record}}"),
       get(subscribe::add_subscription_sse_handler),
-    );
+    )
+    .route(
+      &format!("/{TRANSACTION_API_PATH}"),
+      post(transaction::transaction_handler),
+    )
+    .route(&format!("/{SYNC_API_PATH}/pull"), get(sync::pull_handler))
+    .route(&format!("/{SYNC_API_PATH}/push"), post(sync::push_handler))
+    .layer(middleware::from_fn(
+      request_context::request_context_middleware,
+    ))
+    .layer(middleware::from_fn(
+      crate::auth::api_key::accept_api_keys_middleware,
+    ))
+    .layer(middleware::from_fn(encoding::encode_response_middleware));
 }
 
 // Since this is for APIs access control, we'll use the API- space CRUD terminology instead of
@@ -2,12 +2,16 @@ use base64::prelude::*;
 use log::*;
 use std::collections::HashSet;
 use std::sync::Arc;
+use trailbase_extension::geometry::{GeometryError, geojson_to_wkb};
+use trailbase_schema::metadata::is_geometry_column;
 use trailbase_schema::sqlite::{Column, ColumnDataType};
 use trailbase_schema::{FileUpload, FileUploadInput, FileUploads};
 use trailbase_sqlite::{NamedParams, Value};
 
 use crate::records::RecordApi;
-use crate::schema_metadata::{self, JsonColumnMetadata, TableMetadata};
+use crate::records::encryption::EncryptionError;
+use crate::records::validators::FieldError;
+use crate::schema_metadata::{self, JsonColumnMetadata, JsonSchemaError, TableMetadata};
 
 #[derive(Debug, Clone, thiserror::Error)]
 pub enum ParamsError {
@@ -39,6 +43,16 @@ pub enum ParamsError {
   Schema(#[from] trailbase_schema::Error),
   #[error("ObjectStore error: {0}")]
   Storage(Arc<object_store::Error>),
+  #[error("Field validation failed: {0:?}")]
+  FieldValidation(Vec<FieldError>),
+  #[error("Generated column: {0}")]
+  GeneratedColumn(String),
+  #[error("Geometry error: {0}")]
+  Geometry(#[from] GeometryError),
+  #[error("Encryption error: {0}")]
+  Encryption(#[from] EncryptionError),
+  #[error("File constraint: {0}")]
+  FileConstraint(&'static str),
 }
 
 impl From<serde_json::Error> for ParamsError {
@@ -62,6 +76,16 @@ pub trait SchemaAccessor {
     &self,
     field_name: &str,
   ) -> Option<(usize, &Column, Option<&JsonColumnMetadata>)>;
+
+  /// Applies custom column validators, if any, returning the first failure's message.
+  fn validate_column(&self, _index: usize, _value: &serde_json::Value) -> Result<(), String> {
+    return Ok(());
+  }
+
+  /// Encrypts the converted value of an encrypted column, otherwise returns it as is.
+  fn encrypt_column(&self, _index: usize, value: Value) -> Result<Value, ParamsError> {
+    return Ok(value);
+  }
 }
 
 /// Implementation to build insert/update Params for admin APIs.
@@ -92,6 +116,24 @@ impl SchemaAccessor for RecordApi {
       );
     });
   }
+
+  fn validate_column(&self, index: usize, value: &serde_json::Value) -> Result<(), String> {
+    for validator in self.column_validators(index) {
+      validator(value)?;
+    }
+    return Ok(());
+  }
+
+  fn encrypt_column(&self, index: usize, value: Value) -> Result<Value, ParamsError> {
+    let Some(encryption) = self.column_encryption(index) else {
+      return Ok(value);
+    };
+    return match value {
+      Value::Text(plaintext) => Ok(Value::Text(encryption.encrypt(&plaintext)?)),
+      Value::Null => Ok(Value::Null),
+      _ => Err(ParamsError::Column("Encrypted columns only accept text")),
+    };
+  }
 }
 
 /// Represents a record provided by the user via request, i.e. a create or update record request.
@@ -142,6 +184,7 @@ impl Params {
       column_names: Vec::with_capacity(len),
       column_indexes: Vec::with_capacity(len),
     };
+    let mut field_errors: Vec<FieldError> = vec![];
 
     for (key, value) in json {
       // We simply skip unknown columns, this could simply be malformed input or version skew. This
@@ -150,18 +193,50 @@ impl Params {
         continue;
       };
 
-      let (param, mut json_files) = extract_params_and_files_from_json(col, json_meta, value)?;
+      // Generated columns are computed by SQLite, writing them would fail with an opaque error.
+      if col.is_generated() {
+        return Err(ParamsError::GeneratedColumn(key));
+      }
+
+      // Collect all validation failures rather than returning on the first one, so clients can
+      // report every offending field at once.
+      if let Err(message) = accessor.validate_column(index, &value) {
+        field_errors.push(FieldError {
+          field: key,
+          code: "invalid".to_string(),
+          message,
+        });
+        continue;
+      }
+
+      let (param, mut json_files) = match extract_params_and_files_from_json(col, json_meta, value)
+      {
+        Ok(result) => result,
+        Err(err) => match to_field_errors(&key, &err) {
+          Some(errors) => {
+            field_errors.extend(errors);
+            continue;
+          }
+          None => return Err(err),
+        },
+      };
       if let Some(json_files) = json_files.as_mut() {
         // Note: files provided as a multipart form upload are handled below. They need more
         // special handling to establish the field.name to column mapping.
         params.files.append(json_files);
       }
 
+      let param = accessor.encrypt_column(index, param)?;
+
       params.named_params.push((prefix_colon(&key).into(), param));
       params.column_names.push(key);
       params.column_indexes.push(index);
     }
 
+    if !field_errors.is_empty() {
+      return Err(ParamsError::FieldValidation(field_errors));
+    }
+
     // Note: files provided as part of a JSON request are handled above.
     if let Some(multipart_files) = multipart_files {
       params.append_multipart_files(accessor, multipart_files)?;
@@ -170,6 +245,37 @@ impl Params {
     return Ok(params);
   }
 
+  /// Overrides fields with the given values, e.g. ones changed by record hooks. The values are
+  /// validated and converted like request fields.
+  pub(crate) fn override_with<S: SchemaAccessor>(
+    &mut self,
+    accessor: &S,
+    json: JsonRow,
+  ) -> Result<(), ParamsError> {
+    let mut overrides = Params::from(accessor, json, None)?;
+
+    for ((name, param), (column_name, column_index)) in overrides.named_params.into_iter().zip(
+      overrides
+        .column_names
+        .into_iter()
+        .zip(overrides.column_indexes),
+    ) {
+      match self.column_names.iter().position(|c| *c == column_name) {
+        Some(pos) => {
+          self.named_params[pos].1 = param;
+        }
+        None => {
+          self.named_params.push((name, param));
+          self.column_names.push(column_name);
+          self.column_indexes.push(column_index);
+        }
+      }
+    }
+    self.files.append(&mut overrides.files);
+
+    return Ok(());
+  }
+
   fn append_multipart_files<S: SchemaAccessor>(
     &mut self,
     accessor: &S,
@@ -190,17 +296,30 @@ impl Params {
 
     // Validate and organize by type;
     let mut uploaded_files = HashSet::<&'static str>::new();
-    for (field_name, file_metadata, _content) in &files {
+    for (field_name, file_metadata, content) in &files {
       // We simply skip unknown columns, this could simply be malformed input or version skew. This
       // is similar in spirit to protobuf's unknown fields behavior.
       let Some((index, col, json_meta)) = accessor.column_by_name(field_name) else {
         continue;
       };
 
-      let Some(JsonColumnMetadata::SchemaName(schema_name)) = &json_meta else {
+      let Some(JsonColumnMetadata::SchemaName(schema_name, constraints)) = &json_meta else {
         return Err(ParamsError::Column("Expected json column"));
       };
 
+      constraints
+        .check_file(file_metadata, content.len() as u64)
+        .map_err(ParamsError::FileConstraint)?;
+      if schema_name == "std.FileUploads" {
+        let count = files
+          .iter()
+          .filter(|(name, _, _)| name == field_name)
+          .count();
+        constraints
+          .check_count(count)
+          .map_err(ParamsError::FileConstraint)?;
+      }
+
       let value = Value::Text(serde_json::to_string(&file_metadata)?);
       match schema_name.as_str() {
         "std.FileUpload" => {
@@ -300,6 +419,11 @@ fn extract_params_and_files_from_json(
   let col_name = &col.name;
   match value {
     serde_json::Value::Object(ref _map) => {
+      // GeoJSON geometries are validated and stored as WKB.
+      if is_geometry_column(col) {
+        return Ok((Value::Blob(geojson_to_wkb(&value)?), None));
+      }
+
       // Only text columns are allowed to store nested JSON as text.
       if col.data_type != ColumnDataType::Text {
         return Err(ParamsError::NestedObject(format!(
@@ -317,10 +441,13 @@ fn extract_params_and_files_from_json(
       // For FileUpload columns we have special handling to extract the actual payload and
       // convert the FileUploadInput into an actual FileUpload schema json.
       match json {
-        JsonColumnMetadata::SchemaName(name) if name == "std.FileUpload" => {
+        JsonColumnMetadata::SchemaName(name, constraints) if name == "std.FileUpload" => {
           let file_upload: FileUploadInput = serde_json::from_value(value)?;
 
           let (_col_name, metadata, content) = file_upload.consume()?;
+          constraints
+            .check_file(&metadata, content.len() as u64)
+            .map_err(ParamsError::FileConstraint)?;
           let param = Value::Text(serde_json::to_string(&metadata)?);
 
           return Ok((param, Some(vec![(metadata, content)])));
@@ -339,14 +466,20 @@ fn extract_params_and_files_from_json(
         ColumnDataType::Text => {
           if let Some(ref json) = json_meta {
             match json {
-              JsonColumnMetadata::SchemaName(name) if name == "std.FileUploads" => {
+              JsonColumnMetadata::SchemaName(name, constraints) if name == "std.FileUploads" => {
                 let file_upload_vec: Vec<FileUploadInput> = serde_json::from_value(value)?;
+                constraints
+                  .check_count(file_upload_vec.len())
+                  .map_err(ParamsError::FileConstraint)?;
 
                 // TODO: Optimize the copying here. Not very critical.
                 let mut temp: Vec<FileUpload> = vec![];
                 let mut uploads: FileMetadataContents = vec![];
                 for file in file_upload_vec {
                   let (_col_name, metadata, content) = file.consume()?;
+                  constraints
+                    .check_file(&metadata, content.len() as u64)
+                    .map_err(ParamsError::FileConstraint)?;
                   temp.push(metadata.clone());
                   uploads.push((metadata, content));
                 }
@@ -504,6 +637,44 @@ pub(crate) fn json_string_to_value(
   });
 }
 
+/// Maps failures to convert or validate a single field's value to field errors. Returns `None` for
+/// errors that aren't specific to the field's value, e.g. storage errors.
+fn to_field_errors(field: &str, err: &ParamsError) -> Option<Vec<FieldError>> {
+  let code = match err {
+    ParamsError::JsonValidation(JsonSchemaError::Validation(violations)) => {
+      return Some(
+        violations
+          .iter()
+          .map(|violation| FieldError {
+            // Paths are JSON pointers, i.e. start with a '/' unless empty.
+            field: format!("{field}{}", violation.path),
+            code: "schema".to_string(),
+            message: violation.message.clone(),
+          })
+          .collect(),
+      );
+    }
+    ParamsError::NotANumber
+    | ParamsError::UnexpectedType(..)
+    | ParamsError::NestedObject(_)
+    | ParamsError::NestedArray(_)
+    | ParamsError::InhomogenousArray(_) => "type",
+    ParamsError::Decode(_)
+    | ParamsError::ParseInt(_)
+    | ParamsError::ParseFloat(_)
+    | ParamsError::Geometry(_) => "format",
+    _ => {
+      return None;
+    }
+  };
+
+  return Some(vec![FieldError {
+    field: field.to_string(),
+    code: code.to_string(),
+    message: err.to_string(),
+  }]);
+}
+
 #[inline]
 pub(crate) fn prefix_colon(s: &str) -> String {
   let mut new = String::with_capacity(s.len() + 1);
@@ -572,6 +743,72 @@ mod tests {
     assert_eq!(rows[0].get::<String>(1).unwrap(), "mytext");
   }
 
+  #[test]
+  fn test_file_constraints() {
+    trailbase_schema::registry::try_init_schemas();
+
+    let sql = r#"
+      CREATE TABLE files (
+        id      INTEGER PRIMARY KEY,
+        avatar  TEXT CHECK(jsonschema('std.FileUpload', avatar, 'mime=image/*', 'max_size=1KB')),
+        docs    TEXT CHECK(jsonschema('std.FileUploads', docs, 'max_count=1'))
+      ) STRICT"#;
+    let table: Table = sqlite3_parse_into_statement(sql)
+      .unwrap()
+      .unwrap()
+      .try_into()
+      .unwrap();
+    let metadata = TableMetadata::new(table.clone(), &[table], USER_TABLE);
+
+    let png = |len: usize| {
+      let mut data = vec![0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+      data.resize(len, 0);
+      return FileUploadInput {
+        name: Some("avatar".to_string()),
+        filename: Some("avatar.png".to_string()),
+        content_type: None,
+        data,
+      };
+    };
+    let text = |name: &str| FileUploadInput {
+      name: Some(name.to_string()),
+      filename: Some("notes.txt".to_string()),
+      content_type: None,
+      data: b"plain text".to_vec(),
+    };
+
+    let from = |files: Vec<FileUploadInput>| Params::from(&metadata, JsonRow::new(), Some(files));
+
+    assert!(from(vec![png(512)]).is_ok());
+    assert!(matches!(
+      from(vec![png(2048)]),
+      Err(ParamsError::FileConstraint(_))
+    ));
+    assert!(matches!(
+      from(vec![text("avatar")]),
+      Err(ParamsError::FileConstraint(_))
+    ));
+
+    assert!(from(vec![text("docs")]).is_ok());
+    assert!(matches!(
+      from(vec![text("docs"), text("docs")]),
+      Err(ParamsError::FileConstraint(_))
+    ));
+
+    // Files passed as JSON are subject to the same constraints.
+    let json = json_row_from_value(json!({
+      "avatar": {
+        "filename": "big.png",
+        "data": png(2048).data,
+      },
+    }))
+    .unwrap();
+    assert!(matches!(
+      Params::from(&metadata, json, None),
+      Err(ParamsError::FileConstraint(_))
+    ));
+  }
+
   #[tokio::test]
   async fn test_params() {
     #[allow(unused)]
@@ -764,4 +1001,37 @@ mod tests {
       assert_params(params);
     }
   }
+
+  #[test]
+  fn test_params_collect_field_errors() {
+    let table: Table = sqlite3_parse_into_statement(
+      "CREATE TABLE test (id INTEGER PRIMARY KEY, num INTEGER, data BLOB, text TEXT) STRICT",
+    )
+    .unwrap()
+    .unwrap()
+    .try_into()
+    .unwrap();
+    let metadata = TableMetadata::new(table.clone(), &[table], USER_TABLE);
+
+    let value = json!({
+      "num": "not a number",
+      "data": "!invalid base64!",
+      "text": {"nested": true},
+    });
+
+    let Err(ParamsError::FieldValidation(mut errors)) =
+      Params::from(&metadata, json_row_from_value(value).unwrap(), None)
+    else {
+      panic!("expected field validation error");
+    };
+    errors.sort_by(|a, b| a.field.cmp(&b.field));
+
+    assert_eq!(
+      errors
+        .iter()
+        .map(|e| (e.field.as_str(), e.code.as_str()))
+        .collect::<Vec<_>>(),
+      vec![("data", "format"), ("num", "format"), ("text", "type")]
+    );
+  }
 }
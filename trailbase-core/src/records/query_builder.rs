@@ -1,16 +1,21 @@
 use askama::Template;
 use itertools::Itertools;
 use log::*;
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::sync::Arc;
 use trailbase_schema::sqlite::{Column, ColumnOption};
 use trailbase_schema::{FileUpload, FileUploads};
-use trailbase_sqlite::{NamedParams, Params as _, Value};
+use trailbase_sqlite::{NamedParams, Params as _, Value, named_params};
 
 use crate::AppState;
 use crate::config::proto::ConflictResolutionStrategy;
+use crate::listing::{Expansion, Order, limit_or_default};
 use crate::records::error::RecordError;
+use crate::records::etag::IfMatch;
 use crate::records::files::{FileManager, delete_pending_files};
 use crate::records::params::{FileMetadataContents, Params};
+use crate::records::sql_to_json::{JsonError, row_to_json, row_to_json_expand};
 use crate::schema_metadata::{JsonColumnMetadata, SchemaMetadataCache, TableMetadata};
 
 #[derive(Debug, thiserror::Error)]
@@ -29,6 +34,8 @@ pub enum QueryError {
   File(#[from] crate::records::files::FileError),
   #[error("Not found")]
   NotFound,
+  #[error("Precondition failed")]
+  PreconditionFailed,
   #[error("Internal: {0}")]
   Internal(Box<dyn std::error::Error + Send + Sync>),
 }
@@ -40,64 +47,398 @@ pub(crate) struct ExpandedTable {
 
   pub foreign_table_name: String,
   pub foreign_column_name: String,
+
+  /// Index of the expanded table this one is nested in or None if expanded from the root.
+  pub parent: Option<usize>,
+  /// Columns of the foreign record to be returned. Defaults to all columns.
+  pub select: Option<Vec<String>>,
 }
 
-pub(crate) fn expand_tables<'a, 'b, T: AsRef<str>>(
+/// Resolves (nested) expansions into a flat list of tables to be joined, where nested tables
+/// follow their parent.
+///
+/// Only the root-level expansions are validated by the caller, e.g. against the API's config.
+/// Nested expansions are limited to `max_depth` levels and must not revisit a table, i.e. cycles
+/// are rejected.
+pub(crate) fn expand_tables<'a>(
   schema_metadata: &SchemaMetadataCache,
-  root_column_by_name: impl Fn(&'a str) -> Option<&'b Column>,
-  expand: &'a [T],
+  root_table_name: &str,
+  root_column_by_name: impl Fn(&str) -> Option<&'a Column>,
+  expand: &[Expansion],
+  max_depth: usize,
 ) -> Result<Vec<ExpandedTable>, RecordError> {
   let mut expanded_tables = Vec::<ExpandedTable>::with_capacity(expand.len());
+  let mut path = vec![root_table_name.to_string()];
 
-  for col_name in expand {
-    let col_name = col_name.as_ref();
-    if col_name.is_empty() {
-      continue;
-    }
-    let Some(column) = root_column_by_name(col_name) else {
+  for expansion in expand {
+    let Some(column) = root_column_by_name(&expansion.column) else {
       return Err(RecordError::Internal("Missing column".into()));
     };
 
-    // FIXME: This only expand FKs expressed as column constraints missing table constraints.
-    let Some(ColumnOption::ForeignKey {
-      foreign_table: foreign_table_name,
-      referred_columns: _,
-      ..
-    }) = column
-      .options
-      .iter()
-      .find_or_first(|o| matches!(o, ColumnOption::ForeignKey { .. }))
-    else {
-      return Err(RecordError::Internal("not a foreign key".into()));
-    };
+    expand_table_recursively(
+      schema_metadata,
+      column,
+      expansion,
+      None,
+      &mut path,
+      max_depth,
+      &mut expanded_tables,
+    )?;
+  }
 
-    let Some(foreign_table) = schema_metadata.get_table(foreign_table_name) else {
-      return Err(RecordError::ApiRequiresTable);
-    };
+  return Ok(expanded_tables);
+}
+
+fn expand_table_recursively(
+  schema_metadata: &SchemaMetadataCache,
+  column: &Column,
+  expansion: &Expansion,
+  parent: Option<usize>,
+  path: &mut Vec<String>,
+  max_depth: usize,
+  expanded_tables: &mut Vec<ExpandedTable>,
+) -> Result<(), RecordError> {
+  // NOTE: The path contains the root table, thus `path.len()` is the depth of this expansion.
+  if path.len() > max_depth {
+    return Err(RecordError::BadRequest("Expansion exceeds max depth"));
+  }
+
+  // FIXME: This only expand FKs expressed as column constraints missing table constraints.
+  let Some(ColumnOption::ForeignKey {
+    foreign_table: foreign_table_name,
+    referred_columns,
+    ..
+  }) = column
+    .options
+    .iter()
+    .find_or_first(|o| matches!(o, ColumnOption::ForeignKey { .. }))
+  else {
+    return Err(RecordError::BadRequest("Invalid expansion"));
+  };
+
+  if path.contains(foreign_table_name) {
+    return Err(RecordError::BadRequest("Cyclic expansion"));
+  }
+
+  let Some(foreign_table) = schema_metadata.get_table(foreign_table_name) else {
+    return Err(RecordError::ApiRequiresTable);
+  };
+
+  let Some(foreign_pk_column_idx) = foreign_table.record_pk_column else {
+    return Err(RecordError::Internal("invalid PK".into()));
+  };
+
+  let foreign_pk_column = &foreign_table.schema.columns[foreign_pk_column_idx].name;
+
+  // Root-level expansions are already validated as part of config validation. Nested ones are
+  // subject to the same constraints.
+  if parent.is_some() {
+    if column.name.starts_with('_') || foreign_table_name.starts_with('_') {
+      return Err(RecordError::BadRequest("Invalid expansion"));
+    }
+    match referred_columns.as_slice() {
+      [] => {}
+      [referred_column] if referred_column == foreign_pk_column => {}
+      _ => {
+        return Err(RecordError::BadRequest("Invalid expansion"));
+      }
+    }
+  }
+
+  if expansion.limit.is_some() || expansion.order.is_some() {
+    return Err(RecordError::BadRequest(
+      "Limit and order only apply to reverse expansions",
+    ));
+  }
+
+  if let Some(ref select) = expansion.select {
+    for col_name in select {
+      if col_name.starts_with('_') || foreign_table.column_by_name(col_name).is_none() {
+        return Err(RecordError::BadRequest("Invalid expansion"));
+      }
+    }
+  }
 
-    let Some(foreign_pk_column_idx) = foreign_table.record_pk_column else {
-      return Err(RecordError::Internal("invalid PK".into()));
+  let index = expanded_tables.len();
+  expanded_tables.push(ExpandedTable {
+    metadata: foreign_table.clone(),
+    local_column_name: column.name.clone(),
+    num_columns: foreign_table.schema.columns.len(),
+    foreign_table_name: foreign_table_name.to_string(),
+    foreign_column_name: foreign_pk_column.to_string(),
+    parent,
+    select: expansion.select.clone(),
+  });
+
+  path.push(foreign_table_name.to_string());
+  for nested in &expansion.nested {
+    let Some((_index, nested_column)) = foreign_table.column_by_name(&nested.column) else {
+      return Err(RecordError::BadRequest("Invalid expansion"));
     };
 
-    let foreign_pk_column = &foreign_table.schema.columns[foreign_pk_column_idx].name;
+    expand_table_recursively(
+      schema_metadata,
+      nested_column,
+      nested,
+      Some(index),
+      path,
+      max_depth,
+      expanded_tables,
+    )?;
+  }
+  path.pop();
+
+  return Ok(());
+}
+
+/// Expansion of the records referencing a record, i.e. the reverse of a foreign key relation. For
+/// example, "comments_via_post" expands the comments whose "post" column references a post.
+pub(crate) struct ReverseExpansion {
+  /// Name of the expansion, i.e. "<table>_via_<column>".
+  pub name: String,
+  pub metadata: Arc<TableMetadata>,
+  /// Foreign key column of the referencing table.
+  pub foreign_column_name: String,
 
-    // TODO: Check that `referred_columns` and foreign_pk_column are the same. It's already
-    // validated as part of config validation.
+  pub select: Option<Vec<String>>,
+  pub order: Vec<(String, Order)>,
+  pub limit: usize,
+}
 
-    let num_columns = foreign_table.schema.columns.len();
-    let foreign_table_name = foreign_table_name.to_string();
-    let foreign_column_name = foreign_pk_column.to_string();
+/// Resolves the name of a reverse expansion, i.e. "<table>_via_<column>", where `column` is a
+/// foreign key referencing the primary key of `table_name`. Returns the referencing table and
+/// column.
+pub(crate) fn resolve_reverse_expansion(
+  schema_metadata: &SchemaMetadataCache,
+  table_name: &str,
+  name: &str,
+) -> Option<(Arc<TableMetadata>, String)> {
+  let table = schema_metadata.get_table(table_name)?;
+  let pk_column = &table.schema.columns[table.record_pk_column?].name;
+
+  // Table names may contain "_via_" themselves, thus try all candidates.
+  return name.match_indices("_via_").find_map(|(index, sep)| {
+    let (foreign_table_name, column_name) = (&name[..index], &name[index + sep.len()..]);
+    if foreign_table_name.starts_with('_') || column_name.starts_with('_') {
+      return None;
+    }
 
-    expanded_tables.push(ExpandedTable {
-      metadata: foreign_table,
-      local_column_name: col_name.to_string(),
-      num_columns,
-      foreign_table_name,
-      foreign_column_name,
+    let foreign_table = schema_metadata.get_table(foreign_table_name)?;
+    let (_index, column) = foreign_table.column_by_name(column_name)?;
+    let references_pk = column.options.iter().any(|option| match option {
+      ColumnOption::ForeignKey {
+        foreign_table,
+        referred_columns,
+        ..
+      } => {
+        foreign_table == table_name
+          && match referred_columns.as_slice() {
+            [] => true,
+            [referred_column] => referred_column == pk_column,
+            _ => false,
+          }
+      }
+      _ => false,
     });
+    if !references_pk {
+      return None;
+    }
+
+    return Some((foreign_table.clone(), column_name.to_string()));
+  });
+}
+
+/// Resolves and validates reverse expansions of `table_name`'s records. Unlike foreign key
+/// expansions, they cannot be nested.
+pub(crate) fn reverse_expand_tables(
+  schema_metadata: &SchemaMetadataCache,
+  table_name: &str,
+  expand: &[Expansion],
+) -> Result<Vec<ReverseExpansion>, RecordError> {
+  return expand
+    .iter()
+    .map(|expansion| {
+      if !expansion.nested.is_empty() {
+        return Err(RecordError::BadRequest(
+          "Reverse expansions cannot be nested",
+        ));
+      }
+
+      let Some((metadata, foreign_column_name)) =
+        resolve_reverse_expansion(schema_metadata, table_name, &expansion.column)
+      else {
+        return Err(RecordError::BadRequest("Invalid expansion"));
+      };
+
+      let valid_column =
+        |col_name: &str| !col_name.starts_with('_') && metadata.column_by_name(col_name).is_some();
+      if let Some(ref select) = expansion.select {
+        if !select.iter().all(|col_name| valid_column(col_name)) {
+          return Err(RecordError::BadRequest("Invalid expansion"));
+        }
+      }
+
+      let order = expansion.order.clone().unwrap_or_default();
+      if !order.iter().all(|(col_name, _)| valid_column(col_name)) {
+        return Err(RecordError::BadRequest("Invalid expansion"));
+      }
+
+      return Ok(ReverseExpansion {
+        name: expansion.column.clone(),
+        foreign_column_name,
+        select: expansion.select.clone(),
+        order,
+        limit: limit_or_default(expansion.limit)?,
+        metadata,
+      });
+    })
+    .collect();
+}
+
+impl ReverseExpansion {
+  /// Fetches up to `limit` referencing records for each of the given record ids and returns them
+  /// as JSON arrays in the same order.
+  pub(crate) async fn run(
+    &self,
+    conn: &trailbase_sqlite::Connection,
+    record_ids: &[Value],
+    column_filter: fn(&str) -> bool,
+  ) -> Result<Vec<serde_json::Value>, RecordError> {
+    let mut results: Vec<Vec<serde_json::Value>> = vec![vec![]; record_ids.len()];
+    if record_ids.is_empty() {
+      return Ok(vec![]);
+    }
+
+    let Some(foreign_column_index) = self
+      .metadata
+      .column_index_by_name(&self.foreign_column_name)
+    else {
+      return Err(RecordError::Internal("missing column".into()));
+    };
+
+    let columns = &self.metadata.schema.columns;
+    let column_names = columns.iter().map(|c| format!(r#""{}""#, c.name)).join(",");
+    let order_clause = self
+      .order
+      .iter()
+      .map(|(col_name, order)| {
+        return format!(
+          r#""{col_name}" {}"#,
+          match order {
+            Order::Ascending => "ASC",
+            Order::Descending => "DESC",
+          }
+        );
+      })
+      .chain(
+        self
+          .metadata
+          .record_pk_column
+          .map(|index| format!(r#""{}" ASC"#, columns[index].name)),
+      )
+      .join(",");
+    let placeholders = (0..record_ids.len())
+      .map(|index| format!(":__record_id{index}"))
+      .join(",");
+
+    // Number the referencing records per referenced record to apply the limit to each.
+    let sql = format!(
+      r#"SELECT {column_names} FROM (SELECT {column_names}, ROW_NUMBER() OVER (PARTITION BY "{fk}" ORDER BY {order_clause}) AS _rn_ FROM "{table_name}" WHERE "{fk}" IN ({placeholders})) WHERE _rn_ <= :__limit ORDER BY "{fk}", _rn_"#,
+      fk = self.foreign_column_name,
+      table_name = self.metadata.name(),
+    );
+
+    let mut params: Vec<(Cow<'static, str>, Value)> = record_ids
+      .iter()
+      .enumerate()
+      .map(|(index, id)| (Cow::Owned(format!(":__record_id{index}")), id.clone()))
+      .collect();
+    params.push((Cow::Borrowed(":__limit"), Value::Integer(self.limit as i64)));
+
+    let rows = conn.read_query_rows(sql, params).await?;
+    for row in rows.iter() {
+      let Some(index) = record_ids
+        .iter()
+        .position(|id| Some(id) == row.get_value(foreign_column_index))
+      else {
+        continue;
+      };
+
+      let mut value = row_to_json(
+        columns,
+        &self.metadata.json_metadata.columns,
+        row,
+        column_filter,
+      )
+      .map_err(|err| RecordError::Internal(err.into()))?;
+      if let (Some(select), Some(record)) = (&self.select, value.as_object_mut()) {
+        record.retain(|col_name, _| select.contains(col_name));
+      }
+
+      results[index].push(value);
+    }
+
+    return Ok(results.into_iter().map(serde_json::Value::Array).collect());
   }
+}
 
-  return Ok(expanded_tables);
+/// Splits the expanded tables' columns off a joined row leaving only the root record's columns,
+/// and returns them as one row per expanded table.
+pub(crate) fn split_expanded_row(
+  row: &mut trailbase_sqlite::Row,
+  num_columns: usize,
+  expanded_tables: &[ExpandedTable],
+) -> Vec<trailbase_sqlite::Row> {
+  let mut foreign_rows = Vec::with_capacity(expanded_tables.len());
+
+  let mut curr = row.split_off(num_columns);
+  for expanded_table in expanded_tables {
+    let next = curr.split_off(expanded_table.num_columns);
+    foreign_rows.push(curr);
+    curr = next;
+  }
+
+  return foreign_rows;
+}
+
+/// Serializes the rows of expanded tables, inlining nested expansions into their parents. Returns
+/// the root-level expansions keyed by the root record's foreign key column.
+pub(crate) fn expanded_rows_to_json(
+  expanded_tables: &[ExpandedTable],
+  foreign_rows: &[trailbase_sqlite::Row],
+  column_filter: fn(&str) -> bool,
+) -> Result<Vec<(String, serde_json::Value)>, JsonError> {
+  assert_eq!(expanded_tables.len(), foreign_rows.len());
+
+  let mut nested: Vec<HashMap<String, serde_json::Value>> =
+    vec![HashMap::new(); expanded_tables.len()];
+  let mut result = Vec::<(String, serde_json::Value)>::new();
+
+  // Nested tables follow their parents, thus iterating in reverse serializes children first.
+  for (index, expanded_table) in expanded_tables.iter().enumerate().rev() {
+    let expand = std::mem::take(&mut nested[index]);
+    let mut value = row_to_json_expand(
+      &expanded_table.metadata.schema.columns,
+      &expanded_table.metadata.json_metadata.columns,
+      &foreign_rows[index],
+      column_filter,
+      (!expand.is_empty()).then_some(&expand),
+    )?;
+
+    if let (Some(select), Some(record)) = (&expanded_table.select, value.as_object_mut()) {
+      record.retain(|col_name, _| select.contains(col_name) || expand.contains_key(col_name));
+    }
+
+    let local_column_name = expanded_table.local_column_name.clone();
+    match expanded_table.parent {
+      Some(parent) => {
+        nested[parent].insert(local_column_name, value);
+      }
+      None => result.push((local_column_name, value)),
+    };
+  }
+
+  return Ok(result);
 }
 
 #[derive(Template)]
@@ -105,7 +446,7 @@ pub(crate) fn expand_tables<'a, 'b, T: AsRef<str>>(
 struct ReadRecordExpandedQueryTemplate<'a> {
   table_name: &'a str,
   column_names: &'a [&'a str],
-  pk_column_name: &'a str,
+  record_id_clause: &'a str,
   expanded_tables: &'a [ExpandedTable],
 }
 
@@ -114,32 +455,42 @@ struct ReadRecordExpandedQueryTemplate<'a> {
 struct ReadRecordQueryTemplate<'a> {
   table_name: &'a str,
   column_names: &'a [&'a str],
-  pk_column_name: &'a str,
+  record_id_clause: &'a str,
 }
 
 pub(crate) struct SelectQueryBuilder;
 
 pub(crate) struct ExpandedSelectQueryResult {
   pub(crate) root: trailbase_sqlite::Row,
-  pub(crate) foreign_rows: Vec<(Arc<TableMetadata>, trailbase_sqlite::Row)>,
+  /// One row per expanded table, see [`expanded_rows_to_json`].
+  pub(crate) foreign_rows: Vec<trailbase_sqlite::Row>,
 }
 
 impl SelectQueryBuilder {
-  pub(crate) async fn run(
-    conn: &trailbase_sqlite::Connection,
+  /// Reads the record matching `record_id_clause`, e.g. `MAIN."id" = ?1`, see
+  /// [`crate::records::RecordApi::record_id_clause`].
+  pub(crate) fn sql(
     table_name: &str,
     column_names: &[&str],
-    pk_column: &str,
-    pk_value: Value,
-  ) -> Result<Option<trailbase_sqlite::Row>, RecordError> {
-    let sql = ReadRecordQueryTemplate {
+    record_id_clause: &str,
+  ) -> Result<String, RecordError> {
+    return ReadRecordQueryTemplate {
       table_name,
       column_names,
-      pk_column_name: pk_column,
+      record_id_clause,
     }
     .render()
-    .map_err(|err| RecordError::Internal(err.into()))?;
+    .map_err(|err| RecordError::Internal(err.into()));
+  }
 
+  pub(crate) async fn run(
+    conn: &trailbase_sqlite::Connection,
+    table_name: &str,
+    column_names: &[&str],
+    record_id_clause: &str,
+    pk_value: Value,
+  ) -> Result<Option<trailbase_sqlite::Row>, RecordError> {
+    let sql = Self::sql(table_name, column_names, record_id_clause)?;
     return Ok(conn.read_query_row(sql, [pk_value]).await?);
   }
 
@@ -147,14 +498,14 @@ impl SelectQueryBuilder {
     conn: &trailbase_sqlite::Connection,
     table_name: &str,
     column_names: &[&str],
-    pk_column: &str,
+    record_id_clause: &str,
     pk_value: Value,
     expanded_tables: &[ExpandedTable],
   ) -> Result<Option<ExpandedSelectQueryResult>, RecordError> {
     let sql = ReadRecordExpandedQueryTemplate {
       table_name,
       column_names,
-      pk_column_name: pk_column,
+      record_id_clause,
       expanded_tables,
     }
     .render()
@@ -164,15 +515,7 @@ impl SelectQueryBuilder {
       return Ok(None);
     };
 
-    let mut foreign_rows: Vec<(Arc<TableMetadata>, trailbase_sqlite::Row)> =
-      Vec::with_capacity(expanded_tables.len());
-
-    let mut curr = row.split_off(column_names.len());
-    for expanded_table in expanded_tables {
-      let next = curr.split_off(expanded_table.num_columns);
-      foreign_rows.push((expanded_table.metadata.clone(), curr));
-      curr = next;
-    }
+    let foreign_rows = split_expanded_row(&mut row, column_names.len(), expanded_tables);
 
     return Ok(Some(ExpandedSelectQueryResult {
       root: row,
@@ -189,17 +532,17 @@ impl GetFileQueryBuilder {
     table_name: &str,
     file_column: &Column,
     json_metadata: &JsonColumnMetadata,
-    pk_column: &str,
+    record_id_clause: &str,
     pk_value: Value,
   ) -> Result<FileUpload, QueryError> {
     return match &json_metadata {
-      JsonColumnMetadata::SchemaName(name) if name == "std.FileUpload" => {
+      JsonColumnMetadata::SchemaName(name, _) if name == "std.FileUpload" => {
         let column_name = &file_column.name;
 
         let Some(row) = state
           .conn()
           .read_query_row(
-            format!(r#"SELECT "{column_name}" FROM "{table_name}" WHERE "{pk_column}" = $1"#),
+            format!(r#"SELECT "{column_name}" FROM "{table_name}" WHERE {record_id_clause}"#),
             [pk_value],
           )
           .await?
@@ -224,17 +567,17 @@ impl GetFilesQueryBuilder {
     table_name: &str,
     file_column: &Column,
     json_metadata: &JsonColumnMetadata,
-    pk_column: &str,
+    record_id_clause: &str,
     pk_value: Value,
   ) -> Result<FileUploads, QueryError> {
     return match &json_metadata {
-      JsonColumnMetadata::SchemaName(name) if name == "std.FileUploads" => {
+      JsonColumnMetadata::SchemaName(name, _) if name == "std.FileUploads" => {
         let column_name = &file_column.name;
 
         let Some(row) = state
           .conn()
           .read_query_row(
-            format!(r#"SELECT "{column_name}" FROM "{table_name}" WHERE "{pk_column}" = $1"#),
+            format!(r#"SELECT "{column_name}" FROM "{table_name}" WHERE {record_id_clause}"#),
             [pk_value],
           )
           .await?
@@ -257,9 +600,20 @@ struct CreateRecordQueryTemplate<'a> {
   table_name: &'a str,
   conflict_clause: &'a str,
   column_names: &'a [String],
+  upsert_target: &'a [String],
+  upsert_update: bool,
   returning: &'a [&'a str],
 }
 
+/// UPSERT clause, i.e. `ON CONFLICT (target) DO UPDATE/NOTHING`.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Upsert {
+  /// Columns of a uniqueness constraint.
+  pub target: Vec<String>,
+  /// Update the conflicting row with the inserted values rather than skipping the insert.
+  pub update: bool,
+}
+
 pub(crate) struct InsertQueryBuilder;
 
 impl InsertQueryBuilder {
@@ -275,6 +629,7 @@ impl InsertQueryBuilder {
       table_name,
       params,
       conflict_resolution,
+      None,
       Some(return_column_name),
     )?;
 
@@ -304,14 +659,20 @@ impl InsertQueryBuilder {
     return Ok(return_value);
   }
 
+  /// Inserts all records within a single transaction. Records skipped due to an `upsert` with
+  /// `DO NOTHING` are omitted from the result.
+  ///
+  /// Returned values are paired with whether the record was inserted, i.e. false for existing
+  /// records updated by an `upsert`.
   pub(crate) async fn run_bulk(
     state: &AppState,
     table_name: &str,
     conflict_resolution: Option<ConflictResolutionStrategy>,
+    upsert: Option<&Upsert>,
     return_column_name: &str,
     has_file_columns: bool,
     params_list: Vec<Params>,
-  ) -> Result<Vec<rusqlite::types::Value>, QueryError> {
+  ) -> Result<Vec<(rusqlite::types::Value, bool)>, QueryError> {
     let mut all_files: FileMetadataContents = vec![];
     let mut query_and_params: Vec<(String, NamedParams)> = vec![];
 
@@ -320,6 +681,7 @@ impl InsertQueryBuilder {
         table_name,
         params,
         conflict_resolution,
+        upsert,
         Some(return_column_name),
       )?;
 
@@ -335,20 +697,29 @@ impl InsertQueryBuilder {
       FileManager::write(state, all_files).await?
     };
 
-    let result: Vec<(i64, rusqlite::types::Value)> = state
+    let skip_conflicts = upsert.is_some_and(|u| !u.update);
+    let result: Vec<(i64, rusqlite::types::Value, bool)> = state
       .conn()
       .call(move |conn| {
-        let mut rows = Vec::<(i64, rusqlite::types::Value)>::with_capacity(query_and_params.len());
+        let mut rows =
+          Vec::<(i64, rusqlite::types::Value, bool)>::with_capacity(query_and_params.len());
 
         let tx = conn.transaction()?;
 
         for (query, named_params) in query_and_params {
+          // UPSERTs updating an existing row, unlike inserts, leave the last insert rowid as is.
+          let last_insert_rowid = tx.last_insert_rowid();
+
           let mut stmt = tx.prepare_cached(&query)?;
           named_params.bind(&mut stmt)?;
           let mut result = stmt.raw_query();
 
           match result.next()? {
-            Some(row) => rows.push((row.get(0)?, row.get(1)?)),
+            Some(row) => {
+              let inserted = tx.last_insert_rowid() != last_insert_rowid;
+              rows.push((row.get(0)?, row.get(1)?, inserted));
+            }
+            None if skip_conflicts => {}
             _ => {
               return Err(rusqlite::Error::QueryReturnedNoRows.into());
             }
@@ -364,21 +735,33 @@ impl InsertQueryBuilder {
     // Successful write, do not cleanup written files.
     file_manager.release();
 
-    if Some(ConflictResolutionStrategy::Replace) == conflict_resolution && has_file_columns {
-      for (rowid, _) in &result {
+    let overwrites = Some(ConflictResolutionStrategy::Replace) == conflict_resolution
+      || upsert.is_some_and(|u| u.update);
+    if overwrites && has_file_columns {
+      for (rowid, _, _) in &result {
         delete_pending_files(state, table_name, *rowid).await?;
       }
     }
 
-    return Ok(result.into_iter().map(|(_rowid, v)| v).collect());
+    return Ok(
+      result
+        .into_iter()
+        .map(|(_rowid, v, inserted)| (v, inserted))
+        .collect(),
+    );
   }
 
-  fn build_insert_query(
+  pub(crate) fn build_insert_query(
     table_name: &str,
     params: Params,
     conflict_resolution: Option<ConflictResolutionStrategy>,
+    upsert: Option<&Upsert>,
     return_column_name: Option<&str>,
   ) -> Result<(String, NamedParams, FileMetadataContents), QueryError> {
+    if upsert.is_some() && params.column_names.is_empty() {
+      return Err(QueryError::Precondition("Upsert requires values"));
+    }
+
     let conflict_clause = match conflict_resolution {
       Some(ConflictResolutionStrategy::Abort) => "OR ABORT",
       Some(ConflictResolutionStrategy::Rollback) => "OR ROLLBACK",
@@ -398,6 +781,8 @@ impl InsertQueryBuilder {
       table_name,
       conflict_clause,
       column_names: &params.column_names,
+      upsert_target: upsert.map_or(&[], |u| u.target.as_slice()),
+      upsert_update: upsert.is_some_and(|u| u.update),
       returning,
     }
     .render()
@@ -424,6 +809,7 @@ impl UpdateQueryBuilder {
     table_name: &str,
     pk_column: &str,
     has_file_columns: bool,
+    if_match: Option<IfMatch>,
     mut params: Params,
   ) -> Result<(), QueryError> {
     if params.column_names.len() < 2 {
@@ -441,19 +827,41 @@ impl UpdateQueryBuilder {
       FileManager::write(state, files).await?
     };
 
-    let query = UpdateRecordQueryTemplate {
-      table_name,
-      column_names: &params.column_names,
-      pk_column_name: pk_column,
-      returning: Some("_rowid_"),
-    }
-    .render()
-    .map_err(|err| QueryError::Internal(err.into()))?;
+    let query = Self::sql(table_name, pk_column, &params)?;
 
-    let rowid: Option<i64> = state
-      .conn()
-      .query_row_f(query, params.named_params, |row| row.get(0))
-      .await?;
+    let rowid: Option<i64> = match if_match {
+      Some(if_match) => {
+        let named_params = params.named_params;
+        state
+          .conn()
+          .call(move |conn| {
+            let tx = conn.transaction()?;
+            if !if_match.check(&tx)? {
+              return Ok(Err(QueryError::PreconditionFailed));
+            }
+
+            let rowid: Option<i64> = {
+              let mut stmt = tx.prepare_cached(&query)?;
+              named_params.bind(&mut stmt)?;
+              let mut rows = stmt.raw_query();
+              match rows.next()? {
+                Some(row) => Some(row.get(0)?),
+                None => None,
+              }
+            };
+            tx.commit()?;
+
+            return Ok(Ok(rowid));
+          })
+          .await??
+      }
+      None => {
+        state
+          .conn()
+          .query_row_f(query, params.named_params, |row| row.get(0))
+          .await?
+      }
+    };
 
     // Successful write, do not cleanup written files.
     file_manager.release();
@@ -466,29 +874,109 @@ impl UpdateQueryBuilder {
 
     return Ok(());
   }
+
+  /// Update of the record identified by the primary key in `params`, returning its rowid.
+  pub(crate) fn sql(
+    table_name: &str,
+    pk_column: &str,
+    params: &Params,
+  ) -> Result<String, QueryError> {
+    return UpdateRecordQueryTemplate {
+      table_name,
+      column_names: &params.column_names,
+      pk_column_name: pk_column,
+      returning: Some("_rowid_"),
+    }
+    .render()
+    .map_err(|err| QueryError::Internal(err.into()));
+  }
 }
 
 pub(crate) struct DeleteQueryBuilder;
 
 impl DeleteQueryBuilder {
+  /// Deletion of the record with primary key `:__record_id`, returning its rowid.
+  pub(crate) fn sql(table_name: &str, pk_column: &str) -> String {
+    return format!(
+      r#"DELETE FROM "{table_name}" WHERE "{pk_column}" = :__record_id RETURNING _rowid_"#
+    );
+  }
+
+  /// Soft-deletion of the record with primary key `:__record_id`, i.e. setting `soft_delete_column`
+  /// to the current UNIX timestamp, returning its rowid. Soft-deleted records are not matched.
+  pub(crate) fn soft_delete_sql(
+    table_name: &str,
+    pk_column: &str,
+    soft_delete_column: &str,
+  ) -> String {
+    return format!(
+      r#"UPDATE "{table_name}" SET "{soft_delete_column}" = unixepoch() WHERE "{pk_column}" = :__record_id AND "{soft_delete_column}" IS NULL RETURNING _rowid_"#
+    );
+  }
+
+  /// Restores the soft-deleted record with primary key `:__record_id`, returning its rowid.
+  pub(crate) fn undelete_sql(
+    table_name: &str,
+    pk_column: &str,
+    soft_delete_column: &str,
+  ) -> String {
+    return format!(
+      r#"UPDATE "{table_name}" SET "{soft_delete_column}" = NULL WHERE "{pk_column}" = :__record_id AND "{soft_delete_column}" IS NOT NULL RETURNING _rowid_"#
+    );
+  }
+
+  /// Deletes the record or, given a `soft_delete_column`, only marks it as deleted retaining its
+  /// files.
   pub(crate) async fn run(
     state: &AppState,
     table_name: &str,
     pk_column: &str,
     pk_value: Value,
     has_file_columns: bool,
+    soft_delete_column: Option<&str>,
+    if_match: Option<IfMatch>,
   ) -> Result<i64, QueryError> {
-    let rowid: i64 = state
-      .conn()
-      .query_row_f(
-        format!(r#"DELETE FROM "{table_name}" WHERE "{pk_column}" = $1 RETURNING _rowid_"#),
-        [pk_value],
-        |row| row.get(0),
-      )
-      .await?
-      .ok_or_else(|| QueryError::NotFound)?;
+    let query = match soft_delete_column {
+      Some(column) => Self::soft_delete_sql(table_name, pk_column, column),
+      None => Self::sql(table_name, pk_column),
+    };
+    let rowid: i64 = match if_match {
+      Some(if_match) => {
+        state
+          .conn()
+          .call(move |conn| {
+            let tx = conn.transaction()?;
+            if !if_match.check(&tx)? {
+              return Ok(Err(QueryError::PreconditionFailed));
+            }
 
-    if has_file_columns {
+            let rowid: Option<i64> = {
+              let mut stmt = tx.prepare_cached(&query)?;
+              named_params! {":__record_id": pk_value}.bind(&mut stmt)?;
+              let mut rows = stmt.raw_query();
+              match rows.next()? {
+                Some(row) => Some(row.get(0)?),
+                None => None,
+              }
+            };
+            tx.commit()?;
+
+            return Ok(Ok(rowid));
+          })
+          .await??
+      }
+      None => {
+        state
+          .conn()
+          .query_row_f(query, named_params! {":__record_id": pk_value}, |row| {
+            row.get(0)
+          })
+          .await?
+      }
+    }
+    .ok_or_else(|| QueryError::NotFound)?;
+
+    if has_file_columns && soft_delete_column.is_none() {
       delete_pending_files(state, table_name, rowid).await?;
     }
 
@@ -514,6 +1002,8 @@ mod tests {
         table_name: "table",
         conflict_clause: "OR ABORT",
         column_names: &["index".to_string(), "trigger".to_string()],
+        upsert_target: &[],
+        upsert_update: false,
         returning: &["index"],
       }
       .render()
@@ -527,6 +1017,8 @@ mod tests {
         table_name: "table",
         conflict_clause: "",
         column_names: &[],
+        upsert_target: &[],
+        upsert_update: false,
         returning: &["*"],
       }
       .render()
@@ -540,12 +1032,54 @@ mod tests {
         table_name: "table",
         conflict_clause: "",
         column_names: &["index".to_string()],
+        upsert_target: &[],
+        upsert_update: false,
+        returning: &[],
+      }
+      .render()
+      .unwrap();
+
+      sanitize_template(&query);
+    }
+
+    {
+      let query = CreateRecordQueryTemplate {
+        table_name: "table",
+        conflict_clause: "",
+        column_names: &["index".to_string(), "trigger".to_string()],
+        upsert_target: &["index".to_string()],
+        upsert_update: true,
+        returning: &["index"],
+      }
+      .render()
+      .unwrap();
+
+      sanitize_template(&query);
+      assert!(
+        query.ends_with(
+          r#"ON CONFLICT ("index") DO UPDATE SET "index" = excluded."index", "trigger" = excluded."trigger" RETURNING "index""#
+        ),
+        "{query}"
+      );
+    }
+
+    {
+      let query = CreateRecordQueryTemplate {
+        table_name: "table",
+        conflict_clause: "",
+        column_names: &["index".to_string()],
+        upsert_target: &["index".to_string()],
+        upsert_update: false,
         returning: &[],
       }
       .render()
       .unwrap();
 
       sanitize_template(&query);
+      assert!(
+        query.ends_with("ON CONFLICT (\"index\") DO NOTHING"),
+        "{query}"
+      );
     }
   }
 }
@@ -0,0 +1,273 @@
+use log::*;
+use serde::Serialize;
+use ts_rs::TS;
+
+use crate::app_state::AppState;
+use crate::records::list_records::default_list_query;
+use crate::records::query_builder::SelectQueryBuilder;
+use crate::records::{RecordApi, RecordError};
+
+/// Step of a Record API query's plan, which is likely to be slow on large tables, as reported by
+/// `EXPLAIN QUERY PLAN`.
+#[derive(Clone, Debug, PartialEq, Serialize, TS)]
+#[ts(export)]
+pub struct QueryPlanWarning {
+  pub api_name: String,
+  /// Query that was explained: "list", "read", "read_access" or "filter:<column>".
+  pub query: String,
+  /// Offending plan step, e.g. "SCAN room_members".
+  pub detail: String,
+}
+
+struct CanonicalQuery {
+  name: String,
+  sql: String,
+  /// Whether to prepare the statement ahead of time, i.e. it's run as is by the record APIs.
+  warm: bool,
+}
+
+fn canonical_queries(api: &RecordApi) -> Result<Vec<CanonicalQuery>, RecordError> {
+  let (_index, pk_column) = api.record_pk_column();
+  let column_names: Vec<_> = api.columns().iter().map(|c| c.name.as_str()).collect();
+
+  let mut queries = vec![
+    CanonicalQuery {
+      name: "list".to_string(),
+      sql: default_list_query(api, "TRUE")?,
+      warm: true,
+    },
+    CanonicalQuery {
+      name: "read".to_string(),
+      sql: SelectQueryBuilder::sql(
+        api.table_name(),
+        &column_names,
+        &api.record_id_clause(Some("MAIN"), "?1"),
+      )?,
+      warm: true,
+    },
+  ];
+
+  if let Some(read_access_query) = api.read_access_query() {
+    queries.push(CanonicalQuery {
+      name: "read_access".to_string(),
+      sql: read_access_query.to_string(),
+      warm: true,
+    });
+  }
+
+  // Equality filters on any column that can be filtered on, i.e. isn't hidden.
+  for column in api.columns() {
+    if column.name.starts_with("_") || column.name == pk_column.name {
+      continue;
+    }
+    queries.push(CanonicalQuery {
+      name: format!("filter:{}", column.name),
+      sql: default_list_query(api, &format!(r#"_ROW_."{}" = :__value"#, column.name))?,
+      warm: false,
+    });
+  }
+
+  return Ok(queries);
+}
+
+/// Plan steps considered slow for the given query, i.e. full table scans and sorts.
+fn slow_steps<'a>(query: &str, plan: &'a [String]) -> Vec<&'a String> {
+  if query.starts_with("filter:") {
+    // Ordering by primary key requires a scan unless the filter can use an index.
+    if plan.iter().any(|step| step.starts_with("SEARCH _ROW_")) {
+      return vec![];
+    }
+    return plan
+      .iter()
+      .filter(|step| step.starts_with("SCAN _ROW_"))
+      .collect();
+  }
+
+  return plan
+    .iter()
+    .filter(|step| {
+      // Plain listings walk the table in primary key order until the limit is reached and
+      // access queries materialize the single record and user.
+      if step.starts_with("SCAN CONSTANT ROW")
+        || step.starts_with("SCAN _USER_")
+        || step.starts_with("SCAN _CTX_")
+        || step.starts_with("SCAN _ROW_")
+      {
+        return false;
+      }
+      return step.starts_with("SCAN ") || step.starts_with("USE TEMP B-TREE");
+    })
+    .collect();
+}
+
+/// Runs `EXPLAIN QUERY PLAN` for the canonical list, read and access queries of all Record APIs
+/// and reports steps likely to result in full table scans.
+pub(crate) async fn explain_record_apis(
+  state: &AppState,
+) -> Result<Vec<QueryPlanWarning>, RecordError> {
+  let mut warnings: Vec<QueryPlanWarning> = vec![];
+  for (api_name, api) in state.record_apis().iter() {
+    for query in canonical_queries(api)? {
+      let sql = format!("EXPLAIN QUERY PLAN {}", query.sql);
+      let plan = state
+        .conn()
+        .call(move |conn| {
+          let mut stmt = conn.prepare(&sql)?;
+          let rows = stmt
+            .query_map([], |row| row.get::<_, String>(3))?
+            .collect::<Result<Vec<_>, _>>()?;
+          return Ok(rows);
+        })
+        .await?;
+
+      for step in slow_steps(&query.name, &plan) {
+        warnings.push(QueryPlanWarning {
+          api_name: api_name.clone(),
+          query: query.name.clone(),
+          detail: step.clone(),
+        });
+      }
+    }
+  }
+
+  return Ok(warnings);
+}
+
+/// Prepares the canonical queries of all Record APIs on all connections, so that first requests
+/// don't pay for query planning.
+async fn warm_statement_cache(state: &AppState) -> Result<(), RecordError> {
+  let mut sqls: Vec<String> = vec![];
+  for (_name, api) in state.record_apis().iter() {
+    sqls.extend(
+      canonical_queries(api)?
+        .into_iter()
+        .filter(|q| q.warm)
+        .map(|q| q.sql),
+    );
+  }
+
+  let prepare = move |conn: &rusqlite::Connection| -> Result<(), trailbase_sqlite::Error> {
+    for sql in &sqls {
+      conn.prepare_cached(sql)?;
+    }
+    return Ok(());
+  };
+
+  state.conn().call_readers(prepare.clone()).await?;
+  state
+    .conn()
+    .call(move |conn| {
+      return prepare(conn);
+    })
+    .await?;
+
+  return Ok(());
+}
+
+/// Warms the statement cache and logs query plan warnings in the background, e.g. on startup and
+/// after schema or config changes.
+pub(crate) fn spawn_query_plan_check(state: &AppState) {
+  let state = state.clone();
+  tokio::spawn(async move {
+    if let Err(err) = warm_statement_cache(&state).await {
+      debug!("Failed to warm statement cache: {err}");
+    }
+
+    let warnings = match explain_record_apis(&state).await {
+      Ok(warnings) => warnings,
+      Err(err) => {
+        debug!("Failed to explain record API queries: {err}");
+        return;
+      }
+    };
+
+    for warning in &warnings {
+      if let Some(column) = warning.query.strip_prefix("filter:") {
+        info!(
+          "Record API '{}': filtering by '{column}' scans the entire table, consider adding an \
+           index",
+          warning.api_name
+        );
+      } else {
+        warn!(
+          "Record API '{}': slow {} query plan: {}",
+          warning.api_name, warning.query, warning.detail
+        );
+      }
+    }
+  });
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::app_state::test_state;
+  use crate::config::proto::{PermissionFlag, RecordApiConfig};
+  use crate::records::test_utils::add_record_api_config;
+
+  #[tokio::test]
+  async fn test_explain_record_apis() {
+    let state = test_state(None).await.unwrap();
+    state
+      .conn()
+      .execute_batch(
+        r#"
+          CREATE TABLE member (room INTEGER, user BLOB) STRICT;
+          CREATE TABLE post (
+            id INTEGER PRIMARY KEY,
+            room INTEGER,
+            author TEXT,
+            body TEXT
+          ) STRICT;
+          CREATE INDEX post_room ON post (room);
+        "#,
+      )
+      .await
+      .unwrap();
+    state.refresh_table_cache().await.unwrap();
+
+    add_record_api_config(
+      &state,
+      RecordApiConfig {
+        name: Some("posts".to_string()),
+        table_name: Some("post".to_string()),
+        acl_world: [PermissionFlag::Read as i32].into(),
+        read_access_rule: Some(
+          "EXISTS(SELECT 1 FROM member WHERE room = _ROW_.room AND user = _USER_.id)".to_string(),
+        ),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    let warnings = explain_record_apis(&state).await.unwrap();
+    let queries: Vec<_> = warnings.iter().map(|w| w.query.as_str()).collect();
+
+    // The access rule's sub-query scans the unindexed members table.
+    assert!(
+      warnings
+        .iter()
+        .any(|w| w.query == "read_access" && w.detail.contains("member")),
+      "{warnings:?}"
+    );
+    // Only unindexed columns are flagged.
+    assert!(queries.contains(&"filter:author"), "{queries:?}");
+    assert!(queries.contains(&"filter:body"), "{queries:?}");
+    assert!(!queries.contains(&"filter:room"), "{queries:?}");
+    assert!(!queries.contains(&"read"), "{queries:?}");
+
+    warm_statement_cache(&state).await.unwrap();
+
+    state
+      .conn()
+      .execute_batch("CREATE INDEX member_room_user ON member (room, user);")
+      .await
+      .unwrap();
+    let warnings = explain_record_apis(&state).await.unwrap();
+    assert!(
+      !warnings.iter().any(|w| w.query == "read_access"),
+      "{warnings:?}"
+    );
+  }
+}
@@ -1,29 +1,50 @@
 use axum::{
   Json,
   extract::{Path, Query, State},
-  response::Response,
+  http::{HeaderValue, header::ETAG},
+  response::{IntoResponse, Response},
 };
+use rusqlite::types::ValueRef;
 use serde::Deserialize;
+use trailbase_sqlite::Value;
 
 use crate::app_state::AppState;
 use crate::auth::user::User;
+use crate::config::proto::ResponseFormat;
+use crate::listing::{parse_expand, parse_select};
+use crate::records::cache::{CacheKey, CacheOp, CachedResponse};
+use crate::records::etag::record_etag;
 use crate::records::files::read_file_into_response;
+use crate::records::image_transform::{FileQuery, read_transformed_image_into_response};
+use crate::records::json_api::{json_api_response, record_document};
 use crate::records::query_builder::{
   ExpandedSelectQueryResult, GetFileQueryBuilder, GetFilesQueryBuilder, SelectQueryBuilder,
-  expand_tables,
+  expand_tables, expanded_rows_to_json, reverse_expand_tables,
 };
-use crate::records::sql_to_json::{row_to_json, row_to_json_expand};
-use crate::records::{Permission, RecordError};
+use crate::records::signed_url;
+use crate::records::sql_to_json::row_to_json_expand;
+use crate::records::{Permission, RecordApi, RecordError};
 
 #[derive(Debug, Default, Deserialize)]
 pub struct ReadRecordQuery {
-  /// Comma separated list of foreign key column names that should be expanded.
+  /// Comma separated list of foreign key column names that should be expanded, e.g.
+  /// "author(name,avatar).team", see [`parse_expand`]. Records referencing this record can be
+  /// expanded as "<table>_via_<column>(limit=5,order=-created)".
   ///
   /// Requires the API's configuration to explicitly allow expanding said columns.
   pub expand: Option<String>,
+
+  /// Comma separated list of column names to return, e.g. "id,name". Defaults to all columns.
+  pub select: Option<String>,
+
+  /// Read the record even if it has been soft-deleted. Requires delete access.
+  pub include_deleted: Option<bool>,
 }
 
 /// Read record.
+///
+/// Responds with the record's ETag, which can be passed as `If-Match` to subsequent updates or
+/// deletions to detect concurrent modifications.
 #[utoipa::path(
   get,
   path = "/:name/:record",
@@ -36,42 +57,169 @@ pub async fn read_record_handler(
   Path((api_name, record)): Path<(String, String)>,
   Query(query): Query<ReadRecordQuery>,
   user: Option<User>,
-) -> Result<Json<serde_json::Value>, RecordError> {
+) -> Result<Response, RecordError> {
   let Some(api) = state.lookup_record_api(&api_name) else {
     return Err(RecordError::ApiNotFound);
   };
 
-  let record_id = api.id_to_sql(&record)?;
+  let select = match query.select {
+    Some(ref select) => {
+      let select =
+        parse_select(select).map_err(|_err| RecordError::BadRequest("Invalid select"))?;
+      for col_name in &select {
+        if !prefix_filter(col_name) || api.column_index_by_name(col_name).is_none() {
+          return Err(RecordError::BadRequest("Invalid select"));
+        }
+      }
+      Some(select)
+    }
+    None => None,
+  };
+
+  let (mut record, etag) = read_record_with_etag(
+    &state,
+    &api,
+    &record,
+    query.expand.as_deref(),
+    query.include_deleted.unwrap_or(false),
+    user.as_ref(),
+  )
+  .await?;
 
+  if let Some(select) = select {
+    if let Some(record) = record.as_object_mut() {
+      record.retain(|col_name, _| select.contains(col_name));
+    }
+  }
+
+  let mut response = match api.response_format() {
+    ResponseFormat::JsonApi => json_api_response(record_document(&api, record)),
+    _ => Json(record).into_response(),
+  };
+  if let Ok(etag) = HeaderValue::from_str(&etag) {
+    response.headers_mut().insert(ETAG, etag);
+  }
+
+  return Ok(response);
+}
+
+pub(crate) async fn read_record(
+  state: &AppState,
+  api: &RecordApi,
+  record: &str,
+  expand: Option<&str>,
+  user: Option<&User>,
+) -> Result<serde_json::Value, RecordError> {
+  let (record, _etag) = read_record_with_etag(state, api, record, expand, false, user).await?;
+  return Ok(record);
+}
+
+async fn read_record_with_etag(
+  state: &AppState,
+  api: &RecordApi,
+  record: &str,
+  expand: Option<&str>,
+  include_deleted: bool,
+  user: Option<&User>,
+) -> Result<(serde_json::Value, String), RecordError> {
+  let record_id = api.id_to_sql(record)?;
+
+  // Access is checked for every request, i.e. also on cache hits, since only the resulting record
+  // is cached.
   api
-    .check_record_level_access(Permission::Read, Some(&record_id), None, user.as_ref())
+    .check_record_level_access(Permission::Read, Some(&record_id), None, user)
+    .await?;
+
+  // Soft-deleted records are only visible to users, who could have deleted them.
+  let include_deleted = include_deleted && api.soft_delete_column().is_some();
+  if include_deleted {
+    api
+      .check_record_level_access(Permission::Delete, Some(&record_id), None, user)
+      .await?;
+  }
+
+  if api.cache_ttl().is_none() {
+    return read_record_uncached(state, api, record_id, expand, include_deleted).await;
+  }
+
+  let query = form_urlencoded::Serializer::new(String::new())
+    .append_pair("id", record)
+    .append_pair("expand", expand.unwrap_or_default())
+    .append_pair(
+      "include_deleted",
+      if include_deleted { "true" } else { "false" },
+    )
+    .finish();
+  let response = state
+    .query_cache()
+    .get_or_compute(
+      state.conn(),
+      api,
+      CacheKey::new(api, CacheOp::Read, query, user),
+      async {
+        return read_record_uncached(state, api, record_id, expand, include_deleted)
+          .await
+          .map(|(record, etag)| CachedResponse::Record(record, etag));
+      },
+    )
     .await?;
 
-  let (_index, pk_column) = api.record_pk_column();
+  return match &*response {
+    CachedResponse::Record(record, etag) => Ok((record.clone(), etag.clone())),
+    _ => Err(RecordError::Internal("unexpected response".into())),
+  };
+}
+
+/// Reads the record w/o checking access, which is up to the caller.
+async fn read_record_uncached(
+  state: &AppState,
+  api: &RecordApi,
+  record_id: Value,
+  expand: Option<&str>,
+  include_deleted: bool,
+) -> Result<(serde_json::Value, String), RecordError> {
+  let record_id_clause = api.record_id_clause(Some("MAIN"), "?1");
   let column_names: Vec<_> = api.columns().iter().map(|c| c.name.as_str()).collect();
 
-  return Ok(Json(match query.expand {
+  let etag_of = |row: &trailbase_sqlite::Row| -> String {
+    return record_etag(
+      (0..column_names.len()).map(|idx| row.get_value(idx).map_or(ValueRef::Null, ValueRef::from)),
+    );
+  };
+
+  let (mut record, etag) = match expand {
     Some(query_expand) if !query_expand.is_empty() => {
       let Some(expand) = api.expand() else {
         return Err(RecordError::BadRequest("Invalid expansion"));
       };
 
-      // Input validation, i.e. only accept columns that are also configured.
-      let query_expand: Vec<_> = query_expand.split(",").collect();
-      for col_name in &query_expand {
-        if !query_expand.contains(col_name) {
+      // Input validation, i.e. only accept columns that are also configured. Nested expansions
+      // are validated against the schema.
+      let query_expand =
+        parse_expand(query_expand).map_err(|_err| RecordError::BadRequest("Invalid expansion"))?;
+      for expansion in &query_expand {
+        if !expand.contains_key(&expansion.column) {
           return Err(RecordError::BadRequest("Invalid expansion"));
         }
       }
 
+      // Expansions not naming one of the API's columns expand referencing records.
+      let (query_expand, reverse_expand): (Vec<_>, Vec<_>) = query_expand
+        .into_iter()
+        .partition(|expansion| api.column_index_by_name(&expansion.column).is_some());
+      let reverse_expansions =
+        reverse_expand_tables(state.schema_metadata(), api.table_name(), &reverse_expand)?;
+
       let expanded_tables = expand_tables(
         state.schema_metadata(),
+        api.table_name(),
         |column_name| {
           api
             .column_index_by_name(column_name)
             .map(|idx| &api.columns()[idx])
         },
         &query_expand,
+        api.max_expand_depth(),
       )?;
 
       let Some(ExpandedSelectQueryResult { root, foreign_rows }) =
@@ -79,64 +227,91 @@ pub async fn read_record_handler(
           state.conn(),
           api.table_name(),
           &column_names,
-          &pk_column.name,
-          record_id,
+          &record_id_clause,
+          record_id.clone(),
           &expanded_tables,
         )
         .await?
       else {
         return Err(RecordError::RecordNotFound);
       };
+      if !include_deleted && api.is_soft_deleted(&root) {
+        return Err(RecordError::RecordNotFound);
+      }
 
       // Alloc a map from column name to value that's pre-filled with with Value::Null for all
       // expandable columns.
       let mut expand = expand.clone();
 
-      for (col_name, (metadata, row)) in std::iter::zip(query_expand, foreign_rows) {
-        let foreign_value = row_to_json(
-          &metadata.schema.columns,
-          &metadata.json_metadata.columns,
-          &row,
-          prefix_filter,
-        )
-        .map_err(|err| RecordError::Internal(err.into()))?;
-
-        let result = expand.insert(col_name.to_string(), foreign_value);
+      for (col_name, foreign_value) in
+        expanded_rows_to_json(&expanded_tables, &foreign_rows, prefix_filter)
+          .map_err(|err| RecordError::Internal(err.into()))?
+      {
+        let result = expand.insert(col_name, foreign_value);
         assert!(result.is_some());
       }
 
-      row_to_json_expand(
+      let mut record = row_to_json_expand(
         api.columns(),
         api.json_column_metadata(),
         &root,
         prefix_filter,
         Some(&expand),
       )
-      .map_err(|err| RecordError::Internal(err.into()))?
+      .map_err(|err| RecordError::Internal(err.into()))?;
+
+      for reverse_expansion in &reverse_expansions {
+        let Some(children) = reverse_expansion
+          .run(
+            state.conn(),
+            std::slice::from_ref(&record_id),
+            prefix_filter,
+          )
+          .await?
+          .pop()
+        else {
+          continue;
+        };
+
+        if let Some(record) = record.as_object_mut() {
+          record.insert(reverse_expansion.name.clone(), children);
+        }
+      }
+
+      (record, etag_of(&root))
     }
     Some(_) | None => {
       let Some(row) = SelectQueryBuilder::run(
         state.conn(),
         api.table_name(),
         &column_names,
-        &pk_column.name,
+        &record_id_clause,
         record_id,
       )
       .await?
       else {
         return Err(RecordError::RecordNotFound);
       };
+      if !include_deleted && api.is_soft_deleted(&row) {
+        return Err(RecordError::RecordNotFound);
+      }
 
-      row_to_json_expand(
+      let record = row_to_json_expand(
         api.columns(),
         api.json_column_metadata(),
         &row,
         prefix_filter,
         api.expand(),
       )
-      .map_err(|err| RecordError::Internal(err.into()))?
+      .map_err(|err| RecordError::Internal(err.into()))?;
+
+      (record, etag_of(&row))
     }
-  }));
+  };
+
+  api.decrypt_record(&mut record)?;
+
+  return Ok((record, etag));
 }
 
 type GetUploadedFileFromRecordPath = Path<(
@@ -146,6 +321,9 @@ type GetUploadedFileFromRecordPath = Path<(
 )>;
 
 /// Read file associated with record.
+///
+/// Images can be transformed on the fly, e.g. `?thumb=200x200&fit=cover&format=webp`. Signed URLs,
+/// i.e. `?expires=<timestamp>&signature=<signature>`, grant access without further authentication.
 #[utoipa::path(
   get,
   path = "/:name/:record/file/:column_name",
@@ -156,22 +334,28 @@ type GetUploadedFileFromRecordPath = Path<(
 pub async fn get_uploaded_file_from_record_handler(
   state: State<AppState>,
   Path((api_name, record, column_name)): GetUploadedFileFromRecordPath,
+  Query(query): Query<FileQuery>,
   user: Option<User>,
 ) -> Result<Response, RecordError> {
+  let transform = query.image_transform()?;
+  let signed = signed_url::signed_params(&query)?;
+
   let Some(api) = state.lookup_record_api(&api_name) else {
     return Err(RecordError::ApiNotFound);
   };
 
   let record_id = api.id_to_sql(&record)?;
 
-  let Ok(()) = api
-    .check_record_level_access(Permission::Read, Some(&record_id), None, user.as_ref())
-    .await
-  else {
-    return Err(RecordError::Forbidden);
-  };
+  // Signed URLs grant access on their own and are verified once the file is known.
+  if signed.is_none() {
+    let Ok(()) = api
+      .check_record_level_access(Permission::Read, Some(&record_id), None, user.as_ref())
+      .await
+    else {
+      return Err(RecordError::Forbidden);
+    };
+  }
 
-  let (_index, pk_column) = api.record_pk_column();
   let Some(index) = api.column_index_by_name(&column_name) else {
     return Err(RecordError::BadRequest("Invalid field/column name"));
   };
@@ -186,12 +370,28 @@ pub async fn get_uploaded_file_from_record_handler(
     api.table_name(),
     column,
     column_json_metadata,
-    &pk_column.name,
+    &api.record_id_clause(None, "$1"),
     record_id,
   )
   .await
   .map_err(|err| RecordError::Internal(err.into()))?;
 
+  if let Some((expires, signature)) = signed {
+    signed_url::verify(
+      &state,
+      &api_name,
+      &record,
+      &column_name,
+      &file_upload,
+      expires,
+      signature,
+    )?;
+  }
+
+  if let Some(transform) = transform {
+    return Ok(read_transformed_image_into_response(&state, file_upload, transform).await?);
+  }
+
   return read_file_into_response(&state, file_upload)
     .await
     .map_err(|err| RecordError::Internal(err.into()));
@@ -204,7 +404,8 @@ type GetUploadedFilesFromRecordPath = Path<(
   usize,  // Index
 )>;
 
-/// Read single file from list associated with record.
+/// Read single file from list associated with record. Supports the same image transformations as
+/// [`get_uploaded_file_from_record_handler`].
 #[utoipa::path(
   get,
   path = "/:name/:record/files/:column_name/:file_index",
@@ -215,22 +416,28 @@ type GetUploadedFilesFromRecordPath = Path<(
 pub async fn get_uploaded_files_from_record_handler(
   State(state): State<AppState>,
   Path((api_name, record, column_name, file_index)): GetUploadedFilesFromRecordPath,
+  Query(query): Query<FileQuery>,
   user: Option<User>,
 ) -> Result<Response, RecordError> {
+  let transform = query.image_transform()?;
+  let signed = signed_url::signed_params(&query)?;
+
   let Some(api) = state.lookup_record_api(&api_name) else {
     return Err(RecordError::ApiNotFound);
   };
 
   let record_id = api.id_to_sql(&record)?;
 
-  let Ok(()) = api
-    .check_record_level_access(Permission::Read, Some(&record_id), None, user.as_ref())
-    .await
-  else {
-    return Err(RecordError::Forbidden);
-  };
+  // Signed URLs grant access on their own and are verified once the file is known.
+  if signed.is_none() {
+    let Ok(()) = api
+      .check_record_level_access(Permission::Read, Some(&record_id), None, user.as_ref())
+      .await
+    else {
+      return Err(RecordError::Forbidden);
+    };
+  }
 
-  let (_index, pk_column) = api.record_pk_column();
   let Some(index) = api.column_index_by_name(&column_name) else {
     return Err(RecordError::BadRequest("Invalid field/column name"));
   };
@@ -245,7 +452,7 @@ pub async fn get_uploaded_files_from_record_handler(
     api.table_name(),
     column,
     column_json_metadata,
-    &pk_column.name,
+    &api.record_id_clause(None, "$1"),
     record_id,
   )
   .await
@@ -255,7 +462,24 @@ pub async fn get_uploaded_files_from_record_handler(
     return Err(RecordError::RecordNotFound);
   }
 
-  return read_file_into_response(&state, file_uploads.0.remove(file_index))
+  let file_upload = file_uploads.0.remove(file_index);
+  if let Some((expires, signature)) = signed {
+    signed_url::verify(
+      &state,
+      &api_name,
+      &record,
+      &column_name,
+      &file_upload,
+      expires,
+      signature,
+    )?;
+  }
+
+  if let Some(transform) = transform {
+    return Ok(read_transformed_image_into_response(&state, file_upload, transform).await?);
+  }
+
+  return read_file_into_response(&state, file_upload)
     .await
     .map_err(|err| RecordError::Internal(err.into()));
 }
@@ -267,7 +491,6 @@ fn prefix_filter(col_name: &str) -> bool {
 
 #[cfg(test)]
 mod test {
-  use axum::Json;
   use axum::extract::{Path, Query, State};
   use serde_json::json;
   use trailbase_schema::{FileUpload, FileUploadInput};
@@ -280,6 +503,7 @@ mod test {
   use crate::config::proto::{PermissionFlag, RecordApiConfig};
   use crate::constants::USER_TABLE;
   use crate::extract::Either;
+  use crate::records::RecordsClient;
   use crate::records::create_record::{
     CreateRecordQuery, CreateRecordResponse, create_record_handler,
   };
@@ -288,6 +512,7 @@ mod test {
   use crate::records::test_utils::*;
   use crate::test::unpack_json_response;
   use crate::util::id_to_b64;
+  use axum::http::HeaderMap;
 
   #[tokio::test]
   async fn ignores_extra_sql_parameters_test() {
@@ -402,6 +627,30 @@ mod test {
         assert!(response.is_ok(), "{response:?}");
       }
 
+      {
+        // User X with projection.
+        let read = async |select: &str| {
+          return read_record_handler(
+            State(state.clone()),
+            Path(("messages_api".to_string(), id_to_b64(&message_id))),
+            Query(ReadRecordQuery {
+              select: Some(select.to_string()),
+              ..Default::default()
+            }),
+            User::from_auth_token(&state, &user_x_token.auth_token),
+          )
+          .await;
+        };
+
+        let value: serde_json::Value = json_body(read("mid,data").await.unwrap()).await;
+        let record = value.as_object().unwrap();
+        assert_eq!(record.keys().collect::<Vec<_>>(), vec!["data", "mid"]);
+        assert_eq!(record["data"], "from user_x to room0");
+
+        assert!(read("missing").await.is_err());
+        assert!(read("_owner").await.is_err());
+      }
+
       {
         // User Y
         let response = read_record_handler(
@@ -495,14 +744,17 @@ mod test {
 
     let record_path = (API_NAME.to_string(), create_response.ids[0].clone());
 
-    let Json(_) = read_record_handler(
-      State(state),
-      Path(record_path),
-      Query(ReadRecordQuery::default()),
-      None,
+    let _: serde_json::Value = json_body(
+      read_record_handler(
+        State(state),
+        Path(record_path),
+        Query(ReadRecordQuery::default()),
+        None,
+      )
+      .await
+      .unwrap(),
     )
-    .await
-    .unwrap();
+    .await;
   }
 
   #[tokio::test]
@@ -534,14 +786,17 @@ mod test {
 
     let record_path = (API_NAME.to_string(), create_response.ids[0].clone());
 
-    let Json(value) = read_record_handler(
-      State(state),
-      Path(record_path),
-      Query(ReadRecordQuery::default()),
-      None,
+    let value: serde_json::Value = json_body(
+      read_record_handler(
+        State(state),
+        Path(record_path),
+        Query(ReadRecordQuery::default()),
+        None,
+      )
+      .await
+      .unwrap(),
     )
-    .await
-    .unwrap();
+    .await;
 
     let serde_json::Value::Object(map) = value else {
       panic!("Not a map");
@@ -588,14 +843,17 @@ mod test {
 
     let record_path = (API_NAME.to_string(), create_response.ids[0].clone());
 
-    let Json(value) = read_record_handler(
-      State(state.clone()),
-      Path(record_path.clone()),
-      Query(ReadRecordQuery::default()),
-      None,
+    let value: serde_json::Value = json_body(
+      read_record_handler(
+        State(state.clone()),
+        Path(record_path.clone()),
+        Query(ReadRecordQuery::default()),
+        None,
+      )
+      .await
+      .unwrap(),
     )
-    .await
-    .unwrap();
+    .await;
 
     let serde_json::Value::Object(map) = value else {
       panic!("Not a map");
@@ -614,6 +872,7 @@ mod test {
     let read_response = get_uploaded_file_from_record_handler(
       State(state.clone()),
       Path(record_file_path.clone()),
+      Query(FileQuery::default()),
       None,
     )
     .await
@@ -624,9 +883,14 @@ mod test {
       .unwrap();
     assert_eq!(body.to_vec(), bytes);
 
-    let _ = delete_record_handler(State(state.clone()), Path(record_path.clone()), None)
-      .await
-      .unwrap();
+    let _ = delete_record_handler(
+      State(state.clone()),
+      Path(record_path.clone()),
+      None,
+      HeaderMap::new(),
+    )
+    .await
+    .unwrap();
 
     let mut dir_cnt = 0;
     let mut read_dir = tokio::fs::read_dir(state.data_dir().uploads_path())
@@ -641,6 +905,101 @@ mod test {
       get_uploaded_file_from_record_handler(
         State(state.clone()),
         Path(record_file_path.clone()),
+        Query(FileQuery::default()),
+        None,
+      )
+      .await
+      .is_err()
+    );
+  }
+
+  #[tokio::test]
+  async fn test_signed_file_url_e2e() {
+    let state = test_state(None).await.unwrap();
+    const API_NAME: &str = "test_api";
+    create_test_record_api(&state, API_NAME).await;
+
+    let bytes: Vec<u8> = vec![42, 5, 42, 5];
+    let create_response: CreateRecordResponse = unpack_json_response(
+      create_record_handler(
+        State(state.clone()),
+        Path(API_NAME.to_string()),
+        Query(CreateRecordQuery::default()),
+        None,
+        Either::Json(
+          json_row_from_value(json!({
+            "file": FileUploadInput {
+              name: None,
+              filename: Some("foo.bin".to_string()),
+              content_type: None,
+              data: bytes.clone(),
+            },
+          }))
+          .unwrap()
+          .into(),
+        ),
+      )
+      .await
+      .unwrap(),
+    )
+    .await
+    .unwrap();
+    let record = create_response.ids[0].clone();
+
+    let signed = signed_url::signed_file_url_handler(
+      State(state.clone()),
+      Path((API_NAME.to_string(), record.clone(), "file".to_string())),
+      Query(signed_url::SignedFileUrlQuery::default()),
+      None,
+    )
+    .await
+    .unwrap()
+    .0;
+
+    let url = url::Url::parse(&signed.url).unwrap();
+    assert_eq!(
+      url.path(),
+      format!("/api/records/v1/{API_NAME}/{record}/file/file")
+    );
+    let query: FileQuery = serde_urlencoded::from_str(url.query().unwrap()).unwrap();
+    assert_eq!(query.expires, Some(signed.expires));
+
+    let file_path = Path((API_NAME.to_string(), record.clone(), "file".to_string()));
+    let response = get_uploaded_file_from_record_handler(
+      State(state.clone()),
+      file_path.clone(),
+      Query(query),
+      None,
+    )
+    .await
+    .unwrap();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+      .await
+      .unwrap();
+    assert_eq!(body.to_vec(), bytes);
+
+    // Invalid signatures are rejected, even if the record were readable otherwise.
+    assert!(matches!(
+      get_uploaded_file_from_record_handler(
+        State(state.clone()),
+        file_path.clone(),
+        Query(FileQuery {
+          expires: Some(signed.expires),
+          signature: Some("invalid".to_string()),
+          ..Default::default()
+        }),
+        None,
+      )
+      .await,
+      Err(RecordError::Forbidden)
+    ));
+
+    // Non-file columns cannot be signed.
+    assert!(
+      signed_url::signed_file_url_handler(
+        State(state.clone()),
+        Path((API_NAME.to_string(), record.clone(), "index".to_string())),
+        Query(signed_url::SignedFileUrlQuery::default()),
         None,
       )
       .await
@@ -693,14 +1052,17 @@ mod test {
 
     let record_path = Path((API_NAME.to_string(), resp.ids[0].clone()));
 
-    let Json(value) = read_record_handler(
-      State(state.clone()),
-      record_path,
-      Query(ReadRecordQuery::default()),
-      None,
+    let value: serde_json::Value = json_body(
+      read_record_handler(
+        State(state.clone()),
+        record_path,
+        Query(ReadRecordQuery::default()),
+        None,
+      )
+      .await
+      .unwrap(),
     )
-    .await
-    .unwrap();
+    .await;
 
     let serde_json::Value::Object(map) = value else {
       panic!("Not a map");
@@ -721,10 +1083,14 @@ mod test {
         index,
       ));
 
-      let response =
-        get_uploaded_files_from_record_handler(State(state.clone()), record_file_path, None)
-          .await
-          .unwrap();
+      let response = get_uploaded_files_from_record_handler(
+        State(state.clone()),
+        record_file_path,
+        Query(FileQuery::default()),
+        None,
+      )
+      .await
+      .unwrap();
 
       let body = axum::body::to_bytes(response.into_body(), usize::MAX)
         .await
@@ -856,14 +1222,17 @@ mod test {
 
     assert_eq!(create_response.ids[0], "1");
 
-    let Json(json) = read_record_handler(
-      State(state.clone()),
-      Path((API_NAME.to_string(), create_response.ids[0].clone())),
-      Query(ReadRecordQuery::default()),
-      None,
+    let json: serde_json::Value = json_body(
+      read_record_handler(
+        State(state.clone()),
+        Path((API_NAME.to_string(), create_response.ids[0].clone())),
+        Query(ReadRecordQuery::default()),
+        None,
+      )
+      .await
+      .unwrap(),
     )
-    .await
-    .unwrap();
+    .await;
 
     assert_eq!(json, value);
 
@@ -1026,16 +1395,20 @@ mod test {
       },
     });
 
-    let Json(value) = read_record_handler(
-      State(state.clone()),
-      Path(("child_api".to_string(), "1".to_string())),
-      Query(ReadRecordQuery {
-        expand: Some("parent".to_string()),
-      }),
-      None,
+    let value: serde_json::Value = json_body(
+      read_record_handler(
+        State(state.clone()),
+        Path(("child_api".to_string(), "1".to_string())),
+        Query(ReadRecordQuery {
+          expand: Some("parent".to_string()),
+          ..Default::default()
+        }),
+        None,
+      )
+      .await
+      .unwrap(),
     )
-    .await
-    .unwrap();
+    .await;
 
     assert_eq!(value, expected);
 
@@ -1053,17 +1426,263 @@ mod test {
     .await
     .unwrap();
 
-    let Json(value) = read_record_handler(
-      State(state.clone()),
-      Path(("child_view_api".to_string(), "1".to_string())),
-      Query(ReadRecordQuery {
-        expand: Some("parent".to_string()),
-      }),
-      None,
+    let value: serde_json::Value = json_body(
+      read_record_handler(
+        State(state.clone()),
+        Path(("child_view_api".to_string(), "1".to_string())),
+        Query(ReadRecordQuery {
+          expand: Some("parent".to_string()),
+          ..Default::default()
+        }),
+        None,
+      )
+      .await
+      .unwrap(),
+    )
+    .await;
+
+    assert_eq!(value, expected);
+  }
+
+  #[tokio::test]
+  async fn test_nested_expansion() {
+    let state = test_state(None).await.unwrap();
+
+    state
+      .conn()
+      .execute_batch(
+        r#"
+          CREATE TABLE team (
+            id           INTEGER PRIMARY KEY NOT NULL,
+            name         TEXT NOT NULL,
+            lead         INTEGER REFERENCES author
+          ) STRICT;
+          CREATE TABLE author (
+            id           INTEGER PRIMARY KEY NOT NULL,
+            name         TEXT NOT NULL,
+            avatar       TEXT,
+            team         INTEGER REFERENCES team
+          ) STRICT;
+          CREATE TABLE article (
+            id           INTEGER PRIMARY KEY NOT NULL,
+            title        TEXT NOT NULL,
+            author       INTEGER REFERENCES author
+          ) STRICT;
+
+          INSERT INTO team (id, name, lead) VALUES (1, 'core', 1);
+          INSERT INTO author (id, name, avatar, team) VALUES (1, 'alice', 'a.png', 1);
+          INSERT INTO article (id, title, author) VALUES (1, 'first', 1);
+       "#,
+      )
+      .await
+      .unwrap();
+
+    state.schema_metadata().invalidate_all().await.unwrap();
+
+    for (name, max_expand_depth) in [("articles", Some(3)), ("articles_flat", None)] {
+      add_record_api_config(
+        &state,
+        RecordApiConfig {
+          name: Some(name.to_string()),
+          table_name: Some("article".to_string()),
+          acl_world: [PermissionFlag::Read as i32].into(),
+          expand: vec!["author".to_string()],
+          max_expand_depth,
+          ..Default::default()
+        },
+      )
+      .await
+      .unwrap();
+    }
+
+    let read = async |api_name: &str, expand: &str| -> Result<serde_json::Value, RecordError> {
+      let response = read_record_handler(
+        State(state.clone()),
+        Path((api_name.to_string(), "1".to_string())),
+        Query(ReadRecordQuery {
+          expand: Some(expand.to_string()),
+          ..Default::default()
+        }),
+        None,
+      )
+      .await?;
+      return Ok(json_body(response).await);
+    };
+
+    let expected = json!({
+      "id": 1,
+      "title": "first",
+      "author": {
+        "id": 1,
+        "data": {
+          "name": "alice",
+          "team": {
+            "id": 1,
+            "data": {
+              "id": 1,
+              "name": "core",
+              "lead": 1,
+            },
+          },
+        },
+      },
+    });
+
+    assert_eq!(
+      read("articles", "author(name).team").await.unwrap(),
+      expected
+    );
+
+    let list = RecordsClient::new(&state)
+      .list("articles", Some("expand=author(name).team"))
+      .await
+      .unwrap();
+    assert_eq!(list.records, vec![expected]);
+
+    // Depth is limited.
+    assert!(matches!(
+      read("articles_flat", "author.team").await,
+      Err(RecordError::BadRequest(_))
+    ));
+    assert!(read("articles_flat", "author").await.is_ok());
+
+    // Cycles are rejected, i.e. the team lead is an author again.
+    assert!(matches!(
+      read("articles", "author.team.lead").await,
+      Err(RecordError::BadRequest(_))
+    ));
+
+    // Projections are validated.
+    assert!(matches!(
+      read("articles", "author(missing)").await,
+      Err(RecordError::BadRequest(_))
+    ));
+    assert!(matches!(
+      read("articles", "author(name").await,
+      Err(RecordError::BadRequest(_))
+    ));
+  }
+
+  #[tokio::test]
+  async fn test_reverse_expansion() {
+    let state = test_state(None).await.unwrap();
+
+    state
+      .conn()
+      .execute_batch(
+        r#"
+          CREATE TABLE post (
+            id           INTEGER PRIMARY KEY NOT NULL,
+            title        TEXT NOT NULL
+          ) STRICT;
+          CREATE TABLE comment (
+            id           INTEGER PRIMARY KEY NOT NULL,
+            body         TEXT NOT NULL,
+            post         INTEGER REFERENCES post
+          ) STRICT;
+
+          INSERT INTO post (id, title) VALUES (1, 'first'), (2, 'second');
+          INSERT INTO comment (id, body, post) VALUES (1, 'a', 1), (2, 'b', 1), (3, 'c', 2);
+       "#,
+      )
+      .await
+      .unwrap();
+
+    state.schema_metadata().invalidate_all().await.unwrap();
+
+    add_record_api_config(
+      &state,
+      RecordApiConfig {
+        name: Some("posts".to_string()),
+        table_name: Some("post".to_string()),
+        acl_world: [PermissionFlag::Read as i32].into(),
+        expand: vec!["comment_via_post".to_string()],
+        ..Default::default()
+      },
     )
     .await
     .unwrap();
 
-    assert_eq!(value, expected);
+    // Only foreign keys referencing the API's table can be expanded.
+    assert!(
+      add_record_api_config(
+        &state,
+        RecordApiConfig {
+          name: Some("posts_invalid".to_string()),
+          table_name: Some("post".to_string()),
+          acl_world: [PermissionFlag::Read as i32].into(),
+          expand: vec!["comment_via_body".to_string()],
+          ..Default::default()
+        },
+      )
+      .await
+      .is_err()
+    );
+
+    let read = async |expand: &str| -> Result<serde_json::Value, RecordError> {
+      let response = read_record_handler(
+        State(state.clone()),
+        Path(("posts".to_string(), "1".to_string())),
+        Query(ReadRecordQuery {
+          expand: Some(expand.to_string()),
+          ..Default::default()
+        }),
+        None,
+      )
+      .await?;
+      return Ok(json_body(response).await);
+    };
+
+    assert_eq!(
+      read("comment_via_post").await.unwrap(),
+      json!({
+        "id": 1,
+        "title": "first",
+        "comment_via_post": [
+          {"id": 1, "body": "a", "post": 1},
+          {"id": 2, "body": "b", "post": 1},
+        ],
+      })
+    );
+    assert_eq!(
+      read("comment_via_post(limit=1,order=-id,body)")
+        .await
+        .unwrap(),
+      json!({
+        "id": 1,
+        "title": "first",
+        "comment_via_post": [{"body": "b"}],
+      })
+    );
+
+    let list = RecordsClient::new(&state)
+      .list("posts", Some("expand=comment_via_post(body)&order=id"))
+      .await
+      .unwrap();
+    assert_eq!(
+      list.records,
+      vec![
+        json!({
+          "id": 1,
+          "title": "first",
+          "comment_via_post": [{"body": "a"}, {"body": "b"}],
+        }),
+        json!({
+          "id": 2,
+          "title": "second",
+          "comment_via_post": [{"body": "c"}],
+        }),
+      ]
+    );
+
+    // Reverse expansions can neither be nested nor reference missing columns.
+    assert!(matches!(
+      read("comment_via_post.post").await,
+      Err(RecordError::BadRequest(_))
+    ));
+    assert!(matches!(
+      read("comment_via_post(order=missing)").await,
+      Err(RecordError::BadRequest(_))
+    ));
   }
 }
@@ -1,22 +1,33 @@
 use askama::Template;
+use lazy_static::lazy_static;
 use log::*;
+use regex::Regex;
 use rusqlite::types::ToSqlOutput;
 use std::borrow::Cow;
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
 use trailbase_schema::metadata::{
-  JsonColumnMetadata, TableMetadata, TableOrViewMetadata, ViewMetadata, find_file_column_indexes,
+  FtsIndex, GeoPoint, GeometryColumns, JsonColumnMetadata, TableMetadata, TableOrViewMetadata,
+  VectorIndex, ViewMetadata, find_file_column_indexes, find_geometry_columns,
   find_user_id_foreign_key_columns,
 };
 use trailbase_schema::sqlite::{Column, ColumnDataType, sqlite3_parse_into_statement};
 use trailbase_sqlite::{NamedParamRef, NamedParams, Params as _, Value};
 
 use crate::auth::user::User;
-use crate::config::proto::{ConflictResolutionStrategy, RecordApiConfig};
-use crate::constants::USER_TABLE;
+use crate::config::proto::{
+  ConflictResolutionStrategy, EmbeddingConfig, ListLimitsConfig, PermissionFlag, RateLimitConfig,
+  RecordApiConfig, ResponseFormat, WebhookConfig,
+};
+use crate::constants::{USER_ROLE_TABLE, USER_TABLE};
+use crate::records::encryption::{ColumnEncryption, ENCRYPTION_KEYS_ENV_VAR, encryption_keys};
 use crate::records::params::{LazyParams, prefix_colon};
+use crate::records::request_context::{CONTEXT_COLUMNS, push_request_context_params};
+use crate::records::validators::{ColumnValidatorFn, ScriptValidators, build_column_validator};
 use crate::records::{Permission, RecordError};
-use crate::util::{assert_uuidv7, b64_to_id};
+use crate::schema_metadata::SchemaMetadataCache;
+use crate::util::b64_to_id;
 
 #[derive(Clone)]
 pub struct RecordApi {
@@ -27,11 +38,20 @@ struct RecordApiSchema {
   /// Schema metadata
   table_name: String,
   is_table: bool,
+  /// The primary key column or, for composite primary keys, its first part.
   record_pk_column: (usize, Column),
+  record_pk_columns: Vec<(usize, Column)>,
+  /// Whether the primary key is time-sortable and thus supports cursors.
+  record_pk_sortable: bool,
   columns: Vec<Column>,
   json_column_metadata: Vec<Option<JsonColumnMetadata>>,
   has_file_columns: bool,
   user_id_columns: Vec<usize>,
+  fts_index: Option<FtsIndex>,
+  vector_index: Option<VectorIndex>,
+  geo_points: Vec<GeoPoint>,
+  /// Sets of columns with uniqueness constraints, i.e. valid UPSERT conflict targets.
+  unique_keys: Vec<Vec<String>>,
 
   // Helpers
   column_name_to_index: HashMap<String, usize>,
@@ -42,10 +62,14 @@ impl RecordApiSchema {
   fn from_table(schema_metadata: &TableMetadata, config: &RecordApiConfig) -> Result<Self, String> {
     assert_eq!(config.table_name.as_deref(), Some(schema_metadata.name()));
 
-    let Some((pk_index, pk_column)) = schema_metadata.record_pk_column() else {
-      return Err("RecordApi requires integer/UUIDv7 primary key column".into());
+    let record_pk_columns: Vec<(usize, Column)> = schema_metadata
+      .record_pk_columns()
+      .into_iter()
+      .map(|(index, column)| (index, column.clone()))
+      .collect();
+    let Some(record_pk_column) = record_pk_columns.first().cloned() else {
+      return Err("RecordApi requires integer/UUID/ULID primary key column".into());
     };
-    let record_pk_column = (pk_index, pk_column.clone());
 
     let (columns, json_column_metadata) = filter_columns(
       config,
@@ -63,6 +87,17 @@ impl RecordApiSchema {
         .map(|(index, col)| (col.name.clone(), index)),
     );
 
+    // Geo points need both of their columns to be exposed.
+    let geo_points = schema_metadata
+      .geo_points
+      .iter()
+      .filter(|point| {
+        return column_name_to_index.contains_key(&point.lat_column)
+          && column_name_to_index.contains_key(&point.lng_column);
+      })
+      .cloned()
+      .collect();
+
     let named_params_template: NamedParams = columns
       .iter()
       .map(|column| {
@@ -77,10 +112,16 @@ impl RecordApiSchema {
       table_name: schema_metadata.name().to_string(),
       is_table: true,
       record_pk_column,
+      record_pk_columns,
+      record_pk_sortable: schema_metadata.record_pk_sortable,
       columns,
       json_column_metadata,
       has_file_columns,
       user_id_columns,
+      fts_index: schema_metadata.fts.clone(),
+      vector_index: schema_metadata.vector_index.clone(),
+      geo_points,
+      unique_keys: schema_metadata.unique_keys(),
       column_name_to_index,
       named_params_template,
     });
@@ -91,7 +132,7 @@ impl RecordApiSchema {
 
     let Some((pk_index, pk_column)) = view_metadata.record_pk_column() else {
       return Err(format!(
-        "RecordApi requires integer/UUIDv7 primary key column: {config:?}"
+        "RecordApi requires integer/UUID/ULID primary key column: {config:?}"
       ));
     };
     let record_pk_column = (pk_index, pk_column.clone());
@@ -118,11 +159,17 @@ impl RecordApiSchema {
     return Ok(Self {
       table_name: view_metadata.name().to_string(),
       is_table: false,
-      record_pk_column,
+      record_pk_column: record_pk_column.clone(),
+      record_pk_columns: vec![record_pk_column],
+      record_pk_sortable: view_metadata.record_pk_sortable(),
       columns,
       json_column_metadata,
       has_file_columns,
       user_id_columns,
+      fts_index: None,
+      vector_index: None,
+      geo_points: vec![],
+      unique_keys: vec![],
       column_name_to_index,
       named_params_template: NamedParams::new(),
     });
@@ -142,15 +189,34 @@ struct RecordApiState {
   insert_conflict_resolution_strategy: Option<ConflictResolutionStrategy>,
   insert_autofill_missing_user_id_columns: bool,
   enable_subscriptions: bool,
+  enable_sync: bool,
+  response_format: ResponseFormat,
+  embedding: Option<EmbeddingConfig>,
+  webhooks: Vec<WebhookConfig>,
+  rate_limit: Option<RateLimitConfig>,
+  geometry_columns: Option<GeometryColumns>,
+  cache_ttl: Option<Duration>,
+  cache_max_bytes: Option<u64>,
+  list_limits: Option<ListLimitsConfig>,
+  soft_delete_column: Option<usize>,
 
   // Foreign key expansion configuration. Affects schema.
   expand: Option<HashMap<String, serde_json::Value>>,
+  max_expand_depth: usize,
+
+  // Custom validators indexed by column index.
+  column_validators: Vec<Vec<Arc<ColumnValidatorFn>>>,
+
+  // Encryption of sensitive columns indexed by column index.
+  column_encryption: Vec<Option<ColumnEncryption>>,
 
   // Open question: right now the read_access rule is also used for listing. It might be nice to
   // allow different permissions, however there's a risk of listing records w/o read access.
   // Arguably, this could always be modeled as two APIs with different permissions on the same
   // table.
   read_access_rule: Option<String>,
+  // Likewise, the raw delete rule determines which soft-deleted records can be listed.
+  delete_access_rule: Option<String>,
   read_access_query: Option<Arc<str>>,
   subscription_read_access_query: Option<String>,
 
@@ -178,41 +244,76 @@ impl RecordApi {
     conn: trailbase_sqlite::Connection,
     schema_metadata: &TableMetadata,
     config: RecordApiConfig,
+    script_validators: &ScriptValidators,
   ) -> Result<Self, String> {
     assert_eq!(config.table_name.as_deref(), Some(schema_metadata.name()));
 
     let schema = RecordApiSchema::from_table(schema_metadata, &config)?;
 
-    return Self::from_impl(conn, schema, config);
+    return Self::from_impl(conn, schema, config, script_validators);
   }
 
   pub fn from_view(
     conn: trailbase_sqlite::Connection,
     view_metadata: &ViewMetadata,
     config: RecordApiConfig,
+    script_validators: &ScriptValidators,
   ) -> Result<Self, String> {
     assert_eq!(config.table_name.as_deref(), Some(view_metadata.name()));
 
     let schema = RecordApiSchema::from_view(view_metadata, &config)?;
 
-    return Self::from_impl(conn, schema, config);
+    return Self::from_impl(conn, schema, config, script_validators);
   }
 
   fn from_impl(
     conn: trailbase_sqlite::Connection,
     schema: RecordApiSchema,
-    config: RecordApiConfig,
+    mut config: RecordApiConfig,
+    script_validators: &ScriptValidators,
   ) -> Result<Self, String> {
     assert_eq!(schema.columns.len(), schema.json_column_metadata.len());
 
+    for rule in [
+      &mut config.create_access_rule,
+      &mut config.read_access_rule,
+      &mut config.update_access_rule,
+      &mut config.delete_access_rule,
+      &mut config.schema_access_rule,
+    ]
+    .into_iter()
+    .flatten()
+    {
+      *rule = expand_rule(rule);
+    }
+
     let Some(api_name) = config.name.clone() else {
       return Err(format!("RecordApi misses name: {config:?}"));
     };
 
+    if !schema.record_pk_sortable && config.require_sortable_primary_key.unwrap_or(true) {
+      return Err(format!(
+        "RecordApi '{api_name}' requires time-sortable primary key, e.g. UUIDv7. Set `require_sortable_primary_key: false` to allow random UUIDs"
+      ));
+    }
+
+    // Composite primary keys are read-only for now, since writes address records by a single
+    // primary key column.
+    if schema.record_pk_columns.len() > 1 {
+      const WRITE: u8 =
+        PermissionFlag::Create as u8 | PermissionFlag::Update as u8 | PermissionFlag::Delete as u8;
+      if (convert_acl(&config.acl_world) | convert_acl(&config.acl_authenticated)) & WRITE != 0 {
+        return Err(format!(
+          "RecordApi '{api_name}' with composite primary key only supports reads"
+        ));
+      }
+    }
+
+    let pk_clause = record_id_clause(&schema.record_pk_columns, None, ":__record_id");
     let (read_access_query, subscription_read_access_query) = match &config.read_access_rule {
       Some(rule) => {
         let read_access_query =
-          build_read_delete_schema_query(&schema.table_name, &schema.record_pk_column.1.name, rule);
+          build_read_delete_schema_query(&schema.table_name, &pk_clause, rule);
 
         let subscription_read_access_query = if schema.is_table {
           Some(
@@ -232,13 +333,15 @@ impl RecordApi {
       None => (None, None),
     };
 
-    let delete_access_query = config.delete_access_rule.as_ref().map(|rule| {
-      build_read_delete_schema_query(&schema.table_name, &schema.record_pk_column.1.name, rule)
-    });
+    let delete_access_query = config
+      .delete_access_rule
+      .as_ref()
+      .map(|rule| build_read_delete_schema_query(&schema.table_name, &pk_clause, rule));
 
-    let schema_access_query = config.schema_access_rule.as_ref().map(|rule| {
-      build_read_delete_schema_query(&schema.table_name, &schema.record_pk_column.1.name, rule)
-    });
+    let schema_access_query = config
+      .schema_access_rule
+      .as_ref()
+      .map(|rule| build_read_delete_schema_query(&schema.table_name, &pk_clause, rule));
 
     let create_access_query = match &config.create_access_rule {
       Some(rule) => {
@@ -267,6 +370,92 @@ impl RecordApi {
       None => None,
     };
 
+    let mut column_validators: Vec<Vec<Arc<ColumnValidatorFn>>> =
+      vec![vec![]; schema.columns.len()];
+    for column_validator in &config.column_validators {
+      let (Some(column_name), Some(validator)) =
+        (&column_validator.column_name, &column_validator.validator)
+      else {
+        return Err(format!("Incomplete column validator: {column_validator:?}"));
+      };
+
+      // Validators for excluded columns are skipped, since they cannot be written through this
+      // API anyway.
+      let Some(index) = schema.column_name_to_index.get(column_name) else {
+        continue;
+      };
+
+      column_validators[*index].push(build_column_validator(
+        validator,
+        column_validator.argument.as_deref(),
+        script_validators,
+      )?);
+    }
+
+    let mut column_encryption: Vec<Option<ColumnEncryption>> = vec![None; schema.columns.len()];
+    for encrypted_column in &config.encrypted_columns {
+      let Some(ref column_name) = encrypted_column.column_name else {
+        return Err(format!("Incomplete encrypted column: {encrypted_column:?}"));
+      };
+
+      // Like validators, encryption of excluded columns is skipped.
+      let Some(index) = schema.column_name_to_index.get(column_name) else {
+        continue;
+      };
+      if schema.columns[*index].data_type != ColumnDataType::Text {
+        return Err(format!("Encrypted column must be TEXT: {column_name}"));
+      }
+
+      let Some(keys) = encryption_keys() else {
+        return Err(format!(
+          "Encrypted column '{column_name}' requires encryption keys, see {ENCRYPTION_KEYS_ENV_VAR}"
+        ));
+      };
+      column_encryption[*index] = Some(ColumnEncryption::new(
+        keys,
+        &schema.table_name,
+        column_name,
+        encrypted_column.deterministic.unwrap_or(false),
+      ));
+    }
+
+    let soft_delete_column = match config.soft_delete_column {
+      Some(ref column_name) => {
+        if !schema.is_table {
+          return Err(format!("Soft-deletion requires a table: {column_name}"));
+        }
+        let Some(index) = schema.column_name_to_index.get(column_name) else {
+          return Err(format!("Missing soft-delete column: {column_name}"));
+        };
+        if *index == schema.record_pk_column.0 || schema.columns[*index].is_not_null() {
+          return Err(format!(
+            "Soft-delete column must be nullable: {column_name}"
+          ));
+        }
+        Some(*index)
+      }
+      None => None,
+    };
+
+    let geometry_columns = find_geometry_columns(&schema.columns);
+    let embedding = if schema.is_table {
+      config.embedding
+    } else {
+      None
+    };
+    let webhooks = if schema.is_table {
+      config.webhooks
+    } else {
+      vec![]
+    };
+
+    // Cached responses aren't keyed by the request context, thus APIs whose read or delete, i.e.
+    // soft-deleted listings, access rules read `_CTX_` cannot be cached.
+    let uses_request_context = [&config.read_access_rule, &config.delete_access_rule]
+      .into_iter()
+      .flatten()
+      .any(|rule| rule.contains("_CTX_"));
+
     return Ok(RecordApi {
       state: Arc::new(RecordApiState {
         conn,
@@ -283,6 +472,22 @@ impl RecordApi {
           .autofill_missing_user_id_columns
           .unwrap_or(false),
         enable_subscriptions: config.enable_subscriptions.unwrap_or(false),
+        enable_sync: config.enable_sync.unwrap_or(false),
+        response_format: match config.response_format.and_then(|f| f.try_into().ok()) {
+          Some(ResponseFormat::JsonApi) => ResponseFormat::JsonApi,
+          _ => ResponseFormat::Json,
+        },
+        geometry_columns,
+        embedding,
+        webhooks,
+        rate_limit: config.rate_limit,
+        cache_ttl: config
+          .cache_ttl_sec
+          .filter(|ttl| *ttl > 0 && !uses_request_context)
+          .map(|ttl| Duration::from_secs(ttl.into())),
+        cache_max_bytes: config.cache_max_bytes,
+        list_limits: config.list_limits.clone(),
+        soft_delete_column,
 
         expand: if config.expand.is_empty() {
           None
@@ -295,6 +500,10 @@ impl RecordApi {
               .collect(),
           )
         },
+        max_expand_depth: config.max_expand_depth.map_or(1, |depth| depth as usize),
+
+        column_validators,
+        column_encryption,
 
         // Access control lists.
         acl: [
@@ -307,6 +516,7 @@ impl RecordApi {
 
         // The raw read rule is needed to construct list queries.
         read_access_rule: config.read_access_rule,
+        delete_access_rule: config.delete_access_rule,
         read_access_query,
         subscription_read_access_query,
 
@@ -343,11 +553,58 @@ impl RecordApi {
     return self.state.expand.as_ref();
   }
 
+  /// Maximum number of levels of nested expansions.
+  #[inline]
+  pub(crate) fn max_expand_depth(&self) -> usize {
+    return self.state.max_expand_depth;
+  }
+
   #[inline]
   pub fn record_pk_column(&self) -> &(usize, Column) {
     return &self.state.schema.record_pk_column;
   }
 
+  /// Primary key columns, i.e. multiple for composite primary keys, whose record ids are the
+  /// comma-separated parts, e.g. `<pk1>,<pk2>`.
+  #[inline]
+  pub fn record_pk_columns(&self) -> &[(usize, Column)] {
+    return &self.state.schema.record_pk_columns;
+  }
+
+  /// Whether the primary key is time-sortable. Otherwise, listings don't support cursors and need
+  /// to be paginated using offsets.
+  #[inline]
+  pub fn record_pk_sortable(&self) -> bool {
+    return self.state.schema.record_pk_sortable;
+  }
+
+  /// SQL condition matching the record whose id, see [`RecordApi::id_to_sql`], is bound to
+  /// `placeholder`, e.g. `MAIN."id" = ?1`.
+  pub(crate) fn record_id_clause(&self, qualifier: Option<&str>, placeholder: &str) -> String {
+    return record_id_clause(&self.state.schema.record_pk_columns, qualifier, placeholder);
+  }
+
+  /// Renders the record id of the given record, i.e. the comma-separated parts for composite
+  /// primary keys.
+  pub(crate) fn record_id_of(
+    &self,
+    record: &serde_json::Map<String, serde_json::Value>,
+  ) -> Option<serde_json::Value> {
+    let [(_index, pk_column)] = self.record_pk_columns() else {
+      let parts = self
+        .record_pk_columns()
+        .iter()
+        .map(|(_index, column)| match record.get(&column.name)? {
+          serde_json::Value::String(part) => Some(part.clone()),
+          serde_json::Value::Null => None,
+          part => Some(part.to_string()),
+        })
+        .collect::<Option<Vec<_>>>()?;
+      return Some(serde_json::Value::String(parts.join(",")));
+    };
+    return record.get(&pk_column.name).cloned();
+  }
+
   #[inline]
   pub fn columns(&self) -> &[Column] {
     return &self.state.schema.columns;
@@ -363,32 +620,102 @@ impl RecordApi {
     return self.state.schema.is_table;
   }
 
+  /// FTS5 index of the API's table, if any, which enables `?search=` for list queries.
+  #[inline]
+  pub(crate) fn fts_index(&self) -> Option<&FtsIndex> {
+    return self.state.schema.fts_index.as_ref();
+  }
+
+  /// vec0 index of the API's table, if any, whose columns can be ranked by in addition to the
+  /// table's own vector columns.
+  #[inline]
+  pub(crate) fn vector_index(&self) -> Option<&VectorIndex> {
+    return self.state.schema.vector_index.as_ref();
+  }
+
+  /// Latitude/longitude column pair by name, which enables `[within]` and `[near]` filters.
+  #[inline]
+  pub(crate) fn geo_point(&self, name: &str) -> Option<&GeoPoint> {
+    return self.state.schema.geo_points.iter().find(|p| p.name == name);
+  }
+
+  /// Sets of columns with uniqueness constraints, i.e. valid UPSERT conflict targets.
+  #[inline]
+  pub(crate) fn unique_keys(&self) -> &[Vec<String>] {
+    return &self.state.schema.unique_keys;
+  }
+
   #[inline]
   pub fn column_index_by_name(&self, key: &str) -> Option<usize> {
     return self.state.schema.column_name_to_index.get(key).copied();
   }
 
-  pub fn id_to_sql(&self, id: &str) -> Result<Value, RecordError> {
-    return match self.state.schema.record_pk_column.1.data_type {
-      ColumnDataType::Blob => {
-        // Special handling for text encoded UUIDs. Right now we're guessing based on length, it
-        // would be more explicit rely on CHECK(...) column options.
-        if id.len() == 36 {
-          if let Ok(id) = uuid::Uuid::parse_str(id) {
-            return Ok(Value::Blob(id.into()));
-          }
-        }
+  #[inline]
+  pub(crate) fn column_validators(&self, index: usize) -> &[Arc<ColumnValidatorFn>] {
+    return &self.state.column_validators[index];
+  }
 
-        let record_id = b64_to_id(id).map_err(|_err| RecordError::BadRequest("Invalid id"))?;
-        assert_uuidv7(&record_id);
-        Ok(Value::Blob(record_id.into()))
+  #[inline]
+  pub(crate) fn column_encryption(&self, index: usize) -> Option<&ColumnEncryption> {
+    return self.state.column_encryption[index].as_ref();
+  }
+
+  pub(crate) fn has_encrypted_columns(&self) -> bool {
+    return self.state.column_encryption.iter().any(|e| e.is_some());
+  }
+
+  /// Decrypts encrypted columns of a JSON record in place.
+  pub(crate) fn decrypt_record(&self, record: &mut serde_json::Value) -> Result<(), RecordError> {
+    let Some(record) = record.as_object_mut() else {
+      return Ok(());
+    };
+
+    for (index, encryption) in self.state.column_encryption.iter().enumerate() {
+      let Some(encryption) = encryption else {
+        continue;
+      };
+      let column_name = &self.state.schema.columns[index].name;
+      if let Some(serde_json::Value::String(value)) = record.get_mut(column_name) {
+        let plaintext = encryption
+          .decrypt(value)
+          .map_err(|err| RecordError::Internal(err.into()))?;
+        *value = plaintext.into_owned();
+      }
+    }
+
+    return Ok(());
+  }
+
+  /// Converts a record id from the URL into its SQL value. Composite ids are converted into a JSON
+  /// array of their parts, which [`RecordApi::record_id_clause`] unpacks.
+  pub fn id_to_sql(&self, id: &str) -> Result<Value, RecordError> {
+    let [(_index, pk_column)] = self.record_pk_columns() else {
+      let parts: Vec<&str> = id.split(',').collect();
+      if parts.len() != self.record_pk_columns().len() {
+        return Err(RecordError::BadRequest("Invalid id"));
       }
-      ColumnDataType::Integer => Ok(Value::Integer(
-        id.parse::<i64>()
-          .map_err(|_err| RecordError::BadRequest("Invalid id"))?,
-      )),
-      _ => Err(RecordError::BadRequest("Invalid id")),
+
+      let parts = self
+        .record_pk_columns()
+        .iter()
+        .zip(parts)
+        .map(|((_index, column), part)| {
+          return Ok(match pk_value_to_sql(column, part)? {
+            Value::Integer(i) => serde_json::Value::from(i),
+            Value::Blob(blob) => serde_json::Value::String(
+              uuid::Uuid::from_slice(&blob)
+                .map_err(|_err| RecordError::BadRequest("Invalid id"))?
+                .to_string(),
+            ),
+            _ => return Err(RecordError::BadRequest("Invalid id")),
+          });
+        })
+        .collect::<Result<Vec<_>, RecordError>>()?;
+
+      return Ok(Value::Text(serde_json::Value::Array(parts).to_string()));
     };
+
+    return pk_value_to_sql(pk_column, id);
   }
 
   #[inline]
@@ -406,27 +733,156 @@ impl RecordApi {
     return self.state.enable_subscriptions;
   }
 
+  #[inline]
+  pub fn enable_sync(&self) -> bool {
+    return self.state.enable_sync;
+  }
+
+  #[inline]
+  pub fn response_format(&self) -> ResponseFormat {
+    return self.state.response_format;
+  }
+
+  #[inline]
+  pub(crate) fn geometry_columns(&self) -> Option<&GeometryColumns> {
+    return self.state.geometry_columns.as_ref();
+  }
+
+  #[inline]
+  pub(crate) fn embedding(&self) -> Option<&EmbeddingConfig> {
+    return self.state.embedding.as_ref();
+  }
+
+  #[inline]
+  pub(crate) fn webhooks(&self) -> &[WebhookConfig] {
+    return &self.state.webhooks;
+  }
+
+  #[inline]
+  pub(crate) fn rate_limit(&self) -> Option<&RateLimitConfig> {
+    return self.state.rate_limit.as_ref();
+  }
+
+  /// Time-to-live of cached list and read responses, if caching is enabled and possible, i.e. the
+  /// access rules don't depend on the request context.
+  #[inline]
+  pub(crate) fn cache_ttl(&self) -> Option<Duration> {
+    return self.state.cache_ttl;
+  }
+
+  /// Page size overrides of `server.list_limits`, if any.
+  #[inline]
+  pub(crate) fn list_limits(&self) -> Option<&ListLimitsConfig> {
+    return self.state.list_limits.as_ref();
+  }
+
+  /// Configured upper bound for the size of cached responses, if any.
+  #[inline]
+  pub(crate) fn cache_max_bytes(&self) -> Option<u64> {
+    return self.state.cache_max_bytes;
+  }
+
+  /// Index of the column marking records as soft-deleted, if soft-deletion is enabled.
+  #[inline]
+  pub(crate) fn soft_delete_column(&self) -> Option<usize> {
+    return self.state.soft_delete_column;
+  }
+
+  /// Whether a row, e.g. as returned by a read, has been soft-deleted.
+  pub(crate) fn is_soft_deleted(&self, row: &trailbase_sqlite::Row) -> bool {
+    return self
+      .state
+      .soft_delete_column
+      .is_some_and(|index| !matches!(row.get_value(index), None | Some(Value::Null)));
+  }
+
+  /// The delete access rule, if soft-deletion is enabled, since it determines which soft-deleted
+  /// records can be listed.
+  #[inline]
+  pub(crate) fn soft_delete_access_rule(&self) -> Option<&str> {
+    if self.state.soft_delete_column.is_none() {
+      return None;
+    }
+    return self.state.delete_access_rule.as_deref();
+  }
+
+  /// Returns the condition on `_ROW_` restricting listings to records the user may see, i.e. the
+  /// read access rule and, with soft-deletion enabled, only records that haven't been deleted.
+  /// With `include_deleted`, soft-deleted records the user could delete are included as well.
+  pub(crate) fn list_access_clause(
+    &self,
+    include_deleted: bool,
+    user: Option<&User>,
+  ) -> Result<Cow<'_, str>, RecordError> {
+    let read_access_clause = self.read_access_rule().unwrap_or("TRUE");
+    let Some(index) = self.state.soft_delete_column else {
+      return Ok(Cow::Borrowed(read_access_clause));
+    };
+
+    let column_name = &self.columns()[index].name;
+    if !include_deleted {
+      return Ok(Cow::Owned(format!(
+        r#"({read_access_clause}) AND _ROW_."{column_name}" IS NULL"#
+      )));
+    }
+
+    self.check_table_level_access(Permission::Delete, user)?;
+    return Ok(Cow::Owned(format!(
+      r#"({read_access_clause}) AND (_ROW_."{column_name}" IS NULL OR ({delete_access_clause}))"#,
+      delete_access_clause = self.state.delete_access_rule.as_deref().unwrap_or("TRUE"),
+    )));
+  }
+
+  /// Query evaluating the read access rule for a given record, if any.
+  #[inline]
+  pub(crate) fn read_access_query(&self) -> Option<Arc<str>> {
+    return self.state.cached_access_query(Permission::Read);
+  }
+
+  #[inline]
+  pub(crate) fn has_update_access_rule(&self) -> bool {
+    return self.state.update_access_query.is_some();
+  }
+
   #[inline]
   pub fn insert_conflict_resolution_strategy(&self) -> Option<ConflictResolutionStrategy> {
     return self.state.insert_conflict_resolution_strategy;
   }
 
-  /// Check if the given user (if any) can access a record given the request and the operation.
-  pub async fn check_record_level_access(
+  /// Checks table-level access and returns the access query and its params for the given record
+  /// and request, if an access rule is configured. This allows evaluating the access rule as part
+  /// of a transaction, e.g. to observe preceding writes.
+  pub(crate) fn record_level_access_query(
     &self,
     p: Permission,
     record_id: Option<&Value>,
     request_params: Option<&mut LazyParams<'_, RecordApi>>,
     user: Option<&User>,
-  ) -> Result<(), RecordError> {
+  ) -> Result<Option<(Arc<str>, NamedParams)>, RecordError> {
     // First check table level access and if present check row-level access based on access rule.
     self.check_table_level_access(p, user)?;
 
     let Some(access_query) = self.state.cached_access_query(p) else {
-      return Ok(());
+      return Ok(None);
     };
 
     let params = self.build_named_params(p, record_id, request_params, user)?;
+    return Ok(Some((access_query, params)));
+  }
+
+  /// Check if the given user (if any) can access a record given the request and the operation.
+  pub async fn check_record_level_access(
+    &self,
+    p: Permission,
+    record_id: Option<&Value>,
+    request_params: Option<&mut LazyParams<'_, RecordApi>>,
+    user: Option<&User>,
+  ) -> Result<(), RecordError> {
+    let Some((access_query, params)) =
+      self.record_level_access_query(p, record_id, request_params, user)?
+    else {
+      return Ok(());
+    };
 
     // NOTE: Avoid slushing between sqlite threads with regard to an allowed follow-on action.
     let allowed_result = match p {
@@ -537,6 +993,13 @@ impl RecordApi {
     p: Permission,
     user: Option<&User>,
   ) -> Result<(), RecordError> {
+    // API keys can only ever restrict the access of their user.
+    if let Some(scopes) = user.and_then(|u| u.api_key_scopes.as_ref()) {
+      if !scopes.iter().any(|s| s.grants(self.api_name(), p)) {
+        return Err(RecordError::Forbidden);
+      }
+    }
+
     if (user.is_some() && self.has_access(Entity::Authenticated, p))
       || self.has_access(Entity::World, p)
     {
@@ -546,6 +1009,15 @@ impl RecordApi {
     return Err(RecordError::Forbidden);
   }
 
+  /// Whether the permission is granted on the table level to (anonymous, authenticated) users.
+  #[inline]
+  pub(crate) fn table_level_acl(&self, p: Permission) -> (bool, bool) {
+    return (
+      self.has_access(Entity::World, p),
+      self.has_access(Entity::Authenticated, p),
+    );
+  }
+
   #[inline]
   fn has_access(&self, e: Entity, p: Permission) -> bool {
     return (self.state.acl[e as usize] & (p as u8)) > 0;
@@ -573,7 +1045,7 @@ impl RecordApi {
         let request_params = request_params
           .ok_or_else(|| RecordError::Internal("missing req params".into()))?
           .params()
-          .map_err(|err| RecordError::Internal(err.into()))?;
+          .map_err(RecordError::from)?;
 
         // NOTE: We cannot have access queries access missing _REQ_.props. So we need to inject an
         // explicit NULL value for all missing fields on the request. Can we make this cheaper,
@@ -616,12 +1088,26 @@ impl RecordApi {
       Cow::Borrowed(":__record_id"),
       record_id.map_or(Value::Null, |id| id.clone()),
     ));
+    push_request_context_params(&mut params);
 
     return Ok(params);
   }
 }
 
-pub(crate) fn validate_rule(rule: &str) -> Result<(), String> {
+/// Tables and columns an access rule may reference, used to validate rules against the schema.
+pub(crate) struct RuleScope<'a> {
+  pub permission: Permission,
+  pub schemas: &'a SchemaMetadataCache,
+  /// Columns of the API's table or view, i.e. of `_ROW_` and `_REQ_`.
+  pub columns: &'a [Column],
+}
+
+/// Validates an access rule, i.e. that it is a single SQL expression.
+///
+/// Given a `scope`, additionally validates references to `_ROW_`, `_REQ_`, `_USER_` and `_CTX_`
+/// columns, to tables and views in sub-queries and to columns of said tables. References that
+/// cannot be resolved statically, e.g. unqualified column names, are left to SQLite.
+pub(crate) fn validate_rule(rule: &str, scope: Option<&RuleScope<'_>>) -> Result<(), String> {
   let stmt = sqlite3_parse_into_statement(&format!("SELECT {rule}"))
     .map_err(|err| format!("'{rule}' not a valid SQL expression: {err}"))?;
 
@@ -641,41 +1127,318 @@ pub(crate) fn validate_rule(rule: &str) -> Result<(), String> {
     return Err("Expected expr".to_string());
   };
 
-  validate_expr_recursively(&expr)?;
+  RuleValidator {
+    scope,
+    sources: vec![],
+  }
+  .validate_expr(&expr)
+  .map_err(|err| format!("Invalid rule '{rule}': {err}"))?;
 
   return Ok(());
 }
 
-fn validate_expr_recursively(expr: &sqlite3_parser::ast::Expr) -> Result<(), String> {
-  use sqlite3_parser::ast;
+struct RuleValidator<'a> {
+  scope: Option<&'a RuleScope<'a>>,
+  /// Tables and views of enclosing sub-queries by alias and their columns, if known.
+  sources: Vec<(String, Option<Vec<String>>)>,
+}
+
+impl RuleValidator<'_> {
+  fn validate_expr(&mut self, expr: &sqlite3_parser::ast::Expr) -> Result<(), String> {
+    use sqlite3_parser::ast::Expr;
+
+    match expr {
+      Expr::Qualified(qualifier, name) => {
+        self.validate_column_reference(&unquote(&qualifier.0), &unquote(&name.0))?;
+      }
+      Expr::Binary(lhs, _op, rhs) => {
+        self.validate_expr(lhs)?;
+        self.validate_expr(rhs)?;
+      }
+      Expr::Unary(_op, inner)
+      | Expr::IsNull(inner)
+      | Expr::NotNull(inner)
+      | Expr::Collate(inner, _)
+      | Expr::Cast { expr: inner, .. } => {
+        self.validate_expr(inner)?;
+      }
+      Expr::Between {
+        lhs, start, end, ..
+      } => {
+        self.validate_expr(lhs)?;
+        self.validate_expr(start)?;
+        self.validate_expr(end)?;
+      }
+      Expr::Like {
+        lhs, rhs, escape, ..
+      } => {
+        self.validate_expr(lhs)?;
+        self.validate_expr(rhs)?;
+        if let Some(escape) = escape {
+          self.validate_expr(escape)?;
+        }
+      }
+      Expr::Case {
+        base,
+        when_then_pairs,
+        else_expr,
+      } => {
+        if let Some(base) = base {
+          self.validate_expr(base)?;
+        }
+        for (when, then) in when_then_pairs {
+          self.validate_expr(when)?;
+          self.validate_expr(then)?;
+        }
+        if let Some(else_expr) = else_expr {
+          self.validate_expr(else_expr)?;
+        }
+      }
+      Expr::Parenthesized(exprs) => {
+        for expr in exprs {
+          self.validate_expr(expr)?;
+        }
+      }
+      Expr::FunctionCall { name, args, .. } => {
+        if name.0.eq_ignore_ascii_case("has_role") {
+          let [Expr::Literal(sqlite3_parser::ast::Literal::String(_))] =
+            args.as_deref().unwrap_or_default()
+          else {
+            return Err("has_role() expects a single literal string".to_string());
+          };
+        }
+
+        for arg in args.iter().flatten() {
+          self.validate_expr(arg)?;
+        }
+      }
+      Expr::InList { lhs, rhs, .. } => {
+        self.validate_expr(lhs)?;
+        for expr in rhs.iter().flatten() {
+          self.validate_expr(expr)?;
+        }
+      }
+      Expr::InSelect { lhs, rhs, .. } => {
+        self.validate_expr(lhs)?;
+        self.validate_select(rhs)?;
+      }
+      Expr::Exists(select) | Expr::Subquery(select) => {
+        self.validate_select(select)?;
+      }
+      Expr::InTable { lhs, rhs, .. } => {
+        if rhs.name.0 == "_REQ_FIELDS_" {
+          if !matches!(
+            **lhs,
+            Expr::Literal(sqlite3_parser::ast::Literal::String(_))
+          ) {
+            return Err(format!("Expected literal string: {lhs:?}"));
+          }
+          self.check_available("_REQ_FIELDS_")?;
+        }
+
+        self.validate_expr(lhs)?;
+      }
+      _ => {}
+    };
+
+    return Ok(());
+  }
+
+  fn validate_select(&mut self, select: &sqlite3_parser::ast::Select) -> Result<(), String> {
+    use sqlite3_parser::ast::{JoinConstraint, OneSelect, ResultColumn};
 
-  match &expr {
-    ast::Expr::Binary(lhs, _op, rhs) => {
-      validate_expr_recursively(lhs)?;
-      validate_expr_recursively(rhs)?;
+    let OneSelect::Select {
+      columns,
+      from,
+      where_clause,
+      ..
+    } = &select.body.select
+    else {
+      return Ok(());
+    };
+
+    // Tables of common table expressions or compound selects aren't resolved.
+    let resolve_tables = select.with.is_none() && select.body.compounds.is_none();
+
+    let depth = self.sources.len();
+    if let Some(from) = from {
+      if let Some(ref table) = from.select {
+        self.push_source(table, resolve_tables)?;
+      }
+      for join in from.joins.iter().flatten() {
+        self.push_source(&join.table, resolve_tables)?;
+      }
+      for join in from.joins.iter().flatten() {
+        if let Some(JoinConstraint::On(expr)) = &join.constraint {
+          self.validate_expr(expr)?;
+        }
+      }
     }
-    ast::Expr::IsNull(inner) => {
-      validate_expr_recursively(inner)?;
+
+    for column in columns {
+      if let ResultColumn::Expr(expr, _) = column {
+        self.validate_expr(expr)?;
+      }
     }
-    ast::Expr::InTable { lhs, rhs, .. } => {
-      match rhs {
-        ast::QualifiedName {
-          name: ast::Name(name),
-          ..
-        } if name == "_REQ_FIELDS_" => {
-          if !matches!(**lhs, ast::Expr::Literal(ast::Literal::String(_))) {
-            return Err(format!("Expected literal string: {lhs:?}"));
+    if let Some(where_clause) = where_clause {
+      self.validate_expr(where_clause)?;
+    }
+
+    self.sources.truncate(depth);
+    return Ok(());
+  }
+
+  fn push_source(
+    &mut self,
+    table: &sqlite3_parser::ast::SelectTable,
+    resolve: bool,
+  ) -> Result<(), String> {
+    use sqlite3_parser::ast::{As, SelectTable};
+
+    match table {
+      SelectTable::Table(name, alias, _indexed) => {
+        let table_name = unquote(&name.name.0);
+        let columns = match self.scope {
+          Some(scope) if resolve && table_name != "_REQ_FIELDS_" => {
+            let Some(columns) = lookup_columns(scope.schemas, &table_name) else {
+              return Err(format!("Unknown table or view: {table_name}"));
+            };
+            Some(columns)
           }
+          _ => None,
+        };
+
+        let alias = match alias {
+          Some(As::As(alias)) | Some(As::Elided(alias)) => unquote(&alias.0),
+          None => table_name,
+        };
+        self.sources.push((alias, columns));
+      }
+      SelectTable::Select(select, _alias) => {
+        self.validate_select(select)?;
+      }
+      // Table-valued functions, e.g. `json_each(...)`, and nested joins aren't resolved.
+      _ => {}
+    };
+
+    return Ok(());
+  }
+
+  fn validate_column_reference(&self, qualifier: &str, column: &str) -> Result<(), String> {
+    match qualifier {
+      "_USER_" => {
+        if column != "id" {
+          return Err(format!("Unknown column: _USER_.{column}"));
         }
-        _ => {}
-      };
+      }
+      "_CTX_" => {
+        if !CONTEXT_COLUMNS.contains(&column) {
+          return Err(format!("Unknown column: _CTX_.{column}"));
+        }
+      }
+      "_ROW_" | "_REQ_" => {
+        self.check_available(qualifier)?;
+
+        if let Some(scope) = self.scope {
+          if !scope
+            .columns
+            .iter()
+            .any(|c| c.name.eq_ignore_ascii_case(column))
+          {
+            return Err(format!("Unknown column: {qualifier}.{column}"));
+          }
+        }
+      }
+      _ => {
+        // Innermost sources shadow outer ones.
+        let source = self
+          .sources
+          .iter()
+          .rev()
+          .find(|(alias, _)| alias.eq_ignore_ascii_case(qualifier));
+
+        if let Some((_, Some(columns))) = source {
+          if !columns.iter().any(|c| c.eq_ignore_ascii_case(column)) {
+            return Err(format!("Unknown column: {qualifier}.{column}"));
+          }
+        }
+      }
+    };
+
+    return Ok(());
+  }
 
-      validate_expr_recursively(lhs)?;
+  /// Checks that the given magic table is available for the scope's permission, e.g. there's no
+  /// `_ROW_` yet for record creation.
+  fn check_available(&self, name: &str) -> Result<(), String> {
+    let Some(scope) = self.scope else {
+      return Ok(());
+    };
+
+    let available = match name {
+      "_ROW_" => scope.permission != Permission::Create,
+      "_REQ_" | "_REQ_FIELDS_" => {
+        matches!(scope.permission, Permission::Create | Permission::Update)
+      }
+      _ => true,
+    };
+
+    if !available {
+      return Err(format!(
+        "{name} is not available in {:?} rules",
+        scope.permission
+      ));
     }
-    _ => {}
+    return Ok(());
   }
+}
 
-  return Ok(());
+/// Expands built-in helpers of access rules into plain SQL, e.g. `has_role('editor')` into a
+/// lookup of the authenticated user's roles.
+pub(crate) fn expand_rule(rule: &str) -> String {
+  lazy_static! {
+    static ref HAS_ROLE_RE: Regex =
+      Regex::new(r"(?i)\bhas_role\(\s*('(?:[^']|'')*')\s*\)").expect("valid");
+  }
+
+  return HAS_ROLE_RE
+    .replace_all(
+      rule,
+      format!(
+        "EXISTS(SELECT 1 FROM {USER_ROLE_TABLE} WHERE {USER_ROLE_TABLE}.user = _USER_.id AND {USER_ROLE_TABLE}.role = $1)"
+      ),
+    )
+    .into_owned();
+}
+
+fn lookup_columns(schemas: &SchemaMetadataCache, name: &str) -> Option<Vec<String>> {
+  if let Some(table) = schemas.get_table(name) {
+    return Some(
+      table
+        .schema
+        .columns
+        .iter()
+        .map(|c| c.name.clone())
+        .collect(),
+    );
+  }
+  if let Some(view) = schemas.get_view(name) {
+    return Some(
+      view
+        .columns()
+        .map(|columns| columns.iter().map(|c| c.name.clone()).collect())
+        .unwrap_or_default(),
+    );
+  }
+  return None;
+}
+
+fn unquote(name: &str) -> String {
+  let bytes = name.as_bytes();
+  if bytes.len() >= 2 && matches!(bytes[0], b'"' | b'`' | b'[' | b'\'') {
+    return name[1..name.len() - 1].to_string();
+  }
+  return name.to_string();
 }
 
 #[derive(Template)]
@@ -689,12 +1452,65 @@ struct SubscriptionRecordReadTemplate<'a> {
   column_names: Vec<&'a str>,
 }
 
+/// Converts a single primary key value, i.e. integer or base64/text-encoded UUID, into SQL.
+fn pk_value_to_sql(column: &Column, id: &str) -> Result<Value, RecordError> {
+  return match column.data_type {
+    ColumnDataType::Blob => {
+      // Special handling for text encoded UUIDs. Right now we're guessing based on length, it
+      // would be more explicit rely on CHECK(...) column options.
+      if id.len() == 36 {
+        if let Ok(id) = uuid::Uuid::parse_str(id) {
+          return Ok(Value::Blob(id.into()));
+        }
+      }
+
+      let record_id = b64_to_id(id).map_err(|_err| RecordError::BadRequest("Invalid id"))?;
+      Ok(Value::Blob(record_id.into()))
+    }
+    ColumnDataType::Integer => Ok(Value::Integer(
+      id.parse::<i64>()
+        .map_err(|_err| RecordError::BadRequest("Invalid id"))?,
+    )),
+    _ => Err(RecordError::BadRequest("Invalid id")),
+  };
+}
+
+/// Builds the condition matching a record by its primary key. Composite primary keys are bound as
+/// JSON array, see [`RecordApi::id_to_sql`].
+fn record_id_clause(
+  pk_columns: &[(usize, Column)],
+  qualifier: Option<&str>,
+  placeholder: &str,
+) -> String {
+  let column = |name: &str| match qualifier {
+    Some(qualifier) => format!(r#"{qualifier}."{name}""#),
+    None => format!(r#""{name}""#),
+  };
+
+  if let [(_index, pk_column)] = pk_columns {
+    return format!("{} = {placeholder}", column(&pk_column.name));
+  }
+
+  return pk_columns
+    .iter()
+    .enumerate()
+    .map(|(i, (_index, pk_column))| {
+      let part = format!("json_extract({placeholder}, '$[{i}]')");
+      return match pk_column.data_type {
+        ColumnDataType::Blob => format!("{} = uuid_parse({part})", column(&pk_column.name)),
+        _ => format!("{} = {part}", column(&pk_column.name)),
+      };
+    })
+    .collect::<Vec<_>>()
+    .join(" AND ");
+}
+
 /// Build access query for record reads, deletes and query access.
 ///
 /// Assumes access_rule is an expression: https://www.sqlite.org/syntax/expr.html
 fn build_read_delete_schema_query(
   table_name: &str,
-  pk_column_name: &str,
+  record_id_clause: &str,
   access_rule: &str,
 ) -> Arc<str> {
   return indoc::formatdoc!(
@@ -703,7 +1519,8 @@ fn build_read_delete_schema_query(
         CAST(({access_rule}) AS INTEGER)
       FROM
         (SELECT :__user_id AS id) AS _USER_,
-        (SELECT * FROM "{table_name}" WHERE "{pk_column_name}" = :__record_id) AS _ROW_
+        (SELECT :__ctx_method AS method, :__ctx_ip AS ip, :__ctx_headers AS headers) AS _CTX_,
+        (SELECT * FROM "{table_name}" WHERE {record_id_clause}) AS _ROW_
     "#
   )
   .into();
@@ -947,15 +1764,284 @@ mod tests {
 
   #[test]
   fn test_validate_rule() {
-    assert!(validate_rule("").is_err());
-    assert!(validate_rule("1, 1").is_err());
-    assert!(validate_rule("1").is_ok());
+    assert!(validate_rule("", None).is_err());
+    assert!(validate_rule("1, 1", None).is_err());
+    assert!(validate_rule("1", None).is_ok());
+
+    validate_rule("_USER_.id IS NOT NULL", None).unwrap();
+    validate_rule("_USER_.id IS NOT NULL AND _ROW_.userid = _USER_.id", None).unwrap();
+    validate_rule("_USER_.id IS NOT NULL AND _REQ_.field IS NOT NULL", None).unwrap();
+    validate_rule("_CTX_.method = 'GET' AND _CTX_.ip IS NOT NULL", None).unwrap();
+
+    assert!(validate_rule("'field' IN _REQ_FIELDS_", None).is_ok());
+    assert!(validate_rule("field IN _REQ_FIELDS_", None).is_err());
+    assert!(validate_rule("NOT ('a' || 'b' IN _REQ_FIELDS_)", None).is_err());
+
+    assert!(validate_rule("_USER_.name IS NOT NULL", None).is_err());
+    assert!(validate_rule("_CTX_.path = '/'", None).is_err());
+
+    validate_rule("has_role('editor') OR _ROW_.owner = _USER_.id", None).unwrap();
+    assert!(validate_rule("has_role()", None).is_err());
+    assert!(validate_rule("has_role(_ROW_.role)", None).is_err());
+    assert!(validate_rule("has_role('a', 'b')", None).is_err());
+  }
+
+  #[test]
+  fn test_expand_rule() {
+    assert_eq!(
+      expand_rule("_USER_.id IS NOT NULL"),
+      "_USER_.id IS NOT NULL"
+    );
+    assert_eq!(
+      expand_rule("has_role('editor') OR HAS_ROLE( 'it''s' )"),
+      "EXISTS(SELECT 1 FROM _user_role WHERE _user_role.user = _USER_.id AND _user_role.role = 'editor') OR EXISTS(SELECT 1 FROM _user_role WHERE _user_role.user = _USER_.id AND _user_role.role = 'it''s')"
+    );
+    // Other functions sharing the suffix are left untouched.
+    assert_eq!(expand_rule("my_has_role('x')"), "my_has_role('x')");
+  }
+
+  #[tokio::test]
+  async fn test_validate_rule_with_scope() {
+    let state = crate::app_state::test_state(None).await.unwrap();
+    state
+      .conn()
+      .execute_batch(
+        r#"
+          CREATE TABLE room (id INTEGER PRIMARY KEY, owner BLOB, tenant TEXT) STRICT;
+          CREATE TABLE member (room INTEGER, user BLOB, role TEXT) STRICT;
+        "#,
+      )
+      .await
+      .unwrap();
+    state.schema_metadata().invalidate_all().await.unwrap();
+
+    let table = state.schema_metadata().get_table("room").unwrap();
+    let validate = |permission: Permission, rule: &str| {
+      return validate_rule(
+        rule,
+        Some(&RuleScope {
+          permission,
+          schemas: state.schema_metadata(),
+          columns: &table.schema.columns,
+        }),
+      );
+    };
+
+    // Column references are checked against the table.
+    validate(Permission::Read, "_ROW_.owner = _USER_.id").unwrap();
+    validate(Permission::Read, r#"_ROW_."tenant" = 'acme'"#).unwrap();
+    assert!(validate(Permission::Read, "_ROW_.missing = _USER_.id").is_err());
+    assert!(validate(Permission::Create, "_REQ_.missing IS NULL").is_err());
+
+    // `_ROW_` and `_REQ_` are only available where they're defined.
+    validate(Permission::Create, "_REQ_.owner = _USER_.id").unwrap();
+    validate(Permission::Update, "_REQ_.owner = _ROW_.owner").unwrap();
+    assert!(validate(Permission::Create, "_ROW_.owner = _USER_.id").is_err());
+    assert!(validate(Permission::Read, "_REQ_.owner = _USER_.id").is_err());
+    assert!(validate(Permission::Delete, "'owner' IN _REQ_FIELDS_").is_err());
+
+    // Sub-queries, e.g. membership checks, are resolved against the schema.
+    validate(
+      Permission::Read,
+      "EXISTS(SELECT 1 FROM member AS m WHERE m.room = _ROW_.id AND m.user = _USER_.id)",
+    )
+    .unwrap();
+    validate(
+      Permission::Update,
+      "(SELECT role FROM member WHERE member.room = _ROW_.id AND member.user = _USER_.id) = 'admin'",
+    )
+    .unwrap();
+    validate(
+      Permission::Read,
+      "_ROW_.id IN (SELECT room FROM member WHERE user = _USER_.id)",
+    )
+    .unwrap();
+    assert!(
+      validate(
+        Permission::Read,
+        "EXISTS(SELECT 1 FROM members WHERE room = _ROW_.id)"
+      )
+      .is_err()
+    );
+    assert!(
+      validate(
+        Permission::Read,
+        "EXISTS(SELECT 1 FROM member AS m WHERE m.rooms = _ROW_.id)"
+      )
+      .is_err()
+    );
+
+    // Request context and custom functions.
+    validate(
+      Permission::Read,
+      r#"json_extract(_CTX_.headers, '$."x-tenant"') = _ROW_.tenant AND is_uuid(_USER_.id)"#,
+    )
+    .unwrap();
+    assert!(validate(Permission::Read, "_CTX_.header IS NULL").is_err());
+  }
 
-    validate_rule("_USER_.id IS NOT NULL").unwrap();
-    validate_rule("_USER_.id IS NOT NULL AND _ROW_.userid = _USER_.id").unwrap();
-    validate_rule("_USER_.id IS NOT NULL AND _REQ_.field IS NOT NULL").unwrap();
+  #[tokio::test]
+  async fn test_request_context_access_rule() {
+    use axum::http::{HeaderMap, Method};
+
+    use crate::config::proto::RecordApiConfig;
+    use crate::records::request_context::RequestContext;
+    use crate::records::test_utils::add_record_api_config;
+
+    let state = crate::app_state::test_state(None).await.unwrap();
+    state
+      .conn()
+      .execute_batch(
+        r#"
+          CREATE TABLE doc (id INTEGER PRIMARY KEY, tenant TEXT) STRICT;
+          INSERT INTO doc (id, tenant) VALUES (1, 'acme');
+        "#,
+      )
+      .await
+      .unwrap();
+    state.schema_metadata().invalidate_all().await.unwrap();
+
+    add_record_api_config(
+      &state,
+      RecordApiConfig {
+        name: Some("docs".to_string()),
+        table_name: Some("doc".to_string()),
+        acl_world: [PermissionFlag::Read as i32].into(),
+        read_access_rule: Some(
+          r#"_CTX_.method = 'GET' AND json_extract(_CTX_.headers, '$."x-tenant"') = _ROW_.tenant"#
+            .to_string(),
+        ),
+        require_sortable_primary_key: Some(false),
+        cache_ttl_sec: Some(60),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+    let api = state.lookup_record_api("docs").unwrap();
+    // Responses depend on the request context, which cache entries aren't keyed by.
+    assert_eq!(api.cache_ttl(), None);
+
+    let check = async |method: Method, tenant: Option<&str>| {
+      let mut headers = HeaderMap::new();
+      if let Some(tenant) = tenant {
+        headers.insert("x-tenant", tenant.parse().unwrap());
+      }
+
+      return RequestContext::new(&method, None, &headers)
+        .scope(api.check_record_level_access(
+          Permission::Read,
+          Some(&Value::Integer(1)),
+          None,
+          None,
+        ))
+        .await;
+    };
+
+    assert!(check(Method::GET, Some("acme")).await.is_ok());
+    assert!(check(Method::GET, Some("other")).await.is_err());
+    assert!(check(Method::GET, None).await.is_err());
+    assert!(check(Method::HEAD, Some("acme")).await.is_err());
+
+    // Outside of requests, the context is NULL.
+    assert!(
+      api
+        .check_record_level_access(Permission::Read, Some(&Value::Integer(1)), None, None)
+        .await
+        .is_err()
+    );
+
+    let list = async |tenant: &str| {
+      let mut headers = HeaderMap::new();
+      headers.insert("x-tenant", tenant.parse().unwrap());
+
+      let client = crate::records::RecordsClient::new(&state);
+      return RequestContext::new(&Method::GET, None, &headers)
+        .scope(client.list("docs", None))
+        .await
+        .unwrap()
+        .records
+        .len();
+    };
+    assert_eq!(list("acme").await, 1);
+    assert_eq!(list("other").await, 0);
+    assert_eq!(list("acme").await, 1);
+  }
 
-    assert!(validate_rule("'field' IN _REQ_FIELDS_").is_ok());
-    assert!(validate_rule("field IN _REQ_FIELDS_").is_err());
+  #[tokio::test]
+  async fn test_has_role_access_rule() {
+    use crate::admin::user::create_user_for_test;
+    use crate::config::proto::RecordApiConfig;
+    use crate::records::test_utils::add_record_api_config;
+
+    let state = crate::app_state::test_state(None).await.unwrap();
+    state
+      .conn()
+      .execute_batch(
+        r#"
+          CREATE TABLE doc (id INTEGER PRIMARY KEY, body TEXT) STRICT;
+          INSERT INTO doc (id, body) VALUES (1, 'draft');
+          INSERT INTO _role (name) VALUES ('editor');
+        "#,
+      )
+      .await
+      .unwrap();
+    state.schema_metadata().invalidate_all().await.unwrap();
+
+    add_record_api_config(
+      &state,
+      RecordApiConfig {
+        name: Some("docs".to_string()),
+        table_name: Some("doc".to_string()),
+        acl_authenticated: [PermissionFlag::Read as i32].into(),
+        read_access_rule: Some("has_role('editor')".to_string()),
+        require_sortable_primary_key: Some(false),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+    let api = state.lookup_record_api("docs").unwrap();
+
+    let editor_id = create_user_for_test(&state, "editor@bar.org", "Secret!1!!")
+      .await
+      .unwrap();
+    let other_id = create_user_for_test(&state, "other@bar.org", "Secret!1!!")
+      .await
+      .unwrap();
+    state
+      .conn()
+      .execute(
+        "INSERT INTO _user_role (user, role) VALUES ($1, 'editor')",
+        trailbase_sqlite::params!(editor_id.into_bytes()),
+      )
+      .await
+      .unwrap();
+
+    let editor = User::from_unverified(editor_id, "editor@bar.org");
+    let other = User::from_unverified(other_id, "other@bar.org");
+
+    assert!(
+      api
+        .check_record_level_access(
+          Permission::Read,
+          Some(&Value::Integer(1)),
+          None,
+          Some(&editor)
+        )
+        .await
+        .is_ok()
+    );
+    assert!(
+      api
+        .check_record_level_access(
+          Permission::Read,
+          Some(&Value::Integer(1)),
+          None,
+          Some(&other)
+        )
+        .await
+        .is_err()
+    );
   }
 }
@@ -0,0 +1,122 @@
+use axum::extract::Request;
+use axum::http::{HeaderMap, Method};
+use axum::middleware::Next;
+use axum::response::Response;
+use std::borrow::Cow;
+use std::future::Future;
+use std::sync::Arc;
+use trailbase_sqlite::{NamedParams, Value};
+
+use crate::client_ip::secure_client_ip;
+use crate::constants::HEADER_API_KEY;
+
+/// Columns of `_CTX_` accessible to access rules.
+pub(crate) const CONTEXT_COLUMNS: [&str; 3] = ["method", "ip", "headers"];
+
+/// Headers carrying credentials, which are never exposed to access rules.
+const REDACTED_HEADERS: [&str; 4] = ["authorization", "cookie", "refresh-token", HEADER_API_KEY];
+
+tokio::task_local! {
+  static REQUEST_CONTEXT: Arc<RequestContext>;
+}
+
+/// Metadata of the current request exposed to access rules as `_CTX_`, e.g.
+/// `_CTX_.method = 'GET'` or `json_extract(_CTX_.headers, '$."x-tenant"') = 'acme'`.
+///
+/// Absent, i.e. NULL, outside of HTTP requests, e.g. for realtime subscription events.
+#[derive(Debug)]
+pub(crate) struct RequestContext {
+  method: String,
+  ip: Option<String>,
+  /// JSON object of lower-case header names to values.
+  headers: String,
+}
+
+impl RequestContext {
+  pub(crate) fn new(method: &Method, ip: Option<String>, headers: &HeaderMap) -> Self {
+    let headers: serde_json::Map<String, serde_json::Value> = headers
+      .iter()
+      .filter(|(name, _)| {
+        return !REDACTED_HEADERS
+          .iter()
+          .any(|redacted| name.as_str().eq_ignore_ascii_case(redacted));
+      })
+      .filter_map(|(name, value)| {
+        return Some((name.to_string(), value.to_str().ok()?.into()));
+      })
+      .collect();
+
+    return Self {
+      method: method.to_string(),
+      ip,
+      headers: serde_json::Value::Object(headers).to_string(),
+    };
+  }
+
+  /// Runs `f` with `self` being the current request's context.
+  pub(crate) async fn scope<F: Future>(self, f: F) -> F::Output {
+    return REQUEST_CONTEXT.scope(Arc::new(self), f).await;
+  }
+}
+
+pub(crate) async fn request_context_middleware(request: Request, next: Next) -> Response {
+  // NOTE: Unlike for logging, the IP must not be spoofable via forwarding headers, since access
+  // rules may depend on it.
+  let ip = secure_client_ip(request.headers(), request.extensions()).map(|ip| ip.to_string());
+  let context = RequestContext::new(request.method(), ip, request.headers());
+
+  return context.scope(next.run(request)).await;
+}
+
+/// Binds the current request's context, if any, to the `_CTX_` placeholders of access queries.
+pub(crate) fn push_request_context_params(params: &mut NamedParams) {
+  let context = REQUEST_CONTEXT.try_with(|context| context.clone()).ok();
+
+  let (method, ip, headers) = match context {
+    Some(context) => (
+      Value::Text(context.method.clone()),
+      context.ip.clone().map_or(Value::Null, Value::Text),
+      Value::Text(context.headers.clone()),
+    ),
+    None => (Value::Null, Value::Null, Value::Null),
+  };
+
+  params.push((Cow::Borrowed(":__ctx_method"), method));
+  params.push((Cow::Borrowed(":__ctx_ip"), ip));
+  params.push((Cow::Borrowed(":__ctx_headers"), headers));
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[tokio::test]
+  async fn test_request_context_params() {
+    let mut params = NamedParams::new();
+    push_request_context_params(&mut params);
+    assert!(params.iter().all(|(_, value)| *value == Value::Null));
+
+    let mut headers = HeaderMap::new();
+    headers.insert("x-tenant", "acme".parse().unwrap());
+    headers.insert("authorization", "Bearer secret".parse().unwrap());
+    headers.insert(HEADER_API_KEY, "secret".parse().unwrap());
+
+    let context = RequestContext::new(&Method::POST, Some("127.0.0.1".to_string()), &headers);
+    let params = context
+      .scope(async {
+        let mut params = NamedParams::new();
+        push_request_context_params(&mut params);
+        return params;
+      })
+      .await;
+
+    assert_eq!(params[0].1, Value::Text("POST".to_string()));
+    assert_eq!(params[1].1, Value::Text("127.0.0.1".to_string()));
+
+    let Value::Text(ref headers) = params[2].1 else {
+      panic!("Expected headers: {params:?}");
+    };
+    let headers: serde_json::Value = serde_json::from_str(headers).unwrap();
+    assert_eq!(headers, serde_json::json!({"x-tenant": "acme"}));
+  }
+}
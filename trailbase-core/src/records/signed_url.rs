@@ -0,0 +1,320 @@
+use axum::Json;
+use axum::extract::{Path, Query, State};
+use base64::prelude::*;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use trailbase_schema::FileUpload;
+use ts_rs::TS;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::app_state::AppState;
+use crate::auth::user::User;
+use crate::constants::RECORD_API_PATH;
+use crate::records::image_transform::FileQuery;
+use crate::records::query_builder::{GetFileQueryBuilder, GetFilesQueryBuilder};
+use crate::records::{Permission, RecordError};
+use crate::schema_metadata::JsonColumnMetadata;
+
+/// Version of the signed payload. Bumping it invalidates all outstanding URLs.
+const SIGNATURE_VERSION: &str = "v1";
+const SECRET_PURPOSE: &str = "trailbase signed file url";
+const DEFAULT_TTL_SEC: i64 = 60 * 60;
+
+#[derive(Debug, Default, Deserialize, IntoParams)]
+pub struct SignedFileUrlQuery {
+  /// Index of the file for `std.FileUploads` columns.
+  pub index: Option<usize>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema, TS)]
+#[ts(export)]
+pub struct SignedFileUrlResponse {
+  /// Absolute download URL, which doesn't require any further authentication.
+  pub url: String,
+  /// UNIX timestamp in seconds after which the URL is no longer valid.
+  pub expires: i64,
+}
+
+/// Issue a time-limited, signed download URL for a file associated with a record.
+///
+/// Requires read access to the record. The URL remains valid until it expires, the file is
+/// replaced or the signing key is rotated.
+#[utoipa::path(
+  get,
+  path = "/:name/:record/signed_url/:column_name",
+  params(SignedFileUrlQuery),
+  responses(
+    (status = 200, description = "Signed download URL.", body = SignedFileUrlResponse)
+  )
+)]
+pub async fn signed_file_url_handler(
+  State(state): State<AppState>,
+  Path((api_name, record, column_name)): Path<(String, String, String)>,
+  Query(query): Query<SignedFileUrlQuery>,
+  user: Option<User>,
+) -> Result<Json<SignedFileUrlResponse>, RecordError> {
+  let Some(api) = state.lookup_record_api(&api_name) else {
+    return Err(RecordError::ApiNotFound);
+  };
+
+  let record_id = api.id_to_sql(&record)?;
+
+  let Ok(()) = api
+    .check_record_level_access(Permission::Read, Some(&record_id), None, user.as_ref())
+    .await
+  else {
+    return Err(RecordError::Forbidden);
+  };
+
+  let Some(index) = api.column_index_by_name(&column_name) else {
+    return Err(RecordError::BadRequest("Invalid field/column name"));
+  };
+
+  let column = &api.columns()[index];
+  let Some(ref column_json_metadata) = api.json_column_metadata()[index] else {
+    return Err(RecordError::BadRequest("Invalid column"));
+  };
+
+  let record_id_clause = api.record_id_clause(None, "$1");
+  let (file_upload, path) = match column_json_metadata {
+    JsonColumnMetadata::SchemaName(name, _) if name == "std.FileUpload" => {
+      if query.index.is_some() {
+        return Err(RecordError::BadRequest(
+          "Index requires a std.FileUploads column",
+        ));
+      }
+
+      let file_upload = GetFileQueryBuilder::run(
+        &state,
+        api.table_name(),
+        column,
+        column_json_metadata,
+        &record_id_clause,
+        record_id,
+      )
+      .await
+      .map_err(|err| RecordError::Internal(err.into()))?;
+
+      (file_upload, vec!["file".to_string(), column_name.clone()])
+    }
+    JsonColumnMetadata::SchemaName(name, _) if name == "std.FileUploads" => {
+      let Some(file_index) = query.index else {
+        return Err(RecordError::BadRequest(
+          "Missing index for std.FileUploads column",
+        ));
+      };
+
+      let mut file_uploads = GetFilesQueryBuilder::run(
+        &state,
+        api.table_name(),
+        column,
+        column_json_metadata,
+        &record_id_clause,
+        record_id,
+      )
+      .await
+      .map_err(|err| RecordError::Internal(err.into()))?;
+
+      if file_index >= file_uploads.0.len() {
+        return Err(RecordError::RecordNotFound);
+      }
+
+      (
+        file_uploads.0.remove(file_index),
+        vec![
+          "files".to_string(),
+          column_name.clone(),
+          file_index.to_string(),
+        ],
+      )
+    }
+    _ => {
+      return Err(RecordError::BadRequest("Not a file column"));
+    }
+  };
+
+  let ttl_sec = state.access_config(|c| {
+    return c
+      .server
+      .signed_file_urls
+      .as_ref()
+      .and_then(|c| c.ttl_sec)
+      .unwrap_or(DEFAULT_TTL_SEC);
+  });
+  let expires = state.clock().now().timestamp() + ttl_sec;
+  let signature = sign(
+    &state,
+    api.api_name(),
+    &record,
+    &column_name,
+    &file_upload,
+    expires,
+  );
+
+  let mut url = (*state.site_url()).clone();
+  url
+    .path_segments_mut()
+    .map_err(|_| RecordError::Internal("Site URL cannot be a base".into()))?
+    .pop_if_empty()
+    .extend(RECORD_API_PATH.split('/'))
+    .extend([api.api_name(), record.as_str()])
+    .extend(&path);
+  url
+    .query_pairs_mut()
+    .append_pair("expires", &expires.to_string())
+    .append_pair("signature", &signature);
+
+  return Ok(Json(SignedFileUrlResponse {
+    url: url.to_string(),
+    expires,
+  }));
+}
+
+/// Returns the expiry and signature if the file query belongs to a signed URL.
+pub(crate) fn signed_params(query: &FileQuery) -> Result<Option<(i64, &str)>, RecordError> {
+  return match (query.expires, query.signature.as_deref()) {
+    (Some(expires), Some(signature)) => Ok(Some((expires, signature))),
+    (None, None) => Ok(None),
+    _ => Err(RecordError::BadRequest(
+      "Signed URLs require both 'expires' and 'signature'",
+    )),
+  };
+}
+
+/// Verifies a signed URL previously issued by [`signed_file_url_handler`] for the given file.
+pub(crate) fn verify(
+  state: &AppState,
+  api_name: &str,
+  record: &str,
+  column_name: &str,
+  file: &FileUpload,
+  expires: i64,
+  signature: &str,
+) -> Result<(), RecordError> {
+  if expires < state.clock().now().timestamp() {
+    return Err(RecordError::Forbidden);
+  }
+
+  let signature = BASE64_URL_SAFE_NO_PAD
+    .decode(signature)
+    .map_err(|_| RecordError::Forbidden)?;
+
+  let mut mac = hmac(state);
+  mac.update(&payload(api_name, record, column_name, file, expires));
+  return mac
+    .verify_slice(&signature)
+    .map_err(|_| RecordError::Forbidden);
+}
+
+fn sign(
+  state: &AppState,
+  api_name: &str,
+  record: &str,
+  column_name: &str,
+  file: &FileUpload,
+  expires: i64,
+) -> String {
+  let mut mac = hmac(state);
+  mac.update(&payload(api_name, record, column_name, file, expires));
+  return BASE64_URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes());
+}
+
+/// Binds the signature to the file's storage path, thus replacing the file invalidates the URL.
+fn payload(
+  api_name: &str,
+  record: &str,
+  column_name: &str,
+  file: &FileUpload,
+  expires: i64,
+) -> Vec<u8> {
+  return [
+    SIGNATURE_VERSION,
+    api_name,
+    record,
+    column_name,
+    file.path(),
+    &expires.to_string(),
+  ]
+  .join("\0")
+  .into_bytes();
+}
+
+fn hmac(state: &AppState) -> Hmac<Sha256> {
+  let signing_key = state.access_config(|c| {
+    return c
+      .server
+      .signed_file_urls
+      .as_ref()
+      .and_then(|c| c.signing_key.clone());
+  });
+
+  return match signing_key {
+    Some(key) => Hmac::<Sha256>::new_from_slice(key.as_bytes()),
+    None => Hmac::<Sha256>::new_from_slice(&state.jwt().derive_secret(SECRET_PURPOSE)),
+  }
+  .expect("HMAC accepts keys of any size");
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::app_state::test_state;
+  use crate::config::proto::SignedFileUrlConfig;
+
+  #[tokio::test]
+  async fn test_sign_and_verify() {
+    let state = test_state(None).await.unwrap();
+    let file = FileUpload::new(
+      uuid::Uuid::now_v7(),
+      Some("foo.png".to_string()),
+      None,
+      None,
+    );
+    let other = FileUpload::new(uuid::Uuid::now_v7(), None, None, None);
+
+    let expires = state.clock().now().timestamp() + 60;
+    let signature = sign(&state, "api", "rec", "file", &file, expires);
+
+    assert!(verify(&state, "api", "rec", "file", &file, expires, &signature).is_ok());
+
+    // Signatures are bound to the API, record, column, file and expiry.
+    assert!(verify(&state, "other", "rec", "file", &file, expires, &signature).is_err());
+    assert!(verify(&state, "api", "other", "file", &file, expires, &signature).is_err());
+    assert!(verify(&state, "api", "rec", "other", &file, expires, &signature).is_err());
+    assert!(verify(&state, "api", "rec", "file", &other, expires, &signature).is_err());
+    assert!(verify(&state, "api", "rec", "file", &file, expires + 1, &signature).is_err());
+    assert!(verify(&state, "api", "rec", "file", &file, expires, "garbage").is_err());
+
+    // Expired URLs are rejected.
+    let expired = state.clock().now().timestamp() - 1;
+    let signature_expired = sign(&state, "api", "rec", "file", &file, expired);
+    assert!(matches!(
+      verify(
+        &state,
+        "api",
+        "rec",
+        "file",
+        &file,
+        expired,
+        &signature_expired
+      ),
+      Err(RecordError::Forbidden)
+    ));
+
+    // Rotating the signing key revokes outstanding URLs.
+    let mut config = state.get_config();
+    config.server.signed_file_urls = Some(SignedFileUrlConfig {
+      signing_key: Some("rotated".to_string()),
+      ..Default::default()
+    });
+    state
+      .validate_and_update_config(config, None)
+      .await
+      .unwrap();
+
+    assert!(verify(&state, "api", "rec", "file", &file, expires, &signature).is_err());
+    let signature = sign(&state, "api", "rec", "file", &file, expires);
+    assert!(verify(&state, "api", "rec", "file", &file, expires, &signature).is_ok());
+  }
+}
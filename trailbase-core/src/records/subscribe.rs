@@ -1,14 +1,20 @@
 use async_channel::WeakReceiver;
 use axum::{
-  extract::{Path, State},
+  extract::{
+    Path, RawQuery, State,
+    ws::{Message, WebSocket, WebSocketUpgrade},
+  },
   response::sse::{Event, KeepAlive, Sse},
+  response::{IntoResponse, Response},
 };
-use futures_util::Stream;
+use futures_util::{Stream, StreamExt};
 use log::*;
 use parking_lot::RwLock;
 use pin_project_lite::pin_project;
 use rusqlite::hooks::{Action, PreUpdateCase};
+use rusqlite::types::ToSqlOutput;
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::pin::Pin;
 use std::sync::{
@@ -18,10 +24,15 @@ use std::sync::{
 use std::task::{Context, Poll};
 use trailbase_sqlite::connection::{extract_record_values, extract_row_id};
 use trailbase_sqlite::rows::value_to_json;
+use trailbase_sqlite::{NamedParamRef, NamedParams, Params as _};
 
 use crate::AppState;
 use crate::auth::user::User;
+use crate::listing::{
+  QueryParseResult, WhereClause, build_filter_where_clause, parse_and_sanitize_query,
+};
 use crate::records::RecordApi;
+use crate::records::list_records::encrypt_filter_params;
 use crate::records::{Permission, RecordError};
 use crate::schema_metadata::{SchemaMetadataCache, TableMetadata};
 use crate::value_notifier::Computed;
@@ -43,7 +54,7 @@ struct SubscriptionId {
 /// RAII type for automatically cleaning up subscriptions when the receiving side gets dropped,
 /// e.g. client disconnects.
 struct CleanupSubscription {
-  receiver: WeakReceiver<Event>,
+  receiver: WeakReceiver<Arc<DbEvent>>,
   state: AppState,
   id: SubscriptionId,
 }
@@ -67,11 +78,24 @@ impl Drop for CleanupSubscription {
 pin_project! {
   /// Receiver wrapper that knows how to cleanup the corresponding subscription.
   #[must_use = "streams do nothing unless polled"]
-  struct AutoCleanupEventStream {
+  pub(crate) struct AutoCleanupEventStream {
     cleanup: CleanupSubscription,
 
     #[pin]
-    receiver: async_channel::Receiver<Event>,
+    receiver: async_channel::Receiver<Arc<DbEvent>>,
+  }
+}
+
+impl AutoCleanupEventStream {
+  /// Yields the raw events rather than SSE-encoded ones, e.g. for streaming them over WebSockets
+  /// or gRPC.
+  pub(crate) fn into_db_events(self) -> impl Stream<Item = Arc<DbEvent>> + Send + 'static {
+    let Self { cleanup, receiver } = self;
+    return receiver.map(move |ev| {
+      // Keep the subscription alive for as long as the stream.
+      let _cleanup = &cleanup;
+      ev
+    });
   }
 }
 
@@ -81,7 +105,7 @@ impl Stream for AutoCleanupEventStream {
   fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
     let mut this = self.project();
     let res = futures_util::ready!(this.receiver.as_mut().poll_next(cx));
-    Poll::Ready(res.map(Ok))
+    Poll::Ready(res.map(|ev| Event::default().json_data(ev.as_ref())))
   }
 
   fn size_hint(&self) -> (usize, Option<usize>) {
@@ -125,8 +149,134 @@ pub struct Subscription {
   /// Record id present for subscriptions to specific records.
   // record_id: Option<trailbase_sqlite::Value>,
   user: Option<User>,
-  /// Channel for sending events to the SSE handler.
-  sender: async_channel::Sender<Event>,
+  /// Optional predicate changed records have to match to be sent.
+  filter: Option<SubscriptionFilter>,
+  /// Channel for sending events to the SSE, WebSocket or gRPC handler.
+  sender: async_channel::Sender<Arc<DbEvent>>,
+}
+
+/// Server-side filter for subscriptions using the same syntax as listing records, e.g.
+/// `?status=open&priority[gte]=3`. Evaluated against changed records before fanning out to avoid
+/// waking up subscribers for irrelevant changes.
+struct SubscriptionFilter {
+  query: String,
+  params: NamedParams,
+  /// Names of the API's columns, which are bound as `:__col<index>` to build `_ROW_`.
+  column_names: Vec<String>,
+}
+
+impl SubscriptionFilter {
+  fn new(api: &RecordApi, query: &str) -> Result<Option<Self>, RecordError> {
+    let QueryParseResult {
+      limit,
+      cursor,
+      offset,
+      count,
+      expand,
+      order,
+      params: filter_params,
+      filter,
+      select,
+      nearest,
+      k: _,
+      search,
+      collate,
+      format,
+      include_deleted,
+    } = parse_and_sanitize_query(Some(query)).map_err(|_err| {
+      return RecordError::BadRequest("Invalid query");
+    })?;
+
+    if limit.is_some()
+      || cursor.is_some()
+      || offset.is_some()
+      || count.is_some()
+      || expand.is_some()
+      || order.is_some()
+      || select.is_some()
+      || nearest.is_some()
+      || search.is_some()
+      || format.is_some()
+      || include_deleted.is_some()
+    {
+      return Err(RecordError::BadRequest(
+        "Unsupported parameter for subscriptions",
+      ));
+    }
+
+    if filter_params.is_none() && filter.is_none() {
+      return Ok(None);
+    }
+
+    if let Some(ref collate) = collate {
+      if !trailbase_extension::collation::has_collation(collate) {
+        return Err(RecordError::BadRequest("Invalid collation"));
+      }
+    }
+
+    // NOTE: This will also drop any filters for unknown columns, thus avoiding SQL injections.
+    let (filter_params, filter) = encrypt_filter_params(api, filter_params, filter)?;
+    let WhereClause { clause, params } = build_filter_where_clause(
+      "_ROW_",
+      api.columns(),
+      filter_params,
+      filter,
+      collate.as_deref(),
+    )
+    .map_err(|_err| RecordError::BadRequest("Invalid filter params"))?;
+
+    let column_names: Vec<String> = api.columns().iter().map(|c| c.name.clone()).collect();
+    let row = column_names
+      .iter()
+      .enumerate()
+      .map(|(idx, name)| format!(r#":__col{idx} AS "{name}""#))
+      .collect::<Vec<_>>()
+      .join(", ");
+
+    return Ok(Some(Self {
+      query: format!("SELECT EXISTS(SELECT 1 FROM (SELECT {row}) AS _ROW_ WHERE {clause})"),
+      params,
+      column_names,
+    }));
+  }
+
+  fn matches(
+    &self,
+    conn: &rusqlite::Connection,
+    record: &[(&str, &rusqlite::types::Value)],
+  ) -> bool {
+    let mut params: Vec<NamedParamRef<'_>> =
+      Vec::with_capacity(self.params.len() + self.column_names.len());
+    params.extend(
+      self
+        .params
+        .iter()
+        .map(|(name, value)| (name.clone(), ToSqlOutput::Borrowed(value.into()))),
+    );
+    for (idx, name) in self.column_names.iter().enumerate() {
+      let value = record
+        .iter()
+        .find_map(|(n, v)| (*n == name.as_str()).then_some(ToSqlOutput::Borrowed((*v).into())))
+        .unwrap_or(ToSqlOutput::Owned(rusqlite::types::Value::Null));
+      params.push((Cow::Owned(format!(":__col{idx}")), value));
+    }
+
+    let result = conn.prepare_cached(&self.query).and_then(|mut stmt| {
+      params.bind(&mut stmt)?;
+      return match stmt.raw_query().next()? {
+        Some(row) => row.get::<_, bool>(0),
+        None => Ok(false),
+      };
+    });
+
+    return match result {
+      Ok(matches) => matches,
+      Err(err) => {
+        warn!("Subscription filter failed: {err}");
+        false
+      }
+    };
+  }
 }
 
 /// Internal, shareable state of the cloneable SubscriptionManager.
@@ -258,7 +408,7 @@ impl SubscriptionManager {
     subs: &[Subscription],
     record_subscriptions: bool,
     record: &[(&str, &rusqlite::types::Value)],
-    event: &Event,
+    event: &Arc<DbEvent>,
   ) -> Vec<usize> {
     let mut dead_subscriptions: Vec<usize> = vec![];
     for (idx, sub) in subs.iter().enumerate() {
@@ -274,15 +424,21 @@ impl SubscriptionManager {
         if record_subscriptions {
           // This can happen if the record api configuration has changed since originally
           // subscribed. In this case we just send and error and cancel the subscription.
-          if let Ok(ev) = Event::default().json_data(DbEvent::Error("Access denied".into())) {
-            let _ = sub.sender.try_send(ev);
-          }
+          let _ = sub
+            .sender
+            .try_send(Arc::new(DbEvent::Error("Access denied".into())));
           dead_subscriptions.push(idx);
           sub.sender.close();
         }
         continue;
       }
 
+      if let Some(ref filter) = sub.filter {
+        if !filter.matches(conn, record) {
+          continue;
+        }
+      }
+
       match sub.sender.try_send(event.clone()) {
         Ok(_) => {}
         Err(async_channel::TrySendError::Full(ev)) => {
@@ -336,7 +492,7 @@ impl SubscriptionManager {
       .map(|(idx, v)| (schema_metadata.schema.columns[idx].name.as_str(), v))
       .collect();
 
-    // Build a JSON-encoded SQLite event (insert, update, delete).
+    // Build the SQLite event (insert, update, delete).
     let event = {
       let json_value = serde_json::Value::Object(
         record
@@ -356,11 +512,7 @@ impl SubscriptionManager {
         RecordAction::Update => DbEvent::Update(Some(json_value)),
       };
 
-      let Ok(event) = Event::default().json_data(db_event) else {
-        return;
-      };
-
-      event
+      Arc::new(db_event)
     };
 
     'record_subs: {
@@ -511,16 +663,17 @@ impl SubscriptionManager {
     app_state: AppState,
     api: RecordApi,
     record: trailbase_sqlite::Value,
+    filter: Option<SubscriptionFilter>,
     user: Option<User>,
   ) -> Result<AutoCleanupEventStream, RecordError> {
     let table_name = api.table_name().to_string();
-    let pk_column = &api.record_pk_column().1.name;
+    let record_id_clause = api.record_id_clause(None, "$1");
 
     let Some(row_id): Option<i64> = self
       .state
       .conn
       .read_query_row_f(
-        format!(r#"SELECT _rowid_ FROM "{table_name}" WHERE "{pk_column}" = $1"#),
+        format!(r#"SELECT _rowid_ FROM "{table_name}" WHERE {record_id_clause}"#),
         [record],
         |row| row.get(0),
       )
@@ -529,7 +682,7 @@ impl SubscriptionManager {
       return Err(RecordError::RecordNotFound);
     };
 
-    let (sender, receiver) = async_channel::bounded::<Event>(16);
+    let (sender, receiver) = async_channel::bounded::<Arc<DbEvent>>(16);
 
     let subscription_id = SUBSCRIPTION_COUNTER.fetch_add(1, Ordering::SeqCst);
     let empty = {
@@ -542,6 +695,7 @@ impl SubscriptionManager {
         record_api_name: api.api_name().to_string(),
         // record_id: Some(record),
         user,
+        filter,
         sender,
       });
 
@@ -573,12 +727,13 @@ impl SubscriptionManager {
     &self,
     app_state: AppState,
     api: RecordApi,
+    filter: Option<SubscriptionFilter>,
     user: Option<User>,
   ) -> Result<AutoCleanupEventStream, RecordError> {
     let state = &self.state;
     let table_name = api.table_name().to_string();
 
-    let (sender, receiver) = async_channel::bounded::<Event>(16);
+    let (sender, receiver) = async_channel::bounded::<Arc<DbEvent>>(16);
     let subscription_id = SUBSCRIPTION_COUNTER.fetch_add(1, Ordering::SeqCst);
     let empty = {
       let mut lock = state.table_subscriptions.write();
@@ -589,6 +744,7 @@ impl SubscriptionManager {
         subscription_id,
         record_api_name: api.api_name().to_string(),
         user,
+        filter,
         sender,
       });
 
@@ -620,9 +776,83 @@ impl SubscriptionManager {
 pub async fn add_subscription_sse_handler(
   State(state): State<AppState>,
   Path((api_name, record)): Path<(String, String)>,
+  RawQuery(raw_url_query): RawQuery,
+  user: Option<User>,
+) -> Result<Sse<impl Stream<Item = SseEvent>>, RecordError> {
+  let receiver = subscribe(&state, &api_name, &record, raw_url_query.as_deref(), user).await?;
+  return Ok(Sse::new(receiver).keep_alive(KeepAlive::default()));
+}
+
+/// Subscribes to changes of the entire table, equivalent to `/subscribe/*`.
+pub async fn add_table_subscription_sse_handler(
+  State(state): State<AppState>,
+  Path(api_name): Path<String>,
+  RawQuery(raw_url_query): RawQuery,
   user: Option<User>,
 ) -> Result<Sse<impl Stream<Item = SseEvent>>, RecordError> {
-  let Some(api) = state.lookup_record_api(&api_name) else {
+  let receiver = subscribe(&state, &api_name, "*", raw_url_query.as_deref(), user).await?;
+  return Ok(Sse::new(receiver).keep_alive(KeepAlive::default()));
+}
+
+/// Subscribes to changes of the entire table streaming JSON-encoded events as WebSocket text
+/// messages.
+pub async fn add_subscription_ws_handler(
+  State(state): State<AppState>,
+  Path(api_name): Path<String>,
+  RawQuery(raw_url_query): RawQuery,
+  user: Option<User>,
+  ws: WebSocketUpgrade,
+) -> Result<Response, RecordError> {
+  // Subscribe before upgrading to surface errors as proper HTTP responses.
+  let events = subscribe(&state, &api_name, "*", raw_url_query.as_deref(), user).await?;
+  return Ok(
+    ws.on_upgrade(move |socket| forward_events_to_websocket(socket, events))
+      .into_response(),
+  );
+}
+
+async fn forward_events_to_websocket(mut socket: WebSocket, events: AutoCleanupEventStream) {
+  let mut events = std::pin::pin!(events.into_db_events());
+
+  loop {
+    tokio::select! {
+      event = events.next() => {
+        let Some(event) = event else {
+          break;
+        };
+        let text = match serde_json::to_string(event.as_ref()) {
+          Ok(text) => text,
+          Err(err) => {
+            warn!("Failed to encode event: {err}");
+            continue;
+          }
+        };
+        if socket.send(Message::Text(text.into())).await.is_err() {
+          break;
+        }
+      }
+      message = socket.recv() => {
+        match message {
+          // Ignore anything from the client but closing the connection.
+          Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+          Some(Ok(_)) => {}
+        }
+      }
+    }
+  }
+}
+
+/// Subscribes to changes of either a specific record or, for `record == "*"`, the entire table
+/// after checking the API's access rules. Changes can optionally be filtered using the listing
+/// filter syntax, e.g. `status=open&priority[gte]=3`.
+pub(crate) async fn subscribe(
+  state: &AppState,
+  api_name: &str,
+  record: &str,
+  filter_query: Option<&str>,
+  user: Option<User>,
+) -> Result<AutoCleanupEventStream, RecordError> {
+  let Some(api) = state.lookup_record_api(api_name) else {
     return Err(RecordError::ApiNotFound);
   };
 
@@ -630,35 +860,33 @@ pub async fn add_subscription_sse_handler(
     return Err(RecordError::Forbidden);
   }
 
+  let filter = match filter_query {
+    Some(query) => SubscriptionFilter::new(&api, query)?,
+    None => None,
+  };
+
   if record == "*" {
     api.check_table_level_access(Permission::Read, user.as_ref())?;
 
-    let receiver = state
+    return state
       .subscription_manager()
-      .add_table_subscription(state.clone(), api, user)
-      .await?;
-
-    return Ok(Sse::new(receiver).keep_alive(KeepAlive::default()));
+      .add_table_subscription(state.clone(), api, filter, user)
+      .await;
   } else {
-    let record_id = api.id_to_sql(&record)?;
+    let record_id = api.id_to_sql(record)?;
     api
       .check_record_level_access(Permission::Read, Some(&record_id), None, user.as_ref())
       .await?;
 
-    let receiver = state
+    return state
       .subscription_manager()
-      .add_record_subscription(state.clone(), api, record_id, user)
-      .await?;
-
-    return Ok(Sse::new(receiver).keep_alive(KeepAlive::default()));
+      .add_record_subscription(state.clone(), api, record_id, filter, user)
+      .await;
   }
 }
 
 #[cfg(test)]
 async fn decode_sse_json_event(event: Event) -> serde_json::Value {
-  use axum::response::IntoResponse;
-  use futures_util::stream::StreamExt;
-
   let (sender, receiver) = async_channel::unbounded::<Event>();
   let sse = Sse::new(receiver.map(|ev| -> Result<Event, axum::Error> { Ok(ev) }));
 
@@ -696,8 +924,8 @@ mod tests {
   use crate::records::test_utils::add_record_api_config;
   use crate::util::uuid_to_b64;
 
-  async fn decode_db_event(event: Event) -> DbEvent {
-    let json = decode_sse_json_event(event).await;
+  async fn decode_db_event(event: Arc<DbEvent>) -> DbEvent {
+    let json = decode_sse_json_event(Event::default().json_data(event.as_ref()).unwrap()).await;
     return serde_json::from_value(json).unwrap();
   }
 
@@ -708,9 +936,8 @@ mod tests {
       "b": "text",
     });
     let db_event = DbEvent::Delete(Some(json));
-    let event = Event::default().json_data(db_event.clone()).unwrap();
 
-    assert_eq!(decode_db_event(event).await, db_event);
+    assert_eq!(decode_db_event(Arc::new(db_event.clone())).await, db_event);
   }
 
   async fn setup_world_readable() -> AppState {
@@ -771,6 +998,7 @@ mod tests {
         api,
         trailbase_sqlite::Value::Integer(0),
         None,
+        None,
       )
       .await
       .unwrap();
@@ -840,7 +1068,7 @@ mod tests {
 
     {
       let stream = manager
-        .add_table_subscription(state.clone(), api, None)
+        .add_table_subscription(state.clone(), api, None, None)
         .await
         .unwrap();
 
@@ -913,6 +1141,53 @@ mod tests {
     assert_eq!(0, manager.num_table_subscriptions());
   }
 
+  #[tokio::test]
+  async fn subscribe_to_table_with_filter_test() {
+    let state = setup_world_readable().await;
+    let conn = state.conn().clone();
+
+    assert!(matches!(
+      subscribe(&state, "api_name", "*", Some("limit=5"), None).await,
+      Err(RecordError::BadRequest(_))
+    ));
+
+    let stream = subscribe(
+      &state,
+      "api_name",
+      "*",
+      Some("text[like]=foo%25&id[gte]=2"),
+      None,
+    )
+    .await
+    .unwrap();
+
+    for (id, text) in [(1, "foo"), (2, "bar"), (3, "foobar")] {
+      conn
+        .execute(
+          "INSERT INTO test (id, text) VALUES ($1, $2)",
+          params!(id, text),
+        )
+        .await
+        .unwrap();
+    }
+    conn
+      .execute("UPDATE test SET text = 'foo' WHERE id = 2", ())
+      .await
+      .unwrap();
+
+    // Only changes matching the filter are sent.
+    let events = stream.into_db_events();
+    let mut events = std::pin::pin!(events);
+    assert_eq!(
+      *events.next().await.unwrap(),
+      DbEvent::Insert(Some(serde_json::json!({"id": 3, "text": "foobar"})))
+    );
+    assert_eq!(
+      *events.next().await.unwrap(),
+      DbEvent::Update(Some(serde_json::json!({"id": 2, "text": "foo"})))
+    );
+  }
+
   #[tokio::test]
   async fn subscription_lifecycle_test() {
     let state = setup_world_readable().await;
@@ -935,6 +1210,7 @@ mod tests {
     let sse = add_subscription_sse_handler(
       State(state.clone()),
       Path(("api_name".to_string(), record_id_raw.to_string())),
+      RawQuery(None),
       None,
     )
     .await;
@@ -1002,6 +1278,7 @@ mod tests {
     let sse_or = add_subscription_sse_handler(
       State(state.clone()),
       Path(("api_name".to_string(), "*".to_string())),
+      RawQuery(None),
       None,
     )
     .await;
@@ -1021,6 +1298,7 @@ mod tests {
       let _ = add_subscription_sse_handler(
         State(state.clone()),
         Path(("api_name".to_string(), "*".to_string())),
+        RawQuery(None),
         User::from_auth_token(&state, &user_x_token.auth_token),
       )
       .await
@@ -1047,6 +1325,7 @@ mod tests {
       let _ = add_subscription_sse_handler(
         State(state.clone()),
         Path(("api_name".to_string(), record_id_raw.to_string())),
+        RawQuery(None),
         User::from_auth_token(&state, &user_x_token.auth_token),
       )
       .await
@@ -1067,6 +1346,7 @@ mod tests {
       let sse_or = add_subscription_sse_handler(
         State(state.clone()),
         Path(("api_name".to_string(), record_id_raw.to_string())),
+        RawQuery(None),
         User::from_auth_token(&state, &user_y_token.auth_token),
       )
       .await;
@@ -1107,6 +1387,7 @@ mod tests {
         .add_table_subscription(
           state.clone(),
           api.clone(),
+          None,
           User::from_auth_token(&state, &user_x_token.auth_token),
         )
         .await
@@ -1116,6 +1397,7 @@ mod tests {
         .add_table_subscription(
           state.clone(),
           api.clone(),
+          None,
           User::from_auth_token(&state, &user_y_token.auth_token),
         )
         .await
@@ -1210,6 +1492,7 @@ mod tests {
         state.clone(),
         api,
         trailbase_sqlite::Value::Integer(record_id),
+        None,
         user_x,
       )
       .await
@@ -0,0 +1,795 @@
+use axum::Json;
+use axum::extract::{Query, State};
+use log::*;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::Duration;
+use trailbase_sqlite::{Connection, NamedParams, Value, params};
+
+use crate::app_state::AppState;
+use crate::audit::read_record_json;
+use crate::auth::user::User;
+use crate::constants::SYNC_ROW_TABLE;
+use crate::records::create_record::extract_record_id;
+use crate::records::hooks::{HookContext, HookEvent};
+use crate::records::list_records::column_filter;
+use crate::records::params::JsonRow;
+use crate::records::request_context::push_request_context_params;
+use crate::records::sql_to_json::row_to_json;
+use crate::records::transaction::{Operation, execute_operations};
+use crate::records::{Permission, RecordApi, RecordError};
+use crate::util::{quote_identifier, quote_literal};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+const TRIGGER_PREFIX: &str = "_sync_";
+const DEFAULT_LIMIT: usize = 256;
+const MAX_LIMIT: usize = 1024;
+const MAX_MUTATIONS: usize = 1024;
+
+#[derive(Debug, Default, Deserialize)]
+pub struct PullQuery {
+  /// Version returned by the previous pull. Defaults to 0, i.e. everything.
+  pub since: Option<i64>,
+  pub limit: Option<usize>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SyncChange {
+  pub api_name: String,
+  /// Safe-url base64 encoded id of the changed record.
+  pub record_id: String,
+  pub version: i64,
+  /// Whether the record was deleted, in which case `record` is null.
+  pub deleted: bool,
+  pub record: Option<serde_json::Value>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PullResponse {
+  /// Changes since the requested version ordered by version, only the latest per record.
+  pub changes: Vec<SyncChange>,
+  /// Version to pull from next.
+  pub version: i64,
+  /// Whether more changes are pending beyond `version`.
+  pub has_more: bool,
+}
+
+/// Mutation of a client-side record. `base_version` is the record's version the client last
+/// pulled, which is used to detect conflicting server-side changes.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Mutation {
+  Upsert {
+    api_name: String,
+    record_id: String,
+    value: JsonRow,
+    base_version: Option<i64>,
+  },
+  Delete {
+    api_name: String,
+    record_id: String,
+    base_version: Option<i64>,
+  },
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PushRequest {
+  pub mutations: Vec<Mutation>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MutationStatus {
+  /// Applied as is, including conflicts resolved as last-write-wins.
+  Applied,
+  /// Conflicting and replaced with the record returned by a conflict hook.
+  Resolved,
+  /// Conflicting and vetoed by a conflict hook.
+  Rejected,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct MutationResult {
+  pub status: MutationStatus,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub error: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PushResponse {
+  /// Results in the order of the pushed mutations.
+  pub results: Vec<MutationResult>,
+  /// Latest version after applying the mutations.
+  pub version: i64,
+}
+
+/// Returns record changes since the given version across all record APIs with sync enabled.
+///
+/// Records are filtered by their APIs' read access rules. Since deleted records cannot be
+/// evaluated against access rules anymore, tombstones are returned to all users with table-level
+/// read access.
+pub async fn pull_handler(
+  State(state): State<AppState>,
+  Query(query): Query<PullQuery>,
+  user: Option<User>,
+) -> Result<Json<PullResponse>, RecordError> {
+  let since = query.since.unwrap_or(0);
+  let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+  // Pin the upper bound first. Versions are assigned by serialized writes, i.e. later writes have
+  // higher versions and are picked up by the next pull.
+  let until = latest_version(state.conn()).await?;
+
+  let apis: Vec<RecordApi> = state
+    .record_apis()
+    .iter()
+    .filter(|(_, api)| api.enable_sync())
+    .filter(|(_, api)| {
+      return api
+        .check_table_level_access(Permission::Read, user.as_ref())
+        .is_ok();
+    })
+    .map(|(_, api)| api.clone())
+    .collect();
+
+  let mut changes: Vec<SyncChange> = vec![];
+  let mut has_more = false;
+  for api in &apis {
+    let api_changes = pull_changes(&state, api, user.as_ref(), since, until, limit).await?;
+    has_more |= api_changes.len() >= limit;
+    changes.extend(api_changes);
+  }
+
+  changes.sort_by_key(|change| change.version);
+  if changes.len() > limit {
+    changes.truncate(limit);
+    has_more = true;
+  }
+
+  // Changes hidden by access rules are skipped as well, unless the page is full.
+  let version = match (has_more, changes.last()) {
+    (true, Some(last)) => last.version,
+    _ => until.max(since),
+  };
+
+  return Ok(Json(PullResponse {
+    changes,
+    version,
+    has_more,
+  }));
+}
+
+async fn pull_changes(
+  state: &AppState,
+  api: &RecordApi,
+  user: Option<&User>,
+  since: i64,
+  until: i64,
+  limit: usize,
+) -> Result<Vec<SyncChange>, RecordError> {
+  let (_index, pk_column) = api.record_pk_column();
+  let columns = api
+    .columns()
+    .iter()
+    .map(|c| format!(r#"_ROW_."{}""#, c.name))
+    .collect::<Vec<_>>()
+    .join(", ");
+
+  // NOTE: The read access rule is part of the join condition, s.t. inaccessible records turn out
+  // as missing rather than being filtered along with tombstones.
+  let query = format!(
+    r#"
+      SELECT
+        {columns},
+        _SYNC_.version AS _sync_version,
+        _SYNC_.deleted AS _sync_deleted,
+        _SYNC_.record_id AS _sync_record_id
+      FROM
+        (SELECT :__user_id AS id) AS _USER_,
+        (SELECT :__ctx_method AS method, :__ctx_ip AS ip, :__ctx_headers AS headers) AS _CTX_,
+        {SYNC_ROW_TABLE} AS _SYNC_
+          LEFT JOIN "{table_name}" AS _ROW_
+            ON _ROW_."{pk}" = _SYNC_.record_id AND ({read_access_clause})
+      WHERE
+        _SYNC_.table_name = :__table_name
+        AND _SYNC_.version > :__since AND _SYNC_.version <= :__until
+        AND (_SYNC_.deleted OR _ROW_."{pk}" IS NOT NULL)
+      ORDER BY _SYNC_.version
+      LIMIT :__limit
+    "#,
+    table_name = api.table_name(),
+    pk = pk_column.name,
+    read_access_clause = api.read_access_rule().unwrap_or("TRUE"),
+  );
+
+  let mut params: NamedParams = vec![
+    (
+      Cow::Borrowed(":__user_id"),
+      user.map_or(Value::Null, |u| Value::Blob(u.uuid.into())),
+    ),
+    (
+      Cow::Borrowed(":__table_name"),
+      Value::Text(api.table_name().to_string()),
+    ),
+    (Cow::Borrowed(":__since"), Value::Integer(since)),
+    (Cow::Borrowed(":__until"), Value::Integer(until)),
+    (Cow::Borrowed(":__limit"), Value::Integer(limit as i64)),
+  ];
+  push_request_context_params(&mut params);
+
+  let rows = state.conn().read_query_rows(query, params).await?;
+
+  let offset = api.columns().len();
+  let mut changes = Vec::with_capacity(rows.len());
+  for row in rows.iter() {
+    let version: i64 = row
+      .get(offset)
+      .map_err(|err| RecordError::Internal(err.into()))?;
+    let deleted: bool = row
+      .get(offset + 1)
+      .map_err(|err| RecordError::Internal(err.into()))?;
+    let Some(record_id) = row.get_value(offset + 2).cloned() else {
+      return Err(RecordError::Internal("missing record id".into()));
+    };
+
+    // Soft-deleted records are synced as deletions.
+    let deleted = deleted || api.is_soft_deleted(row);
+    let record = if deleted {
+      None
+    } else {
+      let mut record = row_to_json(
+        api.columns(),
+        api.json_column_metadata(),
+        row,
+        column_filter,
+      )
+      .map_err(|err| RecordError::Internal(err.into()))?;
+      api.decrypt_record(&mut record)?;
+      Some(record)
+    };
+
+    changes.push(SyncChange {
+      api_name: api.api_name().to_string(),
+      record_id: extract_record_id(record_id)?,
+      version,
+      deleted,
+      record,
+    });
+  }
+
+  return Ok(changes);
+}
+
+/// Applies client mutations atomically.
+///
+/// Mutations of records changed since the client's `base_version` conflict. Conflicts are resolved
+/// by `sync_conflict` record hooks, if any, and otherwise as last-write-wins, i.e. the pushed
+/// change is applied. Access rules apply as for the transaction API.
+pub async fn push_handler(
+  State(state): State<AppState>,
+  user: Option<User>,
+  Json(request): Json<PushRequest>,
+) -> Result<Json<PushResponse>, RecordError> {
+  if request.mutations.len() > MAX_MUTATIONS {
+    return Err(RecordError::BadRequest("Push exceeds limit: 1024"));
+  }
+
+  let mut results: Vec<MutationResult> = Vec::with_capacity(request.mutations.len());
+  let mut operations: Vec<Operation> = vec![];
+  for mutation in request.mutations {
+    let (api_name, record_id, value, base_version) = match mutation {
+      Mutation::Upsert {
+        api_name,
+        record_id,
+        value,
+        base_version,
+      } => (api_name, record_id, Some(value), base_version),
+      Mutation::Delete {
+        api_name,
+        record_id,
+        base_version,
+      } => (api_name, record_id, None, base_version),
+    };
+
+    let Some(api) = state.lookup_record_api(&api_name) else {
+      return Err(RecordError::ApiNotFound);
+    };
+    if !api.enable_sync() {
+      return Err(RecordError::ApiNotFound);
+    }
+    let pk = api.id_to_sql(&record_id)?;
+
+    let mut server =
+      read_record_json(&state, &api, &record_id)
+        .await
+        .and_then(|record| match record {
+          serde_json::Value::Object(record) => Some(record),
+          _ => None,
+        });
+    if let Some(ref mut record) = server {
+      let mut value = serde_json::Value::Object(std::mem::take(record));
+      api.decrypt_record(&mut value)?;
+      if let serde_json::Value::Object(decrypted) = value {
+        *record = decrypted;
+      }
+    }
+
+    let version = record_version(state.conn(), api.table_name(), pk).await?;
+    let conflict = version.is_some_and(|v| v > base_version.unwrap_or(0));
+
+    let (status, value) =
+      if conflict && state.record_hooks().has(&api_name, HookEvent::SyncConflict) {
+        let context = HookContext::new(
+          HookEvent::SyncConflict,
+          &api_name,
+          Some(&record_id),
+          user.as_ref(),
+        );
+        match state
+          .record_hooks()
+          .resolve_conflict(context, value.clone(), server.clone())
+          .await
+        {
+          Ok(Some(resolved)) => (MutationStatus::Resolved, Some(resolved)),
+          Ok(None) => (MutationStatus::Applied, value),
+          Err(err) => {
+            results.push(MutationResult {
+              status: MutationStatus::Rejected,
+              error: Some(err.to_string()),
+            });
+            continue;
+          }
+        }
+      } else {
+        (MutationStatus::Applied, value)
+      };
+
+    let (_index, pk_column) = api.record_pk_column();
+    match (value, server.is_some()) {
+      (Some(mut value), exists) => {
+        value.remove(&pk_column.name);
+        if exists {
+          operations.push(Operation::Update {
+            api_name,
+            record_id,
+            value,
+          });
+        } else {
+          value.insert(pk_column.name.clone(), serde_json::Value::String(record_id));
+          operations.push(Operation::Create { api_name, value });
+        }
+      }
+      (None, true) => operations.push(Operation::Delete {
+        api_name,
+        record_id,
+      }),
+      // Deleting an already deleted record.
+      (None, false) => {}
+    };
+
+    results.push(MutationResult {
+      status,
+      error: None,
+    });
+  }
+
+  if !operations.is_empty() {
+    execute_operations(&state, user.as_ref(), operations).await?;
+  }
+
+  return Ok(Json(PushResponse {
+    results,
+    version: latest_version(state.conn()).await?,
+  }));
+}
+
+async fn latest_version(conn: &Connection) -> Result<i64, RecordError> {
+  return Ok(
+    conn
+      .read_query_row_f(
+        format!("SELECT COALESCE(MAX(version), 0) FROM {SYNC_ROW_TABLE}"),
+        (),
+        |row| row.get(0),
+      )
+      .await?
+      .unwrap_or(0),
+  );
+}
+
+/// Returns the current version of a record including tombstones, if tracked.
+async fn record_version(
+  conn: &Connection,
+  table_name: &str,
+  record_id: Value,
+) -> Result<Option<i64>, RecordError> {
+  return Ok(
+    conn
+      .read_query_row_f(
+        format!("SELECT version FROM {SYNC_ROW_TABLE} WHERE table_name = $1 AND record_id = $2"),
+        params!(table_name.to_string(), record_id),
+        |row| row.get(0),
+      )
+      .await?,
+  );
+}
+
+/// Maintains change tracking triggers for record APIs with sync enabled until the process exits.
+pub(crate) async fn run_sync_triggers(state: AppState) {
+  let mut interval = tokio::time::interval(POLL_INTERVAL);
+
+  loop {
+    interval.tick().await;
+
+    // NOTE: Triggers are re-synced continuously to pick up config as well as schema changes, e.g.
+    // re-created tables.
+    if let Err(err) = sync_triggers(state.conn(), &synced_tables(&state)).await {
+      warn!("Failed to sync change tracking triggers: {err}");
+    }
+  }
+}
+
+/// Table and primary key column of a record API with sync enabled.
+struct SyncedTable {
+  table_name: String,
+  pk_column: String,
+}
+
+fn synced_tables(state: &AppState) -> Vec<SyncedTable> {
+  let mut tables: BTreeMap<String, SyncedTable> = BTreeMap::new();
+  for (_, api) in state.record_apis().iter() {
+    if !api.enable_sync() {
+      continue;
+    }
+
+    let (_index, pk_column) = api.record_pk_column();
+    tables.insert(
+      api.table_name().to_string(),
+      SyncedTable {
+        table_name: api.table_name().to_string(),
+        pk_column: pk_column.name.clone(),
+      },
+    );
+  }
+  return tables.into_values().collect();
+}
+
+/// Builds the "_sync_*" change tracking triggers for the given table keyed by name.
+fn build_triggers(table: &SyncedTable) -> Vec<(String, String)> {
+  let table_identifier = quote_identifier(&table.table_name);
+  let table_literal = quote_literal(&table.table_name);
+  let pk_column = quote_identifier(&table.pk_column);
+
+  // Bumps a row's version to the next one in the global sequence.
+  let track = |row: &str, deleted: &str, condition: &str| -> String {
+    return format!(
+      r#"INSERT INTO {SYNC_ROW_TABLE} (table_name, record_id, version, deleted) SELECT {table_literal}, {row}.{pk_column}, (SELECT COALESCE(MAX(version), 0) + 1 FROM {SYNC_ROW_TABLE}), {deleted} WHERE {condition} ON CONFLICT (table_name, record_id) DO UPDATE SET version = excluded.version, deleted = excluded.deleted, updated = UNIXEPOCH();"#
+    );
+  };
+
+  return [
+    ("insert", "INSERT", track("NEW", "FALSE", "TRUE")),
+    (
+      "update",
+      "UPDATE",
+      // Changing the primary key amounts to deleting the old record.
+      format!(
+        "{} {}",
+        track(
+          "OLD",
+          "TRUE",
+          &format!("OLD.{pk_column} IS NOT NEW.{pk_column}")
+        ),
+        track("NEW", "FALSE", "TRUE")
+      ),
+    ),
+    ("delete", "DELETE", track("OLD", "TRUE", "TRUE")),
+  ]
+  .into_iter()
+  .map(|(op, event, body)| {
+    let name = format!("{TRIGGER_PREFIX}{}_{op}", table.table_name);
+    let sql = format!(
+      "CREATE TRIGGER {} AFTER {event} ON {table_identifier} BEGIN {body} END",
+      quote_identifier(&name)
+    );
+    return (name, sql);
+  })
+  .collect();
+}
+
+/// Installs, updates and drops change tracking triggers, such that exactly the given tables are
+/// tracked. Rows of newly tracked tables are added with fresh versions.
+async fn sync_triggers(
+  conn: &Connection,
+  tables: &[SyncedTable],
+) -> Result<(), trailbase_sqlite::Error> {
+  #[derive(Deserialize)]
+  struct Trigger {
+    name: String,
+    sql: String,
+  }
+
+  let desired: BTreeMap<String, (usize, String)> = tables
+    .iter()
+    .enumerate()
+    .flat_map(|(index, table)| {
+      return build_triggers(table)
+        .into_iter()
+        .map(move |(name, sql)| (name, (index, sql)));
+    })
+    .collect();
+  let existing: HashMap<String, String> = conn
+    .read_query_as::<Trigger>(
+      format!(
+        r#"SELECT name, sql FROM sqlite_master WHERE type = 'trigger' AND name GLOB '{TRIGGER_PREFIX}*'"#
+      ),
+      (),
+    )
+    .await?
+    .into_iter()
+    .map(|t| (t.name, t.sql))
+    .collect();
+
+  let stale: Vec<String> = existing
+    .iter()
+    .filter(|(name, sql)| desired.get(*name).map(|(_, desired)| desired) != Some(*sql))
+    .map(|(name, _)| name.clone())
+    .collect();
+  let mut backfill: HashSet<usize> = HashSet::new();
+  let missing: Vec<String> = desired
+    .into_iter()
+    .filter(|(name, (_, sql))| existing.get(name) != Some(sql))
+    .map(|(_, (index, sql))| {
+      backfill.insert(index);
+      return sql;
+    })
+    .collect();
+  if stale.is_empty() && missing.is_empty() {
+    return Ok(());
+  }
+
+  let backfill: Vec<String> = backfill
+    .into_iter()
+    .map(|index| {
+      let SyncedTable {
+        table_name,
+        pk_column,
+      } = &tables[index];
+      return format!(
+        "INSERT INTO {SYNC_ROW_TABLE} (table_name, record_id, version) SELECT {}, {}, (SELECT COALESCE(MAX(version), 0) FROM {SYNC_ROW_TABLE}) + ROW_NUMBER() OVER () FROM {} WHERE TRUE ON CONFLICT DO NOTHING",
+        quote_literal(table_name),
+        quote_identifier(pk_column),
+        quote_identifier(table_name),
+      );
+    })
+    .collect();
+
+  debug!("Syncing change tracking triggers");
+  conn
+    .call(move |conn| {
+      let tx = conn.transaction()?;
+      for name in stale {
+        tx.execute(
+          &format!("DROP TRIGGER IF EXISTS {}", quote_identifier(&name)),
+          (),
+        )?;
+      }
+      for sql in missing {
+        tx.execute(&sql, ())?;
+      }
+      for sql in backfill {
+        tx.execute(&sql, ())?;
+      }
+      tx.commit()?;
+
+      return Ok(());
+    })
+    .await?;
+
+  return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+  use futures_util::FutureExt;
+  use serde_json::json;
+  use std::sync::Arc;
+
+  use super::*;
+  use crate::app_state::test_state;
+  use crate::config::proto::{PermissionFlag, RecordApiConfig};
+  use crate::records::test_utils::add_record_api_config;
+
+  async fn pull(state: &AppState, since: i64) -> PullResponse {
+    let Json(response) = pull_handler(
+      State(state.clone()),
+      Query(PullQuery {
+        since: Some(since),
+        limit: None,
+      }),
+      None,
+    )
+    .await
+    .unwrap();
+    return response;
+  }
+
+  async fn push(state: &AppState, mutations: serde_json::Value) -> PushResponse {
+    let Json(response) = push_handler(
+      State(state.clone()),
+      None,
+      Json(serde_json::from_value(json!({"mutations": mutations})).unwrap()),
+    )
+    .await
+    .unwrap();
+    return response;
+  }
+
+  #[tokio::test]
+  async fn test_sync() {
+    let state = test_state(None).await.unwrap();
+    state
+      .conn()
+      .execute_batch(
+        r#"
+          CREATE TABLE note (id INTEGER PRIMARY KEY, body TEXT NOT NULL) STRICT;
+          INSERT INTO note (id, body) VALUES (1, 'existing');
+        "#,
+      )
+      .await
+      .unwrap();
+    state.refresh_table_cache().await.unwrap();
+
+    add_record_api_config(
+      &state,
+      RecordApiConfig {
+        name: Some("note".to_string()),
+        table_name: Some("note".to_string()),
+        enable_sync: Some(true),
+        acl_world: [
+          PermissionFlag::Create,
+          PermissionFlag::Read,
+          PermissionFlag::Update,
+          PermissionFlag::Delete,
+        ]
+        .into_iter()
+        .map(|flag| flag as i32)
+        .collect(),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    sync_triggers(state.conn(), &synced_tables(&state))
+      .await
+      .unwrap();
+    // Idempotent.
+    sync_triggers(state.conn(), &synced_tables(&state))
+      .await
+      .unwrap();
+
+    // Pre-existing records are backfilled.
+    let initial = pull(&state, 0).await;
+    assert_eq!(initial.changes.len(), 1);
+    assert_eq!(
+      initial.changes[0].record,
+      Some(json!({"id": 1, "body": "existing"}))
+    );
+
+    state
+      .conn()
+      .execute_batch(
+        r#"
+          INSERT INTO note (id, body) VALUES (2, 'second');
+          DELETE FROM note WHERE id = 1;
+        "#,
+      )
+      .await
+      .unwrap();
+
+    let delta = pull(&state, initial.version).await;
+    assert!(!delta.has_more);
+    assert_eq!(
+      delta
+        .changes
+        .iter()
+        .map(|c| (c.record_id.as_str(), c.deleted))
+        .collect::<Vec<_>>(),
+      [("2", false), ("1", true)]
+    );
+    assert!(pull(&state, delta.version).await.changes.is_empty());
+
+    // Pushes without conflicts are applied.
+    let pushed = push(
+      &state,
+      json!([
+        {"op": "upsert", "api_name": "note", "record_id": "3", "value": {"body": "offline"}},
+        {"op": "upsert", "api_name": "note", "record_id": "2", "value": {"body": "edited"}, "base_version": delta.version},
+      ]),
+    )
+    .await;
+    assert!(
+      pushed
+        .results
+        .iter()
+        .all(|r| r.status == MutationStatus::Applied)
+    );
+    assert_eq!(pull(&state, delta.version).await.changes.len(), 2);
+
+    // Conflicting pushes are resolved by hooks.
+    state.record_hooks().register(
+      "note",
+      HookEvent::SyncConflict,
+      Arc::new(|_context: HookContext, conflict: Option<JsonRow>| {
+        let body = conflict
+          .as_ref()
+          .and_then(|c| c["client"]["body"].as_str())
+          .map(str::to_string)
+          .unwrap_or_default();
+        return async move {
+          let mut resolved = JsonRow::new();
+          resolved.insert("body".to_string(), json!(format!("merged: {body}")));
+          return Ok::<Option<JsonRow>, RecordError>(Some(resolved));
+        }
+        .boxed();
+      }),
+    );
+
+    let stale = push(
+      &state,
+      json!([
+        {"op": "upsert", "api_name": "note", "record_id": "2", "value": {"body": "stale"}, "base_version": delta.version},
+      ]),
+    )
+    .await;
+    assert_eq!(stale.results[0].status, MutationStatus::Resolved);
+
+    let body: Option<String> = state
+      .conn()
+      .read_query_row_f("SELECT body FROM note WHERE id = 2", (), |row| row.get(0))
+      .await
+      .unwrap();
+    assert_eq!(body.as_deref(), Some("merged: stale"));
+  }
+
+  #[tokio::test]
+  async fn test_sync_triggers_quoted_names() {
+    let state = test_state(None).await.unwrap();
+    let conn = state.conn();
+    conn
+      .execute_batch(
+        r#"
+          CREATE TABLE "it's" ("a""b" INTEGER PRIMARY KEY, body TEXT) STRICT;
+          INSERT INTO "it's" ("a""b", body) VALUES (1, 'existing');
+        "#,
+      )
+      .await
+      .unwrap();
+
+    let tables = [SyncedTable {
+      table_name: "it's".to_string(),
+      pk_column: r#"a"b"#.to_string(),
+    }];
+    sync_triggers(conn, &tables).await.unwrap();
+    // Idempotent.
+    sync_triggers(conn, &tables).await.unwrap();
+
+    conn
+      .execute(r#"INSERT INTO "it's" ("a""b", body) VALUES (2, 'new')"#, ())
+      .await
+      .unwrap();
+
+    let count: Option<i64> = conn
+      .read_query_row_f(
+        format!("SELECT COUNT(*) FROM {SYNC_ROW_TABLE} WHERE table_name = 'it''s'"),
+        (),
+        |row| row.get(0),
+      )
+      .await
+      .unwrap();
+    assert_eq!(count, Some(2));
+
+    sync_triggers(conn, &[]).await.unwrap();
+  }
+}
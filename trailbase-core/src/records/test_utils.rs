@@ -35,6 +35,19 @@ mod tests {
       delete_access_rule: access_rules.delete,
       schema_access_rule: access_rules.schema,
       expand: vec![],
+      column_validators: vec![],
+      response_format: None,
+      embedding: None,
+      cache_ttl_sec: None,
+      cache_max_bytes: None,
+      list_limits: None,
+      encrypted_columns: vec![],
+      soft_delete_column: None,
+      max_expand_depth: None,
+      require_sortable_primary_key: None,
+      webhooks: vec![],
+      rate_limit: None,
+      enable_sync: None,
     });
 
     return state.validate_and_update_config(config, None).await;
@@ -205,6 +218,13 @@ mod tests {
       _ => Err(anyhow::anyhow!("Not an object: {value:?}")),
     };
   }
+
+  pub async fn json_body<T: serde::de::DeserializeOwned>(response: axum::response::Response) -> T {
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+      .await
+      .unwrap();
+    return serde_json::from_slice(&body).unwrap();
+  }
 }
 
 #[cfg(test)]
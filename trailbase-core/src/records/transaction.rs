@@ -0,0 +1,709 @@
+use axum::Json;
+use axum::extract::State;
+use log::*;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::sync::Arc;
+use trailbase_sqlite::{NamedParams, Params as _};
+
+use crate::app_state::AppState;
+use crate::audit::{AuditAction, audit_record_mutation, read_record_json, snapshot_record};
+use crate::auth::user::User;
+use crate::config::proto::WebhookEvent;
+use crate::queue::Job;
+use crate::records::create_record::{autofill_user_id_columns, extract_record_id};
+use crate::records::files::delete_pending_files;
+use crate::records::hooks::{HookContext, HookEvent};
+use crate::records::params::{JsonRow, LazyParams};
+use crate::records::query_builder::{DeleteQueryBuilder, InsertQueryBuilder, UpdateQueryBuilder};
+use crate::records::webhooks::enqueue_webhooks;
+use crate::records::{Permission, RecordApi, RecordError};
+
+/// Max number of operations per transaction.
+const MAX_OPERATIONS: usize = 1024;
+
+/// Single operation of a transaction, addressing records via their respective record API.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Operation {
+  Create {
+    api_name: String,
+    value: JsonRow,
+  },
+  Update {
+    api_name: String,
+    record_id: String,
+    value: JsonRow,
+  },
+  Delete {
+    api_name: String,
+    record_id: String,
+  },
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TransactionRequest {
+  pub operations: Vec<Operation>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TransactionResponse {
+  /// Safe-url base64 encoded ids of the created records in the order of the create operations.
+  pub ids: Vec<String>,
+}
+
+enum StatementKind {
+  Create,
+  Update,
+  Delete,
+}
+
+/// Access check and write of a single operation, prepared ahead of the transaction.
+struct Statement {
+  kind: StatementKind,
+  access_query: Option<(Arc<str>, NamedParams)>,
+  /// None for updates that don't change anything but still need to pass the access check.
+  query: Option<(String, NamedParams)>,
+}
+
+/// Post-commit side-effects of an operation.
+struct SideEffects {
+  api: RecordApi,
+  record_id: Option<String>,
+  delete_files: bool,
+  embed: bool,
+  event: WebhookEvent,
+  audit: AuditAction,
+  after_hook: HookEvent,
+  /// Snapshot of updated or deleted records for the audit log.
+  before: Option<serde_json::Value>,
+  /// Deleted records for after-delete hooks.
+  deleted: Option<serde_json::Value>,
+}
+
+/// Prepares the operation's statement running before hooks, the same way as the respective
+/// record API endpoints do.
+async fn prepare(
+  state: &AppState,
+  api: &RecordApi,
+  operation: Operation,
+  user: Option<&User>,
+) -> Result<(Statement, Option<String>, bool), RecordError> {
+  if !api.is_table() {
+    return Err(RecordError::ApiRequiresTable);
+  }
+
+  let (_index, pk_column) = api.record_pk_column();
+  let hooks = state.record_hooks();
+
+  return match operation {
+    Operation::Create { mut value, .. } => {
+      autofill_user_id_columns(api, &mut value, user);
+
+      let hook_record = hooks
+        .has(api.api_name(), HookEvent::BeforeCreate)
+        .then(|| value.clone());
+      let mut lazy_params = LazyParams::new(api, value, None);
+      let access_query =
+        api.record_level_access_query(Permission::Create, None, Some(&mut lazy_params), user)?;
+      let mut params = lazy_params.consume().map_err(RecordError::from)?;
+      if let Some(hook_record) = hook_record {
+        let context = HookContext::new(HookEvent::BeforeCreate, api.api_name(), None, user);
+        if let Some(changed) = hooks.run_before(context, Some(hook_record)).await? {
+          params.override_with(api, changed)?;
+        }
+      }
+      if !params.files.is_empty() {
+        return Err(RecordError::BadRequest(
+          "File uploads not supported in transactions",
+        ));
+      }
+
+      let (query, named_params, _files) = InsertQueryBuilder::build_insert_query(
+        api.table_name(),
+        params,
+        api.insert_conflict_resolution_strategy(),
+        None,
+        Some(&pk_column.name),
+      )
+      .map_err(|err| RecordError::Internal(err.into()))?;
+
+      Ok((
+        Statement {
+          kind: StatementKind::Create,
+          access_query,
+          query: Some((query, named_params)),
+        },
+        None,
+        api.embedding().is_some(),
+      ))
+    }
+    Operation::Update {
+      record_id,
+      mut value,
+      ..
+    } => {
+      let record_id_value = api.id_to_sql(&record_id)?;
+      if let Some(existing) = value.insert(
+        pk_column.name.clone(),
+        serde_json::Value::String(record_id.clone()),
+      ) {
+        if existing != record_id {
+          return Err(RecordError::BadRequest("primary key mismatch"));
+        }
+      }
+
+      let embed = api.embedding().is_some_and(|embedding| {
+        return embedding
+          .source_columns
+          .iter()
+          .any(|column| value.contains_key(column));
+      });
+
+      let hook_record = hooks
+        .has(api.api_name(), HookEvent::BeforeUpdate)
+        .then(|| value.clone());
+      let mut lazy_params = LazyParams::new(api, value, None);
+      let access_query = api.record_level_access_query(
+        Permission::Update,
+        Some(&record_id_value),
+        Some(&mut lazy_params),
+        user,
+      )?;
+      let mut params = lazy_params.consume().map_err(RecordError::from)?;
+      if let Some(hook_record) = hook_record {
+        let context = HookContext::new(
+          HookEvent::BeforeUpdate,
+          api.api_name(),
+          Some(&record_id),
+          user,
+        );
+        if let Some(changed) = hooks.run_before(context, Some(hook_record)).await? {
+          if changed.contains_key(&pk_column.name) {
+            return Err(RecordError::BadRequest("primary key mismatch"));
+          }
+          params.override_with(api, changed)?;
+        }
+      }
+      if !params.files.is_empty() {
+        return Err(RecordError::BadRequest(
+          "File uploads not supported in transactions",
+        ));
+      }
+
+      // Only the primary key, i.e. nothing to update.
+      let query = if params.column_names.len() < 2 {
+        None
+      } else {
+        Some((
+          UpdateQueryBuilder::sql(api.table_name(), &pk_column.name, &params)
+            .map_err(|err| RecordError::Internal(err.into()))?,
+          params.named_params,
+        ))
+      };
+
+      Ok((
+        Statement {
+          kind: StatementKind::Update,
+          access_query,
+          query,
+        },
+        Some(record_id),
+        embed,
+      ))
+    }
+    Operation::Delete { record_id, .. } => {
+      let record_id_value = api.id_to_sql(&record_id)?;
+      let access_query =
+        api.record_level_access_query(Permission::Delete, Some(&record_id_value), None, user)?;
+      if hooks.has(api.api_name(), HookEvent::BeforeDelete) {
+        let context = HookContext::new(
+          HookEvent::BeforeDelete,
+          api.api_name(),
+          Some(&record_id),
+          user,
+        );
+        hooks.run_before(context, None).await?;
+      }
+
+      Ok((
+        Statement {
+          kind: StatementKind::Delete,
+          access_query,
+          query: Some((
+            match api.soft_delete_column() {
+              Some(index) => DeleteQueryBuilder::soft_delete_sql(
+                api.table_name(),
+                &pk_column.name,
+                &api.columns()[index].name,
+              ),
+              None => DeleteQueryBuilder::sql(api.table_name(), &pk_column.name),
+            },
+            vec![(Cow::Borrowed(":__record_id"), record_id_value)],
+          )),
+        },
+        Some(record_id),
+        false,
+      ))
+    }
+  };
+}
+
+/// Executes create, update and delete operations across record APIs atomically.
+///
+/// Operations are applied in order within a single transaction, which is rolled back on the first
+/// error. Access rules are evaluated as part of the transaction and thus observe the writes of
+/// preceding operations, e.g. an order's items can require the order to exist.
+pub async fn transaction_handler(
+  State(state): State<AppState>,
+  user: Option<User>,
+  Json(request): Json<TransactionRequest>,
+) -> Result<Json<TransactionResponse>, RecordError> {
+  if request.operations.is_empty() {
+    return Err(RecordError::BadRequest("no operations provided"));
+  }
+  if request.operations.len() > MAX_OPERATIONS {
+    return Err(RecordError::BadRequest("Transaction exceeds limit: 1024"));
+  }
+
+  let ids = execute_operations(&state, user.as_ref(), request.operations).await?;
+
+  return Ok(Json(TransactionResponse { ids }));
+}
+
+/// Applies the operations atomically and runs their side-effects, returning the ids of created
+/// records. Shared with the sync API's push endpoint.
+///
+/// Record hooks run like for the respective record API endpoints, i.e. before hooks can veto
+/// operations, which aborts the entire transaction, or modify records.
+pub(crate) async fn execute_operations(
+  state: &AppState,
+  user: Option<&User>,
+  operations: Vec<Operation>,
+) -> Result<Vec<String>, RecordError> {
+  let mut statements: Vec<Statement> = Vec::with_capacity(operations.len());
+  let mut side_effects: Vec<SideEffects> = Vec::with_capacity(operations.len());
+  for operation in operations {
+    let api_name = match operation {
+      Operation::Create { ref api_name, .. }
+      | Operation::Update { ref api_name, .. }
+      | Operation::Delete { ref api_name, .. } => api_name,
+    };
+    let Some(api) = state.lookup_record_api(api_name) else {
+      return Err(RecordError::ApiNotFound);
+    };
+
+    let (statement, record_id, embed) = prepare(state, &api, operation, user).await?;
+    let before = match (&user, &statement.kind, &record_id) {
+      (Some(_), StatementKind::Update | StatementKind::Delete, Some(record_id)) => {
+        snapshot_record(state, &api, record_id).await
+      }
+      _ => None,
+    };
+    let deleted = match (&statement.kind, &record_id) {
+      (StatementKind::Delete, Some(record_id))
+        if state
+          .record_hooks()
+          .has(api.api_name(), HookEvent::AfterDelete) =>
+      {
+        read_record_json(state, &api, record_id).await
+      }
+      _ => None,
+    };
+    side_effects.push(SideEffects {
+      // Writes may replace files, e.g. via updates, deletions or conflict resolution.
+      delete_files: api.has_file_columns(),
+      api,
+      record_id,
+      embed,
+      event: match statement.kind {
+        StatementKind::Create => WebhookEvent::Create,
+        StatementKind::Update => WebhookEvent::Update,
+        StatementKind::Delete => WebhookEvent::Delete,
+      },
+      audit: match statement.kind {
+        StatementKind::Create => AuditAction::Create,
+        StatementKind::Update => AuditAction::Update,
+        StatementKind::Delete => AuditAction::Delete,
+      },
+      after_hook: match statement.kind {
+        StatementKind::Create => HookEvent::AfterCreate,
+        StatementKind::Update => HookEvent::AfterUpdate,
+        StatementKind::Delete => HookEvent::AfterDelete,
+      },
+      before,
+      deleted,
+    });
+    statements.push(statement);
+  }
+
+  // Returns the rowid of the written record, if any, and the record id of created records.
+  type Written = (Option<i64>, Option<rusqlite::types::Value>);
+
+  let result: Result<Vec<Written>, RecordError> = state
+    .conn()
+    .call(move |conn| {
+      let tx = conn.transaction()?;
+
+      let mut written = Vec::<Written>::with_capacity(statements.len());
+      for statement in statements {
+        if let Some((access_query, params)) = statement.access_query {
+          let mut stmt = tx.prepare_cached(&access_query)?;
+          params.bind(&mut stmt)?;
+          let mut rows = stmt.raw_query();
+          let allowed: bool = match rows.next()? {
+            Some(row) => row.get::<_, Option<bool>>(0)?.unwrap_or(false),
+            None => false,
+          };
+          if !allowed {
+            // Dropping the transaction rolls back preceding operations.
+            return Ok(Err(RecordError::Forbidden));
+          }
+        }
+
+        let Some((query, params)) = statement.query else {
+          written.push((None, None));
+          continue;
+        };
+
+        let mut stmt = tx.prepare_cached(&query)?;
+        params.bind(&mut stmt)?;
+        let mut rows = stmt.raw_query();
+        let row = rows.next()?;
+        written.push(match (statement.kind, row) {
+          (StatementKind::Create, Some(row)) => (Some(row.get(0)?), Some(row.get(1)?)),
+          (StatementKind::Update, row) => (row.map(|row| row.get(0)).transpose()?, None),
+          (StatementKind::Delete, Some(row)) => (Some(row.get(0)?), None),
+          // E.g. deleting a missing record or creates ignored due to conflicts.
+          (_, None) => {
+            return Ok(Err(RecordError::RecordNotFound));
+          }
+        });
+      }
+
+      tx.commit()?;
+
+      return Ok(Ok(written));
+    })
+    .await?;
+  let written = result?;
+
+  let mut ids: Vec<String> = vec![];
+  for (effects, (rowid, record_id)) in std::iter::zip(side_effects, written) {
+    let api = &effects.api;
+    let record_id = match record_id {
+      Some(record_id) => {
+        let record_id = extract_record_id(record_id)?;
+        ids.push(record_id.clone());
+        Some(record_id)
+      }
+      None => effects.record_id,
+    };
+
+    if let (true, Some(rowid)) = (effects.delete_files, rowid) {
+      if let Err(err) = delete_pending_files(state, api.table_name(), rowid).await {
+        warn!(
+          "Failed to delete pending files for '{}': {err}",
+          api.api_name()
+        );
+      }
+    }
+
+    // Updates without changes don't write a row and thus aren't published or audited.
+    if let (Some(_), Some(record_id)) = (rowid, &record_id) {
+      enqueue_webhooks(state, api, effects.event, std::slice::from_ref(record_id)).await;
+
+      let event = effects.after_hook;
+      let hooks = state.record_hooks();
+      if hooks.has(api.api_name(), event) {
+        let record = match event {
+          HookEvent::AfterDelete => effects.deleted,
+          _ => read_record_json(state, api, record_id).await,
+        };
+        let context = HookContext::new(event, api.api_name(), Some(record_id), user);
+        hooks
+          .run_after(context, record.and_then(|r| r.as_object().cloned()))
+          .await;
+      }
+
+      if user.is_some() {
+        let after = match effects.audit {
+          AuditAction::Delete => None,
+          _ => snapshot_record(state, api, record_id).await,
+        };
+        audit_record_mutation(
+          state,
+          user,
+          api,
+          effects.audit,
+          record_id,
+          effects.before,
+          after,
+        )
+        .await;
+      }
+    }
+
+    if let (true, Some(record_id)) = (effects.embed, record_id) {
+      let api_name = api.api_name();
+      let job = Job::Embed {
+        api_name: api_name.to_string(),
+        record_id,
+      };
+      if let Err(err) = state.queue().push(state, job).await {
+        warn!("Failed to enqueue embedding for '{api_name}': {err}");
+      }
+    }
+  }
+
+  return Ok(ids);
+}
+
+#[cfg(test)]
+mod tests {
+  use futures_util::FutureExt;
+  use parking_lot::Mutex;
+  use serde_json::json;
+
+  use super::*;
+  use crate::app_state::test_state;
+  use crate::config::proto::{PermissionFlag, RecordApiConfig};
+  use crate::records::test_utils::add_record_api_config;
+
+  #[tokio::test]
+  async fn test_transaction() {
+    let state = test_state(None).await.unwrap();
+    state
+      .conn()
+      .execute_batch(
+        r#"
+          CREATE TABLE orders (id INTEGER PRIMARY KEY, total INTEGER NOT NULL DEFAULT 0) STRICT;
+          CREATE TABLE order_items (
+            id        INTEGER PRIMARY KEY,
+            order_id  INTEGER NOT NULL REFERENCES orders(id),
+            price     INTEGER NOT NULL
+          ) STRICT;
+        "#,
+      )
+      .await
+      .unwrap();
+    state.refresh_table_cache().await.unwrap();
+
+    let all = [
+      PermissionFlag::Create as i32,
+      PermissionFlag::Read as i32,
+      PermissionFlag::Update as i32,
+      PermissionFlag::Delete as i32,
+    ];
+    add_record_api_config(
+      &state,
+      RecordApiConfig {
+        name: Some("orders".to_string()),
+        table_name: Some("orders".to_string()),
+        acl_world: all.into(),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+    add_record_api_config(
+      &state,
+      RecordApiConfig {
+        name: Some("order_items".to_string()),
+        table_name: Some("order_items".to_string()),
+        acl_world: all.into(),
+        create_access_rule: Some(
+          "EXISTS(SELECT 1 FROM orders WHERE id = _REQ_.order_id)".to_string(),
+        ),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    let transaction = async |operations: serde_json::Value| -> Result<Vec<String>, RecordError> {
+      let request: TransactionRequest =
+        serde_json::from_value(json!({"operations": operations})).unwrap();
+      let Json(response) = transaction_handler(State(state.clone()), None, Json(request)).await?;
+      return Ok(response.ids);
+    };
+    let count = async |table: &'static str| -> i64 {
+      return state
+        .conn()
+        .read_query_row_f(format!("SELECT COUNT(*) FROM {table}"), (), |row| {
+          row.get(0)
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    };
+
+    // Access rules observe preceding writes, i.e. the item's order.
+    assert_eq!(
+      transaction(json!([
+        {"create": {"api_name": "orders", "value": {"id": 1}}},
+        {"create": {"api_name": "order_items", "value": {"order_id": 1, "price": 5}}},
+        {"update": {"api_name": "orders", "record_id": "1", "value": {"total": 5}}},
+      ]))
+      .await
+      .unwrap(),
+      vec!["1", "1"]
+    );
+    let total: Option<i64> = state
+      .conn()
+      .read_query_row_f("SELECT total FROM orders WHERE id = 1", (), |row| {
+        row.get(0)
+      })
+      .await
+      .unwrap();
+    assert_eq!(total, Some(5));
+
+    // Access rule violations roll back preceding operations.
+    assert!(matches!(
+      transaction(json!([
+        {"create": {"api_name": "orders", "value": {"id": 2}}},
+        {"create": {"api_name": "order_items", "value": {"order_id": 3, "price": 1}}},
+      ]))
+      .await,
+      Err(RecordError::Forbidden)
+    ));
+    assert_eq!(count("orders").await, 1);
+
+    // So do constraint violations.
+    assert!(matches!(
+      transaction(json!([
+        {"create": {"api_name": "order_items", "value": {"order_id": 1, "price": 2}}},
+        {"create": {"api_name": "orders", "value": {"id": 1}}},
+      ]))
+      .await,
+      Err(RecordError::Conflict(..))
+    ));
+    assert_eq!(count("order_items").await, 1);
+
+    // And missing records.
+    assert!(matches!(
+      transaction(json!([
+        {"delete": {"api_name": "order_items", "record_id": "1"}},
+        {"delete": {"api_name": "orders", "record_id": "7"}},
+      ]))
+      .await,
+      Err(RecordError::RecordNotFound)
+    ));
+    assert_eq!(count("order_items").await, 1);
+
+    assert!(matches!(
+      transaction(json!([
+        {"delete": {"api_name": "order_items", "record_id": "1"}},
+        {"delete": {"api_name": "orders", "record_id": "1"}},
+      ]))
+      .await,
+      Ok(ids) if ids.is_empty()
+    ));
+    assert_eq!(count("orders").await, 0);
+
+    assert!(matches!(
+      transaction(json!([{"delete": {"api_name": "unknown", "record_id": "1"}}])).await,
+      Err(RecordError::ApiNotFound)
+    ));
+    assert!(transaction(json!([])).await.is_err());
+  }
+
+  #[tokio::test]
+  async fn test_transaction_hooks() {
+    let state = test_state(None).await.unwrap();
+    state
+      .conn()
+      .execute_batch("CREATE TABLE posts (id INTEGER PRIMARY KEY, title TEXT NOT NULL) STRICT;")
+      .await
+      .unwrap();
+    state.refresh_table_cache().await.unwrap();
+
+    add_record_api_config(
+      &state,
+      RecordApiConfig {
+        name: Some("posts".to_string()),
+        table_name: Some("posts".to_string()),
+        acl_world: [PermissionFlag::Create as i32, PermissionFlag::Delete as i32].into(),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    let hooks = state.record_hooks();
+    hooks.register(
+      "posts",
+      HookEvent::BeforeCreate,
+      Arc::new(|_context: HookContext, record: Option<JsonRow>| {
+        return async move {
+          let mut record = record.unwrap_or_default();
+          if record.get("title") == Some(&json!("forbidden")) {
+            return Err(RecordError::Rejected("forbidden title".to_string()));
+          }
+          record.insert("title".to_string(), json!("rewritten"));
+          return Ok(Some(record));
+        }
+        .boxed();
+      }),
+    );
+    let events: Arc<Mutex<Vec<HookEvent>>> = Arc::default();
+    for event in [HookEvent::AfterCreate, HookEvent::AfterDelete] {
+      let events = events.clone();
+      hooks.register(
+        "posts",
+        event,
+        Arc::new(move |context: HookContext, _record: Option<JsonRow>| {
+          events.lock().push(context.event);
+          return async { Ok::<Option<JsonRow>, RecordError>(None) }.boxed();
+        }),
+      );
+    }
+
+    let transaction = async |operations: serde_json::Value| -> Result<Vec<String>, RecordError> {
+      let request: TransactionRequest =
+        serde_json::from_value(json!({"operations": operations})).unwrap();
+      let Json(response) = transaction_handler(State(state.clone()), None, Json(request)).await?;
+      return Ok(response.ids);
+    };
+
+    // A vetoing before hook rejects the entire transaction.
+    assert!(matches!(
+      transaction(json!([
+        {"create": {"api_name": "posts", "value": {"id": 1, "title": "ok"}}},
+        {"create": {"api_name": "posts", "value": {"id": 2, "title": "forbidden"}}},
+      ]))
+      .await,
+      Err(RecordError::Rejected(_))
+    ));
+    let count: Option<i64> = state
+      .conn()
+      .read_query_row_f("SELECT COUNT(*) FROM posts", (), |row| row.get(0))
+      .await
+      .unwrap();
+    assert_eq!(count, Some(0));
+    assert!(events.lock().is_empty());
+
+    // Before hooks can rewrite records and after hooks observe the committed operations.
+    transaction(json!([
+      {"create": {"api_name": "posts", "value": {"id": 1, "title": "ok"}}},
+      {"delete": {"api_name": "posts", "record_id": "1"}},
+      {"create": {"api_name": "posts", "value": {"id": 2, "title": "ok"}}},
+    ]))
+    .await
+    .unwrap();
+    let title: Option<String> = state
+      .conn()
+      .read_query_row_f("SELECT title FROM posts WHERE id = 2", (), |row| row.get(0))
+      .await
+      .unwrap();
+    assert_eq!(title.as_deref(), Some("rewritten"));
+    assert_eq!(
+      *events.lock(),
+      vec![
+        HookEvent::AfterCreate,
+        HookEvent::AfterDelete,
+        HookEvent::AfterCreate
+      ]
+    );
+  }
+}
@@ -0,0 +1,803 @@
+//! Resumable uploads for file columns following the tus protocol, see https://tus.io/protocols/resumable-upload.
+//!
+//! Supports the core protocol as well as the "creation", "expiration" and "termination"
+//! extensions. Chunks are staged on disk and the completed upload is attached to the record's
+//! `std.FileUpload` column going through the same access checks and validation as regular record
+//! updates.
+
+use axum::body::Body;
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, HeaderName, HeaderValue, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use base64::prelude::*;
+use futures_util::StreamExt;
+use lazy_static::lazy_static;
+use log::*;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use thiserror::Error;
+use tokio::io::AsyncWriteExt;
+use trailbase_schema::FileUploadInput;
+
+use crate::app_state::AppState;
+use crate::auth::user::User;
+use crate::constants::{MAX_REQUEST_BODY_SIZE, RECORD_API_PATH};
+use crate::data_dir::DataDir;
+use crate::records::params::JsonRow;
+use crate::records::update_record::update_record;
+use crate::records::{Permission, RecordApi, RecordError};
+use crate::schema_metadata::JsonColumnMetadata;
+
+const TUS_VERSION: &str = "1.0.0";
+const TUS_EXTENSIONS: &str = "creation,expiration,termination";
+const OFFSET_OCTET_STREAM: &str = "application/offset+octet-stream";
+
+/// Upper bound for the total size of a single upload. Completed uploads are read into memory and
+/// handed to the regular record update path in one piece, thus they're subject to the same limit
+/// as regular multipart uploads.
+const MAX_UPLOAD_LENGTH: u64 = MAX_REQUEST_BODY_SIZE as u64;
+/// Incomplete uploads are discarded after this period.
+const UPLOAD_EXPIRATION: chrono::Duration = chrono::Duration::hours(24);
+
+pub(crate) const TUS_RESUMABLE: HeaderName = HeaderName::from_static("tus-resumable");
+pub(crate) const TUS_VERSION_HEADER: HeaderName = HeaderName::from_static("tus-version");
+pub(crate) const TUS_EXTENSION: HeaderName = HeaderName::from_static("tus-extension");
+pub(crate) const TUS_MAX_SIZE: HeaderName = HeaderName::from_static("tus-max-size");
+pub(crate) const UPLOAD_OFFSET: HeaderName = HeaderName::from_static("upload-offset");
+pub(crate) const UPLOAD_LENGTH: HeaderName = HeaderName::from_static("upload-length");
+pub(crate) const UPLOAD_DEFER_LENGTH: HeaderName = HeaderName::from_static("upload-defer-length");
+pub(crate) const UPLOAD_METADATA: HeaderName = HeaderName::from_static("upload-metadata");
+pub(crate) const UPLOAD_EXPIRES: HeaderName = HeaderName::from_static("upload-expires");
+
+/// Headers browser-based tus clients need to send and read, see CORS.
+pub(crate) const TUS_HEADERS: [HeaderName; 7] = [
+  TUS_RESUMABLE,
+  TUS_VERSION_HEADER,
+  TUS_EXTENSION,
+  TUS_MAX_SIZE,
+  UPLOAD_OFFSET,
+  UPLOAD_LENGTH,
+  UPLOAD_METADATA,
+];
+
+#[derive(Debug, Error)]
+pub enum TusError {
+  #[error("Record error: {0}")]
+  Record(RecordError),
+  #[error("Unsupported tus version")]
+  UnsupportedVersion,
+  #[error("Bad request: {0}")]
+  BadRequest(&'static str),
+  #[error("Upload not found")]
+  NotFound,
+  #[error("Offset mismatch")]
+  OffsetMismatch,
+  #[error("Upload too large")]
+  TooLarge,
+  #[error("Unsupported media type")]
+  UnsupportedMediaType,
+  #[error("IO error: {0}")]
+  IO(#[from] std::io::Error),
+  #[error("Json error: {0}")]
+  Json(#[from] serde_json::Error),
+}
+
+impl From<RecordError> for TusError {
+  fn from(err: RecordError) -> Self {
+    return Self::Record(err);
+  }
+}
+
+impl IntoResponse for TusError {
+  fn into_response(self) -> Response {
+    let status = match self {
+      Self::Record(err) => {
+        let mut response = err.into_response();
+        response
+          .headers_mut()
+          .insert(TUS_RESUMABLE, HeaderValue::from_static(TUS_VERSION));
+        return response;
+      }
+      Self::UnsupportedVersion => StatusCode::PRECONDITION_FAILED,
+      Self::BadRequest(_) => StatusCode::BAD_REQUEST,
+      Self::NotFound => StatusCode::NOT_FOUND,
+      Self::OffsetMismatch => StatusCode::CONFLICT,
+      Self::TooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+      Self::UnsupportedMediaType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+      Self::IO(_) | Self::Json(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+
+    let body = match self {
+      Self::BadRequest(msg) => msg.to_string(),
+      Self::IO(_) | Self::Json(_) => "Internal".to_string(),
+      err => err.to_string(),
+    };
+
+    return (
+      status,
+      [
+        (TUS_RESUMABLE, HeaderValue::from_static(TUS_VERSION)),
+        (TUS_VERSION_HEADER, HeaderValue::from_static(TUS_VERSION)),
+      ],
+      body,
+    )
+      .into_response();
+  }
+}
+
+/// Staged upload state persisted next to the staged data.
+#[derive(Debug, Deserialize, Serialize)]
+struct UploadInfo {
+  api_name: String,
+  record: String,
+  column_name: String,
+  /// Id of the user who created the upload. Only they may continue it.
+  user: Option<String>,
+  length: u64,
+  filename: Option<String>,
+  content_type: Option<String>,
+  /// Creation timestamp in seconds since epoch.
+  created: i64,
+}
+
+impl UploadInfo {
+  fn expires(&self) -> chrono::DateTime<chrono::Utc> {
+    return chrono::DateTime::from_timestamp(self.created, 0).unwrap_or_default()
+      + UPLOAD_EXPIRATION;
+  }
+}
+
+lazy_static! {
+  /// Uploads currently receiving data to reject concurrent, interleaving PATCH requests.
+  static ref IN_FLIGHT: Mutex<HashSet<String>> = Mutex::new(HashSet::new());
+}
+
+struct InFlightGuard(String);
+
+impl InFlightGuard {
+  fn acquire(upload_id: &str) -> Result<Self, TusError> {
+    if !IN_FLIGHT.lock().insert(upload_id.to_string()) {
+      return Err(TusError::OffsetMismatch);
+    }
+    return Ok(Self(upload_id.to_string()));
+  }
+}
+
+impl Drop for InFlightGuard {
+  fn drop(&mut self) {
+    IN_FLIGHT.lock().remove(&self.0);
+  }
+}
+
+type UploadPath = Path<(
+  String, // RecordApi name
+  String, // Record id
+  String, // Column name
+  String, // Upload id
+)>;
+
+fn data_path(data_dir: &DataDir, upload_id: &str) -> std::path::PathBuf {
+  return data_dir.upload_staging_path().join(upload_id);
+}
+
+fn info_path(data_dir: &DataDir, upload_id: &str) -> std::path::PathBuf {
+  return data_dir
+    .upload_staging_path()
+    .join(format!("{upload_id}.json"));
+}
+
+fn check_version(headers: &HeaderMap) -> Result<(), TusError> {
+  return match headers.get(&TUS_RESUMABLE) {
+    Some(version) if version == TUS_VERSION => Ok(()),
+    _ => Err(TusError::UnsupportedVersion),
+  };
+}
+
+fn parse_u64_header(headers: &HeaderMap, name: &HeaderName) -> Result<Option<u64>, TusError> {
+  let Some(value) = headers.get(name) else {
+    return Ok(None);
+  };
+  return value
+    .to_str()
+    .ok()
+    .and_then(|v| v.parse::<u64>().ok())
+    .map(Some)
+    .ok_or(TusError::BadRequest("Invalid numeric header"));
+}
+
+/// Parses `Upload-Metadata`, i.e. comma-separated key and base64-encoded value pairs.
+fn parse_upload_metadata(value: &str) -> Result<Vec<(String, String)>, TusError> {
+  let mut metadata = vec![];
+  for pair in value.split(',').map(|p| p.trim()).filter(|p| !p.is_empty()) {
+    let (key, value) = match pair.split_once(' ') {
+      Some((key, value)) => {
+        let decoded = BASE64_STANDARD
+          .decode(value.trim())
+          .map_err(|_| TusError::BadRequest("Invalid Upload-Metadata encoding"))?;
+        (
+          key,
+          String::from_utf8(decoded)
+            .map_err(|_| TusError::BadRequest("Invalid Upload-Metadata encoding"))?,
+        )
+      }
+      None => (pair, String::new()),
+    };
+    metadata.push((key.to_string(), value));
+  }
+  return Ok(metadata);
+}
+
+/// Resolves the record API and checks that `column_name` holds a single file.
+fn lookup_file_column(
+  state: &AppState,
+  api_name: &str,
+  column_name: &str,
+) -> Result<RecordApi, TusError> {
+  let Some(api) = state.lookup_record_api(api_name) else {
+    return Err(RecordError::ApiNotFound.into());
+  };
+  if !api.is_table() {
+    return Err(RecordError::ApiRequiresTable.into());
+  }
+
+  let Some(index) = api.column_index_by_name(column_name) else {
+    return Err(RecordError::BadRequest("Invalid field/column name").into());
+  };
+  match api.json_column_metadata()[index] {
+    Some(JsonColumnMetadata::SchemaName(ref name, _)) if name == "std.FileUpload" => {}
+    _ => {
+      return Err(RecordError::BadRequest("Expected std.FileUpload column").into());
+    }
+  };
+
+  return Ok(api);
+}
+
+/// Loads the staged upload making sure it belongs to the given record, column and user.
+async fn load_upload(
+  state: &AppState,
+  (api_name, record, column_name, upload_id): &(String, String, String, String),
+  user: Option<&User>,
+) -> Result<UploadInfo, TusError> {
+  if upload_id.is_empty() || !upload_id.chars().all(|c| c.is_ascii_alphanumeric()) {
+    return Err(TusError::NotFound);
+  }
+
+  let contents = match tokio::fs::read(info_path(state.data_dir(), upload_id)).await {
+    Ok(contents) => contents,
+    Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+      return Err(TusError::NotFound);
+    }
+    Err(err) => return Err(err.into()),
+  };
+  let info: UploadInfo = serde_json::from_slice(&contents)?;
+
+  if info.api_name != *api_name
+    || info.record != *record
+    || info.column_name != *column_name
+    || info.user != user.map(|u| u.id.clone())
+    || info.expires() < chrono::Utc::now()
+  {
+    return Err(TusError::NotFound);
+  }
+
+  return Ok(info);
+}
+
+async fn staged_offset(data_dir: &DataDir, upload_id: &str) -> Result<u64, TusError> {
+  return match tokio::fs::metadata(data_path(data_dir, upload_id)).await {
+    Ok(metadata) => Ok(metadata.len()),
+    Err(err) if err.kind() == std::io::ErrorKind::NotFound => Err(TusError::NotFound),
+    Err(err) => Err(err.into()),
+  };
+}
+
+async fn remove_upload(data_dir: &DataDir, upload_id: &str) {
+  for path in [
+    data_path(data_dir, upload_id),
+    info_path(data_dir, upload_id),
+  ] {
+    if let Err(err) = tokio::fs::remove_file(&path).await {
+      if err.kind() != std::io::ErrorKind::NotFound {
+        warn!("Failed to remove staged upload {path:?}: {err}");
+      }
+    }
+  }
+}
+
+fn format_http_date(date: chrono::DateTime<chrono::Utc>) -> String {
+  return date.format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+}
+
+/// Announces the server's tus capabilities.
+pub async fn upload_options_handler() -> Response {
+  return (
+    StatusCode::NO_CONTENT,
+    [
+      (TUS_RESUMABLE, TUS_VERSION.to_string()),
+      (TUS_VERSION_HEADER, TUS_VERSION.to_string()),
+      (TUS_EXTENSION, TUS_EXTENSIONS.to_string()),
+      (TUS_MAX_SIZE, MAX_UPLOAD_LENGTH.to_string()),
+    ],
+  )
+    .into_response();
+}
+
+/// Creates a new resumable upload for the record's file column.
+///
+/// Requires update access to the record. Expects `Upload-Length` and optionally
+/// `Upload-Metadata` with "filename" and "filetype" keys.
+pub async fn create_upload_handler(
+  State(state): State<AppState>,
+  Path((api_name, record, column_name)): Path<(String, String, String)>,
+  user: Option<User>,
+  headers: HeaderMap,
+) -> Result<Response, TusError> {
+  check_version(&headers)?;
+
+  let api = lookup_file_column(&state, &api_name, &column_name)?;
+  let record_id = api.id_to_sql(&record)?;
+  let Ok(()) = api
+    .check_record_level_access(Permission::Update, Some(&record_id), None, user.as_ref())
+    .await
+  else {
+    return Err(RecordError::Forbidden.into());
+  };
+
+  if headers.contains_key(&UPLOAD_DEFER_LENGTH) {
+    return Err(TusError::BadRequest("Deferred upload length not supported"));
+  }
+  let Some(length) = parse_u64_header(&headers, &UPLOAD_LENGTH)? else {
+    return Err(TusError::BadRequest("Missing Upload-Length"));
+  };
+  if length > MAX_UPLOAD_LENGTH {
+    return Err(TusError::TooLarge);
+  }
+
+  let metadata = match headers.get(&UPLOAD_METADATA) {
+    Some(value) => parse_upload_metadata(
+      value
+        .to_str()
+        .map_err(|_| TusError::BadRequest("Invalid Upload-Metadata"))?,
+    )?,
+    None => vec![],
+  };
+  let lookup = |keys: &[&str]| {
+    return metadata
+      .iter()
+      .find(|(k, _)| keys.contains(&k.as_str()))
+      .map(|(_, v)| v.clone());
+  };
+
+  let info = UploadInfo {
+    api_name: api_name.clone(),
+    record: record.clone(),
+    column_name: column_name.clone(),
+    user: user.as_ref().map(|u| u.id.clone()),
+    length,
+    filename: lookup(&["filename", "name"]),
+    content_type: lookup(&["filetype", "type"]),
+    created: chrono::Utc::now().timestamp(),
+  };
+
+  let data_dir = state.data_dir();
+  let upload_id = uuid::Uuid::now_v7().simple().to_string();
+
+  tokio::fs::create_dir_all(data_dir.upload_staging_path()).await?;
+  tokio::fs::File::create_new(data_path(data_dir, &upload_id)).await?;
+  tokio::fs::write(info_path(data_dir, &upload_id), serde_json::to_vec(&info)?).await?;
+
+  return Ok(
+    (
+      StatusCode::CREATED,
+      [
+        (
+          header::LOCATION,
+          format!("/{RECORD_API_PATH}/{api_name}/{record}/upload/{column_name}/{upload_id}"),
+        ),
+        (TUS_RESUMABLE, TUS_VERSION.to_string()),
+        (UPLOAD_EXPIRES, format_http_date(info.expires())),
+      ],
+    )
+      .into_response(),
+  );
+}
+
+/// Returns the offset to resume the upload from.
+pub async fn upload_offset_handler(
+  State(state): State<AppState>,
+  Path(path): UploadPath,
+  user: Option<User>,
+  headers: HeaderMap,
+) -> Result<Response, TusError> {
+  check_version(&headers)?;
+
+  let info = load_upload(&state, &path, user.as_ref()).await?;
+  let offset = staged_offset(state.data_dir(), &path.3).await?;
+
+  return Ok(
+    (
+      StatusCode::OK,
+      [
+        (header::CACHE_CONTROL, "no-store".to_string()),
+        (TUS_RESUMABLE, TUS_VERSION.to_string()),
+        (UPLOAD_OFFSET, offset.to_string()),
+        (UPLOAD_LENGTH, info.length.to_string()),
+        (UPLOAD_EXPIRES, format_http_date(info.expires())),
+      ],
+    )
+      .into_response(),
+  );
+}
+
+/// Appends a chunk at `Upload-Offset`. Once all bytes have been received, the file is attached to
+/// the record. Finalization can be retried with an empty chunk at the final offset, e.g. if the
+/// record update was rejected for transient reasons.
+pub async fn append_upload_handler(
+  State(state): State<AppState>,
+  Path(path): UploadPath,
+  user: Option<User>,
+  headers: HeaderMap,
+  body: Body,
+) -> Result<Response, TusError> {
+  check_version(&headers)?;
+
+  if headers
+    .get(header::CONTENT_TYPE)
+    .is_none_or(|t| t != OFFSET_OCTET_STREAM)
+  {
+    return Err(TusError::UnsupportedMediaType);
+  }
+  let Some(offset) = parse_u64_header(&headers, &UPLOAD_OFFSET)? else {
+    return Err(TusError::BadRequest("Missing Upload-Offset"));
+  };
+
+  let info = load_upload(&state, &path, user.as_ref()).await?;
+  let upload_id = &path.3;
+  let data_dir = state.data_dir();
+
+  let _guard = InFlightGuard::acquire(upload_id)?;
+  if offset != staged_offset(data_dir, upload_id).await? {
+    return Err(TusError::OffsetMismatch);
+  }
+
+  // Append the chunk. Data received before a connection drop is kept, so clients can resume from
+  // the last offset.
+  let mut file = tokio::fs::OpenOptions::new()
+    .append(true)
+    .open(data_path(data_dir, upload_id))
+    .await?;
+  let mut new_offset = offset;
+  let mut stream = body.into_data_stream();
+  let mut result: Result<(), TusError> = Ok(());
+  while let Some(chunk) = stream.next().await {
+    let chunk = match chunk {
+      Ok(chunk) => chunk,
+      Err(err) => {
+        debug!("Upload {upload_id} interrupted: {err}");
+        result = Err(TusError::BadRequest("Upload interrupted"));
+        break;
+      }
+    };
+
+    if new_offset + chunk.len() as u64 > info.length {
+      result = Err(TusError::TooLarge);
+      break;
+    }
+    file.write_all(&chunk).await?;
+    new_offset += chunk.len() as u64;
+  }
+  file.flush().await?;
+  drop(file);
+  result?;
+
+  if new_offset == info.length {
+    finalize_upload(&state, info, upload_id, user.as_ref()).await?;
+  }
+
+  return Ok(
+    (
+      StatusCode::NO_CONTENT,
+      [
+        (TUS_RESUMABLE, TUS_VERSION.to_string()),
+        (UPLOAD_OFFSET, new_offset.to_string()),
+      ],
+    )
+      .into_response(),
+  );
+}
+
+/// Attaches the completed upload to the record's file column.
+async fn finalize_upload(
+  state: &AppState,
+  info: UploadInfo,
+  upload_id: &str,
+  user: Option<&User>,
+) -> Result<(), TusError> {
+  let Some(api) = state.lookup_record_api(&info.api_name) else {
+    return Err(RecordError::ApiNotFound.into());
+  };
+
+  let data = tokio::fs::read(data_path(state.data_dir(), upload_id)).await?;
+  update_record(
+    state,
+    &api,
+    info.record,
+    JsonRow::new(),
+    Some(vec![FileUploadInput {
+      name: Some(info.column_name),
+      filename: info.filename,
+      content_type: info.content_type,
+      data,
+    }]),
+    None,
+    user,
+  )
+  .await?;
+
+  remove_upload(state.data_dir(), upload_id).await;
+
+  return Ok(());
+}
+
+/// Discards an incomplete upload.
+pub async fn terminate_upload_handler(
+  State(state): State<AppState>,
+  Path(path): UploadPath,
+  user: Option<User>,
+  headers: HeaderMap,
+) -> Result<Response, TusError> {
+  check_version(&headers)?;
+
+  load_upload(&state, &path, user.as_ref()).await?;
+  let _guard = InFlightGuard::acquire(&path.3)?;
+  remove_upload(state.data_dir(), &path.3).await;
+
+  return Ok(
+    (
+      StatusCode::NO_CONTENT,
+      [(TUS_RESUMABLE, TUS_VERSION.to_string())],
+    )
+      .into_response(),
+  );
+}
+
+/// Removes expired, incomplete uploads.
+pub(crate) async fn delete_expired_uploads(data_dir: &DataDir) -> Result<(), std::io::Error> {
+  let mut read_dir = match tokio::fs::read_dir(data_dir.upload_staging_path()).await {
+    Ok(read_dir) => read_dir,
+    Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+    Err(err) => return Err(err),
+  };
+
+  let now = chrono::Utc::now();
+  while let Some(entry) = read_dir.next_entry().await? {
+    let path = entry.path();
+    let Some(upload_id) = path
+      .file_name()
+      .and_then(|n| n.to_str())
+      .and_then(|n| n.strip_suffix(".json"))
+    else {
+      continue;
+    };
+
+    let valid = match tokio::fs::read(&path).await {
+      Ok(contents) => {
+        serde_json::from_slice::<UploadInfo>(&contents).is_ok_and(|info| info.expires() >= now)
+      }
+      Err(_) => false,
+    };
+    if !valid {
+      remove_upload(data_dir, upload_id).await;
+    }
+  }
+
+  return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::admin::user::create_user_for_test;
+  use crate::app_state::*;
+  use crate::auth::api::login::login_with_password;
+  use crate::config::proto::PermissionFlag;
+  use crate::records::test_utils::*;
+  use crate::records::{AccessRules, Acls};
+  use crate::util::id_to_b64;
+
+  fn tus_headers(pairs: &[(HeaderName, &str)]) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(TUS_RESUMABLE, HeaderValue::from_static(TUS_VERSION));
+    for (name, value) in pairs {
+      headers.insert(name.clone(), HeaderValue::from_str(value).unwrap());
+    }
+    return headers;
+  }
+
+  #[test]
+  fn test_parse_upload_metadata() {
+    assert_eq!(
+      vec![
+        ("filename".to_string(), "world.png".to_string()),
+        ("is_confidential".to_string(), "".to_string()),
+      ],
+      parse_upload_metadata("filename d29ybGQucG5n, is_confidential").unwrap()
+    );
+    assert!(parse_upload_metadata("filename !!!").is_err());
+  }
+
+  #[tokio::test]
+  async fn test_tus_upload() {
+    let state = test_state(None).await.unwrap();
+    state
+      .conn()
+      .execute_batch(
+        r#"
+          CREATE TABLE tus_test (
+            id    INTEGER PRIMARY KEY,
+            file  TEXT CHECK(jsonschema('std.FileUpload', file))
+          ) STRICT;
+          INSERT INTO tus_test (id) VALUES (1);
+        "#,
+      )
+      .await
+      .unwrap();
+    state.refresh_table_cache().await.unwrap();
+
+    add_record_api(
+      &state,
+      "tus_api",
+      "tus_test",
+      Acls {
+        authenticated: vec![PermissionFlag::Read, PermissionFlag::Update],
+        ..Default::default()
+      },
+      AccessRules::default(),
+    )
+    .await
+    .unwrap();
+
+    let password = "Secret!1!!";
+    let user_x = create_user_for_test(&state, "user_x@test.com", password)
+      .await
+      .unwrap();
+    let user_x_token = login_with_password(&state, "user_x@test.com", password)
+      .await
+      .unwrap();
+    let user = User::from_auth_token(&state, &user_x_token.auth_token);
+    assert_eq!(user.as_ref().unwrap().id, id_to_b64(&user_x.into_bytes()));
+
+    let contents = b"0123456789abcdefghij".to_vec();
+    let record_path = || ("tus_api".to_string(), "1".to_string(), "file".to_string());
+
+    // Unauthenticated users lack update access.
+    assert!(
+      create_upload_handler(
+        State(state.clone()),
+        Path(record_path()),
+        None,
+        tus_headers(&[(UPLOAD_LENGTH, "20")]),
+      )
+      .await
+      .is_err()
+    );
+
+    // Completed uploads are held in memory, thus bound by the request body limit.
+    assert!(matches!(
+      create_upload_handler(
+        State(state.clone()),
+        Path(record_path()),
+        user.clone(),
+        tus_headers(&[(UPLOAD_LENGTH, &(MAX_UPLOAD_LENGTH + 1).to_string())]),
+      )
+      .await,
+      Err(TusError::TooLarge)
+    ));
+
+    let response = create_upload_handler(
+      State(state.clone()),
+      Path(record_path()),
+      user.clone(),
+      tus_headers(&[
+        (UPLOAD_LENGTH, &contents.len().to_string()),
+        (
+          UPLOAD_METADATA,
+          "filename ZGF0YS50eHQ=,filetype dGV4dC9wbGFpbg==",
+        ),
+      ]),
+    )
+    .await
+    .unwrap();
+    assert_eq!(StatusCode::CREATED, response.status());
+    let location = response.headers()[header::LOCATION].to_str().unwrap();
+    let upload_id = location.rsplit('/').next().unwrap().to_string();
+    let upload_path = || {
+      let (api, record, column) = record_path();
+      return Path((api, record, column, upload_id.clone()));
+    };
+
+    let append = async |offset: usize, chunk: &[u8]| {
+      return append_upload_handler(
+        State(state.clone()),
+        upload_path(),
+        user.clone(),
+        tus_headers(&[
+          (UPLOAD_OFFSET, &offset.to_string()),
+          (header::CONTENT_TYPE, OFFSET_OCTET_STREAM),
+        ]),
+        Body::from(chunk.to_vec()),
+      )
+      .await;
+    };
+
+    let response = append(0, &contents[..8]).await.unwrap();
+    assert_eq!("8", response.headers()[UPLOAD_OFFSET]);
+
+    // Wrong offsets are rejected.
+    assert!(matches!(
+      append(4, &contents[4..]).await,
+      Err(TusError::OffsetMismatch)
+    ));
+
+    // Resume from the server-side offset.
+    let response = upload_offset_handler(
+      State(state.clone()),
+      upload_path(),
+      user.clone(),
+      tus_headers(&[]),
+    )
+    .await
+    .unwrap();
+    assert_eq!("8", response.headers()[UPLOAD_OFFSET]);
+    assert_eq!("20", response.headers()[UPLOAD_LENGTH]);
+
+    // Other users cannot see the upload.
+    assert!(matches!(
+      upload_offset_handler(State(state.clone()), upload_path(), None, tus_headers(&[])).await,
+      Err(TusError::NotFound)
+    ));
+
+    let response = append(8, &contents[8..]).await.unwrap();
+    assert_eq!("20", response.headers()[UPLOAD_OFFSET]);
+
+    // The file has been attached and the staged upload removed.
+    let json: String = state
+      .conn()
+      .read_query_row_f("SELECT file FROM tus_test WHERE id = 1", (), |row| {
+        row.get(0)
+      })
+      .await
+      .unwrap()
+      .unwrap();
+    let file: trailbase_schema::FileUpload = serde_json::from_str(&json).unwrap();
+    assert_eq!(Some("data.txt"), file.original_filename());
+    assert!(matches!(
+      upload_offset_handler(
+        State(state.clone()),
+        upload_path(),
+        user.clone(),
+        tus_headers(&[])
+      )
+      .await,
+      Err(TusError::NotFound)
+    ));
+
+    // Version is checked.
+    assert_eq!(
+      StatusCode::NO_CONTENT,
+      upload_options_handler().await.status()
+    );
+    assert!(matches!(
+      create_upload_handler(
+        State(state.clone()),
+        Path(record_path()),
+        user.clone(),
+        HeaderMap::new(),
+      )
+      .await,
+      Err(TusError::UnsupportedVersion)
+    ));
+  }
+}
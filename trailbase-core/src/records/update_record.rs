@@ -1,42 +1,90 @@
 use axum::extract::{Path, State};
+use axum::http::{HeaderMap, header::IF_MATCH};
+use log::*;
+use trailbase_schema::FileUploadInput;
 
 use crate::app_state::AppState;
+use crate::audit::{AuditAction, audit_record_mutation, read_record_json, snapshot_record};
 use crate::auth::user::User;
+use crate::config::proto::WebhookEvent;
 use crate::extract::Either;
+use crate::queue::Job;
+use crate::records::etag::IfMatch;
+use crate::records::hooks::{HookContext, HookEvent};
 use crate::records::params::{JsonRow, LazyParams};
-use crate::records::query_builder::UpdateQueryBuilder;
-use crate::records::{Permission, RecordError};
+use crate::records::query_builder::{QueryError, UpdateQueryBuilder};
+use crate::records::webhooks::enqueue_webhooks;
+use crate::records::{Permission, RecordApi, RecordError};
 
 /// Update existing record.
+///
+/// Honors `If-Match`, i.e. only updates the record if it still matches the given ETag.
 #[utoipa::path(
   patch,
   path = "/:name/:record",
   request_body = serde_json::Value,
   responses(
-    (status = 200, description = "Successful update.")
+    (status = 200, description = "Successful update."),
+    (status = 412, description = "Record changed, i.e. If-Match did not match.")
   )
 )]
 pub async fn update_record_handler(
   State(state): State<AppState>,
   Path((api_name, record)): Path<(String, String)>,
   user: Option<User>,
+  headers: HeaderMap,
   either_request: Either<JsonRow>,
 ) -> Result<(), RecordError> {
   let Some(api) = state.lookup_record_api(&api_name) else {
     return Err(RecordError::ApiNotFound);
   };
 
+  let (request, multipart_files) = match either_request {
+    Either::Json(value) => (value, None),
+    Either::Multipart(value, files) => (value, Some(files)),
+    Either::Form(value) => (value, None),
+    Either::MsgPack(value) | Either::Cbor(value) => (value, None),
+  };
+
+  let if_match = match headers.get(IF_MATCH) {
+    Some(value) => Some(
+      value
+        .to_str()
+        .map_err(|_err| RecordError::BadRequest("Invalid If-Match"))?
+        .to_string(),
+    ),
+    None => None,
+  };
+
+  return update_record(
+    &state,
+    &api,
+    record,
+    request,
+    multipart_files,
+    if_match,
+    user.as_ref(),
+  )
+  .await;
+}
+
+/// Updates a record going through the same access checks, validation and side-effects as record
+/// API requests.
+pub(crate) async fn update_record(
+  state: &AppState,
+  api: &RecordApi,
+  record: String,
+  mut request: JsonRow,
+  multipart_files: Option<Vec<FileUploadInput>>,
+  if_match: Option<String>,
+  user: Option<&User>,
+) -> Result<(), RecordError> {
   if !api.is_table() {
     return Err(RecordError::ApiRequiresTable);
   }
 
   let record_id = api.id_to_sql(&record)?;
-
-  let (mut request, multipart_files) = match either_request {
-    Either::Json(value) => (value, None),
-    Either::Multipart(value, files) => (value, Some(files)),
-    Either::Form(value) => (value, None),
-  };
+  let if_match = if_match.map(|header| IfMatch::new(api, record_id.clone(), header));
 
   let (_index, pk_column) = api.record_pk_column();
   if let Some(existing) = request.insert(
@@ -48,27 +96,99 @@ pub async fn update_record_handler(
     }
   }
 
-  let mut lazy_params = LazyParams::new(&api, request, multipart_files);
+  // Only re-compute embeddings if any of the source columns changed.
+  let update_embedding = api.embedding().is_some_and(|embedding| {
+    return embedding
+      .source_columns
+      .iter()
+      .any(|column| request.contains_key(column));
+  });
+
+  let hooks = state.record_hooks();
+  let hook_record = hooks
+    .has(api.api_name(), HookEvent::BeforeUpdate)
+    .then(|| request.clone());
+
+  let mut lazy_params = LazyParams::new(api, request, multipart_files);
   api
     .check_record_level_access(
       Permission::Update,
       Some(&record_id),
       Some(&mut lazy_params),
-      user.as_ref(),
+      user,
     )
     .await?;
 
+  let mut params = lazy_params.consume().map_err(RecordError::from)?;
+  if let Some(hook_record) = hook_record {
+    let context = HookContext::new(HookEvent::BeforeUpdate, api.api_name(), Some(&record), user);
+    if let Some(changed) = hooks.run_before(context, Some(hook_record)).await? {
+      if changed.contains_key(&pk_column.name) {
+        return Err(RecordError::BadRequest("primary key mismatch"));
+      }
+      params.override_with(api, changed)?;
+    }
+  }
+
+  let before = match user {
+    Some(_) => snapshot_record(state, api, &record).await,
+    None => None,
+  };
+
   UpdateQueryBuilder::run(
-    &state,
+    state,
     api.table_name(),
     &pk_column.name,
     api.has_file_columns(),
-    lazy_params
-      .consume()
-      .map_err(|err| RecordError::Internal(err.into()))?,
+    if_match,
+    params,
   )
   .await
-  .map_err(|err| RecordError::Internal(err.into()))?;
+  .map_err(|err| match err {
+    QueryError::PreconditionFailed => RecordError::PreconditionFailed,
+    err => RecordError::Internal(err.into()),
+  })?;
+
+  enqueue_webhooks(
+    state,
+    api,
+    WebhookEvent::Update,
+    std::slice::from_ref(&record),
+  )
+  .await;
+
+  if hooks.has(api.api_name(), HookEvent::AfterUpdate) {
+    let stored = read_record_json(state, api, &record).await;
+    let context = HookContext::new(HookEvent::AfterUpdate, api.api_name(), Some(&record), user);
+    hooks
+      .run_after(context, stored.and_then(|r| r.as_object().cloned()))
+      .await;
+  }
+
+  if before.is_some() {
+    let after = snapshot_record(state, api, &record).await;
+    audit_record_mutation(
+      state,
+      user,
+      api,
+      AuditAction::Update,
+      &record,
+      before,
+      after,
+    )
+    .await;
+  }
+
+  if update_embedding {
+    let api_name = api.api_name();
+    let job = Job::Embed {
+      api_name: api_name.to_string(),
+      record_id: record,
+    };
+    if let Err(err) = state.queue().push(state, job).await {
+      warn!("Failed to enqueue embedding for '{api_name}': {err}");
+    }
+  }
 
   return Ok(());
 }
@@ -76,6 +196,7 @@ pub async fn update_record_handler(
 #[cfg(test)]
 mod test {
   use axum::extract::Query;
+  use axum::http::header::ETAG;
   use trailbase_sqlite::params;
 
   use super::*;
@@ -88,6 +209,8 @@ mod test {
   use crate::records::create_record::{
     CreateRecordQuery, CreateRecordResponse, create_record_handler,
   };
+  use crate::records::delete_record::delete_record_handler;
+  use crate::records::read_record::{ReadRecordQuery, read_record_handler};
   use crate::records::test_utils::*;
   use crate::records::*;
   use crate::test::unpack_json_response;
@@ -184,6 +307,7 @@ mod test {
         State(state.clone()),
         Path(("messages_api".to_string(), b64_id.clone())),
         User::from_auth_token(&state, &user_x_token.auth_token),
+        HeaderMap::new(),
         Either::Json(json_row_from_value(update_json).unwrap().into()),
       )
       .await;
@@ -210,6 +334,7 @@ mod test {
         State(state.clone()),
         Path(("messages_api".to_string(), b64_id.clone())),
         User::from_auth_token(&state, &user_y_token.auth_token),
+        HeaderMap::new(),
         Either::Json(json_row_from_value(update_json).unwrap().into()),
       )
       .await;
@@ -217,4 +342,140 @@ mod test {
       assert!(update_response.is_err(), "{b64_id} {update_response:?}");
     }
   }
+
+  #[tokio::test]
+  async fn test_record_api_update_if_match() {
+    let state = test_state(None).await.unwrap();
+    let conn = state.conn();
+
+    create_chat_message_app_tables(&state).await.unwrap();
+    let room = add_room(conn, "room0").await.unwrap();
+    let password = "Secret!1!!";
+
+    add_record_api(
+      &state,
+      "messages_api",
+      "message",
+      Acls {
+        authenticated: vec![
+          PermissionFlag::Create,
+          PermissionFlag::Read,
+          PermissionFlag::Update,
+          PermissionFlag::Delete,
+        ],
+        ..Default::default()
+      },
+      AccessRules::default(),
+    )
+    .await
+    .unwrap();
+
+    let user_x_email = "user_x@test.com";
+    let user_x = create_user_for_test(&state, user_x_email, password)
+      .await
+      .unwrap()
+      .into_bytes();
+    let user_x_token = login_with_password(&state, user_x_email, password)
+      .await
+      .unwrap();
+    add_user_to_room(conn, user_x, room).await.unwrap();
+
+    let create_json = serde_json::json!({
+      "_owner": id_to_b64(&user_x),
+      "room": id_to_b64(&room),
+      "data": "user_x message to room",
+    });
+    let create_response: CreateRecordResponse = unpack_json_response(
+      create_record_handler(
+        State(state.clone()),
+        Path("messages_api".to_string()),
+        Query(CreateRecordQuery::default()),
+        User::from_auth_token(state, token),
+        Either::Json(json_row_from_value(create_json).unwrap().into()),
+      )
+      .await
+      .unwrap(),
+    )
+    .await
+    .unwrap();
+    let b64_id = create_response.ids[0].clone();
+
+    let (state, b64_id, token) = (&state, &b64_id, &user_x_token.auth_token);
+    let read_etag = move || async move {
+      let response = read_record_handler(
+        State(state.clone()),
+        Path(("messages_api".to_string(), b64_id.clone())),
+        Query(ReadRecordQuery::default()),
+        User::from_auth_token(state, token),
+      )
+      .await
+      .unwrap();
+      return response
+        .headers()
+        .get(ETAG)
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_string();
+    };
+
+    let update = move |data: &'static str, etag: String| async move {
+      let mut headers = HeaderMap::new();
+      headers.insert(IF_MATCH, etag.parse().unwrap());
+      return update_record_handler(
+        State(state.clone()),
+        Path(("messages_api".to_string(), b64_id.clone())),
+        User::from_auth_token(state, token),
+        headers,
+        Either::Json(
+          json_row_from_value(serde_json::json!({"data": data}))
+            .unwrap()
+            .into(),
+        ),
+      )
+      .await;
+    };
+
+    let etag = read_etag().await;
+    assert_eq!(etag, read_etag().await);
+
+    // Matching ETag succeeds and changes the ETag.
+    update("first update", etag.clone()).await.unwrap();
+    let new_etag = read_etag().await;
+    assert_ne!(etag, new_etag);
+
+    // Stale ETag is rejected and leaves the record untouched.
+    assert!(matches!(
+      update("stale update", etag.clone()).await,
+      Err(RecordError::PreconditionFailed)
+    ));
+    let message_text: String = conn
+      .read_query_value(
+        "SELECT data FROM message WHERE mid = $1",
+        params!(b64_to_id(&b64_id).unwrap()),
+      )
+      .await
+      .unwrap()
+      .unwrap();
+    assert_eq!("first update", message_text);
+
+    // Deletion honors If-Match too.
+    let delete = move |etag: String| async move {
+      let mut headers = HeaderMap::new();
+      headers.insert(IF_MATCH, etag.parse().unwrap());
+      return delete_record_handler(
+        State(state.clone()),
+        Path(("messages_api".to_string(), b64_id.clone())),
+        User::from_auth_token(state, token),
+        headers,
+      )
+      .await;
+    };
+
+    assert!(matches!(
+      delete(etag).await,
+      Err(RecordError::PreconditionFailed)
+    ));
+    delete(new_etag).await.unwrap();
+  }
 }
@@ -1,8 +1,12 @@
 use itertools::Itertools;
-use trailbase_schema::sqlite::ColumnOption;
+use trailbase_schema::sqlite::{ColumnDataType, ColumnOption};
 
 use crate::config::{ConfigError, proto};
-use crate::records::record_api::validate_rule;
+use crate::records::Permission;
+use crate::records::encryption::{ENCRYPTION_KEYS_ENV_VAR, encryption_keys};
+use crate::records::query_builder::resolve_reverse_expansion;
+use crate::records::record_api::{RuleScope, validate_rule};
+use crate::records::validators::{ScriptValidators, build_column_validator};
 use crate::schema_metadata::{SchemaMetadataCache, TableOrViewMetadata};
 
 fn validate_record_api_name(name: &str) -> Result<(), ConfigError> {
@@ -53,11 +57,22 @@ pub(crate) fn validate_record_api_config(
       return ierr(&format!("Missing table or view for API: {api_name}"));
     };
 
-  let Some((pk_index, _)) = metadata.record_pk_column() else {
+  let pk_indexes: Vec<usize> = metadata
+    .record_pk_columns()
+    .into_iter()
+    .map(|(index, _)| index)
+    .collect();
+  if pk_indexes.is_empty() {
     return ierr(&format!(
-      "Table for api '{api_name}' is missing valid integer/uuidv7 primary key column."
+      "Table for api '{api_name}' is missing valid integer/uuid/ulid primary key column."
     ));
-  };
+  }
+
+  if !metadata.record_pk_sortable() && api_config.require_sortable_primary_key.unwrap_or(true) {
+    return ierr(&format!(
+      "Primary key of api '{api_name}' isn't time-sortable. Use a UUIDv7/ULID or set `require_sortable_primary_key: false`."
+    ));
+  }
 
   let Some(columns) = metadata.columns() else {
     return ierr(&format!(
@@ -75,7 +90,7 @@ pub(crate) fn validate_record_api_config(
       ));
     };
 
-    if excluded_index == pk_index {
+    if pk_indexes.contains(&excluded_index) {
       return ierr(&format!(
         "PK column '{excluded_column_name}' cannot be excluded from API '{api_name}'.",
       ));
@@ -95,6 +110,10 @@ pub(crate) fn validate_record_api_config(
     }
 
     let Some(column) = columns.iter().find(|c| c.name == *expand) else {
+      // Otherwise, it may expand the records referencing this API's records.
+      if resolve_reverse_expansion(schemas, table_name, expand).is_some() {
+        continue;
+      }
       return ierr(&format!("{api_name} expands missing column: {expand}"));
     };
 
@@ -147,15 +166,180 @@ pub(crate) fn validate_record_api_config(
     };
   }
 
+  if api_config.max_expand_depth == Some(0) {
+    return ierr(&format!("{api_name} max expand depth must be positive"));
+  }
+
+  for column_validator in &api_config.column_validators {
+    let Some(ref column_name) = column_validator.column_name else {
+      return ierr(&format!("{api_name} column validator misses column name"));
+    };
+
+    if !columns.iter().any(|c| c.name == *column_name) {
+      return ierr(&format!(
+        "{api_name} validates missing column: {column_name}"
+      ));
+    }
+
+    let Some(ref validator) = column_validator.validator else {
+      return ierr(&format!(
+        "{api_name} column validator for '{column_name}' misses validator"
+      ));
+    };
+
+    // Script validators are only registered once scripts are loaded and resolved when applied.
+    if let Err(err) = build_column_validator(
+      validator,
+      column_validator.argument.as_deref(),
+      &ScriptValidators::default(),
+    ) {
+      return ierr(&format!(
+        "{api_name} invalid validator '{validator}' for '{column_name}': {err}"
+      ));
+    }
+  }
+
+  for encrypted_column in &api_config.encrypted_columns {
+    let Some(ref column_name) = encrypted_column.column_name else {
+      return ierr(&format!("{api_name} encrypted column misses column name"));
+    };
+
+    let Some(index) = columns.iter().position(|c| c.name == *column_name) else {
+      return ierr(&format!(
+        "{api_name} encrypts missing column: {column_name}"
+      ));
+    };
+
+    if pk_indexes.contains(&index) {
+      return ierr(&format!(
+        "{api_name} cannot encrypt primary key column: {column_name}"
+      ));
+    }
+
+    if columns[index].data_type != ColumnDataType::Text {
+      return ierr(&format!(
+        "{api_name} encrypts non-TEXT column: {column_name}"
+      ));
+    }
+
+    if encryption_keys().is_none() {
+      return ierr(&format!(
+        "{api_name} encrypts '{column_name}' but no keys are configured, see {ENCRYPTION_KEYS_ENV_VAR}"
+      ));
+    }
+  }
+
+  if !api_config.webhooks.is_empty() && schemas.get_table(table_name).is_none() {
+    return ierr(&format!("{api_name} webhooks require a table"));
+  }
+
+  if let Some(ref embedding) = api_config.embedding {
+    if schemas.get_table(table_name).is_none() {
+      return ierr(&format!("{api_name} embeddings require a table"));
+    }
+
+    if embedding.provider.is_none() {
+      return ierr(&format!("{api_name} embedding misses provider"));
+    }
+
+    if embedding.source_columns.is_empty() {
+      return ierr(&format!("{api_name} embedding misses source columns"));
+    }
+
+    for source_column in &embedding.source_columns {
+      let Some(column) = columns.iter().find(|c| c.name == *source_column) else {
+        return ierr(&format!(
+          "{api_name} embeds missing column: {source_column}"
+        ));
+      };
+
+      if column.data_type != ColumnDataType::Text {
+        return ierr(&format!(
+          "{api_name} embeds non-TEXT column: {source_column}"
+        ));
+      }
+    }
+
+    let Some(ref embedding_column) = embedding.embedding_column else {
+      return ierr(&format!("{api_name} embedding misses embedding column"));
+    };
+
+    let Some(column) = columns.iter().find(|c| c.name == *embedding_column) else {
+      return ierr(&format!(
+        "{api_name} embedding column missing: {embedding_column}"
+      ));
+    };
+
+    if column.data_type != ColumnDataType::Blob {
+      return ierr(&format!(
+        "{api_name} embedding column must be a BLOB: {embedding_column}"
+      ));
+    }
+  }
+
+  if let Some(ref soft_delete_column) = api_config.soft_delete_column {
+    if schemas.get_table(table_name).is_none() {
+      return ierr(&format!("{api_name} soft-deletion requires a table"));
+    }
+
+    let Some(index) = columns.iter().position(|c| c.name == *soft_delete_column) else {
+      return ierr(&format!(
+        "{api_name} soft-delete column missing: {soft_delete_column}"
+      ));
+    };
+
+    if pk_indexes.contains(&index) || columns[index].is_not_null() {
+      return ierr(&format!(
+        "{api_name} soft-delete column must be nullable: {soft_delete_column}"
+      ));
+    }
+
+    if api_config.excluded_columns.contains(soft_delete_column) {
+      return ierr(&format!(
+        "{api_name} cannot exclude soft-delete column: {soft_delete_column}"
+      ));
+    }
+  }
+
+  if api_config.cache_max_bytes == Some(0) {
+    return ierr(&format!("{api_name} cache size limit must be positive"));
+  }
+
+  if let Some(ref list_limits) = api_config.list_limits {
+    if let Err(ConfigError::Invalid(msg)) = crate::config::validate_list_limits(list_limits) {
+      return ierr(&format!("{api_name}: {msg}"));
+    }
+  }
+
+  if api_config.enable_sync == Some(true) {
+    if schemas.get_table(table_name).is_none() {
+      return ierr(&format!("{api_name} sync requires a table"));
+    }
+    if pk_indexes.len() != 1 {
+      return ierr(&format!(
+        "{api_name} sync requires a single-column primary key"
+      ));
+    }
+  }
+
   let rules = [
-    &api_config.create_access_rule,
-    &api_config.read_access_rule,
-    &api_config.update_access_rule,
-    &api_config.delete_access_rule,
-    &api_config.schema_access_rule,
+    (Permission::Create, &api_config.create_access_rule),
+    (Permission::Read, &api_config.read_access_rule),
+    (Permission::Update, &api_config.update_access_rule),
+    (Permission::Delete, &api_config.delete_access_rule),
+    (Permission::Schema, &api_config.schema_access_rule),
   ];
-  for rule in rules.into_iter().flatten() {
-    validate_rule(rule).map_err(ConfigError::Invalid)?;
+  for (permission, rule) in rules {
+    let Some(rule) = rule else {
+      continue;
+    };
+
+    let scope = RuleScope {
+      permission,
+      schemas,
+      columns,
+    };
+    validate_rule(rule, Some(&scope)).map_err(ConfigError::Invalid)?;
   }
 
   return Ok(api_name.to_owned());
@@ -0,0 +1,289 @@
+use lazy_static::lazy_static;
+use parking_lot::RwLock;
+use regex::Regex;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use ts_rs::TS;
+use validator::ValidateEmail;
+
+/// A column validator checks a single JSON value from a create or update request.
+///
+/// Returns a human-readable message on failure. NULL values are passed through and should be
+/// rejected by the column's NOT NULL constraint instead.
+pub type ColumnValidatorFn = dyn Fn(&serde_json::Value) -> Result<(), String> + Send + Sync;
+
+/// Builds a validator from its optional config argument, e.g. the pattern for "regex".
+pub type ColumnValidatorFactory =
+  dyn Fn(Option<&str>) -> Result<Arc<ColumnValidatorFn>, String> + Send + Sync;
+
+/// Validation failure for a specific field of a request.
+#[derive(Debug, Clone, PartialEq, Serialize, TS)]
+#[ts(export)]
+pub struct FieldError {
+  /// Name of the offending field. Violations within JSON columns are suffixed with a JSON pointer,
+  /// e.g. "address/zip".
+  pub field: String,
+  /// Machine-readable failure category: "invalid" for custom validators, "schema" for JSON schema
+  /// violations, "type" for mismatching types, "format" for unparsable values and "unique" or
+  /// "not_null" for violated database constraints.
+  pub code: String,
+  pub message: String,
+}
+
+lazy_static! {
+  static ref VALIDATORS: RwLock<HashMap<String, Arc<ColumnValidatorFactory>>> = {
+    let mut m = HashMap::<String, Arc<ColumnValidatorFactory>>::new();
+    m.insert("regex".to_string(), Arc::new(regex_validator));
+    m.insert("luhn".to_string(), simple(is_luhn, "invalid checksum"));
+    m.insert("email".to_string(), simple(is_email, "invalid email"));
+    m.insert(
+      "phone".to_string(),
+      simple(is_phone, "invalid phone number"),
+    );
+    RwLock::new(m)
+  };
+}
+
+/// Name of the validator looking up script validators, see [`ScriptValidators`].
+const SCRIPT_VALIDATOR: &str = "script";
+
+/// Registers a custom validator under the given name to be referenced from `RecordApiConfig`.
+///
+/// Returns false if a validator with the same name already exists. Needs to happen before the
+/// config is loaded.
+pub fn register_column_validator(
+  name: impl Into<String>,
+  factory: Arc<ColumnValidatorFactory>,
+) -> bool {
+  let mut validators = VALIDATORS.write();
+  let name = name.into();
+  if validators.contains_key(&name) {
+    return false;
+  }
+  validators.insert(name, factory);
+  return true;
+}
+
+/// Validators implemented by scripts, e.g. via `addColumnValidator` in JS. Cheap to clone.
+///
+/// They're referenced from `RecordApiConfig` as the "script" validator with the name as its
+/// argument. Scripts are only loaded after the config, thus script validators are looked up when
+/// applied rather than when the config is loaded.
+#[derive(Clone, Default)]
+pub struct ScriptValidators {
+  validators: Arc<RwLock<HashMap<String, Arc<ColumnValidatorFn>>>>,
+}
+
+impl ScriptValidators {
+  /// Returns false if a script validator with the same name already exists.
+  pub fn register(&self, name: impl Into<String>, validator: Arc<ColumnValidatorFn>) -> bool {
+    let mut validators = self.validators.write();
+    let name = name.into();
+    if validators.contains_key(&name) {
+      return false;
+    }
+    validators.insert(name, validator);
+    return true;
+  }
+
+  fn get(&self, name: &str) -> Option<Arc<ColumnValidatorFn>> {
+    return self.validators.read().get(name).cloned();
+  }
+}
+
+pub(crate) fn build_column_validator(
+  name: &str,
+  argument: Option<&str>,
+  script_validators: &ScriptValidators,
+) -> Result<Arc<ColumnValidatorFn>, String> {
+  if name == SCRIPT_VALIDATOR {
+    return script_validator(argument, script_validators.clone());
+  }
+
+  let Some(factory) = VALIDATORS.read().get(name).cloned() else {
+    return Err(format!("Unknown validator: {name}"));
+  };
+  return factory(argument);
+}
+
+fn simple(f: fn(&str) -> bool, message: &'static str) -> Arc<ColumnValidatorFactory> {
+  return Arc::new(move |argument: Option<&str>| {
+    if argument.is_some() {
+      return Err("Validator does not take an argument".to_string());
+    }
+
+    return Ok(Arc::new(move |value: &serde_json::Value| {
+      return match value {
+        serde_json::Value::Null => Ok(()),
+        serde_json::Value::String(s) if f(s) => Ok(()),
+        serde_json::Value::Number(n) if f(&n.to_string()) => Ok(()),
+        _ => Err(message.to_string()),
+      };
+    }) as Arc<ColumnValidatorFn>);
+  });
+}
+
+fn regex_validator(argument: Option<&str>) -> Result<Arc<ColumnValidatorFn>, String> {
+  let Some(pattern) = argument else {
+    return Err("Regex validator requires a pattern".to_string());
+  };
+  let re = Regex::new(pattern).map_err(|err| err.to_string())?;
+
+  return Ok(Arc::new(move |value: &serde_json::Value| {
+    return match value {
+      serde_json::Value::Null => Ok(()),
+      serde_json::Value::String(s) if re.is_match(s) => Ok(()),
+      _ => Err(format!("does not match '{}'", re.as_str())),
+    };
+  }));
+}
+
+fn script_validator(
+  argument: Option<&str>,
+  script_validators: ScriptValidators,
+) -> Result<Arc<ColumnValidatorFn>, String> {
+  let Some(name) = argument else {
+    return Err("Script validator requires a name".to_string());
+  };
+  let name = name.to_string();
+
+  return Ok(Arc::new(move |value: &serde_json::Value| {
+    if value.is_null() {
+      return Ok(());
+    }
+    let Some(validator) = script_validators.get(&name) else {
+      return Err(format!("unknown script validator '{name}'"));
+    };
+    return validator(value);
+  }));
+}
+
+fn is_luhn(s: &str) -> bool {
+  let digits: Vec<u32> = s
+    .chars()
+    .filter(|c| *c != ' ' && *c != '-')
+    .map(|c| c.to_digit(10))
+    .collect::<Option<_>>()
+    .unwrap_or_default();
+
+  if digits.len() < 2 {
+    return false;
+  }
+
+  let sum: u32 = digits
+    .iter()
+    .rev()
+    .enumerate()
+    .map(|(i, d)| {
+      if i % 2 == 1 {
+        let d = d * 2;
+        if d > 9 { d - 9 } else { d }
+      } else {
+        *d
+      }
+    })
+    .sum();
+
+  return sum % 10 == 0;
+}
+
+fn is_email(s: &str) -> bool {
+  return s.validate_email();
+}
+
+/// Loosely E.164: optional leading '+', 7 to 15 digits, common separators allowed.
+fn is_phone(s: &str) -> bool {
+  let s = s.strip_prefix('+').unwrap_or(s);
+  let mut digits = 0;
+  for c in s.chars() {
+    match c {
+      '0'..='9' => digits += 1,
+      ' ' | '-' | '.' | '(' | ')' => {}
+      _ => return false,
+    }
+  }
+  return (7..=15).contains(&digits);
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+
+  #[test]
+  fn test_builtin_validators() {
+    let scripts = ScriptValidators::default();
+
+    let luhn = build_column_validator("luhn", None, &scripts).unwrap();
+    assert!(luhn(&json!("4111 1111 1111 1111")).is_ok());
+    assert!(luhn(&json!(79927398713_i64)).is_ok());
+    assert!(luhn(&json!("4111 1111 1111 1112")).is_err());
+    assert!(luhn(&json!(null)).is_ok());
+
+    let phone = build_column_validator("phone", None, &scripts).unwrap();
+    assert!(phone(&json!("+1 (555) 123-4567")).is_ok());
+    assert!(phone(&json!("123")).is_err());
+    assert!(phone(&json!("call me")).is_err());
+
+    let email = build_column_validator("email", None, &scripts).unwrap();
+    assert!(email(&json!("foo@bar.org")).is_ok());
+    assert!(email(&json!("foo")).is_err());
+
+    assert!(build_column_validator("regex", None, &scripts).is_err());
+    let re = build_column_validator("regex", Some("^[a-z]+$"), &scripts).unwrap();
+    assert!(re(&json!("abc")).is_ok());
+    assert!(re(&json!("ABC")).is_err());
+    assert!(re(&json!(5)).is_err());
+
+    assert!(build_column_validator("unknown", None, &scripts).is_err());
+  }
+
+  #[test]
+  fn test_register_column_validator() {
+    assert!(register_column_validator(
+      "test_even",
+      Arc::new(|_arg: Option<&str>| {
+        return Ok(Arc::new(|value: &serde_json::Value| {
+          return match value.as_i64() {
+            Some(n) if n % 2 == 0 => Ok(()),
+            _ => Err("odd".to_string()),
+          };
+        }) as Arc<ColumnValidatorFn>);
+      }),
+    ));
+    assert!(!register_column_validator(
+      "luhn",
+      Arc::new(|_arg: Option<&str>| Err("dup".to_string()))
+    ));
+
+    let even = build_column_validator("test_even", None, &ScriptValidators::default()).unwrap();
+    assert!(even(&json!(2)).is_ok());
+    assert!(even(&json!(3)).is_err());
+  }
+
+  #[test]
+  fn test_script_validator() {
+    let scripts = ScriptValidators::default();
+    assert!(build_column_validator("script", None, &scripts).is_err());
+
+    // Script validators may be registered after the validator was built, e.g. from the config.
+    let upper = build_column_validator("script", Some("test_upper"), &scripts).unwrap();
+    assert!(upper(&json!("ABC")).is_err());
+
+    assert!(scripts.register(
+      "test_upper",
+      Arc::new(|value: &serde_json::Value| {
+        return match value.as_str() {
+          Some(s) if s.chars().all(|c| c.is_ascii_uppercase()) => Ok(()),
+          _ => Err("not upper case".to_string()),
+        };
+      }),
+    ));
+    assert!(!scripts.register("test_upper", Arc::new(|_value: &serde_json::Value| Ok(()))));
+
+    assert!(upper(&json!("ABC")).is_ok());
+    assert_eq!(upper(&json!("abc")), Err("not upper case".to_string()));
+    assert!(upper(&json!(null)).is_ok());
+  }
+}
@@ -0,0 +1,442 @@
+use base64::prelude::*;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use lazy_static::lazy_static;
+use log::*;
+use reqwest::header::CONTENT_TYPE;
+use serde::Deserialize;
+use sha2::Sha256;
+use std::time::Duration;
+use trailbase_sqlite::{Connection, params};
+
+use crate::app_state::AppState;
+use crate::config::proto::{Config, WebhookEvent};
+use crate::constants::WEBHOOK_DELIVERY_TABLE;
+use crate::records::RecordApi;
+
+/// Deliveries are marked as failed after this many attempts.
+const MAX_ATTEMPTS: i64 = 10;
+const BASE_BACKOFF_SEC: i64 = 30;
+const MAX_BACKOFF_SEC: i64 = 6 * 60 * 60;
+/// Deliveries are leased for the duration of an attempt, such that concurrent dispatchers don't
+/// deliver them twice.
+const LEASE_SEC: i64 = 60;
+const BATCH_SIZE: i64 = 64;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Successful deliveries are kept around for a week for inspection.
+const DELIVERED_RETENTION_SEC: i64 = 7 * 24 * 60 * 60;
+
+fn event_name(event: WebhookEvent) -> &'static str {
+  return match event {
+    WebhookEvent::Create => "create",
+    WebhookEvent::Update => "update",
+    WebhookEvent::Delete => "delete",
+    WebhookEvent::Undefined => "undefined",
+  };
+}
+
+/// Enqueues deliveries of `event` for the given records to all of the API's webhooks subscribed
+/// to it and kicks off their delivery in the background.
+pub(crate) async fn enqueue_webhooks(
+  state: &AppState,
+  api: &RecordApi,
+  event: WebhookEvent,
+  record_ids: &[String],
+) {
+  let urls: Vec<String> = api
+    .webhooks()
+    .iter()
+    .filter(|w| w.events.is_empty() || w.events.contains(&(event as i32)))
+    .filter_map(|w| w.url.clone())
+    .collect();
+  if urls.is_empty() || record_ids.is_empty() {
+    return;
+  }
+
+  let api_name = api.api_name().to_string();
+  let event = event_name(event);
+  let timestamp = Utc::now().timestamp();
+  let payloads: Vec<String> = record_ids
+    .iter()
+    .map(|record_id| {
+      return serde_json::json!({
+        "type": format!("record.{event}"),
+        "api": api_name,
+        "record_id": record_id,
+        "timestamp": timestamp,
+      })
+      .to_string();
+    })
+    .collect();
+
+  lazy_static! {
+    static ref INSERT_QUERY: String = format!(
+      "INSERT INTO {WEBHOOK_DELIVERY_TABLE} (api, url, event, payload, next_attempt) VALUES ($1, $2, $3, $4, $5)"
+    );
+  }
+
+  let result = state
+    .conn()
+    .call(move |conn| {
+      let tx = conn.transaction()?;
+      {
+        let mut stmt = tx.prepare_cached(&INSERT_QUERY)?;
+        for url in &urls {
+          for payload in &payloads {
+            stmt.execute(rusqlite::params![api_name, url, event, payload, timestamp])?;
+          }
+        }
+      }
+      tx.commit()?;
+
+      return Ok(());
+    })
+    .await;
+
+  if let Err(err) = result {
+    warn!("Failed to enqueue webhooks: {err}");
+    return;
+  }
+
+  let conn = state.conn().clone();
+  let config = state.get_config();
+  tokio::spawn(async move {
+    if let Err(err) = deliver_pending_webhooks(&conn, &config).await {
+      warn!("Failed to deliver webhooks: {err}");
+    }
+  });
+}
+
+#[derive(Debug, Deserialize)]
+struct LeasedDelivery {
+  id: i64,
+  api: String,
+  url: String,
+  payload: String,
+  attempts: i64,
+}
+
+/// Attempts all due deliveries, retrying failed ones with exponential backoff. Invoked right
+/// after mutations and periodically by the `WEBHOOK_DELIVERY` system job.
+pub(crate) async fn deliver_pending_webhooks(
+  conn: &Connection,
+  config: &Config,
+) -> Result<(), trailbase_sqlite::Error> {
+  lazy_static! {
+    static ref LEASE_QUERY: String = format!(
+      r#"
+        UPDATE {WEBHOOK_DELIVERY_TABLE} SET next_attempt = $1, updated = $2
+        WHERE id IN (
+          SELECT id FROM {WEBHOOK_DELIVERY_TABLE}
+          WHERE status = 'pending' AND next_attempt <= $2
+          ORDER BY next_attempt LIMIT $3
+        )
+        RETURNING id, api, url, payload, attempts
+      "#
+    );
+    static ref UPDATE_QUERY: String = format!(
+      r#"
+        UPDATE {WEBHOOK_DELIVERY_TABLE}
+        SET status = $2, attempts = $3, next_attempt = $4, last_error = $5, updated = $6
+        WHERE id = $1
+      "#
+    );
+  }
+
+  let now = Utc::now().timestamp();
+  let deliveries: Vec<LeasedDelivery> = conn
+    .query_as(&*LEASE_QUERY, params!(now + LEASE_SEC, now, BATCH_SIZE))
+    .await?;
+  if deliveries.is_empty() {
+    return Ok(());
+  }
+
+  let client = reqwest::Client::builder()
+    // Following redirects opens the client up to SSRF vulnerabilities.
+    .redirect(reqwest::redirect::Policy::none())
+    .timeout(REQUEST_TIMEOUT)
+    .build()
+    .map_err(|err| trailbase_sqlite::Error::Other(err.into()))?;
+  let signing_key = config.server.webhook_signing_key.as_deref();
+
+  let results = futures_util::future::join_all(deliveries.iter().map(async |delivery| {
+    let configured = config.record_apis.iter().any(|api| {
+      return api.name.as_deref() == Some(&delivery.api)
+        && api
+          .webhooks
+          .iter()
+          .any(|w| w.url.as_deref() == Some(&delivery.url));
+    });
+    if !configured {
+      return Err("Webhook no longer configured".to_string());
+    }
+
+    let Some(signing_key) = signing_key else {
+      return Err("Missing webhook signing key".to_string());
+    };
+
+    return deliver(&client, signing_key, delivery).await;
+  }))
+  .await;
+
+  for (delivery, result) in std::iter::zip(deliveries, results) {
+    let attempts = delivery.attempts + 1;
+    let now = Utc::now().timestamp();
+    let (status, next_attempt, error) = match result {
+      Ok(()) => ("delivered", now, None),
+      Err(err) if attempts >= MAX_ATTEMPTS => ("failed", now, Some(err)),
+      Err(err) => ("pending", now + backoff_sec(attempts), Some(err)),
+    };
+
+    if let Some(ref err) = error {
+      debug!(
+        "Webhook delivery {} to {} failed: {err}",
+        delivery.id, delivery.url
+      );
+    }
+
+    conn
+      .execute(
+        &*UPDATE_QUERY,
+        params!(
+          delivery.id,
+          status.to_string(),
+          attempts,
+          next_attempt,
+          error,
+          now
+        ),
+      )
+      .await?;
+  }
+
+  return Ok(());
+}
+
+/// Deletes successful deliveries past their retention.
+pub(crate) async fn delete_delivered_webhooks(
+  conn: &Connection,
+) -> Result<(), trailbase_sqlite::Error> {
+  conn
+    .execute(
+      format!("DELETE FROM {WEBHOOK_DELIVERY_TABLE} WHERE status = 'delivered' AND updated < $1"),
+      params!(Utc::now().timestamp() - DELIVERED_RETENTION_SEC),
+    )
+    .await?;
+  return Ok(());
+}
+
+async fn deliver(
+  client: &reqwest::Client,
+  signing_key: &str,
+  delivery: &LeasedDelivery,
+) -> Result<(), String> {
+  let id = delivery.id.to_string();
+  let timestamp = Utc::now().timestamp();
+
+  let response = client
+    .post(&delivery.url)
+    .header(CONTENT_TYPE, "application/json")
+    .header("webhook-id", &id)
+    .header("webhook-timestamp", timestamp.to_string())
+    .header(
+      "webhook-signature",
+      format!(
+        "v1,{}",
+        sign(signing_key, &id, timestamp, &delivery.payload)
+      ),
+    )
+    .body(delivery.payload.clone())
+    .send()
+    .await
+    .map_err(|err| err.to_string())?;
+
+  if !response.status().is_success() {
+    return Err(format!("Unexpected status: {}", response.status()));
+  }
+  return Ok(());
+}
+
+/// Signs the payload following the Standard Webhooks scheme, i.e. HMAC-SHA256 over
+/// "{id}.{timestamp}.{payload}", see https://www.standardwebhooks.com.
+fn sign(signing_key: &str, id: &str, timestamp: i64, payload: &str) -> String {
+  let mut mac =
+    Hmac::<Sha256>::new_from_slice(signing_key.as_bytes()).expect("HMAC accepts keys of any size");
+  mac.update(format!("{id}.{timestamp}.{payload}").as_bytes());
+  return BASE64_STANDARD.encode(mac.finalize().into_bytes());
+}
+
+fn backoff_sec(attempts: i64) -> i64 {
+  let exponent = (attempts - 1).clamp(0, 20) as u32;
+  return (BASE_BACKOFF_SEC << exponent).min(MAX_BACKOFF_SEC);
+}
+
+#[cfg(test)]
+mod tests {
+  use axum::extract::State;
+  use axum::http::{HeaderMap, StatusCode};
+  use axum::routing::{Router, post};
+  use axum_test::{TestServer, TestServerConfig};
+  use parking_lot::Mutex;
+  use std::sync::Arc;
+
+  use super::*;
+  use crate::app_state::test_state;
+  use crate::config::proto::{RecordApiConfig, WebhookConfig};
+
+  const SIGNING_KEY: &str = "whsec_test";
+
+  type Received = Arc<Mutex<Vec<(HeaderMap, String)>>>;
+
+  #[derive(Debug, Deserialize)]
+  struct Row {
+    status: String,
+    attempts: i64,
+    last_error: Option<String>,
+  }
+
+  async fn deliveries(conn: &Connection) -> Vec<Row> {
+    return conn
+      .read_query_as::<Row>(
+        format!("SELECT status, attempts, last_error FROM {WEBHOOK_DELIVERY_TABLE}"),
+        (),
+      )
+      .await
+      .unwrap();
+  }
+
+  #[test]
+  fn test_backoff() {
+    assert_eq!(backoff_sec(1), BASE_BACKOFF_SEC);
+    assert_eq!(backoff_sec(2), 2 * BASE_BACKOFF_SEC);
+    assert_eq!(backoff_sec(3), 4 * BASE_BACKOFF_SEC);
+    assert_eq!(backoff_sec(1000), MAX_BACKOFF_SEC);
+  }
+
+  #[tokio::test]
+  async fn test_webhook_delivery() {
+    let received: Received = Default::default();
+    let app = Router::new()
+      .route(
+        "/hook",
+        post(
+          |State(received): State<Received>, headers: HeaderMap, body: String| async move {
+            let mut received = received.lock();
+            received.push((headers, body));
+
+            // Fail the first attempt.
+            return match received.len() {
+              1 => StatusCode::INTERNAL_SERVER_ERROR,
+              _ => StatusCode::OK,
+            };
+          },
+        ),
+      )
+      .with_state(received.clone());
+
+    let server = TestServer::new_with_config(
+      app,
+      TestServerConfig {
+        transport: Some(axum_test::Transport::HttpRandomPort),
+        ..Default::default()
+      },
+    )
+    .unwrap();
+    let url = server.server_url("/hook").unwrap().to_string();
+
+    let state = test_state(None).await.unwrap();
+    state
+      .conn()
+      .execute_batch("CREATE TABLE item (id INTEGER PRIMARY KEY, name TEXT) STRICT;")
+      .await
+      .unwrap();
+    state.schema_metadata().invalidate_all().await.unwrap();
+
+    let mut config = state.get_config();
+    config.record_apis.push(RecordApiConfig {
+      name: Some("items".to_string()),
+      table_name: Some("item".to_string()),
+      webhooks: vec![WebhookConfig {
+        url: Some(url.clone()),
+        events: vec![WebhookEvent::Update as i32],
+      }],
+      ..Default::default()
+    });
+
+    // Webhooks require a signing key.
+    assert!(
+      state
+        .validate_and_update_config(config.clone(), None)
+        .await
+        .is_err()
+    );
+
+    config.server.webhook_signing_key = Some(SIGNING_KEY.to_string());
+    state
+      .validate_and_update_config(config, None)
+      .await
+      .unwrap();
+
+    // The webhook isn't subscribed to creations.
+    let api = state.lookup_record_api("items").unwrap();
+    enqueue_webhooks(&state, &api, WebhookEvent::Create, &["1".to_string()]).await;
+    assert!(deliveries(state.conn()).await.is_empty());
+
+    let payload = r#"{"type":"record.update","api":"items","record_id":"1"}"#;
+    state
+      .conn()
+      .execute(
+        format!(
+          "INSERT INTO {WEBHOOK_DELIVERY_TABLE} (api, url, event, payload) VALUES ('items', $1, 'update', $2)"
+        ),
+        params!(url, payload.to_string()),
+      )
+      .await
+      .unwrap();
+
+    let config = state.get_config();
+    deliver_pending_webhooks(state.conn(), &config)
+      .await
+      .unwrap();
+
+    let rows = deliveries(state.conn()).await;
+    assert_eq!(rows[0].status, "pending");
+    assert_eq!(rows[0].attempts, 1);
+    assert!(rows[0].last_error.is_some());
+
+    // The retry isn't due yet.
+    deliver_pending_webhooks(state.conn(), &config)
+      .await
+      .unwrap();
+    assert_eq!(received.lock().len(), 1);
+
+    state
+      .conn()
+      .execute(
+        format!("UPDATE {WEBHOOK_DELIVERY_TABLE} SET next_attempt = 0"),
+        (),
+      )
+      .await
+      .unwrap();
+    deliver_pending_webhooks(state.conn(), &config)
+      .await
+      .unwrap();
+
+    let rows = deliveries(state.conn()).await;
+    assert_eq!(rows[0].status, "delivered");
+    assert_eq!(rows[0].attempts, 2);
+
+    let (headers, body) = received.lock().pop().unwrap();
+    assert_eq!(body, payload);
+
+    let header = |name: &str| headers.get(name).unwrap().to_str().unwrap().to_string();
+    let timestamp: i64 = header("webhook-timestamp").parse().unwrap();
+    assert_eq!(
+      header("webhook-signature"),
+      format!(
+        "v1,{}",
+        sign(SIGNING_KEY, &header("webhook-id"), timestamp, payload)
+      )
+    );
+  }
+}
@@ -0,0 +1,629 @@
+use chrono::Utc;
+use futures_util::TryStreamExt;
+use log::*;
+use object_store::ObjectStore;
+use object_store::path::Path as ObjectPath;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::io::AsyncWriteExt;
+use trailbase_sqlite::Connection;
+
+use crate::app_state::{AppState, build_objectstore};
+use crate::backup::{BackupError, upload_file};
+use crate::config::{ConfigError, read_config_with_secrets_unvalidated};
+use crate::connection::{backup_options, open_with_database_key};
+use crate::constants::WAL_TRUNCATE_THRESHOLD_DEFAULT;
+use crate::data_dir::DataDir;
+
+/// Replicas are stored as "replication/<generation>/snapshot.db" followed by
+/// "replication/<generation>/wal/<index>.wal" segments of committed WAL frames.
+const OBJECT_STORE_PREFIX: &str = "replication";
+const SNAPSHOT: &str = "snapshot.db";
+
+const SYNC_INTERVAL_DEFAULT_MS: u64 = 1000;
+const RETENTION_GENERATIONS_DEFAULT: usize = 2;
+
+const WAL_HEADER_SIZE: u64 = 32;
+const WAL_FRAME_HEADER_SIZE: usize = 24;
+const WAL_MAGIC_LE: u32 = 0x377f0682;
+const WAL_MAGIC_BE: u32 = 0x377f0683;
+
+#[derive(Debug, Error)]
+pub enum ReplicationError {
+  #[error("SQLite error: {0}")]
+  Sqlite(#[from] trailbase_sqlite::Error),
+  #[error("Rusqlite error: {0}")]
+  Rusqlite(#[from] rusqlite::Error),
+  #[error("Connection error: {0}")]
+  Connection(#[from] crate::connection::ConnectionError),
+  #[error("IO error: {0}")]
+  Io(#[from] std::io::Error),
+  #[error("Object store error: {0}")]
+  ObjectStore(#[from] object_store::Error),
+  #[error("Backup error: {0}")]
+  Backup(#[from] BackupError),
+  #[error("Config error: {0}")]
+  Config(#[from] ConfigError),
+  #[error("Replication error: {0}")]
+  Invalid(String),
+}
+
+/// Position within the WAL up to which frames have been shipped.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct WalPosition {
+  /// Salts of the current WAL or None after the WAL was reset, i.e. any salts are expected next.
+  salt: Option<(u32, u32)>,
+  /// Offset past the last shipped commit frame.
+  offset: u64,
+  /// Cumulative checksum of the frame at `offset`.
+  checksum: (u32, u32),
+}
+
+impl WalPosition {
+  const RESET: Self = Self {
+    salt: None,
+    offset: WAL_HEADER_SIZE,
+    checksum: (0, 0),
+  };
+}
+
+#[derive(Debug, PartialEq)]
+enum WalRead {
+  Frames {
+    frames: Vec<u8>,
+    next: WalPosition,
+  },
+  /// The WAL was reset without us knowing, i.e. frames may have been lost.
+  Discontinuity,
+}
+
+struct Generation {
+  name: String,
+  position: WalPosition,
+  index: u64,
+}
+
+/// Ships committed WAL frames of the main database to the object store.
+///
+/// NOTE: Checkpoints that reset the WAL could drop frames before they're shipped. The replicator
+/// therefore takes over checkpointing, i.e. automatic checkpoints and the "WAL Checkpoint" job
+/// are disabled. Any WAL reset not observed, e.g. by an external process, starts a new
+/// generation.
+struct Replicator {
+  conn: Connection,
+  wal_path: PathBuf,
+  data_dir: DataDir,
+  truncate_threshold: u64,
+  retention: usize,
+  generation: Option<Generation>,
+}
+
+impl Replicator {
+  async fn sync(
+    &mut self,
+    object_store: &(dyn ObjectStore + Send + Sync),
+  ) -> Result<(), ReplicationError> {
+    // Taken s.t. failures leave no generation behind, i.e. a new one is started on the next sync.
+    let Some(mut generation) = self.generation.take() else {
+      self.generation = Some(self.start_generation(object_store).await?);
+      return Ok(());
+    };
+
+    let wal_path = self.wal_path.clone();
+    let position = generation.position;
+    let threshold = self.truncate_threshold;
+
+    // Runs on the writer to be serialized with both, writes and checkpoints.
+    let read = self
+      .conn
+      .call(move |conn| {
+        let read = read_committed_frames(&wal_path, &position)
+          .map_err(|err| trailbase_sqlite::Error::Other(err.into()))?;
+
+        let WalRead::Frames { frames, next } = read else {
+          return Ok(WalRead::Discontinuity);
+        };
+        if next.offset < threshold {
+          return Ok(WalRead::Frames { frames, next });
+        }
+
+        let busy: i64 = conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", (), |row| row.get(0))?;
+        return Ok(WalRead::Frames {
+          frames,
+          next: if busy == 0 { WalPosition::RESET } else { next },
+        });
+      })
+      .await?;
+
+    let WalRead::Frames { frames, next } = read else {
+      return Err(ReplicationError::Invalid("WAL discontinuity".to_string()));
+    };
+
+    if !frames.is_empty() {
+      let location = ObjectPath::from(format!(
+        "{OBJECT_STORE_PREFIX}/{}/wal/{:016x}.wal",
+        generation.name, generation.index
+      ));
+      object_store.put(&location, frames.into()).await?;
+      generation.index += 1;
+    }
+
+    generation.position = next;
+    self.generation = Some(generation);
+    return Ok(());
+  }
+
+  async fn start_generation(
+    &self,
+    object_store: &(dyn ObjectStore + Send + Sync),
+  ) -> Result<Generation, ReplicationError> {
+    let wal_path = self.wal_path.clone();
+    let position = self
+      .conn
+      .call(move |_conn| {
+        return match read_committed_frames(&wal_path, &WalPosition::RESET) {
+          Ok(WalRead::Frames { next, .. }) => Ok(next),
+          Ok(WalRead::Discontinuity) => Ok(WalPosition::RESET),
+          Err(err) => Err(trailbase_sqlite::Error::Other(err.into())),
+        };
+      })
+      .await?;
+
+    let name = format!(
+      "{}-{:08x}",
+      Utc::now().format("%Y%m%dT%H%M%SZ"),
+      rand::random::<u32>()
+    );
+
+    // The snapshot is taken after determining the position. It may therefore already contain
+    // some of the subsequently shipped frames, which is fine since re-applying them is
+    // idempotent.
+    let snapshot = self.data_dir.data_path().join(".replication-snapshot.db");
+    let _ = tokio::fs::remove_file(&snapshot).await;
+    self.conn.backup(snapshot.clone(), backup_options()).await?;
+
+    let location = ObjectPath::from(format!("{OBJECT_STORE_PREFIX}/{name}/{SNAPSHOT}"));
+    let result = upload_file(object_store, &snapshot, &location).await;
+    tokio::fs::remove_file(&snapshot).await?;
+    result?;
+
+    delete_old_generations(object_store, self.retention).await?;
+
+    info!("Started replication generation: {name}");
+    return Ok(Generation {
+      name,
+      position,
+      index: 0,
+    });
+  }
+}
+
+/// Continuously replicates the main database if `server.replication` is enabled.
+pub(crate) async fn spawn_replication(state: &AppState) -> Result<(), ReplicationError> {
+  let Some(config) = state.access_config(|c| c.server.replication.clone()) else {
+    return Ok(());
+  };
+  if config.enabled != Some(true) {
+    return Ok(());
+  }
+
+  let conn = state.conn().clone();
+  let Some(main_path) = conn
+    .call(|conn| {
+      return Ok(conn.path().filter(|p| !p.is_empty()).map(str::to_string));
+    })
+    .await?
+  else {
+    warn!("Replication is not supported for in-memory databases");
+    return Ok(());
+  };
+
+  // Frames must not be checkpointed before they're shipped.
+  conn.set_wal_autocheckpoint(0).await?;
+
+  let mut replicator = Replicator {
+    conn,
+    wal_path: PathBuf::from(format!("{main_path}-wal")),
+    data_dir: state.data_dir().clone(),
+    truncate_threshold: state
+      .access_config(|c| c.server.wal_truncate_threshold_bytes)
+      .unwrap_or(WAL_TRUNCATE_THRESHOLD_DEFAULT),
+    retention: config
+      .retention_generations
+      .map_or(RETENTION_GENERATIONS_DEFAULT, |n| n as usize),
+    generation: None,
+  };
+  let interval = config
+    .sync_interval_ms
+    .map_or(SYNC_INTERVAL_DEFAULT_MS, |ms| ms as u64);
+
+  let state = state.clone();
+  tokio::spawn(async move {
+    let mut interval = tokio::time::interval(Duration::from_millis(interval));
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+      interval.tick().await;
+      if let Err(err) = replicator.sync(state.objectstore()).await {
+        warn!("Replication failed, starting new generation: {err}");
+      }
+    }
+  });
+
+  return Ok(());
+}
+
+/// Rebuilds the main database of a fresh data directory from the latest replication generation.
+///
+/// The object store is taken from the target's config, i.e. config and secrets need to be
+/// restored first. Returns the restored generation.
+pub async fn restore_replica(target: &DataDir) -> Result<String, ReplicationError> {
+  let main_db = target.main_db_path();
+  if tokio::fs::try_exists(&main_db).await? {
+    return Err(ReplicationError::Invalid(format!(
+      "{main_db:?} already exists, restore into a fresh data directory"
+    )));
+  }
+
+  let Some(config) = read_config_with_secrets_unvalidated(target).await? else {
+    return Err(ReplicationError::Invalid(format!(
+      "Missing config in {:?}, needed to access the object store",
+      target.config_path()
+    )));
+  };
+  let object_store = build_objectstore(target, &config.server)?;
+  target.ensure_directory_structure().await?;
+
+  let mut generations = list_generations(&*object_store).await?;
+  generations.sort_by(|a, b| b.cmp(a));
+
+  let tmp = target.data_path().join(".restore.tmp");
+  for generation in generations {
+    let snapshot = ObjectPath::from(format!("{OBJECT_STORE_PREFIX}/{generation}/{SNAPSHOT}"));
+    let mut stream = match object_store.get(&snapshot).await {
+      Ok(result) => result.into_stream(),
+      // E.g. the server stopped before the snapshot was uploaded.
+      Err(object_store::Error::NotFound { .. }) => continue,
+      Err(err) => return Err(err.into()),
+    };
+
+    let mut file = tokio::fs::File::create_new(&tmp).await?;
+    while let Some(chunk) = stream.try_next().await? {
+      file.write_all(&chunk).await?;
+    }
+    file.flush().await?;
+    drop(file);
+
+    if let Err(err) = apply_segments(&*object_store, &generation, &tmp).await {
+      let _ = tokio::fs::remove_file(&tmp).await;
+      return Err(err);
+    }
+
+    tokio::fs::rename(&tmp, &main_db).await?;
+    info!("Restored replication generation {generation} into: {main_db:?}");
+    return Ok(generation);
+  }
+
+  return Err(ReplicationError::Invalid("No replica found".to_string()));
+}
+
+async fn apply_segments(
+  object_store: &(dyn ObjectStore + Send + Sync),
+  generation: &str,
+  path: &Path,
+) -> Result<(), ReplicationError> {
+  let prefix = ObjectPath::from(format!("{OBJECT_STORE_PREFIX}/{generation}/wal"));
+  let mut segments: Vec<_> = object_store
+    .list(Some(&prefix))
+    .map_ok(|meta| meta.location)
+    .try_collect()
+    .await?;
+  segments.sort();
+
+  let mut file = std::fs::OpenOptions::new()
+    .read(true)
+    .write(true)
+    .open(path)?;
+  let mut header = [0u8; 100];
+  file.read_exact(&mut header)?;
+  let page_size = match u16::from_be_bytes([header[16], header[17]]) {
+    1 => 65536,
+    size => size as usize,
+  };
+
+  for segment in segments {
+    let frames = object_store.get(&segment).await?.bytes().await?;
+    file = tokio::task::spawn_blocking(move || -> Result<File, ReplicationError> {
+      apply_frames(&mut file, page_size, &frames)?;
+      return Ok(file);
+    })
+    .await
+    .map_err(|err| ReplicationError::Invalid(err.to_string()))??;
+  }
+  file.sync_all()?;
+  drop(file);
+
+  let path = path.to_path_buf();
+  return tokio::task::spawn_blocking(move || -> Result<(), ReplicationError> {
+    let check: String =
+      open_with_database_key(&path)?.query_row("PRAGMA quick_check", (), |row| row.get(0))?;
+    if check != "ok" {
+      return Err(ReplicationError::Invalid(format!(
+        "Integrity check failed: {check}"
+      )));
+    }
+    return Ok(());
+  })
+  .await
+  .map_err(|err| ReplicationError::Invalid(err.to_string()))?;
+}
+
+/// Writes the pages of shipped frames into the database file, akin to a checkpoint.
+fn apply_frames(file: &mut File, page_size: usize, frames: &[u8]) -> Result<(), ReplicationError> {
+  let frame_size = WAL_FRAME_HEADER_SIZE + page_size;
+  if frames.len() % frame_size != 0 {
+    return Err(ReplicationError::Invalid(
+      "Malformed WAL segment".to_string(),
+    ));
+  }
+
+  for frame in frames.chunks_exact(frame_size) {
+    let page = be_u32(frame, 0) as u64;
+    if page == 0 {
+      return Err(ReplicationError::Invalid("Malformed WAL frame".to_string()));
+    }
+    file.seek(SeekFrom::Start((page - 1) * page_size as u64))?;
+    file.write_all(&frame[WAL_FRAME_HEADER_SIZE..])?;
+
+    // Commit frames carry the database size in pages.
+    let db_size = be_u32(frame, 4) as u64;
+    if db_size != 0 {
+      file.set_len(db_size * page_size as u64)?;
+    }
+  }
+
+  return Ok(());
+}
+
+async fn list_generations(
+  object_store: &(dyn ObjectStore + Send + Sync),
+) -> Result<Vec<String>, ReplicationError> {
+  let prefix = ObjectPath::from(OBJECT_STORE_PREFIX);
+  return Ok(
+    object_store
+      .list_with_delimiter(Some(&prefix))
+      .await?
+      .common_prefixes
+      .iter()
+      .filter_map(|p| p.filename().map(|name| name.to_string()))
+      .collect(),
+  );
+}
+
+async fn delete_old_generations(
+  object_store: &(dyn ObjectStore + Send + Sync),
+  retention: usize,
+) -> Result<(), ReplicationError> {
+  let mut generations = list_generations(object_store).await?;
+  generations.sort_by(|a, b| b.cmp(a));
+
+  for generation in generations.iter().skip(retention) {
+    debug!("Deleting replication generation: {generation}");
+    let prefix = ObjectPath::from(format!("{OBJECT_STORE_PREFIX}/{generation}"));
+    let locations: Vec<_> = object_store
+      .list(Some(&prefix))
+      .map_ok(|meta| meta.location)
+      .try_collect()
+      .await?;
+    for location in locations {
+      object_store.delete(&location).await?;
+    }
+  }
+
+  return Ok(());
+}
+
+/// Reads the frames of complete transactions past `position`, verifying salts and checksums like
+/// SQLite does during recovery.
+fn read_committed_frames(path: &Path, position: &WalPosition) -> std::io::Result<WalRead> {
+  let empty = || {
+    return match position.salt {
+      Some(_) => WalRead::Discontinuity,
+      None => WalRead::Frames {
+        frames: vec![],
+        next: *position,
+      },
+    };
+  };
+
+  let mut file = match File::open(path) {
+    Ok(file) => file,
+    Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(empty()),
+    Err(err) => return Err(err),
+  };
+
+  let mut header = [0u8; WAL_HEADER_SIZE as usize];
+  match file.read_exact(&mut header) {
+    Ok(_) => {}
+    Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(empty()),
+    Err(err) => return Err(err),
+  };
+
+  let big_endian = match be_u32(&header, 0) {
+    WAL_MAGIC_BE => true,
+    WAL_MAGIC_LE => false,
+    _ => return Ok(empty()),
+  };
+  let header_checksum = wal_checksum(big_endian, &header[..24], (0, 0));
+  if header_checksum != (be_u32(&header, 24), be_u32(&header, 28)) {
+    return Ok(empty());
+  }
+  let page_size = be_u32(&header, 8) as usize;
+  let salt = (be_u32(&header, 16), be_u32(&header, 20));
+
+  let (offset, mut checksum) = match position.salt {
+    Some(s) if s == salt => (position.offset, position.checksum),
+    Some(_) => return Ok(WalRead::Discontinuity),
+    None => (WAL_HEADER_SIZE, header_checksum),
+  };
+  file.seek(SeekFrom::Start(offset))?;
+
+  let mut frames = vec![];
+  let mut committed = (0, checksum);
+  let mut frame = vec![0u8; WAL_FRAME_HEADER_SIZE + page_size];
+  loop {
+    match file.read_exact(&mut frame) {
+      Ok(_) => {}
+      Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => break,
+      Err(err) => return Err(err),
+    };
+
+    // Frames with other salts or mismatching checksums are left-overs of previous WALs.
+    if frame[8..16] != header[16..24] {
+      break;
+    }
+    checksum = wal_checksum(big_endian, &frame[..8], checksum);
+    checksum = wal_checksum(big_endian, &frame[WAL_FRAME_HEADER_SIZE..], checksum);
+    if checksum != (be_u32(&frame, 16), be_u32(&frame, 20)) {
+      break;
+    }
+
+    frames.extend_from_slice(&frame);
+    if be_u32(&frame, 4) != 0 {
+      committed = (frames.len(), checksum);
+    }
+  }
+  frames.truncate(committed.0);
+
+  return Ok(WalRead::Frames {
+    next: WalPosition {
+      salt: Some(salt),
+      offset: offset + committed.0 as u64,
+      checksum: committed.1,
+    },
+    frames,
+  });
+}
+
+/// SQLite's WAL checksum over 8-byte words in the byte order given by the WAL magic.
+fn wal_checksum(big_endian: bool, data: &[u8], (mut s0, mut s1): (u32, u32)) -> (u32, u32) {
+  for word in data.chunks_exact(8) {
+    let (x0, x1) = if big_endian {
+      (be_u32(word, 0), be_u32(word, 4))
+    } else {
+      (
+        u32::from_le_bytes([word[0], word[1], word[2], word[3]]),
+        u32::from_le_bytes([word[4], word[5], word[6], word[7]]),
+      )
+    };
+    s0 = s0.wrapping_add(x0).wrapping_add(s1);
+    s1 = s1.wrapping_add(x1).wrapping_add(s0);
+  }
+  return (s0, s1);
+}
+
+#[inline]
+fn be_u32(data: &[u8], offset: usize) -> u32 {
+  return u32::from_be_bytes([
+    data[offset],
+    data[offset + 1],
+    data[offset + 2],
+    data[offset + 3],
+  ]);
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_ship_and_apply_wal_frames() {
+    let tmp_dir = temp_dir::TempDir::new().unwrap();
+    let db_path = tmp_dir.path().join("main.db");
+    let wal_path = tmp_dir.path().join("main.db-wal");
+
+    let conn = rusqlite::Connection::open(&db_path).unwrap();
+    conn
+      .execute_batch(
+        r#"
+          PRAGMA journal_mode = WAL;
+          PRAGMA wal_autocheckpoint = 0;
+          CREATE TABLE item (id INTEGER PRIMARY KEY, name TEXT) STRICT;
+          INSERT INTO item (name) VALUES ('a');
+          PRAGMA wal_checkpoint(TRUNCATE);
+        "#,
+      )
+      .unwrap();
+
+    // Snapshot of a fully checkpointed database.
+    let snapshot_path = tmp_dir.path().join("snapshot.db");
+    std::fs::copy(&db_path, &snapshot_path).unwrap();
+
+    conn
+      .execute_batch("INSERT INTO item (name) VALUES ('b'), ('c');")
+      .unwrap();
+    let WalRead::Frames {
+      frames: first,
+      next,
+    } = read_committed_frames(&wal_path, &WalPosition::RESET).unwrap()
+    else {
+      panic!("expected frames");
+    };
+    assert!(!first.is_empty());
+
+    // Nothing new.
+    assert_eq!(
+      read_committed_frames(&wal_path, &next).unwrap(),
+      WalRead::Frames {
+        frames: vec![],
+        next,
+      }
+    );
+
+    conn
+      .execute_batch("UPDATE item SET name = 'x' WHERE id = 1; DELETE FROM item WHERE id = 2;")
+      .unwrap();
+    let WalRead::Frames {
+      frames: second,
+      next,
+    } = read_committed_frames(&wal_path, &next).unwrap()
+    else {
+      panic!("expected frames");
+    };
+    assert!(!second.is_empty());
+
+    let page_size: i64 = conn
+      .query_row("PRAGMA page_size", (), |row| row.get(0))
+      .unwrap();
+    let mut snapshot = std::fs::OpenOptions::new()
+      .write(true)
+      .open(&snapshot_path)
+      .unwrap();
+    apply_frames(&mut snapshot, page_size as usize, &first).unwrap();
+    apply_frames(&mut snapshot, page_size as usize, &second).unwrap();
+    drop(snapshot);
+
+    let restored = rusqlite::Connection::open(&snapshot_path).unwrap();
+    let mut stmt = restored
+      .prepare("SELECT name FROM item ORDER BY id")
+      .unwrap();
+    let names: Vec<String> = stmt
+      .query_map((), |row| row.get(0))
+      .unwrap()
+      .collect::<Result<_, _>>()
+      .unwrap();
+    assert_eq!(names, ["x", "c"]);
+
+    // A checkpoint not observed by the replicator resets the WAL.
+    conn
+      .execute_batch("PRAGMA wal_checkpoint(TRUNCATE); INSERT INTO item (name) VALUES ('d');")
+      .unwrap();
+    assert_eq!(
+      read_committed_frames(&wal_path, &next).unwrap(),
+      WalRead::Discontinuity
+    );
+  }
+}
@@ -0,0 +1,341 @@
+use chrono::{Duration, Utc};
+use log::*;
+use trailbase_schema::sqlite::ColumnDataType;
+use trailbase_sqlite::{Connection, params};
+
+use crate::config::proto::{RetentionAction, RetentionPolicy};
+use crate::config::{ConfigError, proto};
+use crate::schema_metadata::SchemaMetadataCache;
+
+/// Outcome of evaluating a single retention policy.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct RetentionReport {
+  pub table_name: String,
+  pub action: RetentionAction,
+  pub cutoff: i64,
+  pub affected_rows: usize,
+  pub dry_run: bool,
+}
+
+pub(crate) fn validate_retention_policy(
+  tables: &SchemaMetadataCache,
+  policy: &proto::RetentionPolicy,
+) -> Result<(), ConfigError> {
+  let ierr = |msg: String| Err(ConfigError::Invalid(msg));
+
+  let Some(ref table_name) = policy.table_name else {
+    return ierr("Retention policy misses table name".to_string());
+  };
+
+  let Some(table) = tables.get_table(table_name) else {
+    return ierr(format!("Missing table for retention policy: {table_name}"));
+  };
+
+  let Some(ref timestamp_column) = policy.timestamp_column else {
+    return ierr(format!(
+      "Retention policy for '{table_name}' misses timestamp column"
+    ));
+  };
+  let Some((_, column)) = table.column_by_name(timestamp_column) else {
+    return ierr(format!(
+      "Retention policy for '{table_name}' references missing column: {timestamp_column}"
+    ));
+  };
+  // Cutoffs are UNIX timestamps in seconds, which don't compare meaningfully with e.g. dates
+  // stored as TEXT.
+  match column.data_type {
+    ColumnDataType::Integer
+    | ColumnDataType::Int
+    | ColumnDataType::TinyInt
+    | ColumnDataType::SmallInt
+    | ColumnDataType::MediumInt
+    | ColumnDataType::BigInt
+    | ColumnDataType::UnignedBigInt
+    | ColumnDataType::Int2
+    | ColumnDataType::Int4
+    | ColumnDataType::Int8
+    | ColumnDataType::Real
+    | ColumnDataType::Double
+    | ColumnDataType::DoublePrecision
+    | ColumnDataType::Float => {}
+    _ => {
+      return ierr(format!(
+        "Retention policy for '{table_name}' requires an INTEGER or REAL timestamp column: {timestamp_column}"
+      ));
+    }
+  };
+
+  match policy.max_age_sec {
+    Some(max_age) if max_age > 0 => {}
+    _ => {
+      return ierr(format!(
+        "Retention policy for '{table_name}' requires a positive max age"
+      ));
+    }
+  };
+
+  match policy.action() {
+    RetentionAction::Undefined => {
+      return ierr(format!("Retention policy for '{table_name}' misses action"));
+    }
+    RetentionAction::Delete => {
+      if !policy.anonymize_columns.is_empty() {
+        return ierr(format!(
+          "Retention policy for '{table_name}' sets anonymize columns for a delete action"
+        ));
+      }
+    }
+    RetentionAction::Anonymize => {
+      if policy.anonymize_columns.is_empty() {
+        return ierr(format!(
+          "Retention policy for '{table_name}' misses columns to anonymize"
+        ));
+      }
+
+      for column_name in &policy.anonymize_columns {
+        let Some((_, column)) = table.column_by_name(column_name) else {
+          return ierr(format!(
+            "Retention policy for '{table_name}' references missing column: {column_name}"
+          ));
+        };
+
+        if column.is_primary() || column.is_not_null() {
+          return ierr(format!(
+            "Cannot anonymize non-nullable column '{column_name}' of '{table_name}'"
+          ));
+        }
+      }
+    }
+  };
+
+  return Ok(());
+}
+
+/// Applies a single policy and records the outcome in `_retention_audit`.
+///
+/// Expects a policy that already passed `validate_retention_policy`.
+pub(crate) async fn apply_retention_policy(
+  conn: &Connection,
+  policy: &RetentionPolicy,
+) -> Result<RetentionReport, trailbase_sqlite::Error> {
+  let table_name = policy.table_name().to_string();
+  let timestamp_column = policy.timestamp_column();
+  let action = policy.action();
+  let dry_run = policy.dry_run.unwrap_or(false);
+  let cutoff = (Utc::now() - Duration::seconds(policy.max_age_sec.unwrap_or(0))).timestamp();
+
+  // Already anonymized rows shouldn't be reported again on every run.
+  let filter = match action {
+    RetentionAction::Anonymize => format!(
+      r#""{timestamp_column}" < $1 AND ({})"#,
+      policy
+        .anonymize_columns
+        .iter()
+        .map(|c| format!(r#""{c}" IS NOT NULL"#))
+        .collect::<Vec<_>>()
+        .join(" OR ")
+    ),
+    _ => format!(r#""{timestamp_column}" < $1"#),
+  };
+
+  let affected_rows: usize = if dry_run {
+    let count: i64 = conn
+      .read_query_row_f(
+        format!(r#"SELECT COUNT(*) FROM "{table_name}" WHERE {filter}"#),
+        params!(cutoff),
+        |row| row.get(0),
+      )
+      .await?
+      .unwrap_or(0);
+    count as usize
+  } else {
+    match action {
+      RetentionAction::Delete => {
+        conn
+          .execute(
+            format!(r#"DELETE FROM "{table_name}" WHERE {filter}"#),
+            params!(cutoff),
+          )
+          .await?
+      }
+      RetentionAction::Anonymize => {
+        let assignments = policy
+          .anonymize_columns
+          .iter()
+          .map(|c| format!(r#""{c}" = NULL"#))
+          .collect::<Vec<_>>()
+          .join(", ");
+        conn
+          .execute(
+            format!(r#"UPDATE "{table_name}" SET {assignments} WHERE {filter}"#),
+            params!(cutoff),
+          )
+          .await?
+      }
+      RetentionAction::Undefined => 0,
+    }
+  };
+
+  let action_name = match action {
+    RetentionAction::Delete => "delete",
+    RetentionAction::Anonymize => "anonymize",
+    RetentionAction::Undefined => "undefined",
+  };
+
+  conn
+    .execute(
+      "INSERT INTO _retention_audit (table_name, action, cutoff, affected_rows, dry_run) VALUES ($1, $2, $3, $4, $5)",
+      params!(
+        table_name.clone(),
+        action_name,
+        cutoff,
+        affected_rows as i64,
+        dry_run
+      ),
+    )
+    .await?;
+
+  if dry_run {
+    info!("Retention (dry run): {affected_rows} rows of '{table_name}' would be {action_name}d");
+  } else if affected_rows > 0 {
+    info!("Retention: {action_name}d {affected_rows} rows of '{table_name}'");
+  }
+
+  return Ok(RetentionReport {
+    table_name,
+    action,
+    cutoff,
+    affected_rows,
+    dry_run,
+  });
+}
+
+pub(crate) async fn apply_retention_policies(
+  conn: &Connection,
+  policies: &[RetentionPolicy],
+) -> Result<Vec<RetentionReport>, trailbase_sqlite::Error> {
+  let mut reports = Vec::with_capacity(policies.len());
+  for policy in policies {
+    reports.push(apply_retention_policy(conn, policy).await?);
+  }
+  return Ok(reports);
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[tokio::test]
+  async fn test_retention_policies() {
+    let state = crate::app_state::test_state(None).await.unwrap();
+    let conn = state.conn();
+
+    conn
+      .execute_batch(
+        r#"
+          CREATE TABLE retained (
+            id         INTEGER PRIMARY KEY NOT NULL,
+            email      TEXT,
+            created    INTEGER NOT NULL
+          ) STRICT;
+
+          INSERT INTO retained (email, created) VALUES
+            ('old@test.org', UNIXEPOCH() - 1000),
+            ('new@test.org', UNIXEPOCH());
+        "#,
+      )
+      .await
+      .unwrap();
+
+    let anonymize = RetentionPolicy {
+      table_name: Some("retained".to_string()),
+      timestamp_column: Some("created".to_string()),
+      max_age_sec: Some(100),
+      action: Some(RetentionAction::Anonymize as i32),
+      anonymize_columns: vec!["email".to_string()],
+      dry_run: Some(true),
+    };
+
+    let dry_run = apply_retention_policy(conn, &anonymize).await.unwrap();
+    assert_eq!(1, dry_run.affected_rows);
+
+    let report = apply_retention_policy(
+      conn,
+      &RetentionPolicy {
+        dry_run: Some(false),
+        ..anonymize.clone()
+      },
+    )
+    .await
+    .unwrap();
+    assert_eq!(1, report.affected_rows);
+
+    // Anonymized rows are not reported twice.
+    let report = apply_retention_policy(conn, &anonymize).await.unwrap();
+    assert_eq!(0, report.affected_rows);
+
+    let report = apply_retention_policy(
+      conn,
+      &RetentionPolicy {
+        action: Some(RetentionAction::Delete as i32),
+        anonymize_columns: vec![],
+        dry_run: None,
+        ..anonymize
+      },
+    )
+    .await
+    .unwrap();
+    assert_eq!(1, report.affected_rows);
+
+    let remaining: i64 = conn
+      .read_query_row_f("SELECT COUNT(*) FROM retained", (), |row| row.get(0))
+      .await
+      .unwrap()
+      .unwrap();
+    assert_eq!(1, remaining);
+
+    let audit_entries: i64 = conn
+      .read_query_row_f("SELECT COUNT(*) FROM _retention_audit", (), |row| {
+        row.get(0)
+      })
+      .await
+      .unwrap()
+      .unwrap();
+    assert_eq!(4, audit_entries);
+  }
+
+  #[tokio::test]
+  async fn test_validate_retention_policy() {
+    let state = crate::app_state::test_state(None).await.unwrap();
+    state
+      .conn()
+      .execute_batch(
+        r#"
+          CREATE TABLE retained (
+            id         INTEGER PRIMARY KEY NOT NULL,
+            created    INTEGER NOT NULL,
+            updated    REAL,
+            date       TEXT
+          ) STRICT;
+        "#,
+      )
+      .await
+      .unwrap();
+    state.refresh_table_cache().await.unwrap();
+
+    let policy = |timestamp_column: &str| RetentionPolicy {
+      table_name: Some("retained".to_string()),
+      timestamp_column: Some(timestamp_column.to_string()),
+      max_age_sec: Some(100),
+      action: Some(RetentionAction::Delete as i32),
+      anonymize_columns: vec![],
+      dry_run: None,
+    };
+
+    let tables = state.schema_metadata();
+    assert!(validate_retention_policy(tables, &policy("created")).is_ok());
+    assert!(validate_retention_policy(tables, &policy("updated")).is_ok());
+    assert!(validate_retention_policy(tables, &policy("date")).is_err());
+    assert!(validate_retention_policy(tables, &policy("missing")).is_err());
+  }
+}
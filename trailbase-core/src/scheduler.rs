@@ -10,12 +10,20 @@ use std::sync::{
   Arc,
   atomic::{AtomicI32, Ordering},
 };
-use trailbase_sqlite::{Connection, params};
+use trailbase_sqlite::{CheckpointMode, Connection, params};
 
 use crate::DataDir;
+use crate::audit::delete_expired_audit_log_entries;
+use crate::backup::{BackupError, run_backup};
 use crate::config::proto::{Config, SystemJob, SystemJobId};
-use crate::constants::{DEFAULT_REFRESH_TOKEN_TTL, LOGS_RETENTION_DEFAULT, SESSION_TABLE};
+use crate::constants::{
+  DEFAULT_REFRESH_TOKEN_TTL, JOB_RUN_TABLE, JOB_STATE_TABLE, LOGS_RETENTION_DEFAULT, SESSION_TABLE,
+  WAL_TRUNCATE_THRESHOLD_DEFAULT,
+};
 use crate::records::files::{FileDeletionsDb, FileError, delete_pending_files_impl};
+use crate::records::tus_upload::delete_expired_uploads;
+use crate::records::webhooks::{delete_delivered_webhooks, deliver_pending_webhooks};
+use crate::retention::apply_retention_policies;
 
 type CallbackError = Box<dyn std::error::Error + Sync + Send>;
 type CallbackFunction = dyn Fn() -> BoxFuture<'static, Result<(), CallbackError>> + Sync + Send;
@@ -28,6 +36,18 @@ pub struct ExecutionResult {
 
 static JOB_ID_COUNTER: AtomicI32 = AtomicI32::new(1024);
 
+/// Number of most recent runs kept per job in the job history.
+const JOB_HISTORY_LIMIT: i64 = 100;
+
+/// Parses a cron spec. In addition to shorthands, e.g. "@daily", and 6/7-component specs with
+/// seconds, standard 5-component specs, e.g. "0 3 * * *", are accepted and run at second 0.
+pub fn parse_schedule(spec: &str) -> Result<Schedule, cron::error::Error> {
+  if spec.split_whitespace().count() == 5 {
+    return Schedule::from_str(&format!("0 {spec}"));
+  }
+  return Schedule::from_str(spec);
+}
+
 pub trait CallbackResultTrait {
   fn into_result(self) -> Result<(), CallbackError>;
 }
@@ -52,16 +72,35 @@ struct JobState {
 
   handle: Option<tokio::task::AbortHandle>,
   latest: Option<ExecutionResult>,
+  /// Whether a run is in progress, which prevents overlapping runs.
+  in_flight: bool,
+}
+
+/// Clears a job's in-flight flag once a run completes or is aborted.
+struct InFlight(Arc<Mutex<JobState>>);
+
+impl Drop for InFlight {
+  fn drop(&mut self) {
+    self.0.lock().in_flight = false;
+  }
 }
 
 #[derive(Clone)]
 pub struct Job {
   pub id: i32,
   state: Arc<Mutex<JobState>>,
+  /// Connection for persisting the job's state and history, if any.
+  history: Option<Connection>,
 }
 
 impl Job {
-  fn new(id: i32, name: String, schedule: Schedule, callback: Box<CallbackFunction>) -> Self {
+  fn new(
+    id: i32,
+    name: String,
+    schedule: Schedule,
+    callback: Box<CallbackFunction>,
+    history: Option<Connection>,
+  ) -> Self {
     return Job {
       id,
       state: Arc::new(Mutex::new(JobState {
@@ -70,7 +109,9 @@ impl Job {
         callback: callback.into(),
         handle: None,
         latest: None,
+        in_flight: false,
       })),
+      history,
     };
   }
 
@@ -97,9 +138,17 @@ impl Job {
             continue;
           };
 
+          if let Some(ref conn) = job.history {
+            if let Err(err) = store_next_run(conn, &name, next).await {
+              warn!("Failed to persist next run of '{name}': {err}");
+            }
+          }
+
           tokio::time::sleep(duration).await;
 
-          let _ = job.run_now().await;
+          if let Err(err) = job.run_now().await {
+            debug!("Job '{name}' failed: {err}");
+          }
         }
 
         info!("Exited job: '{name}'");
@@ -109,13 +158,28 @@ impl Job {
   }
 
   async fn run_now(&self) -> Result<(), String> {
-    let callback = self.state.lock().callback.clone();
+    let (name, callback) = {
+      let mut lock = self.state.lock();
+      if lock.in_flight {
+        return Err(format!("Job '{}' is already running", lock.name));
+      }
+      lock.in_flight = true;
+      (lock.name.clone(), lock.callback.clone())
+    };
+    let _in_flight = InFlight(self.state.clone());
 
     let start_time = Utc::now();
     let result = callback().await;
     let end_time = Utc::now();
 
     let result_str = result.as_ref().map_err(|err| err.to_string()).copied();
+    if let Some(ref conn) = self.history {
+      let error = result_str.as_ref().err().cloned();
+      if let Err(err) = record_run(conn, &name, start_time, end_time, error).await {
+        warn!("Failed to record run of '{name}': {err}");
+      }
+    }
+
     self.state.lock().latest = Some(ExecutionResult {
       start_time,
       end_time,
@@ -166,12 +230,23 @@ impl Job {
 
 pub struct JobRegistry {
   pub(crate) jobs: Mutex<HashMap<i32, Job>>,
+  history: Option<Connection>,
 }
 
 impl JobRegistry {
   pub fn new() -> Self {
     return JobRegistry {
       jobs: Mutex::new(HashMap::new()),
+      history: None,
+    };
+  }
+
+  /// Registry persisting its jobs' last and next runs as well as their history in the given
+  /// database.
+  pub fn with_history(conn: Connection) -> Self {
+    return JobRegistry {
+      jobs: Mutex::new(HashMap::new()),
+      history: Some(conn),
     };
   }
 
@@ -187,7 +262,13 @@ impl JobRegistry {
       Entry::Occupied(_) => None,
       Entry::Vacant(entry) => Some(
         entry
-          .insert(Job::new(id, name.into(), schedule, callback))
+          .insert(Job::new(
+            id,
+            name.into(),
+            schedule,
+            callback,
+            self.history.clone(),
+          ))
           .clone(),
       ),
     };
@@ -213,6 +294,66 @@ impl Drop for JobRegistry {
   }
 }
 
+async fn store_next_run(
+  conn: &Connection,
+  name: &str,
+  next: DateTime<Utc>,
+) -> Result<(), trailbase_sqlite::Error> {
+  conn
+    .execute(
+      format!(
+        "INSERT INTO {JOB_STATE_TABLE} (name, next_run) VALUES ($1, $2) \
+         ON CONFLICT (name) DO UPDATE SET next_run = excluded.next_run"
+      ),
+      params!(name.to_string(), next.timestamp()),
+    )
+    .await?;
+  return Ok(());
+}
+
+/// Appends a run to the job's history, trimmed to the most recent [`JOB_HISTORY_LIMIT`] runs,
+/// and updates the job's state.
+async fn record_run(
+  conn: &Connection,
+  name: &str,
+  start_time: DateTime<Utc>,
+  end_time: DateTime<Utc>,
+  error: Option<String>,
+) -> Result<(), trailbase_sqlite::Error> {
+  let name = name.to_string();
+  let started = start_time.timestamp();
+  let duration_ms = (end_time - start_time).num_milliseconds();
+
+  return conn
+    .call(move |conn| {
+      let tx = conn.transaction()?;
+      tx.execute(
+        &format!(
+          "INSERT INTO {JOB_RUN_TABLE} (name, started, duration_ms, error) VALUES ($1, $2, $3, $4)"
+        ),
+        rusqlite::params![name, started, duration_ms, error],
+      )?;
+      tx.execute(
+        &format!(
+          "INSERT INTO {JOB_STATE_TABLE} (name, last_run) VALUES ($1, $2) \
+           ON CONFLICT (name) DO UPDATE SET last_run = excluded.last_run"
+        ),
+        rusqlite::params![name, started],
+      )?;
+      tx.execute(
+        &format!(
+          "DELETE FROM {JOB_RUN_TABLE} WHERE name = $1 AND id <= \
+           (SELECT id FROM {JOB_RUN_TABLE} WHERE name = $1 ORDER BY id DESC LIMIT 1 OFFSET $2)"
+        ),
+        rusqlite::params![name, JOB_HISTORY_LIMIT],
+      )?;
+      tx.commit()?;
+
+      return Ok(());
+    })
+    .await;
+}
+
 pub fn build_callback<O, F, Fut>(f: F) -> Box<CallbackFunction>
 where
   F: 'static + Sync + Send + Fn() -> Fut,
@@ -255,8 +396,9 @@ fn build_job(
       }),
     },
     SystemJobId::Backup => {
-      let backup_file = data_dir.backup_path().join("backup.db");
       let conn = conn.clone();
+      let data_dir = data_dir.clone();
+      let backup_config = config.server.backup.clone().unwrap_or_default();
 
       DefaultSystemJob {
         name: "Backup",
@@ -267,24 +409,19 @@ fn build_job(
         },
         callback: build_callback(move || {
           let conn = conn.clone();
-          let backup_file = backup_file.clone();
+          let data_dir = data_dir.clone();
+          let backup_config = backup_config.clone();
+          let object_store = object_store.clone();
 
           return async move {
-            conn
-              .call(|conn| {
-                return Ok(conn.backup(
-                  rusqlite::DatabaseName::Main,
-                  backup_file,
-                  /* progress= */ None,
-                )?);
-              })
+            run_backup(&conn, &data_dir, &*object_store, &backup_config, Utc::now())
               .await
               .map_err(|err| {
                 error!("Backup failed: {err}");
                 err
               })?;
 
-            Ok::<(), trailbase_sqlite::Error>(())
+            Ok::<(), BackupError>(())
           };
         }),
       }
@@ -302,11 +439,17 @@ fn build_job(
       }),
     },
     SystemJobId::LogCleaner => {
+      let conn = conn.clone();
       let logs_conn = logs_conn.clone();
       let retention = config
         .server
         .logs_retention_sec
         .map_or(LOGS_RETENTION_DEFAULT, Duration::seconds);
+      let audit_log_retention_sec = config
+        .server
+        .audit_log
+        .as_ref()
+        .and_then(|a| a.retention_sec);
 
       DefaultSystemJob {
         name: "Logs Cleanup",
@@ -316,6 +459,7 @@ fn build_job(
           disabled: Some(false),
         },
         callback: build_callback(move || {
+          let conn = conn.clone();
           let logs_conn = logs_conn.clone();
 
           return async move {
@@ -328,6 +472,13 @@ fn build_job(
                 err
               })?;
 
+            delete_expired_audit_log_entries(&conn, audit_log_retention_sec)
+              .await
+              .map_err(|err| {
+                warn!("Periodic audit log cleanup failed: {err}");
+                err
+              })?;
+
             Ok::<(), trailbase_sqlite::Error>(())
           };
         }),
@@ -395,6 +546,7 @@ fn build_job(
     }
     SystemJobId::FileDeletions => {
       let conn = conn.clone();
+      let data_dir = data_dir.clone();
 
       DefaultSystemJob {
         name: "File Deletions",
@@ -406,11 +558,15 @@ fn build_job(
         callback: build_callback(move || {
           let conn = conn.clone();
           let object_store = object_store.clone();
+          let data_dir = data_dir.clone();
           return async move {
             let _ = tokio::spawn(async move {
-              if let Err(err) = delete_pending_files_job(&conn, &*object_store).await {
+              if let Err(err) = delete_pending_files_job(&conn, &*object_store, &data_dir).await {
                 warn!("Failed to delete files: {err}");
               }
+              if let Err(err) = delete_expired_uploads(&data_dir).await {
+                warn!("Failed to delete expired uploads: {err}");
+              }
             })
             .await;
             return Ok::<(), trailbase_sqlite::Error>(());
@@ -418,12 +574,136 @@ fn build_job(
         }),
       }
     }
+    SystemJobId::DataRetention => {
+      let conn = conn.clone();
+      let policies = config.jobs.retention_policies.clone();
+
+      DefaultSystemJob {
+        name: "Data Retention",
+        default: SystemJob {
+          id: Some(id as i32),
+          schedule: Some("@daily".into()),
+          disabled: Some(false),
+        },
+        callback: build_callback(move || {
+          let conn = conn.clone();
+          let policies = policies.clone();
+
+          return async move {
+            apply_retention_policies(&conn, &policies)
+              .await
+              .map_err(|err| {
+                warn!("Data retention failed: {err}");
+                err
+              })?;
+
+            Ok::<(), trailbase_sqlite::Error>(())
+          };
+        }),
+      }
+    }
+    SystemJobId::WalCheckpoint => {
+      let conn = conn.clone();
+      let threshold = config
+        .server
+        .wal_truncate_threshold_bytes
+        .unwrap_or(WAL_TRUNCATE_THRESHOLD_DEFAULT);
+      // Checkpoints are taken over by replication, which must ship frames first.
+      let replication = config
+        .server
+        .replication
+        .as_ref()
+        .is_some_and(|r| r.enabled == Some(true));
+
+      DefaultSystemJob {
+        name: "WAL Checkpoint",
+        default: SystemJob {
+          id: Some(id as i32),
+          // sec   min   hour   day of month   month   day of week   year
+          schedule: Some("43 * * * * * *".into()),
+          disabled: Some(false),
+        },
+        callback: build_callback(move || {
+          let conn = conn.clone();
+
+          return async move {
+            if replication {
+              debug!("Skipping WAL checkpoint in favor of replication");
+              return Ok(());
+            }
+
+            run_wal_checkpoint(&conn, threshold).await.map_err(|err| {
+              warn!("Periodic WAL checkpoint failed: {err}");
+              return err;
+            })?;
+
+            Ok::<(), trailbase_sqlite::Error>(())
+          };
+        }),
+      }
+    }
+    SystemJobId::WebhookDelivery => {
+      let conn = conn.clone();
+      let config = Arc::new(config.clone());
+
+      DefaultSystemJob {
+        name: "Webhook Delivery",
+        default: SystemJob {
+          id: Some(id as i32),
+          // First attempts are made right after mutations, the job picks up retries.
+          // sec   min   hour   day of month   month   day of week   year
+          schedule: Some("*/15 * * * * * *".into()),
+          disabled: Some(false),
+        },
+        callback: build_callback(move || {
+          let conn = conn.clone();
+          let config = config.clone();
+
+          return async move {
+            deliver_pending_webhooks(&conn, &config)
+              .await
+              .map_err(|err| {
+                warn!("Webhook delivery failed: {err}");
+                return err;
+              })?;
+            delete_delivered_webhooks(&conn).await?;
+
+            Ok::<(), trailbase_sqlite::Error>(())
+          };
+        }),
+      }
+    }
   };
 }
 
+/// Checkpoints the WAL, truncating it if it grew beyond `threshold` bytes, e.g. because
+/// long-running readers kept automatic checkpoints from catching up.
+async fn run_wal_checkpoint(
+  conn: &Connection,
+  threshold: u64,
+) -> Result<(), trailbase_sqlite::Error> {
+  let Some(size) = conn.wal_size().await? else {
+    return Ok(());
+  };
+
+  let mode = if size > threshold {
+    CheckpointMode::Truncate
+  } else {
+    CheckpointMode::Passive
+  };
+
+  let result = conn.wal_checkpoint(mode).await?;
+  if result.busy {
+    debug!("WAL checkpoint ({mode:?}, {size} bytes) blocked by active readers");
+  }
+
+  return Ok(());
+}
+
 async fn delete_pending_files_job(
   conn: &trailbase_sqlite::Connection,
   object_store: &(dyn object_store::ObjectStore + Send + Sync),
+  data_dir: &DataDir,
 ) -> Result<(), FileError> {
   let rows: Vec<FileDeletionsDb> = match conn
     .read_query_values(
@@ -439,7 +719,7 @@ async fn delete_pending_files_job(
     }
   };
 
-  delete_pending_files_impl(conn, object_store, rows).await?;
+  delete_pending_files_impl(conn, object_store, data_dir, rows).await?;
 
   return Ok(());
 }
@@ -458,9 +738,12 @@ pub fn build_job_registry_from_config(
     SystemJobId::AuthCleaner,
     SystemJobId::QueryOptimizer,
     SystemJobId::FileDeletions,
+    SystemJobId::DataRetention,
+    SystemJobId::WalCheckpoint,
+    SystemJobId::WebhookDelivery,
   ];
 
-  let jobs = JobRegistry::new();
+  let jobs = JobRegistry::with_history(conn.clone());
   for job_id in job_ids {
     let DefaultSystemJob {
       name,
@@ -487,7 +770,7 @@ pub fn build_job_registry_from_config(
       .as_ref()
       .unwrap_or_else(|| default.schedule.as_ref().expect("startup"));
 
-    match Schedule::from_str(schedule) {
+    match parse_schedule(schedule) {
       Ok(schedule) => match jobs.new_job(Some(job_id as i32), name, schedule, callback) {
         Some(job) => {
           if config.disabled != Some(true) {
@@ -504,6 +787,47 @@ pub fn build_job_registry_from_config(
     };
   }
 
+  for cron_job in &config.jobs.cron_jobs {
+    let (Some(name), Some(schedule), Some(query)) = (
+      cron_job.name.clone(),
+      cron_job.schedule.as_ref(),
+      cron_job.query.clone(),
+    ) else {
+      error!("Incomplete cron job definition: {cron_job:?}");
+      continue;
+    };
+
+    let schedule = match parse_schedule(schedule) {
+      Ok(schedule) => schedule,
+      Err(err) => {
+        error!("Invalid time spec for '{name}': {err}");
+        continue;
+      }
+    };
+
+    let conn = conn.clone();
+    let callback = build_callback(move || {
+      let conn = conn.clone();
+      let query = query.clone();
+
+      return async move {
+        conn.execute_batch(query).await?;
+        return Ok::<(), trailbase_sqlite::Error>(());
+      };
+    });
+
+    match jobs.new_job(None, name.clone(), schedule, callback) {
+      Some(job) => {
+        if cron_job.disabled != Some(true) {
+          job.start();
+        }
+      }
+      None => {
+        error!("Duplicate job definition for '{name}'");
+      }
+    }
+  }
+
   return Ok(jobs);
 }
 
@@ -535,6 +859,25 @@ mod tests {
     assert!(Schedule::from_str(expression).is_err());
   }
 
+  #[test]
+  fn test_parse_schedule() {
+    //               min   hour   day of month   month   day of week
+    let schedule = parse_schedule("5     3          *          *          *").unwrap();
+    assert!(schedule.seconds().includes(0));
+    assert!(!schedule.seconds().includes(1));
+    assert!(schedule.minutes().includes(5));
+    assert!(schedule.hours().includes(3));
+
+    let schedule = parse_schedule("1 2 3 * * *").unwrap();
+    assert!(schedule.seconds().includes(1));
+    assert!(schedule.minutes().includes(2));
+
+    parse_schedule("@daily").unwrap();
+
+    assert!(parse_schedule("*/100 * * * *").is_err());
+    assert!(parse_schedule("* * * *").is_err());
+  }
+
   #[tokio::test]
   async fn test_scheduler() {
     // NOTE: Cron is time and not interval based, i.e. something like every 100s is not
@@ -576,11 +919,81 @@ mod tests {
     assert_eq!(err_string, Some("result".to_string()));
   }
 
+  #[tokio::test]
+  async fn test_job_history() {
+    let state = crate::app_state::test_state(None).await.unwrap();
+    let registry = Arc::new(JobRegistry::with_history(state.conn().clone()));
+
+    let (started_sender, started_receiver) = async_channel::unbounded::<()>();
+    let (release_sender, release_receiver) = async_channel::unbounded::<()>();
+    let job = registry
+      .new_job(
+        None,
+        "history",
+        parse_schedule("@yearly").unwrap(),
+        build_callback(move || {
+          let started_sender = started_sender.clone();
+          let release_receiver = release_receiver.clone();
+          return async move {
+            started_sender.send(()).await.unwrap();
+            release_receiver.recv().await.unwrap();
+            Err("failure")
+          };
+        }),
+      )
+      .unwrap();
+    let id = job.id;
+
+    let first = tokio::spawn({
+      let registry = registry.clone();
+      async move { registry.run_job(id).await }
+    });
+    started_receiver.recv().await.unwrap();
+
+    // Overlapping runs are rejected.
+    assert!(registry.run_job(id).await.unwrap().is_err());
+
+    release_sender.send(()).await.unwrap();
+    assert_eq!(first.await.unwrap().unwrap(), Err("failure".to_string()));
+
+    let (count, error): (i64, Option<String>) = state
+      .conn()
+      .query_row_f(
+        format!("SELECT COUNT(*), MAX(error) FROM {JOB_RUN_TABLE} WHERE name = 'history'"),
+        (),
+        |row| Ok::<_, rusqlite::Error>((row.get(0)?, row.get(1)?)),
+      )
+      .await
+      .unwrap()
+      .unwrap();
+    assert_eq!(count, 1);
+    assert_eq!(error.as_deref(), Some("failure"));
+
+    let last_run: Option<i64> = state
+      .conn()
+      .query_row_f(
+        format!("SELECT last_run FROM {JOB_STATE_TABLE} WHERE name = 'history'"),
+        (),
+        |row| row.get(0),
+      )
+      .await
+      .unwrap()
+      .unwrap();
+    assert!(last_run.is_some());
+
+    // No longer in flight.
+    release_sender.send(()).await.unwrap();
+    assert_eq!(
+      registry.run_job(id).await.unwrap(),
+      Err("failure".to_string())
+    );
+  }
+
   #[tokio::test]
   async fn test_delete_pending_files_job() {
     let state = crate::app_state::test_state(None).await.unwrap();
 
-    delete_pending_files_job(state.conn(), state.objectstore())
+    delete_pending_files_job(state.conn(), state.objectstore(), state.data_dir())
       .await
       .unwrap();
   }
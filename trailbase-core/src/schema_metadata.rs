@@ -1,9 +1,10 @@
 use fallible_iterator::FallibleIterator;
 use log::*;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::Arc;
 use thiserror::Error;
-use trailbase_schema::sqlite::{SchemaError, Table, View, sqlite3_parse_into_statement};
+use trailbase_schema::sqlite::{SchemaError, Table, Trigger, View, sqlite3_parse_into_statement};
 use trailbase_sqlite::params;
 
 pub use trailbase_schema::metadata::{
@@ -15,6 +16,8 @@ use crate::constants::{SQLITE_SCHEMA_TABLE, USER_TABLE};
 struct SchemaMetadataCacheState {
   tables: HashMap<String, Arc<TableMetadata>>,
   views: HashMap<String, Arc<ViewMetadata>>,
+  /// Attached database by table name for tables outside the main database.
+  databases: HashMap<String, String>,
 }
 
 #[derive(Clone)]
@@ -25,8 +28,8 @@ pub struct SchemaMetadataCache {
 
 impl SchemaMetadataCache {
   pub async fn new(conn: trailbase_sqlite::Connection) -> Result<Self, SchemaLookupError> {
-    let tables = lookup_and_parse_all_table_schemas(&conn).await?;
-    let table_map = Self::build_tables(&conn, &tables).await?;
+    let (tables, databases) = Self::lookup_tables(&conn).await?;
+    let table_map = Self::build_tables(&conn, &tables, &databases).await?;
     let views = Self::build_views(&conn, &tables).await?;
 
     return Ok(SchemaMetadataCache {
@@ -34,28 +37,59 @@ impl SchemaMetadataCache {
       state: Arc::new(parking_lot::RwLock::new(SchemaMetadataCacheState {
         tables: table_map,
         views,
+        databases,
       })),
     });
   }
 
+  /// Tables of the main and attached databases. Like SQLite's name resolution, tables of the main
+  /// database shadow equally named ones in attached databases.
+  async fn lookup_tables(
+    conn: &trailbase_sqlite::Connection,
+  ) -> Result<(Vec<Table>, HashMap<String, String>), SchemaLookupError> {
+    let mut tables = lookup_and_parse_all_table_schemas(conn).await?;
+
+    let mut databases: HashMap<String, String> = HashMap::new();
+    for (database, table) in lookup_and_parse_attached_table_schemas(conn).await? {
+      if tables.iter().any(|t| t.name == table.name) {
+        warn!(
+          "Table '{}' in database '{database}' is shadowed by an equally named table",
+          table.name
+        );
+        continue;
+      }
+      databases.insert(table.name.clone(), database);
+      tables.push(table);
+    }
+
+    return Ok((tables, databases));
+  }
+
   async fn build_tables(
     conn: &trailbase_sqlite::Connection,
     tables: &[Table],
+    databases: &HashMap<String, String>,
   ) -> Result<HashMap<String, Arc<TableMetadata>>, SchemaLookupError> {
-    let schema_metadata_map: HashMap<String, Arc<TableMetadata>> = tables
+    let mut schema_metadata_map: HashMap<String, TableMetadata> = tables
       .iter()
       .cloned()
-      .map(|t: Table| {
-        (
-          t.name.clone(),
-          Arc::new(TableMetadata::new(t, tables, USER_TABLE)),
-        )
-      })
+      .map(|t: Table| (t.name.clone(), TableMetadata::new(t, tables, USER_TABLE)))
       .collect();
 
     // Install file column triggers. This ain't pretty, this might be better on construction and
     // schema changes.
     for metadata in schema_metadata_map.values() {
+      // Triggers cannot reference tables in other databases, i.e. `_file_deletions`.
+      if databases.contains_key(metadata.name()) {
+        if !metadata.json_metadata.file_column_indexes().is_empty() {
+          warn!(
+            "File columns of '{}' outside the main database are not cleaned up",
+            metadata.name()
+          );
+        }
+        continue;
+      }
+
       for idx in metadata.json_metadata.file_column_indexes() {
         let table_name = &metadata.schema.name;
         let col = &metadata.schema.columns[*idx];
@@ -83,7 +117,18 @@ impl SchemaMetadataCache {
       }
     }
 
-    return Ok(schema_metadata_map);
+    for trigger in lookup_and_parse_all_trigger_schemas(conn).await? {
+      if let Some(metadata) = schema_metadata_map.get_mut(&trigger.table_name) {
+        metadata.triggers.push(trigger);
+      }
+    }
+
+    return Ok(
+      schema_metadata_map
+        .into_iter()
+        .map(|(name, metadata)| (name, Arc::new(metadata)))
+        .collect(),
+    );
   }
 
   async fn build_views(
@@ -111,6 +156,12 @@ impl SchemaMetadataCache {
     self.state.read().views.get(view_name).cloned()
   }
 
+  /// Attached database the table lives in, see `Config::databases`, or None for the main
+  /// database.
+  pub fn table_database(&self, table_name: &str) -> Option<String> {
+    return self.state.read().databases.get(table_name).cloned();
+  }
+
   pub(crate) fn tables(&self) -> Vec<TableMetadata> {
     return self
       .state
@@ -125,19 +176,98 @@ impl SchemaMetadataCache {
     debug!("Rebuilding SchemaMetadataCache");
     let conn = &self.conn;
 
-    let tables = lookup_and_parse_all_table_schemas(conn).await?;
-    let table_map = Self::build_tables(conn, &tables).await?;
+    let (tables, databases) = Self::lookup_tables(conn).await?;
+    let table_map = Self::build_tables(conn, &tables, &databases).await?;
     let views = Self::build_views(conn, &tables).await?;
 
     *self.state.write() = SchemaMetadataCacheState {
       tables: table_map,
       views,
+      databases,
     };
 
     Ok(())
   }
 }
 
+/// Normalized, serializable view of the app's schema, e.g. to detect schema drift between
+/// environments by comparing against a checked-in snapshot.
+///
+/// Tables, views and triggers are sorted by name. TrailBase's own "_"-prefixed tables, views and
+/// triggers are excluded, since they're managed by TrailBase rather than the app.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SchemaSnapshot {
+  pub tables: Vec<Table>,
+  pub views: Vec<View>,
+  #[serde(default)]
+  pub triggers: Vec<Trigger>,
+}
+
+impl SchemaSnapshot {
+  /// Stable, human-readable serialization suitable for checking in and diffing.
+  pub fn to_json_pretty(&self) -> String {
+    let mut json = serde_json::to_string_pretty(self).expect("infallible");
+    json.push('\n');
+    return json;
+  }
+}
+
+/// Takes a snapshot of the currently cached schema.
+pub fn schema_snapshot(state: &crate::AppState) -> SchemaSnapshot {
+  return state.schema_metadata().snapshot();
+}
+
+impl SchemaMetadataCache {
+  fn snapshot(&self) -> SchemaSnapshot {
+    let state = self.state.read();
+    // Attached databases are managed by their own migrations.
+    let is_app_owned = |name: &str| {
+      return !name.starts_with('_')
+        && !name.starts_with("sqlite_")
+        && !state.databases.contains_key(name);
+    };
+
+    let mut tables: Vec<Table> = state
+      .tables
+      .values()
+      .filter(|t| is_app_owned(&t.schema.name))
+      .map(|t| t.schema.clone())
+      .collect();
+    tables.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut views: Vec<View> = state
+      .views
+      .values()
+      .filter(|v| is_app_owned(&v.schema.name))
+      .map(|v| View {
+        // Merely an artifact of how the view was created.
+        if_not_exists: false,
+        ..v.schema.clone()
+      })
+      .collect();
+    views.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut triggers: Vec<Trigger> = state
+      .tables
+      .values()
+      .filter(|t| is_app_owned(&t.schema.name))
+      .flat_map(|t| t.triggers.iter())
+      .filter(|t| is_app_owned(&t.name))
+      .map(|t| Trigger {
+        if_not_exists: false,
+        ..t.clone()
+      })
+      .collect();
+    triggers.sort_by(|a, b| a.name.cmp(&b.name));
+
+    return SchemaSnapshot {
+      tables,
+      views,
+      triggers,
+    };
+  }
+}
+
 impl std::fmt::Debug for SchemaMetadataCache {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
     let state = self.state.read();
@@ -188,15 +318,14 @@ pub async fn lookup_and_parse_all_table_schemas(
 ) -> Result<Vec<Table>, SchemaLookupError> {
   // Then get the actual table.
   let rows = conn
-    .read_query_rows(
+    .read_query_as::<String>(
       format!("SELECT sql FROM {SQLITE_SCHEMA_TABLE} WHERE type = 'table'"),
       (),
     )
     .await?;
 
   let mut tables: Vec<Table> = vec![];
-  for row in rows.iter() {
-    let sql: String = row.get(0)?;
+  for sql in rows {
     let Some(stmt) = sqlite3_parse_into_statement(&sql)? else {
       return Err(SchemaLookupError::Missing);
     };
@@ -206,6 +335,65 @@ pub async fn lookup_and_parse_all_table_schemas(
   return Ok(tables);
 }
 
+/// Tables of attached databases, see `Config::databases`, together with their database's name.
+/// TrailBase's own "_"-prefixed tables, e.g. the migration history, are skipped.
+pub async fn lookup_and_parse_attached_table_schemas(
+  conn: &trailbase_sqlite::Connection,
+) -> Result<Vec<(String, Table)>, SchemaLookupError> {
+  let databases = conn
+    .read_query_as::<String>(
+      "SELECT name FROM pragma_database_list WHERE name NOT IN ('main', 'temp')",
+      (),
+    )
+    .await?;
+
+  let mut tables: Vec<(String, Table)> = vec![];
+  for database in databases {
+    let rows = conn
+      .read_query_as::<String>(
+        format!(r#"SELECT sql FROM "{database}".sqlite_schema WHERE type = 'table'"#),
+        (),
+      )
+      .await?;
+
+    for sql in rows {
+      let Some(stmt) = sqlite3_parse_into_statement(&sql)? else {
+        return Err(SchemaLookupError::Missing);
+      };
+      let table: Table = stmt.try_into()?;
+      if !table.name.starts_with('_') && !table.name.starts_with("sqlite_") {
+        tables.push((database.clone(), table));
+      }
+    }
+  }
+
+  return Ok(tables);
+}
+
+pub async fn lookup_and_parse_all_trigger_schemas(
+  conn: &trailbase_sqlite::Connection,
+) -> Result<Vec<Trigger>, SchemaLookupError> {
+  let rows = conn
+    .read_query_as::<String>(
+      format!("SELECT sql FROM {SQLITE_SCHEMA_TABLE} WHERE type = 'trigger' ORDER BY name"),
+      (),
+    )
+    .await?;
+
+  // Triggers are merely informational, thus don't fail the schema lookup over unparsable ones.
+  let mut triggers: Vec<Trigger> = vec![];
+  for sql in rows {
+    match sqlite3_parse_into_statement(&sql).map(|stmt| stmt.map(Trigger::try_from)) {
+      Ok(Some(Ok(trigger))) => triggers.push(trigger),
+      Ok(None) => {}
+      Ok(Some(Err(err))) => warn!("Failed to parse trigger '{sql}': {err}"),
+      Err(err) => warn!("Failed to parse trigger '{sql}': {err}"),
+    }
+  }
+
+  return Ok(triggers);
+}
+
 fn sqlite3_parse_view(sql: &str, tables: &[Table]) -> Result<View, SchemaLookupError> {
   let mut parser = sqlite3_parser::lexer::sql::Parser::new(sql.as_bytes());
   match parser.next()? {
@@ -226,15 +414,14 @@ pub async fn lookup_and_parse_all_view_schemas(
 ) -> Result<Vec<View>, SchemaLookupError> {
   // Then get the actual table.
   let rows = conn
-    .read_query_rows(
+    .read_query_as::<String>(
       format!("SELECT sql FROM {SQLITE_SCHEMA_TABLE} WHERE type = 'view'"),
       (),
     )
     .await?;
 
   let mut views: Vec<View> = vec![];
-  for row in rows.iter() {
-    let sql: String = row.get(0)?;
+  for sql in rows {
     views.push(sqlite3_parse_view(&sql, tables)?);
   }
 
@@ -249,9 +436,74 @@ mod tests {
 
   use crate::app_state::*;
   use crate::config::proto::{PermissionFlag, RecordApiConfig};
-  use crate::records::list_records::list_records_handler;
+  use crate::extract::AcceptFormat;
+  use crate::records::list_records::{ListResponse, list_records_handler};
   use crate::records::read_record::{ReadRecordQuery, read_record_handler};
-  use crate::records::test_utils::add_record_api_config;
+  use crate::records::test_utils::{add_record_api_config, json_body};
+
+  use super::schema_snapshot;
+
+  #[tokio::test]
+  async fn test_schema_snapshot() {
+    let state = test_state(None).await.unwrap();
+    state
+      .conn()
+      .execute_batch(
+        r#"
+          CREATE TABLE b_table (id INTEGER PRIMARY KEY, name TEXT NOT NULL) STRICT;
+          CREATE TABLE a_table (id INTEGER PRIMARY KEY, b INTEGER REFERENCES b_table(id)) STRICT;
+          CREATE VIEW IF NOT EXISTS a_view AS SELECT id, name FROM b_table;
+          CREATE TRIGGER b_trigger AFTER DELETE ON b_table
+          BEGIN
+            DELETE FROM a_table WHERE b = OLD.id;
+          END;
+        "#,
+      )
+      .await
+      .unwrap();
+    state.schema_metadata().invalidate_all().await.unwrap();
+
+    let snapshot = schema_snapshot(&state);
+    assert_eq!(
+      snapshot
+        .tables
+        .iter()
+        .map(|t| t.name.as_str())
+        .collect::<Vec<_>>(),
+      ["a_table", "b_table"]
+    );
+    assert_eq!(snapshot.views.len(), 1);
+    assert!(!snapshot.views[0].if_not_exists);
+    assert_eq!(snapshot.triggers.len(), 1);
+    assert_eq!(snapshot.triggers[0].table_name, "b_table");
+    assert_eq!(
+      state
+        .schema_metadata()
+        .get_table("b_table")
+        .unwrap()
+        .triggers
+        .len(),
+      1
+    );
+
+    // Stable across rebuilds and round-trips.
+    state.schema_metadata().invalidate_all().await.unwrap();
+    let json = snapshot.to_json_pretty();
+    assert_eq!(json, schema_snapshot(&state).to_json_pretty());
+    assert_eq!(
+      snapshot,
+      serde_json::from_str::<super::SchemaSnapshot>(&json).unwrap()
+    );
+
+    // Drift is detected.
+    state
+      .conn()
+      .execute("ALTER TABLE b_table ADD COLUMN age INTEGER", ())
+      .await
+      .unwrap();
+    state.schema_metadata().invalidate_all().await.unwrap();
+    assert_ne!(json, schema_snapshot(&state).to_json_pretty());
+  }
 
   #[tokio::test]
   async fn test_expanded_foreign_key() {
@@ -358,6 +610,7 @@ mod tests {
         Path(("test_table_api".to_string(), "1".to_string())),
         Query(ReadRecordQuery {
           expand: Some("UNKNOWN".to_string()),
+          ..Default::default()
         }),
         None,
       )
@@ -370,6 +623,7 @@ mod tests {
         Path("test_table_api".to_string()),
         RawQuery(Some("expand=UNKNOWN".to_string())),
         None,
+        AcceptFormat::Json,
       )
       .await;
 
@@ -383,27 +637,34 @@ mod tests {
         "fk":{ "id": 1 },
       });
 
-      let Json(value) = read_record_handler(
-        State(state.clone()),
-        Path(("test_table_api".to_string(), "1".to_string())),
-        Query(ReadRecordQuery::default()),
-        None,
+      let value: serde_json::Value = json_body(
+        read_record_handler(
+          State(state.clone()),
+          Path(("test_table_api".to_string(), "1".to_string())),
+          Query(ReadRecordQuery::default()),
+          None,
+        )
+        .await
+        .unwrap(),
       )
-      .await
-      .unwrap();
+      .await;
 
       validator.validate(&value).expect(&format!("{value}"));
 
       assert_eq!(expected, value);
 
-      let Json(list_response) = list_records_handler(
-        State(state.clone()),
-        Path("test_table_api".to_string()),
-        RawQuery(None),
-        None,
+      let list_response: ListResponse = json_body(
+        list_records_handler(
+          State(state.clone()),
+          Path("test_table_api".to_string()),
+          RawQuery(None),
+          None,
+          AcceptFormat::Json,
+        )
+        .await
+        .unwrap(),
       )
-      .await
-      .unwrap();
+      .await;
 
       assert_eq!(vec![expected.clone()], list_response.records);
       validator.validate(&list_response.records[0]).unwrap();
@@ -420,16 +681,20 @@ mod tests {
     });
 
     {
-      let Json(value) = read_record_handler(
-        State(state.clone()),
-        Path(("test_table_api".to_string(), "1".to_string())),
-        Query(ReadRecordQuery {
-          expand: Some("fk".to_string()),
-        }),
-        None,
+      let value: serde_json::Value = json_body(
+        read_record_handler(
+          State(state.clone()),
+          Path(("test_table_api".to_string(), "1".to_string())),
+          Query(ReadRecordQuery {
+            expand: Some("fk".to_string()),
+            ..Default::default()
+          }),
+          None,
+        )
+        .await
+        .unwrap(),
       )
-      .await
-      .unwrap();
+      .await;
 
       validator.validate(&value).expect(&format!("{value}"));
 
@@ -437,28 +702,36 @@ mod tests {
     }
 
     {
-      let Json(list_response) = list_records_handler(
-        State(state.clone()),
-        Path("test_table_api".to_string()),
-        RawQuery(Some("expand=fk".to_string())),
-        None,
+      let list_response: ListResponse = json_body(
+        list_records_handler(
+          State(state.clone()),
+          Path("test_table_api".to_string()),
+          RawQuery(Some("expand=fk".to_string())),
+          None,
+          AcceptFormat::Json,
+        )
+        .await
+        .unwrap(),
       )
-      .await
-      .unwrap();
+      .await;
 
       assert_eq!(vec![expected.clone()], list_response.records);
       validator.validate(&list_response.records[0]).unwrap();
     }
 
     {
-      let Json(list_response) = list_records_handler(
-        State(state.clone()),
-        Path("test_table_api".to_string()),
-        RawQuery(Some("count=1&expand=fk".to_string())),
-        None,
+      let list_response: ListResponse = json_body(
+        list_records_handler(
+          State(state.clone()),
+          Path("test_table_api".to_string()),
+          RawQuery(Some("count=1&expand=fk".to_string())),
+          None,
+          AcceptFormat::Json,
+        )
+        .await
+        .unwrap(),
       )
-      .await
-      .unwrap();
+      .await;
 
       assert_eq!(Some(1), list_response.total_count);
       assert_eq!(vec![expected], list_response.records);
@@ -528,14 +801,17 @@ mod tests {
 
     // Expand none
     {
-      let Json(value) = read_record_handler(
-        State(state.clone()),
-        Path(("test_table_api".to_string(), "1".to_string())),
-        Query(ReadRecordQuery { expand: None }),
-        None,
+      let value: serde_json::Value = json_body(
+        read_record_handler(
+          State(state.clone()),
+          Path(("test_table_api".to_string(), "1".to_string())),
+          Query(ReadRecordQuery::default()),
+          None,
+        )
+        .await
+        .unwrap(),
       )
-      .await
-      .unwrap();
+      .await;
 
       let expected = json!({
         "id": 1,
@@ -546,14 +822,18 @@ mod tests {
 
       assert_eq!(expected, value);
 
-      let Json(list_response) = list_records_handler(
-        State(state.clone()),
-        Path("test_table_api".to_string()),
-        RawQuery(None),
-        None,
+      let list_response: ListResponse = json_body(
+        list_records_handler(
+          State(state.clone()),
+          Path("test_table_api".to_string()),
+          RawQuery(None),
+          None,
+          AcceptFormat::Json,
+        )
+        .await
+        .unwrap(),
       )
-      .await
-      .unwrap();
+      .await;
 
       assert_eq!(vec![expected], list_response.records);
     }
@@ -572,27 +852,35 @@ mod tests {
         },
       });
 
-      let Json(value) = read_record_handler(
-        State(state.clone()),
-        Path(("test_table_api".to_string(), "1".to_string())),
-        Query(ReadRecordQuery {
-          expand: Some("fk1".to_string()),
-        }),
-        None,
+      let value: serde_json::Value = json_body(
+        read_record_handler(
+          State(state.clone()),
+          Path(("test_table_api".to_string(), "1".to_string())),
+          Query(ReadRecordQuery {
+            expand: Some("fk1".to_string()),
+            ..Default::default()
+          }),
+          None,
+        )
+        .await
+        .unwrap(),
       )
-      .await
-      .unwrap();
+      .await;
 
       assert_eq!(expected, value);
 
-      let Json(list_response) = list_records_handler(
-        State(state.clone()),
-        Path("test_table_api".to_string()),
-        RawQuery(Some("expand=fk1".to_string())),
-        None,
+      let list_response: ListResponse = json_body(
+        list_records_handler(
+          State(state.clone()),
+          Path("test_table_api".to_string()),
+          RawQuery(Some("expand=fk1".to_string())),
+          None,
+          AcceptFormat::Json,
+        )
+        .await
+        .unwrap(),
       )
-      .await
-      .unwrap();
+      .await;
 
       assert_eq!(vec![expected], list_response.records);
     }
@@ -616,16 +904,20 @@ mod tests {
         },
       });
 
-      let Json(value) = read_record_handler(
-        State(state.clone()),
-        Path(("test_table_api".to_string(), "1".to_string())),
-        Query(ReadRecordQuery {
-          expand: Some("fk0,fk1".to_string()),
-        }),
-        None,
+      let value: serde_json::Value = json_body(
+        read_record_handler(
+          State(state.clone()),
+          Path(("test_table_api".to_string(), "1".to_string())),
+          Query(ReadRecordQuery {
+            expand: Some("fk0,fk1".to_string()),
+            ..Default::default()
+          }),
+          None,
+        )
+        .await
+        .unwrap(),
       )
-      .await
-      .unwrap();
+      .await;
 
       assert_eq!(expected, value);
 
@@ -633,14 +925,18 @@ mod tests {
         .await
         .unwrap();
 
-      let Json(list_response) = list_records_handler(
-        State(state.clone()),
-        Path("test_table_api".to_string()),
-        RawQuery(Some("expand=fk0,fk1".to_string())),
-        None,
+      let list_response: ListResponse = json_body(
+        list_records_handler(
+          State(state.clone()),
+          Path("test_table_api".to_string()),
+          RawQuery(Some("expand=fk0,fk1".to_string())),
+          None,
+          AcceptFormat::Json,
+        )
+        .await
+        .unwrap(),
       )
-      .await
-      .unwrap();
+      .await;
 
       assert_eq!(
         vec![
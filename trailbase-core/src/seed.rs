@@ -0,0 +1,492 @@
+use log::*;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use trailbase_sqlite::params;
+
+use crate::admin::AdminError;
+use crate::admin::rows::seed_records;
+use crate::app_state::AppState;
+use crate::records::import_records::coerce_csv_value;
+use crate::records::params::JsonRow;
+use crate::schema_metadata::TableMetadata;
+
+/// Optional key labeling a fixture record, s.t. other fixtures can reference it.
+const LABEL_KEY: &str = "@id";
+/// Prefix of values referencing another fixture's record by "<table>.<label>", e.g.
+/// "@ref:author.alice". References resolve to the record's primary key.
+const REF_PREFIX: &str = "@ref:";
+/// Prefix of file column values loaded from disk, relative to the fixture file, e.g.
+/// "@file:images/alice.png".
+const FILE_PREFIX: &str = "@file:";
+
+#[derive(Debug, Error)]
+pub enum SeedError {
+  #[error("SQLite error: {0}")]
+  Sqlite(#[from] trailbase_sqlite::Error),
+  #[error("IO error: {0}")]
+  Io(#[from] std::io::Error),
+  #[error("JSON error: {0}")]
+  Json(#[from] serde_json::Error),
+  #[error("CSV error: {0}")]
+  Csv(#[from] csv::Error),
+  #[error("Insert error: {0}")]
+  Insert(#[from] AdminError),
+  #[error("Fixture error: {0}")]
+  Invalid(String),
+}
+
+/// Records to be inserted into a table.
+#[derive(Clone, Debug)]
+pub struct Fixture {
+  pub table: String,
+  records: Vec<JsonRow>,
+  /// Directory "@file:" paths are relative to.
+  base_dir: PathBuf,
+  /// CSV cells are strings and need to be coerced according to their column.
+  csv: bool,
+}
+
+impl Fixture {
+  /// A fixture from a JSON record or array of records.
+  pub fn from_json(
+    table: impl Into<String>,
+    records: serde_json::Value,
+    base_dir: impl Into<PathBuf>,
+  ) -> Result<Self, SeedError> {
+    let invalid = || SeedError::Invalid("Expected record or array of records".to_string());
+
+    let records: Vec<JsonRow> = match records {
+      serde_json::Value::Object(record) => vec![record],
+      serde_json::Value::Array(records) => records
+        .into_iter()
+        .map(|record| match record {
+          serde_json::Value::Object(record) => Ok(record),
+          _ => Err(invalid()),
+        })
+        .collect::<Result<_, _>>()?,
+      _ => return Err(invalid()),
+    };
+
+    return Ok(Self {
+      table: table.into(),
+      records,
+      base_dir: base_dir.into(),
+      csv: false,
+    });
+  }
+
+  /// A fixture from CSV with a header row. Empty cells are omitted to let column defaults apply.
+  pub fn from_csv(
+    table: impl Into<String>,
+    data: &[u8],
+    base_dir: impl Into<PathBuf>,
+  ) -> Result<Self, SeedError> {
+    let data = data.strip_prefix("\u{FEFF}".as_bytes()).unwrap_or(data);
+    let mut reader = csv::ReaderBuilder::new()
+      .has_headers(true)
+      .trim(csv::Trim::Headers)
+      .from_reader(data);
+
+    let headers = reader.headers()?.clone();
+    let mut records: Vec<JsonRow> = vec![];
+    for record in reader.records() {
+      records.push(
+        headers
+          .iter()
+          .zip(record?.iter())
+          .filter(|(_, value)| !value.is_empty())
+          .map(|(header, value)| {
+            return (
+              header.to_string(),
+              serde_json::Value::String(value.to_string()),
+            );
+          })
+          .collect(),
+      );
+    }
+
+    return Ok(Self {
+      table: table.into(),
+      records,
+      base_dir: base_dir.into(),
+      csv: true,
+    });
+  }
+
+  /// Loads a JSON or CSV fixture named after its table, e.g. "author.json".
+  pub fn load(path: &Path) -> Result<Self, SeedError> {
+    let Some(table) = path.file_stem().and_then(|stem| stem.to_str()) else {
+      return Err(SeedError::Invalid(format!("Invalid path: {path:?}")));
+    };
+    let base_dir = path.parent().unwrap_or(Path::new(".")).to_path_buf();
+    let contents = std::fs::read(path)?;
+
+    return match path.extension().and_then(|ext| ext.to_str()) {
+      Some("json") => Self::from_json(table, serde_json::from_slice(&contents)?, base_dir),
+      Some("csv") => Self::from_csv(table, &contents, base_dir),
+      _ => Err(SeedError::Invalid(format!(
+        "Expected .json or .csv fixture: {path:?}"
+      ))),
+    };
+  }
+
+  /// Tables referenced via "@ref:" values.
+  fn dependencies(&self) -> HashSet<&str> {
+    fn collect<'a>(value: &'a serde_json::Value, deps: &mut HashSet<&'a str>) {
+      match value {
+        serde_json::Value::String(s) => {
+          if let Some((table, _label)) = s.strip_prefix(REF_PREFIX).and_then(|r| r.split_once('.'))
+          {
+            deps.insert(table);
+          }
+        }
+        serde_json::Value::Array(values) => {
+          for value in values {
+            collect(value, deps);
+          }
+        }
+        _ => {}
+      }
+    }
+
+    let mut deps = HashSet::new();
+    for value in self.records.iter().flat_map(|record| record.values()) {
+      collect(value, &mut deps);
+    }
+    return deps;
+  }
+}
+
+/// Loads fixtures from the given files and directories of files.
+///
+/// Directory entries are loaded in alphabetical order, skipping files other than ".json" and
+/// ".csv".
+pub fn load_fixtures(paths: &[PathBuf]) -> Result<Vec<Fixture>, SeedError> {
+  let mut fixtures: Vec<Fixture> = vec![];
+  for path in paths {
+    if !path.is_dir() {
+      fixtures.push(Fixture::load(path)?);
+      continue;
+    }
+
+    let mut entries: Vec<PathBuf> = std::fs::read_dir(path)?
+      .map(|entry| entry.map(|e| e.path()))
+      .collect::<Result<_, _>>()?;
+    entries.sort();
+
+    for entry in entries {
+      let is_fixture = entry.is_file()
+        && matches!(
+          entry.extension().and_then(|ext| ext.to_str()),
+          Some("json" | "csv")
+        );
+      if is_fixture {
+        fixtures.push(Fixture::load(&entry)?);
+      }
+    }
+  }
+  return Ok(fixtures);
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SeedReport {
+  /// Tables and the number of records inserted into them, in insertion order.
+  pub seeded: Vec<(String, usize)>,
+  /// Tables skipped because they weren't empty.
+  pub skipped: Vec<String>,
+}
+
+/// Inserts the fixtures' records, e.g. for dev environments or tests.
+///
+/// Records are validated like record API requests, see [`seed_records`]. Fixtures are inserted
+/// in dependency order, s.t. "@ref:<table>.<label>" values can resolve to the primary key of
+/// records labeled with `"@id": "<label>"` in another fixture. "@file:<path>" values are read
+/// from disk and stored like file uploads. Each fixture is inserted atomically.
+///
+/// If `if_empty` is set, fixtures for tables that already contain records are skipped.
+pub async fn seed_fixtures(
+  state: &AppState,
+  fixtures: Vec<Fixture>,
+  if_empty: bool,
+) -> Result<SeedReport, SeedError> {
+  let order = dependency_order(&fixtures)?;
+
+  let mut report = SeedReport::default();
+  // Primary keys of labeled records by "<table>.<label>".
+  let mut labels: HashMap<String, serde_json::Value> = HashMap::new();
+
+  for index in order {
+    let fixture = &fixtures[index];
+    let table = fixture.table.as_str();
+    let Some(metadata) = state.schema_metadata().get_table(table) else {
+      return Err(SeedError::Invalid(format!("Table '{table}' not found")));
+    };
+
+    if if_empty {
+      let has_records: bool = state
+        .conn()
+        .read_query_row_f(
+          format!(r#"SELECT EXISTS(SELECT 1 FROM "{table}")"#),
+          (),
+          |row| row.get(0),
+        )
+        .await?
+        .unwrap_or(false);
+      if has_records {
+        debug!("Skipping fixture for non-empty table '{table}'");
+        report.skipped.push(table.to_string());
+        continue;
+      }
+    }
+
+    let mut labeled: Vec<(usize, String)> = vec![];
+    let mut records: Vec<serde_json::Value> = Vec::with_capacity(fixture.records.len());
+    for (position, record) in fixture.records.iter().enumerate() {
+      let mut record = record.clone();
+      match record.remove(LABEL_KEY) {
+        Some(serde_json::Value::String(label)) => labeled.push((position, label)),
+        Some(_) => {
+          return Err(SeedError::Invalid(format!(
+            "Expected string {LABEL_KEY} in fixture '{table}'"
+          )));
+        }
+        None => {}
+      }
+
+      for (column_name, value) in record.iter_mut() {
+        *value = resolve_value(
+          fixture,
+          &metadata,
+          column_name,
+          value.take(),
+          &labels,
+          &report,
+        )?;
+      }
+      records.push(serde_json::Value::Object(record));
+    }
+
+    let rowids = seed_records(state, table, serde_json::Value::Array(records)).await?;
+    report.seeded.push((table.to_string(), rowids.len()));
+
+    let pk_column = metadata.record_pk_column.map_or("_rowid_", |index| {
+      metadata.schema.columns[index].name.as_str()
+    });
+    for (position, label) in labeled {
+      let pk: Option<rusqlite::types::Value> = state
+        .conn()
+        .read_query_row_f(
+          format!(r#"SELECT "{pk_column}" FROM "{table}" WHERE _rowid_ = $1"#),
+          params!(rowids[position]),
+          |row| row.get(0),
+        )
+        .await?;
+
+      labels.insert(
+        format!("{table}.{label}"),
+        match pk {
+          None | Some(rusqlite::types::Value::Null) => serde_json::Value::Null,
+          Some(rusqlite::types::Value::Integer(i)) => i.into(),
+          Some(rusqlite::types::Value::Real(f)) => f.into(),
+          Some(rusqlite::types::Value::Text(s)) => s.into(),
+          Some(rusqlite::types::Value::Blob(b)) => b.into(),
+        },
+      );
+    }
+  }
+
+  return Ok(report);
+}
+
+/// Orders fixtures s.t. referenced fixtures come first, otherwise preserving the input order.
+fn dependency_order(fixtures: &[Fixture]) -> Result<Vec<usize>, SeedError> {
+  let mut tables: HashSet<&str> = HashSet::new();
+  for fixture in fixtures {
+    if !tables.insert(&fixture.table) {
+      return Err(SeedError::Invalid(format!(
+        "Multiple fixtures for table '{}'",
+        fixture.table
+      )));
+    }
+  }
+
+  let dependencies: Vec<HashSet<&str>> = fixtures.iter().map(|f| f.dependencies()).collect();
+  for (fixture, deps) in fixtures.iter().zip(&dependencies) {
+    if let Some(dep) = deps.iter().find(|dep| !tables.contains(*dep)) {
+      return Err(SeedError::Invalid(format!(
+        "Fixture '{}' references '{dep}', which has no fixture",
+        fixture.table
+      )));
+    }
+    if deps.contains(fixture.table.as_str()) {
+      return Err(SeedError::Invalid(format!(
+        "Fixture '{}' references itself",
+        fixture.table
+      )));
+    }
+  }
+
+  let mut order: Vec<usize> = Vec::with_capacity(fixtures.len());
+  let mut done: HashSet<&str> = HashSet::new();
+  while order.len() < fixtures.len() {
+    let Some(next) = (0..fixtures.len()).find(|index| {
+      return !done.contains(fixtures[*index].table.as_str())
+        && dependencies[*index].iter().all(|dep| done.contains(dep));
+    }) else {
+      return Err(SeedError::Invalid(
+        "Fixtures reference each other cyclically".to_string(),
+      ));
+    };
+
+    done.insert(&fixtures[next].table);
+    order.push(next);
+  }
+
+  return Ok(order);
+}
+
+fn resolve_value(
+  fixture: &Fixture,
+  metadata: &TableMetadata,
+  column_name: &str,
+  value: serde_json::Value,
+  labels: &HashMap<String, serde_json::Value>,
+  report: &SeedReport,
+) -> Result<serde_json::Value, SeedError> {
+  return match value {
+    serde_json::Value::String(s) => {
+      if let Some(reference) = s.strip_prefix(REF_PREFIX) {
+        if let Some(pk) = labels.get(reference) {
+          return Ok(pk.clone());
+        }
+
+        let skipped = reference
+          .split_once('.')
+          .is_some_and(|(table, _)| report.skipped.iter().any(|t| t == table));
+        return Err(SeedError::Invalid(if skipped {
+          format!("Reference '{s}' into skipped, non-empty table")
+        } else {
+          format!("Unknown reference '{s}'")
+        }));
+      }
+
+      if let Some(path) = s.strip_prefix(FILE_PREFIX) {
+        let path = fixture.base_dir.join(path);
+        let data = std::fs::read(&path)?;
+        return Ok(serde_json::json!({
+          "filename": path.file_name().and_then(|name| name.to_str()),
+          "data": data,
+        }));
+      }
+
+      if fixture.csv {
+        if let Some((index, column)) = metadata.column_by_name(column_name) {
+          return Ok(coerce_csv_value(
+            column,
+            metadata.json_metadata.columns[index].as_ref(),
+            &s,
+          ));
+        }
+      }
+      Ok(serde_json::Value::String(s))
+    }
+    // E.g. multiple "@file:" values for a `std.FileUploads` column.
+    serde_json::Value::Array(values) => Ok(serde_json::Value::Array(
+      values
+        .into_iter()
+        .map(|value| resolve_value(fixture, metadata, column_name, value, labels, report))
+        .collect::<Result<_, _>>()?,
+    )),
+    value => Ok(value),
+  };
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::app_state::test_state;
+
+  #[tokio::test]
+  async fn test_seed_fixtures() {
+    let state = test_state(None).await.unwrap();
+    state
+      .conn()
+      .execute_batch(
+        r#"
+          CREATE TABLE author (
+            id      BLOB PRIMARY KEY NOT NULL CHECK(is_uuid_v7(id)) DEFAULT (uuid_v7()),
+            name    TEXT NOT NULL
+          ) STRICT;
+          CREATE TABLE post (
+            id      INTEGER PRIMARY KEY,
+            author  BLOB NOT NULL REFERENCES author(id),
+            title   TEXT NOT NULL,
+            draft   INTEGER NOT NULL DEFAULT FALSE,
+            image   TEXT CHECK(jsonschema('std.FileUpload', image))
+          ) STRICT;
+        "#,
+      )
+      .await
+      .unwrap();
+    state.refresh_table_cache().await.unwrap();
+
+    let dir = temp_dir::TempDir::new().unwrap();
+    std::fs::write(
+      dir.path().join("post.csv"),
+      "title,author,draft,image\nFirst,@ref:author.alice,true,@file:image.txt\nSecond,@ref:author.bob,,\n",
+    )
+    .unwrap();
+    std::fs::write(dir.path().join("image.txt"), "hello").unwrap();
+    std::fs::write(
+      dir.path().join("author.json"),
+      r#"[{"@id": "alice", "name": "Alice"}, {"@id": "bob", "name": "Bob"}]"#,
+    )
+    .unwrap();
+
+    let fixtures = load_fixtures(&[dir.path().to_path_buf()]).unwrap();
+    // Loaded alphabetically but inserted in dependency order.
+    assert_eq!(fixtures[0].table, "author");
+
+    let report = seed_fixtures(&state, fixtures.clone(), false)
+      .await
+      .unwrap();
+    assert_eq!(
+      report.seeded,
+      vec![("author".to_string(), 2), ("post".to_string(), 2)]
+    );
+
+    let (name, draft, image): (String, bool, String) = state
+      .conn()
+      .read_query_row_f(
+        "SELECT author.name, post.draft, post.image FROM post JOIN author ON post.author = author.id WHERE post.title = 'First'",
+        (),
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+      )
+      .await
+      .unwrap()
+      .unwrap();
+    assert_eq!(name, "Alice");
+    assert!(draft);
+    let image: serde_json::Value = serde_json::from_str(&image).unwrap();
+    assert_eq!(image["filename"], "image.txt");
+
+    // Non-empty tables are skipped.
+    let report = seed_fixtures(&state, fixtures.clone(), true).await.unwrap();
+    assert!(report.seeded.is_empty());
+    assert_eq!(report.skipped.len(), 2);
+
+    // Broken references are rejected.
+    let fixture = Fixture::from_json(
+      "post",
+      serde_json::json!({"title": "Third", "author": "@ref:author.carol"}),
+      dir.path(),
+    )
+    .unwrap();
+    assert!(
+      seed_fixtures(&state, vec![fixtures[0].clone(), fixture], false)
+        .await
+        .is_err()
+    );
+  }
+}
@@ -0,0 +1,157 @@
+use std::path::PathBuf;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+
+use crate::clock::Clock;
+use crate::config::proto::Config;
+use crate::data_dir::DataDir;
+use crate::server::{InitError, Server, ServerOptions};
+
+/// Builder for embedding TrailBase into a Rust application, see [`Server::builder`].
+///
+/// Unset options fall back to [`ServerOptions::default`], except for the address, which defaults
+/// to "localhost:4000" like the `trail` CLI.
+///
+/// This is part of TrailBase's stable API surface, i.e. breaking changes only come with a new
+/// major version.
+#[derive(Debug)]
+pub struct ServerBuilder {
+  opts: ServerOptions,
+  config: Option<Config>,
+}
+
+impl Default for ServerBuilder {
+  fn default() -> Self {
+    return Self {
+      opts: ServerOptions {
+        address: "localhost:4000".to_string(),
+        ..Default::default()
+      },
+      config: None,
+    };
+  }
+}
+
+impl ServerBuilder {
+  /// Directory for databases, config, migrations, etc. Will be created on first start.
+  pub fn data_dir(mut self, path: impl Into<PathBuf>) -> Self {
+    self.opts.data_dir = DataDir(path.into());
+    return self;
+  }
+
+  /// Config overriding the one loaded from the data directory. It's validated against the
+  /// database schema after migrations have been applied but isn't written back to disk.
+  pub fn config(mut self, config: Config) -> Self {
+    self.config = Some(config);
+    return self;
+  }
+
+  /// Authority (<host>:<port>) the HTTP server binds to, e.g. "localhost:4000".
+  pub fn address(mut self, address: impl Into<String>) -> Self {
+    self.opts.address = address.into();
+    return self;
+  }
+
+  /// Serve the admin UI and APIs on a separate address.
+  pub fn admin_address(mut self, address: impl Into<String>) -> Self {
+    self.opts.admin_address = Some(address.into());
+    return self;
+  }
+
+  /// Static assets to be served at the HTTP root.
+  pub fn public_dir(mut self, path: impl Into<PathBuf>) -> Self {
+    self.opts.public_dir = Some(path.into());
+    return self;
+  }
+
+  /// Additionally log responses to stdout.
+  pub fn log_responses(mut self, log_responses: bool) -> Self {
+    self.opts.log_responses = log_responses;
+    return self;
+  }
+
+  /// Permissive CORS and cookies for development with externally hosted UIs.
+  pub fn dev(mut self, dev: bool) -> Self {
+    self.opts.dev = dev;
+    return self;
+  }
+
+  /// Redact PII from the admin UI.
+  pub fn demo(mut self, demo: bool) -> Self {
+    self.opts.demo = demo;
+    return self;
+  }
+
+  /// Disable the built-in public authentication (login, logout, ...) UI.
+  pub fn disable_auth_ui(mut self, disable: bool) -> Self {
+    self.opts.disable_auth_ui = disable;
+    return self;
+  }
+
+  /// Serve record APIs over gRPC alongside HTTP. Requires the "grpc" feature.
+  pub fn enable_grpc(mut self, enable: bool) -> Self {
+    self.opts.enable_grpc = enable;
+    return self;
+  }
+
+  /// Serve a GraphQL endpoint over record APIs. Requires the "graphql" feature.
+  pub fn enable_graphql(mut self, enable: bool) -> Self {
+    self.opts.enable_graphql = enable;
+    return self;
+  }
+
+  /// Disable response compression, see [`ServerOptions::disable_compression`].
+  pub fn disable_compression(mut self, disable: bool) -> Self {
+    self.opts.disable_compression = disable;
+    return self;
+  }
+
+  /// Limit the set of allowed origins the HTTP server will answer to.
+  pub fn cors_allowed_origins(mut self, origins: impl IntoIterator<Item = String>) -> Self {
+    self.opts.cors_allowed_origins = origins.into_iter().collect();
+    return self;
+  }
+
+  /// Number of V8 worker threads. Defaults to the number of available cores.
+  pub fn js_runtime_threads(mut self, threads: usize) -> Self {
+    self.opts.js_runtime_threads = Some(threads);
+    return self;
+  }
+
+  /// Keep the main and logs databases in memory, see [`ServerOptions::in_memory`].
+  pub fn in_memory(mut self, in_memory: bool) -> Self {
+    self.opts.in_memory = in_memory;
+    return self;
+  }
+
+  /// Don't install the global tracing subscriber, see [`ServerOptions::skip_tracing_init`].
+  pub fn skip_tracing_init(mut self, skip: bool) -> Self {
+    self.opts.skip_tracing_init = skip;
+    return self;
+  }
+
+  /// Trust `X-Test-User` headers, see [`ServerOptions::mock_auth`]. Never enable this in
+  /// production.
+  pub fn mock_auth(mut self, enable: bool) -> Self {
+    self.opts.mock_auth = enable;
+    return self;
+  }
+
+  /// Source of time and UUIDv7s, e.g. a [`Clock::frozen`] one for deterministic tests.
+  pub fn clock(mut self, clock: Clock) -> Self {
+    self.opts.clock = clock;
+    return self;
+  }
+
+  /// Serve HTTPS. Otherwise, a certificate and key are looked up in the data directory's secrets.
+  pub fn tls(mut self, cert: CertificateDer<'static>, key: PrivateKeyDer<'static>) -> Self {
+    self.opts.tls_cert = Some(cert);
+    self.opts.tls_key = Some(key);
+    return self;
+  }
+
+  /// Initializes the data directory, databases and routers. Serving only starts with
+  /// [`Server::serve`], which lets callers first e.g. seed data through [`Server::connection`].
+  pub async fn build(self) -> Result<Server, InitError> {
+    return Server::init_impl(self.opts, self.config, |_| async { Ok(()) }).await;
+  }
+}
@@ -0,0 +1,108 @@
+//! Converts panicking handlers into 500 responses rather than dropping the connection.
+
+use axum::response::{IntoResponse, Response};
+use log::*;
+use std::any::Any;
+use std::backtrace::Backtrace;
+use std::cell::RefCell;
+use std::sync::Once;
+use std::sync::atomic::{AtomicU64, Ordering};
+use uuid::Uuid;
+
+use crate::problem::{ErrorCode, Problem};
+
+static PANICS: AtomicU64 = AtomicU64::new(0);
+static INSTALL_HOOK: Once = Once::new();
+
+thread_local! {
+  // Backtraces can only be captured while unwinding, i.e. from within the panic hook, whereas the
+  // panic is only caught further up the stack on the same thread.
+  static BACKTRACE: RefCell<Option<Backtrace>> = const { RefCell::new(None) };
+}
+
+/// Number of caught handler panics since start.
+pub(crate) fn panic_count() -> u64 {
+  return PANICS.load(Ordering::Relaxed);
+}
+
+/// Chains a panic hook capturing backtraces in front of the existing one.
+pub(super) fn install_panic_hook() {
+  INSTALL_HOOK.call_once(|| {
+    let prev = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+      BACKTRACE.with(|backtrace| {
+        *backtrace.borrow_mut() = Some(Backtrace::force_capture());
+      });
+      prev(info);
+    }));
+  });
+}
+
+/// Handler for `tower_http::catch_panic::CatchPanicLayer`. Logs the panic and its backtrace under
+/// a fresh incident id, which is also returned to the client to be quoted in reports.
+pub(super) fn handle_panic(err: Box<dyn Any + Send + 'static>) -> Response {
+  PANICS.fetch_add(1, Ordering::Relaxed);
+
+  let incident_id = Uuid::now_v7().to_string();
+  let msg = if let Some(msg) = err.downcast_ref::<&str>() {
+    msg.to_string()
+  } else if let Some(msg) = err.downcast_ref::<String>() {
+    msg.clone()
+  } else {
+    "<unknown>".to_string()
+  };
+  let backtrace = BACKTRACE
+    .with(|backtrace| backtrace.borrow_mut().take())
+    .map_or_else(String::new, |backtrace| backtrace.to_string());
+
+  error!("Handler panicked [incident {incident_id}]: {msg}\n{backtrace}");
+
+  return Problem::new(ErrorCode::ServerPanic)
+    .with_incident_id(incident_id)
+    .into_response();
+}
+
+#[cfg(test)]
+mod tests {
+  use axum::Router;
+  use axum::body::Body;
+  use axum::extract::Request;
+  use axum::http::StatusCode;
+  use axum::routing::get;
+  use tower::ServiceExt;
+  use tower_http::catch_panic::CatchPanicLayer;
+
+  use super::*;
+
+  #[tokio::test]
+  async fn test_catch_panic() {
+    install_panic_hook();
+
+    let router = Router::new()
+      .route(
+        "/",
+        get(|| async {
+          if true {
+            panic!("boom");
+          }
+          "unreachable"
+        }),
+      )
+      .layer(CatchPanicLayer::custom(handle_panic));
+
+    let count = panic_count();
+    let response = router
+      .oneshot(Request::builder().uri("/").body(Body::empty()).unwrap())
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert!(panic_count() > count);
+
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+      .await
+      .unwrap();
+    let value: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(value["code"], "server/panic");
+    assert!(value["incident_id"].is_string(), "{value}");
+  }
+}
@@ -0,0 +1,88 @@
+//! Negotiated gzip, brotli and zstd response compression.
+//!
+//! Compression streams, i.e. chunked responses such as record listings are compressed as they're
+//! produced rather than buffered first. Thresholds only apply to responses with a known size.
+
+use axum::Router;
+use tower_http::compression::predicate::{And, NotForContentType, Predicate, SizeAbove};
+use tower_http::compression::{CompressionLayer, CompressionLevel};
+
+use crate::app_state::AppState;
+
+/// Minimum response size for record and SQL APIs. Single record reads are often smaller, where
+/// compression isn't worth the CPU and framing overhead.
+pub(super) const API_MIN_SIZE: u16 = 1024;
+/// Minimum response size for admin APIs, which regularly return large tables and schemas.
+pub(super) const ADMIN_MIN_SIZE: u16 = 256;
+/// Minimum response size for static assets.
+pub(super) const ASSETS_MIN_SIZE: u16 = 860;
+
+type CompressionPredicate =
+  And<And<And<SizeAbove, NotForContentType>, NotForContentType>, NotForContentType>;
+
+pub(super) fn compression_layer(min_size: u16) -> CompressionLayer<CompressionPredicate> {
+  // Server-sent events need to be flushed per event, gRPC has its own compression and images are
+  // compressed already.
+  let predicate = SizeAbove::new(min_size)
+    .and(NotForContentType::SSE)
+    .and(NotForContentType::GRPC)
+    .and(NotForContentType::IMAGES);
+
+  return CompressionLayer::new()
+    .quality(CompressionLevel::Fastest)
+    .compress_when(predicate);
+}
+
+/// Compresses responses of the given router's routes above `min_size` bytes, unless disabled.
+///
+/// NOTE: Auth routes are deliberately left uncompressed, since compressing secrets alongside
+/// attacker-controlled input is prone to BREACH-style attacks.
+pub(super) fn compress(router: Router<AppState>, min_size: u16, enabled: bool) -> Router<AppState> {
+  if !enabled {
+    return router;
+  }
+  return router.layer(compression_layer(min_size));
+}
+
+#[cfg(test)]
+mod tests {
+  use axum::body::Body;
+  use axum::extract::Request;
+  use axum::http::header::{ACCEPT_ENCODING, CONTENT_ENCODING};
+  use axum::routing::get;
+  use tower::ServiceExt;
+
+  use super::*;
+
+  #[tokio::test]
+  async fn test_compression_layer() {
+    let router: Router<()> = Router::new()
+      .route("/small", get(|| async { "small" }))
+      .route("/large", get(|| async { "large".repeat(1000) }))
+      .layer(compression_layer(API_MIN_SIZE));
+
+    let encoding = async |path: &str, accept: &str| {
+      let response = router
+        .clone()
+        .oneshot(
+          Request::builder()
+            .uri(path)
+            .header(ACCEPT_ENCODING, accept)
+            .body(Body::empty())
+            .unwrap(),
+        )
+        .await
+        .unwrap();
+      return response
+        .headers()
+        .get(CONTENT_ENCODING)
+        .map(|v| v.to_str().unwrap().to_string());
+    };
+
+    assert_eq!(encoding("/large", "gzip").await.as_deref(), Some("gzip"));
+    assert_eq!(encoding("/large", "br").await.as_deref(), Some("br"));
+    assert_eq!(encoding("/large", "zstd").await.as_deref(), Some("zstd"));
+    assert_eq!(encoding("/large", "identity").await, None);
+    assert_eq!(encoding("/small", "gzip").await, None);
+  }
+}
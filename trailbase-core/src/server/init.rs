@@ -4,11 +4,15 @@ use thiserror::Error;
 
 use crate::app_state::{AppState, AppStateArgs, build_objectstore};
 use crate::auth::jwt::{JwtHelper, JwtHelperError};
-use crate::config::load_or_init_config_textproto;
+use crate::clock::Clock;
+use crate::config::{
+  collation_spec_from_config, load_or_init_config_textproto, read_config_textproto_unvalidated,
+};
 use crate::constants::USER_TABLE;
 use crate::rand::generate_random_string;
 use crate::schema_metadata::SchemaMetadataCache;
 use crate::server::DataDir;
+use crate::tenant::TenantOptions;
 
 #[derive(Debug, Error)]
 pub enum InitError {
@@ -38,6 +42,8 @@ pub enum InitError {
   Queue(#[from] crate::queue::QueueError),
   #[error("Auth error: {0}")]
   Auth(#[from] crate::auth::AuthError),
+  #[error("Replication error: {0}")]
+  Replication(#[from] crate::replication::ReplicationError),
 }
 
 #[derive(Default)]
@@ -46,6 +52,9 @@ pub struct InitArgs {
   pub dev: bool,
   pub demo: bool,
   pub js_runtime_threads: Option<usize>,
+  pub in_memory: bool,
+  pub mock_auth: bool,
+  pub clock: Clock,
 }
 
 pub async fn init_app_state(
@@ -56,51 +65,137 @@ pub async fn init_app_state(
   // First create directory structure.
   data_dir.ensure_directory_structure().await?;
 
-  // Then open or init new databases.
-  let logs_conn = crate::connection::init_logs_db(Some(&data_dir))?;
+  if args.mock_auth {
+    warn!("Mock auth enabled: requests with an X-Test-User header bypass authentication.");
+  }
+
+  // Then open or init new databases, encrypted at rest if a database key is configured.
+  let key = if args.in_memory {
+    None
+  } else {
+    crate::connection::database_key()?
+  };
+  let logs_conn =
+    crate::connection::init_logs_db((!args.in_memory).then_some(&data_dir), key.clone())?;
 
   // TODO: At this early stage we're using an in-memory db. Go persistent before rolling out.
   let queue = crate::queue::Queue::new(None).await?;
 
+  // SQLite extensions and the number of read connections need to be known when opening
+  // connections, i.e. before the config can be fully loaded and validated against the schema.
+  let unvalidated_config = read_config_textproto_unvalidated(&data_dir).await?;
+  let extensions = match unvalidated_config {
+    Some(ref config) => {
+      crate::connection::resolve_sqlite_extensions(&data_dir, &config.sqlite_extensions)?
+    }
+    None => vec![],
+  };
+  let database_configs = unvalidated_config
+    .as_ref()
+    .map(|config| config.databases.clone())
+    .unwrap_or_default();
+  let read_connections = unvalidated_config
+    .and_then(|config| config.server.read_connections)
+    .map(|n| n as usize);
+  if !extensions.is_empty() {
+    info!("Loading SQLite extensions: {extensions:?}");
+  }
+
   // Open or init the main db. Note that we derive whether a new DB was initialized based on
   // whether the V1 migration had to be applied. Should be fairly robust.
-  let (conn, new_db) = crate::connection::init_main_db(Some(&data_dir), None)?;
+  let main_path = if args.in_memory {
+    // Unique name, so that multiple servers within the same process, e.g. tests, don't share
+    // state.
+    let name = format!("main-{}", uuid::Uuid::now_v7());
+    info!("Using in-memory main database: {name}");
+    PathBuf::from(trailbase_sqlite::connection::shared_memory_uri(&name))
+  } else {
+    data_dir.main_db_path()
+  };
+  let (conn, new_db) = crate::connection::init_main_db_at(
+    Some(main_path.clone()),
+    Some(data_dir.migrations_path()),
+    Some(extensions.clone()),
+    read_connections,
+    key.clone(),
+    args.clock.clone(),
+  )?;
+
+  let read_only_conn = crate::connection::init_read_only_main_db(
+    main_path.clone(),
+    Some(extensions.clone()),
+    key.clone(),
+  )?;
+
+  // Attach additional databases. Per-tenant databases are attached read-only as schema-only
+  // templates, their records live in the tenants' files, see `crate::tenant`.
+  if args.in_memory {
+    if !database_configs.is_empty() {
+      warn!("Additional databases are not supported in-memory. Skipping.");
+    }
+  } else if !database_configs.is_empty() {
+    let mut databases = vec![];
+    for config in &database_configs {
+      let Some(ref name) = config.name else {
+        continue;
+      };
+      let path = data_dir.database_path(name);
+      crate::connection::init_database(
+        &data_dir,
+        name,
+        &path,
+        Some(extensions.clone()),
+        key.as_ref(),
+      )?;
+
+      databases.push(crate::connection::AttachedDatabase {
+        name: name.clone(),
+        path,
+        read_only: config.per_tenant.unwrap_or(false),
+      });
+    }
+
+    info!("Attaching databases: {databases:?}");
+    crate::connection::attach_databases(&conn, &databases, key.as_ref()).await?;
+    crate::connection::attach_databases(&read_only_conn, &databases, key.as_ref()).await?;
+  }
+
+  let tenant_options = (!args.in_memory).then(|| TenantOptions {
+    data_dir: data_dir.clone(),
+    main_path,
+    extensions,
+    key,
+    clock: args.clock.clone(),
+  });
 
   let schema_metadata = SchemaMetadataCache::new(conn.clone()).await?;
 
   // Read config or write default one.
   let config = load_or_init_config_textproto(&data_dir, &schema_metadata).await?;
 
-  debug!("Initializing JSON schemas from config");
-  trailbase_schema::registry::set_user_schemas(
+  debug!("Applying pragma profiles from config");
+  crate::connection::apply_pragma_profiles(&config, &conn, &read_only_conn).await?;
+  if let Some(pages) = config.server.wal_autocheckpoint_pages {
+    conn.set_wal_autocheckpoint(pages).await?;
+  }
+
+  debug!("Initializing JSON schemas");
+  crate::json_schemas::load_json_schemas(&data_dir, &config).await?;
+
+  debug!("Initializing collations from config");
+  if let Err(err) = trailbase_extension::collation::set_collations(
     config
-      .schemas
+      .collations
       .iter()
-      .filter_map(|s| {
-        let Some(ref name) = s.name else {
-          warn!("Schema config entry missing name: {s:?}");
-          return None;
-        };
-
-        let Some(ref schema) = s.schema else {
-          warn!("Schema config entry missing schema: {s:?}");
-          return None;
-        };
-
-        let json = match serde_json::from_str(schema) {
-          Ok(json) => json,
-          Err(err) => {
-            error!("Invalid schema config entry for '{name}': {err}");
-            return None;
-          }
-        };
-
-        return Some((name.clone(), json));
-      })
+      .filter_map(collation_spec_from_config)
       .collect(),
-  )?;
+  ) {
+    error!("Failed to initialize collations: {err}");
+  }
 
-  let jwt = JwtHelper::init_from_path(&data_dir).await?;
+  let jwt = JwtHelper::init_from_path(&data_dir)
+    .await?
+    .with_clock(args.clock.clone());
 
   // Init geoip if present.
   let geoip_db_path = data_dir.root().join("GeoLite2-Country.mmdb");
@@ -108,7 +203,7 @@ pub async fn init_app_state(
     debug!("Failed to load maxmind geoip DB '{geoip_db_path:?}': {err}");
   }
 
-  let object_store = build_objectstore(&data_dir, config.server.s3_storage_config.as_ref())?;
+  let object_store = build_objectstore(&data_dir, &config.server)?;
 
   // Write out the latest .js/.d.ts runtime files.
   #[cfg(feature = "v8")]
@@ -120,16 +215,45 @@ pub async fn init_app_state(
     address: args.address,
     dev: args.dev,
     demo: args.demo,
+    mock_auth: args.mock_auth,
+    clock: args.clock,
     schema_metadata,
     config,
     conn,
+    read_only_conn,
     logs_conn,
     queue,
     jwt,
     object_store,
     js_runtime_threads: args.js_runtime_threads,
+    tenant_options,
   });
 
+  #[cfg(feature = "queue")]
+  {
+    let state = app_state.clone();
+    tokio::spawn(async move {
+      if let Err(err) = state.queue().run(state.clone()).await {
+        error!("Queue worker exited: {err}");
+      }
+    });
+  }
+
+  #[cfg(feature = "cdc")]
+  tokio::spawn(crate::cdc::run_change_data_capture(app_state.clone()));
+
+  tokio::spawn(crate::records::sync::run_sync_triggers(app_state.clone()));
+
+  #[cfg(not(feature = "cdc"))]
+  if app_state
+    .get_config()
+    .server
+    .change_data_capture
+    .is_some_and(|cdc| !cdc.tables.is_empty())
+  {
+    warn!("Change data capture configured but TrailBase was built without the \"cdc\" feature.");
+  }
+
   if new_db {
     let num_admins: i64 = app_state
       .user_conn()
@@ -1,37 +1,57 @@
+mod builder;
+mod catch_panic;
+mod compression;
 mod init;
 mod serve;
 
+use axum::extract::connect_info::IntoMakeServiceWithConnectInfo;
 use axum::extract::{DefaultBodyLimit, Request, State};
 use axum::handler::HandlerWithoutStateExt;
-use axum::http::{HeaderValue, StatusCode};
+use axum::http::header::{CONTENT_TYPE, LOCATION, RETRY_AFTER};
+use axum::http::{HeaderName, HeaderValue, StatusCode};
 use axum::middleware::{self, Next};
 use axum::response::{IntoResponse, Response};
 use axum::routing::get;
 use axum::{RequestExt, Router};
 use log::*;
+use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::signal;
+use tokio::sync::watch;
 use tokio::task::JoinSet;
 use tokio_rustls::{
   TlsAcceptor,
   rustls::ServerConfig,
   rustls::pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
 };
+use tower::Layer;
 use tower_cookies::CookieManagerLayer;
+use tower_http::catch_panic::CatchPanicLayer;
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
 use tower_http::{cors, limit::RequestBodyLimitLayer, services::ServeDir, trace::TraceLayer};
 use tracing_subscriber::{filter, prelude::*};
 use trailbase_assets::AssetService;
 
 use crate::admin;
 use crate::app_state::AppState;
+use crate::audit::audit_admin_action;
 use crate::auth::util::is_admin;
 use crate::auth::{self, AuthError, User};
-use crate::constants::{ADMIN_API_PATH, HEADER_CSRF_TOKEN};
+use crate::clock::Clock;
+use crate::config::proto::Config;
+use crate::constants::{
+  ADMIN_API_PATH, HEADER_CSRF_TOKEN, HEADER_REQUEST_ID, MAX_REQUEST_BODY_SIZE,
+};
 use crate::data_dir::DataDir;
 use crate::logging;
+use crate::rate_limit::{RouteGroup, rate_limit_middleware};
 use crate::records;
+use crate::records::tus_upload;
+use crate::sql_api;
 
+pub use builder::ServerBuilder;
+pub(crate) use catch_panic::panic_count;
 pub use init::{InitArgs, InitError, init_app_state};
 
 /// A set of options to configure serving behaviors. Changing any of these options
@@ -65,18 +85,81 @@ pub struct ServerOptions {
   /// Disable the built-in public authentication (login, logout, ...) UI.
   pub disable_auth_ui: bool,
 
+  /// Serve record APIs over gRPC alongside HTTP. Requires the "grpc" feature.
+  pub enable_grpc: bool,
+
+  /// Serve a GraphQL endpoint over record APIs. Requires the "graphql" feature.
+  pub enable_graphql: bool,
+
+  /// Disable negotiated gzip, brotli and zstd compression of responses, e.g. when running behind
+  /// a reverse proxy that compresses already.
+  pub disable_compression: bool,
+
   /// Limit the set of allowed origins the HTTP server will answer to.
   pub cors_allowed_origins: Vec<String>,
 
   /// Number of V8 worker threads. If set to None, default of num available cores will be used.
   pub js_runtime_threads: Option<usize>,
 
+  /// Keep the main and logs databases in memory rather than the data directory, e.g. for
+  /// integration tests or ephemeral preview environments. Migrations and config are still read
+  /// from the data directory. All data is lost on shutdown.
+  pub in_memory: bool,
+
+  /// Don't install the global tracing subscriber recording request logs. It can only be installed
+  /// once per process, thus this allows running multiple servers within the same process, e.g.
+  /// in tests. Callers may still install a `logging::SqliteLogLayer` themselves.
+  pub skip_tracing_init: bool,
+
+  /// Authenticate requests carrying an `X-Test-User: <id>[;<role>,...]` header as the given user
+  /// without any token validation, e.g. for integration tests and local frontend development.
+  /// Never enable this in production.
+  pub mock_auth: bool,
+
+  /// Source of time and UUIDv7s for record ids and tokens. Defaults to the system clock, see
+  /// [`Clock::frozen`] for deterministic tests.
+  pub clock: Clock,
+
   /// TLS certificate path.
   pub tls_cert: Option<CertificateDer<'static>>,
   /// TLS key path.
   pub tls_key: Option<PrivateKeyDer<'static>>,
 }
 
+/// Handle to gracefully shut down a [`Server`] from within the embedding application, e.g. in
+/// tests or when TrailBase's lifetime is tied to other services. Cheap to clone.
+#[derive(Clone, Debug)]
+pub struct ShutdownHandle {
+  sender: Arc<watch::Sender<bool>>,
+}
+
+impl Default for ShutdownHandle {
+  fn default() -> Self {
+    return Self {
+      sender: Arc::new(watch::Sender::new(false)),
+    };
+  }
+}
+
+impl ShutdownHandle {
+  /// Stops accepting new connections and lets [`Server::serve`] return once in-flight requests
+  /// have completed.
+  pub fn shutdown(&self) {
+    self.sender.send_replace(true);
+  }
+
+  pub fn is_shutdown(&self) -> bool {
+    return *self.sender.borrow();
+  }
+
+  /// Resolves once [`Self::shutdown`] has been called.
+  pub async fn wait(&self) {
+    let mut receiver = self.sender.subscribe();
+    let _ = receiver.wait_for(|shutdown| *shutdown).await;
+  }
+}
+
+#[non_exhaustive]
 pub struct Server {
   pub state: AppState,
 
@@ -86,9 +169,58 @@ pub struct Server {
 
   // TLS/SSL
   pub tls: Option<(CertificateDer<'static>, PrivateKeyDer<'static>)>,
+
+  pub shutdown: ShutdownHandle,
 }
 
 impl Server {
+  /// Returns a builder for embedding TrailBase into a Rust application:
+  ///
+  /// ```no_run
+  /// # async fn run() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+  /// let server = trailbase::Server::builder()
+  ///   .data_dir("./traildepot")
+  ///   .address("localhost:4000")
+  ///   .build()
+  ///   .await?;
+  ///
+  /// let shutdown = server.shutdown_handle();
+  /// tokio::spawn(async move {
+  ///   tokio::time::sleep(std::time::Duration::from_secs(60)).await;
+  ///   shutdown.shutdown();
+  /// });
+  ///
+  /// server.serve().await?;
+  /// # Ok(())
+  /// # }
+  /// ```
+  ///
+  /// The builder, [`Server::router`], [`Server::connection`], [`Server::shutdown_handle`] and
+  /// [`Server::serve`] are covered by semantic versioning guarantees.
+  pub fn builder() -> ServerBuilder {
+    return ServerBuilder::default();
+  }
+
+  /// The main router serving the public APIs, auth UI and, unless served on a separate address,
+  /// the admin UI. Can be merged into or nested within an application's own axum router.
+  pub fn router(&self) -> &Router {
+    return &self.main_router.1;
+  }
+
+  /// Connection to the main database.
+  pub fn connection(&self) -> &trailbase_sqlite::Connection {
+    return self.state.conn();
+  }
+
+  /// Record lifecycle hooks, e.g. to derive fields or veto writes from Rust.
+  pub fn record_hooks(&self) -> &crate::records::hooks::RecordHooks {
+    return self.state.record_hooks();
+  }
+
+  pub fn shutdown_handle(&self) -> ShutdownHandle {
+    return self.shutdown.clone();
+  }
+
   /// Initializes the server. Will create a new data directory on first start.
   pub async fn init(opts: ServerOptions) -> Result<Self, InitError> {
     return Self::init_with_custom_initializer(opts, |_| async { Ok(()) }).await;
@@ -106,6 +238,14 @@ impl Server {
   pub async fn init_with_custom_initializer(
     opts: ServerOptions,
     on_first_init: impl AsyncFnOnce(AppState) -> Result<(), Box<dyn std::error::Error + Sync + Send>>,
+  ) -> Result<Self, InitError> {
+    return Self::init_impl(opts, None, on_first_init).await;
+  }
+
+  async fn init_impl(
+    opts: ServerOptions,
+    config: Option<Config>,
+    on_first_init: impl AsyncFnOnce(AppState) -> Result<(), Box<dyn std::error::Error + Sync + Send>>,
   ) -> Result<Self, InitError> {
     let version_info = rustc_tools_util::get_version_info!();
     info!(
@@ -122,6 +262,9 @@ impl Server {
         dev: opts.dev,
         demo: opts.demo,
         js_runtime_threads: opts.js_runtime_threads,
+        in_memory: opts.in_memory,
+        mock_auth: opts.mock_auth,
+        clock: opts.clock.clone(),
       },
     )
     .await?;
@@ -150,13 +293,15 @@ impl Server {
       .with_default(filter::LevelFilter::OFF)
       .with_target(crate::logging::EVENT_TARGET, crate::logging::LEVEL);
 
-    tracing_subscriber::Registry::default()
-      .with(filter_layer)
-      .with(logging::SqliteLogLayer::new(
-        &state,
-        /* log-to-stdout= */ opts.log_responses,
-      ))
-      .init();
+    if !opts.skip_tracing_init {
+      tracing_subscriber::Registry::default()
+        .with(filter_layer)
+        .with(logging::SqliteLogLayer::new(
+          &state,
+          /* log-to-stdout= */ opts.log_responses,
+        ))
+        .init();
+    }
 
     if new_data_dir {
       on_first_init(state.clone())
@@ -164,6 +309,13 @@ impl Server {
         .map_err(|err| InitError::CustomInit(err.to_string()))?;
     }
 
+    // Applied after the first-init hook, which may create tables referenced by the config.
+    if let Some(config) = config {
+      state.validate_and_update_config(config, None).await?;
+    }
+    crate::records::query_plan::spawn_query_plan_check(&state);
+    crate::replication::spawn_replication(&state).await?;
+
     #[cfg(feature = "v8")]
     let js_routes: Option<Router<AppState>> =
       crate::js::runtime::load_routes_and_jobs_from_js_modules(&state)
@@ -173,11 +325,24 @@ impl Server {
     #[cfg(not(feature = "v8"))]
     let js_routes: Option<Router<AppState>> = None;
 
+    #[cfg(feature = "wasm")]
+    let plugins = crate::wasm::load_plugins(&state)
+      .await
+      .map_err(|err| InitError::ScriptError(err.to_string()))?;
+
     Ok(Self {
       state: state.clone(),
-      main_router: Self::build_main_router(&state, &opts, js_routes).await,
+      main_router: Self::build_main_router(
+        &state,
+        &opts,
+        js_routes,
+        #[cfg(feature = "wasm")]
+        plugins.as_ref(),
+      )
+      .await,
       admin_router: Self::build_independent_admin_router(&state, &opts),
       tls: Self::load_tls(&opts),
+      shutdown: ShutdownHandle::default(),
     })
   }
 
@@ -186,12 +351,16 @@ impl Server {
     #[cfg(unix)]
     {
       let state = self.state.clone();
+      let shutdown = self.shutdown.clone();
       tokio::spawn(async move {
         // An infinite stream of hangup signals.
         let mut stream = signal::unix::signal(signal::unix::SignalKind::hangup()).expect("startup");
 
         loop {
-          stream.recv().await;
+          tokio::select! {
+            _ = stream.recv() => {},
+            _ = shutdown.wait() => break,
+          };
           log::info!("Received HUP signal. Reloading config.");
 
           match crate::config::load_or_init_config_textproto(
@@ -201,6 +370,15 @@ impl Server {
           .await
           {
             Ok(config) => {
+              // Reload JSON schemas first, since tables and record APIs may depend on them.
+              if let Err(err) =
+                crate::json_schemas::load_json_schemas(state.data_dir(), &config).await
+              {
+                log::error!("Failed to reload JSON schemas: {err}");
+              } else if let Err(err) = state.refresh_table_cache().await {
+                log::error!("Failed to rebuild table metadata: {err}");
+              }
+
               if let Err(err) = state.validate_and_update_config(config, None).await {
                 log::error!("Failed to reload config: {err}");
               }
@@ -214,7 +392,7 @@ impl Server {
     }
 
     // Finally start serving.
-    return serve(self.main_router, self.admin_router, self.tls).await;
+    return serve_with_shutdown(self.main_router, self.admin_router, self.tls, self.shutdown).await;
   }
 
   pub fn load_tls(
@@ -252,8 +430,8 @@ impl Server {
     };
   }
 
-  fn build_admin_router(state: &AppState) -> Router<AppState> {
-    return Router::new()
+  fn build_admin_router(state: &AppState, opts: &ServerOptions) -> Router<AppState> {
+    let router = Router::new()
       .nest(
         &format!("/{ADMIN_API_PATH}/"),
         admin::router().layer(middleware::from_fn_with_state(
@@ -269,6 +447,12 @@ impl Server {
           Some("index.html".to_string()),
         ),
       );
+
+    return compression::compress(
+      router,
+      compression::ADMIN_MIN_SIZE,
+      !opts.disable_compression,
+    );
   }
 
   fn build_independent_admin_router(
@@ -282,7 +466,7 @@ impl Server {
 
     let router = Router::new()
       .merge(auth::admin_auth_router())
-      .merge(Self::build_admin_router(state));
+      .merge(Self::build_admin_router(state, opts));
 
     return Some((
       address.clone(),
@@ -294,15 +478,45 @@ impl Server {
     state: &AppState,
     opts: &ServerOptions,
     custom_router: Option<Router<AppState>>,
+    #[cfg(feature = "wasm")] plugins: Option<&crate::wasm::Plugins>,
   ) -> (String, Router<()>) {
+    let compress = |router: Router<AppState>| {
+      return compression::compress(router, compression::API_MIN_SIZE, !opts.disable_compression);
+    };
+
+    let rate_limit = |router: Router<AppState>, group: RouteGroup| {
+      return router.layer(middleware::from_fn_with_state(
+        (state.clone(), group),
+        rate_limit_middleware,
+      ));
+    };
+
+    // Record API requests resolved to a tenant are served from the tenant's databases.
+    let records_router = records::router().layer(middleware::from_fn_with_state(
+      (state.clone(), crate::tenant::TenantRouters::default()),
+      crate::tenant::tenant_middleware,
+    ));
+
     let mut router = Router::new()
       // Public, stable and versioned APIs.
-      .merge(records::router())
-      .merge(auth::router())
-      .route("/api/healthcheck", get(healthcheck_handler));
+      .merge(compress(rate_limit(records_router, RouteGroup::Records)))
+      .merge(rate_limit(auth::router(), RouteGroup::Auth))
+      .merge(compress(rate_limit(sql_api::router(), RouteGroup::Query)))
+      .route("/api/healthcheck", get(healthcheck_handler))
+      .route("/api/openapi.json", get(crate::openapi::openapi_handler));
+
+    if opts.enable_graphql {
+      #[cfg(feature = "graphql")]
+      {
+        router = router.merge(compress(crate::graphql::router()));
+      }
+
+      #[cfg(not(feature = "graphql"))]
+      warn!("GraphQL requested but TrailBase was built without the \"graphql\" feature.");
+    }
 
     if !has_indepenedent_admin_router(opts) {
-      router = router.merge(Self::build_admin_router(state));
+      router = router.merge(Self::build_admin_router(state, opts));
     }
 
     if !opts.disable_auth_ui {
@@ -313,6 +527,11 @@ impl Server {
       router = router.merge(custom_router);
     }
 
+    #[cfg(feature = "wasm")]
+    if let Some(plugins) = plugins {
+      router = router.merge(plugins.router.clone());
+    }
+
     if let Some(public_dir) = &opts.public_dir {
       if !tokio::fs::try_exists(public_dir).await.unwrap_or(false) {
         panic!("--public_dir={public_dir:?} path does not exist.")
@@ -322,8 +541,34 @@ impl Server {
         (StatusCode::NOT_FOUND, "Not found")
       }
 
-      router = router
-        .fallback_service(ServeDir::new(public_dir).not_found_service(handle_404.into_service()));
+      let serve_dir = ServeDir::new(public_dir).not_found_service(handle_404.into_service());
+      router = if opts.disable_compression {
+        router.fallback_service(serve_dir)
+      } else {
+        router.fallback_service(
+          compression::compression_layer(compression::ASSETS_MIN_SIZE).layer(serve_dir),
+        )
+      };
+    }
+
+    // Plugin middleware wraps all routes including fallbacks.
+    #[cfg(feature = "wasm")]
+    if let Some(plugins) = plugins {
+      router = plugins.layer(state, router);
+    }
+
+    if opts.enable_grpc {
+      // gRPC requests are told apart by content type, thus wrap all routes including fallbacks.
+      #[cfg(feature = "grpc")]
+      {
+        router = router.layer(axum::middleware::from_fn_with_state(
+          state.clone(),
+          crate::grpc::grpc_middleware,
+        ));
+      }
+
+      #[cfg(not(feature = "grpc"))]
+      warn!("gRPC requested but TrailBase was built without the \"grpc\" feature.");
     }
 
     return (
@@ -337,7 +582,21 @@ impl Server {
     opts: &ServerOptions,
     router: Router<AppState>,
   ) -> Router<()> {
+    let request_id = HeaderName::from_static(HEADER_REQUEST_ID);
+    catch_panic::install_panic_hook();
+
     return router
+      // Innermost, so that panics are turned into problem responses, which are then subject to
+      // the request id and logging layers below.
+      .layer(CatchPanicLayer::custom(catch_panic::handle_panic))
+      .layer(middleware::from_fn_with_state(
+        state.clone(),
+        crate::problem::problem_middleware,
+      ))
+      .layer(middleware::from_fn_with_state(
+        state.clone(),
+        crate::client_ip::client_ip_source_middleware,
+      ))
       .layer(CookieManagerLayer::new())
       .layer(build_cors(opts))
       .layer(
@@ -347,9 +606,13 @@ impl Server {
           .on_request(logging::sqlite_logger_on_request)
           .on_response(logging::sqlite_logger_on_response),
       )
+      // NOTE: Layers wrap each other outside-in, i.e. the request id is set before the tracing
+      // span gets created and propagated to the response afterwards.
+      .layer(PropagateRequestIdLayer::new(request_id.clone()))
+      .layer(SetRequestIdLayer::new(request_id, MakeRequestUuid))
       // Default is only 2MB Increase to 10MB.
       .layer(DefaultBodyLimit::disable())
-      .layer(RequestBodyLimitLayer::new(10 * 1024 * 1024))
+      .layer(RequestBodyLimitLayer::new(MAX_REQUEST_BODY_SIZE))
       .with_state(state.clone());
   }
 }
@@ -395,7 +658,15 @@ async fn assert_admin_api_access(
     return Err(AuthError::BadRequest("invalid CSRF token"));
   }
 
-  return Ok(next.run(req).await);
+  let method = req.method().clone();
+  let path = req.uri().path().to_string();
+  let response = next.run(req).await;
+
+  if !method.is_safe() && response.status().is_success() {
+    audit_admin_action(&state, &user, method.as_str(), &path).await;
+  }
+
+  return Ok(response);
 }
 
 fn build_cors(opts: &ServerOptions) -> cors::CorsLayer {
@@ -425,11 +696,29 @@ fn build_cors(opts: &ServerOptions) -> cors::CorsLayer {
   // Cannot combine `Access-Control-Allow-Credentials: true` with `Access-Control-Allow-Methods: *`
   return cors::CorsLayer::new()
     .allow_methods(cors::Any)
+    // Resumable uploads require tus' custom headers and "application/offset+octet-stream".
+    .allow_headers(
+      [CONTENT_TYPE]
+        .into_iter()
+        .chain(tus_upload::TUS_HEADERS)
+        .collect::<Vec<_>>(),
+    )
+    .expose_headers(
+      [
+        HeaderName::from_static(HEADER_REQUEST_ID),
+        RETRY_AFTER,
+        LOCATION,
+        tus_upload::UPLOAD_EXPIRES,
+      ]
+      .into_iter()
+      .chain(tus_upload::TUS_HEADERS)
+      .collect::<Vec<_>>(),
+    )
     // .allow_credentials(wildcard)
     .allow_origin(origins);
 }
 
-async fn shutdown_signal() {
+async fn shutdown_signal(shutdown: ShutdownHandle) {
   let ctrl_c = async {
     signal::ctrl_c()
       .await
@@ -471,6 +760,9 @@ async fn shutdown_signal() {
   }
 
   tokio::select! {
+      _ = shutdown.wait() => {
+      info!("Shutdown requested. Shutting down gracefully.");
+    },
       _ = ctrl_c => {
       println!("Received Ctrl+C. Shutting down gracefully.");
       tokio::spawn(timer());
@@ -486,6 +778,16 @@ pub async fn serve(
   main_router: (String, Router),
   admin_router: Option<(String, Router)>,
   tls: Option<(CertificateDer<'static>, PrivateKeyDer<'static>)>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+  return serve_with_shutdown(main_router, admin_router, tls, ShutdownHandle::default()).await;
+}
+
+/// Like [`serve`] but additionally returns once `shutdown` is triggered.
+pub async fn serve_with_shutdown(
+  main_router: (String, Router),
+  admin_router: Option<(String, Router)>,
+  tls: Option<(CertificateDer<'static>, PrivateKeyDer<'static>)>,
+  shutdown: ShutdownHandle,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
   let has_tls = tls.is_some();
   let addr = main_router.0.clone();
@@ -500,12 +802,13 @@ pub async fn serve(
       let tls_clone = tls
         .as_ref()
         .map(|(cert, key)| (cert.clone(), key.clone_key()));
-      set.spawn(async move { start_listen(&addr, router, tls_clone).await });
+      let shutdown = shutdown.clone();
+      set.spawn(async move { start_listen(&addr, router, tls_clone, shutdown).await });
     }
 
     {
       let (addr, router) = main_router;
-      set.spawn(async move { start_listen(&addr, router, tls).await });
+      set.spawn(async move { start_listen(&addr, router, tls, shutdown).await });
     }
 
     set
@@ -521,10 +824,20 @@ pub async fn serve(
   return Ok(());
 }
 
+/// Makes the peer address available to handlers, e.g. for access rules and rate limiting.
+fn with_connect_info(
+  router: &Router<()>,
+) -> IntoMakeServiceWithConnectInfo<Router<()>, SocketAddr> {
+  return router
+    .clone()
+    .into_make_service_with_connect_info::<SocketAddr>();
+}
+
 async fn start_listen(
   addr: &str,
   router: Router<()>,
   tls: Option<(CertificateDer<'static>, PrivateKeyDer<'static>)>,
+  shutdown: ShutdownHandle,
 ) {
   match tls {
     Some((cert, key)) => {
@@ -546,8 +859,8 @@ async fn start_listen(
         acceptor: TlsAcceptor::from(Arc::new(server_config)),
       };
 
-      if let Err(err) = serve::serve(listener, router.clone())
-        .with_graceful_shutdown(shutdown_signal())
+      if let Err(err) = serve::serve(listener, with_connect_info(&router))
+        .with_graceful_shutdown(shutdown_signal(shutdown))
         .await
       {
         error!("Failed to start server: {err}");
@@ -563,8 +876,8 @@ async fn start_listen(
         }
       };
 
-      if let Err(err) = serve::serve(listener, router.clone())
-        .with_graceful_shutdown(shutdown_signal())
+      if let Err(err) = serve::serve(listener, with_connect_info(&router))
+        .with_graceful_shutdown(shutdown_signal(shutdown))
         .await
       {
         error!("Failed to start server: {err}");
@@ -0,0 +1,558 @@
+//! Read-only SQL API for trusted clients, covering reporting use cases the record APIs' filter
+//! language cannot express.
+//!
+//! Queries are guarded by SQLite's authorizer, which only permits `SELECT` statements reading
+//! from the configured allow-list of tables and views, as well as row and time limits. They run on
+//! a dedicated connection opened with `SQLITE_OPEN_READONLY`, which rejects writes even if the
+//! authorizer were bypassed.
+
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use axum::{Json, Router};
+use log::*;
+use rusqlite::hooks::{AuthAction, AuthContext, Authorization};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use trailbase_schema::sqlite::sqlite3_parse_into_statements;
+use trailbase_sqlite::rows::value_to_json;
+
+use crate::app_state::AppState;
+use crate::auth::User;
+use crate::auth::util::is_admin;
+use crate::config::proto::SqlApiConfig;
+use crate::constants::{HEADER_API_KEY, SQL_API_PATH};
+use crate::problem::{ErrorCode, Problem};
+
+const DEFAULT_MAX_ROWS: u64 = 1000;
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Publicly visible errors of the SQL API.
+#[derive(Debug, Error)]
+pub enum SqlApiError {
+  #[error("Not Found")]
+  NotFound,
+  #[error("Forbidden")]
+  Forbidden,
+  #[error("Bad request: {0}")]
+  BadRequest(String),
+  #[error("Query timed out")]
+  Timeout,
+  #[error("Internal: {0}")]
+  Internal(Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl From<rusqlite::Error> for SqlApiError {
+  fn from(err: rusqlite::Error) -> Self {
+    return match err.sqlite_error_code() {
+      Some(rusqlite::ErrorCode::AuthorizationForStatementDenied) => {
+        Self::BadRequest("Query not permitted".to_string())
+      }
+      Some(rusqlite::ErrorCode::OperationInterrupted) => Self::Timeout,
+      // Trusted clients benefit from seeing syntax errors and the like.
+      Some(_) => Self::BadRequest(err.to_string()),
+      None => Self::Internal(err.into()),
+    };
+  }
+}
+
+impl From<trailbase_sqlite::Error> for SqlApiError {
+  fn from(err: trailbase_sqlite::Error) -> Self {
+    return match err {
+      trailbase_sqlite::Error::Rusqlite(err) => err.into(),
+      err => Self::Internal(err.into()),
+    };
+  }
+}
+
+impl IntoResponse for SqlApiError {
+  fn into_response(self) -> Response {
+    let problem = match self {
+      Self::NotFound => Problem::new(ErrorCode::SqlNotFound),
+      Self::Forbidden => Problem::new(ErrorCode::SqlForbidden),
+      Self::BadRequest(msg) => Problem::new(ErrorCode::SqlBadRequest).with_detail(msg),
+      Self::Timeout => Problem::new(ErrorCode::SqlTimeout),
+      Self::Internal(err) => {
+        warn!("SQL API internal error: {err}");
+        Problem::new(ErrorCode::SqlInternal)
+      }
+    };
+
+    return problem.into_response();
+  }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SqlQueryRequest {
+  /// A single `SELECT` statement.
+  pub query: String,
+  /// Positional parameters bound to `?` or `?NNN` placeholders.
+  #[serde(default)]
+  pub params: Vec<serde_json::Value>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct SqlQueryResponse {
+  pub columns: Vec<String>,
+  pub rows: Vec<Vec<serde_json::Value>>,
+  /// Whether rows were dropped due to exceeding the configured row limit.
+  pub truncated: bool,
+}
+
+pub(crate) fn router() -> Router<AppState> {
+  return Router::new().route(&format!("/{SQL_API_PATH}/query"), post(sql_query_handler));
+}
+
+/// Runs a parameterized read-only query.
+pub async fn sql_query_handler(
+  State(state): State<AppState>,
+  user: Option<User>,
+  headers: HeaderMap,
+  Json(request): Json<SqlQueryRequest>,
+) -> Result<Json<SqlQueryResponse>, SqlApiError> {
+  let Some(config) = state.access_config(|c| c.server.sql_api.clone()) else {
+    return Err(SqlApiError::NotFound);
+  };
+
+  check_access(&state, &config, user.as_ref(), &headers).await?;
+
+  // Reject anything but a single SELECT early. The authorizer below is the actual guard.
+  let statements = sqlite3_parse_into_statements(&request.query)
+    .map_err(|err| SqlApiError::BadRequest(err.to_string()))?;
+  match statements.as_slice() {
+    [sqlite3_parser::ast::Stmt::Select(_)] => {}
+    _ => {
+      return Err(SqlApiError::BadRequest(
+        "Expected a single SELECT statement".to_string(),
+      ));
+    }
+  };
+
+  let params = request
+    .params
+    .into_iter()
+    .map(json_to_param)
+    .collect::<Result<Vec<_>, _>>()?;
+
+  let allowed_tables = config.allowed_tables;
+  let max_rows = config.max_rows.unwrap_or(DEFAULT_MAX_ROWS) as usize;
+  let timeout = config
+    .timeout_ms
+    .map_or(DEFAULT_TIMEOUT, Duration::from_millis);
+  let query = request.query;
+
+  let response = state
+    .read_only_conn()
+    .call(move |conn| {
+      conn.authorizer(Some(move |ctx: AuthContext<'_>| {
+        authorize(&allowed_tables, ctx)
+      }));
+      let deadline = Instant::now() + timeout;
+      conn.progress_handler(1000, Some(move || Instant::now() > deadline));
+
+      let result = run_query(conn, &query, params, max_rows);
+
+      conn.authorizer(None::<fn(AuthContext<'_>) -> Authorization>);
+      conn.progress_handler(0, None::<fn() -> bool>);
+
+      return Ok(result);
+    })
+    .await??;
+
+  return Ok(Json(response));
+}
+
+async fn check_access(
+  state: &AppState,
+  config: &SqlApiConfig,
+  user: Option<&User>,
+  headers: &HeaderMap,
+) -> Result<(), SqlApiError> {
+  let api_key = config.api_key.as_deref().filter(|key| !key.is_empty());
+  if let (Some(api_key), Some(received)) = (api_key, headers.get(HEADER_API_KEY)) {
+    if constant_time_eq(api_key.as_bytes(), received.as_bytes()) {
+      return Ok(());
+    }
+    return Err(SqlApiError::Forbidden);
+  }
+
+  if let Some(user) = user {
+    if is_admin(state, user).await {
+      return Ok(());
+    }
+  }
+
+  return Err(SqlApiError::Forbidden);
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+  return a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0;
+}
+
+/// Deterministic SQL functions w/o side effects, which queries may call. Anything else, e.g.
+/// `load_extension`, functions of loaded extensions or `define`, is denied.
+const ALLOWED_FUNCTIONS: &[&str] = &[
+  // Core scalar functions.
+  "abs",
+  "char",
+  "coalesce",
+  "concat",
+  "concat_ws",
+  "format",
+  "glob",
+  "hex",
+  "ifnull",
+  "iif",
+  "instr",
+  "length",
+  "like",
+  "likelihood",
+  "likely",
+  "lower",
+  "ltrim",
+  "max",
+  "min",
+  "nullif",
+  "octet_length",
+  "printf",
+  "quote",
+  "replace",
+  "round",
+  "rtrim",
+  "sign",
+  "substr",
+  "substring",
+  "trim",
+  "typeof",
+  "unhex",
+  "unicode",
+  "unlikely",
+  "upper",
+  // Date and time functions.
+  "date",
+  "time",
+  "datetime",
+  "julianday",
+  "unixepoch",
+  "strftime",
+  "timediff",
+  // Aggregate and window functions.
+  "avg",
+  "count",
+  "group_concat",
+  "string_agg",
+  "sum",
+  "total",
+  "row_number",
+  "rank",
+  "dense_rank",
+  "percent_rank",
+  "cume_dist",
+  "ntile",
+  "lag",
+  "lead",
+  "first_value",
+  "last_value",
+  "nth_value",
+  // Math functions.
+  "acos",
+  "acosh",
+  "asin",
+  "asinh",
+  "atan",
+  "atan2",
+  "atanh",
+  "ceil",
+  "ceiling",
+  "cos",
+  "cosh",
+  "degrees",
+  "exp",
+  "floor",
+  "ln",
+  "log",
+  "log10",
+  "log2",
+  "mod",
+  "pi",
+  "pow",
+  "power",
+  "radians",
+  "sin",
+  "sinh",
+  "sqrt",
+  "tan",
+  "tanh",
+  "trunc",
+  // JSON functions.
+  "json",
+  "jsonb",
+  "json_array",
+  "jsonb_array",
+  "json_array_length",
+  "json_error_position",
+  "json_extract",
+  "jsonb_extract",
+  "json_insert",
+  "jsonb_insert",
+  "json_object",
+  "jsonb_object",
+  "json_patch",
+  "jsonb_patch",
+  "json_pretty",
+  "json_remove",
+  "jsonb_remove",
+  "json_replace",
+  "jsonb_replace",
+  "json_set",
+  "jsonb_set",
+  "json_type",
+  "json_valid",
+  "json_quote",
+  "json_group_array",
+  "jsonb_group_array",
+  "json_group_object",
+  "jsonb_group_object",
+  "json_each",
+  "json_tree",
+  // TrailBase's own deterministic functions.
+  "regexp",
+  "is_uuid",
+  "is_uuid_v7",
+  "is_ulid",
+  "uuid_text",
+  "uuid_parse",
+  "is_email",
+  "is_json",
+];
+
+/// Whether queries may call the function. Functions invoked by views or triggers, i.e. with an
+/// accessor, are up to the schema's author and thus permitted.
+pub(crate) fn is_allowed_function(function_name: &str, accessor: Option<&str>) -> bool {
+  return accessor.is_some()
+    || ALLOWED_FUNCTIONS
+      .iter()
+      .any(|name| name.eq_ignore_ascii_case(function_name));
+}
+
+fn authorize(allowed_tables: &[String], ctx: AuthContext<'_>) -> Authorization {
+  let allowed = |name: &str| allowed_tables.iter().any(|t| t == name);
+
+  return match ctx.action {
+    AuthAction::Select | AuthAction::Recursive => Authorization::Allow,
+    AuthAction::Function { function_name } if is_allowed_function(function_name, ctx.accessor) => {
+      Authorization::Allow
+    }
+    // Reads through views are attributed to the view's underlying tables with the view as
+    // accessor.
+    AuthAction::Read { table_name, .. }
+      if ctx.database_name == Some("main")
+        && (allowed(table_name) || ctx.accessor.is_some_and(allowed)) =>
+    {
+      Authorization::Allow
+    }
+    _ => Authorization::Deny,
+  };
+}
+
+pub(crate) fn json_to_param(
+  value: serde_json::Value,
+) -> Result<trailbase_sqlite::Value, SqlApiError> {
+  use trailbase_sqlite::Value;
+
+  return Ok(match value {
+    serde_json::Value::Null => Value::Null,
+    serde_json::Value::Bool(b) => Value::Integer(b as i64),
+    serde_json::Value::Number(n) => match n.as_i64() {
+      Some(i) => Value::Integer(i),
+      None => Value::Real(n.as_f64().unwrap_or_default()),
+    },
+    serde_json::Value::String(s) => Value::Text(s),
+    value => {
+      return Err(SqlApiError::BadRequest(format!(
+        "Unsupported parameter: {value}"
+      )));
+    }
+  });
+}
+
+pub(crate) fn run_query(
+  conn: &rusqlite::Connection,
+  query: &str,
+  params: Vec<trailbase_sqlite::Value>,
+  max_rows: usize,
+) -> Result<SqlQueryResponse, SqlApiError> {
+  // NOTE: Don't use the statement cache, the authorizer is only invoked when preparing.
+  let mut stmt = conn.prepare(query)?;
+  if !stmt.readonly() {
+    return Err(SqlApiError::BadRequest("Query not permitted".to_string()));
+  }
+  if stmt.parameter_count() != params.len() {
+    return Err(SqlApiError::BadRequest(format!(
+      "Expected {} parameters, got {}",
+      stmt.parameter_count(),
+      params.len()
+    )));
+  }
+
+  let columns: Vec<String> = stmt
+    .column_names()
+    .into_iter()
+    .map(|name| name.to_string())
+    .collect();
+
+  let mut response = SqlQueryResponse {
+    columns,
+    ..Default::default()
+  };
+
+  let mut rows = stmt.query(rusqlite::params_from_iter(params))?;
+  while let Some(row) = rows.next()? {
+    if response.rows.len() >= max_rows {
+      response.truncated = true;
+      break;
+    }
+
+    let values = (0..response.columns.len())
+      .map(|index| {
+        let value = trailbase_sqlite::Value::from(row.get_ref(index)?);
+        return value_to_json(&value).map_err(|err| SqlApiError::Internal(err.into()));
+      })
+      .collect::<Result<Vec<_>, SqlApiError>>()?;
+    response.rows.push(values);
+  }
+
+  return Ok(response);
+}
+
+#[cfg(test)]
+mod tests {
+  use axum::http::HeaderValue;
+  use serde_json::json;
+
+  use super::*;
+  use crate::app_state::test_state;
+
+  async fn query(
+    state: &AppState,
+    api_key: Option<&str>,
+    query: &str,
+    params: Vec<serde_json::Value>,
+  ) -> Result<SqlQueryResponse, SqlApiError> {
+    let mut headers = HeaderMap::new();
+    if let Some(api_key) = api_key {
+      headers.insert(HEADER_API_KEY, HeaderValue::from_str(api_key).unwrap());
+    }
+
+    return sql_query_handler(
+      State(state.clone()),
+      None,
+      headers,
+      Json(SqlQueryRequest {
+        query: query.to_string(),
+        params,
+      }),
+    )
+    .await
+    .map(|response| response.0);
+  }
+
+  #[tokio::test]
+  async fn test_sql_query() {
+    let state = test_state(None).await.unwrap();
+    state
+      .conn()
+      .execute_batch(
+        r#"
+          CREATE TABLE allowed (id INTEGER PRIMARY KEY, value TEXT) STRICT;
+          INSERT INTO allowed (value) VALUES ('a'), ('b'), ('c');
+          CREATE TABLE secret (id INTEGER PRIMARY KEY, value TEXT) STRICT;
+          CREATE VIEW allowed_view AS SELECT a.value FROM allowed AS a JOIN secret AS s;
+        "#,
+      )
+      .await
+      .unwrap();
+    state.schema_metadata().invalidate_all().await.unwrap();
+
+    // Disabled by default.
+    assert!(matches!(
+      query(&state, Some("key"), "SELECT 1", vec![]).await,
+      Err(SqlApiError::NotFound)
+    ));
+
+    let mut config = state.get_config();
+    config.server.sql_api = Some(SqlApiConfig {
+      api_key: Some("key".to_string()),
+      allowed_tables: vec!["allowed".to_string(), "allowed_view".to_string()],
+      max_rows: Some(2),
+      timeout_ms: None,
+    });
+    state
+      .validate_and_update_config(config, None)
+      .await
+      .unwrap();
+
+    assert!(matches!(
+      query(&state, None, "SELECT 1", vec![]).await,
+      Err(SqlApiError::Forbidden)
+    ));
+    assert!(matches!(
+      query(&state, Some("wrong"), "SELECT 1", vec![]).await,
+      Err(SqlApiError::Forbidden)
+    ));
+
+    let response = query(
+      &state,
+      Some("key"),
+      "SELECT value, COUNT(*) OVER () AS total FROM allowed WHERE id > ? ORDER BY id",
+      vec![json!(0)],
+    )
+    .await
+    .unwrap();
+    assert_eq!(response.columns, vec!["value", "total"]);
+    assert_eq!(
+      response.rows,
+      vec![vec![json!("a"), json!(3)], vec![json!("b"), json!(3)]]
+    );
+    assert!(response.truncated);
+
+    // Views may read from tables that aren't allow-listed themselves.
+    let response = query(&state, Some("key"), "SELECT * FROM allowed_view", vec![])
+      .await
+      .unwrap();
+    assert_eq!(response.rows.len(), 0);
+
+    let response = query(
+      &state,
+      Some("key"),
+      "SELECT upper(value), json_extract('{\"a\":1}', '$.a'), value LIKE 'x%' FROM allowed",
+      vec![],
+    )
+    .await
+    .unwrap();
+    assert_eq!(response.columns.len(), 3);
+
+    for forbidden in [
+      "SELECT * FROM secret",
+      "SELECT * FROM allowed JOIN secret",
+      "SELECT * FROM allowed WHERE id IN (SELECT id FROM secret)",
+      "SELECT * FROM sqlite_schema",
+      "DELETE FROM allowed",
+      "SELECT 1; DELETE FROM allowed",
+      "SELECT load_extension('evil')",
+      "SELECT uuid_v7()",
+      "SELECT readfile('/etc/passwd')",
+      "SELECT value FROM allowed WHERE hash_password(value) IS NOT NULL",
+    ] {
+      assert!(
+        matches!(
+          query(&state, Some("key"), forbidden, vec![]).await,
+          Err(SqlApiError::BadRequest(_))
+        ),
+        "{forbidden}"
+      );
+    }
+  }
+}
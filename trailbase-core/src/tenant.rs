@@ -0,0 +1,380 @@
+//! Physically isolated per-tenant databases, see `Config::tenants` and `DatabaseConfig::per_tenant`.
+//!
+//! Each per-tenant database has a schema-only template, which is attached read-only to the main
+//! connections. Record API requests resolved to a tenant are served by a separate connection, on
+//! which the tenant's files are attached under the same names instead. Since SQLite resolves
+//! unqualified table names across attached databases, record APIs transparently operate on the
+//! tenant's data, while users and other tables of the main database remain shared.
+
+use axum::Router;
+use axum::extract::{Request, State};
+use axum::http::{HeaderMap, header};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use log::*;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use thiserror::Error;
+use tower::ServiceExt;
+
+use crate::app_state::AppState;
+use crate::clock::Clock;
+use crate::config::proto::{DatabaseConfig, TenantsConfig};
+use crate::connection::{
+  AttachedDatabase, ConnectionError, DatabaseKey, LoadableExtension, attach_databases,
+  init_database, init_main_db_at,
+};
+use crate::constants::RECORD_API_PATH;
+use crate::data_dir::DataDir;
+use crate::records::RecordError;
+
+#[derive(Debug, Error)]
+pub enum TenantError {
+  #[error("Unknown tenant: {0}")]
+  Unknown(String),
+  #[error("Tenants unsupported: {0}")]
+  Unsupported(&'static str),
+  #[error("Connection error: {0}")]
+  Connection(#[from] ConnectionError),
+  #[error("Join error: {0}")]
+  Join(#[from] tokio::task::JoinError),
+}
+
+/// What's needed to open connections to the main database on behalf of tenants.
+#[derive(Clone, Debug)]
+pub(crate) struct TenantOptions {
+  pub data_dir: DataDir,
+  pub main_path: PathBuf,
+  pub extensions: Vec<LoadableExtension>,
+  pub key: Option<DatabaseKey>,
+  pub clock: Clock,
+}
+
+/// Tenant names are used as directory names and are thus restricted to lower-case alphanumerics,
+/// '-' and '_'.
+fn is_valid_tenant_name(name: &str) -> bool {
+  return !name.is_empty()
+    && name.len() <= 63
+    && name.starts_with(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit())
+    && name
+      .chars()
+      .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+}
+
+pub(crate) fn validate_tenants_config(
+  tenants: &TenantsConfig,
+) -> Result<(), crate::config::ConfigError> {
+  let ierr = |msg: String| Err(crate::config::ConfigError::Invalid(msg));
+
+  if tenants.header.is_none() && tenants.domain.is_none() {
+    return ierr("Tenants require either a header or a domain".to_string());
+  }
+  if let Some(ref header) = tenants.header {
+    if header::HeaderName::try_from(header.as_str()).is_err() {
+      return ierr(format!("Invalid tenant header: {header}"));
+    }
+  }
+  for name in &tenants.names {
+    if !is_valid_tenant_name(name) {
+      return ierr(format!("Invalid tenant name: {name}"));
+    }
+  }
+  return Ok(());
+}
+
+/// Resolves the request's tenant, if any, from the configured header or the host's subdomain.
+pub(crate) fn resolve_tenant(tenants: &TenantsConfig, headers: &HeaderMap) -> Option<String> {
+  if let Some(ref name) = tenants.header {
+    if let Some(value) = headers.get(name.as_str()).and_then(|v| v.to_str().ok()) {
+      return Some(value.to_string());
+    }
+  }
+
+  let domain = tenants.domain.as_deref()?;
+  let host = headers.get(header::HOST)?.to_str().ok()?;
+  let host = host.split_once(':').map_or(host, |(host, _port)| host);
+  let subdomain = host.strip_suffix(domain)?.strip_suffix('.')?;
+  if subdomain.is_empty() || subdomain.contains('.') {
+    return None;
+  }
+  return Some(subdomain.to_string());
+}
+
+/// Opens a connection to the main database with the tenant's files attached in place of the
+/// per-tenant databases' templates. Tenant files are created and migrated as needed.
+pub(crate) async fn open_tenant_connection(
+  options: &TenantOptions,
+  databases: &[DatabaseConfig],
+  tenant: &str,
+) -> Result<trailbase_sqlite::Connection, TenantError> {
+  if !is_valid_tenant_name(tenant) {
+    return Err(TenantError::Unknown(tenant.to_string()));
+  }
+
+  let attached: Vec<AttachedDatabase> = databases
+    .iter()
+    .filter_map(|db| {
+      let name = db.name.clone()?;
+      let path = if db.per_tenant.unwrap_or(false) {
+        options.data_dir.tenant_database_path(tenant, &name)
+      } else {
+        options.data_dir.database_path(&name)
+      };
+      return Some(AttachedDatabase {
+        name,
+        path,
+        read_only: false,
+      });
+    })
+    .collect();
+
+  let conn = {
+    let options = options.clone();
+    let per_tenant: Vec<AttachedDatabase> = databases
+      .iter()
+      .filter(|db| db.per_tenant.unwrap_or(false))
+      .filter_map(|db| attached.iter().find(|a| Some(&a.name) == db.name.as_ref()))
+      .cloned()
+      .collect();
+
+    tokio::task::spawn_blocking(move || -> Result<_, ConnectionError> {
+      for db in &per_tenant {
+        init_database(
+          &options.data_dir,
+          &db.name,
+          &db.path,
+          Some(options.extensions.clone()),
+          options.key.as_ref(),
+        )?;
+      }
+
+      let (conn, _new_db) = init_main_db_at(
+        Some(options.main_path.clone()),
+        Some(options.data_dir.migrations_path()),
+        Some(options.extensions.clone()),
+        Some(1),
+        options.key.clone(),
+        options.clock.clone(),
+      )?;
+      return Ok(conn);
+    })
+    .await??
+  };
+
+  attach_databases(&conn, &attached, options.key.as_ref()).await?;
+  debug!("Opened databases for tenant '{tenant}'");
+
+  return Ok(conn);
+}
+
+/// Per-tenant record API routers, i.e. routers bound to the tenant's state.
+pub(crate) type TenantRouters = Arc<Mutex<HashMap<String, Router>>>;
+
+/// Dispatches record API requests resolved to a tenant to the tenant's router.
+///
+/// Requests w/o tenant for record APIs on per-tenant tables are rejected, since the templates
+/// don't hold any records.
+pub(crate) async fn tenant_middleware(
+  State((state, routers)): State<(AppState, TenantRouters)>,
+  req: Request,
+  next: Next,
+) -> Response {
+  let Some(tenants) = state.access_config(|c| c.tenants.clone()) else {
+    return next.run(req).await;
+  };
+
+  let Some(tenant) = resolve_tenant(&tenants, req.headers()) else {
+    if requires_tenant(&state, req.uri().path()) {
+      return RecordError::BadRequest("Missing tenant").into_response();
+    }
+    return next.run(req).await;
+  };
+
+  let router = routers.lock().get(&tenant).cloned();
+  let router = match router {
+    Some(router) => router,
+    None => {
+      let tenant_state = match state.tenant(&tenant).await {
+        Ok(tenant_state) => tenant_state,
+        Err(TenantError::Unknown(_)) => {
+          return RecordError::BadRequest("Unknown tenant").into_response();
+        }
+        Err(err) => {
+          return RecordError::Internal(err.into()).into_response();
+        }
+      };
+
+      let router = crate::records::router().with_state(tenant_state);
+      routers.lock().insert(tenant, router.clone());
+      router
+    }
+  };
+
+  return match router.oneshot(req).await {
+    Ok(response) => response,
+    Err(infallible) => match infallible {},
+  };
+}
+
+/// Whether the request targets a record API on a table of a per-tenant database.
+fn requires_tenant(state: &AppState, path: &str) -> bool {
+  let Some(api_name) = path
+    .strip_prefix(&format!("/{RECORD_API_PATH}/"))
+    .and_then(|rest| rest.split('/').next())
+  else {
+    return false;
+  };
+  let Some(database) = state
+    .lookup_record_api(api_name)
+    .and_then(|api| state.schema_metadata().table_database(api.table_name()))
+  else {
+    return false;
+  };
+
+  return state.access_config(|c| {
+    return c
+      .databases
+      .iter()
+      .any(|db| db.name.as_ref() == Some(&database) && db.per_tenant.unwrap_or(false));
+  });
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::config::proto::{PermissionFlag, RecordApiConfig};
+  use crate::records::test_utils::add_record_api_config;
+  use crate::server::{InitArgs, init_app_state};
+
+  #[test]
+  fn test_resolve_tenant() {
+    let tenants = TenantsConfig {
+      header: Some("X-Tenant".to_string()),
+      domain: Some("example.com".to_string()),
+      names: vec![],
+    };
+
+    let headers = |pairs: &[(&'static str, &'static str)]| {
+      let mut headers = HeaderMap::new();
+      for (name, value) in pairs {
+        headers.insert(*name, value.parse().unwrap());
+      }
+      return headers;
+    };
+
+    assert_eq!(
+      resolve_tenant(&tenants, &headers(&[("x-tenant", "acme")])),
+      Some("acme".to_string())
+    );
+    assert_eq!(
+      resolve_tenant(&tenants, &headers(&[("host", "globex.example.com:4000")])),
+      Some("globex".to_string())
+    );
+    assert_eq!(
+      resolve_tenant(&tenants, &headers(&[("host", "example.com")])),
+      None
+    );
+    assert_eq!(
+      resolve_tenant(&tenants, &headers(&[("host", "a.b.example.com")])),
+      None
+    );
+    assert_eq!(
+      resolve_tenant(&tenants, &headers(&[("host", "notexample.com")])),
+      None
+    );
+  }
+
+  #[tokio::test]
+  async fn test_tenant_databases() {
+    let temp_dir = temp_dir::TempDir::new().unwrap();
+    let data_dir = DataDir(temp_dir.path().to_path_buf());
+
+    let migrations_path = data_dir.database_migrations_path("shard");
+    std::fs::create_dir_all(&migrations_path).unwrap();
+    std::fs::write(
+      migrations_path.join("U1__notes.sql"),
+      "CREATE TABLE note (id INTEGER PRIMARY KEY, body TEXT NOT NULL) STRICT;",
+    )
+    .unwrap();
+
+    {
+      let (_new_db, state) = init_app_state(data_dir.clone(), None, InitArgs::default())
+        .await
+        .unwrap();
+
+      let mut config = state.get_config();
+      config.databases = vec![DatabaseConfig {
+        name: Some("shard".to_string()),
+        per_tenant: Some(true),
+      }];
+      config.tenants = Some(TenantsConfig {
+        header: Some("X-Tenant".to_string()),
+        domain: None,
+        names: vec!["acme".to_string(), "globex".to_string()],
+      });
+      state
+        .validate_and_update_config(config, None)
+        .await
+        .unwrap();
+    }
+
+    // Databases are attached on startup.
+    let (_new_db, state) = init_app_state(data_dir.clone(), None, InitArgs::default())
+      .await
+      .unwrap();
+    assert_eq!(
+      state.schema_metadata().table_database("note").as_deref(),
+      Some("shard")
+    );
+
+    add_record_api_config(
+      &state,
+      RecordApiConfig {
+        name: Some("notes".to_string()),
+        table_name: Some("note".to_string()),
+        acl_world: vec![PermissionFlag::Create as i32, PermissionFlag::Read as i32],
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+    assert!(requires_tenant(&state, "/api/records/v1/notes"));
+
+    // The template is read-only.
+    assert!(
+      state
+        .conn()
+        .execute("INSERT INTO note (body) VALUES ('shared')", ())
+        .await
+        .is_err()
+    );
+
+    let acme = state.tenant("acme").await.unwrap();
+    acme
+      .conn()
+      .execute("INSERT INTO note (body) VALUES ('acme')", ())
+      .await
+      .unwrap();
+    assert!(acme.lookup_record_api("notes").is_some());
+    assert!(data_dir.tenant_database_path("acme", "shard").exists());
+
+    let count = |state: AppState| async move {
+      return state
+        .conn()
+        .read_query_row_f("SELECT COUNT(*) FROM note", (), |row| row.get::<_, i64>(0))
+        .await
+        .unwrap()
+        .unwrap();
+    };
+    assert_eq!(count(acme).await, 1);
+    assert_eq!(count(state.tenant("globex").await.unwrap()).await, 0);
+    assert_eq!(count(state.clone()).await, 0);
+
+    assert!(matches!(
+      state.tenant("initech").await,
+      Err(TenantError::Unknown(_))
+    ));
+  }
+}
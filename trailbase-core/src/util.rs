@@ -38,16 +38,6 @@ pub fn urlencode(s: &str) -> String {
   return form_urlencoded::byte_serialize(s.as_bytes()).collect();
 }
 
-#[cfg(debug_assertions)]
-#[inline(always)]
-pub(crate) fn assert_uuidv7(id: &[u8; 16]) {
-  assert_uuidv7_version(&Uuid::from_bytes(*id));
-}
-
-#[cfg(not(debug_assertions))]
-#[inline(always)]
-pub(crate) fn assert_uuidv7(_id: &[u8; 16]) {}
-
 #[cfg(debug_assertions)]
 pub(crate) fn assert_uuidv7_version(uuid: &Uuid) {
   let version = uuid.get_version_num();
@@ -87,6 +77,16 @@ pub fn cow_to_string(cow: Cow<'static, [u8]>) -> String {
   }
 }
 
+/// Quotes `s` as SQL string literal, e.g. to embed names in trigger bodies.
+pub(crate) fn quote_literal(s: &str) -> String {
+  return format!("'{}'", s.replace('\'', "''"));
+}
+
+/// Quotes `name` as SQL identifier.
+pub(crate) fn quote_identifier(name: &str) -> String {
+  return format!(r#""{}""#, name.replace('"', r#""""#));
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
@@ -95,4 +95,10 @@ mod tests {
   fn test_urlencode() {
     assert_eq!(urlencode("+col0,-col1"), "%2Bcol0%2C-col1");
   }
+
+  #[test]
+  fn test_quote() {
+    assert_eq!(quote_literal("it's"), "'it''s'");
+    assert_eq!(quote_identifier(r#"a"b"#), r#""a""b""#);
+  }
 }
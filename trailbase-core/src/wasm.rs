@@ -0,0 +1,858 @@
+//! Host for sandboxed WebAssembly plugins, an alternative to the V8 JS runtime for extending
+//! TrailBase in languages compiling to WASI, e.g. Rust, Go or Zig.
+//!
+//! Plugins are core WASM modules loaded from `<data_dir>/plugins/*.wasm` on startup. They talk to
+//! the host by exchanging JSON messages through their linear memory:
+//!
+//! * Plugins export their `memory`, `trailbase_alloc(len) -> ptr` for the host to allocate
+//!   buffers in, `trailbase_init()`, and `trailbase_handle(handler, ptr, len) -> i64` returning
+//!   the response's pointer and length packed as `ptr << 32 | len`. An optional
+//!   `trailbase_free(ptr, len)` lets the host release response buffers.
+//! * During `trailbase_init` plugins register HTTP routes and middleware by calling the imports
+//!   `trailbase.register_route(method_ptr, method_len, path_ptr, path_len, handler)` and
+//!   `trailbase.register_middleware(handler)`.
+//! * Handlers may call `trailbase.query(ptr, len) -> i64` and `trailbase.execute(ptr, len) -> i64`
+//!   with `{"sql": ..., "params": [...]}`. Results are allocated with `trailbase_alloc` and owned
+//!   by the plugin.
+//!
+//! Plugins are sandboxed: they get no file system or network access, bounded memory and a fuel
+//! budget per call. Queries can only access non-internal tables, i.e. not `_`-prefixed ones.
+
+use axum::RequestExt;
+use axum::Router;
+use axum::body::Body;
+use axum::extract::{RawPathParams, Request, State};
+use axum::http::{HeaderMap, HeaderName, HeaderValue, Method, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{MethodFilter, MethodRouter};
+use base64::prelude::*;
+use log::*;
+use parking_lot::Mutex;
+use rusqlite::hooks::{AuthAction, AuthContext, Authorization};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use wasmtime::{Caller, Engine, Extern, Instance, Linker, Memory, Module, Store, StoreLimits};
+use wasmtime_wasi::WasiCtxBuilder;
+use wasmtime_wasi::preview1::{self, WasiP1Ctx};
+
+use crate::AppState;
+use crate::auth::{OptionalUser, User};
+use crate::sql_api::{
+  SqlApiError, SqlQueryResponse, is_allowed_function, json_to_param, run_query,
+};
+
+/// Instructions a single call into a plugin may execute, including nested host calls.
+const FUEL_PER_CALL: u64 = 1_000_000_000;
+const MAX_MEMORY_BYTES: usize = 64 * 1024 * 1024;
+const MAX_QUERY_ROWS: usize = 1000;
+const QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+/// Progress handler invocations, i.e. thousands of VM steps, a single statement may take. Bounds
+/// writes on the main connection independently of the host's speed, e.g. `INSERT ... SELECT` over
+/// a recursive CTE.
+const MAX_EXECUTE_STEPS: u64 = 100_000;
+
+#[derive(Debug, Error)]
+pub enum PluginError {
+  #[error("Wasm: {0}")]
+  Wasm(#[from] wasmtime::Error),
+  #[error("IO: {0}")]
+  IO(#[from] std::io::Error),
+  #[error("Json: {0}")]
+  Json(#[from] serde_json::Error),
+  #[error("Plugin: {0}")]
+  Plugin(String),
+}
+
+struct PluginState {
+  wasi: WasiP1Ctx,
+  limits: StoreLimits,
+  app: AppState,
+  runtime: tokio::runtime::Handle,
+
+  // Registrations during `trailbase_init`.
+  routes: Vec<(String, String, i32)>,
+  middlewares: Vec<i32>,
+}
+
+struct PluginInstance {
+  store: Store<PluginState>,
+  instance: Instance,
+}
+
+/// A loaded plugin. Calls are serialized, since instances aren't re-entrant.
+struct Plugin {
+  name: String,
+  inner: Mutex<PluginInstance>,
+}
+
+impl Plugin {
+  fn load(
+    engine: &Engine,
+    linker: &Linker<PluginState>,
+    state: AppState,
+    runtime: tokio::runtime::Handle,
+    path: &Path,
+  ) -> Result<(Self, Vec<(String, String, i32)>, Vec<i32>), PluginError> {
+    let name = path
+      .file_stem()
+      .map(|stem| stem.to_string_lossy().to_string())
+      .unwrap_or_default();
+
+    let module = Module::from_file(engine, path)?;
+    let mut store = Store::new(
+      engine,
+      PluginState {
+        // NOTE: stdout is reserved for request logs.
+        wasi: WasiCtxBuilder::new().inherit_stderr().build_p1(),
+        limits: wasmtime::StoreLimitsBuilder::new()
+          .memory_size(MAX_MEMORY_BYTES)
+          .build(),
+        app: state,
+        runtime,
+        routes: vec![],
+        middlewares: vec![],
+      },
+    );
+    store.limiter(|state| &mut state.limits);
+    store.set_fuel(FUEL_PER_CALL)?;
+
+    let instance = linker.instantiate(&mut store, &module)?;
+
+    // WASI reactors need to initialize their runtime before any other call.
+    if let Ok(initialize) = instance.get_typed_func::<(), ()>(&mut store, "_initialize") {
+      initialize.call(&mut store, ())?;
+    }
+    instance
+      .get_typed_func::<(), ()>(&mut store, "trailbase_init")?
+      .call(&mut store, ())?;
+
+    let routes = std::mem::take(&mut store.data_mut().routes);
+    let middlewares = std::mem::take(&mut store.data_mut().middlewares);
+
+    return Ok((
+      Plugin {
+        name,
+        inner: Mutex::new(PluginInstance { store, instance }),
+      },
+      routes,
+      middlewares,
+    ));
+  }
+
+  fn call(&self, handler: i32, input: &[u8]) -> Result<Vec<u8>, PluginError> {
+    let mut lock = self.inner.lock();
+    let PluginInstance { store, instance } = &mut *lock;
+    store.set_fuel(FUEL_PER_CALL)?;
+
+    let memory = instance
+      .get_memory(&mut *store, "memory")
+      .ok_or_else(|| PluginError::Plugin("missing memory export".to_string()))?;
+    let alloc = instance.get_typed_func::<i32, i32>(&mut *store, "trailbase_alloc")?;
+    let handle =
+      instance.get_typed_func::<(i32, i32, i32), i64>(&mut *store, "trailbase_handle")?;
+
+    let ptr = alloc.call(&mut *store, input.len() as i32)?;
+    memory.write(&mut *store, ptr as usize, input)?;
+
+    let (out_ptr, out_len) = unpack(handle.call(&mut *store, (handler, ptr, input.len() as i32))?);
+    let mut output = vec![0; out_len];
+    memory.read(&*store, out_ptr, &mut output)?;
+
+    if let Ok(free) = instance.get_typed_func::<(i32, i32), ()>(&mut *store, "trailbase_free") {
+      free.call(&mut *store, (out_ptr as i32, out_len as i32))?;
+    }
+
+    return Ok(output);
+  }
+
+  /// Calls the given handler off the async runtime, since plugins may run for a while.
+  async fn dispatch<T: DeserializeOwned>(
+    self: &Arc<Self>,
+    handler: i32,
+    message: &impl Serialize,
+  ) -> Result<T, PluginError> {
+    let input = serde_json::to_vec(message)?;
+    let plugin = self.clone();
+    let output = tokio::task::spawn_blocking(move || plugin.call(handler, &input))
+      .await
+      .map_err(|err| PluginError::Plugin(err.to_string()))??;
+
+    return Ok(serde_json::from_slice(&output)?);
+  }
+}
+
+/// Routes and middleware registered by all loaded plugins.
+pub(crate) struct Plugins {
+  pub(crate) router: Router<AppState>,
+  middlewares: Arc<Vec<(Arc<Plugin>, i32)>>,
+}
+
+impl Plugins {
+  /// Wraps the given router with the plugins' middleware in load order, i.e. the first loaded
+  /// plugin sees requests first and responses last.
+  pub(crate) fn layer(&self, state: &AppState, router: Router<AppState>) -> Router<AppState> {
+    if self.middlewares.is_empty() {
+      return router;
+    }
+
+    return router.layer(axum::middleware::from_fn_with_state(
+      (state.clone(), self.middlewares.clone()),
+      plugin_middleware,
+    ));
+  }
+}
+
+pub(crate) async fn load_plugins(state: &AppState) -> Result<Option<Plugins>, PluginError> {
+  let plugins_dir = state.data_dir().plugins_path();
+  let mut entries = match tokio::fs::read_dir(&plugins_dir).await {
+    Ok(entries) => entries,
+    Err(err) => {
+      debug!("Skip loading plugins from '{plugins_dir:?}': {err}");
+      return Ok(None);
+    }
+  };
+
+  let mut paths: Vec<PathBuf> = vec![];
+  while let Some(entry) = entries.next_entry().await? {
+    let path = entry.path();
+    if path.extension().is_some_and(|ext| ext == "wasm") {
+      paths.push(path);
+    }
+  }
+  if paths.is_empty() {
+    return Ok(None);
+  }
+  // Load in a deterministic order, which also determines the order of middleware.
+  paths.sort();
+
+  let mut config = wasmtime::Config::new();
+  config.consume_fuel(true);
+  let engine = Engine::new(&config)?;
+  let linker = Arc::new(build_linker(&engine)?);
+
+  let mut router = Router::<AppState>::new();
+  let mut middlewares: Vec<(Arc<Plugin>, i32)> = vec![];
+  for path in paths {
+    let (plugin, routes, plugin_middlewares) = tokio::task::spawn_blocking({
+      let engine = engine.clone();
+      let linker = linker.clone();
+      let state = state.clone();
+      let runtime = tokio::runtime::Handle::current();
+      let path = path.clone();
+      move || Plugin::load(&engine, &linker, state, runtime, &path)
+    })
+    .await
+    .map_err(|err| PluginError::Plugin(err.to_string()))??;
+
+    info!(
+      "Loaded plugin '{}' with {} route(s) and {} middleware",
+      plugin.name,
+      routes.len(),
+      plugin_middlewares.len()
+    );
+
+    let plugin = Arc::new(plugin);
+    for (method, route, handler) in routes {
+      router = router.route(&route, build_route(plugin.clone(), &method, handler)?);
+    }
+    middlewares.extend(plugin_middlewares.into_iter().map(|h| (plugin.clone(), h)));
+  }
+
+  return Ok(Some(Plugins {
+    router,
+    middlewares: Arc::new(middlewares),
+  }));
+}
+
+fn build_linker(engine: &Engine) -> Result<Linker<PluginState>, PluginError> {
+  let mut linker = Linker::<PluginState>::new(engine);
+  preview1::add_to_linker_sync(&mut linker, |state| &mut state.wasi)?;
+
+  linker.func_wrap(
+    "trailbase",
+    "register_route",
+    |mut caller: Caller<'_, PluginState>,
+     method_ptr: i32,
+     method_len: i32,
+     path_ptr: i32,
+     path_len: i32,
+     handler: i32|
+     -> wasmtime::Result<()> {
+      let method = String::from_utf8(read_guest(&mut caller, method_ptr, method_len)?)?;
+      let path = String::from_utf8(read_guest(&mut caller, path_ptr, path_len)?)?;
+      caller.data_mut().routes.push((method, path, handler));
+      return Ok(());
+    },
+  )?;
+
+  linker.func_wrap(
+    "trailbase",
+    "register_middleware",
+    |mut caller: Caller<'_, PluginState>, handler: i32| {
+      caller.data_mut().middlewares.push(handler);
+    },
+  )?;
+
+  linker.func_wrap(
+    "trailbase",
+    "query",
+    |mut caller: Caller<'_, PluginState>, ptr: i32, len: i32| -> wasmtime::Result<i64> {
+      let request: QueryRequest = serde_json::from_slice(&read_guest(&mut caller, ptr, len)?)?;
+      let data = caller.data();
+      let conn = data.app.read_only_conn().clone();
+
+      let result: Result<SqlQueryResponse, SqlApiError> = data.runtime.block_on(async move {
+        let params = request
+          .params
+          .into_iter()
+          .map(json_to_param)
+          .collect::<Result<Vec<_>, _>>()?;
+
+        return conn
+          .call(move |conn| {
+            conn.authorizer(Some(authorize));
+            let deadline = Instant::now() + QUERY_TIMEOUT;
+            conn.progress_handler(1000, Some(move || Instant::now() > deadline));
+
+            let result = run_query(conn, &request.sql, params, MAX_QUERY_ROWS);
+
+            conn.authorizer(None::<fn(AuthContext<'_>) -> Authorization>);
+            conn.progress_handler(0, None::<fn() -> bool>);
+
+            return Ok(result);
+          })
+          .await?;
+      });
+
+      let response = match result {
+        Ok(response) => serde_json::to_vec(&response)?,
+        Err(err) => serde_json::to_vec(&HostError::from(err))?,
+      };
+      return write_guest(&mut caller, &response);
+    },
+  )?;
+
+  linker.func_wrap(
+    "trailbase",
+    "execute",
+    |mut caller: Caller<'_, PluginState>, ptr: i32, len: i32| -> wasmtime::Result<i64> {
+      let request: QueryRequest = serde_json::from_slice(&read_guest(&mut caller, ptr, len)?)?;
+      let data = caller.data();
+      let conn = data.app.conn().clone();
+
+      let result: Result<usize, SqlApiError> = data.runtime.block_on(async move {
+        let params = request
+          .params
+          .into_iter()
+          .map(json_to_param)
+          .collect::<Result<Vec<_>, _>>()?;
+
+        return conn
+          .call(move |conn| {
+            return Ok(execute(conn, &request.sql, params, MAX_EXECUTE_STEPS));
+          })
+          .await?;
+      });
+
+      let response = match result {
+        Ok(rows_affected) => serde_json::to_vec(&ExecuteResponse { rows_affected })?,
+        Err(err) => serde_json::to_vec(&HostError::from(err))?,
+      };
+      return write_guest(&mut caller, &response);
+    },
+  )?;
+
+  return Ok(linker);
+}
+
+/// Runs a plugin's statement subject to the authorizer below, the query timeout and a budget of
+/// `max_steps`, since writes block all other writers.
+fn execute(
+  conn: &rusqlite::Connection,
+  sql: &str,
+  params: Vec<trailbase_sqlite::Value>,
+  max_steps: u64,
+) -> Result<usize, SqlApiError> {
+  conn.authorizer(Some(authorize));
+  let deadline = Instant::now() + QUERY_TIMEOUT;
+  let mut steps: u64 = 0;
+  conn.progress_handler(
+    1000,
+    Some(move || {
+      steps += 1;
+      return steps > max_steps || Instant::now() > deadline;
+    }),
+  );
+
+  // NOTE: Don't use the statement cache, the authorizer is only invoked when preparing.
+  let result = conn
+    .prepare(sql)
+    .and_then(|mut stmt| stmt.execute(rusqlite::params_from_iter(params)))
+    .map_err(SqlApiError::from);
+
+  conn.authorizer(None::<fn(AuthContext<'_>) -> Authorization>);
+  conn.progress_handler(0, None::<fn() -> bool>);
+
+  return result;
+}
+
+/// Only permits reading and writing non-internal tables of the main database as well as calling
+/// deterministic built-in functions. Triggers and views may access internal tables and call any
+/// function on a plugin's behalf, e.g. to record change data.
+fn authorize(ctx: AuthContext<'_>) -> Authorization {
+  let public = |name: &str| !name.starts_with('_') && !name.starts_with("sqlite_");
+
+  return match ctx.action {
+    AuthAction::Select | AuthAction::Recursive => Authorization::Allow,
+    AuthAction::Function { function_name } if is_allowed_function(function_name, ctx.accessor) => {
+      Authorization::Allow
+    }
+    AuthAction::Read { table_name, .. }
+    | AuthAction::Insert { table_name }
+    | AuthAction::Update { table_name, .. }
+    | AuthAction::Delete { table_name }
+      if ctx.database_name == Some("main") && (public(table_name) || ctx.accessor.is_some()) =>
+    {
+      Authorization::Allow
+    }
+    _ => Authorization::Deny,
+  };
+}
+
+#[derive(Debug, Deserialize)]
+struct QueryRequest {
+  sql: String,
+  #[serde(default)]
+  params: Vec<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize)]
+struct ExecuteResponse {
+  rows_affected: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct HostError {
+  error: String,
+}
+
+impl From<SqlApiError> for HostError {
+  fn from(err: SqlApiError) -> Self {
+    return HostError {
+      error: err.to_string(),
+    };
+  }
+}
+
+fn guest_memory(caller: &mut Caller<'_, PluginState>) -> wasmtime::Result<Memory> {
+  return caller
+    .get_export("memory")
+    .and_then(Extern::into_memory)
+    .ok_or_else(|| wasmtime::Error::msg("missing memory export"));
+}
+
+fn read_guest(
+  caller: &mut Caller<'_, PluginState>,
+  ptr: i32,
+  len: i32,
+) -> wasmtime::Result<Vec<u8>> {
+  let memory = guest_memory(caller)?;
+  let mut buffer = vec![0; len as usize];
+  memory.read(&*caller, ptr as usize, &mut buffer)?;
+  return Ok(buffer);
+}
+
+/// Copies the bytes into a buffer allocated by the plugin and returns its packed pointer and
+/// length.
+fn write_guest(caller: &mut Caller<'_, PluginState>, bytes: &[u8]) -> wasmtime::Result<i64> {
+  let alloc = caller
+    .get_export("trailbase_alloc")
+    .and_then(Extern::into_func)
+    .ok_or_else(|| wasmtime::Error::msg("missing trailbase_alloc export"))?
+    .typed::<i32, i32>(&*caller)?;
+
+  let ptr = alloc.call(&mut *caller, bytes.len() as i32)?;
+  guest_memory(caller)?.write(&mut *caller, ptr as usize, bytes)?;
+
+  return Ok(((ptr as u32 as i64) << 32) | bytes.len() as u32 as i64);
+}
+
+fn unpack(packed: i64) -> (usize, usize) {
+  let packed = packed as u64;
+  return ((packed >> 32) as usize, (packed & 0xffff_ffff) as usize);
+}
+
+#[derive(Debug, Serialize)]
+struct PluginUser {
+  /// Url-safe base64 encoded id.
+  id: String,
+  email: String,
+}
+
+impl From<User> for PluginUser {
+  fn from(user: User) -> Self {
+    return PluginUser {
+      id: user.id,
+      email: user.email,
+    };
+  }
+}
+
+#[derive(Debug, Serialize)]
+struct PluginRequest {
+  method: String,
+  uri: String,
+  path_params: Vec<(String, String)>,
+  headers: Vec<(String, String)>,
+  user: Option<PluginUser>,
+  /// Base64 encoded body.
+  body: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct PluginResponse {
+  status: Option<u16>,
+  headers: Option<Vec<(String, String)>>,
+  /// Base64 encoded body.
+  body: Option<String>,
+}
+
+impl PluginResponse {
+  fn into_response(self) -> Result<Response, PluginError> {
+    let body = match self.body {
+      Some(body) => BASE64_STANDARD
+        .decode(body)
+        .map_err(|err| PluginError::Plugin(err.to_string()))?,
+      None => vec![],
+    };
+
+    let mut response = Response::new(Body::from(body));
+    *response.status_mut() = status_code(self.status.unwrap_or(200))?;
+    if let Some(headers) = self.headers {
+      apply_headers(response.headers_mut(), headers)?;
+    }
+    return Ok(response);
+  }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "phase", rename_all = "snake_case")]
+enum MiddlewareMessage {
+  Request {
+    method: String,
+    uri: String,
+    headers: Vec<(String, String)>,
+    user: Option<PluginUser>,
+  },
+  Response {
+    method: String,
+    uri: String,
+    status: u16,
+    headers: Vec<(String, String)>,
+  },
+}
+
+/// Result of a middleware call. In the request phase, a `response` short-circuits the request
+/// and `headers` are set on the request. In the response phase, `status` and `headers` are set on
+/// the response.
+#[derive(Debug, Default, Deserialize)]
+struct MiddlewareResult {
+  response: Option<PluginResponse>,
+  status: Option<u16>,
+  headers: Option<Vec<(String, String)>>,
+}
+
+fn build_route(
+  plugin: Arc<Plugin>,
+  method: &str,
+  handler: i32,
+) -> Result<MethodRouter<AppState>, PluginError> {
+  let method = Method::from_bytes(method.to_uppercase().as_bytes())
+    .map_err(|err| PluginError::Plugin(err.to_string()))?;
+  let filter =
+    MethodFilter::try_from(method).map_err(|err| PluginError::Plugin(err.to_string()))?;
+
+  return Ok(axum::routing::on(
+    filter,
+    move |params: RawPathParams, user: Option<User>, req: Request| async move {
+      let (parts, body) = req.into_parts();
+      let Ok(body) = axum::body::to_bytes(body, usize::MAX).await else {
+        return (StatusCode::BAD_REQUEST, "Failed to read body").into_response();
+      };
+
+      let request = PluginRequest {
+        method: parts.method.to_string(),
+        uri: parts.uri.to_string(),
+        path_params: params
+          .iter()
+          .map(|(k, v)| (k.to_string(), v.to_string()))
+          .collect(),
+        headers: headers_to_vec(&parts.headers),
+        user: user.map(PluginUser::from),
+        body: BASE64_STANDARD.encode(body),
+      };
+
+      return match plugin
+        .dispatch::<PluginResponse>(handler, &request)
+        .await
+        .and_then(PluginResponse::into_response)
+      {
+        Ok(response) => response,
+        Err(err) => {
+          warn!("Plugin '{}' failed: {err}", plugin.name);
+          StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+      };
+    },
+  ));
+}
+
+/// Runs the plugins' middleware. Failing middleware fails the request rather than being skipped,
+/// since it may guard access.
+async fn plugin_middleware(
+  State((state, middlewares)): State<(AppState, Arc<Vec<(Arc<Plugin>, i32)>>)>,
+  mut req: Request,
+  next: Next,
+) -> Response {
+  let user = match req
+    .extract_parts_with_state::<OptionalUser, _>(&state)
+    .await
+  {
+    Ok(OptionalUser(user)) => user,
+    Err(_) => None,
+  };
+  let method = req.method().to_string();
+  let uri = req.uri().to_string();
+
+  let fail = |plugin: &Plugin, err: PluginError| {
+    warn!("Plugin '{}' middleware failed: {err}", plugin.name);
+    return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+  };
+
+  for (plugin, handler) in middlewares.iter() {
+    let message = MiddlewareMessage::Request {
+      method: method.clone(),
+      uri: uri.clone(),
+      headers: headers_to_vec(req.headers()),
+      user: user.clone().map(PluginUser::from),
+    };
+
+    let result = match plugin
+      .dispatch::<MiddlewareResult>(*handler, &message)
+      .await
+    {
+      Ok(result) => result,
+      Err(err) => return fail(plugin, err),
+    };
+
+    if let Some(response) = result.response {
+      return match response.into_response() {
+        Ok(response) => response,
+        Err(err) => fail(plugin, err),
+      };
+    }
+    if let Some(headers) = result.headers {
+      if let Err(err) = apply_headers(req.headers_mut(), headers) {
+        return fail(plugin, err);
+      }
+    }
+  }
+
+  let mut response = next.run(req).await;
+
+  for (plugin, handler) in middlewares.iter().rev() {
+    let message = MiddlewareMessage::Response {
+      method: method.clone(),
+      uri: uri.clone(),
+      status: response.status().as_u16(),
+      headers: headers_to_vec(response.headers()),
+    };
+
+    let result = match plugin
+      .dispatch::<MiddlewareResult>(*handler, &message)
+      .await
+    {
+      Ok(result) => result,
+      Err(err) => return fail(plugin, err),
+    };
+
+    if let Some(status) = result.status {
+      match status_code(status) {
+        Ok(status) => *response.status_mut() = status,
+        Err(err) => return fail(plugin, err),
+      };
+    }
+    if let Some(headers) = result.headers {
+      if let Err(err) = apply_headers(response.headers_mut(), headers) {
+        return fail(plugin, err);
+      }
+    }
+  }
+
+  return response;
+}
+
+fn headers_to_vec(headers: &HeaderMap) -> Vec<(String, String)> {
+  return headers
+    .iter()
+    .filter_map(|(key, value)| Some((key.to_string(), value.to_str().ok()?.to_string())))
+    .collect();
+}
+
+fn apply_headers(map: &mut HeaderMap, headers: Vec<(String, String)>) -> Result<(), PluginError> {
+  for (key, value) in headers {
+    map.insert(
+      HeaderName::from_bytes(key.as_bytes()).map_err(|err| PluginError::Plugin(err.to_string()))?,
+      HeaderValue::from_str(&value).map_err(|err| PluginError::Plugin(err.to_string()))?,
+    );
+  }
+  return Ok(());
+}
+
+fn status_code(status: u16) -> Result<StatusCode, PluginError> {
+  return StatusCode::from_u16(status).map_err(|err| PluginError::Plugin(err.to_string()));
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::app_state::test_state;
+
+  // Minimal plugin in the text format, echoing requests back and recording them in a table.
+  const ECHO_PLUGIN: &str = r#"
+    (module
+      (import "trailbase" "register_route" (func $register_route (param i32 i32 i32 i32 i32)))
+      (import "trailbase" "execute" (func $execute (param i32 i32) (result i64)))
+      (memory (export "memory") 1)
+      (global $heap (mut i32) (i32.const 1024))
+      (data (i32.const 0) "POST")
+      (data (i32.const 16) "/echo")
+      (data (i32.const 32) "{\"sql\":\"INSERT INTO calls (id) VALUES (NULL)\"}")
+      (func (export "trailbase_alloc") (param $len i32) (result i32)
+        (local $ptr i32)
+        (local.set $ptr (global.get $heap))
+        (global.set $heap (i32.add (global.get $heap) (local.get $len)))
+        (local.get $ptr))
+      (func (export "trailbase_init")
+        (call $register_route (i32.const 0) (i32.const 4) (i32.const 16) (i32.const 5) (i32.const 7)))
+      (func (export "trailbase_handle") (param $handler i32) (param $ptr i32) (param $len i32) (result i64)
+        (drop (call $execute (i32.const 32) (i32.const 45)))
+        (i64.or
+          (i64.shl (i64.extend_i32_u (local.get $ptr)) (i64.const 32))
+          (i64.extend_i32_u (local.get $len))))
+    )
+  "#;
+
+  #[tokio::test]
+  async fn test_plugin_call() {
+    let state = test_state(None).await.unwrap();
+    state
+      .conn()
+      .execute_batch("CREATE TABLE calls (id INTEGER PRIMARY KEY) STRICT")
+      .await
+      .unwrap();
+
+    let path = state.data_dir().plugins_path().join("echo.wasm");
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(&path, wat::parse_str(ECHO_PLUGIN).unwrap()).unwrap();
+
+    let mut config = wasmtime::Config::new();
+    config.consume_fuel(true);
+    let engine = Engine::new(&config).unwrap();
+    let linker = build_linker(&engine).unwrap();
+
+    let (plugin, routes, middlewares) = tokio::task::spawn_blocking({
+      let state = state.clone();
+      let runtime = tokio::runtime::Handle::current();
+      move || Plugin::load(&engine, &linker, state, runtime, &path)
+    })
+    .await
+    .unwrap()
+    .unwrap();
+    assert_eq!(routes, vec![("POST".to_string(), "/echo".to_string(), 7)]);
+    assert!(middlewares.is_empty());
+
+    // The plugin echoes its input, i.e. a valid response.
+    let response: serde_json::Value = Arc::new(plugin)
+      .dispatch(7, &serde_json::json!({"status": 201}))
+      .await
+      .unwrap();
+    assert_eq!(response, serde_json::json!({"status": 201}));
+
+    let count: i64 = state
+      .conn()
+      .read_query_row_f("SELECT COUNT(*) FROM calls", (), |row| row.get(0))
+      .await
+      .unwrap()
+      .unwrap();
+    assert_eq!(count, 1);
+  }
+
+  #[test]
+  fn test_authorize() {
+    let conn = rusqlite::Connection::open_in_memory().unwrap();
+    conn
+      .execute_batch(
+        "CREATE TABLE public (id INTEGER PRIMARY KEY); CREATE TABLE _internal (id INTEGER PRIMARY KEY);",
+      )
+      .unwrap();
+    conn.authorizer(Some(authorize));
+
+    assert!(conn.prepare("SELECT * FROM public").is_ok());
+    assert!(conn.prepare("INSERT INTO public (id) VALUES (1)").is_ok());
+    assert!(conn.prepare("SELECT * FROM _internal").is_err());
+    assert!(conn.prepare("DELETE FROM _internal").is_err());
+    assert!(conn.prepare("DROP TABLE public").is_err());
+    assert!(conn.prepare("PRAGMA journal_mode").is_err());
+
+    assert!(
+      conn
+        .prepare("SELECT lower('A'), count(*) FROM public")
+        .is_ok()
+    );
+    assert!(conn.prepare("SELECT load_extension('evil')").is_err());
+    assert!(conn.prepare("SELECT random()").is_err());
+  }
+
+  #[test]
+  fn test_execute_limits() {
+    let conn = rusqlite::Connection::open_in_memory().unwrap();
+    conn
+      .execute_batch("CREATE TABLE public (id INTEGER PRIMARY KEY);")
+      .unwrap();
+
+    assert_eq!(
+      execute(
+        &conn,
+        "INSERT INTO public (id) VALUES (?1)",
+        vec![trailbase_sqlite::Value::Integer(1)],
+        10
+      )
+      .unwrap(),
+      1
+    );
+
+    // Unbounded writes get interrupted and rolled back.
+    let result = execute(
+      &conn,
+      "WITH RECURSIVE c(x) AS (SELECT 2 UNION ALL SELECT x + 1 FROM c) \
+       INSERT INTO public (id) SELECT x FROM c",
+      vec![],
+      10,
+    );
+    assert!(matches!(result, Err(SqlApiError::Timeout)), "{result:?}");
+
+    let count: i64 = conn
+      .query_row("SELECT COUNT(*) FROM public", (), |row| row.get(0))
+      .unwrap();
+    assert_eq!(count, 1);
+
+    // Limits are lifted afterwards.
+    assert!(conn.execute_batch("SELECT 1").is_ok());
+  }
+}
@@ -0,0 +1,21 @@
+SELECT
+{% for expr in select_exprs -%}
+  {%- if !loop.first %},{% endif %}{{ expr }}
+{%- endfor %}
+FROM
+  (SELECT :__user_id AS id) AS _USER_,
+  (SELECT :__ctx_method AS method, :__ctx_ip AS ip, :__ctx_headers AS headers) AS _CTX_,
+  "{{ table_name }}" AS _ROW_
+WHERE
+  ({{ read_access_clause }})
+  AND ({{ filter_clause }})
+{%- if !group_by.is_empty() %}
+GROUP BY
+{% for name in group_by -%}
+  {%- if !loop.first %},{% endif %}_ROW_."{{ name }}"
+{%- endfor %}
+ORDER BY
+  {{ order_clause }}
+{%- endif %}
+LIMIT :__limit
+OFFSET :__offset
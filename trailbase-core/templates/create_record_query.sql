@@ -9,6 +9,18 @@ INSERT {{ conflict_clause }} INTO "{{ table_name }}"
     {%- if !loop.first %},{% endif %}:{{ name }}
   {%- endfor -%}
 )
+{%- if !upsert_target.is_empty() %} ON CONFLICT (
+  {%- for name in upsert_target -%}
+    {%- if !loop.first %},{% endif %}"{{ name }}"
+  {%- endfor -%}
+  ) DO
+  {%- if upsert_update %} UPDATE SET
+    {%- for name in column_names -%}
+      {%- if !loop.first %},{% endif %} "{{ name }}" = excluded."{{ name }}"
+    {%- endfor -%}
+  {%- else %} NOTHING
+  {%- endif -%}
+{%- endif -%}
 {%- endif -%}
 {%- for col in returning -%}
   {%- if loop.first %} RETURNING {% endif -%}
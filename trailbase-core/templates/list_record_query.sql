@@ -4,6 +4,10 @@ WITH
     SELECT COUNT(*) AS _value_
     FROM
       (SELECT :__user_id AS id) AS _USER_,
+      (SELECT :__ctx_method AS method, :__ctx_ip AS ip, :__ctx_headers AS headers) AS _CTX_,
+{%- if let Some(fts_table) = fts_table %}
+      "{{ fts_table }}" AS _FTS_,
+{%- endif %}
       "{{ table_name }}" as _ROW_
     WHERE
       ({{ read_access_clause }})
@@ -23,12 +27,19 @@ SELECT
 {%- endif %}
 FROM
   (SELECT :__user_id AS id) AS _USER_,
+  (SELECT :__ctx_method AS method, :__ctx_ip AS ip, :__ctx_headers AS headers) AS _CTX_,
 {%- if count %}
   total_count,
+{%- endif %}
+{%- if let Some(fts_table) = fts_table %}
+  "{{ fts_table }}" AS _FTS_,
 {%- endif %}
   "{{ table_name }}" AS _ROW_
+{%- if let Some(vec_table) = vec_table %}
+    LEFT JOIN "{{ vec_table }}" AS _VEC_ ON _VEC_.rowid = _ROW_.rowid
+{%- endif %}
 {%- for expanded in expanded_tables %}
-    LEFT JOIN "{{ expanded.foreign_table_name }}" AS F{{ loop.index0 }} ON _ROW_."{{ expanded.local_column_name }}" = F{{ loop.index0 }}."{{ expanded.foreign_column_name }}"
+    LEFT JOIN "{{ expanded.foreign_table_name }}" AS F{{ loop.index0 }} ON {% if let Some(parent) = expanded.parent %}F{{ parent }}{% else %}_ROW_{% endif %}."{{ expanded.local_column_name }}" = F{{ loop.index0 }}."{{ expanded.foreign_column_name }}"
 {%- endfor %}
 WHERE
   ({{ read_access_clause }})
@@ -2,4 +2,4 @@ SELECT
 {% for name in column_names -%}
   {%- if !loop.first %},{% endif %}MAIN."{{ name }}"
 {%- endfor %}
-FROM "{{ table_name }}" as MAIN WHERE MAIN."{{ pk_column_name }}" = ?1
+FROM "{{ table_name }}" as MAIN WHERE {{ record_id_clause }}
@@ -7,6 +7,6 @@ SELECT
 {%- endfor %}
 FROM "{{ table_name }}" AS MAIN
 {% for expanded in expanded_tables %}
-  LEFT JOIN "{{ expanded.foreign_table_name }}" AS F{{ loop.index0 }} ON MAIN."{{ expanded.local_column_name }}" = F{{ loop.index0 }}."{{ expanded.foreign_column_name }}"
+  LEFT JOIN "{{ expanded.foreign_table_name }}" AS F{{ loop.index0 }} ON {% if let Some(parent) = expanded.parent %}F{{ parent }}{% else %}MAIN{% endif %}."{{ expanded.local_column_name }}" = F{{ loop.index0 }}."{{ expanded.foreign_column_name }}"
 {% endfor %}
-WHERE MAIN."{{ pk_column_name }}" = ?1
+WHERE {{ record_id_clause }}
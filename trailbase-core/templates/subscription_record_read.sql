@@ -1,7 +1,8 @@
 SELECT
   CAST(({{ read_access_rule }}) AS INTEGER)
 FROM
-  (SELECT :__user_id AS id) AS _USER_
+  (SELECT :__user_id AS id) AS _USER_,
+  (SELECT :__ctx_method AS method, :__ctx_ip AS ip, :__ctx_headers AS headers) AS _CTX_
   {% if !column_names.is_empty() -%}
   , (SELECT
     {%- for name in column_names -%}
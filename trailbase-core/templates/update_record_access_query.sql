@@ -3,6 +3,7 @@ SELECT
   CAST(({{ update_access_rule }}) AS INTEGER)
 FROM
   (SELECT :__user_id AS id) AS _USER_,
+  (SELECT :__ctx_method AS method, :__ctx_ip AS ip, :__ctx_headers AS headers) AS _CTX_,
   (SELECT * FROM "{{ table_name }}" WHERE "{{ pk_column_name }}" = :__record_id) AS _ROW_
   {% if !column_names.is_empty() -%}
   , (SELECT
@@ -18,6 +18,7 @@ fn test_admin_permissions() {
       main_router,
       admin_router,
       tls,
+      ..
     } = Server::init(ServerOptions {
       data_dir: DataDir(data_dir.path().to_path_buf()),
       address: "localhost:4040".to_string(),
@@ -0,0 +1,66 @@
+use trailbase::Server;
+use trailbase::config::proto::Config;
+
+#[test]
+fn test_embedded_server() {
+  let runtime = tokio::runtime::Builder::new_multi_thread()
+    .enable_all()
+    .build()
+    .unwrap();
+
+  let data_dir = temp_dir::TempDir::new().unwrap();
+
+  runtime.block_on(async move {
+    let port = 4026;
+
+    let mut config = Config::new_with_custom_defaults();
+    config.server.application_name = Some("Embedded".to_string());
+
+    let server = Server::builder()
+      .data_dir(data_dir.path())
+      .address(format!("127.0.0.1:{port}"))
+      .in_memory(true)
+      .config(config)
+      .build()
+      .await
+      .unwrap();
+
+    assert_eq!(
+      server.state.get_config().server.application_name.as_deref(),
+      Some("Embedded")
+    );
+
+    server
+      .connection()
+      .execute("CREATE TABLE embedded (id INTEGER PRIMARY KEY) STRICT", ())
+      .await
+      .unwrap();
+
+    let shutdown = server.shutdown_handle();
+    let handle = tokio::spawn(async move {
+      server.serve().await.unwrap();
+    });
+
+    'success: {
+      for _ in 0..100 {
+        if let Ok(response) = reqwest::get(format!("http://127.0.0.1:{port}/api/healthcheck")).await
+        {
+          assert_eq!(response.text().await.unwrap(), "Ok");
+          break 'success;
+        }
+
+        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+      }
+
+      panic!("Timed out");
+    }
+
+    shutdown.shutdown();
+    assert!(shutdown.is_shutdown());
+
+    tokio::time::timeout(tokio::time::Duration::from_secs(10), handle)
+      .await
+      .expect("graceful shutdown")
+      .unwrap();
+  });
+}
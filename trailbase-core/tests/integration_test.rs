@@ -40,6 +40,7 @@ async fn test_record_apis() {
     main_router,
     admin_router,
     tls,
+    ..
   } = Server::init(ServerOptions {
     data_dir: DataDir(data_dir.path().to_path_buf()),
     address: "localhost:4041".to_string(),
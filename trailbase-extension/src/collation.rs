@@ -0,0 +1,153 @@
+use arc_swap::ArcSwap;
+use icu_collator::{Collator, CollatorOptions, Strength};
+use icu_locid::Locale;
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock};
+
+#[derive(Clone, Debug)]
+pub struct CollationSpec {
+  /// Name used to reference the collation, e.g. `ORDER BY name COLLATE <name>`.
+  pub name: String,
+  /// BCP-47 locale, e.g. "de" or "sv-SE".
+  pub locale: String,
+  /// Ignore case differences, accents remain significant.
+  pub case_insensitive: bool,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum CollationError {
+  #[error("Invalid name: {0}")]
+  InvalidName(String),
+  #[error("Invalid locale: {0}")]
+  InvalidLocale(String),
+}
+
+static COLLATIONS: LazyLock<ArcSwap<HashMap<String, Arc<Collator>>>> =
+  LazyLock::new(|| ArcSwap::from_pointee(HashMap::new()));
+
+fn build_collator(spec: &CollationSpec) -> Result<Collator, CollationError> {
+  if spec.name.is_empty()
+    || !spec
+      .name
+      .chars()
+      .all(|c| c.is_ascii_alphanumeric() || c == '_')
+  {
+    return Err(CollationError::InvalidName(spec.name.clone()));
+  }
+
+  let locale: Locale = spec
+    .locale
+    .parse()
+    .map_err(|_| CollationError::InvalidLocale(spec.locale.clone()))?;
+
+  let mut options = CollatorOptions::new();
+  if spec.case_insensitive {
+    options.strength = Some(Strength::Secondary);
+  }
+
+  return Collator::try_new(&(&locale).into(), options)
+    .map_err(|_| CollationError::InvalidLocale(spec.locale.clone()));
+}
+
+/// Validates the given spec without registering it.
+pub fn validate_collation(spec: &CollationSpec) -> Result<(), CollationError> {
+  return build_collator(spec).map(|_| ());
+}
+
+/// Sets the named collations available to all connections.
+///
+/// NOTE: Connections resolve collations lazily on first use and keep them for their lifetime,
+/// i.e. changes only apply to collations a connection hasn't used yet.
+pub fn set_collations(specs: Vec<CollationSpec>) -> Result<(), CollationError> {
+  let collations = specs
+    .iter()
+    .map(|spec| Ok((spec.name.clone(), Arc::new(build_collator(spec)?))))
+    .collect::<Result<HashMap<_, _>, CollationError>>()?;
+
+  COLLATIONS.store(Arc::new(collations));
+  return Ok(());
+}
+
+pub fn has_collation(name: &str) -> bool {
+  return COLLATIONS.load().contains_key(name);
+}
+
+/// Callback invoked by SQLite when a statement references an unknown collation.
+pub(crate) fn collation_needed(
+  conn: &rusqlite::Connection,
+  name: &str,
+) -> Result<(), rusqlite::Error> {
+  let Some(collator) = COLLATIONS.load().get(name).cloned() else {
+    // Let SQLite fail with "no such collation sequence".
+    return Ok(());
+  };
+
+  return conn.create_collation(name, move |a: &str, b: &str| collator.compare(a, b));
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_collations() {
+    assert!(
+      validate_collation(&CollationSpec {
+        name: "invalid name".to_string(),
+        locale: "de".to_string(),
+        case_insensitive: false,
+      })
+      .is_err()
+    );
+
+    set_collations(vec![
+      CollationSpec {
+        name: "sv".to_string(),
+        locale: "sv".to_string(),
+        case_insensitive: false,
+      },
+      CollationSpec {
+        name: "de_ci".to_string(),
+        locale: "de".to_string(),
+        case_insensitive: true,
+      },
+    ])
+    .unwrap();
+
+    let conn = crate::connect_sqlite(None, None).unwrap();
+    conn
+      .execute_batch(
+        r#"
+          CREATE TABLE test (name TEXT NOT NULL);
+          INSERT INTO test (name) VALUES ('zebra'), ('Äpfel'), ('apfel'), ('Öl');
+        "#,
+      )
+      .unwrap();
+
+    let names = |sql: &str| -> Vec<String> {
+      let mut stmt = conn.prepare(sql).unwrap();
+      return stmt
+        .query_map([], |row| row.get(0))
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    };
+
+    // Swedish sorts Ä and Ö after Z.
+    assert_eq!(
+      names("SELECT name FROM test ORDER BY name COLLATE sv"),
+      vec!["apfel", "zebra", "Äpfel", "Öl"]
+    );
+
+    assert_eq!(
+      names("SELECT name FROM test WHERE name = 'ÖL' COLLATE de_ci"),
+      vec!["Öl"]
+    );
+
+    assert!(
+      conn
+        .prepare("SELECT name FROM test ORDER BY name COLLATE missing")
+        .is_err()
+    );
+  }
+}
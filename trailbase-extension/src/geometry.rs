@@ -0,0 +1,426 @@
+//! Conversion between 2D WKB (well-known binary) geometries and GeoJSON geometry objects.
+//!
+//! See https://libgeos.org/specifications/wkb/ and https://datatracker.ietf.org/doc/html/rfc7946.
+
+use rusqlite::Error;
+use rusqlite::functions::Context;
+use serde_json::{Value, json};
+
+#[derive(thiserror::Error, Clone, Debug, PartialEq)]
+pub enum GeometryError {
+  #[error("Invalid WKB: {0}")]
+  InvalidWkb(&'static str),
+  #[error("Invalid GeoJSON: {0}")]
+  InvalidGeoJson(String),
+}
+
+const POINT: u32 = 1;
+const LINE_STRING: u32 = 2;
+const POLYGON: u32 = 3;
+const MULTI_POINT: u32 = 4;
+const MULTI_LINE_STRING: u32 = 5;
+const MULTI_POLYGON: u32 = 6;
+const GEOMETRY_COLLECTION: u32 = 7;
+
+/// Max nesting of geometry collections.
+const MAX_DEPTH: usize = 16;
+
+struct Reader<'a> {
+  buf: &'a [u8],
+  little_endian: bool,
+}
+
+impl Reader<'_> {
+  fn bytes<const N: usize>(&mut self) -> Result<[u8; N], GeometryError> {
+    let Some((head, tail)) = self.buf.split_first_chunk::<N>() else {
+      return Err(GeometryError::InvalidWkb("unexpected end"));
+    };
+    self.buf = tail;
+    return Ok(*head);
+  }
+
+  fn u32(&mut self) -> Result<u32, GeometryError> {
+    let bytes = self.bytes::<4>()?;
+    return Ok(match self.little_endian {
+      true => u32::from_le_bytes(bytes),
+      false => u32::from_be_bytes(bytes),
+    });
+  }
+
+  fn f64(&mut self) -> Result<f64, GeometryError> {
+    let bytes = self.bytes::<8>()?;
+    return Ok(match self.little_endian {
+      true => f64::from_le_bytes(bytes),
+      false => f64::from_be_bytes(bytes),
+    });
+  }
+
+  fn count(&mut self) -> Result<usize, GeometryError> {
+    let count = self.u32()? as usize;
+    // Guard against bogus counts leading to huge allocations: every element takes >= 4 bytes.
+    if count > self.buf.len() / 4 {
+      return Err(GeometryError::InvalidWkb("invalid count"));
+    }
+    return Ok(count);
+  }
+
+  fn position(&mut self) -> Result<Value, GeometryError> {
+    return Ok(json!([self.f64()?, self.f64()?]));
+  }
+
+  fn positions(&mut self) -> Result<Value, GeometryError> {
+    let count = self.count()?;
+    return (0..count)
+      .map(|_| self.position())
+      .collect::<Result<Vec<_>, _>>()
+      .map(Value::Array);
+  }
+
+  fn rings(&mut self) -> Result<Value, GeometryError> {
+    let count = self.count()?;
+    return (0..count)
+      .map(|_| self.positions())
+      .collect::<Result<Vec<_>, _>>()
+      .map(Value::Array);
+  }
+
+  fn geometry(&mut self, expected: Option<u32>, depth: usize) -> Result<Value, GeometryError> {
+    if depth > MAX_DEPTH {
+      return Err(GeometryError::InvalidWkb("nesting too deep"));
+    }
+
+    self.little_endian = match self.bytes::<1>()? {
+      [0] => false,
+      [1] => true,
+      _ => return Err(GeometryError::InvalidWkb("invalid byte order")),
+    };
+
+    let geometry_type = self.u32()?;
+    if expected.is_some_and(|e| e != geometry_type) {
+      return Err(GeometryError::InvalidWkb("unexpected member type"));
+    }
+
+    let (name, coordinates) = match geometry_type {
+      POINT => {
+        let (x, y) = (self.f64()?, self.f64()?);
+        // Empty points are encoded as NaN coordinates.
+        if x.is_nan() && y.is_nan() {
+          ("Point", json!([]))
+        } else {
+          ("Point", json!([x, y]))
+        }
+      }
+      LINE_STRING => ("LineString", self.positions()?),
+      POLYGON => ("Polygon", self.rings()?),
+      MULTI_POINT | MULTI_LINE_STRING | MULTI_POLYGON => {
+        let member = geometry_type - 3;
+        let count = self.count()?;
+        let members = (0..count)
+          .map(|_| {
+            let mut geometry = self.geometry(Some(member), depth + 1)?;
+            return Ok(geometry["coordinates"].take());
+          })
+          .collect::<Result<Vec<_>, _>>()?;
+
+        let name = match geometry_type {
+          MULTI_POINT => "MultiPoint",
+          MULTI_LINE_STRING => "MultiLineString",
+          _ => "MultiPolygon",
+        };
+        (name, Value::Array(members))
+      }
+      GEOMETRY_COLLECTION => {
+        let count = self.count()?;
+        let geometries = (0..count)
+          .map(|_| self.geometry(None, depth + 1))
+          .collect::<Result<Vec<_>, _>>()?;
+
+        return Ok(json!({
+          "type": "GeometryCollection",
+          "geometries": geometries,
+        }));
+      }
+      _ => return Err(GeometryError::InvalidWkb("unsupported geometry type")),
+    };
+
+    return Ok(json!({
+      "type": name,
+      "coordinates": coordinates,
+    }));
+  }
+}
+
+/// Decodes a 2D WKB geometry into a GeoJSON geometry object.
+pub fn wkb_to_geojson(wkb: &[u8]) -> Result<Value, GeometryError> {
+  let mut reader = Reader {
+    buf: wkb,
+    little_endian: true,
+  };
+  let geometry = reader.geometry(None, 0)?;
+  if !reader.buf.is_empty() {
+    return Err(GeometryError::InvalidWkb("trailing bytes"));
+  }
+  return Ok(geometry);
+}
+
+fn invalid(msg: impl Into<String>) -> GeometryError {
+  return GeometryError::InvalidGeoJson(msg.into());
+}
+
+fn array(value: &Value) -> Result<&Vec<Value>, GeometryError> {
+  return value
+    .as_array()
+    .ok_or_else(|| invalid(format!("expected array, got: {value}")));
+}
+
+/// Writes a WGS84 position, i.e. [longitude, latitude].
+fn write_position(out: &mut Vec<u8>, position: &Value) -> Result<(), GeometryError> {
+  let position = array(position)?;
+  let [lng, lat] = position.as_slice() else {
+    return Err(invalid("positions must have exactly two coordinates"));
+  };
+  let (Some(lng), Some(lat)) = (lng.as_f64(), lat.as_f64()) else {
+    return Err(invalid("coordinates must be numbers"));
+  };
+  if !(-180.0..=180.0).contains(&lng) || !(-90.0..=90.0).contains(&lat) {
+    return Err(invalid(format!("position out of range: [{lng}, {lat}]")));
+  }
+
+  out.extend_from_slice(&lng.to_le_bytes());
+  out.extend_from_slice(&lat.to_le_bytes());
+  return Ok(());
+}
+
+fn write_count(out: &mut Vec<u8>, count: usize) -> Result<(), GeometryError> {
+  let count: u32 = count.try_into().map_err(|_| invalid("too many elements"))?;
+  out.extend_from_slice(&count.to_le_bytes());
+  return Ok(());
+}
+
+fn write_positions(out: &mut Vec<u8>, positions: &Value, min: usize) -> Result<(), GeometryError> {
+  let positions = array(positions)?;
+  if positions.len() < min {
+    return Err(invalid(format!("expected at least {min} positions")));
+  }
+
+  write_count(out, positions.len())?;
+  for position in positions {
+    write_position(out, position)?;
+  }
+  return Ok(());
+}
+
+fn write_rings(out: &mut Vec<u8>, rings: &Value) -> Result<(), GeometryError> {
+  let rings = array(rings)?;
+  write_count(out, rings.len())?;
+  for ring in rings {
+    let positions = array(ring)?;
+    if positions.first() != positions.last() {
+      return Err(invalid("polygon rings must be closed"));
+    }
+    write_positions(out, ring, 4)?;
+  }
+  return Ok(());
+}
+
+fn write_header(out: &mut Vec<u8>, geometry_type: u32) {
+  out.push(1);
+  out.extend_from_slice(&geometry_type.to_le_bytes());
+}
+
+fn write_geometry(out: &mut Vec<u8>, geometry: &Value, depth: usize) -> Result<(), GeometryError> {
+  if depth > MAX_DEPTH {
+    return Err(invalid("nesting too deep"));
+  }
+
+  let Some(geometry_type) = geometry.get("type").and_then(|t| t.as_str()) else {
+    return Err(invalid("missing type"));
+  };
+
+  if geometry_type == "GeometryCollection" {
+    let geometries = array(geometry.get("geometries").unwrap_or(&Value::Null))?;
+    write_header(out, GEOMETRY_COLLECTION);
+    write_count(out, geometries.len())?;
+    for geometry in geometries {
+      write_geometry(out, geometry, depth + 1)?;
+    }
+    return Ok(());
+  }
+
+  let coordinates = geometry.get("coordinates").unwrap_or(&Value::Null);
+  match geometry_type {
+    "Point" => {
+      write_header(out, POINT);
+      write_position(out, coordinates)?;
+    }
+    "LineString" => {
+      write_header(out, LINE_STRING);
+      write_positions(out, coordinates, 2)?;
+    }
+    "Polygon" => {
+      write_header(out, POLYGON);
+      write_rings(out, coordinates)?;
+    }
+    "MultiPoint" | "MultiLineString" | "MultiPolygon" => {
+      let (multi, member) = match geometry_type {
+        "MultiPoint" => (MULTI_POINT, POINT),
+        "MultiLineString" => (MULTI_LINE_STRING, LINE_STRING),
+        _ => (MULTI_POLYGON, POLYGON),
+      };
+
+      let members = array(coordinates)?;
+      write_header(out, multi);
+      write_count(out, members.len())?;
+      for member_coordinates in members {
+        write_header(out, member);
+        match member {
+          POINT => write_position(out, member_coordinates)?,
+          LINE_STRING => write_positions(out, member_coordinates, 2)?,
+          _ => write_rings(out, member_coordinates)?,
+        }
+      }
+    }
+    x => return Err(invalid(format!("unsupported type: {x}"))),
+  };
+
+  return Ok(());
+}
+
+/// Validates and encodes a GeoJSON geometry object as little-endian 2D WKB.
+pub fn geojson_to_wkb(geometry: &Value) -> Result<Vec<u8>, GeometryError> {
+  let mut out = Vec::with_capacity(64);
+  write_geometry(&mut out, geometry, 0)?;
+  return Ok(out);
+}
+
+/// Mean earth radius in meters.
+pub const EARTH_RADIUS_METERS: f64 = 6_371_008.8;
+
+/// Great-circle distance in meters between two WGS84 positions using the haversine formula.
+pub fn haversine_distance(lat0: f64, lng0: f64, lat1: f64, lng1: f64) -> f64 {
+  let (phi0, phi1) = (lat0.to_radians(), lat1.to_radians());
+  let d_phi = (lat1 - lat0).to_radians();
+  let d_lambda = (lng1 - lng0).to_radians();
+
+  let a = (d_phi / 2.0).sin().powi(2) + phi0.cos() * phi1.cos() * (d_lambda / 2.0).sin().powi(2);
+  return 2.0 * EARTH_RADIUS_METERS * a.sqrt().min(1.0).asin();
+}
+
+/// Distance in meters between two positions, i.e. `geo_distance(lat0, lng0, lat1, lng1)`, or NULL
+/// if any coordinate is NULL.
+pub(super) fn geo_distance(context: &Context) -> Result<Option<f64>, Error> {
+  #[cfg(debug_assertions)]
+  if context.len() != 4 {
+    return Err(Error::InvalidParameterCount(context.len(), 4));
+  }
+
+  let (Some(lat0), Some(lng0), Some(lat1), Some(lng1)) = (
+    context.get::<Option<f64>>(0)?,
+    context.get::<Option<f64>>(1)?,
+    context.get::<Option<f64>>(2)?,
+    context.get::<Option<f64>>(3)?,
+  ) else {
+    return Ok(None);
+  };
+  return Ok(Some(haversine_distance(lat0, lng0, lat1, lng1)));
+}
+
+/// Validator for WKB geometry columns, i.e. `CHECK(is_geometry(col))`.
+pub(super) fn is_geometry(context: &Context) -> Result<bool, Error> {
+  #[cfg(debug_assertions)]
+  if context.len() != 1 {
+    return Err(Error::InvalidParameterCount(context.len(), 1));
+  }
+
+  if let Some(blob) = context.get_raw(0).as_blob_or_null()? {
+    return Ok(wkb_to_geojson(blob).is_ok());
+  }
+  return Ok(true);
+}
+
+#[cfg(test)]
+mod tests {
+  use rusqlite::params;
+
+  use super::*;
+
+  #[test]
+  fn test_geojson_wkb_roundtrip() {
+    let geometries = [
+      json!({"type": "Point", "coordinates": [13.4, 52.5]}),
+      json!({"type": "LineString", "coordinates": [[0.0, 0.0], [1.0, 1.0]]}),
+      json!({
+        "type": "Polygon",
+        "coordinates": [[[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 0.0]]],
+      }),
+      json!({"type": "MultiPoint", "coordinates": [[0.0, 0.0], [1.0, 1.0]]}),
+      json!({
+        "type": "GeometryCollection",
+        "geometries": [{"type": "Point", "coordinates": [1.0, 2.0]}],
+      }),
+    ];
+
+    for geometry in geometries {
+      let wkb = geojson_to_wkb(&geometry).unwrap();
+      assert_eq!(wkb_to_geojson(&wkb).unwrap(), geometry);
+    }
+
+    // Big-endian WKB of POINT(1 2).
+    let mut big_endian = vec![0, 0, 0, 0, 1];
+    big_endian.extend_from_slice(&1.0f64.to_be_bytes());
+    big_endian.extend_from_slice(&2.0f64.to_be_bytes());
+    assert_eq!(
+      wkb_to_geojson(&big_endian).unwrap(),
+      json!({"type": "Point", "coordinates": [1.0, 2.0]})
+    );
+
+    assert!(geojson_to_wkb(&json!({"type": "Point", "coordinates": [200.0, 0.0]})).is_err());
+    assert!(geojson_to_wkb(&json!({"type": "Point", "coordinates": [0.0]})).is_err());
+    assert!(
+      geojson_to_wkb(&json!({
+        "type": "Polygon",
+        "coordinates": [[[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.5, 0.5]]],
+      }))
+      .is_err()
+    );
+    assert!(wkb_to_geojson(&big_endian[..10]).is_err());
+  }
+
+  #[test]
+  fn test_is_geometry() {
+    let conn = crate::connect_sqlite(None, None).unwrap();
+    conn
+      .execute(
+        "CREATE TABLE test (geom BLOB CHECK(is_geometry(geom))) STRICT",
+        (),
+      )
+      .unwrap();
+
+    const QUERY: &str = "INSERT INTO test (geom) VALUES ($1)";
+    let point = geojson_to_wkb(&json!({"type": "Point", "coordinates": [1.0, 2.0]})).unwrap();
+    conn.execute(QUERY, params!(point)).unwrap();
+    conn
+      .execute(QUERY, params!(rusqlite::types::Value::Null))
+      .unwrap();
+    assert!(conn.execute(QUERY, params!(vec![1u8, 2, 3])).is_err());
+  }
+
+  #[test]
+  fn test_geo_distance() {
+    // Berlin to Paris is roughly 878km.
+    let distance = haversine_distance(52.52, 13.405, 48.8566, 2.3522);
+    assert!((distance - 877_500.0).abs() < 1_000.0, "{distance}");
+    assert_eq!(haversine_distance(10.0, 20.0, 10.0, 20.0), 0.0);
+
+    let conn = crate::connect_sqlite(None, None).unwrap();
+    let distance: f64 = conn
+      .query_row("SELECT geo_distance(0, 0, 0, 1)", (), |row| row.get(0))
+      .unwrap();
+    assert!((distance - 111_195.0).abs() < 1.0, "{distance}");
+
+    let null: Option<f64> = conn
+      .query_row("SELECT geo_distance(0, NULL, 0, 1)", (), |row| row.get(0))
+      .unwrap();
+    assert_eq!(null, None);
+  }
+}
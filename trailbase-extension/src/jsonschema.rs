@@ -8,7 +8,8 @@ use std::sync::{Arc, LazyLock};
 
 pub type ValidationError = jsonschema::ValidationError<'static>;
 
-type CustomValidatorFn = Arc<dyn Fn(&serde_json::Value, Option<&str>) -> bool + Send + Sync>;
+/// Additional validation receiving the extra arguments, e.g. `jsonschema('name', col, 'a', 'b')`.
+type CustomValidatorFn = Arc<dyn Fn(&serde_json::Value, &[&str]) -> bool + Send + Sync>;
 
 #[derive(Clone)]
 pub struct SchemaEntry {
@@ -24,11 +25,25 @@ impl SchemaEntry {
   ) -> Result<Self, ValidationError> {
     let validator = Validator::new(&schema)?;
 
-    return Ok(Self {
+    return Ok(Self::with_validator(schema, validator, custom_validator));
+  }
+
+  /// Creates an entry from an already compiled validator, e.g. one built with custom options such
+  /// as a retriever resolving `$ref`s to other schemas.
+  pub fn with_validator(
+    schema: serde_json::Value,
+    validator: Validator,
+    custom_validator: Option<CustomValidatorFn>,
+  ) -> Self {
+    return Self {
       schema,
       validator: validator.into(),
       custom_validator,
-    });
+    };
+  }
+
+  pub fn schema(&self) -> &serde_json::Value {
+    return &self.schema;
   }
 }
 
@@ -97,7 +112,7 @@ pub(crate) fn jsonschema_by_name(context: &Context) -> Result<bool, Error> {
   }
 
   if let Some(validator) = entry.custom_validator {
-    if !validator(&json, None) {
+    if !validator(&json, &[]) {
       return Ok(false);
     }
   }
@@ -106,8 +121,16 @@ pub(crate) fn jsonschema_by_name(context: &Context) -> Result<bool, Error> {
 }
 
 pub(crate) fn jsonschema_by_name_with_extra_args(context: &Context) -> Result<bool, Error> {
+  if context.len() < 2 {
+    return Err(Error::UserFunctionError(
+      "Expected jsonschema(name, value, ...)".into(),
+    ));
+  }
+
   let schema_name = context.get_raw(0).as_str()?;
-  let extra_args = context.get_raw(2).as_str()?;
+  let extra_args = (2..context.len())
+    .map(|index| context.get_raw(index).as_str())
+    .collect::<Result<Vec<_>, _>>()?;
 
   // Get and parse the JSON contents. If it's invalid JSON to start with, there's not much
   // we can validate.
@@ -129,7 +152,7 @@ pub(crate) fn jsonschema_by_name_with_extra_args(context: &Context) -> Result<bo
   }
 
   if let Some(validator) = entry.custom_validator {
-    if !validator(&json, Some(extra_args)) {
+    if !validator(&json, &extra_args) {
       return Ok(false);
     }
   }
@@ -238,8 +261,8 @@ mod tests {
         }
     "#;
 
-    fn starts_with(v: &serde_json::Value, param: Option<&str>) -> bool {
-      if let Some(param) = param {
+    fn starts_with(v: &serde_json::Value, params: &[&str]) -> bool {
+      if let Some(param) = params.first() {
         if let serde_json::Value::Object(map) = v {
           if let Some(serde_json::Value::String(str)) = map.get("name") {
             if str.starts_with(param) {
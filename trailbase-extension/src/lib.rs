@@ -1,13 +1,17 @@
 #![forbid(clippy::unwrap_used)]
 #![allow(clippy::needless_return)]
 
+use rusqlite::OptionalExtension;
 use rusqlite::functions::FunctionFlags;
 use std::path::PathBuf;
 
+pub mod collation;
+pub mod geometry;
 pub mod jsonschema;
 pub mod maxminddb;
 pub mod password;
 
+mod like;
 mod regex;
 mod uuid;
 mod validators;
@@ -16,10 +20,61 @@ mod validators;
 pub enum Error {
   #[error("Rusqlite error: {0}")]
   Rusqlite(#[from] rusqlite::Error),
+  #[error("Failed to load extension {path:?} (entry point: {entry_point:?}): {source}")]
+  Extension {
+    path: PathBuf,
+    entry_point: Option<String>,
+    #[source]
+    source: rusqlite::Error,
+  },
+  #[error("Database key error: {0}")]
+  Key(String),
   #[error("Other error: {0}")]
   Other(#[source] Box<dyn std::error::Error + Send + Sync + 'static>),
 }
 
+/// A shared-library SQLite extension loaded into every connection.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LoadableExtension {
+  pub path: PathBuf,
+  /// Name of the init function. If unset, SQLite derives it from the file name, e.g.
+  /// `sqlite3_fts5_init` for `libfts5.so`.
+  pub entry_point: Option<String>,
+}
+
+impl From<PathBuf> for LoadableExtension {
+  fn from(path: PathBuf) -> Self {
+    return Self {
+      path,
+      entry_point: None,
+    };
+  }
+}
+
+#[allow(unsafe_code)]
+fn load_extensions(
+  conn: &rusqlite::Connection,
+  extensions: &[LoadableExtension],
+) -> Result<(), Error> {
+  if extensions.is_empty() {
+    return Ok(());
+  }
+
+  // Only allow loading for the duration of the guard, i.e. not from SQL via `load_extension()`.
+  let _guard = unsafe { rusqlite::LoadExtensionGuard::new(conn)? };
+  for extension in extensions {
+    unsafe { conn.load_extension(&extension.path, extension.entry_point.as_deref()) }.map_err(
+      |source| Error::Extension {
+        path: extension.path.clone(),
+        entry_point: extension.entry_point.clone(),
+        source,
+      },
+    )?;
+  }
+
+  return Ok(());
+}
+
 pub fn apply_default_pragmas(conn: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
   const CONFIG: &[&str] = &[
     "PRAGMA busy_timeout       = 10000",
@@ -51,10 +106,19 @@ pub fn apply_default_pragmas(conn: &rusqlite::Connection) -> Result<(), rusqlite
   return Ok(());
 }
 
-#[allow(unsafe_code)]
 pub fn connect_sqlite(
   path: Option<PathBuf>,
-  extensions: Option<Vec<PathBuf>>,
+  extensions: Option<Vec<LoadableExtension>>,
+) -> Result<rusqlite::Connection, Error> {
+  return connect_sqlite_with_key(path, extensions, None);
+}
+
+/// Like [connect_sqlite] but keys the connection before anything else, see [apply_key].
+#[allow(unsafe_code)]
+pub fn connect_sqlite_with_key(
+  path: Option<PathBuf>,
+  extensions: Option<Vec<LoadableExtension>>,
+  key: Option<&str>,
 ) -> Result<rusqlite::Connection, Error> {
   // First load C extensions like sqlean and vector search.
   let status =
@@ -64,22 +128,26 @@ pub fn connect_sqlite(
   }
 
   // Then open database and load trailbase_extensions.
-  let conn = sqlite3_extension_init(if let Some(p) = path {
+  let conn = if let Some(p) = path {
     use rusqlite::OpenFlags;
+    // URIs allow opening shared in-memory databases, e.g. "file:/main?vfs=memdb".
     let flags = OpenFlags::SQLITE_OPEN_READ_WRITE
       | OpenFlags::SQLITE_OPEN_CREATE
+      | OpenFlags::SQLITE_OPEN_URI
       | OpenFlags::SQLITE_OPEN_NO_MUTEX;
 
     rusqlite::Connection::open_with_flags(p, flags)?
   } else {
     rusqlite::Connection::open_in_memory()?
-  })?;
+  };
+  if let Some(key) = key {
+    apply_key(&conn, key)?;
+  }
+  let conn = sqlite3_extension_init(conn)?;
 
   // Load user-provided extensions.
   if let Some(extensions) = extensions {
-    for path in extensions {
-      unsafe { conn.load_extension(path, None)? }
-    }
+    load_extensions(&conn, &extensions)?;
   }
 
   apply_default_pragmas(&conn)?;
@@ -90,6 +158,80 @@ pub fn connect_sqlite(
   return Ok(conn);
 }
 
+/// Opens an existing database with `SQLITE_OPEN_READONLY`, i.e. any attempt to write will fail
+/// independent of the executed SQL.
+///
+/// Unlike [connect_sqlite], only pragmas that don't require write access are applied, which
+/// presumes the database has already been initialized by a read-write connection.
+#[allow(unsafe_code)]
+pub fn connect_sqlite_read_only(
+  path: PathBuf,
+  extensions: Option<Vec<LoadableExtension>>,
+  key: Option<&str>,
+) -> Result<rusqlite::Connection, Error> {
+  let status =
+    unsafe { rusqlite::ffi::sqlite3_auto_extension(Some(init_sqlean_and_vector_search)) };
+  if status != 0 {
+    return Err(Error::Other("Failed to load extensions".into()));
+  }
+
+  use rusqlite::OpenFlags;
+  let conn = rusqlite::Connection::open_with_flags(
+    path,
+    OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_URI | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+  )?;
+  if let Some(key) = key {
+    apply_key(&conn, key)?;
+  }
+  let conn = sqlite3_extension_init(conn)?;
+
+  if let Some(extensions) = extensions {
+    load_extensions(&conn, &extensions)?;
+  }
+
+  const CONFIG: &[&str] = &[
+    "PRAGMA busy_timeout   = 10000",
+    "PRAGMA foreign_keys   = ON",
+    "PRAGMA temp_store     = MEMORY",
+    "PRAGMA cache_size     = -16000",
+    "PRAGMA trusted_schema = OFF",
+  ];
+
+  for pragma in CONFIG {
+    let mut stmt = conn.prepare(pragma)?;
+    let mut rows = stmt.query([])?;
+    let _maybe_row = rows.next()?;
+  }
+
+  return Ok(conn);
+}
+
+/// Keys an SQLCipher database, i.e. needs to happen before any other access. The key is either a
+/// passphrase or a raw key given as "x'<64 hex digits>'".
+///
+/// Fails if SQLite wasn't built with SQLCipher, which would otherwise silently ignore the key, or
+/// if the key doesn't match, which SQLCipher would otherwise only report on first access.
+pub fn apply_key(conn: &rusqlite::Connection, key: &str) -> Result<(), Error> {
+  let cipher_version: Option<String> = conn
+    .query_row("PRAGMA cipher_version", (), |row| row.get(0))
+    .optional()?;
+  if cipher_version.is_none() {
+    return Err(Error::Key(
+      "encrypted databases require SQLite built with SQLCipher".to_string(),
+    ));
+  }
+
+  conn.pragma_update(None, "key", key)?;
+
+  if let Err(err) = conn.query_row("SELECT COUNT(*) FROM sqlite_schema", (), |_row| Ok(())) {
+    return Err(Error::Key(format!(
+      "failed to decrypt database, wrong key? ({err})"
+    )));
+  }
+
+  return Ok(());
+}
+
 pub fn sqlite3_extension_init(
   db: rusqlite::Connection,
 ) -> Result<rusqlite::Connection, rusqlite::Error> {
@@ -109,6 +251,12 @@ pub fn sqlite3_extension_init(
     FunctionFlags::SQLITE_DETERMINISTIC | FunctionFlags::SQLITE_INNOCUOUS,
     uuid::is_uuid_v7,
   )?;
+  db.create_scalar_function(
+    "is_ulid",
+    1,
+    FunctionFlags::SQLITE_DETERMINISTIC | FunctionFlags::SQLITE_INNOCUOUS,
+    uuid::is_ulid,
+  )?;
   db.create_scalar_function(
     "uuid_text",
     1,
@@ -143,7 +291,7 @@ pub fn sqlite3_extension_init(
       | FunctionFlags::SQLITE_INNOCUOUS,
     jsonschema::jsonschema_matches,
   )?;
-  // Match column against registered JSON schema by name, e.g. jsonschema(col, 'schema-name').
+  // Match column against registered JSON schema by name, e.g. jsonschema('schema-name', col).
   db.create_scalar_function(
     "jsonschema",
     2,
@@ -152,9 +300,11 @@ pub fn sqlite3_extension_init(
       | FunctionFlags::SQLITE_INNOCUOUS,
     jsonschema::jsonschema_by_name,
   )?;
+  // With extra arguments passed to the schema's custom validator, e.g.
+  // jsonschema('std.FileUpload', col, 'mime=image/*').
   db.create_scalar_function(
     "jsonschema",
-    3,
+    -1,
     FunctionFlags::SQLITE_UTF8
       | FunctionFlags::SQLITE_DETERMINISTIC
       | FunctionFlags::SQLITE_INNOCUOUS,
@@ -190,6 +340,19 @@ pub fn sqlite3_extension_init(
     validators::is_json,
   )?;
 
+  db.create_scalar_function(
+    "is_geometry",
+    1,
+    FunctionFlags::SQLITE_DETERMINISTIC | FunctionFlags::SQLITE_INNOCUOUS,
+    geometry::is_geometry,
+  )?;
+  db.create_scalar_function(
+    "geo_distance",
+    4,
+    FunctionFlags::SQLITE_DETERMINISTIC | FunctionFlags::SQLITE_INNOCUOUS,
+    geometry::geo_distance,
+  )?;
+
   db.create_scalar_function(
     "geoip_country",
     1,
@@ -197,6 +360,21 @@ pub fn sqlite3_extension_init(
     maxminddb::geoip_country,
   )?;
 
+  // Override the built-in LIKE to fold the case of non-ASCII characters.
+  for n_arg in [2, 3] {
+    db.create_scalar_function(
+      "like",
+      n_arg,
+      FunctionFlags::SQLITE_UTF8
+        | FunctionFlags::SQLITE_DETERMINISTIC
+        | FunctionFlags::SQLITE_INNOCUOUS,
+      like::like,
+    )?;
+  }
+
+  // Named ICU collations are created lazily on first use.
+  db.collation_needed(collation::collation_needed)?;
+
   return Ok(db);
 }
 
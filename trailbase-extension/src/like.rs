@@ -0,0 +1,144 @@
+use rusqlite::Error;
+use rusqlite::functions::Context;
+use rusqlite::types::ValueRef;
+use std::borrow::Cow;
+
+/// Unicode-aware replacement for SQLite's built-in `like(pattern, value[, escape])`.
+///
+/// NOTE: SQLite's LIKE only folds the case of ASCII characters, e.g. 'Ä' LIKE 'ä' is false. This
+/// mirrors the behavior of SQLite's ICU extension, which folds the case of all characters.
+pub(super) fn like(context: &Context) -> Result<Option<bool>, Error> {
+  #[cfg(debug_assertions)]
+  if context.len() != 2 && context.len() != 3 {
+    return Err(Error::InvalidParameterCount(context.len(), 3));
+  }
+
+  let (Some(pattern), Some(value)) = (to_text(context.get_raw(0)), to_text(context.get_raw(1)))
+  else {
+    return Ok(None);
+  };
+
+  let escape = if context.len() == 3 {
+    let Some(escape) = to_text(context.get_raw(2)) else {
+      return Ok(None);
+    };
+
+    let mut chars = escape.chars();
+    match (chars.next(), chars.next()) {
+      (Some(c), None) => Some(c),
+      _ => {
+        return Err(Error::UserFunctionError(
+          "ESCAPE expression must be a single character".into(),
+        ));
+      }
+    }
+  } else {
+    None
+  };
+
+  return Ok(Some(like_impl(&pattern, &value, escape)));
+}
+
+fn to_text(value: ValueRef<'_>) -> Option<Cow<'_, str>> {
+  return match value {
+    ValueRef::Null => None,
+    ValueRef::Integer(i) => Some(Cow::Owned(i.to_string())),
+    ValueRef::Real(f) => Some(Cow::Owned(f.to_string())),
+    ValueRef::Text(text) | ValueRef::Blob(text) => Some(String::from_utf8_lossy(text)),
+  };
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Token {
+  Any,
+  One,
+  Char(char),
+}
+
+#[inline]
+fn eq_ignore_case(a: char, b: char) -> bool {
+  return a == b || a.to_lowercase().eq(b.to_lowercase());
+}
+
+fn like_impl(pattern: &str, value: &str, escape: Option<char>) -> bool {
+  let pattern: Vec<Token> = {
+    let mut tokens = Vec::with_capacity(pattern.len());
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+      tokens.push(match c {
+        c if Some(c) == escape => match chars.next() {
+          Some(c) => Token::Char(c),
+          // Like SQLite, a trailing escape character never matches.
+          None => return false,
+        },
+        '%' => Token::Any,
+        '_' => Token::One,
+        c => Token::Char(c),
+      });
+    }
+    tokens
+  };
+  let value: Vec<char> = value.chars().collect();
+
+  // Greedy matching, which backtracks to the most recent wildcard on mismatch.
+  let (mut p, mut v) = (0, 0);
+  let mut backtrack: Option<(usize, usize)> = None;
+  while v < value.len() {
+    match pattern.get(p) {
+      Some(Token::Any) => {
+        p += 1;
+        backtrack = Some((p, v));
+        continue;
+      }
+      Some(Token::One) => {
+        p += 1;
+        v += 1;
+        continue;
+      }
+      Some(Token::Char(c)) if eq_ignore_case(*c, value[v]) => {
+        p += 1;
+        v += 1;
+        continue;
+      }
+      _ => {}
+    }
+
+    let Some((bp, bv)) = backtrack else {
+      return false;
+    };
+    p = bp;
+    v = bv + 1;
+    backtrack = Some((bp, bv + 1));
+  }
+
+  return pattern[p..].iter().all(|t| *t == Token::Any);
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_like_impl() {
+    assert!(like_impl("abc", "ABC", None));
+    assert!(like_impl("äpfel", "ÄPFEL", None));
+    assert!(like_impl("%öl", "Motoröl", None));
+    assert!(like_impl("a%c%e", "abcde", None));
+    assert!(like_impl("a_c", "aßc", None));
+    assert!(like_impl("%", "", None));
+    assert!(!like_impl("a_c", "ac", None));
+    assert!(!like_impl("a%d", "abc", None));
+
+    assert!(like_impl("100\\%", "100%", Some('\\')));
+    assert!(!like_impl("100\\%", "1000", Some('\\')));
+
+    let conn = crate::connect_sqlite(None, None).unwrap();
+    let matches =
+      |sql: &str| -> Option<bool> { conn.query_row(sql, (), |row| row.get(0)).unwrap() };
+
+    assert_eq!(Some(true), matches("SELECT 'Ärger' LIKE 'ä%'"));
+    assert_eq!(Some(true), matches("SELECT 'a_b' LIKE 'a!_b' ESCAPE '!'"));
+    assert_eq!(Some(false), matches("SELECT 'axb' LIKE 'a!_b' ESCAPE '!'"));
+    assert_eq!(None, matches("SELECT NULL LIKE 'a'"));
+  }
+}
@@ -20,6 +20,14 @@ pub(super) fn is_uuid_v7(context: &Context) -> Result<bool, Error> {
   return Ok(uuid.get_version_num() == 7);
 }
 
+/// Checks that argument is a valid binary ULID or null.
+///
+/// ULIDs share UUIDs' 128-bit binary layout, i.e. any 16 byte blob is a valid ULID. Null is
+/// explicitly allowed to enable use as CHECK constraint in nullable columns.
+pub(super) fn is_ulid(context: &Context) -> Result<bool, Error> {
+  return Ok(unpack_uuid_or_null(context).is_ok());
+}
+
 /// Creates a new UUIDv7 blob.
 pub(super) fn uuid_v7(_context: &Context) -> Result<Vec<u8>, Error> {
   return Ok(Uuid::now_v7().as_bytes().to_vec());
@@ -76,7 +84,8 @@ mod tests {
         CREATE TABLE test (
           id                           BLOB PRIMARY KEY NOT NULL DEFAULT (uuid_v7()),
           uuid                         BLOB CHECK(is_uuid(uuid)),
-          uuid_v7                      BLOB CHECK(is_uuid_v7(uuid_v7))
+          uuid_v7                      BLOB CHECK(is_uuid_v7(uuid_v7)),
+          ulid                         BLOB CHECK(is_ulid(ulid))
         ) STRICT;
       "#;
     conn.execute(create_table, ()).unwrap();
@@ -128,6 +137,24 @@ mod tests {
       assert_eq!(Uuid::from_slice(&row).unwrap(), uuid);
     }
 
+    {
+      assert!(
+        conn
+          .execute(
+            "INSERT INTO test (ulid) VALUES ($1)",
+            params!(Vec::<u8>::from([0, 1, 2]))
+          )
+          .is_err()
+      );
+
+      conn
+        .execute(
+          "INSERT INTO test (ulid) VALUES ($1)",
+          params!(Vec::<u8>::from([7; 16])),
+        )
+        .unwrap();
+    }
+
     {
       let row = conn
         .query_row(
@@ -19,8 +19,11 @@ globalThis.console.debug = _logStderr;
 export {
   HttpError,
   StatusCodes,
+  addColumnValidator,
   addCronCallback,
+  addCronJob,
   addPeriodicCallback,
+  addRecordHook,
   addRoute,
   execute,
   htmlHandler,
@@ -34,6 +37,7 @@ export {
 
 export type {
   CallbackType,
+  ColumnValidatorCallback,
   HeaderMapType,
   HtmlResponseType,
   JsonRequestType,
@@ -42,6 +46,10 @@ export type {
   Method,
   ParsedPath,
   PathParamsType,
+  RecordHookCallback,
+  RecordHookContext,
+  RecordHookEvent,
+  RecordType,
   RequestType,
   ResponseType,
   StringRequestType,
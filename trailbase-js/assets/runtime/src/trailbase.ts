@@ -13,6 +13,14 @@ declare global {
 
   function __dispatchCron(id: number): Promise<string | undefined>;
 
+  function __dispatchRecordHook(
+    id: number,
+    context: RecordHookContext,
+    record: RecordType | null,
+  ): Promise<RecordHookResult>;
+
+  function __dispatchColumnValidator(id: number, value: unknown): string | null;
+
   var rustyscript: {
     // eslint-disable-next-line @typescript-eslint/no-explicit-any
     functions: any;
@@ -652,11 +660,11 @@ export function addCronCallback(
   cb: () => void | Promise<void>,
 ) {
   const cronRegex =
-    /^(@(yearly|monthly|weekly|daily|hourly|))|((((\d+,)+\d+|(\d+(\/|-)\d+)|\d+|\*)\s*){6,7})$/;
+    /^(@(yearly|monthly|weekly|daily|hourly|))|((((\d+,)+\d+|(\d+(\/|-)\d+)|\d+|\*)\s*){5,7})$/;
 
   const matches = cronRegex.test(schedule);
   if (!matches) {
-    throw Error(`Not a valid 6/7-component cron schedule: ${schedule}`);
+    throw Error(`Not a valid 5/6/7-component cron schedule: ${schedule}`);
   }
 
   if (isolateId() === 0) {
@@ -666,6 +674,18 @@ export function addCronCallback(
   }
 }
 
+/// Installs a Cron job, e.g. `addCronJob("0 3 * * *", cb)` to run `cb` daily at 3am UTC.
+///
+/// Runs don't overlap, i.e. runs are skipped while a previous run is still in
+/// progress. Run history is available via the admin API.
+export function addCronJob(
+  schedule: string,
+  cb: () => void | Promise<void>,
+  name?: string,
+) {
+  addCronCallback(name ?? `cron(${schedule})`, schedule, cb);
+}
+
 async function dispatchCron(id: number): Promise<string | undefined> {
   const cb: (() => void | Promise<void>) | undefined = cronCallbacks.get(id);
   if (!cb) {
@@ -681,6 +701,106 @@ async function dispatchCron(id: number): Promise<string | undefined> {
 
 globalThis.__dispatchCron = dispatchCron;
 
+export type RecordType = { [key: string]: unknown };
+export type RecordHookEvent =
+  | "before_create"
+  | "after_create"
+  | "before_update"
+  | "after_update"
+  | "before_delete"
+  | "after_delete"
+  | "sync_conflict";
+export type RecordHookContext = {
+  event: RecordHookEvent;
+  api_name: string;
+  /// Id of the affected record. Unknown before creation.
+  record_id: string | null;
+  /// Base64 encoded UUIDv7 id of the user performing the operation, if any.
+  user_id: string | null;
+};
+export type RecordHookCallback = (
+  context: RecordHookContext,
+  record: RecordType | null,
+) => RecordType | void | Promise<RecordType | void>;
+type RecordHookResult = { record?: RecordType; error?: string };
+
+const recordHooks = new Map<number, RecordHookCallback>();
+
+/// Installs a hook invoked around writes to the given record API.
+///
+/// Before hooks can modify the record by returning it or veto the operation by
+/// throwing, e.g. an `HttpError`. After hooks run once the operation was
+/// committed and receive the stored record.
+export function addRecordHook(
+  event: RecordHookEvent,
+  apiName: string,
+  cb: RecordHookCallback,
+) {
+  if (isolateId() === 0) {
+    const id = rustyscript.functions.install_record_hook(event, apiName);
+    console.debug(`JS: Added record hook (id=${id}): ${event} "${apiName}"`);
+    recordHooks.set(id, cb);
+  }
+}
+
+async function dispatchRecordHook(
+  id: number,
+  context: RecordHookContext,
+  record: RecordType | null,
+): Promise<RecordHookResult> {
+  const cb: RecordHookCallback | undefined = recordHooks.get(id);
+  if (!cb) {
+    throw Error(`Missing record hook: ${id}`);
+  }
+
+  try {
+    return { record: (await cb(context, record)) ?? undefined };
+  } catch (err) {
+    return { error: err instanceof Error ? err.message : `${err}` };
+  }
+}
+
+globalThis.__dispatchRecordHook = dispatchRecordHook;
+
+export type ColumnValidatorCallback = (value: unknown) => string | void;
+
+const columnValidators = new Map<number, ColumnValidatorCallback>();
+
+/// Registers a column validator, which Record APIs can reference in their
+/// config as the "script" validator with `name` as its argument.
+///
+/// Validators are called with non-null values and return an error message to
+/// reject them. They must be synchronous, since they block the request.
+export function addColumnValidator(
+  name: string,
+  cb: ColumnValidatorCallback,
+) {
+  if (isolateId() === 0) {
+    const id = rustyscript.functions.install_column_validator(name);
+    console.debug(`JS: Added column validator (id=${id}): "${name}"`);
+    columnValidators.set(id, cb);
+  }
+}
+
+function dispatchColumnValidator(id: number, value: unknown): string | null {
+  const cb: ColumnValidatorCallback | undefined = columnValidators.get(id);
+  if (!cb) {
+    throw Error(`Missing column validator: ${id}`);
+  }
+
+  try {
+    const result: unknown = cb(value);
+    if (result instanceof Promise) {
+      return "async column validators are not supported";
+    }
+    return typeof result === "string" ? result : null;
+  } catch (err) {
+    return err instanceof Error ? err.message : `${err}`;
+  }
+}
+
+globalThis.__dispatchColumnValidator = dispatchColumnValidator;
+
 /// Installs a periodic callback in a single isolate and returns a cleanup function.
 export function addPeriodicCallback(
   milliseconds: number,
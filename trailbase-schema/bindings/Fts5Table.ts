@@ -0,0 +1,19 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Options of a "CREATE VIRTUAL TABLE ... USING fts5(...)" full-text search table.
+ */
+export type Fts5Table = { 
+/**
+ * Indexed columns.
+ */
+columns: Array<string>, 
+/**
+ * External content table, i.e. "content='<table>'". None for regular and contentless tables.
+ */
+content: string | null, 
+/**
+ * Column of the content table the FTS rowid maps to, i.e. "content_rowid='<column>'".
+ * Defaults to the content table's rowid.
+ */
+content_rowid: string | null, };
@@ -2,6 +2,25 @@
 import type { Check } from "./Check";
 import type { Column } from "./Column";
 import type { ForeignKey } from "./ForeignKey";
+import type { Fts5Table } from "./Fts5Table";
+import type { RtreeTable } from "./RtreeTable";
 import type { UniqueConstraint } from "./UniqueConstraint";
+import type { Vec0Table } from "./Vec0Table";
 
-export type Table = { name: string, strict: boolean, columns: Array<Column>, foreign_keys: Array<ForeignKey>, unique: Array<UniqueConstraint>, checks: Array<Check>, virtual_table: boolean, temporary: boolean, };
+export type Table = { name: string, strict: boolean, columns: Array<Column>, foreign_keys: Array<ForeignKey>, unique: Array<UniqueConstraint>, checks: Array<Check>, 
+/**
+ * Table-level, e.g. composite, primary key. Column-level primary keys are column options.
+ */
+primary_key?: UniqueConstraint, virtual_table: boolean, temporary: boolean, 
+/**
+ * Set for FTS5 full-text search virtual tables.
+ */
+fts5?: Fts5Table, 
+/**
+ * Set for sqlite-vec vector index virtual tables.
+ */
+vec0?: Vec0Table, 
+/**
+ * Set for R*Tree spatial index virtual tables.
+ */
+rtree?: RtreeTable, };
@@ -0,0 +1,13 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { TriggerEvent } from "./TriggerEvent";
+import type { TriggerTime } from "./TriggerTime";
+
+export type Trigger = { name: string, table_name: string, 
+/**
+ * Defaults to BEFORE if absent.
+ */
+time: TriggerTime | null, event: TriggerEvent, for_each_row: boolean, when: string | null, 
+/**
+ * Statements of the trigger's body, i.e. between BEGIN and END.
+ */
+body: Array<string>, temporary: boolean, };
@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type Vec0Column = { name: string, dimensions: number, 
+/**
+ * Declared distance metric, i.e. "distance_metric=<metric>". Defaults to L2.
+ */
+distance_metric: string | null, };
@@ -0,0 +1,12 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Vec0Column } from "./Vec0Column";
+
+/**
+ * Options of a "CREATE VIRTUAL TABLE ... USING vec0(...)" sqlite-vec vector index.
+ */
+export type Vec0Table = { 
+/**
+ * Float32 vector columns, e.g. "embedding float[384]". Other column kinds, e.g. auxiliary,
+ * metadata or partition key columns, are omitted.
+ */
+columns: Array<Vec0Column>, };
@@ -8,4 +8,12 @@ pub enum Error {
   BuiltinSchema,
   #[error("Missing name")]
   MissingName,
+  #[error("Invalid schema name '{0}', expected '<name>' or '<name>@v<version>'")]
+  InvalidSchemaName(String),
+  #[error("IO error: {0}")]
+  Io(Arc<std::io::Error>),
+  #[error("Invalid file constraint: '{0}'")]
+  InvalidFileConstraint(String),
+  #[error("Invalid schema file {0}: {1}")]
+  InvalidSchemaFile(String, Arc<serde_json::Error>),
 }
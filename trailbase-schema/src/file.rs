@@ -83,7 +83,172 @@ impl FileUpload {
   pub fn original_filename(&self) -> Option<&str> {
     self.filename.as_deref()
   }
+
+  pub fn mime_type(&self) -> Option<&str> {
+    self.mime_type.as_deref()
+  }
 }
 
 #[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct FileUploads(pub Vec<FileUpload>);
+
+/// Constraints on file columns passed as extra arguments to the column's CHECK, e.g.
+/// `jsonschema('std.FileUpload', col, 'mime=image/*', 'max_size=5MB')`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct FileConstraints {
+  /// Accepted mime types, e.g. "image/png" or "image/*". Any if empty.
+  pub mime_types: Vec<String>,
+  /// Maximum size of each file in bytes.
+  pub max_size: Option<u64>,
+  /// Maximum number of files. Only applies to `std.FileUploads`.
+  pub max_count: Option<usize>,
+}
+
+impl FileConstraints {
+  /// Parses "mime=<type>[,<type>]", "max_size=<size>[B|KB|MB|GB]" and "max_count=<n>" arguments.
+  /// For backwards compatibility, arguments without a key are treated as lists of mime types.
+  pub fn parse(args: &[&str]) -> Result<Self, Error> {
+    let invalid = |arg: &str| Error::InvalidFileConstraint(arg.to_string());
+
+    let mut constraints = Self::default();
+    for arg in args {
+      let (key, value) = arg.split_once('=').unwrap_or(("mime", arg));
+      match key.trim() {
+        "mime" => constraints.mime_types.extend(
+          value
+            .split(',')
+            .map(|m| m.trim())
+            .filter(|m| !m.is_empty())
+            .map(|m| m.to_string()),
+        ),
+        "max_size" => {
+          constraints.max_size = Some(parse_size(value.trim()).ok_or_else(|| invalid(arg))?);
+        }
+        "max_count" => {
+          constraints.max_count = Some(value.trim().parse().map_err(|_| invalid(arg))?);
+        }
+        _ => return Err(invalid(arg)),
+      };
+    }
+
+    return Ok(constraints);
+  }
+
+  pub fn is_empty(&self) -> bool {
+    return self.mime_types.is_empty() && self.max_size.is_none() && self.max_count.is_none();
+  }
+
+  /// Whether the inferred mime type is accepted. Supports wildcards like "image/*".
+  pub fn accepts_mime_type(&self, mime_type: Option<&str>) -> bool {
+    if self.mime_types.is_empty() {
+      return true;
+    }
+    let Some(mime_type) = mime_type else {
+      return false;
+    };
+
+    return self.mime_types.iter().any(|accepted| {
+      return match accepted.as_str() {
+        "*" | "*/*" => true,
+        accepted => match accepted.strip_suffix("/*") {
+          Some(prefix) => mime_type
+            .split_once('/')
+            .is_some_and(|(kind, _)| kind == prefix),
+          None => accepted == mime_type,
+        },
+      };
+    });
+  }
+
+  /// Checks a single file of the given size, returning a descriptive error otherwise.
+  pub fn check_file(&self, file: &FileUpload, size: u64) -> Result<(), &'static str> {
+    if !self.accepts_mime_type(file.mime_type()) {
+      return Err("File type not accepted by the column's 'mime' constraint");
+    }
+
+    if self.max_size.is_some_and(|max_size| size > max_size) {
+      return Err("File exceeds the column's 'max_size' constraint");
+    }
+
+    return Ok(());
+  }
+
+  /// Checks the number of files, returning a descriptive error otherwise.
+  pub fn check_count(&self, count: usize) -> Result<(), &'static str> {
+    if self.max_count.is_some_and(|max_count| count > max_count) {
+      return Err("Number of files exceeds the column's 'max_count' constraint");
+    }
+    return Ok(());
+  }
+}
+
+/// Parses sizes like "512", "512B", "64KB", "5MB" or "1GB" using binary units, i.e. 1KB = 1024B.
+fn parse_size(value: &str) -> Option<u64> {
+  let upper = value.to_ascii_uppercase();
+  let (number, multiplier) = if let Some(n) = upper.strip_suffix("GB") {
+    (n, 1024 * 1024 * 1024)
+  } else if let Some(n) = upper.strip_suffix("MB") {
+    (n, 1024 * 1024)
+  } else if let Some(n) = upper.strip_suffix("KB") {
+    (n, 1024)
+  } else if let Some(n) = upper.strip_suffix('B') {
+    (n, 1)
+  } else {
+    (upper.as_str(), 1)
+  };
+
+  return number.trim().parse::<u64>().ok()?.checked_mul(multiplier);
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_file_constraints() {
+    assert!(FileConstraints::parse(&[]).unwrap().is_empty());
+
+    let constraints = FileConstraints::parse(&[
+      "mime=image/*,application/pdf",
+      "max_size=5MB",
+      "max_count=3",
+    ])
+    .unwrap();
+    assert_eq!(
+      FileConstraints {
+        mime_types: vec!["image/*".to_string(), "application/pdf".to_string()],
+        max_size: Some(5 * 1024 * 1024),
+        max_count: Some(3),
+      },
+      constraints
+    );
+
+    assert!(constraints.accepts_mime_type(Some("image/png")));
+    assert!(constraints.accepts_mime_type(Some("application/pdf")));
+    assert!(!constraints.accepts_mime_type(Some("imagefoo/png")));
+    assert!(!constraints.accepts_mime_type(Some("text/plain")));
+    assert!(!constraints.accepts_mime_type(None));
+
+    let file = FileUpload::new(Uuid::now_v7(), None, None, Some("image/png".to_string()));
+    assert!(constraints.check_file(&file, 1024).is_ok());
+    assert!(constraints.check_file(&file, 6 * 1024 * 1024).is_err());
+    assert!(constraints.check_count(3).is_ok());
+    assert!(constraints.check_count(4).is_err());
+
+    // Legacy, unkeyed mime type lists.
+    assert_eq!(
+      vec!["image/jpeg".to_string(), "image/png".to_string()],
+      FileConstraints::parse(&["image/jpeg, image/png"])
+        .unwrap()
+        .mime_types
+    );
+
+    assert!(FileConstraints::parse(&["max_size=5XB"]).is_err());
+    assert!(FileConstraints::parse(&["max_count=-1"]).is_err());
+    assert!(FileConstraints::parse(&["unknown=1"]).is_err());
+
+    assert_eq!(Some(512), parse_size("512"));
+    assert_eq!(Some(64 * 1024), parse_size("64kb"));
+    assert_eq!(Some(1024 * 1024 * 1024), parse_size("1GB"));
+  }
+}
@@ -65,7 +65,7 @@ pub fn build_json_schema_expanded(
           if let Some(json_metadata) = extract_json_metadata(&ColumnOption::Check(check.clone()))? {
             let new_def_name = &col.name;
             match json_metadata {
-              JsonColumnMetadata::SchemaName(name) => {
+              JsonColumnMetadata::SchemaName(name, _) => {
                 let Some(schema) = crate::registry::get_schema(&name) else {
                   return Err(JsonSchemaError::NotFound(name.to_string()));
                 };
@@ -6,8 +6,9 @@ pub mod error;
 pub mod file;
 pub mod json_schema;
 pub mod metadata;
+pub mod migration;
 pub mod registry;
 pub mod sqlite;
 
 pub use error::Error;
-pub use file::{FileUpload, FileUploadInput, FileUploads};
+pub use file::{FileConstraints, FileUpload, FileUploadInput, FileUploads};
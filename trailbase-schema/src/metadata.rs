@@ -6,49 +6,65 @@ use std::collections::HashMap;
 use std::sync::Arc;
 use thiserror::Error;
 
-use crate::sqlite::{Column, ColumnDataType, ColumnOption, Table, View};
+use crate::file::FileConstraints;
+use crate::sqlite::{Column, ColumnDataType, ColumnOption, Table, Trigger, Vec0Column, View};
 
 // TODO: Can we merge this with crate::sqlite::SchemaError?
 #[derive(Debug, Clone, Error)]
 pub enum JsonSchemaError {
   #[error("Schema compile error: {0}")]
   SchemaCompile(String),
-  #[error("Validation error")]
-  Validation,
+  #[error("Validation error: {0:?}")]
+  Validation(Vec<JsonSchemaViolation>),
   #[error("Schema not found: {0}")]
   NotFound(String),
   #[error("Json serialization error: {0}")]
   JsonSerialization(Arc<serde_json::Error>),
+  #[error("Invalid arguments: {0}")]
+  InvalidArguments(String),
+}
+
+/// A single JSON schema violation within a validated value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonSchemaViolation {
+  /// JSON pointer to the offending value, e.g. "/address/zip". Empty for the root.
+  pub path: String,
+  pub message: String,
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum JsonColumnMetadata {
-  SchemaName(String),
+  /// Registered schema's name and, for file columns, the constraints passed as extra arguments.
+  SchemaName(String, FileConstraints),
   Pattern(serde_json::Value),
 }
 
 impl JsonColumnMetadata {
+  /// Validates `value` against the schema, collecting all violations rather than just the first.
   pub fn validate(&self, value: &serde_json::Value) -> Result<(), JsonSchemaError> {
-    match self {
-      Self::SchemaName(name) => {
+    let schema = match self {
+      Self::SchemaName(name, _) => {
         let Some(schema) = crate::registry::get_compiled_schema(name) else {
           return Err(JsonSchemaError::NotFound(name.to_string()));
         };
         schema
-          .validate(value)
-          .map_err(|_err| JsonSchemaError::Validation)?;
-        return Ok(());
-      }
-      Self::Pattern(pattern) => {
-        let schema =
-          Validator::new(pattern).map_err(|err| JsonSchemaError::SchemaCompile(err.to_string()))?;
-        if !schema.is_valid(value) {
-          Err(JsonSchemaError::Validation)
-        } else {
-          Ok(())
-        }
       }
+      Self::Pattern(pattern) => Arc::new(
+        Validator::new(pattern).map_err(|err| JsonSchemaError::SchemaCompile(err.to_string()))?,
+      ),
+    };
+
+    let violations: Vec<JsonSchemaViolation> = schema
+      .iter_errors(value)
+      .map(|err| JsonSchemaViolation {
+        path: err.instance_path.to_string(),
+        message: err.to_string(),
+      })
+      .collect();
+    if !violations.is_empty() {
+      return Err(JsonSchemaError::Validation(violations));
     }
+    return Ok(());
   }
 }
 
@@ -97,15 +113,30 @@ impl JsonMetadata {
 pub struct TableMetadata {
   pub schema: Table,
 
-  /// If and which column on this table qualifies as a record PK column, i.e. integer or UUIDv7.
+  /// If and which column on this table qualifies as a record PK column, i.e. integer, UUID or ULID.
   pub record_pk_column: Option<usize>,
+  /// Record PK columns, i.e. either the single `record_pk_column` or the parts of a composite
+  /// primary key, each of which is an integer, UUID or ULID column.
+  pub record_pk_columns: Vec<usize>,
+  /// Whether all record PK columns are time-sortable, i.e. not random UUIDs.
+  pub record_pk_sortable: bool,
   /// If and which columns on this table reference _user(id).
   pub user_id_columns: Vec<usize>,
+  /// Generated columns, i.e. `GENERATED ALWAYS AS (...)`, which cannot be written.
+  pub generated_columns: Vec<usize>,
   /// Metadata for CHECK(json_schema()) columns.
   pub json_metadata: JsonMetadata,
+  /// FTS5 full-text search index covering this table, if any.
+  pub fts: Option<FtsIndex>,
+  /// sqlite-vec vector index holding embeddings for this table's records, if any.
+  pub vector_index: Option<VectorIndex>,
+  /// Latitude/longitude column pairs, which can be filtered geographically.
+  pub geo_points: Vec<GeoPoint>,
+  /// Triggers defined on this table. Empty unless populated by the caller, since triggers are
+  /// separate schema objects.
+  pub triggers: Vec<Trigger>,
 
   name_to_index: HashMap<String, usize>,
-  // TODO: Add triggers once sqlparser supports a sqlite "CREATE TRIGGER" statements.
 }
 
 impl TableMetadata {
@@ -122,16 +153,39 @@ impl TableMetadata {
         .map(|(index, col)| (col.name.clone(), index)),
     );
 
-    let record_pk_column = find_record_pk_column_index(&table.columns, tables);
+    let record_pk_columns = find_record_pk_column_indexes(&table, tables);
+    let record_pk_column = match record_pk_columns[..] {
+      [index] => Some(index),
+      _ => None,
+    };
+    let record_pk_sortable = record_pk_columns.iter().all(|index| {
+      return record_pk_kind(&table.columns[*index], tables).is_some_and(|kind| kind.is_sortable());
+    });
     let user_id_columns = find_user_id_foreign_key_columns(&table.columns, user_table_name);
+    let generated_columns = table
+      .columns
+      .iter()
+      .enumerate()
+      .filter_map(|(index, col)| col.is_generated().then_some(index))
+      .collect();
     let json_metadata = JsonMetadata::from_table(&table);
+    let fts = find_fts_index(&table, tables);
+    let vector_index = find_vector_index(&table, tables);
+    let geo_points = find_geo_points(&table, tables);
 
     return TableMetadata {
       schema: table,
       name_to_index,
       record_pk_column,
+      record_pk_columns,
+      record_pk_sortable,
       user_id_columns,
+      generated_columns,
       json_metadata,
+      fts,
+      vector_index,
+      geo_points,
+      triggers: vec![],
     };
   }
 
@@ -150,6 +204,186 @@ impl TableMetadata {
     let index = self.column_index_by_name(key)?;
     return Some((index, &self.schema.columns[index]));
   }
+
+  /// Sets of columns with a uniqueness constraint, i.e. primary key and UNIQUE columns as well as
+  /// table-level UNIQUE constraints. These are valid conflict targets for UPSERTs.
+  pub fn unique_keys(&self) -> Vec<Vec<String>> {
+    let columns = self.schema.columns.iter().filter_map(|col| {
+      if col
+        .options
+        .iter()
+        .any(|opt| matches!(opt, ColumnOption::Unique { .. }))
+      {
+        return Some(vec![col.name.clone()]);
+      }
+      return None;
+    });
+
+    let constraints = self.schema.unique.iter().map(|u| u.columns.clone());
+
+    return columns.chain(constraints).collect();
+  }
+}
+
+/// FTS5 virtual table indexing a table's text, i.e. the table itself or an FTS5 table using it as
+/// external content.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FtsIndex {
+  /// Name of the FTS5 virtual table.
+  pub table_name: String,
+  /// Indexed columns.
+  pub columns: Vec<String>,
+  /// Column of the indexed table matching the FTS rowid or None for the indexed table's rowid.
+  pub rowid_column: Option<String>,
+}
+
+fn find_fts_index(table: &Table, tables: &[Table]) -> Option<FtsIndex> {
+  if let Some(ref fts5) = table.fts5 {
+    return Some(FtsIndex {
+      table_name: table.name.clone(),
+      columns: fts5.columns.clone(),
+      rowid_column: None,
+    });
+  }
+
+  return tables.iter().find_map(|t| {
+    let fts5 = t.fts5.as_ref()?;
+    if fts5.content.as_deref() != Some(table.name.as_str()) {
+      return None;
+    }
+    return Some(FtsIndex {
+      table_name: t.name.clone(),
+      columns: fts5.columns.clone(),
+      rowid_column: fts5.content_rowid.clone(),
+    });
+  });
+}
+
+/// vec0 virtual table holding embeddings of another table's records.
+///
+/// Since vec0 tables cannot reference a content table, they're associated by convention: a vec0
+/// table named "<table>_vec" indexes "<table>" and its rowids match the indexed table's rowids.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VectorIndex {
+  /// Name of the vec0 virtual table.
+  pub table_name: String,
+  /// Float32 vector columns.
+  pub columns: Vec<Vec0Column>,
+}
+
+fn find_vector_index(table: &Table, tables: &[Table]) -> Option<VectorIndex> {
+  if table.virtual_table {
+    return None;
+  }
+
+  let name = format!("{}_vec", table.name);
+  return tables.iter().find_map(|t| {
+    let vec0 = t.vec0.as_ref()?;
+    if t.name != name || vec0.columns.is_empty() {
+      return None;
+    }
+    return Some(VectorIndex {
+      table_name: t.name.clone(),
+      columns: vec0.columns.clone(),
+    });
+  });
+}
+
+/// Pair of latitude and longitude columns in WGS84 degrees, e.g. "location_lat" and
+/// "location_lng", addressable as "location".
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeoPoint {
+  pub name: String,
+  pub lat_column: String,
+  pub lng_column: String,
+  /// Optional R*Tree index to speed up bounding box lookups.
+  pub rtree: Option<RtreeIndex>,
+}
+
+/// Two-dimensional R*Tree virtual table indexing a table's geo point, whose ids match the indexed
+/// table's rowids and whose first and second dimension are latitude and longitude, respectively.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RtreeIndex {
+  /// Name of the R*Tree virtual table.
+  pub table_name: String,
+  pub id_column: String,
+  /// Lower and upper bound columns.
+  pub lat_columns: (String, String),
+  pub lng_columns: (String, String),
+}
+
+/// Latitude and longitude column name suffixes forming a geo point. Unprefixed pairs, e.g. "lat"
+/// and "lng", are named "location".
+const GEO_POINT_SUFFIXES: [(&str, &str); 4] = [
+  ("lat", "lng"),
+  ("lat", "lon"),
+  ("lat", "long"),
+  ("latitude", "longitude"),
+];
+
+/// Finds geo points and their R*Tree indexes, which are associated by convention: an R*Tree table
+/// named "<table>_<point>_rtree" or, for tables with a single point, "<table>_rtree".
+fn find_geo_points(table: &Table, tables: &[Table]) -> Vec<GeoPoint> {
+  let is_real = |column: &Column| {
+    return matches!(
+      column.data_type,
+      ColumnDataType::Real
+        | ColumnDataType::Double
+        | ColumnDataType::DoublePrecision
+        | ColumnDataType::Float
+        | ColumnDataType::Numeric
+        | ColumnDataType::Decimal
+    );
+  };
+
+  let mut points: Vec<GeoPoint> = vec![];
+  for lat_column in table.columns.iter().filter(|c| is_real(c)) {
+    for (lat, lng) in GEO_POINT_SUFFIXES {
+      let (name, lng_name) = if lat_column.name == lat {
+        ("location", lng.to_string())
+      } else if let Some(prefix) = lat_column.name.strip_suffix(&format!("_{lat}")) {
+        (prefix, format!("{prefix}_{lng}"))
+      } else {
+        continue;
+      };
+
+      if table
+        .columns
+        .iter()
+        .any(|c| c.name == lng_name && is_real(c))
+      {
+        points.push(GeoPoint {
+          name: name.to_string(),
+          lat_column: lat_column.name.clone(),
+          lng_column: lng_name,
+          rtree: None,
+        });
+        break;
+      }
+    }
+  }
+
+  let single = points.len() == 1;
+  for point in &mut points {
+    let name = format!("{}_{}_rtree", table.name, point.name);
+    point.rtree = tables.iter().find_map(|t| {
+      let rtree = t.rtree.as_ref()?;
+      if t.name != name && !(single && t.name == format!("{}_rtree", table.name)) {
+        return None;
+      }
+      let [lat_columns, lng_columns] = rtree.dimensions.as_slice() else {
+        return None;
+      };
+      return Some(RtreeIndex {
+        table_name: t.name.clone(),
+        id_column: rtree.id.clone(),
+        lat_columns: lat_columns.clone(),
+        lng_columns: lng_columns.clone(),
+      });
+    });
+  }
+
+  return points;
 }
 
 /// A data class describing a sqlite View and future, additional meta data useful for TrailBase.
@@ -159,6 +393,7 @@ pub struct ViewMetadata {
 
   name_to_index: HashMap<String, usize>,
   record_pk_column: Option<usize>,
+  record_pk_sortable: bool,
   json_metadata: Option<JsonMetadata>,
 }
 
@@ -183,12 +418,19 @@ impl ViewMetadata {
       .columns
       .as_ref()
       .and_then(|c| find_record_pk_column_index(c, tables));
+    let record_pk_sortable = match (record_pk_column, &view.columns) {
+      (Some(index), Some(columns)) => {
+        record_pk_kind(&columns[index], tables).is_some_and(|kind| kind.is_sortable())
+      }
+      _ => true,
+    };
     let json_metadata = JsonMetadata::from_view(&view);
 
     return ViewMetadata {
       schema: view,
       name_to_index,
       record_pk_column,
+      record_pk_sortable,
       json_metadata,
     };
   }
@@ -213,6 +455,12 @@ impl ViewMetadata {
 
 pub trait TableOrViewMetadata {
   fn record_pk_column(&self) -> Option<(usize, &Column)>;
+  /// Record PK columns, i.e. multiple for composite primary keys.
+  fn record_pk_columns(&self) -> Vec<(usize, &Column)> {
+    return self.record_pk_column().into_iter().collect();
+  }
+  /// Whether the record PK is time-sortable and thus suitable for cursors, see [`RecordPkKind`].
+  fn record_pk_sortable(&self) -> bool;
   fn json_metadata(&self) -> Option<&JsonMetadata>;
   fn columns(&self) -> Option<&[Column]>;
 }
@@ -230,6 +478,18 @@ impl TableOrViewMetadata for TableMetadata {
     let index = self.record_pk_column?;
     return self.schema.columns.get(index).map(|c| (index, c));
   }
+
+  fn record_pk_sortable(&self) -> bool {
+    return self.record_pk_sortable;
+  }
+
+  fn record_pk_columns(&self) -> Vec<(usize, &Column)> {
+    return self
+      .record_pk_columns
+      .iter()
+      .filter_map(|index| self.schema.columns.get(*index).map(|c| (*index, c)))
+      .collect();
+  }
 }
 
 impl TableOrViewMetadata for ViewMetadata {
@@ -248,6 +508,10 @@ impl TableOrViewMetadata for ViewMetadata {
     let index = self.record_pk_column?;
     return columns.get(index).map(|c| (index, c));
   }
+
+  fn record_pk_sortable(&self) -> bool {
+    return self.record_pk_sortable;
+  }
 }
 
 fn build_json_metadata(col: &Column) -> Option<JsonColumnMetadata> {
@@ -274,9 +538,11 @@ pub fn extract_json_metadata(
   };
 
   lazy_static! {
-    static ref SCHEMA_RE: Regex =
-      Regex::new(r#"(?smR)jsonschema\s*\(\s*[\['"](?<name>.*)[\]'"]\s*,.+?\)"#)
-        .expect("infallible");
+    static ref SCHEMA_RE: Regex = Regex::new(
+      r#"(?smR)jsonschema\s*\(\s*[\['"](?<name>[^\]'"]*)[\]'"]\s*,\s*[^,)]+?(?<args>(?:\s*,\s*'[^']*')*)\s*\)"#
+    )
+    .expect("infallible");
+    static ref ARG_RE: Regex = Regex::new(r#"'(?<arg>[^']*)'"#).expect("infallible");
     static ref MATCHES_RE: Regex =
       Regex::new(r"(?smR)jsonschema_matches\s*\(.+?(?<pattern>\{.*\}).+?\)").expect("infallible");
   }
@@ -293,7 +559,22 @@ pub fn extract_json_metadata(
       )));
     };
 
-    return Ok(Some(JsonColumnMetadata::SchemaName(name.to_string())));
+    let constraints = match name {
+      "std.FileUpload" | "std.FileUploads" => {
+        let args: Vec<&str> = ARG_RE
+          .captures_iter(&cap["args"])
+          .filter_map(|c| c.name("arg").map(|m| m.as_str()))
+          .collect();
+        FileConstraints::parse(&args)
+          .map_err(|err| JsonSchemaError::InvalidArguments(err.to_string()))?
+      }
+      _ => FileConstraints::default(),
+    };
+
+    return Ok(Some(JsonColumnMetadata::SchemaName(
+      name.to_string(),
+      constraints,
+    )));
   }
 
   if let Some(cap) = MATCHES_RE.captures(check) {
@@ -312,10 +593,10 @@ pub fn find_file_column_indexes(json_column_metadata: &[Option<JsonColumnMetadat
   for (index, column) in json_column_metadata.iter().enumerate() {
     if let Some(metadata) = column {
       match metadata {
-        JsonColumnMetadata::SchemaName(name) if name == "std.FileUpload" => {
+        JsonColumnMetadata::SchemaName(name, _) if name == "std.FileUpload" => {
           indexes.push(index);
         }
-        JsonColumnMetadata::SchemaName(name) if name == "std.FileUploads" => {
+        JsonColumnMetadata::SchemaName(name, _) if name == "std.FileUploads" => {
           indexes.push(index);
         }
         _ => {}
@@ -348,6 +629,88 @@ pub fn find_user_id_foreign_key_columns(columns: &[Column], user_table_name: &st
   return indexes;
 }
 
+/// Returns the number of dimensions if the given column holds sqlite-vec float32 vectors, i.e.
+/// is constrained by `CHECK(vec_length(col) = N)`.
+pub fn vector_column_dimensions(column: &Column) -> Option<usize> {
+  lazy_static! {
+    static ref VEC_LENGTH_RE: Regex = Regex::new(
+      r#"(?i)vec_length\s*\(\s*["'`\[]?(?<name>\w+)["'`\]]?\s*\)\s*==?\s*(?<dimensions>\d+)"#
+    )
+    .expect("infallible");
+  }
+
+  if !matches!(column.data_type, ColumnDataType::Blob | ColumnDataType::Any) {
+    return None;
+  }
+
+  return column.options.iter().find_map(|opt| {
+    let ColumnOption::Check(expr) = opt else {
+      return None;
+    };
+    let captures = VEC_LENGTH_RE.captures(expr)?;
+    if captures["name"] != column.name {
+      return None;
+    }
+    return captures["dimensions"].parse().ok();
+  });
+}
+
+/// Returns true if the given column holds WKB geometries, i.e. is constrained by
+/// `CHECK(is_geometry(col))`.
+pub fn is_geometry_column(column: &Column) -> bool {
+  lazy_static! {
+    static ref IS_GEOMETRY_RE: Regex =
+      Regex::new(r#"(?i)is_geometry\s*\(\s*["'`\[]?(?<name>\w+)["'`\]]?\s*\)"#)
+        .expect("infallible");
+  }
+
+  if !matches!(column.data_type, ColumnDataType::Blob | ColumnDataType::Any) {
+    return false;
+  }
+
+  return column.options.iter().any(|opt| {
+    let ColumnOption::Check(expr) = opt else {
+      return false;
+    };
+    return IS_GEOMETRY_RE
+      .captures_iter(expr)
+      .any(|captures| captures["name"] == column.name);
+  });
+}
+
+/// Columns holding a record's location.
+#[derive(Clone, Debug, PartialEq)]
+pub enum GeometryColumns {
+  /// Index of a WKB geometry column.
+  Wkb(usize),
+  /// Indexes of a pair of numeric latitude and longitude columns.
+  LatLng { lat: usize, lng: usize },
+}
+
+/// Finds the columns holding a record's location. WKB geometry columns take precedence over
+/// numeric lat/lng pairs, which are recognized by name, i.e. "lat" or "latitude" and "lng", "lon"
+/// or "longitude".
+pub fn find_geometry_columns(columns: &[Column]) -> Option<GeometryColumns> {
+  if let Some(index) = columns.iter().position(is_geometry_column) {
+    return Some(GeometryColumns::Wkb(index));
+  }
+
+  let find = |names: &[&str]| {
+    return columns.iter().position(|c| {
+      matches!(c.data_type, ColumnDataType::Real | ColumnDataType::Integer)
+        && names.contains(&c.name.to_lowercase().as_str())
+    });
+  };
+
+  return match (
+    find(&["lat", "latitude"]),
+    find(&["lng", "lon", "longitude"]),
+  ) {
+    (Some(lat), Some(lng)) => Some(GeometryColumns::LatLng { lat, lng }),
+    _ => None,
+  };
+}
+
 pub(crate) fn find_pk_column_index(columns: &[Column]) -> Option<usize> {
   return columns.iter().position(|col| {
     for opt in &col.options {
@@ -359,26 +722,72 @@ pub(crate) fn find_pk_column_index(columns: &[Column]) -> Option<usize> {
   });
 }
 
-/// Finds suitable Integer or UUIDv7 primary key columns, if present.
+/// Finds suitable Integer, UUID or ULID primary key columns, if present.
 ///
-/// Cursors require certain properties like a stable, time-sortable primary key.
+/// Cursors additionally require a time-sortable primary key, see [`RecordPkKind::is_sortable`].
 fn find_record_pk_column_index(columns: &[Column], tables: &[Table]) -> Option<usize> {
   let index = find_pk_column_index(columns)?;
-  let column = &columns[index];
+  return is_record_pk_column(&columns[index], tables).then_some(index);
+}
+
+/// Finds the record primary key columns, i.e. a single column-level primary key or the parts of a
+/// table-level, e.g. composite, primary key. Each part has to qualify as a record PK column.
+fn find_record_pk_column_indexes(table: &Table, tables: &[Table]) -> Vec<usize> {
+  if let Some(index) = find_record_pk_column_index(&table.columns, tables) {
+    return vec![index];
+  }
+
+  let Some(ref primary_key) = table.primary_key else {
+    return vec![];
+  };
+
+  return primary_key
+    .columns
+    .iter()
+    .map(|column_name| {
+      let index = table.columns.iter().position(|c| c.name == *column_name)?;
+      return is_record_pk_column(&table.columns[index], tables).then_some(index);
+    })
+    .collect::<Option<Vec<_>>>()
+    .unwrap_or_default();
+}
+
+/// Kinds of columns qualifying as record PK columns.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecordPkKind {
+  Integer,
+  /// `CHECK(is_uuid_v7(col))`.
+  UuidV7,
+  /// `CHECK(is_ulid(col))`.
+  Ulid,
+  /// `CHECK(is_uuid(col))`, e.g. random UUIDv4s.
+  Uuid,
+}
+
+impl RecordPkKind {
+  /// Whether keys are time-sortable, i.e. roughly monotonic. Otherwise, newly inserted records
+  /// can sort anywhere, which keeps cursors from reliably picking up where they left off.
+  pub fn is_sortable(&self) -> bool {
+    return !matches!(self, Self::Uuid);
+  }
+}
+
+/// Whether the column is an Integer, UUID or ULID column, i.e. qualifies as a record PK column.
+fn is_record_pk_column(column: &Column, tables: &[Table]) -> bool {
+  return record_pk_kind(column, tables).is_some();
+}
 
+/// Returns the kind of record PK column, if the column qualifies as one.
+pub fn record_pk_kind(column: &Column, tables: &[Table]) -> Option<RecordPkKind> {
   if column.data_type == ColumnDataType::Integer {
     // TODO: We should detect the "integer pk" desc case and at least warn:
     // https://www.sqlite.org/lang_createtable.html#rowid.
-    return Some(index);
+    return Some(RecordPkKind::Integer);
   }
 
   for opts in &column.options {
-    lazy_static! {
-      static ref UUID_V7_RE: Regex = Regex::new(r"^is_uuid_v7\s*\(").expect("infallible");
-    }
-
     match &opts {
-      // Check if the referenced column is a uuidv7 column.
+      // Check if the referenced column is a UUID/ULID column.
       ColumnOption::ForeignKey {
         foreign_table,
         referred_columns,
@@ -394,16 +803,21 @@ fn find_record_pk_column_index(columns: &[Column], tables: &[Table]) -> Option<u
         }
         let referred_column = &referred_columns[0];
 
-        let col = referred_table
+        let Some(col) = referred_table
           .columns
           .iter()
-          .find(|c| c.name == *referred_column)?;
+          .find(|c| c.name == *referred_column)
+        else {
+          return None;
+        };
 
         let mut is_pk = false;
         for opt in &col.options {
           match opt {
-            ColumnOption::Check(expr) if UUID_V7_RE.is_match(expr) => {
-              return Some(index);
+            ColumnOption::Check(expr) => {
+              if let Some(kind) = check_record_pk_kind(expr) {
+                return Some(kind);
+              }
             }
             ColumnOption::Unique { is_primary, .. } if *is_primary => {
               is_pk = true;
@@ -412,14 +826,13 @@ fn find_record_pk_column_index(columns: &[Column], tables: &[Table]) -> Option<u
           }
         }
 
-        if is_pk && col.data_type == ColumnDataType::Integer {
-          return Some(index);
-        }
-
-        return None;
+        return (is_pk && col.data_type == ColumnDataType::Integer)
+          .then_some(RecordPkKind::Integer);
       }
-      ColumnOption::Check(expr) if UUID_V7_RE.is_match(expr) => {
-        return Some(index);
+      ColumnOption::Check(expr) => {
+        if let Some(kind) = check_record_pk_kind(expr) {
+          return Some(kind);
+        }
       }
       _ => {}
     }
@@ -428,6 +841,23 @@ fn find_record_pk_column_index(columns: &[Column], tables: &[Table]) -> Option<u
   return None;
 }
 
+fn check_record_pk_kind(expr: &str) -> Option<RecordPkKind> {
+  lazy_static! {
+    static ref UUID_V7_RE: Regex = Regex::new(r"^is_uuid_v7\s*\(").expect("infallible");
+    static ref UUID_RE: Regex = Regex::new(r"^is_uuid\s*\(").expect("infallible");
+    static ref ULID_RE: Regex = Regex::new(r"^is_ulid\s*\(").expect("infallible");
+  }
+
+  if UUID_V7_RE.is_match(expr) {
+    return Some(RecordPkKind::UuidV7);
+  } else if ULID_RE.is_match(expr) {
+    return Some(RecordPkKind::Ulid);
+  } else if UUID_RE.is_match(expr) {
+    return Some(RecordPkKind::Uuid);
+  }
+  return None;
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
@@ -505,4 +935,378 @@ mod tests {
       assert_eq!(columns[uuidv7_col.0].name, "id");
     }
   }
+
+  #[test]
+  fn test_extract_file_constraints() {
+    crate::registry::try_init_schemas();
+
+    let table_sql = r#"
+      CREATE TABLE files (
+          id       INTEGER PRIMARY KEY,
+          plain    TEXT CHECK(jsonschema('std.FileUpload', plain)),
+          legacy   TEXT CHECK(jsonschema('std.FileUpload', legacy, 'image/jpeg, image/png')),
+          avatar   TEXT CHECK(jsonschema('std.FileUpload', avatar, 'mime=image/*', 'max_size=5MB')),
+          docs     TEXT CHECK(jsonschema('std.FileUploads', docs, 'mime=application/pdf', 'max_count=3'))
+      ) STRICT;"#;
+    let table: Table = sqlite3_parse_into_statement(table_sql)
+      .unwrap()
+      .unwrap()
+      .try_into()
+      .unwrap();
+
+    let metadata: Vec<Option<JsonColumnMetadata>> =
+      table.columns.iter().map(build_json_metadata).collect();
+
+    assert_eq!(None, metadata[0]);
+    assert_eq!(
+      Some(JsonColumnMetadata::SchemaName(
+        "std.FileUpload".to_string(),
+        FileConstraints::default()
+      )),
+      metadata[1]
+    );
+    assert_eq!(
+      Some(JsonColumnMetadata::SchemaName(
+        "std.FileUpload".to_string(),
+        FileConstraints {
+          mime_types: vec!["image/jpeg".to_string(), "image/png".to_string()],
+          ..Default::default()
+        }
+      )),
+      metadata[2]
+    );
+    assert_eq!(
+      Some(JsonColumnMetadata::SchemaName(
+        "std.FileUpload".to_string(),
+        FileConstraints {
+          mime_types: vec!["image/*".to_string()],
+          max_size: Some(5 * 1024 * 1024),
+          max_count: None,
+        }
+      )),
+      metadata[3]
+    );
+    assert_eq!(
+      Some(JsonColumnMetadata::SchemaName(
+        "std.FileUploads".to_string(),
+        FileConstraints {
+          mime_types: vec!["application/pdf".to_string()],
+          max_size: None,
+          max_count: Some(3),
+        }
+      )),
+      metadata[4]
+    );
+
+    let invalid =
+      ColumnOption::Check("jsonschema('std.FileUpload', col, 'max_size=lots')".to_string());
+    assert!(extract_json_metadata(&invalid).is_err());
+  }
+
+  #[test]
+  fn test_find_fts_index() {
+    let parse = |sql: &str| -> Table {
+      return sqlite3_parse_into_statement(sql)
+        .unwrap()
+        .unwrap()
+        .try_into()
+        .unwrap();
+    };
+
+    let post = parse("CREATE TABLE post (id INTEGER PRIMARY KEY, title TEXT, body TEXT) STRICT");
+    let post_fts = parse(
+      "CREATE VIRTUAL TABLE post_fts USING fts5(title, body UNINDEXED, content='post', content_rowid='id')",
+    );
+    let notes_fts = parse("CREATE VIRTUAL TABLE notes_fts USING fts5(text, tokenize = 'porter')");
+
+    assert_eq!(
+      post_fts.fts5.as_ref().unwrap().columns,
+      vec!["title".to_string(), "body".to_string()]
+    );
+    assert_eq!(notes_fts.fts5.as_ref().unwrap().content, None);
+
+    let tables = vec![post.clone(), post_fts.clone(), notes_fts.clone()];
+    assert_eq!(
+      TableMetadata::new(post, &tables, "_user").fts,
+      Some(FtsIndex {
+        table_name: "post_fts".to_string(),
+        columns: vec!["title".to_string(), "body".to_string()],
+        rowid_column: Some("id".to_string()),
+      })
+    );
+    assert_eq!(
+      TableMetadata::new(notes_fts, &tables, "_user").fts,
+      Some(FtsIndex {
+        table_name: "notes_fts".to_string(),
+        columns: vec!["text".to_string()],
+        rowid_column: None,
+      })
+    );
+
+    let other = parse("CREATE TABLE other (id INTEGER PRIMARY KEY) STRICT");
+    assert_eq!(TableMetadata::new(other, &tables, "_user").fts, None);
+  }
+
+  #[test]
+  fn test_find_vector_index() {
+    let parse = |sql: &str| -> Table {
+      return sqlite3_parse_into_statement(sql)
+        .unwrap()
+        .unwrap()
+        .try_into()
+        .unwrap();
+    };
+
+    let doc = parse("CREATE TABLE doc (id INTEGER PRIMARY KEY, body TEXT) STRICT");
+    let doc_vec = parse(
+      "CREATE VIRTUAL TABLE doc_vec USING vec0(embedding float[3] distance_metric=cosine, +title TEXT, chunk_size=8)",
+    );
+    let other_vec =
+      parse("CREATE VIRTUAL TABLE other_vec USING vec0(id INTEGER PRIMARY KEY, v FLOAT[2])");
+
+    assert_eq!(
+      doc_vec.vec0.as_ref().unwrap().columns,
+      vec![Vec0Column {
+        name: "embedding".to_string(),
+        dimensions: 3,
+        distance_metric: Some("cosine".to_string()),
+      }]
+    );
+    assert_eq!(
+      other_vec.vec0.as_ref().unwrap().columns,
+      vec![Vec0Column {
+        name: "v".to_string(),
+        dimensions: 2,
+        distance_metric: None,
+      }]
+    );
+
+    let tables = vec![doc.clone(), doc_vec.clone(), other_vec.clone()];
+    assert_eq!(
+      TableMetadata::new(doc, &tables, "_user").vector_index,
+      Some(VectorIndex {
+        table_name: "doc_vec".to_string(),
+        columns: doc_vec.vec0.unwrap().columns,
+      })
+    );
+
+    let other = parse("CREATE TABLE other (id INTEGER PRIMARY KEY) STRICT");
+    assert_eq!(
+      TableMetadata::new(other, &tables, "_user").vector_index,
+      None
+    );
+  }
+
+  #[test]
+  fn test_find_geo_points() {
+    let parse = |sql: &str| -> Table {
+      return sqlite3_parse_into_statement(sql)
+        .unwrap()
+        .unwrap()
+        .try_into()
+        .unwrap();
+    };
+
+    let place = parse(
+      "CREATE TABLE place (id INTEGER PRIMARY KEY, lat REAL, lng REAL, home_latitude REAL, home_longitude REAL, work_lat TEXT, work_lng REAL) STRICT",
+    );
+    let place_rtree = parse(
+      "CREATE VIRTUAL TABLE place_location_rtree USING rtree(id, min_lat, max_lat, min_lng, max_lng, +name)",
+    );
+    assert_eq!(
+      place_rtree.rtree.as_ref().unwrap().dimensions,
+      vec![
+        ("min_lat".to_string(), "max_lat".to_string()),
+        ("min_lng".to_string(), "max_lng".to_string()),
+      ]
+    );
+
+    let tables = vec![place.clone(), place_rtree];
+    assert_eq!(
+      TableMetadata::new(place, &tables, "_user").geo_points,
+      vec![
+        GeoPoint {
+          name: "location".to_string(),
+          lat_column: "lat".to_string(),
+          lng_column: "lng".to_string(),
+          rtree: Some(RtreeIndex {
+            table_name: "place_location_rtree".to_string(),
+            id_column: "id".to_string(),
+            lat_columns: ("min_lat".to_string(), "max_lat".to_string()),
+            lng_columns: ("min_lng".to_string(), "max_lng".to_string()),
+          }),
+        },
+        GeoPoint {
+          name: "home".to_string(),
+          lat_column: "home_latitude".to_string(),
+          lng_column: "home_longitude".to_string(),
+          rtree: None,
+        },
+      ]
+    );
+
+    // Single points may use a "<table>_rtree" index.
+    let shop =
+      parse("CREATE TABLE shop (id INTEGER PRIMARY KEY, pos_lat REAL, pos_lon REAL) STRICT");
+    let shop_rtree = parse("CREATE VIRTUAL TABLE shop_rtree USING rtree(id, x0, x1, y0, y1)");
+    let tables = vec![shop.clone(), shop_rtree];
+    let points = TableMetadata::new(shop, &tables, "_user").geo_points;
+    assert_eq!(points.len(), 1);
+    assert_eq!(points[0].name, "pos");
+    assert_eq!(points[0].rtree.as_ref().unwrap().table_name, "shop_rtree");
+  }
+
+  #[test]
+  fn test_unique_keys() {
+    let table: Table = sqlite3_parse_into_statement(
+      "CREATE TABLE t (id INTEGER PRIMARY KEY, email TEXT UNIQUE, a TEXT, b TEXT, c TEXT, UNIQUE (a, b)) STRICT",
+    )
+    .unwrap()
+    .unwrap()
+    .try_into()
+    .unwrap();
+
+    let metadata = TableMetadata::new(table.clone(), &[table], "_user");
+    assert_eq!(
+      metadata.unique_keys(),
+      vec![
+        vec!["id".to_string()],
+        vec!["email".to_string()],
+        vec!["a".to_string(), "b".to_string()],
+      ]
+    );
+  }
+
+  #[test]
+  fn test_composite_primary_key() {
+    let table: Table = sqlite3_parse_into_statement(
+      "CREATE TABLE membership (org INTEGER NOT NULL, member INTEGER NOT NULL, role TEXT, PRIMARY KEY (org, member)) STRICT",
+    )
+    .unwrap()
+    .unwrap()
+    .try_into()
+    .unwrap();
+
+    assert_eq!(
+      Some(vec!["org".to_string(), "member".to_string()]),
+      table.primary_key.as_ref().map(|pk| pk.columns.clone())
+    );
+
+    let roundtrip: Table = sqlite3_parse_into_statement(&table.create_table_statement())
+      .unwrap()
+      .unwrap()
+      .try_into()
+      .unwrap();
+    assert_eq!(table, roundtrip);
+
+    let metadata = TableMetadata::new(table.clone(), &[table], "_user");
+    assert_eq!(vec![0, 1], metadata.record_pk_columns);
+    assert_eq!(None, metadata.record_pk_column);
+
+    // Non-integer/UUID key parts don't qualify.
+    let table: Table = sqlite3_parse_into_statement(
+      "CREATE TABLE tag (name TEXT NOT NULL, id INTEGER NOT NULL, PRIMARY KEY (name, id)) STRICT",
+    )
+    .unwrap()
+    .unwrap()
+    .try_into()
+    .unwrap();
+    let metadata = TableMetadata::new(table.clone(), &[table], "_user");
+    assert!(metadata.record_pk_columns.is_empty());
+  }
+
+  #[test]
+  fn test_record_pk_kinds() {
+    let parse = |sql: &str| -> TableMetadata {
+      let table: Table = sqlite3_parse_into_statement(sql)
+        .unwrap()
+        .unwrap()
+        .try_into()
+        .unwrap();
+      return TableMetadata::new(table.clone(), &[table], "_user");
+    };
+
+    let table = parse("CREATE TABLE t (id INTEGER PRIMARY KEY) STRICT");
+    assert_eq!(Some(0), table.record_pk_column);
+    assert!(table.record_pk_sortable);
+
+    for (check, sortable) in [("is_uuid_v7", true), ("is_ulid", true), ("is_uuid", false)] {
+      let table = parse(&format!(
+        "CREATE TABLE t (id BLOB PRIMARY KEY CHECK({check}(id)), value TEXT) STRICT"
+      ));
+      assert_eq!(Some(0), table.record_pk_column, "{check}");
+      assert_eq!(sortable, table.record_pk_sortable, "{check}");
+    }
+
+    let table = parse("CREATE TABLE t (id BLOB PRIMARY KEY, value TEXT) STRICT");
+    assert_eq!(None, table.record_pk_column);
+  }
+
+  #[test]
+  fn test_find_geometry_columns() {
+    let parse = |sql: &str| -> Table {
+      return sqlite3_parse_into_statement(sql)
+        .unwrap()
+        .unwrap()
+        .try_into()
+        .unwrap();
+    };
+
+    let table = parse(
+      r#"
+      CREATE TABLE places (
+          id            INTEGER PRIMARY KEY,
+          lat           REAL,
+          lng           REAL,
+          area          BLOB CHECK(is_geometry(area))
+      ) STRICT;"#,
+    );
+    assert_eq!(
+      find_geometry_columns(&table.columns),
+      Some(GeometryColumns::Wkb(3))
+    );
+
+    let table = parse(
+      r#"
+      CREATE TABLE places (
+          id            INTEGER PRIMARY KEY,
+          latitude      REAL NOT NULL,
+          longitude     REAL NOT NULL
+      ) STRICT;"#,
+    );
+    assert_eq!(
+      find_geometry_columns(&table.columns),
+      Some(GeometryColumns::LatLng { lat: 1, lng: 2 })
+    );
+
+    let table = parse(
+      r#"
+      CREATE TABLE places (
+          id            INTEGER PRIMARY KEY,
+          lat           TEXT,
+          lng           TEXT
+      ) STRICT;"#,
+    );
+    assert_eq!(find_geometry_columns(&table.columns), None);
+  }
+
+  #[test]
+  fn test_vector_column_dimensions() {
+    let table_sql = r#"
+      CREATE TABLE docs (
+          id            INTEGER PRIMARY KEY,
+          embedding     BLOB CHECK(vec_length(embedding) = 3),
+          other         BLOB CHECK(vec_length(embedding) = 3),
+          plain         BLOB
+      ) STRICT;"#;
+
+    let table: Table = sqlite3_parse_into_statement(table_sql)
+      .unwrap()
+      .unwrap()
+      .try_into()
+      .unwrap();
+
+    let dimensions: Vec<_> = table.columns.iter().map(vector_column_dimensions).collect();
+    assert_eq!(dimensions, vec![None, Some(3), None, None]);
+  }
 }
@@ -0,0 +1,483 @@
+//! Declarative schema migrations, i.e. deriving the statements to get from the current schema to a
+//! desired one, e.g. given as a set of `CREATE TABLE`, `CREATE VIEW` and `CREATE INDEX` statements.
+//!
+//! SQLite's `ALTER TABLE` is very limited, thus anything but appending columns requires rebuilding
+//! the table: create the new table, copy the data over, drop the old one and rename the new one,
+//! see https://sqlite.org/lang_altertable.html#otheralter.
+
+use sqlite3_parser::ast::Stmt;
+use std::collections::HashSet;
+
+use crate::sqlite::{
+  Column, ColumnOption, SchemaError, Table, TableIndex, Trigger, View,
+  sqlite3_parse_into_statements,
+};
+
+/// A set of tables, views and indexes, either desired or currently present.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DeclaredSchema {
+  pub tables: Vec<Table>,
+  pub views: Vec<View>,
+  pub indexes: Vec<TableIndex>,
+
+  /// Triggers aren't managed declaratively. Existing ones are merely re-created when their table
+  /// gets rebuilt.
+  pub triggers: Vec<Trigger>,
+}
+
+impl DeclaredSchema {
+  /// Parses a desired schema from `CREATE TABLE`, `CREATE VIEW` and `CREATE INDEX` statements.
+  pub fn parse(sql: &str) -> Result<Self, SchemaError> {
+    let statements =
+      sqlite3_parse_into_statements(sql).map_err(|err| SchemaError::Precondition(err.into()))?;
+
+    let mut schema = DeclaredSchema::default();
+    let mut view_statements: Vec<Stmt> = vec![];
+    for stmt in statements {
+      match stmt {
+        Stmt::CreateTable { .. } => schema.tables.push(stmt.try_into()?),
+        Stmt::CreateIndex { .. } => schema.indexes.push(TableIndex {
+          if_not_exists: false,
+          ..stmt.try_into()?
+        }),
+        // Views are parsed last to infer their columns from all tables.
+        Stmt::CreateView { .. } => view_statements.push(stmt),
+        _ => {
+          return Err(SchemaError::Precondition(
+            format!("Expected CREATE TABLE, VIEW or INDEX, got: {stmt:?}").into(),
+          ));
+        }
+      };
+    }
+
+    for stmt in view_statements {
+      schema.views.push(View {
+        if_not_exists: false,
+        ..View::from(stmt, &schema.tables)?
+      });
+    }
+
+    let names = schema
+      .tables
+      .iter()
+      .map(|t| (&t.name, t.temporary))
+      .chain(schema.views.iter().map(|v| (&v.name, v.temporary)))
+      .chain(schema.indexes.iter().map(|i| (&i.name, false)));
+    let mut seen: HashSet<&str> = HashSet::new();
+    for (name, temporary) in names {
+      if name.starts_with('_') || name.starts_with("sqlite_") {
+        return Err(SchemaError::Precondition(
+          format!("'{name}' uses a reserved prefix").into(),
+        ));
+      }
+      if temporary {
+        return Err(SchemaError::Precondition(
+          format!("'{name}' must not be TEMPORARY").into(),
+        ));
+      }
+      if !seen.insert(name) {
+        return Err(SchemaError::Precondition(
+          format!("'{name}' is declared more than once").into(),
+        ));
+      }
+    }
+
+    return Ok(schema);
+  }
+}
+
+/// Whether a column can be appended in-place using `ALTER TABLE ADD COLUMN`, see
+/// https://sqlite.org/lang_altertable.html#altertabaddcol.
+fn is_addable_column(column: &Column) -> bool {
+  let default = column.options.iter().find_map(|o| match o {
+    ColumnOption::Default(default) => Some(default.as_str()),
+    _ => None,
+  });
+
+  let constant_default = default.is_none_or(|d| {
+    return !d.starts_with('(') && !d.to_uppercase().starts_with("CURRENT_");
+  });
+  let null_default = default.is_none_or(|d| d.eq_ignore_ascii_case("NULL"));
+
+  return constant_default
+    && (!column.is_not_null() || !null_default)
+    && column.options.iter().all(|o| match o {
+      ColumnOption::Unique { .. } => false,
+      ColumnOption::Generated { .. } => false,
+      ColumnOption::ForeignKey { .. } => null_default,
+      _ => true,
+    });
+}
+
+enum TableChange<'a> {
+  AddColumns(&'a Table, &'a [Column]),
+  Rebuild(&'a Table, &'a Table),
+}
+
+fn classify_table_change<'a>(current: &'a Table, desired: &'a Table) -> TableChange<'a> {
+  let n = current.columns.len();
+  let appended_only = desired.columns.len() > n
+    && desired.columns[..n] == current.columns[..]
+    && Table {
+      columns: current.columns.clone(),
+      ..desired.clone()
+    } == *current;
+
+  if appended_only && desired.columns[n..].iter().all(is_addable_column) {
+    return TableChange::AddColumns(desired, &desired.columns[n..]);
+  }
+  return TableChange::Rebuild(current, desired);
+}
+
+fn rebuild_table_statements(current: &Table, desired: &Table) -> Vec<String> {
+  let table_name = &desired.name;
+  let temp_table_name = format!("__new_{table_name}");
+
+  let current_columns: HashSet<&str> = current
+    .columns
+    .iter()
+    .filter(|c| !c.is_generated())
+    .map(|c| c.name.as_str())
+    .collect();
+  let column_list = desired
+    .columns
+    .iter()
+    .filter(|c| !c.is_generated() && current_columns.contains(c.name.as_str()))
+    .map(|c| format!(r#""{}""#, c.name))
+    .collect::<Vec<_>>()
+    .join(", ");
+
+  let mut statements = vec![
+    Table {
+      name: temp_table_name.clone(),
+      ..desired.clone()
+    }
+    .create_table_statement(),
+  ];
+  if !column_list.is_empty() {
+    statements.push(format!(
+      r#"INSERT INTO "{temp_table_name}" ({column_list}) SELECT {column_list} FROM "{table_name}""#
+    ));
+  }
+  statements.push(format!(r#"DROP TABLE "{table_name}""#));
+  statements.push(format!(
+    r#"ALTER TABLE "{temp_table_name}" RENAME TO "{table_name}""#
+  ));
+  return statements;
+}
+
+/// Derives the statements migrating the `current` schema to the `desired` one.
+///
+/// Objects are matched by name, i.e. renames show up as drop & create. Unless `allow_data_loss` is
+/// set, dropping tables or columns is rejected.
+pub fn plan_migration(
+  current: &DeclaredSchema,
+  desired: &DeclaredSchema,
+  allow_data_loss: bool,
+) -> Result<Vec<String>, SchemaError> {
+  let data_loss = |msg: String| -> Result<(), SchemaError> {
+    if allow_data_loss {
+      return Ok(());
+    }
+    return Err(SchemaError::Precondition(
+      format!("{msg}, which requires allowing data loss").into(),
+    ));
+  };
+
+  // Tables.
+  let mut dropped_tables: Vec<&Table> = vec![];
+  for table in &current.tables {
+    if !desired.tables.iter().any(|t| t.name == table.name) {
+      data_loss(format!("Dropping table '{}'", table.name))?;
+      dropped_tables.push(table);
+    }
+  }
+
+  let mut created_tables: Vec<&Table> = vec![];
+  let mut changes: Vec<TableChange> = vec![];
+  for table in &desired.tables {
+    if table.virtual_table {
+      return Err(SchemaError::Precondition(
+        format!("Virtual table '{}' not supported", table.name).into(),
+      ));
+    }
+
+    match current.tables.iter().find(|t| t.name == table.name) {
+      None => created_tables.push(table),
+      Some(existing) if existing == table => {}
+      Some(existing) => {
+        if existing.virtual_table {
+          return Err(SchemaError::Precondition(
+            format!("Virtual table '{}' cannot be migrated", table.name).into(),
+          ));
+        }
+
+        for column in &existing.columns {
+          if !column.is_generated() && !table.columns.iter().any(|c| c.name == column.name) {
+            data_loss(format!("Dropping column '{}.{}'", table.name, column.name))?;
+          }
+        }
+
+        changes.push(classify_table_change(existing, table));
+      }
+    };
+  }
+
+  let rebuilt_tables: HashSet<&str> = changes
+    .iter()
+    .filter_map(|change| match change {
+      TableChange::Rebuild(_, desired) => Some(desired.name.as_str()),
+      TableChange::AddColumns(..) => None,
+    })
+    .collect();
+  let replaced = |table_name: &str| {
+    return rebuilt_tables.contains(table_name)
+      || dropped_tables.iter().any(|t| t.name == table_name);
+  };
+
+  // Views may reference rebuilt or dropped tables, thus we conservatively re-create all of them.
+  let recreate_all_views = !rebuilt_tables.is_empty() || !dropped_tables.is_empty();
+  let contains_view = |views: &[View], view: &View| {
+    return views
+      .iter()
+      .any(|v| v.name == view.name && v.query == view.query);
+  };
+
+  let mut statements: Vec<String> = vec![];
+
+  for view in &current.views {
+    if recreate_all_views || !contains_view(&desired.views, view) {
+      statements.push(format!(r#"DROP VIEW "{}""#, view.name));
+    }
+  }
+
+  for index in &current.indexes {
+    if replaced(&index.table_name) {
+      // Dropped together with the table.
+      continue;
+    }
+    if !desired.indexes.contains(index) {
+      statements.push(format!(r#"DROP INDEX "{}""#, index.name));
+    }
+  }
+
+  for table in &dropped_tables {
+    statements.push(format!(r#"DROP TABLE "{}""#, table.name));
+  }
+
+  for table in &created_tables {
+    statements.push(table.create_table_statement());
+  }
+
+  for change in &changes {
+    match change {
+      TableChange::AddColumns(table, columns) => {
+        for column in *columns {
+          statements.push(format!(
+            r#"ALTER TABLE "{}" ADD COLUMN {}"#,
+            table.name,
+            column.to_fragment()
+          ));
+        }
+      }
+      TableChange::Rebuild(current_table, desired_table) => {
+        statements.extend(rebuild_table_statements(current_table, desired_table));
+
+        for trigger in &current.triggers {
+          if trigger.table_name == desired_table.name {
+            statements.push(trigger.create_trigger_statement());
+          }
+        }
+      }
+    };
+  }
+
+  for index in &desired.indexes {
+    if replaced(&index.table_name) || !current.indexes.contains(index) {
+      statements.push(index.create_index_statement());
+    }
+  }
+
+  for view in &desired.views {
+    if recreate_all_views || !contains_view(&current.views, view) {
+      statements.push(view.create_view_statement());
+    }
+  }
+
+  if !statements.is_empty() && (!rebuilt_tables.is_empty() || !dropped_tables.is_empty()) {
+    statements.insert(0, "PRAGMA foreign_keys = OFF".to_string());
+    statements.push("PRAGMA foreign_keys = ON".to_string());
+  }
+
+  return Ok(statements);
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn apply(conn: &rusqlite::Connection, statements: &[String]) {
+    for stmt in statements {
+      conn.execute_batch(stmt).unwrap();
+    }
+  }
+
+  fn current(conn: &rusqlite::Connection) -> DeclaredSchema {
+    let mut stmt = conn
+      .prepare("SELECT sql FROM sqlite_schema WHERE sql IS NOT NULL ORDER BY rowid")
+      .unwrap();
+    let sql: Vec<String> = stmt
+      .query_map([], |row| row.get::<_, String>(0))
+      .unwrap()
+      .map(|sql| format!("{};", sql.unwrap()))
+      .collect();
+    return DeclaredSchema::parse(&sql.join("\n")).unwrap();
+  }
+
+  #[test]
+  fn test_plan_migration() {
+    let conn = rusqlite::Connection::open_in_memory().unwrap();
+
+    let v1 = DeclaredSchema::parse(
+      r#"
+        CREATE TABLE author (id INTEGER PRIMARY KEY, name TEXT NOT NULL) STRICT;
+        CREATE TABLE post (
+          id      INTEGER PRIMARY KEY,
+          author  INTEGER REFERENCES author(id),
+          title   TEXT NOT NULL,
+          body    TEXT
+        ) STRICT;
+        CREATE INDEX post_title_index ON post (title);
+        CREATE VIEW post_titles AS SELECT id, title FROM post;
+      "#,
+    )
+    .unwrap();
+
+    let statements = plan_migration(&DeclaredSchema::default(), &v1, false).unwrap();
+    apply(&conn, &statements);
+    conn
+      .execute_batch(
+        r#"
+          INSERT INTO author (id, name) VALUES (1, 'alice');
+          INSERT INTO post (author, title, body) VALUES (1, 'first', 'body');
+        "#,
+      )
+      .unwrap();
+
+    assert_eq!(v1, current(&conn));
+    assert!(
+      plan_migration(&current(&conn), &v1, false)
+        .unwrap()
+        .is_empty()
+    );
+
+    // Appending a nullable column is done in place.
+    let v2 = DeclaredSchema::parse(
+      r#"
+        CREATE TABLE author (id INTEGER PRIMARY KEY, name TEXT NOT NULL, bio TEXT) STRICT;
+        CREATE TABLE post (
+          id      INTEGER PRIMARY KEY,
+          author  INTEGER REFERENCES author(id),
+          title   TEXT NOT NULL,
+          body    TEXT
+        ) STRICT;
+        CREATE INDEX post_title_index ON post (title);
+        CREATE VIEW post_titles AS SELECT id, title FROM post;
+      "#,
+    )
+    .unwrap();
+    let statements = plan_migration(&current(&conn), &v2, false).unwrap();
+    assert_eq!(
+      vec![r#"ALTER TABLE "author" ADD COLUMN 'bio' TEXT"#.to_string()],
+      statements
+    );
+    apply(&conn, &statements);
+
+    // Dropping a column requires a rebuild and allowing data loss.
+    let v3 = DeclaredSchema::parse(
+      r#"
+        CREATE TABLE author (id INTEGER PRIMARY KEY, name TEXT NOT NULL, bio TEXT) STRICT;
+        CREATE TABLE post (
+          id         INTEGER PRIMARY KEY,
+          author     INTEGER REFERENCES author(id),
+          title      TEXT NOT NULL,
+          published  INTEGER NOT NULL DEFAULT 0 CHECK(published IN (0, 1))
+        ) STRICT;
+        CREATE INDEX post_title_index ON post (title);
+        CREATE UNIQUE INDEX post_author_title_index ON post (author, title);
+        CREATE VIEW post_titles AS SELECT id, title FROM post WHERE published = 1;
+      "#,
+    )
+    .unwrap();
+    assert!(plan_migration(&current(&conn), &v3, false).is_err());
+
+    let statements = plan_migration(&current(&conn), &v3, true).unwrap();
+    assert_eq!("PRAGMA foreign_keys = OFF", statements[0]);
+    assert_eq!(r#"DROP VIEW "post_titles""#, statements[1]);
+    assert!(statements[2].starts_with(r#"CREATE TABLE '__new_post'"#));
+    assert_eq!(
+      r#"INSERT INTO "__new_post" ("id", "author", "title") SELECT "id", "author", "title" FROM "post""#,
+      statements[3]
+    );
+    apply(&conn, &statements);
+
+    assert_eq!(v3, current(&conn));
+    let title: String = conn
+      .query_row("SELECT title FROM post WHERE id = 1", [], |row| row.get(0))
+      .unwrap();
+    assert_eq!("first", title);
+
+    // Dropping tables.
+    let v4 = DeclaredSchema::parse(
+      "CREATE TABLE author (id INTEGER PRIMARY KEY, name TEXT NOT NULL, bio TEXT) STRICT;",
+    )
+    .unwrap();
+    assert!(plan_migration(&current(&conn), &v4, false).is_err());
+    apply(&conn, &plan_migration(&current(&conn), &v4, true).unwrap());
+    assert_eq!(v4, current(&conn));
+  }
+
+  #[test]
+  fn test_is_addable_column() {
+    let column = |sql: &str| -> Column {
+      let table: Table =
+        crate::sqlite::sqlite3_parse_into_statement(&format!("CREATE TABLE t ({sql})"))
+          .unwrap()
+          .unwrap()
+          .try_into()
+          .unwrap();
+      return table.columns[0].clone();
+    };
+
+    assert!(is_addable_column(&column("a TEXT")));
+    assert!(is_addable_column(&column("a TEXT NOT NULL DEFAULT ''")));
+    assert!(is_addable_column(&column("a INTEGER REFERENCES b(id)")));
+    assert!(is_addable_column(&column("a TEXT CHECK(a != '')")));
+
+    assert!(!is_addable_column(&column("a TEXT NOT NULL")));
+    assert!(!is_addable_column(&column("a TEXT UNIQUE")));
+    assert!(!is_addable_column(&column("a INTEGER PRIMARY KEY")));
+    assert!(!is_addable_column(&column("a BLOB DEFAULT (uuid_v7())")));
+    assert!(!is_addable_column(&column(
+      "a INTEGER DEFAULT CURRENT_TIMESTAMP"
+    )));
+    assert!(!is_addable_column(&column(
+      "a INTEGER DEFAULT 1 REFERENCES b(id)"
+    )));
+    assert!(!is_addable_column(&column(
+      "a INTEGER GENERATED ALWAYS AS (1)"
+    )));
+  }
+
+  #[test]
+  fn test_parse_declared_schema() {
+    assert!(DeclaredSchema::parse("DROP TABLE foo;").is_err());
+    assert!(DeclaredSchema::parse("CREATE TABLE _foo (id INTEGER PRIMARY KEY);").is_err());
+    assert!(
+      DeclaredSchema::parse(
+        "CREATE TABLE foo (id INTEGER PRIMARY KEY); CREATE VIEW foo AS SELECT 1;"
+      )
+      .is_err()
+    );
+  }
+}
@@ -1,28 +1,40 @@
-use jsonschema::Validator;
+use jsonschema::{Retrieve, Uri, Validator};
 use lazy_static::lazy_static;
 use schemars::schema_for;
+use serde::Serialize;
 use std::collections::HashMap;
+use std::path::Path;
 use std::sync::Arc;
 use trailbase_extension::jsonschema::SchemaEntry;
+use ts_rs::TS;
 
 use crate::error::Error;
-use crate::file::{FileUpload, FileUploads};
+use crate::file::{FileConstraints, FileUpload, FileUploads};
 
 fn builtin_schemas() -> &'static HashMap<String, SchemaEntry> {
-  fn validate_mime_type(value: &serde_json::Value, extra_args: Option<&str>) -> bool {
-    let Some(valid_mime_types) = extra_args else {
-      return true;
-    };
+  fn mime_type(value: &serde_json::Value) -> Option<&str> {
+    return value.get("mime_type").and_then(|m| m.as_str());
+  }
 
-    if let serde_json::Value::Object(map) = value {
-      if let Some(serde_json::Value::String(mime_type)) = map.get("mime_type") {
-        if valid_mime_types.contains(mime_type) {
-          return true;
-        }
-      }
-    }
+  // NOTE: File sizes aren't part of the stored metadata and are enforced on write by record APIs.
+  fn validate_file_upload(value: &serde_json::Value, extra_args: &[&str]) -> bool {
+    let Ok(constraints) = FileConstraints::parse(extra_args) else {
+      return false;
+    };
+    return constraints.accepts_mime_type(mime_type(value));
+  }
 
-    return false;
+  fn validate_file_uploads(value: &serde_json::Value, extra_args: &[&str]) -> bool {
+    let Ok(constraints) = FileConstraints::parse(extra_args) else {
+      return false;
+    };
+    let serde_json::Value::Array(files) = value else {
+      return false;
+    };
+    return constraints.check_count(files.len()).is_ok()
+      && files
+        .iter()
+        .all(|file| constraints.accepts_mime_type(mime_type(file)));
   }
 
   lazy_static! {
@@ -31,7 +43,7 @@ fn builtin_schemas() -> &'static HashMap<String, SchemaEntry> {
         "std.FileUpload".to_string(),
         SchemaEntry::from(
           serde_json::to_value(schema_for!(FileUpload)).expect("infallible"),
-          Some(Arc::new(validate_mime_type))
+          Some(Arc::new(validate_file_upload))
         )
         .expect("infallible")
       ),
@@ -39,7 +51,7 @@ fn builtin_schemas() -> &'static HashMap<String, SchemaEntry> {
         "std.FileUploads".to_string(),
         SchemaEntry::from(
           serde_json::to_value(schema_for!(FileUploads)).expect("infallible"),
-          None
+          Some(Arc::new(validate_file_uploads))
         )
         .expect("infallible"),
       )
@@ -85,38 +97,277 @@ pub fn get_schemas() -> Vec<Schema> {
     .collect();
 }
 
+/// Resolves `$ref`s to other registered schemas by name, e.g. `{"$ref": "std.FileUpload"}` or
+/// `{"$ref": "my.Address#/properties/zip"}`.
+#[derive(Clone)]
+struct RegistryRetriever {
+  schemas: Arc<HashMap<String, serde_json::Value>>,
+}
+
+impl Retrieve for RegistryRetriever {
+  fn retrieve(
+    &self,
+    uri: &Uri<String>,
+  ) -> Result<serde_json::Value, Box<dyn std::error::Error + Send + Sync>> {
+    // Relative references resolve against the default base URI, i.e. "json-schema:///<name>".
+    let name = uri.path().as_str().trim_start_matches('/');
+    return self
+      .schemas
+      .get(name)
+      .cloned()
+      .ok_or_else(|| format!("Schema not found: {uri}").into());
+  }
+}
+
+impl RegistryRetriever {
+  /// Retriever for the builtin and the given user schemas.
+  fn new(schemas: &[(String, serde_json::Value)]) -> Self {
+    return RegistryRetriever {
+      schemas: Arc::new(
+        builtin_schemas()
+          .iter()
+          .map(|(name, entry)| (name.clone(), entry.schema().clone()))
+          .chain(schemas.iter().cloned())
+          .collect(),
+      ),
+    };
+  }
+}
+
+/// Splits a schema name into its base name and optional version, e.g. "my.Schema@v2" into
+/// ("my.Schema", Some(2)). Different versions are registered side-by-side as independent schemas.
+pub fn split_schema_version(name: &str) -> Result<(&str, Option<u32>), Error> {
+  let Some((base, version)) = name.rsplit_once('@') else {
+    return Ok((name, None));
+  };
+
+  let Some(digits) = version.strip_prefix('v') else {
+    return Err(Error::InvalidSchemaName(name.to_string()));
+  };
+  if base.is_empty() || digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+    return Err(Error::InvalidSchemaName(name.to_string()));
+  }
+
+  return match digits.parse::<u32>() {
+    Ok(version) => Ok((base, Some(version))),
+    Err(_) => Err(Error::InvalidSchemaName(name.to_string())),
+  };
+}
+
+/// Returns all registered versions of the schema with the given base name, including the
+/// unversioned one, ordered by version.
+pub fn get_schema_versions(base_name: &str) -> Vec<Schema> {
+  let mut versions: Vec<(Option<u32>, Schema)> = get_schemas()
+    .into_iter()
+    .filter_map(|schema| match split_schema_version(&schema.name) {
+      Ok((base, version)) if base == base_name => Some((version, schema)),
+      _ => None,
+    })
+    .collect();
+
+  versions.sort_by_key(|(version, _)| *version);
+  return versions.into_iter().map(|(_, schema)| schema).collect();
+}
+
+/// Compiles user schemas, resolving `$ref`s against the builtin and all other given schemas.
+fn compile_user_schemas(
+  schemas: Vec<(String, serde_json::Value)>,
+) -> Result<Vec<(String, SchemaEntry)>, Error> {
+  let retriever = RegistryRetriever::new(&schemas);
+
+  return schemas
+    .into_iter()
+    .map(|(name, schema)| {
+      split_schema_version(&name)?;
+
+      let validator = jsonschema::options()
+        .with_retriever(retriever.clone())
+        .build(&schema)
+        .map_err(|err| Error::JsonSchema(Arc::new(err)))?;
+      return Ok((name, SchemaEntry::with_validator(schema, validator, None)));
+    })
+    .collect();
+}
+
+fn get_user_schemas() -> Vec<(String, serde_json::Value)> {
+  let builtins = builtin_schemas();
+  return trailbase_extension::jsonschema::get_schemas()
+    .into_iter()
+    .filter(|(name, _)| !builtins.contains_key(name))
+    .collect();
+}
+
+/// Compiles `schema` as if it were registered as `name` w/o actually registering it, e.g. to
+/// check existing data against a new schema before switching over.
+pub fn compile_user_schema(name: &str, schema: &serde_json::Value) -> Result<Validator, Error> {
+  if builtin_schemas().contains_key(name) {
+    return Err(Error::BuiltinSchema);
+  }
+  split_schema_version(name)?;
+
+  let mut schemas: Vec<(String, serde_json::Value)> = get_user_schemas()
+    .into_iter()
+    .filter(|(n, _)| n != name)
+    .collect();
+  schemas.push((name.to_string(), schema.clone()));
+
+  return jsonschema::options()
+    .with_retriever(RegistryRetriever::new(&schemas))
+    .build(schema)
+    .map_err(|err| Error::JsonSchema(Arc::new(err)));
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, TS)]
+pub enum SchemaChangeKind {
+  Added,
+  Removed,
+  Changed,
+}
+
+/// A single structural difference between two schemas.
+#[derive(Clone, Debug, PartialEq, Serialize, TS)]
+#[ts(export)]
+pub struct SchemaChange {
+  /// JSON pointer to the changed location, e.g. "/properties/zip/type".
+  pub path: String,
+  pub kind: SchemaChangeKind,
+  pub old: Option<serde_json::Value>,
+  pub new: Option<serde_json::Value>,
+}
+
+/// Structurally diffs two schemas. Objects are compared key by key, any other values, including
+/// arrays like "required" or "enum", as a whole. Changes are ordered by path.
+pub fn diff_schemas(old: &serde_json::Value, new: &serde_json::Value) -> Vec<SchemaChange> {
+  fn escape(key: &str) -> String {
+    return key.replace('~', "~0").replace('/', "~1");
+  }
+
+  fn diff(
+    path: String,
+    old: &serde_json::Value,
+    new: &serde_json::Value,
+    out: &mut Vec<SchemaChange>,
+  ) {
+    match (old, new) {
+      (serde_json::Value::Object(old), serde_json::Value::Object(new)) => {
+        for (key, old_value) in old {
+          let path = format!("{path}/{}", escape(key));
+          match new.get(key) {
+            Some(new_value) => diff(path, old_value, new_value, out),
+            None => out.push(SchemaChange {
+              path,
+              kind: SchemaChangeKind::Removed,
+              old: Some(old_value.clone()),
+              new: None,
+            }),
+          }
+        }
+
+        for (key, new_value) in new {
+          if !old.contains_key(key) {
+            out.push(SchemaChange {
+              path: format!("{path}/{}", escape(key)),
+              kind: SchemaChangeKind::Added,
+              old: None,
+              new: Some(new_value.clone()),
+            });
+          }
+        }
+      }
+      _ if old == new => {}
+      _ => out.push(SchemaChange {
+        path,
+        kind: SchemaChangeKind::Changed,
+        old: Some(old.clone()),
+        new: Some(new.clone()),
+      }),
+    };
+  }
+
+  let mut changes: Vec<SchemaChange> = vec![];
+  diff(String::new(), old, new, &mut changes);
+  changes.sort_by(|a, b| a.path.cmp(&b.path));
+  return changes;
+}
+
+/// Adds, updates or removes a single user schema. All user schemas get recompiled, since they may
+/// reference the changed one. Fails w/o changes, if any of them would no longer compile.
 pub fn set_user_schema(name: &str, pattern: Option<serde_json::Value>) -> Result<(), Error> {
   let builtins = builtin_schemas();
   if builtins.contains_key(name) {
     return Err(Error::BuiltinSchema);
   }
 
+  let mut schemas: Vec<(String, serde_json::Value)> = get_user_schemas()
+    .into_iter()
+    .filter(|(n, _)| n != name)
+    .collect();
+  let remove = pattern.is_none();
   if let Some(p) = pattern {
-    let entry = SchemaEntry::from(p, None).map_err(|err| Error::JsonSchema(Arc::new(err)))?;
-    trailbase_extension::jsonschema::set_schema(name, Some(entry));
-  } else {
+    schemas.push((name.to_string(), p));
+  }
+
+  let entries = compile_user_schemas(schemas)?;
+
+  // NOTE: Entries are updated individually rather than replacing the registry to not race with
+  // concurrent updates to other schemas.
+  if remove {
     trailbase_extension::jsonschema::set_schema(name, None);
   }
+  for (name, entry) in entries {
+    trailbase_extension::jsonschema::set_schema(&name, Some(entry));
+  }
 
   return Ok(());
 }
 
+/// Loads user schemas from the given directory, where each `<name>.json` file contains the schema
+/// registered as `<name>`. A missing directory yields no schemas.
+pub fn load_schemas_from_dir(path: &Path) -> Result<Vec<(String, serde_json::Value)>, Error> {
+  let entries = match std::fs::read_dir(path) {
+    Ok(entries) => entries,
+    Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+      return Ok(vec![]);
+    }
+    Err(err) => {
+      return Err(Error::Io(Arc::new(err)));
+    }
+  };
+
+  let mut schemas: Vec<(String, serde_json::Value)> = vec![];
+  for entry in entries {
+    let path = entry.map_err(|err| Error::Io(Arc::new(err)))?.path();
+    if path.extension().is_none_or(|ext| ext != "json") {
+      continue;
+    }
+    let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) else {
+      continue;
+    };
+
+    let contents = std::fs::read_to_string(&path).map_err(|err| Error::Io(Arc::new(err)))?;
+    let schema = serde_json::from_str(&contents)
+      .map_err(|err| Error::InvalidSchemaFile(path.display().to_string(), Arc::new(err)))?;
+
+    schemas.push((name.to_string(), schema));
+  }
+
+  schemas.sort_by(|a, b| a.0.cmp(&b.0));
+  return Ok(schemas);
+}
+
 lazy_static! {
   static ref INIT: parking_lot::Mutex<bool> = parking_lot::Mutex::new(false);
 }
 
+/// Replaces all user schemas, e.g. when (re-)loading them from config and disk, recompiling their
+/// validators.
 pub fn set_user_schemas(schemas: Vec<(String, serde_json::Value)>) -> Result<(), Error> {
   let mut entries: Vec<(String, SchemaEntry)> = vec![];
   for (name, entry) in builtin_schemas() {
     entries.push((name.clone(), entry.clone()));
   }
 
-  for (name, schema) in schemas {
-    entries.push((
-      name,
-      SchemaEntry::from(schema, None).map_err(|err| Error::JsonSchema(Arc::new(err)))?,
-    ));
-  }
+  entries.extend(compile_user_schemas(schemas)?);
 
   trailbase_extension::jsonschema::set_schemas(Some(entries));
 
@@ -184,4 +435,215 @@ mod tests {
       );
     }
   }
+
+  #[test]
+  fn test_schema_references() {
+    let address = json!({
+      "type": "object",
+      "properties": {
+        "zip": { "type": "string", "pattern": "^[0-9]{5}$" },
+      },
+      "required": ["zip"],
+    });
+    set_user_schema("test_refs.Address", Some(address)).unwrap();
+    set_user_schema(
+      "test_refs.Person",
+      Some(json!({
+        "type": "object",
+        "properties": {
+          "home": { "$ref": "test_refs.Address" },
+          "zip": { "$ref": "test_refs.Address#/properties/zip" },
+        },
+      })),
+    )
+    .unwrap();
+
+    let person = get_compiled_schema("test_refs.Person").unwrap();
+    assert!(person.is_valid(&json!({"home": {"zip": "12345"}, "zip": "54321"})));
+    assert!(!person.is_valid(&json!({"home": {"zip": "abc"}})));
+    assert!(!person.is_valid(&json!({"zip": "abc"})));
+
+    // Updating a referenced schema recompiles dependents.
+    set_user_schema(
+      "test_refs.Address",
+      Some(json!({
+        "type": "object",
+        "properties": {
+          "zip": { "type": "string" },
+        },
+      })),
+    )
+    .unwrap();
+    let person = get_compiled_schema("test_refs.Person").unwrap();
+    assert!(person.is_valid(&json!({"home": {"zip": "abc"}})));
+
+    // Removing a referenced schema fails w/o changes.
+    assert!(set_user_schema("test_refs.Address", None).is_err());
+    assert!(get_schema("test_refs.Address").is_some());
+
+    assert!(
+      set_user_schema(
+        "test_refs.Dangling",
+        Some(json!({"$ref": "test_refs.Missing"}))
+      )
+      .is_err()
+    );
+
+    set_user_schema("test_refs.Person", None).unwrap();
+    set_user_schema("test_refs.Address", None).unwrap();
+  }
+
+  #[test]
+  fn test_schema_versions() {
+    assert_eq!(
+      ("my.Schema", None),
+      split_schema_version("my.Schema").unwrap()
+    );
+    assert_eq!(
+      ("my.Schema", Some(2)),
+      split_schema_version("my.Schema@v2").unwrap()
+    );
+    for invalid in [
+      "my.Schema@2",
+      "my.Schema@v",
+      "my.Schema@v+1",
+      "@v1",
+      "my.Schema@vx",
+    ] {
+      assert!(split_schema_version(invalid).is_err(), "{invalid}");
+    }
+
+    set_user_schema("test_versions.Name", Some(json!({"type": "string"}))).unwrap();
+    set_user_schema("test_versions.Name@v2", Some(json!({"type": "integer"}))).unwrap();
+    set_user_schema("test_versions.Name@v10", Some(json!({"type": "boolean"}))).unwrap();
+    set_user_schema("test_versions.NameOther", Some(json!({"type": "string"}))).unwrap();
+    assert!(set_user_schema("test_versions.Name@latest", Some(json!({}))).is_err());
+
+    let versions: Vec<String> = get_schema_versions("test_versions.Name")
+      .into_iter()
+      .map(|s| s.name)
+      .collect();
+    assert_eq!(
+      vec![
+        "test_versions.Name",
+        "test_versions.Name@v2",
+        "test_versions.Name@v10"
+      ],
+      versions
+    );
+
+    // Candidates compile against the registry w/o being registered.
+    let candidate = compile_user_schema(
+      "test_versions.Name@v3",
+      &json!({"anyOf": [{"$ref": "test_versions.Name"}, {"$ref": "test_versions.Name@v2"}]}),
+    )
+    .unwrap();
+    assert!(candidate.is_valid(&json!("foo")));
+    assert!(candidate.is_valid(&json!(5)));
+    assert!(!candidate.is_valid(&json!(true)));
+    assert!(get_schema("test_versions.Name@v3").is_none());
+
+    for name in [
+      "test_versions.Name",
+      "test_versions.Name@v2",
+      "test_versions.Name@v10",
+      "test_versions.NameOther",
+    ] {
+      set_user_schema(name, None).unwrap();
+    }
+  }
+
+  #[test]
+  fn test_diff_schemas() {
+    let v1 = json!({
+      "type": "object",
+      "properties": {
+        "name": { "type": "string" },
+        "zip": { "type": "string" },
+      },
+      "required": ["name"],
+    });
+    let v2 = json!({
+      "type": "object",
+      "properties": {
+        "name": { "type": "string" },
+        "zip": { "type": "integer" },
+        "a/b": { "type": "string" },
+      },
+      "required": ["name", "zip"],
+    });
+
+    assert!(diff_schemas(&v1, &v1).is_empty());
+    assert_eq!(
+      vec![
+        SchemaChange {
+          path: "/properties/a~1b".to_string(),
+          kind: SchemaChangeKind::Added,
+          old: None,
+          new: Some(json!({"type": "string"})),
+        },
+        SchemaChange {
+          path: "/properties/zip/type".to_string(),
+          kind: SchemaChangeKind::Changed,
+          old: Some(json!("string")),
+          new: Some(json!("integer")),
+        },
+        SchemaChange {
+          path: "/required".to_string(),
+          kind: SchemaChangeKind::Changed,
+          old: Some(json!(["name"])),
+          new: Some(json!(["name", "zip"])),
+        },
+      ],
+      diff_schemas(&v1, &v2)
+    );
+
+    assert_eq!(
+      vec![SchemaChange {
+        path: "/properties/a~1b".to_string(),
+        kind: SchemaChangeKind::Removed,
+        old: Some(json!({"type": "string"})),
+        new: None,
+      }],
+      diff_schemas(
+        &v2,
+        &json!({
+          "type": "object",
+          "properties": {
+            "name": { "type": "string" },
+            "zip": { "type": "integer" },
+          },
+          "required": ["name", "zip"],
+        })
+      )
+    );
+  }
+
+  #[test]
+  fn test_load_schemas_from_dir() {
+    let dir = std::env::temp_dir().join(format!("trailbase_schemas_{}", std::process::id()));
+    assert!(load_schemas_from_dir(&dir).unwrap().is_empty());
+
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("b.Schema.json"), r#"{"type": "string"}"#).unwrap();
+    std::fs::write(dir.join("a.json"), r#"{"$ref": "b.Schema"}"#).unwrap();
+    std::fs::write(dir.join("README.md"), "ignored").unwrap();
+
+    let schemas = load_schemas_from_dir(&dir).unwrap();
+    assert_eq!(
+      vec![
+        ("a".to_string(), json!({"$ref": "b.Schema"})),
+        ("b.Schema".to_string(), json!({"type": "string"})),
+      ],
+      schemas
+    );
+
+    std::fs::write(dir.join("c.json"), "not json").unwrap();
+    assert!(matches!(
+      load_schemas_from_dir(&dir),
+      Err(Error::InvalidSchemaFile(..))
+    ));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
 }
@@ -167,18 +167,25 @@ pub struct UniqueConstraint {
 
 impl UniqueConstraint {
   fn to_fragment(&self) -> String {
+    return self.to_fragment_with_keyword("UNIQUE");
+  }
+
+  fn to_fragment_with_keyword(&self, keyword: &str) -> String {
     let cols = quote(&self.columns);
 
     return match (self.name.as_ref(), &self.conflict_clause.as_ref()) {
       (Some(name), Some(resolution)) => format!(
-        "CONSTRAINT '{name}' UNIQUE ({cols}) ON CONFLICT {}",
+        "CONSTRAINT '{name}' {keyword} ({cols}) ON CONFLICT {}",
         resolution.to_fragment()
       ),
-      (Some(name), None) => format!("CONSTRAINT '{name}' UNIQUE ({cols})"),
+      (Some(name), None) => format!("CONSTRAINT '{name}' {keyword} ({cols})"),
       (None, Some(resolution)) => {
-        format!("UNIQUE ({cols}) ON CONFLICT {}", resolution.to_fragment())
+        format!(
+          "{keyword} ({cols}) ON CONFLICT {}",
+          resolution.to_fragment()
+        )
       }
-      (None, None) => format!("UNIQUE ({cols})"),
+      (None, None) => format!("{keyword} ({cols})"),
     };
   }
 }
@@ -464,7 +471,7 @@ pub struct Column {
 }
 
 impl Column {
-  fn to_fragment(&self) -> String {
+  pub(crate) fn to_fragment(&self) -> String {
     let options: Vec<String> = self.options.iter().map(|o| o.to_fragment()).collect();
 
     return if options.is_empty() {
@@ -502,6 +509,14 @@ impl Column {
       |opt| matches!(opt, ColumnOption::Unique { is_primary, conflict_clause: _ } if *is_primary ),
     );
   }
+
+  /// Whether the column is computed, i.e. `GENERATED ALWAYS AS (...)`, and thus cannot be written.
+  pub fn is_generated(&self) -> bool {
+    return self
+      .options
+      .iter()
+      .any(|opt| matches!(opt, ColumnOption::Generated { .. }));
+  }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, TS, PartialEq)]
@@ -518,10 +533,152 @@ pub struct Table {
   pub foreign_keys: Vec<ForeignKey>,
   pub unique: Vec<UniqueConstraint>,
   pub checks: Vec<Check>,
+  /// Table-level, e.g. composite, primary key. Column-level primary keys are column options.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  #[ts(optional)]
+  pub primary_key: Option<UniqueConstraint>,
 
   // NOTE: consider parsing "CREATE VIRTUAL TABLE" into a separate struct.
   pub virtual_table: bool,
   pub temporary: bool,
+
+  /// Set for FTS5 full-text search virtual tables.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  #[ts(optional)]
+  pub fts5: Option<Fts5Table>,
+
+  /// Set for sqlite-vec vector index virtual tables.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  #[ts(optional)]
+  pub vec0: Option<Vec0Table>,
+
+  /// Set for R*Tree spatial index virtual tables.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  #[ts(optional)]
+  pub rtree: Option<RtreeTable>,
+}
+
+/// Options of a "CREATE VIRTUAL TABLE ... USING fts5(...)" full-text search table.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, TS, PartialEq)]
+#[ts(export)]
+pub struct Fts5Table {
+  /// Indexed columns.
+  pub columns: Vec<String>,
+  /// External content table, i.e. "content='<table>'". None for regular and contentless tables.
+  pub content: Option<String>,
+  /// Column of the content table the FTS rowid maps to, i.e. "content_rowid='<column>'".
+  /// Defaults to the content table's rowid.
+  pub content_rowid: Option<String>,
+}
+
+impl Fts5Table {
+  /// Parses the module arguments, e.g. ["title", "body", "content='post'"].
+  fn from_args(args: &[String]) -> Self {
+    let mut fts5 = Self::default();
+    for arg in args {
+      match arg.split_once('=') {
+        Some((key, value)) => {
+          let value = unquote_string(value.trim().to_string());
+          match key.trim().to_ascii_lowercase().as_str() {
+            "content" if !value.is_empty() => fts5.content = Some(value),
+            "content_rowid" => fts5.content_rowid = Some(value),
+            _ => {}
+          }
+        }
+        None => {
+          // Columns may be followed by "UNINDEXED".
+          if let Some(column) = arg.split_whitespace().next() {
+            fts5.columns.push(unquote_string(column.to_string()));
+          }
+        }
+      }
+    }
+    return fts5;
+  }
+}
+
+/// Options of a "CREATE VIRTUAL TABLE ... USING vec0(...)" sqlite-vec vector index.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, TS, PartialEq)]
+#[ts(export)]
+pub struct Vec0Table {
+  /// Float32 vector columns, e.g. "embedding float[384]". Other column kinds, e.g. auxiliary,
+  /// metadata or partition key columns, are omitted.
+  pub columns: Vec<Vec0Column>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, TS, PartialEq)]
+#[ts(export)]
+pub struct Vec0Column {
+  pub name: String,
+  pub dimensions: usize,
+  /// Declared distance metric, i.e. "distance_metric=<metric>". Defaults to L2.
+  pub distance_metric: Option<String>,
+}
+
+impl Vec0Table {
+  /// Parses the module arguments, e.g. ["embedding float[384] distance_metric=cosine"].
+  fn from_args(args: &[String]) -> Self {
+    let mut vec0 = Self::default();
+    for arg in args {
+      let mut tokens = arg.split_whitespace();
+      let (Some(name), Some(data_type)) = (tokens.next(), tokens.next()) else {
+        continue;
+      };
+
+      let data_type = data_type.to_ascii_lowercase();
+      let Some(dimensions) = data_type
+        .strip_prefix("float[")
+        .or_else(|| data_type.strip_prefix("f32["))
+        .and_then(|rest| rest.strip_suffix(']'))
+        .and_then(|dimensions| dimensions.trim().parse::<usize>().ok())
+      else {
+        continue;
+      };
+
+      // Strip whitespace to also accept "distance_metric = cosine".
+      let options: String = tokens.collect();
+      let distance_metric = options
+        .split_once('=')
+        .filter(|(key, _)| key.eq_ignore_ascii_case("distance_metric"))
+        .map(|(_, value)| unquote_string(value.to_ascii_lowercase()));
+
+      vec0.columns.push(Vec0Column {
+        name: unquote_string(name.to_string()),
+        dimensions,
+        distance_metric,
+      });
+    }
+    return vec0;
+  }
+}
+
+/// Columns of a "CREATE VIRTUAL TABLE ... USING rtree(...)" spatial index.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, TS, PartialEq)]
+#[ts(export)]
+pub struct RtreeTable {
+  /// Integer primary key column.
+  pub id: String,
+  /// Lower and upper bound columns per dimension, e.g. [("min_x", "max_x"), ("min_y", "max_y")].
+  pub dimensions: Vec<(String, String)>,
+}
+
+impl RtreeTable {
+  /// Parses the module arguments, e.g. ["id", "min_x", "max_x", "min_y", "max_y", "+name"].
+  fn from_args(args: &[String]) -> Self {
+    // Auxiliary columns, i.e. "+<name>", are skipped.
+    let mut columns = args
+      .iter()
+      .filter_map(|arg| arg.split_whitespace().next())
+      .filter(|column| !column.starts_with('+'))
+      .map(|column| unquote_string(column.to_string()));
+
+    let id = columns.next().unwrap_or_default();
+    let mut dimensions = vec![];
+    while let (Some(min), Some(max)) = (columns.next(), columns.next()) {
+      dimensions.push((min, max));
+    }
+    return Self { id, dimensions };
+  }
 }
 
 impl Table {
@@ -535,6 +692,14 @@ impl Table {
 
     column_defs_and_table_constraints.extend(self.columns.iter().map(|c| c.to_fragment()));
 
+    // Example: PRIMARY KEY (org, member)
+    column_defs_and_table_constraints.extend(
+      self
+        .primary_key
+        .iter()
+        .map(|pk| pk.to_fragment_with_keyword("PRIMARY KEY")),
+    );
+
     // Example: UNIQUE (email),
     column_defs_and_table_constraints.extend(self.unique.iter().map(|unique| unique.to_fragment()));
 
@@ -645,6 +810,7 @@ impl TryFrom<sqlite3_parser::ast::Stmt> for Table {
         let mut foreign_keys: Vec<ForeignKey> = vec![];
         let mut unique: Vec<UniqueConstraint> = vec![];
         let mut checks: Vec<Check> = vec![];
+        let mut primary_key: Option<UniqueConstraint> = None;
 
         for constraint in constraints.unwrap_or_default() {
           match constraint.constraint {
@@ -676,8 +842,16 @@ impl TryFrom<sqlite3_parser::ast::Stmt> for Table {
                 expr: expr.to_string(),
               });
             }
-            TableConstraint::PrimaryKey { .. } => {
-              warn!("PK table constraint not implemented. Use column constraints.");
+            TableConstraint::PrimaryKey {
+              columns,
+              conflict_clause,
+              ..
+            } => {
+              primary_key = Some(UniqueConstraint {
+                name: constraint.name.map(unquote_name),
+                columns: columns.into_iter().map(|c| unquote_expr(c.expr)).collect(),
+                conflict_clause: conflict_clause.map(|c| c.into()),
+              });
             }
           }
         }
@@ -720,24 +894,39 @@ impl TryFrom<sqlite3_parser::ast::Stmt> for Table {
           foreign_keys,
           unique,
           checks,
+          primary_key,
           virtual_table: false,
           temporary,
+          fts5: None,
+          vec0: None,
+          rtree: None,
         })
       }
       Stmt::CreateVirtualTable {
         tbl_name,
-        args: _args,
+        module_name,
+        args,
         ..
-      } => Ok(Table {
-        name: unquote_qualified(tbl_name),
-        strict: false,
-        columns: vec![],
-        foreign_keys: vec![],
-        unique: vec![],
-        checks: vec![],
-        virtual_table: true,
-        temporary: false,
-      }),
+      } => {
+        let module = unquote_name(module_name).to_ascii_lowercase();
+        let args = args.as_deref().unwrap_or_default();
+
+        Ok(Table {
+          name: unquote_qualified(tbl_name),
+          strict: false,
+          columns: vec![],
+          foreign_keys: vec![],
+          unique: vec![],
+          checks: vec![],
+          primary_key: None,
+          virtual_table: true,
+          temporary: false,
+          fts5: (module == "fts5").then(|| Fts5Table::from_args(args)),
+          vec0: (module == "vec0").then(|| Vec0Table::from_args(args)),
+          rtree: matches!(module.as_str(), "rtree" | "rtree_i32")
+            .then(|| RtreeTable::from_args(args)),
+        })
+      }
       _ => Err(SchemaError::Precondition(
         format!("expected 'CREATE [VIRTUAL] TABLE', got: {value:?}").into(),
       )),
@@ -859,6 +1048,20 @@ impl std::fmt::Display for SelectFormatter {
 }
 
 impl View {
+  pub fn create_view_statement(&self) -> String {
+    return format!(
+      r#"CREATE{temporary} VIEW{if_not_exists} "{name}" AS {query}"#,
+      temporary = if self.temporary { " TEMPORARY" } else { "" },
+      if_not_exists = if self.if_not_exists {
+        " IF NOT EXISTS"
+      } else {
+        ""
+      },
+      name = self.name,
+      query = self.query,
+    );
+  }
+
   pub fn from(value: sqlite3_parser::ast::Stmt, tables: &[Table]) -> Result<Self, SchemaError> {
     return match value {
       sqlite3_parser::ast::Stmt::CreateView {
@@ -896,6 +1099,164 @@ impl View {
   }
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize, TS, PartialEq)]
+pub enum TriggerTime {
+  Before,
+  After,
+  InsteadOf,
+}
+
+impl TriggerTime {
+  // https://www.sqlite.org/lang_createtrigger.html
+  fn to_fragment(&self) -> &'static str {
+    return match self {
+      Self::Before => "BEFORE",
+      Self::After => "AFTER",
+      Self::InsteadOf => "INSTEAD OF",
+    };
+  }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, TS, PartialEq)]
+pub enum TriggerEvent {
+  Delete,
+  Insert,
+  /// Fires on updates of the given columns or any column if empty.
+  Update(Vec<String>),
+}
+
+impl TriggerEvent {
+  fn to_fragment(&self) -> String {
+    return match self {
+      Self::Delete => "DELETE".to_string(),
+      Self::Insert => "INSERT".to_string(),
+      Self::Update(columns) if columns.is_empty() => "UPDATE".to_string(),
+      Self::Update(columns) => format!(
+        "UPDATE OF {}",
+        columns.iter().map(|c| format!(r#""{c}""#)).join(", ")
+      ),
+    };
+  }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, TS, PartialEq)]
+#[ts(export)]
+pub struct Trigger {
+  pub name: String,
+  pub table_name: String,
+
+  /// Defaults to BEFORE if absent.
+  pub time: Option<TriggerTime>,
+  pub event: TriggerEvent,
+  pub for_each_row: bool,
+  pub when: Option<String>,
+
+  /// Statements of the trigger's body, i.e. between BEGIN and END.
+  pub body: Vec<String>,
+
+  pub temporary: bool,
+
+  #[ts(skip)]
+  #[serde(default)]
+  pub if_not_exists: bool,
+}
+
+impl Trigger {
+  pub fn create_trigger_statement(&self) -> String {
+    return format!(
+      r#"CREATE{temporary} TRIGGER{if_not_exists} "{name}"{time} {event} ON "{table_name}"{for_each_row}{when} BEGIN {body}; END"#,
+      temporary = if self.temporary { " TEMPORARY" } else { "" },
+      if_not_exists = if self.if_not_exists {
+        " IF NOT EXISTS"
+      } else {
+        ""
+      },
+      name = self.name,
+      time = self
+        .time
+        .as_ref()
+        .map_or_else(|| "".to_string(), |t| format!(" {}", t.to_fragment())),
+      event = self.event.to_fragment(),
+      table_name = self.table_name,
+      for_each_row = if self.for_each_row {
+        " FOR EACH ROW"
+      } else {
+        ""
+      },
+      when = self
+        .when
+        .as_ref()
+        .map_or_else(|| "".to_string(), |w| format!(" WHEN {w}")),
+      body = self.body.join("; "),
+    );
+  }
+}
+
+struct TokensFormatter<'a, T: ToTokens>(&'a T);
+
+impl<T: ToTokens> std::fmt::Display for TokensFormatter<'_, T> {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    self.0.to_fmt(f)
+  }
+}
+
+fn build_trigger_event(event: &sqlite3_parser::ast::TriggerEvent) -> TriggerEvent {
+  use sqlite3_parser::ast::TriggerEvent as Event;
+  return match event {
+    Event::Delete => TriggerEvent::Delete,
+    Event::Insert => TriggerEvent::Insert,
+    Event::Update => TriggerEvent::Update(vec![]),
+    Event::UpdateOf(columns) => {
+      TriggerEvent::Update(columns.iter().map(|c| unquote_name(c.clone())).collect())
+    }
+  };
+}
+
+impl TryFrom<sqlite3_parser::ast::Stmt> for Trigger {
+  type Error = SchemaError;
+
+  fn try_from(value: sqlite3_parser::ast::Stmt) -> Result<Self, Self::Error> {
+    use sqlite3_parser::ast::TriggerTime as Time;
+
+    return match value {
+      Stmt::CreateTrigger {
+        temporary,
+        if_not_exists,
+        trigger_name,
+        time,
+        event,
+        tbl_name,
+        for_each_row,
+        when_clause,
+        commands,
+      } => Ok(Trigger {
+        name: unquote_qualified(trigger_name),
+        table_name: unquote_name(tbl_name),
+        time: time.map(|time| match time {
+          Time::Before => TriggerTime::Before,
+          Time::After => TriggerTime::After,
+          Time::InsteadOf => TriggerTime::InsteadOf,
+        }),
+        event: build_trigger_event(&event),
+        for_each_row,
+        when: when_clause.map(|expr| {
+          // NOTE: this is deliberately not unquoting.
+          expr.to_string()
+        }),
+        body: commands
+          .iter()
+          .map(|cmd| TokensFormatter(cmd).to_string())
+          .collect(),
+        temporary,
+        if_not_exists,
+      }),
+      _ => Err(SchemaError::Precondition(
+        format!("expected 'CREATE TRIGGER', got: {value:?}").into(),
+      )),
+    };
+  }
+}
+
 fn to_entry(qn: QualifiedName, alias: Option<sqlite3_parser::ast::As>) -> (String, String) {
   return (
     alias
@@ -925,6 +1286,208 @@ struct ColumnMapping {
   referred_column: Option<ReferredColumn>,
 }
 
+/// A table referenced by a view's FROM clause.
+struct ViewSource<'a> {
+  table: &'a Table,
+  /// Whether the table's columns may be NULL regardless of their constraints, e.g. the right-hand
+  /// side of a LEFT JOIN.
+  nullable: bool,
+  /// Whether the table is the one driving the SELECT, i.e. the first one in the FROM clause. PKs of
+  /// joined tables aren't propagated, since they're not necessarily unique within the view.
+  primary: bool,
+}
+
+impl ViewSource<'_> {
+  fn map_column(&self, column: &Column, name: String) -> ColumnMapping {
+    let options = column
+      .options
+      .iter()
+      .filter(|o| match o {
+        ColumnOption::NotNull => !self.nullable,
+        ColumnOption::Unique { .. } => self.primary && !self.nullable,
+        _ => true,
+      })
+      .cloned()
+      .collect();
+
+    return ColumnMapping {
+      column: Column {
+        name,
+        data_type: column.data_type,
+        options,
+      },
+      referred_column: Some(ReferredColumn {
+        table_name: self.table.name.clone(),
+        column_name: column.name.clone(),
+      }),
+    };
+  }
+}
+
+/// Type and nullability of a view's result column expression, i.e. `None` data type for NULL.
+struct ExprType {
+  data_type: Option<ColumnDataType>,
+  not_null: bool,
+}
+
+fn alias_name(alias: Option<sqlite3_parser::ast::As>) -> Option<String> {
+  return alias.and_then(|alias| {
+    if let sqlite3_parser::ast::As::As(name) = alias {
+      return Some(unquote_name(name));
+    }
+    None
+  });
+}
+
+/// Resolves a (qualified) column reference against the view's sources.
+fn resolve_column<'a>(
+  sources: &'a indexmap::IndexMap<String, ViewSource<'a>>,
+  qualifier: Option<&str>,
+  column_name: &str,
+) -> Result<Option<(&'a ViewSource<'a>, &'a Column)>, SchemaError> {
+  if let Some(qualifier) = qualifier {
+    let Some(source) = sources.get(qualifier) else {
+      return Err(SchemaError::Precondition(
+        format!("Missing table with qualifier: {qualifier}").into(),
+      ));
+    };
+    let Some(column) = source.table.columns.iter().find(|c| c.name == column_name) else {
+      return Err(SchemaError::Precondition(
+        format!("Missing col: {column_name}").into(),
+      ));
+    };
+    return Ok(Some((source, column)));
+  }
+
+  let mut candidates = sources.values().filter_map(|source| {
+    let column = source
+      .table
+      .columns
+      .iter()
+      .find(|c| c.name == column_name)?;
+    return Some((source, column));
+  });
+  let Some(candidate) = candidates.next() else {
+    return Err(SchemaError::Precondition(
+      format!("Missing columns: {column_name}").into(),
+    ));
+  };
+  if candidates.next().is_some() {
+    info!("Skipping view: ambiguous column: {column_name}");
+    return Ok(None);
+  }
+  return Ok(Some(candidate));
+}
+
+/// Infers the type of common expressions, e.g. column references, literals, casts and
+/// COALESCE/IFNULL. Returns `None` for anything else.
+fn infer_expr_type(
+  sources: &indexmap::IndexMap<String, ViewSource<'_>>,
+  expr: &Expr,
+) -> Result<Option<ExprType>, SchemaError> {
+  let from_column = |source: &ViewSource, column: &Column| ExprType {
+    data_type: Some(column.data_type),
+    not_null: !source.nullable && column.is_not_null(),
+  };
+
+  return Ok(match expr {
+    Expr::Id(id) => resolve_column(sources, None, &unquote_id(id.clone()))?
+      .map(|(source, column)| from_column(source, column)),
+    Expr::Qualified(qualifier, name) => resolve_column(
+      sources,
+      Some(&unquote_name(qualifier.clone())),
+      &unquote_name(name.clone()),
+    )?
+    .map(|(source, column)| from_column(source, column)),
+    Expr::Literal(literal) => match literal {
+      Literal::Numeric(n) => Some(ExprType {
+        data_type: Some(match n.parse::<i64>() {
+          Ok(_) => ColumnDataType::Integer,
+          Err(_) => ColumnDataType::Real,
+        }),
+        not_null: true,
+      }),
+      Literal::String(_) => Some(ExprType {
+        data_type: Some(ColumnDataType::Text),
+        not_null: true,
+      }),
+      Literal::Blob(_) => Some(ExprType {
+        data_type: Some(ColumnDataType::Blob),
+        not_null: true,
+      }),
+      Literal::Null => Some(ExprType {
+        data_type: None,
+        not_null: false,
+      }),
+      _ => None,
+    },
+    Expr::Parenthesized(exprs) if exprs.len() == 1 => infer_expr_type(sources, &exprs[0])?,
+    Expr::Cast { expr, type_name } => {
+      let Some(data_type) = type_name
+        .as_ref()
+        .and_then(|t| ColumnDataType::from_type_name(&t.name))
+      else {
+        return Err(SchemaError::Precondition(
+          "Missing type_name in cast".into(),
+        ));
+      };
+
+      // CAST(NULL AS T) is NULL, otherwise the cast preserves nullability.
+      let not_null = infer_expr_type(sources, expr)?.is_some_and(|t| t.not_null);
+      Some(ExprType {
+        data_type: Some(data_type),
+        not_null,
+      })
+    }
+    Expr::FunctionCall {
+      name,
+      args: Some(args),
+      ..
+    } if ["coalesce", "ifnull"].contains(&unquote_id(name.clone()).to_lowercase().as_str()) => {
+      let mut data_type: Option<ColumnDataType> = None;
+      let mut not_null = false;
+      for arg in args {
+        let Some(arg_type) = infer_expr_type(sources, arg)? else {
+          return Ok(None);
+        };
+
+        match (data_type, arg_type.data_type) {
+          (_, None) => {}
+          (None, Some(t)) => data_type = Some(t),
+          (Some(a), Some(b)) if a == b => {}
+          // Mixed types.
+          _ => return Ok(None),
+        };
+        not_null |= arg_type.not_null;
+      }
+
+      data_type.map(|t| ExprType {
+        data_type: Some(t),
+        not_null,
+      })
+    }
+    _ => None,
+  });
+}
+
+/// Looks up a table referenced by a view, making sure it exists and is strict.
+fn lookup_view_table<'a>(
+  all_tables: &HashMap<&str, &'a Table>,
+  table_name: &str,
+) -> Result<Option<&'a Table>, SchemaError> {
+  let Some(table) = all_tables.get(table_name).copied() else {
+    return Err(SchemaError::Precondition(
+      format!("View's SELECT references missing table: {table_name}").into(),
+    ));
+  };
+
+  if !table.strict {
+    info!("Skipping view: referenced table: {table_name} not strict");
+    return Ok(None);
+  }
+  return Ok(Some(table));
+}
+
 fn try_extract_column_mapping(
   select: sqlite3_parser::ast::Select,
   tables: &[Table],
@@ -962,8 +1525,24 @@ fn try_extract_column_mapping(
     return Ok(None);
   };
 
+  let all_tables: HashMap<&str, &Table> = tables.iter().map(|t| (t.name.as_str(), t)).collect();
+
   // Use IndexMap to preserve insertion order.
-  let mut table_names = indexmap::IndexMap::<String, String>::from([to_entry(fqn, alias)]);
+  let mut sources = indexmap::IndexMap::<String, ViewSource>::new();
+  {
+    let (alias, table_name) = to_entry(fqn, alias);
+    let Some(table) = lookup_view_table(&all_tables, &table_name)? else {
+      return Ok(None);
+    };
+    sources.insert(
+      alias,
+      ViewSource {
+        table,
+        nullable: false,
+        primary: true,
+      },
+    );
+  }
 
   if let Some(joins) = joins {
     for join in joins {
@@ -971,181 +1550,112 @@ fn try_extract_column_mapping(
         return Ok(None);
       };
 
-      let entry = to_entry(fqn, alias);
-      table_names.insert(entry.0, entry.1);
-    }
-  }
-
-  // Now we should have a map of all involved tables and their aliases (if any).
-  let all_tables: HashMap<String, &Table> = tables.iter().map(|t| (t.name.clone(), t)).collect();
-  let mut all_columns = HashMap::<String, (&Table, &Column)>::new();
-
-  // Make sure we know all tables and all tables are strict.
-  for table_name in table_names.values() {
-    match all_tables.get(table_name) {
-      Some(table) => {
-        if !table.strict {
-          info!("Skipping view: referenced table: {table_name} not strict");
-          return Ok(None);
+      // Outer joins make the columns of the respective other side nullable.
+      let operator = TokensFormatter(&join.operator).to_string().to_uppercase();
+      let (left_nullable, right_nullable) = (
+        operator.contains("RIGHT") || operator.contains("FULL"),
+        operator.contains("LEFT") || operator.contains("FULL"),
+      );
+      if left_nullable {
+        for source in sources.values_mut() {
+          source.nullable = true;
         }
-
-        for col in &table.columns {
-          all_columns.insert(col.name.clone(), (table, col));
-        }
-      }
-      None => {
-        return Err(SchemaError::Precondition(
-          format!("View's SELECT references missing table: {table_name}").into(),
-        ));
       }
-    };
+
+      let (alias, table_name) = to_entry(fqn, alias);
+      let Some(table) = lookup_view_table(&all_tables, &table_name)? else {
+        return Ok(None);
+      };
+      sources.insert(
+        alias,
+        ViewSource {
+          table,
+          nullable: right_nullable,
+          primary: false,
+        },
+      );
+    }
   }
 
+  // Now we should have a map of all involved tables and their aliases (if any).
   let mut mapping: Vec<ColumnMapping> = vec![];
   for col in columns {
-    use sqlite3_parser::ast::Expr;
     use sqlite3_parser::ast::ResultColumn;
 
     match col {
       ResultColumn::Star => {
-        for table_name in table_names.values() {
-          let table = all_tables.get(table_name).expect("checked above");
-          for c in &table.columns {
-            mapping.push(ColumnMapping {
-              column: c.clone(),
-              referred_column: Some(ReferredColumn {
-                table_name: table.name.clone(),
-                column_name: c.name.clone(),
-              }),
-            });
+        for source in sources.values() {
+          for c in &source.table.columns {
+            mapping.push(source.map_column(c, c.name.clone()));
           }
         }
       }
       ResultColumn::TableStar(name) => {
         let name = unquote_name(name);
-        let Some(table_name) = table_names.get(&name) else {
+        let Some(source) = sources.get(&name) else {
           return Err(SchemaError::Precondition(
             format!("Missing alias: {name}").into(),
           ));
         };
 
-        let table = all_tables.get(table_name).expect("checked above");
-        for c in &table.columns {
-          mapping.push(ColumnMapping {
-            column: c.clone(),
-            referred_column: Some(ReferredColumn {
-              table_name: table.name.clone(),
-              column_name: c.name.clone(),
-            }),
-          });
+        for c in &source.table.columns {
+          mapping.push(source.map_column(c, c.name.clone()));
         }
       }
-      ResultColumn::Expr(expr, alias) => match expr {
-        Expr::Id(id) => {
-          let col_name = unquote_id(id.clone());
-          let Some((table, column)) = all_columns.get(&col_name) else {
-            return Err(SchemaError::Precondition(
-              format!("Missing columns: {id:?}").into(),
-            ));
-          };
-
-          let name = alias
-            .and_then(|alias| {
-              if let sqlite3_parser::ast::As::As(name) = alias {
-                return Some(unquote_name(name));
-              }
-              None
-            })
-            .unwrap_or_else(|| column.name.clone());
-
-          mapping.push(ColumnMapping {
-            column: Column {
-              name,
-              data_type: column.data_type,
-              options: column.options.clone(),
-            },
-            referred_column: Some(ReferredColumn {
-              table_name: table.name.clone(),
-              column_name: column.name.clone(),
-            }),
-          });
-        }
-        Expr::Qualified(qualifier, name) => {
-          let qualifier = unquote_name(qualifier);
-          let col_name = unquote_name(name);
-
-          let Some(table_name) = table_names.get(&qualifier) else {
-            return Err(SchemaError::Precondition(
-              format!("Missing table with qualifier: {qualifier}").into(),
-            ));
-          };
+      ResultColumn::Expr(expr, alias) => {
+        let column_ref = match &expr {
+          Expr::Id(id) => Some((None, unquote_id(id.clone()))),
+          Expr::Qualified(qualifier, name) => Some((
+            Some(unquote_name(qualifier.clone())),
+            unquote_name(name.clone()),
+          )),
+          _ => None,
+        };
 
-          let table = all_tables.get(table_name).expect("checked above");
-          let Some(column) = table.columns.iter().find(|c| c.name == col_name) else {
-            return Err(SchemaError::Precondition(
-              format!("Missing col: {col_name}").into(),
-            ));
+        // Plain column references retain the referred column's constraints, e.g. the PK.
+        if let Some((qualifier, column_name)) = column_ref {
+          let Some((source, column)) =
+            resolve_column(&sources, qualifier.as_deref(), &column_name)?
+          else {
+            return Ok(None);
           };
 
-          let name = alias
-            .and_then(|alias| {
-              if let sqlite3_parser::ast::As::As(name) = alias {
-                return Some(unquote_name(name));
-              }
-              None
-            })
-            .unwrap_or_else(|| column.name.clone());
-
-          mapping.push(ColumnMapping {
-            column: Column {
-              name,
-              data_type: column.data_type,
-              options: column.options.clone(),
-            },
-            referred_column: Some(ReferredColumn {
-              table_name: table.name.clone(),
-              column_name: column.name.clone(),
-            }),
-          });
+          let name = alias_name(alias).unwrap_or_else(|| column.name.clone());
+          mapping.push(source.map_column(column, name));
+          continue;
         }
-        Expr::Cast { expr: _, type_name } => {
-          let Some(type_name) = type_name else {
-            return Err(SchemaError::Precondition(
-              "Missing type_name in cast".into(),
-            ));
-          };
-          let Some(data_type) = ColumnDataType::from_type_name(&type_name.name) else {
-            return Err(SchemaError::Precondition(
-              "Missing type_name in cast".into(),
-            ));
-          };
-
-          let Some(name) = alias.and_then(|alias| {
-            if let sqlite3_parser::ast::As::As(name) = alias {
-              return Some(unquote_name(name));
-            }
-            None
-          }) else {
-            return Err(SchemaError::Precondition("Missing alias in cast".into()));
-          };
 
-          mapping.push(ColumnMapping {
-            column: Column {
-              name,
-              data_type,
-              options: vec![ColumnOption::Null],
-            },
-            referred_column: None,
-          });
-        }
-        _x => {
+        let Some(ExprType {
+          data_type: Some(data_type),
+          not_null,
+        }) = infer_expr_type(&sources, &expr)?
+        else {
           // We cannot map arbitrary expressions.
           #[cfg(debug_assertions)]
-          debug!("skipping expr: {_x:?}");
+          debug!("skipping expr: {expr:?}");
 
           return Ok(None);
-        }
-      },
+        };
+
+        // Expressions w/o alias are named by SQLite after their textual representation.
+        let Some(name) = alias_name(alias) else {
+          info!("Skipping view: missing alias for: {expr}");
+          return Ok(None);
+        };
+
+        mapping.push(ColumnMapping {
+          column: Column {
+            name,
+            data_type,
+            options: vec![if not_null {
+              ColumnOption::NotNull
+            } else {
+              ColumnOption::Null
+            }],
+          },
+          referred_column: None,
+        });
+      }
     };
   }
 
@@ -1429,7 +1939,51 @@ mod tests {
       END
     "#;
 
-    sqlite3_parse_into_statement(SQL).unwrap().unwrap();
+    let statement = sqlite3_parse_into_statement(SQL).unwrap().unwrap();
+    let trigger: Trigger = statement.try_into().unwrap();
+    assert_eq!(trigger.name, "cust_addr_chng");
+    assert_eq!(trigger.table_name, "customer_address");
+    assert_eq!(trigger.time, Some(TriggerTime::InsteadOf));
+    assert_eq!(
+      trigger.event,
+      TriggerEvent::Update(vec!["cust_addr".to_string()])
+    );
+    assert!(trigger.for_each_row);
+    assert_eq!(trigger.body.len(), 1);
+
+    let sql1 = trigger.create_trigger_statement();
+    let trigger1: Trigger = sqlite3_parse_into_statement(&sql1)
+      .unwrap()
+      .unwrap()
+      .try_into()
+      .unwrap();
+    assert_eq!(trigger, trigger1, "Parsed: {sql1}");
+
+    const SQL2: &str = r#"
+      CREATE TRIGGER IF NOT EXISTS "log_delete" AFTER DELETE ON "post"
+        WHEN OLD.author IS NOT NULL
+      BEGIN
+        INSERT INTO log (msg) VALUES ('deleted');
+        DELETE FROM comment WHERE post = OLD.id;
+      END
+    "#;
+    let trigger2: Trigger = sqlite3_parse_into_statement(SQL2)
+      .unwrap()
+      .unwrap()
+      .try_into()
+      .unwrap();
+    assert_eq!(trigger2.event, TriggerEvent::Delete);
+    assert_eq!(trigger2.body.len(), 2);
+    assert!(trigger2.when.is_some());
+    assert!(!trigger2.for_each_row);
+
+    let sql3 = trigger2.create_trigger_statement();
+    let trigger3: Trigger = sqlite3_parse_into_statement(&sql3)
+      .unwrap()
+      .unwrap()
+      .try_into()
+      .unwrap();
+    assert_eq!(trigger2, trigger3, "Parsed: {sql3}");
   }
 
   #[test]
@@ -1484,8 +2038,12 @@ mod tests {
         foreign_keys: vec![],
         unique: vec![],
         checks: vec![],
+        primary_key: None,
         virtual_table: false,
         temporary: false,
+        fts5: None,
+        vec0: None,
+        rtree: None,
       },
       Table {
         name: "articles".to_string(),
@@ -1518,8 +2076,12 @@ mod tests {
         foreign_keys: vec![],
         unique: vec![],
         checks: vec![],
+        primary_key: None,
         virtual_table: false,
         temporary: false,
+        fts5: None,
+        vec0: None,
+        rtree: None,
       },
     ];
 
@@ -1547,4 +2109,87 @@ mod tests {
       ]
     );
   }
+
+  #[test]
+  fn test_view_column_inference() {
+    let parse_table = |sql: &str| -> Table {
+      return sqlite3_parse_into_statement(sql)
+        .unwrap()
+        .unwrap()
+        .try_into()
+        .unwrap();
+    };
+    let tables = vec![
+      parse_table(
+        "CREATE TABLE author (id INTEGER PRIMARY KEY, name TEXT NOT NULL, nick TEXT) STRICT",
+      ),
+      parse_table(
+        r#"CREATE TABLE post (
+          id      BLOB PRIMARY KEY NOT NULL CHECK(is_uuid_v7(id)),
+          author  INTEGER NOT NULL REFERENCES author(id),
+          title   TEXT NOT NULL,
+          score   REAL
+        ) STRICT"#,
+      ),
+    ];
+
+    let extract = |sql: &str| -> Option<Vec<Column>> {
+      let Stmt::Select(select) = sqlite3_parse_into_statement(sql).unwrap().unwrap() else {
+        panic!("Not a select");
+      };
+      return try_extract_column_mapping(*select, &tables)
+        .unwrap()
+        .map(|mapping| mapping.into_iter().map(|m| m.column).collect());
+    };
+
+    let columns = extract(
+      r#"
+        SELECT
+          p.id, p.title AS headline, a.id AS author_id, a.name,
+          COALESCE(a.nick, a.name) AS display_name, IFNULL(p.score, 0.0) AS score,
+          CAST(p.author AS TEXT) AS author_text, (p.title) AS title, 'post' AS kind, 1 AS one
+        FROM post AS p LEFT JOIN author AS a ON p.author = a.id
+      "#,
+    )
+    .unwrap();
+
+    assert_eq!(
+      vec![
+        ("id", ColumnDataType::Blob, true),
+        ("headline", ColumnDataType::Text, true),
+        ("author_id", ColumnDataType::Integer, false),
+        ("name", ColumnDataType::Text, false),
+        ("display_name", ColumnDataType::Text, false),
+        ("score", ColumnDataType::Real, true),
+        ("author_text", ColumnDataType::Text, true),
+        ("title", ColumnDataType::Text, true),
+        ("kind", ColumnDataType::Text, true),
+        ("one", ColumnDataType::Integer, true),
+      ],
+      columns
+        .iter()
+        .map(|c| (c.name.as_str(), c.data_type, c.is_not_null()))
+        .collect::<Vec<_>>()
+    );
+
+    // Only the driving table's PK is propagated.
+    assert!(columns[0].is_primary());
+    assert!(
+      columns[0]
+        .options
+        .contains(&ColumnOption::Check("is_uuid_v7(id)".to_string()))
+    );
+    assert!(!columns[2].is_primary());
+
+    // Inner joins retain NOT NULL.
+    let columns =
+      extract("SELECT a.name, p.title FROM author AS a JOIN post AS p ON p.author = a.id").unwrap();
+    assert!(columns[0].is_not_null() && columns[1].is_not_null());
+
+    // Ambiguous, mixed-type and unaliased expressions cannot be mapped.
+    assert!(extract("SELECT id FROM post JOIN author ON post.author = author.id").is_none());
+    assert!(extract("SELECT COALESCE(score, title) AS x FROM post").is_none());
+    assert!(extract("SELECT COALESCE(score, 0.0) FROM post").is_none());
+    assert!(extract("SELECT score + 1 AS x FROM post").is_none());
+  }
 }
@@ -0,0 +1,569 @@
+use rusqlite::OptionalExtension;
+use std::collections::HashMap;
+
+/// SQLite storage class inferred from a column's declared type.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Kind {
+  Integer,
+  Real,
+  Text,
+  Blob,
+  Any,
+}
+
+impl Kind {
+  /// Follows SQLite's type affinity rules, see https://www.sqlite.org/datatype3.html.
+  fn from_decl_type(decl_type: &str) -> Self {
+    let decl_type = decl_type.to_ascii_uppercase();
+    if decl_type.contains("INT") {
+      return Self::Integer;
+    }
+    if ["CHAR", "CLOB", "TEXT"]
+      .iter()
+      .any(|t| decl_type.contains(t))
+    {
+      return Self::Text;
+    }
+    if decl_type.contains("BLOB") {
+      return Self::Blob;
+    }
+    if ["REAL", "FLOA", "DOUB"]
+      .iter()
+      .any(|t| decl_type.contains(t))
+    {
+      return Self::Real;
+    }
+    return Self::Any;
+  }
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) struct ResultColumn {
+  /// Column name as returned by SQLite, used for deserialization.
+  pub name: String,
+  /// Field name, i.e. `name` without a type override.
+  pub field: String,
+  /// Rust type provided via a `"name: Type"` alias.
+  pub type_override: Option<String>,
+  pub kind: Kind,
+  pub nullable: bool,
+}
+
+#[derive(Debug)]
+pub(crate) struct Analysis {
+  pub columns: Vec<ResultColumn>,
+  /// Storage class per parameter index, starting at 1.
+  pub params: Vec<Kind>,
+  pub readonly: bool,
+}
+
+/// Prepares `query` against an in-memory database initialized from `schema`, which validates
+/// syntax as well as referenced tables and columns, and infers result and parameter types.
+pub(crate) fn analyze(schema: &str, query: &str) -> Result<Analysis, String> {
+  // Use TrailBase's extensions, since schemas may reference custom functions, e.g. in CHECKs.
+  let conn = trailbase_extension::connect_sqlite(None, None)
+    .map_err(|err| format!("Failed to open SQLite: {err}"))?;
+  conn
+    .execute_batch(schema)
+    .map_err(|err| format!("Invalid schema snapshot: {err}"))?;
+
+  let stmt = conn
+    .prepare(query)
+    .map_err(|err| format!("Invalid query: {err}"))?;
+
+  let mut columns = vec![];
+  for (index, column) in stmt.columns().iter().enumerate() {
+    let name = column.name().to_string();
+    let (field, type_override) = match name.split_once(':') {
+      Some((field, ty)) => (field.trim().to_string(), Some(ty.trim().to_string())),
+      None => (name.clone(), None),
+    };
+
+    let origin = (
+      stmt.column_table_name(index).ok().flatten(),
+      stmt.column_origin_name(index).ok().flatten(),
+    );
+    let nullable = match origin {
+      (Some(table), Some(column)) => !is_not_null(&conn, table, column)?,
+      // Expressions may always be NULL.
+      _ => true,
+    };
+
+    columns.push(ResultColumn {
+      name,
+      field,
+      type_override,
+      kind: column.decl_type().map_or(Kind::Any, Kind::from_decl_type),
+      nullable,
+    });
+  }
+
+  let params = infer_param_kinds(&conn, &stmt, query)?;
+
+  return Ok(Analysis {
+    columns,
+    params,
+    readonly: stmt.readonly(),
+  });
+}
+
+fn is_not_null(conn: &rusqlite::Connection, table: &str, column: &str) -> Result<bool, String> {
+  // NOTE: A sole INTEGER PRIMARY KEY aliases the rowid and can thus never be NULL.
+  const QUERY: &str = r#"
+    SELECT
+      "notnull" OR (
+        pk > 0 AND UPPER(type) = 'INTEGER' AND
+        (SELECT COUNT(*) FROM pragma_table_info(?1) WHERE pk > 0) = 1
+      )
+    FROM pragma_table_info(?1) WHERE name = ?2
+  "#;
+
+  return conn
+    .query_row(QUERY, (table, column), |row| row.get::<_, bool>(0))
+    .optional()
+    .map(|not_null| not_null.unwrap_or(false))
+    .map_err(|err| format!("Failed to look up '{table}.{column}': {err}"));
+}
+
+fn column_kind(conn: &rusqlite::Connection, table: &str, column: &str) -> Option<Kind> {
+  return conn
+    .query_row(
+      "SELECT type FROM pragma_table_info(?1) WHERE name = ?2",
+      (table, column),
+      |row| row.get::<_, String>(0),
+    )
+    .ok()
+    .map(|decl_type| Kind::from_decl_type(&decl_type));
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+  Ident(String),
+  Param(String),
+  Punct(String),
+  Other,
+}
+
+impl Token {
+  fn is_keyword(&self, keyword: &str) -> bool {
+    return matches!(self, Token::Ident(ident) if ident.eq_ignore_ascii_case(keyword));
+  }
+
+  fn is_punct(&self, punct: &str) -> bool {
+    return matches!(self, Token::Punct(p) if p == punct);
+  }
+}
+
+/// Minimal SQL lexer, only distinguishing what's needed to infer parameter types.
+fn tokenize(sql: &str) -> Vec<Token> {
+  let chars: Vec<char> = sql.chars().collect();
+  let mut tokens = vec![];
+  let mut i = 0;
+
+  let take_while = |mut i: usize, pred: &dyn Fn(char) -> bool| -> usize {
+    while i < chars.len() && pred(chars[i]) {
+      i += 1;
+    }
+    return i;
+  };
+  let is_ident_char = |c: char| c.is_alphanumeric() || c == '_' || c == '$';
+
+  while i < chars.len() {
+    let c = chars[i];
+    match c {
+      c if c.is_whitespace() => i += 1,
+      '-' if chars.get(i + 1) == Some(&'-') => i = take_while(i, &|c| c != '\n'),
+      '/' if chars.get(i + 1) == Some(&'*') => {
+        i += 2;
+        while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+          i += 1;
+        }
+        i += 2;
+      }
+      '\'' | '"' | '`' | '[' => {
+        let close = if c == '[' { ']' } else { c };
+        let mut text = String::new();
+        i += 1;
+        while i < chars.len() {
+          if chars[i] == close {
+            // Quotes are escaped by doubling them.
+            if close != ']' && chars.get(i + 1) == Some(&close) {
+              text.push(close);
+              i += 2;
+              continue;
+            }
+            break;
+          }
+          text.push(chars[i]);
+          i += 1;
+        }
+        i += 1;
+        tokens.push(if c == '\'' {
+          Token::Other
+        } else {
+          Token::Ident(text)
+        });
+      }
+      '?' => {
+        let end = take_while(i + 1, &|c| c.is_ascii_digit());
+        tokens.push(Token::Param(chars[i..end].iter().collect()));
+        i = end;
+      }
+      ':' | '$' | '@' if chars.get(i + 1).is_some_and(|c| is_ident_char(*c)) => {
+        let end = take_while(i + 1, &is_ident_char);
+        tokens.push(Token::Param(chars[i..end].iter().collect()));
+        i = end;
+      }
+      c if c.is_ascii_digit() => {
+        i = take_while(i, &|c| c.is_alphanumeric() || c == '.');
+        tokens.push(Token::Other);
+      }
+      c if is_ident_char(c) => {
+        let end = take_while(i, &is_ident_char);
+        tokens.push(Token::Ident(chars[i..end].iter().collect()));
+        i = end;
+      }
+      _ => {
+        let two: String = chars[i..(i + 2).min(chars.len())].iter().collect();
+        if ["==", "!=", "<>", "<=", ">=", "||", "<<", ">>"].contains(&two.as_str()) {
+          tokens.push(Token::Punct(two));
+          i += 2;
+        } else {
+          tokens.push(Token::Punct(c.to_string()));
+          i += 1;
+        }
+      }
+    }
+  }
+
+  return tokens;
+}
+
+const RESERVED: &[&str] = &[
+  "WHERE",
+  "JOIN",
+  "LEFT",
+  "RIGHT",
+  "FULL",
+  "INNER",
+  "OUTER",
+  "CROSS",
+  "NATURAL",
+  "ON",
+  "USING",
+  "SET",
+  "VALUES",
+  "DEFAULT",
+  "SELECT",
+  "ORDER",
+  "GROUP",
+  "HAVING",
+  "LIMIT",
+  "WINDOW",
+  "UNION",
+  "EXCEPT",
+  "INTERSECT",
+  "RETURNING",
+  "AND",
+  "OR",
+  "NOT",
+  "IS",
+  "IN",
+  "LIKE",
+  "GLOB",
+  "BETWEEN",
+];
+
+fn is_reserved(token: &Token) -> bool {
+  return RESERVED.iter().any(|k| token.is_keyword(k));
+}
+
+/// Collects tables following FROM, JOIN, INTO and UPDATE, keyed by name and alias.
+fn referenced_tables(tokens: &[Token]) -> HashMap<String, String> {
+  let mut tables = HashMap::new();
+  for (i, token) in tokens.iter().enumerate() {
+    if !["FROM", "JOIN", "INTO", "UPDATE"]
+      .iter()
+      .any(|k| token.is_keyword(k))
+    {
+      continue;
+    }
+
+    let mut j = i + 1;
+    // Skip schema qualifiers, e.g. `main.table`.
+    if tokens.get(j + 1).is_some_and(|t| t.is_punct(".")) {
+      j += 2;
+    }
+    let Some(Token::Ident(table)) = tokens.get(j) else {
+      continue;
+    };
+    if is_reserved(&tokens[j]) {
+      continue;
+    }
+    tables.insert(table.clone(), table.clone());
+
+    let mut k = j + 1;
+    if tokens.get(k).is_some_and(|t| t.is_keyword("AS")) {
+      k += 1;
+    }
+    if let Some(alias @ Token::Ident(name)) = tokens.get(k) {
+      if !is_reserved(alias) {
+        tables.insert(name.clone(), table.clone());
+      }
+    }
+  }
+  return tables;
+}
+
+/// Resolves the (optionally qualified) column reference ending at `tokens[end]`.
+fn column_ref_kind(
+  conn: &rusqlite::Connection,
+  tables: &HashMap<String, String>,
+  tokens: &[Token],
+  start: usize,
+  end: usize,
+) -> Option<Kind> {
+  let Token::Ident(column) = &tokens[end] else {
+    return None;
+  };
+  if is_reserved(&tokens[end]) {
+    return None;
+  }
+
+  let qualifier = match end.checked_sub(start) {
+    Some(2) if tokens[start + 1].is_punct(".") => match &tokens[start] {
+      Token::Ident(q) => Some(q),
+      _ => None,
+    },
+    _ => None,
+  };
+
+  let mut kinds: Vec<Kind> = match qualifier {
+    Some(q) => {
+      let table = tables.get(q).unwrap_or(q);
+      column_kind(conn, table, column).into_iter().collect()
+    }
+    None => {
+      let mut unique: Vec<&String> = tables.values().collect();
+      unique.sort();
+      unique.dedup();
+      unique
+        .into_iter()
+        .filter_map(|table| column_kind(conn, table, column))
+        .collect()
+    }
+  };
+
+  // Ambiguous references are left untyped.
+  return match kinds.len() {
+    1 => kinds.pop(),
+    _ => None,
+  };
+}
+
+fn is_comparison(token: &Token) -> bool {
+  return ["=", "==", "!=", "<>", "<", "<=", ">", ">="]
+    .iter()
+    .any(|op| token.is_punct(op))
+    || ["LIKE", "GLOB", "IS"].iter().any(|k| token.is_keyword(k));
+}
+
+fn infer_param_kinds(
+  conn: &rusqlite::Connection,
+  stmt: &rusqlite::Statement<'_>,
+  query: &str,
+) -> Result<Vec<Kind>, String> {
+  let mut kinds = vec![Kind::Any; stmt.parameter_count()];
+  let tokens = tokenize(query);
+  let tables = referenced_tables(&tokens);
+
+  // Map parameter tokens to their indexes. Anonymous `?` parameters are assigned the next index
+  // after the largest one assigned so far.
+  let mut max_index = 0;
+  let mut indexes: HashMap<usize, usize> = HashMap::new();
+  for (i, token) in tokens.iter().enumerate() {
+    let Token::Param(param) = token else {
+      continue;
+    };
+    let index = match param.as_str() {
+      "?" => max_index + 1,
+      p if p.starts_with('?') => p[1..].parse().unwrap_or(0),
+      p => stmt
+        .parameter_index(p)
+        .map_err(|err| err.to_string())?
+        .unwrap_or(0),
+    };
+    max_index = max_index.max(index);
+    if index > 0 {
+      indexes.insert(i, index);
+    }
+  }
+
+  let mut assign = |index: usize, kind: Option<Kind>| {
+    let (Some(slot), Some(kind)) = (kinds.get_mut(index - 1), kind) else {
+      return;
+    };
+    *slot = match *slot {
+      Kind::Any => kind,
+      existing if existing == kind => kind,
+      // Conflicting uses, leave it to SQLite.
+      _ => Kind::Any,
+    };
+  };
+
+  // Comparisons, e.g. `col = $1`, `t.col > ?` or `?2 = col`.
+  for (&i, &index) in &indexes {
+    if i >= 2 && is_comparison(&tokens[i - 1]) {
+      let start = if i >= 4 && tokens[i - 3].is_punct(".") {
+        i - 4
+      } else {
+        i - 2
+      };
+      assign(index, column_ref_kind(conn, &tables, &tokens, start, i - 2));
+    }
+    if i + 2 < tokens.len() && is_comparison(&tokens[i + 1]) {
+      let end = if tokens.get(i + 3).is_some_and(|t| t.is_punct(".")) && i + 4 < tokens.len() {
+        i + 4
+      } else {
+        i + 2
+      };
+      assign(index, column_ref_kind(conn, &tables, &tokens, i + 2, end));
+    }
+  }
+
+  // Inserted values, e.g. `INSERT INTO t (a, b) VALUES ($1, $2)`.
+  if let Some(into) = tokens.iter().position(|t| t.is_keyword("INTO")) {
+    if let (Some(Token::Ident(table)), true) = (
+      tokens.get(into + 1),
+      tokens.get(into + 2).is_some_and(|t| t.is_punct("(")),
+    ) {
+      let mut columns = vec![];
+      let mut i = into + 3;
+      while let Some(token) = tokens.get(i) {
+        match token {
+          Token::Ident(column) => columns.push(column.clone()),
+          Token::Punct(p) if p == ")" => break,
+          _ => {}
+        }
+        i += 1;
+      }
+
+      if tokens.get(i + 1).is_some_and(|t| t.is_keyword("VALUES")) {
+        let mut position = 0;
+        let mut depth = 0;
+        for (j, token) in tokens.iter().enumerate().skip(i + 2) {
+          match token {
+            Token::Punct(p) if p == "(" => {
+              depth += 1;
+              if depth == 1 {
+                position = 0;
+              }
+            }
+            Token::Punct(p) if p == ")" => {
+              depth -= 1;
+              if depth < 0 {
+                break;
+              }
+            }
+            Token::Punct(p) if p == "," && depth == 1 => position += 1,
+            Token::Param(_) if depth == 1 => {
+              let single_value = tokens[j - 1].is_punct("(") || tokens[j - 1].is_punct(",");
+              let single_value = single_value
+                && tokens
+                  .get(j + 1)
+                  .is_some_and(|t| t.is_punct(")") || t.is_punct(","));
+              if let (true, Some(column), Some(index)) =
+                (single_value, columns.get(position), indexes.get(&j))
+              {
+                assign(*index, column_kind(conn, table, column));
+              }
+            }
+            Token::Ident(_) if depth == 0 => break,
+            _ => {}
+          }
+        }
+      }
+    }
+  }
+
+  return Ok(kinds);
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const SCHEMA: &str = r#"
+    CREATE TABLE user (
+      id      INTEGER PRIMARY KEY,
+      email   TEXT NOT NULL,
+      name    TEXT,
+      avatar  BLOB
+    ) STRICT;
+    CREATE TABLE post (
+      id      INTEGER PRIMARY KEY,
+      author  INTEGER NOT NULL REFERENCES user(id),
+      score   REAL NOT NULL
+    ) STRICT;
+  "#;
+
+  #[test]
+  fn test_result_columns() {
+    let analysis = analyze(
+      SCHEMA,
+      r#"SELECT u.id, u.name, p.score, COUNT(*) AS "count: i64" FROM user AS u JOIN post AS p ON p.author = u.id GROUP BY u.id"#,
+    )
+    .unwrap();
+
+    assert!(analysis.readonly);
+    let columns: Vec<_> = analysis
+      .columns
+      .iter()
+      .map(|c| {
+        (
+          c.field.as_str(),
+          c.kind,
+          c.nullable,
+          c.type_override.as_deref(),
+        )
+      })
+      .collect();
+    assert_eq!(
+      columns,
+      vec![
+        ("id", Kind::Integer, false, None),
+        ("name", Kind::Text, true, None),
+        ("score", Kind::Real, false, None),
+        ("count", Kind::Any, true, Some("i64")),
+      ]
+    );
+  }
+
+  #[test]
+  fn test_param_kinds() {
+    let analysis = analyze(
+      SCHEMA,
+      "SELECT id FROM user AS u WHERE u.email = $1 AND ?2 < id AND name LIKE :name",
+    )
+    .unwrap();
+    assert_eq!(analysis.params, vec![Kind::Text, Kind::Integer, Kind::Text]);
+
+    let analysis = analyze(
+      SCHEMA,
+      "INSERT INTO post (author, score) VALUES (?, ?) RETURNING id",
+    )
+    .unwrap();
+    assert!(!analysis.readonly);
+    assert_eq!(analysis.params, vec![Kind::Integer, Kind::Real]);
+
+    let analysis = analyze(SCHEMA, "UPDATE user SET avatar = $1 WHERE id = $2").unwrap();
+    assert_eq!(analysis.params, vec![Kind::Blob, Kind::Integer]);
+  }
+
+  #[test]
+  fn test_invalid_queries() {
+    assert!(analyze(SCHEMA, "SELECT missing FROM user").is_err());
+    assert!(analyze(SCHEMA, "SELECT * FROM missing").is_err());
+    assert!(analyze(SCHEMA, "SELEC 1").is_err());
+  }
+}
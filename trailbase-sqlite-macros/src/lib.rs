@@ -0,0 +1,173 @@
+#![forbid(unsafe_code, clippy::unwrap_used)]
+#![allow(clippy::needless_return)]
+
+//! Compile-time checked queries for `trailbase-sqlite`, see [`tb_query!`].
+
+mod analyze;
+mod schema;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use std::collections::HashSet;
+use syn::parse::{Parse, ParseStream};
+use syn::{Expr, LitStr, Token, parse_macro_input};
+
+use crate::analyze::{Analysis, Kind, analyze};
+use crate::schema::load_schema;
+
+struct QueryInput {
+  conn: Expr,
+  sql: LitStr,
+  args: Vec<Expr>,
+}
+
+impl Parse for QueryInput {
+  fn parse(input: ParseStream) -> syn::Result<Self> {
+    let conn: Expr = input.parse()?;
+    input.parse::<Token![,]>()?;
+    let sql: LitStr = input.parse()?;
+
+    let mut args = vec![];
+    while input.parse::<Option<Token![,]>>()?.is_some() {
+      if input.is_empty() {
+        break;
+      }
+      args.push(input.parse()?);
+    }
+
+    return Ok(Self { conn, sql, args });
+  }
+}
+
+/// Validates a query against the schema snapshot at build time and runs it, deserializing rows
+/// into an anonymous struct with one typed field per result column.
+///
+/// ```ignore
+/// let users = tb_query!(conn, "SELECT id, email FROM _user WHERE verified = $1", true).await?;
+/// println!("{}", users[0].email);
+/// ```
+///
+/// The snapshot is read from `schema.sql` in the crate's root or the SQL file or migrations
+/// directory set via the `TRAILBASE_SCHEMA` environment variable. Arguments are bound by
+/// parameter index and checked against the columns they're compared to or inserted into.
+/// Result columns without a declared type, e.g. `COUNT(*)`, are typed via an alias:
+/// `COUNT(*) AS "count: i64"`.
+#[proc_macro]
+pub fn tb_query(input: TokenStream) -> TokenStream {
+  let input = parse_macro_input!(input as QueryInput);
+  return match expand(input) {
+    Ok(tokens) => tokens.into(),
+    Err(err) => err.to_compile_error().into(),
+  };
+}
+
+fn expand(input: QueryInput) -> syn::Result<TokenStream2> {
+  let span = input.sql.span();
+  let schema = load_schema().map_err(|err| syn::Error::new(span, err))?;
+  let query = input.sql.value();
+  let analysis = analyze(&schema.sql, &query).map_err(|err| syn::Error::new(span, err))?;
+
+  if analysis.params.len() != input.args.len() {
+    return Err(syn::Error::new(
+      span,
+      format!(
+        "Query expects {} parameters, got {}",
+        analysis.params.len(),
+        input.args.len()
+      ),
+    ));
+  }
+
+  let fields = result_fields(&analysis, span)?;
+  let params = analysis.params.iter().zip(&input.args).map(|(kind, arg)| {
+    let kind = match kind {
+      Kind::Integer => quote!(Integer),
+      Kind::Real => quote!(Real),
+      Kind::Text => quote!(Text),
+      Kind::Blob => quote!(Blob),
+      Kind::Any => quote!(Any),
+    };
+    return quote!(::trailbase_sqlite::checked::bind::<::trailbase_sqlite::checked::#kind, _>(#arg));
+  });
+
+  // Re-run the macro when the snapshot changes.
+  let tracked = schema.files.iter().map(|file| {
+    let path = file.to_string_lossy();
+    return quote!(
+      const _: &[u8] = include_bytes!(#path);
+    );
+  });
+
+  let conn = &input.conn;
+  let method = if analysis.readonly {
+    format_ident!("read_query_as")
+  } else {
+    format_ident!("query_as")
+  };
+
+  return Ok(quote! {
+    {
+      #(#tracked)*
+
+      #[derive(::std::fmt::Debug, ::trailbase_sqlite::checked::__serde::Deserialize)]
+      #[serde(crate = "::trailbase_sqlite::checked::__serde")]
+      struct Row {
+        #(#fields,)*
+      }
+
+      (#conn).#method::<Row>(#query, [#(#params),*])
+    }
+  });
+}
+
+fn result_fields(analysis: &Analysis, span: proc_macro2::Span) -> syn::Result<Vec<TokenStream2>> {
+  let mut names = HashSet::<&str>::new();
+  let mut fields = vec![];
+
+  for column in &analysis.columns {
+    let Ok(ident) = syn::parse_str::<syn::Ident>(&column.field) else {
+      return Err(syn::Error::new(
+        span,
+        format!(
+          "Column '{}' is not a valid field name, use an alias",
+          column.name
+        ),
+      ));
+    };
+    if !names.insert(&column.field) {
+      return Err(syn::Error::new(
+        span,
+        format!("Duplicate column '{}', use an alias", column.field),
+      ));
+    }
+
+    let ty: TokenStream2 = match &column.type_override {
+      Some(ty) => syn::parse_str::<syn::Type>(ty)
+        .map_err(|err| syn::Error::new(span, format!("Invalid type for '{}': {err}", ident)))
+        .map(|ty| quote!(#ty))?,
+      None => {
+        let ty = match column.kind {
+          Kind::Integer => quote!(i64),
+          Kind::Real => quote!(f64),
+          Kind::Text => quote!(::std::string::String),
+          Kind::Blob => quote!(::std::vec::Vec<u8>),
+          Kind::Any => quote!(::trailbase_sqlite::checked::AnyValue),
+        };
+        if column.nullable {
+          quote!(::std::option::Option<#ty>)
+        } else {
+          ty
+        }
+      }
+    };
+
+    let name = &column.name;
+    fields.push(quote! {
+      #[serde(rename = #name)]
+      pub #ident: #ty
+    });
+  }
+
+  return Ok(fields);
+}
@@ -0,0 +1,82 @@
+use std::path::{Path, PathBuf};
+
+/// Environment variable overriding the schema snapshot's location.
+const SCHEMA_ENV_VAR: &str = "TRAILBASE_SCHEMA";
+const DEFAULT_SCHEMA_PATH: &str = "schema.sql";
+
+pub(crate) struct Schema {
+  pub sql: String,
+  /// Files the snapshot was read from, tracked to re-run the macro on change.
+  pub files: Vec<PathBuf>,
+}
+
+/// Loads the schema snapshot, which is either a single SQL file, e.g. the output of
+/// `sqlite3 traildepot/data/main.db .schema`, or a directory of migrations.
+///
+/// Relative paths are resolved against the crate's manifest directory.
+pub(crate) fn load_schema() -> Result<Schema, String> {
+  let manifest_dir =
+    std::env::var("CARGO_MANIFEST_DIR").map_err(|_| "CARGO_MANIFEST_DIR not set".to_string())?;
+  let path = std::env::var(SCHEMA_ENV_VAR).unwrap_or_else(|_| DEFAULT_SCHEMA_PATH.to_string());
+  let path = Path::new(&manifest_dir).join(path);
+
+  let files = if path.is_dir() {
+    migration_files(&path)?
+  } else {
+    vec![path.clone()]
+  };
+
+  let mut sql = String::new();
+  for file in &files {
+    let contents = std::fs::read_to_string(file).map_err(|err| {
+      format!(
+        "Failed to read schema snapshot '{}': {err}. Set {SCHEMA_ENV_VAR} to point to an SQL \
+        file or migrations directory",
+        file.display()
+      )
+    })?;
+    sql.push_str(&contents);
+    sql.push_str(";\n");
+  }
+
+  return Ok(Schema { sql, files });
+}
+
+/// Lists `*.sql` migrations ordered by their version, e.g. `U1712345678__create_table.sql`.
+fn migration_files(dir: &Path) -> Result<Vec<PathBuf>, String> {
+  let entries = std::fs::read_dir(dir)
+    .map_err(|err| format!("Failed to read migrations '{}': {err}", dir.display()))?;
+
+  let mut files: Vec<(u64, PathBuf)> = entries
+    .filter_map(|entry| entry.ok().map(|e| e.path()))
+    .filter(|path| path.extension().is_some_and(|ext| ext == "sql"))
+    .map(|path| {
+      let version = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(migration_version)
+        .unwrap_or(u64::MAX);
+      return (version, path);
+    })
+    .collect();
+  files.sort();
+
+  return Ok(files.into_iter().map(|(_, path)| path).collect());
+}
+
+fn migration_version(file_name: &str) -> Option<u64> {
+  let (prefix, _) = file_name.split_once("__")?;
+  return prefix.get(1..)?.parse().ok();
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_migration_version() {
+    assert_eq!(migration_version("U10__foo.sql"), Some(10));
+    assert_eq!(migration_version("V2__bar.sql"), Some(2));
+    assert_eq!(migration_version("baz.sql"), None);
+  }
+}
@@ -0,0 +1,98 @@
+use rusqlite::OpenFlags;
+use rusqlite::backup::{Backup, StepResult};
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BackupProgress {
+  /// Number of pages still to be copied.
+  pub remaining: usize,
+  /// Total number of pages in the source database.
+  pub total: usize,
+}
+
+pub struct BackupOptions {
+  /// Number of pages copied per step. Negative values copy everything in one step.
+  pub pages_per_step: i32,
+  /// Pause between steps to throttle the backup's I/O.
+  pub pause: Duration,
+  /// Called after every step.
+  pub progress: Option<Box<dyn Fn(BackupProgress) + Send + 'static>>,
+  /// SQLCipher key of the source database, with which the backup is encrypted as well.
+  pub key: Option<String>,
+}
+
+impl Default for BackupOptions {
+  fn default() -> Self {
+    return Self {
+      pages_per_step: 1024,
+      pause: Duration::ZERO,
+      progress: None,
+      key: None,
+    };
+  }
+}
+
+/// Copies `src` to `dst` step by step.
+pub(crate) fn run_backup(
+  src: &rusqlite::Connection,
+  dst: PathBuf,
+  options: &BackupOptions,
+) -> rusqlite::Result<()> {
+  let mut dst = rusqlite::Connection::open(dst)?;
+  if let Some(ref key) = options.key {
+    dst.pragma_update(None, "key", key)?;
+  }
+  let backup = Backup::new(src, &mut dst)?;
+
+  loop {
+    let result = backup.step(options.pages_per_step)?;
+
+    if let Some(ref progress) = options.progress {
+      let p = backup.progress();
+      progress(BackupProgress {
+        remaining: p.remaining as usize,
+        total: p.pagecount as usize,
+      });
+    }
+
+    match result {
+      StepResult::Done => return Ok(()),
+      StepResult::More | StepResult::Busy | StepResult::Locked => {
+        if !options.pause.is_zero() {
+          std::thread::sleep(options.pause);
+        }
+      }
+      // StepResult is non-exhaustive.
+      _ => {}
+    }
+  }
+}
+
+/// Backs up the database at `path` from a dedicated read-only connection.
+///
+/// The whole backup runs within a single read transaction. In WAL mode, this gives the backup a
+/// consistent snapshot, which isn't restarted by concurrent writes, while not blocking writers.
+pub(crate) fn run_snapshot_backup(
+  path: String,
+  dst: PathBuf,
+  options: &BackupOptions,
+) -> rusqlite::Result<()> {
+  let src = rusqlite::Connection::open_with_flags(
+    path,
+    OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX | OpenFlags::SQLITE_OPEN_URI,
+  )?;
+  if let Some(ref key) = options.key {
+    src.pragma_update(None, "key", key)?;
+  }
+
+  src.execute_batch("BEGIN")?;
+  // Reading starts the read transaction, i.e. pins the snapshot.
+  src.query_row("SELECT COUNT(*) FROM sqlite_schema", (), |_row| Ok(()))?;
+
+  let result = run_backup(&src, dst, options);
+
+  src.execute_batch("ROLLBACK")?;
+
+  return result;
+}
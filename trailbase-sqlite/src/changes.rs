@@ -0,0 +1,174 @@
+use parking_lot::Mutex;
+use rusqlite::hooks::{Action, PreUpdateCase};
+use rusqlite::types::Value;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+
+use crate::hooks::Hooks;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChangeOp {
+  Insert,
+  Update,
+  Delete,
+}
+
+/// A committed change to a single row.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChangeEvent {
+  pub table_name: String,
+  pub op: ChangeOp,
+  pub rowid: i64,
+  /// Column values before the change, set for updates and deletes.
+  pub old: Option<Vec<Value>>,
+  /// Column values after the change, set for inserts and updates.
+  pub new: Option<Vec<Value>>,
+}
+
+/// Stream of [`ChangeEvent`]s, see [`crate::Connection::change_stream`].
+#[derive(Debug)]
+pub struct ChangeStream {
+  receiver: UnboundedReceiver<ChangeEvent>,
+}
+
+impl ChangeStream {
+  /// Receives the next change or None if the stream was replaced or the connection closed.
+  pub async fn recv(&mut self) -> Option<ChangeEvent> {
+    return self.receiver.recv().await;
+  }
+}
+
+impl futures_core::Stream for ChangeStream {
+  type Item = ChangeEvent;
+
+  fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+    return self.receiver.poll_recv(cx);
+  }
+}
+
+fn to_event(action: Action, table_name: &str, case: &PreUpdateCase) -> Option<ChangeEvent> {
+  return match (action, case) {
+    (Action::SQLITE_INSERT, PreUpdateCase::Insert(accessor)) => Some(ChangeEvent {
+      table_name: table_name.to_string(),
+      op: ChangeOp::Insert,
+      rowid: accessor.get_new_row_id(),
+      old: None,
+      new: Some(
+        (0..accessor.get_column_count())
+          .map(|idx| {
+            accessor
+              .get_new_column_value(idx)
+              .map_or(Value::Null, |v| v.into())
+          })
+          .collect(),
+      ),
+    }),
+    (Action::SQLITE_DELETE, PreUpdateCase::Delete(accessor)) => Some(ChangeEvent {
+      table_name: table_name.to_string(),
+      op: ChangeOp::Delete,
+      rowid: accessor.get_old_row_id(),
+      old: Some(
+        (0..accessor.get_column_count())
+          .map(|idx| {
+            accessor
+              .get_old_column_value(idx)
+              .map_or(Value::Null, |v| v.into())
+          })
+          .collect(),
+      ),
+      new: None,
+    }),
+    (
+      Action::SQLITE_UPDATE,
+      PreUpdateCase::Update {
+        old_value_accessor,
+        new_value_accessor,
+      },
+    ) => Some(ChangeEvent {
+      table_name: table_name.to_string(),
+      op: ChangeOp::Update,
+      rowid: new_value_accessor.get_new_row_id(),
+      old: Some(
+        (0..old_value_accessor.get_column_count())
+          .map(|idx| {
+            old_value_accessor
+              .get_old_column_value(idx)
+              .map_or(Value::Null, |v| v.into())
+          })
+          .collect(),
+      ),
+      new: Some(
+        (0..new_value_accessor.get_column_count())
+          .map(|idx| {
+            new_value_accessor
+              .get_new_column_value(idx)
+              .map_or(Value::Null, |v| v.into())
+          })
+          .collect(),
+      ),
+    }),
+    _ => None,
+  };
+}
+
+/// Pending changes of the current transaction.
+struct Pending {
+  sender: UnboundedSender<ChangeEvent>,
+  events: Mutex<Vec<ChangeEvent>>,
+}
+
+/// Installs a pre-update hook, which buffers changes of the current transaction, and registers
+/// commit and rollback listeners with the connection's shared [`Hooks`], which forward them once
+/// committed.
+///
+/// The listeners only hold on to the buffer weakly, i.e. they unregister themselves once the
+/// pre-update hook is replaced, e.g. by a subsequent change stream.
+///
+/// NOTE: Changes undone via `ROLLBACK TO` a savepoint are still reported, since SQLite provides no
+/// hook for partial rollbacks.
+pub(crate) fn install_hooks(conn: &rusqlite::Connection, hooks: &Arc<Hooks>) -> ChangeStream {
+  let (sender, receiver) = tokio::sync::mpsc::unbounded_channel::<ChangeEvent>();
+  let pending = Arc::new(Pending {
+    sender,
+    events: Mutex::new(vec![]),
+  });
+
+  {
+    let pending = Arc::downgrade(&pending);
+    hooks.add_commit_listener(conn, move || -> bool {
+      let Some(pending) = pending.upgrade() else {
+        return false;
+      };
+      for event in std::mem::take(&mut *pending.events.lock()) {
+        let _ = pending.sender.send(event);
+      }
+      return !pending.sender.is_closed();
+    });
+  }
+
+  {
+    let pending = Arc::downgrade(&pending);
+    hooks.add_rollback_listener(conn, move || -> bool {
+      let Some(pending) = pending.upgrade() else {
+        return false;
+      };
+      pending.events.lock().clear();
+      return !pending.sender.is_closed();
+    });
+  }
+
+  conn.preupdate_hook(Some(
+    move |action: Action, _db: &str, table_name: &str, case: &PreUpdateCase| {
+      if pending.sender.is_closed() {
+        return;
+      }
+      if let Some(event) = to_event(action, table_name, case) {
+        pending.events.lock().push(event);
+      }
+    },
+  ));
+
+  return ChangeStream { receiver };
+}
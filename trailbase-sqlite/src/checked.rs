@@ -0,0 +1,101 @@
+//! Runtime support for the compile-time checked `tb_query!` macro.
+//!
+//! The macro infers a storage class for every query parameter from the schema snapshot, e.g.
+//! because it's compared against or inserted into an `INTEGER` column, and binds arguments through
+//! [`bind`], which only accepts Rust types compatible with that storage class.
+
+use rusqlite::types::Value;
+use serde::de::{Deserialize, Deserializer, Visitor};
+
+use crate::params::ToSqlType;
+
+#[doc(hidden)]
+pub use serde as __serde;
+
+/// Storage class of a parameter compared to or stored in an `INTEGER` column.
+pub struct Integer;
+/// Storage class of a parameter compared to or stored in a `REAL` column.
+pub struct Real;
+/// Storage class of a parameter compared to or stored in a `TEXT` column.
+pub struct Text;
+/// Storage class of a parameter compared to or stored in a `BLOB` column.
+pub struct Blob;
+/// Storage class of parameters, whose type couldn't be inferred.
+pub struct Any;
+
+/// Implemented for Rust types, which can be bound to parameters of storage class `K`.
+pub trait SqlParam<K>: Into<ToSqlType> {}
+
+macro_rules! sql_param {
+  ($kind:ty: $($t:ty),+) => {
+    $(
+      impl SqlParam<$kind> for $t {}
+      impl SqlParam<$kind> for Option<$t> {}
+    )+
+  };
+}
+
+sql_param!(Integer: i64, bool, Value);
+sql_param!(Real: f64, i64, Value);
+sql_param!(Text: String, Value);
+sql_param!(Blob: Vec<u8>, Value);
+
+impl SqlParam<Text> for &'static str {}
+impl<const N: usize> SqlParam<Blob> for [u8; N] {}
+
+impl<T: Into<ToSqlType>> SqlParam<Any> for T {}
+
+/// Converts `value` into a bindable parameter after checking its compatibility with `K`.
+#[inline]
+pub fn bind<K, T: SqlParam<K>>(value: T) -> ToSqlType {
+  return value.into();
+}
+
+/// Dynamically typed result column, used for expressions without a declared type. Use a
+/// `"name: Type"` column alias to pick a concrete type instead.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnyValue(pub Value);
+
+impl<'de> Deserialize<'de> for AnyValue {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    struct AnyValueVisitor;
+
+    impl Visitor<'_> for AnyValueVisitor {
+      type Value = AnyValue;
+
+      fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        return f.write_str("an SQLite value");
+      }
+
+      fn visit_unit<E: serde::de::Error>(self) -> Result<AnyValue, E> {
+        return Ok(AnyValue(Value::Null));
+      }
+
+      fn visit_none<E: serde::de::Error>(self) -> Result<AnyValue, E> {
+        return Ok(AnyValue(Value::Null));
+      }
+
+      fn visit_bool<E: serde::de::Error>(self, v: bool) -> Result<AnyValue, E> {
+        return Ok(AnyValue(Value::Integer(v as i64)));
+      }
+
+      fn visit_i64<E: serde::de::Error>(self, v: i64) -> Result<AnyValue, E> {
+        return Ok(AnyValue(Value::Integer(v)));
+      }
+
+      fn visit_f64<E: serde::de::Error>(self, v: f64) -> Result<AnyValue, E> {
+        return Ok(AnyValue(Value::Real(v)));
+      }
+
+      fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<AnyValue, E> {
+        return Ok(AnyValue(Value::Text(v.to_string())));
+      }
+
+      fn visit_bytes<E: serde::de::Error>(self, v: &[u8]) -> Result<AnyValue, E> {
+        return Ok(AnyValue(Value::Blob(v.to_vec())));
+      }
+    }
+
+    return deserializer.deserialize_any(AnyValueVisitor);
+  }
+}
@@ -1,20 +1,30 @@
 use kanal::{Receiver, Sender};
 use log::*;
-use parking_lot::RwLock;
+use parking_lot::{MappedMutexGuard, Mutex, MutexGuard};
 use rusqlite::fallible_iterator::FallibleIterator;
 use rusqlite::hooks::{Action, PreUpdateCase};
 use rusqlite::types::Value;
 use std::ops::{Deref, DerefMut};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::{
   fmt::{self, Debug},
   sync::Arc,
 };
 use tokio::sync::oneshot;
 
+use crate::backup::{BackupOptions, run_backup, run_snapshot_backup};
+use crate::changes::{ChangeStream, install_hooks};
 use crate::error::Error;
+use crate::hooks::Hooks;
+use crate::params::NamedParams;
 pub use crate::params::Params;
 use crate::rows::{Column, columns};
-pub use crate::rows::{Row, Rows};
+pub use crate::rows::{Row, RowStream, Rows};
+use crate::statement_cache::{
+  DEFAULT_STATEMENT_CACHE_CAPACITY, StatementCacheMetrics, StatementCacheStats,
+};
+use crate::wal::{CheckpointMode, CheckpointResult, run_checkpoint, wal_file_size};
 
 #[macro_export]
 macro_rules! params {
@@ -36,11 +46,26 @@ macro_rules! named_params {
     };
 }
 
-struct LockedConnections(RwLock<Vec<rusqlite::Connection>>);
-
 // NOTE: We must never access the same connection concurrently even as &Connection, due to
-// Statement cache. We can ensure this by uniquely assigning one connection to each thread.
-unsafe impl Sync for LockedConnections {}
+// Statement cache. Each connection is thus guarded by its own mutex and uniquely assigned to one
+// thread, which lets readers proceed while the writer is busy. Connections are only taken on close.
+type ConnectionSlot = Mutex<Option<rusqlite::Connection>>;
+
+struct LockedConnections {
+  /// The single writer connection, which also serves reads if there are no dedicated readers.
+  writer: ConnectionSlot,
+  /// Read-only connections, one per dedicated reader thread.
+  readers: Vec<ConnectionSlot>,
+}
+
+impl LockedConnections {
+  fn slot(&self, id: Option<usize>) -> &ConnectionSlot {
+    return match id {
+      Some(index) => &self.readers[index],
+      None => &self.writer,
+    };
+  }
+}
 
 /// The result returned on method calls in this crate.
 pub type Result<T> = std::result::Result<T, Error>;
@@ -48,6 +73,8 @@ pub type Result<T> = std::result::Result<T, Error>;
 enum Message {
   RunMut(Box<dyn FnOnce(&mut rusqlite::Connection) + Send + 'static>),
   RunConst(Box<dyn FnOnce(&rusqlite::Connection) + Send + 'static>),
+  /// Exclusively serve the given transaction channel until all its senders are dropped.
+  Transaction(Receiver<Message>),
   Terminate,
 }
 
@@ -55,6 +82,8 @@ enum Message {
 pub struct Options {
   pub busy_timeout: std::time::Duration,
   pub n_read_threads: usize,
+  /// Number of prepared statements cached per connection, evicting the least recently used.
+  pub statement_cache_capacity: usize,
 }
 
 impl Default for Options {
@@ -62,6 +91,7 @@ impl Default for Options {
     return Self {
       busy_timeout: std::time::Duration::from_secs(5),
       n_read_threads: 0,
+      statement_cache_capacity: DEFAULT_STATEMENT_CACHE_CAPACITY,
     };
   }
 }
@@ -72,6 +102,9 @@ pub struct Connection {
   reader: Sender<Message>,
   writer: Sender<Message>,
   conns: Arc<LockedConnections>,
+  metrics: Arc<StatementCacheMetrics>,
+  /// Hooks shared by all consumers of the writer connection.
+  hooks: Arc<Hooks>,
 }
 
 impl Connection {
@@ -84,6 +117,11 @@ impl Connection {
       if let Some(timeout) = opt.as_ref().map(|o| o.busy_timeout) {
         conn.busy_timeout(timeout).expect("busy timeout failed");
       }
+      conn.set_prepared_statement_cache_capacity(
+        opt.as_ref().map_or(DEFAULT_STATEMENT_CACHE_CAPACITY, |o| {
+          o.statement_cache_capacity
+        }),
+      );
       return Ok(conn);
     };
 
@@ -97,16 +135,18 @@ impl Connection {
       }
     });
 
-    let n_read_threads = if name.is_some() {
-      let n_read_threads = match opt.as_ref().map_or(0, |o| o.n_read_threads) {
-        1 => {
-          warn!(
-            "Using a single dedicated reader thread won't improve performance, falling back to 0."
-          );
-          0
-        }
-        n => n,
-      };
+    // Concurrent readers require WAL mode, otherwise they'd block on the writer anyway. Shared
+    // in-memory databases are the exception, since they don't support WAL mode but we still want
+    // to exercise the same code paths, e.g. in tests.
+    let wal = conn
+      .pragma_query_value(None, "journal_mode", |row| row.get::<_, String>(0))
+      .is_ok_and(|mode| mode.eq_ignore_ascii_case("wal"));
+    let shared_memory = is_shared_memory(&conn);
+
+    let n_read_threads = if name.is_some() && (wal || shared_memory) {
+      // NOTE: Even a single dedicated reader helps, since long-running reads no longer hold up
+      // writes queued on the writer thread.
+      let n_read_threads = opt.as_ref().map_or(0, |o| o.n_read_threads);
 
       if let Ok(n) = std::thread::available_parallelism() {
         if n_read_threads > n.get() {
@@ -119,30 +159,42 @@ impl Connection {
 
       n_read_threads
     } else {
-      // We cannot share an in-memory database across threads, they're all independent.
+      if name.is_some() && !wal {
+        debug!("Not using dedicated reader threads w/o WAL mode");
+      }
+      // Private in-memory databases cannot be shared across connections, they're all
+      // independent.
       0
     };
 
     let conns = {
-      let mut conns = vec![conn];
+      let mut readers = vec![];
       for _ in 0..n_read_threads {
-        conns.push(new_conn()?);
+        let reader = new_conn()?;
+        // Guard against accidental writes on reader threads.
+        reader
+          .pragma_update(None, "query_only", true)
+          .expect("query_only failed");
+        readers.push(Mutex::new(Some(reader)));
       }
 
-      Arc::new(LockedConnections(RwLock::new(conns)))
+      Arc::new(LockedConnections {
+        writer: Mutex::new(Some(conn)),
+        readers,
+      })
     };
 
     // Spawn writer.
     let (shared_write_sender, shared_write_receiver) = kanal::unbounded::<Message>();
     let conns_clone = conns.clone();
-    std::thread::spawn(move || event_loop(0, conns_clone, shared_write_receiver));
+    std::thread::spawn(move || event_loop(None, conns_clone, shared_write_receiver));
 
     let shared_read_sender = if n_read_threads > 0 {
       let (shared_read_sender, shared_read_receiver) = kanal::unbounded::<Message>();
       for i in 0..n_read_threads {
         let shared_read_receiver = shared_read_receiver.clone();
         let conns_clone = conns.clone();
-        std::thread::spawn(move || event_loop(i, conns_clone, shared_read_receiver));
+        std::thread::spawn(move || event_loop(Some(i), conns_clone, shared_read_receiver));
       }
       shared_read_sender
     } else {
@@ -158,21 +210,26 @@ impl Connection {
       reader: shared_read_sender,
       writer: shared_write_sender,
       conns,
+      metrics: Arc::new(StatementCacheMetrics::default()),
+      hooks: Arc::new(Hooks::default()),
     });
   }
 
   pub fn from_connection_test_only(conn: rusqlite::Connection) -> Self {
-    use parking_lot::lock_api::RwLock;
-
     let (shared_write_sender, shared_write_receiver) = kanal::unbounded::<Message>();
-    let conns = Arc::new(LockedConnections(RwLock::new(vec![conn])));
+    let conns = Arc::new(LockedConnections {
+      writer: Mutex::new(Some(conn)),
+      readers: vec![],
+    });
     let conns_clone = conns.clone();
-    std::thread::spawn(move || event_loop(0, conns_clone, shared_write_receiver));
+    std::thread::spawn(move || event_loop(None, conns_clone, shared_write_receiver));
 
     return Self {
       reader: shared_write_sender.clone(),
       writer: shared_write_sender,
       conns,
+      metrics: Arc::new(StatementCacheMetrics::default()),
+      hooks: Arc::new(Hooks::default()),
     };
   }
 
@@ -185,20 +242,53 @@ impl Connection {
     return Self::new(|| Ok(rusqlite::Connection::open_in_memory()?), None);
   }
 
+  /// Open a new connection to a named in-memory database, which is shared with other connections
+  /// opened using the same `name` within this process, see [`shared_memory_uri`]. Unlike
+  /// [`Self::open_in_memory`], this allows for dedicated reader threads.
+  ///
+  /// # Failure
+  ///
+  /// Will return `Err` if the underlying SQLite open call fails.
+  pub fn open_shared_in_memory(name: &str, opt: Option<Options>) -> Result<Self> {
+    let uri = shared_memory_uri(name);
+    return Self::new(
+      || {
+        use rusqlite::OpenFlags;
+        return Ok(rusqlite::Connection::open_with_flags(
+          &uri,
+          OpenFlags::SQLITE_OPEN_READ_WRITE
+            | OpenFlags::SQLITE_OPEN_CREATE
+            | OpenFlags::SQLITE_OPEN_URI
+            | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )?);
+      },
+      opt,
+    );
+  }
+
+  /// Returns the prepared statement cache hits and misses across all connections.
+  /// Number of dedicated reader connections. Zero if reads are served by the writer.
+  pub fn read_connections(&self) -> usize {
+    return self.conns.readers.len();
+  }
+
+  pub fn statement_cache_stats(&self) -> StatementCacheStats {
+    return self.metrics.stats();
+  }
+
+  /// Locks the writer connection, readers remain unaffected.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the connection has been closed.
   #[inline]
   pub fn write_lock(&self) -> LockGuard<'_> {
-    return LockGuard {
-      guard: self.conns.0.write(),
-    };
+    return LockGuard::new(self.conns.writer.lock());
   }
 
   #[inline]
   pub fn try_write_lock_for(&self, duration: tokio::time::Duration) -> Option<LockGuard<'_>> {
-    return self
-      .conns
-      .0
-      .try_write_for(duration)
-      .map(|guard| LockGuard { guard });
+    return self.conns.writer.try_lock_for(duration).map(LockGuard::new);
   }
 
   /// Call a function in background thread and get the result
@@ -213,19 +303,47 @@ impl Connection {
     F: FnOnce(&mut rusqlite::Connection) -> Result<R> + Send + 'static,
     R: Send + 'static,
   {
-    // return call_impl(&self.writer, function).await;
-    let (sender, receiver) = oneshot::channel::<Result<R>>();
+    return call_impl(&self.writer, function).await;
+  }
 
+  /// Runs `f` within a transaction, which is committed if `f` returns `Ok` and rolled back
+  /// otherwise. Transactions are also rolled back if the returned future is dropped early.
+  ///
+  /// While the transaction is open, the writer thread exclusively serves statements issued through
+  /// the [`Transaction`] handle, i.e. other writes are queued until it completes.
+  ///
+  /// WARN: Issuing writes through `self` from within `f` will deadlock. Use the handle instead.
+  pub async fn transaction<F, R, E>(&self, f: F) -> std::result::Result<R, E>
+  where
+    F: AsyncFnOnce(&Transaction) -> std::result::Result<R, E>,
+    E: From<Error>,
+  {
+    let (sender, receiver) = kanal::unbounded::<Message>();
     self
       .writer
-      .send(Message::RunMut(Box::new(move |conn| {
-        if !sender.is_closed() {
-          let _ = sender.send(function(conn));
-        }
-      })))
+      .send(Message::Transaction(receiver))
       .map_err(|_| Error::ConnectionClosed)?;
 
-    receiver.await.map_err(|_| Error::ConnectionClosed)?
+    let tx = Transaction {
+      sender,
+      depth: AtomicUsize::new(0),
+      metrics: self.metrics.clone(),
+    };
+    // NOTE: Take the write lock upfront rather than upgrading later to avoid SQLITE_BUSY.
+    tx.execute_batch("BEGIN IMMEDIATE").await?;
+
+    return match f(&tx).await {
+      Ok(result) => {
+        tx.execute_batch("COMMIT").await?;
+        Ok(result)
+      }
+      Err(err) => {
+        if let Err(rollback_err) = tx.execute_batch("ROLLBACK").await {
+          warn!("Failed to roll back transaction: {rollback_err}");
+        }
+        Err(err)
+      }
+    };
   }
 
   #[inline]
@@ -235,19 +353,33 @@ impl Connection {
       .send(Message::RunMut(Box::new(move |conn| function(conn))));
   }
 
+  /// Like `call` but runs on a reader.
+  ///
+  /// Reads are interrupted if the returned future is dropped mid-way, e.g. when the client of an
+  /// HTTP request disconnects, to not keep burning CPU and blocking readers.
   #[inline]
   async fn call_reader<F, R>(&self, function: F) -> Result<R>
   where
     F: FnOnce(&rusqlite::Connection) -> Result<R> + Send + 'static,
     R: Send + 'static,
   {
+    // NOTE: The guard must be dropped after the receiver, so that closures observing an open
+    // receiver are guaranteed to be interrupted.
+    let guard = InterruptOnDrop::default();
+    let running = guard.0.clone();
+
     let (sender, receiver) = oneshot::channel::<Result<R>>();
 
     self
       .reader
       .send(Message::RunConst(Box::new(move |conn| {
+        *running.lock() = Some(conn.get_interrupt_handle());
         if !sender.is_closed() {
-          let _ = sender.send(function(conn));
+          let result = function(conn);
+          running.lock().take();
+          let _ = sender.send(result);
+        } else {
+          running.lock().take();
         }
       })))
       .map_err(|_| Error::ConnectionClosed)?;
@@ -255,15 +387,80 @@ impl Connection {
     receiver.await.map_err(|_| Error::ConnectionClosed)?
   }
 
+  /// Streams the rows of a read-only query in batches of up to `batch_size` rows.
+  ///
+  /// Rows are produced on a reader thread, which is suspended while the consumer lags more than
+  /// `STREAM_BUFFER` batches behind, i.e. memory use is bounded regardless of the result size.
+  /// The query is aborted when the stream is dropped.
+  ///
+  /// WARN: The reader thread is occupied until the stream is exhausted or dropped. W/o dedicated
+  /// readers this is the writer thread, thus the stream must not be held while awaiting writes.
+  pub fn query_stream(
+    &self,
+    sql: impl AsRef<str> + Send + 'static,
+    params: impl Params + Send + 'static,
+    batch_size: usize,
+  ) -> RowStream {
+    const STREAM_BUFFER: usize = 2;
+
+    let (sender, receiver) = tokio::sync::mpsc::channel::<Result<Rows>>(STREAM_BUFFER);
+    let batch_size = batch_size.max(1);
+    let metrics = self.metrics.clone();
+    let error_sender = sender.clone();
+
+    let produce = move |conn: &rusqlite::Connection| -> Result<()> {
+      let mut stmt = metrics.prepare_cached(conn, sql.as_ref())?;
+      assert!(stmt.readonly());
+
+      params.bind(&mut stmt)?;
+      let cols: Arc<Vec<Column>> = Arc::new(columns(&stmt));
+
+      let mut rows = stmt.raw_query();
+      let mut batch = Vec::with_capacity(batch_size);
+      while let Some(row) = rows.next()? {
+        batch.push(Row::from_row(row, Some(cols.clone()))?);
+
+        if batch.len() >= batch_size {
+          let full = std::mem::replace(&mut batch, Vec::with_capacity(batch_size));
+          if sender.blocking_send(Ok(Rows(full, cols.clone()))).is_err() {
+            // Stream was dropped.
+            return Ok(());
+          }
+        }
+      }
+
+      if !batch.is_empty() {
+        let _ = sender.blocking_send(Ok(Rows(batch, cols)));
+      }
+      return Ok(());
+    };
+
+    let closed_sender = error_sender.clone();
+    if self
+      .reader
+      .send(Message::RunConst(Box::new(move |conn| {
+        if let Err(err) = produce(conn) {
+          let _ = error_sender.blocking_send(Err(err));
+        }
+      })))
+      .is_err()
+    {
+      let _ = closed_sender.try_send(Err(Error::ConnectionClosed));
+    }
+
+    return RowStream { receiver };
+  }
+
   /// Query SQL statement.
   pub async fn read_query_rows(
     &self,
     sql: impl AsRef<str> + Send + 'static,
     params: impl Params + Send + 'static,
   ) -> Result<Rows> {
+    let metrics = self.metrics.clone();
     return self
       .call_reader(move |conn: &rusqlite::Connection| {
-        let mut stmt = conn.prepare_cached(sql.as_ref())?;
+        let mut stmt = metrics.prepare_cached(conn, sql.as_ref())?;
         assert!(stmt.readonly());
 
         params.bind(&mut stmt)?;
@@ -278,9 +475,10 @@ impl Connection {
     sql: impl AsRef<str> + Send + 'static,
     params: impl Params + Send + 'static,
   ) -> Result<Rows> {
+    let metrics = self.metrics.clone();
     return self
       .call(move |conn: &mut rusqlite::Connection| {
-        let mut stmt = conn.prepare_cached(sql.as_ref())?;
+        let mut stmt = metrics.prepare_cached(conn, sql.as_ref())?;
 
         params.bind(&mut stmt)?;
         let rows = stmt.raw_query();
@@ -310,9 +508,10 @@ impl Connection {
     T: Send + 'static,
     crate::error::Error: From<E>,
   {
+    let metrics = self.metrics.clone();
     return self
       .call(move |conn: &mut rusqlite::Connection| {
-        let mut stmt = conn.prepare_cached(sql.as_ref())?;
+        let mut stmt = metrics.prepare_cached(conn, sql.as_ref())?;
         params.bind(&mut stmt)?;
 
         let mut rows = stmt.raw_query();
@@ -336,9 +535,10 @@ impl Connection {
     T: Send + 'static,
     crate::error::Error: From<E>,
   {
+    let metrics = self.metrics.clone();
     return self
       .call_reader(move |conn: &rusqlite::Connection| {
-        let mut stmt = conn.prepare_cached(sql.as_ref())?;
+        let mut stmt = metrics.prepare_cached(conn, sql.as_ref())?;
         assert!(stmt.readonly());
 
         params.bind(&mut stmt)?;
@@ -378,9 +578,10 @@ impl Connection {
     sql: impl AsRef<str> + Send + 'static,
     params: impl Params + Send + 'static,
   ) -> Result<Vec<T>> {
+    let metrics = self.metrics.clone();
     return self
       .call_reader(move |conn: &rusqlite::Connection| {
-        let mut stmt = conn.prepare_cached(sql.as_ref())?;
+        let mut stmt = metrics.prepare_cached(conn, sql.as_ref())?;
         assert!(stmt.readonly());
 
         params.bind(&mut stmt)?;
@@ -395,15 +596,44 @@ impl Connection {
       .await;
   }
 
+  /// Queries rows and deserializes them into `T` by column name, see [`crate::de`].
+  pub async fn query_as<T: serde::de::DeserializeOwned + Send + 'static>(
+    &self,
+    sql: impl AsRef<str> + Send + 'static,
+    params: impl Params + Send + 'static,
+  ) -> Result<Vec<T>> {
+    let metrics = self.metrics.clone();
+    return self
+      .call(move |conn: &mut rusqlite::Connection| {
+        return query_as_impl(&metrics, conn, sql.as_ref(), params);
+      })
+      .await;
+  }
+
+  /// Like [`Self::query_as`] but served by a reader connection, i.e. for read-only queries.
+  pub async fn read_query_as<T: serde::de::DeserializeOwned + Send + 'static>(
+    &self,
+    sql: impl AsRef<str> + Send + 'static,
+    params: impl Params + Send + 'static,
+  ) -> Result<Vec<T>> {
+    let metrics = self.metrics.clone();
+    return self
+      .call_reader(move |conn: &rusqlite::Connection| {
+        return query_as_impl(&metrics, conn, sql.as_ref(), params);
+      })
+      .await;
+  }
+
   /// Execute SQL statement.
   pub async fn execute(
     &self,
     sql: impl AsRef<str> + Send + 'static,
     params: impl Params + Send + 'static,
   ) -> Result<usize> {
+    let metrics = self.metrics.clone();
     return self
       .call(move |conn: &mut rusqlite::Connection| {
-        let mut stmt = conn.prepare_cached(sql.as_ref())?;
+        let mut stmt = metrics.prepare_cached(conn, sql.as_ref())?;
         params.bind(&mut stmt)?;
 
         let n = stmt.raw_execute()?;
@@ -447,6 +677,46 @@ impl Connection {
       .await;
   }
 
+  /// Executes the same statement once for each set of parameters within a single transaction and
+  /// returns the rows produced by all executions, e.g. using `RETURNING`.
+  ///
+  /// The statement is only prepared once. If any execution fails, the entire batch is rolled back.
+  pub async fn execute_batch_many(
+    &self,
+    sql: impl AsRef<str> + Send + 'static,
+    params: Vec<NamedParams>,
+  ) -> Result<Rows> {
+    let metrics = self.metrics.clone();
+    return self
+      .call(move |conn: &mut rusqlite::Connection| {
+        let tx = conn.transaction()?;
+
+        let rows = {
+          let mut stmt = metrics.prepare_cached(&tx, sql.as_ref())?;
+          let cols: Arc<Vec<Column>> = Arc::new(columns(&stmt));
+
+          let mut result = vec![];
+          for params in params {
+            // NOTE: Parameters not included in `params` must not retain previous bindings.
+            stmt.clear_bindings();
+            params.bind(&mut stmt)?;
+
+            let mut rows = stmt.raw_query();
+            while let Some(row) = rows.next()? {
+              result.push(Row::from_row(row, Some(cols.clone()))?);
+            }
+          }
+
+          Rows(result, cols)
+        };
+
+        tx.commit()?;
+
+        return Ok(rows);
+      })
+      .await;
+  }
+
   /// Convenience API for (un)setting a new pre-update hook.
   pub async fn add_preupdate_hook(
     &self,
@@ -460,6 +730,141 @@ impl Connection {
       .await;
   }
 
+  /// Registers `listener` to be called on every commit of the writer connection for as long as it
+  /// returns true. Unlike [`rusqlite::Connection::commit_hook`], this doesn't replace other
+  /// listeners, e.g. change streams.
+  pub async fn add_commit_listener(
+    &self,
+    listener: impl FnMut() -> bool + Send + 'static,
+  ) -> Result<()> {
+    let hooks = self.hooks.clone();
+    return self
+      .call(move |conn| {
+        hooks.add_commit_listener(conn, listener);
+        return Ok(());
+      })
+      .await;
+  }
+
+  /// Registers `listener` to be called with the action, database name, table name and rowid of
+  /// every row changed on the writer connection for as long as it returns true. Unlike
+  /// [`rusqlite::Connection::update_hook`], this doesn't replace other listeners.
+  pub async fn add_update_listener(
+    &self,
+    listener: impl FnMut(Action, &str, &str, i64) -> bool + Send + 'static,
+  ) -> Result<()> {
+    let hooks = self.hooks.clone();
+    return self
+      .call(move |conn| {
+        hooks.add_update_listener(conn, listener);
+        return Ok(());
+      })
+      .await;
+  }
+
+  /// Backs up the database to `dst`.
+  ///
+  /// File-based databases are copied from a consistent snapshot using a dedicated read-only
+  /// connection, i.e. without blocking writes. In-memory databases can only be backed up from the
+  /// writer connection, which blocks writes for the duration.
+  pub async fn backup(&self, dst: impl Into<PathBuf>, options: BackupOptions) -> Result<()> {
+    let dst = dst.into();
+    let path = self
+      .call(|conn| {
+        return Ok(file_path(conn));
+      })
+      .await?;
+
+    match path {
+      Some(path) => {
+        tokio::task::spawn_blocking(move || run_snapshot_backup(path, dst, &options))
+          .await
+          .map_err(|err| Error::Other(err.into()))??;
+      }
+      None => {
+        self
+          .call(move |conn| {
+            return Ok(run_backup(conn, dst, &options)?);
+          })
+          .await?;
+      }
+    };
+
+    return Ok(());
+  }
+
+  /// Runs a WAL checkpoint on the writer connection.
+  ///
+  /// Blocking modes wait for active readers, e.g. long-running streams, and report `busy` if they
+  /// couldn't complete.
+  pub async fn wal_checkpoint(&self, mode: CheckpointMode) -> Result<CheckpointResult> {
+    return self
+      .call(move |conn| {
+        return Ok(run_checkpoint(conn, mode)?);
+      })
+      .await;
+  }
+
+  /// Sets the number of WAL pages after which commits trigger an automatic, passive checkpoint.
+  /// Zero disables automatic checkpoints.
+  pub async fn set_wal_autocheckpoint(&self, pages: u32) -> Result<()> {
+    return self
+      .call(move |conn| {
+        conn.pragma_update(None, "wal_autocheckpoint", pages)?;
+        return Ok(());
+      })
+      .await;
+  }
+
+  /// Returns the current size of the WAL file in bytes or `None` for in-memory databases and
+  /// databases without a WAL file.
+  pub async fn wal_size(&self) -> Result<Option<u64>> {
+    let path = self
+      .call(|conn| {
+        return Ok(file_path(conn));
+      })
+      .await?;
+
+    let Some(path) = path else {
+      return Ok(None);
+    };
+    return wal_file_size(&path).map_err(|err| Error::Other(err.into()));
+  }
+
+  /// Subscribes to committed row changes on this connection.
+  ///
+  /// Changes are buffered per transaction and only emitted after commit, i.e. rolled back changes
+  /// are never observed. Replaces any previous change stream as well as hooks installed via
+  /// [`Self::add_preupdate_hook`], since SQLite only supports a single pre-update hook. Commit
+  /// listeners, see [`Self::add_commit_listener`], are unaffected.
+  pub async fn change_stream(&self) -> Result<ChangeStream> {
+    let hooks = self.hooks.clone();
+    return self
+      .call(move |conn| {
+        return Ok(install_hooks(conn, &hooks));
+      })
+      .await;
+  }
+
+  /// Runs `function` once on every dedicated reader connection, e.g. to apply reader-specific
+  /// pragmas. Does nothing if reads are served by the writer.
+  pub async fn call_readers<F>(&self, function: F) -> Result<()>
+  where
+    F: Fn(&rusqlite::Connection) -> Result<()> + Send + 'static,
+  {
+    let conns = self.conns.clone();
+    return tokio::task::spawn_blocking(move || {
+      for slot in &conns.readers {
+        if let Some(conn) = slot.lock().as_ref() {
+          function(conn)?;
+        }
+      }
+      return Ok(());
+    })
+    .await
+    .map_err(|err| Error::Other(err.into()))?;
+  }
+
   /// Close the database connection.
   ///
   /// This is functionally equivalent to the `Drop` implementation for `Connection`. It consumes
@@ -478,7 +883,9 @@ impl Connection {
     }
 
     let mut errors = vec![];
-    let conns: Vec<_> = std::mem::take(&mut self.conns.0.write());
+    let conns = std::iter::once(&self.conns.writer)
+      .chain(self.conns.readers.iter())
+      .filter_map(|slot| slot.lock().take());
     for conn in conns {
       if let Err((_, err)) = conn.close() {
         errors.push(err);
@@ -500,16 +907,108 @@ impl Debug for Connection {
   }
 }
 
-fn event_loop(id: usize, conns: Arc<LockedConnections>, receiver: Receiver<Message>) {
+fn query_as_impl<T: serde::de::DeserializeOwned>(
+  metrics: &StatementCacheMetrics,
+  conn: &rusqlite::Connection,
+  sql: &str,
+  params: impl Params,
+) -> Result<Vec<T>> {
+  let mut stmt = metrics.prepare_cached(conn, sql)?;
+  params.bind(&mut stmt)?;
+
+  let mut rows = stmt.raw_query();
+  let mut values = vec![];
+  while let Some(row) = rows.next()? {
+    values.push(crate::de::from_row(row)?);
+  }
+  return Ok(values);
+}
+
+/// Returns the URI of a named in-memory database using SQLite's "memdb" VFS. All connections
+/// opened with the same URI within a process share the database, which lives until the last
+/// connection is closed. Requires opening with `SQLITE_OPEN_URI`.
+pub fn shared_memory_uri(name: &str) -> String {
+  return format!("file:/{name}?vfs=memdb");
+}
+
+/// Whether `conn` is backed by the "memdb" VFS, i.e. is a shared in-memory database.
+#[allow(unsafe_code)]
+fn is_shared_memory(conn: &rusqlite::Connection) -> bool {
+  use rusqlite::ffi;
+
+  let mut vfs: *mut std::ffi::c_char = std::ptr::null_mut();
+  // SAFETY: On success, SQLITE_FCNTL_VFSNAME hands us a string allocated by `sqlite3_malloc`,
+  // which we own and free below.
+  unsafe {
+    let rc = ffi::sqlite3_file_control(
+      conn.handle(),
+      c"main".as_ptr(),
+      ffi::SQLITE_FCNTL_VFSNAME,
+      (&raw mut vfs).cast(),
+    );
+    if rc != ffi::SQLITE_OK || vfs.is_null() {
+      return false;
+    }
+
+    // The memdb VFS reports itself as "memdb(<ptr>,<size>)".
+    let memdb = std::ffi::CStr::from_ptr(vfs)
+      .to_bytes()
+      .starts_with(b"memdb");
+    ffi::sqlite3_free(vfs.cast());
+    return memdb;
+  }
+}
+
+/// Returns the path of the database file or `None` for in-memory databases.
+fn file_path(conn: &rusqlite::Connection) -> Option<String> {
+  // Returns empty string for private in-memory databases.
+  return conn
+    .path()
+    .filter(|p| !p.is_empty() && !is_shared_memory(conn))
+    .map(str::to_string);
+}
+
+/// Serves messages using the writer connection if `id` is None or the given reader otherwise.
+///
+/// NOTE: Messages are dropped once the connection has been closed, which lets callers observe a
+/// closed result channel.
+fn event_loop(id: Option<usize>, conns: Arc<LockedConnections>, receiver: Receiver<Message>) {
+  let slot = conns.slot(id);
+
   while let Ok(message) = receiver.recv() {
     match message {
       Message::RunConst(f) => {
-        let lock = conns.0.read();
-        f(&lock[id])
+        if let Some(conn) = slot.lock().as_ref() {
+          f(conn);
+        }
       }
       Message::RunMut(f) => {
-        let mut lock = conns.0.write();
-        f(&mut lock[0])
+        if let Some(conn) = slot.lock().as_mut() {
+          f(conn);
+        }
+      }
+      Message::Transaction(tx_receiver) => {
+        let mut lock = slot.lock();
+        let Some(conn) = lock.as_mut() else {
+          continue;
+        };
+
+        while let Ok(message) = tx_receiver.recv() {
+          match message {
+            Message::RunMut(f) => f(&mut *conn),
+            Message::RunConst(f) => f(&*conn),
+            Message::Transaction(_) | Message::Terminate => {
+              warn!("Unexpected message within transaction");
+            }
+          }
+        }
+
+        // The handle was dropped without committing or rolling back, e.g. cancelled future.
+        if !conn.is_autocommit() {
+          if let Err(err) = conn.execute_batch("ROLLBACK") {
+            warn!("Failed to roll back abandoned transaction: {err}");
+          }
+        }
       }
       Message::Terminate => {
         return;
@@ -518,6 +1017,190 @@ fn event_loop(id: usize, conns: Arc<LockedConnections>, receiver: Receiver<Messa
   }
 }
 
+/// Interrupts the query currently executed on behalf of a caller, if any, when dropped.
+#[derive(Default)]
+struct InterruptOnDrop(Arc<Mutex<Option<rusqlite::InterruptHandle>>>);
+
+impl Drop for InterruptOnDrop {
+  fn drop(&mut self) {
+    if let Some(handle) = self.0.lock().as_ref() {
+      debug!("Interrupting abandoned query");
+      handle.interrupt();
+    }
+  }
+}
+
+async fn call_impl<F, R>(sender: &Sender<Message>, function: F) -> Result<R>
+where
+  F: FnOnce(&mut rusqlite::Connection) -> Result<R> + Send + 'static,
+  R: Send + 'static,
+{
+  let (result_sender, receiver) = oneshot::channel::<Result<R>>();
+
+  sender
+    .send(Message::RunMut(Box::new(move |conn| {
+      if !result_sender.is_closed() {
+        let _ = result_sender.send(function(conn));
+      }
+    })))
+    .map_err(|_| Error::ConnectionClosed)?;
+
+  receiver.await.map_err(|_| Error::ConnectionClosed)?
+}
+
+/// Handle to an open transaction, see [`Connection::transaction`].
+pub struct Transaction {
+  sender: Sender<Message>,
+  depth: AtomicUsize,
+  metrics: Arc<StatementCacheMetrics>,
+}
+
+impl Transaction {
+  /// Call a function on the transaction's connection and get the result asynchronously.
+  #[inline]
+  pub async fn call<F, R>(&self, function: F) -> Result<R>
+  where
+    F: FnOnce(&mut rusqlite::Connection) -> Result<R> + Send + 'static,
+    R: Send + 'static,
+  {
+    return call_impl(&self.sender, function).await;
+  }
+
+  /// Runs `f` within a nested savepoint, which is released if `f` returns `Ok` and rolled back
+  /// otherwise, leaving the outer transaction intact.
+  pub async fn savepoint<F, R, E>(&self, f: F) -> std::result::Result<R, E>
+  where
+    F: AsyncFnOnce(&Transaction) -> std::result::Result<R, E>,
+    E: From<Error>,
+  {
+    let name = format!(
+      "_tx_savepoint_{}",
+      self.depth.fetch_add(1, Ordering::SeqCst)
+    );
+    self.execute_batch(format!("SAVEPOINT {name}")).await?;
+
+    let result = f(self).await;
+    self.depth.fetch_sub(1, Ordering::SeqCst);
+
+    return match result {
+      Ok(result) => {
+        self.execute_batch(format!("RELEASE {name}")).await?;
+        Ok(result)
+      }
+      Err(err) => {
+        self
+          .execute_batch(format!("ROLLBACK TO {name}; RELEASE {name}"))
+          .await?;
+        Err(err)
+      }
+    };
+  }
+
+  /// Queries rows and deserializes them into `T` by column name, see [`crate::de`].
+  pub async fn query_as<T: serde::de::DeserializeOwned + Send + 'static>(
+    &self,
+    sql: impl AsRef<str> + Send + 'static,
+    params: impl Params + Send + 'static,
+  ) -> Result<Vec<T>> {
+    let metrics = self.metrics.clone();
+    return self
+      .call(move |conn: &mut rusqlite::Connection| {
+        return query_as_impl(&metrics, conn, sql.as_ref(), params);
+      })
+      .await;
+  }
+
+  /// Like [`Self::query_as`] but served by a reader connection, i.e. for read-only queries.
+  pub async fn read_query_as<T: serde::de::DeserializeOwned + Send + 'static>(
+    &self,
+    sql: impl AsRef<str> + Send + 'static,
+    params: impl Params + Send + 'static,
+  ) -> Result<Vec<T>> {
+    let metrics = self.metrics.clone();
+    return self
+      .call_reader(move |conn: &rusqlite::Connection| {
+        return query_as_impl(&metrics, conn, sql.as_ref(), params);
+      })
+      .await;
+  }
+
+  /// Execute SQL statement.
+  pub async fn execute(
+    &self,
+    sql: impl AsRef<str> + Send + 'static,
+    params: impl Params + Send + 'static,
+  ) -> Result<usize> {
+    let metrics = self.metrics.clone();
+    return self
+      .call(move |conn: &mut rusqlite::Connection| {
+        let mut stmt = metrics.prepare_cached(conn, sql.as_ref())?;
+        params.bind(&mut stmt)?;
+
+        return Ok(stmt.raw_execute()?);
+      })
+      .await;
+  }
+
+  /// Batch execute SQL statements.
+  pub async fn execute_batch(&self, sql: impl AsRef<str> + Send + 'static) -> Result<()> {
+    return self
+      .call(move |conn: &mut rusqlite::Connection| {
+        return Ok(conn.execute_batch(sql.as_ref())?);
+      })
+      .await;
+  }
+
+  /// Query SQL statement.
+  pub async fn query_rows(
+    &self,
+    sql: impl AsRef<str> + Send + 'static,
+    params: impl Params + Send + 'static,
+  ) -> Result<Rows> {
+    let metrics = self.metrics.clone();
+    return self
+      .call(move |conn: &mut rusqlite::Connection| {
+        let mut stmt = metrics.prepare_cached(conn, sql.as_ref())?;
+
+        params.bind(&mut stmt)?;
+        let rows = stmt.raw_query();
+        Ok(Rows::from_rows(rows)?)
+      })
+      .await;
+  }
+
+  pub async fn query_row_f<T, E>(
+    &self,
+    sql: impl AsRef<str> + Send + 'static,
+    params: impl Params + Send + 'static,
+    f: impl (FnOnce(&rusqlite::Row<'_>) -> std::result::Result<T, E>) + Send + 'static,
+  ) -> Result<Option<T>>
+  where
+    T: Send + 'static,
+    crate::error::Error: From<E>,
+  {
+    let metrics = self.metrics.clone();
+    return self
+      .call(move |conn: &mut rusqlite::Connection| {
+        let mut stmt = metrics.prepare_cached(conn, sql.as_ref())?;
+        params.bind(&mut stmt)?;
+
+        let mut rows = stmt.raw_query();
+
+        if let Some(row) = rows.next()? {
+          return Ok(Some(f(row)?));
+        }
+        Ok(None)
+      })
+      .await;
+  }
+}
+
+impl Debug for Transaction {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("Transaction").finish()
+  }
+}
+
 pub fn extract_row_id(case: &PreUpdateCase) -> Option<i64> {
   return match case {
     PreUpdateCase::Insert(accessor) => Some(accessor.get_new_row_id()),
@@ -563,21 +1246,29 @@ pub fn extract_record_values(case: &PreUpdateCase) -> Option<Vec<Value>> {
 }
 
 pub struct LockGuard<'a> {
-  guard: parking_lot::RwLockWriteGuard<'a, Vec<rusqlite::Connection>>,
+  guard: MappedMutexGuard<'a, rusqlite::Connection>,
+}
+
+impl<'a> LockGuard<'a> {
+  fn new(guard: MutexGuard<'a, Option<rusqlite::Connection>>) -> Self {
+    return Self {
+      guard: MutexGuard::map(guard, |conn| conn.as_mut().expect("connection closed")),
+    };
+  }
 }
 
 impl Deref for LockGuard<'_> {
   type Target = rusqlite::Connection;
   #[inline]
   fn deref(&self) -> &rusqlite::Connection {
-    return &self.guard.deref()[0];
+    return self.guard.deref();
   }
 }
 
 impl DerefMut for LockGuard<'_> {
   #[inline]
   fn deref_mut(&mut self) -> &mut rusqlite::Connection {
-    return &mut self.guard.deref_mut()[0];
+    return self.guard.deref_mut();
   }
 }
 
@@ -0,0 +1,335 @@
+//! Deserialization of rows into arbitrary [`serde::Deserialize`] types by column name.
+//!
+//! Compared to `serde_rusqlite`, BLOBs can be deserialized into byte arrays and UUIDs, TEXT
+//! columns holding JSON can be deserialized into nested structs, maps and sequences, and
+//! single-column rows can be deserialized directly into primitives.
+
+use rusqlite::types::ValueRef;
+use serde::de::value::SeqDeserializer;
+use serde::de::{
+  DeserializeOwned, DeserializeSeed, Deserializer, Error as _, IntoDeserializer, MapAccess,
+  SeqAccess, Visitor,
+};
+use serde::{Deserialize, forward_to_deserialize_any};
+
+#[derive(thiserror::Error, Debug)]
+pub enum DeserializeError {
+  #[error("Rusqlite error: {0}")]
+  Rusqlite(#[from] rusqlite::Error),
+  #[error("{0}")]
+  Custom(String),
+}
+
+impl serde::de::Error for DeserializeError {
+  fn custom<T: std::fmt::Display>(msg: T) -> Self {
+    return Self::Custom(msg.to_string());
+  }
+}
+
+/// Wrapper for TEXT columns holding JSON, e.g. to deserialize into a `serde_json::Value`, which
+/// would otherwise hold the raw string.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Json<T>(pub T);
+
+impl<'de, T: DeserializeOwned> Deserialize<'de> for Json<T> {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    return serde_json::from_str(&text)
+      .map(Json)
+      .map_err(D::Error::custom);
+  }
+}
+
+/// Deserializes `row` into `T`, mapping columns to struct fields by name.
+pub fn from_row<T: DeserializeOwned>(row: &rusqlite::Row<'_>) -> Result<T, DeserializeError> {
+  return T::deserialize(RowDeserializer { row });
+}
+
+struct RowDeserializer<'a, 'stmt> {
+  row: &'a rusqlite::Row<'stmt>,
+}
+
+impl RowDeserializer<'_, '_> {
+  fn single_column(&self) -> Result<ValueDeserializer<'_>, DeserializeError> {
+    let count = self.row.as_ref().column_count();
+    if count != 1 {
+      return Err(DeserializeError::Custom(format!(
+        "Expected a single column, got {count}"
+      )));
+    }
+    return Ok(ValueDeserializer {
+      value: self.row.get_ref(0)?,
+    });
+  }
+}
+
+macro_rules! forward_to_single_column {
+  ($($method:ident)*) => {
+    $(
+      fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        return self.single_column()?.$method(visitor);
+      }
+    )*
+  };
+}
+
+impl<'de> Deserializer<'de> for RowDeserializer<'_, '_> {
+  type Error = DeserializeError;
+
+  fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+    return self.deserialize_map(visitor);
+  }
+
+  fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+    return visitor.visit_map(RowMapAccess {
+      row: self.row,
+      index: 0,
+    });
+  }
+
+  fn deserialize_struct<V: Visitor<'de>>(
+    self,
+    _name: &'static str,
+    _fields: &'static [&'static str],
+    visitor: V,
+  ) -> Result<V::Value, Self::Error> {
+    return self.deserialize_map(visitor);
+  }
+
+  fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+    return visitor.visit_seq(RowSeqAccess {
+      row: self.row,
+      index: 0,
+    });
+  }
+
+  fn deserialize_tuple<V: Visitor<'de>>(
+    self,
+    _len: usize,
+    visitor: V,
+  ) -> Result<V::Value, Self::Error> {
+    return self.deserialize_seq(visitor);
+  }
+
+  fn deserialize_tuple_struct<V: Visitor<'de>>(
+    self,
+    _name: &'static str,
+    _len: usize,
+    visitor: V,
+  ) -> Result<V::Value, Self::Error> {
+    return self.deserialize_seq(visitor);
+  }
+
+  fn deserialize_newtype_struct<V: Visitor<'de>>(
+    self,
+    _name: &'static str,
+    visitor: V,
+  ) -> Result<V::Value, Self::Error> {
+    return visitor.visit_newtype_struct(self);
+  }
+
+  fn deserialize_enum<V: Visitor<'de>>(
+    self,
+    name: &'static str,
+    variants: &'static [&'static str],
+    visitor: V,
+  ) -> Result<V::Value, Self::Error> {
+    return self
+      .single_column()?
+      .deserialize_enum(name, variants, visitor);
+  }
+
+  // Single-column rows, e.g. `SELECT COUNT(*)`, deserialize directly into primitives.
+  forward_to_single_column! {
+    deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64
+    deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64 deserialize_f32
+    deserialize_f64 deserialize_char deserialize_str deserialize_string deserialize_bytes
+    deserialize_byte_buf deserialize_option deserialize_unit deserialize_identifier
+    deserialize_ignored_any
+  }
+
+  fn deserialize_unit_struct<V: Visitor<'de>>(
+    self,
+    _name: &'static str,
+    visitor: V,
+  ) -> Result<V::Value, Self::Error> {
+    return self.single_column()?.deserialize_unit(visitor);
+  }
+}
+
+struct RowMapAccess<'a, 'stmt> {
+  row: &'a rusqlite::Row<'stmt>,
+  index: usize,
+}
+
+impl<'de> MapAccess<'de> for RowMapAccess<'_, '_> {
+  type Error = DeserializeError;
+
+  fn next_key_seed<K: DeserializeSeed<'de>>(
+    &mut self,
+    seed: K,
+  ) -> Result<Option<K::Value>, Self::Error> {
+    let stmt = self.row.as_ref();
+    if self.index >= stmt.column_count() {
+      return Ok(None);
+    }
+    let name: &str = stmt.column_name(self.index)?;
+    return seed.deserialize(name.into_deserializer()).map(Some);
+  }
+
+  fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Self::Error> {
+    let value = self.row.get_ref(self.index)?;
+    self.index += 1;
+    return seed.deserialize(ValueDeserializer { value });
+  }
+}
+
+struct RowSeqAccess<'a, 'stmt> {
+  row: &'a rusqlite::Row<'stmt>,
+  index: usize,
+}
+
+impl<'de> SeqAccess<'de> for RowSeqAccess<'_, '_> {
+  type Error = DeserializeError;
+
+  fn next_element_seed<T: DeserializeSeed<'de>>(
+    &mut self,
+    seed: T,
+  ) -> Result<Option<T::Value>, Self::Error> {
+    if self.index >= self.row.as_ref().column_count() {
+      return Ok(None);
+    }
+    let value = self.row.get_ref(self.index)?;
+    self.index += 1;
+    return seed.deserialize(ValueDeserializer { value }).map(Some);
+  }
+}
+
+#[derive(Clone, Copy)]
+struct ValueDeserializer<'a> {
+  value: ValueRef<'a>,
+}
+
+impl ValueDeserializer<'_> {
+  fn text(&self) -> Result<Option<&str>, DeserializeError> {
+    return match self.value {
+      ValueRef::Text(bytes) => Ok(Some(
+        std::str::from_utf8(bytes).map_err(DeserializeError::custom)?,
+      )),
+      _ => Ok(None),
+    };
+  }
+}
+
+impl<'de> Deserializer<'de> for ValueDeserializer<'_> {
+  type Error = DeserializeError;
+
+  fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+    return match self.value {
+      ValueRef::Null => visitor.visit_unit(),
+      ValueRef::Integer(i) => visitor.visit_i64(i),
+      ValueRef::Real(f) => visitor.visit_f64(f),
+      ValueRef::Text(_) => visitor.visit_str(self.text()?.unwrap_or_default()),
+      // Also serves UUIDs, which accept 16 bytes.
+      ValueRef::Blob(bytes) => visitor.visit_bytes(bytes),
+    };
+  }
+
+  fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+    return match self.value {
+      ValueRef::Null => visitor.visit_none(),
+      _ => visitor.visit_some(self),
+    };
+  }
+
+  fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+    return match self.value {
+      ValueRef::Integer(i) => visitor.visit_bool(i != 0),
+      _ => self.deserialize_any(visitor),
+    };
+  }
+
+  fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+    if let ValueRef::Blob(bytes) = self.value {
+      return visitor.visit_seq(SeqDeserializer::<_, DeserializeError>::new(
+        bytes.iter().copied(),
+      ));
+    }
+    if let Some(text) = self.text()? {
+      return serde_json::Deserializer::from_str(text)
+        .deserialize_seq(visitor)
+        .map_err(DeserializeError::custom);
+    }
+    return self.deserialize_any(visitor);
+  }
+
+  fn deserialize_tuple<V: Visitor<'de>>(
+    self,
+    _len: usize,
+    visitor: V,
+  ) -> Result<V::Value, Self::Error> {
+    return self.deserialize_seq(visitor);
+  }
+
+  fn deserialize_tuple_struct<V: Visitor<'de>>(
+    self,
+    _name: &'static str,
+    _len: usize,
+    visitor: V,
+  ) -> Result<V::Value, Self::Error> {
+    return self.deserialize_seq(visitor);
+  }
+
+  fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+    if let Some(text) = self.text()? {
+      return serde_json::Deserializer::from_str(text)
+        .deserialize_map(visitor)
+        .map_err(DeserializeError::custom);
+    }
+    return self.deserialize_any(visitor);
+  }
+
+  fn deserialize_struct<V: Visitor<'de>>(
+    self,
+    name: &'static str,
+    fields: &'static [&'static str],
+    visitor: V,
+  ) -> Result<V::Value, Self::Error> {
+    if let Some(text) = self.text()? {
+      return serde_json::Deserializer::from_str(text)
+        .deserialize_struct(name, fields, visitor)
+        .map_err(DeserializeError::custom);
+    }
+    return self.deserialize_any(visitor);
+  }
+
+  fn deserialize_enum<V: Visitor<'de>>(
+    self,
+    name: &'static str,
+    variants: &'static [&'static str],
+    visitor: V,
+  ) -> Result<V::Value, Self::Error> {
+    return match self.text()? {
+      // Externally tagged enums with data are stored as JSON objects.
+      Some(text) if text.starts_with('{') => serde_json::Deserializer::from_str(text)
+        .deserialize_enum(name, variants, visitor)
+        .map_err(DeserializeError::custom),
+      // Unit variants are stored by name.
+      Some(text) => visitor.visit_enum(text.into_deserializer()),
+      None => self.deserialize_any(visitor),
+    };
+  }
+
+  fn deserialize_newtype_struct<V: Visitor<'de>>(
+    self,
+    _name: &'static str,
+    visitor: V,
+  ) -> Result<V::Value, Self::Error> {
+    return visitor.visit_newtype_struct(self);
+  }
+
+  forward_to_deserialize_any! {
+    i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf unit
+    unit_struct identifier ignored_any
+  }
+}
@@ -16,6 +16,9 @@ pub enum Error {
   #[error("SerdeRusqlite error: {0}")]
   SerdeRusqlite(#[from] serde_rusqlite::Error),
 
+  #[error("Deserialize error: {0}")]
+  Deserialize(#[from] crate::de::DeserializeError),
+
   #[error("Other error: {0}")]
   Other(#[source] Box<dyn std::error::Error + Send + Sync + 'static>),
 }
@@ -0,0 +1,79 @@
+use parking_lot::Mutex;
+use rusqlite::hooks::Action;
+use std::sync::{Arc, Once};
+
+/// Listener, which stays registered for as long as it returns true.
+type Listener = Box<dyn FnMut() -> bool + Send>;
+type UpdateListener = Box<dyn FnMut(Action, &str, &str, i64) -> bool + Send>;
+
+/// Dispatches SQLite's commit, rollback and update hooks to any number of listeners.
+///
+/// SQLite only supports a single hook of each kind per connection, i.e. setting one replaces the
+/// previous. Independent consumers, e.g. change streams and caches, thus have to share them.
+#[derive(Default)]
+pub(crate) struct Hooks {
+  installed: Once,
+  commit: Mutex<Vec<Listener>>,
+  rollback: Mutex<Vec<Listener>>,
+  update: Mutex<Vec<UpdateListener>>,
+}
+
+impl Hooks {
+  pub(crate) fn add_commit_listener(
+    self: &Arc<Self>,
+    conn: &rusqlite::Connection,
+    listener: impl FnMut() -> bool + Send + 'static,
+  ) {
+    self.install(conn);
+    self.commit.lock().push(Box::new(listener));
+  }
+
+  pub(crate) fn add_rollback_listener(
+    self: &Arc<Self>,
+    conn: &rusqlite::Connection,
+    listener: impl FnMut() -> bool + Send + 'static,
+  ) {
+    self.install(conn);
+    self.rollback.lock().push(Box::new(listener));
+  }
+
+  pub(crate) fn add_update_listener(
+    self: &Arc<Self>,
+    conn: &rusqlite::Connection,
+    listener: impl FnMut(Action, &str, &str, i64) -> bool + Send + 'static,
+  ) {
+    self.install(conn);
+    self.update.lock().push(Box::new(listener));
+  }
+
+  /// Installs the dispatching hooks. Must always be called with the same connection.
+  fn install(self: &Arc<Self>, conn: &rusqlite::Connection) {
+    self.installed.call_once(|| {
+      {
+        let hooks = self.clone();
+        conn.commit_hook(Some(move || -> bool {
+          hooks.commit.lock().retain_mut(|listener| listener());
+          // Returning false lets the commit proceed.
+          return false;
+        }));
+      }
+      {
+        let hooks = self.clone();
+        conn.rollback_hook(Some(move || {
+          hooks.rollback.lock().retain_mut(|listener| listener());
+        }));
+      }
+      {
+        let hooks = self.clone();
+        conn.update_hook(Some(
+          move |action: Action, db: &str, table_name: &str, rowid: i64| {
+            hooks
+              .update
+              .lock()
+              .retain_mut(|listener| listener(action, db, table_name, rowid));
+          },
+        ));
+      }
+    });
+  }
+}
@@ -10,14 +10,29 @@
   clippy::needless_continue
 )]
 
+pub mod backup;
+pub mod changes;
+pub mod checked;
 pub mod connection;
+pub mod de;
 pub mod error;
+mod hooks;
 pub mod params;
 pub mod rows;
+pub mod statement_cache;
+pub mod wal;
 
 pub use rusqlite::types::Value;
 
-pub use connection::Connection;
+pub use backup::{BackupOptions, BackupProgress};
+pub use changes::{ChangeEvent, ChangeOp, ChangeStream};
+pub use connection::{Connection, Transaction};
+pub use de::Json;
 pub use error::Error;
 pub use params::{NamedParamRef, NamedParams, NamedParamsRef, Params};
 pub use rows::{Row, Rows, ValueType};
+pub use statement_cache::StatementCacheStats;
+pub use wal::{CheckpointMode, CheckpointResult};
+
+#[cfg(feature = "macros")]
+pub use trailbase_sqlite_macros::tb_query;
@@ -2,9 +2,12 @@ use base64::prelude::*;
 use rusqlite::{Statement, types};
 use std::fmt::Debug;
 use std::ops::Index;
+use std::pin::Pin;
 use std::str::FromStr;
 use std::sync::Arc;
+use std::task::{Context, Poll};
 use thiserror::Error;
+use tokio::sync::mpsc::Receiver;
 
 #[derive(Debug, Copy, Clone)]
 pub enum ValueType {
@@ -82,6 +85,11 @@ impl Rows {
     return self.0.last();
   }
 
+  /// Keeps the first `len` rows, dropping the rest.
+  pub fn truncate(&mut self, len: usize) {
+    self.0.truncate(len);
+  }
+
   pub fn column_count(&self) -> usize {
     return self.1.len();
   }
@@ -130,6 +138,20 @@ impl IntoIterator for Rows {
   }
 }
 
+/// Stream of row batches, see [`crate::Connection::query_stream`].
+#[derive(Debug)]
+pub struct RowStream {
+  pub(crate) receiver: Receiver<crate::connection::Result<Rows>>,
+}
+
+impl futures_core::Stream for RowStream {
+  type Item = crate::connection::Result<Rows>;
+
+  fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+    return self.receiver.poll_recv(cx);
+  }
+}
+
 #[derive(Debug)]
 pub struct Row(Vec<types::Value>, Arc<Vec<Column>>);
 
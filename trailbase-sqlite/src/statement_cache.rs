@@ -0,0 +1,47 @@
+use rusqlite::{CachedStatement, StatementStatus};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Default number of prepared statements cached per connection.
+pub const DEFAULT_STATEMENT_CACHE_CAPACITY: usize = 128;
+
+/// Snapshot of the prepared statement cache counters across all connections.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StatementCacheStats {
+  pub hits: u64,
+  pub misses: u64,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct StatementCacheMetrics {
+  hits: AtomicU64,
+  misses: AtomicU64,
+}
+
+impl StatementCacheMetrics {
+  /// Prepares `sql` using the connection's LRU statement cache, which is keyed by SQL text, and
+  /// records whether the statement was served from the cache.
+  pub(crate) fn prepare_cached<'a>(
+    &self,
+    conn: &'a rusqlite::Connection,
+    sql: &str,
+  ) -> rusqlite::Result<CachedStatement<'a>> {
+    let stmt = conn.prepare_cached(sql)?;
+
+    // NOTE: rusqlite doesn't expose cache hits. Freshly prepared statements haven't run yet,
+    // whereas cached ones have been run at least once before being returned to the cache.
+    if stmt.get_status(StatementStatus::Run) > 0 {
+      self.hits.fetch_add(1, Ordering::Relaxed);
+    } else {
+      self.misses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    return Ok(stmt);
+  }
+
+  pub(crate) fn stats(&self) -> StatementCacheStats {
+    return StatementCacheStats {
+      hits: self.hits.load(Ordering::Relaxed),
+      misses: self.misses.load(Ordering::Relaxed),
+    };
+  }
+}
@@ -3,8 +3,11 @@ use rusqlite::hooks::PreUpdateCase;
 use serde::Deserialize;
 use std::borrow::Cow;
 
-use crate::connection::{Connection, Error, Options, extract_row_id};
-use crate::{Value, ValueType, named_params, params};
+use crate::connection::{Connection, Error, Options, extract_row_id, shared_memory_uri};
+use crate::{
+  BackupOptions, ChangeEvent, ChangeOp, CheckpointMode, NamedParams, Value, ValueType,
+  named_params, params,
+};
 use rusqlite::ErrorCode;
 
 #[tokio::test]
@@ -93,6 +96,94 @@ async fn close_success_test() {
   assert!(conn.close().await.is_ok());
 }
 
+#[tokio::test]
+async fn test_read_pool() {
+  let tmp_dir = tempfile::TempDir::new().unwrap();
+  let fname = tmp_dir.path().join("main.sqlite");
+
+  let conn = Connection::new(
+    move || {
+      let conn = rusqlite::Connection::open(&fname)?;
+      conn.execute_batch("PRAGMA journal_mode = WAL")?;
+      return Ok::<_, rusqlite::Error>(conn);
+    },
+    Some(Options {
+      n_read_threads: 2,
+      ..Default::default()
+    }),
+  )
+  .unwrap();
+
+  conn
+    .execute_batch(
+      r#"
+        CREATE TABLE test (id INTEGER PRIMARY KEY) STRICT;
+        INSERT INTO test (id) VALUES (1), (2);
+      "#,
+    )
+    .await
+    .unwrap();
+
+  conn
+    .transaction(async |tx| -> Result<(), Error> {
+      tx.execute("INSERT INTO test (id) VALUES (3)", ()).await?;
+
+      // Readers neither block on the open write transaction nor observe its uncommitted writes.
+      let rows = conn.read_query_rows("SELECT * FROM test", ()).await?;
+      assert_eq!(rows.len(), 2);
+
+      return Ok(());
+    })
+    .await
+    .unwrap();
+
+  let rows = conn
+    .read_query_rows("SELECT * FROM test", ())
+    .await
+    .unwrap();
+  assert_eq!(rows.len(), 3);
+
+  assert!(conn.close().await.is_ok());
+}
+
+#[tokio::test]
+async fn test_single_reader() {
+  let tmp_dir = tempfile::TempDir::new().unwrap();
+  let fname = tmp_dir.path().join("main.sqlite");
+
+  let conn = Connection::new(
+    move || {
+      let conn = rusqlite::Connection::open(&fname)?;
+      conn.execute_batch("PRAGMA journal_mode = WAL")?;
+      return Ok::<_, rusqlite::Error>(conn);
+    },
+    Some(Options {
+      n_read_threads: 1,
+      ..Default::default()
+    }),
+  )
+  .unwrap();
+  assert_eq!(conn.read_connections(), 1);
+
+  // Reads are served by the dedicated, query-only reader rather than the writer.
+  assert_eq!(
+    conn
+      .read_query_row_f("PRAGMA query_only", (), |row| row.get::<_, i64>(0))
+      .await
+      .unwrap(),
+    Some(1)
+  );
+  assert_eq!(
+    conn
+      .query_row_f("PRAGMA query_only", (), |row| row.get::<_, i64>(0))
+      .await
+      .unwrap(),
+    Some(0)
+  );
+
+  assert!(conn.close().await.is_ok());
+}
+
 #[tokio::test]
 async fn double_close_test() {
   let conn = Connection::open_in_memory().unwrap();
@@ -349,6 +440,332 @@ async fn test_execute_batch_error() {
   assert!(result.is_err(), "{result:?}");
 }
 
+#[tokio::test]
+async fn test_statement_cache_stats() {
+  let conn = Connection::open_in_memory().unwrap();
+  conn
+    .execute_batch("CREATE TABLE test (id INTEGER PRIMARY KEY NOT NULL) STRICT;")
+    .await
+    .unwrap();
+
+  let before = conn.statement_cache_stats();
+  for id in 0..3_i64 {
+    conn
+      .execute("INSERT INTO test (id) VALUES ($1)", params!(id))
+      .await
+      .unwrap();
+  }
+  conn
+    .read_query_rows("SELECT * FROM test", ())
+    .await
+    .unwrap();
+
+  let after = conn.statement_cache_stats();
+  assert_eq!(after.misses - before.misses, 2);
+  assert_eq!(after.hits - before.hits, 2);
+}
+
+#[tokio::test]
+async fn test_interrupt_abandoned_reads() {
+  let conn = Connection::open_in_memory().unwrap();
+
+  // A query that would run for a very long time unless interrupted.
+  let slow = conn.read_query_rows(
+    r#"
+      WITH RECURSIVE cnt(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM cnt)
+      SELECT MAX(x) FROM cnt
+    "#,
+    (),
+  );
+  assert!(
+    tokio::time::timeout(std::time::Duration::from_millis(50), slow)
+      .await
+      .is_err()
+  );
+
+  let rows = tokio::time::timeout(
+    std::time::Duration::from_secs(10),
+    conn.read_query_rows("SELECT 1", ()),
+  )
+  .await
+  .unwrap()
+  .unwrap();
+  assert_eq!(rows.len(), 1);
+}
+
+#[tokio::test]
+async fn test_query_stream() {
+  use futures_util::StreamExt;
+
+  let conn = Connection::open_in_memory().unwrap();
+  conn
+    .execute_batch(
+      r#"
+        CREATE TABLE test (id INTEGER PRIMARY KEY) STRICT;
+        WITH RECURSIVE cnt(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM cnt LIMIT 10)
+          INSERT INTO test (id) SELECT x FROM cnt;
+      "#,
+    )
+    .await
+    .unwrap();
+
+  let batches: Vec<_> = conn
+    .query_stream("SELECT id FROM test ORDER BY id", (), 3)
+    .collect()
+    .await;
+  assert_eq!(
+    batches
+      .iter()
+      .map(|b| b.as_ref().unwrap().len())
+      .collect::<Vec<_>>(),
+    vec![3, 3, 3, 1]
+  );
+  assert_eq!(batches[3].as_ref().unwrap().0[0].get::<i64>(0), Ok(10));
+
+  // Dropping the stream early releases the reader.
+  {
+    let mut stream = conn.query_stream("SELECT id FROM test", (), 1);
+    assert!(stream.next().await.is_some());
+  }
+  assert_eq!(conn.read_query_rows("SELECT 1", ()).await.unwrap().len(), 1);
+
+  let mut stream = conn.query_stream("SELECT * FROM missing", (), 1);
+  assert!(stream.next().await.unwrap().is_err());
+  assert!(stream.next().await.is_none());
+}
+
+#[tokio::test]
+async fn test_query_as() {
+  #[derive(Debug, Deserialize, PartialEq)]
+  struct Meta {
+    tags: Vec<String>,
+  }
+
+  #[derive(Debug, Deserialize, PartialEq)]
+  enum Kind {
+    Small,
+    Large,
+  }
+
+  #[derive(Debug, Deserialize, PartialEq)]
+  struct Item {
+    id: uuid::Uuid,
+    name: Option<String>,
+    active: bool,
+    kind: Kind,
+    meta: Meta,
+    raw: crate::Json<serde_json::Value>,
+  }
+
+  let conn = Connection::open_in_memory().unwrap();
+  conn
+    .execute_batch(
+      r#"
+        CREATE TABLE item (
+          id      BLOB PRIMARY KEY,
+          name    TEXT,
+          active  INTEGER NOT NULL,
+          kind    TEXT NOT NULL,
+          meta    TEXT NOT NULL,
+          raw     TEXT NOT NULL
+        ) STRICT;
+      "#,
+    )
+    .await
+    .unwrap();
+
+  let id = uuid::Uuid::now_v7();
+  conn
+    .execute(
+      r#"INSERT INTO item VALUES ($1, NULL, 1, 'Large', '{"tags": ["a"]}', '[1, 2]')"#,
+      params!(id.as_bytes().to_vec()),
+    )
+    .await
+    .unwrap();
+
+  let items: Vec<Item> = conn.read_query_as("SELECT * FROM item", ()).await.unwrap();
+  assert_eq!(
+    items,
+    vec![Item {
+      id,
+      name: None,
+      active: true,
+      kind: Kind::Large,
+      meta: Meta {
+        tags: vec!["a".to_string()],
+      },
+      raw: crate::Json(serde_json::json!([1, 2])),
+    }]
+  );
+
+  // Single columns deserialize into primitives and tuples by position.
+  let counts: Vec<i64> = conn
+    .query_as("SELECT COUNT(*) FROM item", ())
+    .await
+    .unwrap();
+  assert_eq!(counts, vec![1]);
+  let pairs: Vec<(Vec<u8>, bool)> = conn
+    .read_query_as("SELECT id, active FROM item", ())
+    .await
+    .unwrap();
+  assert_eq!(pairs, vec![(id.as_bytes().to_vec(), true)]);
+
+  assert!(matches!(
+    conn
+      .read_query_as::<i64>("SELECT id, active FROM item", ())
+      .await,
+    Err(Error::Deserialize(_))
+  ));
+  assert!(
+    conn
+      .read_query_as::<Kind>("SELECT 'Medium'", ())
+      .await
+      .is_err()
+  );
+}
+
+#[tokio::test]
+async fn test_execute_batch_many() {
+  let conn = Connection::open_in_memory().unwrap();
+  conn
+    .execute_batch("CREATE TABLE test (id INTEGER PRIMARY KEY NOT NULL, value TEXT) STRICT;")
+    .await
+    .unwrap();
+
+  let params = |id: i64, value: Option<&str>| -> NamedParams {
+    let mut params: NamedParams = vec![(Cow::Borrowed(":id"), Value::Integer(id))];
+    if let Some(value) = value {
+      params.push((Cow::Borrowed(":value"), Value::Text(value.to_string())));
+    }
+    return params;
+  };
+
+  let rows = conn
+    .execute_batch_many(
+      "INSERT INTO test (id, value) VALUES (:id, :value) RETURNING id",
+      vec![params(1, Some("a")), params(2, None), params(3, Some("c"))],
+    )
+    .await
+    .unwrap();
+  let ids: Vec<i64> = rows.iter().map(|row| row.get(0).unwrap()).collect();
+  assert_eq!(ids, vec![1, 2, 3]);
+
+  // Bindings don't leak into subsequent executions.
+  let value: Option<String> = conn
+    .read_query_row_f("SELECT value FROM test WHERE id = 2", (), |row| row.get(0))
+    .await
+    .unwrap()
+    .unwrap();
+  assert_eq!(value, None);
+
+  // Failures roll back the entire batch.
+  assert!(
+    conn
+      .execute_batch_many(
+        "INSERT INTO test (id) VALUES (:id)",
+        vec![params(4, None), params(1, None)],
+      )
+      .await
+      .is_err()
+  );
+  assert_eq!(
+    conn
+      .read_query_row_f("SELECT COUNT(*) FROM test", (), |row| row.get::<_, i64>(0))
+      .await
+      .unwrap(),
+    Some(3)
+  );
+}
+
+#[tokio::test]
+async fn test_transaction() {
+  let conn = Connection::open_in_memory().unwrap();
+  conn
+    .execute_batch("CREATE TABLE test (id INTEGER PRIMARY KEY NOT NULL) STRICT;")
+    .await
+    .unwrap();
+
+  let count = async || -> i64 {
+    return conn
+      .read_query_row_f("SELECT COUNT(*) FROM test", (), |row| row.get(0))
+      .await
+      .unwrap()
+      .unwrap();
+  };
+
+  // Committed.
+  let n = conn
+    .transaction(async |tx| -> Result<usize, Error> {
+      tx.execute("INSERT INTO test (id) VALUES (1)", ()).await?;
+      return tx.execute("INSERT INTO test (id) VALUES (2)", ()).await;
+    })
+    .await
+    .unwrap();
+  assert_eq!(n, 1);
+  assert_eq!(count().await, 2);
+
+  // Rolled back on error.
+  let result = conn
+    .transaction(async |tx| -> Result<(), Error> {
+      tx.execute("INSERT INTO test (id) VALUES (3)", ()).await?;
+      tx.execute("INSERT INTO test (id) VALUES (1)", ()).await?;
+      return Ok(());
+    })
+    .await;
+  assert!(result.is_err());
+  assert_eq!(count().await, 2);
+
+  // Failing savepoints are rolled back without affecting the outer transaction.
+  conn
+    .transaction(async |tx| -> Result<(), Error> {
+      tx.execute("INSERT INTO test (id) VALUES (3)", ()).await?;
+
+      let inner = tx
+        .savepoint(async |tx| -> Result<(), Error> {
+          tx.execute("INSERT INTO test (id) VALUES (4)", ()).await?;
+          return tx.execute_batch("INVALID SQL").await;
+        })
+        .await;
+      assert!(inner.is_err());
+
+      tx.savepoint(async |tx| -> Result<(), Error> {
+        tx.execute("INSERT INTO test (id) VALUES (5)", ()).await?;
+        return Ok(());
+      })
+      .await?;
+
+      return Ok(());
+    })
+    .await
+    .unwrap();
+
+  let rows = conn
+    .read_query_rows("SELECT id FROM test ORDER BY id", ())
+    .await
+    .unwrap();
+  let ids: Vec<i64> = rows.iter().map(|row| row.get(0).unwrap()).collect();
+  assert_eq!(ids, vec![1, 2, 3, 5]);
+
+  // Abandoned transactions are rolled back and release the writer.
+  {
+    let fut = conn.transaction(async |tx| -> Result<(), Error> {
+      tx.execute("INSERT INTO test (id) VALUES (6)", ()).await?;
+      std::future::pending::<()>().await;
+      return Ok(());
+    });
+    assert!(
+      tokio::time::timeout(std::time::Duration::from_millis(50), fut)
+        .await
+        .is_err()
+    );
+  }
+  conn
+    .execute("INSERT INTO test (id) VALUES (7)", ())
+    .await
+    .unwrap();
+  assert_eq!(count().await, 5);
+}
+
 #[test]
 fn test_locking() {
   let conn = Connection::open_in_memory().unwrap();
@@ -441,6 +858,295 @@ async fn test_params() {
   assert_eq!(4, count);
 }
 
+#[tokio::test]
+async fn test_backup() {
+  let tmp_dir = tempfile::TempDir::new().unwrap();
+  let fname = tmp_dir.path().join("main.sqlite");
+
+  let file_conn = Connection::new(
+    move || {
+      let conn = rusqlite::Connection::open(&fname)?;
+      conn.execute_batch("PRAGMA journal_mode = WAL")?;
+      return Ok::<_, rusqlite::Error>(conn);
+    },
+    None,
+  )
+  .unwrap();
+
+  for (name, conn) in [
+    ("file", file_conn),
+    ("memory", Connection::open_in_memory().unwrap()),
+  ] {
+    conn
+      .execute_batch(
+        r#"
+          CREATE TABLE test (id INTEGER PRIMARY KEY, text TEXT) STRICT;
+          WITH RECURSIVE cnt(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM cnt LIMIT 1000)
+            INSERT INTO test (id, text) SELECT x, hex(randomblob(64)) FROM cnt;
+        "#,
+      )
+      .await
+      .unwrap();
+
+    let steps = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let steps_clone = steps.clone();
+
+    let dst = tmp_dir.path().join(format!("{name}_backup.sqlite"));
+    conn
+      .backup(
+        dst.clone(),
+        BackupOptions {
+          pages_per_step: 8,
+          progress: Some(Box::new(move |_progress| {
+            steps_clone.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+          })),
+          ..Default::default()
+        },
+      )
+      .await
+      .unwrap();
+
+    assert!(steps.load(std::sync::atomic::Ordering::SeqCst) > 1);
+
+    let backup = rusqlite::Connection::open(&dst).unwrap();
+    let count: i64 = backup
+      .query_row("SELECT COUNT(*) FROM test", (), |row| row.get(0))
+      .unwrap();
+    assert_eq!(count, 1000, "{name}");
+  }
+}
+
+#[tokio::test]
+async fn test_wal_checkpoint() {
+  let tmp_dir = tempfile::TempDir::new().unwrap();
+  let fname = tmp_dir.path().join("main.sqlite");
+
+  let conn = Connection::new(
+    move || {
+      let conn = rusqlite::Connection::open(&fname)?;
+      conn.execute_batch("PRAGMA journal_mode = WAL")?;
+      return Ok::<_, rusqlite::Error>(conn);
+    },
+    None,
+  )
+  .unwrap();
+
+  conn.set_wal_autocheckpoint(0).await.unwrap();
+  conn
+    .execute_batch(
+      r#"
+        CREATE TABLE test (id INTEGER PRIMARY KEY, text TEXT) STRICT;
+        WITH RECURSIVE cnt(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM cnt LIMIT 1000)
+          INSERT INTO test (id, text) SELECT x, hex(randomblob(64)) FROM cnt;
+      "#,
+    )
+    .await
+    .unwrap();
+
+  let size = conn.wal_size().await.unwrap().unwrap();
+  assert!(size > 0);
+
+  let result = conn.wal_checkpoint(CheckpointMode::Passive).await.unwrap();
+  assert!(!result.busy);
+  assert!(result.log_frames > 0);
+  assert_eq!(result.log_frames, result.checkpointed_frames);
+
+  conn.wal_checkpoint(CheckpointMode::Truncate).await.unwrap();
+  assert_eq!(conn.wal_size().await.unwrap().unwrap_or(0), 0);
+
+  let memory = Connection::open_in_memory().unwrap();
+  assert_eq!(memory.wal_size().await.unwrap(), None);
+  let result = memory
+    .wal_checkpoint(CheckpointMode::Truncate)
+    .await
+    .unwrap();
+  assert_eq!(result.log_frames, -1);
+}
+
+#[tokio::test]
+async fn test_shared_in_memory() {
+  let conn = Connection::open_shared_in_memory(
+    "test_shared_in_memory",
+    Some(Options {
+      n_read_threads: 2,
+      ..Default::default()
+    }),
+  )
+  .unwrap();
+
+  conn
+    .execute_batch("CREATE TABLE test (id INTEGER PRIMARY KEY, text TEXT) STRICT;")
+    .await
+    .unwrap();
+  conn
+    .execute("INSERT INTO test (text) VALUES ($1)", params!("foo"))
+    .await
+    .unwrap();
+
+  // Served by dedicated readers sharing the writer's database.
+  for _ in 0..4 {
+    let text: String = conn
+      .read_query_row_f("SELECT text FROM test", (), |row| row.get(0))
+      .await
+      .unwrap()
+      .unwrap();
+    assert_eq!(text, "foo");
+  }
+
+  // Other connections within the process see the same database.
+  let other = rusqlite::Connection::open_with_flags(
+    shared_memory_uri("test_shared_in_memory"),
+    rusqlite::OpenFlags::SQLITE_OPEN_READ_WRITE | rusqlite::OpenFlags::SQLITE_OPEN_URI,
+  )
+  .unwrap();
+  let count: i64 = other
+    .query_row("SELECT COUNT(*) FROM test", (), |row| row.get(0))
+    .unwrap();
+  assert_eq!(count, 1);
+
+  assert_eq!(conn.wal_size().await.unwrap(), None);
+
+  let tmp_dir = tempfile::TempDir::new().unwrap();
+  let dst = tmp_dir.path().join("backup.sqlite");
+  conn
+    .backup(dst.clone(), BackupOptions::default())
+    .await
+    .unwrap();
+  let backup = rusqlite::Connection::open(&dst).unwrap();
+  let count: i64 = backup
+    .query_row("SELECT COUNT(*) FROM test", (), |row| row.get(0))
+    .unwrap();
+  assert_eq!(count, 1);
+}
+
+#[tokio::test]
+async fn test_change_stream() {
+  let conn = Connection::open_in_memory().unwrap();
+  conn
+    .execute_batch("CREATE TABLE test (id INTEGER PRIMARY KEY, text TEXT) STRICT;")
+    .await
+    .unwrap();
+
+  let mut stream = conn.change_stream().await.unwrap();
+
+  conn
+    .execute("INSERT INTO test (id, text) VALUES (1, 'foo')", ())
+    .await
+    .unwrap();
+
+  // Rolled back changes are never emitted.
+  let _ = conn
+    .transaction(async |tx| -> Result<(), Error> {
+      tx.execute("INSERT INTO test (id, text) VALUES (2, 'bar')", ())
+        .await?;
+      return Err(Error::Other("abort".into()));
+    })
+    .await;
+
+  conn
+    .execute_batch(
+      r#"
+        BEGIN;
+        UPDATE test SET text = 'baz' WHERE id = 1;
+        DELETE FROM test WHERE id = 1;
+        COMMIT;
+      "#,
+    )
+    .await
+    .unwrap();
+
+  let event = stream.recv().await.unwrap();
+  assert_eq!(
+    event,
+    ChangeEvent {
+      table_name: "test".to_string(),
+      op: ChangeOp::Insert,
+      rowid: 1,
+      old: None,
+      new: Some(vec![Value::Integer(1), Value::Text("foo".to_string())]),
+    }
+  );
+
+  let event = stream.recv().await.unwrap();
+  assert_eq!(event.op, ChangeOp::Update);
+  assert_eq!(
+    event.old,
+    Some(vec![Value::Integer(1), Value::Text("foo".to_string())])
+  );
+  assert_eq!(
+    event.new,
+    Some(vec![Value::Integer(1), Value::Text("baz".to_string())])
+  );
+
+  let event = stream.recv().await.unwrap();
+  assert_eq!(event.op, ChangeOp::Delete);
+  assert_eq!(event.rowid, 1);
+  assert_eq!(event.new, None);
+}
+
+#[tokio::test]
+async fn test_shared_hook_listeners() {
+  let conn = Connection::open_in_memory().unwrap();
+  conn
+    .execute_batch("CREATE TABLE test (id INTEGER PRIMARY KEY, text TEXT) STRICT;")
+    .await
+    .unwrap();
+
+  let commits = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+  let updates = std::sync::Arc::new(parking_lot::Mutex::new(Vec::<String>::new()));
+
+  let mut stream = conn.change_stream().await.unwrap();
+  {
+    let commits = commits.clone();
+    conn
+      .add_commit_listener(move || -> bool {
+        commits.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        return true;
+      })
+      .await
+      .unwrap();
+  }
+  {
+    let updates = updates.clone();
+    conn
+      .add_update_listener(
+        move |_action, _db: &str, table_name: &str, _rowid| -> bool {
+          updates.lock().push(table_name.to_string());
+          // Unregister after the first update.
+          return false;
+        },
+      )
+      .await
+      .unwrap();
+  }
+
+  // Listeners registered after the change stream don't replace its hooks.
+  conn
+    .execute("INSERT INTO test (id, text) VALUES (1, 'foo')", ())
+    .await
+    .unwrap();
+  conn
+    .execute("INSERT INTO test (id, text) VALUES (2, 'bar')", ())
+    .await
+    .unwrap();
+
+  assert_eq!(stream.recv().await.unwrap().rowid, 1);
+  assert_eq!(stream.recv().await.unwrap().rowid, 2);
+  assert_eq!(commits.load(std::sync::atomic::Ordering::SeqCst), 2);
+  assert_eq!(*updates.lock(), vec!["test".to_string()]);
+
+  // Nor does a subsequent change stream replace other listeners, only the previous stream.
+  let mut next = conn.change_stream().await.unwrap();
+  conn
+    .execute("INSERT INTO test (id, text) VALUES (3, 'baz')", ())
+    .await
+    .unwrap();
+
+  assert_eq!(next.recv().await.unwrap().rowid, 3);
+  assert_eq!(stream.recv().await, None);
+  assert_eq!(commits.load(std::sync::atomic::Ordering::SeqCst), 3);
+}
+
 #[tokio::test]
 async fn test_hooks() {
   let conn = Connection::open_in_memory().unwrap();
@@ -0,0 +1,63 @@
+/// Checkpoint modes, see <https://www.sqlite.org/pragma.html#pragma_wal_checkpoint>.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CheckpointMode {
+  /// Checkpoints as many frames as possible without waiting for readers or writers.
+  #[default]
+  Passive,
+  /// Waits for writers and until all readers are reading from the most recent snapshot, then
+  /// checkpoints all frames.
+  Full,
+  /// Like [`Self::Full`] but additionally waits for readers to finish, so that the next writer
+  /// restarts the log from the beginning.
+  Restart,
+  /// Like [`Self::Restart`] but additionally truncates the WAL file to zero bytes.
+  Truncate,
+}
+
+impl CheckpointMode {
+  pub(crate) fn as_str(&self) -> &'static str {
+    return match self {
+      Self::Passive => "PASSIVE",
+      Self::Full => "FULL",
+      Self::Restart => "RESTART",
+      Self::Truncate => "TRUNCATE",
+    };
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CheckpointResult {
+  /// Whether a blocking checkpoint couldn't complete, e.g. due to active readers.
+  pub busy: bool,
+  /// Number of frames in the WAL or -1 if the database isn't in WAL mode.
+  pub log_frames: i64,
+  /// Number of frames checkpointed into the database or -1 if the database isn't in WAL mode.
+  pub checkpointed_frames: i64,
+}
+
+pub(crate) fn run_checkpoint(
+  conn: &rusqlite::Connection,
+  mode: CheckpointMode,
+) -> rusqlite::Result<CheckpointResult> {
+  return conn.query_row(
+    &format!("PRAGMA wal_checkpoint({})", mode.as_str()),
+    (),
+    |row| {
+      return Ok(CheckpointResult {
+        busy: row.get::<_, i64>(0)? != 0,
+        log_frames: row.get(1)?,
+        checkpointed_frames: row.get(2)?,
+      });
+    },
+  );
+}
+
+/// Returns the size of the WAL file belonging to the database at `path` or `None` if it doesn't
+/// exist, e.g. after a truncating checkpoint or when not in WAL mode.
+pub(crate) fn wal_file_size(path: &str) -> std::io::Result<Option<u64>> {
+  return match std::fs::metadata(format!("{path}-wal")) {
+    Ok(metadata) => Ok(Some(metadata.len())),
+    Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+    Err(err) => Err(err),
+  };
+}
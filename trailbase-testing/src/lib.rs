@@ -0,0 +1,379 @@
+//! Ephemeral TrailBase instances for end-to-end tests.
+//!
+//! A [`TestInstance`] runs TrailBase in-process on a random local port and a temporary data
+//! directory, which is removed on drop. Migrations and fixtures can be applied up-front and users
+//! created on demand, so that apps can be tested against their actual schema and config using the
+//! regular client:
+//!
+//! ```no_run
+//! # async fn test() -> Result<(), trailbase_testing::Error> {
+//! let instance = trailbase_testing::TestInstance::builder()
+//!   .migrations("./traildepot/migrations")
+//!   .fixture("INSERT INTO room (name) VALUES ('lobby')")
+//!   .build()
+//!   .await?;
+//!
+//! instance.create_user("alice@test.org", "secret123").await?;
+//! let client = instance.client_for("alice@test.org", "secret123").await?;
+//! let rooms = client
+//!   .records("rooms")
+//!   .list::<serde_json::Value>(Default::default())
+//!   .await?;
+//! # Ok(())
+//! # }
+//! ```
+#![forbid(unsafe_code, clippy::unwrap_used)]
+#![allow(clippy::needless_return)]
+#![warn(clippy::await_holding_lock, clippy::inefficient_to_string)]
+
+use axum::extract::{Json, State};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use tokio::task::JoinHandle;
+use trailbase::config::proto::Config;
+use trailbase::{AppState, Clock, DataDir, Server, ShutdownHandle};
+use uuid::Uuid;
+
+pub use trailbase_client::{Client, Tokens};
+
+const UPDATE_SNAPSHOTS_ENV: &str = "TRAILBASE_UPDATE_SNAPSHOTS";
+
+#[derive(Debug, Error)]
+pub enum Error {
+  #[error("IO error: {0}")]
+  IO(#[from] std::io::Error),
+  #[error("Init error: {0}")]
+  Init(#[from] trailbase::InitError),
+  #[error("SQLite error: {0}")]
+  Sqlite(#[from] trailbase_sqlite::Error),
+  #[error("Fixture error: {0}")]
+  Fixture(String),
+  #[error("Seed error: {0}")]
+  Seed(String),
+  #[error("Schema drift: {0}")]
+  SchemaDrift(String),
+  #[error("User error: {0}")]
+  User(String),
+  #[error("Client error: {0}")]
+  Client(#[from] trailbase_client::Error),
+  #[error("Serve error: {0}")]
+  Serve(#[from] tokio::task::JoinError),
+}
+
+/// Builder for a [`TestInstance`].
+#[derive(Debug)]
+pub struct TestInstanceBuilder {
+  config: Option<Config>,
+  migrations: Vec<PathBuf>,
+  fixtures: Vec<String>,
+  in_memory: bool,
+  mock_auth: bool,
+  clock: Clock,
+  js_runtime_threads: Option<usize>,
+}
+
+impl Default for TestInstanceBuilder {
+  fn default() -> Self {
+    return Self {
+      config: None,
+      migrations: vec![],
+      fixtures: vec![],
+      in_memory: true,
+      mock_auth: false,
+      clock: Clock::system(),
+      js_runtime_threads: Some(1),
+    };
+  }
+}
+
+impl TestInstanceBuilder {
+  /// Config to test against, e.g. parsed from the app's `config.textproto` using
+  /// [`Config::from_text`]. Otherwise, the default config is used.
+  pub fn config(mut self, config: Config) -> Self {
+    self.config = Some(config);
+    return self;
+  }
+
+  /// Directory containing the app's migrations, e.g. "traildepot/migrations". Can be called
+  /// repeatedly to apply migrations from multiple directories.
+  pub fn migrations(mut self, dir: impl Into<PathBuf>) -> Self {
+    self.migrations.push(dir.into());
+    return self;
+  }
+
+  /// SQL executed after migrations have been applied, e.g. to insert test data. Fixtures are
+  /// applied in order.
+  pub fn fixture(mut self, sql: impl Into<String>) -> Self {
+    self.fixtures.push(sql.into());
+    return self;
+  }
+
+  /// Keep databases in memory rather than the temporary data directory. Defaults to true.
+  pub fn in_memory(mut self, in_memory: bool) -> Self {
+    self.in_memory = in_memory;
+    return self;
+  }
+
+  /// Authenticate requests with an `X-Test-User: <id>[;<role>,...]` header as the given user,
+  /// e.g. to exercise access rules without going through the login flow.
+  pub fn mock_auth(mut self, enable: bool) -> Self {
+    self.mock_auth = enable;
+    return self;
+  }
+
+  /// Source of time and UUIDv7s. Use a [`Clock::frozen`] clock, and keep a clone to advance it,
+  /// for deterministic record ids and token timestamps, e.g. to make cursor pagination stable.
+  pub fn clock(mut self, clock: Clock) -> Self {
+    self.clock = clock;
+    return self;
+  }
+
+  /// Number of V8 worker threads. Defaults to 1.
+  pub fn js_runtime_threads(mut self, threads: usize) -> Self {
+    self.js_runtime_threads = Some(threads);
+    return self;
+  }
+
+  pub async fn build(self) -> Result<TestInstance, Error> {
+    let data_dir = temp_dir::TempDir::new()?;
+    let depot = DataDir(data_dir.path().to_path_buf());
+
+    // Migrations are picked up from the data directory on init.
+    let migrations_path = depot.migrations_path();
+    tokio::fs::create_dir_all(&migrations_path).await?;
+    for dir in &self.migrations {
+      let mut entries = tokio::fs::read_dir(dir).await?;
+      while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if path.extension().is_some_and(|ext| ext == "sql") {
+          tokio::fs::copy(&path, migrations_path.join(entry.file_name())).await?;
+        }
+      }
+    }
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let address = listener.local_addr()?;
+
+    let mut builder = Server::builder()
+      .data_dir(depot.root())
+      .address(address.to_string())
+      .in_memory(self.in_memory)
+      .mock_auth(self.mock_auth)
+      .clock(self.clock)
+      // Multiple instances may run within the same test binary.
+      .skip_tracing_init(true);
+    if let Some(config) = self.config {
+      builder = builder.config(config);
+    }
+    if let Some(threads) = self.js_runtime_threads {
+      builder = builder.js_runtime_threads(threads);
+    }
+    let server = builder.build().await?;
+
+    for fixture in self.fixtures {
+      server.connection().execute_batch(fixture).await?;
+    }
+    server
+      .state
+      .refresh_table_cache()
+      .await
+      .map_err(|err| Error::Fixture(err.to_string()))?;
+
+    let state = server.state.clone();
+    let shutdown = server.shutdown_handle();
+    let router = server.router().clone();
+    let serving = {
+      let shutdown = shutdown.clone();
+      tokio::spawn(async move {
+        let _ = axum::serve(
+          listener,
+          router.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .with_graceful_shutdown(async move { shutdown.wait().await })
+        .await;
+      })
+    };
+
+    return Ok(TestInstance {
+      state,
+      site: format!("http://{address}"),
+      shutdown,
+      serving,
+      data_dir,
+    });
+  }
+}
+
+/// An in-process TrailBase instance serving on a random local port. Shuts down on drop.
+pub struct TestInstance {
+  state: AppState,
+  site: String,
+  shutdown: ShutdownHandle,
+  serving: JoinHandle<()>,
+  data_dir: temp_dir::TempDir,
+}
+
+impl TestInstance {
+  pub fn builder() -> TestInstanceBuilder {
+    return TestInstanceBuilder::default();
+  }
+
+  /// Starts an instance with the default config and no app migrations.
+  pub async fn new() -> Result<Self, Error> {
+    return Self::builder().build().await;
+  }
+
+  /// Base URL, e.g. "http://127.0.0.1:41234".
+  pub fn site(&self) -> &str {
+    return &self.site;
+  }
+
+  pub fn state(&self) -> &AppState {
+    return &self.state;
+  }
+
+  /// Connection to the main database, e.g. to assert on side-effects.
+  pub fn connection(&self) -> &trailbase_sqlite::Connection {
+    return self.state.conn();
+  }
+
+  pub fn data_dir(&self) -> &Path {
+    return self.data_dir.path();
+  }
+
+  /// Inserts a record or an array of records into the given table and returns their rowids.
+  ///
+  /// Unlike SQL fixtures, records go through the same conversion and validation as record API
+  /// requests, i.e. JSON schema columns are checked and file columns stored in the object store.
+  pub async fn seed(&self, table: &str, records: serde_json::Value) -> Result<Vec<i64>, Error> {
+    return trailbase::api::seed_records(&self.state, table, records)
+      .await
+      .map_err(|err| Error::Seed(err.to_string()));
+  }
+
+  /// Inserts JSON or CSV fixtures from the given file or directory, resolving references between
+  /// them, see [`trailbase::api::seed_fixtures`].
+  pub async fn seed_fixtures(
+    &self,
+    path: impl AsRef<Path>,
+  ) -> Result<trailbase::api::SeedReport, Error> {
+    let fixtures = trailbase::api::load_fixtures(&[path.as_ref().to_path_buf()])
+      .map_err(|err| Error::Seed(err.to_string()))?;
+    return trailbase::api::seed_fixtures(&self.state, fixtures, false)
+      .await
+      .map_err(|err| Error::Seed(err.to_string()));
+  }
+
+  /// Normalized JSON serialization of the app's tables and views, see
+  /// [`trailbase::api::SchemaSnapshot`].
+  pub fn schema_snapshot(&self) -> String {
+    return trailbase::api::schema_snapshot(&self.state).to_json_pretty();
+  }
+
+  /// Compares the schema against a checked-in snapshot file, e.g. to catch migrations drifting
+  /// from what's deployed elsewhere.
+  ///
+  /// Missing snapshots are written. Existing ones are overwritten rather than compared when the
+  /// `TRAILBASE_UPDATE_SNAPSHOTS` environment variable is set, e.g. after intentional changes.
+  pub async fn assert_schema_snapshot(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+    let path = path.as_ref();
+    let actual = self.schema_snapshot();
+
+    let update = std::env::var_os(UPDATE_SNAPSHOTS_ENV).is_some();
+    if update || !tokio::fs::try_exists(path).await? {
+      if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+      }
+      tokio::fs::write(path, actual).await?;
+      return Ok(());
+    }
+
+    let expected = tokio::fs::read_to_string(path).await?;
+    if expected == actual {
+      return Ok(());
+    }
+
+    let expected_lines: Vec<&str> = expected.lines().collect();
+    let actual_lines: Vec<&str> = actual.lines().collect();
+    let idx = (0..expected_lines.len().max(actual_lines.len()))
+      .find(|idx| expected_lines.get(*idx) != actual_lines.get(*idx))
+      .unwrap_or(expected_lines.len());
+    let (line, expected_line, actual_line) =
+      (idx + 1, expected_lines.get(idx), actual_lines.get(idx));
+
+    return Err(Error::SchemaDrift(format!(
+      "{path:?} differs at line {line}:\n- {}\n+ {}\nRe-run with {UPDATE_SNAPSHOTS_ENV}=1 to \
+       update the snapshot.",
+      expected_line.unwrap_or(&"<eof>"),
+      actual_line.unwrap_or(&"<eof>"),
+    )));
+  }
+
+  /// Creates a verified user.
+  pub async fn create_user(&self, email: &str, password: &str) -> Result<Uuid, Error> {
+    return self.create_user_impl(email, password, false).await;
+  }
+
+  /// Creates a verified admin user.
+  pub async fn create_admin(&self, email: &str, password: &str) -> Result<Uuid, Error> {
+    return self.create_user_impl(email, password, true).await;
+  }
+
+  /// Logs in an existing user and returns their tokens.
+  pub async fn tokens(&self, email: &str, password: &str) -> Result<Tokens, Error> {
+    let tokens = trailbase::api::login_with_password(&self.state, email, password)
+      .await
+      .map_err(|err| Error::User(err.to_string()))?;
+
+    return Ok(Tokens {
+      auth_token: tokens.auth_token,
+      refresh_token: Some(tokens.refresh_token),
+      csrf_token: Some(tokens.csrf_token),
+    });
+  }
+
+  /// Client without credentials.
+  pub fn client(&self) -> Result<Client, Error> {
+    return Ok(Client::new(&self.site, None)?);
+  }
+
+  /// Client logged in as an existing user.
+  pub async fn client_for(&self, email: &str, password: &str) -> Result<Client, Error> {
+    let tokens = self.tokens(email, password).await?;
+    return Ok(Client::new(&self.site, Some(tokens))?);
+  }
+
+  /// Shuts down gracefully, waiting for in-flight requests to complete.
+  pub async fn shutdown(mut self) -> Result<(), Error> {
+    self.shutdown.shutdown();
+    return Ok((&mut self.serving).await?);
+  }
+
+  async fn create_user_impl(
+    &self,
+    email: &str,
+    password: &str,
+    admin: bool,
+  ) -> Result<Uuid, Error> {
+    let Json(response) = trailbase::api::create_user_handler(
+      State(self.state.clone()),
+      Json(trailbase::api::CreateUserRequest {
+        email: email.to_string(),
+        password: password.to_string(),
+        verified: true,
+        admin,
+      }),
+    )
+    .await
+    .map_err(|err| Error::User(err.to_string()))?;
+
+    return Ok(response.id);
+  }
+}
+
+impl Drop for TestInstance {
+  fn drop(&mut self) {
+    self.shutdown.shutdown();
+  }
+}
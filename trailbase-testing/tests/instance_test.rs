@@ -0,0 +1,103 @@
+use serde::Deserialize;
+use trailbase::config::proto::{Config, PermissionFlag, RecordApiConfig};
+use trailbase_testing::TestInstance;
+
+#[derive(Debug, Deserialize)]
+struct Room {
+  name: String,
+}
+
+#[tokio::test]
+async fn test_instance() {
+  let migrations = temp_dir::TempDir::new().unwrap();
+  std::fs::write(
+    migrations.path().join("U1700000000__create_room.sql"),
+    "CREATE TABLE room (id INTEGER PRIMARY KEY, name TEXT NOT NULL) STRICT;",
+  )
+  .unwrap();
+
+  let mut config = Config::new_with_custom_defaults();
+  config.record_apis.push(RecordApiConfig {
+    name: Some("rooms".to_string()),
+    table_name: Some("room".to_string()),
+    acl_authenticated: vec![PermissionFlag::Read as i32],
+    ..Default::default()
+  });
+
+  let instance = TestInstance::builder()
+    .migrations(migrations.path())
+    .config(config)
+    .fixture("INSERT INTO room (name) VALUES ('lobby');")
+    .build()
+    .await
+    .unwrap();
+
+  let email = "alice@test.org";
+  let password = "secret123";
+  instance.create_user(email, password).await.unwrap();
+
+  let client = instance.client_for(email, password).await.unwrap();
+  assert_eq!(client.user().unwrap().email, email);
+
+  let rooms = client
+    .records("rooms")
+    .list::<Room>(Default::default())
+    .await
+    .unwrap();
+  assert_eq!(rooms.records.len(), 1);
+  assert_eq!(rooms.records[0].name, "lobby");
+
+  let rowids = instance
+    .seed(
+      "room",
+      serde_json::json!([{ "name": "kitchen" }, { "name": "garden" }]),
+    )
+    .await
+    .unwrap();
+  assert_eq!(rowids.len(), 2);
+  assert!(instance.seed("room", serde_json::json!({})).await.is_err());
+
+  let rooms = client
+    .records("rooms")
+    .list::<Room>(Default::default())
+    .await
+    .unwrap();
+  assert_eq!(rooms.records.len(), 3);
+
+  // Anonymous users lack access.
+  assert!(
+    instance
+      .client()
+      .unwrap()
+      .records("rooms")
+      .list::<Room>(Default::default())
+      .await
+      .is_err()
+  );
+
+  // Schema snapshots are written initially and detect subsequent drift.
+  let snapshot = migrations.path().join("schema.json");
+  instance.assert_schema_snapshot(&snapshot).await.unwrap();
+  assert!(
+    std::fs::read_to_string(&snapshot)
+      .unwrap()
+      .contains("\"room\"")
+  );
+  instance.assert_schema_snapshot(&snapshot).await.unwrap();
+
+  instance
+    .connection()
+    .execute("ALTER TABLE room ADD COLUMN floor INTEGER", ())
+    .await
+    .unwrap();
+  instance.state().refresh_table_cache().await.unwrap();
+  assert!(instance.assert_schema_snapshot(&snapshot).await.is_err());
+
+  // Instances are isolated from each other.
+  let other = TestInstance::new().await.unwrap();
+  assert_ne!(other.site(), instance.site());
+  assert!(other.tokens(email, password).await.is_err());
+
+  other.shutdown().await.unwrap();
+  instance.shutdown().await.unwrap();
+}